@@ -54,11 +54,8 @@ export const platform = {
 	getFileUrlByPath: (path) =>
 		constructServerUrl(`/local-file-by-path/${encodeURIComponent(path)}`),
 	getRemoteRspcEndpoint: (remote_identity) => ({
-		url: `${customUriServerUrl?.[0]
-			?.replace('https', 'wss')
-			?.replace('http', 'ws')}/remote/${encodeURIComponent(
-			remote_identity
-		)}/rspc/ws?token=${customUriAuthToken}`
+		url: `${customUriServerUrl?.[0]}/remote/${encodeURIComponent(remote_identity)}/rspc`,
+		headers: { Authorization: `Bearer ${customUriAuthToken}` }
 	}),
 	constructRemoteRspcPath: (remote_identity, path) =>
 		constructServerUrl(
@@ -4,7 +4,7 @@ import { useEffect } from 'react';
 import { Controller } from 'react-hook-form';
 import { Alert, Text, View } from 'react-native';
 import { z } from 'zod';
-import { useLibraryMutation, useLibraryQuery, useZodForm } from '@sd/client';
+import { IdentifierRule, useLibraryMutation, useLibraryQuery, useZodForm } from '@sd/client';
 import ScreenContainer from '~/components/layout/ScreenContainer';
 import { AnimatedButton } from '~/components/primitive/Button';
 import { Divider } from '~/components/primitive/Divider';
@@ -23,7 +23,9 @@ const schema = z.object({
 	indexer_rules_ids: z.array(z.string()),
 	generatePreviewMedia: z.boolean().nullable(),
 	syncPreviewMedia: z.boolean().nullable(),
-	hidden: z.boolean().nullable()
+	hidden: z.boolean().nullable(),
+	// Not editable here yet, so kept as they are
+	identifierRules: z.custom<IdentifierRule[]>().nullable()
 });
 
 const EditLocationSettingsScreen = ({
@@ -54,7 +56,8 @@ const EditLocationSettingsScreen = ({
 			sync_preview_media: data.syncPreviewMedia,
 			generate_preview_media: data.generatePreviewMedia,
 			hidden: data.hidden,
-			indexer_rules_ids: []
+			indexer_rules_ids: [],
+			identifier_rules: data.identifierRules
 		})
 	);
 
@@ -99,7 +102,8 @@ const EditLocationSettingsScreen = ({
 					indexer_rules_ids: data.indexer_rules.map((i) => i.id.toString()),
 					generatePreviewMedia: data.generate_preview_media,
 					syncPreviewMedia: data.sync_preview_media,
-					hidden: data.hidden
+					hidden: data.hidden,
+					identifierRules: data.identifier_rules
 				});
 		}
 	});
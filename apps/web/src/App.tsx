@@ -50,9 +50,7 @@ const platform: Platform = {
 		)}/${encodeURIComponent(filePathId)}`,
 	getFileUrlByPath: (path) => `${spacedriveURL}/local-file-by-path/${encodeURIComponent(path)}`,
 	getRemoteRspcEndpoint: (remote_identity) => ({
-		url: `${spacedriveURL
-			.replace('https', 'wss')
-			.replace('http', 'ws')}/remote/${encodeURIComponent(remote_identity)}/rspc/ws`
+		url: `${spacedriveURL}/remote/${encodeURIComponent(remote_identity)}/rspc`
 	}),
 	constructRemoteRspcPath: (remote_identity, path) =>
 		`${spacedriveURL}/remote/${encodeURIComponent(remote_identity)}/uri/${path}`,
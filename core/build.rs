@@ -1,4 +1,4 @@
-use std::process::Command;
+use std::{fs, process::Command};
 
 fn main() {
 	let output = Command::new("git")
@@ -8,4 +8,20 @@ fn main() {
 	let git_hash = String::from_utf8(output.stdout)
 		.expect("Error passing output of `git rev-parse --short HEAD`");
 	println!("cargo:rustc-env=GIT_HASH={git_hash}");
+
+	// Migrations are named after the time they were written, so the last one sorted is the latest
+	let latest_migration = fs::read_dir("prisma/migrations")
+		.expect("error reading prisma migrations")
+		.filter_map(|entry| {
+			let entry = entry.ok()?;
+			entry
+				.file_type()
+				.ok()?
+				.is_dir()
+				.then(|| entry.file_name().into_string().ok())
+				.flatten()
+		})
+		.max()
+		.expect("no prisma migrations found");
+	println!("cargo:rustc-env=SD_LATEST_MIGRATION={latest_migration}");
 }
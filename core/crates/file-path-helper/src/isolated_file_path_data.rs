@@ -1,8 +1,11 @@
 use sd_core_prisma_helpers::{
-	file_path_for_file_identifier, file_path_for_media_processor, file_path_for_object_validator,
-	file_path_to_full_path, file_path_to_handle_custom_uri, file_path_to_handle_p2p_serve_file,
-	file_path_to_isolate, file_path_to_isolate_with_id, file_path_to_isolate_with_pub_id,
-	file_path_walker, file_path_with_object,
+	file_path_for_backup, file_path_for_bulk_rename, file_path_for_cold_archiver,
+	file_path_for_file_copier, file_path_for_file_identifier, file_path_for_file_mover,
+	file_path_for_folder_sync, file_path_for_integrity_verifier, file_path_for_kind_reidentifier,
+	file_path_for_media_processor, file_path_for_object_validator, file_path_to_full_path,
+	file_path_to_handle_custom_uri, file_path_to_handle_p2p_serve_file, file_path_to_isolate,
+	file_path_to_isolate_with_id, file_path_to_isolate_with_pub_id, file_path_walker,
+	file_path_with_object,
 };
 
 use sd_prisma::prisma::{file_path, location};
@@ -510,7 +513,15 @@ impl_from_db!(
 );
 
 impl_from_db_without_location_id!(
+	file_path_for_backup,
+	file_path_for_bulk_rename,
+	file_path_for_cold_archiver,
+	file_path_for_file_copier,
 	file_path_for_file_identifier,
+	file_path_for_file_mover,
+	file_path_for_folder_sync,
+	file_path_for_integrity_verifier,
+	file_path_for_kind_reidentifier,
 	file_path_to_full_path,
 	file_path_for_media_processor,
 	file_path_for_object_validator,
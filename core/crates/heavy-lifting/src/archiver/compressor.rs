@@ -0,0 +1,821 @@
+use crate::{
+	archiver,
+	file_copier::{
+		copier::{partial_path, remove_partial},
+		exists, MAX_RENAME_ATTEMPTS,
+	},
+	file_identifier::{generate_cas_id, not_in_archive, statistics, CasIdAlgorithm},
+	job_system::{
+		job::{Job, JobReturn, JobTaskDispatcher, ReturnStatus},
+		report::ReportOutputMetadata,
+		utils::{cancel_pending_tasks, location_concurrency_key},
+		SerializableJob, SerializedTasks,
+	},
+	utils::io_throttle::IoThrottle,
+	Error, JobName, OuterContext, ProgressUpdate,
+};
+
+use sd_core_file_path_helper::{FilePathMetadata, IsolatedFilePathData, IsolatedFilePathDataParts};
+use sd_core_prisma_helpers::file_path_for_file_copier;
+
+use sd_file_ext::kind::ObjectKind;
+use sd_prisma::{
+	prisma::{file_path, location, object},
+	prisma_sync,
+};
+use sd_sync::{sync_db_entry, OperationFactory};
+use sd_task_system::{
+	IntoTask, SerializableTask, Task, TaskDispatcher, TaskHandle, TaskId, TaskOutput, TaskStatus,
+	TaskSystemError,
+};
+use sd_utils::{
+	db::{inode_to_db, maybe_missing, size_in_bytes_from_db},
+	error::FileIOError,
+	msgpack, uuid_to_bytes,
+};
+
+use std::{
+	collections::HashMap,
+	hash::{Hash, Hasher},
+	mem,
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::Duration,
+};
+
+use async_channel as chan;
+use chrono::Utc;
+use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
+use futures_concurrency::future::{Race, TryJoin};
+use prisma_client_rust::QueryError;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::{fs, time::Instant};
+use tracing::{debug, error, warn};
+use uuid::Uuid;
+
+use super::{
+	tasks::{
+		compress::{self, ArchiveEntry},
+		ArchiveProgress, Compress,
+	},
+	CompressionFormat,
+};
+
+// Byte progress arrives every chunk of every file, the UI doesn't need it that often
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Builds a zip or tar.zst archive from a selection of file paths of a location, directories with
+/// all their contents, then adds it to the location as a new file path with its own object, without
+/// waiting for it to be indexed.
+///
+/// The archive is written under a hidden partial name and only moved into place once complete.
+/// Zips are written without encryption, as the only one the zip crate can write, `ZipCrypto`, is
+/// broken, while encrypted zips can still be extracted by the [`Extractor`](super::Extractor).
+#[derive(Debug)]
+pub struct Compressor {
+	location: Arc<location::Data>,
+	location_path: Arc<PathBuf>,
+	file_path_ids: Vec<file_path::id::Type>,
+	target_path: PathBuf,
+	name: String,
+	format: CompressionFormat,
+	cas_id_algorithm: CasIdAlgorithm,
+
+	metadata: Metadata,
+	current_entry: Option<PathBuf>,
+	progress_tx: chan::Sender<(TaskId, ArchiveProgress)>,
+	progress_rx: chan::Receiver<(TaskId, ArchiveProgress)>,
+
+	pending_tasks_on_resume: Vec<TaskHandle<Error>>,
+	tasks_for_shutdown: Vec<Box<dyn Task<Error>>>,
+}
+
+impl Hash for Compressor {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.location.id.hash(state);
+		self.file_path_ids.hash(state);
+		self.target_path.hash(state);
+		self.name.hash(state);
+	}
+}
+
+impl Job for Compressor {
+	const NAME: JobName = JobName::Compressor;
+
+	async fn resume_tasks(
+		&mut self,
+		dispatcher: &JobTaskDispatcher,
+		ctx: &impl OuterContext,
+		SerializedTasks(serialized_tasks): SerializedTasks,
+	) -> Result<(), Error> {
+		let dispatcher =
+			dispatcher.with_concurrency_key(location_concurrency_key(ctx.id(), self.location.id));
+		let progress_tx = &self.progress_tx;
+
+		self.pending_tasks_on_resume = dispatcher
+			.dispatch_many_boxed(
+				rmp_serde::from_slice::<Vec<Vec<u8>>>(&serialized_tasks)
+					.map_err(archiver::Error::from)?
+					.into_iter()
+					.map(|task_bytes| async move {
+						Compress::deserialize(&task_bytes, progress_tx.clone())
+							.await
+							.map(IntoTask::into_task)
+					})
+					.collect::<Vec<_>>()
+					.try_join()
+					.await
+					.map_err(archiver::Error::from)?,
+			)
+			.await;
+
+		Ok(())
+	}
+
+	async fn run<Ctx: OuterContext>(
+		mut self,
+		dispatcher: JobTaskDispatcher,
+		ctx: Ctx,
+	) -> Result<ReturnStatus, Error> {
+		enum RaceOutput {
+			Task(Option<Result<TaskStatus<Error>, TaskSystemError>>),
+			Progress(Option<(TaskId, ArchiveProgress)>),
+		}
+
+		let dispatcher =
+			dispatcher.with_concurrency_key(location_concurrency_key(ctx.id(), self.location.id));
+
+		let mut pending_running_tasks = FuturesUnordered::new();
+
+		self.init_or_resume(&mut pending_running_tasks, &ctx, &dispatcher)
+			.await?;
+
+		let mut last_progress_update = Instant::now();
+		let mut compressed = false;
+
+		while !pending_running_tasks.is_empty() {
+			let output = (
+				pending_running_tasks.next().map(RaceOutput::Task),
+				self.progress_rx
+					.recv()
+					.map(|res| RaceOutput::Progress(res.ok())),
+			)
+				.race()
+				.await;
+
+			let task = match output {
+				RaceOutput::Progress(Some((_, progress))) => {
+					self.process_progress(progress);
+
+					if last_progress_update.elapsed() > PROGRESS_INTERVAL {
+						last_progress_update = Instant::now();
+						self.report_progress(&ctx);
+					}
+
+					continue;
+				}
+				// The job holds a sender, so the channel is never closed
+				RaceOutput::Progress(None) | RaceOutput::Task(None) => continue,
+				RaceOutput::Task(Some(task)) => task,
+			};
+
+			match task {
+				Ok(TaskStatus::Done((_, TaskOutput::Out(out)))) => {
+					let compress::Output {
+						compressed_files,
+						archive_size,
+						compress_time,
+					} = *out
+						.downcast::<compress::Output>()
+						.expect("the compressor job only dispatches compress tasks");
+
+					self.metadata.compressed_files += compressed_files;
+					self.metadata.archive_size = archive_size;
+					self.metadata.compress_time += compress_time;
+					compressed = true;
+				}
+
+				Ok(TaskStatus::Done((task_id, TaskOutput::Empty))) => {
+					warn!("Task <id='{task_id}'> returned an empty output");
+				}
+
+				Ok(TaskStatus::Shutdown(task)) => {
+					self.tasks_for_shutdown.push(task);
+				}
+
+				Ok(TaskStatus::Error(e)) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e);
+				}
+
+				Ok(TaskStatus::Canceled | TaskStatus::ForcedAbortion) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Ok(ReturnStatus::Canceled);
+				}
+
+				Err(e) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e.into());
+				}
+			}
+		}
+
+		if !self.tasks_for_shutdown.is_empty() {
+			return Ok(ReturnStatus::Shutdown(
+				SerializableJob::<Ctx>::serialize(self).await,
+			));
+		}
+
+		if compressed {
+			if let Err(e) = self.register_archive(&ctx).await {
+				remove_partial(&self.partial()).await;
+
+				return Err(e.into());
+			}
+		}
+
+		ctx.invalidate_query("search.paths");
+
+		Ok(ReturnStatus::Completed(
+			JobReturn::builder().with_metadata(self.metadata).build(),
+		))
+	}
+}
+
+impl Compressor {
+	/// Compresses the `file_path_ids` of `location` into an archive named `name`, plus the
+	/// extension of `format`, in its `target_sub_path`. `cas_id_algorithm` must be the one the
+	/// library's `cas_id`s were generated with.
+	pub fn new(
+		location: location::Data,
+		file_path_ids: Vec<file_path::id::Type>,
+		target_sub_path: impl AsRef<Path>,
+		name: impl Into<String>,
+		format: CompressionFormat,
+		cas_id_algorithm: CasIdAlgorithm,
+	) -> Result<Self, archiver::Error> {
+		let location_path = maybe_missing(&location.path, "location.path").map(PathBuf::from)?;
+		let target_path = location_path.join(target_sub_path);
+
+		let (progress_tx, progress_rx) = chan::unbounded();
+
+		Ok(Self {
+			location: Arc::new(location),
+			location_path: Arc::new(location_path),
+			file_path_ids,
+			target_path,
+			name: name.into(),
+			format,
+			cas_id_algorithm,
+			metadata: Metadata::default(),
+			current_entry: None,
+			progress_tx,
+			progress_rx,
+			pending_tasks_on_resume: Vec::new(),
+			tasks_for_shutdown: Vec::new(),
+		})
+	}
+
+	/// Where the archive is written until complete
+	fn partial(&self) -> PathBuf {
+		partial_path(
+			&self
+				.target_path
+				.join(format!("{}.{}", self.name, self.format.extension())),
+		)
+	}
+
+	async fn init_or_resume(
+		&mut self,
+		pending_running_tasks: &mut FuturesUnordered<TaskHandle<Error>>,
+		ctx: &impl OuterContext,
+		dispatcher: &JobTaskDispatcher,
+	) -> Result<(), archiver::Error> {
+		// Resumed tasks were already dispatched by `resume_tasks`
+		if !self.pending_tasks_on_resume.is_empty() {
+			pending_running_tasks.extend(mem::take(&mut self.pending_tasks_on_resume));
+
+			return Ok(());
+		}
+
+		let entries = self.gather_entries(ctx).await?;
+
+		debug!(
+			"Compressing {} files, {} bytes, from location {} into \"{}\"",
+			self.metadata.total_files,
+			self.metadata.total_bytes,
+			self.location.id,
+			self.partial().display()
+		);
+
+		pending_running_tasks.push(
+			dispatcher
+				.dispatch(Compress::new(
+					self.partial(),
+					self.format,
+					entries,
+					self.progress_tx.clone(),
+				))
+				.await,
+		);
+
+		self.report_progress(ctx);
+
+		Ok(())
+	}
+
+	/// Files and directories to put in the archive, named after their path from the selection
+	async fn gather_entries(
+		&mut self,
+		ctx: &impl OuterContext,
+	) -> Result<Vec<ArchiveEntry>, archiver::Error> {
+		let db = ctx.db();
+		let location_id = self.location.id;
+		let location_path = &*self.location_path;
+
+		let file_paths = db
+			.file_path()
+			.find_many(vec![
+				file_path::location_id::equals(Some(location_id)),
+				file_path::id::in_vec(self.file_path_ids.clone()),
+				not_in_archive(),
+			])
+			.select(file_path_for_file_copier::select())
+			.exec()
+			.await?;
+
+		if let Some(missing_id) = self
+			.file_path_ids
+			.iter()
+			.find(|id| !file_paths.iter().any(|file_path| file_path.id == **id))
+		{
+			return Err(archiver::Error::FilePathNotFound(*missing_id));
+		}
+
+		let mut entries = Vec::new();
+
+		for file_path in file_paths {
+			let iso_file_path = IsolatedFilePathData::try_from((location_id, &file_path))?;
+			let source = location_path.join(&iso_file_path);
+			let name = iso_file_path.full_name();
+
+			if !iso_file_path.is_dir() {
+				self.push_entry(&mut entries, source, name, &file_path);
+				continue;
+			}
+
+			let children = db
+				.file_path()
+				.find_many(vec![
+					file_path::location_id::equals(Some(location_id)),
+					file_path::materialized_path::starts_with(
+						iso_file_path
+							.materialized_path_for_children()
+							.expect("we checked that the iso_file_path is a directory"),
+					),
+					not_in_archive(),
+				])
+				.select(file_path_for_file_copier::select())
+				.exec()
+				.await?;
+
+			entries.push(ArchiveEntry {
+				source: source.clone(),
+				name: name.clone(),
+				is_dir: true,
+			});
+
+			for child in children {
+				let child_iso_file_path = IsolatedFilePathData::try_from((location_id, &child))?;
+				let child_source = location_path.join(&child_iso_file_path);
+				let child_name = child_source
+					.strip_prefix(&source)
+					.expect("children paths start with their parent's")
+					.components()
+					.fold(name.clone(), |mut child_name, component| {
+						child_name.push('/');
+						child_name.push_str(&component.as_os_str().to_string_lossy());
+						child_name
+					});
+
+				if child_iso_file_path.is_dir() {
+					entries.push(ArchiveEntry {
+						source: child_source,
+						name: child_name,
+						is_dir: true,
+					});
+				} else {
+					self.push_entry(&mut entries, child_source, child_name, &child);
+				}
+			}
+		}
+
+		Ok(entries)
+	}
+
+	fn push_entry(
+		&mut self,
+		entries: &mut Vec<ArchiveEntry>,
+		source: PathBuf,
+		name: String,
+		file_path: &file_path_for_file_copier::Data,
+	) {
+		self.metadata.total_files += 1;
+		self.metadata.total_bytes += file_path
+			.size_in_bytes_bytes
+			.as_deref()
+			.map(size_in_bytes_from_db)
+			.unwrap_or_default();
+
+		entries.push(ArchiveEntry {
+			source,
+			name,
+			is_dir: false,
+		});
+	}
+
+	/// First free name for the archive, ` (n)` suffixed before its whole extension
+	async fn archive_path(&self) -> Result<PathBuf, archiver::Error> {
+		let extension = self.format.extension();
+
+		for n in 0..=MAX_RENAME_ATTEMPTS {
+			let candidate = self.target_path.join(if n == 0 {
+				format!("{}.{extension}", self.name)
+			} else {
+				format!("{} ({n}).{extension}", self.name)
+			});
+
+			if !exists(&candidate).await? {
+				return Ok(candidate);
+			}
+		}
+
+		Err(archiver::Error::FailedToFindAvailableName(
+			self.target_path.join(format!("{}.{extension}", self.name)),
+		))
+	}
+
+	/// Creates the object and file path of the finished archive, then moves it into place. File
+	/// paths are created first, so the watcher finds the archive already indexed. The partial
+	/// archive is left for the caller to remove on failure.
+	async fn register_archive(&mut self, ctx: &impl OuterContext) -> Result<(), archiver::Error> {
+		let (db, sync) = (ctx.db(), ctx.sync());
+		let partial = self.partial();
+
+		let archive_path = self.archive_path().await?;
+
+		let metadata = fs::metadata(&partial)
+			.await
+			.map_err(|e| FileIOError::from((&partial, e)))?;
+		let FilePathMetadata {
+			inode,
+			size_in_bytes,
+			created_at,
+			modified_at,
+			hidden,
+		} = FilePathMetadata::from_path(&partial, &metadata)?;
+
+		let cas_id = generate_cas_id(
+			&partial,
+			size_in_bytes,
+			self.cas_id_algorithm
+				.for_file_size(size_in_bytes, ctx.deep_hash_threshold().await),
+			&IoThrottle::default(),
+		)
+		.await
+		.map_err(|e| FileIOError::from((&partial, e)))?;
+
+		let iso_file_path = IsolatedFilePathData::new(
+			self.location.id,
+			&*self.location_path,
+			&archive_path,
+			false,
+		)?;
+		let IsolatedFilePathDataParts {
+			materialized_path,
+			name,
+			extension,
+			..
+		} = iso_file_path.to_parts();
+
+		let object_pub_id = uuid_to_bytes(Uuid::new_v4());
+		let (sync_params, db_params): (Vec<_>, Vec<_>) = [
+			sync_db_entry!(created_at.into(), object::date_created),
+			sync_db_entry!(ObjectKind::Archive as i32, object::kind),
+		]
+		.into_iter()
+		.unzip();
+
+		let object = sync
+			.write_ops(
+				db,
+				(
+					sync.shared_create(
+						prisma_sync::object::SyncId {
+							pub_id: object_pub_id.clone(),
+						},
+						sync_params,
+					),
+					db.object()
+						.create(object_pub_id.clone(), db_params)
+						.select(object::select!({ id })),
+				),
+			)
+			.await?;
+
+		let file_path_pub_id = uuid_to_bytes(Uuid::new_v4());
+		let (sync_params, db_params): (Vec<_>, Vec<_>) = [
+			(
+				(
+					file_path::location::NAME,
+					msgpack!(prisma_sync::location::SyncId {
+						pub_id: self.location.pub_id.clone()
+					}),
+				),
+				file_path::location_id::set(Some(self.location.id)),
+			),
+			(
+				(
+					file_path::object::NAME,
+					msgpack!(prisma_sync::object::SyncId {
+						pub_id: object_pub_id.clone()
+					}),
+				),
+				file_path::object_id::set(Some(object.id)),
+			),
+			sync_db_entry!(cas_id, file_path::cas_id),
+			sync_db_entry!(materialized_path.to_string(), file_path::materialized_path),
+			sync_db_entry!(name.to_string(), file_path::name),
+			sync_db_entry!(false, file_path::is_dir),
+			sync_db_entry!(extension.to_string(), file_path::extension),
+			sync_db_entry!(
+				size_in_bytes.to_be_bytes().to_vec(),
+				file_path::size_in_bytes_bytes
+			),
+			sync_db_entry!(inode_to_db(inode), file_path::inode),
+			sync_db_entry!(created_at.into(), file_path::date_created),
+			sync_db_entry!(modified_at.into(), file_path::date_modified),
+			sync_db_entry!(Utc::now().into(), file_path::date_indexed),
+			sync_db_entry!(hidden, file_path::hidden),
+		]
+		.into_iter()
+		.unzip();
+
+		let created_pub_id = &file_path_pub_id;
+		let file_path = db
+			._transaction()
+			.with_timeout(30 * 1000)
+			.run(|db| async move {
+				let file_path = sync
+					.write_ops(
+						&db,
+						(
+							sync.shared_create(
+								prisma_sync::file_path::SyncId {
+									pub_id: created_pub_id.clone(),
+								},
+								sync_params,
+							),
+							db.file_path()
+								.create_unchecked(created_pub_id.clone(), db_params)
+								.select(file_path::select!({ id })),
+						),
+					)
+					.await?;
+
+				statistics::snapshot(vec![file_path::id::equals(file_path.id)], &db)
+					.await?
+					.commit(&db)
+					.await?;
+
+				Ok::<_, QueryError>(file_path)
+			})
+			.await?;
+
+		if let Err(e) = fs::rename(&partial, &archive_path).await {
+			let file_path_id = file_path.id;
+			let removed = async {
+				db._transaction()
+					.with_timeout(30 * 1000)
+					.run(|db| async move {
+						statistics::forget(vec![file_path::id::equals(file_path_id)], &db).await?;
+
+						sync.write_ops(
+							&db,
+							(
+								vec![sync.shared_delete(prisma_sync::file_path::SyncId {
+									pub_id: file_path_pub_id,
+								})],
+								db.file_path()
+									.delete(file_path::id::equals(file_path_id))
+									.select(file_path::select!({ id })),
+							),
+						)
+						.await
+					})
+					.await?;
+
+				sync.write_ops(
+					db,
+					(
+						vec![sync.shared_delete(prisma_sync::object::SyncId {
+							pub_id: object_pub_id,
+						})],
+						db.object()
+							.delete(object::id::equals(object.id))
+							.select(object::select!({ id })),
+					),
+				)
+				.await
+			};
+
+			if let Err(e) = removed.await {
+				error!(
+					"Failed to remove the file path of <path='{}'> after failing to move it into \
+					place: {e:#?}",
+					archive_path.display()
+				);
+			}
+
+			return Err(
+				FileIOError::from((&archive_path, e, "Failed to move archive into place")).into(),
+			);
+		}
+
+		self.metadata.archive_file_path_id = Some(file_path.id);
+		self.metadata.archive_path = Some(archive_path);
+
+		Ok(())
+	}
+
+	fn process_progress(&mut self, progress: ArchiveProgress) {
+		match progress {
+			ArchiveProgress::Started(path) => {
+				self.metadata.completed_entries += 1;
+				self.current_entry = Some(path);
+			}
+			ArchiveProgress::Processed(bytes) => self.metadata.compressed_bytes += bytes,
+			// Only sent when extracting
+			ArchiveProgress::PasswordRequired => {}
+		}
+	}
+
+	fn report_progress(&self, ctx: &impl OuterContext) {
+		let total = format!(
+			"{} of {} bytes",
+			// A job resumed after a shutdown compresses everything again
+			self.metadata
+				.compressed_bytes
+				.min(self.metadata.total_bytes),
+			self.metadata.total_bytes
+		);
+
+		let message = self.current_entry.as_ref().map_or_else(
+			|| format!("Compressed {total}"),
+			|path| format!("Compressing \"{}\", {total}", path.display()),
+		);
+
+		ctx.progress(vec![
+			ProgressUpdate::TaskCount(self.metadata.total_files),
+			ProgressUpdate::CompletedTaskCount(
+				self.metadata
+					.completed_entries
+					.min(self.metadata.total_files),
+			),
+			ProgressUpdate::Message(message),
+		]);
+	}
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Metadata {
+	total_files: u64,
+	total_bytes: u64,
+	completed_entries: u64,
+	compressed_files: u64,
+	compressed_bytes: u64,
+	archive_size: u64,
+	compress_time: Duration,
+	archive_path: Option<PathBuf>,
+	archive_file_path_id: Option<file_path::id::Type>,
+}
+
+impl From<Metadata> for ReportOutputMetadata {
+	fn from(value: Metadata) -> Self {
+		Self::Metrics(HashMap::from([
+			("total_files".into(), json!(value.total_files)),
+			("total_bytes".into(), json!(value.total_bytes)),
+			("compressed_files".into(), json!(value.compressed_files)),
+			("archive_size".into(), json!(value.archive_size)),
+			("compress_time".into(), json!(value.compress_time)),
+			("archive_path".into(), json!(value.archive_path)),
+			(
+				"archive_file_path_id".into(),
+				json!(value.archive_file_path_id),
+			),
+		]))
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+struct SaveState {
+	location: Arc<location::Data>,
+	location_path: Arc<PathBuf>,
+	file_path_ids: Vec<file_path::id::Type>,
+	target_path: PathBuf,
+	name: String,
+	format: CompressionFormat,
+	cas_id_algorithm: CasIdAlgorithm,
+
+	metadata: Metadata,
+
+	tasks_for_shutdown_bytes: Option<SerializedTasks>,
+}
+
+impl<Ctx: OuterContext> SerializableJob<Ctx> for Compressor {
+	async fn serialize(self) -> Result<Option<Vec<u8>>, rmp_serde::encode::Error> {
+		let Self {
+			location,
+			location_path,
+			file_path_ids,
+			target_path,
+			name,
+			format,
+			cas_id_algorithm,
+			metadata,
+			tasks_for_shutdown,
+			..
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState {
+			location,
+			location_path,
+			file_path_ids,
+			target_path,
+			name,
+			format,
+			cas_id_algorithm,
+			metadata,
+			tasks_for_shutdown_bytes: Some(SerializedTasks(rmp_serde::to_vec_named(
+				&tasks_for_shutdown
+					.into_iter()
+					.map(|task| async move {
+						task.downcast::<Compress>()
+							.expect("the compressor job only dispatches compress tasks")
+							.serialize()
+							.await
+					})
+					.collect::<Vec<_>>()
+					.try_join()
+					.await?,
+			)?)),
+		})
+		.map(Some)
+	}
+
+	async fn deserialize(
+		serialized_job: &[u8],
+		_: &Ctx,
+	) -> Result<Option<(Self, Option<SerializedTasks>)>, rmp_serde::decode::Error> {
+		let SaveState {
+			location,
+			location_path,
+			file_path_ids,
+			target_path,
+			name,
+			format,
+			cas_id_algorithm,
+			mut metadata,
+			tasks_for_shutdown_bytes,
+		} = rmp_serde::from_slice::<SaveState>(serialized_job)?;
+
+		// The archive is written again from its first entry
+		metadata.completed_entries = 0;
+		metadata.compressed_bytes = 0;
+
+		let (progress_tx, progress_rx) = chan::unbounded();
+
+		Ok(Some((
+			Self {
+				location,
+				location_path,
+				file_path_ids,
+				target_path,
+				name,
+				format,
+				cas_id_algorithm,
+				metadata,
+				current_entry: None,
+				progress_tx,
+				progress_rx,
+				pending_tasks_on_resume: Vec::new(),
+				tasks_for_shutdown: Vec::new(),
+			},
+			tasks_for_shutdown_bytes,
+		)))
+	}
+}
@@ -0,0 +1,504 @@
+use crate::{
+	archiver,
+	job_system::{
+		job::{Job, JobReturn, JobTaskDispatcher, ReturnStatus},
+		report::ReportOutputMetadata,
+		utils::{cancel_pending_tasks, location_concurrency_key},
+		SerializableJob, SerializedTasks,
+	},
+	Error, JobName, NonCriticalError, OuterContext, ProgressUpdate,
+};
+
+use sd_core_file_path_helper::IsolatedFilePathData;
+use sd_core_prisma_helpers::file_path_for_file_copier;
+
+use sd_prisma::prisma::{file_path, location};
+use sd_task_system::{
+	IntoTask, SerializableTask, Task, TaskDispatcher, TaskHandle, TaskId, TaskOutput, TaskStatus,
+	TaskSystemError,
+};
+use sd_utils::{db::maybe_missing, error::FileIOError};
+
+use std::{
+	collections::HashMap,
+	hash::{Hash, Hasher},
+	mem,
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::Duration,
+};
+
+use async_channel as chan;
+use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
+use futures_concurrency::future::{Race, TryJoin};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::{fs, time::Instant};
+use tracing::{debug, warn};
+
+use super::{
+	tasks::{extract, ArchiveProgress, Extract},
+	ConflictPolicy, ExtractionFormat,
+};
+
+// Byte progress arrives every chunk read from the archive, the UI doesn't need it that often
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Extracts a zip, tar, tar.gz or tar.zst archive of a location into one of its directories,
+/// decrypting zips with the given password. Extracted files are left for the watcher or the next
+/// scan to index, as with any other file added to the location.
+#[derive(Debug)]
+pub struct Extractor {
+	location: Arc<location::Data>,
+	location_path: Arc<PathBuf>,
+	archive_file_path_id: file_path::id::Type,
+	target_path: PathBuf,
+	conflict_policy: ConflictPolicy,
+	password: Option<String>,
+
+	metadata: Metadata,
+	current_entry: Option<PathBuf>,
+	waiting_for_password: bool,
+	progress_tx: chan::Sender<(TaskId, ArchiveProgress)>,
+	progress_rx: chan::Receiver<(TaskId, ArchiveProgress)>,
+
+	errors: Vec<NonCriticalError>,
+
+	pending_tasks_on_resume: Vec<TaskHandle<Error>>,
+	tasks_for_shutdown: Vec<Box<dyn Task<Error>>>,
+}
+
+impl Hash for Extractor {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.location.id.hash(state);
+		self.archive_file_path_id.hash(state);
+		self.target_path.hash(state);
+	}
+}
+
+impl Job for Extractor {
+	const NAME: JobName = JobName::Extractor;
+
+	async fn resume_tasks(
+		&mut self,
+		dispatcher: &JobTaskDispatcher,
+		ctx: &impl OuterContext,
+		SerializedTasks(serialized_tasks): SerializedTasks,
+	) -> Result<(), Error> {
+		let dispatcher =
+			dispatcher.with_concurrency_key(location_concurrency_key(ctx.id(), self.location.id));
+		let progress_tx = &self.progress_tx;
+		let keys = ctx.key_manager();
+
+		self.pending_tasks_on_resume = dispatcher
+			.dispatch_many_boxed(
+				rmp_serde::from_slice::<Vec<Vec<u8>>>(&serialized_tasks)
+					.map_err(archiver::Error::from)?
+					.into_iter()
+					.map(|task_bytes| async move {
+						Extract::deserialize(&task_bytes, (progress_tx.clone(), Arc::clone(keys)))
+							.await
+							.map(IntoTask::into_task)
+					})
+					.collect::<Vec<_>>()
+					.try_join()
+					.await
+					.map_err(archiver::Error::from)?,
+			)
+			.await;
+
+		Ok(())
+	}
+
+	async fn run<Ctx: OuterContext>(
+		mut self,
+		dispatcher: JobTaskDispatcher,
+		ctx: Ctx,
+	) -> Result<ReturnStatus, Error> {
+		enum RaceOutput {
+			Task(Option<Result<TaskStatus<Error>, TaskSystemError>>),
+			Progress(Option<(TaskId, ArchiveProgress)>),
+		}
+
+		let dispatcher =
+			dispatcher.with_concurrency_key(location_concurrency_key(ctx.id(), self.location.id));
+
+		let mut pending_running_tasks = FuturesUnordered::new();
+
+		self.init_or_resume(&mut pending_running_tasks, &ctx, &dispatcher)
+			.await?;
+
+		let mut last_progress_update = Instant::now();
+
+		while !pending_running_tasks.is_empty() {
+			let output = (
+				pending_running_tasks.next().map(RaceOutput::Task),
+				self.progress_rx
+					.recv()
+					.map(|res| RaceOutput::Progress(res.ok())),
+			)
+				.race()
+				.await;
+
+			let task = match output {
+				RaceOutput::Progress(Some((_, progress))) => {
+					self.process_progress(progress);
+
+					if last_progress_update.elapsed() > PROGRESS_INTERVAL {
+						last_progress_update = Instant::now();
+						self.report_progress(&ctx);
+					}
+
+					continue;
+				}
+				// The job holds a sender, so the channel is never closed
+				RaceOutput::Progress(None) | RaceOutput::Task(None) => continue,
+				RaceOutput::Task(Some(task)) => task,
+			};
+
+			match task {
+				Ok(TaskStatus::Done((_, TaskOutput::Out(out)))) => {
+					self.process_extract_output(
+						*out.downcast::<extract::Output>()
+							.expect("the extractor job only dispatches extract tasks"),
+					);
+					self.report_progress(&ctx);
+				}
+
+				Ok(TaskStatus::Done((task_id, TaskOutput::Empty))) => {
+					warn!("Task <id='{task_id}'> returned an empty output");
+				}
+
+				Ok(TaskStatus::Shutdown(task)) => {
+					self.tasks_for_shutdown.push(task);
+				}
+
+				Ok(TaskStatus::Error(e)) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e);
+				}
+
+				Ok(TaskStatus::Canceled | TaskStatus::ForcedAbortion) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Ok(ReturnStatus::Canceled);
+				}
+
+				Err(e) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e.into());
+				}
+			}
+		}
+
+		if !self.tasks_for_shutdown.is_empty() {
+			return Ok(ReturnStatus::Shutdown(
+				SerializableJob::<Ctx>::serialize(self).await,
+			));
+		}
+
+		ctx.invalidate_query("search.paths");
+
+		let Self {
+			metadata, errors, ..
+		} = self;
+
+		Ok(ReturnStatus::Completed(
+			JobReturn::builder()
+				.with_metadata(metadata)
+				.with_non_critical_errors(errors)
+				.build(),
+		))
+	}
+}
+
+impl Extractor {
+	/// Extracts the archive at `archive_file_path_id` of `location` into its `target_sub_path`,
+	/// created if missing
+	pub fn new(
+		location: location::Data,
+		archive_file_path_id: file_path::id::Type,
+		target_sub_path: impl AsRef<Path>,
+	) -> Result<Self, archiver::Error> {
+		let location_path = maybe_missing(&location.path, "location.path").map(PathBuf::from)?;
+		let target_path = location_path.join(target_sub_path);
+
+		let (progress_tx, progress_rx) = chan::unbounded();
+
+		Ok(Self {
+			location: Arc::new(location),
+			location_path: Arc::new(location_path),
+			archive_file_path_id,
+			target_path,
+			conflict_policy: ConflictPolicy::default(),
+			password: None,
+			metadata: Metadata::default(),
+			current_entry: None,
+			waiting_for_password: false,
+			progress_tx,
+			progress_rx,
+			errors: Vec::new(),
+			pending_tasks_on_resume: Vec::new(),
+			tasks_for_shutdown: Vec::new(),
+		})
+	}
+
+	#[must_use]
+	pub const fn with_conflict_policy(mut self, conflict_policy: ConflictPolicy) -> Self {
+		self.conflict_policy = conflict_policy;
+		self
+	}
+
+	/// Password of encrypted zips. It isn't saved with the job, so extractions resumed after a
+	/// shutdown wait for it to be given again, see [`Extract`].
+	#[must_use]
+	pub fn with_password(mut self, password: impl Into<String>) -> Self {
+		self.password = Some(password.into());
+		self
+	}
+
+	async fn init_or_resume(
+		&mut self,
+		pending_running_tasks: &mut FuturesUnordered<TaskHandle<Error>>,
+		ctx: &impl OuterContext,
+		dispatcher: &JobTaskDispatcher,
+	) -> Result<(), archiver::Error> {
+		// Resumed tasks were already dispatched by `resume_tasks`
+		if !self.pending_tasks_on_resume.is_empty() {
+			pending_running_tasks.extend(mem::take(&mut self.pending_tasks_on_resume));
+
+			return Ok(());
+		}
+
+		let file_path = ctx
+			.db()
+			.file_path()
+			.find_first(vec![
+				file_path::location_id::equals(Some(self.location.id)),
+				file_path::id::equals(self.archive_file_path_id),
+			])
+			.select(file_path_for_file_copier::select())
+			.exec()
+			.await?
+			.ok_or(archiver::Error::FilePathNotFound(self.archive_file_path_id))?;
+
+		let archive_path = self.location_path.join(IsolatedFilePathData::try_from((
+			self.location.id,
+			&file_path,
+		))?);
+
+		let format = ExtractionFormat::from_path(&archive_path)
+			.ok_or_else(|| archiver::Error::UnknownFormat(archive_path.clone()))?;
+
+		self.metadata.archive_size = fs::metadata(&archive_path)
+			.await
+			.map_err(|e| FileIOError::from((&archive_path, e)))?
+			.len();
+
+		fs::create_dir_all(&self.target_path).await.map_err(|e| {
+			FileIOError::from((&self.target_path, e, "Failed to create target directory"))
+		})?;
+
+		debug!(
+			"Extracting \"{}\", {} bytes, into \"{}\"",
+			archive_path.display(),
+			self.metadata.archive_size,
+			self.target_path.display()
+		);
+
+		pending_running_tasks.push(
+			dispatcher
+				.dispatch(Extract::new(
+					archive_path,
+					format,
+					self.target_path.clone(),
+					self.conflict_policy,
+					self.password.clone(),
+					Arc::clone(ctx.key_manager()),
+					self.progress_tx.clone(),
+				))
+				.await,
+		);
+
+		self.report_progress(ctx);
+
+		Ok(())
+	}
+
+	fn process_progress(&mut self, progress: ArchiveProgress) {
+		match progress {
+			ArchiveProgress::Started(path) => {
+				self.metadata.completed_entries += 1;
+				self.current_entry = Some(path);
+				self.waiting_for_password = false;
+			}
+			ArchiveProgress::Processed(bytes) => self.metadata.read_bytes += bytes,
+			ArchiveProgress::PasswordRequired => self.waiting_for_password = true,
+		}
+	}
+
+	fn report_progress(&self, ctx: &impl OuterContext) {
+		// Tarballs are read again up to where a job resumed after a shutdown stopped
+		let read_bytes = self.metadata.read_bytes.min(self.metadata.archive_size);
+
+		let message = if self.waiting_for_password {
+			"Waiting for the archive's password to be given again to resume extracting it"
+				.to_string()
+		} else {
+			self.current_entry.as_ref().map_or_else(
+				|| format!("Extracted {read_bytes} bytes"),
+				|path| {
+					format!(
+						"Extracting \"{}\", {read_bytes} of {} bytes",
+						path.display(),
+						self.metadata.archive_size
+					)
+				},
+			)
+		};
+
+		// Archives are measured by how much of them was read, as the number of entries of tarballs
+		// is only known after reading them whole
+		ctx.progress(vec![
+			ProgressUpdate::TaskCount(self.metadata.archive_size),
+			ProgressUpdate::CompletedTaskCount(read_bytes),
+			ProgressUpdate::Message(message),
+		]);
+	}
+
+	fn process_extract_output(
+		&mut self,
+		extract::Output {
+			extracted_files,
+			skipped_files,
+			failed_entries,
+			extract_time,
+			errors,
+		}: extract::Output,
+	) {
+		self.metadata.extracted_files += extracted_files;
+		self.metadata.skipped_files += skipped_files;
+		self.metadata.failed_entries += failed_entries;
+		self.metadata.extract_time += extract_time;
+
+		self.errors.extend(errors);
+	}
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Metadata {
+	archive_size: u64,
+	read_bytes: u64,
+	completed_entries: u64,
+	extracted_files: u64,
+	skipped_files: u64,
+	failed_entries: u64,
+	extract_time: Duration,
+}
+
+impl From<Metadata> for ReportOutputMetadata {
+	fn from(value: Metadata) -> Self {
+		Self::Metrics(HashMap::from([
+			("archive_size".into(), json!(value.archive_size)),
+			("extracted_files".into(), json!(value.extracted_files)),
+			("skipped_files".into(), json!(value.skipped_files)),
+			("failed_entries".into(), json!(value.failed_entries)),
+			("extract_time".into(), json!(value.extract_time)),
+		]))
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+struct SaveState {
+	location: Arc<location::Data>,
+	location_path: Arc<PathBuf>,
+	archive_file_path_id: file_path::id::Type,
+	target_path: PathBuf,
+	conflict_policy: ConflictPolicy,
+
+	metadata: Metadata,
+
+	errors: Vec<NonCriticalError>,
+
+	tasks_for_shutdown_bytes: Option<SerializedTasks>,
+}
+
+impl<Ctx: OuterContext> SerializableJob<Ctx> for Extractor {
+	async fn serialize(self) -> Result<Option<Vec<u8>>, rmp_serde::encode::Error> {
+		let Self {
+			location,
+			location_path,
+			archive_file_path_id,
+			target_path,
+			conflict_policy,
+			metadata,
+			errors,
+			tasks_for_shutdown,
+			..
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState {
+			location,
+			location_path,
+			archive_file_path_id,
+			target_path,
+			conflict_policy,
+			metadata,
+			errors,
+			tasks_for_shutdown_bytes: Some(SerializedTasks(rmp_serde::to_vec_named(
+				&tasks_for_shutdown
+					.into_iter()
+					.map(|task| async move {
+						task.downcast::<Extract>()
+							.expect("the extractor job only dispatches extract tasks")
+							.serialize()
+							.await
+					})
+					.collect::<Vec<_>>()
+					.try_join()
+					.await?,
+			)?)),
+		})
+		.map(Some)
+	}
+
+	async fn deserialize(
+		serialized_job: &[u8],
+		_: &Ctx,
+	) -> Result<Option<(Self, Option<SerializedTasks>)>, rmp_serde::decode::Error> {
+		let SaveState {
+			location,
+			location_path,
+			archive_file_path_id,
+			target_path,
+			conflict_policy,
+			metadata,
+			errors,
+			tasks_for_shutdown_bytes,
+		} = rmp_serde::from_slice::<SaveState>(serialized_job)?;
+
+		let (progress_tx, progress_rx) = chan::unbounded();
+
+		Ok(Some((
+			Self {
+				location,
+				location_path,
+				archive_file_path_id,
+				target_path,
+				conflict_policy,
+				password: None,
+				metadata,
+				current_entry: None,
+				waiting_for_password: false,
+				progress_tx,
+				progress_rx,
+				errors,
+				pending_tasks_on_resume: Vec::new(),
+				tasks_for_shutdown: Vec::new(),
+			},
+			tasks_for_shutdown_bytes,
+		)))
+	}
+}
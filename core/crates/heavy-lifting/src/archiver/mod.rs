@@ -0,0 +1,176 @@
+use sd_core_file_path_helper::FilePathError;
+
+use sd_prisma::prisma::file_path;
+use sd_utils::{db::MissingFieldError, error::FileIOError};
+
+use std::path::{Path, PathBuf};
+
+use prisma_client_rust::QueryError;
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+pub mod compressor;
+pub mod extractor;
+mod tasks;
+
+pub use crate::file_copier::ConflictPolicy;
+pub use compressor::Compressor;
+pub use extractor::Extractor;
+pub use tasks::{compress, extract};
+
+// Bytes read between progress reports, from files being compressed or entries being extracted
+const PROGRESS_CHUNK_SIZE: u64 = 1024 * 1024; // 1 MiB
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("file_path not found in location: <id='{0}'>")]
+	FilePathNotFound(file_path::id::Type),
+	#[error("not a zip, tar, tar.gz or tar.zst archive: <path='{}'>", .0.display())]
+	UnknownFormat(PathBuf),
+	#[error("archive is password protected: <path='{}'>", .0.display())]
+	PasswordRequired(PathBuf),
+	#[error("wrong password for archive: <path='{}'>", .0.display())]
+	WrongPassword(PathBuf),
+	#[error("failed to find an available name for the archive: <path='{}'>", .0.display())]
+	FailedToFindAvailableName(PathBuf),
+	#[error("failed to write archive: <path='{}'>: {1}", .0.display())]
+	Compress(PathBuf, String),
+	#[error("failed to read archive: <path='{}'>: {1}", .0.display())]
+	Extract(PathBuf, String),
+	#[error("missing field on database: {0}")]
+	MissingField(#[from] MissingFieldError),
+	#[error("database error: {0}")]
+	Database(#[from] QueryError),
+	#[error("failed to deserialized stored tasks for job resume: {0}")]
+	DeserializeTasks(#[from] rmp_serde::decode::Error),
+
+	#[error(transparent)]
+	FilePathError(#[from] FilePathError),
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+}
+
+impl From<Error> for rspc::Error {
+	fn from(err: Error) -> Self {
+		match err {
+			Error::FilePathNotFound(_) => {
+				Self::with_cause(ErrorCode::NotFound, err.to_string(), err)
+			}
+
+			Error::UnknownFormat(_) | Error::PasswordRequired(_) | Error::WrongPassword(_) => {
+				Self::with_cause(ErrorCode::BadRequest, err.to_string(), err)
+			}
+
+			_ => Self::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize, Type)]
+pub enum NonCriticalError {
+	#[error(transparent)]
+	Extract(#[from] extract::NonCriticalError),
+}
+
+/// Formats archives are created in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum CompressionFormat {
+	/// Deflate compressed zip, opened by every platform without extra tools
+	#[default]
+	Zip,
+	/// Tarball compressed with Zstandard, smaller and faster but needing a recent archiver
+	TarZstd,
+}
+
+impl CompressionFormat {
+	#[must_use]
+	pub const fn extension(self) -> &'static str {
+		match self {
+			Self::Zip => "zip",
+			Self::TarZstd => "tar.zst",
+		}
+	}
+}
+
+/// Formats archives can be extracted from, chosen by extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExtractionFormat {
+	Zip,
+	Tar,
+	TarGz,
+	TarZstd,
+}
+
+impl ExtractionFormat {
+	#[must_use]
+	pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+		let name = path.as_ref().file_name()?.to_str()?.to_lowercase();
+
+		if name.ends_with(".zip") {
+			Some(Self::Zip)
+		} else if name.ends_with(".tar") {
+			Some(Self::Tar)
+		} else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+			Some(Self::TarGz)
+		} else if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+			Some(Self::TarZstd)
+		} else {
+			None
+		}
+	}
+}
+
+/// Path of an archive entry, with `/` separators, made relative and stripped of `.` components.
+/// `None` if nothing is left, or if it has `..` components or drive prefixes that could take it out
+/// of the target directory.
+pub(crate) fn sanitize_entry_path(entry_path: &str) -> Option<PathBuf> {
+	let components = entry_path
+		.split(['/', '\\'])
+		.filter(|component| !component.is_empty() && *component != ".")
+		.collect::<Vec<_>>();
+
+	(!components.is_empty()
+		&& !components
+			.iter()
+			.any(|component| *component == ".." || component.contains(':')))
+	.then(|| components.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn extraction_formats() {
+		assert_eq!(
+			ExtractionFormat::from_path("/a/Photos.ZIP"),
+			Some(ExtractionFormat::Zip)
+		);
+		assert_eq!(
+			ExtractionFormat::from_path("backup.tar.zst"),
+			Some(ExtractionFormat::TarZstd)
+		);
+		assert_eq!(
+			ExtractionFormat::from_path("backup.tgz"),
+			Some(ExtractionFormat::TarGz)
+		);
+		assert_eq!(ExtractionFormat::from_path("notes.txt"), None);
+	}
+
+	#[test]
+	fn entry_paths_stay_inside_target() {
+		assert_eq!(
+			sanitize_entry_path("./docs//notes.txt"),
+			Some(PathBuf::from("docs").join("notes.txt"))
+		);
+		assert_eq!(
+			sanitize_entry_path("/etc/passwd"),
+			Some(PathBuf::from("etc").join("passwd"))
+		);
+		assert_eq!(sanitize_entry_path("../../.bashrc"), None);
+		assert_eq!(sanitize_entry_path("a\\..\\..\\b"), None);
+		assert_eq!(sanitize_entry_path("C:\\Windows\\win.ini"), None);
+		assert_eq!(sanitize_entry_path("./"), None);
+	}
+}
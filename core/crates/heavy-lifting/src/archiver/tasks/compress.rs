@@ -0,0 +1,415 @@
+use crate::{
+	archiver::{self, CompressionFormat},
+	file_copier::copier::remove_partial,
+	Error,
+};
+
+use sd_task_system::{
+	ExecStatus, Interrupter, InterruptionKind, IntoAnyTaskOutput, SerializableTask, Task, TaskId,
+};
+use sd_utils::error::FileIOError;
+
+use std::{
+	fmt,
+	fs::File,
+	future::IntoFuture,
+	io::{self, Read},
+	mem,
+	path::{Path, PathBuf},
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc, Mutex, PoisonError,
+	},
+	time::{Duration, SystemTime},
+};
+
+use async_channel as chan;
+use chrono::{DateTime, Datelike, Local, Timelike};
+use futures::FutureExt;
+use futures_concurrency::future::Race;
+use serde::{Deserialize, Serialize};
+use tokio::{fs, task::spawn_blocking, time::Instant};
+use tracing::trace;
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+use super::{ArchiveProgress, ProgressReader};
+
+// Above this size, zip entries need the zip64 extension
+const ZIP64_THRESHOLD: u64 = 0xFFFF_FFFF;
+
+/// A file or directory to put in the archive
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveEntry {
+	pub source: PathBuf,
+	/// Path inside the archive, with `/` separators
+	pub name: String,
+	pub is_dir: bool,
+}
+
+/// Writes every entry into a single archive, at a partial path the job moves into place.
+///
+/// Entries are written one after the other on a blocking thread, interruptions being checked
+/// between them. A paused task resumes with the archive it was writing, but one shut down
+/// starts over, as half written archives can't be saved.
+#[derive(Debug)]
+pub struct Compress {
+	id: TaskId,
+	partial: PathBuf,
+	format: CompressionFormat,
+	entries: Arc<Vec<ArchiveEntry>>,
+	next: usize,
+	writer: Option<Mutex<ArchiveWriter>>,
+	progress_tx: chan::Sender<(TaskId, ArchiveProgress)>,
+	output: Output,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Output {
+	pub compressed_files: u64,
+	pub archive_size: u64,
+	pub compress_time: Duration,
+}
+
+enum ArchiveWriter {
+	Zip(ZipWriter<File>),
+	TarZstd(tar::Builder<zstd::Encoder<'static, File>>),
+}
+
+impl fmt::Debug for ArchiveWriter {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Zip(_) => f.write_str("ArchiveWriter::Zip"),
+			Self::TarZstd(_) => f.write_str("ArchiveWriter::TarZstd"),
+		}
+	}
+}
+
+/// How far a blocking run of [`write_entries`] went
+struct Written {
+	/// `None` once the archive is finished
+	writer: Option<ArchiveWriter>,
+	next: usize,
+	files: u64,
+}
+
+impl Compress {
+	#[must_use]
+	pub fn new(
+		partial: PathBuf,
+		format: CompressionFormat,
+		entries: Vec<ArchiveEntry>,
+		progress_tx: chan::Sender<(TaskId, ArchiveProgress)>,
+	) -> Self {
+		Self {
+			id: TaskId::new_v4(),
+			partial,
+			format,
+			entries: Arc::new(entries),
+			next: 0,
+			writer: None,
+			progress_tx,
+			output: Output::default(),
+		}
+	}
+}
+
+impl ArchiveWriter {
+	fn create(path: &Path, format: CompressionFormat) -> io::Result<Self> {
+		let file = File::create(path)?;
+
+		Ok(match format {
+			CompressionFormat::Zip => Self::Zip(ZipWriter::new(file)),
+			CompressionFormat::TarZstd => Self::TarZstd(tar::Builder::new(zstd::Encoder::new(
+				file,
+				zstd::DEFAULT_COMPRESSION_LEVEL,
+			)?)),
+		})
+	}
+
+	fn append(
+		&mut self,
+		entry: &ArchiveEntry,
+		progress: impl FnOnce(File) -> ProgressReader<File>,
+	) -> Result<(), archiver::Error> {
+		let ArchiveEntry {
+			source,
+			name,
+			is_dir,
+		} = entry;
+
+		if *is_dir {
+			return match self {
+				Self::Zip(zip) => zip
+					.add_directory(name.as_str(), FileOptions::default())
+					.map_err(io::Error::from),
+				Self::TarZstd(tar) => tar.append_dir(name, source),
+			}
+			.map_err(|e| archiver::Error::Compress(source.clone(), e.to_string()));
+		}
+
+		let file = File::open(source).map_err(|e| FileIOError::from((source, e)))?;
+		let metadata = file
+			.metadata()
+			.map_err(|e| FileIOError::from((source, e)))?;
+		let size = metadata.len();
+
+		// Files growing while compressed would corrupt tar headers, which hold their size
+		let mut reader = progress(file).take(size);
+
+		match self {
+			Self::Zip(zip) => {
+				let mut options = FileOptions::default()
+					.compression_method(CompressionMethod::Deflated)
+					.large_file(size >= ZIP64_THRESHOLD);
+
+				if let Some(modified) = metadata.modified().ok().and_then(zip_date_time) {
+					options = options.last_modified_time(modified);
+				}
+
+				zip.start_file(name.as_str(), options)
+					.map_err(io::Error::from)
+					.and_then(|()| io::copy(&mut reader, zip))
+					.map(|_| ())
+			}
+			Self::TarZstd(tar) => {
+				let mut header = tar::Header::new_gnu();
+				header.set_metadata(&metadata);
+
+				tar.append_data(&mut header, name, reader)
+			}
+		}
+		.map_err(|e| archiver::Error::Compress(source.clone(), e.to_string()))
+	}
+
+	fn finish(self) -> io::Result<()> {
+		let file = match self {
+			Self::Zip(mut zip) => zip.finish()?,
+			Self::TarZstd(tar) => tar.into_inner()?.finish()?,
+		};
+
+		file.sync_all()
+	}
+}
+
+/// Zip entries hold local times, without time zone, from 1980 to 2107
+fn zip_date_time(modified: SystemTime) -> Option<zip::DateTime> {
+	let date = DateTime::<Local>::from(modified);
+
+	zip::DateTime::from_date_and_time(
+		u16::try_from(date.year()).ok()?,
+		u8::try_from(date.month()).ok()?,
+		u8::try_from(date.day()).ok()?,
+		u8::try_from(date.hour()).ok()?,
+		u8::try_from(date.minute()).ok()?,
+		u8::try_from(date.second()).ok()?,
+	)
+	.ok()
+}
+
+/// Appends entries from `next` until all are written, then finishes the archive, or until `stop`
+/// is set
+fn write_entries(
+	partial: &Path,
+	format: CompressionFormat,
+	entries: &[ArchiveEntry],
+	mut next: usize,
+	writer: Option<ArchiveWriter>,
+	stop: &AtomicBool,
+	(task_id, progress_tx): (TaskId, chan::Sender<(TaskId, ArchiveProgress)>),
+) -> Result<Written, archiver::Error> {
+	let failed = |e: io::Error| archiver::Error::Compress(partial.to_path_buf(), e.to_string());
+
+	let mut writer = match writer {
+		Some(writer) => writer,
+		None => ArchiveWriter::create(partial, format).map_err(failed)?,
+	};
+	let mut files = 0;
+
+	while let Some(entry) = entries.get(next) {
+		if stop.load(Ordering::Relaxed) {
+			return Ok(Written {
+				writer: Some(writer),
+				next,
+				files,
+			});
+		}
+
+		if progress_tx
+			.send_blocking((task_id, ArchiveProgress::Started(entry.source.clone())))
+			.is_err()
+		{
+			trace!("Job stopped listening to archive progress");
+		}
+
+		writer.append(entry, |file| {
+			ProgressReader::new(file, task_id, progress_tx.clone())
+		})?;
+
+		if !entry.is_dir {
+			files += 1;
+		}
+		next += 1;
+	}
+
+	writer.finish().map_err(failed)?;
+
+	Ok(Written {
+		writer: None,
+		next,
+		files,
+	})
+}
+
+#[async_trait::async_trait]
+impl Task<Error> for Compress {
+	fn id(&self) -> TaskId {
+		self.id
+	}
+
+	async fn run(&mut self, interrupter: &Interrupter) -> Result<ExecStatus, Error> {
+		enum RaceOutput {
+			Written(Result<Result<Written, archiver::Error>, tokio::task::JoinError>),
+			Interrupted(InterruptionKind),
+		}
+
+		let start = Instant::now();
+		let stop = Arc::new(AtomicBool::new(false));
+
+		let mut handle = spawn_blocking({
+			let partial = self.partial.clone();
+			let format = self.format;
+			let entries = Arc::clone(&self.entries);
+			let next = self.next;
+			let writer = self
+				.writer
+				.take()
+				.map(|writer| writer.into_inner().unwrap_or_else(PoisonError::into_inner));
+			let stop = Arc::clone(&stop);
+			let progress = (self.id, self.progress_tx.clone());
+
+			move || write_entries(&partial, format, &entries, next, writer, &stop, progress)
+		});
+
+		let output = (
+			(&mut handle).map(RaceOutput::Written),
+			interrupter.into_future().map(RaceOutput::Interrupted),
+		)
+			.race()
+			.await;
+
+		let (written, interruption) = match output {
+			RaceOutput::Written(written) => (written, None),
+			RaceOutput::Interrupted(kind) => {
+				// Stops after the entry being written, as zip and tar can't stop in the middle
+				stop.store(true, Ordering::Relaxed);
+				(handle.await, Some(kind))
+			}
+		};
+
+		self.output.compress_time += start.elapsed();
+
+		let written = match written
+			.map_err(|e| archiver::Error::Compress(self.partial.clone(), e.to_string()))
+			.and_then(|written| written)
+		{
+			Ok(written) => written,
+			Err(e) => {
+				remove_partial(&self.partial).await;
+				return Err(e.into());
+			}
+		};
+
+		self.next = written.next;
+		self.output.compressed_files += written.files;
+
+		match (written.writer, interruption) {
+			(None, _) => {
+				self.output.archive_size = fs::metadata(&self.partial)
+					.await
+					.map_err(|e| FileIOError::from((&self.partial, e)))
+					.map_err(archiver::Error::from)?
+					.len();
+
+				Ok(ExecStatus::Done(mem::take(&mut self.output).into_output()))
+			}
+
+			(Some(writer), Some(InterruptionKind::Cancel)) => {
+				drop(writer);
+				remove_partial(&self.partial).await;
+
+				Ok(ExecStatus::Canceled)
+			}
+
+			// Only stopping when interrupted, so this is a pause
+			(Some(writer), _) => {
+				self.writer = Some(Mutex::new(writer));
+
+				Ok(ExecStatus::Paused)
+			}
+		}
+	}
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SaveState {
+	id: TaskId,
+	partial: PathBuf,
+	format: CompressionFormat,
+	entries: Arc<Vec<ArchiveEntry>>,
+	compress_time: Duration,
+}
+
+impl SerializableTask<Error> for Compress {
+	type SerializeError = rmp_serde::encode::Error;
+
+	type DeserializeError = rmp_serde::decode::Error;
+
+	type DeserializeCtx = chan::Sender<(TaskId, ArchiveProgress)>;
+
+	async fn serialize(self) -> Result<Vec<u8>, Self::SerializeError> {
+		let Self {
+			id,
+			partial,
+			format,
+			entries,
+			output,
+			..
+		} = self;
+
+		// The archive is written again from its first entry on resume
+		rmp_serde::to_vec_named(&SaveState {
+			id,
+			partial,
+			format,
+			entries,
+			compress_time: output.compress_time,
+		})
+	}
+
+	async fn deserialize(
+		data: &[u8],
+		progress_tx: Self::DeserializeCtx,
+	) -> Result<Self, Self::DeserializeError> {
+		rmp_serde::from_slice(data).map(
+			|SaveState {
+			     id,
+			     partial,
+			     format,
+			     entries,
+			     compress_time,
+			 }| Self {
+				id,
+				partial,
+				format,
+				entries,
+				next: 0,
+				writer: None,
+				progress_tx,
+				output: Output {
+					compress_time,
+					..Default::default()
+				},
+			},
+		)
+	}
+}
@@ -0,0 +1,540 @@
+use crate::{
+	archiver::{self, sanitize_entry_path, ConflictPolicy, ExtractionFormat},
+	crypto::KeyManager,
+	file_copier::{available_path, copier::partial_path, exists},
+	Error,
+};
+
+use sd_task_system::{
+	ExecStatus, Interrupter, InterruptionKind, IntoAnyTaskOutput, SerializableTask, Task, TaskId,
+};
+
+use std::{
+	fs::{self, File},
+	future::IntoFuture,
+	io::{self, Read},
+	mem,
+	path::{Path, PathBuf},
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc,
+	},
+	time::Duration,
+};
+
+use async_channel as chan;
+use flate2::read::GzDecoder;
+use futures::FutureExt;
+use futures_concurrency::future::Race;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::{runtime::Handle, task::spawn_blocking, time::Instant};
+use tracing::{trace, warn};
+use zip::{result::ZipError, ZipArchive};
+
+use super::{ArchiveProgress, ProgressReader};
+
+/// Extracts every entry of an archive into a directory, one after the other on a blocking thread,
+/// interruptions being checked between them.
+///
+/// Files are written under a partial name and moved into place once complete, so an interrupted
+/// extraction never leaves truncated files. Directories are always merged into existing ones, the
+/// conflict policy applying to the files in them.
+///
+/// The password of encrypted zips isn't saved with the task. A task resumed after a shutdown pauses
+/// itself until the password is given again with
+/// [`KeyManager::provide_archive_password`] and its job is resumed.
+#[derive(Debug)]
+pub struct Extract {
+	id: TaskId,
+	archive_path: PathBuf,
+	format: ExtractionFormat,
+	target_dir: PathBuf,
+	conflict_policy: ConflictPolicy,
+	password: Option<String>,
+	/// Whether the archive needs a password, to wait for it when resumed without one
+	encrypted: bool,
+	keys: Arc<KeyManager>,
+	/// Index of the first entry left to extract
+	next: usize,
+	progress_tx: chan::Sender<(TaskId, ArchiveProgress)>,
+	output: Output,
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize, Type)]
+pub enum NonCriticalError {
+	#[error("archive entry would be extracted outside of the target directory: <name='{0}'>")]
+	UnsafeEntryPath(String),
+	#[error("archive entry is a link or special file, which aren't extracted: <name='{0}'>")]
+	UnsupportedEntry(String),
+	#[error("failed to find an available name to avoid a conflict: <path='{}'>", .0.display())]
+	FailedToFindAvailableName(PathBuf),
+	#[error("failed to extract archive entry: <path='{}'>: {1}", .0.display())]
+	FailedToExtract(PathBuf, String),
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Output {
+	pub extracted_files: u64,
+	pub skipped_files: u64,
+	pub failed_entries: u64,
+	pub extract_time: Duration,
+	pub errors: Vec<crate::NonCriticalError>,
+}
+
+enum EntryOutcome {
+	File,
+	Directory,
+	Skipped,
+}
+
+/// What extracting entries needs on the blocking thread
+struct ExtractCtx {
+	archive_path: PathBuf,
+	target_dir: PathBuf,
+	conflict_policy: ConflictPolicy,
+	password: Option<String>,
+	task_id: TaskId,
+	progress_tx: chan::Sender<(TaskId, ArchiveProgress)>,
+	runtime: Handle,
+	stop: Arc<AtomicBool>,
+}
+
+/// How far a blocking run of [`extract_zip`] or [`extract_tar`] went
+struct Extracted {
+	next: usize,
+	done: bool,
+	output: Output,
+}
+
+impl Extract {
+	#[must_use]
+	pub fn new(
+		archive_path: PathBuf,
+		format: ExtractionFormat,
+		target_dir: PathBuf,
+		conflict_policy: ConflictPolicy,
+		password: Option<String>,
+		keys: Arc<KeyManager>,
+		progress_tx: chan::Sender<(TaskId, ArchiveProgress)>,
+	) -> Self {
+		Self {
+			id: TaskId::new_v4(),
+			archive_path,
+			format,
+			target_dir,
+			conflict_policy,
+			encrypted: password.is_some(),
+			password,
+			keys,
+			next: 0,
+			progress_tx,
+			output: Output::default(),
+		}
+	}
+}
+
+impl ExtractCtx {
+	fn read_failed(&self, e: impl ToString) -> archiver::Error {
+		archiver::Error::Extract(self.archive_path.clone(), e.to_string())
+	}
+
+	fn open(&self) -> Result<ProgressReader<File>, archiver::Error> {
+		File::open(&self.archive_path)
+			.map(|file| ProgressReader::new(file, self.task_id, self.progress_tx.clone()))
+			.map_err(|e| self.read_failed(e))
+	}
+
+	/// Extracts an entry, counting it in `output`
+	fn entry(
+		&self,
+		name: &str,
+		is_dir: bool,
+		is_file: bool,
+		data: &mut impl Read,
+		output: &mut Output,
+	) {
+		match self.extract_entry(name, is_dir, is_file, data) {
+			Ok(EntryOutcome::File) => output.extracted_files += 1,
+			Ok(EntryOutcome::Directory) => {}
+			Ok(EntryOutcome::Skipped) => output.skipped_files += 1,
+			Err(e) => {
+				warn!("{e}");
+				output.failed_entries += 1;
+				output
+					.errors
+					.push(archiver::NonCriticalError::from(e).into());
+			}
+		}
+	}
+
+	fn extract_entry(
+		&self,
+		name: &str,
+		is_dir: bool,
+		is_file: bool,
+		data: &mut impl Read,
+	) -> Result<EntryOutcome, NonCriticalError> {
+		let mut target = sanitize_entry_path(name)
+			.map(|relative| self.target_dir.join(relative))
+			.ok_or_else(|| NonCriticalError::UnsafeEntryPath(name.to_string()))?;
+
+		let failed = |path: &Path, e: io::Error| {
+			NonCriticalError::FailedToExtract(path.to_path_buf(), e.to_string())
+		};
+
+		if is_dir {
+			fs::create_dir_all(&target).map_err(|e| failed(&target, e))?;
+			return Ok(EntryOutcome::Directory);
+		}
+
+		if !is_file {
+			return Err(NonCriticalError::UnsupportedEntry(name.to_string()));
+		}
+
+		if let Some(parent) = target.parent() {
+			fs::create_dir_all(parent).map_err(|e| failed(parent, e))?;
+		}
+
+		let target_exists = self
+			.runtime
+			.block_on(exists(&target))
+			.map_err(|e| NonCriticalError::FailedToExtract(target.clone(), e.to_string()))?;
+
+		if target_exists {
+			match self.conflict_policy {
+				ConflictPolicy::Skip => {
+					trace!(
+						"Skipping extraction to existing <path='{}'>",
+						target.display()
+					);
+					return Ok(EntryOutcome::Skipped);
+				}
+				ConflictPolicy::Rename => {
+					target = self
+						.runtime
+						.block_on(available_path(&target))
+						.map_err(|e| {
+							NonCriticalError::FailedToExtract(target.clone(), e.to_string())
+						})?
+						.ok_or_else(|| {
+							NonCriticalError::FailedToFindAvailableName(target.clone())
+						})?;
+				}
+				ConflictPolicy::Overwrite => { /* Replaced by the rename once extracted */ }
+			}
+		}
+
+		let partial = partial_path(&target);
+
+		let written = File::create(&partial).and_then(|mut file| {
+			io::copy(data, &mut file)?;
+			file.sync_all()
+		});
+
+		if let Err(e) = written.and_then(|()| fs::rename(&partial, &target)) {
+			if let Err(e) = fs::remove_file(&partial) {
+				warn!(
+					"Failed to remove partial extraction <path='{}'>: {e:#?}",
+					partial.display()
+				);
+			}
+
+			return Err(failed(&target, e));
+		}
+
+		Ok(EntryOutcome::File)
+	}
+
+	fn started(&self, name: &str) {
+		if self
+			.progress_tx
+			.send_blocking((self.task_id, ArchiveProgress::Started(PathBuf::from(name))))
+			.is_err()
+		{
+			trace!("Job stopped listening to archive progress");
+		}
+	}
+}
+
+/// Extracts zip entries from `next` until all are extracted, or until `stop` is set
+fn extract_zip(ctx: &ExtractCtx, mut next: usize) -> Result<Extracted, archiver::Error> {
+	let zip_failed = |e: ZipError| match e {
+		ZipError::UnsupportedArchive(reason) if reason == ZipError::PASSWORD_REQUIRED => {
+			archiver::Error::PasswordRequired(ctx.archive_path.clone())
+		}
+		e => ctx.read_failed(e),
+	};
+
+	let mut archive = ZipArchive::new(ctx.open()?).map_err(zip_failed)?;
+	let mut output = Output::default();
+
+	while next < archive.len() {
+		if ctx.stop.load(Ordering::Relaxed) {
+			return Ok(Extracted {
+				next,
+				done: false,
+				output,
+			});
+		}
+
+		let mut entry = match &ctx.password {
+			Some(password) => archive
+				.by_index_decrypt(next, password.as_bytes())
+				.map_err(zip_failed)?
+				.map_err(|_| archiver::Error::WrongPassword(ctx.archive_path.clone()))?,
+			None => archive.by_index(next).map_err(zip_failed)?,
+		};
+
+		let name = entry.name().to_string();
+		let is_dir = entry.is_dir();
+
+		ctx.started(&name);
+		ctx.entry(&name, is_dir, !is_dir, &mut entry, &mut output);
+
+		next += 1;
+	}
+
+	Ok(Extracted {
+		next,
+		done: true,
+		output,
+	})
+}
+
+/// Extracts tar entries from `next` until all are extracted, or until `stop` is set. Tarballs can
+/// only be read in order, so entries before `next` are read through again.
+fn extract_tar(
+	ctx: &ExtractCtx,
+	format: ExtractionFormat,
+	mut next: usize,
+) -> Result<Extracted, archiver::Error> {
+	let reader = ctx.open()?;
+
+	let reader: Box<dyn Read> = match format {
+		ExtractionFormat::TarGz => Box::new(GzDecoder::new(reader)),
+		ExtractionFormat::TarZstd => {
+			Box::new(zstd::Decoder::new(reader).map_err(|e| ctx.read_failed(e))?)
+		}
+		ExtractionFormat::Tar | ExtractionFormat::Zip => Box::new(reader),
+	};
+
+	let mut archive = tar::Archive::new(reader);
+	let mut output = Output::default();
+
+	for (index, entry) in archive
+		.entries()
+		.map_err(|e| ctx.read_failed(e))?
+		.enumerate()
+		.skip(next)
+	{
+		if ctx.stop.load(Ordering::Relaxed) {
+			return Ok(Extracted {
+				next,
+				done: false,
+				output,
+			});
+		}
+
+		let mut entry = entry.map_err(|e| ctx.read_failed(e))?;
+
+		let name = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+		let entry_type = entry.header().entry_type();
+
+		ctx.started(&name);
+		ctx.entry(
+			&name,
+			entry_type.is_dir(),
+			entry_type.is_file(),
+			&mut entry,
+			&mut output,
+		);
+
+		next = index + 1;
+	}
+
+	Ok(Extracted {
+		next,
+		done: true,
+		output,
+	})
+}
+
+#[async_trait::async_trait]
+impl Task<Error> for Extract {
+	fn id(&self) -> TaskId {
+		self.id
+	}
+
+	async fn run(&mut self, interrupter: &Interrupter) -> Result<ExecStatus, Error> {
+		enum RaceOutput {
+			Extracted(Result<Result<Extracted, archiver::Error>, tokio::task::JoinError>),
+			Interrupted(InterruptionKind),
+		}
+
+		if self.encrypted && self.password.is_none() {
+			self.password = self.keys.take_archive_password(&self.archive_path);
+
+			if self.password.is_none() {
+				if self
+					.progress_tx
+					.send((self.id, ArchiveProgress::PasswordRequired))
+					.await
+					.is_err()
+				{
+					trace!("Job stopped listening to archive progress");
+				}
+
+				return Ok(ExecStatus::Paused);
+			}
+		}
+
+		let start = Instant::now();
+		let stop = Arc::new(AtomicBool::new(false));
+
+		let mut handle = spawn_blocking({
+			let ctx = ExtractCtx {
+				archive_path: self.archive_path.clone(),
+				target_dir: self.target_dir.clone(),
+				conflict_policy: self.conflict_policy,
+				password: self.password.clone(),
+				task_id: self.id,
+				progress_tx: self.progress_tx.clone(),
+				runtime: Handle::current(),
+				stop: Arc::clone(&stop),
+			};
+			let format = self.format;
+			let next = self.next;
+
+			move || match format {
+				ExtractionFormat::Zip => extract_zip(&ctx, next),
+				format => extract_tar(&ctx, format, next),
+			}
+		});
+
+		let output = (
+			(&mut handle).map(RaceOutput::Extracted),
+			interrupter.into_future().map(RaceOutput::Interrupted),
+		)
+			.race()
+			.await;
+
+		let (extracted, interruption) = match output {
+			RaceOutput::Extracted(extracted) => (extracted, None),
+			RaceOutput::Interrupted(kind) => {
+				// Stops after the entry being extracted
+				stop.store(true, Ordering::Relaxed);
+				(handle.await, Some(kind))
+			}
+		};
+
+		self.output.extract_time += start.elapsed();
+
+		let Extracted {
+			next,
+			done,
+			output:
+				Output {
+					extracted_files,
+					skipped_files,
+					failed_entries,
+					errors,
+					..
+				},
+		} = extracted
+			.map_err(|e| archiver::Error::Extract(self.archive_path.clone(), e.to_string()))
+			.and_then(|extracted| extracted)?;
+
+		self.next = next;
+		self.output.extracted_files += extracted_files;
+		self.output.skipped_files += skipped_files;
+		self.output.failed_entries += failed_entries;
+		self.output.errors.extend(errors);
+
+		if done {
+			return Ok(ExecStatus::Done(mem::take(&mut self.output).into_output()));
+		}
+
+		// Only stopping when interrupted. Canceling keeps what was already extracted.
+		Ok(match interruption {
+			Some(InterruptionKind::Cancel) => ExecStatus::Canceled,
+			_ => ExecStatus::Paused,
+		})
+	}
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SaveState {
+	id: TaskId,
+	archive_path: PathBuf,
+	format: ExtractionFormat,
+	target_dir: PathBuf,
+	conflict_policy: ConflictPolicy,
+	#[serde(default)]
+	encrypted: bool,
+	next: usize,
+	output: Output,
+}
+
+impl SerializableTask<Error> for Extract {
+	type SerializeError = rmp_serde::encode::Error;
+
+	type DeserializeError = rmp_serde::decode::Error;
+
+	type DeserializeCtx = (chan::Sender<(TaskId, ArchiveProgress)>, Arc<KeyManager>);
+
+	async fn serialize(self) -> Result<Vec<u8>, Self::SerializeError> {
+		let Self {
+			id,
+			archive_path,
+			format,
+			target_dir,
+			conflict_policy,
+			encrypted,
+			next,
+			output,
+			..
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState {
+			id,
+			archive_path,
+			format,
+			target_dir,
+			conflict_policy,
+			encrypted,
+			next,
+			output,
+		})
+	}
+
+	async fn deserialize(
+		data: &[u8],
+		(progress_tx, keys): Self::DeserializeCtx,
+	) -> Result<Self, Self::DeserializeError> {
+		rmp_serde::from_slice(data).map(
+			|SaveState {
+			     id,
+			     archive_path,
+			     format,
+			     target_dir,
+			     conflict_policy,
+			     encrypted,
+			     next,
+			     output,
+			 }| Self {
+				id,
+				archive_path,
+				format,
+				target_dir,
+				conflict_policy,
+				password: None,
+				encrypted,
+				keys,
+				next,
+				progress_tx,
+				output,
+			},
+		)
+	}
+}
@@ -0,0 +1,92 @@
+use sd_task_system::TaskId;
+
+use std::{
+	io::{self, Read, Seek, SeekFrom},
+	path::PathBuf,
+};
+
+use async_channel as chan;
+use tracing::trace;
+
+use super::PROGRESS_CHUNK_SIZE;
+
+pub mod compress;
+pub mod extract;
+
+pub use compress::Compress;
+pub use extract::Extract;
+
+/// Sent by archiver tasks as they go, so the job can report progress in the middle of large entries
+#[derive(Debug)]
+pub enum ArchiveProgress {
+	/// An entry started being compressed or extracted
+	Started(PathBuf),
+	/// Bytes read since the last update, from the files being compressed or the archive being
+	/// extracted
+	Processed(u64),
+	/// Extraction of an encrypted archive was resumed without its password, and waits for it
+	PasswordRequired,
+}
+
+/// Wraps what archiver tasks read from, reporting read bytes every [`PROGRESS_CHUNK_SIZE`].
+///
+/// Archives are read and written on blocking threads, so progress is sent without awaiting.
+pub(crate) struct ProgressReader<R> {
+	inner: R,
+	task_id: TaskId,
+	progress_tx: chan::Sender<(TaskId, ArchiveProgress)>,
+	unreported: u64,
+}
+
+impl<R> ProgressReader<R> {
+	pub(crate) const fn new(
+		inner: R,
+		task_id: TaskId,
+		progress_tx: chan::Sender<(TaskId, ArchiveProgress)>,
+	) -> Self {
+		Self {
+			inner,
+			task_id,
+			progress_tx,
+			unreported: 0,
+		}
+	}
+
+	fn report(&mut self) {
+		if self.unreported > 0
+			&& self
+				.progress_tx
+				.send_blocking((self.task_id, ArchiveProgress::Processed(self.unreported)))
+				.is_err()
+		{
+			trace!("Job stopped listening to archive progress");
+		}
+
+		self.unreported = 0;
+	}
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let read = self.inner.read(buf)?;
+
+		self.unreported += read as u64;
+		if self.unreported >= PROGRESS_CHUNK_SIZE {
+			self.report();
+		}
+
+		Ok(read)
+	}
+}
+
+impl<R: Seek> Seek for ProgressReader<R> {
+	fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+		self.inner.seek(pos)
+	}
+}
+
+impl<R> Drop for ProgressReader<R> {
+	fn drop(&mut self) {
+		self.report();
+	}
+}
@@ -0,0 +1,546 @@
+use crate::{
+	backup,
+	file_copier::{
+		copier::{self, Copier, CopyProgress},
+		ConflictPolicy,
+	},
+	file_identifier::CasIdAlgorithm,
+	job_system::{
+		job::{Job, JobReturn, JobTaskDispatcher, ReturnStatus},
+		report::ReportOutputMetadata,
+		utils::{cancel_pending_tasks, location_concurrency_key},
+		SerializableJob, SerializedTasks,
+	},
+	utils::io_throttle::IoThrottle,
+	Error, JobName, NonCriticalError, OuterContext, ProgressUpdate,
+};
+
+use sd_prisma::prisma::{file_path, location};
+use sd_task_system::{
+	IntoTask, SerializableTask, Task, TaskDispatcher, TaskHandle, TaskId, TaskOutput, TaskStatus,
+	TaskSystemError,
+};
+use sd_utils::{db::maybe_missing, error::FileIOError};
+
+use std::{
+	collections::HashMap,
+	hash::{Hash, Hasher},
+	mem,
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::Duration,
+};
+
+use async_channel as chan;
+use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
+use futures_concurrency::future::{Race, TryJoin};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::{fs, time::Instant};
+use tracing::{debug, warn};
+
+use super::{plan, BackupSide, DeletionPolicy};
+
+// How many files each copier task copies, one after the other
+const BATCH_SIZE: usize = 50;
+
+// Byte progress arrives every chunk of every task, the UI doesn't need it that often
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Mirrors a source location, or a selection of its file paths, into the same relative paths of a
+/// target location. Files whose `cas_id` matches the one already in the target are skipped, the
+/// others are copied and verified like the [`FileCopier`](crate::file_copier::FileCopier) does,
+/// and with [`DeletionPolicy::Mirror`] files of the target missing from the source are deleted.
+#[derive(Debug)]
+pub struct Backup {
+	source_location: Arc<location::Data>,
+	source_location_path: Arc<PathBuf>,
+	target_location_id: location::id::Type,
+	target_location_path: PathBuf,
+	file_path_ids: Option<Vec<file_path::id::Type>>,
+	deletion_policy: DeletionPolicy,
+	cas_id_algorithm: CasIdAlgorithm,
+	io_throttle: IoThrottle,
+
+	metadata: Metadata,
+	progress_tx: chan::Sender<(TaskId, CopyProgress)>,
+	progress_rx: chan::Receiver<(TaskId, CopyProgress)>,
+
+	errors: Vec<NonCriticalError>,
+
+	pending_tasks_on_resume: Vec<TaskHandle<Error>>,
+	tasks_for_shutdown: Vec<Box<dyn Task<Error>>>,
+}
+
+impl Hash for Backup {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.source_location.id.hash(state);
+		self.target_location_id.hash(state);
+		self.file_path_ids.hash(state);
+	}
+}
+
+impl Job for Backup {
+	const NAME: JobName = JobName::Backup;
+
+	async fn resume_tasks(
+		&mut self,
+		dispatcher: &JobTaskDispatcher,
+		ctx: &impl OuterContext,
+		SerializedTasks(serialized_tasks): SerializedTasks,
+	) -> Result<(), Error> {
+		let dispatcher = dispatcher
+			.with_concurrency_key(location_concurrency_key(ctx.id(), self.target_location_id));
+		let io_throttle = &dispatcher.io_throttle(&self.io_throttle);
+		let progress_tx = &self.progress_tx;
+
+		self.pending_tasks_on_resume = dispatcher
+			.dispatch_many_boxed(
+				rmp_serde::from_slice::<Vec<Vec<u8>>>(&serialized_tasks)
+					.map_err(backup::Error::from)?
+					.into_iter()
+					.map(|task_bytes| async move {
+						Copier::deserialize(&task_bytes, progress_tx.clone())
+							.await
+							.map(|task| task.with_io_throttle(io_throttle.clone()))
+							.map(IntoTask::into_task)
+					})
+					.collect::<Vec<_>>()
+					.try_join()
+					.await
+					.map_err(backup::Error::from)?,
+			)
+			.await;
+
+		Ok(())
+	}
+
+	async fn run<Ctx: OuterContext>(
+		mut self,
+		dispatcher: JobTaskDispatcher,
+		ctx: Ctx,
+	) -> Result<ReturnStatus, Error> {
+		enum RaceOutput {
+			Task(Option<Result<TaskStatus<Error>, TaskSystemError>>),
+			Progress(Option<(TaskId, CopyProgress)>),
+		}
+
+		let dispatcher = dispatcher
+			.with_concurrency_key(location_concurrency_key(ctx.id(), self.target_location_id));
+
+		let mut pending_running_tasks = FuturesUnordered::new();
+
+		self.init_or_resume(&mut pending_running_tasks, &ctx, &dispatcher)
+			.await?;
+
+		let mut last_progress_update = Instant::now();
+
+		while !pending_running_tasks.is_empty() {
+			let output = (
+				pending_running_tasks.next().map(RaceOutput::Task),
+				self.progress_rx
+					.recv()
+					.map(|res| RaceOutput::Progress(res.ok())),
+			)
+				.race()
+				.await;
+
+			let task = match output {
+				RaceOutput::Progress(Some((_, progress))) => {
+					self.process_progress(progress);
+
+					if last_progress_update.elapsed() > PROGRESS_INTERVAL {
+						last_progress_update = Instant::now();
+						self.report_progress(&ctx);
+					}
+
+					continue;
+				}
+				// The job holds a sender, so the channel is never closed
+				RaceOutput::Progress(None) | RaceOutput::Task(None) => continue,
+				RaceOutput::Task(Some(task)) => task,
+			};
+
+			match task {
+				Ok(TaskStatus::Done((_, TaskOutput::Out(out)))) => {
+					self.process_copier_output(
+						*out.downcast::<copier::Output>()
+							.expect("the backup job only dispatches copier tasks"),
+					);
+					self.report_progress(&ctx);
+				}
+
+				Ok(TaskStatus::Done((task_id, TaskOutput::Empty))) => {
+					warn!("Task <id='{task_id}'> returned an empty output");
+				}
+
+				Ok(TaskStatus::Shutdown(task)) => {
+					self.tasks_for_shutdown.push(task);
+				}
+
+				Ok(TaskStatus::Error(e)) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e);
+				}
+
+				Ok(TaskStatus::Canceled | TaskStatus::ForcedAbortion) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Ok(ReturnStatus::Canceled);
+				}
+
+				Err(e) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e.into());
+				}
+			}
+		}
+
+		while let Ok((_, progress)) = self.progress_rx.try_recv() {
+			self.process_progress(progress);
+		}
+
+		if !self.tasks_for_shutdown.is_empty() {
+			return Ok(ReturnStatus::Shutdown(
+				SerializableJob::<Ctx>::serialize(self).await,
+			));
+		}
+
+		ctx.invalidate_query("search.paths");
+
+		let Self {
+			metadata, errors, ..
+		} = self;
+
+		Ok(ReturnStatus::Completed(
+			JobReturn::builder()
+				.with_metadata(metadata)
+				.with_non_critical_errors(errors)
+				.build(),
+		))
+	}
+}
+
+impl Backup {
+	/// Backs up `file_path_ids` of `source_location`, the whole location if `None`, into
+	/// `target_location`. `cas_id_algorithm` must be the one the library's `cas_id`s were
+	/// generated with.
+	pub fn new(
+		source_location: location::Data,
+		target_location: &location::Data,
+		file_path_ids: Option<Vec<file_path::id::Type>>,
+		deletion_policy: DeletionPolicy,
+		cas_id_algorithm: CasIdAlgorithm,
+	) -> Result<Self, backup::Error> {
+		let (progress_tx, progress_rx) = chan::unbounded();
+
+		Ok(Self {
+			source_location_path: maybe_missing(&source_location.path, "location.path")
+				.map(PathBuf::from)
+				.map(Arc::new)?,
+			source_location: Arc::new(source_location),
+			target_location_id: target_location.id,
+			target_location_path: maybe_missing(&target_location.path, "location.path")
+				.map(PathBuf::from)?,
+			file_path_ids,
+			deletion_policy,
+			cas_id_algorithm,
+			io_throttle: IoThrottle::default(),
+			metadata: Metadata::default(),
+			progress_tx,
+			progress_rx,
+			errors: Vec::new(),
+			pending_tasks_on_resume: Vec::new(),
+			tasks_for_shutdown: Vec::new(),
+		})
+	}
+
+	/// Caps how fast files are read, shared by every copier task of the job
+	#[must_use]
+	pub fn with_io_throttle(mut self, io_throttle: IoThrottle) -> Self {
+		self.io_throttle = io_throttle;
+		self
+	}
+
+	async fn init_or_resume(
+		&mut self,
+		pending_running_tasks: &mut FuturesUnordered<TaskHandle<Error>>,
+		ctx: &impl OuterContext,
+		dispatcher: &JobTaskDispatcher,
+	) -> Result<(), backup::Error> {
+		// Resumed tasks were already dispatched by `resume_tasks`
+		if !self.pending_tasks_on_resume.is_empty() {
+			pending_running_tasks.extend(mem::take(&mut self.pending_tasks_on_resume));
+
+			return Ok(());
+		}
+
+		let deep_hash_threshold = ctx.deep_hash_threshold().await;
+
+		ctx.progress(vec![ProgressUpdate::Message(
+			"Comparing with the backup".to_string(),
+		)]);
+
+		let io_throttle = dispatcher.io_throttle(&self.io_throttle);
+
+		let plan = plan(
+			ctx.db(),
+			BackupSide {
+				location_id: self.source_location.id,
+				path: &self.source_location_path,
+			},
+			BackupSide {
+				location_id: self.target_location_id,
+				path: &self.target_location_path,
+			},
+			self.file_path_ids.as_deref(),
+			self.deletion_policy,
+			self.cas_id_algorithm,
+			&io_throttle,
+		)
+		.await?;
+
+		self.errors.extend(plan.errors.into_iter().map(Into::into));
+		self.metadata.unchanged_files = u64::from(plan.diff.unchanged_files);
+		self.metadata.total_files = plan.copies.len() as u64;
+		self.metadata.total_bytes = plan.total_bytes;
+
+		debug!(
+			"Backing up location {} into location {}: {} files to copy, {} bytes, {} to delete",
+			self.source_location.id,
+			self.target_location_id,
+			self.metadata.total_files,
+			self.metadata.total_bytes,
+			plan.deletions.len()
+		);
+
+		for path in plan.deletions {
+			match delete(&path).await {
+				Ok(()) => self.metadata.deleted_files += 1,
+				Err(e) => self
+					.errors
+					.push(backup::NonCriticalError::FailedToDelete(path, e.to_string()).into()),
+			}
+		}
+
+		for directory in plan.directories {
+			fs::create_dir_all(&directory).await.map_err(|e| {
+				FileIOError::from((&directory, e, "Failed to create backup directory"))
+			})?;
+		}
+
+		pending_running_tasks.extend(
+			dispatcher
+				.dispatch_many(
+					plan.copies
+						.chunks(BATCH_SIZE)
+						.map(|chunk| {
+							Copier::new(
+								// Reversed as tasks pop their entries
+								chunk.iter().rev().cloned().collect(),
+								// Changed files replace their outdated copy once verified
+								ConflictPolicy::Overwrite,
+								self.cas_id_algorithm,
+								deep_hash_threshold,
+								self.progress_tx.clone(),
+							)
+							.with_io_throttle(io_throttle.clone())
+						})
+						.collect::<Vec<_>>(),
+				)
+				.await,
+		);
+
+		self.report_progress(ctx);
+
+		Ok(())
+	}
+
+	fn process_progress(&mut self, progress: CopyProgress) {
+		match progress {
+			CopyProgress::Started { .. } => {}
+			CopyProgress::Copied(bytes) => self.metadata.copied_bytes += bytes,
+			CopyProgress::Finished => self.metadata.completed_files += 1,
+		}
+	}
+
+	fn report_progress(&self, ctx: &impl OuterContext) {
+		ctx.progress(vec![
+			ProgressUpdate::TaskCount(self.metadata.total_files),
+			ProgressUpdate::CompletedTaskCount(self.metadata.completed_files),
+			ProgressUpdate::Message(format!(
+				"Backed up {} of {} bytes",
+				self.metadata.copied_bytes, self.metadata.total_bytes
+			)),
+		]);
+	}
+
+	fn process_copier_output(
+		&mut self,
+		copier::Output {
+			copied_files,
+			skipped_files: _,
+			failed_files,
+			copy_time,
+			verification_time,
+			errors,
+		}: copier::Output,
+	) {
+		self.metadata.copied_files += copied_files;
+		self.metadata.failed_files += failed_files;
+		self.metadata.copy_time += copy_time;
+		self.metadata.verification_time += verification_time;
+
+		self.errors.extend(errors);
+	}
+}
+
+async fn delete(path: &Path) -> Result<(), FileIOError> {
+	let metadata = fs::symlink_metadata(path)
+		.await
+		.map_err(|e| FileIOError::from((path, e)))?;
+
+	if metadata.is_dir() {
+		fs::remove_dir_all(path).await
+	} else {
+		fs::remove_file(path).await
+	}
+	.map_err(|e| FileIOError::from((path, e, "Failed to delete from the backup")))
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Metadata {
+	total_files: u64,
+	total_bytes: u64,
+	completed_files: u64,
+	copied_files: u64,
+	unchanged_files: u64,
+	deleted_files: u64,
+	failed_files: u64,
+	copied_bytes: u64,
+	copy_time: Duration,
+	verification_time: Duration,
+}
+
+impl From<Metadata> for ReportOutputMetadata {
+	fn from(value: Metadata) -> Self {
+		Self::Metrics(HashMap::from([
+			("total_files".into(), json!(value.total_files)),
+			("total_bytes".into(), json!(value.total_bytes)),
+			("copied_files".into(), json!(value.copied_files)),
+			("unchanged_files".into(), json!(value.unchanged_files)),
+			("deleted_files".into(), json!(value.deleted_files)),
+			("failed_files".into(), json!(value.failed_files)),
+			("copied_bytes".into(), json!(value.copied_bytes)),
+			("copy_time".into(), json!(value.copy_time)),
+			("verification_time".into(), json!(value.verification_time)),
+		]))
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+struct SaveState {
+	source_location: Arc<location::Data>,
+	source_location_path: Arc<PathBuf>,
+	target_location_id: location::id::Type,
+	target_location_path: PathBuf,
+	file_path_ids: Option<Vec<file_path::id::Type>>,
+	deletion_policy: DeletionPolicy,
+	cas_id_algorithm: CasIdAlgorithm,
+	io_throttle: IoThrottle,
+
+	metadata: Metadata,
+
+	errors: Vec<NonCriticalError>,
+
+	tasks_for_shutdown_bytes: Option<SerializedTasks>,
+}
+
+impl<Ctx: OuterContext> SerializableJob<Ctx> for Backup {
+	async fn serialize(self) -> Result<Option<Vec<u8>>, rmp_serde::encode::Error> {
+		let Self {
+			source_location,
+			source_location_path,
+			target_location_id,
+			target_location_path,
+			file_path_ids,
+			deletion_policy,
+			cas_id_algorithm,
+			io_throttle,
+			metadata,
+			errors,
+			tasks_for_shutdown,
+			..
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState {
+			source_location,
+			source_location_path,
+			target_location_id,
+			target_location_path,
+			file_path_ids,
+			deletion_policy,
+			cas_id_algorithm,
+			io_throttle,
+			metadata,
+			errors,
+			tasks_for_shutdown_bytes: Some(SerializedTasks(rmp_serde::to_vec_named(
+				&tasks_for_shutdown
+					.into_iter()
+					.map(|task| async move {
+						task.downcast::<Copier>()
+							.expect("the backup job only dispatches copier tasks")
+							.serialize()
+							.await
+					})
+					.collect::<Vec<_>>()
+					.try_join()
+					.await?,
+			)?)),
+		})
+		.map(Some)
+	}
+
+	async fn deserialize(
+		serialized_job: &[u8],
+		_: &Ctx,
+	) -> Result<Option<(Self, Option<SerializedTasks>)>, rmp_serde::decode::Error> {
+		let SaveState {
+			source_location,
+			source_location_path,
+			target_location_id,
+			target_location_path,
+			file_path_ids,
+			deletion_policy,
+			cas_id_algorithm,
+			io_throttle,
+			metadata,
+			errors,
+			tasks_for_shutdown_bytes,
+		} = rmp_serde::from_slice::<SaveState>(serialized_job)?;
+
+		let (progress_tx, progress_rx) = chan::unbounded();
+
+		Ok(Some((
+			Self {
+				source_location,
+				source_location_path,
+				target_location_id,
+				target_location_path,
+				file_path_ids,
+				deletion_policy,
+				cas_id_algorithm,
+				io_throttle,
+				metadata,
+				progress_tx,
+				progress_rx,
+				errors,
+				pending_tasks_on_resume: Vec::new(),
+				tasks_for_shutdown: Vec::new(),
+			},
+			tasks_for_shutdown_bytes,
+		)))
+	}
+}
@@ -0,0 +1,533 @@
+use crate::{
+	file_copier::{copier::CopyEntry, exists},
+	file_identifier::{generate_cas_id, not_in_archive, CasIdAlgorithm},
+	utils::io_throttle::IoThrottle,
+};
+
+use sd_core_file_path_helper::{FilePathError, IsolatedFilePathData};
+use sd_core_prisma_helpers::file_path_for_backup;
+
+use sd_prisma::prisma::{file_path, location, PrismaClient};
+use sd_utils::{
+	db::{size_in_bytes_from_db, MissingFieldError},
+	error::FileIOError,
+};
+
+use std::{
+	collections::{HashMap, HashSet},
+	ffi::OsStr,
+	path::{Path, PathBuf},
+};
+
+use prisma_client_rust::QueryError;
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::fs;
+
+pub mod job;
+
+pub use job::Backup;
+
+/// Files of the target that are never deleted, as they aren't part of the backup: the location's
+/// metadata and the partial copies of an interrupted run
+const KEPT_NAMES: [&str; 2] = [".spacedrive", ".DS_Store"];
+const PARTIAL_EXTENSION: &str = "sdpart";
+
+/// Whether an entry named `name` is location metadata or a partial copy, never backed up or synced
+pub(crate) fn is_ignored(name: &OsStr) -> bool {
+	KEPT_NAMES.iter().any(|kept| name == OsStr::new(kept))
+		|| Path::new(name).extension() == Some(OsStr::new(PARTIAL_EXTENSION))
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("file_path not found in source location: <id='{0}'>")]
+	FilePathNotFound(file_path::id::Type),
+	#[error("can't back up a location into itself: <id='{0}'>")]
+	SameLocation(location::id::Type),
+	#[error("locations are nested, backing one up into the other would copy the backup again: <source='{}', target='{}'>", .0.display(), .1.display())]
+	NestedLocations(PathBuf, PathBuf),
+	#[error("missing field on database: {0}")]
+	MissingField(#[from] MissingFieldError),
+	#[error("database error: {0}")]
+	Database(#[from] QueryError),
+	#[error("failed to deserialized stored tasks for job resume: {0}")]
+	DeserializeTasks(#[from] rmp_serde::decode::Error),
+
+	#[error(transparent)]
+	FilePathError(#[from] FilePathError),
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+}
+
+impl From<Error> for rspc::Error {
+	fn from(err: Error) -> Self {
+		match err {
+			Error::FilePathNotFound(_) => {
+				Self::with_cause(ErrorCode::NotFound, err.to_string(), err)
+			}
+
+			Error::SameLocation(_) | Error::NestedLocations(..) => {
+				Self::with_cause(ErrorCode::BadRequest, err.to_string(), err)
+			}
+
+			_ => Self::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize, Type)]
+pub enum NonCriticalError {
+	#[error(transparent)]
+	Copier(#[from] crate::file_copier::copier::NonCriticalError),
+	#[error("failed to delete from the backup: <path='{}'>: {1}", .0.display())]
+	FailedToDelete(PathBuf, String),
+	#[error("failed to compare with the backup, copying it again: <path='{}'>: {1}", .0.display())]
+	FailedToCompare(PathBuf, String),
+}
+
+/// What happens to the files of the target that aren't in the source
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum DeletionPolicy {
+	/// Keep them, so files deleted from the source stay in the backup
+	#[default]
+	Additive,
+	/// Delete them, the target ending up with exactly the files of the source
+	Mirror,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum BackupChange {
+	/// Not in the target yet
+	Added,
+	/// In the target with different contents
+	Modified,
+	/// Only in the target, deleted with [`DeletionPolicy::Mirror`]
+	Deleted,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct BackupDiffEntry {
+	/// Relative to the root of the locations
+	pub path: PathBuf,
+	pub is_dir: bool,
+	pub change: BackupChange,
+	/// As a string as it may not fit in a JS number, zero for directories
+	pub size_in_bytes: String,
+}
+
+/// What a backup would do, without doing it
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Type)]
+pub struct BackupDiff {
+	pub entries: Vec<BackupDiffEntry>,
+	/// Files whose `cas_id` matches the target's, so they're skipped
+	pub unchanged_files: u32,
+	pub bytes_to_copy: String,
+}
+
+/// What the backup job does, in order: deleting, creating directories, then copying
+#[derive(Debug, Default)]
+pub(crate) struct Plan {
+	pub(crate) diff: BackupDiff,
+	pub(crate) deletions: Vec<PathBuf>,
+	/// Parents first
+	pub(crate) directories: Vec<PathBuf>,
+	pub(crate) copies: Vec<CopyEntry>,
+	pub(crate) total_bytes: u64,
+	pub(crate) errors: Vec<NonCriticalError>,
+}
+
+/// A location, or the part of it selected with `file_path_ids`
+#[derive(Debug, Clone, Copy)]
+pub struct BackupSide<'a> {
+	pub location_id: location::id::Type,
+	pub path: &'a Path,
+}
+
+/// What backing up `file_path_ids` of `source`, the whole location if `None`, into `target` would
+/// do. `cas_id_algorithm` must be the one the library's `cas_id`s were generated with.
+pub async fn preview(
+	db: &PrismaClient,
+	source: BackupSide<'_>,
+	target: BackupSide<'_>,
+	file_path_ids: Option<&[file_path::id::Type]>,
+	deletion_policy: DeletionPolicy,
+	cas_id_algorithm: CasIdAlgorithm,
+) -> Result<BackupDiff, Error> {
+	plan(
+		db,
+		source,
+		target,
+		file_path_ids,
+		deletion_policy,
+		cas_id_algorithm,
+		&IoThrottle::default(),
+	)
+	.await
+	.map(|plan| plan.diff)
+}
+
+pub(crate) async fn plan(
+	db: &PrismaClient,
+	source: BackupSide<'_>,
+	target: BackupSide<'_>,
+	file_path_ids: Option<&[file_path::id::Type]>,
+	deletion_policy: DeletionPolicy,
+	cas_id_algorithm: CasIdAlgorithm,
+	io_throttle: &IoThrottle,
+) -> Result<Plan, Error> {
+	if source.location_id == target.location_id {
+		return Err(Error::SameLocation(source.location_id));
+	}
+
+	if source.path.starts_with(target.path) || target.path.starts_with(source.path) {
+		return Err(Error::NestedLocations(
+			source.path.to_path_buf(),
+			target.path.to_path_buf(),
+		));
+	}
+
+	let (source_file_paths, scan_roots) = match file_path_ids {
+		None => (
+			db.file_path()
+				.find_many(vec![
+					file_path::location_id::equals(Some(source.location_id)),
+					not_in_archive(),
+				])
+				.select(file_path_for_backup::select())
+				.exec()
+				.await?,
+			vec![PathBuf::new()],
+		),
+		Some(file_path_ids) => selection(db, source.location_id, file_path_ids).await?,
+	};
+
+	// `cas_id`s the target's own indexing already computed, spares reading the files again
+	let target_cas_ids = db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(Some(target.location_id)),
+			file_path::is_dir::equals(Some(false)),
+			file_path::cas_id::not(None),
+			not_in_archive(),
+		])
+		.select(file_path_for_backup::select())
+		.exec()
+		.await?
+		.into_iter()
+		.map(|file_path| {
+			let relative = PathBuf::from(
+				IsolatedFilePathData::try_from((target.location_id, &file_path))?.as_ref(),
+			);
+
+			Ok((relative, (size_of(&file_path), file_path.cas_id)))
+		})
+		.collect::<Result<HashMap<_, _>, Error>>()?;
+
+	let mut plan = Plan::default();
+	let mut directories = HashSet::new();
+	let mut files = HashSet::new();
+	let mut bytes_to_copy = 0;
+
+	for file_path in &source_file_paths {
+		let iso_file_path = IsolatedFilePathData::try_from((source.location_id, file_path))?;
+		if iso_file_path.is_root() {
+			continue;
+		}
+
+		let relative = PathBuf::from(iso_file_path.as_ref());
+		let source_path = source.path.join(&relative);
+		let target_path = target.path.join(&relative);
+
+		if iso_file_path.is_dir() {
+			if !exists(&target_path).await? {
+				plan.diff.entries.push(BackupDiffEntry {
+					path: relative.clone(),
+					is_dir: true,
+					change: BackupChange::Added,
+					size_in_bytes: "0".to_string(),
+				});
+			}
+
+			directories.insert(relative);
+			continue;
+		}
+
+		let size = size_of(file_path);
+
+		let change = match fs::metadata(&target_path).await {
+			Ok(metadata) if metadata.is_file() && metadata.len() == size => {
+				let unchanged = match (file_path.cas_id.as_ref(), target_cas_ids.get(&relative)) {
+					(Some(cas_id), Some((target_size, Some(target_cas_id))))
+						if *target_size == size =>
+					{
+						cas_id == target_cas_id
+					}
+					// One side wasn't identified, so both are hashed the same way now
+					_ => same_contents(
+						&source_path,
+						&target_path,
+						size,
+						cas_id_algorithm,
+						io_throttle,
+					)
+					.await
+					.unwrap_or_else(|e| {
+						plan.errors.push(e);
+						false
+					}),
+				};
+
+				if unchanged {
+					plan.diff.unchanged_files += 1;
+					files.insert(relative);
+					continue;
+				}
+
+				BackupChange::Modified
+			}
+			Ok(_) => BackupChange::Modified,
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => BackupChange::Added,
+			Err(e) => {
+				return Err(
+					FileIOError::from((&target_path, e, "Failed to read backed up file")).into(),
+				)
+			}
+		};
+
+		bytes_to_copy += size;
+		plan.diff.entries.push(BackupDiffEntry {
+			path: relative.clone(),
+			is_dir: false,
+			change,
+			size_in_bytes: size.to_string(),
+		});
+		plan.copies.push(CopyEntry {
+			source: source_path,
+			target: target_path,
+			resolved: true,
+		});
+		files.insert(relative);
+	}
+
+	if deletion_policy == DeletionPolicy::Mirror {
+		for (relative, is_dir) in
+			target_extras(target.path, &scan_roots, &directories, &files).await?
+		{
+			plan.diff.entries.push(BackupDiffEntry {
+				path: relative.clone(),
+				is_dir,
+				change: BackupChange::Deleted,
+				size_in_bytes: "0".to_string(),
+			});
+			plan.deletions.push(target.path.join(relative));
+		}
+	}
+
+	let mut directories = directories.into_iter().collect::<Vec<_>>();
+	// Shorter paths first puts parents before their children
+	directories.sort_by_key(|relative| relative.components().count());
+	plan.directories = directories
+		.into_iter()
+		.map(|relative| target.path.join(relative))
+		.collect();
+
+	plan.diff.entries.sort_by(|a, b| a.path.cmp(&b.path));
+	plan.diff.bytes_to_copy = bytes_to_copy.to_string();
+	plan.total_bytes = bytes_to_copy;
+
+	Ok(plan)
+}
+
+/// The selected file paths with the contents of the selected directories, and the directories
+/// whose extra files the target can lose
+async fn selection(
+	db: &PrismaClient,
+	location_id: location::id::Type,
+	file_path_ids: &[file_path::id::Type],
+) -> Result<(Vec<file_path_for_backup::Data>, Vec<PathBuf>), Error> {
+	let selected = db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(Some(location_id)),
+			file_path::id::in_vec(file_path_ids.to_vec()),
+			not_in_archive(),
+		])
+		.select(file_path_for_backup::select())
+		.exec()
+		.await?;
+
+	if let Some(missing_id) = file_path_ids
+		.iter()
+		.find(|id| !selected.iter().any(|file_path| file_path.id == **id))
+	{
+		return Err(Error::FilePathNotFound(*missing_id));
+	}
+
+	let mut scan_roots = vec![];
+	let mut file_paths = HashMap::new();
+
+	for file_path in selected {
+		let iso_file_path = IsolatedFilePathData::try_from((location_id, &file_path))?;
+
+		if let Some(children_path) = iso_file_path.materialized_path_for_children() {
+			scan_roots.push(PathBuf::from(iso_file_path.as_ref()));
+
+			for child in db
+				.file_path()
+				.find_many(vec![
+					file_path::location_id::equals(Some(location_id)),
+					file_path::materialized_path::starts_with(children_path),
+					not_in_archive(),
+				])
+				.select(file_path_for_backup::select())
+				.exec()
+				.await?
+			{
+				file_paths.insert(child.id, child);
+			}
+		}
+
+		file_paths.insert(file_path.id, file_path);
+	}
+
+	Ok((file_paths.into_values().collect(), scan_roots))
+}
+
+fn size_of(file_path: &file_path_for_backup::Data) -> u64 {
+	file_path
+		.size_in_bytes_bytes
+		.as_deref()
+		.map(size_in_bytes_from_db)
+		.unwrap_or_default()
+}
+
+async fn same_contents(
+	source: &Path,
+	target: &Path,
+	size: u64,
+	cas_id_algorithm: CasIdAlgorithm,
+	io_throttle: &IoThrottle,
+) -> Result<bool, NonCriticalError> {
+	let failed =
+		|e: std::io::Error| NonCriticalError::FailedToCompare(target.to_path_buf(), e.to_string());
+
+	Ok(generate_cas_id(source, size, cas_id_algorithm, io_throttle)
+		.await
+		.map_err(failed)?
+		== generate_cas_id(target, size, cas_id_algorithm, io_throttle)
+			.await
+			.map_err(failed)?)
+}
+
+/// Entries under `scan_roots` of the target that the source doesn't have, relative to
+/// `target_root` and with whether they're directories. A directory missing from the source is
+/// returned without its contents, as deleting it takes them too.
+pub(crate) async fn target_extras(
+	target_root: &Path,
+	scan_roots: &[PathBuf],
+	directories: &HashSet<PathBuf>,
+	files: &HashSet<PathBuf>,
+) -> Result<Vec<(PathBuf, bool)>, FileIOError> {
+	let mut extras = vec![];
+	let mut pending = scan_roots.to_vec();
+
+	while let Some(relative_dir) = pending.pop() {
+		let dir = target_root.join(&relative_dir);
+
+		let mut read_dir = match fs::read_dir(&dir).await {
+			Ok(read_dir) => read_dir,
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+			Err(e) => {
+				return Err(FileIOError::from((
+					dir,
+					e,
+					"Failed to read backup directory",
+				)))
+			}
+		};
+
+		while let Some(entry) = read_dir
+			.next_entry()
+			.await
+			.map_err(|e| FileIOError::from((&dir, e, "Failed to read backup directory entry")))?
+		{
+			let name = entry.file_name();
+			if is_ignored(&name) {
+				continue;
+			}
+
+			let relative = relative_dir.join(&name);
+			let is_dir = entry
+				.file_type()
+				.await
+				.map_err(|e| FileIOError::from((entry.path(), e, "Failed to read file type")))?
+				.is_dir();
+
+			if is_dir && directories.contains(&relative) {
+				pending.push(relative);
+			} else if (is_dir && !directories.contains(&relative))
+				|| (!is_dir && !files.contains(&relative))
+			{
+				extras.push((relative, is_dir));
+			}
+		}
+	}
+
+	extras.sort();
+
+	Ok(extras)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[tokio::test]
+	async fn extras_are_what_the_source_lacks() {
+		let target = tempfile::tempdir().expect("temp dir");
+		let root = target.path();
+
+		for dir in ["photos/2023", "photos/old", "docs"] {
+			std::fs::create_dir_all(root.join(dir)).expect("create dir");
+		}
+		for file in [
+			"photos/2023/a.jpg",
+			"photos/2023/b.jpg",
+			"photos/old/c.jpg",
+			"docs/notes.txt",
+			"docs/.report.pdf.sdpart",
+			".spacedrive",
+		] {
+			std::fs::write(root.join(file), b"backup").expect("write file");
+		}
+
+		let directories = ["photos", "photos/2023", "docs"]
+			.into_iter()
+			.map(PathBuf::from)
+			.collect::<HashSet<_>>();
+		let files = ["photos/2023/a.jpg", "docs/notes.txt"]
+			.into_iter()
+			.map(PathBuf::from)
+			.collect::<HashSet<_>>();
+
+		assert_eq!(
+			target_extras(root, &[PathBuf::new()], &directories, &files)
+				.await
+				.expect("extras"),
+			vec![
+				(PathBuf::from("photos/2023/b.jpg"), false),
+				(PathBuf::from("photos/old"), true),
+			]
+		);
+
+		// Selections only lose extras inside the selected directories
+		assert_eq!(
+			target_extras(root, &[PathBuf::from("docs")], &directories, &files)
+				.await
+				.expect("extras"),
+			vec![]
+		);
+	}
+}
@@ -0,0 +1,357 @@
+use crate::{
+	bulk_rename,
+	job_system::{
+		job::{Job, JobReturn, JobTaskDispatcher, ReturnStatus},
+		report::ReportOutputMetadata,
+		utils::{cancel_pending_tasks, location_concurrency_key},
+		SerializableJob, SerializedTasks,
+	},
+	utils::relink::LocationRoot,
+	Error, JobName, OuterContext, ProgressUpdate,
+};
+
+use sd_prisma::prisma::{file_path, location};
+use sd_task_system::{
+	IntoTask, SerializableTask, Task, TaskDispatcher, TaskHandle, TaskOutput, TaskStatus,
+};
+use sd_utils::db::maybe_missing;
+
+use std::{
+	cmp::Reverse,
+	collections::HashMap,
+	hash::{Hash, Hasher},
+	mem,
+	path::PathBuf,
+	sync::Arc,
+	time::Duration,
+};
+
+use futures::{stream::FuturesUnordered, StreamExt};
+use futures_concurrency::future::TryJoin;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{debug, warn};
+
+use super::{
+	plan,
+	tasks::{renamer, Renamer},
+	RenamePattern,
+};
+
+/// Renames a selection of file paths of a location following a [`RenamePattern`], after checking
+/// every new name is valid and free, the same way [`preview`](super::preview) does. Renames are
+/// all or nothing: if one fails, the others are rolled back.
+#[derive(Debug)]
+pub struct BulkRename {
+	location: LocationRoot,
+	file_path_ids: Vec<file_path::id::Type>,
+	pattern: RenamePattern,
+
+	metadata: Metadata,
+
+	pending_tasks_on_resume: Vec<TaskHandle<Error>>,
+	tasks_for_shutdown: Vec<Box<dyn Task<Error>>>,
+}
+
+impl Hash for BulkRename {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.location.id.hash(state);
+		self.file_path_ids.hash(state);
+	}
+}
+
+impl Job for BulkRename {
+	const NAME: JobName = JobName::BulkRename;
+
+	async fn resume_tasks(
+		&mut self,
+		dispatcher: &JobTaskDispatcher,
+		ctx: &impl OuterContext,
+		SerializedTasks(serialized_tasks): SerializedTasks,
+	) -> Result<(), Error> {
+		let dispatcher =
+			dispatcher.with_concurrency_key(location_concurrency_key(ctx.id(), self.location.id));
+
+		self.pending_tasks_on_resume = dispatcher
+			.dispatch_many_boxed(
+				rmp_serde::from_slice::<Vec<Vec<u8>>>(&serialized_tasks)
+					.map_err(bulk_rename::Error::from)?
+					.into_iter()
+					.map(|task_bytes| async move {
+						Renamer::deserialize(
+							&task_bytes,
+							(Arc::clone(ctx.db()), Arc::clone(ctx.sync())),
+						)
+						.await
+						.map(IntoTask::into_task)
+					})
+					.collect::<Vec<_>>()
+					.try_join()
+					.await
+					.map_err(bulk_rename::Error::from)?,
+			)
+			.await;
+
+		Ok(())
+	}
+
+	async fn run<Ctx: OuterContext>(
+		mut self,
+		dispatcher: JobTaskDispatcher,
+		ctx: Ctx,
+	) -> Result<ReturnStatus, Error> {
+		let dispatcher =
+			dispatcher.with_concurrency_key(location_concurrency_key(ctx.id(), self.location.id));
+
+		let mut pending_running_tasks = FuturesUnordered::new();
+
+		self.init_or_resume(&mut pending_running_tasks, &ctx, &dispatcher)
+			.await?;
+
+		while let Some(task) = pending_running_tasks.next().await {
+			match task {
+				Ok(TaskStatus::Done((_, TaskOutput::Out(out)))) => {
+					let renamer::Output {
+						renamed,
+						rename_time,
+						db_write_time,
+					} = *out
+						.downcast::<renamer::Output>()
+						.expect("the bulk rename job only dispatches renamer tasks");
+
+					self.metadata.renamed += renamed;
+					self.metadata.rename_time += rename_time;
+					self.metadata.db_write_time += db_write_time;
+
+					ctx.progress(vec![ProgressUpdate::CompletedTaskCount(
+						self.metadata.renamed,
+					)]);
+				}
+
+				Ok(TaskStatus::Done((task_id, TaskOutput::Empty))) => {
+					warn!("Task <id='{task_id}'> returned an empty output");
+				}
+
+				Ok(TaskStatus::Shutdown(task)) => {
+					self.tasks_for_shutdown.push(task);
+				}
+
+				Ok(TaskStatus::Error(e)) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e);
+				}
+
+				Ok(TaskStatus::Canceled | TaskStatus::ForcedAbortion) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Ok(ReturnStatus::Canceled);
+				}
+
+				Err(e) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e.into());
+				}
+			}
+		}
+
+		if !self.tasks_for_shutdown.is_empty() {
+			return Ok(ReturnStatus::Shutdown(
+				SerializableJob::<Ctx>::serialize(self).await,
+			));
+		}
+
+		ctx.invalidate_query("search.paths");
+
+		Ok(ReturnStatus::Completed(
+			JobReturn::builder().with_metadata(self.metadata).build(),
+		))
+	}
+}
+
+impl BulkRename {
+	pub fn new(
+		location: &location::Data,
+		file_path_ids: Vec<file_path::id::Type>,
+		pattern: RenamePattern,
+	) -> Result<Self, bulk_rename::Error> {
+		Ok(Self {
+			location: LocationRoot {
+				id: location.id,
+				pub_id: location.pub_id.clone(),
+				path: maybe_missing(&location.path, "location.path")
+					.map(PathBuf::from)
+					.map(Arc::new)?,
+			},
+			file_path_ids,
+			pattern,
+			metadata: Metadata::default(),
+			pending_tasks_on_resume: Vec::new(),
+			tasks_for_shutdown: Vec::new(),
+		})
+	}
+
+	async fn init_or_resume(
+		&mut self,
+		pending_running_tasks: &mut FuturesUnordered<TaskHandle<Error>>,
+		ctx: &impl OuterContext,
+		dispatcher: &JobTaskDispatcher,
+	) -> Result<(), bulk_rename::Error> {
+		// Resumed tasks were already dispatched by `resume_tasks`
+		if !self.pending_tasks_on_resume.is_empty() {
+			pending_running_tasks.extend(mem::take(&mut self.pending_tasks_on_resume));
+
+			return Ok(());
+		}
+
+		let planned = plan(
+			ctx.db(),
+			self.location.id,
+			&self.location.path,
+			&self.file_path_ids,
+			&self.pattern,
+		)
+		.await?;
+
+		let conflicts = planned
+			.iter()
+			.filter(|(mapping, _)| mapping.problem.is_some())
+			.count();
+		if conflicts > 0 {
+			return Err(bulk_rename::Error::Conflicts(conflicts));
+		}
+
+		let mut entries = planned
+			.into_iter()
+			.filter_map(|(_, entry)| entry)
+			.collect::<Vec<_>>();
+
+		self.metadata.total_file_paths = self.file_path_ids.len() as u64;
+		self.metadata.unchanged = self.metadata.total_file_paths - entries.len() as u64;
+
+		debug!(
+			"Renaming {} file paths of location {}, {} keep their names",
+			entries.len(),
+			self.location.id,
+			self.metadata.unchanged
+		);
+
+		if entries.is_empty() {
+			return Ok(());
+		}
+
+		// Deepest first, so renaming a directory doesn't move what's still to be renamed inside it
+		entries.sort_by_key(|entry| Reverse(entry.from.components().count()));
+
+		ctx.progress(vec![
+			ProgressUpdate::TaskCount(entries.len() as u64),
+			ProgressUpdate::Message(format!("Renaming {} file paths", entries.len())),
+		]);
+
+		// A single task, as rolling back needs to know everything renamed before a failure
+		pending_running_tasks.push(
+			dispatcher
+				.dispatch(Renamer::new(
+					self.location.clone(),
+					entries,
+					Arc::clone(ctx.db()),
+					Arc::clone(ctx.sync()),
+				))
+				.await,
+		);
+
+		Ok(())
+	}
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Metadata {
+	total_file_paths: u64,
+	renamed: u64,
+	unchanged: u64,
+	rename_time: Duration,
+	db_write_time: Duration,
+}
+
+impl From<Metadata> for ReportOutputMetadata {
+	fn from(value: Metadata) -> Self {
+		Self::Metrics(HashMap::from([
+			("total_file_paths".into(), json!(value.total_file_paths)),
+			("renamed".into(), json!(value.renamed)),
+			("unchanged".into(), json!(value.unchanged)),
+			("rename_time".into(), json!(value.rename_time)),
+			("db_write_time".into(), json!(value.db_write_time)),
+		]))
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+struct SaveState {
+	location: LocationRoot,
+	file_path_ids: Vec<file_path::id::Type>,
+	pattern: RenamePattern,
+
+	metadata: Metadata,
+
+	tasks_for_shutdown_bytes: Option<SerializedTasks>,
+}
+
+impl<Ctx: OuterContext> SerializableJob<Ctx> for BulkRename {
+	async fn serialize(self) -> Result<Option<Vec<u8>>, rmp_serde::encode::Error> {
+		let Self {
+			location,
+			file_path_ids,
+			pattern,
+			metadata,
+			tasks_for_shutdown,
+			..
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState {
+			location,
+			file_path_ids,
+			pattern,
+			metadata,
+			tasks_for_shutdown_bytes: Some(SerializedTasks(rmp_serde::to_vec_named(
+				&tasks_for_shutdown
+					.into_iter()
+					.map(|task| async move {
+						task.downcast::<Renamer>()
+							.expect("the bulk rename job only dispatches renamer tasks")
+							.serialize()
+							.await
+					})
+					.collect::<Vec<_>>()
+					.try_join()
+					.await?,
+			)?)),
+		})
+		.map(Some)
+	}
+
+	async fn deserialize(
+		serialized_job: &[u8],
+		_: &Ctx,
+	) -> Result<Option<(Self, Option<SerializedTasks>)>, rmp_serde::decode::Error> {
+		let SaveState {
+			location,
+			file_path_ids,
+			pattern,
+			metadata,
+			tasks_for_shutdown_bytes,
+		} = rmp_serde::from_slice::<SaveState>(serialized_job)?;
+
+		Ok(Some((
+			Self {
+				location,
+				file_path_ids,
+				pattern,
+				metadata,
+				pending_tasks_on_resume: Vec::new(),
+				tasks_for_shutdown: Vec::new(),
+			},
+			tasks_for_shutdown_bytes,
+		)))
+	}
+}
@@ -0,0 +1,332 @@
+use crate::{file_copier::exists, utils::relink};
+
+use sd_core_file_path_helper::{FilePathError, IsolatedFilePathData};
+use sd_core_prisma_helpers::file_path_for_bulk_rename;
+
+use sd_media_metadata::exif::{CameraData, MediaDate};
+use sd_prisma::prisma::{file_path, location, PrismaClient};
+use sd_utils::{db::MissingFieldError, error::FileIOError};
+
+use std::{
+	collections::{HashMap, HashSet},
+	path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, FixedOffset};
+use prisma_client_rust::QueryError;
+use regex::Regex;
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+pub mod job;
+mod tasks;
+mod template;
+
+pub use job::BulkRename;
+pub use tasks::renamer;
+pub use template::{Template, TemplateError};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("file_path not found in location: <id='{0}'>")]
+	FilePathNotFound(file_path::id::Type),
+	#[error("invalid template: {0}")]
+	Template(#[from] TemplateError),
+	#[error("invalid pattern: {0}")]
+	Pattern(#[from] regex::Error),
+	#[error("template uses capture group {0}, missing from the pattern")]
+	MissingCaptureGroup(usize),
+	#[error("{0} file paths can't be renamed with this template, see the preview")]
+	Conflicts(usize),
+	#[error(
+		"failed to rename <from='{}', to='{}'>, previous renames were rolled back: {2}",
+		.0.display(),
+		.1.display()
+	)]
+	RenameFailed(PathBuf, PathBuf, String),
+	#[error("missing field on database: {0}")]
+	MissingField(#[from] MissingFieldError),
+	#[error("database error: {0}")]
+	Database(#[from] QueryError),
+	#[error("failed to deserialized stored tasks for job resume: {0}")]
+	DeserializeTasks(#[from] rmp_serde::decode::Error),
+
+	#[error(transparent)]
+	FilePathError(#[from] FilePathError),
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+	#[error(transparent)]
+	Relink(#[from] relink::Error),
+}
+
+impl From<Error> for rspc::Error {
+	fn from(err: Error) -> Self {
+		match err {
+			Error::FilePathNotFound(_) => {
+				Self::with_cause(ErrorCode::NotFound, err.to_string(), err)
+			}
+
+			Error::Template(_)
+			| Error::Pattern(_)
+			| Error::MissingCaptureGroup(_)
+			| Error::Conflicts(_) => Self::with_cause(ErrorCode::BadRequest, err.to_string(), err),
+
+			_ => Self::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+/// How to rename a batch of file paths
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct RenamePattern {
+	/// New name, see [`Template`] for its tokens
+	pub template: String,
+	/// Regex matched on names without extension, for the `{0}`, `{1}`, ... tokens. File paths it
+	/// doesn't match are left alone.
+	#[serde(default)]
+	pub find: Option<String>,
+	/// The template renders whole names, otherwise it renders names without extension and
+	/// extensions are kept
+	#[serde(default)]
+	pub replace_extension: bool,
+	/// Value of `{counter}` for the first file path, following ones counting up in the order they
+	/// were selected
+	#[serde(default = "default_counter_start")]
+	pub counter_start: u32,
+}
+
+const fn default_counter_start() -> u32 {
+	1
+}
+
+/// The new name of a file path, as seen on a preview
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct RenameMapping {
+	pub file_path_id: file_path::id::Type,
+	pub from: String,
+	pub to: String,
+	/// Why the file path can't be renamed, renaming nothing while any has one
+	pub problem: Option<RenameProblem>,
+}
+
+#[derive(thiserror::Error, Debug, Clone, Serialize, Deserialize, Type)]
+pub enum RenameProblem {
+	#[error("no value for the `{{{0}}}` token")]
+	MissingValue(String),
+	#[error("invalid file name")]
+	InvalidName,
+	#[error("a file with this name already exists")]
+	AlreadyExists,
+	#[error("another file path of the batch gets the same name")]
+	Duplicate,
+}
+
+/// A file path to rename, with its current and new full paths
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenameEntry {
+	pub file_path_id: file_path::id::Type,
+	pub pub_id: Vec<u8>,
+	pub is_dir: bool,
+	pub from: PathBuf,
+	pub to: PathBuf,
+}
+
+/// New names `pattern` gives to `file_path_ids`, without renaming anything. Renaming only happens
+/// if none of them has a problem.
+///
+/// New names must be free, so swapping names or shifting numbered names by one isn't possible in a
+/// single batch.
+pub async fn preview(
+	db: &PrismaClient,
+	location_id: location::id::Type,
+	location_path: impl AsRef<Path> + Send,
+	file_path_ids: &[file_path::id::Type],
+	pattern: &RenamePattern,
+) -> Result<Vec<RenameMapping>, Error> {
+	plan(
+		db,
+		location_id,
+		location_path.as_ref(),
+		file_path_ids,
+		pattern,
+	)
+	.await
+	.map(|planned| planned.into_iter().map(|(mapping, _)| mapping).collect())
+}
+
+/// Mappings of every file path, paired with what to rename for the ones that change names
+async fn plan(
+	db: &PrismaClient,
+	location_id: location::id::Type,
+	location_path: &Path,
+	file_path_ids: &[file_path::id::Type],
+	pattern: &RenamePattern,
+) -> Result<Vec<(RenameMapping, Option<RenameEntry>)>, Error> {
+	let template = pattern.template.parse::<Template>()?;
+	let find = pattern.find.as_deref().map(Regex::new).transpose()?;
+
+	if let Some(group) = template.max_capture() {
+		if find
+			.as_ref()
+			.map_or(true, |regex| group >= regex.captures_len())
+		{
+			return Err(Error::MissingCaptureGroup(group));
+		}
+	}
+
+	let mut file_paths = db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(Some(location_id)),
+			file_path::id::in_vec(file_path_ids.to_vec()),
+		])
+		.select(file_path_for_bulk_rename::select())
+		.exec()
+		.await?
+		.into_iter()
+		.map(|file_path| (file_path.id, file_path))
+		.collect::<HashMap<_, _>>();
+
+	let mut targets = HashSet::with_capacity(file_path_ids.len());
+	let mut planned = Vec::with_capacity(file_path_ids.len());
+
+	// Following the order file paths were selected in, as it's the one of counters
+	for (counter, id) in (u64::from(pattern.counter_start)..).zip(file_path_ids) {
+		let file_path = file_paths.remove(id).ok_or(Error::FilePathNotFound(*id))?;
+
+		let iso_file_path = IsolatedFilePathData::try_from((location_id, &file_path))?;
+		let parts = iso_file_path.to_parts();
+		let from = iso_file_path.full_name();
+
+		let captures = match &find {
+			Some(regex) => match regex.captures(parts.name) {
+				Some(captures) => Some(captures),
+				None => {
+					planned.push((unchanged(*id, from), None));
+					continue;
+				}
+			},
+			None => None,
+		};
+
+		let (camera, date_taken) = exif_values(&file_path);
+
+		let rendered = template.render(&template::Values {
+			name: parts.name,
+			extension: parts.extension,
+			date_created: file_path.date_created,
+			counter,
+			camera: camera.as_deref(),
+			date_taken,
+			captures: captures.as_ref(),
+		});
+
+		let to = match rendered {
+			Ok(name) if pattern.replace_extension || parts.extension.is_empty() => name,
+			Ok(name) => format!("{name}.{}", parts.extension),
+			Err(token) => {
+				planned.push((
+					RenameMapping {
+						problem: Some(RenameProblem::MissingValue(token.to_string())),
+						..unchanged(*id, from)
+					},
+					None,
+				));
+				continue;
+			}
+		};
+
+		if to == from {
+			planned.push((unchanged(*id, from), None));
+			continue;
+		}
+
+		let parent = location_path.join(iso_file_path.parent());
+		let from_path = parent.join(&from);
+		let to_path = parent.join(&to);
+
+		let problem = if to.is_empty() || !IsolatedFilePathData::accept_file_name(&to) {
+			Some(RenameProblem::InvalidName)
+		} else if !targets.insert(to_path.clone()) {
+			Some(RenameProblem::Duplicate)
+		// Changing only the case of a name finds itself on case insensitive file systems
+		} else if to.to_lowercase() != from.to_lowercase() && exists(&to_path).await? {
+			Some(RenameProblem::AlreadyExists)
+		} else {
+			None
+		};
+
+		let entry = problem.is_none().then(|| RenameEntry {
+			file_path_id: file_path.id,
+			pub_id: file_path.pub_id.clone(),
+			is_dir: iso_file_path.is_dir(),
+			from: from_path,
+			to: to_path,
+		});
+
+		planned.push((
+			RenameMapping {
+				file_path_id: file_path.id,
+				from,
+				to,
+				problem,
+			},
+			entry,
+		));
+	}
+
+	Ok(planned)
+}
+
+fn unchanged(file_path_id: file_path::id::Type, name: String) -> RenameMapping {
+	RenameMapping {
+		file_path_id,
+		to: name.clone(),
+		from: name,
+		problem: None,
+	}
+}
+
+/// Camera and date taken of pictures, from their exif data
+fn exif_values(
+	file_path: &file_path_for_bulk_rename::Data,
+) -> (Option<String>, Option<DateTime<FixedOffset>>) {
+	let Some(exif_data) = file_path
+		.object
+		.as_ref()
+		.and_then(|object| object.exif_data.as_ref())
+	else {
+		return (None, None);
+	};
+
+	let camera = exif_data
+		.camera_data
+		.as_deref()
+		.and_then(|bytes| serde_json::from_slice::<CameraData>(bytes).ok())
+		.and_then(
+			|CameraData {
+			     device_make,
+			     device_model,
+			     ..
+			 }| match (device_make, device_model) {
+				// Some makers already start their models with their name
+				(Some(make), Some(model)) if model.starts_with(&make) => Some(model),
+				(Some(make), Some(model)) => Some(format!("{make} {model}")),
+				(make, model) => make.or(model),
+			},
+		);
+
+	let date_taken = exif_data
+		.media_date
+		.as_deref()
+		.and_then(|bytes| serde_json::from_slice::<Option<MediaDate>>(bytes).ok())
+		.flatten()
+		.map(|date| match date {
+			MediaDate::Utc(date) => date,
+			// Without a time zone, the local time is what people expect to see in names
+			MediaDate::Naive(date) => date.and_utc().fixed_offset(),
+		});
+
+	(camera, date_taken)
+}
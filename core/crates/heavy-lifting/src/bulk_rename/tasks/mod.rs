@@ -0,0 +1,3 @@
+pub mod renamer;
+
+pub use renamer::Renamer;
@@ -0,0 +1,232 @@
+use crate::{
+	bulk_rename::{self, RenameEntry},
+	file_copier::exists,
+	utils::relink::{self, LocationRoot, Relinked},
+	Error,
+};
+
+use sd_core_sync::Manager as SyncManager;
+
+use sd_prisma::prisma::PrismaClient;
+use sd_task_system::{
+	check_interruption, ExecStatus, Interrupter, IntoAnyTaskOutput, SerializableTask, Task, TaskId,
+};
+use sd_utils::error::FileIOError;
+
+use std::{io, mem, path::Path, sync::Arc, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use tokio::{fs, time::Instant};
+use tracing::error;
+
+/// Renames a whole batch of file paths, or none of them: when one fails, the ones renamed before
+/// are renamed back.
+///
+/// Entries must be ordered deepest first, so renaming a file path never changes the path of
+/// another one still to be renamed.
+#[derive(Debug)]
+pub struct Renamer {
+	id: TaskId,
+	location: LocationRoot,
+	entries: Vec<RenameEntry>,
+	db: Arc<PrismaClient>,
+	sync: Arc<SyncManager>,
+	output: Output,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Output {
+	pub renamed: u64,
+	pub rename_time: Duration,
+	pub db_write_time: Duration,
+}
+
+impl Renamer {
+	#[must_use]
+	pub fn new(
+		location: LocationRoot,
+		entries: Vec<RenameEntry>,
+		db: Arc<PrismaClient>,
+		sync: Arc<SyncManager>,
+	) -> Self {
+		Self {
+			id: TaskId::new_v4(),
+			location,
+			entries,
+			db,
+			sync,
+			output: Output::default(),
+		}
+	}
+
+	/// Points file paths to the new name before renaming, so the watcher finds them already there,
+	/// then renames on disk
+	async fn rename(&mut self, entry: &RenameEntry) -> Result<(), bulk_rename::Error> {
+		let start = Instant::now();
+		self.relink(entry, &entry.from, &entry.to).await?;
+		self.output.db_write_time += start.elapsed();
+
+		let start = Instant::now();
+		let renamed = rename_if_free(&entry.from, &entry.to).await;
+		self.output.rename_time += start.elapsed();
+
+		if let Err(e) = renamed {
+			if let Err(e) = self.relink(entry, &entry.to, &entry.from).await {
+				error!(
+					"Failed to point file path back to <path='{}'>: {e:#?}",
+					entry.from.display()
+				);
+			}
+
+			return Err(bulk_rename::Error::RenameFailed(
+				entry.from.clone(),
+				entry.to.clone(),
+				e.to_string(),
+			));
+		}
+
+		Ok(())
+	}
+
+	/// Renames back entries already renamed, the last one first
+	async fn roll_back(&self, renamed: &[RenameEntry]) {
+		for entry in renamed.iter().rev() {
+			if let Err(e) = fs::rename(&entry.to, &entry.from).await {
+				error!(
+					"Failed to roll back rename of <path='{}'>: {:#?}",
+					entry.from.display(),
+					FileIOError::from((&entry.to, e))
+				);
+				// The file path stays where the file is
+				continue;
+			}
+
+			if let Err(e) = self.relink(entry, &entry.to, &entry.from).await {
+				error!(
+					"Failed to roll back file path of <path='{}'>: {e:#?}",
+					entry.from.display()
+				);
+			}
+		}
+	}
+
+	async fn relink(
+		&self,
+		entry: &RenameEntry,
+		from: &Path,
+		to: &Path,
+	) -> Result<(), bulk_rename::Error> {
+		relink::relink(
+			Relinked {
+				file_path_id: entry.file_path_id,
+				pub_id: &entry.pub_id,
+				is_dir: entry.is_dir,
+			},
+			(&self.location, from),
+			(&self.location, to),
+			&self.db,
+			&self.sync,
+		)
+		.await?
+		.write(&self.db, &self.sync)
+		.await?;
+
+		Ok(())
+	}
+}
+
+/// Renaming silently replaces existing files on most platforms, so names taken since the preview
+/// are checked again
+async fn rename_if_free(from: &Path, to: &Path) -> Result<(), FileIOError> {
+	// Changing only the case of a name finds itself on case insensitive file systems
+	let same_name = from.to_string_lossy().to_lowercase() == to.to_string_lossy().to_lowercase();
+
+	if !same_name && exists(to).await? {
+		return Err(FileIOError::from((
+			to,
+			io::Error::from(io::ErrorKind::AlreadyExists),
+			"Name was taken since the preview",
+		)));
+	}
+
+	fs::rename(from, to)
+		.await
+		.map_err(|e| FileIOError::from((from, e)))
+}
+
+#[async_trait::async_trait]
+impl Task<Error> for Renamer {
+	fn id(&self) -> TaskId {
+		self.id
+	}
+
+	async fn run(&mut self, interrupter: &Interrupter) -> Result<ExecStatus, Error> {
+		// Renames are quick and the batch must be renamed as a whole, so interruptions are only
+		// checked before starting
+		check_interruption!(interrupter);
+
+		let entries = mem::take(&mut self.entries);
+
+		for (i, entry) in entries.iter().enumerate() {
+			if let Err(e) = self.rename(entry).await {
+				self.roll_back(&entries[..i]).await;
+
+				return Err(e.into());
+			}
+
+			self.output.renamed += 1;
+		}
+
+		Ok(ExecStatus::Done(mem::take(&mut self.output).into_output()))
+	}
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SaveState {
+	id: TaskId,
+	location: LocationRoot,
+	entries: Vec<RenameEntry>,
+}
+
+impl SerializableTask<Error> for Renamer {
+	type SerializeError = rmp_serde::encode::Error;
+
+	type DeserializeError = rmp_serde::decode::Error;
+
+	type DeserializeCtx = (Arc<PrismaClient>, Arc<SyncManager>);
+
+	async fn serialize(self) -> Result<Vec<u8>, Self::SerializeError> {
+		let Self {
+			id,
+			location,
+			entries,
+			..
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState {
+			id,
+			location,
+			entries,
+		})
+	}
+
+	async fn deserialize(
+		data: &[u8],
+		(db, sync): Self::DeserializeCtx,
+	) -> Result<Self, Self::DeserializeError> {
+		rmp_serde::from_slice(data).map(
+			|SaveState {
+			     id,
+			     location,
+			     entries,
+			 }| Self {
+				id,
+				location,
+				entries,
+				db,
+				sync,
+				output: Output::default(),
+			},
+		)
+	}
+}
@@ -0,0 +1,277 @@
+use std::{mem, str::FromStr};
+
+use chrono::{
+	format::{Item, StrftimeItems},
+	DateTime, FixedOffset,
+};
+use regex::Captures;
+
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+// Zero padding wider than this is surely a typo, and would make absurdly long names
+const MAX_COUNTER_WIDTH: usize = 20;
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum TemplateError {
+	#[error("unclosed token starting at {0}")]
+	Unclosed(usize),
+	#[error("unmatched `}}` at {0}, write `}}}}` for a literal one")]
+	Unmatched(usize),
+	#[error("unknown token: <token='{0}'>")]
+	UnknownToken(String),
+	#[error("invalid counter width: <width='{0}'>")]
+	InvalidCounterWidth(String),
+	#[error("invalid date format: <format='{0}'>")]
+	InvalidDateFormat(String),
+}
+
+/// A name template, with literal text and `{token}`s replaced for each renamed file path:
+/// - `{name}` and `{ext}`: the current name, without extension, and extension;
+/// - `{date}` or `{date:<format>}`: when the file was created, formatted with `strftime` specifiers,
+///   `%Y-%m-%d` by default;
+/// - `{counter}` or `{counter:<width>}`: the position in the batch, zero padded to `width` digits;
+/// - `{exif.camera}`: make and model of the camera that took the picture;
+/// - `{exif.date}` or `{exif.date:<format>}`: when the picture was taken, formatted as `{date}`;
+/// - `{0}`, `{1}`, ...: the whole match and capture groups of the pattern matched on the name.
+///
+/// Braces are written `{{` and `}}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+	parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+	Literal(String),
+	Name,
+	Extension,
+	Date(String),
+	Counter(usize),
+	ExifCamera,
+	ExifDate(String),
+	Capture(usize),
+}
+
+/// Values tokens are replaced with, for one file path
+#[derive(Debug, Default)]
+pub struct Values<'a> {
+	pub name: &'a str,
+	pub extension: &'a str,
+	pub date_created: Option<DateTime<FixedOffset>>,
+	pub counter: u64,
+	pub camera: Option<&'a str>,
+	pub date_taken: Option<DateTime<FixedOffset>>,
+	pub captures: Option<&'a Captures<'a>>,
+}
+
+impl Template {
+	/// Highest capture group referenced, to check it against the pattern matched on names
+	#[must_use]
+	pub fn max_capture(&self) -> Option<usize> {
+		self.parts
+			.iter()
+			.filter_map(|part| match part {
+				Part::Capture(group) => Some(*group),
+				_ => None,
+			})
+			.max()
+	}
+
+	/// Renders the template, or returns the token that has no value for this file path
+	pub fn render(&self, values: &Values<'_>) -> Result<String, &'static str> {
+		let mut rendered = String::new();
+
+		for part in &self.parts {
+			match part {
+				Part::Literal(text) => rendered.push_str(text),
+				Part::Name => rendered.push_str(values.name),
+				Part::Extension => rendered.push_str(values.extension),
+				Part::Date(format) => {
+					// Formats were validated on parse, so formatting can't panic
+					let date = values.date_created.ok_or("date")?;
+					rendered.push_str(&date.format(format).to_string());
+				}
+				Part::Counter(width) => {
+					rendered.push_str(&format!("{:0width$}", values.counter, width = *width));
+				}
+				Part::ExifCamera => rendered.push_str(values.camera.ok_or("exif.camera")?),
+				Part::ExifDate(format) => {
+					let date = values.date_taken.ok_or("exif.date")?;
+					rendered.push_str(&date.format(format).to_string());
+				}
+				Part::Capture(group) => rendered.push_str(
+					values
+						.captures
+						.and_then(|captures| captures.get(*group))
+						.map(|capture| capture.as_str())
+						.ok_or("capture group")?,
+				),
+			}
+		}
+
+		Ok(rendered)
+	}
+}
+
+impl FromStr for Template {
+	type Err = TemplateError;
+
+	fn from_str(template: &str) -> Result<Self, Self::Err> {
+		let mut parts = Vec::new();
+		let mut literal = String::new();
+		let mut chars = template.char_indices().peekable();
+
+		while let Some((i, c)) = chars.next() {
+			match c {
+				'{' if chars.next_if(|(_, c)| *c == '{').is_some() => literal.push('{'),
+				'}' if chars.next_if(|(_, c)| *c == '}').is_some() => literal.push('}'),
+				'}' => return Err(TemplateError::Unmatched(i)),
+				'{' => {
+					let start = i + 1;
+					let end = loop {
+						match chars.next() {
+							Some((j, '}')) => break j,
+							Some(_) => {}
+							None => return Err(TemplateError::Unclosed(i)),
+						}
+					};
+
+					if !literal.is_empty() {
+						parts.push(Part::Literal(mem::take(&mut literal)));
+					}
+					parts.push(parse_token(&template[start..end])?);
+				}
+				c => literal.push(c),
+			}
+		}
+
+		if !literal.is_empty() {
+			parts.push(Part::Literal(literal));
+		}
+
+		Ok(Self { parts })
+	}
+}
+
+fn parse_token(token: &str) -> Result<Part, TemplateError> {
+	let (name, argument) = token
+		.split_once(':')
+		.map_or((token, None), |(name, argument)| (name, Some(argument)));
+
+	match (name, argument) {
+		("name", None) => Ok(Part::Name),
+		("ext", None) => Ok(Part::Extension),
+		("date", format) => date_format(format).map(Part::Date),
+		("exif.date", format) => date_format(format).map(Part::ExifDate),
+		("exif.camera", None) => Ok(Part::ExifCamera),
+		("counter", None) => Ok(Part::Counter(1)),
+		("counter", Some(width)) => width
+			.parse()
+			.ok()
+			.filter(|width| (1..=MAX_COUNTER_WIDTH).contains(width))
+			.map(Part::Counter)
+			.ok_or_else(|| TemplateError::InvalidCounterWidth(width.to_string())),
+		(group, None) if !group.is_empty() && group.bytes().all(|b| b.is_ascii_digit()) => group
+			.parse()
+			.map(Part::Capture)
+			.map_err(|_| TemplateError::UnknownToken(token.to_string())),
+		_ => Err(TemplateError::UnknownToken(token.to_string())),
+	}
+}
+
+fn date_format(format: Option<&str>) -> Result<String, TemplateError> {
+	let format = format.unwrap_or(DEFAULT_DATE_FORMAT);
+
+	if format.is_empty() || StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+		return Err(TemplateError::InvalidDateFormat(format.to_string()));
+	}
+
+	Ok(format.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use regex::Regex;
+
+	fn render(template: &str, values: &Values<'_>) -> Result<String, &'static str> {
+		template
+			.parse::<Template>()
+			.expect("valid test template")
+			.render(values)
+	}
+
+	#[test]
+	fn names_and_counters() {
+		let values = Values {
+			name: "IMG_0042",
+			extension: "jpg",
+			counter: 7,
+			..Default::default()
+		};
+
+		assert_eq!(
+			render("{name}-{counter:3}", &values),
+			Ok("IMG_0042-007".to_string())
+		);
+		assert_eq!(
+			render("{{{counter}}} {ext}", &values),
+			Ok("{7} jpg".to_string())
+		);
+	}
+
+	#[test]
+	fn dates() {
+		let date = DateTime::parse_from_rfc3339("2024-06-20T10:15:42+02:00").ok();
+		let values = Values {
+			date_created: date,
+			..Default::default()
+		};
+
+		assert_eq!(render("{date}", &values), Ok("2024-06-20".to_string()));
+		assert_eq!(
+			render("{date:%Y%m%d_%H%M}", &values),
+			Ok("20240620_1015".to_string())
+		);
+		assert_eq!(render("{exif.date}", &values), Err("exif.date"));
+	}
+
+	#[test]
+	fn capture_groups() {
+		let regex = Regex::new(r"(\w+)_(\d+)").expect("valid test regex");
+		let captures = regex.captures("IMG_0042").expect("matching test name");
+		let values = Values {
+			captures: Some(&captures),
+			..Default::default()
+		};
+
+		assert_eq!(render("{2}-{1}", &values), Ok("0042-IMG".to_string()));
+		assert_eq!(render("{3}", &values), Err("capture group"));
+		assert_eq!(
+			"{2}-{1}".parse::<Template>().map(|t| t.max_capture()),
+			Ok(Some(2))
+		);
+	}
+
+	#[test]
+	fn invalid_templates() {
+		assert_eq!("{name".parse::<Template>(), Err(TemplateError::Unclosed(0)));
+		assert_eq!(
+			"name}".parse::<Template>(),
+			Err(TemplateError::Unmatched(4))
+		);
+		assert_eq!(
+			"{nope}".parse::<Template>(),
+			Err(TemplateError::UnknownToken("nope".to_string()))
+		);
+		assert_eq!(
+			"{counter:0}".parse::<Template>(),
+			Err(TemplateError::InvalidCounterWidth("0".to_string()))
+		);
+		assert_eq!(
+			"{date:%Q}".parse::<Template>(),
+			Err(TemplateError::InvalidDateFormat("%Q".to_string()))
+		);
+	}
+}
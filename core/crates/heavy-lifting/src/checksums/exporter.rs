@@ -0,0 +1,600 @@
+use crate::{
+	checksums::{self, format_manifest, update_persisted_failures, ChecksumFormat, ManifestEntry},
+	file_copier::{available_path, exists},
+	file_identifier,
+	job_system::{
+		job::{Job, JobReturn, JobTaskDispatcher, ReturnStatus},
+		report::ReportOutputMetadata,
+		utils::{cancel_pending_tasks, location_concurrency_key},
+		SerializableJob, SerializedTasks,
+	},
+	utils::{
+		io_throttle::IoThrottle,
+		sub_path::{self, get_full_path_from_sub_path},
+	},
+	Error, JobName, NonCriticalError, OuterContext, ProgressUpdate,
+};
+
+use sd_core_file_path_helper::IsolatedFilePathData;
+use sd_core_prisma_helpers::file_path_for_file_copier;
+
+use sd_prisma::prisma::{file_path, location, PrismaClient, SortOrder};
+use sd_task_system::{
+	IntoTask, SerializableTask, Task, TaskDispatcher, TaskHandle, TaskOutput, TaskStatus,
+};
+use sd_utils::{db::maybe_missing, error::FileIOError};
+
+use std::{
+	collections::HashMap,
+	hash::{Hash, Hasher},
+	mem,
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::Duration,
+};
+
+use futures::{stream::FuturesUnordered, StreamExt};
+use futures_concurrency::future::TryJoin;
+use prisma_client_rust::operator::or;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::fs;
+use tracing::{debug, warn};
+
+use super::{
+	tasks::{
+		checksum::{self, ChecksumFile},
+		Checksum,
+	},
+	BATCH_SIZE,
+};
+
+/// Computes the checksums of the files of a location, or of a directory or selection in it, and
+/// writes them into a `SHA256SUMS` or `.sfv` manifest that external tools can verify.
+///
+/// The manifest is written in the sub path directory, or in the location root, with the paths of
+/// the files relative to it.
+#[derive(Debug)]
+pub struct ChecksumExporter {
+	location: Arc<location::Data>,
+	location_path: Arc<PathBuf>,
+	sub_path: Option<PathBuf>,
+	file_path_ids: Option<Vec<file_path::id::Type>>,
+	format: ChecksumFormat,
+	io_throttle: IoThrottle,
+
+	/// Where the manifest is written, resolved when the job starts
+	manifest_dir: Option<PathBuf>,
+	entries: Vec<ManifestEntry>,
+
+	metadata: Metadata,
+
+	errors: Vec<NonCriticalError>,
+
+	pending_tasks_on_resume: Vec<TaskHandle<Error>>,
+	tasks_for_shutdown: Vec<Box<dyn Task<Error>>>,
+}
+
+impl Hash for ChecksumExporter {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.location.id.hash(state);
+		if let Some(ref sub_path) = self.sub_path {
+			sub_path.hash(state);
+		}
+		if let Some(ref file_path_ids) = self.file_path_ids {
+			file_path_ids.hash(state);
+		}
+		self.format.hash(state);
+	}
+}
+
+impl Job for ChecksumExporter {
+	const NAME: JobName = JobName::ChecksumExporter;
+
+	async fn resume_tasks(
+		&mut self,
+		dispatcher: &JobTaskDispatcher,
+		ctx: &impl OuterContext,
+		SerializedTasks(serialized_tasks): SerializedTasks,
+	) -> Result<(), Error> {
+		let dispatcher =
+			dispatcher.with_concurrency_key(location_concurrency_key(ctx.id(), self.location.id));
+		let io_throttle = &dispatcher.io_throttle(&self.io_throttle);
+
+		self.pending_tasks_on_resume = dispatcher
+			.dispatch_many_boxed(
+				rmp_serde::from_slice::<Vec<Vec<u8>>>(&serialized_tasks)
+					.map_err(checksums::Error::from)?
+					.into_iter()
+					.map(|task_bytes| async move {
+						Checksum::deserialize(&task_bytes, ())
+							.await
+							// Tasks share the job's budget again, instead of one each
+							.map(|task| task.with_io_throttle(io_throttle.clone()))
+							.map(IntoTask::into_task)
+					})
+					.collect::<Vec<_>>()
+					.try_join()
+					.await
+					.map_err(checksums::Error::from)?,
+			)
+			.await;
+
+		Ok(())
+	}
+
+	async fn run<Ctx: OuterContext>(
+		mut self,
+		dispatcher: JobTaskDispatcher,
+		ctx: Ctx,
+	) -> Result<ReturnStatus, Error> {
+		let dispatcher =
+			dispatcher.with_concurrency_key(location_concurrency_key(ctx.id(), self.location.id));
+
+		let mut pending_running_tasks = FuturesUnordered::new();
+
+		self.init_or_resume(&mut pending_running_tasks, &ctx, &dispatcher)
+			.await?;
+
+		while let Some(task) = pending_running_tasks.next().await {
+			match task {
+				Ok(TaskStatus::Done((_, TaskOutput::Out(out)))) => {
+					self.process_checksum_output(
+						*out.downcast::<checksum::Output>()
+							.expect("the checksum exporter job only dispatches checksum tasks"),
+						&ctx,
+					)
+					.await;
+				}
+
+				Ok(TaskStatus::Done((task_id, TaskOutput::Empty))) => {
+					warn!("Task <id='{task_id}'> returned an empty output");
+				}
+
+				Ok(TaskStatus::Shutdown(task)) => {
+					self.tasks_for_shutdown.push(task);
+				}
+
+				Ok(TaskStatus::Error(e)) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e);
+				}
+
+				Ok(TaskStatus::Canceled | TaskStatus::ForcedAbortion) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Ok(ReturnStatus::Canceled);
+				}
+
+				Err(e) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e.into());
+				}
+			}
+		}
+
+		if !self.tasks_for_shutdown.is_empty() {
+			return Ok(ReturnStatus::Shutdown(
+				SerializableJob::<Ctx>::serialize(self).await,
+			));
+		}
+
+		self.write_manifest().await?;
+
+		let Self {
+			metadata, errors, ..
+		} = self;
+
+		Ok(ReturnStatus::Completed(
+			JobReturn::builder()
+				.with_metadata(metadata)
+				.with_non_critical_errors(errors)
+				.build(),
+		))
+	}
+}
+
+impl ChecksumExporter {
+	pub fn new(
+		location: location::Data,
+		sub_path: Option<PathBuf>,
+		format: ChecksumFormat,
+	) -> Result<Self, checksums::Error> {
+		Ok(Self {
+			location_path: maybe_missing(&location.path, "location.path")
+				.map(PathBuf::from)
+				.map(Arc::new)?,
+			location: Arc::new(location),
+			sub_path,
+			file_path_ids: None,
+			format,
+			io_throttle: IoThrottle::default(),
+			manifest_dir: None,
+			entries: Vec::new(),
+			metadata: Metadata::default(),
+			errors: Vec::new(),
+			pending_tasks_on_resume: Vec::new(),
+			tasks_for_shutdown: Vec::new(),
+		})
+	}
+
+	/// Caps how fast files are read, shared by every checksum task of the job
+	#[must_use]
+	pub fn with_io_throttle(mut self, io_throttle: IoThrottle) -> Self {
+		self.io_throttle = io_throttle;
+		self
+	}
+
+	/// Only exports the selected file paths, the files in selected directories included, instead
+	/// of every file in the sub path
+	#[must_use]
+	pub fn with_file_paths(mut self, file_path_ids: Vec<file_path::id::Type>) -> Self {
+		self.file_path_ids = Some(file_path_ids);
+		self
+	}
+
+	async fn init_or_resume(
+		&mut self,
+		pending_running_tasks: &mut FuturesUnordered<TaskHandle<Error>>,
+		ctx: &impl OuterContext,
+		dispatcher: &JobTaskDispatcher,
+	) -> Result<(), checksums::Error> {
+		// Resumed tasks were already dispatched by `resume_tasks`
+		if !self.pending_tasks_on_resume.is_empty() {
+			pending_running_tasks.extend(mem::take(&mut self.pending_tasks_on_resume));
+
+			return Ok(());
+		}
+
+		let db = ctx.db();
+		let location_id = self.location.id;
+		let location_path = &*self.location_path;
+
+		let manifest_dir =
+			get_full_path_from_sub_path(location_id, &self.sub_path, location_path, db).await?;
+		let iso_file_path =
+			IsolatedFilePathData::new(location_id, location_path, &manifest_dir, true)
+				.map_err(sub_path::Error::from)?;
+
+		debug!("Exporting checksums of files in location {location_id} at directory \"{iso_file_path}\"");
+
+		let selection_filter = self.selection_filter(db).await?;
+
+		let io_throttle = dispatcher.io_throttle(&self.io_throttle);
+		let mut last_file_path_id = None;
+
+		loop {
+			#[allow(clippy::cast_possible_wrap)]
+			// SAFETY: we know that BATCH_SIZE is a valid i64
+			let file_paths = db
+				.file_path()
+				.find_many(sd_utils::chain_optional_iter(
+					[
+						file_path::location_id::equals(Some(location_id)),
+						file_path::is_dir::equals(Some(false)),
+						file_identifier::not_in_archive(),
+						file_path::materialized_path::starts_with(
+							iso_file_path
+								.materialized_path_for_children()
+								.expect("manifest iso_file_path must be a directory"),
+						),
+					],
+					[
+						last_file_path_id.map(file_path::id::gt),
+						selection_filter.clone(),
+					],
+				))
+				.order_by(file_path::id::order(SortOrder::Asc))
+				.take(BATCH_SIZE as i64)
+				.select(file_path_for_file_copier::select())
+				.exec()
+				.await?;
+
+			let Some(last_file_path) = file_paths.last() else {
+				break;
+			};
+
+			last_file_path_id = Some(last_file_path.id);
+			self.metadata.total_files += file_paths.len() as u64;
+
+			let files = file_paths
+				.iter()
+				.map(|file_path| {
+					IsolatedFilePathData::try_from((location_id, file_path)).map(|iso_file_path| {
+						ChecksumFile {
+							file_path_id: Some(file_path.id),
+							path: location_path.join(iso_file_path),
+							expected: None,
+						}
+					})
+				})
+				.collect::<Result<Vec<_>, _>>()?;
+
+			pending_running_tasks.push(
+				dispatcher
+					.dispatch(
+						Checksum::new(self.format, files).with_io_throttle(io_throttle.clone()),
+					)
+					.await,
+			);
+		}
+
+		self.manifest_dir = Some(manifest_dir);
+
+		ctx.progress(vec![
+			ProgressUpdate::TaskCount(self.metadata.total_files),
+			ProgressUpdate::Message(format!(
+				"Computing checksums of {} files",
+				self.metadata.total_files
+			)),
+		]);
+
+		Ok(())
+	}
+
+	/// Matches the selected files, and the files in selected directories
+	async fn selection_filter(
+		&self,
+		db: &PrismaClient,
+	) -> Result<Option<file_path::WhereParam>, checksums::Error> {
+		let Some(file_path_ids) = &self.file_path_ids else {
+			return Ok(None);
+		};
+
+		let selected = db
+			.file_path()
+			.find_many(vec![
+				file_path::location_id::equals(Some(self.location.id)),
+				file_path::id::in_vec(file_path_ids.clone()),
+			])
+			.select(file_path_for_file_copier::select())
+			.exec()
+			.await?;
+
+		if let Some(missing_id) = file_path_ids
+			.iter()
+			.find(|id| !selected.iter().any(|file_path| file_path.id == **id))
+		{
+			return Err(checksums::Error::FilePathNotFound(*missing_id));
+		}
+
+		let mut params = vec![file_path::id::in_vec(file_path_ids.clone())];
+
+		for file_path in &selected {
+			let iso_file_path = IsolatedFilePathData::try_from((self.location.id, file_path))?;
+
+			if let Some(children_path) = iso_file_path.materialized_path_for_children() {
+				params.push(file_path::materialized_path::starts_with(children_path));
+			}
+		}
+
+		Ok(Some(or(params)))
+	}
+
+	async fn process_checksum_output(
+		&mut self,
+		checksum::Output {
+			checksums,
+			succeeded_file_path_ids,
+			missing,
+			unreadable,
+			read_bytes,
+			checksum_time,
+			failures,
+			..
+		}: checksum::Output,
+		ctx: &impl OuterContext,
+	) {
+		let manifest_dir = self
+			.manifest_dir
+			.as_deref()
+			.expect("manifest directory is resolved before dispatching tasks");
+
+		self.metadata.checksummed_files += checksums.len() as u64;
+		self.metadata.failed_files += missing + unreadable;
+		self.metadata.read_bytes += read_bytes;
+		self.metadata.checksum_time += checksum_time;
+
+		self.entries
+			.extend(checksums.into_iter().filter_map(|(path, checksum)| {
+				manifest_entry_path(manifest_dir, &path)
+					.map(|path| ManifestEntry { path, checksum })
+			}));
+
+		let failed_file_paths = failures
+			.into_iter()
+			.filter_map(|(file_path_id, error)| {
+				self.errors
+					.push(checksums::NonCriticalError::from(error.clone()).into());
+				file_path_id.map(|id| (id, checksums::NonCriticalError::from(error).into()))
+			})
+			.collect::<Vec<_>>();
+
+		update_persisted_failures(
+			ctx.db(),
+			JobName::ChecksumExporter,
+			self.location.id,
+			succeeded_file_path_ids,
+			failed_file_paths,
+		)
+		.await;
+
+		ctx.progress(vec![ProgressUpdate::CompletedTaskCount(
+			self.metadata.checksummed_files + self.metadata.failed_files,
+		)]);
+	}
+
+	/// Writes the manifest with every checksum, sorted by path. An existing manifest is kept, the
+	/// new one gets a free name next to it.
+	async fn write_manifest(&mut self) -> Result<(), checksums::Error> {
+		let manifest_dir = self
+			.manifest_dir
+			.as_deref()
+			.expect("manifest directory is resolved before dispatching tasks");
+
+		let dir_name = manifest_dir
+			.file_name()
+			.map(|name| name.to_string_lossy().into_owned())
+			.unwrap_or_default();
+
+		let mut manifest_path = manifest_dir.join(self.format.manifest_name(&dir_name));
+		if exists(&manifest_path).await? {
+			manifest_path = available_path(&manifest_path).await?.ok_or_else(|| {
+				checksums::Error::FailedToFindAvailableName(manifest_path.clone())
+			})?;
+		}
+
+		self.entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+		fs::write(&manifest_path, format_manifest(self.format, &self.entries))
+			.await
+			.map_err(|e| {
+				FileIOError::from((&manifest_path, e, "Failed to write checksum manifest"))
+			})?;
+
+		self.metadata.manifest_path = Some(manifest_path);
+
+		Ok(())
+	}
+}
+
+/// Path of a file relative to the manifest's directory, with `/` separators
+fn manifest_entry_path(manifest_dir: &Path, path: &Path) -> Option<String> {
+	let relative = path.strip_prefix(manifest_dir).ok()?;
+
+	Some(
+		relative
+			.components()
+			.map(|component| component.as_os_str().to_string_lossy())
+			.collect::<Vec<_>>()
+			.join("/"),
+	)
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Metadata {
+	total_files: u64,
+	checksummed_files: u64,
+	failed_files: u64,
+	read_bytes: u64,
+	checksum_time: Duration,
+	manifest_path: Option<PathBuf>,
+}
+
+impl From<Metadata> for ReportOutputMetadata {
+	fn from(value: Metadata) -> Self {
+		Self::Metrics(HashMap::from([
+			("total_files".into(), json!(value.total_files)),
+			("checksummed_files".into(), json!(value.checksummed_files)),
+			("failed_files".into(), json!(value.failed_files)),
+			("read_bytes".into(), json!(value.read_bytes)),
+			("checksum_time".into(), json!(value.checksum_time)),
+			("manifest_path".into(), json!(value.manifest_path)),
+		]))
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+struct SaveState {
+	location: Arc<location::Data>,
+	location_path: Arc<PathBuf>,
+	sub_path: Option<PathBuf>,
+	file_path_ids: Option<Vec<file_path::id::Type>>,
+	format: ChecksumFormat,
+	#[serde(default)]
+	io_throttle: IoThrottle,
+
+	manifest_dir: Option<PathBuf>,
+	entries: Vec<ManifestEntry>,
+
+	metadata: Metadata,
+
+	errors: Vec<NonCriticalError>,
+
+	tasks_for_shutdown_bytes: Option<SerializedTasks>,
+}
+
+impl<Ctx: OuterContext> SerializableJob<Ctx> for ChecksumExporter {
+	async fn serialize(self) -> Result<Option<Vec<u8>>, rmp_serde::encode::Error> {
+		let Self {
+			location,
+			location_path,
+			sub_path,
+			file_path_ids,
+			format,
+			io_throttle,
+			manifest_dir,
+			entries,
+			metadata,
+			errors,
+			tasks_for_shutdown,
+			..
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState {
+			location,
+			location_path,
+			sub_path,
+			file_path_ids,
+			format,
+			io_throttle,
+			manifest_dir,
+			entries,
+			metadata,
+			errors,
+			tasks_for_shutdown_bytes: Some(SerializedTasks(rmp_serde::to_vec_named(
+				&tasks_for_shutdown
+					.into_iter()
+					.map(|task| async move {
+						task.downcast::<Checksum>()
+							.expect("the checksum exporter job only dispatches checksum tasks")
+							.serialize()
+							.await
+					})
+					.collect::<Vec<_>>()
+					.try_join()
+					.await?,
+			)?)),
+		})
+		.map(Some)
+	}
+
+	async fn deserialize(
+		serialized_job: &[u8],
+		_: &Ctx,
+	) -> Result<Option<(Self, Option<SerializedTasks>)>, rmp_serde::decode::Error> {
+		let SaveState {
+			location,
+			location_path,
+			sub_path,
+			file_path_ids,
+			format,
+			io_throttle,
+			manifest_dir,
+			entries,
+			metadata,
+			errors,
+			tasks_for_shutdown_bytes,
+		} = rmp_serde::from_slice::<SaveState>(serialized_job)?;
+
+		Ok(Some((
+			Self {
+				location,
+				location_path,
+				sub_path,
+				file_path_ids,
+				format,
+				io_throttle,
+				manifest_dir,
+				entries,
+				metadata,
+				errors,
+				pending_tasks_on_resume: Vec::new(),
+				tasks_for_shutdown: Vec::new(),
+			},
+			tasks_for_shutdown_bytes,
+		)))
+	}
+}
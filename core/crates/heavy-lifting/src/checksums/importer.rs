@@ -0,0 +1,525 @@
+use crate::{
+	archiver::sanitize_entry_path,
+	checksums::{self, parse_manifest, update_persisted_failures, ChecksumFormat, ManifestEntry},
+	job_system::{
+		job::{Job, JobReturn, JobTaskDispatcher, ReturnStatus},
+		report::ReportOutputMetadata,
+		utils::{cancel_pending_tasks, location_concurrency_key},
+		SerializableJob, SerializedTasks,
+	},
+	utils::io_throttle::IoThrottle,
+	Error, JobName, NonCriticalError, OuterContext, ProgressUpdate,
+};
+
+use sd_core_file_path_helper::{filter_existing_file_path_params, IsolatedFilePathData};
+use sd_core_prisma_helpers::file_path_for_file_copier;
+
+use sd_prisma::prisma::{file_path, location, PrismaClient};
+use sd_task_system::{
+	IntoTask, SerializableTask, Task, TaskDispatcher, TaskHandle, TaskOutput, TaskStatus,
+};
+use sd_utils::{db::maybe_missing, error::FileIOError};
+
+use std::{
+	collections::HashMap,
+	hash::{Hash, Hasher},
+	mem,
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::Duration,
+};
+
+use futures::{stream::FuturesUnordered, StreamExt};
+use futures_concurrency::future::TryJoin;
+use prisma_client_rust::operator::{and, or};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::fs;
+use tracing::{debug, warn};
+
+use super::{
+	tasks::{
+		checksum::{self, ChecksumFile},
+		Checksum,
+	},
+	BATCH_SIZE,
+};
+
+/// Verifies the files listed in a `SHA256SUMS` or `.sfv` manifest of a location against their
+/// checksums, like `sha256sum --check` would.
+///
+/// Missing, unreadable and mismatched files are reported as non-critical errors, and also persisted
+/// per file path for the ones indexed in the location, so they can be listed with the location's
+/// failures and checked again later.
+#[derive(Debug)]
+pub struct ChecksumImporter {
+	location: Arc<location::Data>,
+	location_path: Arc<PathBuf>,
+	manifest_file_path_id: file_path::id::Type,
+	io_throttle: IoThrottle,
+
+	metadata: Metadata,
+
+	errors: Vec<NonCriticalError>,
+
+	pending_tasks_on_resume: Vec<TaskHandle<Error>>,
+	tasks_for_shutdown: Vec<Box<dyn Task<Error>>>,
+}
+
+impl Hash for ChecksumImporter {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.location.id.hash(state);
+		self.manifest_file_path_id.hash(state);
+	}
+}
+
+impl Job for ChecksumImporter {
+	const NAME: JobName = JobName::ChecksumImporter;
+
+	async fn resume_tasks(
+		&mut self,
+		dispatcher: &JobTaskDispatcher,
+		ctx: &impl OuterContext,
+		SerializedTasks(serialized_tasks): SerializedTasks,
+	) -> Result<(), Error> {
+		let dispatcher =
+			dispatcher.with_concurrency_key(location_concurrency_key(ctx.id(), self.location.id));
+		let io_throttle = &dispatcher.io_throttle(&self.io_throttle);
+
+		self.pending_tasks_on_resume = dispatcher
+			.dispatch_many_boxed(
+				rmp_serde::from_slice::<Vec<Vec<u8>>>(&serialized_tasks)
+					.map_err(checksums::Error::from)?
+					.into_iter()
+					.map(|task_bytes| async move {
+						Checksum::deserialize(&task_bytes, ())
+							.await
+							// Tasks share the job's budget again, instead of one each
+							.map(|task| task.with_io_throttle(io_throttle.clone()))
+							.map(IntoTask::into_task)
+					})
+					.collect::<Vec<_>>()
+					.try_join()
+					.await
+					.map_err(checksums::Error::from)?,
+			)
+			.await;
+
+		Ok(())
+	}
+
+	async fn run<Ctx: OuterContext>(
+		mut self,
+		dispatcher: JobTaskDispatcher,
+		ctx: Ctx,
+	) -> Result<ReturnStatus, Error> {
+		let dispatcher =
+			dispatcher.with_concurrency_key(location_concurrency_key(ctx.id(), self.location.id));
+
+		let mut pending_running_tasks = FuturesUnordered::new();
+
+		self.init_or_resume(&mut pending_running_tasks, &ctx, &dispatcher)
+			.await?;
+
+		while let Some(task) = pending_running_tasks.next().await {
+			match task {
+				Ok(TaskStatus::Done((_, TaskOutput::Out(out)))) => {
+					self.process_checksum_output(
+						*out.downcast::<checksum::Output>()
+							.expect("the checksum importer job only dispatches checksum tasks"),
+						&ctx,
+					)
+					.await;
+				}
+
+				Ok(TaskStatus::Done((task_id, TaskOutput::Empty))) => {
+					warn!("Task <id='{task_id}'> returned an empty output");
+				}
+
+				Ok(TaskStatus::Shutdown(task)) => {
+					self.tasks_for_shutdown.push(task);
+				}
+
+				Ok(TaskStatus::Error(e)) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e);
+				}
+
+				Ok(TaskStatus::Canceled | TaskStatus::ForcedAbortion) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Ok(ReturnStatus::Canceled);
+				}
+
+				Err(e) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e.into());
+				}
+			}
+		}
+
+		if !self.tasks_for_shutdown.is_empty() {
+			return Ok(ReturnStatus::Shutdown(
+				SerializableJob::<Ctx>::serialize(self).await,
+			));
+		}
+
+		let Self {
+			metadata, errors, ..
+		} = self;
+
+		Ok(ReturnStatus::Completed(
+			JobReturn::builder()
+				.with_metadata(metadata)
+				.with_non_critical_errors(errors)
+				.build(),
+		))
+	}
+}
+
+impl ChecksumImporter {
+	/// The manifest's format is told by its name, see [`ChecksumFormat::from_path`]
+	pub fn new(
+		location: location::Data,
+		manifest_file_path_id: file_path::id::Type,
+	) -> Result<Self, checksums::Error> {
+		Ok(Self {
+			location_path: maybe_missing(&location.path, "location.path")
+				.map(PathBuf::from)
+				.map(Arc::new)?,
+			location: Arc::new(location),
+			manifest_file_path_id,
+			io_throttle: IoThrottle::default(),
+			metadata: Metadata::default(),
+			errors: Vec::new(),
+			pending_tasks_on_resume: Vec::new(),
+			tasks_for_shutdown: Vec::new(),
+		})
+	}
+
+	/// Caps how fast files are read, shared by every checksum task of the job
+	#[must_use]
+	pub fn with_io_throttle(mut self, io_throttle: IoThrottle) -> Self {
+		self.io_throttle = io_throttle;
+		self
+	}
+
+	async fn init_or_resume(
+		&mut self,
+		pending_running_tasks: &mut FuturesUnordered<TaskHandle<Error>>,
+		ctx: &impl OuterContext,
+		dispatcher: &JobTaskDispatcher,
+	) -> Result<(), checksums::Error> {
+		// Resumed tasks were already dispatched by `resume_tasks`
+		if !self.pending_tasks_on_resume.is_empty() {
+			pending_running_tasks.extend(mem::take(&mut self.pending_tasks_on_resume));
+
+			return Ok(());
+		}
+
+		let db = ctx.db();
+		let location_id = self.location.id;
+		let location_path = &*self.location_path;
+
+		let manifest_file_path = db
+			.file_path()
+			.find_first(vec![
+				file_path::location_id::equals(Some(location_id)),
+				file_path::id::equals(self.manifest_file_path_id),
+			])
+			.select(file_path_for_file_copier::select())
+			.exec()
+			.await?
+			.ok_or(checksums::Error::FilePathNotFound(
+				self.manifest_file_path_id,
+			))?;
+
+		let manifest_path = location_path.join(IsolatedFilePathData::try_from((
+			location_id,
+			&manifest_file_path,
+		))?);
+
+		let format = ChecksumFormat::from_path(&manifest_path)
+			.ok_or_else(|| checksums::Error::UnknownFormat(manifest_path.clone()))?;
+
+		let manifest = fs::read(&manifest_path).await.map_err(|e| {
+			FileIOError::from((&manifest_path, e, "Failed to read checksum manifest"))
+		})?;
+
+		let manifest_dir = manifest_path
+			.parent()
+			.expect("a file in a location has a parent directory");
+
+		debug!(
+			"Verifying files of checksum manifest <path='{}'> in location {location_id}",
+			manifest_path.display()
+		);
+
+		let mut files = Vec::new();
+
+		for entry in parse_manifest(format, &String::from_utf8_lossy(&manifest)) {
+			match entry {
+				Ok(ManifestEntry { path, checksum }) => {
+					// Manifests can come from anywhere, their entries must stay in the directory
+					if let Some(relative_path) = sanitize_entry_path(&path) {
+						files.push(ChecksumFile {
+							file_path_id: None,
+							path: manifest_dir.join(relative_path),
+							expected: Some(checksum),
+						});
+					} else {
+						self.errors.push(
+							checksums::NonCriticalError::EntryOutsideDirectory(
+								manifest_path.clone(),
+								path,
+							)
+							.into(),
+						);
+					}
+				}
+
+				Err(line) => {
+					self.errors.push(
+						checksums::NonCriticalError::MalformedLine(manifest_path.clone(), line)
+							.into(),
+					);
+				}
+			}
+		}
+
+		self.metadata.total_files = files.len() as u64;
+		self.metadata.invalid_entries = self.errors.len() as u64;
+
+		let io_throttle = dispatcher.io_throttle(&self.io_throttle);
+
+		for mut chunk in files
+			.chunks(BATCH_SIZE)
+			.map(<[_]>::to_vec)
+			.collect::<Vec<_>>()
+		{
+			set_file_path_ids(db, location_id, location_path, &mut chunk).await?;
+
+			pending_running_tasks.push(
+				dispatcher
+					.dispatch(Checksum::new(format, chunk).with_io_throttle(io_throttle.clone()))
+					.await,
+			);
+		}
+
+		ctx.progress(vec![
+			ProgressUpdate::TaskCount(self.metadata.total_files),
+			ProgressUpdate::Message(format!(
+				"Verifying {} files of checksum manifest",
+				self.metadata.total_files
+			)),
+		]);
+
+		Ok(())
+	}
+
+	async fn process_checksum_output(
+		&mut self,
+		checksum::Output {
+			succeeded_file_path_ids,
+			matched,
+			mismatched,
+			missing,
+			unreadable,
+			read_bytes,
+			checksum_time,
+			failures,
+			..
+		}: checksum::Output,
+		ctx: &impl OuterContext,
+	) {
+		self.metadata.matched += matched;
+		self.metadata.mismatched += mismatched;
+		self.metadata.missing += missing;
+		self.metadata.unreadable += unreadable;
+		self.metadata.read_bytes += read_bytes;
+		self.metadata.checksum_time += checksum_time;
+
+		let failed_file_paths = failures
+			.into_iter()
+			.filter_map(|(file_path_id, error)| {
+				self.errors
+					.push(checksums::NonCriticalError::from(error.clone()).into());
+				file_path_id.map(|id| (id, checksums::NonCriticalError::from(error).into()))
+			})
+			.collect::<Vec<_>>();
+
+		update_persisted_failures(
+			ctx.db(),
+			JobName::ChecksumImporter,
+			self.location.id,
+			succeeded_file_path_ids,
+			failed_file_paths,
+		)
+		.await;
+
+		ctx.progress(vec![ProgressUpdate::CompletedTaskCount(
+			self.metadata.matched
+				+ self.metadata.mismatched
+				+ self.metadata.missing
+				+ self.metadata.unreadable,
+		)]);
+	}
+}
+
+/// Links manifest entries to the file paths indexed in the location, the ones outside of it or
+/// not indexed yet are still verified but their failures are only in the job report
+async fn set_file_path_ids(
+	db: &PrismaClient,
+	location_id: location::id::Type,
+	location_path: &Path,
+	files: &mut [ChecksumFile],
+) -> Result<(), checksums::Error> {
+	let iso_file_paths = files
+		.iter()
+		.map(|file| IsolatedFilePathData::new(location_id, location_path, &file.path, false).ok())
+		.collect::<Vec<_>>();
+
+	let params = iso_file_paths
+		.iter()
+		.flatten()
+		.map(|iso_file_path| and(filter_existing_file_path_params(iso_file_path)))
+		.collect::<Vec<_>>();
+
+	if params.is_empty() {
+		return Ok(());
+	}
+
+	let file_paths = db
+		.file_path()
+		.find_many(vec![or(params)])
+		.select(file_path_for_file_copier::select())
+		.exec()
+		.await?;
+
+	for (file, iso_file_path) in files.iter_mut().zip(iso_file_paths) {
+		let Some(iso_file_path) = iso_file_path else {
+			continue;
+		};
+
+		file.file_path_id = file_paths
+			.iter()
+			.find(|file_path| {
+				IsolatedFilePathData::try_from((location_id, *file_path))
+					.is_ok_and(|found| found == iso_file_path)
+			})
+			.map(|file_path| file_path.id);
+	}
+
+	Ok(())
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Metadata {
+	total_files: u64,
+	matched: u64,
+	mismatched: u64,
+	missing: u64,
+	unreadable: u64,
+	invalid_entries: u64,
+	read_bytes: u64,
+	checksum_time: Duration,
+}
+
+impl From<Metadata> for ReportOutputMetadata {
+	fn from(value: Metadata) -> Self {
+		Self::Metrics(HashMap::from([
+			("total_files".into(), json!(value.total_files)),
+			("matched_files".into(), json!(value.matched)),
+			("mismatched_files".into(), json!(value.mismatched)),
+			("missing_files".into(), json!(value.missing)),
+			("unreadable_files".into(), json!(value.unreadable)),
+			("invalid_entries".into(), json!(value.invalid_entries)),
+			("read_bytes".into(), json!(value.read_bytes)),
+			("checksum_time".into(), json!(value.checksum_time)),
+		]))
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+struct SaveState {
+	location: Arc<location::Data>,
+	location_path: Arc<PathBuf>,
+	manifest_file_path_id: file_path::id::Type,
+	#[serde(default)]
+	io_throttle: IoThrottle,
+
+	metadata: Metadata,
+
+	errors: Vec<NonCriticalError>,
+
+	tasks_for_shutdown_bytes: Option<SerializedTasks>,
+}
+
+impl<Ctx: OuterContext> SerializableJob<Ctx> for ChecksumImporter {
+	async fn serialize(self) -> Result<Option<Vec<u8>>, rmp_serde::encode::Error> {
+		let Self {
+			location,
+			location_path,
+			manifest_file_path_id,
+			io_throttle,
+			metadata,
+			errors,
+			tasks_for_shutdown,
+			..
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState {
+			location,
+			location_path,
+			manifest_file_path_id,
+			io_throttle,
+			metadata,
+			errors,
+			tasks_for_shutdown_bytes: Some(SerializedTasks(rmp_serde::to_vec_named(
+				&tasks_for_shutdown
+					.into_iter()
+					.map(|task| async move {
+						task.downcast::<Checksum>()
+							.expect("the checksum importer job only dispatches checksum tasks")
+							.serialize()
+							.await
+					})
+					.collect::<Vec<_>>()
+					.try_join()
+					.await?,
+			)?)),
+		})
+		.map(Some)
+	}
+
+	async fn deserialize(
+		serialized_job: &[u8],
+		_: &Ctx,
+	) -> Result<Option<(Self, Option<SerializedTasks>)>, rmp_serde::decode::Error> {
+		let SaveState {
+			location,
+			location_path,
+			manifest_file_path_id,
+			io_throttle,
+			metadata,
+			errors,
+			tasks_for_shutdown_bytes,
+		} = rmp_serde::from_slice::<SaveState>(serialized_job)?;
+
+		Ok(Some((
+			Self {
+				location,
+				location_path,
+				manifest_file_path_id,
+				io_throttle,
+				metadata,
+				errors,
+				pending_tasks_on_resume: Vec::new(),
+				tasks_for_shutdown: Vec::new(),
+			},
+			tasks_for_shutdown_bytes,
+		)))
+	}
+}
@@ -0,0 +1,382 @@
+use crate::{job_system::failures, utils::sub_path, JobName};
+
+use sd_core_file_path_helper::FilePathError;
+
+use sd_prisma::prisma::{file_path, location, PrismaClient};
+use sd_utils::{db::MissingFieldError, error::FileIOError};
+
+use std::path::{Path, PathBuf};
+
+use futures_concurrency::future::TryJoin;
+use prisma_client_rust::QueryError;
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use specta::Type;
+use tracing::warn;
+
+pub mod exporter;
+pub mod importer;
+mod tasks;
+
+pub use exporter::ChecksumExporter;
+pub use importer::ChecksumImporter;
+pub use tasks::checksum;
+
+// How many files each checksum task reads
+const BATCH_SIZE: usize = 100;
+
+const SHA256SUMS_NAME: &str = "SHA256SUMS";
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("file_path not found in location: <id='{0}'>")]
+	FilePathNotFound(file_path::id::Type),
+	#[error("not a SHA256SUMS or .sfv checksum manifest: <path='{}'>", .0.display())]
+	UnknownFormat(PathBuf),
+	#[error("failed to find an available name for the manifest: <path='{}'>", .0.display())]
+	FailedToFindAvailableName(PathBuf),
+	#[error("missing field on database: {0}")]
+	MissingField(#[from] MissingFieldError),
+	#[error("database error: {0}")]
+	Database(#[from] QueryError),
+	#[error("failed to deserialized stored tasks for job resume: {0}")]
+	DeserializeTasks(#[from] rmp_serde::decode::Error),
+
+	#[error(transparent)]
+	FilePathError(#[from] FilePathError),
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+	#[error(transparent)]
+	SubPath(#[from] sub_path::Error),
+}
+
+impl From<Error> for rspc::Error {
+	fn from(err: Error) -> Self {
+		match err {
+			Error::FilePathNotFound(_) => {
+				Self::with_cause(ErrorCode::NotFound, err.to_string(), err)
+			}
+
+			Error::UnknownFormat(_) => {
+				Self::with_cause(ErrorCode::BadRequest, err.to_string(), err)
+			}
+
+			Error::SubPath(sub_path_err) => sub_path_err.into(),
+
+			_ => Self::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize, Type)]
+pub enum NonCriticalError {
+	#[error(transparent)]
+	Checksum(#[from] checksum::NonCriticalError),
+	#[error("malformed line in checksum manifest: <manifest='{}', line={1}>", .0.display())]
+	MalformedLine(PathBuf, usize),
+	#[error("checksum manifest entry points outside of its directory: <manifest='{}', entry='{1}'>", .0.display())]
+	EntryOutsideDirectory(PathBuf, String),
+}
+
+/// Standard formats of checksum manifests, as read and written by common tools
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize, Type)]
+pub enum ChecksumFormat {
+	/// SHA-256 digests as written by `sha256sum`, in a file named `SHA256SUMS`
+	#[default]
+	Sha256Sums,
+	/// CRC32 checksums in a Simple File Verification `.sfv` file, weaker but still common for
+	/// archived collections
+	Sfv,
+}
+
+impl ChecksumFormat {
+	/// Tells the format of a manifest by its name
+	#[must_use]
+	pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+		let name = path.as_ref().file_name()?.to_str()?.to_lowercase();
+
+		if name == "sha256sums" || name.ends_with(".sha256") || name.ends_with(".sha256sums") {
+			Some(Self::Sha256Sums)
+		} else if name.ends_with(".sfv") {
+			Some(Self::Sfv)
+		} else {
+			None
+		}
+	}
+
+	/// Name of a manifest exported for the directory named `dir_name`
+	#[must_use]
+	pub fn manifest_name(self, dir_name: &str) -> String {
+		match self {
+			Self::Sha256Sums => SHA256SUMS_NAME.to_string(),
+			Self::Sfv if dir_name.is_empty() => "checksums.sfv".to_string(),
+			Self::Sfv => format!("{dir_name}.sfv"),
+		}
+	}
+
+	pub(crate) fn hasher(self) -> ChecksumHasher {
+		match self {
+			Self::Sha256Sums => ChecksumHasher::Sha256(Sha256::new()),
+			Self::Sfv => ChecksumHasher::Crc32(crc32fast::Hasher::new()),
+		}
+	}
+}
+
+pub(crate) enum ChecksumHasher {
+	Sha256(Sha256),
+	Crc32(crc32fast::Hasher),
+}
+
+impl ChecksumHasher {
+	pub(crate) fn update(&mut self, chunk: &[u8]) {
+		match self {
+			Self::Sha256(hasher) => hasher.update(chunk),
+			Self::Crc32(hasher) => hasher.update(chunk),
+		}
+	}
+
+	/// Lowercase hex for SHA-256, uppercase for CRC32, as tools write them
+	pub(crate) fn finalize(self) -> String {
+		match self {
+			Self::Sha256(hasher) => format!("{:x}", hasher.finalize()),
+			Self::Crc32(hasher) => format!("{:08X}", hasher.finalize()),
+		}
+	}
+}
+
+/// A file listed in a checksum manifest, with its path relative to the manifest's directory
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+	/// Always with `/` separators
+	pub path: String,
+	pub checksum: String,
+}
+
+/// Writes entries in the manifest format, one per line
+#[must_use]
+pub fn format_manifest(format: ChecksumFormat, entries: &[ManifestEntry]) -> String {
+	let mut manifest = String::new();
+
+	if format == ChecksumFormat::Sfv {
+		manifest.push_str("; Generated by Spacedrive\n");
+	}
+
+	for ManifestEntry { path, checksum } in entries {
+		match format {
+			// Like `sha256sum`, names with a backslash or a new line are escaped and the line
+			// starts with a backslash
+			ChecksumFormat::Sha256Sums if path.contains(['\\', '\n']) => {
+				let escaped = path.replace('\\', "\\\\").replace('\n', "\\n");
+				manifest.push_str(&format!("\\{checksum}  {escaped}\n"));
+			}
+			ChecksumFormat::Sha256Sums => manifest.push_str(&format!("{checksum}  {path}\n")),
+			ChecksumFormat::Sfv => manifest.push_str(&format!("{path} {checksum}\n")),
+		}
+	}
+
+	manifest
+}
+
+/// Reads the entries of a manifest, or the 1-based number of each line that isn't one. Blank
+/// lines and comments are skipped.
+pub fn parse_manifest(format: ChecksumFormat, manifest: &str) -> Vec<Result<ManifestEntry, usize>> {
+	manifest
+		.lines()
+		.enumerate()
+		.filter(|(_, line)| {
+			let line = line.trim();
+			!line.is_empty()
+				&& !(format == ChecksumFormat::Sfv && line.starts_with(';'))
+				&& !(format == ChecksumFormat::Sha256Sums && line.starts_with('#'))
+		})
+		.map(|(idx, line)| {
+			match format {
+				ChecksumFormat::Sha256Sums => parse_sha256sums_line(line),
+				ChecksumFormat::Sfv => parse_sfv_line(line),
+			}
+			.ok_or(idx + 1)
+		})
+		.collect()
+}
+
+fn parse_sha256sums_line(line: &str) -> Option<ManifestEntry> {
+	let (escaped, line) = line
+		.strip_prefix('\\')
+		.map_or((false, line), |line| (true, line));
+
+	let checksum = line.get(..64)?;
+	let rest = line.get(64..)?;
+	// A second space for text mode, or a `*` for binary mode, which are the same on every platform
+	// Spacedrive runs on
+	let path = rest.strip_prefix(' ')?;
+	let path = path
+		.strip_prefix(' ')
+		.or_else(|| path.strip_prefix('*'))?
+		.trim_end_matches('\r');
+
+	if path.is_empty() || !checksum.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+		return None;
+	}
+
+	Some(ManifestEntry {
+		path: if escaped {
+			unescape(path)?
+		} else {
+			path.to_string()
+		},
+		checksum: checksum.to_lowercase(),
+	})
+}
+
+fn parse_sfv_line(line: &str) -> Option<ManifestEntry> {
+	let (path, checksum) = line.trim_end().rsplit_once([' ', '\t'])?;
+	let path = path.trim_end();
+
+	(!path.is_empty() && checksum.len() == 8 && checksum.bytes().all(|b| b.is_ascii_hexdigit()))
+		.then(|| ManifestEntry {
+			path: path.replace('\\', "/"),
+			checksum: checksum.to_uppercase(),
+		})
+}
+
+fn unescape(path: &str) -> Option<String> {
+	let mut unescaped = String::with_capacity(path.len());
+	let mut chars = path.chars();
+
+	while let Some(c) = chars.next() {
+		if c == '\\' {
+			match chars.next()? {
+				'\\' => unescaped.push('\\'),
+				'n' => unescaped.push('\n'),
+				_ => return None,
+			}
+		} else {
+			unescaped.push(c);
+		}
+	}
+
+	Some(unescaped)
+}
+
+/// Persists the errors of file paths that failed to be checksummed or didn't match their manifest,
+/// and clears the ones of file paths that succeeded this time, so they can be listed per path. It's
+/// best effort, as the errors are still in the job report.
+async fn update_persisted_failures(
+	db: &PrismaClient,
+	job_name: JobName,
+	location_id: location::id::Type,
+	succeeded_file_path_ids: Vec<file_path::id::Type>,
+	failed_file_paths: Vec<(file_path::id::Type, crate::NonCriticalError)>,
+) {
+	if let Err(e) = (
+		failures::record_failures(db, job_name, location_id, failed_file_paths),
+		failures::clear_failures(db, job_name, succeeded_file_path_ids),
+	)
+		.try_join()
+		.await
+	{
+		warn!("Failed to persist {job_name} failures: {e:#?}");
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn manifest_formats() {
+		assert_eq!(
+			ChecksumFormat::from_path("/a/SHA256SUMS"),
+			Some(ChecksumFormat::Sha256Sums)
+		);
+		assert_eq!(
+			ChecksumFormat::from_path("photos.sha256"),
+			Some(ChecksumFormat::Sha256Sums)
+		);
+		assert_eq!(
+			ChecksumFormat::from_path("Album.SFV"),
+			Some(ChecksumFormat::Sfv)
+		);
+		assert_eq!(ChecksumFormat::from_path("notes.txt"), None);
+	}
+
+	#[test]
+	fn checksums_match_external_tools() {
+		let mut sha256 = ChecksumFormat::Sha256Sums.hasher();
+		sha256.update(b"hello\n");
+		assert_eq!(
+			sha256.finalize(),
+			"5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03"
+		);
+
+		let mut crc32 = ChecksumFormat::Sfv.hasher();
+		crc32.update(b"hello\n");
+		assert_eq!(crc32.finalize(), "363A3020");
+	}
+
+	#[test]
+	fn round_trips() {
+		let entries = vec![
+			ManifestEntry {
+				path: "docs/notes.txt".to_string(),
+				checksum: "5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03"
+					.to_string(),
+			},
+			ManifestEntry {
+				path: "odd\\name.txt".to_string(),
+				checksum: "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+					.to_string(),
+			},
+		];
+
+		assert_eq!(
+			parse_manifest(
+				ChecksumFormat::Sha256Sums,
+				&format_manifest(ChecksumFormat::Sha256Sums, &entries)
+			),
+			entries.into_iter().map(Ok).collect::<Vec<_>>()
+		);
+
+		let entries = vec![ManifestEntry {
+			path: "disc 1/track 01.flac".to_string(),
+			checksum: "363A3020".to_string(),
+		}];
+
+		assert_eq!(
+			parse_manifest(
+				ChecksumFormat::Sfv,
+				&format_manifest(ChecksumFormat::Sfv, &entries)
+			),
+			entries.into_iter().map(Ok).collect::<Vec<_>>()
+		);
+	}
+
+	#[test]
+	fn malformed_lines() {
+		let manifest = "\
+5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03 *bin.dat
+
+not a checksum line
+e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855
+";
+
+		assert_eq!(
+			parse_manifest(ChecksumFormat::Sha256Sums, manifest),
+			vec![
+				Ok(ManifestEntry {
+					path: "bin.dat".to_string(),
+					checksum: "5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03"
+						.to_string(),
+				}),
+				Err(3),
+				Err(4),
+			]
+		);
+
+		assert_eq!(
+			parse_manifest(ChecksumFormat::Sfv, "; comment\nfile.bin 1234\n"),
+			vec![Err(2)]
+		);
+	}
+}
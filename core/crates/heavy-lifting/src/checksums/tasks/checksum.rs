@@ -0,0 +1,260 @@
+use crate::{checksums::ChecksumFormat, utils::io_throttle::IoThrottle, Error};
+
+use sd_prisma::prisma::file_path;
+use sd_task_system::{
+	check_interruption, ExecStatus, Interrupter, IntoAnyTaskOutput, SerializableTask, Task, TaskId,
+};
+
+use std::{
+	mem,
+	path::{Path, PathBuf},
+	time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::{
+	fs::File,
+	io::{self, AsyncReadExt},
+	time::Instant,
+};
+use tracing::warn;
+
+// Buffer size used to stream files through the hasher
+const BUFFER_SIZE: usize = 1024 * 64;
+
+/// Computes the checksums of a batch of files, comparing them with the expected ones when
+/// verifying a manifest
+#[derive(Debug)]
+pub struct Checksum {
+	id: TaskId,
+	format: ChecksumFormat,
+	files: Vec<ChecksumFile>,
+	io_throttle: IoThrottle,
+	output: Output,
+}
+
+/// A file to compute the checksum of
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChecksumFile {
+	/// Only files indexed in the location get their failures recorded
+	pub file_path_id: Option<file_path::id::Type>,
+	pub path: PathBuf,
+	/// Checksum from a manifest, `None` when exporting
+	pub expected: Option<String>,
+}
+
+#[derive(thiserror::Error, Debug, Clone, Serialize, Deserialize, Type)]
+pub enum NonCriticalError {
+	#[error("file to checksum is missing: <file='{}'>", .0.display())]
+	Missing(PathBuf),
+	#[error("content doesn't match the manifest checksum: <file='{}', expected='{1}', found='{2}'>", .0.display())]
+	ChecksumMismatch(PathBuf, String, String),
+	#[error("failed to read file for checksum: <file='{}'>: {1}", .0.display())]
+	FailedToReadFile(PathBuf, String),
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Output {
+	/// Checksums of files without an expected one
+	pub checksums: Vec<(PathBuf, String)>,
+	/// File paths read without failures, matching their expected checksum if they had one
+	pub succeeded_file_path_ids: Vec<file_path::id::Type>,
+	pub matched: u64,
+	pub mismatched: u64,
+	pub missing: u64,
+	pub unreadable: u64,
+	pub read_bytes: u64,
+	pub checksum_time: Duration,
+	pub failures: Vec<(Option<file_path::id::Type>, NonCriticalError)>,
+}
+
+impl Checksum {
+	#[must_use]
+	pub fn new(format: ChecksumFormat, files: Vec<ChecksumFile>) -> Self {
+		Self {
+			id: TaskId::new_v4(),
+			format,
+			files,
+			io_throttle: IoThrottle::default(),
+			output: Output::default(),
+		}
+	}
+
+	#[must_use]
+	pub fn with_io_throttle(mut self, io_throttle: IoThrottle) -> Self {
+		self.io_throttle = io_throttle;
+		self
+	}
+}
+
+#[async_trait::async_trait]
+impl Task<Error> for Checksum {
+	fn id(&self) -> TaskId {
+		self.id
+	}
+
+	async fn run(&mut self, interrupter: &Interrupter) -> Result<ExecStatus, Error> {
+		let Self {
+			format,
+			files,
+			io_throttle,
+			output:
+				Output {
+					checksums,
+					succeeded_file_path_ids,
+					matched,
+					mismatched,
+					missing,
+					unreadable,
+					read_bytes,
+					checksum_time,
+					failures,
+				},
+			..
+		} = self;
+
+		let start = Instant::now();
+
+		// Files are popped, so a resumed task only reads the remaining ones
+		while let Some(ChecksumFile {
+			file_path_id,
+			path,
+			expected,
+		}) = files.pop()
+		{
+			match (
+				compute_checksum(&path, *format, io_throttle).await,
+				expected,
+			) {
+				(Ok((checksum, size)), None) => {
+					*read_bytes += size;
+					succeeded_file_path_ids.extend(file_path_id);
+					checksums.push((path, checksum));
+				}
+
+				(Ok((checksum, size)), Some(expected)) => {
+					*read_bytes += size;
+
+					if checksum.eq_ignore_ascii_case(&expected) {
+						*matched += 1;
+						succeeded_file_path_ids.extend(file_path_id);
+					} else {
+						warn!(
+							"Checksum mismatch <path='{}', expected='{expected}', found='{checksum}'>",
+							path.display()
+						);
+						*mismatched += 1;
+						failures.push((
+							file_path_id,
+							NonCriticalError::ChecksumMismatch(path, expected, checksum),
+						));
+					}
+				}
+
+				(Err(e), _) if e.kind() == io::ErrorKind::NotFound => {
+					*missing += 1;
+					failures.push((file_path_id, NonCriticalError::Missing(path)));
+				}
+
+				(Err(e), _) => {
+					warn!("Failed to read file <path='{}'>: {e:#?}", path.display());
+					*unreadable += 1;
+					failures.push((
+						file_path_id,
+						NonCriticalError::FailedToReadFile(path, e.to_string()),
+					));
+				}
+			}
+
+			check_interruption!(interrupter, start, checksum_time);
+		}
+
+		*checksum_time += start.elapsed();
+
+		Ok(ExecStatus::Done(mem::take(&mut self.output).into_output()))
+	}
+}
+
+/// Streams the file through the format's hasher, returning its checksum and how many bytes were
+/// read. Reads are accounted in `io_throttle`.
+async fn compute_checksum(
+	path: &Path,
+	format: ChecksumFormat,
+	io_throttle: &IoThrottle,
+) -> Result<(String, u64), io::Error> {
+	let mut file = File::open(path).await?;
+	let mut hasher = format.hasher();
+	let mut buf = vec![0; BUFFER_SIZE].into_boxed_slice();
+	let mut size = 0;
+
+	loop {
+		let read = file.read(&mut buf).await?;
+		if read == 0 {
+			break;
+		}
+
+		io_throttle.consume(read as u64).await;
+		hasher.update(&buf[..read]);
+		size += read as u64;
+	}
+
+	Ok((hasher.finalize(), size))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SaveState {
+	id: TaskId,
+	format: ChecksumFormat,
+	files: Vec<ChecksumFile>,
+	#[serde(default)]
+	io_throttle: IoThrottle,
+	output: Output,
+}
+
+impl SerializableTask<Error> for Checksum {
+	type SerializeError = rmp_serde::encode::Error;
+
+	type DeserializeError = rmp_serde::decode::Error;
+
+	type DeserializeCtx = ();
+
+	async fn serialize(self) -> Result<Vec<u8>, Self::SerializeError> {
+		let Self {
+			id,
+			format,
+			files,
+			io_throttle,
+			output,
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState {
+			id,
+			format,
+			files,
+			io_throttle,
+			output,
+		})
+	}
+
+	async fn deserialize(
+		data: &[u8],
+		(): Self::DeserializeCtx,
+	) -> Result<Self, Self::DeserializeError> {
+		rmp_serde::from_slice(data).map(
+			|SaveState {
+			     id,
+			     format,
+			     files,
+			     io_throttle,
+			     output,
+			 }| Self {
+				id,
+				format,
+				files,
+				io_throttle,
+				output,
+			},
+		)
+	}
+}
@@ -0,0 +1,3 @@
+pub mod checksum;
+
+pub use checksum::Checksum;
@@ -0,0 +1,621 @@
+use crate::{
+	cold_archiver,
+	file_copier::copier::CopyProgress,
+	file_identifier::{not_in_archive, CasIdAlgorithm},
+	file_mover::{
+		mover::{self, MoveEntry, Mover, Stage},
+		ConflictPolicy, LocationRoot,
+	},
+	job_system::{
+		job::{Job, JobReturn, JobTaskDispatcher, ReturnStatus},
+		report::ReportOutputMetadata,
+		utils::{cancel_pending_tasks, location_concurrency_key},
+		SerializableJob, SerializedTasks,
+	},
+	Error, JobName, NonCriticalError, OuterContext, ProgressUpdate,
+};
+
+use sd_core_file_path_helper::IsolatedFilePathData;
+use sd_core_prisma_helpers::file_path_for_cold_archiver;
+
+use sd_prisma::prisma::{archive_policy, archive_stub, file_path, location, object, PrismaClient};
+use sd_task_system::{
+	IntoTask, SerializableTask, Task, TaskDispatcher, TaskHandle, TaskId, TaskOutput, TaskStatus,
+	TaskSystemError,
+};
+use sd_utils::db::{maybe_missing, size_in_bytes_from_db};
+
+use std::{
+	collections::HashMap,
+	hash::{Hash, Hasher},
+	mem,
+	path::PathBuf,
+	sync::Arc,
+	time::Duration,
+};
+
+use async_channel as chan;
+use chrono::{DateTime, FixedOffset, Utc};
+use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
+use futures_concurrency::future::{Race, TryJoin};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::fs;
+use tracing::{debug, warn};
+
+use super::ArchivePolicy;
+
+// How many cold files each mover task moves, one after the other
+const BATCH_SIZE: usize = 50;
+
+/// Moves the files of a location matched by the rules of its archive policy into the same
+/// relative paths of its cold location. File paths are moved along, keeping their objects, so
+/// archived files are still found by searches, as stored on the cold location. Stubs are left in
+/// the original location so it still lists them.
+#[derive(Debug)]
+pub struct ColdArchiver {
+	source: LocationRoot,
+	target: LocationRoot,
+	policy: ArchivePolicy,
+	cas_id_algorithm: CasIdAlgorithm,
+
+	/// Where the files being archived were, to leave stubs once they're moved
+	stubs: Vec<Stub>,
+
+	metadata: Metadata,
+	progress_tx: chan::Sender<(TaskId, CopyProgress)>,
+	progress_rx: chan::Receiver<(TaskId, CopyProgress)>,
+
+	errors: Vec<NonCriticalError>,
+
+	pending_tasks_on_resume: Vec<TaskHandle<Error>>,
+	tasks_for_shutdown: Vec<Box<dyn Task<Error>>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Stub {
+	file_path_id: file_path::id::Type,
+	materialized_path: String,
+	name: String,
+	extension: String,
+}
+
+impl Hash for ColdArchiver {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.source.id.hash(state);
+		self.target.id.hash(state);
+	}
+}
+
+impl Job for ColdArchiver {
+	const NAME: JobName = JobName::ColdArchiver;
+
+	async fn resume_tasks(
+		&mut self,
+		dispatcher: &JobTaskDispatcher,
+		ctx: &impl OuterContext,
+		SerializedTasks(serialized_tasks): SerializedTasks,
+	) -> Result<(), Error> {
+		let dispatcher =
+			dispatcher.with_concurrency_key(location_concurrency_key(ctx.id(), self.target.id));
+		let progress_tx = &self.progress_tx;
+
+		self.pending_tasks_on_resume = dispatcher
+			.dispatch_many_boxed(
+				rmp_serde::from_slice::<Vec<Vec<u8>>>(&serialized_tasks)
+					.map_err(cold_archiver::Error::from)?
+					.into_iter()
+					.map(|task_bytes| async move {
+						Mover::deserialize(
+							&task_bytes,
+							(
+								progress_tx.clone(),
+								Arc::clone(ctx.db()),
+								Arc::clone(ctx.sync()),
+							),
+						)
+						.await
+						.map(IntoTask::into_task)
+					})
+					.collect::<Vec<_>>()
+					.try_join()
+					.await
+					.map_err(cold_archiver::Error::from)?,
+			)
+			.await;
+
+		Ok(())
+	}
+
+	async fn run<Ctx: OuterContext>(
+		mut self,
+		dispatcher: JobTaskDispatcher,
+		ctx: Ctx,
+	) -> Result<ReturnStatus, Error> {
+		enum RaceOutput {
+			Task(Option<Result<TaskStatus<Error>, TaskSystemError>>),
+			Progress(Option<(TaskId, CopyProgress)>),
+		}
+
+		let dispatcher =
+			dispatcher.with_concurrency_key(location_concurrency_key(ctx.id(), self.target.id));
+
+		let mut pending_running_tasks = FuturesUnordered::new();
+
+		self.init_or_resume(&mut pending_running_tasks, &ctx, &dispatcher)
+			.await?;
+
+		while !pending_running_tasks.is_empty() {
+			let output = (
+				pending_running_tasks.next().map(RaceOutput::Task),
+				self.progress_rx
+					.recv()
+					.map(|res| RaceOutput::Progress(res.ok())),
+			)
+				.race()
+				.await;
+
+			let task = match output {
+				RaceOutput::Progress(Some((_, progress))) => {
+					if matches!(progress, CopyProgress::Finished) {
+						self.metadata.completed_paths += 1;
+						self.report_progress(&ctx);
+					}
+
+					continue;
+				}
+				// The job holds a sender, so the channel is never closed
+				RaceOutput::Progress(None) | RaceOutput::Task(None) => continue,
+				RaceOutput::Task(Some(task)) => task,
+			};
+
+			match task {
+				Ok(TaskStatus::Done((_, TaskOutput::Out(out)))) => {
+					self.process_mover_output(
+						*out.downcast::<mover::Output>()
+							.expect("the cold archiver job only dispatches mover tasks"),
+					);
+				}
+
+				Ok(TaskStatus::Done((task_id, TaskOutput::Empty))) => {
+					warn!("Task <id='{task_id}'> returned an empty output");
+				}
+
+				Ok(TaskStatus::Shutdown(task)) => {
+					self.tasks_for_shutdown.push(task);
+				}
+
+				Ok(TaskStatus::Error(e)) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e);
+				}
+
+				Ok(TaskStatus::Canceled | TaskStatus::ForcedAbortion) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Ok(ReturnStatus::Canceled);
+				}
+
+				Err(e) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e.into());
+				}
+			}
+		}
+
+		if !self.tasks_for_shutdown.is_empty() {
+			return Ok(ReturnStatus::Shutdown(
+				SerializableJob::<Ctx>::serialize(self).await,
+			));
+		}
+
+		if let Err(e) = self.leave_stubs(ctx.db()).await {
+			self.errors
+				.push(cold_archiver::NonCriticalError::FailedToRecordStubs(e.to_string()).into());
+		}
+
+		ctx.db()
+			.archive_policy()
+			.update(
+				archive_policy::location_id::equals(self.source.id),
+				vec![archive_policy::date_last_run::set(Some(Utc::now().into()))],
+			)
+			.exec()
+			.await
+			.map_err(cold_archiver::Error::from)?;
+
+		ctx.invalidate_query("search.paths");
+		ctx.invalidate_query("locations.coldArchive.policy");
+		ctx.invalidate_query("locations.coldArchive.stubs");
+
+		let Self {
+			metadata, errors, ..
+		} = self;
+
+		Ok(ReturnStatus::Completed(
+			JobReturn::builder()
+				.with_metadata(metadata)
+				.with_non_critical_errors(errors)
+				.build(),
+		))
+	}
+}
+
+impl ColdArchiver {
+	/// Archives the cold files of `location` into `cold_location`, see [`cold_archiver::locations`].
+	/// `cas_id_algorithm` must be the one the library's `cas_id`s were generated with, as it's
+	/// used to verify copies when the cold location is on another device.
+	pub fn new(
+		policy: ArchivePolicy,
+		location: &location::Data,
+		cold_location: &location::Data,
+		cas_id_algorithm: CasIdAlgorithm,
+	) -> Result<Self, cold_archiver::Error> {
+		let root = |location: &location::Data| {
+			maybe_missing(&location.path, "location.path").map(|path| LocationRoot {
+				id: location.id,
+				pub_id: location.pub_id.clone(),
+				path: Arc::new(PathBuf::from(path)),
+			})
+		};
+
+		let (progress_tx, progress_rx) = chan::unbounded();
+
+		Ok(Self {
+			source: root(location)?,
+			target: root(cold_location)?,
+			policy,
+			cas_id_algorithm,
+			stubs: Vec::new(),
+			metadata: Metadata::default(),
+			progress_tx,
+			progress_rx,
+			errors: Vec::new(),
+			pending_tasks_on_resume: Vec::new(),
+			tasks_for_shutdown: Vec::new(),
+		})
+	}
+
+	async fn init_or_resume(
+		&mut self,
+		pending_running_tasks: &mut FuturesUnordered<TaskHandle<Error>>,
+		ctx: &impl OuterContext,
+		dispatcher: &JobTaskDispatcher,
+	) -> Result<(), cold_archiver::Error> {
+		// Resumed tasks were already dispatched by `resume_tasks`
+		if !self.pending_tasks_on_resume.is_empty() {
+			pending_running_tasks.extend(mem::take(&mut self.pending_tasks_on_resume));
+
+			return Ok(());
+		}
+
+		let entries = self.gather_entries(ctx.db(), Utc::now()).await?;
+
+		debug!(
+			"Archiving {} cold files, {} bytes, from location {} into location {}",
+			self.metadata.total_paths, self.metadata.total_bytes, self.source.id, self.target.id
+		);
+
+		pending_running_tasks.extend(
+			dispatcher
+				.dispatch_many(
+					entries
+						.chunks(BATCH_SIZE)
+						.map(|chunk| {
+							Mover::new(
+								self.source.clone(),
+								self.target.clone(),
+								// Reversed as tasks pop their entries
+								chunk.iter().rev().cloned().collect(),
+								// Files already in the cold location are never replaced
+								ConflictPolicy::Rename,
+								self.cas_id_algorithm,
+								None,
+								self.progress_tx.clone(),
+								Arc::clone(ctx.db()),
+								Arc::clone(ctx.sync()),
+							)
+						})
+						.collect::<Vec<_>>(),
+				)
+				.await,
+		);
+
+		self.report_progress(ctx);
+
+		Ok(())
+	}
+
+	async fn gather_entries(
+		&mut self,
+		db: &PrismaClient,
+		now: DateTime<Utc>,
+	) -> Result<Vec<MoveEntry>, cold_archiver::Error> {
+		let mut filters = vec![
+			file_path::location_id::equals(Some(self.source.id)),
+			file_path::is_dir::equals(Some(false)),
+			file_path::date_trashed::equals(None),
+			not_in_archive(),
+		];
+
+		if let Some(kinds) = &self.policy.rules.kinds {
+			filters.push(file_path::object::is(vec![object::kind::in_vec(
+				kinds.iter().map(|kind| Some(*kind as i32)).collect(),
+			)]));
+		}
+
+		let file_paths = db
+			.file_path()
+			.find_many(filters)
+			.select(file_path_for_cold_archiver::select())
+			.exec()
+			.await?;
+
+		let mut entries = Vec::new();
+
+		for file_path in file_paths {
+			if !self.policy.rules.is_cold(
+				file_path
+					.object
+					.as_ref()
+					.and_then(|object| object.date_accessed)
+					.map(Into::into),
+				file_path.date_modified.map(Into::into),
+				now,
+			) {
+				continue;
+			}
+
+			let iso_file_path = IsolatedFilePathData::try_from((self.source.id, &file_path))?;
+			let source = self.source.path.join(&iso_file_path);
+			let target = self.target.path.join(&iso_file_path);
+
+			if let Some(parent) = target.parent() {
+				if let Err(e) = fs::create_dir_all(parent).await {
+					self.metadata.failed_paths += 1;
+					self.errors.push(
+						cold_archiver::NonCriticalError::FailedToCreateDirectory(
+							parent.to_path_buf(),
+							e.to_string(),
+						)
+						.into(),
+					);
+					continue;
+				}
+			}
+
+			self.metadata.total_paths += 1;
+			self.metadata.total_bytes += file_path
+				.size_in_bytes_bytes
+				.as_deref()
+				.map(size_in_bytes_from_db)
+				.unwrap_or_default();
+
+			self.stubs.push(Stub {
+				file_path_id: file_path.id,
+				materialized_path: maybe_missing(
+					file_path.materialized_path,
+					"file_path.materialized_path",
+				)?,
+				name: maybe_missing(file_path.name, "file_path.name")?,
+				extension: maybe_missing(file_path.extension, "file_path.extension")?,
+			});
+
+			entries.push(MoveEntry {
+				file_path_id: file_path.id,
+				pub_id: file_path.pub_id,
+				is_dir: false,
+				source,
+				target,
+				stage: Stage::Pending,
+			});
+		}
+
+		Ok(entries)
+	}
+
+	/// Movers point the file paths of moved files to the cold location, and back to the original
+	/// one if moving failed, so the file paths left in the cold location are the archived ones
+	async fn leave_stubs(&self, db: &PrismaClient) -> Result<(), cold_archiver::Error> {
+		let archived = db
+			.file_path()
+			.find_many(vec![
+				file_path::location_id::equals(Some(self.target.id)),
+				file_path::id::in_vec(self.stubs.iter().map(|stub| stub.file_path_id).collect()),
+			])
+			.select(file_path::select!({ id }))
+			.exec()
+			.await?
+			.into_iter()
+			.map(|file_path| file_path.id)
+			.collect::<Vec<_>>();
+
+		// Files archived before, restored since then, replace their previous stubs
+		db.archive_stub()
+			.delete_many(vec![archive_stub::file_path_id::in_vec(archived.clone())])
+			.exec()
+			.await?;
+
+		let date_archived: DateTime<FixedOffset> = Utc::now().into();
+
+		db.archive_stub()
+			.create_many(
+				self.stubs
+					.iter()
+					.filter(|stub| archived.contains(&stub.file_path_id))
+					.map(|stub| {
+						archive_stub::create_unchecked(
+							stub.file_path_id,
+							self.source.id,
+							stub.materialized_path.clone(),
+							stub.name.clone(),
+							stub.extension.clone(),
+							date_archived,
+							vec![],
+						)
+					})
+					.collect(),
+			)
+			.exec()
+			.await?;
+
+		Ok(())
+	}
+
+	fn report_progress(&self, ctx: &impl OuterContext) {
+		ctx.progress(vec![
+			ProgressUpdate::TaskCount(self.metadata.total_paths),
+			ProgressUpdate::CompletedTaskCount(self.metadata.completed_paths),
+			ProgressUpdate::Message(format!(
+				"Archived {} of {} cold files",
+				self.metadata.completed_paths, self.metadata.total_paths
+			)),
+		]);
+	}
+
+	fn process_mover_output(
+		&mut self,
+		mover::Output {
+			moved,
+			copied_across_devices,
+			skipped,
+			failed,
+			move_time,
+			copy_time,
+			errors,
+			..
+		}: mover::Output,
+	) {
+		self.metadata.archived_paths += moved;
+		self.metadata.copied_across_devices += copied_across_devices;
+		self.metadata.skipped_paths += skipped;
+		self.metadata.failed_paths += failed;
+		self.metadata.move_time += move_time;
+		self.metadata.copy_time += copy_time;
+
+		self.errors.extend(errors);
+	}
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Metadata {
+	total_paths: u64,
+	total_bytes: u64,
+	completed_paths: u64,
+	archived_paths: u64,
+	skipped_paths: u64,
+	failed_paths: u64,
+	copied_across_devices: u64,
+	move_time: Duration,
+	copy_time: Duration,
+}
+
+impl From<Metadata> for ReportOutputMetadata {
+	fn from(value: Metadata) -> Self {
+		Self::Metrics(HashMap::from([
+			("total_paths".into(), json!(value.total_paths)),
+			("total_bytes".into(), json!(value.total_bytes)),
+			("archived_paths".into(), json!(value.archived_paths)),
+			("skipped_paths".into(), json!(value.skipped_paths)),
+			("failed_paths".into(), json!(value.failed_paths)),
+			(
+				"copied_across_devices".into(),
+				json!(value.copied_across_devices),
+			),
+			("move_time".into(), json!(value.move_time)),
+			("copy_time".into(), json!(value.copy_time)),
+		]))
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+struct SaveState {
+	source: LocationRoot,
+	target: LocationRoot,
+	policy: ArchivePolicy,
+	cas_id_algorithm: CasIdAlgorithm,
+	stubs: Vec<Stub>,
+
+	metadata: Metadata,
+
+	errors: Vec<NonCriticalError>,
+
+	tasks_for_shutdown_bytes: Option<SerializedTasks>,
+}
+
+impl<Ctx: OuterContext> SerializableJob<Ctx> for ColdArchiver {
+	async fn serialize(self) -> Result<Option<Vec<u8>>, rmp_serde::encode::Error> {
+		let Self {
+			source,
+			target,
+			policy,
+			cas_id_algorithm,
+			stubs,
+			metadata,
+			errors,
+			tasks_for_shutdown,
+			..
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState {
+			source,
+			target,
+			policy,
+			cas_id_algorithm,
+			stubs,
+			metadata,
+			errors,
+			tasks_for_shutdown_bytes: Some(SerializedTasks(rmp_serde::to_vec_named(
+				&tasks_for_shutdown
+					.into_iter()
+					.map(|task| async move {
+						task.downcast::<Mover>()
+							.expect("the cold archiver job only dispatches mover tasks")
+							.serialize()
+							.await
+					})
+					.collect::<Vec<_>>()
+					.try_join()
+					.await?,
+			)?)),
+		})
+		.map(Some)
+	}
+
+	async fn deserialize(
+		serialized_job: &[u8],
+		_: &Ctx,
+	) -> Result<Option<(Self, Option<SerializedTasks>)>, rmp_serde::decode::Error> {
+		let SaveState {
+			source,
+			target,
+			policy,
+			cas_id_algorithm,
+			stubs,
+			metadata,
+			errors,
+			tasks_for_shutdown_bytes,
+		} = rmp_serde::from_slice::<SaveState>(serialized_job)?;
+
+		let (progress_tx, progress_rx) = chan::unbounded();
+
+		Ok(Some((
+			Self {
+				source,
+				target,
+				policy,
+				cas_id_algorithm,
+				stubs,
+				metadata,
+				progress_tx,
+				progress_rx,
+				errors,
+				pending_tasks_on_resume: Vec::new(),
+				tasks_for_shutdown: Vec::new(),
+			},
+			tasks_for_shutdown_bytes,
+		)))
+	}
+}
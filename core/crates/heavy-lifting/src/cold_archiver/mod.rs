@@ -0,0 +1,316 @@
+use sd_core_file_path_helper::{FilePathError, IsolatedFilePathData};
+
+use sd_file_ext::kind::ObjectKind;
+use sd_prisma::prisma::{
+	archive_policy, archive_stub, file_path, location, object, PrismaClient, SortOrder,
+};
+use sd_utils::{
+	db::{maybe_missing, MissingFieldError},
+	error::FileIOError,
+};
+
+use std::{borrow::Cow, path::PathBuf};
+
+use chrono::{DateTime, Months, Utc};
+use prisma_client_rust::QueryError;
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+pub mod job;
+
+pub use job::ColdArchiver;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("location has no archive policy: <id='{0}'>")]
+	PolicyNotFound(location::id::Type),
+	#[error("can't archive a location into itself: <id='{0}'>")]
+	SameLocation(location::id::Type),
+	#[error("locations are nested, archiving would move files within the same location: <location='{}', cold_location='{}'>", .0.display(), .1.display())]
+	NestedLocations(PathBuf, PathBuf),
+	#[error("failed to decode the kinds of the archive policy of location <id='{0}'>: {1}")]
+	DecodeKinds(location::id::Type, rmp_serde::decode::Error),
+	#[error("missing field on database: {0}")]
+	MissingField(#[from] MissingFieldError),
+	#[error("database error: {0}")]
+	Database(#[from] QueryError),
+	#[error("failed to deserialized stored tasks for job resume: {0}")]
+	DeserializeTasks(#[from] rmp_serde::decode::Error),
+
+	#[error(transparent)]
+	FilePathError(#[from] FilePathError),
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+}
+
+impl From<Error> for rspc::Error {
+	fn from(err: Error) -> Self {
+		match err {
+			Error::PolicyNotFound(_) => Self::with_cause(ErrorCode::NotFound, err.to_string(), err),
+
+			Error::SameLocation(_) | Error::NestedLocations(..) => {
+				Self::with_cause(ErrorCode::BadRequest, err.to_string(), err)
+			}
+
+			_ => Self::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize, Type)]
+pub enum NonCriticalError {
+	#[error("failed to create directory in cold location: <path='{}'>: {1}", .0.display())]
+	FailedToCreateDirectory(PathBuf, String),
+	#[error("failed to record where archived files were: {0}")]
+	FailedToRecordStubs(String),
+}
+
+/// Which files of a location went cold, every rule set having to match
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct ArchiveRules {
+	/// Files whose object wasn't opened in this many months, nor the file modified, every file if
+	/// `None`
+	#[serde(default)]
+	pub not_accessed_months: Option<u32>,
+	/// Files of these kinds only, every kind if `None`
+	#[serde(default)]
+	pub kinds: Option<Vec<ObjectKind>>,
+}
+
+impl ArchiveRules {
+	/// Files that were neither opened nor modified as far as the library knows are left alone, as
+	/// there's no telling when they were last used
+	pub(crate) fn is_cold(
+		&self,
+		date_accessed: Option<DateTime<Utc>>,
+		date_modified: Option<DateTime<Utc>>,
+		now: DateTime<Utc>,
+	) -> bool {
+		let Some(months) = self.not_accessed_months else {
+			return true;
+		};
+
+		date_accessed
+			.max(date_modified)
+			.zip(now.checked_sub_months(Months::new(months)))
+			.map_or(false, |(last_used, cutoff)| last_used < cutoff)
+	}
+}
+
+/// Moves the cold files of a location into its cold location each time the cold archiver runs
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct ArchivePolicy {
+	pub location_id: location::id::Type,
+	pub cold_location_id: location::id::Type,
+	pub rules: ArchiveRules,
+	pub date_last_run: Option<DateTime<Utc>>,
+}
+
+impl TryFrom<archive_policy::Data> for ArchivePolicy {
+	type Error = Error;
+
+	fn try_from(data: archive_policy::Data) -> Result<Self, Self::Error> {
+		#[allow(clippy::cast_sign_loss)]
+		// SAFETY: only ever set from an u32
+		let not_accessed_months = data.not_accessed_months.map(|months| months as u32);
+
+		let kinds = data
+			.kinds
+			.as_deref()
+			.map(rmp_serde::from_slice)
+			.transpose()
+			.map_err(|e| Error::DecodeKinds(data.location_id, e))?;
+
+		Ok(Self {
+			location_id: data.location_id,
+			cold_location_id: data.cold_location_id,
+			rules: ArchiveRules {
+				not_accessed_months,
+				kinds,
+			},
+			date_last_run: data.date_last_run.map(Into::into),
+		})
+	}
+}
+
+/// Where an archived file was, found in its original location while it's stored on another one
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct ArchiveStub {
+	pub id: archive_stub::id::Type,
+	pub file_path_id: file_path::id::Type,
+	pub object_id: Option<object::id::Type>,
+	/// Relative to the root of the original location
+	pub path: PathBuf,
+	/// The location the file is stored on now
+	pub stored_on_location_id: Option<location::id::Type>,
+	pub stored_on: Option<String>,
+	pub date_archived: DateTime<Utc>,
+}
+
+/// Sets the rules of the location and where its cold files go, replacing its previous policy
+pub async fn set_policy(
+	db: &PrismaClient,
+	location: &location::Data,
+	cold_location: &location::Data,
+	rules: ArchiveRules,
+) -> Result<ArchivePolicy, Error> {
+	if location.id == cold_location.id {
+		return Err(Error::SameLocation(location.id));
+	}
+
+	let path = maybe_missing(&location.path, "location.path").map(PathBuf::from)?;
+	let cold_path = maybe_missing(&cold_location.path, "location.path").map(PathBuf::from)?;
+
+	if path.starts_with(&cold_path) || cold_path.starts_with(&path) {
+		return Err(Error::NestedLocations(path, cold_path));
+	}
+
+	let params = vec![
+		archive_policy::not_accessed_months::set(
+			rules
+				.not_accessed_months
+				.map(|months| i32::try_from(months).unwrap_or(i32::MAX)),
+		),
+		archive_policy::kinds::set(rules.kinds.as_ref().map(|kinds| {
+			rmp_serde::to_vec_named(kinds).expect("object kinds are always serializable")
+		})),
+	];
+
+	db.archive_policy()
+		.upsert(
+			archive_policy::location_id::equals(location.id),
+			archive_policy::create(
+				location::id::equals(location.id),
+				location::id::equals(cold_location.id),
+				[
+					params.clone(),
+					vec![archive_policy::date_created::set(Some(Utc::now().into()))],
+				]
+				.concat(),
+			),
+			[
+				params,
+				vec![archive_policy::cold_location::connect(
+					location::id::equals(cold_location.id),
+				)],
+			]
+			.concat(),
+		)
+		.exec()
+		.await?
+		.try_into()
+}
+
+pub async fn policy(
+	db: &PrismaClient,
+	location_id: location::id::Type,
+) -> Result<Option<ArchivePolicy>, Error> {
+	db.archive_policy()
+		.find_unique(archive_policy::location_id::equals(location_id))
+		.exec()
+		.await?
+		.map(TryInto::try_into)
+		.transpose()
+}
+
+/// Stops archiving the location, its archived files staying where they are
+pub async fn delete_policy(
+	db: &PrismaClient,
+	location_id: location::id::Type,
+) -> Result<(), Error> {
+	let deleted = db
+		.archive_policy()
+		.delete_many(vec![archive_policy::location_id::equals(location_id)])
+		.exec()
+		.await?;
+
+	if deleted == 0 {
+		return Err(Error::PolicyNotFound(location_id));
+	}
+
+	Ok(())
+}
+
+/// The policy of the location, with the location and its cold location
+pub async fn locations(
+	db: &PrismaClient,
+	location_id: location::id::Type,
+) -> Result<(ArchivePolicy, location::Data, location::Data), Error> {
+	let mut data = db
+		.archive_policy()
+		.find_unique(archive_policy::location_id::equals(location_id))
+		.with(archive_policy::location::fetch())
+		.with(archive_policy::cold_location::fetch())
+		.exec()
+		.await?
+		.ok_or(Error::PolicyNotFound(location_id))?;
+
+	let location = *maybe_missing(data.location.take(), "archive_policy.location")?;
+	let cold_location = *maybe_missing(data.cold_location.take(), "archive_policy.cold_location")?;
+
+	Ok((data.try_into()?, location, cold_location))
+}
+
+/// Files archived out of the location, newest first
+pub async fn stubs(
+	db: &PrismaClient,
+	location_id: location::id::Type,
+) -> Result<Vec<ArchiveStub>, Error> {
+	db.archive_stub()
+		.find_many(vec![archive_stub::location_id::equals(location_id)])
+		.with(archive_stub::file_path::fetch().with(file_path::location::fetch()))
+		.order_by(archive_stub::date_archived::order(SortOrder::Desc))
+		.exec()
+		.await?
+		.into_iter()
+		.map(|stub| {
+			let file_path = maybe_missing(stub.file_path, "archive_stub.file_path")?;
+			let stored_on = file_path.location.flatten();
+
+			Ok::<_, Error>(ArchiveStub {
+				id: stub.id,
+				file_path_id: stub.file_path_id,
+				object_id: file_path.object_id,
+				path: PathBuf::from(
+					IsolatedFilePathData::from_db_data(
+						location_id,
+						false,
+						Cow::Borrowed(&stub.materialized_path),
+						Cow::Borrowed(&stub.name),
+						Cow::Borrowed(&stub.extension),
+					)
+					.as_ref(),
+				),
+				stored_on_location_id: file_path.location_id,
+				stored_on: stored_on.and_then(|location| location.name),
+				date_archived: stub.date_archived.into(),
+			})
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn cold_files() {
+		let now = Utc::now();
+		let months_ago = |months| now.checked_sub_months(Months::new(months));
+
+		let rules = ArchiveRules {
+			not_accessed_months: Some(6),
+			kinds: None,
+		};
+
+		assert!(rules.is_cold(months_ago(7), months_ago(12), now));
+		assert!(rules.is_cold(None, months_ago(7), now));
+		// Opening a file keeps it hot, however long ago it was modified
+		assert!(!rules.is_cold(months_ago(1), months_ago(24), now));
+		assert!(!rules.is_cold(None, months_ago(5), now));
+		assert!(!rules.is_cold(None, None, now));
+
+		assert!(ArchiveRules::default().is_cold(None, None, now));
+	}
+}
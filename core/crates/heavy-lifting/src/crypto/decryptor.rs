@@ -0,0 +1,441 @@
+use crate::{
+	crypto,
+	job_system::{
+		job::{Job, JobReturn, JobTaskDispatcher, ReturnStatus},
+		report::ReportOutputMetadata,
+		utils::{cancel_pending_tasks, location_concurrency_key},
+		SerializableJob, SerializedTasks,
+	},
+	Error, JobName, NonCriticalError, OuterContext, ProgressUpdate,
+};
+
+use sd_prisma::prisma::{file_path, location};
+use sd_task_system::{
+	IntoTask, SerializableTask, Task, TaskDispatcher, TaskHandle, TaskId, TaskOutput, TaskStatus,
+	TaskSystemError,
+};
+use sd_utils::db::maybe_missing;
+
+use std::{
+	collections::HashMap,
+	hash::{Hash, Hasher},
+	mem,
+	path::PathBuf,
+	sync::Arc,
+	time::Duration,
+};
+
+use async_channel as chan;
+use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
+use futures_concurrency::future::{Race, TryJoin};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::time::Instant;
+use tracing::{debug, warn};
+
+use super::{
+	gather_files,
+	tasks::{decrypt, CryptoProgress, Decrypt},
+};
+
+// Byte progress arrives every chunk of every file, the UI doesn't need it that often
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Decrypts a selection of encrypted file paths of a location, directories with all the `.sdenc`
+/// files in them, with whichever mounted key of the library opens each. Files are restored next to
+/// their encrypted copy, under their original name and modification date, and only once their
+/// integrity was verified, encrypted copies being left untouched. Decrypted files are left for the
+/// watcher or the next scan to index, as with any other file added to the location.
+#[derive(Debug)]
+pub struct FileDecryptor {
+	location: Arc<location::Data>,
+	location_path: Arc<PathBuf>,
+	file_path_ids: Vec<file_path::id::Type>,
+
+	metadata: Metadata,
+	current_file: Option<PathBuf>,
+	progress_tx: chan::Sender<(TaskId, CryptoProgress)>,
+	progress_rx: chan::Receiver<(TaskId, CryptoProgress)>,
+
+	errors: Vec<NonCriticalError>,
+
+	pending_tasks_on_resume: Vec<TaskHandle<Error>>,
+	tasks_for_shutdown: Vec<Box<dyn Task<Error>>>,
+}
+
+impl Hash for FileDecryptor {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.location.id.hash(state);
+		self.file_path_ids.hash(state);
+	}
+}
+
+impl Job for FileDecryptor {
+	const NAME: JobName = JobName::FileDecryptor;
+
+	async fn resume_tasks(
+		&mut self,
+		dispatcher: &JobTaskDispatcher,
+		ctx: &impl OuterContext,
+		SerializedTasks(serialized_tasks): SerializedTasks,
+	) -> Result<(), Error> {
+		let dispatcher =
+			dispatcher.with_concurrency_key(location_concurrency_key(ctx.id(), self.location.id));
+		let progress_tx = &self.progress_tx;
+		let keys = ctx.key_manager();
+
+		self.pending_tasks_on_resume = dispatcher
+			.dispatch_many_boxed(
+				rmp_serde::from_slice::<Vec<Vec<u8>>>(&serialized_tasks)
+					.map_err(crypto::Error::from)?
+					.into_iter()
+					.map(|task_bytes| async move {
+						Decrypt::deserialize(&task_bytes, (progress_tx.clone(), Arc::clone(keys)))
+							.await
+							.map(IntoTask::into_task)
+					})
+					.collect::<Vec<_>>()
+					.try_join()
+					.await
+					.map_err(crypto::Error::from)?,
+			)
+			.await;
+
+		Ok(())
+	}
+
+	async fn run<Ctx: OuterContext>(
+		mut self,
+		dispatcher: JobTaskDispatcher,
+		ctx: Ctx,
+	) -> Result<ReturnStatus, Error> {
+		enum RaceOutput {
+			Task(Option<Result<TaskStatus<Error>, TaskSystemError>>),
+			Progress(Option<(TaskId, CryptoProgress)>),
+		}
+
+		let dispatcher =
+			dispatcher.with_concurrency_key(location_concurrency_key(ctx.id(), self.location.id));
+
+		let mut pending_running_tasks = FuturesUnordered::new();
+
+		self.init_or_resume(&mut pending_running_tasks, &ctx, &dispatcher)
+			.await?;
+
+		let mut last_progress_update = Instant::now();
+
+		while !pending_running_tasks.is_empty() {
+			let output = (
+				pending_running_tasks.next().map(RaceOutput::Task),
+				self.progress_rx
+					.recv()
+					.map(|res| RaceOutput::Progress(res.ok())),
+			)
+				.race()
+				.await;
+
+			let task = match output {
+				RaceOutput::Progress(Some((_, progress))) => {
+					self.process_progress(progress);
+
+					if last_progress_update.elapsed() > PROGRESS_INTERVAL {
+						last_progress_update = Instant::now();
+						self.report_progress(&ctx);
+					}
+
+					continue;
+				}
+				// The job holds a sender, so the channel is never closed
+				RaceOutput::Progress(None) | RaceOutput::Task(None) => continue,
+				RaceOutput::Task(Some(task)) => task,
+			};
+
+			match task {
+				Ok(TaskStatus::Done((_, TaskOutput::Out(out)))) => {
+					self.process_decrypt_output(
+						*out.downcast::<decrypt::Output>()
+							.expect("the file decryptor job only dispatches decrypt tasks"),
+					);
+					self.report_progress(&ctx);
+				}
+
+				Ok(TaskStatus::Done((task_id, TaskOutput::Empty))) => {
+					warn!("Task <id='{task_id}'> returned an empty output");
+				}
+
+				Ok(TaskStatus::Shutdown(task)) => {
+					self.tasks_for_shutdown.push(task);
+				}
+
+				Ok(TaskStatus::Error(e)) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e);
+				}
+
+				Ok(TaskStatus::Canceled | TaskStatus::ForcedAbortion) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Ok(ReturnStatus::Canceled);
+				}
+
+				Err(e) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e.into());
+				}
+			}
+		}
+
+		if !self.tasks_for_shutdown.is_empty() {
+			return Ok(ReturnStatus::Shutdown(
+				SerializableJob::<Ctx>::serialize(self).await,
+			));
+		}
+
+		ctx.invalidate_query("search.paths");
+
+		let Self {
+			metadata, errors, ..
+		} = self;
+
+		Ok(ReturnStatus::Completed(
+			JobReturn::builder()
+				.with_metadata(metadata)
+				.with_non_critical_errors(errors)
+				.build(),
+		))
+	}
+}
+
+impl FileDecryptor {
+	/// Decrypts the `file_path_ids` of `location`, with the keys mounted while the job runs
+	pub fn new(
+		location: location::Data,
+		file_path_ids: Vec<file_path::id::Type>,
+	) -> Result<Self, crypto::Error> {
+		let location_path = maybe_missing(&location.path, "location.path").map(PathBuf::from)?;
+
+		let (progress_tx, progress_rx) = chan::unbounded();
+
+		Ok(Self {
+			location: Arc::new(location),
+			location_path: Arc::new(location_path),
+			file_path_ids,
+			metadata: Metadata::default(),
+			current_file: None,
+			progress_tx,
+			progress_rx,
+			errors: Vec::new(),
+			pending_tasks_on_resume: Vec::new(),
+			tasks_for_shutdown: Vec::new(),
+		})
+	}
+
+	async fn init_or_resume(
+		&mut self,
+		pending_running_tasks: &mut FuturesUnordered<TaskHandle<Error>>,
+		ctx: &impl OuterContext,
+		dispatcher: &JobTaskDispatcher,
+	) -> Result<(), crypto::Error> {
+		// Resumed tasks were already dispatched by `resume_tasks`
+		if !self.pending_tasks_on_resume.is_empty() {
+			pending_running_tasks.extend(mem::take(&mut self.pending_tasks_on_resume));
+
+			return Ok(());
+		}
+
+		// Checked before gathering files, so locked keys fail the job right away
+		if ctx.key_manager().mounted().is_empty() {
+			return Err(crypto::Error::NoMountedKeys);
+		}
+
+		let (files, total_bytes) = gather_files(
+			ctx.db(),
+			self.location.id,
+			&self.location_path,
+			&self.file_path_ids,
+			true,
+		)
+		.await?;
+
+		self.metadata.total_files = files.len() as u64;
+		self.metadata.total_bytes = total_bytes;
+
+		debug!(
+			"Decrypting {} files, {total_bytes} bytes, from location {}",
+			files.len(),
+			self.location.id
+		);
+
+		pending_running_tasks.push(
+			dispatcher
+				.dispatch(Decrypt::new(
+					files,
+					Arc::clone(ctx.key_manager()),
+					self.progress_tx.clone(),
+				))
+				.await,
+		);
+
+		self.report_progress(ctx);
+
+		Ok(())
+	}
+
+	fn process_progress(&mut self, progress: CryptoProgress) {
+		match progress {
+			CryptoProgress::Started(path) => {
+				self.metadata.completed_files += 1;
+				self.current_file = Some(path);
+			}
+			CryptoProgress::Processed(bytes) => self.metadata.read_bytes += bytes,
+		}
+	}
+
+	fn report_progress(&self, ctx: &impl OuterContext) {
+		let total = format!(
+			"{} of {} bytes",
+			// Files interrupted by a shutdown are decrypted again from their start
+			self.metadata.read_bytes.min(self.metadata.total_bytes),
+			self.metadata.total_bytes
+		);
+
+		let message = self.current_file.as_ref().map_or_else(
+			|| format!("Decrypted {total}"),
+			|path| format!("Decrypting \"{}\", {total}", path.display()),
+		);
+
+		ctx.progress(vec![
+			ProgressUpdate::TaskCount(self.metadata.total_files),
+			ProgressUpdate::CompletedTaskCount(
+				self.metadata.completed_files.min(self.metadata.total_files),
+			),
+			ProgressUpdate::Message(message),
+		]);
+	}
+
+	fn process_decrypt_output(
+		&mut self,
+		decrypt::Output {
+			decrypted_files,
+			decrypted_bytes,
+			failed_files,
+			decrypt_time,
+			errors,
+		}: decrypt::Output,
+	) {
+		self.metadata.decrypted_files += decrypted_files;
+		self.metadata.decrypted_bytes += decrypted_bytes;
+		self.metadata.failed_files += failed_files;
+		self.metadata.decrypt_time += decrypt_time;
+
+		self.errors.extend(errors);
+	}
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Metadata {
+	total_files: u64,
+	total_bytes: u64,
+	completed_files: u64,
+	read_bytes: u64,
+	decrypted_files: u64,
+	decrypted_bytes: u64,
+	failed_files: u64,
+	decrypt_time: Duration,
+}
+
+impl From<Metadata> for ReportOutputMetadata {
+	fn from(value: Metadata) -> Self {
+		Self::Metrics(HashMap::from([
+			("total_files".into(), json!(value.total_files)),
+			("total_bytes".into(), json!(value.total_bytes)),
+			("decrypted_files".into(), json!(value.decrypted_files)),
+			("decrypted_bytes".into(), json!(value.decrypted_bytes)),
+			("failed_files".into(), json!(value.failed_files)),
+			("decrypt_time".into(), json!(value.decrypt_time)),
+		]))
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+struct SaveState {
+	location: Arc<location::Data>,
+	location_path: Arc<PathBuf>,
+	file_path_ids: Vec<file_path::id::Type>,
+
+	metadata: Metadata,
+
+	errors: Vec<NonCriticalError>,
+
+	tasks_for_shutdown_bytes: Option<SerializedTasks>,
+}
+
+impl<Ctx: OuterContext> SerializableJob<Ctx> for FileDecryptor {
+	async fn serialize(self) -> Result<Option<Vec<u8>>, rmp_serde::encode::Error> {
+		let Self {
+			location,
+			location_path,
+			file_path_ids,
+			metadata,
+			errors,
+			tasks_for_shutdown,
+			..
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState {
+			location,
+			location_path,
+			file_path_ids,
+			metadata,
+			errors,
+			tasks_for_shutdown_bytes: Some(SerializedTasks(rmp_serde::to_vec_named(
+				&tasks_for_shutdown
+					.into_iter()
+					.map(|task| async move {
+						task.downcast::<Decrypt>()
+							.expect("the file decryptor job only dispatches decrypt tasks")
+							.serialize()
+							.await
+					})
+					.collect::<Vec<_>>()
+					.try_join()
+					.await?,
+			)?)),
+		})
+		.map(Some)
+	}
+
+	async fn deserialize(
+		serialized_job: &[u8],
+		_: &Ctx,
+	) -> Result<Option<(Self, Option<SerializedTasks>)>, rmp_serde::decode::Error> {
+		let SaveState {
+			location,
+			location_path,
+			file_path_ids,
+			metadata,
+			errors,
+			tasks_for_shutdown_bytes,
+		} = rmp_serde::from_slice::<SaveState>(serialized_job)?;
+
+		let (progress_tx, progress_rx) = chan::unbounded();
+
+		Ok(Some((
+			Self {
+				location,
+				location_path,
+				file_path_ids,
+				metadata,
+				current_file: None,
+				progress_tx,
+				progress_rx,
+				errors,
+				pending_tasks_on_resume: Vec::new(),
+				tasks_for_shutdown: Vec::new(),
+			},
+			tasks_for_shutdown_bytes,
+		)))
+	}
+}
@@ -0,0 +1,280 @@
+use crate::crypto;
+
+use sd_crypto::{
+	ct::ConstantTimeEq,
+	encoding,
+	hashing::Hasher,
+	types::{DerivationContext, HashingAlgorithm, Key, Params, Salt, SecretKey},
+	vault::EphemeralVault,
+	Protected,
+};
+use sd_prisma::prisma::{key, PrismaClient};
+use sd_utils::uuid_to_bytes;
+
+use std::{
+	collections::HashMap,
+	fmt,
+	path::{Path, PathBuf},
+	sync::{Mutex, PoisonError},
+};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::task::spawn_blocking;
+use uuid::Uuid;
+
+use super::ALGORITHM;
+
+const KEY_VERIFICATION_CONTEXT: DerivationContext =
+	DerivationContext::new("spacedrive 2024-07-01 12:00:00 key verification context");
+
+/// How costly passwords are to hash, and so to guess, for new keys
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum KeyStrength {
+	#[default]
+	Standard,
+	Hardened,
+	Paranoid,
+}
+
+impl From<KeyStrength> for HashingAlgorithm {
+	fn from(strength: KeyStrength) -> Self {
+		Self::Argon2id(match strength {
+			KeyStrength::Standard => Params::Standard,
+			KeyStrength::Hardened => Params::Hardened,
+			KeyStrength::Paranoid => Params::Paranoid,
+		})
+	}
+}
+
+/// A key unlocked with its password, what files are encrypted and decrypted with
+pub(crate) struct MountedKey {
+	pub(crate) hashed_password: Key,
+	pub(crate) hashing_algorithm: HashingAlgorithm,
+	pub(crate) salt: Salt,
+}
+
+/// Keys of a library mounted with their passwords, kept encrypted in memory until unmounted or
+/// the library is closed.
+///
+/// Passwords are never stored, so jobs interrupted by a shutdown can only be resumed once the keys
+/// they use are mounted again. The same goes for the passwords of encrypted archives being
+/// extracted, given again with [`KeyManager::provide_archive_password`].
+pub struct KeyManager {
+	vault: EphemeralVault<Uuid, Key>,
+	/// Hashing parameters of mounted keys, which aren't secret
+	mounted: Mutex<HashMap<Uuid, (HashingAlgorithm, Salt)>>,
+	/// Passwords of encrypted archives whose extraction waits for them, taken once used
+	archive_passwords: Mutex<HashMap<PathBuf, Protected<String>>>,
+}
+
+impl fmt::Debug for KeyManager {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("KeyManager")
+			.field("mounted", &self.mounted())
+			.finish_non_exhaustive()
+	}
+}
+
+impl Default for KeyManager {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl KeyManager {
+	#[must_use]
+	pub fn new() -> Self {
+		Self {
+			vault: EphemeralVault::new(ALGORITHM),
+			mounted: Mutex::default(),
+			archive_passwords: Mutex::default(),
+		}
+	}
+
+	/// Creates a key unlocked by `password` and mounts it
+	pub async fn create(
+		&self,
+		db: &PrismaClient,
+		name: Option<String>,
+		password: String,
+		strength: KeyStrength,
+	) -> Result<key::Data, crypto::Error> {
+		let hashing_algorithm = HashingAlgorithm::from(strength);
+		let salt = Salt::generate();
+		let hashed_password = hash_password(hashing_algorithm, password, salt).await?;
+		let pub_id = Uuid::new_v4();
+
+		let key = db
+			.key()
+			.create(
+				uuid_to_bytes(pub_id),
+				encoding::encode(&hashing_algorithm)?,
+				salt.inner().to_vec(),
+				verification(&hashed_password, salt).expose().to_vec(),
+				vec![key::name::set(name)],
+			)
+			.exec()
+			.await?;
+
+		self.insert(pub_id, hashed_password, hashing_algorithm, salt)?;
+
+		Ok(key)
+	}
+
+	/// Unlocks a key of the library with its password, so jobs can use it
+	pub async fn mount(
+		&self,
+		db: &PrismaClient,
+		pub_id: Uuid,
+		password: String,
+	) -> Result<(), crypto::Error> {
+		let key = db
+			.key()
+			.find_unique(key::pub_id::equals(uuid_to_bytes(pub_id)))
+			.exec()
+			.await?
+			.ok_or(crypto::Error::KeyNotFound(pub_id))?;
+
+		let hashing_algorithm = encoding::decode::<HashingAlgorithm>(&key.hashing_algorithm)?;
+		let salt = Salt::try_from(key.salt)?;
+		let hashed_password = hash_password(hashing_algorithm, password, salt).await?;
+
+		if !bool::from(
+			verification(&hashed_password, salt)
+				.ct_eq(&Key::try_from(Protected::new(key.verification))?),
+		) {
+			return Err(crypto::Error::WrongPassword(pub_id));
+		}
+
+		self.insert(pub_id, hashed_password, hashing_algorithm, salt)
+	}
+
+	pub fn unmount(&self, pub_id: Uuid) {
+		if self
+			.mounted
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.remove(&pub_id)
+			.is_some()
+		{
+			// Only fails if the key isn't in the vault, which is what we want anyway
+			self.vault.remove(&pub_id).ok();
+		}
+	}
+
+	#[must_use]
+	pub fn is_mounted(&self, pub_id: Uuid) -> bool {
+		self.mounted
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.contains_key(&pub_id)
+	}
+
+	#[must_use]
+	pub fn mounted(&self) -> Vec<Uuid> {
+		self.mounted
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.keys()
+			.copied()
+			.collect()
+	}
+
+	/// Unmounts and deletes a key. Files encrypted with it can't be decrypted anymore.
+	pub async fn delete(&self, db: &PrismaClient, pub_id: Uuid) -> Result<(), crypto::Error> {
+		self.unmount(pub_id);
+
+		if db
+			.key()
+			.delete_many(vec![key::pub_id::equals(uuid_to_bytes(pub_id))])
+			.exec()
+			.await? == 0
+		{
+			return Err(crypto::Error::KeyNotFound(pub_id));
+		}
+
+		Ok(())
+	}
+
+	pub(crate) fn get(&self, pub_id: Uuid) -> Result<MountedKey, crypto::Error> {
+		let (hashing_algorithm, salt) = *self
+			.mounted
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.get(&pub_id)
+			.ok_or(crypto::Error::KeyNotMounted(pub_id))?;
+
+		Ok(MountedKey {
+			hashed_password: self.vault.get(&pub_id)?,
+			hashing_algorithm,
+			salt,
+		})
+	}
+
+	/// Gives back the password of an encrypted archive whose extraction was resumed after a
+	/// shutdown, for the extraction to go on once its job is resumed
+	pub fn provide_archive_password(&self, archive_path: impl Into<PathBuf>, password: String) {
+		self.archive_passwords
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.insert(archive_path.into(), Protected::new(password));
+	}
+
+	pub(crate) fn take_archive_password(&self, archive_path: &Path) -> Option<String> {
+		self.archive_passwords
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.remove(archive_path)
+			.map(Protected::into_inner)
+	}
+
+	/// Every mounted key, as encrypted files don't tell which key they were encrypted with
+	pub(crate) fn mounted_keys(&self) -> Result<Vec<Key>, crypto::Error> {
+		self.mounted()
+			.into_iter()
+			.map(|pub_id| self.vault.get(&pub_id).map_err(Into::into))
+			.collect()
+	}
+
+	fn insert(
+		&self,
+		pub_id: Uuid,
+		hashed_password: Key,
+		hashing_algorithm: HashingAlgorithm,
+		salt: Salt,
+	) -> Result<(), crypto::Error> {
+		let mut mounted = self.mounted.lock().unwrap_or_else(PoisonError::into_inner);
+
+		if mounted.remove(&pub_id).is_some() {
+			self.vault.remove(&pub_id).ok();
+		}
+
+		self.vault.insert(pub_id, hashed_password)?;
+		mounted.insert(pub_id, (hashing_algorithm, salt));
+
+		Ok(())
+	}
+}
+
+/// Hashes on a blocking thread, as hashing is made to be slow
+async fn hash_password(
+	hashing_algorithm: HashingAlgorithm,
+	password: String,
+	salt: Salt,
+) -> Result<Key, crypto::Error> {
+	spawn_blocking(move || {
+		Hasher::hash_password(
+			hashing_algorithm,
+			&Protected::new(password.into_bytes()),
+			salt,
+			&SecretKey::Null,
+		)
+	})
+	.await?
+	.map_err(Into::into)
+}
+
+fn verification(hashed_password: &Key, salt: Salt) -> Key {
+	Hasher::derive_key(hashed_password, salt, KEY_VERIFICATION_CONTEXT)
+}
@@ -0,0 +1,190 @@
+use crate::file_identifier::not_in_archive;
+
+use sd_core_file_path_helper::{FilePathError, IsolatedFilePathData};
+use sd_core_prisma_helpers::file_path_for_file_copier;
+
+use sd_crypto::types::{Algorithm, DerivationContext, MagicBytes};
+use sd_prisma::prisma::{file_path, location, PrismaClient};
+use sd_utils::{
+	db::{size_in_bytes_from_db, MissingFieldError},
+	error::FileIOError,
+};
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use prisma_client_rust::QueryError;
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub mod decryptor;
+pub mod encryptor;
+pub mod keys;
+mod tasks;
+
+pub use decryptor::FileDecryptor;
+pub use encryptor::FileEncryptor;
+pub use keys::{KeyManager, KeyStrength};
+pub use tasks::{decrypt, encrypt};
+
+/// Extension appended to the name of encrypted files
+pub const ENCRYPTED_EXTENSION: &str = "sdenc";
+
+// Bytes read between progress reports, from files being encrypted or decrypted
+const PROGRESS_CHUNK_SIZE: u64 = 1024 * 1024; // 1 MiB
+
+/// Starts every encrypted file, followed by its header and then the encrypted content
+const MAGIC_BYTES: MagicBytes<8> = MagicBytes::new(*b"sdenc\0\0\x01");
+
+const ALGORITHM: Algorithm = Algorithm::XChaCha20Poly1305;
+
+const HEADER_KEY_CONTEXT: DerivationContext =
+	DerivationContext::new("spacedrive 2024-07-01 12:00:00 encrypted file header key context");
+
+const HEADER_METADATA_CONTEXT: DerivationContext =
+	DerivationContext::new("spacedrive 2024-07-01 12:00:00 encrypted file metadata context");
+
+const METADATA_OBJECT_NAME: &str = "FileMetadata";
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("file_path not found in location: <id='{0}'>")]
+	FilePathNotFound(file_path::id::Type),
+	#[error("key not found: <pub_id='{0}'>")]
+	KeyNotFound(Uuid),
+	#[error("key isn't mounted, its password must be given again: <pub_id='{0}'>")]
+	KeyNotMounted(Uuid),
+	#[error("no keys are mounted to decrypt files with")]
+	NoMountedKeys,
+	#[error("wrong password for key: <pub_id='{0}'>")]
+	WrongPassword(Uuid),
+	#[error("crypto error: {0}")]
+	Crypto(#[from] sd_crypto::Error),
+	#[error("failed to run blocking crypto task: {0}")]
+	Join(#[from] tokio::task::JoinError),
+	#[error("missing field on database: {0}")]
+	MissingField(#[from] MissingFieldError),
+	#[error("database error: {0}")]
+	Database(#[from] QueryError),
+	#[error("failed to deserialized stored tasks for job resume: {0}")]
+	DeserializeTasks(#[from] rmp_serde::decode::Error),
+
+	#[error(transparent)]
+	FilePathError(#[from] FilePathError),
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+}
+
+impl From<Error> for rspc::Error {
+	fn from(err: Error) -> Self {
+		match err {
+			Error::FilePathNotFound(_) | Error::KeyNotFound(_) => {
+				Self::with_cause(ErrorCode::NotFound, err.to_string(), err)
+			}
+
+			Error::KeyNotMounted(_) | Error::NoMountedKeys | Error::WrongPassword(_) => {
+				Self::with_cause(ErrorCode::BadRequest, err.to_string(), err)
+			}
+
+			_ => Self::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize, specta::Type)]
+pub enum NonCriticalError {
+	#[error(transparent)]
+	Encrypt(#[from] encrypt::NonCriticalError),
+	#[error(transparent)]
+	Decrypt(#[from] decrypt::NonCriticalError),
+}
+
+/// What an encrypted file keeps of the original, encrypted in its header with the content's key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileMetadata {
+	/// Full name, with extension
+	pub name: String,
+	pub size: u64,
+	pub date_created: Option<DateTime<Utc>>,
+	pub date_modified: Option<DateTime<Utc>>,
+}
+
+/// Files of a selection of file paths of a location, directories being replaced by the files in
+/// them, with how many bytes they add up to. Only encrypted files are taken from directories when
+/// `only_encrypted` is set.
+async fn gather_files(
+	db: &PrismaClient,
+	location_id: location::id::Type,
+	location_path: &Path,
+	file_path_ids: &[file_path::id::Type],
+	only_encrypted: bool,
+) -> Result<(Vec<PathBuf>, u64), Error> {
+	let file_paths = db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(Some(location_id)),
+			file_path::id::in_vec(file_path_ids.to_vec()),
+			not_in_archive(),
+		])
+		.select(file_path_for_file_copier::select())
+		.exec()
+		.await?;
+
+	if let Some(missing_id) = file_path_ids
+		.iter()
+		.find(|id| !file_paths.iter().any(|file_path| file_path.id == **id))
+	{
+		return Err(Error::FilePathNotFound(*missing_id));
+	}
+
+	let mut files = Vec::with_capacity(file_paths.len());
+	let mut total_bytes = 0;
+
+	for file_path in file_paths {
+		let iso_file_path = IsolatedFilePathData::try_from((location_id, &file_path))?;
+
+		if !iso_file_path.is_dir() {
+			total_bytes += file_size(&file_path);
+			files.push(location_path.join(&iso_file_path));
+			continue;
+		}
+
+		let mut params = vec![
+			file_path::location_id::equals(Some(location_id)),
+			file_path::materialized_path::starts_with(
+				iso_file_path
+					.materialized_path_for_children()
+					.expect("we checked that the iso_file_path is a directory"),
+			),
+			file_path::is_dir::equals(Some(false)),
+			not_in_archive(),
+		];
+		if only_encrypted {
+			params.push(file_path::extension::equals(Some(
+				ENCRYPTED_EXTENSION.to_string(),
+			)));
+		}
+
+		for child in db
+			.file_path()
+			.find_many(params)
+			.select(file_path_for_file_copier::select())
+			.exec()
+			.await?
+		{
+			total_bytes += file_size(&child);
+			files.push(location_path.join(IsolatedFilePathData::try_from((location_id, &child))?));
+		}
+	}
+
+	Ok((files, total_bytes))
+}
+
+fn file_size(file_path: &file_path_for_file_copier::Data) -> u64 {
+	file_path
+		.size_in_bytes_bytes
+		.as_deref()
+		.map(size_in_bytes_from_db)
+		.unwrap_or_default()
+}
@@ -0,0 +1,381 @@
+use crate::{
+	crypto::{
+		self, keys::KeyManager, FileMetadata, HEADER_KEY_CONTEXT, HEADER_METADATA_CONTEXT,
+		MAGIC_BYTES, METADATA_OBJECT_NAME,
+	},
+	file_copier::{available_path, copier::partial_path, exists},
+	Error,
+};
+
+use sd_crypto::{
+	crypto::Decryptor,
+	encoding::Header,
+	types::{Aad, Key},
+};
+use sd_task_system::{
+	ExecStatus, Interrupter, InterruptionKind, IntoAnyTaskOutput, SerializableTask, Task, TaskId,
+};
+
+use std::{
+	fs::{self, File},
+	future::IntoFuture,
+	io::{self, BufReader, BufWriter},
+	mem,
+	path::{Path, PathBuf},
+	sync::{atomic::AtomicBool, atomic::Ordering, Arc},
+	time::{Duration, SystemTime},
+};
+
+use async_channel as chan;
+use futures::FutureExt;
+use futures_concurrency::future::Race;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::{runtime::Handle, task::spawn_blocking, time::Instant};
+use tracing::warn;
+
+use super::{remove_partial, CryptoProgress, FileCtx};
+
+/// Decrypts `.sdenc` files one after the other on a blocking thread, each next to it under its
+/// original name, with any mounted key that opens it.
+///
+/// Every block of content is authenticated along with the header, and the last one is marked as
+/// such, so files changed or truncated since they were encrypted fail to decrypt instead of being
+/// restored damaged. Decrypted files are written under a partial name and only moved into place
+/// once all their content was verified.
+#[derive(Debug)]
+pub struct Decrypt {
+	id: TaskId,
+	files: Arc<Vec<PathBuf>>,
+	/// Index of the first file left to decrypt
+	next: usize,
+	keys: Arc<KeyManager>,
+	progress_tx: chan::Sender<(TaskId, CryptoProgress)>,
+	output: Output,
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize, Type)]
+pub enum NonCriticalError {
+	#[error("not an encrypted file, or its header is damaged: <path='{}'>", .0.display())]
+	NotEncrypted(PathBuf),
+	#[error("none of the mounted keys can decrypt file: <path='{}'>", .0.display())]
+	NoMatchingKey(PathBuf),
+	#[error("encrypted file was changed or truncated since it was encrypted: <path='{}'>", .0.display())]
+	Tampered(PathBuf),
+	#[error("failed to find an available name to avoid a conflict: <path='{}'>", .0.display())]
+	FailedToFindAvailableName(PathBuf),
+	#[error("failed to decrypt file: <path='{}'>: {1}", .0.display())]
+	FailedToDecrypt(PathBuf, String),
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Output {
+	pub decrypted_files: u64,
+	pub decrypted_bytes: u64,
+	pub failed_files: u64,
+	pub decrypt_time: Duration,
+	pub errors: Vec<crate::NonCriticalError>,
+}
+
+/// How far a blocking run of [`decrypt_files`] went
+struct Decrypted {
+	next: usize,
+	done: bool,
+	output: Output,
+}
+
+impl Decrypt {
+	#[must_use]
+	pub fn new(
+		files: Vec<PathBuf>,
+		keys: Arc<KeyManager>,
+		progress_tx: chan::Sender<(TaskId, CryptoProgress)>,
+	) -> Self {
+		Self {
+			id: TaskId::new_v4(),
+			files: Arc::new(files),
+			next: 0,
+			keys,
+			progress_tx,
+			output: Output::default(),
+		}
+	}
+}
+
+/// Decrypts `source` next to it, returning where it was decrypted to and how many bytes were
+pub(crate) fn decrypt_file(
+	ctx: &FileCtx,
+	keys: &[Key],
+	source: &Path,
+) -> Result<(PathBuf, u64), NonCriticalError> {
+	let failed = |e: &dyn ToString| NonCriticalError::FailedToDecrypt(source.into(), e.to_string());
+
+	ctx.started(source);
+
+	let mut reader = BufReader::new(File::open(source).map_err(|e| failed(&e))?);
+
+	let (header, aad) = Header::from_reader(&mut reader, MAGIC_BYTES)
+		.map_err(|_| NonCriticalError::NotEncrypted(source.into()))?;
+
+	let (master_key, _) = header
+		.decrypt_master_key(keys, HEADER_KEY_CONTEXT)
+		.map_err(|_| NonCriticalError::NoMatchingKey(source.into()))?;
+
+	let metadata = header
+		.decrypt_object(METADATA_OBJECT_NAME, HEADER_METADATA_CONTEXT, &master_key)
+		.ok()
+		.and_then(|bytes| rmp_serde::from_slice::<FileMetadata>(bytes.expose()).ok())
+		.ok_or_else(|| NonCriticalError::Tampered(source.into()))?;
+
+	// Only the name is kept from the metadata, which can't be trusted to stay in the directory
+	let mut target = Path::new(&metadata.name)
+		.file_name()
+		.or_else(|| source.file_stem())
+		.map_or_else(
+			|| source.with_extension(""),
+			|name| source.with_file_name(name),
+		);
+
+	if ctx
+		.runtime
+		.block_on(exists(&target))
+		.map_err(|e| failed(&e))?
+	{
+		target = ctx
+			.runtime
+			.block_on(available_path(&target))
+			.map_err(|e| failed(&e))?
+			.ok_or_else(|| NonCriticalError::FailedToFindAvailableName(target.clone()))?;
+	}
+
+	let partial = partial_path(&target);
+
+	match write_decrypted(ctx, &master_key, &header, aad, reader, &metadata, &partial) {
+		Ok(size) if size == metadata.size => {}
+		Ok(_) | Err(sd_crypto::Error::Decrypt) => {
+			remove_partial(&partial);
+			return Err(NonCriticalError::Tampered(source.into()));
+		}
+		Err(e) => {
+			remove_partial(&partial);
+			return Err(failed(&e));
+		}
+	}
+
+	if let Err(e) = fs::rename(&partial, &target) {
+		remove_partial(&partial);
+		return Err(failed(&e));
+	}
+
+	Ok((target, metadata.size))
+}
+
+/// Writes the decrypted content, returning its size. The original modification date is restored,
+/// creation dates can't be set on every platform.
+fn write_decrypted(
+	ctx: &FileCtx,
+	master_key: &Key,
+	header: &Header,
+	aad: Aad,
+	reader: BufReader<File>,
+	metadata: &FileMetadata,
+	partial: &Path,
+) -> Result<u64, sd_crypto::Error> {
+	let mut writer = BufWriter::new(File::create(partial)?);
+
+	Decryptor::new(master_key, &header.nonce, header.algorithm)?.decrypt_streams(
+		ctx.reader(reader),
+		&mut writer,
+		aad,
+	)?;
+
+	let file = writer
+		.into_inner()
+		.map_err(io::IntoInnerError::into_error)?;
+
+	if let Some(date_modified) = metadata.date_modified {
+		file.set_modified(SystemTime::from(date_modified))?;
+	}
+
+	file.sync_all()?;
+
+	Ok(file.metadata()?.len())
+}
+
+/// Decrypts files from `next` until all are decrypted, or until the task is interrupted
+fn decrypt_files(ctx: &FileCtx, keys: &[Key], files: &[PathBuf], mut next: usize) -> Decrypted {
+	let mut output = Output::default();
+
+	while let Some(source) = files.get(next) {
+		if ctx.stopped() {
+			return Decrypted {
+				next,
+				done: false,
+				output,
+			};
+		}
+
+		match decrypt_file(ctx, keys, source) {
+			Ok((_, size)) => {
+				output.decrypted_files += 1;
+				output.decrypted_bytes += size;
+			}
+			// Interrupted in the middle of the file, left for the next run
+			Err(_) if ctx.stopped() => {
+				return Decrypted {
+					next,
+					done: false,
+					output,
+				};
+			}
+			Err(e) => {
+				warn!("{e}");
+				output.failed_files += 1;
+				output.errors.push(crypto::NonCriticalError::from(e).into());
+			}
+		}
+
+		next += 1;
+	}
+
+	Decrypted {
+		next,
+		done: true,
+		output,
+	}
+}
+
+#[async_trait::async_trait]
+impl Task<Error> for Decrypt {
+	fn id(&self) -> TaskId {
+		self.id
+	}
+
+	async fn run(&mut self, interrupter: &Interrupter) -> Result<ExecStatus, Error> {
+		enum RaceOutput {
+			Decrypted(Result<Decrypted, tokio::task::JoinError>),
+			Interrupted(InterruptionKind),
+		}
+
+		let keys = self.keys.mounted_keys()?;
+		if keys.is_empty() {
+			return Err(crypto::Error::NoMountedKeys.into());
+		}
+
+		let start = Instant::now();
+		let ctx = Arc::new(FileCtx {
+			task_id: self.id,
+			progress_tx: self.progress_tx.clone(),
+			runtime: Handle::current(),
+			stop: AtomicBool::new(false),
+		});
+
+		let mut handle = spawn_blocking({
+			let ctx = Arc::clone(&ctx);
+			let files = Arc::clone(&self.files);
+			let next = self.next;
+
+			move || decrypt_files(&ctx, &keys, &files, next)
+		});
+
+		let output = (
+			(&mut handle).map(RaceOutput::Decrypted),
+			interrupter.into_future().map(RaceOutput::Interrupted),
+		)
+			.race()
+			.await;
+
+		let (decrypted, interruption) = match output {
+			RaceOutput::Decrypted(decrypted) => (decrypted, None),
+			RaceOutput::Interrupted(kind) => {
+				ctx.stop.store(true, Ordering::Relaxed);
+				(handle.await, Some(kind))
+			}
+		};
+
+		self.output.decrypt_time += start.elapsed();
+
+		let Decrypted {
+			next,
+			done,
+			output:
+				Output {
+					decrypted_files,
+					decrypted_bytes,
+					failed_files,
+					errors,
+					..
+				},
+		} = decrypted.map_err(crypto::Error::from)?;
+
+		self.next = next;
+		self.output.decrypted_files += decrypted_files;
+		self.output.decrypted_bytes += decrypted_bytes;
+		self.output.failed_files += failed_files;
+		self.output.errors.extend(errors);
+
+		if done {
+			return Ok(ExecStatus::Done(mem::take(&mut self.output).into_output()));
+		}
+
+		// Only stopping when interrupted. Canceling keeps what was already decrypted.
+		Ok(match interruption {
+			Some(InterruptionKind::Cancel) => ExecStatus::Canceled,
+			_ => ExecStatus::Paused,
+		})
+	}
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SaveState {
+	id: TaskId,
+	files: Arc<Vec<PathBuf>>,
+	next: usize,
+	output: Output,
+}
+
+impl SerializableTask<Error> for Decrypt {
+	type SerializeError = rmp_serde::encode::Error;
+
+	type DeserializeError = rmp_serde::decode::Error;
+
+	type DeserializeCtx = (chan::Sender<(TaskId, CryptoProgress)>, Arc<KeyManager>);
+
+	async fn serialize(self) -> Result<Vec<u8>, Self::SerializeError> {
+		let Self {
+			id,
+			files,
+			next,
+			output,
+			..
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState {
+			id,
+			files,
+			next,
+			output,
+		})
+	}
+
+	async fn deserialize(
+		data: &[u8],
+		(progress_tx, keys): Self::DeserializeCtx,
+	) -> Result<Self, Self::DeserializeError> {
+		rmp_serde::from_slice(data).map(
+			|SaveState {
+			     id,
+			     files,
+			     next,
+			     output,
+			 }| Self {
+				id,
+				files,
+				next,
+				keys,
+				progress_tx,
+				output,
+			},
+		)
+	}
+}
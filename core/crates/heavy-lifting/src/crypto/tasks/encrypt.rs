@@ -0,0 +1,378 @@
+use crate::{
+	crypto::{
+		self,
+		keys::{KeyManager, MountedKey},
+		FileMetadata, ALGORITHM, ENCRYPTED_EXTENSION, HEADER_KEY_CONTEXT, HEADER_METADATA_CONTEXT,
+		MAGIC_BYTES, METADATA_OBJECT_NAME,
+	},
+	file_copier::{available_path, copier::partial_path, exists},
+	Error,
+};
+
+use sd_crypto::{crypto::Encryptor, encoding::Header, types::Key};
+use sd_task_system::{
+	ExecStatus, Interrupter, InterruptionKind, IntoAnyTaskOutput, SerializableTask, Task, TaskId,
+};
+
+use std::{
+	fs::{self, File},
+	future::IntoFuture,
+	io::{self, BufWriter},
+	mem,
+	path::{Path, PathBuf},
+	sync::{atomic::AtomicBool, atomic::Ordering, Arc},
+	time::Duration,
+};
+
+use async_channel as chan;
+use chrono::DateTime;
+use futures::FutureExt;
+use futures_concurrency::future::Race;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::{runtime::Handle, task::spawn_blocking, time::Instant};
+use tracing::warn;
+use uuid::Uuid;
+
+use super::{remove_partial, CryptoProgress, FileCtx};
+
+/// Encrypts files one after the other on a blocking thread, each into a `.sdenc` file next to it.
+///
+/// Encrypted files are written under a partial name and moved into place once complete. An
+/// interruption stops the file being encrypted, which is encrypted again from its start on resume.
+#[derive(Debug)]
+pub struct Encrypt {
+	id: TaskId,
+	key_pub_id: Uuid,
+	files: Arc<Vec<PathBuf>>,
+	/// Index of the first file left to encrypt
+	next: usize,
+	keys: Arc<KeyManager>,
+	progress_tx: chan::Sender<(TaskId, CryptoProgress)>,
+	output: Output,
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize, Type)]
+pub enum NonCriticalError {
+	#[error("failed to find an available name to avoid a conflict: <path='{}'>", .0.display())]
+	FailedToFindAvailableName(PathBuf),
+	#[error("failed to encrypt file: <path='{}'>: {1}", .0.display())]
+	FailedToEncrypt(PathBuf, String),
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Output {
+	pub encrypted_files: u64,
+	pub encrypted_bytes: u64,
+	pub failed_files: u64,
+	pub encrypt_time: Duration,
+	pub errors: Vec<crate::NonCriticalError>,
+}
+
+/// How far a blocking run of [`encrypt_files`] went
+struct Encrypted {
+	next: usize,
+	done: bool,
+	output: Output,
+}
+
+impl Encrypt {
+	#[must_use]
+	pub fn new(
+		key_pub_id: Uuid,
+		files: Vec<PathBuf>,
+		keys: Arc<KeyManager>,
+		progress_tx: chan::Sender<(TaskId, CryptoProgress)>,
+	) -> Self {
+		Self {
+			id: TaskId::new_v4(),
+			key_pub_id,
+			files: Arc::new(files),
+			next: 0,
+			keys,
+			progress_tx,
+			output: Output::default(),
+		}
+	}
+}
+
+/// Encrypts `source` next to it, returning where it was encrypted to and how many bytes were
+pub(crate) fn encrypt_file(
+	ctx: &FileCtx,
+	key: &MountedKey,
+	source: &Path,
+) -> Result<(PathBuf, u64), NonCriticalError> {
+	let failed = |e: &dyn ToString| NonCriticalError::FailedToEncrypt(source.into(), e.to_string());
+
+	ctx.started(source);
+
+	let mut target = source.as_os_str().to_owned();
+	target.push(".");
+	target.push(ENCRYPTED_EXTENSION);
+	let mut target = PathBuf::from(target);
+
+	if ctx
+		.runtime
+		.block_on(exists(&target))
+		.map_err(|e| failed(&e))?
+	{
+		target = ctx
+			.runtime
+			.block_on(available_path(&target))
+			.map_err(|e| failed(&e))?
+			.ok_or_else(|| NonCriticalError::FailedToFindAvailableName(target.clone()))?;
+	}
+
+	let file = File::open(source).map_err(|e| failed(&e))?;
+	let fs_metadata = file.metadata().map_err(|e| failed(&e))?;
+	let metadata = FileMetadata {
+		name: source
+			.file_name()
+			.unwrap_or_default()
+			.to_string_lossy()
+			.into_owned(),
+		size: fs_metadata.len(),
+		date_created: fs_metadata.created().ok().map(DateTime::from),
+		date_modified: fs_metadata.modified().ok().map(DateTime::from),
+	};
+	let metadata_bytes = rmp_serde::to_vec_named(&metadata).map_err(|e| failed(&e))?;
+
+	let partial = partial_path(&target);
+
+	if let Err(e) = write_encrypted(ctx, key, &metadata_bytes, file, &partial) {
+		remove_partial(&partial);
+		return Err(failed(&e));
+	}
+
+	if let Err(e) = fs::rename(&partial, &target) {
+		remove_partial(&partial);
+		return Err(failed(&e));
+	}
+
+	Ok((target, metadata.size))
+}
+
+/// Writes the header, with a keyslot opened by `key` and the original's metadata, then the
+/// content, both encrypted with a random key of their own
+fn write_encrypted(
+	ctx: &FileCtx,
+	key: &MountedKey,
+	metadata: &[u8],
+	file: File,
+	partial: &Path,
+) -> Result<(), sd_crypto::Error> {
+	let master_key = Key::generate();
+
+	let mut header = Header::new(ALGORITHM);
+	header.add_keyslot(
+		key.hashing_algorithm,
+		key.salt,
+		&key.hashed_password,
+		&master_key,
+		HEADER_KEY_CONTEXT,
+	)?;
+	header.add_object(
+		METADATA_OBJECT_NAME,
+		HEADER_METADATA_CONTEXT,
+		&master_key,
+		metadata,
+	)?;
+
+	let mut writer = BufWriter::new(File::create(partial)?);
+	header.to_writer(&mut writer, MAGIC_BYTES)?;
+
+	// The header is authenticated with every block of content
+	Encryptor::new(&master_key, &header.nonce, header.algorithm)?.encrypt_streams(
+		ctx.reader(file),
+		&mut writer,
+		header.generate_aad(),
+	)?;
+
+	writer
+		.into_inner()
+		.map_err(io::IntoInnerError::into_error)?
+		.sync_all()?;
+
+	Ok(())
+}
+
+/// Encrypts files from `next` until all are encrypted, or until the task is interrupted
+fn encrypt_files(ctx: &FileCtx, key: &MountedKey, files: &[PathBuf], mut next: usize) -> Encrypted {
+	let mut output = Output::default();
+
+	while let Some(source) = files.get(next) {
+		if ctx.stopped() {
+			return Encrypted {
+				next,
+				done: false,
+				output,
+			};
+		}
+
+		match encrypt_file(ctx, key, source) {
+			Ok((_, size)) => {
+				output.encrypted_files += 1;
+				output.encrypted_bytes += size;
+			}
+			// Interrupted in the middle of the file, left for the next run
+			Err(_) if ctx.stopped() => {
+				return Encrypted {
+					next,
+					done: false,
+					output,
+				};
+			}
+			Err(e) => {
+				warn!("{e}");
+				output.failed_files += 1;
+				output.errors.push(crypto::NonCriticalError::from(e).into());
+			}
+		}
+
+		next += 1;
+	}
+
+	Encrypted {
+		next,
+		done: true,
+		output,
+	}
+}
+
+#[async_trait::async_trait]
+impl Task<Error> for Encrypt {
+	fn id(&self) -> TaskId {
+		self.id
+	}
+
+	async fn run(&mut self, interrupter: &Interrupter) -> Result<ExecStatus, Error> {
+		enum RaceOutput {
+			Encrypted(Result<Encrypted, tokio::task::JoinError>),
+			Interrupted(InterruptionKind),
+		}
+
+		// Fails if the key was unmounted, or never mounted again after a shutdown
+		let key = self.keys.get(self.key_pub_id)?;
+
+		let start = Instant::now();
+		let ctx = Arc::new(FileCtx {
+			task_id: self.id,
+			progress_tx: self.progress_tx.clone(),
+			runtime: Handle::current(),
+			stop: AtomicBool::new(false),
+		});
+
+		let mut handle = spawn_blocking({
+			let ctx = Arc::clone(&ctx);
+			let files = Arc::clone(&self.files);
+			let next = self.next;
+
+			move || encrypt_files(&ctx, &key, &files, next)
+		});
+
+		let output = (
+			(&mut handle).map(RaceOutput::Encrypted),
+			interrupter.into_future().map(RaceOutput::Interrupted),
+		)
+			.race()
+			.await;
+
+		let (encrypted, interruption) = match output {
+			RaceOutput::Encrypted(encrypted) => (encrypted, None),
+			RaceOutput::Interrupted(kind) => {
+				ctx.stop.store(true, Ordering::Relaxed);
+				(handle.await, Some(kind))
+			}
+		};
+
+		self.output.encrypt_time += start.elapsed();
+
+		let Encrypted {
+			next,
+			done,
+			output:
+				Output {
+					encrypted_files,
+					encrypted_bytes,
+					failed_files,
+					errors,
+					..
+				},
+		} = encrypted.map_err(crypto::Error::from)?;
+
+		self.next = next;
+		self.output.encrypted_files += encrypted_files;
+		self.output.encrypted_bytes += encrypted_bytes;
+		self.output.failed_files += failed_files;
+		self.output.errors.extend(errors);
+
+		if done {
+			return Ok(ExecStatus::Done(mem::take(&mut self.output).into_output()));
+		}
+
+		// Only stopping when interrupted. Canceling keeps what was already encrypted.
+		Ok(match interruption {
+			Some(InterruptionKind::Cancel) => ExecStatus::Canceled,
+			_ => ExecStatus::Paused,
+		})
+	}
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SaveState {
+	id: TaskId,
+	key_pub_id: Uuid,
+	files: Arc<Vec<PathBuf>>,
+	next: usize,
+	output: Output,
+}
+
+impl SerializableTask<Error> for Encrypt {
+	type SerializeError = rmp_serde::encode::Error;
+
+	type DeserializeError = rmp_serde::decode::Error;
+
+	type DeserializeCtx = (chan::Sender<(TaskId, CryptoProgress)>, Arc<KeyManager>);
+
+	async fn serialize(self) -> Result<Vec<u8>, Self::SerializeError> {
+		let Self {
+			id,
+			key_pub_id,
+			files,
+			next,
+			output,
+			..
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState {
+			id,
+			key_pub_id,
+			files,
+			next,
+			output,
+		})
+	}
+
+	async fn deserialize(
+		data: &[u8],
+		(progress_tx, keys): Self::DeserializeCtx,
+	) -> Result<Self, Self::DeserializeError> {
+		rmp_serde::from_slice(data).map(
+			|SaveState {
+			     id,
+			     key_pub_id,
+			     files,
+			     next,
+			     output,
+			 }| Self {
+				id,
+				key_pub_id,
+				files,
+				next,
+				keys,
+				progress_tx,
+				output,
+			},
+		)
+	}
+}
@@ -0,0 +1,283 @@
+use sd_task_system::TaskId;
+
+use std::{
+	fs,
+	io::{self, Read},
+	path::{Path, PathBuf},
+	sync::atomic::{AtomicBool, Ordering},
+};
+
+use async_channel as chan;
+use tokio::runtime::Handle;
+use tracing::{trace, warn};
+
+use super::PROGRESS_CHUNK_SIZE;
+
+pub mod decrypt;
+pub mod encrypt;
+
+pub use decrypt::Decrypt;
+pub use encrypt::Encrypt;
+
+/// Sent by crypto tasks as they go, so the job can report progress in the middle of large files
+#[derive(Debug)]
+pub enum CryptoProgress {
+	/// A file started being encrypted or decrypted
+	Started(PathBuf),
+	/// Bytes read since the last update
+	Processed(u64),
+}
+
+/// What encrypting or decrypting files needs on the blocking thread
+pub(crate) struct FileCtx {
+	pub(crate) task_id: TaskId,
+	pub(crate) progress_tx: chan::Sender<(TaskId, CryptoProgress)>,
+	pub(crate) runtime: Handle,
+	pub(crate) stop: AtomicBool,
+}
+
+impl FileCtx {
+	fn started(&self, path: &Path) {
+		if self
+			.progress_tx
+			.send_blocking((self.task_id, CryptoProgress::Started(path.to_path_buf())))
+			.is_err()
+		{
+			trace!("Job stopped listening to crypto progress");
+		}
+	}
+
+	fn stopped(&self) -> bool {
+		self.stop.load(Ordering::Relaxed)
+	}
+
+	const fn reader<R>(&self, inner: R) -> ProgressReader<'_, R> {
+		ProgressReader {
+			inner,
+			ctx: self,
+			unreported: 0,
+		}
+	}
+}
+
+/// Wraps what crypto tasks read from, reporting read bytes every [`PROGRESS_CHUNK_SIZE`] and
+/// failing once the task is interrupted, so large files don't hold interruptions until done.
+///
+/// Encryption streams take any read shorter than a block as the last one, so reads always fill
+/// the buffer unless the end is reached.
+pub(crate) struct ProgressReader<'ctx, R> {
+	inner: R,
+	ctx: &'ctx FileCtx,
+	unreported: u64,
+}
+
+impl<R> ProgressReader<'_, R> {
+	fn report(&mut self) {
+		if self.unreported > 0
+			&& self
+				.ctx
+				.progress_tx
+				.send_blocking((self.ctx.task_id, CryptoProgress::Processed(self.unreported)))
+				.is_err()
+		{
+			trace!("Job stopped listening to crypto progress");
+		}
+
+		self.unreported = 0;
+	}
+}
+
+impl<R: Read> Read for ProgressReader<'_, R> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		if self.ctx.stopped() {
+			return Err(io::Error::new(io::ErrorKind::Other, "task interrupted"));
+		}
+
+		let mut read = 0;
+		while read < buf.len() {
+			match self.inner.read(&mut buf[read..]) {
+				Ok(0) => break,
+				Ok(n) => read += n,
+				Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+				Err(e) => return Err(e),
+			}
+		}
+
+		self.unreported += read as u64;
+		if self.unreported >= PROGRESS_CHUNK_SIZE {
+			self.report();
+		}
+
+		Ok(read)
+	}
+}
+
+impl<R> Drop for ProgressReader<'_, R> {
+	fn drop(&mut self) {
+		self.report();
+	}
+}
+
+fn remove_partial(partial: &Path) {
+	if let Err(e) = fs::remove_file(partial) {
+		if e.kind() != io::ErrorKind::NotFound {
+			warn!(
+				"Failed to remove partial file <path='{}'>: {e:#?}",
+				partial.display()
+			);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::crypto::{keys::MountedKey, KeyStrength, ENCRYPTED_EXTENSION};
+
+	use sd_crypto::{
+		hashing::Hasher,
+		types::{HashingAlgorithm, Salt, SecretKey},
+		Protected,
+	};
+
+	use std::{
+		fs::{self, OpenOptions},
+		io::{Seek, SeekFrom, Write},
+		sync::atomic::AtomicBool,
+	};
+
+	use tokio::runtime::Handle;
+
+	use super::{decrypt, encrypt, FileCtx};
+
+	fn mounted_key(password: &[u8]) -> MountedKey {
+		let hashing_algorithm = HashingAlgorithm::from(KeyStrength::Standard);
+		let salt = Salt::generate();
+
+		MountedKey {
+			hashed_password: Hasher::hash_password(
+				hashing_algorithm,
+				&Protected::new(password.to_vec()),
+				salt,
+				&SecretKey::Null,
+			)
+			.expect("password hashing failed"),
+			hashing_algorithm,
+			salt,
+		}
+	}
+
+	fn file_ctx() -> FileCtx {
+		let (progress_tx, _) = async_channel::unbounded();
+
+		FileCtx {
+			task_id: uuid::Uuid::new_v4(),
+			progress_tx,
+			runtime: Handle::current(),
+			stop: AtomicBool::new(false),
+		}
+	}
+
+	#[tokio::test]
+	async fn round_trip_restores_name_and_content() {
+		let dir = tempfile::tempdir().expect("failed to create temp dir");
+		let source = dir.path().join("notes.txt");
+		// Spans a few stream blocks, the last one partial
+		let content = (0..=250u8).cycle().take(3_000_000).collect::<Vec<_>>();
+		fs::write(&source, &content).expect("failed to write source");
+
+		let key = mounted_key(b"password");
+		let encrypted = tokio::task::spawn_blocking({
+			let source = source.clone();
+			move || encrypt::encrypt_file(&file_ctx(), &key, &source).map(|(path, _)| (path, key))
+		})
+		.await
+		.expect("encrypt thread panicked")
+		.expect("failed to encrypt");
+		let (encrypted_path, key) = encrypted;
+
+		assert_eq!(
+			encrypted_path,
+			dir.path().join(format!("notes.txt.{ENCRYPTED_EXTENSION}"))
+		);
+
+		fs::remove_file(&source).expect("failed to remove source");
+
+		let (decrypted_path, _) = tokio::task::spawn_blocking({
+			let encrypted_path = encrypted_path.clone();
+			move || decrypt::decrypt_file(&file_ctx(), &[key.hashed_password], &encrypted_path)
+		})
+		.await
+		.expect("decrypt thread panicked")
+		.expect("failed to decrypt");
+
+		assert_eq!(decrypted_path, source);
+		assert_eq!(
+			fs::read(&source).expect("failed to read decrypted"),
+			content
+		);
+	}
+
+	#[tokio::test]
+	async fn tampered_or_wrong_key_fails() {
+		let dir = tempfile::tempdir().expect("failed to create temp dir");
+		let source = dir.path().join("photo.jpg");
+		fs::write(&source, vec![7u8; 4096]).expect("failed to write source");
+
+		let key = mounted_key(b"password");
+		let other_key = mounted_key(b"password");
+
+		let (encrypted_path, key, other_key) = tokio::task::spawn_blocking({
+			let source = source.clone();
+			move || {
+				encrypt::encrypt_file(&file_ctx(), &key, &source)
+					.map(|(path, _)| (path, key, other_key))
+			}
+		})
+		.await
+		.expect("encrypt thread panicked")
+		.expect("failed to encrypt");
+
+		// Same password, different salt, so a different key
+		let wrong = tokio::task::spawn_blocking({
+			let encrypted_path = encrypted_path.clone();
+			move || {
+				decrypt::decrypt_file(&file_ctx(), &[other_key.hashed_password], &encrypted_path)
+			}
+		})
+		.await
+		.expect("decrypt thread panicked");
+		assert!(matches!(
+			wrong,
+			Err(decrypt::NonCriticalError::NoMatchingKey(_))
+		));
+
+		let mut file = OpenOptions::new()
+			.write(true)
+			.open(&encrypted_path)
+			.expect("failed to open encrypted file");
+		file.seek(SeekFrom::End(-100))
+			.expect("failed to seek encrypted file");
+		file.write_all(&[0; 10])
+			.expect("failed to tamper encrypted file");
+		drop(file);
+
+		let tampered = tokio::task::spawn_blocking({
+			let encrypted_path = encrypted_path.clone();
+			move || decrypt::decrypt_file(&file_ctx(), &[key.hashed_password], &encrypted_path)
+		})
+		.await
+		.expect("decrypt thread panicked");
+		assert!(matches!(
+			tampered,
+			Err(decrypt::NonCriticalError::Tampered(_))
+		));
+
+		// Nothing is left behind by failed decryptions
+		assert_eq!(
+			fs::read_dir(dir.path())
+				.expect("failed to read temp dir")
+				.count(),
+			2
+		);
+	}
+}
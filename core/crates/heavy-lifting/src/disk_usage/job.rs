@@ -0,0 +1,328 @@
+use crate::{
+	disk_usage,
+	job_system::{
+		job::{Job, JobReturn, JobTaskDispatcher, ReturnStatus},
+		report::ReportOutputMetadata,
+		utils::{cancel_pending_tasks, location_concurrency_key},
+		SerializableJob, SerializedTasks,
+	},
+	utils::sub_path::maybe_get_iso_file_path_from_sub_path,
+	Error, JobName, OuterContext, ProgressUpdate,
+};
+
+use sd_prisma::prisma::location;
+use sd_task_system::{
+	IntoTask, SerializableTask, Task, TaskDispatcher, TaskHandle, TaskOutput, TaskStatus,
+};
+use sd_utils::db::maybe_missing;
+
+use std::{
+	collections::HashMap,
+	hash::{Hash, Hasher},
+	mem,
+	path::PathBuf,
+	sync::Arc,
+	time::Duration,
+};
+
+use futures::{stream::FuturesUnordered, StreamExt};
+use futures_concurrency::future::TryJoin;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{debug, warn};
+
+use super::{
+	parent_of,
+	tasks::{aggregator, Aggregator},
+};
+
+/// Computes the usage of every directory of a location, or of a directory of it, from the sizes of
+/// the files already indexed. The indexer and the file identifier keep it up to date afterwards.
+#[derive(Debug)]
+pub struct DiskUsageAnalyzer {
+	location: Arc<location::Data>,
+	location_path: Arc<PathBuf>,
+	sub_path: Option<PathBuf>,
+	/// Materialized path of the children of the analyzed directory, once resolved
+	root: Option<String>,
+
+	metadata: Metadata,
+
+	pending_tasks_on_resume: Vec<TaskHandle<Error>>,
+	tasks_for_shutdown: Vec<Box<dyn Task<Error>>>,
+}
+
+impl Hash for DiskUsageAnalyzer {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.location.id.hash(state);
+		if let Some(ref sub_path) = self.sub_path {
+			sub_path.hash(state);
+		}
+	}
+}
+
+impl Job for DiskUsageAnalyzer {
+	const NAME: JobName = JobName::DiskUsageAnalyzer;
+
+	async fn resume_tasks(
+		&mut self,
+		dispatcher: &JobTaskDispatcher,
+		ctx: &impl OuterContext,
+		SerializedTasks(serialized_tasks): SerializedTasks,
+	) -> Result<(), Error> {
+		self.pending_tasks_on_resume = dispatcher
+			.with_concurrency_key(location_concurrency_key(ctx.id(), self.location.id))
+			.dispatch_many_boxed(
+				rmp_serde::from_slice::<Vec<Vec<u8>>>(&serialized_tasks)
+					.map_err(disk_usage::Error::from)?
+					.into_iter()
+					.map(|task_bytes| async move {
+						Aggregator::deserialize(&task_bytes, Arc::clone(ctx.db()))
+							.await
+							.map(IntoTask::into_task)
+					})
+					.collect::<Vec<_>>()
+					.try_join()
+					.await
+					.map_err(disk_usage::Error::from)?,
+			)
+			.await;
+
+		Ok(())
+	}
+
+	async fn run<Ctx: OuterContext>(
+		mut self,
+		dispatcher: JobTaskDispatcher,
+		ctx: Ctx,
+	) -> Result<ReturnStatus, Error> {
+		let dispatcher =
+			dispatcher.with_concurrency_key(location_concurrency_key(ctx.id(), self.location.id));
+
+		let mut pending_running_tasks = FuturesUnordered::new();
+
+		self.init_or_resume(&mut pending_running_tasks, &ctx, &dispatcher)
+			.await?;
+
+		while let Some(task) = pending_running_tasks.next().await {
+			match task {
+				Ok(TaskStatus::Done((_, TaskOutput::Out(out)))) => {
+					let aggregator::Output {
+						aggregated_files,
+						aggregation_time,
+					} = *out
+						.downcast::<aggregator::Output>()
+						.expect("the disk usage analyzer job only dispatches aggregator tasks");
+
+					self.metadata.aggregated_files += aggregated_files;
+					self.metadata.aggregation_time += aggregation_time;
+				}
+
+				Ok(TaskStatus::Done((task_id, TaskOutput::Empty))) => {
+					warn!("Task <id='{task_id}'> returned an empty output");
+				}
+
+				Ok(TaskStatus::Shutdown(task)) => {
+					self.tasks_for_shutdown.push(task);
+				}
+
+				Ok(TaskStatus::Error(e)) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e);
+				}
+
+				Ok(TaskStatus::Canceled | TaskStatus::ForcedAbortion) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Ok(ReturnStatus::Canceled);
+				}
+
+				Err(e) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e.into());
+				}
+			}
+		}
+
+		if !self.tasks_for_shutdown.is_empty() {
+			return Ok(ReturnStatus::Shutdown(
+				SerializableJob::<Ctx>::serialize(self).await,
+			));
+		}
+
+		// The directories above the analyzed one hold its usage too
+		if let Some(parent) = self.root.as_deref().and_then(parent_of) {
+			disk_usage::recompute_directories(ctx.db(), self.location.id, [parent.to_string()])
+				.await?;
+		}
+
+		ctx.invalidate_query("locations.diskUsage");
+
+		Ok(ReturnStatus::Completed(
+			JobReturn::builder().with_metadata(self.metadata).build(),
+		))
+	}
+}
+
+impl DiskUsageAnalyzer {
+	pub fn new(
+		location: location::Data,
+		sub_path: Option<PathBuf>,
+	) -> Result<Self, disk_usage::Error> {
+		Ok(Self {
+			location_path: maybe_missing(&location.path, "location.path")
+				.map(PathBuf::from)
+				.map(Arc::new)?,
+			location: Arc::new(location),
+			sub_path,
+			root: None,
+			metadata: Metadata::default(),
+			pending_tasks_on_resume: Vec::new(),
+			tasks_for_shutdown: Vec::new(),
+		})
+	}
+
+	async fn init_or_resume(
+		&mut self,
+		pending_running_tasks: &mut FuturesUnordered<TaskHandle<Error>>,
+		ctx: &impl OuterContext,
+		dispatcher: &JobTaskDispatcher,
+	) -> Result<(), disk_usage::Error> {
+		// Resumed tasks were already dispatched by `resume_tasks`
+		if !self.pending_tasks_on_resume.is_empty() {
+			pending_running_tasks.extend(mem::take(&mut self.pending_tasks_on_resume));
+
+			return Ok(());
+		}
+
+		let db = ctx.db();
+		let location_id = self.location.id;
+
+		// The usage of a directory alone would leave its ancestors without the rest of the location
+		let root = if disk_usage::is_analyzed(db, location_id).await? {
+			maybe_get_iso_file_path_from_sub_path(
+				location_id,
+				&self.sub_path,
+				&*self.location_path,
+				db,
+			)
+			.await?
+			.map(|iso_file_path| {
+				iso_file_path
+					.materialized_path_for_children()
+					.expect("sub path iso_file_path must be a directory")
+			})
+		} else {
+			None
+		}
+		.unwrap_or_else(|| "/".to_string());
+
+		debug!("Analyzing disk usage of location {location_id} at directory \"{root}\"");
+
+		pending_running_tasks.push(
+			dispatcher
+				.dispatch(Aggregator::new(location_id, root.clone(), Arc::clone(db)))
+				.await,
+		);
+
+		ctx.progress(vec![ProgressUpdate::Message(format!(
+			"Analyzing disk usage under \"{root}\""
+		))]);
+
+		self.root = Some(root);
+
+		Ok(())
+	}
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Metadata {
+	aggregated_files: u64,
+	aggregation_time: Duration,
+}
+
+impl From<Metadata> for ReportOutputMetadata {
+	fn from(value: Metadata) -> Self {
+		Self::Metrics(HashMap::from([
+			("aggregated_files".into(), json!(value.aggregated_files)),
+			("aggregation_time".into(), json!(value.aggregation_time)),
+		]))
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+struct SaveState {
+	location: Arc<location::Data>,
+	location_path: Arc<PathBuf>,
+	sub_path: Option<PathBuf>,
+	root: Option<String>,
+
+	metadata: Metadata,
+
+	tasks_for_shutdown_bytes: Option<SerializedTasks>,
+}
+
+impl<Ctx: OuterContext> SerializableJob<Ctx> for DiskUsageAnalyzer {
+	async fn serialize(self) -> Result<Option<Vec<u8>>, rmp_serde::encode::Error> {
+		let Self {
+			location,
+			location_path,
+			sub_path,
+			root,
+			metadata,
+			tasks_for_shutdown,
+			..
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState {
+			location,
+			location_path,
+			sub_path,
+			root,
+			metadata,
+			tasks_for_shutdown_bytes: Some(SerializedTasks(rmp_serde::to_vec_named(
+				&tasks_for_shutdown
+					.into_iter()
+					.map(|task| async move {
+						task.downcast::<Aggregator>()
+							.expect("the disk usage analyzer job only dispatches aggregator tasks")
+							.serialize()
+							.await
+					})
+					.collect::<Vec<_>>()
+					.try_join()
+					.await?,
+			)?)),
+		})
+		.map(Some)
+	}
+
+	async fn deserialize(
+		serialized_job: &[u8],
+		_: &Ctx,
+	) -> Result<Option<(Self, Option<SerializedTasks>)>, rmp_serde::decode::Error> {
+		let SaveState {
+			location,
+			location_path,
+			sub_path,
+			root,
+			metadata,
+			tasks_for_shutdown_bytes,
+		} = rmp_serde::from_slice::<SaveState>(serialized_job)?;
+
+		Ok(Some((
+			Self {
+				location,
+				location_path,
+				sub_path,
+				root,
+				metadata,
+				pending_tasks_on_resume: Vec::new(),
+				tasks_for_shutdown: Vec::new(),
+			},
+			tasks_for_shutdown_bytes,
+		)))
+	}
+}
@@ -0,0 +1,634 @@
+use crate::file_identifier::not_in_archive;
+
+use sd_prisma::prisma::{directory_usage, file_path, location, PrismaClient};
+use sd_utils::db::{size_in_bytes_from_db, MissingFieldError};
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use prisma_client_rust::QueryError;
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+pub mod job;
+mod tasks;
+
+pub use job::DiskUsageAnalyzer;
+pub use tasks::aggregator;
+
+/// Levels of directories sent at once, the UI asking for more as it zooms into the treemap
+pub const DEFAULT_TREE_DEPTH: u8 = 2;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("missing field on database: {0}")]
+	MissingField(#[from] MissingFieldError),
+	#[error("database error: {0}")]
+	Database(#[from] QueryError),
+	#[error("failed to deserialized stored tasks for job resume: {0}")]
+	DeserializeTasks(#[from] rmp_serde::decode::Error),
+
+	#[error(transparent)]
+	SubPath(#[from] crate::utils::sub_path::Error),
+}
+
+impl From<Error> for rspc::Error {
+	fn from(err: Error) -> Self {
+		match err {
+			Error::SubPath(sub_path_err) => sub_path_err.into(),
+
+			_ => Self::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+/// Files under a directory, its subdirectories included
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Usage {
+	pub file_count: u64,
+	pub total_bytes: u64,
+	/// Of files with an object, the others not being identified yet
+	pub identified_file_count: u64,
+	pub identified_bytes: u64,
+}
+
+impl Usage {
+	fn add_file(&mut self, size: u64, identified: bool) {
+		self.file_count += 1;
+		self.total_bytes += size;
+		if identified {
+			self.identified_file_count += 1;
+			self.identified_bytes += size;
+		}
+	}
+
+	fn add(&mut self, other: &Self) {
+		self.file_count += other.file_count;
+		self.total_bytes += other.total_bytes;
+		self.identified_file_count += other.identified_file_count;
+		self.identified_bytes += other.identified_bytes;
+	}
+}
+
+/// A directory of a treemap, sizes being strings as they may not fit in a JS number
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct UsageNode {
+	/// Empty for the root of the location
+	pub name: String,
+	/// Materialized path of the children of the directory, to ask for the tree under it
+	pub path: String,
+	pub file_count: u32,
+	pub total_bytes: String,
+	/// Of files with an object, the others not being identified yet
+	pub identified_file_count: u32,
+	pub identified_bytes: String,
+	/// Biggest first, empty past the requested depth
+	pub children: Vec<UsageNode>,
+}
+
+/// The directory holding `path`, `None` for the root. Paths are materialized paths of the
+/// children of directories, like `/` and `/Photos/2024/`.
+pub(crate) fn parent_of(path: &str) -> Option<&str> {
+	let trimmed = path.strip_suffix('/')?;
+
+	trimmed.rfind('/').map(|idx| &path[..=idx])
+}
+
+fn name_of(path: &str) -> &str {
+	parent_of(path).map_or("", |parent| &path[parent.len()..path.len() - 1])
+}
+
+/// Usage of `root` and of every directory under it, from the `dirs` under it (as materialized
+/// paths of their children) and its `files` (as their materialized path, size and whether they're
+/// identified)
+pub(crate) fn rollup(
+	root: &str,
+	dirs: impl IntoIterator<Item = String>,
+	files: impl IntoIterator<Item = (String, u64, bool)>,
+) -> BTreeMap<String, Usage> {
+	let mut usages = dirs
+		.into_iter()
+		.chain([root.to_string()])
+		.map(|dir| (dir, Usage::default()))
+		.collect::<BTreeMap<_, _>>();
+
+	for (materialized_path, size, identified) in files {
+		let mut dir = Some(materialized_path.as_str());
+
+		while let Some(current) = dir.filter(|current| current.starts_with(root)) {
+			usages
+				.entry(current.to_string())
+				.or_default()
+				.add_file(size, identified);
+
+			dir = parent_of(current);
+		}
+	}
+
+	usages
+}
+
+/// The treemap under `root`, down to `depth` levels below it, from the usage of its directories
+pub(crate) fn tree(root: &str, usages: &BTreeMap<String, Usage>, depth: u8) -> UsageNode {
+	#[allow(clippy::cast_possible_truncation)]
+	// SAFETY: no directory holds billions of files
+	let node = |path: &str, usage: Usage, children| UsageNode {
+		name: name_of(path).to_string(),
+		path: path.to_string(),
+		file_count: usage.file_count as u32,
+		total_bytes: usage.total_bytes.to_string(),
+		identified_file_count: usage.identified_file_count as u32,
+		identified_bytes: usage.identified_bytes.to_string(),
+		children,
+	};
+
+	let mut children = if depth == 0 {
+		vec![]
+	} else {
+		usages
+			.range::<str, _>((std::ops::Bound::Excluded(root), std::ops::Bound::Unbounded))
+			.take_while(|(path, _)| path.starts_with(root))
+			.filter(|(path, _)| parent_of(path) == Some(root))
+			.map(|(path, _)| tree(path, usages, depth - 1))
+			.collect::<Vec<_>>()
+	};
+
+	children.sort_by_key(|child| {
+		std::cmp::Reverse(child.total_bytes.parse::<u64>().unwrap_or_default())
+	});
+
+	node(
+		root,
+		usages.get(root).copied().unwrap_or_default(),
+		children,
+	)
+}
+
+/// Computes the usage of `root` and every directory under it again from the index, replacing what
+/// was stored for them. Ancestors of `root` are left as they were.
+pub(crate) async fn recompute(
+	db: &PrismaClient,
+	location_id: location::id::Type,
+	root: &str,
+) -> Result<u64, Error> {
+	let file_paths = db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(Some(location_id)),
+			file_path::materialized_path::starts_with(root.to_string()),
+			not_in_archive(),
+		])
+		.select(file_path::select!({
+			materialized_path
+			name
+			is_dir
+			size_in_bytes_bytes
+			object_id
+		}))
+		.exec()
+		.await?;
+
+	let (dirs, files) = file_paths
+		.into_iter()
+		.filter_map(|file_path| {
+			Some((
+				file_path.materialized_path?,
+				file_path.name?,
+				file_path.is_dir.unwrap_or_default(),
+				file_path.size_in_bytes_bytes,
+				file_path.object_id.is_some(),
+			))
+		})
+		.partition::<Vec<_>, _>(|(_, _, is_dir, _, _)| *is_dir);
+
+	let usages = rollup(
+		root,
+		dirs.into_iter()
+			.map(|(materialized_path, name, ..)| format!("{materialized_path}{name}/")),
+		files
+			.into_iter()
+			.map(|(materialized_path, _, _, size, identified)| {
+				(
+					materialized_path,
+					size.as_deref().map_or(0, size_in_bytes_from_db),
+					identified,
+				)
+			}),
+	);
+
+	let files_count = usages.get(root).map_or(0, |usage| usage.file_count);
+
+	write(db, location_id, root, usages).await?;
+
+	Ok(files_count)
+}
+
+/// Computes the usage of `dir` from its own files and the stored usage of its subdirectories
+async fn recompute_directory(
+	db: &PrismaClient,
+	location_id: location::id::Type,
+	dir: &str,
+) -> Result<(), Error> {
+	let mut usage = Usage::default();
+	let mut children = vec![];
+
+	for file_path in db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(Some(location_id)),
+			file_path::materialized_path::equals(Some(dir.to_string())),
+			not_in_archive(),
+		])
+		.select(file_path::select!({ name is_dir size_in_bytes_bytes object_id }))
+		.exec()
+		.await?
+	{
+		if file_path.is_dir.unwrap_or_default() {
+			children.extend(file_path.name.map(|name| format!("{dir}{name}/")));
+		} else {
+			usage.add_file(
+				file_path
+					.size_in_bytes_bytes
+					.as_deref()
+					.map_or(0, size_in_bytes_from_db),
+				file_path.object_id.is_some(),
+			);
+		}
+	}
+
+	if !children.is_empty() {
+		for child in db
+			.directory_usage()
+			.find_many(vec![
+				directory_usage::location_id::equals(location_id),
+				directory_usage::path::in_vec(children),
+			])
+			.exec()
+			.await?
+		{
+			usage.add(&Usage::from(&child));
+		}
+	}
+
+	let params = usage_params(usage);
+
+	db.directory_usage()
+		.upsert(
+			directory_usage::location_id_path(location_id, dir.to_string()),
+			directory_usage::create_unchecked(location_id, dir.to_string(), params.clone()),
+			params,
+		)
+		.exec()
+		.await?;
+
+	Ok(())
+}
+
+async fn write(
+	db: &PrismaClient,
+	location_id: location::id::Type,
+	root: &str,
+	usages: BTreeMap<String, Usage>,
+) -> Result<(), Error> {
+	db._batch((
+		db.directory_usage().delete_many(vec![
+			directory_usage::location_id::equals(location_id),
+			directory_usage::path::starts_with(root.to_string()),
+		]),
+		usages
+			.into_iter()
+			.map(|(path, usage)| {
+				db.directory_usage()
+					.create_unchecked(location_id, path, usage_params(usage))
+			})
+			.collect::<Vec<_>>(),
+	))
+	.await?;
+
+	Ok(())
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+// SAFETY: counts and sizes fit in the database's integers
+fn usage_params(usage: Usage) -> Vec<directory_usage::SetParam> {
+	vec![
+		directory_usage::file_count::set(usage.file_count as i32),
+		directory_usage::total_bytes::set(usage.total_bytes as i64),
+		directory_usage::identified_file_count::set(usage.identified_file_count as i32),
+		directory_usage::identified_bytes::set(usage.identified_bytes as i64),
+	]
+}
+
+impl From<&directory_usage::Data> for Usage {
+	#[allow(clippy::cast_sign_loss)]
+	// SAFETY: only ever set from unsigned values
+	fn from(data: &directory_usage::Data) -> Self {
+		Self {
+			file_count: data.file_count as u64,
+			total_bytes: data.total_bytes as u64,
+			identified_file_count: data.identified_file_count as u64,
+			identified_bytes: data.identified_bytes as u64,
+		}
+	}
+}
+
+/// Roots not under any other root, as those are computed along with them
+fn outermost(roots: impl IntoIterator<Item = String>) -> Vec<String> {
+	let roots = roots.into_iter().collect::<BTreeSet<_>>();
+
+	roots
+		.iter()
+		.filter(|root| {
+			!roots
+				.iter()
+				.any(|other| other != *root && root.starts_with(other.as_str()))
+		})
+		.cloned()
+		.collect()
+}
+
+/// `dirs` along with all of their ancestors
+fn with_ancestors(dirs: impl IntoIterator<Item = String>) -> BTreeSet<String> {
+	let mut all = BTreeSet::new();
+
+	for dir in dirs {
+		let mut ancestor = parent_of(&dir).map(str::to_string);
+		while let Some(current) = ancestor {
+			ancestor = parent_of(&current).map(str::to_string);
+			all.insert(current);
+		}
+		all.insert(dir);
+	}
+
+	all
+}
+
+pub(crate) async fn is_analyzed(
+	db: &PrismaClient,
+	location_id: location::id::Type,
+) -> Result<bool, Error> {
+	db.directory_usage()
+		.find_first(vec![
+			directory_usage::location_id::equals(location_id),
+			directory_usage::path::equals("/".to_string()),
+		])
+		.exec()
+		.await
+		.map(|root| root.is_some())
+		.map_err(Into::into)
+}
+
+/// Computes `dirs` and their ancestors again from their own files and the stored usage of their
+/// subdirectories
+pub(crate) async fn recompute_directories(
+	db: &PrismaClient,
+	location_id: location::id::Type,
+	dirs: impl IntoIterator<Item = String>,
+) -> Result<(), Error> {
+	// A directory sorts after all of its ancestors, so going backwards each one sees the usage of its
+	// subdirectories already updated
+	for dir in with_ancestors(dirs).iter().rev() {
+		recompute_directory(db, location_id, dir).await?;
+	}
+
+	Ok(())
+}
+
+/// Brings the usage of the location up to date after the directories under `roots` were indexed
+/// again, by computing their subtrees again along with their ancestors, the rest of the location
+/// staying as it was. Does nothing for locations whose usage was never analyzed, see
+/// [`DiskUsageAnalyzer`].
+pub async fn refresh(
+	db: &PrismaClient,
+	location_id: location::id::Type,
+	roots: impl IntoIterator<Item = String>,
+) -> Result<(), Error> {
+	if !is_analyzed(db, location_id).await? {
+		return Ok(());
+	}
+
+	let roots = outermost(roots);
+
+	for root in &roots {
+		recompute(db, location_id, root).await?;
+	}
+
+	recompute_directories(
+		db,
+		location_id,
+		roots
+			.iter()
+			.filter_map(|root| parent_of(root))
+			.map(str::to_string),
+	)
+	.await
+}
+
+/// Like [`refresh`], for when only the files right inside `dirs` changed, their subdirectories
+/// being left as they were
+pub async fn refresh_directories(
+	db: &PrismaClient,
+	location_id: location::id::Type,
+	dirs: impl IntoIterator<Item = String>,
+) -> Result<(), Error> {
+	if !is_analyzed(db, location_id).await? {
+		return Ok(());
+	}
+
+	recompute_directories(db, location_id, dirs).await
+}
+
+/// Counters to be added to the identified usage of directories, accumulated for the files linked
+/// to objects or removed
+#[derive(Debug, Default)]
+pub(crate) struct IdentifiedDelta(BTreeMap<(location::id::Type, String), (i32, i64)>);
+
+impl IdentifiedDelta {
+	#[allow(clippy::cast_possible_wrap)] // SAFETY: no file is bigger than 8 EiB
+	pub(crate) fn record(
+		&mut self,
+		location_id: location::id::Type,
+		materialized_path: &str,
+		size: u64,
+	) {
+		for dir in with_ancestors([materialized_path.to_string()]) {
+			let (count, bytes) = self.0.entry((location_id, dir)).or_default();
+			*count += 1;
+			*bytes += size as i64;
+		}
+	}
+
+	pub(crate) fn subtract(&mut self, other: Self) {
+		for (key, (other_count, other_bytes)) in other.0 {
+			let (count, bytes) = self.0.entry(key).or_default();
+			*count -= other_count;
+			*bytes -= other_bytes;
+		}
+	}
+
+	/// Adds the accumulated counters to the stored usage, directories of locations never analyzed
+	/// having nothing to update
+	pub(crate) async fn commit(self, db: &PrismaClient) -> Result<(), QueryError> {
+		if self.0.is_empty() {
+			return Ok(());
+		}
+
+		db._batch(
+			self.0
+				.into_iter()
+				.filter(|(_, (count, bytes))| *count != 0 || *bytes != 0)
+				.map(|((location_id, path), (count, bytes))| {
+					db.directory_usage().update_many(
+						vec![
+							directory_usage::location_id::equals(location_id),
+							directory_usage::path::equals(path),
+						],
+						vec![
+							directory_usage::identified_file_count::increment(count),
+							directory_usage::identified_bytes::increment(bytes),
+						],
+					)
+				})
+				.collect::<Vec<_>>(),
+		)
+		.await?;
+
+		Ok(())
+	}
+}
+
+/// The treemap of the location under `path`, `None` if its usage was never analyzed
+pub async fn fetch(
+	db: &PrismaClient,
+	location_id: location::id::Type,
+	path: &str,
+	depth: u8,
+) -> Result<Option<UsageNode>, Error> {
+	let path_depth = path.matches('/').count();
+
+	let usages = db
+		.directory_usage()
+		.find_many(vec![
+			directory_usage::location_id::equals(location_id),
+			directory_usage::path::starts_with(path.to_string()),
+		])
+		.exec()
+		.await?
+		.into_iter()
+		.filter(|data| data.path.matches('/').count() <= path_depth + usize::from(depth))
+		.map(|data| {
+			let usage = Usage::from(&data);
+			(data.path, usage)
+		})
+		.collect::<BTreeMap<_, _>>();
+
+	Ok(usages
+		.contains_key(path)
+		.then(|| tree(path, &usages, depth)))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn usage(file_count: u64, total_bytes: u64, identified_bytes: u64) -> Usage {
+		Usage {
+			file_count,
+			total_bytes,
+			identified_file_count: u64::from(identified_bytes > 0),
+			identified_bytes,
+		}
+	}
+
+	#[test]
+	fn paths() {
+		assert_eq!(parent_of("/"), None);
+		assert_eq!(parent_of("/Photos/"), Some("/"));
+		assert_eq!(parent_of("/Photos/2024/"), Some("/Photos/"));
+		assert_eq!(name_of("/"), "");
+		assert_eq!(name_of("/Photos/2024/"), "2024");
+	}
+
+	#[test]
+	fn refreshed_directories() {
+		assert_eq!(
+			outermost([
+				"/Photos/2024/".to_string(),
+				"/Photos/".to_string(),
+				"/Music/".to_string(),
+			]),
+			["/Music/", "/Photos/"]
+		);
+
+		let dirs = with_ancestors(["/Photos/2024/".to_string(), "/Music/".to_string()]);
+		assert_eq!(
+			dirs.iter().rev().collect::<Vec<_>>(),
+			["/Photos/2024/", "/Photos/", "/Music/", "/"]
+		);
+	}
+
+	#[test]
+	fn rolls_files_up_to_the_root() {
+		let usages = rollup(
+			"/",
+			[
+				"/Photos/".to_string(),
+				"/Photos/2024/".to_string(),
+				"/Empty/".to_string(),
+			],
+			[
+				("/".to_string(), 10, false),
+				("/Photos/".to_string(), 100, true),
+				("/Photos/2024/".to_string(), 1000, false),
+			],
+		);
+
+		assert_eq!(usages["/"], usage(3, 1110, 100));
+		assert_eq!(usages["/Photos/"], usage(2, 1100, 100));
+		assert_eq!(usages["/Photos/2024/"], usage(1, 1000, 0));
+		assert_eq!(usages["/Empty/"], Usage::default());
+
+		// Computing a subtree leaves its ancestors out
+		let usages = rollup(
+			"/Photos/",
+			["/Photos/2024/".to_string()],
+			[
+				("/Photos/".to_string(), 100, true),
+				("/Photos/2024/".to_string(), 1000, false),
+			],
+		);
+
+		assert!(!usages.contains_key("/"));
+		assert_eq!(usages["/Photos/"], usage(2, 1100, 100));
+	}
+
+	#[test]
+	fn treemap_down_to_depth() {
+		let usages = rollup(
+			"/",
+			[
+				"/Small/".to_string(),
+				"/Big/".to_string(),
+				"/Big/Nested/".to_string(),
+				"/Big/Nested/Deeper/".to_string(),
+			],
+			[
+				("/Small/".to_string(), 1, false),
+				("/Big/Nested/Deeper/".to_string(), 50, true),
+			],
+		);
+
+		let root = tree("/", &usages, 2);
+
+		assert_eq!(root.total_bytes, "51");
+		assert_eq!(
+			root.children
+				.iter()
+				.map(|child| child.name.as_str())
+				.collect::<Vec<_>>(),
+			["Big", "Small"]
+		);
+		assert_eq!(root.children[0].children[0].path, "/Big/Nested/");
+		assert!(root.children[0].children[0].children.is_empty());
+	}
+}
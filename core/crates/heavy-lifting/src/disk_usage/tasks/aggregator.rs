@@ -0,0 +1,116 @@
+use crate::{disk_usage, Error};
+
+use sd_prisma::prisma::{location, PrismaClient};
+use sd_task_system::{ExecStatus, Interrupter, IntoAnyTaskOutput, SerializableTask, Task, TaskId};
+
+use std::{mem, sync::Arc, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+/// Rolls the sizes of the files under a directory of a location up into the usage of each
+/// directory, replacing what was stored for them
+#[derive(Debug)]
+pub struct Aggregator {
+	id: TaskId,
+	location_id: location::id::Type,
+	/// Materialized path of the children of the directory
+	root: String,
+	db: Arc<PrismaClient>,
+	output: Output,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Output {
+	pub aggregated_files: u64,
+	pub aggregation_time: Duration,
+}
+
+impl Aggregator {
+	#[must_use]
+	pub fn new(location_id: location::id::Type, root: String, db: Arc<PrismaClient>) -> Self {
+		Self {
+			id: TaskId::new_v4(),
+			location_id,
+			root,
+			db,
+			output: Output::default(),
+		}
+	}
+}
+
+#[async_trait::async_trait]
+impl Task<Error> for Aggregator {
+	fn id(&self) -> TaskId {
+		self.id
+	}
+
+	async fn run(&mut self, _: &Interrupter) -> Result<ExecStatus, Error> {
+		let Self {
+			location_id,
+			root,
+			db,
+			output: Output {
+				aggregated_files,
+				aggregation_time,
+			},
+			..
+		} = self;
+
+		let start = Instant::now();
+
+		*aggregated_files = disk_usage::recompute(db, *location_id, root).await?;
+		*aggregation_time = start.elapsed();
+
+		Ok(ExecStatus::Done(mem::take(&mut self.output).into_output()))
+	}
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SaveState {
+	id: TaskId,
+	location_id: location::id::Type,
+	root: String,
+}
+
+impl SerializableTask<Error> for Aggregator {
+	type SerializeError = rmp_serde::encode::Error;
+
+	type DeserializeError = rmp_serde::decode::Error;
+
+	type DeserializeCtx = Arc<PrismaClient>;
+
+	async fn serialize(self) -> Result<Vec<u8>, Self::SerializeError> {
+		let Self {
+			id,
+			location_id,
+			root,
+			..
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState {
+			id,
+			location_id,
+			root,
+		})
+	}
+
+	async fn deserialize(
+		data: &[u8],
+		db: Self::DeserializeCtx,
+	) -> Result<Self, Self::DeserializeError> {
+		rmp_serde::from_slice(data).map(
+			|SaveState {
+			     id,
+			     location_id,
+			     root,
+			 }| Self {
+				id,
+				location_id,
+				root,
+				db,
+				output: Output::default(),
+			},
+		)
+	}
+}
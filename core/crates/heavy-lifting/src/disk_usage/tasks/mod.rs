@@ -0,0 +1,3 @@
+pub mod aggregator;
+
+pub use aggregator::Aggregator;
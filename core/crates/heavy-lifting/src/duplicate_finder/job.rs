@@ -0,0 +1,225 @@
+use crate::{
+	duplicate_finder::{self, DuplicatesReport},
+	job_system::{
+		job::{Job, JobOutputData, JobReturn, JobTaskDispatcher, ReturnStatus},
+		report::ReportOutputMetadata,
+		utils::cancel_pending_tasks,
+		SerializableJob, SerializedTasks,
+	},
+	Error, JobName, OuterContext,
+};
+
+use sd_task_system::{
+	IntoTask, SerializableTask, Task, TaskDispatcher, TaskHandle, TaskOutput, TaskStatus,
+};
+
+use std::{
+	collections::HashMap,
+	hash::{Hash, Hasher},
+	mem,
+	sync::Arc,
+	time::Duration,
+};
+
+use futures::{stream::FuturesUnordered, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::warn;
+
+use super::tasks::{duplicates_grouper, DuplicatesGrouper};
+
+/// Finds objects linked to more than one file path across the library, reporting each group of
+/// duplicates and how many bytes could be reclaimed by keeping a single copy of each
+#[derive(Debug, Default)]
+pub struct DuplicateFinder {
+	metadata: Metadata,
+
+	pending_tasks_on_resume: Vec<TaskHandle<Error>>,
+	tasks_for_shutdown: Vec<Box<dyn Task<Error>>>,
+}
+
+impl Hash for DuplicateFinder {
+	// There is a single duplicate finder per library, so nothing tells two of them apart
+	fn hash<H: Hasher>(&self, _: &mut H) {}
+}
+
+impl Job for DuplicateFinder {
+	const NAME: JobName = JobName::DuplicateFinder;
+
+	async fn resume_tasks(
+		&mut self,
+		dispatcher: &JobTaskDispatcher,
+		ctx: &impl OuterContext,
+		SerializedTasks(serialized_tasks): SerializedTasks,
+	) -> Result<(), Error> {
+		let mut tasks = Vec::new();
+
+		for task_bytes in rmp_serde::from_slice::<Vec<Vec<u8>>>(&serialized_tasks)
+			.map_err(duplicate_finder::Error::from)?
+		{
+			tasks.push(
+				DuplicatesGrouper::deserialize(&task_bytes, Arc::clone(ctx.db()))
+					.await
+					.map(IntoTask::into_task)
+					.map_err(duplicate_finder::Error::from)?,
+			);
+		}
+
+		self.pending_tasks_on_resume = dispatcher.dispatch_many_boxed(tasks).await;
+
+		Ok(())
+	}
+
+	async fn run<Ctx: OuterContext>(
+		mut self,
+		dispatcher: JobTaskDispatcher,
+		ctx: Ctx,
+	) -> Result<ReturnStatus, Error> {
+		let mut pending_running_tasks = FuturesUnordered::new();
+
+		if self.pending_tasks_on_resume.is_empty() {
+			ctx.progress_msg("Searching for duplicates");
+
+			pending_running_tasks.push(
+				dispatcher
+					.dispatch(DuplicatesGrouper::new(Arc::clone(ctx.db())))
+					.await,
+			);
+		} else {
+			pending_running_tasks.extend(mem::take(&mut self.pending_tasks_on_resume));
+		}
+
+		while let Some(task) = pending_running_tasks.next().await {
+			match task {
+				Ok(TaskStatus::Done((_, TaskOutput::Out(out)))) => {
+					let duplicates_grouper::Output {
+						groups_count,
+						reclaimable_bytes,
+						grouping_time,
+					} = *out
+						.downcast::<duplicates_grouper::Output>()
+						.expect("the duplicate finder only dispatches duplicates grouper tasks");
+
+					self.metadata.grouping_time += grouping_time;
+					self.metadata.groups_count += groups_count;
+					self.metadata.total_reclaimable_bytes += reclaimable_bytes;
+				}
+
+				Ok(TaskStatus::Done((task_id, TaskOutput::Empty))) => {
+					warn!("Task <id='{task_id}'> returned an empty output");
+				}
+
+				Ok(TaskStatus::Shutdown(task)) => {
+					self.tasks_for_shutdown.push(task);
+				}
+
+				Ok(TaskStatus::Error(e)) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e);
+				}
+
+				Ok(TaskStatus::Canceled | TaskStatus::ForcedAbortion) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Ok(ReturnStatus::Canceled);
+				}
+
+				Err(e) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e.into());
+				}
+			}
+		}
+
+		if !self.tasks_for_shutdown.is_empty() {
+			return Ok(ReturnStatus::Shutdown(
+				SerializableJob::<Ctx>::serialize(self).await,
+			));
+		}
+
+		let report = DuplicatesReport {
+			groups_count: self.metadata.groups_count.to_string(),
+			total_reclaimable_bytes: self.metadata.total_reclaimable_bytes.to_string(),
+		};
+
+		Ok(ReturnStatus::Completed(
+			JobReturn::builder()
+				.with_data(JobOutputData::DuplicateFinder(report))
+				.with_metadata(self.metadata)
+				.build(),
+		))
+	}
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Metadata {
+	grouping_time: Duration,
+	groups_count: u64,
+	total_reclaimable_bytes: u64,
+}
+
+impl From<Metadata> for ReportOutputMetadata {
+	fn from(value: Metadata) -> Self {
+		Self::Metrics(HashMap::from([
+			("grouping_time".into(), json!(value.grouping_time)),
+			("groups_count".into(), json!(value.groups_count)),
+			(
+				"total_reclaimable_bytes".into(),
+				json!(value.total_reclaimable_bytes),
+			),
+		]))
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+struct SaveState {
+	metadata: Metadata,
+	tasks_for_shutdown_bytes: Option<SerializedTasks>,
+}
+
+impl<Ctx: OuterContext> SerializableJob<Ctx> for DuplicateFinder {
+	async fn serialize(self) -> Result<Option<Vec<u8>>, rmp_serde::encode::Error> {
+		let Self {
+			metadata,
+			tasks_for_shutdown,
+			..
+		} = self;
+
+		let mut tasks_bytes = Vec::with_capacity(tasks_for_shutdown.len());
+
+		for task in tasks_for_shutdown {
+			tasks_bytes.push(
+				task.downcast::<DuplicatesGrouper>()
+					.expect("the duplicate finder only dispatches duplicates grouper tasks")
+					.serialize()
+					.await?,
+			);
+		}
+
+		rmp_serde::to_vec_named(&SaveState {
+			metadata,
+			tasks_for_shutdown_bytes: Some(SerializedTasks(rmp_serde::to_vec_named(&tasks_bytes)?)),
+		})
+		.map(Some)
+	}
+
+	async fn deserialize(
+		serialized_job: &[u8],
+		_: &Ctx,
+	) -> Result<Option<(Self, Option<SerializedTasks>)>, rmp_serde::decode::Error> {
+		let SaveState {
+			metadata,
+			tasks_for_shutdown_bytes,
+		} = rmp_serde::from_slice::<SaveState>(serialized_job)?;
+
+		Ok(Some((
+			Self {
+				metadata,
+				..Default::default()
+			},
+			tasks_for_shutdown_bytes,
+		)))
+	}
+}
@@ -0,0 +1,315 @@
+use crate::{file_identifier, media_processor};
+
+use sd_core_prisma_helpers::file_path_for_duplicate_finder;
+
+use sd_prisma::prisma::{
+	content_fingerprint, file_path, object, perceptual_hash, PrismaClient, SortOrder,
+};
+use sd_utils::db::size_in_bytes_from_db;
+
+use std::collections::BTreeMap;
+
+use prisma_client_rust::{raw, PrismaValue, QueryError};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+pub mod job;
+mod tasks;
+
+pub use job::DuplicateFinder;
+
+/// How many objects with duplicates we fetch from the database at once
+pub const PAGE_SIZE: usize = 100;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("database error: {0}")]
+	Database(#[from] QueryError),
+	#[error("failed to deserialized stored tasks for job resume: {0}")]
+	DeserializeTasks(#[from] rmp_serde::decode::Error),
+}
+
+impl From<Error> for rspc::Error {
+	fn from(e: Error) -> Self {
+		Self::with_cause(rspc::ErrorCode::InternalServerError, e.to_string(), e)
+	}
+}
+
+/// File paths linked to the same object, so with the same content, across all locations
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct DuplicateGroup {
+	pub object_id: object::id::Type,
+	pub cas_id: Option<String>,
+	/// Size of a single copy, as a string as it may not fit in a JS number
+	pub size_in_bytes: String,
+	/// Sum of the bytes each copy takes on disk on its own, without what it shares with
+	/// copy-on-write clones
+	pub physical_size: String,
+	/// Bytes freed by keeping a single copy
+	pub reclaimable_bytes: String,
+	pub file_paths: Vec<file_path_for_duplicate_finder::Data>,
+}
+
+/// Totals of a duplicate finder run. A library can have too many duplicates to keep them around,
+/// so the groups themselves are paged with [`fetch_duplicates_page`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Type)]
+pub struct DuplicatesReport {
+	pub groups_count: String,
+	pub total_reclaimable_bytes: String,
+}
+
+/// Objects that look or sound alike, even if their contents differ, like resized images or
+/// re-encoded videos
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct SimilarGroup {
+	pub object_ids: Vec<object::id::Type>,
+	pub file_paths: Vec<file_path_for_duplicate_finder::Data>,
+}
+
+#[derive(Debug)]
+pub struct DuplicatesPage {
+	pub groups: Vec<DuplicateGroup>,
+	pub reclaimable_bytes: u64,
+	/// Object id to continue from, `None` when there are no more pages
+	pub next_cursor: Option<object::id::Type>,
+}
+
+/// Fetches up to `take` groups of duplicates, for objects with ids greater than `cursor`
+pub async fn fetch_duplicates_page(
+	db: &PrismaClient,
+	cursor: Option<object::id::Type>,
+	take: usize,
+) -> Result<DuplicatesPage, Error> {
+	#[derive(Deserialize)]
+	struct ObjectWithDuplicates {
+		object_id: object::id::Type,
+	}
+
+	#[allow(clippy::cast_possible_wrap)]
+	// SAFETY: we know that `take` is a small page size
+	let object_ids = db
+		._query_raw::<ObjectWithDuplicates>(raw!(
+			"SELECT object_id
+			FROM file_path
+			WHERE
+				object_id IS NOT NULL
+				AND object_id > {}
+				AND is_dir = FALSE
+			GROUP BY object_id
+			HAVING COUNT(*) > 1
+			ORDER BY object_id ASC
+			LIMIT {}",
+			PrismaValue::Int(i64::from(cursor.unwrap_or_default())),
+			PrismaValue::Int(take as i64)
+		))
+		.exec()
+		.await?
+		.into_iter()
+		.map(|ObjectWithDuplicates { object_id }| object_id)
+		.collect::<Vec<_>>();
+
+	let next_cursor = (object_ids.len() == take)
+		.then(|| object_ids.last().copied())
+		.flatten();
+
+	let mut file_paths_by_object_id = BTreeMap::<_, Vec<_>>::new();
+
+	for file_path in db
+		.file_path()
+		.find_many(vec![
+			file_path::object_id::in_vec(object_ids),
+			file_path::is_dir::equals(Some(false)),
+			// Archive entries can't be removed on their own, so they're never reclaimable
+			file_identifier::not_in_archive(),
+		])
+		.order_by(file_path::id::order(SortOrder::Asc))
+		.select(file_path_for_duplicate_finder::select())
+		.exec()
+		.await?
+	{
+		if let Some(object_id) = file_path.object_id {
+			file_paths_by_object_id
+				.entry(object_id)
+				.or_default()
+				.push(file_path);
+		}
+	}
+
+	let mut reclaimable_bytes = 0;
+
+	let groups = file_paths_by_object_id
+		.into_iter()
+		.map(|(object_id, file_paths)| {
+			let size_in_bytes = file_paths
+				.iter()
+				.find_map(|file_path| file_path.size_in_bytes_bytes.as_deref())
+				.map_or(0, size_in_bytes_from_db);
+
+			let (physical_size, largest_physical_size) = group_physical_size(
+				size_in_bytes,
+				file_paths.iter().map(|file_path| {
+					file_path
+						.physical_size_bytes
+						.as_deref()
+						.map(size_in_bytes_from_db)
+				}),
+			);
+
+			// Keeping the copy with the most bytes of its own frees the most
+			let group_reclaimable_bytes = physical_size - largest_physical_size;
+			reclaimable_bytes += group_reclaimable_bytes;
+
+			DuplicateGroup {
+				object_id,
+				cas_id: file_paths
+					.iter()
+					.find_map(|file_path| file_path.cas_id.clone()),
+				size_in_bytes: size_in_bytes.to_string(),
+				physical_size: physical_size.to_string(),
+				reclaimable_bytes: group_reclaimable_bytes.to_string(),
+				file_paths,
+			}
+		})
+		.collect();
+
+	Ok(DuplicatesPage {
+		groups,
+		reclaimable_bytes,
+		next_cursor,
+	})
+}
+
+/// Groups objects whose perceptual hashes are within `threshold` bits of each other, biggest groups
+/// first. Only objects hashed by the media processor are taken into account.
+pub async fn fetch_similar_groups(
+	db: &PrismaClient,
+	threshold: u32,
+) -> Result<Vec<SimilarGroup>, Error> {
+	let hashes = db
+		.perceptual_hash()
+		.find_many(vec![])
+		.select(perceptual_hash::select!({ object_id hash }))
+		.exec()
+		.await?
+		.into_iter()
+		.filter_map(|data| {
+			media_processor::perceptual_hash_from_bytes(&data.hash)
+				.map(|hash| (data.object_id, hash))
+		})
+		.collect::<Vec<_>>();
+
+	similar_groups_with_file_paths(
+		media_processor::group_similar(
+			&hashes,
+			threshold.min(media_processor::MAX_SIMILARITY_THRESHOLD),
+		),
+		db,
+	)
+	.await
+}
+
+/// Groups audio and video objects whose content fingerprints match, so different encodes of the
+/// same song, movie or episode end up together, biggest groups first. Only objects fingerprinted by
+/// the media processor are taken into account.
+pub async fn fetch_similar_content_groups(db: &PrismaClient) -> Result<Vec<SimilarGroup>, Error> {
+	let fingerprints = db
+		.content_fingerprint()
+		.find_many(vec![])
+		.select(content_fingerprint::select!({ object_id kind fingerprint }))
+		.exec()
+		.await?
+		.into_iter()
+		.filter_map(|data| {
+			media_processor::ContentFingerprintKind::try_from(data.kind)
+				.ok()
+				.and_then(|kind| {
+					media_processor::ContentFingerprint::from_bytes(kind, &data.fingerprint)
+				})
+				.map(|fingerprint| (data.object_id, fingerprint))
+		})
+		.collect::<Vec<_>>();
+
+	similar_groups_with_file_paths(media_processor::group_similar_content(&fingerprints), db).await
+}
+
+async fn similar_groups_with_file_paths(
+	mut groups: Vec<Vec<object::id::Type>>,
+	db: &PrismaClient,
+) -> Result<Vec<SimilarGroup>, Error> {
+	groups.sort_unstable_by(|a, b| b.len().cmp(&a.len()));
+
+	let object_ids = groups.iter().flatten().copied().collect::<Vec<_>>();
+	let mut file_paths_by_object_id = BTreeMap::<_, Vec<_>>::new();
+
+	// A whole library can be in these groups, so we don't hit SQLite's variables limit
+	for object_ids in object_ids.chunks(PAGE_SIZE * 10) {
+		for file_path in db
+			.file_path()
+			.find_many(vec![
+				file_path::object_id::in_vec(object_ids.to_vec()),
+				file_path::is_dir::equals(Some(false)),
+			])
+			.order_by(file_path::id::order(SortOrder::Asc))
+			.select(file_path_for_duplicate_finder::select())
+			.exec()
+			.await?
+		{
+			if let Some(object_id) = file_path.object_id {
+				file_paths_by_object_id
+					.entry(object_id)
+					.or_default()
+					.push(file_path);
+			}
+		}
+	}
+
+	Ok(groups
+		.into_iter()
+		.map(|mut object_ids| {
+			object_ids.sort_unstable();
+
+			SimilarGroup {
+				file_paths: object_ids
+					.iter()
+					.filter_map(|object_id| file_paths_by_object_id.remove(object_id))
+					.flatten()
+					.collect(),
+				object_ids,
+			}
+		})
+		.collect())
+}
+
+/// Sums the physical sizes of the copies of an object, along with the largest of them. Bytes
+/// shared through copy-on-write clones aren't counted, as deleting copies doesn't free them.
+/// Copies without a physical size were identified on a file system that can't clone files, so
+/// they take their whole size.
+fn group_physical_size(
+	size_in_bytes: u64,
+	physical_sizes: impl IntoIterator<Item = Option<u64>>,
+) -> (u64, u64) {
+	physical_sizes
+		.into_iter()
+		.map(|physical_size| physical_size.map_or(size_in_bytes, |size| size.min(size_in_bytes)))
+		.fold((0, 0), |(total, largest), physical_size| {
+			(total + physical_size, largest.max(physical_size))
+		})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn physical_size_of_groups() {
+		// Plain copies
+		assert_eq!(group_physical_size(10, [None, None, None]), (30, 10));
+		// Clones sharing all their storage
+		assert_eq!(group_physical_size(10, [Some(0), Some(0)]), (0, 0));
+		// A clone modified after cloning, along with a plain copy
+		assert_eq!(group_physical_size(10, [Some(4), Some(0), None]), (14, 10));
+		// Physical sizes can't be bigger than the files
+		assert_eq!(group_physical_size(10, [Some(12), Some(3)]), (13, 10));
+		assert_eq!(group_physical_size(10, []), (0, 0));
+	}
+}
@@ -0,0 +1,127 @@
+use crate::{
+	duplicate_finder::{fetch_duplicates_page, DuplicatesPage, PAGE_SIZE},
+	Error,
+};
+
+use sd_prisma::prisma::{object, PrismaClient};
+use sd_task_system::{
+	check_interruption, ExecStatus, Interrupter, IntoAnyTaskOutput, SerializableTask, Task, TaskId,
+};
+
+use std::{mem, sync::Arc, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+use tracing::trace;
+
+/// Walks every object with more than one file path, page by page, counting their groups and the
+/// bytes they take. Only a page of groups is held at a time.
+#[derive(Debug)]
+pub struct DuplicatesGrouper {
+	id: TaskId,
+	db: Arc<PrismaClient>,
+	cursor: Option<object::id::Type>,
+	output: Output,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SaveState {
+	id: TaskId,
+	cursor: Option<object::id::Type>,
+	output: Output,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Output {
+	#[serde(default)]
+	pub groups_count: u64,
+	pub reclaimable_bytes: u64,
+	pub grouping_time: Duration,
+}
+
+impl DuplicatesGrouper {
+	#[must_use]
+	pub fn new(db: Arc<PrismaClient>) -> Self {
+		Self {
+			id: TaskId::new_v4(),
+			db,
+			cursor: None,
+			output: Output::default(),
+		}
+	}
+}
+
+#[async_trait::async_trait]
+impl Task<Error> for DuplicatesGrouper {
+	fn id(&self) -> TaskId {
+		self.id
+	}
+
+	async fn run(&mut self, interrupter: &Interrupter) -> Result<ExecStatus, Error> {
+		let Self {
+			db,
+			cursor,
+			output: Output {
+				groups_count,
+				reclaimable_bytes,
+				grouping_time,
+			},
+			..
+		} = self;
+
+		let start = Instant::now();
+
+		loop {
+			let DuplicatesPage {
+				groups: page_groups,
+				reclaimable_bytes: page_reclaimable_bytes,
+				next_cursor,
+			} = fetch_duplicates_page(db, *cursor, PAGE_SIZE).await?;
+
+			trace!("Found {} groups of duplicates", page_groups.len());
+
+			*groups_count += page_groups.len() as u64;
+			*reclaimable_bytes += page_reclaimable_bytes;
+
+			let Some(next_cursor) = next_cursor else {
+				break;
+			};
+
+			*cursor = Some(next_cursor);
+
+			check_interruption!(interrupter, start, grouping_time);
+		}
+
+		*grouping_time += start.elapsed();
+
+		Ok(ExecStatus::Done(mem::take(&mut self.output).into_output()))
+	}
+}
+
+impl SerializableTask<Error> for DuplicatesGrouper {
+	type SerializeError = rmp_serde::encode::Error;
+
+	type DeserializeError = rmp_serde::decode::Error;
+
+	type DeserializeCtx = Arc<PrismaClient>;
+
+	async fn serialize(self) -> Result<Vec<u8>, Self::SerializeError> {
+		let Self {
+			id, cursor, output, ..
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState { id, cursor, output })
+	}
+
+	async fn deserialize(
+		data: &[u8],
+		db: Self::DeserializeCtx,
+	) -> Result<Self, Self::DeserializeError> {
+		rmp_serde::from_slice(data).map(|SaveState { id, cursor, output }| Self {
+			id,
+			db,
+			cursor,
+			output,
+		})
+	}
+}
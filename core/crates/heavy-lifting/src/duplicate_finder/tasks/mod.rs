@@ -0,0 +1,3 @@
+pub mod duplicates_grouper;
+
+pub use duplicates_grouper::DuplicatesGrouper;
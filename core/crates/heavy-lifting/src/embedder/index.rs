@@ -0,0 +1,222 @@
+use crate::media_processor::{embedding_from_bytes, embedding_similarity};
+
+use sd_prisma::prisma::{object, object_embedding, PrismaClient};
+use sd_utils::error::FileIOError;
+
+use std::path::{Path, PathBuf};
+
+use instant_distance::{Builder, HnswMap, Point, Search};
+use serde::{Deserialize, Serialize};
+use tokio::{fs, io, task::spawn_blocking};
+use uuid::Uuid;
+
+use super::Error;
+
+/// Directory inside the data directory where the index of each library is kept
+const INDEXES_DIR_NAME: &str = "embeddings";
+
+/// Candidates kept while walking the graph, searches never return more results than this
+pub const MAX_RESULTS: usize = 200;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Embedding(Vec<f32>);
+
+impl Point for Embedding {
+	fn distance(&self, other: &Self) -> f32 {
+		// Embeddings are L2 normalized, so this is the cosine distance
+		1.0 - embedding_similarity(&self.0, &other.0)
+	}
+}
+
+/// Approximate nearest neighbours index (HNSW) over the embeddings of a library, so finding the
+/// objects closest to a query doesn't mean comparing it with every embedding.
+///
+/// Embeddings are kept in the database, the index is built from them and can always be rebuilt.
+#[derive(Serialize, Deserialize)]
+pub struct EmbeddingIndex {
+	model: String,
+	map: HnswMap<Embedding, object::id::Type>,
+}
+
+impl EmbeddingIndex {
+	#[must_use]
+	pub fn new(model: String, embeddings: Vec<(object::id::Type, Vec<f32>)>) -> Self {
+		let (values, points) = embeddings
+			.into_iter()
+			.map(|(object_id, embedding)| (object_id, Embedding(embedding)))
+			.unzip();
+
+		Self {
+			model,
+			map: Builder::default()
+				.ef_search(MAX_RESULTS)
+				// Same embeddings always give the same graph
+				.seed(0)
+				.build(points, values),
+		}
+	}
+
+	/// Builds the index from the embeddings of `model` in the database
+	pub async fn build(model: &str, db: &PrismaClient) -> Result<Self, Error> {
+		let embeddings = db
+			.object_embedding()
+			.find_many(vec![object_embedding::model::equals(model.to_string())])
+			.select(object_embedding::select!({ object_id embedding }))
+			.exec()
+			.await?
+			.into_iter()
+			.map(|data| (data.object_id, embedding_from_bytes(&data.embedding)))
+			.collect::<Vec<_>>();
+
+		let model = model.to_string();
+
+		spawn_blocking(move || Self::new(model, embeddings))
+			.await
+			.map_err(|e| Error::PanicWhileBuildingIndex(e.to_string()))
+	}
+
+	/// Model of the embeddings in the index, queries must be embedded by the same one
+	#[must_use]
+	pub fn model(&self) -> &str {
+		&self.model
+	}
+
+	#[must_use]
+	pub fn len(&self) -> usize {
+		self.map.values.len()
+	}
+
+	#[must_use]
+	pub fn is_empty(&self) -> bool {
+		self.map.values.is_empty()
+	}
+
+	/// Objects closest to the query with their cosine similarity to it, most similar first
+	#[must_use]
+	pub fn search(&self, query: &[f32], take: usize) -> Vec<(object::id::Type, f32)> {
+		if self.is_empty() {
+			return vec![];
+		}
+
+		let mut search = Search::default();
+
+		self.map
+			.search(&Embedding(query.to_vec()), &mut search)
+			.take(take)
+			.map(|item| (*item.value, 1.0 - item.distance))
+			.collect()
+	}
+
+	/// Loads the index saved at `path`, if there is one
+	pub async fn load(path: impl AsRef<Path>) -> Result<Option<Self>, Error> {
+		let path = path.as_ref();
+
+		match fs::read(path).await {
+			Ok(bytes) => rmp_serde::from_slice(&bytes)
+				.map(Some)
+				.map_err(Error::DecodeIndex),
+			Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+			Err(e) => {
+				Err(FileIOError::from((path, e, "Failed to read the embeddings index")).into())
+			}
+		}
+	}
+
+	pub async fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+		let path = path.as_ref();
+
+		if let Some(parent) = path.parent() {
+			fs::create_dir_all(parent).await.map_err(|e| {
+				FileIOError::from((parent, e, "Failed to create embeddings index directory"))
+			})?;
+		}
+
+		// Written aside and renamed when complete, so searches never read a partial index
+		let part_path = path.with_extension("part");
+
+		fs::write(&part_path, rmp_serde::to_vec(self)?)
+			.await
+			.map_err(|e| {
+				FileIOError::from((&part_path, e, "Failed to write the embeddings index"))
+			})?;
+
+		fs::rename(&part_path, path)
+			.await
+			.map_err(|e| FileIOError::from((path, e, "Failed to move the embeddings index")))?;
+
+		Ok(())
+	}
+}
+
+impl std::fmt::Debug for EmbeddingIndex {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("EmbeddingIndex")
+			.field("model", &self.model)
+			.field("len", &self.len())
+			.finish_non_exhaustive()
+	}
+}
+
+/// Where the index of a library is saved
+#[must_use]
+pub fn index_path(data_dir: impl AsRef<Path>, library_id: Uuid) -> PathBuf {
+	data_dir
+		.as_ref()
+		.join(INDEXES_DIR_NAME)
+		.join(format!("{library_id}.hnsw"))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn unit(values: &[f32]) -> Vec<f32> {
+		let norm = values.iter().map(|v| v * v).sum::<f32>().sqrt();
+		values.iter().map(|v| v / norm).collect()
+	}
+
+	#[test]
+	fn closest_objects_come_first() {
+		let index = EmbeddingIndex::new(
+			"test".to_string(),
+			vec![
+				(1, unit(&[1.0, 0.0, 0.0])),
+				(2, unit(&[0.0, 1.0, 0.0])),
+				(3, unit(&[0.9, 0.1, 0.0])),
+				(4, unit(&[0.0, 0.0, 1.0])),
+			],
+		);
+
+		let results = index.search(&unit(&[1.0, 0.05, 0.0]), 2);
+
+		assert_eq!(
+			results.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+			vec![1, 3]
+		);
+		assert!(results[0].1 > 0.99);
+	}
+
+	#[test]
+	fn empty_index_finds_nothing() {
+		let index = EmbeddingIndex::new("test".to_string(), vec![]);
+
+		assert!(index.is_empty());
+		assert!(index.search(&unit(&[1.0, 0.0]), 10).is_empty());
+	}
+
+	#[test]
+	fn survives_a_round_trip() {
+		let index = EmbeddingIndex::new(
+			"test".to_string(),
+			vec![(7, unit(&[0.3, 0.4])), (8, unit(&[0.4, -0.3]))],
+		);
+
+		let index = rmp_serde::from_slice::<EmbeddingIndex>(
+			&rmp_serde::to_vec(&index).expect("index serializes"),
+		)
+		.expect("index deserializes");
+
+		assert_eq!(index.model(), "test");
+		assert_eq!(index.search(&unit(&[0.3, 0.4]), 1)[0].0, 7);
+	}
+}
@@ -0,0 +1,509 @@
+use crate::{
+	embedder,
+	job_system::{
+		job::{Job, JobReturn, JobTaskDispatcher, ReturnStatus},
+		report::ReportOutputMetadata,
+		utils::{cancel_pending_tasks, location_concurrency_key},
+		SerializableJob, SerializedTasks,
+	},
+	media_processor::THUMBNAIL_CACHE_DIR_NAME,
+	utils::sub_path::{self, maybe_get_iso_file_path_from_sub_path},
+	Error, JobName, NonCriticalError, OuterContext, ProgressUpdate,
+};
+
+use sd_core_file_path_helper::IsolatedFilePathData;
+use sd_core_prisma_helpers::file_path_for_media_processor;
+
+use sd_prisma::prisma::{location, PrismaClient};
+use sd_task_system::{
+	IntoTask, SerializableTask, Task, TaskDispatcher, TaskHandle, TaskOutput, TaskStatus,
+};
+use sd_utils::db::maybe_missing;
+
+use std::{
+	collections::HashMap,
+	hash::{Hash, Hasher},
+	mem,
+	path::PathBuf,
+	sync::Arc,
+	time::Duration,
+};
+
+use futures::{stream::FuturesUnordered, StreamExt};
+use futures_concurrency::future::TryJoin;
+use itertools::Itertools;
+use prisma_client_rust::{raw, PrismaValue};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::{fs, time::Instant};
+use tracing::{debug, warn};
+
+use super::{
+	index_path,
+	tasks::{encoder, Encoder},
+	EmbeddingIndex, ImageEmbedder, AVAILABLE_EXTENSIONS, BATCH_SIZE, CLIP_MODEL_ID,
+};
+
+/// Embeds the images of a location, or of a directory of it, in the same space as text, then
+/// rebuilds the embeddings index of the library so they can be found by describing them
+#[derive(Debug)]
+pub struct Embedder {
+	location: Arc<location::Data>,
+	location_path: Arc<PathBuf>,
+	sub_path: Option<PathBuf>,
+	regenerate: bool,
+
+	// Loaded when the job runs, as a model can't be serialized
+	model: Option<Arc<ImageEmbedder>>,
+
+	metadata: Metadata,
+
+	errors: Vec<NonCriticalError>,
+
+	pending_tasks_on_resume: Vec<TaskHandle<Error>>,
+	tasks_for_shutdown: Vec<Box<dyn Task<Error>>>,
+}
+
+impl Hash for Embedder {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.location.id.hash(state);
+		if let Some(ref sub_path) = self.sub_path {
+			sub_path.hash(state);
+		}
+	}
+}
+
+impl Job for Embedder {
+	const NAME: JobName = JobName::Embedder;
+
+	async fn resume_tasks(
+		&mut self,
+		dispatcher: &JobTaskDispatcher,
+		ctx: &impl OuterContext,
+		SerializedTasks(serialized_tasks): SerializedTasks,
+	) -> Result<(), Error> {
+		let model = self.load_model(ctx).await?;
+
+		self.pending_tasks_on_resume = dispatcher
+			.with_concurrency_key(location_concurrency_key(ctx.id(), self.location.id))
+			.dispatch_many_boxed(
+				rmp_serde::from_slice::<Vec<Vec<u8>>>(&serialized_tasks)
+					.map_err(embedder::Error::from)?
+					.into_iter()
+					.map(|task_bytes| {
+						let model = Arc::clone(&model);
+						async move {
+							Encoder::deserialize(&task_bytes, (model, Arc::clone(ctx.db())))
+								.await
+								.map(IntoTask::into_task)
+						}
+					})
+					.collect::<Vec<_>>()
+					.try_join()
+					.await
+					.map_err(embedder::Error::from)?,
+			)
+			.await;
+
+		Ok(())
+	}
+
+	async fn run<Ctx: OuterContext>(
+		mut self,
+		dispatcher: JobTaskDispatcher,
+		ctx: Ctx,
+	) -> Result<ReturnStatus, Error> {
+		let dispatcher =
+			dispatcher.with_concurrency_key(location_concurrency_key(ctx.id(), self.location.id));
+
+		let mut pending_running_tasks = FuturesUnordered::new();
+
+		self.init_or_resume(&mut pending_running_tasks, &ctx, &dispatcher)
+			.await?;
+
+		while let Some(task) = pending_running_tasks.next().await {
+			match task {
+				Ok(TaskStatus::Done((_, TaskOutput::Out(out)))) => {
+					self.process_encoder_output(
+						*out.downcast::<encoder::Output>()
+							.expect("the embedder job only dispatches encoder tasks"),
+						&ctx,
+					);
+				}
+
+				Ok(TaskStatus::Done((task_id, TaskOutput::Empty))) => {
+					warn!("Task <id='{task_id}'> returned an empty output");
+				}
+
+				Ok(TaskStatus::Shutdown(task)) => {
+					self.tasks_for_shutdown.push(task);
+				}
+
+				Ok(TaskStatus::Error(e)) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e);
+				}
+
+				Ok(TaskStatus::Canceled | TaskStatus::ForcedAbortion) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Ok(ReturnStatus::Canceled);
+				}
+
+				Err(e) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e.into());
+				}
+			}
+		}
+
+		if !self.tasks_for_shutdown.is_empty() {
+			return Ok(ReturnStatus::Shutdown(
+				SerializableJob::<Ctx>::serialize(self).await,
+			));
+		}
+
+		self.update_index(&ctx).await?;
+
+		let Self {
+			metadata, errors, ..
+		} = self;
+
+		Ok(ReturnStatus::Completed(
+			JobReturn::builder()
+				.with_metadata(metadata)
+				.with_non_critical_errors(errors)
+				.build(),
+		))
+	}
+}
+
+impl Embedder {
+	pub fn new(
+		location: location::Data,
+		sub_path: Option<PathBuf>,
+	) -> Result<Self, embedder::Error> {
+		Ok(Self {
+			location_path: maybe_missing(&location.path, "location.path")
+				.map(PathBuf::from)
+				.map(Arc::new)?,
+			location: Arc::new(location),
+			sub_path,
+			regenerate: false,
+			model: None,
+			metadata: Metadata::default(),
+			errors: Vec::new(),
+			pending_tasks_on_resume: Vec::new(),
+			tasks_for_shutdown: Vec::new(),
+		})
+	}
+
+	/// Embeds again the objects that already have an embedding of their current content
+	#[must_use]
+	pub const fn with_regenerate(mut self, regenerate: bool) -> Self {
+		self.regenerate = regenerate;
+		self
+	}
+
+	async fn load_model(
+		&mut self,
+		ctx: &impl OuterContext,
+	) -> Result<Arc<ImageEmbedder>, embedder::Error> {
+		if let Some(model) = &self.model {
+			return Ok(Arc::clone(model));
+		}
+
+		let model = Arc::new(ImageEmbedder::load(ctx.get_data_directory()).await?);
+
+		self.model = Some(Arc::clone(&model));
+
+		Ok(model)
+	}
+
+	async fn init_or_resume(
+		&mut self,
+		pending_running_tasks: &mut FuturesUnordered<TaskHandle<Error>>,
+		ctx: &impl OuterContext,
+		dispatcher: &JobTaskDispatcher,
+	) -> Result<(), embedder::Error> {
+		// Resumed tasks were already dispatched by `resume_tasks`
+		if !self.pending_tasks_on_resume.is_empty() {
+			pending_running_tasks.extend(mem::take(&mut self.pending_tasks_on_resume));
+
+			return Ok(());
+		}
+
+		let db = ctx.db();
+		let location_id = self.location.id;
+		let location_path = &*self.location_path;
+
+		let iso_file_path =
+			maybe_get_iso_file_path_from_sub_path(location_id, &self.sub_path, location_path, db)
+				.await?
+				.map_or_else(
+					|| {
+						IsolatedFilePathData::new(location_id, location_path, location_path, true)
+							.map_err(sub_path::Error::from)
+					},
+					Ok,
+				)?;
+
+		let model = self.load_model(ctx).await?;
+
+		debug!(
+			"Embedding images in location {location_id} at directory \"{iso_file_path}\" with {CLIP_MODEL_ID}"
+		);
+
+		let file_paths = get_files_to_embed(db, &iso_file_path, self.regenerate).await?;
+
+		self.metadata.total_files = file_paths.len() as u64;
+
+		let thumbnails_directory_path =
+			Arc::new(ctx.get_data_directory().join(THUMBNAIL_CACHE_DIR_NAME));
+
+		pending_running_tasks.extend(
+			dispatcher
+				.dispatch_many(
+					file_paths
+						.into_iter()
+						.chunks(BATCH_SIZE)
+						.into_iter()
+						.map(|chunk| {
+							Encoder::new(
+								Arc::clone(&thumbnails_directory_path),
+								&chunk.collect::<Vec<_>>(),
+								(location_id, location_path),
+								ctx.id(),
+								Arc::clone(&model),
+								Arc::clone(db),
+							)
+						})
+						.collect::<Vec<_>>(),
+				)
+				.await,
+		);
+
+		ctx.progress(vec![
+			ProgressUpdate::TaskCount(self.metadata.total_files),
+			ProgressUpdate::Message(format!(
+				"Embedding {} images in {} chunks",
+				self.metadata.total_files,
+				pending_running_tasks.len()
+			)),
+		]);
+
+		Ok(())
+	}
+
+	fn process_encoder_output(
+		&mut self,
+		encoder::Output {
+			embedded,
+			skipped,
+			embedding_time,
+			db_write_time,
+			errors,
+		}: encoder::Output,
+		ctx: &impl OuterContext,
+	) {
+		self.metadata.embedded += embedded;
+		self.metadata.skipped += skipped;
+		self.metadata.embedding_time += embedding_time;
+		self.metadata.db_write_time += db_write_time;
+
+		self.errors.extend(errors);
+
+		ctx.progress(vec![ProgressUpdate::CompletedTaskCount(
+			self.metadata.embedded + self.metadata.skipped,
+		)]);
+	}
+
+	/// Rebuilds the index of the library from every embedding in it, as other locations share it
+	async fn update_index(&mut self, ctx: &impl OuterContext) -> Result<(), embedder::Error> {
+		let path = index_path(ctx.get_data_directory(), ctx.id());
+
+		// Nothing changed, unless the index was never built or was deleted
+		if self.metadata.embedded == 0 && fs::metadata(&path).await.is_ok() {
+			return Ok(());
+		}
+
+		ctx.progress(vec![ProgressUpdate::Message(
+			"Updating the embeddings index".to_string(),
+		)]);
+
+		let start = Instant::now();
+
+		let index = EmbeddingIndex::build(CLIP_MODEL_ID, ctx.db()).await?;
+		index.save(&path).await?;
+
+		self.metadata.indexed_objects = index.len() as u64;
+		self.metadata.index_time = start.elapsed();
+
+		ctx.invalidate_query("search.semantic");
+
+		Ok(())
+	}
+}
+
+/// Images of the directory, leaving out the ones already embedded from their current content unless
+/// regenerating
+async fn get_files_to_embed(
+	db: &PrismaClient,
+	parent_iso_file_path: &IsolatedFilePathData<'_>,
+	regenerate: bool,
+) -> Result<Vec<file_path_for_media_processor::Data>, embedder::Error> {
+	// FIXME: Had to use format! macro because PCR doesn't support IN with Vec for SQLite
+	// We have no data coming from the user, so this is sql injection safe
+	db._query_raw(raw!(
+		&format!(
+			"SELECT id, materialized_path, is_dir, name, extension, cas_id, object_id
+			FROM file_path
+			WHERE
+				location_id={{}}
+				AND cas_id IS NOT NULL
+				AND object_id IS NOT NULL
+				AND (in_archive IS NULL OR in_archive = 0)
+				AND LOWER(extension) IN ({})
+				AND materialized_path LIKE {{}}
+				AND NOT EXISTS (
+					SELECT 1 FROM object_embedding
+					WHERE
+						object_embedding.object_id = file_path.object_id
+						AND object_embedding.model = {{}}
+						AND object_embedding.cas_id IS file_path.cas_id
+				)
+			ORDER BY materialized_path ASC",
+			AVAILABLE_EXTENSIONS
+				.iter()
+				.map(|ext| format!("LOWER('{ext}')"))
+				.collect::<Vec<_>>()
+				.join(","),
+		),
+		PrismaValue::Int(parent_iso_file_path.location_id()),
+		PrismaValue::String(format!(
+			"{}%",
+			parent_iso_file_path
+				.materialized_path_for_children()
+				.expect("sub path iso_file_path must be a directory")
+		)),
+		// No model is named like that, so nothing is left out when regenerating
+		PrismaValue::String(if regenerate {
+			String::new()
+		} else {
+			CLIP_MODEL_ID.to_string()
+		})
+	))
+	.exec()
+	.await
+	.map_err(Into::into)
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Metadata {
+	total_files: u64,
+	embedded: u64,
+	skipped: u64,
+	/// Objects of the whole library in the index once it was rebuilt
+	indexed_objects: u64,
+	embedding_time: Duration,
+	db_write_time: Duration,
+	index_time: Duration,
+}
+
+impl From<Metadata> for ReportOutputMetadata {
+	fn from(value: Metadata) -> Self {
+		Self::Metrics(HashMap::from([
+			("total_files".into(), json!(value.total_files)),
+			("embedded_files".into(), json!(value.embedded)),
+			("skipped_files".into(), json!(value.skipped)),
+			("indexed_objects".into(), json!(value.indexed_objects)),
+			("embedding_time".into(), json!(value.embedding_time)),
+			("db_write_time".into(), json!(value.db_write_time)),
+			("index_time".into(), json!(value.index_time)),
+		]))
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+struct SaveState {
+	location: Arc<location::Data>,
+	location_path: Arc<PathBuf>,
+	sub_path: Option<PathBuf>,
+	regenerate: bool,
+
+	metadata: Metadata,
+
+	errors: Vec<NonCriticalError>,
+
+	tasks_for_shutdown_bytes: Option<SerializedTasks>,
+}
+
+impl<Ctx: OuterContext> SerializableJob<Ctx> for Embedder {
+	async fn serialize(self) -> Result<Option<Vec<u8>>, rmp_serde::encode::Error> {
+		let Self {
+			location,
+			location_path,
+			sub_path,
+			regenerate,
+			metadata,
+			errors,
+			tasks_for_shutdown,
+			..
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState {
+			location,
+			location_path,
+			sub_path,
+			regenerate,
+			metadata,
+			errors,
+			tasks_for_shutdown_bytes: Some(SerializedTasks(rmp_serde::to_vec_named(
+				&tasks_for_shutdown
+					.into_iter()
+					.map(|task| async move {
+						task.downcast::<Encoder>()
+							.expect("the embedder job only dispatches encoder tasks")
+							.serialize()
+							.await
+					})
+					.collect::<Vec<_>>()
+					.try_join()
+					.await?,
+			)?)),
+		})
+		.map(Some)
+	}
+
+	async fn deserialize(
+		serialized_job: &[u8],
+		_: &Ctx,
+	) -> Result<Option<(Self, Option<SerializedTasks>)>, rmp_serde::decode::Error> {
+		let SaveState {
+			location,
+			location_path,
+			sub_path,
+			regenerate,
+			metadata,
+			errors,
+			tasks_for_shutdown_bytes,
+		} = rmp_serde::from_slice::<SaveState>(serialized_job)?;
+
+		Ok(Some((
+			Self {
+				location,
+				location_path,
+				sub_path,
+				regenerate,
+				model: None,
+				metadata,
+				errors,
+				pending_tasks_on_resume: Vec::new(),
+				tasks_for_shutdown: Vec::new(),
+			},
+			tasks_for_shutdown_bytes,
+		)))
+	}
+}
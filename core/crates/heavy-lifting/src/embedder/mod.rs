@@ -0,0 +1,114 @@
+use crate::{
+	media_processor::{can_generate_thumbnail_for_image, embedding_to_bytes},
+	utils::sub_path,
+};
+
+use sd_core_file_path_helper::FilePathError;
+
+use sd_ai::embeddings::EmbeddingsError;
+use sd_file_ext::extensions::{Extension, ALL_IMAGE_EXTENSIONS};
+use sd_prisma::prisma::{object, object_embedding, PrismaClient};
+use sd_utils::{db::MissingFieldError, error::FileIOError};
+
+use once_cell::sync::Lazy;
+use prisma_client_rust::QueryError;
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+pub mod index;
+pub mod job;
+mod tasks;
+
+pub use index::{index_path, EmbeddingIndex};
+pub use job::Embedder;
+pub use sd_ai::embeddings::{ImageEmbedder, TextEmbedder, CLIP_MODEL_ID};
+pub use tasks::encoder;
+
+// Inference is slow, so each task gets only a few files to be interrupted often enough
+const BATCH_SIZE: usize = 10;
+
+pub static AVAILABLE_EXTENSIONS: Lazy<Vec<Extension>> = Lazy::new(|| {
+	ALL_IMAGE_EXTENSIONS
+		.iter()
+		.copied()
+		.filter(|&ext| can_generate_thumbnail_for_image(ext))
+		.map(Extension::Image)
+		.collect()
+});
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("missing field on database: {0}")]
+	MissingField(#[from] MissingFieldError),
+	#[error("database error: {0}")]
+	Database(#[from] QueryError),
+	#[error("failed to deserialized stored tasks for job resume: {0}")]
+	DeserializeTasks(#[from] rmp_serde::decode::Error),
+	#[error("failed to load the embeddings model: {0}")]
+	LoadModel(#[from] EmbeddingsError),
+	#[error("failed to encode the embeddings index: {0}")]
+	EncodeIndex(#[from] rmp_serde::encode::Error),
+	#[error("failed to decode the embeddings index: {0}")]
+	DecodeIndex(rmp_serde::decode::Error),
+	#[error("processing thread panicked while building the embeddings index: {0}")]
+	PanicWhileBuildingIndex(String),
+
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+	#[error(transparent)]
+	FilePathError(#[from] FilePathError),
+	#[error(transparent)]
+	SubPath(#[from] sub_path::Error),
+}
+
+impl From<Error> for rspc::Error {
+	fn from(err: Error) -> Self {
+		match err {
+			Error::SubPath(sub_path_err) => sub_path_err.into(),
+
+			_ => Self::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize, Type)]
+pub enum NonCriticalError {
+	#[error(transparent)]
+	Encoder(#[from] encoder::NonCriticalError),
+}
+
+/// Replaces the embeddings of the objects, returning how many were saved
+async fn save(
+	embeddings: Vec<(object::id::Type, String, Vec<f32>)>,
+	model: &str,
+	db: &PrismaClient,
+) -> Result<u64, Error> {
+	// Embeddings come from the content on each device, so they aren't synced
+	db._batch(
+		embeddings
+			.into_iter()
+			.map(|(object_id, cas_id, embedding)| {
+				let embedding = embedding_to_bytes(&embedding);
+
+				db.object_embedding().upsert(
+					object_embedding::object_id::equals(object_id),
+					object_embedding::create(
+						model.to_string(),
+						embedding.clone(),
+						object::id::equals(object_id),
+						vec![object_embedding::cas_id::set(Some(cas_id.clone()))],
+					),
+					vec![
+						object_embedding::model::set(model.to_string()),
+						object_embedding::embedding::set(embedding),
+						object_embedding::cas_id::set(Some(cas_id)),
+					],
+				)
+			})
+			.collect::<Vec<_>>(),
+	)
+	.await
+	.map(|saved| saved.len() as u64)
+	.map_err(Into::into)
+}
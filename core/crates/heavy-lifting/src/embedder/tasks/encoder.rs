@@ -0,0 +1,288 @@
+use crate::{
+	embedder::{self, save},
+	media_processor::{thumbnail_path, ThumbnailKind},
+	Error,
+};
+
+use sd_core_file_path_helper::IsolatedFilePathData;
+use sd_core_prisma_helpers::file_path_for_media_processor;
+
+use sd_ai::embeddings::{ImageEmbedder, CLIP_MODEL_ID};
+use sd_images::{format_image, ConvertibleExtension};
+use sd_media_metadata::exif::Orientation;
+use sd_prisma::prisma::{file_path, location, object, PrismaClient};
+use sd_task_system::{
+	check_interruption, ExecStatus, Interrupter, IntoAnyTaskOutput, SerializableTask, Task, TaskId,
+};
+
+use std::{
+	collections::HashSet,
+	mem,
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::{task::spawn_blocking, time::Instant};
+use tracing::{error, trace};
+use uuid::Uuid;
+
+/// Embeds a batch of images with the image half of CLIP, so they can be found by describing them
+#[derive(Debug)]
+pub struct Encoder {
+	id: TaskId,
+	library_id: Uuid,
+	thumbnails_directory_path: Arc<PathBuf>,
+	files: Vec<(object::id::Type, String, PathBuf)>,
+	embeddings: Vec<(object::id::Type, String, Vec<f32>)>,
+	model: Arc<ImageEmbedder>,
+	db: Arc<PrismaClient>,
+	output: Output,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Output {
+	pub embedded: u64,
+	pub skipped: u64,
+	pub embedding_time: Duration,
+	pub db_write_time: Duration,
+	pub errors: Vec<crate::NonCriticalError>,
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize, Type)]
+pub enum NonCriticalError {
+	#[error("failed to extract isolated file path data from file path <id='{0}'>: {1}")]
+	FailedToExtractIsolatedFilePathData(file_path::id::Type, String),
+	#[error("file path missing object id: <file_path_id='{0}'>")]
+	FilePathMissingObjectId(file_path::id::Type),
+	#[error("failed to decode image to embed <path='{}'>: {1}", .0.display())]
+	FormatImage(PathBuf, String),
+	#[error("failed to embed image <path='{}'>: {1}", .0.display())]
+	Embed(PathBuf, String),
+	#[error("processing thread panicked while embedding image <path='{}'>: {1}", .0.display())]
+	PanicWhileEmbedding(PathBuf, String),
+}
+
+impl Encoder {
+	#[must_use]
+	pub fn new(
+		thumbnails_directory_path: Arc<PathBuf>,
+		file_paths: &[file_path_for_media_processor::Data],
+		(location_id, location_path): (location::id::Type, &Path),
+		library_id: Uuid,
+		model: Arc<ImageEmbedder>,
+		db: Arc<PrismaClient>,
+	) -> Self {
+		let mut errors = Vec::new();
+		let mut seen_objects = HashSet::with_capacity(file_paths.len());
+
+		Self {
+			id: TaskId::new_v4(),
+			library_id,
+			thumbnails_directory_path,
+			files: file_paths
+				.iter()
+				.filter_map(|file_path| {
+					// Only file paths with cas_id are fetched for the embedder
+					let cas_id = file_path.cas_id.clone()?;
+
+					let Some(object_id) = file_path.object_id else {
+						errors.push(
+							embedder::NonCriticalError::from(
+								NonCriticalError::FilePathMissingObjectId(file_path.id),
+							)
+							.into(),
+						);
+						return None;
+					};
+
+					// Many file paths can point to the same object, we embed it just once
+					if !seen_objects.insert(object_id) {
+						return None;
+					}
+
+					IsolatedFilePathData::try_from((location_id, file_path))
+						.map_err(|e| {
+							errors.push(
+								embedder::NonCriticalError::from(
+									NonCriticalError::FailedToExtractIsolatedFilePathData(
+										file_path.id,
+										e.to_string(),
+									),
+								)
+								.into(),
+							);
+						})
+						.ok()
+						.map(|iso_file_path| (object_id, cas_id, location_path.join(iso_file_path)))
+				})
+				.collect(),
+			embeddings: Vec::new(),
+			model,
+			db,
+			output: Output {
+				errors,
+				..Default::default()
+			},
+		}
+	}
+}
+
+#[async_trait::async_trait]
+impl Task<Error> for Encoder {
+	fn id(&self) -> TaskId {
+		self.id
+	}
+
+	async fn run(&mut self, interrupter: &Interrupter) -> Result<ExecStatus, Error> {
+		let Self {
+			library_id,
+			thumbnails_directory_path,
+			files,
+			embeddings,
+			model,
+			db,
+			output:
+				Output {
+					embedded,
+					skipped,
+					embedding_time,
+					db_write_time,
+					errors,
+				},
+			..
+		} = self;
+
+		let start = Instant::now();
+		let kind = ThumbnailKind::Indexed(*library_id);
+
+		// Files are popped, so a resumed task only processes the remaining ones
+		while let Some((object_id, cas_id, path)) = files.pop() {
+			let thumbnail = thumbnail_path(&**thumbnails_directory_path, &cas_id, &kind);
+
+			match embed_image(path, thumbnail, Arc::clone(model)).await {
+				Ok(embedding) => embeddings.push((object_id, cas_id, embedding)),
+				Err(e) => {
+					error!("{e:#?}");
+					errors.push(embedder::NonCriticalError::from(e).into());
+					*skipped += 1;
+				}
+			}
+
+			check_interruption!(interrupter, start, embedding_time);
+		}
+
+		*embedding_time += start.elapsed();
+
+		let db_write_start = Instant::now();
+		*embedded = save(mem::take(embeddings), CLIP_MODEL_ID, db).await?;
+		*db_write_time = db_write_start.elapsed();
+
+		Ok(ExecStatus::Done(mem::take(&mut self.output).into_output()))
+	}
+}
+
+/// Embeds the thumbnail when there is one, as the model shrinks images way below thumbnail size
+/// anyway, and thumbnails already have their orientation corrected
+async fn embed_image(
+	path: PathBuf,
+	thumbnail: PathBuf,
+	model: Arc<ImageEmbedder>,
+) -> Result<Vec<f32>, NonCriticalError> {
+	spawn_blocking({
+		let path = path.clone();
+
+		move || {
+			let img = if let Ok(img) = image::open(&thumbnail) {
+				trace!("Embedding thumbnail of {}", path.display());
+				img
+			} else {
+				let mut img = format_image(&path)
+					.map_err(|e| NonCriticalError::FormatImage(path.clone(), e.to_string()))?;
+
+				// Models are trained on upright images
+				if let Some(orientation) = Orientation::from_path(&path) {
+					if ConvertibleExtension::try_from(path.as_path())
+						.is_ok_and(|extension| extension.should_rotate())
+					{
+						img = orientation.correct_thumbnail(img);
+					}
+				}
+
+				img
+			};
+
+			model
+				.embed(&img)
+				.map_err(|e| NonCriticalError::Embed(path.clone(), e.to_string()))
+		}
+	})
+	.await
+	.map_err(|e| NonCriticalError::PanicWhileEmbedding(path, e.to_string()))?
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SaveState {
+	id: TaskId,
+	library_id: Uuid,
+	thumbnails_directory_path: Arc<PathBuf>,
+	files: Vec<(object::id::Type, String, PathBuf)>,
+	embeddings: Vec<(object::id::Type, String, Vec<f32>)>,
+	output: Output,
+}
+
+impl SerializableTask<Error> for Encoder {
+	type SerializeError = rmp_serde::encode::Error;
+
+	type DeserializeError = rmp_serde::decode::Error;
+
+	type DeserializeCtx = (Arc<ImageEmbedder>, Arc<PrismaClient>);
+
+	async fn serialize(self) -> Result<Vec<u8>, Self::SerializeError> {
+		let Self {
+			id,
+			library_id,
+			thumbnails_directory_path,
+			files,
+			embeddings,
+			output,
+			..
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState {
+			id,
+			library_id,
+			thumbnails_directory_path,
+			files,
+			embeddings,
+			output,
+		})
+	}
+
+	async fn deserialize(
+		data: &[u8],
+		(model, db): Self::DeserializeCtx,
+	) -> Result<Self, Self::DeserializeError> {
+		rmp_serde::from_slice(data).map(
+			|SaveState {
+			     id,
+			     library_id,
+			     thumbnails_directory_path,
+			     files,
+			     embeddings,
+			     output,
+			 }| Self {
+				id,
+				library_id,
+				thumbnails_directory_path,
+				files,
+				embeddings,
+				model,
+				db,
+				output,
+			},
+		)
+	}
+}
@@ -0,0 +1,3 @@
+pub mod encoder;
+
+pub use encoder::Encoder;
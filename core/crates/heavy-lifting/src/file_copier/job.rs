@@ -0,0 +1,683 @@
+use crate::{
+	file_copier,
+	file_identifier::{not_in_archive, CasIdAlgorithm},
+	job_system::{
+		job::{Job, JobReturn, JobTaskDispatcher, ReturnStatus},
+		report::ReportOutputMetadata,
+		utils::{cancel_pending_tasks, location_concurrency_key},
+		SerializableJob, SerializedTasks,
+	},
+	utils::io_throttle::IoThrottle,
+	Error, JobName, NonCriticalError, OuterContext, ProgressUpdate,
+};
+
+use sd_core_file_path_helper::IsolatedFilePathData;
+use sd_core_prisma_helpers::file_path_for_file_copier;
+
+use sd_prisma::prisma::{file_path, location};
+use sd_task_system::{
+	IntoTask, SerializableTask, Task, TaskDispatcher, TaskHandle, TaskId, TaskOutput, TaskStatus,
+	TaskSystemError,
+};
+use sd_utils::{
+	db::{maybe_missing, size_in_bytes_from_db},
+	error::FileIOError,
+};
+
+use std::{
+	collections::HashMap,
+	hash::{Hash, Hasher},
+	mem,
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::Duration,
+};
+
+use async_channel as chan;
+use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
+use futures_concurrency::future::{Race, TryJoin};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::{fs, time::Instant};
+use tracing::{debug, trace, warn};
+
+use super::{
+	available_path, exists,
+	tasks::{
+		copier::{self, CopyEntry, CopyProgress},
+		Copier,
+	},
+	ConflictPolicy, BATCH_SIZE,
+};
+
+// Byte progress arrives every chunk of every task, the UI doesn't need it that often
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Copies a selection of file paths of a location, directories with all their contents, into a
+/// directory of another location (or the same one). Copies are written next to their target under
+/// a hidden partial name and resumed from where they stopped, then only moved into place once
+/// their `cas_id` matches the one of their source.
+#[derive(Debug)]
+pub struct FileCopier {
+	source_location: Arc<location::Data>,
+	source_location_path: Arc<PathBuf>,
+	target_location_id: location::id::Type,
+	target_path: PathBuf,
+	file_path_ids: Vec<file_path::id::Type>,
+	conflict_policy: ConflictPolicy,
+	cas_id_algorithm: CasIdAlgorithm,
+	io_throttle: IoThrottle,
+
+	metadata: Metadata,
+	files_progress: HashMap<TaskId, FileProgress>,
+	progress_tx: chan::Sender<(TaskId, CopyProgress)>,
+	progress_rx: chan::Receiver<(TaskId, CopyProgress)>,
+
+	errors: Vec<NonCriticalError>,
+
+	pending_tasks_on_resume: Vec<TaskHandle<Error>>,
+	tasks_for_shutdown: Vec<Box<dyn Task<Error>>>,
+}
+
+/// The file a task is copying right now
+#[derive(Debug)]
+struct FileProgress {
+	path: PathBuf,
+	size: u64,
+	copied: u64,
+}
+
+impl Hash for FileCopier {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.source_location.id.hash(state);
+		self.target_path.hash(state);
+		self.file_path_ids.hash(state);
+	}
+}
+
+impl Job for FileCopier {
+	const NAME: JobName = JobName::FileCopier;
+
+	async fn resume_tasks(
+		&mut self,
+		dispatcher: &JobTaskDispatcher,
+		ctx: &impl OuterContext,
+		SerializedTasks(serialized_tasks): SerializedTasks,
+	) -> Result<(), Error> {
+		let dispatcher = dispatcher
+			.with_concurrency_key(location_concurrency_key(ctx.id(), self.target_location_id));
+		let io_throttle = &dispatcher.io_throttle(&self.io_throttle);
+		let progress_tx = &self.progress_tx;
+
+		self.pending_tasks_on_resume = dispatcher
+			.dispatch_many_boxed(
+				rmp_serde::from_slice::<Vec<Vec<u8>>>(&serialized_tasks)
+					.map_err(file_copier::Error::from)?
+					.into_iter()
+					.map(|task_bytes| async move {
+						Copier::deserialize(&task_bytes, progress_tx.clone())
+							.await
+							// Tasks share the job's budget again, instead of one each
+							.map(|task| task.with_io_throttle(io_throttle.clone()))
+							.map(IntoTask::into_task)
+					})
+					.collect::<Vec<_>>()
+					.try_join()
+					.await
+					.map_err(file_copier::Error::from)?,
+			)
+			.await;
+
+		Ok(())
+	}
+
+	async fn run<Ctx: OuterContext>(
+		mut self,
+		dispatcher: JobTaskDispatcher,
+		ctx: Ctx,
+	) -> Result<ReturnStatus, Error> {
+		enum RaceOutput {
+			Task(Option<Result<TaskStatus<Error>, TaskSystemError>>),
+			Progress(Option<(TaskId, CopyProgress)>),
+		}
+
+		let dispatcher = dispatcher
+			.with_concurrency_key(location_concurrency_key(ctx.id(), self.target_location_id));
+
+		let mut pending_running_tasks = FuturesUnordered::new();
+
+		self.init_or_resume(&mut pending_running_tasks, &ctx, &dispatcher)
+			.await?;
+
+		let mut last_progress_update = Instant::now();
+
+		while !pending_running_tasks.is_empty() {
+			let output = (
+				pending_running_tasks.next().map(RaceOutput::Task),
+				self.progress_rx
+					.recv()
+					.map(|res| RaceOutput::Progress(res.ok())),
+			)
+				.race()
+				.await;
+
+			let task = match output {
+				RaceOutput::Progress(Some((task_id, progress))) => {
+					self.process_progress(task_id, progress);
+
+					if last_progress_update.elapsed() > PROGRESS_INTERVAL {
+						last_progress_update = Instant::now();
+						self.report_progress(&ctx);
+					}
+
+					continue;
+				}
+				// The job holds a sender, so the channel is never closed
+				RaceOutput::Progress(None) | RaceOutput::Task(None) => continue,
+				RaceOutput::Task(Some(task)) => task,
+			};
+
+			match task {
+				Ok(TaskStatus::Done((task_id, TaskOutput::Out(out)))) => {
+					self.files_progress.remove(&task_id);
+					self.process_copier_output(
+						*out.downcast::<copier::Output>()
+							.expect("the file copier job only dispatches copier tasks"),
+					);
+					self.report_progress(&ctx);
+				}
+
+				Ok(TaskStatus::Done((task_id, TaskOutput::Empty))) => {
+					warn!("Task <id='{task_id}'> returned an empty output");
+				}
+
+				Ok(TaskStatus::Shutdown(task)) => {
+					self.tasks_for_shutdown.push(task);
+				}
+
+				Ok(TaskStatus::Error(e)) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e);
+				}
+
+				Ok(TaskStatus::Canceled | TaskStatus::ForcedAbortion) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Ok(ReturnStatus::Canceled);
+				}
+
+				Err(e) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e.into());
+				}
+			}
+		}
+
+		// Progress sent right before the tasks finished, so the copied bytes add up
+		while let Ok((task_id, progress)) = self.progress_rx.try_recv() {
+			self.process_progress(task_id, progress);
+		}
+
+		if !self.tasks_for_shutdown.is_empty() {
+			return Ok(ReturnStatus::Shutdown(
+				SerializableJob::<Ctx>::serialize(self).await,
+			));
+		}
+
+		ctx.invalidate_query("search.paths");
+
+		let Self {
+			metadata, errors, ..
+		} = self;
+
+		Ok(ReturnStatus::Completed(
+			JobReturn::builder()
+				.with_metadata(metadata)
+				.with_non_critical_errors(errors)
+				.build(),
+		))
+	}
+}
+
+impl FileCopier {
+	/// Copies the `file_path_ids` of `source_location` into `target_sub_path` of `target_location`.
+	/// `cas_id_algorithm` must be the one the library's `cas_id`s were generated with.
+	pub fn new(
+		source_location: location::Data,
+		target_location: &location::Data,
+		target_sub_path: impl AsRef<Path>,
+		file_path_ids: Vec<file_path::id::Type>,
+		cas_id_algorithm: CasIdAlgorithm,
+	) -> Result<Self, file_copier::Error> {
+		let target_path = maybe_missing(&target_location.path, "location.path")
+			.map(|path| Path::new(path).join(target_sub_path))?;
+
+		let (progress_tx, progress_rx) = chan::unbounded();
+
+		Ok(Self {
+			source_location_path: maybe_missing(&source_location.path, "location.path")
+				.map(PathBuf::from)
+				.map(Arc::new)?,
+			source_location: Arc::new(source_location),
+			target_location_id: target_location.id,
+			target_path,
+			file_path_ids,
+			conflict_policy: ConflictPolicy::default(),
+			cas_id_algorithm,
+			io_throttle: IoThrottle::default(),
+			metadata: Metadata::default(),
+			files_progress: HashMap::new(),
+			progress_tx,
+			progress_rx,
+			errors: Vec::new(),
+			pending_tasks_on_resume: Vec::new(),
+			tasks_for_shutdown: Vec::new(),
+		})
+	}
+
+	#[must_use]
+	pub const fn with_conflict_policy(mut self, conflict_policy: ConflictPolicy) -> Self {
+		self.conflict_policy = conflict_policy;
+		self
+	}
+
+	/// Caps how fast files are read, shared by every copier task of the job
+	#[must_use]
+	pub fn with_io_throttle(mut self, io_throttle: IoThrottle) -> Self {
+		self.io_throttle = io_throttle;
+		self
+	}
+
+	async fn init_or_resume(
+		&mut self,
+		pending_running_tasks: &mut FuturesUnordered<TaskHandle<Error>>,
+		ctx: &impl OuterContext,
+		dispatcher: &JobTaskDispatcher,
+	) -> Result<(), file_copier::Error> {
+		// Resumed tasks were already dispatched by `resume_tasks`
+		if !self.pending_tasks_on_resume.is_empty() {
+			pending_running_tasks.extend(mem::take(&mut self.pending_tasks_on_resume));
+
+			return Ok(());
+		}
+
+		let deep_hash_threshold = ctx.deep_hash_threshold().await;
+
+		let entries = self.gather_entries(ctx).await?;
+
+		debug!(
+			"Copying {} files, {} bytes, from location {} into \"{}\"",
+			self.metadata.total_files,
+			self.metadata.total_bytes,
+			self.source_location.id,
+			self.target_path.display()
+		);
+
+		let io_throttle = dispatcher.io_throttle(&self.io_throttle);
+
+		pending_running_tasks.extend(
+			dispatcher
+				.dispatch_many(
+					entries
+						.chunks(BATCH_SIZE)
+						.map(|chunk| {
+							Copier::new(
+								// Reversed as tasks pop their entries
+								chunk.iter().rev().cloned().collect(),
+								self.conflict_policy,
+								self.cas_id_algorithm,
+								deep_hash_threshold,
+								self.progress_tx.clone(),
+							)
+							.with_io_throttle(io_throttle.clone())
+						})
+						.collect::<Vec<_>>(),
+				)
+				.await,
+		);
+
+		self.report_progress(ctx);
+
+		Ok(())
+	}
+
+	/// Files to copy, with their targets, creating the target directories along the way so empty
+	/// ones are copied too
+	async fn gather_entries(
+		&mut self,
+		ctx: &impl OuterContext,
+	) -> Result<Vec<CopyEntry>, file_copier::Error> {
+		let db = ctx.db();
+		let location_id = self.source_location.id;
+		let location_path = &*self.source_location_path;
+
+		let file_paths = db
+			.file_path()
+			.find_many(vec![
+				file_path::location_id::equals(Some(location_id)),
+				file_path::id::in_vec(self.file_path_ids.clone()),
+				not_in_archive(),
+			])
+			.select(file_path_for_file_copier::select())
+			.exec()
+			.await?;
+
+		if let Some(missing_id) = self
+			.file_path_ids
+			.iter()
+			.find(|id| !file_paths.iter().any(|file_path| file_path.id == **id))
+		{
+			return Err(file_copier::Error::FilePathNotFound(*missing_id));
+		}
+
+		create_dir(&self.target_path).await?;
+
+		let mut entries = Vec::new();
+
+		for file_path in file_paths {
+			let iso_file_path = IsolatedFilePathData::try_from((location_id, &file_path))?;
+			let source = location_path.join(&iso_file_path);
+			let mut target = self.target_path.join(iso_file_path.full_name());
+
+			if !iso_file_path.is_dir() {
+				self.push_entry(&mut entries, source, target, &file_path);
+				continue;
+			}
+
+			// Conflicts on files are handled by the tasks, as they're copied
+			if exists(&target).await? {
+				match self.conflict_policy {
+					ConflictPolicy::Skip => {
+						trace!("Skipping copy to existing <path='{}'>", target.display());
+						self.metadata.skipped_files += 1;
+						continue;
+					}
+					ConflictPolicy::Rename => {
+						let Some(available) = available_path(&target).await? else {
+							self.errors.push(
+								file_copier::NonCriticalError::from(
+									copier::NonCriticalError::FailedToFindAvailableName(target),
+								)
+								.into(),
+							);
+							continue;
+						};
+						target = available;
+					}
+					ConflictPolicy::Overwrite => { /* Merging into the existing directory */ }
+				}
+			}
+
+			create_dir(&target).await?;
+
+			let children = db
+				.file_path()
+				.find_many(vec![
+					file_path::location_id::equals(Some(location_id)),
+					file_path::materialized_path::starts_with(
+						iso_file_path
+							.materialized_path_for_children()
+							.expect("we checked that the iso_file_path is a directory"),
+					),
+					not_in_archive(),
+				])
+				.select(file_path_for_file_copier::select())
+				.exec()
+				.await?;
+
+			for child in children {
+				let child_iso_file_path = IsolatedFilePathData::try_from((location_id, &child))?;
+				let child_source = location_path.join(&child_iso_file_path);
+				let child_target = target.join(
+					child_source
+						.strip_prefix(&source)
+						.expect("children paths start with their parent's"),
+				);
+
+				if child_iso_file_path.is_dir() {
+					create_dir(&child_target).await?;
+				} else {
+					self.push_entry(&mut entries, child_source, child_target, &child);
+				}
+			}
+		}
+
+		Ok(entries)
+	}
+
+	fn push_entry(
+		&mut self,
+		entries: &mut Vec<CopyEntry>,
+		source: PathBuf,
+		target: PathBuf,
+		file_path: &file_path_for_file_copier::Data,
+	) {
+		self.metadata.total_files += 1;
+		self.metadata.total_bytes += file_path
+			.size_in_bytes_bytes
+			.as_deref()
+			.map(size_in_bytes_from_db)
+			.unwrap_or_default();
+
+		entries.push(CopyEntry {
+			source,
+			target,
+			resolved: false,
+		});
+	}
+
+	fn process_progress(&mut self, task_id: TaskId, progress: CopyProgress) {
+		match progress {
+			CopyProgress::Started {
+				path,
+				size,
+				resumed_at,
+			} => {
+				self.files_progress.insert(
+					task_id,
+					FileProgress {
+						path,
+						size,
+						copied: resumed_at,
+					},
+				);
+			}
+			CopyProgress::Copied(bytes) => {
+				self.metadata.copied_bytes += bytes;
+				if let Some(file) = self.files_progress.get_mut(&task_id) {
+					file.copied += bytes;
+				}
+			}
+			CopyProgress::Finished => {
+				self.metadata.completed_files += 1;
+				self.files_progress.remove(&task_id);
+			}
+		}
+	}
+
+	fn report_progress(&self, ctx: &impl OuterContext) {
+		let total = format!(
+			"{} of {} bytes",
+			self.metadata.copied_bytes, self.metadata.total_bytes
+		);
+
+		let message = self.files_progress.values().next().map_or_else(
+			|| format!("Copied {total}"),
+			|FileProgress { path, size, copied }| {
+				format!(
+					"Copying \"{}\": {copied} of {size} bytes, {total} in total",
+					path.display()
+				)
+			},
+		);
+
+		ctx.progress(vec![
+			ProgressUpdate::TaskCount(self.metadata.total_files),
+			ProgressUpdate::CompletedTaskCount(self.metadata.completed_files),
+			ProgressUpdate::Message(message),
+		]);
+	}
+
+	fn process_copier_output(
+		&mut self,
+		copier::Output {
+			copied_files,
+			skipped_files,
+			failed_files,
+			copy_time,
+			verification_time,
+			errors,
+		}: copier::Output,
+	) {
+		self.metadata.copied_files += copied_files;
+		self.metadata.skipped_files += skipped_files;
+		self.metadata.failed_files += failed_files;
+		self.metadata.copy_time += copy_time;
+		self.metadata.verification_time += verification_time;
+
+		self.errors.extend(errors);
+	}
+}
+
+async fn create_dir(path: &Path) -> Result<(), FileIOError> {
+	fs::create_dir_all(path)
+		.await
+		.map_err(|e| FileIOError::from((path, e, "Failed to create target directory")))
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Metadata {
+	total_files: u64,
+	total_bytes: u64,
+	completed_files: u64,
+	copied_files: u64,
+	skipped_files: u64,
+	failed_files: u64,
+	copied_bytes: u64,
+	copy_time: Duration,
+	verification_time: Duration,
+}
+
+impl From<Metadata> for ReportOutputMetadata {
+	fn from(value: Metadata) -> Self {
+		Self::Metrics(HashMap::from([
+			("total_files".into(), json!(value.total_files)),
+			("total_bytes".into(), json!(value.total_bytes)),
+			("copied_files".into(), json!(value.copied_files)),
+			("skipped_files".into(), json!(value.skipped_files)),
+			("failed_files".into(), json!(value.failed_files)),
+			("copied_bytes".into(), json!(value.copied_bytes)),
+			("copy_time".into(), json!(value.copy_time)),
+			("verification_time".into(), json!(value.verification_time)),
+		]))
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+struct SaveState {
+	source_location: Arc<location::Data>,
+	source_location_path: Arc<PathBuf>,
+	target_location_id: location::id::Type,
+	target_path: PathBuf,
+	file_path_ids: Vec<file_path::id::Type>,
+	conflict_policy: ConflictPolicy,
+	cas_id_algorithm: CasIdAlgorithm,
+	#[serde(default)]
+	io_throttle: IoThrottle,
+
+	metadata: Metadata,
+
+	errors: Vec<NonCriticalError>,
+
+	tasks_for_shutdown_bytes: Option<SerializedTasks>,
+}
+
+impl<Ctx: OuterContext> SerializableJob<Ctx> for FileCopier {
+	async fn serialize(self) -> Result<Option<Vec<u8>>, rmp_serde::encode::Error> {
+		let Self {
+			source_location,
+			source_location_path,
+			target_location_id,
+			target_path,
+			file_path_ids,
+			conflict_policy,
+			cas_id_algorithm,
+			io_throttle,
+			metadata,
+			errors,
+			tasks_for_shutdown,
+			..
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState {
+			source_location,
+			source_location_path,
+			target_location_id,
+			target_path,
+			file_path_ids,
+			conflict_policy,
+			cas_id_algorithm,
+			io_throttle,
+			metadata,
+			errors,
+			tasks_for_shutdown_bytes: Some(SerializedTasks(rmp_serde::to_vec_named(
+				&tasks_for_shutdown
+					.into_iter()
+					.map(|task| async move {
+						task.downcast::<Copier>()
+							.expect("the file copier job only dispatches copier tasks")
+							.serialize()
+							.await
+					})
+					.collect::<Vec<_>>()
+					.try_join()
+					.await?,
+			)?)),
+		})
+		.map(Some)
+	}
+
+	async fn deserialize(
+		serialized_job: &[u8],
+		_: &Ctx,
+	) -> Result<Option<(Self, Option<SerializedTasks>)>, rmp_serde::decode::Error> {
+		let SaveState {
+			source_location,
+			source_location_path,
+			target_location_id,
+			target_path,
+			file_path_ids,
+			conflict_policy,
+			cas_id_algorithm,
+			io_throttle,
+			metadata,
+			errors,
+			tasks_for_shutdown_bytes,
+		} = rmp_serde::from_slice::<SaveState>(serialized_job)?;
+
+		let (progress_tx, progress_rx) = chan::unbounded();
+
+		Ok(Some((
+			Self {
+				source_location,
+				source_location_path,
+				target_location_id,
+				target_path,
+				file_path_ids,
+				conflict_policy,
+				cas_id_algorithm,
+				io_throttle,
+				metadata,
+				files_progress: HashMap::new(),
+				progress_tx,
+				progress_rx,
+				errors,
+				pending_tasks_on_resume: Vec::new(),
+				tasks_for_shutdown: Vec::new(),
+			},
+			tasks_for_shutdown_bytes,
+		)))
+	}
+}
@@ -0,0 +1,108 @@
+use sd_core_file_path_helper::FilePathError;
+
+use sd_prisma::prisma::file_path;
+use sd_utils::{db::MissingFieldError, error::FileIOError};
+
+use std::{
+	ffi::OsString,
+	io,
+	path::{Path, PathBuf},
+};
+
+use prisma_client_rust::QueryError;
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::fs;
+
+pub mod job;
+mod tasks;
+
+pub use job::FileCopier;
+pub use tasks::copier;
+
+// How many files each copier task copies, one after the other
+const BATCH_SIZE: usize = 50;
+
+// Size of the ranged reads and writes, and so how often progress is reported and interruptions checked
+const CHUNK_SIZE: usize = 1024 * 1024; // 1 MiB
+
+// Tried suffixes before giving up on finding a free name for a conflicting copy
+pub(crate) const MAX_RENAME_ATTEMPTS: usize = 1000;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("file_path not found in source location: <id='{0}'>")]
+	FilePathNotFound(file_path::id::Type),
+	#[error("missing field on database: {0}")]
+	MissingField(#[from] MissingFieldError),
+	#[error("database error: {0}")]
+	Database(#[from] QueryError),
+	#[error("failed to deserialized stored tasks for job resume: {0}")]
+	DeserializeTasks(#[from] rmp_serde::decode::Error),
+
+	#[error(transparent)]
+	FilePathError(#[from] FilePathError),
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+}
+
+impl From<Error> for rspc::Error {
+	fn from(err: Error) -> Self {
+		match err {
+			Error::FilePathNotFound(_) => {
+				Self::with_cause(ErrorCode::NotFound, err.to_string(), err)
+			}
+
+			_ => Self::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize, Type)]
+pub enum NonCriticalError {
+	#[error(transparent)]
+	Copier(#[from] copier::NonCriticalError),
+}
+
+/// What to do when a copy would land on a file or directory that already exists
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum ConflictPolicy {
+	/// Leave the existing one alone and don't copy, for directories their whole contents
+	#[default]
+	Skip,
+	/// Copy next to the existing one, with a ` (n)` suffix on its name
+	Rename,
+	/// Replace existing files once their copy is verified, merging into existing directories
+	Overwrite,
+}
+
+/// First ` (n)` suffixed variant of `path` that doesn't exist, `None` after [`MAX_RENAME_ATTEMPTS`]
+pub(crate) async fn available_path(path: &Path) -> Result<Option<PathBuf>, FileIOError> {
+	let stem = path.file_stem().unwrap_or_default();
+	let extension = path.extension();
+
+	for n in 1..=MAX_RENAME_ATTEMPTS {
+		let mut name = OsString::from(stem);
+		name.push(format!(" ({n})"));
+		if let Some(extension) = extension {
+			name.push(".");
+			name.push(extension);
+		}
+
+		let candidate = path.with_file_name(name);
+		if !exists(&candidate).await? {
+			return Ok(Some(candidate));
+		}
+	}
+
+	Ok(None)
+}
+
+pub(crate) async fn exists(path: &Path) -> Result<bool, FileIOError> {
+	match fs::symlink_metadata(path).await {
+		Ok(_) => Ok(true),
+		Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+		Err(e) => Err(FileIOError::from((path, e))),
+	}
+}
@@ -0,0 +1,561 @@
+use crate::{
+	file_copier::{self, available_path, exists, ConflictPolicy, CHUNK_SIZE},
+	file_identifier::{generate_cas_id, CasIdAlgorithm},
+	utils::io_throttle::IoThrottle,
+	Error,
+};
+
+use sd_task_system::{
+	check_interruption, ExecStatus, Interrupter, InterruptionKind, IntoAnyTaskOutput,
+	SerializableTask, Task, TaskId,
+};
+use sd_utils::error::FileIOError;
+
+use std::{
+	ffi::OsString,
+	io::SeekFrom,
+	mem,
+	path::{Path, PathBuf},
+	time::Duration,
+};
+
+use async_channel as chan;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::{
+	fs::{self, File, OpenOptions},
+	io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+	time::Instant,
+};
+use tracing::{trace, warn};
+
+/// Extension of the partially written copies, renamed onto their target once verified
+const PARTIAL_EXTENSION: &str = "sdpart";
+
+/// A file to be copied, with its target already accounting for the job's conflict policy on
+/// directories
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CopyEntry {
+	pub source: PathBuf,
+	pub target: PathBuf,
+	/// Whether the conflict policy was already applied to `target`, so a resumed copy keeps the
+	/// name it was given
+	#[serde(default)]
+	pub resolved: bool,
+}
+
+/// Sent by copier tasks as they go, so the job can report progress in the middle of large files
+#[derive(Debug)]
+pub enum CopyProgress {
+	/// A file started being copied, from `resumed_at` bytes if a partial copy was already there
+	Started {
+		path: PathBuf,
+		size: u64,
+		resumed_at: u64,
+	},
+	/// Bytes written to the current file since the last update
+	Copied(u64),
+	/// The current file was copied, skipped or failed
+	Finished,
+}
+
+/// Copies a batch of files, one after the other, verifying each copy against its source
+#[derive(Debug)]
+pub struct Copier {
+	id: TaskId,
+	entries: Vec<CopyEntry>,
+	conflict_policy: ConflictPolicy,
+	cas_id_algorithm: CasIdAlgorithm,
+	deep_hash_threshold: Option<u64>,
+	io_throttle: IoThrottle,
+	progress_tx: chan::Sender<(TaskId, CopyProgress)>,
+	output: Output,
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize, Type)]
+pub enum NonCriticalError {
+	#[error("failed to copy file: <source='{}', target='{}'>: {2}", .0.display(), .1.display())]
+	FailedToCopy(PathBuf, PathBuf, String),
+	#[error("copy doesn't match its source: <target='{}', expected='{1}', found='{2}'>", .0.display())]
+	VerificationFailed(PathBuf, String, String),
+	#[error("failed to find an available name to avoid a conflict: <path='{}'>", .0.display())]
+	FailedToFindAvailableName(PathBuf),
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Output {
+	pub copied_files: u64,
+	pub skipped_files: u64,
+	pub failed_files: u64,
+	pub copy_time: Duration,
+	pub verification_time: Duration,
+	pub errors: Vec<crate::NonCriticalError>,
+}
+
+enum CopyOutcome {
+	Copied,
+	Skipped,
+	Interrupted(InterruptionKind),
+}
+
+/// Outcome of [`copy_verified`] when it didn't fail
+pub(crate) enum VerifiedCopy {
+	Done,
+	Interrupted(InterruptionKind),
+}
+
+/// Sends the progress of a task to its job
+#[derive(Debug, Clone)]
+pub(crate) struct ProgressReporter {
+	pub(crate) task_id: TaskId,
+	pub(crate) progress_tx: chan::Sender<(TaskId, CopyProgress)>,
+}
+
+impl ProgressReporter {
+	pub(crate) async fn report(&self, progress: CopyProgress) {
+		if self
+			.progress_tx
+			.send((self.task_id, progress))
+			.await
+			.is_err()
+		{
+			trace!("Job stopped listening to copy progress");
+		}
+	}
+}
+
+impl Copier {
+	#[must_use]
+	pub fn new(
+		entries: Vec<CopyEntry>,
+		conflict_policy: ConflictPolicy,
+		cas_id_algorithm: CasIdAlgorithm,
+		deep_hash_threshold: Option<u64>,
+		progress_tx: chan::Sender<(TaskId, CopyProgress)>,
+	) -> Self {
+		Self {
+			id: TaskId::new_v4(),
+			entries,
+			conflict_policy,
+			cas_id_algorithm,
+			deep_hash_threshold,
+			io_throttle: IoThrottle::default(),
+			progress_tx,
+			output: Output::default(),
+		}
+	}
+
+	#[must_use]
+	pub fn with_io_throttle(mut self, io_throttle: IoThrottle) -> Self {
+		self.io_throttle = io_throttle;
+		self
+	}
+
+	fn reporter(&self) -> ProgressReporter {
+		ProgressReporter {
+			task_id: self.id,
+			progress_tx: self.progress_tx.clone(),
+		}
+	}
+
+	async fn copy(&mut self, interrupter: &Interrupter) -> Result<CopyOutcome, NonCriticalError> {
+		let Some(entry) = self.entries.last_mut() else {
+			return Ok(CopyOutcome::Skipped);
+		};
+
+		if !entry.resolved {
+			let target_exists = exists(&entry.target)
+				.await
+				.map_err(|e| failed_to_copy(entry, e))?;
+
+			if target_exists {
+				match self.conflict_policy {
+					ConflictPolicy::Skip => {
+						trace!(
+							"Skipping copy to existing <path='{}'>",
+							entry.target.display()
+						);
+						return Ok(CopyOutcome::Skipped);
+					}
+					ConflictPolicy::Rename => {
+						entry.target = available_path(&entry.target)
+							.await
+							.map_err(|e| failed_to_copy(entry, e))?
+							.ok_or_else(|| {
+								NonCriticalError::FailedToFindAvailableName(entry.target.clone())
+							})?;
+					}
+					ConflictPolicy::Overwrite => { /* Replaced by the rename once verified */ }
+				}
+			}
+
+			entry.resolved = true;
+		}
+
+		let entry = entry.clone();
+
+		match copy_verified(
+			&entry.source,
+			&entry.target,
+			self.cas_id_algorithm,
+			self.deep_hash_threshold,
+			&self.io_throttle,
+			interrupter,
+			&self.reporter(),
+			(
+				&mut self.output.copy_time,
+				&mut self.output.verification_time,
+			),
+		)
+		.await?
+		{
+			VerifiedCopy::Done => Ok(CopyOutcome::Copied),
+			VerifiedCopy::Interrupted(kind) => Ok(CopyOutcome::Interrupted(kind)),
+		}
+	}
+}
+
+/// Copies `source` to a partial file next to `target`, resuming it if a previous run left one,
+/// then moves it onto `target` once its `cas_id` matches the source's. The partial copy is kept
+/// when paused, to be resumed, and removed when canceled or failed.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn copy_verified(
+	source: &Path,
+	target: &Path,
+	cas_id_algorithm: CasIdAlgorithm,
+	deep_hash_threshold: Option<u64>,
+	io_throttle: &IoThrottle,
+	interrupter: &Interrupter,
+	reporter: &ProgressReporter,
+	(copy_time, verification_time): (&mut Duration, &mut Duration),
+) -> Result<VerifiedCopy, NonCriticalError> {
+	let failed = |e: FileIOError| {
+		NonCriticalError::FailedToCopy(source.to_path_buf(), target.to_path_buf(), e.to_string())
+	};
+
+	let partial = partial_path(target);
+
+	let start = Instant::now();
+	let written = write_partial(source, target, &partial, io_throttle, interrupter, reporter).await;
+	*copy_time += start.elapsed();
+
+	match written {
+		Ok(None) => {}
+		Ok(Some(InterruptionKind::Pause)) => {
+			return Ok(VerifiedCopy::Interrupted(InterruptionKind::Pause));
+		}
+		Ok(Some(InterruptionKind::Cancel)) => {
+			remove_partial(&partial).await;
+			return Ok(VerifiedCopy::Interrupted(InterruptionKind::Cancel));
+		}
+		Err(e) => {
+			remove_partial(&partial).await;
+			return Err(failed(e));
+		}
+	}
+
+	let start = Instant::now();
+	let verified = verify(
+		source,
+		&partial,
+		cas_id_algorithm,
+		deep_hash_threshold,
+		io_throttle,
+	)
+	.await;
+	*verification_time += start.elapsed();
+
+	match verified {
+		Ok(None) => {}
+		Ok(Some((expected, found))) => {
+			remove_partial(&partial).await;
+			return Err(NonCriticalError::VerificationFailed(
+				target.to_path_buf(),
+				expected,
+				found,
+			));
+		}
+		Err(e) => {
+			remove_partial(&partial).await;
+			return Err(failed(e));
+		}
+	}
+
+	fs::rename(&partial, target).await.map_err(|e| {
+		failed(FileIOError::from((
+			target,
+			e,
+			"Failed to move copy into place",
+		)))
+	})?;
+
+	Ok(VerifiedCopy::Done)
+}
+
+/// Appends to the partial copy from wherever a previous run stopped, returning early with the
+/// interruption kind if interrupted between chunks
+async fn write_partial(
+	source_path: &Path,
+	target_path: &Path,
+	partial: &Path,
+	io_throttle: &IoThrottle,
+	interrupter: &Interrupter,
+	reporter: &ProgressReporter,
+) -> Result<Option<InterruptionKind>, FileIOError> {
+	let mut source = File::open(source_path)
+		.await
+		.map_err(|e| FileIOError::from((source_path, e)))?;
+	let size = source
+		.metadata()
+		.await
+		.map_err(|e| FileIOError::from((source_path, e)))?
+		.len();
+
+	let mut target = OpenOptions::new()
+		.write(true)
+		.create(true)
+		.truncate(false)
+		.open(partial)
+		.await
+		.map_err(|e| FileIOError::from((partial, e)))?;
+
+	let mut offset = target
+		.metadata()
+		.await
+		.map_err(|e| FileIOError::from((partial, e)))?
+		.len();
+
+	if offset > size {
+		// The source shrank since the partial copy was written, starting over
+		target
+			.set_len(0)
+			.await
+			.map_err(|e| FileIOError::from((partial, e)))?;
+		offset = 0;
+	}
+
+	if offset > 0 {
+		trace!(
+			"Resuming copy of <path='{}'> at {offset} of {size} bytes",
+			source_path.display()
+		);
+	}
+
+	source
+		.seek(SeekFrom::Start(offset))
+		.await
+		.map_err(|e| FileIOError::from((source_path, e)))?;
+	target
+		.seek(SeekFrom::Start(offset))
+		.await
+		.map_err(|e| FileIOError::from((partial, e)))?;
+
+	reporter
+		.report(CopyProgress::Started {
+			path: target_path.to_path_buf(),
+			size,
+			resumed_at: offset,
+		})
+		.await;
+
+	let mut buf = vec![0; CHUNK_SIZE];
+
+	loop {
+		let read = source
+			.read(&mut buf)
+			.await
+			.map_err(|e| FileIOError::from((source_path, e)))?;
+
+		if read == 0 {
+			break;
+		}
+
+		io_throttle.consume(read as u64).await;
+
+		target
+			.write_all(&buf[..read])
+			.await
+			.map_err(|e| FileIOError::from((partial, e)))?;
+
+		reporter.report(CopyProgress::Copied(read as u64)).await;
+
+		if let Some(kind) = interrupter.try_check_interrupt() {
+			// Making sure what was written is on disk, to be resumed from
+			target
+				.flush()
+				.await
+				.map_err(|e| FileIOError::from((partial, e)))?;
+
+			return Ok(Some(kind));
+		}
+	}
+
+	target
+		.flush()
+		.await
+		.map_err(|e| FileIOError::from((partial, e)))?;
+	target
+		.sync_all()
+		.await
+		.map_err(|e| FileIOError::from((partial, e)))?;
+
+	Ok(None)
+}
+
+/// Compares the `cas_id`s of the source and the copy, returning both when they differ
+async fn verify(
+	source: &Path,
+	partial: &Path,
+	cas_id_algorithm: CasIdAlgorithm,
+	deep_hash_threshold: Option<u64>,
+	io_throttle: &IoThrottle,
+) -> Result<Option<(String, String)>, FileIOError> {
+	let size = fs::metadata(source)
+		.await
+		.map_err(|e| FileIOError::from((source, e)))?
+		.len();
+	let algorithm = cas_id_algorithm.for_file_size(size, deep_hash_threshold);
+
+	let expected = generate_cas_id(source, size, algorithm, io_throttle)
+		.await
+		.map_err(|e| FileIOError::from((source, e)))?;
+
+	let copied_size = fs::metadata(partial)
+		.await
+		.map_err(|e| FileIOError::from((partial, e)))?
+		.len();
+
+	let found = generate_cas_id(partial, copied_size, algorithm, io_throttle)
+		.await
+		.map_err(|e| FileIOError::from((partial, e)))?;
+
+	Ok((expected != found).then_some((expected, found)))
+}
+
+#[async_trait::async_trait]
+impl Task<Error> for Copier {
+	fn id(&self) -> TaskId {
+		self.id
+	}
+
+	async fn run(&mut self, interrupter: &Interrupter) -> Result<ExecStatus, Error> {
+		// Copied entries are popped, so a resumed task picks up from the file it was copying
+		while !self.entries.is_empty() {
+			match self.copy(interrupter).await {
+				Ok(CopyOutcome::Copied) => self.output.copied_files += 1,
+				Ok(CopyOutcome::Skipped) => self.output.skipped_files += 1,
+				Ok(CopyOutcome::Interrupted(InterruptionKind::Pause)) => {
+					return Ok(ExecStatus::Paused);
+				}
+				Ok(CopyOutcome::Interrupted(InterruptionKind::Cancel)) => {
+					return Ok(ExecStatus::Canceled);
+				}
+				Err(e) => {
+					warn!("{e}");
+					self.output.failed_files += 1;
+					self.output
+						.errors
+						.push(file_copier::NonCriticalError::from(e).into());
+				}
+			}
+
+			self.entries.pop();
+			self.reporter().report(CopyProgress::Finished).await;
+
+			check_interruption!(interrupter);
+		}
+
+		Ok(ExecStatus::Done(mem::take(&mut self.output).into_output()))
+	}
+}
+
+pub(crate) fn partial_path(target: &Path) -> PathBuf {
+	let mut name = OsString::from(".");
+	name.push(target.file_name().unwrap_or_default());
+	name.push(".");
+	name.push(PARTIAL_EXTENSION);
+
+	target.with_file_name(name)
+}
+
+pub(crate) async fn remove_partial(partial: &Path) {
+	if let Err(e) = fs::remove_file(partial).await {
+		warn!(
+			"Failed to remove partial copy <path='{}'>: {e:#?}",
+			partial.display()
+		);
+	}
+}
+
+fn failed_to_copy(entry: &CopyEntry, e: FileIOError) -> NonCriticalError {
+	NonCriticalError::FailedToCopy(entry.source.clone(), entry.target.clone(), e.to_string())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SaveState {
+	id: TaskId,
+	entries: Vec<CopyEntry>,
+	conflict_policy: ConflictPolicy,
+	cas_id_algorithm: CasIdAlgorithm,
+	deep_hash_threshold: Option<u64>,
+	#[serde(default)]
+	io_throttle: IoThrottle,
+	output: Output,
+}
+
+impl SerializableTask<Error> for Copier {
+	type SerializeError = rmp_serde::encode::Error;
+
+	type DeserializeError = rmp_serde::decode::Error;
+
+	type DeserializeCtx = chan::Sender<(TaskId, CopyProgress)>;
+
+	async fn serialize(self) -> Result<Vec<u8>, Self::SerializeError> {
+		let Self {
+			id,
+			entries,
+			conflict_policy,
+			cas_id_algorithm,
+			deep_hash_threshold,
+			io_throttle,
+			output,
+			..
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState {
+			id,
+			entries,
+			conflict_policy,
+			cas_id_algorithm,
+			deep_hash_threshold,
+			io_throttle,
+			output,
+		})
+	}
+
+	async fn deserialize(
+		data: &[u8],
+		progress_tx: Self::DeserializeCtx,
+	) -> Result<Self, Self::DeserializeError> {
+		rmp_serde::from_slice(data).map(
+			|SaveState {
+			     id,
+			     entries,
+			     conflict_policy,
+			     cas_id_algorithm,
+			     deep_hash_threshold,
+			     io_throttle,
+			     output,
+			 }| Self {
+				id,
+				entries,
+				conflict_policy,
+				cas_id_algorithm,
+				deep_hash_threshold,
+				io_throttle,
+				progress_tx,
+				output,
+			},
+		)
+	}
+}
@@ -0,0 +1,3 @@
+pub mod copier;
+
+pub use copier::Copier;
@@ -0,0 +1,497 @@
+use sd_core_file_path_helper::IsolatedFilePathData;
+use sd_core_sync::Manager as SyncManager;
+
+use sd_file_ext::{custom_kind::KindRegistry, kind::ObjectKind};
+use sd_prisma::{
+	prisma::{file_path, location, PrismaClient},
+	prisma_sync,
+};
+use sd_sync::{option_sync_db_entry, sync_db_entry, OperationFactory};
+use sd_utils::{chain_optional_iter, msgpack, uuid_to_bytes};
+
+use std::{
+	collections::BTreeSet,
+	io,
+	path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, FixedOffset, Utc};
+use prisma_client_rust::{or, QueryError};
+use serde::{Deserialize, Serialize};
+use tokio::task::spawn_blocking;
+use uuid::Uuid;
+
+use super::{resolve_kind_from_extension, statistics, CasIdAlgorithm};
+
+/// Archives with more entries than this are left unwalked, so a zip bomb can't flood the library
+const MAX_ARCHIVE_ENTRIES: usize = 10_000;
+
+/// Archives whose entries add up to more than this once decompressed are left unwalked, as every
+/// entry is decompressed to be hashed and a few kilobytes can expand to terabytes
+const MAX_ARCHIVE_UNCOMPRESSED_SIZE: u64 = 8 * 1024 * 1024 * 1024;
+
+/// Archive formats whose entries can be identified without extracting them, chosen by extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+	Zip,
+	Tar,
+	TarGz,
+	SevenZip,
+}
+
+impl ArchiveFormat {
+	/// `None` for anything else, or for every file if the `archives` feature is disabled
+	#[must_use]
+	pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+		if !cfg!(feature = "archives") {
+			return None;
+		}
+
+		Self::from_name(path.as_ref().file_name()?.to_str()?)
+	}
+
+	/// Regardless of the `archives` feature, as entries walked before it was disabled must still
+	/// be cleaned up
+	pub(crate) fn from_name(name: &str) -> Option<Self> {
+		let name = name.to_lowercase();
+
+		if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+			Some(Self::TarGz)
+		} else if name.ends_with(".tar") {
+			Some(Self::Tar)
+		} else if name.ends_with(".zip") {
+			Some(Self::Zip)
+		} else if name.ends_with(".7z") {
+			Some(Self::SevenZip)
+		} else {
+			None
+		}
+	}
+}
+
+/// Entries read from an archive, to be saved as virtual file paths by the object processor
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalkedArchive {
+	pub location_id: location::id::Type,
+	/// Materialized path of the archive's top level entries, the archive's own path followed by `/`
+	pub entries_root: String,
+	pub entries: Vec<ArchiveEntry>,
+}
+
+/// A file inside an archive, identified from its content as read from the archive
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveEntry {
+	/// Path of the entry inside the archive, with `/` separators and without a leading one
+	pub path: String,
+	pub size: u64,
+	pub kind: ObjectKind,
+	pub custom_kind: Option<String>,
+	/// `None` for empty entries
+	pub cas_id: Option<String>,
+}
+
+/// Reads every file entry of an archive, resolving its kind from its extension and hashing its
+/// content with the same `cas_id` algorithm used for files on disk, so an entry and its extracted
+/// copy share an object. Archives nested inside the archive aren't walked.
+pub async fn walk(
+	path: PathBuf,
+	iso_file_path: &IsolatedFilePathData<'_>,
+	format: ArchiveFormat,
+	cas_id_algorithm: CasIdAlgorithm,
+	deep_hash_threshold: Option<u64>,
+	kind_registry: KindRegistry,
+) -> Result<WalkedArchive, io::Error> {
+	let entries = spawn_blocking(move || {
+		let mut entries = vec![];
+		let mut total_size = 0u64;
+
+		let mut on_entry = |entry_path: &str, size: u64, reader: &mut dyn io::Read| {
+			if entries.len() == MAX_ARCHIVE_ENTRIES {
+				return Err(io::Error::other(format!(
+					"archive has more than {MAX_ARCHIVE_ENTRIES} entries"
+				)));
+			}
+
+			// Readers are bounded by the declared sizes, so an entry lying about its size can't
+			// make us decompress more than this either
+			total_size = total_size.saturating_add(size);
+			if total_size > MAX_ARCHIVE_UNCOMPRESSED_SIZE {
+				return Err(io::Error::other(format!(
+					"archive has more than {MAX_ARCHIVE_UNCOMPRESSED_SIZE} uncompressed bytes"
+				)));
+			}
+
+			let Some(entry_path) = sanitize_entry_path(entry_path) else {
+				return Ok(());
+			};
+
+			let (kind, custom_kind) =
+				resolve_kind_from_extension(Path::new(&entry_path), &kind_registry);
+
+			let cas_id = if size != 0 {
+				Some(super::cas_id::generate_cas_id_from_reader(
+					reader,
+					size,
+					cas_id_algorithm.for_file_size(size, deep_hash_threshold),
+				)?)
+			} else {
+				None
+			};
+
+			entries.push(ArchiveEntry {
+				path: entry_path,
+				size,
+				kind,
+				custom_kind,
+				cas_id,
+			});
+
+			Ok(())
+		};
+
+		read_entries(&path, format, &mut on_entry)?;
+
+		Ok::<_, io::Error>(entries)
+	})
+	.await??;
+
+	Ok(WalkedArchive {
+		location_id: iso_file_path.location_id(),
+		entries_root: entries_root(
+			iso_file_path.to_parts().materialized_path,
+			&iso_file_path.full_name(),
+		),
+		entries,
+	})
+}
+
+/// Materialized path of the top level entries of the archive at `materialized_path`/`full_name`
+pub(crate) fn entries_root(materialized_path: &str, full_name: &str) -> String {
+	format!("{materialized_path}{full_name}/")
+}
+
+/// Drops entries that would escape the archive (e.g. `../../etc/passwd`) and normalizes separators
+fn sanitize_entry_path(entry_path: &str) -> Option<String> {
+	let components = entry_path
+		.split(['/', '\\'])
+		.filter(|component| !component.is_empty() && *component != ".")
+		.collect::<Vec<_>>();
+
+	(!components.is_empty() && !components.contains(&"..")).then(|| components.join("/"))
+}
+
+#[cfg(feature = "archives")]
+fn read_entries(
+	path: &Path,
+	format: ArchiveFormat,
+	on_entry: &mut dyn FnMut(&str, u64, &mut dyn io::Read) -> Result<(), io::Error>,
+) -> Result<(), io::Error> {
+	use std::fs::File;
+
+	match format {
+		ArchiveFormat::Zip => {
+			let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+
+			for index in 0..archive.len() {
+				let mut entry = archive.by_index(index)?;
+				if entry.is_file() {
+					let name = entry.name().to_string();
+					on_entry(&name, entry.size(), &mut entry)?;
+				}
+			}
+
+			Ok(())
+		}
+
+		ArchiveFormat::Tar => read_tar_entries(File::open(path)?, on_entry),
+
+		ArchiveFormat::TarGz => {
+			read_tar_entries(flate2::read::GzDecoder::new(File::open(path)?), on_entry)
+		}
+
+		ArchiveFormat::SevenZip => {
+			let mut archive = sevenz_rust::SevenZReader::open(path, sevenz_rust::Password::empty())
+				.map_err(io::Error::other)?;
+
+			let mut result = Ok(());
+
+			archive
+				.for_each_entries(|entry, reader| {
+					if entry.is_directory() {
+						return Ok(true);
+					}
+
+					result = on_entry(entry.name(), entry.size(), reader);
+
+					Ok(result.is_ok())
+				})
+				.map_err(io::Error::other)?;
+
+			result
+		}
+	}
+}
+
+#[cfg(feature = "archives")]
+fn read_tar_entries(
+	reader: impl io::Read,
+	on_entry: &mut dyn FnMut(&str, u64, &mut dyn io::Read) -> Result<(), io::Error>,
+) -> Result<(), io::Error> {
+	let mut archive = tar::Archive::new(reader);
+
+	for entry in archive.entries()? {
+		let mut entry = entry?;
+		if entry.header().entry_type().is_file() {
+			let name = entry.path()?.to_string_lossy().into_owned();
+			let size = entry.size();
+			on_entry(&name, size, &mut entry)?;
+		}
+	}
+
+	Ok(())
+}
+
+#[cfg(not(feature = "archives"))]
+fn read_entries(
+	_: &Path,
+	_: ArchiveFormat,
+	_: &mut dyn FnMut(&str, u64, &mut dyn io::Read) -> Result<(), io::Error>,
+) -> Result<(), io::Error> {
+	unreachable!("archive formats are only detected with the `archives` feature")
+}
+
+/// Creates virtual file paths for the entries of an archive, children of the archive's own file
+/// path, along with the directories implied by their paths. Entries from a previous walk of the
+/// same archive are removed first, as its content may have changed.
+///
+/// Returns the pub id of the file path created for each entry, in the same order, so objects can
+/// be assigned to them.
+pub async fn save_entries(
+	WalkedArchive {
+		location_id: archive_location_id,
+		entries_root,
+		entries,
+	}: &WalkedArchive,
+	archive_date_created: Option<DateTime<FixedOffset>>,
+	db: &PrismaClient,
+	sync: &SyncManager,
+) -> Result<Vec<Uuid>, QueryError> {
+	use file_path::{
+		create_unchecked, date_created, date_indexed, extension, in_archive, is_dir, location_id,
+		materialized_path, name, size_in_bytes_bytes,
+	};
+
+	let Some(location_pub_id) = db
+		.location()
+		.find_unique(location::id::equals(*archive_location_id))
+		.select(location::select!({ pub_id }))
+		.exec()
+		.await?
+		.map(|location| location.pub_id)
+	else {
+		// The location was removed while identifying it
+		return Ok(vec![]);
+	};
+
+	remove_entries_under(*archive_location_id, entries_root.clone(), db, sync).await?;
+
+	let directories = entries
+		.iter()
+		.flat_map(|entry| {
+			entry
+				.path
+				.match_indices('/')
+				.map(|(idx, _)| &entry.path[..idx])
+		})
+		.collect::<BTreeSet<_>>();
+
+	let now: DateTime<FixedOffset> = Utc::now().into();
+
+	let mut entries_pub_ids = Vec::with_capacity(entries.len());
+
+	let (sync_stuff, paths) = directories
+		.into_iter()
+		.map(|directory| (directory, true, 0))
+		.chain(
+			entries
+				.iter()
+				.map(|entry| (entry.path.as_str(), false, entry.size)),
+		)
+		.map(|(entry_path, entry_is_dir, size)| {
+			let (parent, entry_name) = entry_path.rsplit_once('/').unwrap_or(("", entry_path));
+
+			let entry_materialized_path = if parent.is_empty() {
+				entries_root.clone()
+			} else {
+				format!("{entries_root}{parent}/")
+			};
+
+			let (entry_name, entry_extension) = if entry_is_dir {
+				(entry_name, "")
+			} else {
+				IsolatedFilePathData::separate_name_and_extension_from_str(entry_name)
+					.unwrap_or((entry_name, ""))
+			};
+
+			let pub_id = Uuid::new_v4();
+			if !entry_is_dir {
+				entries_pub_ids.push(pub_id);
+			}
+
+			let (sync_params, db_params) = chain_optional_iter(
+				[
+					(
+						(
+							file_path::location::NAME,
+							msgpack!(prisma_sync::location::SyncId {
+								pub_id: location_pub_id.clone()
+							}),
+						),
+						location_id::set(Some(*archive_location_id)),
+					),
+					sync_db_entry!(entry_materialized_path, materialized_path),
+					sync_db_entry!(entry_name.to_string(), name),
+					sync_db_entry!(entry_is_dir, is_dir),
+					sync_db_entry!(entry_extension.to_string(), extension),
+					sync_db_entry!(size.to_be_bytes().to_vec(), size_in_bytes_bytes),
+					sync_db_entry!(now, date_indexed),
+					sync_db_entry!(true, in_archive),
+				],
+				[option_sync_db_entry!(archive_date_created, date_created)],
+			)
+			.into_iter()
+			.unzip::<_, _, Vec<_>, Vec<_>>();
+
+			(
+				sync.shared_create(
+					prisma_sync::file_path::SyncId {
+						pub_id: uuid_to_bytes(pub_id),
+					},
+					sync_params,
+				),
+				create_unchecked(uuid_to_bytes(pub_id), db_params),
+			)
+		})
+		.unzip::<_, _, Vec<_>, Vec<_>>();
+
+	sync.write_ops(
+		db,
+		(
+			sync_stuff.into_iter().flatten().collect(),
+			db.file_path().create_many(paths).skip_duplicates(),
+		),
+	)
+	.await?;
+
+	Ok(entries_pub_ids)
+}
+
+/// Removes the virtual file paths of the entries of the archives among `file_path_ids`, which are
+/// stale once their archive is removed or its content changes. Must be called before removing the
+/// archives' own file paths. A changed archive gets its entries back the next time it's
+/// identified with archive walking.
+pub async fn remove_archive_entries(
+	file_path_ids: Vec<file_path::id::Type>,
+	db: &PrismaClient,
+	sync: &SyncManager,
+) -> Result<u64, QueryError> {
+	if file_path_ids.is_empty() {
+		return Ok(0);
+	}
+
+	let archives = db
+		.file_path()
+		.find_many(vec![
+			file_path::id::in_vec(file_path_ids),
+			file_path::is_dir::equals(Some(false)),
+			not_in_archive(),
+		])
+		.select(file_path::select!({ location_id materialized_path name extension }))
+		.exec()
+		.await?
+		.into_iter()
+		.filter_map(|file_path| {
+			let name = file_path.name?;
+			let full_name = match file_path.extension {
+				Some(extension) if !extension.is_empty() => format!("{name}.{extension}"),
+				_ => name,
+			};
+
+			ArchiveFormat::from_name(&full_name)?;
+
+			Some((
+				file_path.location_id?,
+				entries_root(&file_path.materialized_path?, &full_name),
+			))
+		})
+		.collect::<Vec<_>>();
+
+	let mut removed_count = 0;
+	for (location_id, entries_root) in archives {
+		removed_count += remove_entries_under(location_id, entries_root, db, sync).await?;
+	}
+
+	Ok(removed_count)
+}
+
+async fn remove_entries_under(
+	archive_location_id: location::id::Type,
+	entries_root: String,
+	db: &PrismaClient,
+	sync: &SyncManager,
+) -> Result<u64, QueryError> {
+	let entries = db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(Some(archive_location_id)),
+			file_path::materialized_path::starts_with(entries_root),
+			file_path::in_archive::equals(Some(true)),
+		])
+		.select(file_path::select!({ id pub_id }))
+		.exec()
+		.await?;
+
+	if entries.is_empty() {
+		return Ok(0);
+	}
+
+	let (sync_params, ids) = entries
+		.into_iter()
+		.map(|file_path| {
+			(
+				sync.shared_delete(prisma_sync::file_path::SyncId {
+					pub_id: file_path.pub_id,
+				}),
+				file_path.id,
+			)
+		})
+		.unzip::<_, _, Vec<_>, Vec<_>>();
+
+	db._transaction()
+		.with_timeout(30 * 1000)
+		.run(|db| async move {
+			statistics::forget(vec![file_path::id::in_vec(ids.clone())], &db).await?;
+
+			sync.write_ops(
+				&db,
+				(
+					sync_params,
+					db.file_path().delete_many(vec![file_path::id::in_vec(ids)]),
+				),
+			)
+			.await
+		})
+		.await
+		.map(
+			#[allow(clippy::cast_sign_loss)]
+			|count| count as u64,
+		)
+}
+
+/// Archive entries only exist in the database, so jobs reading files from disk must skip them
+pub fn not_in_archive() -> file_path::WhereParam {
+	or!(
+		file_path::in_archive::equals(None),
+		file_path::in_archive::equals(Some(false))
+	)
+}
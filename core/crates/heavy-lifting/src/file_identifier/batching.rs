@@ -0,0 +1,95 @@
+use std::{
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc,
+	},
+	time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use super::CHUNK_SIZE;
+
+/// How long we want the database to take committing a batch of identified files, batches committed
+/// faster than this grow and slower ones shrink
+const TARGET_COMMIT_LATENCY: Duration = Duration::from_millis(500);
+
+/// Bounds for the amount of orphans identified by each task, see [`AdaptiveBatchSize`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct BatchSizeBounds {
+	pub min: usize,
+	pub max: usize,
+}
+
+impl Default for BatchSizeBounds {
+	fn default() -> Self {
+		Self { min: 25, max: 1000 }
+	}
+}
+
+/// Amount of orphans handed to each task, adjusted from how long the database took to commit the
+/// previous batches. Slow storage (e.g. SQLite on an SD card) ends up with small batches, keeping
+/// each commit short, while fast NVMe drives get bigger ones to cut the per commit overhead.
+///
+/// Clones share the current size, so the orphans seeker picks up adjustments for its next page.
+#[derive(Debug, Clone)]
+pub struct AdaptiveBatchSize {
+	bounds: BatchSizeBounds,
+	current: Arc<AtomicUsize>,
+}
+
+impl AdaptiveBatchSize {
+	#[must_use]
+	pub fn new(bounds: BatchSizeBounds, current: Option<usize>) -> Self {
+		let bounds = BatchSizeBounds {
+			min: bounds.min.max(1),
+			max: bounds.max.max(bounds.min.max(1)),
+		};
+
+		Self {
+			current: Arc::new(AtomicUsize::new(
+				current.unwrap_or(CHUNK_SIZE).clamp(bounds.min, bounds.max),
+			)),
+			bounds,
+		}
+	}
+
+	#[must_use]
+	pub const fn bounds(&self) -> BatchSizeBounds {
+		self.bounds
+	}
+
+	#[must_use]
+	pub fn get(&self) -> usize {
+		self.current.load(Ordering::Relaxed)
+	}
+
+	/// Adjusts the batch size so the next batches take about [`TARGET_COMMIT_LATENCY`] to commit,
+	/// at most doubling it at once. Returns the new batch size.
+	#[allow(
+		clippy::cast_precision_loss,
+		clippy::cast_possible_truncation,
+		clippy::cast_sign_loss
+	)]
+	// SAFETY: batch sizes are small and positive, so they fit in a f64 and back
+	pub fn record_commit(&self, committed_files: u64, latency: Duration) -> usize {
+		let current = self.get();
+
+		// Partial batches, like the last one of a directory, tell us nothing about a full one
+		if committed_files == 0 || (committed_files as usize) < current / 2 {
+			return current;
+		}
+
+		let files_per_target_latency = committed_files as f64 * TARGET_COMMIT_LATENCY.as_secs_f64()
+			/ latency.as_secs_f64().max(f64::EPSILON);
+
+		let new = (files_per_target_latency.round() as usize)
+			.min(current.saturating_mul(2))
+			.clamp(self.bounds.min, self.bounds.max);
+
+		self.current.store(new, Ordering::Relaxed);
+
+		new
+	}
+}
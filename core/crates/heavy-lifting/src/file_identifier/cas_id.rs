@@ -1,12 +1,21 @@
-use std::path::Path;
+use crate::utils::io_throttle::IoThrottle;
 
-use blake3::Hasher;
+use sd_task_system::{Interrupter, InterruptionKind};
+
+use std::{io::Read, mem, ops::Range, path::Path, slice};
+
+use blake3::{guts, Hasher};
+use serde::{Deserialize, Serialize};
+use sha2::{digest::generic_array::GenericArray, Digest, Sha256};
+use specta::Type;
 use static_assertions::const_assert;
 use tokio::{
 	fs::{self, File},
 	io::{self, AsyncReadExt, AsyncSeekExt, SeekFrom},
 };
 
+use super::sparse;
+
 const SAMPLE_COUNT: u64 = 4;
 const SAMPLE_SIZE: u64 = 1024 * 10;
 const HEADER_OR_FOOTER_SIZE: u64 = 1024 * 8;
@@ -14,19 +23,470 @@ const HEADER_OR_FOOTER_SIZE: u64 = 1024 * 8;
 // minimum file size of 100KiB, to avoid sample hashing for small files as they can be smaller than the total sample size
 const MINIMUM_FILE_SIZE: u64 = 1024 * 100;
 
+// buffer size used when we need to stream the entire file through a hasher
+const FULL_HASH_BUFFER_SIZE: usize = 1024 * 64;
+
 // Asserting that nobody messed up our consts
 const_assert!((HEADER_OR_FOOTER_SIZE * 2 + SAMPLE_COUNT * SAMPLE_SIZE) < MINIMUM_FILE_SIZE);
 
 // Asserting that the sample size is larger than header/footer size, as the same buffer is used for both
 const_assert!(SAMPLE_SIZE > HEADER_OR_FOOTER_SIZE);
 
+/// Hashing scheme used to generate `cas_id`s, stored per library.
+///
+/// `Blake3Sampled` is Spacedrive's historical scheme, it only hashes some samples of large files and
+/// truncates the result. The other variants hash the entire file content and keep the whole digest,
+/// so they match the output of external tools like `b3sum` and `sha256sum`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Type)]
+pub enum CasIdAlgorithm {
+	#[default]
+	Blake3Sampled,
+	Blake3Full,
+	Sha256,
+}
+
+impl CasIdAlgorithm {
+	/// When a deep hash threshold is set, files up to that size are hashed entirely with BLAKE3
+	/// instead of being sampled, as sampling collides on files that only differ in unsampled regions
+	#[must_use]
+	pub const fn for_file_size(self, size: u64, deep_hash_threshold: Option<u64>) -> Self {
+		match (self, deep_hash_threshold) {
+			(Self::Blake3Sampled, Some(threshold)) if size <= threshold => Self::Blake3Full,
+			_ => self,
+		}
+	}
+}
+
+/// Reads are accounted in `io_throttle`, waiting for it when the job is over its disk read cap
+pub async fn generate_cas_id(
+	path: impl AsRef<Path> + Send,
+	size: u64,
+	algorithm: CasIdAlgorithm,
+	io_throttle: &IoThrottle,
+) -> Result<String, io::Error> {
+	match algorithm {
+		CasIdAlgorithm::Blake3Sampled => generate_sampled_blake3(path, size, io_throttle).await,
+		CasIdAlgorithm::Blake3Full => {
+			let mut hasher = Hasher::new();
+			stream_file(path, io_throttle, |chunk| {
+				hasher.update(chunk);
+			})
+			.await?;
+
+			Ok(hasher.finalize().to_hex().to_string())
+		}
+		CasIdAlgorithm::Sha256 => {
+			let mut hasher = Sha256::new();
+			stream_file(path, io_throttle, |chunk| hasher.update(chunk)).await?;
+
+			Ok(format!("{:x}", hasher.finalize()))
+		}
+	}
+}
+
+/// Where the hashing of a file stopped when its task was interrupted, so it can go on from there,
+/// even after its task was serialized on shutdown.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartialCasId {
+	algorithm: CasIdAlgorithm,
+	size: u64,
+	offset: u64,
+	hasher: PartialHasher,
+}
+
+/// Hasher states that can be serialized, which the hashers of `blake3` and `sha2` can't export.
+/// They're built on the same primitives, so they get the same digests.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum PartialHasher {
+	Blake3(Blake3State),
+	Sha256(Sha256State),
+}
+
+impl PartialHasher {
+	fn new(algorithm: CasIdAlgorithm) -> Self {
+		match algorithm {
+			CasIdAlgorithm::Blake3Sampled | CasIdAlgorithm::Blake3Full => {
+				Self::Blake3(Blake3State::default())
+			}
+			CasIdAlgorithm::Sha256 => Self::Sha256(Sha256State::default()),
+		}
+	}
+
+	fn update(&mut self, input: &[u8]) {
+		match self {
+			Self::Blake3(state) => state.update(input),
+			Self::Sha256(state) => state.update(input),
+		}
+	}
+
+	fn finalize(self) -> String {
+		match self {
+			Self::Blake3(state) => state.finalize(),
+			Self::Sha256(state) => state.finalize(),
+		}
+	}
+}
+
+/// BLAKE3 tree of the content so far: the chaining values of its complete subtrees, merged the same
+/// way `blake3::Hasher` does, and the last chunk, which can only be hashed once we know whether it's
+/// the root. Chunks are hashed one at a time, slower than `blake3::Hasher` hashing several at once,
+/// but still faster than most disks.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Blake3State {
+	cv_stack: Vec<[u8; 32]>,
+	chunk_counter: u64,
+	last_chunk: Vec<u8>,
+}
+
+impl Blake3State {
+	fn update(&mut self, mut input: &[u8]) {
+		if !self.last_chunk.is_empty() {
+			let missing = (guts::CHUNK_LEN - self.last_chunk.len()).min(input.len());
+			self.last_chunk.extend_from_slice(&input[..missing]);
+			input = &input[missing..];
+
+			if input.is_empty() {
+				return;
+			}
+
+			let chunk = mem::take(&mut self.last_chunk);
+			self.push_chunk(&chunk);
+		}
+
+		while input.len() > guts::CHUNK_LEN {
+			let (chunk, rest) = input.split_at(guts::CHUNK_LEN);
+			self.push_chunk(chunk);
+			input = rest;
+		}
+
+		self.last_chunk.extend_from_slice(input);
+	}
+
+	fn push_chunk(&mut self, chunk: &[u8]) {
+		let mut cv = guts::ChunkState::new(self.chunk_counter)
+			.update(chunk)
+			.finalize(false);
+		self.chunk_counter += 1;
+
+		// A subtree is complete for each trailing zero bit of the chunk count
+		let mut total_chunks = self.chunk_counter;
+		while total_chunks & 1 == 0 {
+			// SAFETY: there is a left subtree for each one of them
+			let left = self.cv_stack.pop().expect("missing left subtree");
+			cv = guts::parent_cv(&left.into(), &cv, false);
+			total_chunks >>= 1;
+		}
+
+		self.cv_stack.push(cv.into());
+	}
+
+	fn finalize(self) -> String {
+		let mut last_chunk = guts::ChunkState::new(self.chunk_counter);
+		last_chunk.update(&self.last_chunk);
+
+		let Some((root_left, lefts)) = self.cv_stack.split_first() else {
+			return last_chunk.finalize(true).to_hex().to_string();
+		};
+
+		let cv = lefts
+			.iter()
+			.rev()
+			.fold(last_chunk.finalize(false), |cv, left| {
+				guts::parent_cv(&(*left).into(), &cv, false)
+			});
+
+		guts::parent_cv(&(*root_left).into(), &cv, true)
+			.to_hex()
+			.to_string()
+	}
+}
+
+/// SHA-256 state after the complete blocks of the content so far, and the bytes of the incomplete one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Sha256State {
+	state: [u32; 8],
+	len: u64,
+	last_block: Vec<u8>,
+}
+
+const SHA256_BLOCK_LEN: usize = 64;
+
+impl Default for Sha256State {
+	fn default() -> Self {
+		Self {
+			state: [
+				0x6a09_e667,
+				0xbb67_ae85,
+				0x3c6e_f372,
+				0xa54f_f53a,
+				0x510e_527f,
+				0x9b05_688c,
+				0x1f83_d9ab,
+				0x5be0_cd19,
+			],
+			len: 0,
+			last_block: Vec::with_capacity(SHA256_BLOCK_LEN),
+		}
+	}
+}
+
+impl Sha256State {
+	fn update(&mut self, mut input: &[u8]) {
+		self.len += input.len() as u64;
+
+		if !self.last_block.is_empty() {
+			let missing = (SHA256_BLOCK_LEN - self.last_block.len()).min(input.len());
+			self.last_block.extend_from_slice(&input[..missing]);
+			input = &input[missing..];
+
+			if self.last_block.len() < SHA256_BLOCK_LEN {
+				return;
+			}
+
+			let block = mem::take(&mut self.last_block);
+			self.compress(&block);
+		}
+
+		let complete_len = input.len() - input.len() % SHA256_BLOCK_LEN;
+		self.compress(&input[..complete_len]);
+		self.last_block.extend_from_slice(&input[complete_len..]);
+	}
+
+	fn compress(&mut self, blocks: &[u8]) {
+		for block in blocks.chunks_exact(SHA256_BLOCK_LEN) {
+			sha2::compress256(
+				&mut self.state,
+				slice::from_ref(GenericArray::from_slice(block)),
+			);
+		}
+	}
+
+	fn finalize(mut self) -> String {
+		// Padding with a single set bit, then zeros up to the length in bits at the end of a block
+		let bit_len = self.len * 8;
+		let mut padding = mem::take(&mut self.last_block);
+		padding.push(0x80);
+		padding.resize(
+			padding.len() + (SHA256_BLOCK_LEN * 2 - 8 - padding.len()) % SHA256_BLOCK_LEN,
+			0,
+		);
+		padding.extend_from_slice(&bit_len.to_be_bytes());
+		self.compress(&padding);
+
+		self.state
+			.iter()
+			.map(|word| format!("{word:08x}"))
+			.collect()
+	}
+}
+
+#[derive(Debug)]
+pub enum CasIdProgress {
+	Done(String),
+	/// The interruption was consumed while hashing, so the caller must pause or cancel its task
+	Interrupted(PartialCasId, InterruptionKind),
+}
+
+/// Same as [`generate_cas_id`], but full file hashes are read in chunks checked against the
+/// `interrupter`, so a pause doesn't have to wait for a multi-gigabyte file to be hashed.
+/// Sampled hashes only read a few KiB, so they always run to completion.
+///
+/// A `partial` hash is ignored if the file size changed since it was interrupted.
+pub async fn generate_cas_id_interruptible(
+	path: impl AsRef<Path> + Send,
+	size: u64,
+	algorithm: CasIdAlgorithm,
+	partial: Option<PartialCasId>,
+	io_throttle: &IoThrottle,
+	interrupter: &Interrupter,
+) -> Result<CasIdProgress, io::Error> {
+	let (mut hasher, mut offset) = match partial {
+		Some(partial) if partial.algorithm == algorithm && partial.size == size => {
+			(partial.hasher, partial.offset)
+		}
+
+		_ if algorithm == CasIdAlgorithm::Blake3Sampled => {
+			return generate_sampled_blake3(path, size, io_throttle)
+				.await
+				.map(CasIdProgress::Done);
+		}
+
+		_ => (PartialHasher::new(algorithm), 0),
+	};
+
+	let mut file = File::open(path).await?;
+	if offset != 0 {
+		file.seek(SeekFrom::Start(offset)).await?;
+	}
+
+	let mut buf = vec![0; FULL_HASH_BUFFER_SIZE].into_boxed_slice();
+
+	loop {
+		if let Some(kind) = interrupter.try_check_interrupt() {
+			return Ok(CasIdProgress::Interrupted(
+				PartialCasId {
+					algorithm,
+					size,
+					offset,
+					hasher,
+				},
+				kind,
+			));
+		}
+
+		let read = file.read(&mut buf).await?;
+		if read == 0 {
+			break;
+		}
+
+		io_throttle.consume(read as u64).await;
+		hasher.update(&buf[..read]);
+		offset += read as u64;
+	}
+
+	Ok(CasIdProgress::Done(hasher.finalize()))
+}
+
+/// Same as [`generate_cas_id`] for content that can only be read once from start to end, like the
+/// entries of a compressed archive. Sampled hashes read and discard the bytes between samples, so
+/// the content gets the same `cas_id` as it would have as a file on disk.
+///
+/// It blocks while reading, so it must run on a blocking thread.
+// SAFETY: Casts here are safe, they're hardcoded values we have some const assertions above to make sure they're correct
+#[allow(clippy::cast_possible_truncation)]
+pub fn generate_cas_id_from_reader(
+	mut reader: impl Read,
+	size: u64,
+	algorithm: CasIdAlgorithm,
+) -> Result<String, io::Error> {
+	match algorithm {
+		CasIdAlgorithm::Blake3Sampled => {
+			let mut hasher = Hasher::new();
+			hasher.update(&size.to_le_bytes());
+
+			if size <= MINIMUM_FILE_SIZE {
+				// For small files, we hash the whole content
+				let mut content = Vec::with_capacity(size as usize);
+				reader.take(size).read_to_end(&mut content)?;
+				hasher.update(&content);
+			} else {
+				let mut buf = vec![0; SAMPLE_SIZE as usize].into_boxed_slice();
+
+				// Hashing the header
+				reader.read_exact(&mut buf[..HEADER_OR_FOOTER_SIZE as usize])?;
+				hasher.update(&buf[..HEADER_OR_FOOTER_SIZE as usize]);
+				let mut current_pos = HEADER_OR_FOOTER_SIZE;
+
+				// Sampling at the same offsets as `generate_sampled_blake3` seeks to
+				let seek_jump = (size - HEADER_OR_FOOTER_SIZE * 2) / SAMPLE_COUNT;
+				for sample in 0..SAMPLE_COUNT {
+					let sample_pos = HEADER_OR_FOOTER_SIZE + seek_jump * sample;
+					skip(&mut reader, sample_pos - current_pos)?;
+
+					reader.read_exact(&mut buf)?;
+					hasher.update(&buf);
+					current_pos = sample_pos + SAMPLE_SIZE;
+				}
+
+				// Hashing the footer
+				skip(&mut reader, size - HEADER_OR_FOOTER_SIZE - current_pos)?;
+				reader.read_exact(&mut buf[..HEADER_OR_FOOTER_SIZE as usize])?;
+				hasher.update(&buf[..HEADER_OR_FOOTER_SIZE as usize]);
+			}
+
+			Ok(hasher.finalize().to_hex()[..16].to_string())
+		}
+		CasIdAlgorithm::Blake3Full => {
+			let mut hasher = Hasher::new();
+			read_to_end(reader, |chunk| {
+				hasher.update(chunk);
+			})?;
+
+			Ok(hasher.finalize().to_hex().to_string())
+		}
+		CasIdAlgorithm::Sha256 => {
+			let mut hasher = Sha256::new();
+			read_to_end(reader, |chunk| hasher.update(chunk))?;
+
+			Ok(format!("{:x}", hasher.finalize()))
+		}
+	}
+}
+
+fn read_to_end(mut reader: impl Read, mut update: impl FnMut(&[u8])) -> Result<(), io::Error> {
+	let mut buf = vec![0; FULL_HASH_BUFFER_SIZE].into_boxed_slice();
+
+	loop {
+		let read = reader.read(&mut buf)?;
+		if read == 0 {
+			return Ok(());
+		}
+
+		update(&buf[..read]);
+	}
+}
+
+fn skip(reader: &mut impl Read, bytes: u64) -> Result<(), io::Error> {
+	let skipped = std::io::copy(&mut reader.take(bytes), &mut std::io::sink())?;
+
+	if skipped == bytes {
+		Ok(())
+	} else {
+		Err(io::ErrorKind::UnexpectedEof.into())
+	}
+}
+
+/// Links don't have content of their own, so their `cas_id` is derived from where they point to,
+/// making every link to the same target share an object
+#[must_use]
+pub fn generate_link_cas_id(target: &Path) -> String {
+	let mut hasher = Hasher::new();
+	hasher.update(b"link:");
+	hasher.update(target.as_os_str().as_encoded_bytes());
+
+	hasher.finalize().to_hex()[..16].to_string()
+}
+
+async fn stream_file(
+	path: impl AsRef<Path> + Send,
+	io_throttle: &IoThrottle,
+	mut update: impl FnMut(&[u8]) + Send,
+) -> Result<(), io::Error> {
+	let mut file = File::open(path).await?;
+	let mut buf = vec![0; FULL_HASH_BUFFER_SIZE].into_boxed_slice();
+
+	loop {
+		let read = file.read(&mut buf).await?;
+		if read == 0 {
+			break;
+		}
+
+		io_throttle.consume(read as u64).await;
+
+		update(&buf[..read]);
+	}
+
+	Ok(())
+}
+
 // SAFETY: Casts here are safe, they're hardcoded values we have some const assertions above to make sure they're correct
 #[allow(clippy::cast_possible_truncation)]
 #[allow(clippy::cast_possible_wrap)]
-pub async fn generate_cas_id(
+async fn generate_sampled_blake3(
 	path: impl AsRef<Path> + Send,
 	size: u64,
+	io_throttle: &IoThrottle,
 ) -> Result<String, io::Error> {
+	io_throttle
+		.consume(size.min(HEADER_OR_FOOTER_SIZE * 2 + SAMPLE_COUNT * SAMPLE_SIZE))
+		.await;
+
+	if size > MINIMUM_FILE_SIZE {
+		if let Some(ranges) = sparse::allocated_ranges(path.as_ref(), size).await {
+			return generate_sampled_blake3_sparse(path, size, &ranges).await;
+		}
+	}
+
 	let mut hasher = Hasher::new();
 	hasher.update(&size.to_le_bytes());
 
@@ -66,3 +526,293 @@ pub async fn generate_cas_id(
 
 	Ok(hasher.finalize().to_hex()[..16].to_string())
 }
+
+/// Same `cas_id` as [`generate_sampled_blake3`], without reading the holes of a sparse file, as
+/// they read as zeroes anyway. Samples of mostly empty files (e.g. VM disk images) often land in
+/// holes, sparing their I/O.
+// SAFETY: Casts here are safe, they're hardcoded values we have some const assertions above to make sure they're correct
+#[allow(clippy::cast_possible_truncation)]
+async fn generate_sampled_blake3_sparse(
+	path: impl AsRef<Path> + Send,
+	size: u64,
+	ranges: &[Range<u64>],
+) -> Result<String, io::Error> {
+	let mut hasher = Hasher::new();
+	hasher.update(&size.to_le_bytes());
+
+	let mut file = File::open(path).await?;
+	let mut buf = vec![0; SAMPLE_SIZE as usize].into_boxed_slice();
+
+	// Hashing the header
+	read_sparse_at(
+		&mut file,
+		ranges,
+		0,
+		&mut buf[..HEADER_OR_FOOTER_SIZE as usize],
+	)
+	.await?;
+	hasher.update(&buf[..HEADER_OR_FOOTER_SIZE as usize]);
+
+	// Sample hashing the inner content, at the same offsets as `generate_sampled_blake3`
+	let seek_jump = (size - HEADER_OR_FOOTER_SIZE * 2) / SAMPLE_COUNT;
+	for sample in 0..SAMPLE_COUNT {
+		read_sparse_at(
+			&mut file,
+			ranges,
+			HEADER_OR_FOOTER_SIZE + seek_jump * sample,
+			&mut buf,
+		)
+		.await?;
+		hasher.update(&buf);
+	}
+
+	// Hashing the footer
+	read_sparse_at(
+		&mut file,
+		ranges,
+		size - HEADER_OR_FOOTER_SIZE,
+		&mut buf[..HEADER_OR_FOOTER_SIZE as usize],
+	)
+	.await?;
+	hasher.update(&buf[..HEADER_OR_FOOTER_SIZE as usize]);
+
+	Ok(hasher.finalize().to_hex()[..16].to_string())
+}
+
+/// Fills `buf` from `offset` of a sparse file, only reading the parts overlapping its data
+/// regions, the rest being holes
+// SAFETY: Positions are bounded by the buffer length
+#[allow(clippy::cast_possible_truncation)]
+async fn read_sparse_at(
+	file: &mut File,
+	ranges: &[Range<u64>],
+	offset: u64,
+	buf: &mut [u8],
+) -> Result<(), io::Error> {
+	let end = offset + buf.len() as u64;
+
+	buf.fill(0);
+
+	for range in ranges {
+		if range.start >= end {
+			break;
+		}
+
+		let start = range.start.max(offset);
+		let stop = range.end.min(end);
+		if start >= stop {
+			continue;
+		}
+
+		file.seek(SeekFrom::Start(start)).await?;
+		file.read_exact(&mut buf[(start - offset) as usize..(stop - offset) as usize])
+			.await?;
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use sd_task_system::{ExecStatus, Task, TaskId, TaskOutput, TaskStatus, TaskSystem};
+
+	use std::{num::NonZeroU64, path::PathBuf, time::Duration};
+
+	use tempfile::tempdir;
+	use tokio::{sync::oneshot, time::sleep};
+	use tracing_test::traced_test;
+
+	fn content(size: usize) -> Vec<u8> {
+		(0..size)
+			.map(|i| u8::try_from(i * 31 % 251).unwrap())
+			.collect()
+	}
+
+	#[test]
+	fn partial_hashers_match_digests() {
+		for size in [
+			0,
+			1,
+			55,
+			56,
+			64,
+			65,
+			1023,
+			1024,
+			1025,
+			2048,
+			3 * 1024 + 5,
+			200_003,
+		] {
+			let content = content(size);
+
+			for split in [1, 63, 64, 1000, 1024, FULL_HASH_BUFFER_SIZE] {
+				for algorithm in [CasIdAlgorithm::Blake3Full, CasIdAlgorithm::Sha256] {
+					let mut hasher = PartialHasher::new(algorithm);
+
+					for (i, chunk) in content.chunks(split).enumerate() {
+						hasher.update(chunk);
+
+						// As if the task was serialized in the middle of the hashing
+						if i == 2 {
+							hasher =
+								rmp_serde::from_slice(&rmp_serde::to_vec_named(&hasher).unwrap())
+									.unwrap();
+						}
+					}
+
+					let expected = match algorithm {
+						CasIdAlgorithm::Sha256 => format!("{:x}", Sha256::digest(&content)),
+						_ => blake3::hash(&content).to_hex().to_string(),
+					};
+
+					assert_eq!(
+						hasher.finalize(),
+						expected,
+						"<size={size}, split={split}, algorithm={algorithm:?}>"
+					);
+				}
+			}
+		}
+	}
+
+	/// Hashes a file the way `ExtractFileMetadataTask` does, serializing its partial hash on pause
+	#[derive(Debug)]
+	struct HashFileTask {
+		id: TaskId,
+		path: PathBuf,
+		size: u64,
+		algorithm: CasIdAlgorithm,
+		partial: Option<Vec<u8>>,
+		was_interrupted: bool,
+		io_throttle: IoThrottle,
+		began_tx: Option<oneshot::Sender<()>>,
+	}
+
+	#[async_trait::async_trait]
+	impl Task<io::Error> for HashFileTask {
+		fn id(&self) -> TaskId {
+			self.id
+		}
+
+		async fn run(&mut self, interrupter: &Interrupter) -> Result<ExecStatus, io::Error> {
+			if let Some(began_tx) = self.began_tx.take() {
+				began_tx.send(()).unwrap();
+			}
+
+			let partial = self
+				.partial
+				.take()
+				.map(|partial| rmp_serde::from_slice(&partial).unwrap());
+
+			match generate_cas_id_interruptible(
+				&self.path,
+				self.size,
+				self.algorithm,
+				partial,
+				&self.io_throttle,
+				interrupter,
+			)
+			.await?
+			{
+				CasIdProgress::Done(cas_id) => Ok(ExecStatus::Done(TaskOutput::Out(Box::new((
+					cas_id,
+					self.was_interrupted,
+				))))),
+				CasIdProgress::Interrupted(partial, kind) => {
+					assert!(partial.offset > 0 && partial.offset < self.size);
+					self.was_interrupted = true;
+					self.partial = Some(rmp_serde::to_vec_named(&partial).unwrap());
+
+					Ok(match kind {
+						InterruptionKind::Pause => ExecStatus::Paused,
+						InterruptionKind::Cancel => ExecStatus::Canceled,
+					})
+				}
+			}
+		}
+	}
+
+	#[tokio::test]
+	#[traced_test]
+	async fn pause_and_resume_full_hash() {
+		let dir = tempdir().unwrap();
+		let path = dir.path().join("file");
+		let content = content(1024 * 1024 + 13);
+		fs::write(&path, &content).await.unwrap();
+
+		let system = TaskSystem::new();
+
+		for algorithm in [CasIdAlgorithm::Blake3Full, CasIdAlgorithm::Sha256] {
+			let (began_tx, began_rx) = oneshot::channel();
+
+			// The first half is read right away, the other one takes a second
+			let handle = system
+				.dispatch(HashFileTask {
+					id: TaskId::new_v4(),
+					path: path.clone(),
+					size: content.len() as u64,
+					algorithm,
+					partial: None,
+					was_interrupted: false,
+					io_throttle: IoThrottle::new(NonZeroU64::new(512 * 1024).unwrap()),
+					began_tx: Some(began_tx),
+				})
+				.await;
+
+			began_rx.await.unwrap();
+			sleep(Duration::from_millis(100)).await;
+
+			handle.pause().await.unwrap();
+			handle.resume().await.unwrap();
+
+			let Ok(TaskStatus::Done((_, TaskOutput::Out(output)))) = handle.await else {
+				panic!("unexpected task status");
+			};
+
+			let (cas_id, was_interrupted) = *output.downcast::<(String, bool)>().unwrap();
+
+			assert!(was_interrupted);
+			assert_eq!(
+				cas_id,
+				generate_cas_id(
+					&path,
+					content.len() as u64,
+					algorithm,
+					&IoThrottle::default()
+				)
+				.await
+				.unwrap()
+			);
+		}
+
+		system.shutdown().await;
+	}
+
+	#[tokio::test]
+	async fn sparse_sampling_matches_regular_sampling() {
+		let dir = tempdir().unwrap();
+		let path = dir.path().join("file");
+
+		// Data at the start, in the middle and at the end, zeroes elsewhere as holes would read
+		let size = 4 * 1024 * 1024;
+		let mut file_content = vec![0; size];
+		let ranges =
+			[0..5000, 1_000_000..1_500_000, size - 100..size].map(|range: Range<usize>| {
+				file_content[range.clone()].copy_from_slice(&content(range.len()));
+				range.start as u64..range.end as u64
+			});
+		fs::write(&path, &file_content).await.unwrap();
+
+		assert_eq!(
+			generate_sampled_blake3_sparse(&path, size as u64, &ranges)
+				.await
+				.unwrap(),
+			generate_sampled_blake3(&path, size as u64, &IoThrottle::default())
+				.await
+				.unwrap()
+		);
+	}
+}
@@ -0,0 +1,198 @@
+use std::{fs::Metadata, path::Path};
+
+use tokio::task::spawn_blocking;
+use tracing::trace;
+
+/// Bytes of a file that aren't shared with other files through copy-on-write clones (reflinks on
+/// btrfs or XFS, clones on APFS), which is what deleting the file would actually free.
+///
+/// Returns `None` when the file system can't tell, callers should then assume nothing is shared.
+pub async fn physical_size(path: impl AsRef<Path> + Send, fs_metadata: &Metadata) -> Option<u64> {
+	let path = path.as_ref().to_path_buf();
+	let size = fs_metadata.len();
+
+	spawn_blocking(move || {
+		platform::shared_bytes(&path, size)
+			.map_err(|e| {
+				trace!(
+					"Unable to detect clones <path='{}'>: {e:#?}",
+					path.display()
+				)
+			})
+			.ok()
+			.flatten()
+			.map(|shared_bytes| size.saturating_sub(shared_bytes))
+	})
+	.await
+	.ok()
+	.flatten()
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+	use std::{fs::File, io, os::fd::AsRawFd, path::Path};
+
+	// From linux/fiemap.h and linux/fs.h
+	const FS_IOC_FIEMAP: u64 = 0xC020_660B;
+	const FIEMAP_EXTENT_LAST: u32 = 0x0000_0001;
+	const FIEMAP_EXTENT_SHARED: u32 = 0x0000_2000;
+	const EXTENTS_PER_CALL: usize = 64;
+
+	// Mirrors of the kernel structs, some fields are only read by the kernel
+	#[allow(dead_code)]
+	#[repr(C)]
+	#[derive(Default, Clone, Copy)]
+	struct FiemapExtent {
+		fe_logical: u64,
+		fe_physical: u64,
+		fe_length: u64,
+		fe_reserved64: [u64; 2],
+		fe_flags: u32,
+		fe_reserved: [u32; 3],
+	}
+
+	#[allow(dead_code)]
+	#[repr(C)]
+	struct Fiemap {
+		fm_start: u64,
+		fm_length: u64,
+		fm_flags: u32,
+		fm_mapped_extents: u32,
+		fm_extent_count: u32,
+		fm_reserved: u32,
+		fm_extents: [FiemapExtent; EXTENTS_PER_CALL],
+	}
+
+	/// Sums the length of the file extents flagged as shared by the file system
+	#[allow(clippy::cast_possible_truncation)]
+	pub fn shared_bytes(path: &Path, size: u64) -> Result<Option<u64>, io::Error> {
+		let file = File::open(path)?;
+
+		let mut shared_bytes = 0;
+		let mut start = 0;
+
+		while start < size {
+			let mut map = Fiemap {
+				fm_start: start,
+				fm_length: size - start,
+				fm_flags: 0,
+				fm_mapped_extents: 0,
+				fm_extent_count: EXTENTS_PER_CALL as u32,
+				fm_reserved: 0,
+				fm_extents: [FiemapExtent::default(); EXTENTS_PER_CALL],
+			};
+
+			// SAFETY: `map` is a valid `struct fiemap` with room for `fm_extent_count` extents and
+			// `file` keeps the descriptor open for the whole call
+			if unsafe { libc::ioctl(file.as_raw_fd(), FS_IOC_FIEMAP as _, &mut map) } != 0 {
+				let e = io::Error::last_os_error();
+				return match e.raw_os_error() {
+					// File systems without extents support can't share them
+					Some(libc::EOPNOTSUPP) => Ok(None),
+					_ => Err(e),
+				};
+			}
+
+			let extents = &map.fm_extents[..map.fm_mapped_extents as usize];
+
+			let Some(last) = extents.last() else {
+				break;
+			};
+
+			shared_bytes += extents
+				.iter()
+				.filter(|extent| extent.fe_flags & FIEMAP_EXTENT_SHARED != 0)
+				.map(|extent| extent.fe_length)
+				.sum::<u64>();
+
+			if last.fe_flags & FIEMAP_EXTENT_LAST != 0 {
+				break;
+			}
+
+			start = last.fe_logical + last.fe_length;
+		}
+
+		// The last extent may be longer than the file itself
+		Ok(Some(shared_bytes.min(size)))
+	}
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+	use std::{ffi::CString, io, mem, os::unix::ffi::OsStrExt, path::Path};
+
+	// From sys/attr.h, APFS reports the bytes private to a file since macOS 10.15
+	const ATTR_BIT_MAP_COUNT: u16 = 5;
+	const ATTR_CMN_RETURNED_ATTRS: u32 = 0x8000_0000;
+	const ATTR_CMNEXT_PRIVATESIZE: u32 = 0x0000_0008;
+	const FSOPT_ATTR_CMN_EXTENDED: u32 = 0x0000_0020;
+
+	#[repr(C)]
+	struct AttrList {
+		bitmapcount: u16,
+		reserved: u16,
+		commonattr: u32,
+		volattr: u32,
+		dirattr: u32,
+		fileattr: u32,
+		forkattr: u32,
+	}
+
+	#[repr(C, packed(4))]
+	struct AttrBuf {
+		_length: u32,
+		returned: [u32; ATTR_BIT_MAP_COUNT as usize],
+		private_size: i64,
+	}
+
+	pub fn shared_bytes(path: &Path, size: u64) -> Result<Option<u64>, io::Error> {
+		let c_path = CString::new(path.as_os_str().as_bytes())?;
+
+		let mut attr_list = AttrList {
+			bitmapcount: ATTR_BIT_MAP_COUNT,
+			reserved: 0,
+			commonattr: ATTR_CMN_RETURNED_ATTRS,
+			volattr: 0,
+			dirattr: 0,
+			fileattr: 0,
+			forkattr: ATTR_CMNEXT_PRIVATESIZE,
+		};
+
+		// SAFETY: an all zeroes buffer is a valid `AttrBuf`
+		let mut buf = unsafe { mem::zeroed::<AttrBuf>() };
+
+		// SAFETY: `c_path` is a valid C string, `attr_list` and `buf` outlive the call
+		if unsafe {
+			libc::getattrlist(
+				c_path.as_ptr(),
+				(&mut attr_list as *mut AttrList).cast(),
+				(&mut buf as *mut AttrBuf).cast(),
+				mem::size_of::<AttrBuf>(),
+				FSOPT_ATTR_CMN_EXTENDED,
+			)
+		} != 0
+		{
+			return Err(io::Error::last_os_error());
+		}
+
+		// Only APFS returns the private size, other file systems can't clone files anyway
+		if buf.returned[4] & ATTR_CMNEXT_PRIVATESIZE == 0 {
+			return Ok(None);
+		}
+
+		#[allow(clippy::cast_sign_loss)]
+		let private_size = buf.private_size.max(0) as u64;
+
+		Ok(Some(size.saturating_sub(private_size)))
+	}
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+mod platform {
+	use std::{io, path::Path};
+
+	#[allow(clippy::unnecessary_wraps)]
+	pub const fn shared_bytes(_: &Path, _: u64) -> Result<Option<u64>, io::Error> {
+		Ok(None)
+	}
+}
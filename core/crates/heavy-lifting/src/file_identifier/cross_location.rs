@@ -0,0 +1,39 @@
+use sd_core_prisma_helpers::{file_path_for_file_identifier, object_for_file_identifier};
+
+use sd_prisma::prisma::{file_path, location, object};
+
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+/// A file path linked to an object that already had file paths in other locations, like a copy of
+/// a file from another location. Reported once identification is done, so the user can be offered
+/// to consolidate the tags and notes of the object kept in the other locations.
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct CrossLocationLink {
+	pub file_path_id: file_path::id::Type,
+	pub object_id: object::id::Type,
+	pub other_location_ids: Vec<location::id::Type>,
+}
+
+impl CrossLocationLink {
+	/// `None` if every file path the object had was in the same location as `file_path`
+	pub(super) fn find(
+		file_path: &file_path_for_file_identifier::Data,
+		object: &object_for_file_identifier::Data,
+	) -> Option<Self> {
+		let other_location_ids = object
+			.file_paths
+			.iter()
+			.filter_map(|other_file_path| other_file_path.location_id)
+			.filter(|location_id| Some(*location_id) != file_path.location_id)
+			.collect::<BTreeSet<_>>();
+
+		(!other_location_ids.is_empty()).then(|| Self {
+			file_path_id: file_path.id,
+			object_id: object.id,
+			other_location_ids: other_location_ids.into_iter().collect(),
+		})
+	}
+}
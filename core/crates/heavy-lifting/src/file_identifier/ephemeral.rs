@@ -0,0 +1,126 @@
+use crate::file_identifier::{
+	FileAnalysis, FileMetadata, FileMetadataOptions, HardLinks, NonCriticalError,
+};
+
+use sd_core_file_path_helper::IsolatedFilePathData;
+use sd_file_ext::kind::ObjectKind;
+use sd_task_system::Interrupter;
+
+use std::path::{Path, PathBuf};
+
+use futures_concurrency::future::Join;
+use serde::Serialize;
+use specta::Type;
+use tokio::fs;
+
+/// What we could tell about a file outside of any location, see [`ephemeral_identify`]
+#[derive(Debug, Serialize, Type)]
+pub struct EphemeralFileMetadata {
+	pub path: PathBuf,
+	pub cas_id: Option<String>,
+	pub kind: ObjectKind,
+	/// Name of the library defined kind, see [`KindRegistry`](sd_file_ext::custom_kind::KindRegistry)
+	pub custom_kind: Option<String>,
+	/// Only set for symbolic links identified with [`SymlinkPolicy::RecordAsLink`](super::SymlinkPolicy::RecordAsLink)
+	pub link_target: Option<PathBuf>,
+	/// Cloud placeholder identified without reading its content, see [`OnDemandFilePolicy`](super::OnDemandFilePolicy)
+	pub remote_only: bool,
+}
+
+#[derive(Debug, Default, Serialize, Type)]
+pub struct EphemeralIdentification {
+	pub files: Vec<EphemeralFileMetadata>,
+	pub errors: Vec<String>,
+}
+
+/// Identifies arbitrary paths, like the ones browsed outside of any location in the Explorer,
+/// entirely in memory, without writing anything to the database.
+///
+/// Directories and symbolic links skipped by the [`SymlinkPolicy`](super::SymlinkPolicy) are left
+/// out of the result, and failing to identify a file doesn't prevent identifying the others.
+pub async fn ephemeral_identify(
+	paths: impl IntoIterator<Item = PathBuf> + Send,
+	options: &FileMetadataOptions,
+) -> EphemeralIdentification {
+	// Not running in the task system, so there is no one to interrupt us
+	let interrupter = Interrupter::uninterruptible();
+	let hard_links = HardLinks::default();
+
+	paths
+		.into_iter()
+		.map(|path| identify(path, options, &hard_links, &interrupter))
+		.collect::<Vec<_>>()
+		.join()
+		.await
+		.into_iter()
+		.flatten()
+		.fold(
+			EphemeralIdentification::default(),
+			|mut identification, res| {
+				match res {
+					Ok(file) => identification.files.push(file),
+					Err(e) => identification.errors.push(e.to_string()),
+				}
+
+				identification
+			},
+		)
+}
+
+async fn identify(
+	path: PathBuf,
+	options: &FileMetadataOptions,
+	hard_links: &HardLinks,
+	interrupter: &Interrupter,
+) -> Option<Result<EphemeralFileMetadata, NonCriticalError>> {
+	if fs::metadata(&path)
+		.await
+		.map_or(false, |metadata| metadata.is_dir())
+	{
+		return None;
+	}
+
+	// Each path is isolated from its own parent directory, as there is no location to be relative to
+	let parent = path.parent().unwrap_or_else(|| Path::new(""));
+
+	let iso_file_path = match IsolatedFilePathData::new(0, parent, &path, false) {
+		Ok(iso_file_path) => iso_file_path,
+		Err(e) => {
+			return Some(Err(NonCriticalError::FailedToExtractIsolatedFilePathData(
+				e.to_string(),
+			)))
+		}
+	};
+
+	match FileMetadata::new(
+		parent,
+		&iso_file_path,
+		options,
+		hard_links,
+		None,
+		interrupter,
+	)
+	.await
+	{
+		Ok(FileAnalysis::Analyzed(FileMetadata {
+			cas_id,
+			kind,
+			custom_kind,
+			link_target,
+			remote_only,
+			..
+		})) => Some(Ok(EphemeralFileMetadata {
+			path,
+			cas_id,
+			kind,
+			custom_kind,
+			link_target,
+			remote_only,
+		})),
+		Ok(FileAnalysis::Skipped) => None,
+		Ok(FileAnalysis::Interrupted(..)) => unreachable!("we can't be interrupted"),
+		Err(e) => Some(Err(NonCriticalError::FailedToExtractFileMetadata(
+			e.to_string(),
+		))),
+	}
+}
@@ -0,0 +1,118 @@
+use std::{collections::HashMap, fs::Metadata, future::Future, path::Path, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+use tokio::{
+	io,
+	sync::{Mutex, OnceCell},
+};
+
+/// Identifies a file on disk no matter which path reaches it, so hard links to the same file share
+/// the same id: device and inode on unix, volume serial number and file index on Windows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FileId {
+	device: u64,
+	index: u64,
+}
+
+impl FileId {
+	/// Returns `None` for files with a single link, as nothing else can point to them
+	pub async fn for_hard_link(
+		path: impl AsRef<Path> + Send,
+		fs_metadata: &Metadata,
+	) -> Result<Option<Self>, io::Error> {
+		platform::for_hard_link(path.as_ref(), fs_metadata).await
+	}
+}
+
+/// `cas_id`s already computed for hard linked files, shared by all tasks of the same job so each
+/// file is hashed once even when many of its links are identified concurrently
+#[derive(Debug, Clone, Default)]
+pub struct HardLinks(Arc<Mutex<HashMap<FileId, Arc<OnceCell<String>>>>>);
+
+impl HardLinks {
+	pub async fn cas_id_or_init<Fut>(
+		&self,
+		file_id: FileId,
+		init: impl FnOnce() -> Fut + Send,
+	) -> Result<String, io::Error>
+	where
+		Fut: Future<Output = Result<String, io::Error>> + Send,
+	{
+		let cell = Arc::clone(self.0.lock().await.entry(file_id).or_default());
+
+		cell.get_or_try_init(init).await.cloned()
+	}
+}
+
+#[cfg(unix)]
+mod platform {
+	use super::FileId;
+
+	use std::{fs::Metadata, os::unix::fs::MetadataExt, path::Path};
+
+	use tokio::io;
+
+	#[allow(clippy::unused_async)]
+	pub async fn for_hard_link(
+		_: &Path,
+		fs_metadata: &Metadata,
+	) -> Result<Option<FileId>, io::Error> {
+		Ok((fs_metadata.nlink() > 1).then(|| FileId {
+			device: fs_metadata.dev(),
+			index: fs_metadata.ino(),
+		}))
+	}
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+	use super::FileId;
+
+	use std::{
+		fs::{File, Metadata},
+		os::windows::io::AsRawHandle,
+		path::Path,
+	};
+
+	use tokio::{io, task::spawn_blocking};
+	use windows::Win32::{
+		Foundation::HANDLE,
+		Storage::FileSystem::{GetFileInformationByHandle, BY_HANDLE_FILE_INFORMATION},
+	};
+
+	// The link count and file index aren't exposed by std on stable, so we ask the file system
+	pub async fn for_hard_link(path: &Path, _: &Metadata) -> Result<Option<FileId>, io::Error> {
+		let path = path.to_path_buf();
+
+		spawn_blocking(move || {
+			let file = File::open(path)?;
+			let mut info = BY_HANDLE_FILE_INFORMATION::default();
+
+			// SAFETY: `file` keeps the handle open for the whole call
+			unsafe {
+				GetFileInformationByHandle(HANDLE(file.as_raw_handle() as isize), &mut info)
+			}?;
+
+			Ok((info.nNumberOfLinks > 1).then(|| FileId {
+				device: u64::from(info.dwVolumeSerialNumber),
+				index: (u64::from(info.nFileIndexHigh) << 32) | u64::from(info.nFileIndexLow),
+			}))
+		})
+		.await
+		.map_err(io::Error::other)?
+	}
+}
+
+#[cfg(not(any(unix, target_os = "windows")))]
+mod platform {
+	use super::FileId;
+
+	use std::{fs::Metadata, path::Path};
+
+	use tokio::io;
+
+	#[allow(clippy::unused_async, clippy::unnecessary_wraps)]
+	pub async fn for_hard_link(_: &Path, _: &Metadata) -> Result<Option<FileId>, io::Error> {
+		Ok(None)
+	}
+}
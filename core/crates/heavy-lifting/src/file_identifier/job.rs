@@ -1,22 +1,31 @@
 use crate::{
-	file_identifier,
+	file_identifier::{
+		self, CasIdAlgorithm, CrossLocationLink, FileMetadataOptions, HardLinks, IdentifierRules,
+		IdentifierSettings, OnDemandFilePolicy, RulesScope, SymlinkPolicy,
+	},
 	job_system::{
 		job::{Job, JobReturn, JobTaskDispatcher, ReturnStatus},
 		report::ReportOutputMetadata,
-		utils::cancel_pending_tasks,
+		utils::{cancel_pending_tasks, location_concurrency_key},
 		SerializableJob, SerializedTasks,
 	},
-	utils::sub_path::maybe_get_iso_file_path_from_sub_path,
-	Error, JobName, LocationScanState, NonCriticalError, OuterContext, ProgressUpdate, UpdateEvent,
+	utils::{
+		io_throttle::IoThrottle,
+		network_share::{self, ensure_location_mounted},
+		sub_path::maybe_get_iso_file_path_from_sub_path,
+	},
+	Error, JobName, JobProgressMetrics, LocationScanState, NonCriticalError, OuterContext,
+	ProgressUpdate, UpdateEvent,
 };
 
 use sd_core_file_path_helper::IsolatedFilePathData;
 use sd_core_prisma_helpers::file_path_for_file_identifier;
 
-use sd_prisma::prisma::{file_path, location, SortOrder};
+use sd_file_ext::custom_kind::KindRegistry;
+use sd_prisma::prisma::{file_path, location, PrismaClient, SortOrder};
 use sd_task_system::{
 	AnyTaskOutput, IntoTask, SerializableTask, Task, TaskDispatcher, TaskHandle, TaskId,
-	TaskOutput, TaskStatus,
+	TaskOutput, TaskStatus, TaskSystemError,
 };
 use sd_utils::db::maybe_missing;
 
@@ -29,30 +38,54 @@ use std::{
 	time::Duration,
 };
 
-use futures::{stream::FuturesUnordered, StreamExt};
-use futures_concurrency::future::TryJoin;
+use async_channel as chan;
+use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
+use futures_concurrency::future::{Race, TryJoin};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use tokio::time::Instant;
-use tracing::warn;
+use tokio::{spawn, time::Instant};
+use tracing::{debug, trace, warn};
 
 use super::{
-	orphan_path_filters_deep, orphan_path_filters_shallow,
+	batching::{AdaptiveBatchSize, BatchSizeBounds},
+	orphan_path_filters_deep, orphan_path_filters_shallow, rules,
 	tasks::{
 		extract_file_metadata, object_processor, ExtractFileMetadataTask, ObjectProcessorTask,
 	},
-	CHUNK_SIZE,
+	update_persisted_failures,
 };
 
+/// How many tasks we keep dispatched at once, the orphans seeker waits while we're at this limit
+const MAX_IN_FLIGHT_TASKS: usize = 32;
+/// How many chunks of orphans the seeker can load ahead of being dispatched
+const ORPHANS_CHANNEL_CAPACITY: usize = 4;
+
 #[derive(Debug)]
 pub struct FileIdentifier {
 	location: Arc<location::Data>,
 	location_path: Arc<PathBuf>,
 	sub_path: Option<PathBuf>,
+	options: FileMetadataOptions,
+	identifier_rules: Arc<IdentifierRules>,
+	/// Files a dry run would have flagged as skipped by the identifier rules, which it leaves out
+	dry_run_skipped: HashSet<file_path::id::Type>,
+	reidentify: bool,
+	retry_failed: bool,
+	dry_run: bool,
+	hard_links: HardLinks,
+	batch_size: AdaptiveBatchSize,
+	priority_lane_tx: chan::Sender<PathBuf>,
+	priority_lane_rx: chan::Receiver<PathBuf>,
 
 	metadata: Metadata,
+	progress_baseline: ProgressBaseline,
 
 	priority_tasks_ids: HashSet<TaskId>,
+	file_paths_already_identifying: HashSet<file_path::id::Type>,
+	last_orphan_file_path_id: Option<file_path::id::Type>,
+	seeking_orphans: bool,
+	previewed_cas_ids: HashSet<String>,
+	cross_location_links: Vec<CrossLocationLink>,
 
 	errors: Vec<NonCriticalError>,
 
@@ -60,12 +93,34 @@ pub struct FileIdentifier {
 	tasks_for_shutdown: Vec<Box<dyn Task<Error>>>,
 }
 
+/// Handle to ask a running [`FileIdentifier`] to identify the orphans of a directory ahead of the
+/// remaining ones, e.g. when the user opens that directory in the Explorer
+#[derive(Debug, Clone)]
+pub struct PriorityLane(chan::Sender<PathBuf>);
+
+impl PriorityLane {
+	/// A lane for identifiers running outside the job system, which receive the prioritized
+	/// directories themselves
+	#[must_use]
+	pub fn channel() -> (Self, chan::Receiver<PathBuf>) {
+		let (tx, rx) = chan::unbounded();
+		(Self(tx), rx)
+	}
+
+	/// Directories can be absolute or relative to the location path, only their direct children
+	/// are prioritized. Returns `false` if the job isn't running anymore.
+	pub fn prioritize(&self, directory: impl Into<PathBuf>) -> bool {
+		self.0.try_send(directory.into()).is_ok()
+	}
+}
+
 impl Hash for FileIdentifier {
 	fn hash<H: Hasher>(&self, state: &mut H) {
 		self.location.id.hash(state);
 		if let Some(ref sub_path) = self.sub_path {
 			sub_path.hash(state);
 		}
+		self.retry_failed.hash(state);
 	}
 }
 
@@ -78,6 +133,10 @@ impl Job for FileIdentifier {
 		ctx: &impl OuterContext,
 		SerializedTasks(serialized_tasks): SerializedTasks,
 	) -> Result<(), Error> {
+		let dispatcher =
+			dispatcher.with_concurrency_key(location_concurrency_key(ctx.id(), self.location.id));
+		let io_throttle = &dispatcher.io_throttle(&self.options.io_throttle);
+
 		self.pending_tasks_on_resume = dispatcher
 			.dispatch_many_boxed(
 				rmp_serde::from_slice::<Vec<(TaskKind, Vec<u8>)>>(&serialized_tasks)
@@ -91,6 +150,7 @@ impl Job for FileIdentifier {
 									(),
 								)
 								.await
+								.map(|task| task.with_io_throttle(io_throttle.clone()))
 								.map(IntoTask::into_task)
 							}
 
@@ -117,20 +177,72 @@ impl Job for FileIdentifier {
 		dispatcher: JobTaskDispatcher,
 		ctx: Ctx,
 	) -> Result<ReturnStatus, Error> {
+		let dispatcher =
+			dispatcher.with_concurrency_key(location_concurrency_key(ctx.id(), self.location.id));
+
+		self.progress_baseline = ProgressBaseline::from(&self.metadata);
+
+		ctx.register_priority_lane(self.location.id, self.priority_lane());
+
 		let mut pending_running_tasks = FuturesUnordered::new();
 
-		self.init_or_resume(&mut pending_running_tasks, &ctx, &dispatcher)
+		let mut maybe_orphans_rx = self
+			.init_or_resume(&mut pending_running_tasks, &ctx, &dispatcher)
 			.await?;
 
-		while let Some(task) = pending_running_tasks.next().await {
+		loop {
+			if let Some(orphans_rx) = &maybe_orphans_rx {
+				if !self
+					.receive_orphans(orphans_rx, &mut pending_running_tasks, &ctx, &dispatcher)
+					.await?
+				{
+					// Seeker is done or we're shutting down, dropping the receiver stops the seeker
+					maybe_orphans_rx = None;
+				}
+			}
+
+			let task = if maybe_orphans_rx.is_some() {
+				// While the seeker still has orphans to hand out, prioritized directories can jump
+				// ahead of them
+				match (
+					pending_running_tasks.next().map(LoopMessage::TaskStatus),
+					self.priority_lane_rx
+						.recv()
+						.map(|res| LoopMessage::PrioritizedDirectory(res.ok())),
+				)
+					.race()
+					.await
+				{
+					LoopMessage::TaskStatus(task) => task,
+
+					LoopMessage::PrioritizedDirectory(maybe_directory) => {
+						if let Some(directory) = maybe_directory {
+							self.dispatch_prioritized_directory(
+								directory,
+								&ctx,
+								&dispatcher,
+								&pending_running_tasks,
+							)
+							.await?;
+						}
+
+						continue;
+					}
+				}
+			} else {
+				pending_running_tasks.next().await
+			};
+
+			let Some(task) = task else {
+				break;
+			};
+
 			match task {
 				Ok(TaskStatus::Done((task_id, TaskOutput::Out(out)))) => {
-					if let Some(new_object_processor_task) = self
-						.process_task_output(task_id, out, &ctx, &dispatcher)
-						.await
-					{
-						pending_running_tasks.push(new_object_processor_task);
-					};
+					pending_running_tasks.extend(
+						self.process_task_output(task_id, out, &ctx, &dispatcher)
+							.await,
+					);
 				}
 
 				Ok(TaskStatus::Done((task_id, TaskOutput::Empty))) => {
@@ -170,22 +282,38 @@ impl Job for FileIdentifier {
 		// From this point onward, we are done with the job and it can't be interrupted anymore
 		let Self {
 			location,
+			dry_run,
 			metadata,
+			cross_location_links,
 			errors,
 			..
 		} = self;
 
-		ctx.db()
-			.location()
-			.update(
-				location::id::equals(location.id),
-				vec![location::scan_state::set(
-					LocationScanState::FilesIdentified as i32,
-				)],
-			)
-			.exec()
-			.await
-			.map_err(file_identifier::Error::from)?;
+		if !cross_location_links.is_empty() {
+			debug!(
+				"Linked {} file paths to objects from other locations",
+				cross_location_links.len()
+			);
+
+			ctx.report_update(UpdateEvent::NewCrossLocationLinks {
+				links: cross_location_links,
+			});
+		}
+
+		// Dry runs leave orphans as they were, so the location isn't identified yet
+		if !dry_run {
+			ctx.db()
+				.location()
+				.update(
+					location::id::equals(location.id),
+					vec![location::scan_state::set(
+						LocationScanState::FilesIdentified as i32,
+					)],
+				)
+				.exec()
+				.await
+				.map_err(file_identifier::Error::from)?;
+		}
 
 		Ok(ReturnStatus::Completed(
 			JobReturn::builder()
@@ -200,40 +328,224 @@ impl FileIdentifier {
 	pub fn new(
 		location: location::Data,
 		sub_path: Option<PathBuf>,
+		cas_id_algorithm: CasIdAlgorithm,
 	) -> Result<Self, file_identifier::Error> {
-		Ok(Self {
+		let (priority_lane_tx, priority_lane_rx) = chan::unbounded();
+		let settings = IdentifierSettings::of_location(&location);
+
+		let mut job = Self {
 			location_path: maybe_missing(&location.path, "location.path")
 				.map(PathBuf::from)
 				.map(Arc::new)?,
+			identifier_rules: Arc::new(IdentifierRules::from_location(&location)),
+			dry_run_skipped: HashSet::new(),
 			location: Arc::new(location),
 			sub_path,
+			options: FileMetadataOptions {
+				cas_id_algorithm,
+				..Default::default()
+			},
+			reidentify: false,
+			retry_failed: false,
+			dry_run: false,
+			hard_links: HardLinks::default(),
+			batch_size: AdaptiveBatchSize::new(BatchSizeBounds::default(), None),
+			priority_lane_tx,
+			priority_lane_rx,
 			metadata: Metadata::default(),
+			progress_baseline: ProgressBaseline::default(),
 			priority_tasks_ids: HashSet::new(),
+			file_paths_already_identifying: HashSet::new(),
+			last_orphan_file_path_id: None,
+			seeking_orphans: false,
+			previewed_cas_ids: HashSet::new(),
+			cross_location_links: Vec::new(),
 			errors: Vec::new(),
 			pending_tasks_on_resume: Vec::new(),
 			tasks_for_shutdown: Vec::new(),
+		}
+		.with_symlink_policy(settings.symlink_policy)
+		.with_on_demand_file_policy(settings.on_demand_policy);
+
+		if settings.walk_archives {
+			job = job.with_archive_walking();
+		}
+
+		if settings.extract_quick_metadata {
+			job = job.with_quick_metadata();
+		}
+
+		if let Some(sniff_read_budget) = settings.sniff_read_budget {
+			job = job.with_sniff_read_budget(sniff_read_budget);
+		}
+
+		Ok(job)
+	}
+
+	/// Reads platform extended attributes of each file while identifying it, importing tags set by
+	/// the OS file manager (e.g. Finder tags) as Spacedrive tags on the file's object
+	#[must_use]
+	pub const fn with_xattrs_extraction(mut self) -> Self {
+		self.options.extract_xattrs = true;
+		self
+	}
+
+	/// How many bytes we read from files with a missing or unknown extension to sniff their kind,
+	/// defaults to [`DEFAULT_SNIFF_READ_BUDGET`](super::DEFAULT_SNIFF_READ_BUDGET), 0 disables sniffing
+	#[must_use]
+	pub const fn with_sniff_read_budget(mut self, bytes: usize) -> Self {
+		self.options.sniff_read_budget = bytes;
+		self
+	}
+
+	/// What to do with symbolic links found among orphan file paths, defaults to following them
+	#[must_use]
+	pub const fn with_symlink_policy(mut self, policy: SymlinkPolicy) -> Self {
+		self.options.symlink_policy = policy;
+		self
+	}
+
+	/// What to do with cloud placeholder files, defaults to recording them as remote only objects
+	/// without downloading their content
+	#[must_use]
+	pub const fn with_on_demand_file_policy(mut self, policy: OnDemandFilePolicy) -> Self {
+		self.options.on_demand_policy = policy;
+		self
+	}
+
+	/// Custom kinds defined by the library, resolved for each file on top of its builtin kind
+	#[must_use]
+	pub fn with_kind_registry(mut self, kind_registry: KindRegistry) -> Self {
+		self.options.kind_registry = kind_registry;
+		self
+	}
+
+	/// Identifies the entries of zip, tar and 7z archives, storing them as virtual file paths under
+	/// their archive so they're searchable without extracting it. Requires the `archives` feature.
+	#[must_use]
+	pub const fn with_archive_walking(mut self) -> Self {
+		self.options.walk_archives = true;
+		self
+	}
+
+	/// Reads dimensions, durations and codecs from the headers of media files while identifying
+	/// them, so they're available before the media processor runs
+	#[must_use]
+	pub const fn with_quick_metadata(mut self) -> Self {
+		self.options.extract_quick_metadata = true;
+		self
+	}
+
+	/// Caps how fast files are read while hashing them, so identifying a large location in the
+	/// background doesn't starve other disk users
+	#[must_use]
+	pub fn with_io_throttle(mut self, io_throttle: IoThrottle) -> Self {
+		self.options.io_throttle = io_throttle;
+		self
+	}
+
+	/// Bounds for the amount of orphans identified by each task, adapted between them from the
+	/// database commit latency. Equal bounds disable the adaptive batching.
+	#[must_use]
+	pub fn with_batch_size_bounds(mut self, bounds: BatchSizeBounds) -> Self {
+		self.batch_size = AdaptiveBatchSize::new(bounds, None);
+		self
+	}
+
+	/// Computes `cas_id`s and kinds and reports how many objects would be created or linked, without
+	/// committing anything, to preview the impact of identifying a big location before syncing it
+	#[must_use]
+	pub const fn with_dry_run(mut self) -> Self {
+		self.dry_run = true;
+		self.metadata.dry_run = true;
+		self
+	}
+
+	/// Handle to prioritize directories while this job is running, handed to
+	/// [`OuterContext::register_priority_lane`] when it starts or resumes. Prioritized directories
+	/// aren't persisted if the job is paused.
+	#[must_use]
+	pub fn priority_lane(&self) -> PriorityLane {
+		PriorityLane(self.priority_lane_tx.clone())
+	}
+
+	/// Creates a job that recomputes the `cas_id` of every file path in the location, not only
+	/// orphans. Used to migrate a library to a new [`CasIdAlgorithm`].
+	pub fn new_cas_id_migration(
+		location: location::Data,
+		cas_id_algorithm: CasIdAlgorithm,
+	) -> Result<Self, file_identifier::Error> {
+		Self::new(location, None, cas_id_algorithm).map(|mut job| {
+			job.reidentify = true;
+			job
 		})
 	}
 
+	/// Creates a job that reads the extended attributes of every file path under `sub_path`, not
+	/// only orphans, so tags set by the OS file manager on files identified before are imported too
+	pub fn new_os_tags_import(
+		location: location::Data,
+		sub_path: Option<PathBuf>,
+		cas_id_algorithm: CasIdAlgorithm,
+	) -> Result<Self, file_identifier::Error> {
+		Self::new(location, sub_path, cas_id_algorithm).map(|mut job| {
+			job.reidentify = true;
+			job.with_xattrs_extraction()
+		})
+	}
+
+	/// Creates a job that identifies again only the file paths of the location that previous runs
+	/// failed to identify, as persisted in the `job_error` table, e.g. after fixing the permissions
+	/// of a directory
+	pub fn new_retry_failed(
+		location: location::Data,
+		cas_id_algorithm: CasIdAlgorithm,
+	) -> Result<Self, file_identifier::Error> {
+		Self::new(location, None, cas_id_algorithm).map(|mut job| {
+			// Failed file paths may already have a `cas_id` or an object, e.g. when only linking failed
+			job.reidentify = true;
+			job.retry_failed = true;
+			job
+		})
+	}
+
+	/// Options for new tasks, also capped by the disk reads of the job's resource profile
+	fn task_options(&self, dispatcher: &JobTaskDispatcher) -> FileMetadataOptions {
+		FileMetadataOptions {
+			io_throttle: dispatcher.io_throttle(&self.options.io_throttle),
+			..self.options.clone()
+		}
+	}
+
 	async fn init_or_resume(
 		&mut self,
 		pending_running_tasks: &mut FuturesUnordered<TaskHandle<Error>>,
 		ctx: &impl OuterContext,
 		dispatcher: &JobTaskDispatcher,
-	) -> Result<(), file_identifier::Error> {
-		// if we don't have any pending task, then this is a fresh job
-		if self.pending_tasks_on_resume.is_empty() {
-			let db = ctx.db();
-			let maybe_sub_iso_file_path = maybe_get_iso_file_path_from_sub_path(
-				self.location.id,
-				&self.sub_path,
-				&*self.location_path,
-				db,
-			)
-			.await?;
+	) -> Result<Option<OrphansReceiver>, file_identifier::Error> {
+		self.options.deep_hash_threshold = ctx.deep_hash_threshold().await;
+
+		ensure_location_mounted(
+			self.location.id,
+			self.location.network_share.as_deref(),
+			&*self.location_path,
+		)
+		.await?;
 
-			let mut last_orphan_file_path_id = None;
+		let db = ctx.db();
+		let maybe_sub_iso_file_path = maybe_get_iso_file_path_from_sub_path(
+			self.location.id,
+			&self.sub_path,
+			&*self.location_path,
+			db,
+		)
+		.await?;
+
+		self.apply_identifier_rules(maybe_sub_iso_file_path.as_ref(), ctx)
+			.await?;
 
+		// if we don't have any pending task and we weren't seeking orphans, then this is a fresh job
+		if self.pending_tasks_on_resume.is_empty() && !self.seeking_orphans {
 			let start = Instant::now();
 
 			let location_root_iso_file_path = IsolatedFilePathData::new(
@@ -246,34 +558,137 @@ impl FileIdentifier {
 
 			// First we dispatch some shallow priority tasks to quickly identify orphans in the location
 			// root directory or in the desired sub-path
-			let file_paths_already_identifying = self
-				.dispatch_priority_identifier_tasks(
-					&mut last_orphan_file_path_id,
-					maybe_sub_iso_file_path
-						.as_ref()
-						.unwrap_or(&location_root_iso_file_path),
-					ctx,
-					dispatcher,
-					pending_running_tasks,
-				)
-				.await?;
-
-			self.dispatch_deep_identifier_tasks(
-				&mut last_orphan_file_path_id,
-				&maybe_sub_iso_file_path,
+			self.dispatch_priority_identifier_tasks(
+				maybe_sub_iso_file_path
+					.as_ref()
+					.unwrap_or(&location_root_iso_file_path),
+				None,
 				ctx,
 				dispatcher,
 				pending_running_tasks,
-				&file_paths_already_identifying,
 			)
 			.await?;
 
 			self.metadata.seeking_orphans_time = start.elapsed();
 		} else {
 			pending_running_tasks.extend(mem::take(&mut self.pending_tasks_on_resume));
+
+			if !self.seeking_orphans {
+				return Ok(None);
+			}
 		}
 
-		Ok(())
+		// Then the remaining orphans are streamed by a seeker through a bounded channel, so we only
+		// load more orphans from the database when we have room for more tasks
+		let (orphans_tx, orphans_rx) = chan::bounded(ORPHANS_CHANNEL_CAPACITY);
+
+		spawn(seek_orphans(
+			Arc::clone(db),
+			self.location.id,
+			self.last_orphan_file_path_id,
+			maybe_sub_iso_file_path,
+			self.reidentify,
+			self.retry_failed,
+			self.file_paths_already_identifying.clone(),
+			self.batch_size.clone(),
+			orphans_tx,
+		));
+
+		self.seeking_orphans = true;
+
+		Ok(Some(orphans_rx))
+	}
+
+	/// Dispatch orphans received from the seeker while we're below [`MAX_IN_FLIGHT_TASKS`].
+	///
+	/// Returns `false` when we must stop receiving, either because the seeker is done or because
+	/// the job is shutting down
+	async fn receive_orphans(
+		&mut self,
+		orphans_rx: &OrphansReceiver,
+		pending_running_tasks: &mut FuturesUnordered<TaskHandle<Error>>,
+		ctx: &impl OuterContext,
+		dispatcher: &JobTaskDispatcher,
+	) -> Result<bool, file_identifier::Error> {
+		if !self.tasks_for_shutdown.is_empty() {
+			// We keep `seeking_orphans` set, so we continue from the last dispatched orphan on resume
+			return Ok(false);
+		}
+
+		while pending_running_tasks.len() < MAX_IN_FLIGHT_TASKS {
+			let received = if pending_running_tasks.is_empty() {
+				// Nothing else to do, so we wait for the seeker
+				orphans_rx
+					.recv()
+					.await
+					.map_err(|_| chan::TryRecvError::Closed)
+			} else {
+				orphans_rx.try_recv()
+			};
+
+			match received {
+				Ok(Ok((mut orphan_paths, seek_time))) => {
+					self.metadata.seeking_orphans_time += seek_time;
+					self.last_orphan_file_path_id = Some(
+						orphan_paths
+							.last()
+							.expect("seeker never sends empty chunks")
+							.id,
+					);
+
+					// The seeker only knows about orphans dispatched before it started, so we also
+					// skip the ones dispatched since then from prioritized directories
+					orphan_paths.retain(|path| {
+						!self.file_paths_already_identifying.contains(&path.id)
+							&& !self.dry_run_skipped.contains(&path.id)
+					});
+
+					if orphan_paths.is_empty() {
+						continue;
+					}
+
+					self.metadata.total_found_orphans += orphan_paths.len() as u64;
+
+					ctx.progress(vec![
+						ProgressUpdate::TaskCount(self.metadata.total_found_orphans),
+						ProgressUpdate::Message(format!(
+							"{} files to be identified",
+							self.metadata.total_found_orphans
+						)),
+					]);
+
+					pending_running_tasks.push(
+						dispatcher
+							.dispatch(ExtractFileMetadataTask::new(
+								Arc::clone(&self.location),
+								Arc::clone(&self.location_path),
+								orphan_paths,
+								false,
+								self.task_options(dispatcher),
+								self.hard_links.clone(),
+							))
+							.await,
+					);
+				}
+
+				Ok(Err(e)) => {
+					cancel_pending_tasks(&*pending_running_tasks).await;
+
+					return Err(e);
+				}
+
+				Err(chan::TryRecvError::Empty) => break,
+
+				Err(chan::TryRecvError::Closed) => {
+					self.seeking_orphans = false;
+					self.file_paths_already_identifying = HashSet::new();
+
+					return Ok(false);
+				}
+			}
+		}
+
+		Ok(true)
 	}
 
 	/// Process output of tasks, according to the downcasted output type
@@ -287,7 +702,7 @@ impl FileIdentifier {
 		any_task_output: Box<dyn AnyTaskOutput>,
 		ctx: &impl OuterContext,
 		dispatcher: &JobTaskDispatcher,
-	) -> Option<TaskHandle<Error>> {
+	) -> Vec<TaskHandle<Error>> {
 		if any_task_output.is::<extract_file_metadata::Output>() {
 			return self
 				.process_extract_file_metadata_output(
@@ -311,7 +726,7 @@ impl FileIdentifier {
 			unreachable!("Unexpected task output type: <id='{task_id}'>");
 		}
 
-		None
+		vec![]
 	}
 
 	async fn process_extract_file_metadata_output(
@@ -320,33 +735,91 @@ impl FileIdentifier {
 		extract_file_metadata::Output {
 			identified_files,
 			extract_metadata_time,
+			hashed_bytes,
 			errors,
+			failed_file_paths,
+			disconnected_file_paths,
 		}: extract_file_metadata::Output,
 		ctx: &impl OuterContext,
 		dispatcher: &JobTaskDispatcher,
-	) -> Option<TaskHandle<Error>> {
+	) -> Vec<TaskHandle<Error>> {
 		self.metadata.extract_metadata_time += extract_metadata_time;
+		self.metadata.hashed_bytes += hashed_bytes;
 		self.errors.extend(errors);
 
+		if !self.dry_run {
+			update_persisted_failures(
+				ctx.db(),
+				self.location.id,
+				&identified_files,
+				failed_file_paths,
+			)
+			.await;
+		}
+
+		let with_priority = self.priority_tasks_ids.remove(&task_id);
+
+		let mut new_tasks = Vec::with_capacity(2);
+
 		if identified_files.is_empty() {
 			self.metadata.completed_tasks += 1;
 
 			ctx.progress(vec![ProgressUpdate::CompletedTaskCount(
 				self.metadata.completed_tasks,
 			)]);
-
-			None
 		} else {
 			ctx.progress_msg(format!("Identified {} files", identified_files.len()));
 
-			let with_priority = self.priority_tasks_ids.remove(&task_id);
+			let task = dispatcher
+				.dispatch(
+					ObjectProcessorTask::new(
+						identified_files,
+						Arc::clone(ctx.db()),
+						Arc::clone(ctx.sync()),
+						with_priority,
+					)
+					.with_checkpoint_chunk_size(self.batch_size.get())
+					.with_dry_run(self.dry_run),
+				)
+				.await;
+
+			if with_priority {
+				self.priority_tasks_ids.insert(task.task_id());
+			}
+
+			new_tasks.push(task);
+		}
+
+		if !disconnected_file_paths.is_empty() {
+			// Instead of failing these files, we hold the job here until the share comes back,
+			// while pause and cancel commands still reach it
+			warn!(
+				"Network share of location <id={}> disconnected during identification, \
+				waiting for it to identify {} files",
+				self.location.id,
+				disconnected_file_paths.len()
+			);
+
+			ctx.progress_msg(format!(
+				"Network share disconnected, waiting to identify {} files",
+				disconnected_file_paths.len()
+			));
+
+			network_share::wait_until_available(
+				self.location.id,
+				self.location.network_share.as_deref(),
+				&*self.location_path,
+			)
+			.await;
 
 			let task = dispatcher
-				.dispatch(ObjectProcessorTask::new(
-					identified_files,
-					Arc::clone(ctx.db()),
-					Arc::clone(ctx.sync()),
+				.dispatch(ExtractFileMetadataTask::new(
+					Arc::clone(&self.location),
+					Arc::clone(&self.location_path),
+					disconnected_file_paths,
 					with_priority,
+					self.task_options(dispatcher),
+					self.hard_links.clone(),
 				))
 				.await;
 
@@ -354,8 +827,10 @@ impl FileIdentifier {
 				self.priority_tasks_ids.insert(task.task_id());
 			}
 
-			Some(task)
+			new_tasks.push(task);
 		}
+
+		new_tasks
 	}
 
 	fn process_object_processor_output(
@@ -369,25 +844,64 @@ impl FileIdentifier {
 			create_object_time,
 			created_objects_count,
 			linked_objects_count,
+			import_tags_time,
+			assigned_tags_count,
+			apply_tag_rules_time,
+			rule_tags_count,
+			new_objects_cas_ids,
+			cross_location_links,
 		}: object_processor::Output,
 		ctx: &impl OuterContext,
 	) {
+		// On dry runs the objects previewed by earlier tasks aren't in the database, so files with
+		// the same `cas_id` would actually be linked to them
+		let already_previewed_count = new_objects_cas_ids
+			.iter()
+			.flatten()
+			.filter(|cas_id| self.previewed_cas_ids.contains(*cas_id))
+			.count() as u64;
+		self.previewed_cas_ids
+			.extend(new_objects_cas_ids.into_iter().flatten());
+
+		let created_objects_count = created_objects_count - already_previewed_count;
+		let linked_objects_count = linked_objects_count + already_previewed_count;
+
 		self.metadata.assign_cas_ids_time += assign_cas_ids_time;
 		self.metadata.fetch_existing_objects_time += fetch_existing_objects_time;
 		self.metadata.assign_to_existing_object_time += assign_to_existing_object_time;
 		self.metadata.create_object_time += create_object_time;
 		self.metadata.created_objects_count += created_objects_count;
 		self.metadata.linked_objects_count += linked_objects_count;
+		self.metadata.import_tags_time += import_tags_time;
+		self.metadata.assigned_tags_count += assigned_tags_count;
+		self.metadata.apply_tag_rules_time += apply_tag_rules_time;
+		self.metadata.rule_tags_count += rule_tags_count;
+		self.metadata.cross_location_links_count += cross_location_links.len() as u64;
+		self.cross_location_links.extend(cross_location_links);
 
 		self.metadata.completed_tasks += 1;
 
+		// Dry runs don't commit anything, so they can't tell how the database copes
+		if !self.dry_run {
+			let previous_batch_size = self.batch_size.get();
+			let batch_size = self.batch_size.record_commit(
+				created_objects_count + linked_objects_count,
+				assign_cas_ids_time + assign_to_existing_object_time + create_object_time,
+			);
+			if batch_size != previous_batch_size {
+				trace!("Adjusted batch size <from={previous_batch_size}, to={batch_size}>");
+			}
+			self.metadata.batch_size = batch_size as u64;
+		}
+
 		ctx.progress(vec![
 			ProgressUpdate::CompletedTaskCount(self.metadata.completed_tasks),
 			ProgressUpdate::Message(format!(
 				"Processed {} of {} objects",
-				self.metadata.created_objects_count + self.metadata.linked_objects_count,
+				self.metadata.processed_files(),
 				self.metadata.total_found_orphans
 			)),
+			ProgressUpdate::Metrics(self.progress_metrics()),
 		]);
 
 		if self.priority_tasks_ids.remove(&task_id) {
@@ -397,30 +911,117 @@ impl FileIdentifier {
 		}
 	}
 
+	/// Rates only account for what was processed since the job started running, or since it was
+	/// resumed, as the time it spent paused would drag them down
+	fn progress_metrics(&self) -> JobProgressMetrics {
+		let ProgressBaseline {
+			started_at,
+			processed_files,
+			hashed_bytes,
+		} = self.progress_baseline;
+
+		JobProgressMetrics::new(
+			self.metadata.processed_files() - processed_files,
+			self.metadata
+				.total_found_orphans
+				.saturating_sub(processed_files),
+			self.metadata.hashed_bytes - hashed_bytes,
+			started_at.elapsed(),
+		)
+		.with_phase("seeking_orphans", self.metadata.seeking_orphans_time)
+		.with_phase("extract_metadata", self.metadata.extract_metadata_time)
+		.with_phase("assign_cas_ids", self.metadata.assign_cas_ids_time)
+		.with_phase(
+			"fetch_existing_objects",
+			self.metadata.fetch_existing_objects_time,
+		)
+		.with_phase(
+			"assign_to_existing_object",
+			self.metadata.assign_to_existing_object_time,
+		)
+		.with_phase("create_object", self.metadata.create_object_time)
+		.with_phase("import_tags", self.metadata.import_tags_time)
+		.with_phase("apply_tag_rules", self.metadata.apply_tag_rules_time)
+	}
+
+	/// Dispatches priority tasks for the orphans of a directory requested through a [`PriorityLane`].
+	/// Only orphans the seeker didn't hand out yet are dispatched, the others are already queued.
+	async fn dispatch_prioritized_directory(
+		&mut self,
+		directory: PathBuf,
+		ctx: &impl OuterContext,
+		dispatcher: &JobTaskDispatcher,
+		pending_running_tasks: &FuturesUnordered<TaskHandle<Error>>,
+	) -> Result<(), file_identifier::Error> {
+		let iso_directory = match maybe_get_iso_file_path_from_sub_path(
+			self.location.id,
+			&Some(&directory),
+			&*self.location_path,
+			ctx.db(),
+		)
+		.await
+		{
+			Ok(Some(iso_directory)) => iso_directory,
+			Ok(None) => IsolatedFilePathData::new(
+				self.location.id,
+				&*self.location_path,
+				&*self.location_path,
+				true,
+			)?,
+			Err(e) => {
+				warn!(
+					"Ignoring prioritized directory <path='{}'>: {e:#?}",
+					directory.display()
+				);
+				return Ok(());
+			}
+		};
+
+		trace!(
+			"Prioritizing orphans of directory <path='{}'>",
+			directory.display()
+		);
+
+		let start = Instant::now();
+
+		self.dispatch_priority_identifier_tasks(
+			&iso_directory,
+			self.last_orphan_file_path_id,
+			ctx,
+			dispatcher,
+			pending_running_tasks,
+		)
+		.await?;
+
+		self.metadata.seeking_orphans_time += start.elapsed();
+
+		Ok(())
+	}
+
 	async fn dispatch_priority_identifier_tasks(
 		&mut self,
-		last_orphan_file_path_id: &mut Option<i32>,
 		sub_iso_file_path: &IsolatedFilePathData<'static>,
+		mut last_orphan_file_path_id: Option<file_path::id::Type>,
 		ctx: &impl OuterContext,
 		dispatcher: &JobTaskDispatcher,
 		pending_running_tasks: &FuturesUnordered<TaskHandle<Error>>,
-	) -> Result<HashSet<file_path::id::Type>, file_identifier::Error> {
+	) -> Result<(), file_identifier::Error> {
 		let db = ctx.db();
 
-		let mut file_paths_already_identifying = HashSet::new();
-
 		loop {
 			#[allow(clippy::cast_possible_wrap)]
-			// SAFETY: we know that CHUNK_SIZE is a valid i64
-			let orphan_paths = db
+			// SAFETY: batch sizes are bounded, so they're valid i64
+			let mut orphan_paths = db
 				.file_path()
 				.find_many(orphan_path_filters_shallow(
 					self.location.id,
-					*last_orphan_file_path_id,
+					last_orphan_file_path_id,
 					sub_iso_file_path,
+					self.reidentify,
+					self.retry_failed,
 				))
 				.order_by(file_path::id::order(SortOrder::Asc))
-				.take(CHUNK_SIZE as i64)
+				.take(self.batch_size.get() as i64)
 				.select(file_path_for_file_identifier::select())
 				.exec()
 				.await?;
@@ -429,11 +1030,23 @@ impl FileIdentifier {
 				break;
 			}
 
-			file_paths_already_identifying.extend(orphan_paths.iter().map(|path| path.id));
+			last_orphan_file_path_id =
+				Some(orphan_paths.last().expect("orphan_paths is not empty").id);
+
+			// A directory can be prioritized more than once
+			orphan_paths.retain(|path| {
+				!self.file_paths_already_identifying.contains(&path.id)
+					&& !self.dry_run_skipped.contains(&path.id)
+			});
+
+			if orphan_paths.is_empty() {
+				continue;
+			}
+
+			self.file_paths_already_identifying
+				.extend(orphan_paths.iter().map(|path| path.id));
 
 			self.metadata.total_found_orphans += orphan_paths.len() as u64;
-			*last_orphan_file_path_id =
-				Some(orphan_paths.last().expect("orphan_paths is not empty").id);
 
 			ctx.progress(vec![
 				ProgressUpdate::TaskCount(self.metadata.total_found_orphans),
@@ -449,6 +1062,8 @@ impl FileIdentifier {
 					Arc::clone(&self.location_path),
 					orphan_paths,
 					true,
+					self.task_options(dispatcher),
+					self.hard_links.clone(),
 				))
 				.await;
 
@@ -457,80 +1072,157 @@ impl FileIdentifier {
 			pending_running_tasks.push(priority_task);
 		}
 
-		Ok(file_paths_already_identifying)
+		Ok(())
 	}
 
-	async fn dispatch_deep_identifier_tasks(
+	/// Flags the files matching the location's [`IdentifierRules`] as skipped, so they aren't
+	/// found as orphans. Dry runs only keep them aside to leave them out.
+	async fn apply_identifier_rules(
 		&mut self,
-		last_orphan_file_path_id: &mut Option<file_path::id::Type>,
-		maybe_sub_iso_file_path: &Option<IsolatedFilePathData<'static>>,
+		maybe_sub_iso_file_path: Option<&IsolatedFilePathData<'_>>,
 		ctx: &impl OuterContext,
-		dispatcher: &JobTaskDispatcher,
-		pending_running_tasks: &FuturesUnordered<TaskHandle<Error>>,
-		file_paths_already_identifying: &HashSet<file_path::id::Type>,
 	) -> Result<(), file_identifier::Error> {
-		let db = ctx.db();
+		let materialized_path =
+			maybe_sub_iso_file_path.and_then(IsolatedFilePathData::materialized_path_for_children);
+		let scope = materialized_path
+			.as_deref()
+			.map_or(RulesScope::Location, RulesScope::Descendants);
+
+		if self.dry_run {
+			self.dry_run_skipped = self
+				.identifier_rules
+				.matching(self.location.id, scope, ctx.db())
+				.await?
+				.into_iter()
+				.collect();
+
+			self.metadata.skipped_by_rules_count = self.dry_run_skipped.len() as u64;
+		} else {
+			self.metadata.skipped_by_rules_count += rules::skip_matching(
+				&self.identifier_rules,
+				self.location.id,
+				scope,
+				ctx.db(),
+				ctx.sync(),
+			)
+			.await?;
+		}
 
-		loop {
-			#[allow(clippy::cast_possible_wrap)]
-			// SAFETY: we know that CHUNK_SIZE is a valid i64
-			let mut orphan_paths = db
-				.file_path()
-				.find_many(orphan_path_filters_deep(
-					self.location.id,
-					*last_orphan_file_path_id,
-					maybe_sub_iso_file_path,
-				))
-				.order_by(file_path::id::order(SortOrder::Asc))
-				.take(CHUNK_SIZE as i64)
-				.select(file_path_for_file_identifier::select())
-				.exec()
-				.await?;
+		Ok(())
+	}
+}
 
-			// No other orphans to identify, we can break the loop
-			if orphan_paths.is_empty() {
-				break;
-			}
+/// Where the job was when it started running, to compute its throughput
+#[derive(Debug, Clone, Copy)]
+struct ProgressBaseline {
+	started_at: Instant,
+	processed_files: u64,
+	hashed_bytes: u64,
+}
 
-			// We grab the last id to use as a starting point for the next iteration, in case we skip this one
-			*last_orphan_file_path_id =
-				Some(orphan_paths.last().expect("orphan_paths is not empty").id);
+impl Default for ProgressBaseline {
+	fn default() -> Self {
+		Self {
+			started_at: Instant::now(),
+			processed_files: 0,
+			hashed_bytes: 0,
+		}
+	}
+}
 
-			orphan_paths.retain(|path| !file_paths_already_identifying.contains(&path.id));
+impl From<&Metadata> for ProgressBaseline {
+	fn from(metadata: &Metadata) -> Self {
+		Self {
+			started_at: Instant::now(),
+			processed_files: metadata.processed_files(),
+			hashed_bytes: metadata.hashed_bytes,
+		}
+	}
+}
 
-			// If we don't have any new orphan paths after filtering out, we can skip this iteration
-			if orphan_paths.is_empty() {
-				continue;
+enum LoopMessage {
+	TaskStatus(Option<Result<TaskStatus<Error>, TaskSystemError>>),
+	PrioritizedDirectory(Option<PathBuf>),
+}
+
+type OrphansReceiver = chan::Receiver<
+	Result<(Vec<file_path_for_file_identifier::Data>, Duration), file_identifier::Error>,
+>;
+
+/// Pages through the location's orphans, sending them in chunks through a bounded channel.
+/// When the job isn't receiving, the channel fills up and the seeker waits, so we don't load the
+/// whole location in memory at once. The seeker stops when the receiver is dropped.
+#[allow(clippy::too_many_arguments)]
+async fn seek_orphans(
+	db: Arc<PrismaClient>,
+	location_id: location::id::Type,
+	mut last_orphan_file_path_id: Option<file_path::id::Type>,
+	maybe_sub_iso_file_path: Option<IsolatedFilePathData<'static>>,
+	reidentify: bool,
+	retry_failed: bool,
+	file_paths_already_identifying: HashSet<file_path::id::Type>,
+	batch_size: AdaptiveBatchSize,
+	orphans_tx: chan::Sender<
+		Result<(Vec<file_path_for_file_identifier::Data>, Duration), file_identifier::Error>,
+	>,
+) {
+	loop {
+		let start = Instant::now();
+
+		#[allow(clippy::cast_possible_wrap)]
+		// SAFETY: batch sizes are bounded, so they're valid i64
+		let mut orphan_paths = match db
+			.file_path()
+			.find_many(orphan_path_filters_deep(
+				location_id,
+				last_orphan_file_path_id,
+				&maybe_sub_iso_file_path,
+				reidentify,
+				retry_failed,
+			))
+			.order_by(file_path::id::order(SortOrder::Asc))
+			.take(batch_size.get() as i64)
+			.select(file_path_for_file_identifier::select())
+			.exec()
+			.await
+		{
+			Ok(orphan_paths) => orphan_paths,
+			Err(e) => {
+				if orphans_tx.send(Err(e.into())).await.is_err() {
+					trace!("File identifier stopped receiving orphans before seeker error");
+				}
+				return;
 			}
+		};
 
-			self.metadata.total_found_orphans += orphan_paths.len() as u64;
+		// No other orphans to identify, we can break the loop
+		if orphan_paths.is_empty() {
+			break;
+		}
 
-			ctx.progress(vec![
-				ProgressUpdate::TaskCount(self.metadata.total_found_orphans),
-				ProgressUpdate::Message(format!(
-					"{} files to be identified",
-					self.metadata.total_found_orphans
-				)),
-			]);
+		// We grab the last id to use as a starting point for the next iteration, in case we skip this one
+		last_orphan_file_path_id = Some(orphan_paths.last().expect("orphan_paths is not empty").id);
 
-			pending_running_tasks.push(
-				dispatcher
-					.dispatch(ExtractFileMetadataTask::new(
-						Arc::clone(&self.location),
-						Arc::clone(&self.location_path),
-						orphan_paths,
-						false,
-					))
-					.await,
-			);
+		orphan_paths.retain(|path| !file_paths_already_identifying.contains(&path.id));
+
+		// If we don't have any new orphan paths after filtering out, we can skip this iteration
+		if orphan_paths.is_empty() {
+			continue;
 		}
 
-		Ok(())
+		if orphans_tx
+			.send(Ok((orphan_paths, start.elapsed())))
+			.await
+			.is_err()
+		{
+			trace!("File identifier stopped receiving orphans, stopping seeker");
+			break;
+		}
 	}
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
-enum TaskKind {
+pub(super) enum TaskKind {
 	ExtractFileMetadata,
 	ObjectProcessor,
 }
@@ -540,10 +1232,30 @@ struct SaveState {
 	location: Arc<location::Data>,
 	location_path: Arc<PathBuf>,
 	sub_path: Option<PathBuf>,
+	#[serde(default)]
+	options: FileMetadataOptions,
+	#[serde(default)]
+	reidentify: bool,
+	#[serde(default)]
+	retry_failed: bool,
+	#[serde(default)]
+	batch_size_bounds: BatchSizeBounds,
+	#[serde(default)]
+	dry_run: bool,
 
 	metadata: Metadata,
 
 	priority_tasks_ids: HashSet<TaskId>,
+	#[serde(default)]
+	file_paths_already_identifying: HashSet<file_path::id::Type>,
+	#[serde(default)]
+	last_orphan_file_path_id: Option<file_path::id::Type>,
+	#[serde(default)]
+	seeking_orphans: bool,
+	#[serde(default)]
+	previewed_cas_ids: HashSet<String>,
+	#[serde(default)]
+	cross_location_links: Vec<CrossLocationLink>,
 
 	errors: Vec<NonCriticalError>,
 
@@ -558,12 +1270,38 @@ pub struct Metadata {
 	assign_to_existing_object_time: Duration,
 	create_object_time: Duration,
 	seeking_orphans_time: Duration,
+	#[serde(default)]
+	import_tags_time: Duration,
+	#[serde(default)]
+	apply_tag_rules_time: Duration,
 	total_found_orphans: u64,
 	created_objects_count: u64,
 	linked_objects_count: u64,
+	#[serde(default)]
+	assigned_tags_count: u64,
+	#[serde(default)]
+	rule_tags_count: u64,
+	#[serde(default)]
+	cross_location_links_count: u64,
+	#[serde(default)]
+	skipped_by_rules_count: u64,
+	#[serde(default)]
+	hashed_bytes: u64,
+	/// Orphans handed to each task after the last adjustment, see [`AdaptiveBatchSize`]
+	#[serde(default)]
+	batch_size: u64,
+	/// Counts are what would have been created or linked, nothing was committed
+	#[serde(default)]
+	dry_run: bool,
 	completed_tasks: u64,
 }
 
+impl Metadata {
+	const fn processed_files(&self) -> u64 {
+		self.created_objects_count + self.linked_objects_count
+	}
+}
+
 impl From<Metadata> for ReportOutputMetadata {
 	fn from(value: Metadata) -> Self {
 		Self::Metrics(HashMap::from([
@@ -588,6 +1326,11 @@ impl From<Metadata> for ReportOutputMetadata {
 				"seeking_orphans_time".into(),
 				json!(value.seeking_orphans_time),
 			),
+			("import_tags_time".into(), json!(value.import_tags_time)),
+			(
+				"apply_tag_rules_time".into(),
+				json!(value.apply_tag_rules_time),
+			),
 			(
 				"total_found_orphans".into(),
 				json!(value.total_found_orphans),
@@ -600,6 +1343,22 @@ impl From<Metadata> for ReportOutputMetadata {
 				"linked_objects_count".into(),
 				json!(value.linked_objects_count),
 			),
+			(
+				"assigned_tags_count".into(),
+				json!(value.assigned_tags_count),
+			),
+			("rule_tags_count".into(), json!(value.rule_tags_count)),
+			(
+				"cross_location_links_count".into(),
+				json!(value.cross_location_links_count),
+			),
+			(
+				"skipped_by_rules_count".into(),
+				json!(value.skipped_by_rules_count),
+			),
+			("hashed_bytes".into(), json!(value.hashed_bytes)),
+			("batch_size".into(), json!(value.batch_size)),
+			("dry_run".into(), json!(value.dry_run)),
 			("total_tasks".into(), json!(value.completed_tasks)),
 		]))
 	}
@@ -611,8 +1370,18 @@ impl<Ctx: OuterContext> SerializableJob<Ctx> for FileIdentifier {
 			location,
 			location_path,
 			sub_path,
+			options,
+			reidentify,
+			retry_failed,
+			dry_run,
+			batch_size,
 			metadata,
 			priority_tasks_ids,
+			file_paths_already_identifying,
+			last_orphan_file_path_id,
+			seeking_orphans,
+			previewed_cas_ids,
+			cross_location_links,
 			errors,
 			tasks_for_shutdown,
 			..
@@ -622,8 +1391,18 @@ impl<Ctx: OuterContext> SerializableJob<Ctx> for FileIdentifier {
 			location,
 			location_path,
 			sub_path,
+			options,
+			reidentify,
+			retry_failed,
+			batch_size_bounds: batch_size.bounds(),
+			dry_run,
 			metadata,
 			priority_tasks_ids,
+			file_paths_already_identifying,
+			last_orphan_file_path_id,
+			seeking_orphans,
+			previewed_cas_ids,
+			cross_location_links,
 			tasks_for_shutdown_bytes: Some(SerializedTasks(rmp_serde::to_vec_named(
 				&tasks_for_shutdown
 					.into_iter()
@@ -663,19 +1442,54 @@ impl<Ctx: OuterContext> SerializableJob<Ctx> for FileIdentifier {
 			location,
 			location_path,
 			sub_path,
+			options,
+			reidentify,
+			retry_failed,
+			batch_size_bounds,
+			dry_run,
 			metadata,
 			priority_tasks_ids,
+			file_paths_already_identifying,
+			last_orphan_file_path_id,
+			seeking_orphans,
+			previewed_cas_ids,
+			cross_location_links,
 			errors,
 			tasks_for_shutdown_bytes,
 		} = rmp_serde::from_slice::<SaveState>(serialized_job)?;
 
+		let (priority_lane_tx, priority_lane_rx) = chan::unbounded();
+
 		Ok(Some((
 			Self {
+				// Rules aren't persisted, so changes made while the job was paused are picked up
+				identifier_rules: Arc::new(IdentifierRules::from_location(&location)),
+				dry_run_skipped: HashSet::new(),
 				location,
 				location_path,
 				sub_path,
+				options,
+				reidentify,
+				retry_failed,
+				dry_run,
+				hard_links: HardLinks::default(),
+				// Resuming from the last adjustment, as the storage didn't change
+				batch_size: AdaptiveBatchSize::new(
+					batch_size_bounds,
+					usize::try_from(metadata.batch_size)
+						.ok()
+						.filter(|batch_size| *batch_size != 0),
+				),
+				priority_lane_tx,
+				priority_lane_rx,
 				metadata,
+				progress_baseline: ProgressBaseline::default(),
 				priority_tasks_ids,
+				file_paths_already_identifying,
+				last_orphan_file_path_id,
+				seeking_orphans,
+				previewed_cas_ids,
+				cross_location_links,
 				errors,
 				pending_tasks_on_resume: Vec::new(),
 				tasks_for_shutdown: Vec::new(),
@@ -1,33 +1,81 @@
-use crate::utils::sub_path;
+use crate::{
+	job_system::failures,
+	utils::{io_throttle::IoThrottle, network_share, sub_path},
+	JobName,
+};
 
 use sd_core_file_path_helper::{FilePathError, IsolatedFilePathData};
 
-use sd_file_ext::{extensions::Extension, kind::ObjectKind};
-use sd_prisma::prisma::{file_path, location};
+use sd_file_ext::{
+	custom_kind::KindRegistry, extensions::Extension, kind::ObjectKind, magic::ExtensionPossibility,
+};
+use sd_prisma::prisma::{file_path, job_error, location, PrismaClient};
+use sd_task_system::{Interrupter, InterruptionKind};
 use sd_utils::{db::MissingFieldError, error::FileIOError};
 
-use std::{fs::Metadata, path::Path};
+use std::{
+	collections::HashMap,
+	ffi::OsStr,
+	fs::Metadata,
+	path::{Path, PathBuf},
+};
 
-use prisma_client_rust::{or, QueryError};
+use futures_concurrency::future::TryJoin;
+use prisma_client_rust::{operator::and, or, QueryError};
 use rspc::ErrorCode;
 use serde::{Deserialize, Serialize};
 use specta::Type;
 use tokio::fs;
-use tracing::trace;
+use tracing::{trace, warn};
+use uuid::Uuid;
 
+mod archive;
+mod batching;
 mod cas_id;
+mod clones;
+mod cross_location;
+mod ephemeral;
+mod hard_links;
 pub mod job;
+mod on_demand;
+mod quick_metadata;
+mod rules;
+mod settings;
 mod shallow;
+mod sparse;
+pub mod statistics;
 mod tasks;
+mod xattrs;
 
-use cas_id::generate_cas_id;
+use cas_id::{generate_cas_id_interruptible, generate_link_cas_id, CasIdProgress};
 
-pub use job::FileIdentifier;
-pub use shallow::shallow;
+pub(crate) use archive::entries_root as archive_entries_root;
 
-// we break these tasks into chunks of 100 to improve performance
+pub use archive::{
+	not_in_archive, remove_archive_entries, ArchiveEntry, ArchiveFormat, WalkedArchive,
+};
+pub use batching::BatchSizeBounds;
+pub use cas_id::{generate_cas_id, CasIdAlgorithm, PartialCasId};
+pub use cross_location::CrossLocationLink;
+pub use ephemeral::{ephemeral_identify, EphemeralFileMetadata, EphemeralIdentification};
+pub use hard_links::{FileId, HardLinks};
+pub use job::{FileIdentifier, PriorityLane};
+pub use on_demand::{is_placeholder, OnDemandFilePolicy};
+pub use quick_metadata::QuickMetadata;
+pub use rules::{
+	clear_skipped, not_skipped, skip_matching, IdentifierRule, IdentifierRules, RulesScope,
+};
+pub use settings::IdentifierSettings;
+pub use shallow::{identify, shallow, Identified};
+pub use statistics::{IdentificationStatistics, KindIdentificationStatistics};
+pub use xattrs::ExtendedAttributes;
+
+// we break these tasks into chunks of 100 to improve performance, the job adapts it from there
 const CHUNK_SIZE: usize = 100;
 
+/// How many bytes from the start of a file we read to guess its kind when the extension doesn't tell it
+pub const DEFAULT_SNIFF_READ_BUDGET: usize = 1024 * 8;
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
 	#[error("missing field on database: {0}")]
@@ -41,6 +89,8 @@ pub enum Error {
 	FilePathError(#[from] FilePathError),
 	#[error(transparent)]
 	SubPath(#[from] sub_path::Error),
+	#[error(transparent)]
+	NetworkShare(#[from] network_share::Error),
 }
 
 impl From<Error> for rspc::Error {
@@ -48,12 +98,14 @@ impl From<Error> for rspc::Error {
 		match err {
 			Error::SubPath(sub_path_err) => sub_path_err.into(),
 
+			Error::NetworkShare(network_share_err) => network_share_err.into(),
+
 			_ => Self::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
 		}
 	}
 }
 
-#[derive(thiserror::Error, Debug, Serialize, Deserialize, Type)]
+#[derive(thiserror::Error, Debug, Clone, Serialize, Deserialize, Type)]
 pub enum NonCriticalError {
 	#[error("failed to extract file metadata: {0}")]
 	FailedToExtractFileMetadata(String),
@@ -64,73 +116,391 @@ pub enum NonCriticalError {
 	FailedToExtractIsolatedFilePathData(String),
 }
 
+/// What the identifier does with file paths that are symbolic links
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Type)]
+pub enum SymlinkPolicy {
+	/// Leave them without an object
+	Skip,
+	/// Identify the file they point to, broken links are reported as errors
+	#[default]
+	Follow,
+	/// Identify them as [`ObjectKind::Link`], keeping where they point to in `file_path.link_target`
+	RecordAsLink,
+}
+
+/// Options that change how [`FileMetadata::new`] analyzes each file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FileMetadataOptions {
+	pub cas_id_algorithm: CasIdAlgorithm,
+	/// Files up to this size are hashed entirely, see [`CasIdAlgorithm::for_file_size`]. Always
+	/// the one of the library, see [`OuterContext::deep_hash_threshold`](crate::OuterContext::deep_hash_threshold)
+	pub deep_hash_threshold: Option<u64>,
+	/// Read platform extended attributes, like Finder tags
+	pub extract_xattrs: bool,
+	/// How many bytes we read to sniff the kind of files with a missing or unknown extension,
+	/// 0 disables sniffing
+	pub sniff_read_budget: usize,
+	pub symlink_policy: SymlinkPolicy,
+	pub on_demand_policy: OnDemandFilePolicy,
+	/// Custom kinds defined by the library, resolved on top of the builtin [`ObjectKind`]
+	pub kind_registry: KindRegistry,
+	/// Identify the entries of zip, tar and 7z archives, see [`ArchiveFormat`]. Only has an effect
+	/// with the `archives` feature.
+	pub walk_archives: bool,
+	/// Read dimensions, durations and codecs from the headers of media files, see [`QuickMetadata`]
+	pub extract_quick_metadata: bool,
+	/// Caps how fast files are read while hashing them
+	pub io_throttle: IoThrottle,
+}
+
+impl Default for FileMetadataOptions {
+	fn default() -> Self {
+		Self {
+			cas_id_algorithm: CasIdAlgorithm::default(),
+			deep_hash_threshold: None,
+			extract_xattrs: false,
+			sniff_read_budget: DEFAULT_SNIFF_READ_BUDGET,
+			symlink_policy: SymlinkPolicy::default(),
+			on_demand_policy: OnDemandFilePolicy::default(),
+			kind_registry: KindRegistry::default(),
+			walk_archives: false,
+			extract_quick_metadata: false,
+			io_throttle: IoThrottle::default(),
+		}
+	}
+}
+
 #[derive(Debug, Clone)]
 pub struct FileMetadata {
 	pub cas_id: Option<String>,
 	pub kind: ObjectKind,
+	/// Name of the library defined kind matching this file, see [`KindRegistry`]
+	pub custom_kind: Option<String>,
 	pub fs_metadata: Metadata,
+	pub xattrs: Option<ExtendedAttributes>,
+	/// Only set for symbolic links identified with [`SymlinkPolicy::RecordAsLink`]
+	pub link_target: Option<PathBuf>,
+	/// Only set for files with more than one hard link
+	pub file_id: Option<FileId>,
+	/// Cloud placeholder identified without reading its content, see [`OnDemandFilePolicy`]
+	pub remote_only: bool,
+	/// Bytes not shared with copy-on-write clones of this file, `None` when the file system can't
+	/// tell or for files we don't read
+	pub physical_size: Option<u64>,
+	/// Only set for sparse files, bytes actually holding data, the rest being holes
+	pub allocated_size: Option<u64>,
+	/// Entries of archives walked with [`FileMetadataOptions::walk_archives`]
+	pub archive: Option<WalkedArchive>,
+	/// Only set with [`FileMetadataOptions::extract_quick_metadata`]
+	pub quick_metadata: Option<QuickMetadata>,
+}
+
+/// What [`FileMetadata::new`] got out of a file
+#[derive(Debug)]
+pub enum FileAnalysis {
+	Analyzed(FileMetadata),
+	/// Symbolic link left aside, see [`SymlinkPolicy::Skip`]
+	Skipped,
+	/// The task was interrupted while hashing the file, analyzing it again with this partial
+	/// `cas_id` continues the hashing from where it stopped
+	Interrupted(PartialCasId, InterruptionKind),
 }
 
 impl FileMetadata {
 	/// Fetch metadata from the file system and generate a cas id for the file
 	/// if it's not empty.
 	///
+	/// Hashing the whole file content checks the `interrupter` between chunks, see
+	/// [`FileAnalysis::Interrupted`]. Hard linked files are hashed only once, their `cas_id` being
+	/// kept in `hard_links`, so they always run to completion.
+	///
 	/// # Panics
 	/// Will panic if the file is a directory.
 	pub async fn new(
 		location_path: impl AsRef<Path> + Send,
 		iso_file_path: &IsolatedFilePathData<'_>,
-	) -> Result<Self, FileIOError> {
+		FileMetadataOptions {
+			cas_id_algorithm,
+			deep_hash_threshold,
+			extract_xattrs,
+			sniff_read_budget,
+			symlink_policy,
+			on_demand_policy,
+			kind_registry,
+			walk_archives,
+			extract_quick_metadata,
+			io_throttle,
+		}: &FileMetadataOptions,
+		hard_links: &HardLinks,
+		partial_cas_id: Option<PartialCasId>,
+		interrupter: &Interrupter,
+	) -> Result<FileAnalysis, FileIOError> {
 		let path = location_path.as_ref().join(iso_file_path);
 
-		let fs_metadata = fs::metadata(&path)
+		let mut fs_metadata = fs::symlink_metadata(&path)
 			.await
 			.map_err(|e| FileIOError::from((&path, e)))?;
 
+		if fs_metadata.is_symlink() {
+			match *symlink_policy {
+				SymlinkPolicy::Skip => {
+					trace!("Skipped symbolic link: <path='{}'>", path.display());
+					return Ok(FileAnalysis::Skipped);
+				}
+
+				SymlinkPolicy::Follow => {
+					fs_metadata = fs::metadata(&path)
+						.await
+						.map_err(|e| FileIOError::from((&path, e)))?;
+				}
+
+				SymlinkPolicy::RecordAsLink => {
+					let link_target = fs::read_link(&path)
+						.await
+						.map_err(|e| FileIOError::from((&path, e)))?;
+
+					trace!(
+						"Analyzed symbolic link: <path='{}', link_target='{}'>",
+						path.display(),
+						link_target.display()
+					);
+
+					return Ok(FileAnalysis::Analyzed(Self {
+						cas_id: Some(generate_link_cas_id(&link_target)),
+						kind: ObjectKind::Link,
+						custom_kind: None,
+						fs_metadata,
+						xattrs: None,
+						link_target: Some(link_target),
+						file_id: None,
+						remote_only: false,
+						physical_size: None,
+						allocated_size: None,
+						archive: None,
+						quick_metadata: None,
+					}));
+				}
+			}
+		}
+
 		assert!(
 			!fs_metadata.is_dir(),
 			"We can't generate cas_id for directories"
 		);
 
-		// derive Object kind
-		let kind = Extension::resolve_conflicting(&path, false)
-			.await
-			.map_or(ObjectKind::Unknown, Into::into);
+		// Reading a cloud placeholder would download its whole content, so we don't even sniff it
+		if *on_demand_policy == OnDemandFilePolicy::RecordAsRemote
+			&& on_demand::is_placeholder(&fs_metadata)
+		{
+			let (kind, custom_kind) = resolve_kind_from_extension(&path, kind_registry);
+
+			trace!(
+				"Analyzed on-demand file: <path='{}', object_kind={kind}, custom_kind={custom_kind:?}>",
+				path.display()
+			);
+
+			return Ok(FileAnalysis::Analyzed(Self {
+				cas_id: None,
+				kind,
+				custom_kind,
+				fs_metadata,
+				xattrs: None,
+				link_target: None,
+				file_id: None,
+				remote_only: true,
+				physical_size: None,
+				allocated_size: None,
+				archive: None,
+				quick_metadata: None,
+			}));
+		}
 
-		let cas_id = if fs_metadata.len() != 0 {
-			generate_cas_id(&path, fs_metadata.len())
+		let (kind, custom_kind) =
+			resolve_kind(&path, fs_metadata.len(), *sniff_read_budget, kind_registry).await;
+
+		let (cas_id, file_id) = if fs_metadata.len() != 0 {
+			let file_id = FileId::for_hard_link(&path, &fs_metadata)
+				.await
+				.map_err(|e| FileIOError::from((&path, e)))?;
+
+			let algorithm = cas_id_algorithm.for_file_size(fs_metadata.len(), *deep_hash_threshold);
+
+			let cas_id = if let Some(file_id) = file_id {
+				hard_links
+					.cas_id_or_init(file_id, || {
+						generate_cas_id(&path, fs_metadata.len(), algorithm, io_throttle)
+					})
+					.await
+					.map_err(|e| FileIOError::from((&path, e)))?
+			} else {
+				match generate_cas_id_interruptible(
+					&path,
+					fs_metadata.len(),
+					algorithm,
+					partial_cas_id,
+					io_throttle,
+					interrupter,
+				)
 				.await
-				.map(Some)
 				.map_err(|e| FileIOError::from((&path, e)))?
+				{
+					CasIdProgress::Done(cas_id) => cas_id,
+					CasIdProgress::Interrupted(partial_cas_id, kind) => {
+						trace!("Interrupted hashing of file: <path='{}'>", path.display());
+						return Ok(FileAnalysis::Interrupted(partial_cas_id, kind));
+					}
+				}
+			};
+
+			(Some(cas_id), file_id)
 		} else {
 			// We can't do shit with empty files
+			(None, None)
+		};
+
+		let (physical_size, allocated_size) = if fs_metadata.len() != 0 {
+			(
+				clones::physical_size(&path, &fs_metadata).await,
+				sparse::allocated_ranges(&path, fs_metadata.len())
+					.await
+					.map(|ranges| ranges.iter().map(|range| range.end - range.start).sum()),
+			)
+		} else {
+			(None, None)
+		};
+
+		// Failing to read extended attributes shouldn't prevent the file from being identified
+		let xattrs = if *extract_xattrs {
+			ExtendedAttributes::extract(&path)
+				.await
+				.map_err(|e| {
+					warn!(
+						"Failed to extract extended attributes <path='{}'>: {e:#?}",
+						path.display()
+					);
+				})
+				.ok()
+		} else {
+			None
+		};
+
+		// Same for archives, a corrupted or encrypted one is still identified as a file
+		let archive = match ArchiveFormat::from_path(&path) {
+			Some(format) if *walk_archives && fs_metadata.len() != 0 => archive::walk(
+				path.clone(),
+				iso_file_path,
+				format,
+				*cas_id_algorithm,
+				*deep_hash_threshold,
+				kind_registry.clone(),
+			)
+			.await
+			.map_err(|e| {
+				warn!("Failed to walk archive <path='{}'>: {e:#?}", path.display());
+			})
+			.ok(),
+			_ => None,
+		};
+
+		// Right after hashing, so the headers are still in the page cache
+		let quick_metadata = if *extract_quick_metadata && fs_metadata.len() != 0 {
+			QuickMetadata::extract(&path, kind)
+				.await
+				.map_err(|e| {
+					warn!(
+						"Failed to extract quick metadata <path='{}'>: {e:#?}",
+						path.display()
+					);
+				})
+				.ok()
+				.flatten()
+		} else {
 			None
 		};
 
 		trace!(
-			"Analyzed file: <path='{}', cas_id={cas_id:?}, object_kind={kind}>",
+			"Analyzed file: <path='{}', cas_id={cas_id:?}, object_kind={kind}, custom_kind={custom_kind:?}>",
 			path.display()
 		);
 
-		Ok(Self {
+		Ok(FileAnalysis::Analyzed(Self {
 			cas_id,
 			kind,
+			custom_kind,
 			fs_metadata,
-		})
+			xattrs,
+			link_target: None,
+			file_id,
+			remote_only: false,
+			physical_size,
+			allocated_size,
+			archive,
+			quick_metadata,
+		}))
 	}
 }
 
+/// Derives the [`ObjectKind`] of a file, falling back to its content when the extension doesn't
+/// tell it, along with the name of its library defined kind if any. Doesn't hash the file.
+pub async fn resolve_kind(
+	path: impl AsRef<Path> + Send,
+	size: u64,
+	sniff_read_budget: usize,
+	kind_registry: &KindRegistry,
+) -> (ObjectKind, Option<String>) {
+	let path = path.as_ref();
+
+	let kind = match Extension::resolve_conflicting(path, false).await {
+		Some(extension) => Some(extension),
+		None if sniff_read_budget != 0 && size != 0 => {
+			Extension::sniff(path, sniff_read_budget).await
+		}
+		None => None,
+	}
+	.map_or(ObjectKind::Unknown, Into::into);
+
+	let custom_kind = if kind_registry.is_empty() {
+		None
+	} else {
+		kind_registry
+			.resolve_path(path)
+			.await
+			.map(|custom_kind| custom_kind.name.clone())
+	};
+
+	(kind, custom_kind)
+}
+
+/// Same as [`resolve_kind`] without touching the file content, conflicting extensions are left as
+/// [`ObjectKind::Unknown`] as telling them apart requires their magic bytes
+fn resolve_kind_from_extension(
+	path: &Path,
+	kind_registry: &KindRegistry,
+) -> (ObjectKind, Option<String>) {
+	let extension = path.extension().and_then(OsStr::to_str);
+
+	let kind = match extension.and_then(Extension::from_str) {
+		Some(ExtensionPossibility::Known(extension)) => extension.into(),
+		Some(ExtensionPossibility::Conflicts(_)) | None => ObjectKind::Unknown,
+	};
+
+	let custom_kind = kind_registry
+		.resolve(extension, &[])
+		.map(|custom_kind| custom_kind.name.clone());
+
+	(kind, custom_kind)
+}
+
 fn orphan_path_filters_shallow(
 	location_id: location::id::Type,
 	file_path_id: Option<file_path::id::Type>,
 	sub_iso_file_path: &IsolatedFilePathData<'_>,
+	reidentify: bool,
+	retry_failed: bool,
 ) -> Vec<file_path::WhereParam> {
 	sd_utils::chain_optional_iter(
 		[
-			or!(
-				file_path::object_id::equals(None),
-				file_path::cas_id::equals(None)
-			),
 			file_path::is_dir::equals(Some(false)),
 			file_path::location_id::equals(Some(location_id)),
 			file_path::materialized_path::equals(Some(
@@ -139,8 +509,14 @@ fn orphan_path_filters_shallow(
 					.expect("sub path for shallow identifier must be a directory"),
 			)),
 			file_path::size_in_bytes_bytes::not(Some(0u64.to_be_bytes().to_vec())),
+			not_in_archive(),
+			not_skipped(),
+		],
+		[
+			file_path_id.map(file_path::id::gt),
+			(!reidentify).then(orphans_filter),
+			retry_failed.then(failed_filter),
 		],
-		[file_path_id.map(file_path::id::gte)],
 	)
 }
 
@@ -148,20 +524,20 @@ fn orphan_path_filters_deep(
 	location_id: location::id::Type,
 	file_path_id: Option<file_path::id::Type>,
 	maybe_sub_iso_file_path: &Option<IsolatedFilePathData<'_>>,
+	reidentify: bool,
+	retry_failed: bool,
 ) -> Vec<file_path::WhereParam> {
 	sd_utils::chain_optional_iter(
 		[
-			or!(
-				file_path::object_id::equals(None),
-				file_path::cas_id::equals(None)
-			),
 			file_path::is_dir::equals(Some(false)),
 			file_path::location_id::equals(Some(location_id)),
 			file_path::size_in_bytes_bytes::not(Some(0u64.to_be_bytes().to_vec())),
+			not_in_archive(),
+			not_skipped(),
 		],
 		[
 			// this is a workaround for the cursor not working properly
-			file_path_id.map(file_path::id::gte),
+			file_path_id.map(file_path::id::gt),
 			maybe_sub_iso_file_path.as_ref().map(|sub_iso_file_path| {
 				file_path::materialized_path::starts_with(
 					sub_iso_file_path
@@ -169,6 +545,66 @@ fn orphan_path_filters_deep(
 						.expect("sub path iso_file_path must be a directory"),
 				)
 			}),
+			(!reidentify).then(orphans_filter),
+			retry_failed.then(failed_filter),
 		],
 	)
 }
+
+/// Orphans are file paths without an object or without a `cas_id`, when re-identifying a location
+/// (e.g. after a [`CasIdAlgorithm`] change) we skip this filter to process every file path.
+/// Cloud placeholders never get a `cas_id` until hydrated, so they aren't orphans once they have an object.
+fn orphans_filter() -> file_path::WhereParam {
+	or!(
+		file_path::object_id::equals(None),
+		and(vec![
+			file_path::cas_id::equals(None),
+			or!(
+				file_path::remote_only::equals(None),
+				file_path::remote_only::equals(Some(false))
+			),
+		])
+	)
+}
+
+/// File paths the file identifier failed to identify before, as persisted in the `job_error` table
+fn failed_filter() -> file_path::WhereParam {
+	file_path::job_errors::some(vec![job_error::job_name::equals(Some(
+		JobName::FileIdentifier.to_string(),
+	))])
+}
+
+/// Persists the errors of file paths that failed to be identified, and clears the ones of file
+/// paths identified this time, so they can be listed and retried later. It's best effort, as the
+/// errors are still in the job report.
+async fn update_persisted_failures(
+	db: &PrismaClient,
+	location_id: location::id::Type,
+	identified_files: &HashMap<Uuid, tasks::IdentifiedFile>,
+	failed_file_paths: Vec<(file_path::id::Type, NonCriticalError)>,
+) {
+	if let Err(e) = (
+		failures::record_failures(
+			db,
+			JobName::FileIdentifier,
+			location_id,
+			failed_file_paths
+				.into_iter()
+				.map(|(file_path_id, error)| (file_path_id, error.into()))
+				.collect(),
+		),
+		failures::clear_failures(
+			db,
+			JobName::FileIdentifier,
+			identified_files
+				.values()
+				.map(|identified_file| identified_file.file_path.id)
+				.collect(),
+		),
+	)
+		.try_join()
+		.await
+	{
+		warn!("Failed to persist file identifier failures: {e:#?}");
+	}
+}
@@ -0,0 +1,48 @@
+use std::fs::Metadata;
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+/// What the identifier does with cloud backed placeholder files (e.g. OneDrive files available
+/// on-demand), whose content is only downloaded when read. Only Windows exposes them for now.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Type)]
+pub enum OnDemandFilePolicy {
+	/// Read them like any other file, failing if the cloud provider can't hydrate them
+	Fail,
+	/// Identify them without reading their content, taking the kind from their extension and
+	/// leaving them without a `cas_id`, flagged in `file_path.remote_only` until hydrated
+	#[default]
+	RecordAsRemote,
+}
+
+/// Tells if the file content lives in the cloud and would be downloaded by reading it
+#[must_use]
+pub fn is_placeholder(fs_metadata: &Metadata) -> bool {
+	platform::is_placeholder(fs_metadata)
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+	use std::{fs::Metadata, os::windows::fs::MetadataExt};
+
+	use windows::Win32::Storage::FileSystem::{
+		FILE_ATTRIBUTE_OFFLINE, FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS, FILE_ATTRIBUTE_RECALL_ON_OPEN,
+	};
+
+	pub fn is_placeholder(fs_metadata: &Metadata) -> bool {
+		fs_metadata.file_attributes()
+			& (FILE_ATTRIBUTE_OFFLINE.0
+				| FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS.0
+				| FILE_ATTRIBUTE_RECALL_ON_OPEN.0)
+			!= 0
+	}
+}
+
+#[cfg(not(target_os = "windows"))]
+mod platform {
+	use std::fs::Metadata;
+
+	pub const fn is_placeholder(_: &Metadata) -> bool {
+		false
+	}
+}
@@ -0,0 +1,321 @@
+use sd_file_ext::kind::ObjectKind;
+
+use std::{io::Cursor, path::Path};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::{
+	fs::File,
+	io::{self, AsyncReadExt},
+};
+
+/// How many bytes from the start of a media file we read looking for its quick metadata
+const QUICK_METADATA_READ_BUDGET: u64 = 1024 * 64;
+
+/// A few media properties read from the headers of a file while identifying it, so the Explorer can
+/// show them before the media processor extracts the full metadata. Each field is only set when it
+/// fits in the first [`QUICK_METADATA_READ_BUDGET`] bytes of the file.
+///
+/// Supported: image dimensions for formats the `image` crate can decode, audio duration for FLAC
+/// and WAV, and video dimensions, duration and codec for MP4 and QuickTime files with their `moov`
+/// box at the start (a.k.a. "fast start").
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, Type)]
+pub struct QuickMetadata {
+	pub dimensions: Option<(u32, u32)>,
+	pub duration_ms: Option<u64>,
+	/// Four character code of the video track's sample entry, e.g. `avc1` or `hvc1`
+	pub video_codec: Option<String>,
+}
+
+impl QuickMetadata {
+	/// `None` for kinds we don't extract anything from, or if nothing was found in the headers
+	pub async fn extract(
+		path: impl AsRef<Path> + Send,
+		kind: ObjectKind,
+	) -> Result<Option<Self>, io::Error> {
+		if !matches!(
+			kind,
+			ObjectKind::Image | ObjectKind::Audio | ObjectKind::Video
+		) {
+			return Ok(None);
+		}
+
+		let mut header = Vec::new();
+		File::open(path)
+			.await?
+			.take(QUICK_METADATA_READ_BUDGET)
+			.read_to_end(&mut header)
+			.await?;
+
+		let quick_metadata = Self::from_header(&header, kind);
+
+		Ok((quick_metadata != Self::default()).then_some(quick_metadata))
+	}
+
+	fn from_header(header: &[u8], kind: ObjectKind) -> Self {
+		match kind {
+			ObjectKind::Image => Self {
+				dimensions: image::io::Reader::new(Cursor::new(header))
+					.with_guessed_format()
+					.ok()
+					.and_then(|reader| reader.into_dimensions().ok()),
+				..Default::default()
+			},
+
+			ObjectKind::Audio => Self {
+				duration_ms: flac_duration_ms(header).or_else(|| wav_duration_ms(header)),
+				..Default::default()
+			},
+
+			ObjectKind::Video => mp4_metadata(header),
+
+			_ => Self::default(),
+		}
+	}
+}
+
+fn flac_duration_ms(header: &[u8]) -> Option<u64> {
+	// STREAMINFO is always the first metadata block, right after the 4 bytes block header
+	let stream_info = header.strip_prefix(b"fLaC")?.get(4..4 + 34)?;
+	let fields = &stream_info[10..18];
+
+	// Sample rate takes 20 bits, the total samples count takes the last 36 bits
+	let sample_rate =
+		(u64::from(fields[0]) << 12) | (u64::from(fields[1]) << 4) | (u64::from(fields[2]) >> 4);
+	let total_samples = (u64::from(fields[3] & 0x0F) << 32)
+		| u64::from(u32::from_be_bytes([
+			fields[4], fields[5], fields[6], fields[7],
+		]));
+
+	(sample_rate != 0 && total_samples != 0).then(|| total_samples * 1000 / sample_rate)
+}
+
+fn wav_duration_ms(header: &[u8]) -> Option<u64> {
+	if header.get(..4)? != b"RIFF" || header.get(8..12)? != b"WAVE" {
+		return None;
+	}
+
+	let mut byte_rate = None;
+	let mut chunks = header.get(12..)?;
+
+	while let (Some(id), Some(size)) = (chunks.get(..4), chunks.get(4..8)) {
+		let size = u32::from_le_bytes([size[0], size[1], size[2], size[3]]) as usize;
+
+		match id {
+			b"fmt " => {
+				let byte_rate_bytes = chunks.get(16..20)?;
+				byte_rate = Some(u64::from(u32::from_le_bytes([
+					byte_rate_bytes[0],
+					byte_rate_bytes[1],
+					byte_rate_bytes[2],
+					byte_rate_bytes[3],
+				])));
+			}
+			b"data" => {
+				return byte_rate
+					.filter(|byte_rate| *byte_rate != 0)
+					.map(|byte_rate| size as u64 * 1000 / byte_rate);
+			}
+			_ => {}
+		}
+
+		// Chunks are padded to an even size
+		chunks = chunks.get(8 + size + (size & 1)..)?;
+	}
+
+	None
+}
+
+fn mp4_metadata(header: &[u8]) -> QuickMetadata {
+	let mut quick_metadata = QuickMetadata::default();
+
+	let Some(moov) = find_box(header, b"moov") else {
+		// Files without "fast start" have their `moov` box after the media data
+		return quick_metadata;
+	};
+
+	if let Some(mvhd) = find_box(moov, b"mvhd") {
+		quick_metadata.duration_ms = mvhd_duration_ms(mvhd);
+	}
+
+	for (box_type, trak) in boxes(moov) {
+		if &box_type != b"trak" {
+			continue;
+		}
+
+		let Some(mdia) = find_box(trak, b"mdia") else {
+			continue;
+		};
+
+		// Handler type comes after version, flags and a predefined field
+		if find_box(mdia, b"hdlr").and_then(|hdlr| hdlr.get(8..12)) != Some(&b"vide"[..]) {
+			continue;
+		}
+
+		quick_metadata.dimensions = find_box(trak, b"tkhd").and_then(tkhd_dimensions);
+
+		// The sample entry of the first sample description comes after version, flags and the
+		// entries count, its type is the codec
+		quick_metadata.video_codec = find_box(mdia, b"minf")
+			.and_then(|minf| find_box(minf, b"stbl"))
+			.and_then(|stbl| find_box(stbl, b"stsd"))
+			.and_then(|stsd| stsd.get(12..16))
+			.map(|codec| String::from_utf8_lossy(codec).trim().to_string());
+
+		break;
+	}
+
+	quick_metadata
+}
+
+fn mvhd_duration_ms(mvhd: &[u8]) -> Option<u64> {
+	let (timescale, duration) = if *mvhd.first()? == 1 {
+		(
+			read_u32(mvhd, 20)?,
+			u64::from_be_bytes(mvhd.get(24..32)?.try_into().ok()?),
+		)
+	} else {
+		(read_u32(mvhd, 12)?, u64::from(read_u32(mvhd, 16)?))
+	};
+
+	(timescale != 0).then(|| duration * 1000 / u64::from(timescale))
+}
+
+fn tkhd_dimensions(tkhd: &[u8]) -> Option<(u32, u32)> {
+	// Width and height are 16.16 fixed point numbers at the end of the box
+	let dimensions = tkhd.get(tkhd.len().checked_sub(8)?..)?;
+	let (width, height) = (
+		read_u32(dimensions, 0)? >> 16,
+		read_u32(dimensions, 4)? >> 16,
+	);
+
+	(width != 0 && height != 0).then_some((width, height))
+}
+
+/// Children boxes of an ISO base media file box, stopping at the first one cut by the read budget
+fn boxes(data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+	let mut remaining = data;
+
+	std::iter::from_fn(move || {
+		let size = read_u32(remaining, 0)? as usize;
+		let box_type: [u8; 4] = remaining.get(4..8)?.try_into().ok()?;
+
+		let (header_size, size) = match size {
+			// Box extends to the end of the file
+			0 => (8, remaining.len()),
+			// 64 bits size right after the type
+			1 => (
+				16,
+				usize::try_from(u64::from_be_bytes(remaining.get(8..16)?.try_into().ok()?)).ok()?,
+			),
+			size => (8, size),
+		};
+
+		let content = remaining.get(header_size..size)?;
+		remaining = &remaining[size..];
+
+		Some((box_type, content))
+	})
+}
+
+fn find_box<'data>(data: &'data [u8], box_type: &[u8; 4]) -> Option<&'data [u8]> {
+	boxes(data).find_map(|(found_type, content)| (&found_type == box_type).then_some(content))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+	data.get(offset..offset + 4)
+		.and_then(|bytes| bytes.try_into().ok())
+		.map(u32::from_be_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn mp4_box(box_type: &[u8; 4], content: &[u8]) -> Vec<u8> {
+		let mut data = u32::try_from(content.len() + 8)
+			.expect("test boxes are small")
+			.to_be_bytes()
+			.to_vec();
+		data.extend_from_slice(box_type);
+		data.extend_from_slice(content);
+		data
+	}
+
+	#[test]
+	fn flac_duration() {
+		let mut header = b"fLaC".to_vec();
+		header.extend_from_slice(&[0x80, 0, 0, 34]);
+		let mut stream_info = [0u8; 34];
+		// 44100 Hz, 2 channels, 16 bits, 441000 samples
+		stream_info[10..18].copy_from_slice(&[0x0A, 0xC4, 0x42, 0xF0, 0x00, 0x06, 0xBA, 0xA8]);
+		header.extend_from_slice(&stream_info);
+
+		assert_eq!(
+			QuickMetadata::from_header(&header, ObjectKind::Audio).duration_ms,
+			Some(10_000)
+		);
+	}
+
+	#[test]
+	fn wav_duration() {
+		let mut header = b"RIFF\0\0\0\0WAVE".to_vec();
+		header.extend_from_slice(b"fmt ");
+		header.extend_from_slice(&16u32.to_le_bytes());
+		header.extend_from_slice(&[1, 0, 2, 0]);
+		header.extend_from_slice(&44_100u32.to_le_bytes());
+		header.extend_from_slice(&176_400u32.to_le_bytes());
+		header.extend_from_slice(&[4, 0, 16, 0]);
+		header.extend_from_slice(b"data");
+		header.extend_from_slice(&352_800u32.to_le_bytes());
+
+		assert_eq!(
+			QuickMetadata::from_header(&header, ObjectKind::Audio).duration_ms,
+			Some(2_000)
+		);
+	}
+
+	#[test]
+	fn mp4_fast_start() {
+		let mut mvhd = vec![0u8; 20];
+		mvhd[12..16].copy_from_slice(&1000u32.to_be_bytes());
+		mvhd[16..20].copy_from_slice(&90_500u32.to_be_bytes());
+
+		let mut tkhd = vec![0u8; 84];
+		tkhd[76..80].copy_from_slice(&(1920u32 << 16).to_be_bytes());
+		tkhd[80..84].copy_from_slice(&(1080u32 << 16).to_be_bytes());
+
+		let mut hdlr = vec![0u8; 12];
+		hdlr[8..12].copy_from_slice(b"vide");
+
+		let mut stsd = vec![0u8; 16];
+		stsd[12..16].copy_from_slice(b"hvc1");
+
+		let stbl = mp4_box(b"stbl", &mp4_box(b"stsd", &stsd));
+		let minf = mp4_box(b"minf", &stbl);
+		let mdia = mp4_box(b"mdia", &[mp4_box(b"hdlr", &hdlr), minf].concat());
+		let trak = mp4_box(b"trak", &[mp4_box(b"tkhd", &tkhd), mdia].concat());
+		let moov = mp4_box(b"moov", &[mp4_box(b"mvhd", &mvhd), trak].concat());
+
+		let header = [mp4_box(b"ftyp", b"isom"), moov].concat();
+
+		assert_eq!(
+			QuickMetadata::from_header(&header, ObjectKind::Video),
+			QuickMetadata {
+				dimensions: Some((1920, 1080)),
+				duration_ms: Some(90_500),
+				video_codec: Some("hvc1".to_string()),
+			}
+		);
+	}
+
+	#[test]
+	fn mp4_without_fast_start() {
+		let header = [mp4_box(b"ftyp", b"isom"), mp4_box(b"mdat", &[0; 64])].concat();
+
+		assert_eq!(
+			QuickMetadata::from_header(&header, ObjectKind::Video),
+			QuickMetadata::default()
+		);
+	}
+}
@@ -0,0 +1,350 @@
+use sd_core_sync::Manager as SyncManager;
+
+use sd_prisma::{
+	prisma::{file_path, location, PrismaClient},
+	prisma_sync,
+};
+use sd_sync::OperationFactory;
+use sd_utils::msgpack;
+
+use std::collections::BTreeSet;
+
+use globset::Glob;
+use prisma_client_rust::{or, PrismaValue, QueryError, Raw};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tracing::{trace, warn};
+
+const MIB: u64 = 1024 * 1024;
+
+/// File paths flagged per query, well below SQLite's limit of variables
+const CHUNK_SIZE: usize = 1000;
+
+/// Tells which files of a location the file identifier must leave alone. Matching files stay
+/// indexed, but they aren't hashed nor get an object, and are flagged with `identification_skipped`.
+///
+/// Stored msgpack encoded in `location.identifier_rules`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum IdentifierRule {
+	/// Files bigger than this many MiB
+	MaxSizeMiB(u32),
+	/// Extensions without the leading dot, case insensitive
+	Extensions(Vec<String>),
+	/// Glob matched against the path of the file relative to the location root, e.g. `backups/**`
+	Glob(String),
+}
+
+/// Identifier rules of a location, compiled to a SQL condition so matching file paths are found
+/// and flagged in the database, without going through every orphan
+#[derive(Debug, Clone)]
+pub struct IdentifierRules {
+	max_size: Option<u64>,
+	extensions: BTreeSet<String>,
+	/// In SQLite's `GLOB` syntax
+	globs: Vec<String>,
+}
+
+/// Which file paths of a location identifier rules are applied to
+#[derive(Debug, Clone, Copy)]
+pub enum RulesScope<'a> {
+	Location,
+	/// Files directly in the directory with this materialized path for its children
+	Children(&'a str),
+	/// Files anywhere under the directory with this materialized path for its children
+	Descendants(&'a str),
+}
+
+impl IdentifierRules {
+	/// Invalid globs are ignored, as a broken rule shouldn't stop the whole location from being
+	/// identified
+	#[must_use]
+	pub fn new(rules: &[IdentifierRule]) -> Self {
+		let mut max_size = None;
+		let mut extensions = BTreeSet::new();
+		let mut globs = Vec::new();
+
+		for rule in rules {
+			match rule {
+				IdentifierRule::MaxSizeMiB(max_size_mib) => {
+					let rule_max_size = u64::from(*max_size_mib) * MIB;
+					max_size = Some(
+						max_size.map_or(rule_max_size, |max_size: u64| max_size.min(rule_max_size)),
+					);
+				}
+
+				IdentifierRule::Extensions(rule_extensions) => extensions.extend(
+					rule_extensions
+						.iter()
+						.map(|extension| extension.trim_start_matches('.').to_lowercase())
+						.filter(|extension| !extension.is_empty()),
+				),
+
+				IdentifierRule::Glob(glob) => match Glob::new(glob) {
+					Ok(_) => globs.extend(to_sqlite_globs(glob)),
+					Err(e) => {
+						warn!("Ignoring invalid identifier rule glob <glob='{glob}'>: {e:#?}");
+					}
+				},
+			}
+		}
+
+		Self {
+			max_size,
+			extensions,
+			globs,
+		}
+	}
+
+	#[must_use]
+	pub fn from_location(location: &location::Data) -> Self {
+		Self::new(&IdentifierRule::of_location(location))
+	}
+
+	/// SQL condition matching the file paths these rules apply to, `None` if there are no rules
+	fn compile(&self) -> Option<(String, Vec<PrismaValue>)> {
+		let mut conditions = Vec::new();
+		let mut params = Vec::new();
+
+		if let Some(max_size) = self.max_size {
+			// Sizes are stored as 8 big endian bytes, so they compare like the numbers they hold
+			conditions
+				.push("(LENGTH(size_in_bytes_bytes) = 8 AND size_in_bytes_bytes > {})".to_string());
+			params.push(PrismaValue::Bytes(max_size.to_be_bytes().to_vec()));
+		}
+
+		if !self.extensions.is_empty() {
+			conditions.push(format!(
+				"LOWER(extension) IN ({})",
+				vec!["{}"; self.extensions.len()].join(",")
+			));
+			params.extend(self.extensions.iter().cloned().map(PrismaValue::String));
+		}
+
+		for glob in &self.globs {
+			conditions.push(
+				"(LTRIM(materialized_path, '/') || name || CASE
+					WHEN extension IS NULL OR extension = '' THEN ''
+					ELSE '.' || extension
+				END) GLOB {}"
+					.to_string(),
+			);
+			params.push(PrismaValue::String(glob.clone()));
+		}
+
+		(!conditions.is_empty()).then(|| (conditions.join(" OR "), params))
+	}
+
+	/// Ids of the files in `scope` matching these rules which aren't flagged as skipped yet
+	pub async fn matching(
+		&self,
+		location_id: location::id::Type,
+		scope: RulesScope<'_>,
+		db: &PrismaClient,
+	) -> Result<Vec<file_path::id::Type>, QueryError> {
+		#[derive(Deserialize)]
+		struct RuleMatch {
+			id: file_path::id::Type,
+		}
+
+		let Some((conditions, rules_params)) = self.compile() else {
+			return Ok(vec![]);
+		};
+
+		let mut params = vec![PrismaValue::Int(i64::from(location_id))];
+
+		let scope_condition = match scope {
+			RulesScope::Location => "1",
+			RulesScope::Children(materialized_path) => {
+				params.push(PrismaValue::String(materialized_path.to_string()));
+				"materialized_path = {}"
+			}
+			RulesScope::Descendants(materialized_path) => {
+				params.push(PrismaValue::String(format!(
+					"{}%",
+					materialized_path
+						.replace('\\', "\\\\")
+						.replace('%', "\\%")
+						.replace('_', "\\_")
+				)));
+				"materialized_path LIKE {} ESCAPE '\\'"
+			}
+		};
+
+		params.extend(rules_params);
+
+		db._query_raw::<RuleMatch>(Raw::new(
+			&format!(
+				"SELECT id
+				FROM file_path
+				WHERE
+					location_id = {{}}
+					AND is_dir = FALSE
+					AND (in_archive IS NULL OR in_archive = 0)
+					AND (identification_skipped IS NULL OR identification_skipped = 0)
+					AND {scope_condition}
+					AND ({conditions})"
+			),
+			params,
+		))
+		.exec()
+		.await
+		.map(|matches| matches.into_iter().map(|RuleMatch { id }| id).collect())
+	}
+}
+
+impl IdentifierRule {
+	/// The rules stored in `location.identifier_rules`, none if they can't be read
+	#[must_use]
+	pub fn of_location(location: &location::Data) -> Vec<Self> {
+		location
+			.identifier_rules
+			.as_deref()
+			.and_then(|rules| {
+				rmp_serde::from_slice::<Vec<Self>>(rules)
+					.map_err(|e| {
+						warn!(
+							"Failed to deserialize identifier rules <location_id={}>: {e:#?}",
+							location.id
+						);
+					})
+					.ok()
+			})
+			.unwrap_or_default()
+	}
+}
+
+/// Translates a glob to SQLite's `GLOB` syntax, one glob for each of its `{a,b}` alternatives.
+///
+/// Both match `/` with `*`, so `**` is the same as `*`.
+fn to_sqlite_globs(glob: &str) -> Vec<String> {
+	let Some((prefix, rest)) = glob.split_once('{') else {
+		return vec![glob.replace("**", "*").replace("[!", "[^")];
+	};
+
+	let Some((alternatives, suffix)) = rest.split_once('}') else {
+		return vec![glob.replace("**", "*").replace("[!", "[^")];
+	};
+
+	alternatives
+		.split(',')
+		.flat_map(|alternative| to_sqlite_globs(&format!("{prefix}{alternative}{suffix}")))
+		.collect()
+}
+
+/// Flags the files in `scope` matching `rules` as skipped, all found with a single query, returning
+/// how many were flagged
+pub async fn skip_matching(
+	rules: &IdentifierRules,
+	location_id: location::id::Type,
+	scope: RulesScope<'_>,
+	db: &PrismaClient,
+	sync: &SyncManager,
+) -> Result<u64, QueryError> {
+	let ids = rules.matching(location_id, scope, db).await?;
+
+	if ids.is_empty() {
+		return Ok(0);
+	}
+
+	trace!("Skipping {} file paths by identifier rules", ids.len());
+
+	for chunk in ids.chunks(CHUNK_SIZE) {
+		let file_paths = db
+			.file_path()
+			.find_many(vec![file_path::id::in_vec(chunk.to_vec())])
+			.select(file_path::select!({ id pub_id }))
+			.exec()
+			.await?;
+
+		set_identification_skipped(
+			file_paths
+				.into_iter()
+				.map(|file_path| (file_path.id, file_path.pub_id))
+				.collect(),
+			Some(true),
+			db,
+			sync,
+		)
+		.await?;
+	}
+
+	Ok(ids.len() as u64)
+}
+
+/// Clears the skipped flag of every file path in the location, so they're matched against the
+/// location's identifier rules again when they change
+pub async fn clear_skipped(
+	location_id: location::id::Type,
+	db: &PrismaClient,
+	sync: &SyncManager,
+) -> Result<(), QueryError> {
+	let skipped = db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(Some(location_id)),
+			file_path::identification_skipped::equals(Some(true)),
+		])
+		.select(file_path::select!({ id pub_id }))
+		.exec()
+		.await?;
+
+	if skipped.is_empty() {
+		return Ok(());
+	}
+
+	set_identification_skipped(
+		skipped
+			.into_iter()
+			.map(|file_path| (file_path.id, file_path.pub_id))
+			.collect(),
+		None,
+		db,
+		sync,
+	)
+	.await
+}
+
+async fn set_identification_skipped(
+	file_paths: Vec<(file_path::id::Type, Vec<u8>)>,
+	identification_skipped: Option<bool>,
+	db: &PrismaClient,
+	sync: &SyncManager,
+) -> Result<(), QueryError> {
+	let (sync_ops, ids) = file_paths
+		.into_iter()
+		.map(|(id, pub_id)| {
+			(
+				sync.shared_update(
+					prisma_sync::file_path::SyncId { pub_id },
+					file_path::identification_skipped::NAME,
+					msgpack!(identification_skipped),
+				),
+				id,
+			)
+		})
+		.unzip::<_, _, Vec<_>, Vec<_>>();
+
+	sync.write_ops(
+		db,
+		(
+			sync_ops,
+			db.file_path().update_many(
+				vec![file_path::id::in_vec(ids)],
+				vec![file_path::identification_skipped::set(
+					identification_skipped,
+				)],
+			),
+		),
+	)
+	.await?;
+
+	Ok(())
+}
+
+/// Skipped file paths aren't orphans, even if they don't have an object
+pub fn not_skipped() -> file_path::WhereParam {
+	or!(
+		file_path::identification_skipped::equals(None),
+		file_path::identification_skipped::equals(Some(false))
+	)
+}
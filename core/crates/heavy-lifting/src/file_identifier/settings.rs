@@ -0,0 +1,60 @@
+use sd_prisma::prisma::location;
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tracing::warn;
+
+use super::{FileMetadataOptions, OnDemandFilePolicy, SymlinkPolicy};
+
+/// How the file identifier treats the files of a location, applied to every scan of it.
+///
+/// Stored msgpack encoded in `location.identifier_settings`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(default)]
+pub struct IdentifierSettings {
+	/// What to do with the symbolic links of the location
+	pub symlink_policy: SymlinkPolicy,
+	/// Identify the entries of zip, tar and 7z archives, see [`ArchiveFormat`](super::ArchiveFormat)
+	pub walk_archives: bool,
+	/// Read dimensions, durations and codecs from the headers of media files while identifying
+	/// them, see [`QuickMetadata`](super::QuickMetadata)
+	pub extract_quick_metadata: bool,
+	/// How many bytes we read to sniff the kind of files with a missing or unknown extension, 0
+	/// disables sniffing, [`DEFAULT_SNIFF_READ_BUDGET`](super::DEFAULT_SNIFF_READ_BUDGET) if unset
+	pub sniff_read_budget: Option<usize>,
+	/// What to do with cloud placeholder files, whose content is downloaded by reading it
+	pub on_demand_policy: OnDemandFilePolicy,
+}
+
+impl IdentifierSettings {
+	/// The settings stored in `location.identifier_settings`, the default ones if they can't be read
+	#[must_use]
+	pub fn of_location(location: &location::Data) -> Self {
+		location
+			.identifier_settings
+			.as_deref()
+			.and_then(|settings| {
+				rmp_serde::from_slice::<Self>(settings)
+					.map_err(|e| {
+						warn!(
+							"Failed to deserialize identifier settings <location_id={}>: {e:#?}",
+							location.id
+						);
+					})
+					.ok()
+			})
+			.unwrap_or_default()
+	}
+
+	/// Overrides the `options` the library identifies files with by the ones of the location
+	pub fn apply(&self, options: &mut FileMetadataOptions) {
+		options.symlink_policy = self.symlink_policy;
+		options.walk_archives = self.walk_archives;
+		options.extract_quick_metadata = self.extract_quick_metadata;
+		options.on_demand_policy = self.on_demand_policy;
+
+		if let Some(sniff_read_budget) = self.sniff_read_budget {
+			options.sniff_read_budget = sniff_read_budget;
+		}
+	}
+}
@@ -1,6 +1,7 @@
 use crate::{
-	file_identifier, utils::sub_path::maybe_get_iso_file_path_from_sub_path, Error,
-	NonCriticalError, OuterContext,
+	file_identifier::{self, FileMetadataOptions, HardLinks, IdentifierRules, RulesScope},
+	utils::sub_path::maybe_get_iso_file_path_from_sub_path,
+	Error, NonCriticalError, OuterContext,
 };
 
 use sd_core_file_path_helper::IsolatedFilePathData;
@@ -22,19 +23,22 @@ use lending_stream::{LendingStream, StreamExt};
 use tracing::{debug, warn};
 
 use super::{
-	orphan_path_filters_shallow,
+	orphan_path_filters_shallow, rules,
 	tasks::{
 		extract_file_metadata, object_processor, ExtractFileMetadataTask, ObjectProcessorTask,
 	},
-	CHUNK_SIZE,
+	update_persisted_failures, CHUNK_SIZE,
 };
 
 pub async fn shallow(
 	location: location::Data,
 	sub_path: impl AsRef<Path> + Send,
+	mut options: FileMetadataOptions,
 	dispatcher: BaseTaskDispatcher<Error>,
 	ctx: impl OuterContext,
 ) -> Result<Vec<NonCriticalError>, Error> {
+	options.deep_hash_threshold = ctx.deep_hash_threshold().await;
+
 	let sub_path = sub_path.as_ref();
 	let db = ctx.db();
 
@@ -57,6 +61,20 @@ pub async fn shallow(
 				Ok,
 			)?;
 
+	if let Some(materialized_path) = sub_iso_file_path.materialized_path_for_children() {
+		rules::skip_matching(
+			&IdentifierRules::from_location(&location),
+			location.id,
+			RulesScope::Children(&materialized_path),
+			db,
+			ctx.sync(),
+		)
+		.await
+		.map_err(file_identifier::Error::from)?;
+	}
+
+	let hard_links = HardLinks::default();
+
 	let mut orphans_count = 0;
 	let mut last_orphan_file_path_id = None;
 
@@ -71,6 +89,8 @@ pub async fn shallow(
 				location.id,
 				last_orphan_file_path_id,
 				&sub_iso_file_path,
+				false,
+				false,
 			))
 			.order_by(file_path::id::order(SortOrder::Asc))
 			.take(CHUNK_SIZE as i64)
@@ -94,6 +114,8 @@ pub async fn shallow(
 					Arc::clone(&location_path),
 					orphan_paths,
 					true,
+					options.clone(),
+					hard_links.clone(),
 				))
 				.await,
 		));
@@ -108,22 +130,72 @@ pub async fn shallow(
 		return Ok(vec![]);
 	}
 
-	let errors = process_tasks(pending_running_tasks, dispatcher, ctx).await?;
+	let Identified { errors, .. } =
+		process_tasks(location.id, pending_running_tasks, dispatcher, ctx).await?;
 
 	Ok(errors)
 }
 
+/// What [`identify`] did with the file paths it got
+#[derive(Debug, Default)]
+pub struct Identified {
+	pub created_objects_count: u64,
+	pub linked_objects_count: u64,
+	pub errors: Vec<NonCriticalError>,
+}
+
+/// Identifies some orphan `file_paths` of a location right away, with the same tasks as
+/// [`shallow`], for callers that walk the orphans by themselves
+pub async fn identify(
+	location: location::Data,
+	file_paths: Vec<file_path_for_file_identifier::Data>,
+	mut options: FileMetadataOptions,
+	dispatcher: BaseTaskDispatcher<Error>,
+	ctx: impl OuterContext,
+) -> Result<Identified, Error> {
+	options.deep_hash_threshold = ctx.deep_hash_threshold().await;
+
+	if file_paths.is_empty() {
+		return Ok(Identified::default());
+	}
+
+	let location_path = maybe_missing(&location.path, "location.path")
+		.map(PathBuf::from)
+		.map(Arc::new)
+		.map_err(file_identifier::Error::from)?;
+
+	let location_id = location.id;
+
+	let mut pending_running_tasks = FutureGroup::new();
+
+	pending_running_tasks.insert(CancelTaskOnDrop(
+		dispatcher
+			.dispatch(ExtractFileMetadataTask::new(
+				Arc::new(location),
+				location_path,
+				file_paths,
+				true,
+				options,
+				HardLinks::default(),
+			))
+			.await,
+	));
+
+	process_tasks(location_id, pending_running_tasks, dispatcher, ctx).await
+}
+
 async fn process_tasks(
+	location_id: location::id::Type,
 	pending_running_tasks: FutureGroup<CancelTaskOnDrop<Error>>,
 	dispatcher: BaseTaskDispatcher<Error>,
 	ctx: impl OuterContext,
-) -> Result<Vec<NonCriticalError>, Error> {
+) -> Result<Identified, Error> {
 	let mut pending_running_tasks = pending_running_tasks.lend_mut();
 
 	let db = ctx.db();
 	let sync = ctx.sync();
 
-	let mut errors = vec![];
+	let mut identified = Identified::default();
 
 	while let Some((pending_running_tasks, task_result)) = pending_running_tasks.next().await {
 		match task_result {
@@ -135,10 +207,19 @@ async fn process_tasks(
 					let extract_file_metadata::Output {
 						identified_files,
 						errors: more_errors,
+						failed_file_paths,
 						..
 					} = *any_task_output.downcast().expect("just checked");
 
-					errors.extend(more_errors);
+					identified.errors.extend(more_errors);
+
+					update_persisted_failures(
+						db,
+						location_id,
+						&identified_files,
+						failed_file_paths,
+					)
+					.await;
 
 					if !identified_files.is_empty() {
 						pending_running_tasks.insert(CancelTaskOnDrop(
@@ -155,12 +236,24 @@ async fn process_tasks(
 				} else {
 					let object_processor::Output {
 						file_path_ids_with_new_object,
+						cross_location_links,
+						created_objects_count,
+						linked_objects_count,
 						..
 					} = *any_task_output.downcast().expect("just checked");
 
+					identified.created_objects_count += created_objects_count;
+					identified.linked_objects_count += linked_objects_count;
+
 					ctx.report_update(crate::UpdateEvent::NewIdentifiedObjects {
 						file_path_ids: file_path_ids_with_new_object,
 					});
+
+					if !cross_location_links.is_empty() {
+						ctx.report_update(crate::UpdateEvent::NewCrossLocationLinks {
+							links: cross_location_links,
+						});
+					}
 				}
 			}
 
@@ -172,7 +265,7 @@ async fn process_tasks(
 				debug!(
 					"Spacedrive is shutting down while a shallow file identifier was in progress"
 				);
-				return Ok(vec![]);
+				return Ok(Identified::default());
 			}
 
 			Ok(TaskStatus::Error(e)) => {
@@ -181,7 +274,7 @@ async fn process_tasks(
 
 			Ok(TaskStatus::Canceled | TaskStatus::ForcedAbortion) => {
 				warn!("Task was cancelled or aborted on shallow file identifier");
-				return Ok(vec![]);
+				return Ok(Identified::default());
 			}
 
 			Err(e) => {
@@ -190,5 +283,5 @@ async fn process_tasks(
 		}
 	}
 
-	Ok(errors)
+	Ok(identified)
 }
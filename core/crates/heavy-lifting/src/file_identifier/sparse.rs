@@ -0,0 +1,187 @@
+use std::{ops::Range, path::Path};
+
+use tokio::task::spawn_blocking;
+use tracing::trace;
+
+/// Regions of a sparse file actually holding data, in ascending order, the rest of the file being
+/// holes that read as zeroes (e.g. VM disk images or torrents still being downloaded).
+///
+/// Returns `None` for files without holes, or when the file system can't tell.
+pub async fn allocated_ranges(path: impl AsRef<Path> + Send, size: u64) -> Option<Vec<Range<u64>>> {
+	let path = path.as_ref().to_path_buf();
+
+	spawn_blocking(move || {
+		// Asking for the allocated ranges of every file would be wasteful, so we only do it for
+		// files that look sparse from their metadata
+		if !platform::may_be_sparse(&path.metadata().ok()?) {
+			return None;
+		}
+
+		platform::allocated_ranges(&path, size)
+			.map_err(|e| {
+				trace!(
+					"Unable to detect sparse regions <path='{}'>: {e:#?}",
+					path.display()
+				);
+			})
+			.ok()
+			.filter(|ranges| ranges.as_slice() != [0..size])
+	})
+	.await
+	.ok()
+	.flatten()
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod platform {
+	use std::{
+		fs::{File, Metadata},
+		io,
+		ops::Range,
+		os::{fd::AsRawFd, unix::fs::MetadataExt},
+		path::Path,
+	};
+
+	/// Files with less blocks allocated than their size must have holes, or be compressed
+	pub fn may_be_sparse(metadata: &Metadata) -> bool {
+		metadata.blocks() * 512 < metadata.len()
+	}
+
+	pub fn allocated_ranges(path: &Path, size: u64) -> Result<Vec<Range<u64>>, io::Error> {
+		let file = File::open(path)?;
+
+		let mut ranges = vec![];
+		let mut offset = 0;
+
+		while offset < size {
+			let start = match seek(&file, offset, libc::SEEK_DATA) {
+				Ok(start) => start,
+				// Only a hole from here to the end of the file
+				Err(e) if e.raw_os_error() == Some(libc::ENXIO) => break,
+				Err(e) => return Err(e),
+			};
+
+			let end = seek(&file, start, libc::SEEK_HOLE)?.min(size);
+
+			ranges.push(start..end);
+			offset = end;
+		}
+
+		Ok(ranges)
+	}
+
+	// SAFETY: Offsets are bounded by the file size, which fits in an `off_t`
+	#[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+	fn seek(file: &File, offset: u64, whence: libc::c_int) -> Result<u64, io::Error> {
+		// SAFETY: `file` keeps the descriptor open for the whole call
+		let new_offset = unsafe { libc::lseek(file.as_raw_fd(), offset as libc::off_t, whence) };
+
+		if new_offset < 0 {
+			Err(io::Error::last_os_error())
+		} else {
+			Ok(new_offset as u64)
+		}
+	}
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+	use std::{
+		fs::{File, Metadata},
+		io, mem,
+		ops::Range,
+		os::windows::{fs::MetadataExt, io::AsRawHandle},
+		path::Path,
+	};
+
+	use windows::Win32::{
+		Foundation::{ERROR_MORE_DATA, HANDLE},
+		Storage::FileSystem::FILE_ATTRIBUTE_SPARSE_FILE,
+		System::{
+			Ioctl::{FILE_ALLOCATED_RANGE_BUFFER, FSCTL_QUERY_ALLOCATED_RANGES},
+			IO::DeviceIoControl,
+		},
+	};
+
+	const RANGES_PER_CALL: usize = 64;
+
+	pub fn may_be_sparse(metadata: &Metadata) -> bool {
+		metadata.file_attributes() & FILE_ATTRIBUTE_SPARSE_FILE.0 != 0
+	}
+
+	// SAFETY: Offsets are bounded by the file size and buffer sizes are small, the casts can't overflow
+	#[allow(
+		clippy::cast_possible_truncation,
+		clippy::cast_possible_wrap,
+		clippy::cast_sign_loss
+	)]
+	pub fn allocated_ranges(path: &Path, size: u64) -> Result<Vec<Range<u64>>, io::Error> {
+		let file = File::open(path)?;
+
+		let mut ranges = vec![];
+		let mut query = FILE_ALLOCATED_RANGE_BUFFER {
+			FileOffset: 0,
+			Length: size as i64,
+		};
+
+		loop {
+			let mut buf = [FILE_ALLOCATED_RANGE_BUFFER::default(); RANGES_PER_CALL];
+			let mut returned_bytes = 0;
+
+			// SAFETY: `query` and `buf` outlive the call and their sizes are the ones we pass,
+			// `file` keeps the handle open for the whole call
+			let res = unsafe {
+				DeviceIoControl(
+					HANDLE(file.as_raw_handle() as isize),
+					FSCTL_QUERY_ALLOCATED_RANGES,
+					Some((&query as *const FILE_ALLOCATED_RANGE_BUFFER).cast()),
+					mem::size_of::<FILE_ALLOCATED_RANGE_BUFFER>() as u32,
+					Some(buf.as_mut_ptr().cast()),
+					mem::size_of_val(&buf) as u32,
+					Some(&mut returned_bytes),
+					None,
+				)
+			};
+
+			let returned_ranges =
+				returned_bytes as usize / mem::size_of::<FILE_ALLOCATED_RANGE_BUFFER>();
+
+			ranges.extend(
+				buf[..returned_ranges]
+					.iter()
+					.map(|range| range.FileOffset as u64..(range.FileOffset + range.Length) as u64),
+			);
+
+			match res {
+				Ok(()) => break,
+
+				Err(e) if e.code() == ERROR_MORE_DATA.to_hresult() => {
+					let Some(last) = ranges.last() else {
+						break;
+					};
+
+					query.FileOffset = last.end as i64;
+					query.Length = size as i64 - query.FileOffset;
+				}
+
+				Err(e) => return Err(io::Error::other(e)),
+			}
+		}
+
+		Ok(ranges)
+	}
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+mod platform {
+	use std::{fs::Metadata, io, ops::Range, path::Path};
+
+	pub const fn may_be_sparse(_: &Metadata) -> bool {
+		false
+	}
+
+	#[allow(clippy::unnecessary_wraps)]
+	pub fn allocated_ranges(_: &Path, _: u64) -> Result<Vec<Range<u64>>, io::Error> {
+		Ok(vec![])
+	}
+}
@@ -0,0 +1,355 @@
+use crate::disk_usage::IdentifiedDelta;
+
+use sd_core_prisma_helpers::file_path_for_statistics;
+
+use sd_file_ext::kind::ObjectKind;
+use sd_prisma::prisma::{file_path, identification_statistics, location, PrismaClient, SortOrder};
+use sd_utils::db::size_in_bytes_from_db;
+
+use std::collections::BTreeMap;
+
+use prisma_client_rust::QueryError;
+use serde::Serialize;
+use specta::Type;
+
+use super::{not_skipped, Error};
+
+/// How much of a location the file identifier has gone through
+#[derive(Debug, Clone, Serialize, Type)]
+pub struct IdentificationStatistics {
+	pub identified_count: u32,
+	pub orphan_count: u32,
+	pub empty_file_count: u32,
+	/// As a string as it may not fit in a JS number
+	pub total_bytes: String,
+	pub by_kind: Vec<KindIdentificationStatistics>,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+pub struct KindIdentificationStatistics {
+	/// Enum: `sd_file_ext::kind::ObjectKind`
+	pub kind: i32,
+	pub identified_count: u32,
+	pub empty_file_count: u32,
+	pub total_bytes: String,
+}
+
+/// Counters to be added to the statistics of each location and kind, and to the usage of the
+/// directories holding the files.
+///
+/// Taken as a [`snapshot`] of the file paths about to change and another one once they changed, in
+/// the same transaction as the change, so file paths identified again or removed are accounted for
+/// exactly once.
+#[derive(Debug, Default)]
+pub struct StatisticsDelta {
+	by_kind: BTreeMap<(location::id::Type, i32), KindDelta>,
+	by_directory: IdentifiedDelta,
+}
+
+#[derive(Debug, Default)]
+struct KindDelta {
+	identified_count: i32,
+	empty_file_count: i32,
+	total_bytes: i64,
+}
+
+impl StatisticsDelta {
+	#[allow(clippy::cast_possible_wrap)] // SAFETY: no file is bigger than 8 EiB
+	fn record(
+		&mut self,
+		file_path_for_statistics::Data {
+			location_id,
+			materialized_path,
+			size_in_bytes_bytes,
+			object,
+		}: &file_path_for_statistics::Data,
+	) {
+		let Some(location_id) = location_id else {
+			return;
+		};
+
+		let kind = object
+			.as_ref()
+			.and_then(|object| object.kind)
+			.unwrap_or(ObjectKind::Unknown as i32);
+
+		let size = size_in_bytes_bytes
+			.as_deref()
+			.map_or(0, size_in_bytes_from_db);
+
+		let delta = self.by_kind.entry((*location_id, kind)).or_default();
+
+		delta.identified_count += 1;
+		delta.empty_file_count += i32::from(size == 0);
+		delta.total_bytes += size as i64;
+
+		if let Some(materialized_path) = materialized_path {
+			self.by_directory
+				.record(*location_id, materialized_path, size);
+		}
+	}
+
+	/// How much the counters went from `before` to `self`, both being snapshots of the same file
+	/// paths
+	#[must_use]
+	pub fn since(mut self, before: Self) -> Self {
+		for (key, before) in before.by_kind {
+			let delta = self.by_kind.entry(key).or_default();
+
+			delta.identified_count -= before.identified_count;
+			delta.empty_file_count -= before.empty_file_count;
+			delta.total_bytes -= before.total_bytes;
+		}
+
+		self.by_directory.subtract(before.by_directory);
+
+		self
+	}
+
+	/// Adds the accumulated counters to the stored statistics and directory usage
+	pub async fn commit(self, db: &PrismaClient) -> Result<(), QueryError> {
+		use identification_statistics::{
+			create_unchecked, empty_file_count, identified_count, location_id_kind, total_bytes,
+		};
+
+		let by_kind = self
+			.by_kind
+			.into_iter()
+			.filter(|(_, delta)| {
+				delta.identified_count != 0 || delta.empty_file_count != 0 || delta.total_bytes != 0
+			})
+			.collect::<Vec<_>>();
+
+		if !by_kind.is_empty() {
+			db._batch(
+				by_kind
+					.into_iter()
+					.map(|((location_id, kind), delta)| {
+						db.identification_statistics().upsert(
+							location_id_kind(location_id, kind),
+							create_unchecked(
+								location_id,
+								kind,
+								vec![
+									identified_count::set(delta.identified_count.max(0)),
+									empty_file_count::set(delta.empty_file_count.max(0)),
+									total_bytes::set(delta.total_bytes.max(0)),
+								],
+							),
+							vec![
+								identified_count::increment(delta.identified_count),
+								empty_file_count::increment(delta.empty_file_count),
+								total_bytes::increment(delta.total_bytes),
+							],
+						)
+					})
+					.collect::<Vec<_>>(),
+			)
+			.await?;
+		}
+
+		self.by_directory.commit(db).await
+	}
+}
+
+/// What the identified file paths matching `where_params` count for in the statistics right now.
+///
+/// Taken before and after a change, in the same transaction as the change, to commit the
+/// difference with [`StatisticsDelta::since`].
+pub async fn snapshot(
+	mut where_params: Vec<file_path::WhereParam>,
+	db: &PrismaClient,
+) -> Result<StatisticsDelta, QueryError> {
+	where_params.extend([
+		file_path::is_dir::equals(Some(false)),
+		file_path::object_id::not(None),
+	]);
+
+	let mut delta = StatisticsDelta::default();
+
+	for file_path in db
+		.file_path()
+		.find_many(where_params)
+		.select(file_path_for_statistics::select())
+		.exec()
+		.await?
+	{
+		delta.record(&file_path);
+	}
+
+	Ok(delta)
+}
+
+/// Takes the identified file paths matching `where_params` out of the statistics, in the same
+/// transaction that removes them or detaches them from their location
+pub async fn forget(
+	where_params: Vec<file_path::WhereParam>,
+	db: &PrismaClient,
+) -> Result<(), QueryError> {
+	StatisticsDelta::default()
+		.since(snapshot(where_params, db).await?)
+		.commit(db)
+		.await
+}
+
+/// Statistics of a location, from the counters kept up to date as file paths are identified and
+/// removed.
+///
+/// Only orphans and identified file paths are counted on each call, everything else is read from the stored counters.
+/// Those are computed again with [`recompute`] when they don't add up to the identified file paths,
+/// for locations identified before they existed or changed by other devices through sync.
+pub async fn fetch(
+	location_id: location::id::Type,
+	db: &PrismaClient,
+) -> Result<IdentificationStatistics, Error> {
+	let (rows, orphan_count, identified_count) = db
+		._batch((
+			db.identification_statistics()
+				.find_many(vec![identification_statistics::location_id::equals(
+					location_id,
+				)])
+				.order_by(identification_statistics::kind::order(SortOrder::Asc)),
+			db.file_path().count(orphans(location_id)),
+			db.file_path().count(identified(location_id)),
+		))
+		.await?;
+
+	let rows = if rows
+		.iter()
+		.map(|row| i64::from(row.identified_count))
+		.sum::<i64>()
+		== identified_count
+	{
+		rows
+	} else {
+		recompute(location_id, db).await?
+	};
+
+	Ok(from_rows(rows, orphan_count))
+}
+
+/// Rebuilds the statistics of a location from its file paths, for locations identified before the
+/// counters existed or whose counters drifted away, e.g. from changes synced by other devices.
+#[allow(clippy::cast_possible_wrap)] // SAFETY: no file is bigger than 8 EiB
+pub async fn recompute(
+	location_id: location::id::Type,
+	db: &PrismaClient,
+) -> Result<Vec<identification_statistics::Data>, Error> {
+	let mut by_kind = BTreeMap::<i32, KindDelta>::new();
+
+	for file_path in db
+		.file_path()
+		.find_many(identified(location_id))
+		.select(file_path::select!({ size_in_bytes_bytes object: select { kind } }))
+		.exec()
+		.await?
+	{
+		let kind = file_path
+			.object
+			.and_then(|object| object.kind)
+			.unwrap_or(ObjectKind::Unknown as i32);
+
+		let delta = by_kind.entry(kind).or_default();
+
+		let size = file_path
+			.size_in_bytes_bytes
+			.as_deref()
+			.map_or(0, size_in_bytes_from_db);
+
+		delta.identified_count += 1;
+		delta.empty_file_count += i32::from(size == 0);
+		delta.total_bytes += size as i64;
+	}
+
+	let (_, rows) = db
+		._batch((
+			db.identification_statistics().delete_many(vec![
+				identification_statistics::location_id::equals(location_id),
+			]),
+			by_kind
+				.into_iter()
+				.map(|(kind, delta)| {
+					db.identification_statistics().create_unchecked(
+						location_id,
+						kind,
+						vec![
+							identification_statistics::identified_count::set(
+								delta.identified_count,
+							),
+							identification_statistics::empty_file_count::set(
+								delta.empty_file_count,
+							),
+							identification_statistics::total_bytes::set(delta.total_bytes),
+						],
+					)
+				})
+				.collect::<Vec<_>>(),
+		))
+		.await?;
+
+	Ok(rows)
+}
+
+fn orphans(location_id: location::id::Type) -> Vec<file_path::WhereParam> {
+	vec![
+		file_path::location_id::equals(Some(location_id)),
+		file_path::is_dir::equals(Some(false)),
+		file_path::object_id::equals(None),
+		not_skipped(),
+	]
+}
+
+fn identified(location_id: location::id::Type) -> Vec<file_path::WhereParam> {
+	vec![
+		file_path::location_id::equals(Some(location_id)),
+		file_path::is_dir::equals(Some(false)),
+		file_path::object_id::not(None),
+	]
+}
+
+// SAFETY: counters are clamped to 0, and a location can't have 4 billion files
+#[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+fn from_rows(
+	rows: Vec<identification_statistics::Data>,
+	orphan_count: i64,
+) -> IdentificationStatistics {
+	let mut statistics = IdentificationStatistics {
+		identified_count: 0,
+		orphan_count: orphan_count as u32,
+		empty_file_count: 0,
+		total_bytes: String::new(),
+		by_kind: Vec::with_capacity(rows.len()),
+	};
+	let mut total_bytes = 0;
+
+	for identification_statistics::Data {
+		kind,
+		identified_count,
+		empty_file_count,
+		total_bytes: kind_total_bytes,
+		..
+	} in rows
+	{
+		// Removals racing with a recompute can take a counter below 0 until the next one
+		let (identified_count, empty_file_count, kind_total_bytes) = (
+			identified_count.max(0),
+			empty_file_count.max(0),
+			kind_total_bytes.max(0),
+		);
+
+		statistics.identified_count += identified_count as u32;
+		statistics.empty_file_count += empty_file_count as u32;
+		total_bytes += kind_total_bytes as u64;
+
+		statistics.by_kind.push(KindIdentificationStatistics {
+			kind,
+			identified_count: identified_count as u32,
+			empty_file_count: empty_file_count as u32,
+			total_bytes: (kind_total_bytes as u64).to_string(),
+		});
+	}
+
+	statistics.total_bytes = total_bytes.to_string();
+
+	statistics
+}
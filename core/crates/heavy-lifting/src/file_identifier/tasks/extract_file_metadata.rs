@@ -1,12 +1,16 @@
 use crate::{
-	file_identifier::{self, FileMetadata},
+	file_identifier::{
+		self, ExtendedAttributes, FileAnalysis, FileMetadata, FileMetadataOptions, HardLinks,
+		PartialCasId,
+	},
+	utils::{io_throttle::IoThrottle, network_share},
 	Error, NonCriticalError,
 };
 
 use sd_core_file_path_helper::IsolatedFilePathData;
 use sd_core_prisma_helpers::file_path_for_file_identifier;
 
-use sd_prisma::prisma::location;
+use sd_prisma::prisma::{file_path, location};
 use sd_task_system::{
 	ExecStatus, Interrupter, InterruptionKind, IntoAnyTaskOutput, SerializableTask, Task, TaskId,
 };
@@ -21,7 +25,7 @@ use futures::stream::{self, FuturesUnordered, StreamExt};
 use futures_concurrency::stream::Merge;
 use serde::{Deserialize, Serialize};
 use tokio::time::Instant;
-use tracing::error;
+use tracing::{debug_span, error, Instrument};
 use uuid::Uuid;
 
 use super::IdentifiedFile;
@@ -34,15 +38,35 @@ pub struct ExtractFileMetadataTask {
 	file_paths_by_id: HashMap<Uuid, file_path_for_file_identifier::Data>,
 	identified_files: HashMap<Uuid, IdentifiedFile>,
 	extract_metadata_time: Duration,
+	#[serde(default)]
+	hashed_bytes: u64,
 	errors: Vec<NonCriticalError>,
+	#[serde(default)]
+	failed_file_paths: Vec<(file_path::id::Type, file_identifier::NonCriticalError)>,
+	#[serde(default)]
+	disconnected_file_paths: Vec<file_path_for_file_identifier::Data>,
 	with_priority: bool,
+	#[serde(default)]
+	options: FileMetadataOptions,
+	// Not worth persisting, a resumed task just won't share hashes with its siblings
+	#[serde(skip)]
+	hard_links: HardLinks,
+	#[serde(default)]
+	partial_cas_ids: HashMap<Uuid, PartialCasId>,
 }
 
 #[derive(Debug)]
 pub struct Output {
 	pub identified_files: HashMap<Uuid, IdentifiedFile>,
 	pub extract_metadata_time: Duration,
+	/// Content size of the files we generated a `cas_id` for
+	pub hashed_bytes: u64,
 	pub errors: Vec<NonCriticalError>,
+	/// Same errors as in `errors` that are tied to a file path, to be persisted for retries
+	pub failed_file_paths: Vec<(file_path::id::Type, file_identifier::NonCriticalError)>,
+	/// Files we couldn't reach as the location's network share got disconnected, they aren't
+	/// failures and must be identified again when the share comes back
+	pub disconnected_file_paths: Vec<file_path_for_file_identifier::Data>,
 }
 
 impl ExtractFileMetadataTask {
@@ -52,6 +76,8 @@ impl ExtractFileMetadataTask {
 		location_path: Arc<PathBuf>,
 		file_paths: Vec<file_path_for_file_identifier::Data>,
 		with_priority: bool,
+		options: FileMetadataOptions,
+		hard_links: HardLinks,
 	) -> Self {
 		Self {
 			id: TaskId::new_v4(),
@@ -69,10 +95,23 @@ impl ExtractFileMetadataTask {
 				})
 				.collect(),
 			extract_metadata_time: Duration::ZERO,
+			hashed_bytes: 0,
 			errors: Vec::new(),
+			failed_file_paths: Vec::new(),
+			disconnected_file_paths: Vec::new(),
 			with_priority,
+			options,
+			hard_links,
+			partial_cas_ids: HashMap::new(),
 		}
 	}
+
+	/// Replaces the throttle this task was serialized with, so resumed tasks share their job's
+	#[must_use]
+	pub fn with_io_throttle(mut self, io_throttle: IoThrottle) -> Self {
+		self.options.io_throttle = io_throttle;
+		self
+	}
 }
 
 #[async_trait::async_trait]
@@ -90,7 +129,7 @@ impl Task<Error> for ExtractFileMetadataTask {
 		// so we ignore the size difference to optimize for usage
 		#[allow(clippy::large_enum_variant)]
 		enum StreamMessage {
-			Processed(Uuid, Result<FileMetadata, FileIOError>),
+			Processed(Uuid, Result<FileAnalysis, FileIOError>),
 			Interrupt(InterruptionKind),
 		}
 
@@ -100,10 +139,18 @@ impl Task<Error> for ExtractFileMetadataTask {
 			file_paths_by_id,
 			identified_files,
 			extract_metadata_time,
+			hashed_bytes,
 			errors,
+			failed_file_paths,
+			disconnected_file_paths,
+			options,
+			hard_links,
+			partial_cas_ids,
 			..
 		} = self;
 
+		let options = &*options;
+
 		let start_time = Instant::now();
 
 		if !file_paths_by_id.is_empty() {
@@ -116,73 +163,155 @@ impl Task<Error> for ExtractFileMetadataTask {
 						file_path,
 						Arc::clone(location_path),
 						errors,
+						failed_file_paths,
 					)
 				})
-				.map(|(file_path_id, iso_file_path, location_path)| async move {
-					StreamMessage::Processed(
-						file_path_id,
-						FileMetadata::new(&*location_path, &iso_file_path).await,
-					)
+				.map(|(file_path_id, iso_file_path, location_path)| {
+					let hard_links = hard_links.clone();
+					let partial_cas_id = partial_cas_ids.remove(&file_path_id);
+					async move {
+						StreamMessage::Processed(
+							file_path_id,
+							FileMetadata::new(
+								&*location_path,
+								&iso_file_path,
+								options,
+								&hard_links,
+								partial_cas_id,
+								interrupter,
+							)
+							.instrument(debug_span!("file_metadata", %file_path_id))
+							.await,
+						)
+					}
 				})
 				.collect::<FuturesUnordered<_>>();
 
+			// Once all files have been processed we end this merged stream and don't keep waiting an
+			// interrupt signal, which files interrupted while hashing may have consumed already
+			let mut pending_count = extraction_futures.len();
+
 			let mut msg_stream = pin!((
 				extraction_futures,
 				stream::once(interrupter.into_future()).map(StreamMessage::Interrupt)
 			)
 				.merge());
 
-			while let Some(msg) = msg_stream.next().await {
+			let mut interruption = None;
+
+			while pending_count > 0 {
+				let Some(msg) = msg_stream.next().await else {
+					break;
+				};
+
 				match msg {
 					StreamMessage::Processed(file_path_pub_id, res) => {
+						pending_count -= 1;
+
+						if let Ok(FileAnalysis::Interrupted(partial_cas_id, kind)) = res {
+							// The file stays with the ones to be analyzed, continuing from here on resume
+							partial_cas_ids.insert(file_path_pub_id, partial_cas_id);
+							interruption = Some(kind);
+							continue;
+						}
+
 						let file_path = file_paths_by_id
 							.remove(&file_path_pub_id)
 							.expect("file_path must be here");
 
 						match res {
-							Ok(FileMetadata { cas_id, kind, .. }) => {
+							Ok(FileAnalysis::Analyzed(FileMetadata {
+								cas_id,
+								kind,
+								custom_kind,
+								fs_metadata,
+								xattrs,
+								link_target,
+								file_id,
+								remote_only,
+								physical_size,
+								allocated_size,
+								archive,
+								quick_metadata,
+							})) => {
+								if cas_id.is_some() && link_target.is_none() {
+									*hashed_bytes += fs_metadata.len();
+								}
+
+								let ExtendedAttributes {
+									tags: xattr_tags,
+									raw: raw_xattrs,
+								} = xattrs.unwrap_or_default();
+
 								identified_files.insert(
 									file_path_pub_id,
 									IdentifiedFile {
 										file_path,
 										cas_id,
 										kind,
+										custom_kind,
+										xattr_tags,
+										raw_xattrs,
+										link_target: link_target.map(|link_target| {
+											link_target.to_string_lossy().into_owned()
+										}),
+										file_id,
+										remote_only,
+										physical_size,
+										allocated_size,
+										archive,
+										quick_metadata,
 									},
 								);
 							}
+							// Skipped symbolic link, it stays an orphan
+							Ok(FileAnalysis::Skipped) => {}
+							Ok(FileAnalysis::Interrupted(..)) => unreachable!("handled above"),
+							Err(e)
+								if location.network_share.is_some()
+									&& network_share::is_disconnection(&e.source) =>
+							{
+								disconnected_file_paths.push(file_path);
+							}
 							Err(e) => {
 								handle_non_critical_errors(
 									location.id,
 									file_path_pub_id,
+									file_path.id,
 									&e,
 									errors,
+									failed_file_paths,
 								);
 							}
 						}
-
-						if file_paths_by_id.is_empty() {
-							// All files have been processed so we can end this merged stream and don't keep waiting an
-							// interrupt signal
-							break;
-						}
 					}
 
 					StreamMessage::Interrupt(kind) => {
-						*extract_metadata_time += start_time.elapsed();
-						return Ok(match kind {
-							InterruptionKind::Pause => ExecStatus::Paused,
-							InterruptionKind::Cancel => ExecStatus::Canceled,
-						});
+						// Files being hashed stop at their next chunk, so we wait for them to
+						// keep their partial hashes, and for the others to keep their metadata
+						interruption = Some(kind);
 					}
 				}
 			}
+
+			// If every file got analyzed anyway, there is nothing left to pause
+			if let (Some(kind), false) = (interruption, partial_cas_ids.is_empty()) {
+				*extract_metadata_time += start_time.elapsed();
+				return Ok(match kind {
+					InterruptionKind::Pause => ExecStatus::Paused,
+					InterruptionKind::Cancel => ExecStatus::Canceled,
+				});
+			}
 		}
 
 		Ok(ExecStatus::Done(
 			Output {
 				identified_files: mem::take(identified_files),
 				extract_metadata_time: *extract_metadata_time + start_time.elapsed(),
+				hashed_bytes: *hashed_bytes,
 				errors: mem::take(errors),
+				failed_file_paths: mem::take(failed_file_paths),
+				disconnected_file_paths: mem::take(disconnected_file_paths),
 			}
 			.into_output(),
 		))
@@ -192,37 +321,28 @@ impl Task<Error> for ExtractFileMetadataTask {
 fn handle_non_critical_errors(
 	location_id: location::id::Type,
 	file_path_pub_id: Uuid,
+	file_path_id: file_path::id::Type,
 	e: &FileIOError,
 	errors: &mut Vec<NonCriticalError>,
+	failed_file_paths: &mut Vec<(file_path::id::Type, file_identifier::NonCriticalError)>,
 ) {
 	error!("Failed to extract file metadata <location_id={location_id}, file_path_pub_id='{file_path_pub_id}'>: {e:#?}");
 
 	let formatted_error = format!("<file_path_pub_id='{file_path_pub_id}', error={e}>");
 
+	// Handle case where file is on-demand (NTFS only)
 	#[cfg(target_os = "windows")]
-	{
-		// Handle case where file is on-demand (NTFS only)
-		if e.source.raw_os_error().map_or(false, |code| code == 362) {
-			errors.push(
-				file_identifier::NonCriticalError::FailedToExtractMetadataFromOnDemandFile(
-					formatted_error,
-				)
-				.into(),
-			);
-		} else {
-			errors.push(
-				file_identifier::NonCriticalError::FailedToExtractFileMetadata(formatted_error)
-					.into(),
-			);
-		}
-	}
+	let error = if e.source.raw_os_error().map_or(false, |code| code == 362) {
+		file_identifier::NonCriticalError::FailedToExtractMetadataFromOnDemandFile(formatted_error)
+	} else {
+		file_identifier::NonCriticalError::FailedToExtractFileMetadata(formatted_error)
+	};
 
 	#[cfg(not(target_os = "windows"))]
-	{
-		errors.push(
-			file_identifier::NonCriticalError::FailedToExtractFileMetadata(formatted_error).into(),
-		);
-	}
+	let error = file_identifier::NonCriticalError::FailedToExtractFileMetadata(formatted_error);
+
+	failed_file_paths.push((file_path_id, error.clone()));
+	errors.push(error.into());
 }
 
 fn try_iso_file_path_extraction(
@@ -231,18 +351,18 @@ fn try_iso_file_path_extraction(
 	file_path: &file_path_for_file_identifier::Data,
 	location_path: Arc<PathBuf>,
 	errors: &mut Vec<NonCriticalError>,
+	failed_file_paths: &mut Vec<(file_path::id::Type, file_identifier::NonCriticalError)>,
 ) -> Option<(Uuid, IsolatedFilePathData<'static>, Arc<PathBuf>)> {
 	IsolatedFilePathData::try_from((location_id, file_path))
 		.map(IsolatedFilePathData::to_owned)
 		.map(|iso_file_path| (file_path_pub_id, iso_file_path, location_path))
 		.map_err(|e| {
 			error!("Failed to extract isolated file path data: {e:#?}");
-			errors.push(
-				file_identifier::NonCriticalError::FailedToExtractIsolatedFilePathData(format!(
-					"<file_path_pub_id='{file_path_pub_id}', error={e}>"
-				))
-				.into(),
+			let error = file_identifier::NonCriticalError::FailedToExtractIsolatedFilePathData(
+				format!("<file_path_pub_id='{file_path_pub_id}', error={e}>"),
 			);
+			failed_file_paths.push((file_path.id, error.clone()));
+			errors.push(error.into());
 		})
 		.ok()
 }
@@ -1,7 +1,11 @@
+use crate::file_identifier::{FileId, QuickMetadata, WalkedArchive};
+
 use sd_core_prisma_helpers::file_path_for_file_identifier;
 
 use sd_file_ext::kind::ObjectKind;
 
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
 pub mod extract_file_metadata;
@@ -15,4 +19,34 @@ pub(super) struct IdentifiedFile {
 	pub(super) file_path: file_path_for_file_identifier::Data,
 	pub(super) cas_id: Option<String>,
 	pub(super) kind: ObjectKind,
+	/// Name of the library defined kind, see [`KindRegistry`](sd_file_ext::custom_kind::KindRegistry)
+	#[serde(default)]
+	pub(super) custom_kind: Option<String>,
+	/// Tags read from the file's extended attributes, to be assigned to its object
+	#[serde(default)]
+	pub(super) xattr_tags: Vec<String>,
+	/// Remaining extended attributes of the file, saved on its file path
+	#[serde(default)]
+	pub(super) raw_xattrs: BTreeMap<String, Vec<u8>>,
+	/// Where the file path points to, for symbolic links identified as [`ObjectKind::Link`]
+	#[serde(default)]
+	pub(super) link_target: Option<String>,
+	/// Hard links to the same file share this id, so they get a single object
+	#[serde(default)]
+	pub(super) file_id: Option<FileId>,
+	/// Cloud placeholder identified without reading its content, so without a `cas_id`
+	#[serde(default)]
+	pub(super) remote_only: bool,
+	/// Bytes not shared with copy-on-write clones of this file
+	#[serde(default)]
+	pub(super) physical_size: Option<u64>,
+	/// Bytes holding data in sparse files
+	#[serde(default)]
+	pub(super) allocated_size: Option<u64>,
+	/// Entries of the archive, to be saved as virtual file paths with their own objects
+	#[serde(default)]
+	pub(super) archive: Option<WalkedArchive>,
+	/// Media properties read from the file headers, stored on the object created for the file
+	#[serde(default)]
+	pub(super) quick_metadata: Option<QuickMetadata>,
 }
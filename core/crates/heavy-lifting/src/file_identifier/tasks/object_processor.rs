@@ -1,4 +1,7 @@
-use crate::{file_identifier, Error};
+use crate::{
+	file_identifier::{self, archive, statistics, ArchiveEntry, CrossLocationLink},
+	tag_rules, Error,
+};
 
 use sd_core_prisma_helpers::{
 	file_path_for_file_identifier, file_path_pub_id, object_for_file_identifier,
@@ -6,48 +9,73 @@ use sd_core_prisma_helpers::{
 use sd_core_sync::Manager as SyncManager;
 
 use sd_prisma::{
-	prisma::{file_path, object, PrismaClient},
+	prisma::{file_path, object, tag, tag_on_object, PrismaClient},
 	prisma_sync,
 };
 use sd_sync::{CRDTOperation, OperationFactory};
 use sd_task_system::{
 	check_interruption, ExecStatus, Interrupter, IntoAnyTaskOutput, SerializableTask, Task, TaskId,
 };
-use sd_utils::{msgpack, uuid_to_bytes};
+use sd_utils::{chain_optional_iter, msgpack, uuid_to_bytes};
 
 use std::{
-	collections::{HashMap, HashSet},
+	collections::{BTreeMap, HashMap, HashSet},
 	mem,
 	sync::Arc,
 	time::Duration,
 };
 
+use chrono::{DateTime, FixedOffset, Utc};
 use prisma_client_rust::Select;
 use serde::{Deserialize, Serialize};
 use tokio::time::Instant;
-use tracing::{debug, trace};
+use tracing::{debug, instrument, trace};
 use uuid::Uuid;
 
 use super::IdentifiedFile;
 
+// How many distinct cas_ids we commit to the database before saving a checkpoint, unless the job
+// sets its own batch size
+const DEFAULT_CHECKPOINT_CHUNK_SIZE: usize = 25;
+
+const fn default_checkpoint_chunk_size() -> usize {
+	DEFAULT_CHECKPOINT_CHUNK_SIZE
+}
+
 #[derive(Debug)]
 pub struct ObjectProcessorTask {
 	id: TaskId,
 	db: Arc<PrismaClient>,
 	sync: Arc<SyncManager>,
 	identified_files: HashMap<Uuid, IdentifiedFile>,
+	tags_to_import: HashMap<Uuid, Vec<String>>,
+	// Identified files are removed as they're processed, tag rules are applied to all at the end
+	file_path_ids: Vec<file_path::id::Type>,
 	output: Output,
 	stage: Stage,
+	checkpoint: Option<String>,
+	checkpoint_chunk_size: usize,
 	with_priority: bool,
+	dry_run: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SaveState {
 	id: TaskId,
 	identified_files: HashMap<Uuid, IdentifiedFile>,
+	#[serde(default)]
+	tags_to_import: HashMap<Uuid, Vec<String>>,
+	#[serde(default)]
+	file_path_ids: Vec<file_path::id::Type>,
 	output: Output,
 	stage: Stage,
+	#[serde(default)]
+	checkpoint: Option<String>,
+	#[serde(default = "default_checkpoint_chunk_size")]
+	checkpoint_chunk_size: usize,
 	with_priority: bool,
+	#[serde(default)]
+	dry_run: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, Default)]
@@ -59,6 +87,22 @@ pub struct Output {
 	pub create_object_time: Duration,
 	pub created_objects_count: u64,
 	pub linked_objects_count: u64,
+	#[serde(default)]
+	pub import_tags_time: Duration,
+	#[serde(default)]
+	pub assigned_tags_count: u64,
+	#[serde(default)]
+	pub apply_tag_rules_time: Duration,
+	/// Tags assigned by the tag rules of the library
+	#[serde(default)]
+	pub rule_tags_count: u64,
+	/// Only filled on dry runs, the `cas_id` of each object that would be created, `None` for
+	/// empty files
+	#[serde(default)]
+	pub new_objects_cas_ids: Vec<Option<String>>,
+	/// File paths linked to existing objects that have file paths in other locations
+	#[serde(default)]
+	pub cross_location_links: Vec<CrossLocationLink>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -69,6 +113,8 @@ enum Stage {
 		existing_objects_by_cas_id: HashMap<String, object_for_file_identifier::Data>,
 	},
 	CreateObjects,
+	ImportTags,
+	ApplyTagRules,
 }
 
 impl ObjectProcessorTask {
@@ -79,16 +125,47 @@ impl ObjectProcessorTask {
 		sync: Arc<SyncManager>,
 		with_priority: bool,
 	) -> Self {
+		let tags_to_import = identified_files
+			.iter()
+			.filter(|(_, IdentifiedFile { xattr_tags, .. })| !xattr_tags.is_empty())
+			.map(|(pub_id, IdentifiedFile { xattr_tags, .. })| (*pub_id, xattr_tags.clone()))
+			.collect();
+
+		let file_path_ids = identified_files
+			.values()
+			.map(|IdentifiedFile { file_path, .. }| file_path.id)
+			.collect();
+
 		Self {
 			id: TaskId::new_v4(),
 			db,
 			sync,
 			identified_files,
+			tags_to_import,
+			file_path_ids,
 			stage: Stage::Starting,
 			output: Output::default(),
+			checkpoint: None,
+			checkpoint_chunk_size: DEFAULT_CHECKPOINT_CHUNK_SIZE,
 			with_priority,
+			dry_run: false,
 		}
 	}
+
+	/// How many distinct `cas_id`s are committed to the database at once, jobs adapting their
+	/// batch size to the commit latency set it to that batch size
+	#[must_use]
+	pub fn with_checkpoint_chunk_size(mut self, checkpoint_chunk_size: usize) -> Self {
+		self.checkpoint_chunk_size = checkpoint_chunk_size.max(1);
+		self
+	}
+
+	/// Only counts the objects that would be created or linked, without writing anything
+	#[must_use]
+	pub const fn with_dry_run(mut self, dry_run: bool) -> Self {
+		self.dry_run = dry_run;
+		self
+	}
 }
 
 #[async_trait::async_trait]
@@ -106,7 +183,12 @@ impl Task<Error> for ObjectProcessorTask {
 			db,
 			sync,
 			identified_files,
+			tags_to_import,
+			file_path_ids,
 			stage,
+			checkpoint,
+			checkpoint_chunk_size,
+			dry_run,
 			output:
 				Output {
 					file_path_ids_with_new_object,
@@ -116,16 +198,63 @@ impl Task<Error> for ObjectProcessorTask {
 					create_object_time,
 					created_objects_count,
 					linked_objects_count,
+					import_tags_time,
+					assigned_tags_count,
+					apply_tag_rules_time,
+					rule_tags_count,
+					new_objects_cas_ids,
+					cross_location_links,
 				},
 			..
 		} = self;
 
+		if *dry_run {
+			let start = Instant::now();
+			let existing_objects_by_cas_id =
+				fetch_existing_objects_by_cas_id(identified_files, db).await?;
+			*fetch_existing_objects_time = start.elapsed();
+
+			let (would_link_count, would_create_cas_ids) =
+				preview_objects(identified_files, &existing_objects_by_cas_id);
+
+			*linked_objects_count = would_link_count;
+			*created_objects_count = would_create_cas_ids.len() as u64;
+			*new_objects_cas_ids = would_create_cas_ids;
+			*assigned_tags_count = tags_to_import.values().map(|tags| tags.len() as u64).sum();
+
+			return Ok(ExecStatus::Done(mem::take(&mut self.output).into_output()));
+		}
+
 		loop {
 			match stage {
 				Stage::Starting => {
 					let start = Instant::now();
-					assign_cas_id_to_file_paths(identified_files, db, sync).await?;
-					*assign_cas_ids_time = start.elapsed();
+					if checkpoint.is_none() {
+						let archives_entries =
+							save_archives_entries(identified_files, db, sync).await?;
+						file_path_ids.extend(
+							archives_entries
+								.values()
+								.map(|IdentifiedFile { file_path, .. }| file_path.id),
+						);
+						identified_files.extend(archives_entries);
+					}
+
+					for (last_cas_id, chunk) in
+						pending_chunks(identified_files, checkpoint, *checkpoint_chunk_size)
+					{
+						assign_cas_id_to_file_paths(
+							&chunk_files(identified_files, &chunk),
+							db,
+							sync,
+						)
+						.await?;
+						*checkpoint = Some(last_cas_id);
+
+						check_interruption!(interrupter, start, assign_cas_ids_time);
+					}
+					*assign_cas_ids_time += start.elapsed();
+					*checkpoint = None;
 					*stage = Stage::FetchExistingObjects;
 				}
 
@@ -143,47 +272,131 @@ impl Task<Error> for ObjectProcessorTask {
 					existing_objects_by_cas_id,
 				} => {
 					let start = Instant::now();
-					let assigned_file_path_pub_ids = assign_existing_objects_to_file_paths(
-						identified_files,
-						existing_objects_by_cas_id,
-						db,
-						sync,
-					)
-					.await?;
-					*assign_to_existing_object_time = start.elapsed();
-					*linked_objects_count = assigned_file_path_pub_ids.len() as u64;
+					let existing_objects = &*existing_objects_by_cas_id;
+					for (last_cas_id, chunk) in
+						pending_chunks(identified_files, checkpoint, *checkpoint_chunk_size)
+					{
+						let files = &chunk_files(identified_files, &chunk);
+						let assigned_file_path_pub_ids = db
+							._transaction()
+							.with_timeout(30 * 1000)
+							.run(|tx| async move {
+								let before =
+									statistics::snapshot(statistics_params(files), &tx).await?;
+
+								let assigned = assign_existing_objects_to_file_paths(
+									files,
+									existing_objects,
+									&tx,
+									sync,
+								)
+								.await?;
+
+								statistics::snapshot(statistics_params(files), &tx)
+									.await?
+									.since(before)
+									.commit(&tx)
+									.await?;
+
+								Ok::<_, file_identifier::Error>(assigned)
+							})
+							.await?;
+						*linked_objects_count += assigned_file_path_pub_ids.len() as u64;
+
+						for file_path_pub_id::Data { pub_id } in assigned_file_path_pub_ids {
+							let pub_id = Uuid::from_slice(&pub_id).expect("uuid bytes are invalid");
+							trace!(
+								"Assigned file path <file_path_pub_id={pub_id}> to existing object"
+							);
+
+							let IdentifiedFile {
+								file_path, cas_id, ..
+							} = identified_files
+								.remove(&pub_id)
+								.expect("file_path must be here");
+
+							cross_location_links.extend(
+								cas_id
+									.and_then(|cas_id| existing_objects.get(&cas_id))
+									.and_then(|object| CrossLocationLink::find(&file_path, object)),
+							);
+						}
+
+						*checkpoint = Some(last_cas_id);
+
+						check_interruption!(interrupter, start, assign_to_existing_object_time);
+					}
+					*assign_to_existing_object_time += start.elapsed();
 
 					debug!(
 						"Found {} existing Objects, linked file paths to them",
 						existing_objects_by_cas_id.len()
 					);
 
-					for file_path_pub_id::Data { pub_id } in assigned_file_path_pub_ids {
-						let pub_id = Uuid::from_slice(&pub_id).expect("uuid bytes are invalid");
-						trace!("Assigned file path <file_path_pub_id={pub_id}> to existing object");
+					*checkpoint = None;
+					*stage = if identified_files.is_empty() {
+						// No objects to be created, we can skip straight to the tags
+						Stage::ImportTags
+					} else {
+						Stage::CreateObjects
+					};
+				}
 
-						identified_files
-							.remove(&pub_id)
-							.expect("file_path must be here");
-					}
+				Stage::CreateObjects => {
+					let start = Instant::now();
+					for (last_cas_id, chunk) in
+						pending_chunks(identified_files, checkpoint, *checkpoint_chunk_size)
+					{
+						let files = &chunk_files(identified_files, &chunk);
 
-					*stage = Stage::CreateObjects;
+						*created_objects_count += db
+							._transaction()
+							.with_timeout(30 * 1000)
+							.run(|tx| async move {
+								let before =
+									statistics::snapshot(statistics_params(files), &tx).await?;
 
-					if identified_files.is_empty() {
-						// No objects to be created, we're good to finish already
-						break;
+								let created = create_objects(files, &tx, sync).await?;
+
+								statistics::snapshot(statistics_params(files), &tx)
+									.await?
+									.since(before)
+									.commit(&tx)
+									.await?;
+
+								Ok::<_, file_identifier::Error>(created)
+							})
+							.await?;
+
+						file_path_ids_with_new_object.extend(
+							files
+								.iter()
+								.map(|(_, IdentifiedFile { file_path, .. })| file_path.id),
+						);
+
+						*checkpoint = Some(last_cas_id);
+
+						check_interruption!(interrupter, start, create_object_time);
 					}
+					*create_object_time += start.elapsed();
+					*checkpoint = None;
+					*stage = Stage::ImportTags;
 				}
 
-				Stage::CreateObjects => {
-					let start = Instant::now();
-					*created_objects_count = create_objects(identified_files, db, sync).await?;
-					*create_object_time = start.elapsed();
+				Stage::ImportTags => {
+					if !tags_to_import.is_empty() {
+						let start = Instant::now();
+						*assigned_tags_count = import_tags(tags_to_import, db, sync).await?;
+						*import_tags_time = start.elapsed();
+					}
+
+					*stage = Stage::ApplyTagRules;
+				}
 
-					*file_path_ids_with_new_object = identified_files
-						.values()
-						.map(|IdentifiedFile { file_path, .. }| file_path.id)
-						.collect();
+				Stage::ApplyTagRules => {
+					let start = Instant::now();
+					*rule_tags_count = tag_rules::apply(file_path_ids, db, sync).await?;
+					*apply_tag_rules_time = start.elapsed();
 
 					break;
 				}
@@ -196,58 +409,278 @@ impl Task<Error> for ObjectProcessorTask {
 	}
 }
 
-async fn assign_cas_id_to_file_paths(
+/// Groups the file paths still pending after `checkpoint` by `cas_id`, in ascending order, and
+/// splits them in chunks of `chunk_size` distinct `cas_id`s. As file paths with the same
+/// `cas_id` never end up in different chunks, the last `cas_id` of a committed chunk is a safe
+/// point to resume from.
+fn pending_chunks(
 	identified_files: &HashMap<Uuid, IdentifiedFile>,
+	checkpoint: &Option<String>,
+	chunk_size: usize,
+) -> Vec<(String, Vec<Uuid>)> {
+	let mut pub_ids_by_cas_id = BTreeMap::<&str, Vec<Uuid>>::new();
+
+	for (pub_id, IdentifiedFile { cas_id, .. }) in identified_files {
+		// Empty files don't have a cas_id, so they're sorted first
+		let cas_id = cas_id.as_deref().unwrap_or_default();
+		if checkpoint
+			.as_deref()
+			.map_or(true, |checkpoint| cas_id > checkpoint)
+		{
+			pub_ids_by_cas_id.entry(cas_id).or_default().push(*pub_id);
+		}
+	}
+
+	let mut chunks = Vec::with_capacity(pub_ids_by_cas_id.len() / chunk_size + 1);
+	let mut groups = pub_ids_by_cas_id.into_iter().peekable();
+
+	while groups.peek().is_some() {
+		let mut last_cas_id = "";
+		let pub_ids = groups
+			.by_ref()
+			.take(chunk_size)
+			.flat_map(|(cas_id, pub_ids)| {
+				last_cas_id = cas_id;
+				pub_ids
+			})
+			.collect();
+
+		chunks.push((last_cas_id.to_string(), pub_ids));
+	}
+
+	chunks
+}
+
+/// The file paths of `files`, for their statistics to be taken before and after they're linked
+fn statistics_params(files: &[(&Uuid, &IdentifiedFile)]) -> Vec<file_path::WhereParam> {
+	vec![file_path::id::in_vec(
+		files
+			.iter()
+			.map(|(_, IdentifiedFile { file_path, .. })| file_path.id)
+			.collect(),
+	)]
+}
+
+fn chunk_files<'files>(
+	identified_files: &'files HashMap<Uuid, IdentifiedFile>,
+	chunk: &[Uuid],
+) -> Vec<(&'files Uuid, &'files IdentifiedFile)> {
+	chunk
+		.iter()
+		.map(|pub_id| {
+			identified_files
+				.get_key_value(pub_id)
+				.expect("file_path must be here")
+		})
+		.collect()
+}
+
+#[instrument(level = "debug", skip_all, fields(files = files.len()))]
+async fn assign_cas_id_to_file_paths(
+	files: &[(&Uuid, &IdentifiedFile)],
 	db: &PrismaClient,
 	sync: &SyncManager,
 ) -> Result<(), file_identifier::Error> {
-	// Assign cas_id to each file path
-	sync.write_ops(
-		db,
-		identified_files
-			.iter()
-			.map(|(pub_id, IdentifiedFile { cas_id, .. })| {
+	// Assign cas_id to each file path, along with the link target for symbolic links, the
+	// remote only flag for cloud placeholders, the physical size of cloned files, the allocated
+	// size of sparse files and the extended attributes that weren't imported as tags
+	let (sync_stuff, paths_to_update) = files
+		.iter()
+		.map(
+			|&(
+				pub_id,
+				IdentifiedFile {
+					cas_id,
+					link_target,
+					remote_only,
+					physical_size,
+					allocated_size,
+					raw_xattrs,
+					..
+				},
+			)| {
+				let (sync_params, db_params) = chain_optional_iter(
+					[(
+						(file_path::cas_id::NAME, msgpack!(cas_id)),
+						file_path::cas_id::set(cas_id.clone()),
+					)],
+					[
+						link_target.as_ref().map(|link_target| {
+							(
+								(file_path::link_target::NAME, msgpack!(link_target)),
+								file_path::link_target::set(Some(link_target.clone())),
+							)
+						}),
+						remote_only.then(|| {
+							(
+								(file_path::remote_only::NAME, msgpack!(true)),
+								file_path::remote_only::set(Some(true)),
+							)
+						}),
+						physical_size.map(|physical_size| {
+							let physical_size_bytes = physical_size.to_be_bytes().to_vec();
+							(
+								(
+									file_path::physical_size_bytes::NAME,
+									msgpack!(physical_size_bytes.clone()),
+								),
+								file_path::physical_size_bytes::set(Some(physical_size_bytes)),
+							)
+						}),
+						allocated_size.map(|allocated_size| {
+							let allocated_size_bytes = allocated_size.to_be_bytes().to_vec();
+							(
+								(
+									file_path::allocated_size_bytes::NAME,
+									msgpack!(allocated_size_bytes.clone()),
+								),
+								file_path::allocated_size_bytes::set(Some(allocated_size_bytes)),
+							)
+						}),
+						(!raw_xattrs.is_empty()).then(|| {
+							let xattrs = rmp_serde::to_vec_named(raw_xattrs)
+								.expect("extended attributes are always serializable");
+							(
+								(file_path::xattrs::NAME, msgpack!(xattrs.clone())),
+								file_path::xattrs::set(Some(xattrs)),
+							)
+						}),
+					],
+				)
+				.into_iter()
+				.unzip::<_, _, Vec<_>, Vec<_>>();
+
 				(
-					sync.shared_update(
-						prisma_sync::file_path::SyncId {
-							pub_id: uuid_to_bytes(*pub_id),
-						},
-						file_path::cas_id::NAME,
-						msgpack!(cas_id),
-					),
+					sync_params
+						.into_iter()
+						.map(|(field, value)| {
+							sync.shared_update(
+								prisma_sync::file_path::SyncId {
+									pub_id: uuid_to_bytes(*pub_id),
+								},
+								field,
+								value,
+							)
+						})
+						.collect::<Vec<_>>(),
 					db.file_path()
-						.update(
-							file_path::pub_id::equals(uuid_to_bytes(*pub_id)),
-							vec![file_path::cas_id::set(cas_id.clone())],
-						)
+						.update(file_path::pub_id::equals(uuid_to_bytes(*pub_id)), db_params)
 						// We don't need any data here, just the id avoids receiving the entire object
 						// as we can't pass an empty select macro call
 						.select(file_path::select!({ id })),
 				)
-			})
-			.unzip::<_, _, _, Vec<_>>(),
+			},
+		)
+		.unzip::<_, _, Vec<_>, Vec<_>>();
+
+	sync.write_ops(
+		db,
+		(sync_stuff.into_iter().flatten().collect(), paths_to_update),
 	)
 	.await?;
 
 	Ok(())
 }
 
+/// Saves the entries of walked archives as virtual file paths, returning them as identified files so
+/// they get objects like any other file
+#[instrument(level = "debug", skip_all)]
+async fn save_archives_entries(
+	identified_files: &mut HashMap<Uuid, IdentifiedFile>,
+	db: &PrismaClient,
+	sync: &SyncManager,
+) -> Result<HashMap<Uuid, IdentifiedFile>, file_identifier::Error> {
+	let mut entries_by_pub_id = HashMap::new();
+
+	for IdentifiedFile {
+		file_path, archive, ..
+	} in identified_files.values_mut()
+	{
+		let Some(walked_archive) = archive.take() else {
+			continue;
+		};
+
+		let entries_pub_ids =
+			archive::save_entries(&walked_archive, file_path.date_created, db, sync).await?;
+
+		trace!(
+			"Saved {} archive entries <entries_root='{}'>",
+			entries_pub_ids.len(),
+			walked_archive.entries_root
+		);
+
+		entries_by_pub_id.extend(entries_pub_ids.into_iter().zip(walked_archive.entries));
+	}
+
+	if entries_by_pub_id.is_empty() {
+		return Ok(HashMap::new());
+	}
+
+	db.file_path()
+		.find_many(vec![file_path::pub_id::in_vec(
+			entries_by_pub_id
+				.keys()
+				.copied()
+				.map(uuid_to_bytes)
+				.collect(),
+		)])
+		.select(file_path_for_file_identifier::select())
+		.exec()
+		.await
+		.map_err(Into::into)
+		.map(|file_paths| {
+			file_paths
+				.into_iter()
+				.filter_map(|file_path| {
+					// SAFETY: This pub_id is generated by the uuid lib, but we have to store bytes in sqlite
+					let pub_id =
+						Uuid::from_slice(&file_path.pub_id).expect("uuid bytes are invalid");
+
+					let ArchiveEntry {
+						kind,
+						custom_kind,
+						cas_id,
+						..
+					} = entries_by_pub_id.remove(&pub_id)?;
+
+					Some((
+						pub_id,
+						IdentifiedFile {
+							file_path,
+							cas_id,
+							kind,
+							custom_kind,
+							xattr_tags: vec![],
+							raw_xattrs: BTreeMap::new(),
+							link_target: None,
+							file_id: None,
+							remote_only: false,
+							physical_size: None,
+							allocated_size: None,
+							archive: None,
+							quick_metadata: None,
+						},
+					))
+				})
+				.collect()
+		})
+}
+
+#[instrument(level = "debug", skip_all, fields(files = identified_files.len()))]
 async fn fetch_existing_objects_by_cas_id(
 	identified_files: &HashMap<Uuid, IdentifiedFile>,
 	db: &PrismaClient,
 ) -> Result<HashMap<String, object_for_file_identifier::Data>, file_identifier::Error> {
+	let cas_ids = identified_files
+		.values()
+		.filter_map(|IdentifiedFile { cas_id, .. }| cas_id.as_ref())
+		.cloned()
+		.collect::<HashSet<_>>();
+
 	// Retrieves objects that are already connected to file paths with the same id
 	db.object()
 		.find_many(vec![object::file_paths::some(vec![
-			file_path::cas_id::in_vec(
-				identified_files
-					.values()
-					.filter_map(|IdentifiedFile { cas_id, .. }| cas_id.as_ref())
-					.cloned()
-					.collect::<HashSet<_>>()
-					.into_iter()
-					.collect(),
-			),
+			file_path::cas_id::in_vec(cas_ids.iter().cloned().collect()),
 		])])
 		.select(object_for_file_identifier::select())
 		.exec()
@@ -257,18 +690,55 @@ async fn fetch_existing_objects_by_cas_id(
 			objects
 				.into_iter()
 				.filter_map(|object| {
+					// An object can have file paths with outdated cas_ids (e.g. while re-identifying
+					// a location with a new algorithm), so we look for the one that matched our query
 					object
 						.file_paths
-						.first()
-						.and_then(|file_path| file_path.cas_id.clone())
+						.iter()
+						.filter_map(|file_path| file_path.cas_id.as_ref())
+						.find(|cas_id| cas_ids.contains(*cas_id))
+						.cloned()
 						.map(|cas_id| (cas_id, object))
 				})
 				.collect()
 		})
 }
 
-async fn assign_existing_objects_to_file_paths(
+/// What committing the identified files would do. Returns how many file paths would be linked to
+/// an existing object, and the `cas_id` of each object that would be created.
+fn preview_objects(
 	identified_files: &HashMap<Uuid, IdentifiedFile>,
+	existing_objects_by_cas_id: &HashMap<String, object_for_file_identifier::Data>,
+) -> (u64, Vec<Option<String>>) {
+	let mut would_link_count = 0;
+	let mut would_create_cas_ids = vec![];
+	let mut file_ids = HashSet::new();
+	let mut cas_ids = HashSet::new();
+
+	for IdentifiedFile {
+		cas_id, file_id, ..
+	} in identified_files.values()
+	{
+		if cas_id
+			.as_ref()
+			.is_some_and(|cas_id| existing_objects_by_cas_id.contains_key(cas_id))
+			// Hard links to the same file share a single object
+			|| file_id.is_some_and(|file_id| !file_ids.insert(file_id))
+			// As do copies of the same content in this task
+			|| cas_id.as_ref().is_some_and(|cas_id| !cas_ids.insert(cas_id))
+		{
+			would_link_count += 1;
+		} else {
+			would_create_cas_ids.push(cas_id.clone());
+		}
+	}
+
+	(would_link_count, would_create_cas_ids)
+}
+
+#[instrument(level = "debug", skip_all, fields(files = files.len()))]
+async fn assign_existing_objects_to_file_paths(
+	files: &[(&Uuid, &IdentifiedFile)],
 	objects_by_cas_id: &HashMap<String, object_for_file_identifier::Data>,
 	db: &PrismaClient,
 	sync: &SyncManager,
@@ -277,9 +747,9 @@ async fn assign_existing_objects_to_file_paths(
 	// connected to file paths with the same cas_id
 	sync.write_ops(
 		db,
-		identified_files
+		files
 			.iter()
-			.filter_map(|(pub_id, IdentifiedFile { cas_id, .. })| {
+			.filter_map(|&(pub_id, IdentifiedFile { cas_id, .. })| {
 				objects_by_cas_id
 					// Filtering out files without cas_id due to being empty
 					.get(cas_id.as_ref()?)
@@ -329,43 +799,92 @@ fn connect_file_path_to_object<'db>(
 	)
 }
 
+#[instrument(level = "debug", skip_all, fields(files = files.len()))]
 async fn create_objects(
-	identified_files: &HashMap<Uuid, IdentifiedFile>,
+	files: &[(&Uuid, &IdentifiedFile)],
 	db: &PrismaClient,
 	sync: &SyncManager,
 ) -> Result<u64, file_identifier::Error> {
-	trace!("Creating {} new Objects", identified_files.len(),);
+	trace!("Creating {} new Objects", files.len(),);
 
-	let (object_create_args, file_path_update_args) = identified_files
+	// Hard links to the same file share a single object, as do copies of the same content
+	let mut object_pub_ids_by_file_id = HashMap::new();
+	let mut object_pub_ids_by_cas_id = HashMap::new();
+
+	let (object_create_args, file_path_update_args) = files
 		.iter()
 		.map(
-			|(
+			|&(
 				file_path_pub_id,
 				IdentifiedFile {
 					file_path: file_path_for_file_identifier::Data { date_created, .. },
+					cas_id,
 					kind,
+					custom_kind,
+					file_id,
+					quick_metadata,
 					..
 				},
 			)| {
+				if let Some(&object_pub_id) = file_id
+					.as_ref()
+					.and_then(|file_id| object_pub_ids_by_file_id.get(file_id))
+					.or_else(|| {
+						cas_id
+							.as_ref()
+							.and_then(|cas_id| object_pub_ids_by_cas_id.get(cas_id))
+					}) {
+					return (
+						None,
+						connect_file_path_to_object(*file_path_pub_id, object_pub_id, sync, db),
+					);
+				}
+
 				let object_pub_id = Uuid::new_v4();
 
+				if let Some(file_id) = file_id {
+					object_pub_ids_by_file_id.insert(*file_id, object_pub_id);
+				}
+				if let Some(cas_id) = cas_id {
+					object_pub_ids_by_cas_id.insert(cas_id, object_pub_id);
+				}
+
 				let kind = *kind as i32;
 
-				let (sync_params, db_params) = [
-					(
-						(object::date_created::NAME, msgpack!(date_created)),
-						object::date_created::set(*date_created),
-					),
-					(
-						(object::kind::NAME, msgpack!(kind)),
-						object::kind::set(Some(kind)),
-					),
-				]
+				let (sync_params, db_params) = chain_optional_iter(
+					[
+						(
+							(object::date_created::NAME, msgpack!(date_created)),
+							object::date_created::set(*date_created),
+						),
+						(
+							(object::kind::NAME, msgpack!(kind)),
+							object::kind::set(Some(kind)),
+						),
+					],
+					[
+						custom_kind.as_ref().map(|custom_kind| {
+							(
+								(object::custom_kind::NAME, msgpack!(custom_kind)),
+								object::custom_kind::set(Some(custom_kind.clone())),
+							)
+						}),
+						quick_metadata
+							.as_ref()
+							.and_then(|quick_metadata| rmp_serde::to_vec_named(quick_metadata).ok())
+							.map(|quick_metadata| {
+								(
+									(object::quick_metadata::NAME, msgpack!(quick_metadata)),
+									object::quick_metadata::set(Some(quick_metadata)),
+								)
+							}),
+					],
+				)
 				.into_iter()
 				.unzip::<_, _, Vec<_>, Vec<_>>();
 
 				(
-					(
+					Some((
 						sync.shared_create(
 							prisma_sync::object::SyncId {
 								pub_id: uuid_to_bytes(object_pub_id),
@@ -373,7 +892,7 @@ async fn create_objects(
 							sync_params,
 						),
 						object::create_unchecked(uuid_to_bytes(object_pub_id), db_params),
-					),
+					)),
 					connect_file_path_to_object(*file_path_pub_id, object_pub_id, sync, db),
 				)
 			},
@@ -385,6 +904,7 @@ async fn create_objects(
 		.write_ops(db, {
 			let (sync, db_params) = object_create_args
 				.into_iter()
+				.flatten()
 				.unzip::<_, _, Vec<_>, Vec<_>>();
 
 			(
@@ -414,6 +934,171 @@ async fn create_objects(
 	Ok(total_created_files as u64)
 }
 
+/// Assigns tags read from extended attributes to the objects of their file paths, creating the
+/// tags that don't exist yet, matched by name
+#[instrument(level = "debug", skip_all, fields(objects = tags_to_import.len()))]
+async fn import_tags(
+	tags_to_import: &HashMap<Uuid, Vec<String>>,
+	db: &PrismaClient,
+	sync: &SyncManager,
+) -> Result<u64, file_identifier::Error> {
+	let tag_names = tags_to_import
+		.values()
+		.flatten()
+		.cloned()
+		.collect::<HashSet<_>>();
+
+	let mut tags_by_name = fetch_tags_by_name(tag_names.iter().cloned().collect(), db).await?;
+
+	let missing_tag_names = tag_names
+		.into_iter()
+		.filter(|name| !tags_by_name.contains_key(name))
+		.collect::<Vec<_>>();
+
+	if !missing_tag_names.is_empty() {
+		trace!("Creating {} new Tags", missing_tag_names.len());
+
+		let date_created: DateTime<FixedOffset> = Utc::now().into();
+
+		// Tasks importing the same tag name at the same time, here or on other devices, derive the
+		// same pub_id from it, so they end up with a single tag
+		let (sync_ops, db_upserts) = missing_tag_names
+			.iter()
+			.map(|name| {
+				let pub_id = imported_tag_pub_id(name);
+
+				let (sync_params, db_params) = [
+					(
+						(tag::name::NAME, msgpack!(name)),
+						tag::name::set(Some(name.clone())),
+					),
+					(
+						(tag::is_hidden::NAME, msgpack!(false)),
+						tag::is_hidden::set(Some(false)),
+					),
+					(
+						(tag::date_created::NAME, msgpack!(date_created)),
+						tag::date_created::set(Some(date_created)),
+					),
+				]
+				.into_iter()
+				.unzip::<_, _, Vec<_>, Vec<_>>();
+
+				(
+					sync.shared_create(
+						prisma_sync::tag::SyncId {
+							pub_id: pub_id.clone(),
+						},
+						sync_params,
+					),
+					db.tag().upsert(
+						tag::pub_id::equals(pub_id.clone()),
+						tag::create_unchecked(pub_id, db_params),
+						vec![],
+					),
+				)
+			})
+			.unzip::<_, _, Vec<_>, Vec<_>>();
+
+		sync.write_ops(db, (sync_ops.into_iter().flatten().collect(), db_upserts))
+			.await?;
+
+		tags_by_name.extend(fetch_tags_by_name(missing_tag_names, db).await?);
+	}
+
+	let file_paths = db
+		.file_path()
+		.find_many(vec![file_path::pub_id::in_vec(
+			tags_to_import
+				.keys()
+				.map(|pub_id| uuid_to_bytes(*pub_id))
+				.collect(),
+		)])
+		.select(file_path::select!({ pub_id object: select { id pub_id } }))
+		.exec()
+		.await?;
+
+	let date_created: DateTime<FixedOffset> = Utc::now().into();
+
+	let mut sync_ops = vec![];
+	let mut db_creates = vec![];
+
+	for file_path in file_paths {
+		let Some(object) = file_path.object else {
+			continue;
+		};
+
+		let Some(tag_names) = tags_to_import
+			.get(&Uuid::from_slice(&file_path.pub_id).expect("uuid bytes are invalid"))
+		else {
+			continue;
+		};
+
+		for tag in tag_names.iter().filter_map(|name| tags_by_name.get(name)) {
+			sync_ops.extend(sync.relation_create(
+				prisma_sync::tag_on_object::SyncId {
+					tag: prisma_sync::tag::SyncId {
+						pub_id: tag.pub_id.clone(),
+					},
+					object: prisma_sync::object::SyncId {
+						pub_id: object.pub_id.clone(),
+					},
+				},
+				[],
+			));
+
+			db_creates.push(tag_on_object::CreateUnchecked {
+				tag_id: tag.id,
+				object_id: object.id,
+				_params: vec![tag_on_object::date_created::set(Some(date_created))],
+			});
+		}
+	}
+
+	if db_creates.is_empty() {
+		return Ok(0);
+	}
+
+	let assigned_tags_count = sync
+		.write_ops(
+			db,
+			(
+				sync_ops,
+				db.tag_on_object().create_many(db_creates).skip_duplicates(),
+			),
+		)
+		.await?;
+
+	trace!("Assigned {assigned_tags_count} Tags from extended attributes");
+
+	#[allow(clippy::cast_sign_loss)] // SAFETY: We're sure the value is positive
+	Ok(assigned_tags_count as u64)
+}
+
+fn imported_tag_pub_id(name: &str) -> Vec<u8> {
+	let mut hasher = blake3::Hasher::new();
+	hasher.update(b"sd-imported-tag:");
+	hasher.update(name.as_bytes());
+
+	hasher.finalize().as_bytes()[..16].to_vec()
+}
+
+async fn fetch_tags_by_name(
+	names: Vec<String>,
+	db: &PrismaClient,
+) -> Result<HashMap<String, tag::Data>, file_identifier::Error> {
+	db.tag()
+		.find_many(vec![tag::name::in_vec(names)])
+		.exec()
+		.await
+		.map(|tags| {
+			tags.into_iter()
+				.filter_map(|tag| tag.name.clone().map(|name| (name, tag)))
+				.collect()
+		})
+		.map_err(Into::into)
+}
+
 impl SerializableTask<Error> for ObjectProcessorTask {
 	type SerializeError = rmp_serde::encode::Error;
 
@@ -425,18 +1110,28 @@ impl SerializableTask<Error> for ObjectProcessorTask {
 		let Self {
 			id,
 			identified_files,
+			tags_to_import,
+			file_path_ids,
 			output,
 			stage,
+			checkpoint,
+			checkpoint_chunk_size,
 			with_priority,
+			dry_run,
 			..
 		} = self;
 
 		rmp_serde::to_vec_named(&SaveState {
 			id,
 			identified_files,
+			tags_to_import,
+			file_path_ids,
 			output,
 			stage,
+			checkpoint,
+			checkpoint_chunk_size,
 			with_priority,
+			dry_run,
 		})
 	}
 
@@ -448,18 +1143,200 @@ impl SerializableTask<Error> for ObjectProcessorTask {
 			|SaveState {
 			     id,
 			     identified_files,
+			     tags_to_import,
+			     file_path_ids,
 			     output,
 			     stage,
+			     checkpoint,
+			     checkpoint_chunk_size,
 			     with_priority,
+			     dry_run,
 			 }| Self {
 				id,
 				db,
 				sync,
 				identified_files,
+				tags_to_import,
+				file_path_ids,
 				output,
 				stage,
+				checkpoint,
+				checkpoint_chunk_size,
 				with_priority,
+				dry_run,
 			},
 		)
 	}
 }
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+	use super::*;
+
+	use sd_file_ext::kind::ObjectKind;
+	use sd_prisma::prisma::location;
+	use sd_task_system::TaskOutput;
+
+	use std::sync::atomic::AtomicBool;
+
+	use tempfile::tempdir;
+
+	fn identified_file(
+		file_path: file_path_for_file_identifier::Data,
+		cas_id: &str,
+	) -> IdentifiedFile {
+		IdentifiedFile {
+			file_path,
+			cas_id: Some(cas_id.to_string()),
+			kind: ObjectKind::Document,
+			custom_kind: None,
+			xattr_tags: vec!["From xattrs".to_string()],
+			raw_xattrs: BTreeMap::new(),
+			link_target: None,
+			file_id: None,
+			remote_only: false,
+			physical_size: None,
+			allocated_size: None,
+			archive: None,
+			quick_metadata: None,
+		}
+	}
+
+	#[tokio::test]
+	async fn dry_run_writes_nothing() {
+		let dir = tempdir().unwrap();
+		let instance_id = Uuid::new_v4();
+
+		let db = Arc::new(
+			PrismaClient::_builder()
+				.with_url(format!("file:{}", dir.path().join("library.db").display()))
+				.build()
+				.await
+				.unwrap(),
+		);
+		db._db_push().await.unwrap();
+
+		db.instance()
+			.create(
+				uuid_to_bytes(instance_id),
+				vec![],
+				vec![],
+				Utc::now().into(),
+				Utc::now().into(),
+				vec![],
+			)
+			.exec()
+			.await
+			.unwrap();
+
+		let sync = Arc::new(
+			SyncManager::new(
+				&db,
+				instance_id,
+				&Arc::new(AtomicBool::new(true)),
+				HashMap::new(),
+				&Default::default(),
+			)
+			.await
+			.manager,
+		);
+
+		let location = db
+			.location()
+			.create(
+				uuid_to_bytes(Uuid::new_v4()),
+				vec![location::path::set(Some(dir.path().display().to_string()))],
+			)
+			.exec()
+			.await
+			.unwrap();
+
+		let object = db
+			.object()
+			.create(uuid_to_bytes(Uuid::new_v4()), vec![])
+			.exec()
+			.await
+			.unwrap();
+
+		let mut file_paths = vec![];
+		for (name, linked_object) in [("existing", Some(&object)), ("copy", None), ("new", None)] {
+			file_paths.push(
+				db.file_path()
+					.create(
+						uuid_to_bytes(Uuid::new_v4()),
+						chain_optional_iter(
+							[
+								file_path::location::connect(location::id::equals(location.id)),
+								file_path::materialized_path::set(Some("/".to_string())),
+								file_path::name::set(Some(name.to_string())),
+								file_path::extension::set(Some("txt".to_string())),
+								file_path::is_dir::set(Some(false)),
+							],
+							[
+								linked_object
+									.map(|_| file_path::cas_id::set(Some("existing".to_string()))),
+								linked_object.map(|object| {
+									file_path::object::connect(object::id::equals(object.id))
+								}),
+							],
+						),
+					)
+					.select(file_path_for_file_identifier::select())
+					.exec()
+					.await
+					.unwrap(),
+			);
+		}
+
+		let identified_files = file_paths
+			.into_iter()
+			.skip(1)
+			.zip(["existing", "new"])
+			.map(|(file_path, cas_id)| {
+				(
+					Uuid::from_slice(&file_path.pub_id).unwrap(),
+					identified_file(file_path, cas_id),
+				)
+			})
+			.collect();
+
+		let ExecStatus::Done(TaskOutput::Out(output)) =
+			ObjectProcessorTask::new(identified_files, Arc::clone(&db), sync, false)
+				.with_dry_run(true)
+				.run(&Interrupter::uninterruptible())
+				.await
+				.unwrap()
+		else {
+			panic!("dry run didn't return an output");
+		};
+
+		let Output {
+			created_objects_count,
+			linked_objects_count,
+			assigned_tags_count,
+			new_objects_cas_ids,
+			..
+		} = *output.downcast::<Output>().unwrap();
+
+		assert_eq!(created_objects_count, 1);
+		assert_eq!(linked_objects_count, 1);
+		assert_eq!(assigned_tags_count, 2);
+		assert_eq!(new_objects_cas_ids, vec![Some("new".to_string())]);
+
+		assert_eq!(db.object().count(vec![]).exec().await.unwrap(), 1);
+		assert_eq!(db.tag().count(vec![]).exec().await.unwrap(), 0);
+		assert_eq!(db.crdt_operation().count(vec![]).exec().await.unwrap(), 0);
+		assert_eq!(
+			db.file_path()
+				.count(vec![
+					file_path::cas_id::equals(None),
+					file_path::object_id::equals(None)
+				])
+				.exec()
+				.await
+				.unwrap(),
+			2
+		);
+	}
+}
@@ -0,0 +1,209 @@
+use std::{collections::BTreeMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+use tokio::{io, task::spawn_blocking};
+
+// Bigger values (like resource forks or huge alternate data streams) aren't worth keeping around
+#[cfg_attr(not(any(target_os = "linux", target_os = "windows")), allow(dead_code))]
+const MAX_ATTRIBUTE_SIZE: usize = 1024 * 64;
+
+/// Platform extended attributes of a file, read during identification when requested.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExtendedAttributes {
+	/// Tag names set by the OS file manager: Finder tags on macOS and `user.xdg.tags` on Linux
+	pub tags: Vec<String>,
+	/// Remaining attributes by name: `user.*` xattrs on Linux and named alternate data streams on Windows
+	pub raw: BTreeMap<String, Vec<u8>>,
+}
+
+impl ExtendedAttributes {
+	pub async fn extract(path: impl AsRef<Path> + Send) -> Result<Self, io::Error> {
+		let path = path.as_ref().to_path_buf();
+
+		spawn_blocking(move || platform::extract(&path))
+			.await
+			.map_err(io::Error::other)?
+	}
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+	use super::ExtendedAttributes;
+
+	use std::{io, path::Path};
+
+	use tracing::warn;
+
+	const FINDER_TAGS_ATTRIBUTE: &str = "com.apple.metadata:_kMDItemUserTags";
+
+	pub fn extract(path: &Path) -> Result<ExtendedAttributes, io::Error> {
+		let Some(value) = xattr::get(path, FINDER_TAGS_ATTRIBUTE)? else {
+			return Ok(ExtendedAttributes::default());
+		};
+
+		// Finder stores tags as a binary plist array of "<name>\n<color index>" strings
+		let tags = match plist::from_bytes::<Vec<String>>(&value) {
+			Ok(tags) => tags
+				.into_iter()
+				.filter_map(|tag| {
+					tag.split('\n')
+						.next()
+						.filter(|name| !name.is_empty())
+						.map(str::to_string)
+				})
+				.collect(),
+			Err(e) => {
+				warn!(
+					"Failed to parse Finder tags <path='{}'>: {e:#?}",
+					path.display()
+				);
+				vec![]
+			}
+		};
+
+		Ok(ExtendedAttributes {
+			tags,
+			..Default::default()
+		})
+	}
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+	use super::{ExtendedAttributes, MAX_ATTRIBUTE_SIZE};
+
+	use std::{io, path::Path};
+
+	const USER_NAMESPACE: &str = "user.";
+	// Used by KDE's Dolphin and other freedesktop file managers
+	const XDG_TAGS_ATTRIBUTE: &str = "user.xdg.tags";
+
+	pub fn extract(path: &Path) -> Result<ExtendedAttributes, io::Error> {
+		let mut attributes = ExtendedAttributes::default();
+
+		for name in xattr::list(path)? {
+			let Some(name) = name.to_str() else {
+				continue;
+			};
+
+			if !name.starts_with(USER_NAMESPACE) {
+				continue;
+			}
+
+			let Some(value) = xattr::get(path, name)? else {
+				continue;
+			};
+
+			if name == XDG_TAGS_ATTRIBUTE {
+				attributes.tags = String::from_utf8_lossy(&value)
+					.split(',')
+					.map(str::trim)
+					.filter(|tag| !tag.is_empty())
+					.map(str::to_string)
+					.collect();
+			} else if value.len() <= MAX_ATTRIBUTE_SIZE {
+				attributes.raw.insert(name.to_string(), value);
+			}
+		}
+
+		Ok(attributes)
+	}
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+	use super::{ExtendedAttributes, MAX_ATTRIBUTE_SIZE};
+
+	use std::{ffi::c_void, fs, io, iter, os::windows::ffi::OsStrExt, path::Path, ptr};
+
+	use tracing::warn;
+	use windows::{
+		core::PCWSTR,
+		Win32::Storage::FileSystem::{
+			FindClose, FindFirstStreamW, FindNextStreamW, FindStreamInfoStandard,
+			WIN32_FIND_STREAM_DATA,
+		},
+	};
+
+	pub fn extract(path: &Path) -> Result<ExtendedAttributes, io::Error> {
+		let wide_path = path
+			.as_os_str()
+			.encode_wide()
+			.chain(iter::once(0))
+			.collect::<Vec<_>>();
+
+		let mut stream_data = WIN32_FIND_STREAM_DATA::default();
+		let stream_data_ptr = ptr::addr_of_mut!(stream_data).cast::<c_void>();
+
+		// SAFETY: `wide_path` is null terminated and `stream_data` outlives the handle
+		let handle = unsafe {
+			FindFirstStreamW(
+				PCWSTR(wide_path.as_ptr()),
+				FindStreamInfoStandard,
+				stream_data_ptr,
+				0,
+			)
+		}?;
+
+		let mut attributes = ExtendedAttributes::default();
+
+		loop {
+			if let Some(name) = named_stream(&stream_data) {
+				if usize::try_from(stream_data.StreamSize)
+					.map_or(false, |size| size <= MAX_ATTRIBUTE_SIZE)
+				{
+					let mut stream_path = path.as_os_str().to_owned();
+					stream_path.push(":");
+					stream_path.push(&name);
+
+					match fs::read(&stream_path) {
+						Ok(value) => {
+							attributes.raw.insert(name, value);
+						}
+						Err(e) => warn!(
+							"Failed to read alternate data stream <path='{}', stream='{name}'>: {e:#?}",
+							path.display()
+						),
+					}
+				}
+			}
+
+			// SAFETY: `handle` is a valid find handle until we close it below
+			if !unsafe { FindNextStreamW(handle, stream_data_ptr) }.as_bool() {
+				break;
+			}
+		}
+
+		// SAFETY: `handle` was returned by `FindFirstStreamW` and is closed only once
+		unsafe { FindClose(handle) };
+
+		Ok(attributes)
+	}
+
+	/// Stream names come as `:<name>:$DATA`, the unnamed one being the file content itself
+	fn named_stream(stream_data: &WIN32_FIND_STREAM_DATA) -> Option<String> {
+		let len = stream_data
+			.cStreamName
+			.iter()
+			.position(|&c| c == 0)
+			.unwrap_or(stream_data.cStreamName.len());
+
+		String::from_utf16_lossy(&stream_data.cStreamName[..len])
+			.strip_prefix(':')?
+			.strip_suffix(":$DATA")
+			.filter(|name| !name.is_empty())
+			.map(str::to_string)
+	}
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+mod platform {
+	use super::ExtendedAttributes;
+
+	use std::{io, path::Path};
+
+	#[allow(clippy::unnecessary_wraps)]
+	pub fn extract(_: &Path) -> Result<ExtendedAttributes, io::Error> {
+		Ok(ExtendedAttributes::default())
+	}
+}
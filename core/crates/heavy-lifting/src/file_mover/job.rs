@@ -0,0 +1,646 @@
+use crate::{
+	file_copier::copier::CopyProgress,
+	file_identifier::{not_in_archive, CasIdAlgorithm},
+	file_mover,
+	job_system::{
+		job::{Job, JobReturn, JobTaskDispatcher, ReturnStatus},
+		report::ReportOutputMetadata,
+		utils::{cancel_pending_tasks, location_concurrency_key},
+		SerializableJob, SerializedTasks,
+	},
+	utils::io_throttle::IoThrottle,
+	Error, JobName, NonCriticalError, OuterContext, ProgressUpdate,
+};
+
+use sd_core_file_path_helper::IsolatedFilePathData;
+use sd_core_prisma_helpers::file_path_for_file_mover;
+
+use sd_prisma::prisma::{file_path, location};
+use sd_task_system::{
+	IntoTask, SerializableTask, Task, TaskDispatcher, TaskHandle, TaskId, TaskOutput, TaskStatus,
+	TaskSystemError,
+};
+use sd_utils::{
+	db::{maybe_missing, size_in_bytes_from_db},
+	error::FileIOError,
+};
+
+use std::{
+	collections::HashMap,
+	hash::{Hash, Hasher},
+	mem,
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::Duration,
+};
+
+use async_channel as chan;
+use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
+use futures_concurrency::future::{Race, TryJoin};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::{fs, time::Instant};
+use tracing::{debug, warn};
+
+use super::{
+	tasks::{
+		mover::{self, MoveEntry, Stage},
+		Mover,
+	},
+	ConflictPolicy, LocationRoot, BATCH_SIZE,
+};
+
+// Byte progress arrives every chunk of cross device moves, the UI doesn't need it that often
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Moves a selection of file paths of a location into a directory of another location (or the
+/// same one). File paths are updated in place instead of being indexed again, so moved files keep
+/// their objects, and with them their tags, labels and notes. Moves are plain renames within a
+/// device, and verified copies followed by deleting the source across devices.
+#[derive(Debug)]
+pub struct FileMover {
+	source: LocationRoot,
+	target: LocationRoot,
+	target_path: PathBuf,
+	file_path_ids: Vec<file_path::id::Type>,
+	conflict_policy: ConflictPolicy,
+	cas_id_algorithm: CasIdAlgorithm,
+	io_throttle: IoThrottle,
+
+	metadata: Metadata,
+	paths_progress: HashMap<TaskId, PathProgress>,
+	progress_tx: chan::Sender<(TaskId, CopyProgress)>,
+	progress_rx: chan::Receiver<(TaskId, CopyProgress)>,
+
+	errors: Vec<NonCriticalError>,
+
+	pending_tasks_on_resume: Vec<TaskHandle<Error>>,
+	tasks_for_shutdown: Vec<Box<dyn Task<Error>>>,
+}
+
+/// The file a task is copying to another device right now
+#[derive(Debug)]
+struct PathProgress {
+	path: PathBuf,
+	size: u64,
+	copied: u64,
+}
+
+impl Hash for FileMover {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.source.id.hash(state);
+		self.target_path.hash(state);
+		self.file_path_ids.hash(state);
+	}
+}
+
+impl Job for FileMover {
+	const NAME: JobName = JobName::FileMover;
+
+	async fn resume_tasks(
+		&mut self,
+		dispatcher: &JobTaskDispatcher,
+		ctx: &impl OuterContext,
+		SerializedTasks(serialized_tasks): SerializedTasks,
+	) -> Result<(), Error> {
+		let dispatcher =
+			dispatcher.with_concurrency_key(location_concurrency_key(ctx.id(), self.target.id));
+		let io_throttle = &dispatcher.io_throttle(&self.io_throttle);
+		let progress_tx = &self.progress_tx;
+
+		self.pending_tasks_on_resume = dispatcher
+			.dispatch_many_boxed(
+				rmp_serde::from_slice::<Vec<Vec<u8>>>(&serialized_tasks)
+					.map_err(file_mover::Error::from)?
+					.into_iter()
+					.map(|task_bytes| async move {
+						Mover::deserialize(
+							&task_bytes,
+							(
+								progress_tx.clone(),
+								Arc::clone(ctx.db()),
+								Arc::clone(ctx.sync()),
+							),
+						)
+						.await
+						// Tasks share the job's budget again, instead of one each
+						.map(|task| task.with_io_throttle(io_throttle.clone()))
+						.map(IntoTask::into_task)
+					})
+					.collect::<Vec<_>>()
+					.try_join()
+					.await
+					.map_err(file_mover::Error::from)?,
+			)
+			.await;
+
+		Ok(())
+	}
+
+	async fn run<Ctx: OuterContext>(
+		mut self,
+		dispatcher: JobTaskDispatcher,
+		ctx: Ctx,
+	) -> Result<ReturnStatus, Error> {
+		enum RaceOutput {
+			Task(Option<Result<TaskStatus<Error>, TaskSystemError>>),
+			Progress(Option<(TaskId, CopyProgress)>),
+		}
+
+		let dispatcher =
+			dispatcher.with_concurrency_key(location_concurrency_key(ctx.id(), self.target.id));
+
+		let mut pending_running_tasks = FuturesUnordered::new();
+
+		self.init_or_resume(&mut pending_running_tasks, &ctx, &dispatcher)
+			.await?;
+
+		let mut last_progress_update = Instant::now();
+
+		while !pending_running_tasks.is_empty() {
+			let output = (
+				pending_running_tasks.next().map(RaceOutput::Task),
+				self.progress_rx
+					.recv()
+					.map(|res| RaceOutput::Progress(res.ok())),
+			)
+				.race()
+				.await;
+
+			let task = match output {
+				RaceOutput::Progress(Some((task_id, progress))) => {
+					self.process_progress(task_id, progress);
+
+					if last_progress_update.elapsed() > PROGRESS_INTERVAL {
+						last_progress_update = Instant::now();
+						self.report_progress(&ctx);
+					}
+
+					continue;
+				}
+				// The job holds a sender, so the channel is never closed
+				RaceOutput::Progress(None) | RaceOutput::Task(None) => continue,
+				RaceOutput::Task(Some(task)) => task,
+			};
+
+			match task {
+				Ok(TaskStatus::Done((task_id, TaskOutput::Out(out)))) => {
+					self.paths_progress.remove(&task_id);
+					self.process_mover_output(
+						*out.downcast::<mover::Output>()
+							.expect("the file mover job only dispatches mover tasks"),
+					);
+					self.report_progress(&ctx);
+				}
+
+				Ok(TaskStatus::Done((task_id, TaskOutput::Empty))) => {
+					warn!("Task <id='{task_id}'> returned an empty output");
+				}
+
+				Ok(TaskStatus::Shutdown(task)) => {
+					self.tasks_for_shutdown.push(task);
+				}
+
+				Ok(TaskStatus::Error(e)) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e);
+				}
+
+				Ok(TaskStatus::Canceled | TaskStatus::ForcedAbortion) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Ok(ReturnStatus::Canceled);
+				}
+
+				Err(e) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e.into());
+				}
+			}
+		}
+
+		// Progress sent right before the tasks finished, so the copied bytes add up
+		while let Ok((task_id, progress)) = self.progress_rx.try_recv() {
+			self.process_progress(task_id, progress);
+		}
+
+		if !self.tasks_for_shutdown.is_empty() {
+			return Ok(ReturnStatus::Shutdown(
+				SerializableJob::<Ctx>::serialize(self).await,
+			));
+		}
+
+		ctx.invalidate_query("search.paths");
+
+		let Self {
+			metadata, errors, ..
+		} = self;
+
+		Ok(ReturnStatus::Completed(
+			JobReturn::builder()
+				.with_metadata(metadata)
+				.with_non_critical_errors(errors)
+				.build(),
+		))
+	}
+}
+
+impl FileMover {
+	/// Moves the `file_path_ids` of `source_location` into `target_sub_path` of `target_location`.
+	/// `cas_id_algorithm` must be the one the library's `cas_id`s were generated with, as it's
+	/// used to verify copies when moving across devices.
+	pub fn new(
+		source_location: &location::Data,
+		target_location: &location::Data,
+		target_sub_path: impl AsRef<Path>,
+		file_path_ids: Vec<file_path::id::Type>,
+		cas_id_algorithm: CasIdAlgorithm,
+	) -> Result<Self, file_mover::Error> {
+		let root = |location: &location::Data| {
+			maybe_missing(&location.path, "location.path").map(|path| LocationRoot {
+				id: location.id,
+				pub_id: location.pub_id.clone(),
+				path: Arc::new(PathBuf::from(path)),
+			})
+		};
+
+		let target = root(target_location)?;
+		let target_path = target.path.join(target_sub_path);
+
+		let (progress_tx, progress_rx) = chan::unbounded();
+
+		Ok(Self {
+			source: root(source_location)?,
+			target,
+			target_path,
+			file_path_ids,
+			conflict_policy: ConflictPolicy::default(),
+			cas_id_algorithm,
+			io_throttle: IoThrottle::default(),
+			metadata: Metadata::default(),
+			paths_progress: HashMap::new(),
+			progress_tx,
+			progress_rx,
+			errors: Vec::new(),
+			pending_tasks_on_resume: Vec::new(),
+			tasks_for_shutdown: Vec::new(),
+		})
+	}
+
+	#[must_use]
+	pub const fn with_conflict_policy(mut self, conflict_policy: ConflictPolicy) -> Self {
+		self.conflict_policy = conflict_policy;
+		self
+	}
+
+	/// Caps how fast files are read when moved across devices, shared by every mover task of the job
+	#[must_use]
+	pub fn with_io_throttle(mut self, io_throttle: IoThrottle) -> Self {
+		self.io_throttle = io_throttle;
+		self
+	}
+
+	async fn init_or_resume(
+		&mut self,
+		pending_running_tasks: &mut FuturesUnordered<TaskHandle<Error>>,
+		ctx: &impl OuterContext,
+		dispatcher: &JobTaskDispatcher,
+	) -> Result<(), file_mover::Error> {
+		// Resumed tasks were already dispatched by `resume_tasks`
+		if !self.pending_tasks_on_resume.is_empty() {
+			pending_running_tasks.extend(mem::take(&mut self.pending_tasks_on_resume));
+
+			return Ok(());
+		}
+
+		let deep_hash_threshold = ctx.deep_hash_threshold().await;
+
+		let entries = self.gather_entries(ctx).await?;
+
+		debug!(
+			"Moving {} file paths, {} bytes, from location {} into \"{}\"",
+			self.metadata.total_paths,
+			self.metadata.total_bytes,
+			self.source.id,
+			self.target_path.display()
+		);
+
+		let io_throttle = dispatcher.io_throttle(&self.io_throttle);
+
+		pending_running_tasks.extend(
+			dispatcher
+				.dispatch_many(
+					entries
+						.chunks(BATCH_SIZE)
+						.map(|chunk| {
+							Mover::new(
+								self.source.clone(),
+								self.target.clone(),
+								// Reversed as tasks pop their entries
+								chunk.iter().rev().cloned().collect(),
+								self.conflict_policy,
+								self.cas_id_algorithm,
+								deep_hash_threshold,
+								self.progress_tx.clone(),
+								Arc::clone(ctx.db()),
+								Arc::clone(ctx.sync()),
+							)
+							.with_io_throttle(io_throttle.clone())
+						})
+						.collect::<Vec<_>>(),
+				)
+				.await,
+		);
+
+		self.report_progress(ctx);
+
+		Ok(())
+	}
+
+	async fn gather_entries(
+		&mut self,
+		ctx: &impl OuterContext,
+	) -> Result<Vec<MoveEntry>, file_mover::Error> {
+		let file_paths = ctx
+			.db()
+			.file_path()
+			.find_many(vec![
+				file_path::location_id::equals(Some(self.source.id)),
+				file_path::id::in_vec(self.file_path_ids.clone()),
+				not_in_archive(),
+			])
+			.select(file_path_for_file_mover::select())
+			.exec()
+			.await?;
+
+		if let Some(missing_id) = self
+			.file_path_ids
+			.iter()
+			.find(|id| !file_paths.iter().any(|file_path| file_path.id == **id))
+		{
+			return Err(file_mover::Error::FilePathNotFound(*missing_id));
+		}
+
+		fs::create_dir_all(&self.target_path).await.map_err(|e| {
+			FileIOError::from((&self.target_path, e, "Failed to create target directory"))
+		})?;
+
+		let mut entries = Vec::with_capacity(file_paths.len());
+
+		for file_path in file_paths {
+			let iso_file_path = IsolatedFilePathData::try_from((self.source.id, &file_path))?;
+			let source = self.source.path.join(&iso_file_path);
+			let target = self.target_path.join(iso_file_path.full_name());
+
+			if iso_file_path.is_dir() && target.starts_with(&source) {
+				self.metadata.failed_paths += 1;
+				self.errors.push(
+					file_mover::NonCriticalError::from(mover::NonCriticalError::FailedToMove(
+						source,
+						target,
+						"can't move a directory into itself".to_string(),
+					))
+					.into(),
+				);
+				continue;
+			}
+
+			self.metadata.total_paths += 1;
+			self.metadata.total_bytes += file_path
+				.size_in_bytes_bytes
+				.as_deref()
+				.map(size_in_bytes_from_db)
+				.unwrap_or_default();
+
+			entries.push(MoveEntry {
+				file_path_id: file_path.id,
+				pub_id: file_path.pub_id,
+				is_dir: iso_file_path.is_dir(),
+				source,
+				target,
+				stage: Stage::Pending,
+			});
+		}
+
+		Ok(entries)
+	}
+
+	fn process_progress(&mut self, task_id: TaskId, progress: CopyProgress) {
+		match progress {
+			CopyProgress::Started {
+				path,
+				size,
+				resumed_at,
+			} => {
+				self.paths_progress.insert(
+					task_id,
+					PathProgress {
+						path,
+						size,
+						copied: resumed_at,
+					},
+				);
+			}
+			CopyProgress::Copied(bytes) => {
+				self.metadata.copied_bytes += bytes;
+				if let Some(file) = self.paths_progress.get_mut(&task_id) {
+					file.copied += bytes;
+				}
+			}
+			CopyProgress::Finished => {
+				self.metadata.completed_paths += 1;
+				self.paths_progress.remove(&task_id);
+			}
+		}
+	}
+
+	fn report_progress(&self, ctx: &impl OuterContext) {
+		let message = self.paths_progress.values().next().map_or_else(
+			|| {
+				format!(
+					"Moved {} of {} paths",
+					self.metadata.completed_paths, self.metadata.total_paths
+				)
+			},
+			|PathProgress { path, size, copied }| {
+				format!(
+					"Copying \"{}\" to another device: {copied} of {size} bytes",
+					path.display()
+				)
+			},
+		);
+
+		ctx.progress(vec![
+			ProgressUpdate::TaskCount(self.metadata.total_paths),
+			ProgressUpdate::CompletedTaskCount(self.metadata.completed_paths),
+			ProgressUpdate::Message(message),
+		]);
+	}
+
+	fn process_mover_output(
+		&mut self,
+		mover::Output {
+			moved,
+			copied_across_devices,
+			skipped,
+			failed,
+			move_time,
+			copy_time,
+			verification_time,
+			db_write_time,
+			errors,
+		}: mover::Output,
+	) {
+		self.metadata.moved_paths += moved;
+		self.metadata.copied_across_devices += copied_across_devices;
+		self.metadata.skipped_paths += skipped;
+		self.metadata.failed_paths += failed;
+		self.metadata.move_time += move_time;
+		self.metadata.copy_time += copy_time;
+		self.metadata.verification_time += verification_time;
+		self.metadata.db_write_time += db_write_time;
+
+		self.errors.extend(errors);
+	}
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Metadata {
+	total_paths: u64,
+	total_bytes: u64,
+	completed_paths: u64,
+	moved_paths: u64,
+	skipped_paths: u64,
+	failed_paths: u64,
+	copied_across_devices: u64,
+	copied_bytes: u64,
+	move_time: Duration,
+	copy_time: Duration,
+	verification_time: Duration,
+	db_write_time: Duration,
+}
+
+impl From<Metadata> for ReportOutputMetadata {
+	fn from(value: Metadata) -> Self {
+		Self::Metrics(HashMap::from([
+			("total_paths".into(), json!(value.total_paths)),
+			("total_bytes".into(), json!(value.total_bytes)),
+			("moved_paths".into(), json!(value.moved_paths)),
+			("skipped_paths".into(), json!(value.skipped_paths)),
+			("failed_paths".into(), json!(value.failed_paths)),
+			(
+				"copied_across_devices".into(),
+				json!(value.copied_across_devices),
+			),
+			("copied_bytes".into(), json!(value.copied_bytes)),
+			("move_time".into(), json!(value.move_time)),
+			("copy_time".into(), json!(value.copy_time)),
+			("verification_time".into(), json!(value.verification_time)),
+			("db_write_time".into(), json!(value.db_write_time)),
+		]))
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+struct SaveState {
+	source: LocationRoot,
+	target: LocationRoot,
+	target_path: PathBuf,
+	file_path_ids: Vec<file_path::id::Type>,
+	conflict_policy: ConflictPolicy,
+	cas_id_algorithm: CasIdAlgorithm,
+	#[serde(default)]
+	io_throttle: IoThrottle,
+
+	metadata: Metadata,
+
+	errors: Vec<NonCriticalError>,
+
+	tasks_for_shutdown_bytes: Option<SerializedTasks>,
+}
+
+impl<Ctx: OuterContext> SerializableJob<Ctx> for FileMover {
+	async fn serialize(self) -> Result<Option<Vec<u8>>, rmp_serde::encode::Error> {
+		let Self {
+			source,
+			target,
+			target_path,
+			file_path_ids,
+			conflict_policy,
+			cas_id_algorithm,
+			io_throttle,
+			metadata,
+			errors,
+			tasks_for_shutdown,
+			..
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState {
+			source,
+			target,
+			target_path,
+			file_path_ids,
+			conflict_policy,
+			cas_id_algorithm,
+			io_throttle,
+			metadata,
+			errors,
+			tasks_for_shutdown_bytes: Some(SerializedTasks(rmp_serde::to_vec_named(
+				&tasks_for_shutdown
+					.into_iter()
+					.map(|task| async move {
+						task.downcast::<Mover>()
+							.expect("the file mover job only dispatches mover tasks")
+							.serialize()
+							.await
+					})
+					.collect::<Vec<_>>()
+					.try_join()
+					.await?,
+			)?)),
+		})
+		.map(Some)
+	}
+
+	async fn deserialize(
+		serialized_job: &[u8],
+		_: &Ctx,
+	) -> Result<Option<(Self, Option<SerializedTasks>)>, rmp_serde::decode::Error> {
+		let SaveState {
+			source,
+			target,
+			target_path,
+			file_path_ids,
+			conflict_policy,
+			cas_id_algorithm,
+			io_throttle,
+			metadata,
+			errors,
+			tasks_for_shutdown_bytes,
+		} = rmp_serde::from_slice::<SaveState>(serialized_job)?;
+
+		let (progress_tx, progress_rx) = chan::unbounded();
+
+		Ok(Some((
+			Self {
+				source,
+				target,
+				target_path,
+				file_path_ids,
+				conflict_policy,
+				cas_id_algorithm,
+				io_throttle,
+				metadata,
+				paths_progress: HashMap::new(),
+				progress_tx,
+				progress_rx,
+				errors,
+				pending_tasks_on_resume: Vec::new(),
+				tasks_for_shutdown: Vec::new(),
+			},
+			tasks_for_shutdown_bytes,
+		)))
+	}
+}
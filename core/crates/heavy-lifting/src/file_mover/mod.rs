@@ -0,0 +1,67 @@
+use sd_core_file_path_helper::FilePathError;
+
+use sd_prisma::prisma::file_path;
+use sd_utils::{db::MissingFieldError, error::FileIOError};
+
+use std::io;
+
+use prisma_client_rust::QueryError;
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+pub mod job;
+mod tasks;
+
+pub use crate::{file_copier::ConflictPolicy, utils::relink::LocationRoot};
+pub use job::FileMover;
+pub use tasks::mover;
+
+// How many selected file paths each mover task moves, one after the other
+const BATCH_SIZE: usize = 50;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("file_path not found in source location: <id='{0}'>")]
+	FilePathNotFound(file_path::id::Type),
+	#[error("missing field on database: {0}")]
+	MissingField(#[from] MissingFieldError),
+	#[error("database error: {0}")]
+	Database(#[from] QueryError),
+	#[error("failed to deserialized stored tasks for job resume: {0}")]
+	DeserializeTasks(#[from] rmp_serde::decode::Error),
+
+	#[error(transparent)]
+	FilePathError(#[from] FilePathError),
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+}
+
+impl From<Error> for rspc::Error {
+	fn from(err: Error) -> Self {
+		match err {
+			Error::FilePathNotFound(_) => {
+				Self::with_cause(ErrorCode::NotFound, err.to_string(), err)
+			}
+
+			_ => Self::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize, Type)]
+pub enum NonCriticalError {
+	#[error(transparent)]
+	Mover(#[from] mover::NonCriticalError),
+}
+
+/// Renames fail with `EXDEV` on Unix and `ERROR_NOT_SAME_DEVICE` on Windows when moving between
+/// file systems, as `io::ErrorKind::CrossesDevices` isn't stable yet
+fn crosses_devices(e: &io::Error) -> bool {
+	#[cfg(unix)]
+	const CROSS_DEVICE_ERROR: i32 = 18;
+	#[cfg(windows)]
+	const CROSS_DEVICE_ERROR: i32 = 17;
+
+	e.raw_os_error() == Some(CROSS_DEVICE_ERROR)
+}
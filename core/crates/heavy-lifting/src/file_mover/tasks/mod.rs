@@ -0,0 +1,3 @@
+pub mod mover;
+
+pub use mover::Mover;
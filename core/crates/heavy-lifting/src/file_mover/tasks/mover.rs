@@ -0,0 +1,679 @@
+use crate::{
+	file_copier::{
+		available_path,
+		copier::{self, copy_verified, CopyProgress, ProgressReporter, VerifiedCopy},
+		exists, ConflictPolicy,
+	},
+	file_identifier::{self, CasIdAlgorithm},
+	file_mover::{self, crosses_devices, LocationRoot},
+	utils::{
+		io_throttle::IoThrottle,
+		relink::{self, Relinked},
+	},
+	Error,
+};
+
+use sd_core_file_path_helper::IsolatedFilePathData;
+use sd_core_sync::Manager as SyncManager;
+
+use sd_prisma::{
+	prisma::{file_path, PrismaClient},
+	prisma_sync,
+};
+use sd_sync::OperationFactory;
+use sd_task_system::{
+	check_interruption, ExecStatus, Interrupter, InterruptionKind, IntoAnyTaskOutput,
+	SerializableTask, Task, TaskId,
+};
+use sd_utils::error::FileIOError;
+
+use std::{
+	mem,
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::Duration,
+};
+
+use async_channel as chan;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::{fs, time::Instant};
+use tracing::{trace, warn};
+
+/// A selected file path to be moved, with everything under it for directories
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MoveEntry {
+	pub file_path_id: file_path::id::Type,
+	pub pub_id: Vec<u8>,
+	pub is_dir: bool,
+	pub source: PathBuf,
+	pub target: PathBuf,
+	#[serde(default)]
+	pub stage: Stage,
+}
+
+/// How far a move went, so a resumed task doesn't redo what's done. File paths are only pointed
+/// to the target once their files are there, so the database never has them somewhere they
+/// aren't yet.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub enum Stage {
+	/// Nothing done yet, the conflict policy still has to be applied to the target
+	#[default]
+	Pending,
+	/// Files are at the target, their file paths still have to be pointed there, forgetting the
+	/// one of the file it replaced
+	Moved { replacing: bool },
+	/// Renaming failed as the target is in another device, so files are copied then deleted one
+	/// by one, the ones left being in `files`. Each file path is pointed to the target right
+	/// after its file is there.
+	CrossDevice {
+		files: Vec<(PathBuf, PathBuf)>,
+		failed: u64,
+		replacing: bool,
+	},
+}
+
+/// Moves a batch of file paths, one after the other, keeping their rows in the database so they
+/// keep their objects, with their tags, labels and notes
+#[derive(Debug)]
+pub struct Mover {
+	id: TaskId,
+	source: LocationRoot,
+	target: LocationRoot,
+	entries: Vec<MoveEntry>,
+	conflict_policy: ConflictPolicy,
+	cas_id_algorithm: CasIdAlgorithm,
+	deep_hash_threshold: Option<u64>,
+	io_throttle: IoThrottle,
+	progress_tx: chan::Sender<(TaskId, CopyProgress)>,
+	db: Arc<PrismaClient>,
+	sync: Arc<SyncManager>,
+	output: Output,
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize, Type)]
+pub enum NonCriticalError {
+	#[error("failed to move: <source='{}', target='{}'>: {2}", .0.display(), .1.display())]
+	FailedToMove(PathBuf, PathBuf, String),
+	#[error("failed to update file paths of moved path: <path='{}'>: {1}", .0.display())]
+	FailedToRelink(PathBuf, String),
+	#[error("failed to remove source after copying it to another device: <path='{}'>: {1}", .0.display())]
+	FailedToRemoveSource(PathBuf, String),
+	#[error("failed to find an available name to avoid a conflict: <path='{}'>", .0.display())]
+	FailedToFindAvailableName(PathBuf),
+	#[error(transparent)]
+	Copy(copier::NonCriticalError),
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Output {
+	pub moved: u64,
+	/// Files copied then deleted, as they were moved to another device
+	pub copied_across_devices: u64,
+	pub skipped: u64,
+	pub failed: u64,
+	pub move_time: Duration,
+	pub copy_time: Duration,
+	pub verification_time: Duration,
+	pub db_write_time: Duration,
+	pub errors: Vec<crate::NonCriticalError>,
+}
+
+enum MoveOutcome {
+	Moved,
+	Skipped,
+	Failed,
+	Interrupted(InterruptionKind),
+}
+
+impl Mover {
+	#[allow(clippy::too_many_arguments)]
+	#[must_use]
+	pub fn new(
+		source: LocationRoot,
+		target: LocationRoot,
+		entries: Vec<MoveEntry>,
+		conflict_policy: ConflictPolicy,
+		cas_id_algorithm: CasIdAlgorithm,
+		deep_hash_threshold: Option<u64>,
+		progress_tx: chan::Sender<(TaskId, CopyProgress)>,
+		db: Arc<PrismaClient>,
+		sync: Arc<SyncManager>,
+	) -> Self {
+		Self {
+			id: TaskId::new_v4(),
+			source,
+			target,
+			entries,
+			conflict_policy,
+			cas_id_algorithm,
+			deep_hash_threshold,
+			io_throttle: IoThrottle::default(),
+			progress_tx,
+			db,
+			sync,
+			output: Output::default(),
+		}
+	}
+
+	#[must_use]
+	pub fn with_io_throttle(mut self, io_throttle: IoThrottle) -> Self {
+		self.io_throttle = io_throttle;
+		self
+	}
+
+	fn reporter(&self) -> ProgressReporter {
+		ProgressReporter {
+			task_id: self.id,
+			progress_tx: self.progress_tx.clone(),
+		}
+	}
+
+	async fn move_entry(
+		&mut self,
+		interrupter: &Interrupter,
+	) -> Result<MoveOutcome, NonCriticalError> {
+		let reporter = self.reporter();
+
+		let Self {
+			source,
+			target,
+			entries,
+			conflict_policy,
+			cas_id_algorithm,
+			deep_hash_threshold,
+			io_throttle,
+			db,
+			sync,
+			output,
+			..
+		} = self;
+
+		let Some(entry) = entries.last_mut() else {
+			return Ok(MoveOutcome::Skipped);
+		};
+
+		if matches!(entry.stage, Stage::Pending) {
+			let failed = |e: FileIOError| {
+				NonCriticalError::FailedToMove(
+					entry.source.clone(),
+					entry.target.clone(),
+					e.to_string(),
+				)
+			};
+
+			let replacing = if exists(&entry.target).await.map_err(failed)? {
+				match (*conflict_policy, entry.is_dir) {
+					(ConflictPolicy::Skip, _) => {
+						trace!(
+							"Skipping move to existing <path='{}'>",
+							entry.target.display()
+						);
+						return Ok(MoveOutcome::Skipped);
+					}
+					// Moves never merge directories, they're renamed on overwrites too
+					(ConflictPolicy::Rename, _) | (ConflictPolicy::Overwrite, true) => {
+						let available = available_path(&entry.target).await.map_err(failed)?;
+						entry.target = available.ok_or_else(|| {
+							NonCriticalError::FailedToFindAvailableName(entry.target.clone())
+						})?;
+						false
+					}
+					(ConflictPolicy::Overwrite, false) => true,
+				}
+			} else {
+				false
+			};
+
+			let start = Instant::now();
+			let renamed = fs::rename(&entry.source, &entry.target).await;
+			output.move_time += start.elapsed();
+
+			entry.stage = match renamed {
+				Ok(()) => Stage::Moved { replacing },
+
+				Err(e) if crosses_devices(&e) => {
+					trace!(
+						"Moving <path='{}'> to another device, copying it instead",
+						entry.source.display()
+					);
+
+					Stage::CrossDevice {
+						files: files_to_copy(&entry.source, &entry.target, entry.is_dir)
+							.await
+							.map_err(|e| {
+								NonCriticalError::FailedToMove(
+									entry.source.clone(),
+									entry.target.clone(),
+									e.to_string(),
+								)
+							})?,
+						failed: 0,
+						replacing,
+					}
+				}
+
+				// Nothing moved, so file paths are left alone
+				Err(e) => {
+					return Err(NonCriticalError::FailedToMove(
+						entry.source.clone(),
+						entry.target.clone(),
+						FileIOError::from((&entry.source, e)).to_string(),
+					))
+				}
+			};
+		}
+
+		if let Stage::CrossDevice {
+			files,
+			failed,
+			replacing,
+		} = &mut entry.stage
+		{
+			// Copied files are popped, so a resumed move picks up from the file it was copying
+			while let Some((source_file, target_file)) = files.last().cloned() {
+				match copy_verified(
+					&source_file,
+					&target_file,
+					*cas_id_algorithm,
+					*deep_hash_threshold,
+					io_throttle,
+					interrupter,
+					&reporter,
+					(&mut output.copy_time, &mut output.verification_time),
+				)
+				.await
+				{
+					Ok(VerifiedCopy::Done) => {
+						output.copied_across_devices += 1;
+						if let Err(e) = fs::remove_file(&source_file).await {
+							output.errors.push(
+								file_mover::NonCriticalError::from(
+									NonCriticalError::FailedToRemoveSource(
+										source_file,
+										e.to_string(),
+									),
+								)
+								.into(),
+							);
+						} else if entry.is_dir {
+							// The file is only at the target now, its file path goes with it
+							let start = Instant::now();
+							let relinked = relink_moved_file(
+								(source, &source_file),
+								(target, &target_file),
+								db,
+								sync,
+							)
+							.await;
+							output.db_write_time += start.elapsed();
+
+							if let Err(e) = relinked {
+								output
+									.errors
+									.push(file_mover::NonCriticalError::from(e).into());
+							}
+						}
+					}
+					Ok(VerifiedCopy::Interrupted(kind)) => {
+						return Ok(MoveOutcome::Interrupted(kind))
+					}
+					Err(e) => {
+						*failed += 1;
+						output.errors.push(
+							file_mover::NonCriticalError::from(NonCriticalError::Copy(e)).into(),
+						);
+					}
+				}
+
+				files.pop();
+			}
+
+			if *failed > 0 {
+				// Files that failed stay in the source directory with their file paths, for the
+				// indexer to pick up the target directories created for them
+				return Ok(MoveOutcome::Failed);
+			}
+
+			if entry.is_dir {
+				fs::remove_dir_all(&entry.source).await.map_err(|e| {
+					NonCriticalError::FailedToRemoveSource(entry.source.clone(), e.to_string())
+				})?;
+			}
+
+			entry.stage = Stage::Moved {
+				replacing: *replacing,
+			};
+		}
+
+		let Stage::Moved { replacing } = entry.stage else {
+			unreachable!("the other stages returned or moved on to the moved stage");
+		};
+
+		// Directories moved across devices had their files relinked one by one already, only the
+		// directories are left, relinked with the entry
+		let start = Instant::now();
+		if replacing {
+			forget_replaced(target, &entry.target, db, sync).await?;
+		}
+		relink(
+			entry,
+			(source, &entry.source),
+			(target, &entry.target),
+			db,
+			sync,
+		)
+		.await?;
+		output.db_write_time += start.elapsed();
+
+		Ok(MoveOutcome::Moved)
+	}
+}
+
+#[async_trait::async_trait]
+impl Task<Error> for Mover {
+	fn id(&self) -> TaskId {
+		self.id
+	}
+
+	async fn run(&mut self, interrupter: &Interrupter) -> Result<ExecStatus, Error> {
+		// Moved entries are popped, so a resumed task picks up from the entry it was moving
+		while !self.entries.is_empty() {
+			match self.move_entry(interrupter).await {
+				Ok(MoveOutcome::Moved) => self.output.moved += 1,
+				Ok(MoveOutcome::Skipped) => self.output.skipped += 1,
+				Ok(MoveOutcome::Failed) => self.output.failed += 1,
+				Ok(MoveOutcome::Interrupted(InterruptionKind::Pause)) => {
+					return Ok(ExecStatus::Paused);
+				}
+				Ok(MoveOutcome::Interrupted(InterruptionKind::Cancel)) => {
+					return Ok(ExecStatus::Canceled);
+				}
+				Err(e) => {
+					warn!("{e}");
+					self.output.failed += 1;
+					self.output
+						.errors
+						.push(file_mover::NonCriticalError::from(e).into());
+				}
+			}
+
+			self.entries.pop();
+			self.reporter().report(CopyProgress::Finished).await;
+
+			check_interruption!(interrupter);
+		}
+
+		Ok(ExecStatus::Done(mem::take(&mut self.output).into_output()))
+	}
+}
+
+/// Files under `source` paired with where they go under `target`, creating the target
+/// directories along the way
+async fn files_to_copy(
+	source: &Path,
+	target: &Path,
+	is_dir: bool,
+) -> Result<Vec<(PathBuf, PathBuf)>, FileIOError> {
+	if !is_dir {
+		return Ok(vec![(source.to_path_buf(), target.to_path_buf())]);
+	}
+
+	let mut files = Vec::new();
+	let mut to_walk = vec![(source.to_path_buf(), target.to_path_buf())];
+
+	while let Some((source_dir, target_dir)) = to_walk.pop() {
+		fs::create_dir_all(&target_dir)
+			.await
+			.map_err(|e| FileIOError::from((&target_dir, e)))?;
+
+		let mut read_dir = fs::read_dir(&source_dir)
+			.await
+			.map_err(|e| FileIOError::from((&source_dir, e)))?;
+
+		while let Some(entry) = read_dir
+			.next_entry()
+			.await
+			.map_err(|e| FileIOError::from((&source_dir, e)))?
+		{
+			let paths = (entry.path(), target_dir.join(entry.file_name()));
+
+			if entry
+				.file_type()
+				.await
+				.map_err(|e| FileIOError::from((&paths.0, e)))?
+				.is_dir()
+			{
+				to_walk.push(paths);
+			} else {
+				files.push(paths);
+			}
+		}
+	}
+
+	Ok(files)
+}
+
+/// Points the file paths of the entry to where it's moved, keeping their ids so they stay linked
+/// to their objects
+async fn relink(
+	entry: &MoveEntry,
+	from: (&LocationRoot, &Path),
+	to: (&LocationRoot, &Path),
+	db: &PrismaClient,
+	sync: &SyncManager,
+) -> Result<(), NonCriticalError> {
+	let failed = |e: &dyn ToString| NonCriticalError::FailedToRelink(to.1.into(), e.to_string());
+
+	relink::relink(
+		Relinked {
+			file_path_id: entry.file_path_id,
+			pub_id: &entry.pub_id,
+			is_dir: entry.is_dir,
+		},
+		from,
+		to,
+		db,
+		sync,
+	)
+	.await
+	.map_err(|e| failed(&e))?
+	.write(db, sync)
+	.await
+	.map_err(|e| failed(&e))
+}
+
+/// Points the file path of a file moved with its directory to another device to where it was
+/// copied, if it was indexed
+async fn relink_moved_file(
+	(source, source_file): (&LocationRoot, &Path),
+	(target, target_file): (&LocationRoot, &Path),
+	db: &PrismaClient,
+	sync: &SyncManager,
+) -> Result<(), NonCriticalError> {
+	let failed =
+		|e: &dyn ToString| NonCriticalError::FailedToRelink(target_file.into(), e.to_string());
+
+	let iso_file_path = IsolatedFilePathData::new(source.id, &*source.path, source_file, false)
+		.map_err(|e| failed(&e))?;
+
+	let Some(file_path) = db
+		.file_path()
+		.find_unique(iso_file_path.into())
+		.select(file_path::select!({ id pub_id }))
+		.exec()
+		.await
+		.map_err(|e| failed(&e))?
+	else {
+		return Ok(());
+	};
+
+	relink::relink(
+		Relinked {
+			file_path_id: file_path.id,
+			pub_id: &file_path.pub_id,
+			is_dir: false,
+		},
+		(source, source_file),
+		(target, target_file),
+		db,
+		sync,
+	)
+	.await
+	.map_err(|e| failed(&e))?
+	.write(db, sync)
+	.await
+	.map_err(|e| failed(&e))
+}
+
+/// Removes the file path of a file overwritten by a move, as the moved one takes its place
+async fn forget_replaced(
+	target: &LocationRoot,
+	path: &Path,
+	db: &PrismaClient,
+	sync: &SyncManager,
+) -> Result<(), NonCriticalError> {
+	let failed = |e: &dyn ToString| NonCriticalError::FailedToRelink(path.into(), e.to_string());
+
+	let iso_file_path =
+		IsolatedFilePathData::new(target.id, &*target.path, path, false).map_err(|e| failed(&e))?;
+	let parts = iso_file_path.to_parts();
+
+	let replaced = db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(Some(target.id)),
+			file_path::materialized_path::equals(Some(parts.materialized_path.to_string())),
+			file_path::name::equals(Some(parts.name.to_string())),
+			file_path::extension::equals(Some(parts.extension.to_string())),
+		])
+		.select(file_path::select!({ id pub_id }))
+		.exec()
+		.await
+		.map_err(|e| failed(&e))?;
+
+	if replaced.is_empty() {
+		return Ok(());
+	}
+
+	let (sync_params, ids) = replaced
+		.into_iter()
+		.map(|file_path| {
+			(
+				sync.shared_delete(prisma_sync::file_path::SyncId {
+					pub_id: file_path.pub_id,
+				}),
+				file_path.id,
+			)
+		})
+		.unzip::<_, _, Vec<_>, Vec<_>>();
+
+	file_identifier::remove_archive_entries(ids.clone(), db, sync)
+		.await
+		.map_err(|e| failed(&e))?;
+
+	db._transaction()
+		.with_timeout(30 * 1000)
+		.run(|db| async move {
+			file_identifier::statistics::forget(vec![file_path::id::in_vec(ids.clone())], &db)
+				.await?;
+
+			sync.write_ops(
+				&db,
+				(
+					sync_params,
+					db.file_path().delete_many(vec![file_path::id::in_vec(ids)]),
+				),
+			)
+			.await
+		})
+		.await
+		.map_err(|e| failed(&e))?;
+
+	Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SaveState {
+	id: TaskId,
+	source: LocationRoot,
+	target: LocationRoot,
+	entries: Vec<MoveEntry>,
+	conflict_policy: ConflictPolicy,
+	cas_id_algorithm: CasIdAlgorithm,
+	deep_hash_threshold: Option<u64>,
+	#[serde(default)]
+	io_throttle: IoThrottle,
+	output: Output,
+}
+
+impl SerializableTask<Error> for Mover {
+	type SerializeError = rmp_serde::encode::Error;
+
+	type DeserializeError = rmp_serde::decode::Error;
+
+	type DeserializeCtx = (
+		chan::Sender<(TaskId, CopyProgress)>,
+		Arc<PrismaClient>,
+		Arc<SyncManager>,
+	);
+
+	async fn serialize(self) -> Result<Vec<u8>, Self::SerializeError> {
+		let Self {
+			id,
+			source,
+			target,
+			entries,
+			conflict_policy,
+			cas_id_algorithm,
+			deep_hash_threshold,
+			io_throttle,
+			output,
+			..
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState {
+			id,
+			source,
+			target,
+			entries,
+			conflict_policy,
+			cas_id_algorithm,
+			deep_hash_threshold,
+			io_throttle,
+			output,
+		})
+	}
+
+	async fn deserialize(
+		data: &[u8],
+		(progress_tx, db, sync): Self::DeserializeCtx,
+	) -> Result<Self, Self::DeserializeError> {
+		rmp_serde::from_slice(data).map(
+			|SaveState {
+			     id,
+			     source,
+			     target,
+			     entries,
+			     conflict_policy,
+			     cas_id_algorithm,
+			     deep_hash_threshold,
+			     io_throttle,
+			     output,
+			 }| Self {
+				id,
+				source,
+				target,
+				entries,
+				conflict_policy,
+				cas_id_algorithm,
+				deep_hash_threshold,
+				io_throttle,
+				progress_tx,
+				db,
+				sync,
+				output,
+			},
+		)
+	}
+}
@@ -0,0 +1,778 @@
+use crate::{
+	file_copier::{
+		self, available_path,
+		copier::{self, Copier, CopyEntry, CopyProgress},
+		ConflictPolicy,
+	},
+	file_identifier::CasIdAlgorithm,
+	folder_sync,
+	job_system::{
+		job::{Job, JobReturn, JobTaskDispatcher, ReturnStatus},
+		report::ReportOutputMetadata,
+		utils::{cancel_pending_tasks, location_concurrency_key},
+		SerializableJob, SerializedTasks,
+	},
+	utils::io_throttle::IoThrottle,
+	Error, JobName, NonCriticalError, OuterContext, ProgressUpdate,
+};
+
+use sd_prisma::prisma::{folder_sync as folder_sync_db, folder_sync_entry, location, PrismaClient};
+use sd_task_system::{
+	IntoTask, SerializableTask, Task, TaskDispatcher, TaskHandle, TaskId, TaskOutput, TaskStatus,
+	TaskSystemError,
+};
+use sd_utils::error::FileIOError;
+
+use std::{
+	collections::{HashMap, HashSet},
+	hash::{Hash, Hasher},
+	io, mem,
+	path::{Path, PathBuf},
+	time::Duration,
+};
+
+use async_channel as chan;
+use chrono::Utc;
+use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
+use futures_concurrency::future::{Race, TryJoin};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::{fs, time::Instant};
+use tracing::{debug, warn};
+
+use super::{check_locations, plan, BaseUpdate, Content, SyncAction};
+
+// How many files each copier task copies, one after the other
+const BATCH_SIZE: usize = 50;
+
+// Byte progress arrives every chunk of every task, the UI doesn't need it that often
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Brings two locations to the same contents, applying the changes made on each side since the
+/// last sync to the other. Paths both sides changed are conflicts: they are left alone and listed
+/// for the user to resolve, the next sync applying the resolution.
+#[derive(Debug)]
+pub struct FolderSync {
+	id: folder_sync_db::id::Type,
+	location_a_id: location::id::Type,
+	location_a_path: PathBuf,
+	location_b_id: location::id::Type,
+	location_b_path: PathBuf,
+	cas_id_algorithm: CasIdAlgorithm,
+	io_throttle: IoThrottle,
+
+	updates: Vec<BaseUpdate>,
+	failed: HashSet<PathBuf>,
+
+	metadata: Metadata,
+	progress_tx: chan::Sender<(TaskId, CopyProgress)>,
+	progress_rx: chan::Receiver<(TaskId, CopyProgress)>,
+
+	errors: Vec<NonCriticalError>,
+
+	pending_tasks_on_resume: Vec<TaskHandle<Error>>,
+	tasks_for_shutdown: Vec<Box<dyn Task<Error>>>,
+}
+
+impl Hash for FolderSync {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.id.hash(state);
+	}
+}
+
+impl Job for FolderSync {
+	const NAME: JobName = JobName::FolderSync;
+
+	async fn resume_tasks(
+		&mut self,
+		dispatcher: &JobTaskDispatcher,
+		ctx: &impl OuterContext,
+		SerializedTasks(serialized_tasks): SerializedTasks,
+	) -> Result<(), Error> {
+		let dispatcher =
+			dispatcher.with_concurrency_key(location_concurrency_key(ctx.id(), self.location_a_id));
+		let io_throttle = &dispatcher.io_throttle(&self.io_throttle);
+		let progress_tx = &self.progress_tx;
+
+		self.pending_tasks_on_resume = dispatcher
+			.dispatch_many_boxed(
+				rmp_serde::from_slice::<Vec<Vec<u8>>>(&serialized_tasks)
+					.map_err(folder_sync::Error::from)?
+					.into_iter()
+					.map(|task_bytes| async move {
+						Copier::deserialize(&task_bytes, progress_tx.clone())
+							.await
+							.map(|task| task.with_io_throttle(io_throttle.clone()))
+							.map(IntoTask::into_task)
+					})
+					.collect::<Vec<_>>()
+					.try_join()
+					.await
+					.map_err(folder_sync::Error::from)?,
+			)
+			.await;
+
+		Ok(())
+	}
+
+	async fn run<Ctx: OuterContext>(
+		mut self,
+		dispatcher: JobTaskDispatcher,
+		ctx: Ctx,
+	) -> Result<ReturnStatus, Error> {
+		enum RaceOutput {
+			Task(Option<Result<TaskStatus<Error>, TaskSystemError>>),
+			Progress(Option<(TaskId, CopyProgress)>),
+		}
+
+		let dispatcher =
+			dispatcher.with_concurrency_key(location_concurrency_key(ctx.id(), self.location_a_id));
+
+		let mut pending_running_tasks = FuturesUnordered::new();
+
+		self.init_or_resume(&mut pending_running_tasks, &ctx, &dispatcher)
+			.await?;
+
+		let mut last_progress_update = Instant::now();
+
+		while !pending_running_tasks.is_empty() {
+			let output = (
+				pending_running_tasks.next().map(RaceOutput::Task),
+				self.progress_rx
+					.recv()
+					.map(|res| RaceOutput::Progress(res.ok())),
+			)
+				.race()
+				.await;
+
+			let task = match output {
+				RaceOutput::Progress(Some((_, progress))) => {
+					self.process_progress(progress);
+
+					if last_progress_update.elapsed() > PROGRESS_INTERVAL {
+						last_progress_update = Instant::now();
+						self.report_progress(&ctx);
+					}
+
+					continue;
+				}
+				// The job holds a sender, so the channel is never closed
+				RaceOutput::Progress(None) | RaceOutput::Task(None) => continue,
+				RaceOutput::Task(Some(task)) => task,
+			};
+
+			match task {
+				Ok(TaskStatus::Done((_, TaskOutput::Out(out)))) => {
+					self.process_copier_output(
+						*out.downcast::<copier::Output>()
+							.expect("the folder sync job only dispatches copier tasks"),
+					);
+					self.report_progress(&ctx);
+				}
+
+				Ok(TaskStatus::Done((task_id, TaskOutput::Empty))) => {
+					warn!("Task <id='{task_id}'> returned an empty output");
+				}
+
+				Ok(TaskStatus::Shutdown(task)) => {
+					self.tasks_for_shutdown.push(task);
+				}
+
+				Ok(TaskStatus::Error(e)) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e);
+				}
+
+				Ok(TaskStatus::Canceled | TaskStatus::ForcedAbortion) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Ok(ReturnStatus::Canceled);
+				}
+
+				Err(e) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e.into());
+				}
+			}
+		}
+
+		while let Ok((_, progress)) = self.progress_rx.try_recv() {
+			self.process_progress(progress);
+		}
+
+		if !self.tasks_for_shutdown.is_empty() {
+			return Ok(ReturnStatus::Shutdown(
+				SerializableJob::<Ctx>::serialize(self).await,
+			));
+		}
+
+		self.save_base(ctx.db()).await?;
+
+		ctx.invalidate_query("search.paths");
+		ctx.invalidate_query("locations.folderSync.list");
+
+		let Self {
+			metadata, errors, ..
+		} = self;
+
+		Ok(ReturnStatus::Completed(
+			JobReturn::builder()
+				.with_metadata(metadata)
+				.with_non_critical_errors(errors)
+				.build(),
+		))
+	}
+}
+
+impl FolderSync {
+	/// Syncs the folder sync `id` between `location_a` and `location_b`, see
+	/// [`folder_sync::locations`]. `cas_id_algorithm` must be the one the library's `cas_id`s were
+	/// generated with.
+	pub fn new(
+		id: folder_sync_db::id::Type,
+		location_a: &location::Data,
+		location_b: &location::Data,
+		cas_id_algorithm: CasIdAlgorithm,
+	) -> Result<Self, folder_sync::Error> {
+		let (location_a_path, location_b_path) = check_locations(location_a, location_b)?;
+		let (progress_tx, progress_rx) = chan::unbounded();
+
+		Ok(Self {
+			id,
+			location_a_id: location_a.id,
+			location_a_path,
+			location_b_id: location_b.id,
+			location_b_path,
+			cas_id_algorithm,
+			io_throttle: IoThrottle::default(),
+			updates: Vec::new(),
+			failed: HashSet::new(),
+			metadata: Metadata::default(),
+			progress_tx,
+			progress_rx,
+			errors: Vec::new(),
+			pending_tasks_on_resume: Vec::new(),
+			tasks_for_shutdown: Vec::new(),
+		})
+	}
+
+	/// Caps how fast files are read, shared by every copier task of the job
+	#[must_use]
+	pub fn with_io_throttle(mut self, io_throttle: IoThrottle) -> Self {
+		self.io_throttle = io_throttle;
+		self
+	}
+
+	#[allow(clippy::too_many_lines)]
+	async fn init_or_resume(
+		&mut self,
+		pending_running_tasks: &mut FuturesUnordered<TaskHandle<Error>>,
+		ctx: &impl OuterContext,
+		dispatcher: &JobTaskDispatcher,
+	) -> Result<(), folder_sync::Error> {
+		// Resumed tasks were already dispatched by `resume_tasks`
+		if !self.pending_tasks_on_resume.is_empty() {
+			pending_running_tasks.extend(mem::take(&mut self.pending_tasks_on_resume));
+
+			return Ok(());
+		}
+
+		let deep_hash_threshold = ctx.deep_hash_threshold().await;
+
+		ctx.progress(vec![ProgressUpdate::Message(
+			"Comparing both locations".to_string(),
+		)]);
+
+		let io_throttle = dispatcher.io_throttle(&self.io_throttle);
+
+		let mut plan = plan(
+			ctx.db(),
+			self.id,
+			(self.location_a_id, &self.location_a_path),
+			(self.location_b_id, &self.location_b_path),
+			self.cas_id_algorithm,
+			deep_hash_threshold,
+			&io_throttle,
+		)
+		.await?;
+
+		self.errors.extend(
+			mem::take(&mut plan.a.errors)
+				.into_iter()
+				.chain(mem::take(&mut plan.b.errors))
+				.map(Into::into),
+		);
+		self.updates = mem::take(&mut plan.updates);
+
+		let (path_a, path_b) = (&self.location_a_path, &self.location_b_path);
+
+		// Deepest first, so directories are deleted after their contents
+		for (path, action) in plan.actions.iter().rev() {
+			let root = match action {
+				SyncAction::DeleteFromA => path_a,
+				SyncAction::DeleteFromB => path_b,
+				SyncAction::Conflict => {
+					self.metadata.conflicts += 1;
+					continue;
+				}
+				_ => continue,
+			};
+
+			match delete(&root.join(path)).await {
+				Ok(()) => self.metadata.deleted_files += 1,
+				Err(e) => {
+					self.failed.insert(path.clone());
+					self.errors.push(
+						folder_sync::NonCriticalError::FailedToDelete(
+							root.join(path),
+							e.to_string(),
+						)
+						.into(),
+					);
+				}
+			}
+		}
+
+		let mut copies = Vec::new();
+
+		for (path, action) in &plan.actions {
+			let (from, to, content, existing) = match action {
+				SyncAction::CopyToA => (
+					path_b,
+					path_a,
+					plan.b.contents.get(path),
+					plan.a.contents.get(path),
+				),
+				SyncAction::CopyToB => (
+					path_a,
+					path_b,
+					plan.a.contents.get(path),
+					plan.b.contents.get(path),
+				),
+				SyncAction::KeepBoth => {
+					// B's version goes next to A's under a new name on both sides, then A's
+					// version replaces it in B like any other copy
+					match keep_both(path_b, path).await {
+						Ok(renamed) => {
+							copies.push(CopyEntry {
+								source: path_b.join(&renamed),
+								target: path_a.join(&renamed),
+								resolved: false,
+							});
+							self.metadata.total_bytes +=
+								plan.b.sizes.get(path).copied().unwrap_or_default();
+							self.updates.push(BaseUpdate {
+								path: renamed,
+								content: plan.b.contents.get(path).cloned(),
+								conflict: false,
+							});
+						}
+						Err(e) => {
+							self.failed.insert(path.clone());
+							self.errors.push(
+								folder_sync::NonCriticalError::FailedToKeepBoth(
+									path_b.join(path),
+									e.to_string(),
+								)
+								.into(),
+							);
+
+							continue;
+						}
+					}
+
+					(
+						path_a,
+						path_b,
+						plan.a.contents.get(path),
+						// Renamed away
+						None,
+					)
+				}
+				_ => continue,
+			};
+
+			let target = to.join(path);
+
+			// A file replacing a directory or the other way around
+			let replaced = match (content, existing) {
+				(Some(Content::Dir), Some(Content::File { .. }))
+				| (Some(Content::File { .. }), Some(Content::Dir)) => delete(&target).await,
+				_ => Ok(()),
+			};
+
+			if let Err(e) = replaced {
+				self.failed.insert(path.clone());
+				self.errors.push(
+					folder_sync::NonCriticalError::FailedToDelete(target, e.to_string()).into(),
+				);
+
+				continue;
+			}
+
+			match content {
+				Some(Content::Dir) => fs::create_dir_all(&target).await.map_err(|e| {
+					FileIOError::from((&target, e, "Failed to create synced directory"))
+				})?,
+				Some(Content::File { .. }) => {
+					let sizes = if from == path_a {
+						&plan.a.sizes
+					} else {
+						&plan.b.sizes
+					};
+					self.metadata.total_bytes += sizes.get(path).copied().unwrap_or_default();
+
+					copies.push(CopyEntry {
+						source: from.join(path),
+						target,
+						resolved: false,
+					});
+				}
+				None => {}
+			}
+		}
+
+		self.metadata.total_files = copies.len() as u64;
+
+		debug!(
+			"Syncing locations {} and {}: {} files to copy, {} bytes, {} conflicts",
+			self.location_a_id,
+			self.location_b_id,
+			self.metadata.total_files,
+			self.metadata.total_bytes,
+			self.metadata.conflicts
+		);
+
+		pending_running_tasks.extend(
+			dispatcher
+				.dispatch_many(
+					copies
+						.chunks(BATCH_SIZE)
+						.map(|chunk| {
+							Copier::new(
+								// Reversed as tasks pop their entries
+								chunk.iter().rev().cloned().collect(),
+								// The version being replaced is the one the sync decided against
+								ConflictPolicy::Overwrite,
+								self.cas_id_algorithm,
+								deep_hash_threshold,
+								self.progress_tx.clone(),
+							)
+							.with_io_throttle(io_throttle.clone())
+						})
+						.collect::<Vec<_>>(),
+				)
+				.await,
+		);
+
+		self.report_progress(ctx);
+
+		Ok(())
+	}
+
+	fn process_progress(&mut self, progress: CopyProgress) {
+		match progress {
+			CopyProgress::Started { .. } => {}
+			CopyProgress::Copied(bytes) => self.metadata.copied_bytes += bytes,
+			CopyProgress::Finished => self.metadata.completed_files += 1,
+		}
+	}
+
+	fn report_progress(&self, ctx: &impl OuterContext) {
+		ctx.progress(vec![
+			ProgressUpdate::TaskCount(self.metadata.total_files),
+			ProgressUpdate::CompletedTaskCount(self.metadata.completed_files),
+			ProgressUpdate::Message(format!(
+				"Synced {} of {} bytes",
+				self.metadata.copied_bytes, self.metadata.total_bytes
+			)),
+		]);
+	}
+
+	fn process_copier_output(
+		&mut self,
+		copier::Output {
+			copied_files,
+			skipped_files: _,
+			failed_files,
+			copy_time,
+			verification_time,
+			errors,
+		}: copier::Output,
+	) {
+		self.metadata.copied_files += copied_files;
+		self.metadata.failed_files += failed_files;
+		self.metadata.copy_time += copy_time;
+		self.metadata.verification_time += verification_time;
+
+		// Failed copies keep what the last sync recorded, so the next one tries again
+		self.failed.extend(errors.iter().filter_map(|e| {
+			let NonCriticalError::FileCopier(file_copier::NonCriticalError::Copier(
+				copier::NonCriticalError::FailedToCopy(_, target, _)
+				| copier::NonCriticalError::VerificationFailed(target, ..),
+			)) = e
+			else {
+				return None;
+			};
+
+			target
+				.strip_prefix(&self.location_a_path)
+				.or_else(|_| target.strip_prefix(&self.location_b_path))
+				.ok()
+				.map(Path::to_path_buf)
+		}));
+
+		self.errors.extend(errors);
+	}
+
+	/// Records what both sides hold now, for the next sync to tell which side changed what
+	async fn save_base(&mut self, db: &PrismaClient) -> Result<(), folder_sync::Error> {
+		let updates = mem::take(&mut self.updates)
+			.into_iter()
+			.filter(|update| !self.failed.contains(&update.path))
+			.collect::<Vec<_>>();
+
+		let (upserts, deletions) = updates
+			.into_iter()
+			.partition::<Vec<_>, _>(|update| update.conflict || update.content.is_some());
+
+		db._batch(
+			upserts
+				.into_iter()
+				.map(
+					|BaseUpdate {
+					     path,
+					     content,
+					     conflict,
+					 }| {
+						let path = path.to_string_lossy().to_string();
+						let params = vec![
+							folder_sync_entry::is_dir::set(content == Some(Content::Dir)),
+							folder_sync_entry::cas_id::set(match content {
+								Some(Content::File { cas_id }) => Some(cas_id),
+								_ => None,
+							}),
+							folder_sync_entry::conflict::set(conflict),
+							folder_sync_entry::resolution::set(None),
+						];
+
+						db.folder_sync_entry().upsert(
+							folder_sync_entry::folder_sync_id_path(self.id, path.clone()),
+							folder_sync_entry::create(
+								folder_sync_db::id::equals(self.id),
+								path,
+								params.clone(),
+							),
+							params,
+						)
+					},
+				)
+				.collect::<Vec<_>>(),
+		)
+		.await?;
+
+		db.folder_sync_entry()
+			.delete_many(vec![
+				folder_sync_entry::folder_sync_id::equals(self.id),
+				folder_sync_entry::path::in_vec(
+					deletions
+						.into_iter()
+						.map(|update| update.path.to_string_lossy().to_string())
+						.collect(),
+				),
+			])
+			.exec()
+			.await?;
+
+		db.folder_sync()
+			.update(
+				folder_sync_db::id::equals(self.id),
+				vec![folder_sync_db::date_last_synced::set(Some(
+					Utc::now().into(),
+				))],
+			)
+			.exec()
+			.await?;
+
+		Ok(())
+	}
+}
+
+async fn delete(path: &Path) -> Result<(), FileIOError> {
+	let metadata = match fs::symlink_metadata(path).await {
+		Ok(metadata) => metadata,
+		// Already gone, which is all we wanted
+		Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+		Err(e) => return Err(FileIOError::from((path, e))),
+	};
+
+	if metadata.is_dir() {
+		fs::remove_dir_all(path).await
+	} else {
+		fs::remove_file(path).await
+	}
+	.map_err(|e| FileIOError::from((path, e, "Failed to delete synced deletion")))
+}
+
+/// Moves the file at `path` of `root` to an available name next to it, returning the new path
+async fn keep_both(root: &Path, path: &Path) -> Result<PathBuf, FileIOError> {
+	let source = root.join(path);
+
+	let Some(renamed) = available_path(&source).await? else {
+		return Err(FileIOError::from((
+			&source,
+			io::Error::new(io::ErrorKind::AlreadyExists, "no available name"),
+		)));
+	};
+
+	fs::rename(&source, &renamed)
+		.await
+		.map_err(|e| FileIOError::from((&source, e, "Failed to rename conflicting file")))?;
+
+	Ok(renamed
+		.strip_prefix(root)
+		.map_or_else(|_| renamed.clone(), Path::to_path_buf))
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Metadata {
+	total_files: u64,
+	total_bytes: u64,
+	completed_files: u64,
+	copied_files: u64,
+	deleted_files: u64,
+	failed_files: u64,
+	conflicts: u64,
+	copied_bytes: u64,
+	copy_time: Duration,
+	verification_time: Duration,
+}
+
+impl From<Metadata> for ReportOutputMetadata {
+	fn from(value: Metadata) -> Self {
+		Self::Metrics(HashMap::from([
+			("total_files".into(), json!(value.total_files)),
+			("total_bytes".into(), json!(value.total_bytes)),
+			("copied_files".into(), json!(value.copied_files)),
+			("deleted_files".into(), json!(value.deleted_files)),
+			("failed_files".into(), json!(value.failed_files)),
+			("conflicts".into(), json!(value.conflicts)),
+			("copied_bytes".into(), json!(value.copied_bytes)),
+			("copy_time".into(), json!(value.copy_time)),
+			("verification_time".into(), json!(value.verification_time)),
+		]))
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+struct SaveState {
+	id: folder_sync_db::id::Type,
+	location_a_id: location::id::Type,
+	location_a_path: PathBuf,
+	location_b_id: location::id::Type,
+	location_b_path: PathBuf,
+	cas_id_algorithm: CasIdAlgorithm,
+	io_throttle: IoThrottle,
+
+	updates: Vec<BaseUpdate>,
+	failed: HashSet<PathBuf>,
+
+	metadata: Metadata,
+
+	errors: Vec<NonCriticalError>,
+
+	tasks_for_shutdown_bytes: Option<SerializedTasks>,
+}
+
+impl<Ctx: OuterContext> SerializableJob<Ctx> for FolderSync {
+	async fn serialize(self) -> Result<Option<Vec<u8>>, rmp_serde::encode::Error> {
+		let Self {
+			id,
+			location_a_id,
+			location_a_path,
+			location_b_id,
+			location_b_path,
+			cas_id_algorithm,
+			io_throttle,
+			updates,
+			failed,
+			metadata,
+			errors,
+			tasks_for_shutdown,
+			..
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState {
+			id,
+			location_a_id,
+			location_a_path,
+			location_b_id,
+			location_b_path,
+			cas_id_algorithm,
+			io_throttle,
+			updates,
+			failed,
+			metadata,
+			errors,
+			tasks_for_shutdown_bytes: Some(SerializedTasks(rmp_serde::to_vec_named(
+				&tasks_for_shutdown
+					.into_iter()
+					.map(|task| async move {
+						task.downcast::<Copier>()
+							.expect("the folder sync job only dispatches copier tasks")
+							.serialize()
+							.await
+					})
+					.collect::<Vec<_>>()
+					.try_join()
+					.await?,
+			)?)),
+		})
+		.map(Some)
+	}
+
+	async fn deserialize(
+		serialized_job: &[u8],
+		_: &Ctx,
+	) -> Result<Option<(Self, Option<SerializedTasks>)>, rmp_serde::decode::Error> {
+		let SaveState {
+			id,
+			location_a_id,
+			location_a_path,
+			location_b_id,
+			location_b_path,
+			cas_id_algorithm,
+			io_throttle,
+			updates,
+			failed,
+			metadata,
+			errors,
+			tasks_for_shutdown_bytes,
+		} = rmp_serde::from_slice::<SaveState>(serialized_job)?;
+
+		let (progress_tx, progress_rx) = chan::unbounded();
+
+		Ok(Some((
+			Self {
+				id,
+				location_a_id,
+				location_a_path,
+				location_b_id,
+				location_b_path,
+				cas_id_algorithm,
+				io_throttle,
+				updates,
+				failed,
+				metadata,
+				progress_tx,
+				progress_rx,
+				errors,
+				pending_tasks_on_resume: Vec::new(),
+				tasks_for_shutdown: Vec::new(),
+			},
+			tasks_for_shutdown_bytes,
+		)))
+	}
+}
@@ -0,0 +1,888 @@
+use crate::{
+	backup::is_ignored,
+	file_identifier::{generate_cas_id, not_in_archive, CasIdAlgorithm},
+	utils::io_throttle::IoThrottle,
+};
+
+use sd_core_file_path_helper::{FilePathError, IsolatedFilePathData};
+use sd_core_prisma_helpers::file_path_for_folder_sync;
+
+use sd_prisma::prisma::{file_path, folder_sync, folder_sync_entry, location, PrismaClient};
+use sd_utils::{
+	db::{maybe_missing, size_in_bytes_from_db, MissingFieldError},
+	error::FileIOError,
+};
+
+use std::{
+	collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+	path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use prisma_client_rust::QueryError;
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::fs;
+
+pub mod job;
+
+pub use job::FolderSync;
+
+/// Modification dates of the index are compared with the files' within this, as some file systems
+/// only keep them to the second
+pub(crate) const MODIFIED_TOLERANCE_MS: i64 = 1000;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("folder sync not found: <id='{0}'>")]
+	NotFound(folder_sync::id::Type),
+	#[error("can't sync a location with itself: <id='{0}'>")]
+	SameLocation(location::id::Type),
+	#[error("locations are nested, syncing them would copy them into themselves: <a='{}', b='{}'>", .0.display(), .1.display())]
+	NestedLocations(PathBuf, PathBuf),
+	#[error("no conflict to resolve at <path='{}'>", .0.display())]
+	NoConflict(PathBuf),
+	#[error("missing field on database: {0}")]
+	MissingField(#[from] MissingFieldError),
+	#[error("database error: {0}")]
+	Database(#[from] QueryError),
+	#[error("failed to deserialized stored tasks for job resume: {0}")]
+	DeserializeTasks(#[from] rmp_serde::decode::Error),
+
+	#[error(transparent)]
+	FilePathError(#[from] FilePathError),
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+}
+
+impl From<Error> for rspc::Error {
+	fn from(err: Error) -> Self {
+		match err {
+			Error::NotFound(_) => Self::with_cause(ErrorCode::NotFound, err.to_string(), err),
+
+			Error::SameLocation(_) | Error::NestedLocations(..) | Error::NoConflict(_) => {
+				Self::with_cause(ErrorCode::BadRequest, err.to_string(), err)
+			}
+
+			_ => Self::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize, Type)]
+pub enum NonCriticalError {
+	#[error("failed to hash file, leaving it out of this sync: <path='{}'>: {1}", .0.display())]
+	FailedToHash(PathBuf, String),
+	#[error("failed to delete synced deletion: <path='{}'>: {1}", .0.display())]
+	FailedToDelete(PathBuf, String),
+	#[error("failed to keep both versions of a conflict: <path='{}'>: {1}", .0.display())]
+	FailedToKeepBoth(PathBuf, String),
+}
+
+/// What a path holds on one side
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Content {
+	Dir,
+	File { cas_id: String },
+}
+
+/// How the user settled a path both sides changed, applied by the next sync
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum ConflictResolution {
+	/// Location A's version replaces B's, deletions included
+	KeepA = 0,
+	/// Location B's version replaces A's, deletions included
+	KeepB = 1,
+	/// B's version is renamed with a ` (n)` suffix and synced as a new file, then A's version is
+	/// synced. Like [`ConflictResolution::KeepA`] unless both sides have a file.
+	KeepBoth = 2,
+}
+
+impl ConflictResolution {
+	const fn from_db(value: i32) -> Option<Self> {
+		match value {
+			0 => Some(Self::KeepA),
+			1 => Some(Self::KeepB),
+			2 => Some(Self::KeepBoth),
+			_ => None,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum SyncAction {
+	CopyToA,
+	CopyToB,
+	DeleteFromA,
+	DeleteFromB,
+	/// Both sides made the same change, only the record of the last sync is updated
+	Converged,
+	/// Both sides changed the path differently, it's left alone until the user resolves it
+	Conflict,
+	/// See [`ConflictResolution::KeepBoth`]
+	KeepBoth,
+}
+
+impl SyncAction {
+	/// What the path holds on both sides once the action is done
+	pub(crate) fn synced(self, a: Option<&Content>, b: Option<&Content>) -> Option<Content> {
+		match self {
+			Self::CopyToB | Self::DeleteFromB | Self::Converged | Self::KeepBoth => a.cloned(),
+			Self::CopyToA | Self::DeleteFromA => b.cloned(),
+			Self::Conflict => None,
+		}
+	}
+}
+
+const fn toward_a(b: Option<&Content>) -> SyncAction {
+	if b.is_some() {
+		SyncAction::CopyToA
+	} else {
+		SyncAction::DeleteFromA
+	}
+}
+
+const fn toward_b(a: Option<&Content>) -> SyncAction {
+	if a.is_some() {
+		SyncAction::CopyToB
+	} else {
+		SyncAction::DeleteFromB
+	}
+}
+
+/// What to do with each path given what both sides held after the last sync, `base`, and what
+/// they hold now. A path only one side changed goes to the other side, one both sides changed the
+/// same way is just recorded, and one both sides changed differently is a conflict unless the user
+/// resolved it.
+pub(crate) fn reconcile(
+	base: &HashMap<PathBuf, Content>,
+	a: &HashMap<PathBuf, Content>,
+	b: &HashMap<PathBuf, Content>,
+	resolutions: &HashMap<PathBuf, ConflictResolution>,
+) -> BTreeMap<PathBuf, SyncAction> {
+	let mut actions = base
+		.keys()
+		.chain(a.keys())
+		.chain(b.keys())
+		.collect::<BTreeSet<_>>()
+		.into_iter()
+		.filter_map(|path| {
+			let (base, a, b) = (base.get(path), a.get(path), b.get(path));
+
+			let action = if a == b {
+				if a == base {
+					return None;
+				}
+				SyncAction::Converged
+			} else if let Some(resolution) = resolutions.get(path) {
+				match resolution {
+					ConflictResolution::KeepBoth
+						if matches!(
+							(a, b),
+							(Some(Content::File { .. }), Some(Content::File { .. }))
+						) =>
+					{
+						SyncAction::KeepBoth
+					}
+					ConflictResolution::KeepA | ConflictResolution::KeepBoth => toward_b(a),
+					ConflictResolution::KeepB => toward_a(b),
+				}
+			} else if a == base {
+				toward_a(b)
+			} else if b == base {
+				toward_b(a)
+			} else {
+				SyncAction::Conflict
+			};
+
+			Some((path.clone(), action))
+		})
+		.collect::<BTreeMap<_, _>>();
+
+	// Directories about to be removed from one side, deleted or replaced by a file, but still
+	// holding content to keep there would take that content with them. Deleted ones are recreated
+	// instead, and replaced ones are conflicts unless the user chose which version to keep.
+	let kept_dirs = actions
+		.iter()
+		.filter_map(|(dir, action)| {
+			let (a, b) = (a.get(dir), b.get(dir));
+			let (on_a, replaced) = match action {
+				SyncAction::DeleteFromA if a == Some(&Content::Dir) => (true, false),
+				SyncAction::DeleteFromB if b == Some(&Content::Dir) => (false, false),
+				SyncAction::CopyToA if a == Some(&Content::Dir) => (true, true),
+				SyncAction::CopyToB if b == Some(&Content::Dir) => (false, true),
+				_ => return None,
+			};
+
+			let keeps = |other: &SyncAction| {
+				if on_a {
+					!matches!(
+						other,
+						SyncAction::DeleteFromA | SyncAction::CopyToA | SyncAction::Converged
+					)
+				} else {
+					!matches!(
+						other,
+						SyncAction::DeleteFromB | SyncAction::CopyToB | SyncAction::Converged
+					)
+				}
+			};
+
+			let kept = actions
+				.iter()
+				.any(|(path, other)| path != dir && path.starts_with(dir) && keeps(other));
+
+			match (kept, replaced) {
+				(false, _) => None,
+				(true, true) if resolutions.contains_key(dir) => None,
+				(true, true) => Some((dir.clone(), SyncAction::Conflict)),
+				(true, false) if on_a => Some((dir.clone(), SyncAction::CopyToB)),
+				(true, false) => Some((dir.clone(), SyncAction::CopyToA)),
+			}
+		})
+		.collect::<Vec<_>>();
+
+	actions.extend(kept_dirs);
+
+	// Whatever is inside a conflicting directory waits for the conflict to be resolved
+	let conflicting_dirs = actions
+		.iter()
+		.filter(|(path, action)| {
+			**action == SyncAction::Conflict
+				&& (a.get(*path) == Some(&Content::Dir) || b.get(*path) == Some(&Content::Dir))
+		})
+		.map(|(path, _)| path.clone())
+		.collect::<Vec<_>>();
+
+	actions.retain(|path, _| {
+		!conflicting_dirs
+			.iter()
+			.any(|dir| path != dir && path.starts_with(dir))
+	});
+
+	actions
+}
+
+/// One side of a sync, as it is now
+#[derive(Debug, Default)]
+pub(crate) struct Scan {
+	pub(crate) contents: HashMap<PathBuf, Content>,
+	pub(crate) sizes: HashMap<PathBuf, u64>,
+	pub(crate) unreadable: Vec<PathBuf>,
+	pub(crate) errors: Vec<NonCriticalError>,
+}
+
+/// Walks `root`, taking the `cas_id`s of the index for files that didn't change since they were
+/// indexed and hashing the others, so files the index doesn't know about yet aren't mistaken for
+/// deletions
+pub(crate) async fn scan(
+	db: &PrismaClient,
+	location_id: location::id::Type,
+	root: &Path,
+	cas_id_algorithm: CasIdAlgorithm,
+	deep_hash_threshold: Option<u64>,
+	io_throttle: &IoThrottle,
+) -> Result<Scan, Error> {
+	let indexed = db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(Some(location_id)),
+			file_path::is_dir::equals(Some(false)),
+			file_path::cas_id::not(None),
+			not_in_archive(),
+		])
+		.select(file_path_for_folder_sync::select())
+		.exec()
+		.await?
+		.into_iter()
+		.map(|file_path| {
+			let relative =
+				PathBuf::from(IsolatedFilePathData::try_from((location_id, &file_path))?.as_ref());
+
+			Ok((relative, file_path))
+		})
+		.collect::<Result<HashMap<_, _>, Error>>()?;
+
+	let mut scan = Scan::default();
+	let mut pending = vec![PathBuf::new()];
+
+	while let Some(relative_dir) = pending.pop() {
+		let dir = root.join(&relative_dir);
+		let mut read_dir = fs::read_dir(&dir)
+			.await
+			.map_err(|e| FileIOError::from((&dir, e, "Failed to read synced directory")))?;
+
+		while let Some(entry) = read_dir
+			.next_entry()
+			.await
+			.map_err(|e| FileIOError::from((&dir, e, "Failed to read synced directory entry")))?
+		{
+			let name = entry.file_name();
+			if is_ignored(&name) {
+				continue;
+			}
+
+			let relative = relative_dir.join(&name);
+			let metadata = entry
+				.metadata()
+				.await
+				.map_err(|e| FileIOError::from((entry.path(), e, "Failed to read metadata")))?;
+
+			if metadata.is_dir() {
+				scan.contents.insert(relative.clone(), Content::Dir);
+				pending.push(relative);
+				continue;
+			}
+
+			let size = metadata.len();
+			let modified = metadata.modified().ok().map(DateTime::<Utc>::from);
+
+			let indexed_cas_id = indexed
+				.get(&relative)
+				.filter(|file_path| {
+					let same_size = file_path
+						.size_in_bytes_bytes
+						.as_deref()
+						.map(size_in_bytes_from_db)
+						== Some(size);
+
+					let same_date = file_path.date_modified.zip(modified).map_or(
+						false,
+						|(indexed, modified)| {
+							(indexed.with_timezone(&Utc) - modified)
+								.num_milliseconds()
+								.abs() < MODIFIED_TOLERANCE_MS
+						},
+					);
+
+					same_size && same_date
+				})
+				.and_then(|file_path| file_path.cas_id.clone());
+
+			let cas_id = match indexed_cas_id {
+				Some(cas_id) => cas_id,
+				None => {
+					match generate_cas_id(
+						entry.path(),
+						size,
+						cas_id_algorithm.for_file_size(size, deep_hash_threshold),
+						io_throttle,
+					)
+					.await
+					{
+						Ok(cas_id) => cas_id,
+						Err(e) => {
+							scan.errors
+								.push(NonCriticalError::FailedToHash(entry.path(), e.to_string()));
+							scan.unreadable.push(relative);
+							continue;
+						}
+					}
+				}
+			};
+
+			scan.contents
+				.insert(relative.clone(), Content::File { cas_id });
+			scan.sizes.insert(relative, size);
+		}
+	}
+
+	Ok(scan)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct FolderSyncPair {
+	pub id: folder_sync::id::Type,
+	pub location_a_id: location::id::Type,
+	pub location_b_id: location::id::Type,
+	pub date_last_synced: Option<DateTime<Utc>>,
+	/// Paths waiting for the user to resolve them
+	pub conflicts: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct FolderSyncConflict {
+	/// Relative to the roots of the locations
+	pub path: PathBuf,
+	/// Set once the user resolved it, until the next sync applies it
+	pub resolution: Option<ConflictResolution>,
+}
+
+/// Pairs two locations, both paths being checked so a sync never copies a location into itself
+pub async fn create(
+	db: &PrismaClient,
+	location_a: &location::Data,
+	location_b: &location::Data,
+) -> Result<FolderSyncPair, Error> {
+	check_locations(location_a, location_b)?;
+
+	let created = db
+		.folder_sync()
+		.create(
+			location::id::equals(location_a.id),
+			location::id::equals(location_b.id),
+			vec![folder_sync::date_created::set(Some(Utc::now().into()))],
+		)
+		.exec()
+		.await?;
+
+	Ok(FolderSyncPair {
+		id: created.id,
+		location_a_id: location_a.id,
+		location_b_id: location_b.id,
+		date_last_synced: None,
+		conflicts: 0,
+	})
+}
+
+pub(crate) fn check_locations(
+	location_a: &location::Data,
+	location_b: &location::Data,
+) -> Result<(PathBuf, PathBuf), Error> {
+	if location_a.id == location_b.id {
+		return Err(Error::SameLocation(location_a.id));
+	}
+
+	let path_a = maybe_missing(&location_a.path, "location.path").map(PathBuf::from)?;
+	let path_b = maybe_missing(&location_b.path, "location.path").map(PathBuf::from)?;
+
+	if path_a.starts_with(&path_b) || path_b.starts_with(&path_a) {
+		return Err(Error::NestedLocations(path_a, path_b));
+	}
+
+	Ok((path_a, path_b))
+}
+
+pub async fn list(db: &PrismaClient) -> Result<Vec<FolderSyncPair>, Error> {
+	let pairs = db.folder_sync().find_many(vec![]).exec().await?;
+
+	let mut listed = Vec::with_capacity(pairs.len());
+
+	for pair in pairs {
+		#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+		// SAFETY: a count is never negative and there aren't billions of conflicts
+		let conflicts = db
+			.folder_sync_entry()
+			.count(vec![
+				folder_sync_entry::folder_sync_id::equals(pair.id),
+				folder_sync_entry::conflict::equals(true),
+			])
+			.exec()
+			.await? as u32;
+
+		listed.push(FolderSyncPair {
+			id: pair.id,
+			location_a_id: pair.location_a_id,
+			location_b_id: pair.location_b_id,
+			date_last_synced: pair.date_last_synced.map(Into::into),
+			conflicts,
+		});
+	}
+
+	Ok(listed)
+}
+
+pub async fn delete(db: &PrismaClient, id: folder_sync::id::Type) -> Result<(), Error> {
+	let deleted = db
+		.folder_sync()
+		.delete_many(vec![folder_sync::id::equals(id)])
+		.exec()
+		.await?;
+
+	if deleted == 0 {
+		return Err(Error::NotFound(id));
+	}
+
+	Ok(())
+}
+
+pub async fn conflicts(
+	db: &PrismaClient,
+	id: folder_sync::id::Type,
+) -> Result<Vec<FolderSyncConflict>, Error> {
+	Ok(db
+		.folder_sync_entry()
+		.find_many(vec![
+			folder_sync_entry::folder_sync_id::equals(id),
+			folder_sync_entry::conflict::equals(true),
+		])
+		.exec()
+		.await?
+		.into_iter()
+		.map(|entry| FolderSyncConflict {
+			path: PathBuf::from(entry.path),
+			resolution: entry.resolution.and_then(ConflictResolution::from_db),
+		})
+		.collect())
+}
+
+/// Records how to settle the conflict at `path`, which the next sync applies
+pub async fn resolve(
+	db: &PrismaClient,
+	id: folder_sync::id::Type,
+	path: &Path,
+	resolution: ConflictResolution,
+) -> Result<(), Error> {
+	let updated = db
+		.folder_sync_entry()
+		.update_many(
+			vec![
+				folder_sync_entry::folder_sync_id::equals(id),
+				folder_sync_entry::path::equals(path.to_string_lossy().to_string()),
+				folder_sync_entry::conflict::equals(true),
+			],
+			vec![folder_sync_entry::resolution::set(Some(resolution as i32))],
+		)
+		.exec()
+		.await?;
+
+	if updated == 0 {
+		return Err(Error::NoConflict(path.to_path_buf()));
+	}
+
+	Ok(())
+}
+
+/// What both sides held after the last sync, with the conflicts it left
+#[derive(Debug, Default)]
+pub(crate) struct Base {
+	pub(crate) contents: HashMap<PathBuf, Content>,
+	pub(crate) conflicts: HashSet<PathBuf>,
+	pub(crate) resolutions: HashMap<PathBuf, ConflictResolution>,
+}
+
+pub(crate) async fn load_base(db: &PrismaClient, id: folder_sync::id::Type) -> Result<Base, Error> {
+	let mut base = Base::default();
+
+	for entry in db
+		.folder_sync_entry()
+		.find_many(vec![folder_sync_entry::folder_sync_id::equals(id)])
+		.exec()
+		.await?
+	{
+		let path = PathBuf::from(entry.path);
+
+		if entry.conflict {
+			base.conflicts.insert(path.clone());
+		}
+
+		if let Some(resolution) = entry.resolution.and_then(ConflictResolution::from_db) {
+			base.resolutions.insert(path.clone(), resolution);
+		}
+
+		// Conflicts on paths that didn't exist at the last sync are recorded without content
+		let content = if entry.is_dir {
+			Some(Content::Dir)
+		} else {
+			entry.cas_id.map(|cas_id| Content::File { cas_id })
+		};
+
+		if let Some(content) = content {
+			base.contents.insert(path, content);
+		}
+	}
+
+	Ok(base)
+}
+
+/// The locations of a folder sync, A then B
+pub async fn locations(
+	db: &PrismaClient,
+	id: folder_sync::id::Type,
+) -> Result<(location::Data, location::Data), Error> {
+	let pair = db
+		.folder_sync()
+		.find_unique(folder_sync::id::equals(id))
+		.with(folder_sync::location_a::fetch())
+		.with(folder_sync::location_b::fetch())
+		.exec()
+		.await?
+		.ok_or(Error::NotFound(id))?;
+
+	Ok((
+		*maybe_missing(pair.location_a, "folder_sync.location_a")?,
+		*maybe_missing(pair.location_b, "folder_sync.location_b")?,
+	))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct FolderSyncChange {
+	/// Relative to the roots of the locations
+	pub path: PathBuf,
+	pub is_dir: bool,
+	pub action: SyncAction,
+}
+
+/// What a path holds on both sides once the sync is done, `None` if it's gone from both
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct BaseUpdate {
+	pub(crate) path: PathBuf,
+	pub(crate) content: Option<Content>,
+	pub(crate) conflict: bool,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Plan {
+	pub(crate) actions: BTreeMap<PathBuf, SyncAction>,
+	pub(crate) a: Scan,
+	pub(crate) b: Scan,
+	pub(crate) updates: Vec<BaseUpdate>,
+}
+
+impl Plan {
+	pub(crate) fn changes(&self) -> Vec<FolderSyncChange> {
+		self.actions
+			.iter()
+			.map(|(path, action)| FolderSyncChange {
+				path: path.clone(),
+				is_dir: matches!(
+					self.a
+						.contents
+						.get(path)
+						.or_else(|| self.b.contents.get(path)),
+					Some(Content::Dir)
+				),
+				action: *action,
+			})
+			.collect()
+	}
+}
+
+/// What syncing the locations would do. `cas_id_algorithm` and `deep_hash_threshold` must be the
+/// ones the library's `cas_id`s were generated with.
+pub async fn preview(
+	db: &PrismaClient,
+	id: folder_sync::id::Type,
+	cas_id_algorithm: CasIdAlgorithm,
+	deep_hash_threshold: Option<u64>,
+) -> Result<Vec<FolderSyncChange>, Error> {
+	let (location_a, location_b) = locations(db, id).await?;
+	let (path_a, path_b) = check_locations(&location_a, &location_b)?;
+
+	plan(
+		db,
+		id,
+		(location_a.id, &path_a),
+		(location_b.id, &path_b),
+		cas_id_algorithm,
+		deep_hash_threshold,
+		&IoThrottle::default(),
+	)
+	.await
+	.map(|plan| plan.changes())
+}
+
+pub(crate) async fn plan(
+	db: &PrismaClient,
+	id: folder_sync::id::Type,
+	(location_a_id, path_a): (location::id::Type, &Path),
+	(location_b_id, path_b): (location::id::Type, &Path),
+	cas_id_algorithm: CasIdAlgorithm,
+	deep_hash_threshold: Option<u64>,
+	io_throttle: &IoThrottle,
+) -> Result<Plan, Error> {
+	let a = scan(
+		db,
+		location_a_id,
+		path_a,
+		cas_id_algorithm,
+		deep_hash_threshold,
+		io_throttle,
+	)
+	.await?;
+	let b = scan(
+		db,
+		location_b_id,
+		path_b,
+		cas_id_algorithm,
+		deep_hash_threshold,
+		io_throttle,
+	)
+	.await?;
+	let base = load_base(db, id).await?;
+
+	let mut actions = reconcile(&base.contents, &a.contents, &b.contents, &base.resolutions);
+
+	// Files we couldn't hash are missing from their side, which would read as a deletion, of them
+	// and of the directories holding them
+	for path in a.unreadable.iter().chain(&b.unreadable) {
+		actions.remove(path);
+
+		for ancestor in path.ancestors().skip(1) {
+			if matches!(
+				actions.get(ancestor),
+				Some(SyncAction::DeleteFromA | SyncAction::DeleteFromB)
+			) {
+				actions.remove(ancestor);
+			}
+		}
+	}
+
+	let mut updates = actions
+		.iter()
+		.map(|(path, action)| match action {
+			SyncAction::Conflict => BaseUpdate {
+				path: path.clone(),
+				content: base.contents.get(path).cloned(),
+				conflict: true,
+			},
+			_ => BaseUpdate {
+				path: path.clone(),
+				content: action.synced(a.contents.get(path), b.contents.get(path)),
+				conflict: false,
+			},
+		})
+		.collect::<Vec<_>>();
+
+	// Conflicts both sides settled on their own, by going back to the same contents
+	updates.extend(
+		base.conflicts
+			.iter()
+			.filter(|path| {
+				!actions.contains_key(*path) && a.contents.get(*path) == b.contents.get(*path)
+			})
+			.map(|path| BaseUpdate {
+				path: path.clone(),
+				content: a.contents.get(path).cloned(),
+				conflict: false,
+			}),
+	);
+
+	Ok(Plan {
+		actions,
+		a,
+		b,
+		updates,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn file(cas_id: &str) -> Content {
+		Content::File {
+			cas_id: cas_id.to_string(),
+		}
+	}
+
+	fn side(entries: &[(&str, Content)]) -> HashMap<PathBuf, Content> {
+		entries
+			.iter()
+			.map(|(path, content)| (PathBuf::from(path), content.clone()))
+			.collect()
+	}
+
+	fn actions(entries: &[(&str, SyncAction)]) -> BTreeMap<PathBuf, SyncAction> {
+		entries
+			.iter()
+			.map(|(path, action)| (PathBuf::from(path), *action))
+			.collect()
+	}
+
+	#[test]
+	fn changes_go_to_the_other_side() {
+		let base = side(&[
+			("notes.md", file("1")),
+			("todo.md", file("2")),
+			("old.md", file("3")),
+		]);
+		let a = side(&[
+			("notes.md", file("1b")),
+			("todo.md", file("2")),
+			("new.md", file("4")),
+		]);
+		let b = side(&[
+			("notes.md", file("1")),
+			("todo.md", file("2c")),
+			("old.md", file("3")),
+		]);
+
+		assert_eq!(
+			reconcile(&base, &a, &b, &HashMap::new()),
+			actions(&[
+				("new.md", SyncAction::CopyToB),
+				("notes.md", SyncAction::CopyToB),
+				("old.md", SyncAction::DeleteFromB),
+				("todo.md", SyncAction::CopyToA),
+			])
+		);
+	}
+
+	#[test]
+	fn both_sides_changing_is_a_conflict_until_resolved() {
+		let base = side(&[("report.doc", file("1")), ("same.doc", file("2"))]);
+		let a = side(&[("report.doc", file("1a")), ("same.doc", file("2x"))]);
+		let b = side(&[
+			("report.doc", file("1b")),
+			("same.doc", file("2x")),
+			("fresh.doc", file("5")),
+		]);
+
+		assert_eq!(
+			reconcile(&base, &a, &b, &HashMap::new()),
+			actions(&[
+				("fresh.doc", SyncAction::CopyToA),
+				("report.doc", SyncAction::Conflict),
+				("same.doc", SyncAction::Converged),
+			])
+		);
+
+		let resolutions =
+			HashMap::from([(PathBuf::from("report.doc"), ConflictResolution::KeepBoth)]);
+		assert_eq!(
+			reconcile(&base, &a, &b, &resolutions).get(Path::new("report.doc")),
+			Some(&SyncAction::KeepBoth)
+		);
+
+		// The first sync has no base, so files differing on both sides conflict
+		assert_eq!(
+			reconcile(&HashMap::new(), &a, &b, &HashMap::new()).get(Path::new("report.doc")),
+			Some(&SyncAction::Conflict)
+		);
+	}
+
+	#[test]
+	fn deleted_directories_with_new_content_are_kept() {
+		let base = side(&[("photos", Content::Dir), ("photos/a.jpg", file("1"))]);
+		let a = side(&[]);
+		let b = side(&[
+			("photos", Content::Dir),
+			("photos/a.jpg", file("1")),
+			("photos/b.jpg", file("2")),
+		]);
+
+		assert_eq!(
+			reconcile(&base, &a, &b, &HashMap::new()),
+			actions(&[
+				("photos", SyncAction::CopyToA),
+				("photos/a.jpg", SyncAction::DeleteFromB),
+				("photos/b.jpg", SyncAction::CopyToA),
+			])
+		);
+	}
+
+	#[test]
+	fn files_replacing_directories_with_new_content_conflict() {
+		let base = side(&[("drafts", Content::Dir), ("drafts/a.md", file("1"))]);
+		let a = side(&[
+			("drafts", Content::Dir),
+			("drafts/a.md", file("1")),
+			("drafts/b.md", file("2")),
+		]);
+		let b = side(&[("drafts", file("3"))]);
+
+		assert_eq!(
+			reconcile(&base, &a, &b, &HashMap::new()),
+			actions(&[("drafts", SyncAction::Conflict)])
+		);
+
+		let resolutions = HashMap::from([(PathBuf::from("drafts"), ConflictResolution::KeepB)]);
+		assert_eq!(
+			reconcile(&base, &a, &b, &resolutions).get(Path::new("drafts")),
+			Some(&SyncAction::CopyToA)
+		);
+	}
+}
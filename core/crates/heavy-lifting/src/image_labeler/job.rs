@@ -0,0 +1,503 @@
+use crate::{
+	image_labeler,
+	job_system::{
+		job::{Job, JobReturn, JobTaskDispatcher, ReturnStatus},
+		report::ReportOutputMetadata,
+		utils::{cancel_pending_tasks, location_concurrency_key},
+		SerializableJob, SerializedTasks,
+	},
+	media_processor::THUMBNAIL_CACHE_DIR_NAME,
+	utils::sub_path::{self, maybe_get_iso_file_path_from_sub_path},
+	Error, JobName, NonCriticalError, OuterContext, ProgressUpdate,
+};
+
+use sd_core_file_path_helper::IsolatedFilePathData;
+use sd_core_prisma_helpers::file_path_for_media_processor;
+
+use sd_prisma::prisma::{location, PrismaClient};
+use sd_task_system::{
+	IntoTask, SerializableTask, Task, TaskDispatcher, TaskHandle, TaskOutput, TaskStatus,
+};
+use sd_utils::db::maybe_missing;
+
+use std::{
+	collections::HashMap,
+	hash::{Hash, Hasher},
+	mem,
+	path::PathBuf,
+	sync::Arc,
+	time::Duration,
+};
+
+use futures::{stream::FuturesUnordered, StreamExt};
+use futures_concurrency::future::TryJoin;
+use itertools::Itertools;
+use prisma_client_rust::{raw, PrismaValue};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{debug, warn};
+
+use super::{
+	tasks::{labeler, Labeler},
+	LoadedModel, YoloV8, AVAILABLE_EXTENSIONS, BATCH_SIZE,
+};
+
+/// Labels the images of a location, or of a directory of it, with what the labeling model sees in
+/// them, like `dog` or `car`
+#[derive(Debug)]
+pub struct ImageLabeler {
+	location: Arc<location::Data>,
+	location_path: Arc<PathBuf>,
+	sub_path: Option<PathBuf>,
+	/// One of [`YoloV8`]'s versions, or its default one
+	model_version: Option<String>,
+	regenerate: bool,
+
+	// Loaded when the job runs, as a model can't be serialized
+	model: Option<Arc<LoadedModel>>,
+
+	metadata: Metadata,
+
+	errors: Vec<NonCriticalError>,
+
+	pending_tasks_on_resume: Vec<TaskHandle<Error>>,
+	tasks_for_shutdown: Vec<Box<dyn Task<Error>>>,
+}
+
+impl Hash for ImageLabeler {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.location.id.hash(state);
+		if let Some(ref sub_path) = self.sub_path {
+			sub_path.hash(state);
+		}
+	}
+}
+
+impl Job for ImageLabeler {
+	const NAME: JobName = JobName::ImageLabeler;
+
+	async fn resume_tasks(
+		&mut self,
+		dispatcher: &JobTaskDispatcher,
+		ctx: &impl OuterContext,
+		SerializedTasks(serialized_tasks): SerializedTasks,
+	) -> Result<(), Error> {
+		let model = self.load_model(ctx).await?;
+
+		self.pending_tasks_on_resume = dispatcher
+			.with_concurrency_key(location_concurrency_key(ctx.id(), self.location.id))
+			.dispatch_many_boxed(
+				rmp_serde::from_slice::<Vec<Vec<u8>>>(&serialized_tasks)
+					.map_err(image_labeler::Error::from)?
+					.into_iter()
+					.map(|task_bytes| {
+						let model = Arc::clone(&model);
+						async move {
+							Labeler::deserialize(
+								&task_bytes,
+								(model, Arc::clone(ctx.db()), Arc::clone(ctx.sync())),
+							)
+							.await
+							.map(IntoTask::into_task)
+						}
+					})
+					.collect::<Vec<_>>()
+					.try_join()
+					.await
+					.map_err(image_labeler::Error::from)?,
+			)
+			.await;
+
+		Ok(())
+	}
+
+	async fn run<Ctx: OuterContext>(
+		mut self,
+		dispatcher: JobTaskDispatcher,
+		ctx: Ctx,
+	) -> Result<ReturnStatus, Error> {
+		let dispatcher =
+			dispatcher.with_concurrency_key(location_concurrency_key(ctx.id(), self.location.id));
+
+		let mut pending_running_tasks = FuturesUnordered::new();
+
+		self.init_or_resume(&mut pending_running_tasks, &ctx, &dispatcher)
+			.await?;
+
+		while let Some(task) = pending_running_tasks.next().await {
+			match task {
+				Ok(TaskStatus::Done((_, TaskOutput::Out(out)))) => {
+					self.process_labeler_output(
+						*out.downcast::<labeler::Output>()
+							.expect("the image labeler job only dispatches labeler tasks"),
+						&ctx,
+					);
+				}
+
+				Ok(TaskStatus::Done((task_id, TaskOutput::Empty))) => {
+					warn!("Task <id='{task_id}'> returned an empty output");
+				}
+
+				Ok(TaskStatus::Shutdown(task)) => {
+					self.tasks_for_shutdown.push(task);
+				}
+
+				Ok(TaskStatus::Error(e)) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e);
+				}
+
+				Ok(TaskStatus::Canceled | TaskStatus::ForcedAbortion) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Ok(ReturnStatus::Canceled);
+				}
+
+				Err(e) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e.into());
+				}
+			}
+		}
+
+		if !self.tasks_for_shutdown.is_empty() {
+			return Ok(ReturnStatus::Shutdown(
+				SerializableJob::<Ctx>::serialize(self).await,
+			));
+		}
+
+		if self.metadata.with_new_labels > 0 {
+			ctx.invalidate_query("labels.list");
+		}
+		if self.metadata.labeled > 0 {
+			ctx.invalidate_query("labels.getForObject");
+			ctx.invalidate_query("labels.getWithObjects");
+		}
+
+		let Self {
+			metadata, errors, ..
+		} = self;
+
+		Ok(ReturnStatus::Completed(
+			JobReturn::builder()
+				.with_metadata(metadata)
+				.with_non_critical_errors(errors)
+				.build(),
+		))
+	}
+}
+
+impl ImageLabeler {
+	pub fn new(
+		location: location::Data,
+		sub_path: Option<PathBuf>,
+	) -> Result<Self, image_labeler::Error> {
+		Ok(Self {
+			location_path: maybe_missing(&location.path, "location.path")
+				.map(PathBuf::from)
+				.map(Arc::new)?,
+			location: Arc::new(location),
+			sub_path,
+			model_version: None,
+			regenerate: false,
+			model: None,
+			metadata: Metadata::default(),
+			errors: Vec::new(),
+			pending_tasks_on_resume: Vec::new(),
+			tasks_for_shutdown: Vec::new(),
+		})
+	}
+
+	/// Labels with another version of the model, downloading it if needed. Objects labeled by other
+	/// versions are labeled again
+	#[must_use]
+	pub fn with_model_version(mut self, model_version: String) -> Self {
+		self.model_version = Some(model_version);
+		self
+	}
+
+	/// Labels again the objects that were already labeled by the same model
+	#[must_use]
+	pub const fn with_regenerate(mut self, regenerate: bool) -> Self {
+		self.regenerate = regenerate;
+		self
+	}
+
+	async fn load_model(
+		&mut self,
+		ctx: &impl OuterContext,
+	) -> Result<Arc<LoadedModel>, image_labeler::Error> {
+		if let Some(model) = &self.model {
+			return Ok(Arc::clone(model));
+		}
+
+		let model = Arc::new(
+			LoadedModel::load(
+				YoloV8::model(self.model_version.as_ref())?,
+				ctx.get_data_directory(),
+			)
+			.await?,
+		);
+
+		self.model = Some(Arc::clone(&model));
+
+		Ok(model)
+	}
+
+	async fn init_or_resume(
+		&mut self,
+		pending_running_tasks: &mut FuturesUnordered<TaskHandle<Error>>,
+		ctx: &impl OuterContext,
+		dispatcher: &JobTaskDispatcher,
+	) -> Result<(), image_labeler::Error> {
+		// Resumed tasks were already dispatched by `resume_tasks`
+		if !self.pending_tasks_on_resume.is_empty() {
+			pending_running_tasks.extend(mem::take(&mut self.pending_tasks_on_resume));
+
+			return Ok(());
+		}
+
+		let db = ctx.db();
+		let location_id = self.location.id;
+		let location_path = &*self.location_path;
+
+		let iso_file_path =
+			maybe_get_iso_file_path_from_sub_path(location_id, &self.sub_path, location_path, db)
+				.await?
+				.map_or_else(
+					|| {
+						IsolatedFilePathData::new(location_id, location_path, location_path, true)
+							.map_err(sub_path::Error::from)
+					},
+					Ok,
+				)?;
+
+		let model = self.load_model(ctx).await?;
+
+		debug!(
+			"Labeling images in location {location_id} at directory \"{iso_file_path}\" with {}",
+			model.id()
+		);
+
+		let file_paths =
+			get_files_to_label(db, &iso_file_path, (!self.regenerate).then(|| model.id())).await?;
+
+		self.metadata.total_files = file_paths.len() as u64;
+
+		let thumbnails_directory_path =
+			Arc::new(ctx.get_data_directory().join(THUMBNAIL_CACHE_DIR_NAME));
+
+		pending_running_tasks.extend(
+			dispatcher
+				.dispatch_many(
+					file_paths
+						.into_iter()
+						.chunks(BATCH_SIZE)
+						.into_iter()
+						.map(|chunk| {
+							Labeler::new(
+								Arc::clone(&thumbnails_directory_path),
+								&chunk.collect::<Vec<_>>(),
+								(location_id, location_path),
+								ctx.id(),
+								Arc::clone(&model),
+								Arc::clone(db),
+								Arc::clone(ctx.sync()),
+							)
+						})
+						.collect::<Vec<_>>(),
+				)
+				.await,
+		);
+
+		ctx.progress(vec![
+			ProgressUpdate::TaskCount(self.metadata.total_files),
+			ProgressUpdate::Message(format!(
+				"Labeling {} images in {} chunks",
+				self.metadata.total_files,
+				pending_running_tasks.len()
+			)),
+		]);
+
+		Ok(())
+	}
+
+	fn process_labeler_output(
+		&mut self,
+		labeler::Output {
+			labeled,
+			with_new_labels,
+			skipped,
+			labeling_time,
+			db_write_time,
+			errors,
+		}: labeler::Output,
+		ctx: &impl OuterContext,
+	) {
+		self.metadata.labeled += labeled;
+		self.metadata.with_new_labels += with_new_labels;
+		self.metadata.skipped += skipped;
+		self.metadata.labeling_time += labeling_time;
+		self.metadata.db_write_time += db_write_time;
+
+		self.errors.extend(errors);
+
+		ctx.progress(vec![ProgressUpdate::CompletedTaskCount(
+			self.metadata.labeled + self.metadata.skipped,
+		)]);
+	}
+}
+
+/// Images of the directory, leaving out the ones already labeled by `skip_labeled_by` when given
+async fn get_files_to_label(
+	db: &PrismaClient,
+	parent_iso_file_path: &IsolatedFilePathData<'_>,
+	skip_labeled_by: Option<String>,
+) -> Result<Vec<file_path_for_media_processor::Data>, image_labeler::Error> {
+	// FIXME: Had to use format! macro because PCR doesn't support IN with Vec for SQLite
+	// We have no data coming from the user, so this is sql injection safe
+	db._query_raw(raw!(
+		&format!(
+			"SELECT id, materialized_path, is_dir, name, extension, cas_id, object_id
+			FROM file_path
+			WHERE
+				location_id={{}}
+				AND cas_id IS NOT NULL
+				AND object_id IS NOT NULL
+				AND (in_archive IS NULL OR in_archive = 0)
+				AND LOWER(extension) IN ({})
+				AND materialized_path LIKE {{}}
+				AND object_id NOT IN (SELECT object_id FROM labeled_object WHERE model = {{}})
+			ORDER BY materialized_path ASC",
+			AVAILABLE_EXTENSIONS
+				.iter()
+				.map(|ext| format!("LOWER('{ext}')"))
+				.collect::<Vec<_>>()
+				.join(","),
+		),
+		PrismaValue::Int(parent_iso_file_path.location_id()),
+		PrismaValue::String(format!(
+			"{}%",
+			parent_iso_file_path
+				.materialized_path_for_children()
+				.expect("sub path iso_file_path must be a directory")
+		)),
+		// No model is named like that, so nothing is left out when regenerating
+		PrismaValue::String(skip_labeled_by.unwrap_or_default())
+	))
+	.exec()
+	.await
+	.map_err(Into::into)
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Metadata {
+	total_files: u64,
+	labeled: u64,
+	with_new_labels: u64,
+	skipped: u64,
+	labeling_time: Duration,
+	db_write_time: Duration,
+}
+
+impl From<Metadata> for ReportOutputMetadata {
+	fn from(value: Metadata) -> Self {
+		Self::Metrics(HashMap::from([
+			("total_files".into(), json!(value.total_files)),
+			("labeled_files".into(), json!(value.labeled)),
+			("files_with_new_labels".into(), json!(value.with_new_labels)),
+			("skipped_files".into(), json!(value.skipped)),
+			("labeling_time".into(), json!(value.labeling_time)),
+			("db_write_time".into(), json!(value.db_write_time)),
+		]))
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+struct SaveState {
+	location: Arc<location::Data>,
+	location_path: Arc<PathBuf>,
+	sub_path: Option<PathBuf>,
+	model_version: Option<String>,
+	regenerate: bool,
+
+	metadata: Metadata,
+
+	errors: Vec<NonCriticalError>,
+
+	tasks_for_shutdown_bytes: Option<SerializedTasks>,
+}
+
+impl<Ctx: OuterContext> SerializableJob<Ctx> for ImageLabeler {
+	async fn serialize(self) -> Result<Option<Vec<u8>>, rmp_serde::encode::Error> {
+		let Self {
+			location,
+			location_path,
+			sub_path,
+			model_version,
+			regenerate,
+			metadata,
+			errors,
+			tasks_for_shutdown,
+			..
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState {
+			location,
+			location_path,
+			sub_path,
+			model_version,
+			regenerate,
+			metadata,
+			errors,
+			tasks_for_shutdown_bytes: Some(SerializedTasks(rmp_serde::to_vec_named(
+				&tasks_for_shutdown
+					.into_iter()
+					.map(|task| async move {
+						task.downcast::<Labeler>()
+							.expect("the image labeler job only dispatches labeler tasks")
+							.serialize()
+							.await
+					})
+					.collect::<Vec<_>>()
+					.try_join()
+					.await?,
+			)?)),
+		})
+		.map(Some)
+	}
+
+	async fn deserialize(
+		serialized_job: &[u8],
+		_: &Ctx,
+	) -> Result<Option<(Self, Option<SerializedTasks>)>, rmp_serde::decode::Error> {
+		let SaveState {
+			location,
+			location_path,
+			sub_path,
+			model_version,
+			regenerate,
+			metadata,
+			errors,
+			tasks_for_shutdown_bytes,
+		} = rmp_serde::from_slice::<SaveState>(serialized_job)?;
+
+		Ok(Some((
+			Self {
+				location,
+				location_path,
+				sub_path,
+				model_version,
+				regenerate,
+				model: None,
+				metadata,
+				errors,
+				pending_tasks_on_resume: Vec::new(),
+				tasks_for_shutdown: Vec::new(),
+			},
+			tasks_for_shutdown_bytes,
+		)))
+	}
+}
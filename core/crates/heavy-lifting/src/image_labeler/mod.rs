@@ -0,0 +1,96 @@
+use crate::{media_processor::can_generate_thumbnail_for_image, utils::sub_path};
+
+use sd_core_file_path_helper::FilePathError;
+
+use sd_ai::image_labeler::{DownloadModelError, ImageLabelerError};
+use sd_file_ext::extensions::{Extension, ALL_IMAGE_EXTENSIONS};
+use sd_prisma::prisma::{labeled_object, object, PrismaClient};
+use sd_utils::db::MissingFieldError;
+
+use once_cell::sync::Lazy;
+use prisma_client_rust::QueryError;
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+pub mod job;
+mod tasks;
+
+pub use job::ImageLabeler;
+pub use sd_ai::image_labeler::{LoadedModel, YoloV8, DEFAULT_MODEL_VERSION};
+pub use tasks::labeler;
+
+// Inference is slow, so each task gets only a few files to be interrupted often enough
+const BATCH_SIZE: usize = 10;
+
+pub static AVAILABLE_EXTENSIONS: Lazy<Vec<Extension>> = Lazy::new(|| {
+	ALL_IMAGE_EXTENSIONS
+		.iter()
+		.copied()
+		.filter(|&ext| can_generate_thumbnail_for_image(ext))
+		.map(Extension::Image)
+		.collect()
+});
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("missing field on database: {0}")]
+	MissingField(#[from] MissingFieldError),
+	#[error("database error: {0}")]
+	Database(#[from] QueryError),
+	#[error("failed to deserialized stored tasks for job resume: {0}")]
+	DeserializeTasks(#[from] rmp_serde::decode::Error),
+	#[error("failed to get the labeling model: {0}")]
+	DownloadModel(#[from] DownloadModelError),
+	#[error(transparent)]
+	ImageLabeler(#[from] ImageLabelerError),
+
+	#[error(transparent)]
+	FilePathError(#[from] FilePathError),
+	#[error(transparent)]
+	SubPath(#[from] sub_path::Error),
+}
+
+impl From<Error> for rspc::Error {
+	fn from(err: Error) -> Self {
+		match err {
+			Error::SubPath(sub_path_err) => sub_path_err.into(),
+
+			_ => Self::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize, Type)]
+pub enum NonCriticalError {
+	#[error(transparent)]
+	Labeler(#[from] labeler::NonCriticalError),
+}
+
+/// Records which model labeled the objects, so the next runs skip them while the model is the same
+async fn mark_labeled(
+	object_ids: Vec<object::id::Type>,
+	model: &str,
+	db: &PrismaClient,
+) -> Result<u64, Error> {
+	// Labels themselves are synced, but each device keeps track of what it labeled
+	db._batch(
+		object_ids
+			.into_iter()
+			.map(|object_id| {
+				db.labeled_object().upsert(
+					labeled_object::object_id::equals(object_id),
+					labeled_object::create(
+						model.to_string(),
+						object::id::equals(object_id),
+						vec![],
+					),
+					vec![labeled_object::model::set(model.to_string())],
+				)
+			})
+			.collect::<Vec<_>>(),
+	)
+	.await
+	.map(|marked| marked.len() as u64)
+	.map_err(Into::into)
+}
@@ -0,0 +1,311 @@
+use crate::{
+	image_labeler::{self, mark_labeled},
+	media_processor::{thumbnail_path, ThumbnailKind},
+	Error,
+};
+
+use sd_core_file_path_helper::IsolatedFilePathData;
+use sd_core_prisma_helpers::file_path_for_media_processor;
+use sd_core_sync::Manager as SyncManager;
+
+use sd_ai::image_labeler::{assign_labels, LoadedModel};
+use sd_images::{format_image, ConvertibleExtension};
+use sd_media_metadata::exif::Orientation;
+use sd_prisma::prisma::{file_path, location, object, PrismaClient};
+use sd_task_system::{
+	check_interruption, ExecStatus, Interrupter, IntoAnyTaskOutput, SerializableTask, Task, TaskId,
+};
+
+use std::{
+	collections::{HashMap, HashSet},
+	mem,
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::{task::spawn_blocking, time::Instant};
+use tracing::{error, trace};
+use uuid::Uuid;
+
+/// Runs the labeling model over a batch of images, linking the labels it finds to their objects
+#[derive(Debug)]
+pub struct Labeler {
+	id: TaskId,
+	library_id: Uuid,
+	thumbnails_directory_path: Arc<PathBuf>,
+	files: Vec<(object::id::Type, String, PathBuf)>,
+	labeled: Vec<object::id::Type>,
+	model: Arc<LoadedModel>,
+	db: Arc<PrismaClient>,
+	sync: Arc<SyncManager>,
+	output: Output,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Output {
+	pub labeled: u64,
+	/// Objects with labels never seen before in the library
+	pub with_new_labels: u64,
+	pub skipped: u64,
+	pub labeling_time: Duration,
+	pub db_write_time: Duration,
+	pub errors: Vec<crate::NonCriticalError>,
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize, Type)]
+pub enum NonCriticalError {
+	#[error("failed to extract isolated file path data from file path <id='{0}'>: {1}")]
+	FailedToExtractIsolatedFilePathData(file_path::id::Type, String),
+	#[error("file path missing object id: <file_path_id='{0}'>")]
+	FilePathMissingObjectId(file_path::id::Type),
+	#[error("failed to decode image to label <path='{}'>: {1}", .0.display())]
+	FormatImage(PathBuf, String),
+	#[error("failed to label image <path='{}'>: {1}", .0.display())]
+	Label(PathBuf, String),
+	#[error("processing thread panicked while labeling image <path='{}'>: {1}", .0.display())]
+	PanicWhileLabeling(PathBuf, String),
+}
+
+impl Labeler {
+	#[must_use]
+	pub fn new(
+		thumbnails_directory_path: Arc<PathBuf>,
+		file_paths: &[file_path_for_media_processor::Data],
+		(location_id, location_path): (location::id::Type, &Path),
+		library_id: Uuid,
+		model: Arc<LoadedModel>,
+		db: Arc<PrismaClient>,
+		sync: Arc<SyncManager>,
+	) -> Self {
+		let mut errors = Vec::new();
+		let mut seen_objects = HashSet::with_capacity(file_paths.len());
+
+		Self {
+			id: TaskId::new_v4(),
+			library_id,
+			thumbnails_directory_path,
+			files: file_paths
+				.iter()
+				.filter_map(|file_path| {
+					// Only file paths with cas_id are fetched for the labeler
+					let cas_id = file_path.cas_id.clone()?;
+
+					let Some(object_id) = file_path.object_id else {
+						errors.push(
+							image_labeler::NonCriticalError::from(
+								NonCriticalError::FilePathMissingObjectId(file_path.id),
+							)
+							.into(),
+						);
+						return None;
+					};
+
+					// Many file paths can point to the same object, we label it just once
+					if !seen_objects.insert(object_id) {
+						return None;
+					}
+
+					IsolatedFilePathData::try_from((location_id, file_path))
+						.map_err(|e| {
+							errors.push(
+								image_labeler::NonCriticalError::from(
+									NonCriticalError::FailedToExtractIsolatedFilePathData(
+										file_path.id,
+										e.to_string(),
+									),
+								)
+								.into(),
+							);
+						})
+						.ok()
+						.map(|iso_file_path| (object_id, cas_id, location_path.join(iso_file_path)))
+				})
+				.collect(),
+			labeled: Vec::new(),
+			model,
+			db,
+			sync,
+			output: Output {
+				errors,
+				..Default::default()
+			},
+		}
+	}
+}
+
+#[async_trait::async_trait]
+impl Task<Error> for Labeler {
+	fn id(&self) -> TaskId {
+		self.id
+	}
+
+	async fn run(&mut self, interrupter: &Interrupter) -> Result<ExecStatus, Error> {
+		let Self {
+			library_id,
+			thumbnails_directory_path,
+			files,
+			labeled,
+			model,
+			db,
+			sync,
+			output:
+				Output {
+					labeled: labeled_count,
+					with_new_labels,
+					skipped,
+					labeling_time,
+					db_write_time,
+					errors,
+				},
+			..
+		} = self;
+
+		let start = Instant::now();
+		let kind = ThumbnailKind::Indexed(*library_id);
+
+		// Files are popped, so a resumed task only processes the remaining ones
+		while let Some((object_id, cas_id, path)) = files.pop() {
+			let thumbnail = thumbnail_path(&**thumbnails_directory_path, &cas_id, &kind);
+
+			match label_image(path, thumbnail, Arc::clone(model)).await {
+				Ok(labels) => {
+					let db_write_start = Instant::now();
+
+					if assign_labels(object_id, labels, db, sync)
+						.await
+						.map_err(image_labeler::Error::from)?
+					{
+						*with_new_labels += 1;
+					}
+
+					*db_write_time += db_write_start.elapsed();
+
+					// Objects without any label are also marked, so they aren't labeled again
+					labeled.push(object_id);
+				}
+				Err(e) => {
+					error!("{e:#?}");
+					errors.push(image_labeler::NonCriticalError::from(e).into());
+					*skipped += 1;
+				}
+			}
+
+			check_interruption!(interrupter, start, labeling_time);
+		}
+
+		*labeling_time += start.elapsed();
+
+		let db_write_start = Instant::now();
+		*labeled_count = mark_labeled(mem::take(labeled), &model.id(), db).await?;
+		*db_write_time += db_write_start.elapsed();
+
+		Ok(ExecStatus::Done(mem::take(&mut self.output).into_output()))
+	}
+}
+
+/// Labels the thumbnail when there is one, as the model shrinks images way below thumbnail size
+/// anyway, and thumbnails already have their orientation corrected
+async fn label_image(
+	path: PathBuf,
+	thumbnail: PathBuf,
+	model: Arc<LoadedModel>,
+) -> Result<HashMap<String, f32>, NonCriticalError> {
+	spawn_blocking({
+		let path = path.clone();
+
+		move || {
+			let img = if let Ok(img) = image::open(&thumbnail) {
+				trace!("Labeling thumbnail of {}", path.display());
+				img
+			} else {
+				let mut img = format_image(&path)
+					.map_err(|e| NonCriticalError::FormatImage(path.clone(), e.to_string()))?;
+
+				// Models are trained on upright images
+				if let Some(orientation) = Orientation::from_path(&path) {
+					if ConvertibleExtension::try_from(path.as_path())
+						.is_ok_and(|extension| extension.should_rotate())
+					{
+						img = orientation.correct_thumbnail(img);
+					}
+				}
+
+				img
+			};
+
+			model
+				.label(&img)
+				.map_err(|e| NonCriticalError::Label(path.clone(), e.to_string()))
+		}
+	})
+	.await
+	.map_err(|e| NonCriticalError::PanicWhileLabeling(path, e.to_string()))?
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SaveState {
+	id: TaskId,
+	library_id: Uuid,
+	thumbnails_directory_path: Arc<PathBuf>,
+	files: Vec<(object::id::Type, String, PathBuf)>,
+	labeled: Vec<object::id::Type>,
+	output: Output,
+}
+
+impl SerializableTask<Error> for Labeler {
+	type SerializeError = rmp_serde::encode::Error;
+
+	type DeserializeError = rmp_serde::decode::Error;
+
+	type DeserializeCtx = (Arc<LoadedModel>, Arc<PrismaClient>, Arc<SyncManager>);
+
+	async fn serialize(self) -> Result<Vec<u8>, Self::SerializeError> {
+		let Self {
+			id,
+			library_id,
+			thumbnails_directory_path,
+			files,
+			labeled,
+			output,
+			..
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState {
+			id,
+			library_id,
+			thumbnails_directory_path,
+			files,
+			labeled,
+			output,
+		})
+	}
+
+	async fn deserialize(
+		data: &[u8],
+		(model, db, sync): Self::DeserializeCtx,
+	) -> Result<Self, Self::DeserializeError> {
+		rmp_serde::from_slice(data).map(
+			|SaveState {
+			     id,
+			     library_id,
+			     thumbnails_directory_path,
+			     files,
+			     labeled,
+			     output,
+			 }| Self {
+				id,
+				library_id,
+				thumbnails_directory_path,
+				files,
+				labeled,
+				model,
+				db,
+				sync,
+				output,
+			},
+		)
+	}
+}
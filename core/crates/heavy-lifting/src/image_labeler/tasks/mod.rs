@@ -0,0 +1,3 @@
+pub mod labeler;
+
+pub use labeler::Labeler;
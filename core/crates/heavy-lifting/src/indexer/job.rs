@@ -1,5 +1,5 @@
 use crate::{
-	indexer,
+	disk_usage, indexer,
 	job_system::{
 		job::{
 			Job, JobName, JobReturn, JobTaskDispatcher, OuterContext, ProgressUpdate, ReturnStatus,
@@ -8,7 +8,7 @@ use crate::{
 		utils::cancel_pending_tasks,
 		SerializableJob, SerializedTasks,
 	},
-	utils::sub_path::get_full_path_from_sub_path,
+	utils::{network_share::ensure_location_mounted, sub_path::get_full_path_from_sub_path},
 	Error, LocationScanState, NonCriticalError,
 };
 
@@ -24,6 +24,7 @@ use sd_task_system::{
 use sd_utils::db::maybe_missing;
 
 use std::{
+	cmp::Reverse,
 	collections::{HashMap, HashSet},
 	hash::{Hash, Hasher},
 	mem,
@@ -41,6 +42,7 @@ use tokio::time::Instant;
 use tracing::warn;
 
 use super::{
+	ntfs::{self, ScanPlan, UsnJournalCursor},
 	remove_non_existing_file_paths, reverse_update_directories_sizes,
 	tasks::{
 		saver::{SaveTask, SaveTaskOutput},
@@ -59,6 +61,8 @@ pub struct Indexer {
 	iso_file_path_factory: IsoFilePathFactory,
 	indexer_ruler: IndexerRuler,
 	walker_root_path: Option<Arc<PathBuf>>,
+	incremental_roots: Vec<Arc<PathBuf>>,
+	usn_journal_cursor: Option<UsnJournalCursor>,
 	ancestors_needing_indexing: HashSet<WalkedEntry>,
 	ancestors_already_indexed: HashSet<IsolatedFilePathData<'static>>,
 	iso_paths_and_sizes: HashMap<IsolatedFilePathData<'static>, u64>,
@@ -130,6 +134,7 @@ impl Job for Indexer {
 		Ok(())
 	}
 
+	#[allow(clippy::too_many_lines)]
 	async fn run<Ctx: OuterContext>(
 		mut self,
 		dispatcher: JobTaskDispatcher,
@@ -194,9 +199,12 @@ impl Job for Indexer {
 
 		let Self {
 			location,
+			sub_path,
 			mut metadata,
 			iso_file_path_factory,
 			walker_root_path,
+			incremental_roots,
+			usn_journal_cursor,
 			iso_paths_and_sizes,
 			mut errors,
 			tasks_for_shutdown,
@@ -221,8 +229,51 @@ impl Job for Indexer {
 				.await?;
 			}
 
+			// Deepest roots first, so their sizes are up to date when we get to their ancestors
+			for root_path in incremental_roots
+				.iter()
+				.sorted_by_key(|root_path| Reverse(root_path.components().count()))
+			{
+				reverse_update_directories_sizes(
+					&**root_path,
+					location.id,
+					&*iso_file_path_factory.location_path,
+					ctx.db(),
+					ctx.sync(),
+					&mut errors,
+				)
+				.await?;
+			}
+
 			update_location_size(location.id, ctx.db(), &ctx).await?;
 
+			// Only the walked directories changed, so only them and their ancestors need their usage
+			// computed again
+			let disk_usage_roots = if incremental_roots.is_empty() {
+				vec![root_path]
+			} else {
+				incremental_roots
+			}
+			.into_iter()
+			.filter_map(|path| {
+				IsolatedFilePathData::new(
+					location.id,
+					&*iso_file_path_factory.location_path,
+					&*path,
+					true,
+				)
+				.map_err(|e| {
+					errors.push(indexer::NonCriticalError::IsoFilePath(e.to_string()).into());
+				})
+				.ok()
+				.and_then(|iso_file_path| iso_file_path.materialized_path_for_children())
+			})
+			.collect::<Vec<_>>();
+
+			if let Err(e) = disk_usage::refresh(ctx.db(), location.id, disk_usage_roots).await {
+				errors.push(indexer::NonCriticalError::DiskUsage(e.to_string()).into());
+			}
+
 			metadata.db_write_time += start_size_update_time.elapsed();
 		}
 
@@ -239,12 +290,19 @@ impl Job for Indexer {
 			"all tasks must be completed here"
 		);
 
+		let mut location_updates =
+			vec![location::scan_state::set(LocationScanState::Indexed as i32)];
+
+		// The journal cursor only means something after indexing the whole location
+		if sub_path.is_none() {
+			location_updates.push(location::usn_journal_cursor::set(
+				usn_journal_cursor.map(UsnJournalCursor::to_db),
+			));
+		}
+
 		ctx.db()
 			.location()
-			.update(
-				location::id::equals(location.id),
-				vec![location::scan_state::set(LocationScanState::Indexed as i32)],
-			)
+			.update(location::id::equals(location.id), location_updates)
 			.exec()
 			.await
 			.map_err(indexer::Error::from)?;
@@ -277,6 +335,8 @@ impl Indexer {
 					.map(Arc::new)?,
 			},
 			walker_root_path: None,
+			incremental_roots: Vec::new(),
+			usn_journal_cursor: None,
 			ancestors_needing_indexing: HashSet::new(),
 			ancestors_already_indexed: HashSet::new(),
 			iso_paths_and_sizes: HashMap::new(),
@@ -545,6 +605,13 @@ impl Indexer {
 		ctx: &impl OuterContext,
 		dispatcher: &JobTaskDispatcher,
 	) -> Result<(), indexer::Error> {
+		ensure_location_mounted(
+			self.location.id,
+			self.location.network_share.as_deref(),
+			&*self.iso_file_path_factory.location_path,
+		)
+		.await?;
+
 		// if we don't have any pending task, then this is a fresh job
 		if self.pending_tasks_on_resume.is_empty() {
 			let walker_root_path = Arc::new(
@@ -557,21 +624,84 @@ impl Indexer {
 				.await?,
 			);
 
-			pending_running_tasks.push(
-				dispatcher
-					.dispatch(WalkDirTask::new_deep(
-						walker_root_path.as_ref(),
-						Arc::clone(&walker_root_path),
-						self.indexer_ruler.clone(),
-						self.iso_file_path_factory.clone(),
-						WalkerDBProxy {
-							location_id: self.location.id,
-							db: Arc::clone(ctx.db()),
-						},
-						dispatcher.clone(),
-					)?)
-					.await,
-			);
+			let db_proxy = WalkerDBProxy {
+				location_id: self.location.id,
+				db: Arc::clone(ctx.db()),
+			};
+
+			// Only whole location runs can be planned from (and move forward) the USN journal cursor
+			let scan_plan = if self.sub_path.is_none() {
+				ntfs::plan_scan(
+					Arc::clone(&walker_root_path),
+					self.location
+						.usn_journal_cursor
+						.as_deref()
+						.and_then(UsnJournalCursor::from_db),
+				)
+				.await
+			} else {
+				ScanPlan::portable()
+			};
+
+			match scan_plan {
+				ScanPlan::Full { mft_index, cursor } => {
+					self.usn_journal_cursor = cursor;
+
+					pending_running_tasks.push(
+						dispatcher
+							.dispatch(
+								WalkDirTask::new_deep(
+									walker_root_path.as_ref(),
+									Arc::clone(&walker_root_path),
+									self.indexer_ruler.clone(),
+									self.iso_file_path_factory.clone(),
+									db_proxy,
+									dispatcher.clone(),
+								)?
+								.with_mft_index(mft_index),
+							)
+							.await,
+					);
+				}
+
+				ScanPlan::Incremental {
+					changed_dirs,
+					arrived_dirs,
+					cursor,
+				} => {
+					self.usn_journal_cursor = Some(cursor);
+
+					let mut walk_tasks =
+						Vec::with_capacity(changed_dirs.len() + arrived_dirs.len());
+
+					for (dir, keep_walking) in changed_dirs
+						.into_iter()
+						.map(|dir| (dir, false))
+						.chain(arrived_dirs.into_iter().map(|dir| (dir, true)))
+					{
+						let root_path = Arc::new(dir);
+
+						let task = WalkDirTask::new_deep(
+							root_path.as_ref(),
+							Arc::clone(&root_path),
+							self.indexer_ruler.clone(),
+							self.iso_file_path_factory.clone(),
+							db_proxy.clone(),
+							dispatcher.clone(),
+						)?;
+
+						walk_tasks.push(if keep_walking {
+							task
+						} else {
+							task.without_keep_walking()
+						});
+
+						self.incremental_roots.push(root_path);
+					}
+
+					pending_running_tasks.extend(dispatcher.dispatch_many(walk_tasks).await);
+				}
+			}
 
 			self.walker_root_path = Some(walker_root_path);
 		} else {
@@ -633,6 +763,8 @@ struct SaveState {
 	iso_file_path_factory: IsoFilePathFactory,
 	indexer_ruler_bytes: Vec<u8>,
 	walker_root_path: Option<Arc<PathBuf>>,
+	incremental_roots: Vec<Arc<PathBuf>>,
+	usn_journal_cursor: Option<UsnJournalCursor>,
 	ancestors_needing_indexing: HashSet<WalkedEntry>,
 	ancestors_already_indexed: HashSet<IsolatedFilePathData<'static>>,
 	paths_and_sizes: HashMap<IsolatedFilePathData<'static>, u64>,
@@ -651,6 +783,8 @@ impl<Ctx: OuterContext> SerializableJob<Ctx> for Indexer {
 			iso_file_path_factory,
 			indexer_ruler,
 			walker_root_path,
+			incremental_roots,
+			usn_journal_cursor,
 			ancestors_needing_indexing,
 			ancestors_already_indexed,
 			iso_paths_and_sizes: paths_and_sizes,
@@ -666,6 +800,8 @@ impl<Ctx: OuterContext> SerializableJob<Ctx> for Indexer {
 			iso_file_path_factory,
 			indexer_ruler_bytes: indexer_ruler.serialize().await?,
 			walker_root_path,
+			incremental_roots,
+			usn_journal_cursor,
 			ancestors_needing_indexing,
 			ancestors_already_indexed,
 			paths_and_sizes,
@@ -719,6 +855,8 @@ impl<Ctx: OuterContext> SerializableJob<Ctx> for Indexer {
 			iso_file_path_factory,
 			indexer_ruler_bytes,
 			walker_root_path,
+			incremental_roots,
+			usn_journal_cursor,
 			ancestors_needing_indexing,
 			ancestors_already_indexed,
 			paths_and_sizes,
@@ -736,6 +874,8 @@ impl<Ctx: OuterContext> SerializableJob<Ctx> for Indexer {
 				iso_file_path_factory,
 				indexer_ruler,
 				walker_root_path,
+				incremental_roots,
+				usn_journal_cursor,
 				ancestors_needing_indexing,
 				ancestors_already_indexed,
 				iso_paths_and_sizes: paths_and_sizes,
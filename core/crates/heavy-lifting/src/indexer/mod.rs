@@ -1,4 +1,8 @@
-use crate::{utils::sub_path, OuterContext};
+use crate::{
+	file_identifier,
+	utils::{network_share, sub_path},
+	OuterContext,
+};
 
 use sd_core_file_path_helper::{FilePathError, IsolatedFilePathData};
 use sd_core_indexer_rules::IndexerRuleError;
@@ -34,6 +38,7 @@ use specta::Type;
 use tracing::warn;
 
 pub mod job;
+mod ntfs;
 mod shallow;
 mod tasks;
 
@@ -51,6 +56,8 @@ pub enum Error {
 	IndexerRuleNotFound(indexer_rule::id::Type),
 	#[error(transparent)]
 	SubPath(#[from] sub_path::Error),
+	#[error(transparent)]
+	NetworkShare(#[from] network_share::Error),
 
 	// Internal Errors
 	#[error("database Error: {0}")]
@@ -80,6 +87,8 @@ impl From<Error> for rspc::Error {
 
 			Error::SubPath(sub_path_err) => sub_path_err.into(),
 
+			Error::NetworkShare(network_share_err) => network_share_err.into(),
+
 			Error::Rules(rule_err) => rule_err.into(),
 
 			_ => Self::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
@@ -107,6 +116,8 @@ pub enum NonCriticalError {
 	DispatchKeepWalking(String),
 	#[error("missing file_path data on database: {0}")]
 	MissingFilePathData(String),
+	#[error("failed to update the disk usage of indexed directories: {0}")]
+	DiskUsage(String),
 }
 
 fn chunk_db_queries<'db, 'iso>(
@@ -211,6 +222,13 @@ async fn remove_non_existing_file_paths(
 	db: &PrismaClient,
 	sync: &sd_core_sync::Manager,
 ) -> Result<u64, Error> {
+	file_identifier::remove_archive_entries(
+		to_remove.iter().map(|file_path| file_path.id).collect(),
+		db,
+		sync,
+	)
+	.await?;
+
 	#[allow(clippy::cast_sign_loss)]
 	let (sync_params, db_params): (Vec<_>, Vec<_>) = to_remove
 		.into_iter()
@@ -224,20 +242,31 @@ async fn remove_non_existing_file_paths(
 		})
 		.unzip();
 
-	sync.write_ops(
-		db,
-		(
-			sync_params,
-			db.file_path()
-				.delete_many(vec![file_path::id::in_vec(db_params)]),
-		),
-	)
-	.await
-	.map(
-		#[allow(clippy::cast_sign_loss)]
-		|count| count as u64,
-	)
-	.map_err(Into::into)
+	db._transaction()
+		.with_timeout(30 * 1000)
+		.run(|db| async move {
+			file_identifier::statistics::forget(
+				vec![file_path::id::in_vec(db_params.clone())],
+				&db,
+			)
+			.await?;
+
+			sync.write_ops(
+				&db,
+				(
+					sync_params,
+					db.file_path()
+						.delete_many(vec![file_path::id::in_vec(db_params)]),
+				),
+			)
+			.await
+		})
+		.await
+		.map(
+			#[allow(clippy::cast_sign_loss)]
+			|count| count as u64,
+		)
+		.map_err(Into::into)
 }
 
 #[allow(clippy::missing_panics_doc)] // Can't actually panic as we only deal with directories
@@ -0,0 +1,600 @@
+use std::{
+	collections::HashMap,
+	path::{Path, PathBuf},
+	sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::task::spawn_blocking;
+use tracing::warn;
+
+/// Position in the USN journal of the NTFS volume holding a location, taken right before the
+/// location was fully indexed, so the next index only has to look at what changed since then.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsnJournalCursor {
+	journal_id: u64,
+	next_usn: i64,
+}
+
+impl UsnJournalCursor {
+	pub fn from_db(bytes: &[u8]) -> Option<Self> {
+		if bytes.len() != 16 {
+			return None;
+		}
+
+		let (journal_id, next_usn) = bytes.split_at(8);
+
+		Some(Self {
+			journal_id: u64::from_be_bytes(journal_id.try_into().ok()?),
+			next_usn: i64::from_be_bytes(next_usn.try_into().ok()?),
+		})
+	}
+
+	pub fn to_db(self) -> Vec<u8> {
+		let mut bytes = Vec::with_capacity(16);
+		bytes.extend_from_slice(&self.journal_id.to_be_bytes());
+		bytes.extend_from_slice(&self.next_usn.to_be_bytes());
+		bytes
+	}
+}
+
+/// Listing of every directory under a location, read straight from the NTFS Master File Table
+/// instead of opening each directory, so walkers can skip the `read_dir` step.
+///
+/// Files with multiple hard links only show up under one of their names in the MFT enumeration.
+#[derive(Debug, Default)]
+pub struct MftIndex {
+	children_by_dir: HashMap<PathBuf, Vec<PathBuf>>,
+}
+
+impl MftIndex {
+	pub fn read_dir(&self, path: impl AsRef<Path>) -> Option<Vec<PathBuf>> {
+		self.children_by_dir.get(path.as_ref()).cloned()
+	}
+}
+
+#[derive(Debug)]
+pub enum ScanPlan {
+	/// Walk the whole location, listing directories from the MFT when it could be read
+	Full {
+		mft_index: Option<Arc<MftIndex>>,
+		cursor: Option<UsnJournalCursor>,
+	},
+	/// Only walk the directories touched since the last full index
+	#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+	Incremental {
+		/// Directories which had entries created, removed, renamed or modified, walked without
+		/// entering their sub-directories
+		changed_dirs: Vec<PathBuf>,
+		/// Directories created or moved into the location, walked with all their sub-directories
+		arrived_dirs: Vec<PathBuf>,
+		cursor: UsnJournalCursor,
+	},
+}
+
+impl ScanPlan {
+	pub const fn portable() -> Self {
+		Self::Full {
+			mft_index: None,
+			cursor: None,
+		}
+	}
+}
+
+/// Decides how to index a location: incrementally from the USN journal when we have a cursor
+/// still covered by it, from the MFT when the location lives in a NTFS volume, or with the
+/// portable walker otherwise.
+///
+/// Reading the MFT and the USN journal requires a handle to the volume, which Windows only hands
+/// out to administrators, so without elevated privileges we fall back to the portable walker.
+pub async fn plan_scan(
+	location_path: Arc<PathBuf>,
+	last_cursor: Option<UsnJournalCursor>,
+) -> ScanPlan {
+	spawn_blocking(move || {
+		platform::plan_scan(&location_path, last_cursor)
+			.map_err(|e| {
+				warn!(
+					"Unable to use the NTFS fast-scan backend, falling back to walking directories \
+					<location_path='{}'>: {e:#?}",
+					location_path.display()
+				);
+			})
+			.ok()
+			.flatten()
+			.unwrap_or_else(ScanPlan::portable)
+	})
+	.await
+	.unwrap_or_else(|e| {
+		warn!("NTFS fast-scan backend task panicked: {e:#?}");
+		ScanPlan::portable()
+	})
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+	use std::{
+		collections::{HashMap, HashSet, VecDeque},
+		ffi::{OsStr, OsString},
+		fs::{File, OpenOptions},
+		io, iter, mem,
+		os::windows::{
+			ffi::{OsStrExt, OsStringExt},
+			fs::OpenOptionsExt,
+			io::AsRawHandle,
+		},
+		path::{Path, PathBuf},
+		sync::Arc,
+	};
+
+	use tracing::debug;
+	use windows::{
+		core::PCWSTR,
+		Win32::{
+			Foundation::{ERROR_HANDLE_EOF, ERROR_JOURNAL_ENTRY_DELETED, HANDLE},
+			Storage::FileSystem::{
+				GetFileInformationByHandle, GetVolumeInformationW,
+				GetVolumeNameForVolumeMountPointW, GetVolumePathNameW, BY_HANDLE_FILE_INFORMATION,
+				FILE_ATTRIBUTE_DIRECTORY, FILE_FLAG_BACKUP_SEMANTICS, FILE_SHARE_READ,
+				FILE_SHARE_WRITE,
+			},
+			System::{
+				Ioctl::{
+					FSCTL_ENUM_USN_DATA, FSCTL_QUERY_USN_JOURNAL, FSCTL_READ_USN_JOURNAL,
+					MFT_ENUM_DATA_V0, READ_USN_JOURNAL_DATA_V0, USN_JOURNAL_DATA_V0,
+					USN_REASON_FILE_CREATE, USN_REASON_RENAME_NEW_NAME,
+				},
+				IO::DeviceIoControl,
+			},
+		},
+	};
+
+	use super::{MftIndex, ScanPlan, UsnJournalCursor};
+
+	const BUFFER_SIZE: usize = 64 * 1024;
+	const MAX_PATH_LEN: usize = 32 * 1024;
+
+	/// A parsed `USN_RECORD_V2`, we only ask for major version 2 records
+	struct UsnRecord {
+		file_reference: u64,
+		parent_file_reference: u64,
+		reason: u32,
+		is_dir: bool,
+		name: OsString,
+	}
+
+	struct MftEntry {
+		parent_file_reference: u64,
+		is_dir: bool,
+		name: OsString,
+	}
+
+	pub fn plan_scan(
+		location_path: &Path,
+		last_cursor: Option<UsnJournalCursor>,
+	) -> Result<Option<ScanPlan>, io::Error> {
+		let Some(volume) = open_ntfs_volume(location_path)? else {
+			return Ok(None);
+		};
+
+		let root_file_reference = file_reference(location_path)?;
+		let journal = query_journal(&volume)?;
+
+		let cursor = UsnJournalCursor {
+			journal_id: journal.UsnJournalID,
+			next_usn: journal.NextUsn,
+		};
+
+		let entries = enumerate_mft(&volume, journal.NextUsn)?;
+		let (mft_index, dir_paths) = build_index(location_path, root_file_reference, &entries);
+
+		if let Some(last_cursor) = last_cursor.filter(|last_cursor| {
+			last_cursor.journal_id == journal.UsnJournalID
+				&& last_cursor.next_usn >= journal.FirstUsn
+		}) {
+			if let Some(records) = read_journal(&volume, last_cursor, journal.NextUsn)? {
+				let mut changed_dirs = HashSet::new();
+				let mut arrived_dirs = HashSet::new();
+
+				for record in records {
+					if let Some(parent_path) = dir_paths.get(&record.parent_file_reference) {
+						changed_dirs.insert(parent_path.clone());
+
+						if record.is_dir
+							&& record.reason & (USN_REASON_FILE_CREATE | USN_REASON_RENAME_NEW_NAME)
+								!= 0
+						{
+							arrived_dirs.insert(
+								dir_paths
+									.get(&record.file_reference)
+									.cloned()
+									.unwrap_or_else(|| parent_path.join(&record.name)),
+							);
+						}
+					}
+				}
+
+				let (changed_dirs, arrived_dirs) = collapse_dirs(
+					changed_dirs
+						.into_iter()
+						.filter(|dir| dir.is_dir())
+						.collect(),
+					arrived_dirs
+						.into_iter()
+						.filter(|dir| dir.is_dir())
+						.collect(),
+				);
+
+				return Ok(Some(ScanPlan::Incremental {
+					changed_dirs,
+					arrived_dirs,
+					cursor,
+				}));
+			}
+		}
+
+		Ok(Some(ScanPlan::Full {
+			mft_index: Some(Arc::new(mft_index)),
+			cursor: Some(cursor),
+		}))
+	}
+
+	fn to_wide(path: impl AsRef<OsStr>) -> Vec<u16> {
+		path.as_ref().encode_wide().chain(iter::once(0)).collect()
+	}
+
+	fn from_wide(buf: &[u16]) -> OsString {
+		OsString::from_wide(&buf[..buf.iter().position(|&c| c == 0).unwrap_or(buf.len())])
+	}
+
+	/// Opens the volume holding `path`, if it's a NTFS one
+	fn open_ntfs_volume(path: &Path) -> Result<Option<File>, io::Error> {
+		let wide_path = to_wide(path);
+		let mut mount_point = vec![0u16; MAX_PATH_LEN];
+
+		// SAFETY: `wide_path` is null terminated and outlives the call
+		unsafe { GetVolumePathNameW(PCWSTR(wide_path.as_ptr()), &mut mount_point) }
+			.map_err(io::Error::other)?;
+
+		let mut file_system_name = [0u16; 64];
+
+		// SAFETY: `mount_point` was null terminated by the previous call and outlives this one
+		unsafe {
+			GetVolumeInformationW(
+				PCWSTR(mount_point.as_ptr()),
+				None,
+				None,
+				None,
+				None,
+				Some(&mut file_system_name),
+			)
+		}
+		.map_err(io::Error::other)?;
+
+		if from_wide(&file_system_name) != "NTFS" {
+			return Ok(None);
+		}
+
+		let mut volume_name = vec![0u16; MAX_PATH_LEN];
+
+		// SAFETY: `mount_point` is null terminated and outlives the call
+		unsafe {
+			GetVolumeNameForVolumeMountPointW(PCWSTR(mount_point.as_ptr()), &mut volume_name)
+		}
+		.map_err(io::Error::other)?;
+
+		// Volume names end with a trailing backslash, which would open the root directory instead
+		let volume_name = from_wide(&volume_name);
+		let volume_name = volume_name.to_string_lossy();
+
+		OpenOptions::new()
+			.read(true)
+			.share_mode(FILE_SHARE_READ.0 | FILE_SHARE_WRITE.0)
+			.open(volume_name.trim_end_matches('\\'))
+			.map(Some)
+	}
+
+	fn file_reference(path: &Path) -> Result<u64, io::Error> {
+		let dir = OpenOptions::new()
+			.read(true)
+			.custom_flags(FILE_FLAG_BACKUP_SEMANTICS.0)
+			.open(path)?;
+
+		let mut info = BY_HANDLE_FILE_INFORMATION::default();
+
+		// SAFETY: `dir` keeps the handle open for the whole call and `info` outlives it
+		unsafe { GetFileInformationByHandle(HANDLE(dir.as_raw_handle() as isize), &mut info) }
+			.map_err(io::Error::other)?;
+
+		Ok((u64::from(info.nFileIndexHigh) << 32) | u64::from(info.nFileIndexLow))
+	}
+
+	// SAFETY: The struct size is a small constant, it fits in an u32
+	#[allow(clippy::cast_possible_truncation)]
+	fn query_journal(volume: &File) -> Result<USN_JOURNAL_DATA_V0, io::Error> {
+		let mut journal = USN_JOURNAL_DATA_V0::default();
+		let mut returned_bytes = 0;
+
+		// SAFETY: `journal` outlives the call and its size is the one we pass,
+		// `volume` keeps the handle open for the whole call
+		unsafe {
+			DeviceIoControl(
+				HANDLE(volume.as_raw_handle() as isize),
+				FSCTL_QUERY_USN_JOURNAL,
+				None,
+				0,
+				Some((&mut journal as *mut USN_JOURNAL_DATA_V0).cast()),
+				mem::size_of::<USN_JOURNAL_DATA_V0>() as u32,
+				Some(&mut returned_bytes),
+				None,
+			)
+		}
+		.map_err(io::Error::other)?;
+
+		Ok(journal)
+	}
+
+	// SAFETY: Buffer and struct sizes are small constants, they fit in an u32
+	#[allow(clippy::cast_possible_truncation)]
+	fn enumerate_mft(volume: &File, high_usn: i64) -> Result<HashMap<u64, MftEntry>, io::Error> {
+		let mut entries = HashMap::new();
+		let mut query = MFT_ENUM_DATA_V0 {
+			StartFileReferenceNumber: 0,
+			LowUsn: 0,
+			HighUsn: high_usn,
+		};
+		let mut buf = vec![0u8; BUFFER_SIZE];
+
+		loop {
+			let mut returned_bytes = 0;
+
+			// SAFETY: `query` and `buf` outlive the call and their sizes are the ones we pass,
+			// `volume` keeps the handle open for the whole call
+			let res = unsafe {
+				DeviceIoControl(
+					HANDLE(volume.as_raw_handle() as isize),
+					FSCTL_ENUM_USN_DATA,
+					Some((&query as *const MFT_ENUM_DATA_V0).cast()),
+					mem::size_of::<MFT_ENUM_DATA_V0>() as u32,
+					Some(buf.as_mut_ptr().cast()),
+					buf.len() as u32,
+					Some(&mut returned_bytes),
+					None,
+				)
+			};
+
+			match res {
+				Ok(()) => {}
+				Err(e) if e.code() == ERROR_HANDLE_EOF.to_hresult() => break,
+				Err(e) => return Err(io::Error::other(e)),
+			}
+
+			let returned = &buf[..returned_bytes as usize];
+			if returned.len() <= 8 {
+				break;
+			}
+
+			// The first 8 bytes hold where the next enumeration call must start from
+			query.StartFileReferenceNumber = read_u64(returned, 0);
+
+			entries.extend(parse_records(&returned[8..]).map(|record| {
+				(
+					record.file_reference,
+					MftEntry {
+						parent_file_reference: record.parent_file_reference,
+						is_dir: record.is_dir,
+						name: record.name,
+					},
+				)
+			}));
+		}
+
+		Ok(entries)
+	}
+
+	/// Reads every record from `cursor` up to `high_usn`, returning `None` if the journal was
+	/// truncated past our cursor in the meantime
+	// SAFETY: Buffer and struct sizes are small constants, they fit in an u32
+	#[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+	fn read_journal(
+		volume: &File,
+		cursor: UsnJournalCursor,
+		high_usn: i64,
+	) -> Result<Option<Vec<UsnRecord>>, io::Error> {
+		let mut records = vec![];
+		let mut query = READ_USN_JOURNAL_DATA_V0 {
+			StartUsn: cursor.next_usn,
+			ReasonMask: u32::MAX,
+			ReturnOnlyOnClose: 0,
+			Timeout: 0,
+			BytesToWaitFor: 0,
+			UsnJournalID: cursor.journal_id,
+		};
+		let mut buf = vec![0u8; BUFFER_SIZE];
+
+		while query.StartUsn < high_usn {
+			let mut returned_bytes = 0;
+
+			// SAFETY: `query` and `buf` outlive the call and their sizes are the ones we pass,
+			// `volume` keeps the handle open for the whole call
+			let res = unsafe {
+				DeviceIoControl(
+					HANDLE(volume.as_raw_handle() as isize),
+					FSCTL_READ_USN_JOURNAL,
+					Some((&query as *const READ_USN_JOURNAL_DATA_V0).cast()),
+					mem::size_of::<READ_USN_JOURNAL_DATA_V0>() as u32,
+					Some(buf.as_mut_ptr().cast()),
+					buf.len() as u32,
+					Some(&mut returned_bytes),
+					None,
+				)
+			};
+
+			match res {
+				Ok(()) => {}
+				Err(e) if e.code() == ERROR_JOURNAL_ENTRY_DELETED.to_hresult() => return Ok(None),
+				Err(e) => return Err(io::Error::other(e)),
+			}
+
+			let returned = &buf[..returned_bytes as usize];
+			if returned.len() <= 8 {
+				break;
+			}
+
+			// The first 8 bytes hold the USN to continue reading from
+			query.StartUsn = read_u64(returned, 0) as i64;
+
+			records.extend(parse_records(&returned[8..]));
+		}
+
+		Ok(Some(records))
+	}
+
+	/// Walks the MFT entries from the location root, building the listing of each directory and
+	/// the path of each directory by its file reference number
+	fn build_index(
+		location_path: &Path,
+		root_file_reference: u64,
+		entries: &HashMap<u64, MftEntry>,
+	) -> (MftIndex, HashMap<u64, PathBuf>) {
+		let mut children_by_parent = HashMap::<_, Vec<_>>::new();
+		for (file_reference, entry) in entries {
+			children_by_parent
+				.entry(entry.parent_file_reference)
+				.or_default()
+				.push(*file_reference);
+		}
+
+		let mut children_by_dir = HashMap::new();
+		let mut dir_paths = HashMap::new();
+		let mut to_visit = VecDeque::from([(root_file_reference, location_path.to_path_buf())]);
+
+		while let Some((dir_file_reference, dir_path)) = to_visit.pop_front() {
+			let children = children_by_parent
+				.get(&dir_file_reference)
+				.map(|children| {
+					children
+						.iter()
+						.filter_map(|file_reference| {
+							entries.get(file_reference).map(|entry| {
+								let path = dir_path.join(&entry.name);
+								if entry.is_dir {
+									to_visit.push_back((*file_reference, path.clone()));
+								}
+								path
+							})
+						})
+						.collect()
+				})
+				.unwrap_or_default();
+
+			children_by_dir.insert(dir_path.clone(), children);
+			dir_paths.insert(dir_file_reference, dir_path);
+		}
+
+		(MftIndex { children_by_dir }, dir_paths)
+	}
+
+	/// Parses a buffer of contiguous `USN_RECORD_V2` structs, skipping records of other versions
+	fn parse_records(mut buf: &[u8]) -> impl Iterator<Item = UsnRecord> + '_ {
+		iter::from_fn(move || loop {
+			if buf.len() < 60 {
+				return None;
+			}
+
+			let record_len = read_u32(buf, 0) as usize;
+			if record_len == 0 || record_len > buf.len() {
+				return None;
+			}
+
+			let (record, rest) = buf.split_at(record_len);
+			buf = rest;
+
+			if read_u16(record, 4) != 2 {
+				continue;
+			}
+
+			let name_len = usize::from(read_u16(record, 56));
+			let name_offset = usize::from(read_u16(record, 58));
+			let Some(name_bytes) = record.get(name_offset..name_offset + name_len) else {
+				continue;
+			};
+
+			return Some(UsnRecord {
+				file_reference: read_u64(record, 8),
+				parent_file_reference: read_u64(record, 16),
+				reason: read_u32(record, 40),
+				is_dir: read_u32(record, 52) & FILE_ATTRIBUTE_DIRECTORY.0 != 0,
+				name: OsString::from_wide(
+					&name_bytes
+						.chunks_exact(2)
+						.map(|c| u16::from_le_bytes([c[0], c[1]]))
+						.collect::<Vec<_>>(),
+				),
+			});
+		})
+	}
+
+	/// Keeps only the outermost directories of `arrived_dirs`, and the `changed_dirs` not already
+	/// covered by one of them
+	fn collapse_dirs(
+		mut changed_dirs: Vec<PathBuf>,
+		mut arrived_dirs: Vec<PathBuf>,
+	) -> (Vec<PathBuf>, Vec<PathBuf>) {
+		arrived_dirs.sort();
+		arrived_dirs.dedup();
+
+		let mut outermost_arrived_dirs = Vec::<PathBuf>::with_capacity(arrived_dirs.len());
+		for dir in arrived_dirs {
+			if !outermost_arrived_dirs
+				.iter()
+				.any(|outer_dir| dir.starts_with(outer_dir))
+			{
+				outermost_arrived_dirs.push(dir);
+			}
+		}
+
+		changed_dirs.sort();
+		changed_dirs.dedup();
+		changed_dirs.retain(|dir| {
+			!outermost_arrived_dirs
+				.iter()
+				.any(|arrived_dir| dir.starts_with(arrived_dir))
+		});
+
+		debug!(
+			"USN journal incremental scan: {} changed directories and {} arrived directories",
+			changed_dirs.len(),
+			outermost_arrived_dirs.len()
+		);
+
+		(changed_dirs, outermost_arrived_dirs)
+	}
+
+	fn read_u16(buf: &[u8], offset: usize) -> u16 {
+		u16::from_le_bytes([buf[offset], buf[offset + 1]])
+	}
+
+	fn read_u32(buf: &[u8], offset: usize) -> u32 {
+		u32::from_le_bytes(buf[offset..offset + 4].try_into().expect("4 bytes slice"))
+	}
+
+	fn read_u64(buf: &[u8], offset: usize) -> u64 {
+		u64::from_le_bytes(buf[offset..offset + 8].try_into().expect("8 bytes slice"))
+	}
+}
+
+#[cfg(not(target_os = "windows"))]
+mod platform {
+	use std::{io, path::Path};
+
+	use super::{ScanPlan, UsnJournalCursor};
+
+	#[allow(clippy::unnecessary_wraps)]
+	pub const fn plan_scan(
+		_: &Path,
+		_: Option<UsnJournalCursor>,
+	) -> Result<Option<ScanPlan>, io::Error> {
+		Ok(None)
+	}
+}
@@ -1,5 +1,6 @@
 use crate::{
-	indexer, utils::sub_path::get_full_path_from_sub_path, Error, NonCriticalError, OuterContext,
+	disk_usage, indexer, utils::sub_path::get_full_path_from_sub_path, Error, NonCriticalError,
+	OuterContext,
 };
 
 use sd_core_indexer_rules::{IndexerRule, IndexerRuler};
@@ -90,6 +91,8 @@ pub async fn shallow(
 	};
 
 	if indexed_count > 0 || removed_count > 0 || updated_count > 0 {
+		let directory = directory_iso_file_path.materialized_path_for_children();
+
 		update_directory_sizes(
 			HashMap::from([(directory_iso_file_path, total_size)]),
 			db,
@@ -110,6 +113,11 @@ pub async fn shallow(
 		}
 
 		update_location_size(location.id, db, &ctx).await?;
+
+		// Subdirectories weren't walked, only the files right inside the directory changed
+		if let Err(e) = disk_usage::refresh_directories(db, location.id, directory).await {
+			errors.push(indexer::NonCriticalError::DiskUsage(e.to_string()).into());
+		}
 	}
 
 	if indexed_count > 0 || removed_count > 0 {
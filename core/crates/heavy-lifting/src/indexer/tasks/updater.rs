@@ -1,4 +1,4 @@
-use crate::{indexer, Error};
+use crate::{file_identifier, indexer, Error};
 
 use sd_core_file_path_helper::IsolatedFilePathDataParts;
 use sd_core_sync::Manager as SyncManager;
@@ -15,6 +15,7 @@ use sd_utils::{chain_optional_iter, db::inode_to_db, msgpack};
 
 use std::{collections::HashSet, sync::Arc, time::Duration};
 
+use prisma_client_rust::QueryError;
 use serde::{Deserialize, Serialize};
 use tokio::time::Instant;
 use tracing::trace;
@@ -156,6 +157,11 @@ impl Task<Error> for UpdateTask {
 
 		check_interruption!(interrupter);
 
+		let pub_ids = &walked_entries
+			.iter()
+			.map(|entry| sd_utils::uuid_to_bytes(entry.pub_id))
+			.collect::<Vec<_>>();
+
 		let (sync_stuff, paths_to_update) = walked_entries
 			.drain(..)
 			.map(|entry| {
@@ -216,14 +222,47 @@ impl Task<Error> for UpdateTask {
 			})
 			.unzip::<_, _, Vec<_>, Vec<_>>();
 
-		let updated = sync
-			.write_ops(
-				db,
-				(sync_stuff.into_iter().flatten().collect(), paths_to_update),
-			)
+		let sync = &**sync;
+		let updated = db
+			._transaction()
+			.with_timeout(30 * 1000)
+			.run(|db| async move {
+				let before = file_identifier::statistics::snapshot(
+					vec![file_path::pub_id::in_vec(pub_ids.clone())],
+					&db,
+				)
+				.await?;
+
+				let updated = sync
+					.write_ops(
+						&db,
+						(sync_stuff.into_iter().flatten().collect(), paths_to_update),
+					)
+					.await?;
+
+				file_identifier::statistics::snapshot(
+					vec![file_path::pub_id::in_vec(pub_ids.clone())],
+					&db,
+				)
+				.await?
+				.since(before)
+				.commit(&db)
+				.await?;
+
+				Ok::<_, QueryError>(updated)
+			})
 			.await
 			.map_err(indexer::Error::from)?;
 
+		// The content of these files changed, so entries walked from archives among them are stale
+		file_identifier::remove_archive_entries(
+			updated.iter().map(|file_path| file_path.id).collect(),
+			db,
+			sync,
+		)
+		.await
+		.map_err(indexer::Error::from)?;
+
 		trace!("Updated {updated:?} records");
 
 		Ok(ExecStatus::Done(
@@ -1,8 +1,11 @@
-use crate::{indexer, Error, NonCriticalError};
+use crate::{
+	indexer::{self, ntfs::MftIndex},
+	Error, NonCriticalError,
+};
 
 use sd_core_file_path_helper::{FilePathError, FilePathMetadata, IsolatedFilePathData};
 use sd_core_indexer_rules::{
-	seed::{GitIgnoreRules, GITIGNORE},
+	seed::{GitIgnoreRules, SdIgnoreRules, GITIGNORE},
 	IndexerRuler, MetadataForIndexerRules, RuleKind,
 };
 use sd_core_prisma_helpers::{file_path_pub_and_cas_ids, file_path_walker};
@@ -368,6 +371,7 @@ where
 	db_proxy: DBProxy,
 	stage: WalkerStage,
 	maybe_dispatcher: Option<Dispatcher>,
+	mft_index: Option<Arc<MftIndex>>,
 	errors: Vec<NonCriticalError>,
 	scan_time: Duration,
 	is_shallow: bool,
@@ -398,11 +402,27 @@ where
 			stage: WalkerStage::Start,
 			entry,
 			maybe_dispatcher: Some(dispatcher),
+			mft_index: None,
 			is_shallow: false,
 			errors: Vec::new(),
 			scan_time: Duration::ZERO,
 		})
 	}
+
+	/// Lists directories from a snapshot of the NTFS Master File Table instead of reading them
+	/// from disk, passing it along to the tasks walking the sub-directories
+	#[must_use]
+	pub fn with_mft_index(mut self, mft_index: Option<Arc<MftIndex>>) -> Self {
+		self.mft_index = mft_index;
+		self
+	}
+
+	/// Only walks the entry directory itself, without dispatching tasks for its sub-directories
+	#[must_use]
+	pub fn without_keep_walking(mut self) -> Self {
+		self.maybe_dispatcher = None;
+		self
+	}
 }
 
 impl<DBProxy, IsoPathFactory> WalkDirTask<DBProxy, IsoPathFactory, BaseTaskDispatcher<Error>>
@@ -428,6 +448,7 @@ where
 			stage: WalkerStage::Start,
 			entry,
 			maybe_dispatcher: None,
+			mft_index: None,
 			is_shallow: true,
 			errors: Vec::new(),
 			scan_time: Duration::ZERO,
@@ -494,6 +515,7 @@ where
 				db_proxy,
 				stage: stage.into(),
 				maybe_dispatcher: is_shallow.then_some(dispatcher),
+				mft_index: None,
 				errors,
 				scan_time,
 				is_shallow,
@@ -533,6 +555,7 @@ where
 			db_proxy,
 			stage,
 			maybe_dispatcher,
+			mft_index,
 			errors,
 			scan_time,
 			..
@@ -551,16 +574,24 @@ where
 						}
 					}
 
-					*stage = WalkerStage::Walking {
-						read_dir_stream: ReadDirStream::new(fs::read_dir(&path).await.map_err(
-							|e| {
-								indexer::Error::FileIO(
-									(&path, e, "Failed to open directory to read its entries")
-										.into(),
-								)
-							},
-						)?),
-						found_paths: Vec::new(),
+					// Directories listed in the MFT snapshot don't need to be read from disk
+					*stage = if let Some(found_paths) = mft_index
+						.as_ref()
+						.and_then(|mft_index| mft_index.read_dir(&path))
+					{
+						WalkerStage::CollectingMetadata { found_paths }
+					} else {
+						WalkerStage::Walking {
+							read_dir_stream: ReadDirStream::new(
+								fs::read_dir(&path).await.map_err(|e| {
+									indexer::Error::FileIO(
+										(&path, e, "Failed to open directory to read its entries")
+											.into(),
+									)
+								})?,
+							),
+							found_paths: Vec::new(),
+						}
 					};
 				}
 
@@ -603,10 +634,24 @@ where
 				WalkerStage::CheckingIndexerRules {
 					paths_and_metadatas,
 				} => {
+					// `.sdignore` files only apply to their own directories, so they're layered on
+					// a ruler of our own instead of extending the one shared with other walkers
+					let sd_ignore_ruler = match SdIgnoreRules::get_rules(root.as_ref(), path).await
+					{
+						Some(Ok(rules)) => Some(indexer_ruler.layered([rules.into()]).await),
+						Some(Err(e)) => {
+							errors.push(NonCriticalError::Indexer(
+								indexer::NonCriticalError::IndexerRule(e.to_string()),
+							));
+							None
+						}
+						None => None,
+					};
+
 					*stage = WalkerStage::ProcessingRulesResults {
 						paths_metadatas_and_acceptance: apply_indexer_rules(
 							paths_and_metadatas,
-							indexer_ruler,
+							sd_ignore_ruler.as_ref().unwrap_or(indexer_ruler),
 							errors,
 						)
 						.await,
@@ -679,6 +724,7 @@ where
 						db_proxy,
 						maybe_to_keep_walking,
 						maybe_dispatcher,
+						mft_index.as_ref(),
 						errors,
 					)
 					.await;
@@ -793,6 +839,7 @@ async fn segregate_creates_and_updates(
 	}
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn keep_walking(
 	root: &Arc<PathBuf>,
 	indexer_ruler: &IndexerRuler,
@@ -800,6 +847,7 @@ async fn keep_walking(
 	db_proxy: &impl WalkerDBProxy,
 	maybe_to_keep_walking: &mut Option<Vec<ToWalkEntry>>,
 	dispatcher: &Option<impl TaskDispatcher<Error>>,
+	mft_index: Option<&Arc<MftIndex>>,
 	errors: &mut Vec<NonCriticalError>,
 ) -> Vec<TaskHandle<Error>> {
 	if let (Some(dispatcher), Some(to_keep_walking)) = (dispatcher, maybe_to_keep_walking) {
@@ -816,6 +864,7 @@ async fn keep_walking(
 							db_proxy.clone(),
 							dispatcher.clone(),
 						)
+						.map(|task| task.with_mft_index(mft_index.cloned()))
 						.map_err(|e| indexer::NonCriticalError::DispatchKeepWalking(e.to_string()))
 					})
 					.filter_map(|res| res.map_err(|e| errors.push(e.into())).ok()),
@@ -912,6 +961,15 @@ async fn process_rules_results(
 				return (accepted, accepted_ancestors);
 			}
 
+			if rejected_by_git_ignore(&acceptance_per_rule_kind) {
+				trace!(
+					"Path {} rejected by `RuleKind::IgnoredByGit`",
+					current_path.display()
+				);
+
+				return (accepted, accepted_ancestors);
+			}
+
 			let is_dir = metadata.is_dir();
 
 			if is_dir
@@ -1087,6 +1145,14 @@ fn rejected_by_reject_glob(acceptance_per_rule_kind: &HashMap<RuleKind, Vec<bool
 		})
 }
 
+fn rejected_by_git_ignore(acceptance_per_rule_kind: &HashMap<RuleKind, Vec<bool>>) -> bool {
+	acceptance_per_rule_kind
+		.get(&RuleKind::IgnoredByGit)
+		.map_or(false, |accept_results| {
+			accept_results.iter().any(|accept| !accept)
+		})
+}
+
 async fn gather_file_paths_to_remove(
 	accepted_paths: &mut HashMap<PathBuf, InnerMetadata>,
 	entry_iso_file_path: &IsolatedFilePathData<'_>,
@@ -0,0 +1,484 @@
+use sd_task_system::ResourceProfile;
+
+use std::collections::VecDeque;
+
+use serde::Serialize;
+use specta::Type;
+
+use super::{
+	job::{DynJob, IntoJob, Job, JobName, OuterContext},
+	report::{Report, ReportInputMetadata, ReportMetadata},
+	JobId, SerializableJob,
+};
+
+/// Jobs run one after the other on the same location, e.g. indexing, then identifying, then
+/// processing media. Each stage only starts after the previous one completed, even with
+/// non-critical errors, and a failed or canceled stage cancels the remaining ones.
+///
+/// Stages are reported as children of the first one, which identifies the chain. On shutdown the
+/// remaining stages are stored along with the running one, so the chain resumes where it stopped.
+pub struct JobChain<Ctx: OuterContext> {
+	action: Option<String>,
+	resource_profile: Option<ResourceProfile>,
+	stages: Vec<Box<dyn DynJob<Ctx>>>,
+}
+
+/// How far a running chain is, by stages as jobs don't share a common progress unit
+#[derive(Debug, Clone, Serialize, Type)]
+pub struct ChainProgress {
+	pub chain_id: JobId,
+	pub current_stage: JobName,
+	pub current_stage_task_count: i32,
+	pub current_stage_completed_task_count: i32,
+	pub completed_stages: u32,
+	pub total_stages: u32,
+	pub remaining_stages: Vec<JobName>,
+}
+
+impl<Ctx: OuterContext> JobChain<Ctx> {
+	pub fn new<J: Job + SerializableJob<Ctx>>(first: J) -> Self {
+		Self {
+			action: None,
+			resource_profile: None,
+			stages: vec![IntoJob::<J, Ctx>::into_job(first)],
+		}
+	}
+
+	/// Runs `next` after the stages added so far succeed
+	#[must_use]
+	pub fn then<J: Job + SerializableJob<Ctx>>(mut self, next: J) -> Self {
+		self.stages.push(IntoJob::<J, Ctx>::into_job(next));
+		self
+	}
+
+	/// Action of the first stage, the others are suffixed with their position in the chain
+	#[must_use]
+	pub fn with_action(mut self, action: impl Into<String>) -> Self {
+		self.action = Some(action.into());
+		self
+	}
+
+	/// Profile followed by every stage, see
+	/// [`JobBuilder::with_resource_profile`](super::job::JobBuilder::with_resource_profile)
+	#[must_use]
+	pub const fn with_resource_profile(mut self, profile: ResourceProfile) -> Self {
+		self.resource_profile = Some(profile);
+		self
+	}
+
+	/// Id of the first stage, by which the chain is tracked
+	#[must_use]
+	pub fn id(&self) -> JobId {
+		self.stages[0].id()
+	}
+
+	/// The first stage, holding the remaining ones as its next jobs
+	pub(super) fn build(self) -> Box<dyn DynJob<Ctx>> {
+		let Self {
+			action,
+			resource_profile,
+			stages,
+		} = self;
+
+		let chain_id = stages[0].id();
+		#[allow(clippy::cast_possible_truncation)]
+		// SAFETY: no one chains 4 billion jobs
+		let total_stages = stages.len() as u32;
+
+		let mut stages = stages
+			.into_iter()
+			.zip(1..)
+			.map(|(mut stage, stage_number)| {
+				let report = stage.report_mut();
+
+				if let Some(action) = &action {
+					report.action = Some(if stage_number == 1 {
+						action.clone()
+					} else {
+						format!("{action}-{}", stage_number - 1)
+					});
+				}
+
+				if stage_number != 1 {
+					report.parent_id = Some(chain_id);
+				}
+
+				report
+					.metadata
+					.push(ReportMetadata::Input(ReportInputMetadata::ChainStage {
+						stage: stage_number,
+						total_stages,
+					}));
+
+				if let Some(profile) = resource_profile {
+					report.metadata.push(ReportMetadata::Input(
+						ReportInputMetadata::ResourceProfile(profile),
+					));
+				}
+
+				stage
+			})
+			.collect::<VecDeque<_>>();
+
+		let mut first = stages
+			.pop_front()
+			.expect("chains always have a first stage");
+		first.set_next_jobs(stages);
+
+		first
+	}
+}
+
+impl ChainProgress {
+	/// `None` if `current` isn't a stage of a chain
+	pub(super) fn new<Ctx: OuterContext>(
+		current: &Report,
+		remaining: &VecDeque<Box<dyn DynJob<Ctx>>>,
+	) -> Option<Self> {
+		current.metadata.iter().find_map(|metadata| {
+			let ReportMetadata::Input(ReportInputMetadata::ChainStage {
+				stage,
+				total_stages,
+			}) = metadata
+			else {
+				return None;
+			};
+
+			Some(Self {
+				chain_id: current.parent_id.unwrap_or(current.id),
+				current_stage: current.name,
+				current_stage_task_count: current.task_count,
+				current_stage_completed_task_count: current.completed_task_count,
+				completed_stages: stage - 1,
+				total_stages: *total_stages,
+				remaining_stages: remaining.iter().map(|next| next.job_name()).collect(),
+			})
+		})
+	}
+}
+
+#[cfg(all(test, unix))]
+#[allow(clippy::unwrap_used)]
+mod tests {
+	use crate::{
+		crypto::KeyManager,
+		job_system::report::Status,
+		script_runner::{ScriptCommand, ScriptRunner},
+		Error, JobSystem, ProgressUpdate, UpdateEvent,
+	};
+
+	use sd_core_sync::Manager as SyncManager;
+
+	use sd_prisma::prisma::{job, location, PrismaClient};
+	use sd_task_system::TaskSystem;
+	use sd_utils::uuid_to_bytes;
+
+	use std::{
+		collections::HashMap,
+		path::{Path, PathBuf},
+		pin::pin,
+		sync::{atomic::AtomicBool, Arc, Mutex},
+		time::Duration,
+	};
+
+	use chrono::Utc;
+	use futures::StreamExt;
+	use tempfile::{tempdir, TempDir};
+	use tokio::{fs, time::timeout};
+	use uuid::Uuid;
+
+	use super::*;
+
+	#[derive(Clone)]
+	struct TestContext {
+		id: Uuid,
+		db: Arc<PrismaClient>,
+		sync: Arc<SyncManager>,
+		data_directory: PathBuf,
+		key_manager: Arc<KeyManager>,
+		finished: Arc<Mutex<Vec<(JobId, Status, location::id::Type)>>>,
+	}
+
+	impl OuterContext for TestContext {
+		fn id(&self) -> Uuid {
+			self.id
+		}
+
+		fn db(&self) -> &Arc<PrismaClient> {
+			&self.db
+		}
+
+		fn sync(&self) -> &Arc<SyncManager> {
+			&self.sync
+		}
+
+		fn invalidate_query(&self, _: &'static str) {}
+
+		fn query_invalidator(&self) -> impl Fn(&'static str) + Send + Sync {
+			|_| {}
+		}
+
+		fn progress(&self, _: Vec<ProgressUpdate>) {}
+
+		fn report_update(&self, _: UpdateEvent) {}
+
+		fn get_data_directory(&self) -> &Path {
+			&self.data_directory
+		}
+
+		fn key_manager(&self) -> &Arc<KeyManager> {
+			&self.key_manager
+		}
+
+		async fn deep_hash_threshold(&self) -> Option<u64> {
+			None
+		}
+
+		async fn job_finished(&self, report: &Report, location_id: location::id::Type) {
+			self.finished
+				.lock()
+				.unwrap()
+				.push((report.id, report.status, location_id));
+		}
+	}
+
+	struct Setup {
+		dir: TempDir,
+		ctx: TestContext,
+		location: location::Data,
+		task_system: TaskSystem<Error>,
+		job_system: JobSystem<TestContext>,
+	}
+
+	impl Setup {
+		async fn new() -> Self {
+			let dir = tempdir().unwrap();
+			let instance_id = Uuid::new_v4();
+
+			let db = Arc::new(
+				PrismaClient::_builder()
+					.with_url(format!("file:{}", dir.path().join("library.db").display()))
+					.build()
+					.await
+					.unwrap(),
+			);
+			db._db_push().await.unwrap();
+
+			db.instance()
+				.create(
+					uuid_to_bytes(instance_id),
+					vec![],
+					vec![],
+					Utc::now().into(),
+					Utc::now().into(),
+					vec![],
+				)
+				.exec()
+				.await
+				.unwrap();
+
+			let sync = Arc::new(
+				SyncManager::new(
+					&db,
+					instance_id,
+					&Arc::new(AtomicBool::new(true)),
+					HashMap::new(),
+					&Default::default(),
+				)
+				.await
+				.manager,
+			);
+
+			let location_path = dir.path().join("location");
+			fs::create_dir(&location_path).await.unwrap();
+
+			let location = db
+				.location()
+				.create(
+					uuid_to_bytes(Uuid::new_v4()),
+					vec![location::path::set(Some(
+						location_path.to_string_lossy().into_owned(),
+					))],
+				)
+				.exec()
+				.await
+				.unwrap();
+
+			let task_system = TaskSystem::new();
+			let job_system = JobSystem::new(task_system.get_dispatcher(), dir.path());
+
+			Self {
+				ctx: TestContext {
+					id: Uuid::new_v4(),
+					db,
+					sync,
+					data_directory: dir.path().to_path_buf(),
+					key_manager: Arc::new(KeyManager::new()),
+					finished: Arc::default(),
+				},
+				dir,
+				location,
+				task_system,
+				job_system,
+			}
+		}
+
+		fn location_path(&self) -> PathBuf {
+			self.dir.path().join("location")
+		}
+
+		fn script(&self, script: &str) -> ScriptRunner {
+			ScriptRunner::new(
+				&self.location,
+				None,
+				ScriptCommand {
+					program: "sh".into(),
+					args: vec!["-c".into(), script.into()],
+				},
+			)
+			.unwrap()
+		}
+
+		async fn dispatch(&self, chain: JobChain<TestContext>) -> JobId {
+			self.job_system
+				.dispatch_chain(chain, self.location.id, self.ctx.clone())
+				.await
+				.unwrap()
+		}
+
+		/// Outputs of the next `count` jobs to finish
+		async fn outputs(&self, count: usize) -> Vec<(JobId, bool)> {
+			let outputs = pin!(self.job_system.receive_job_outputs());
+
+			timeout(
+				Duration::from_secs(30),
+				outputs
+					.take(count)
+					.map(|(id, res)| (id, res.is_ok()))
+					.collect::<Vec<_>>(),
+			)
+			.await
+			.unwrap()
+		}
+
+		async fn status_of_stage(&self, action: &str) -> Status {
+			let job = self
+				.ctx
+				.db
+				.job()
+				.find_first(vec![job::action::equals(Some(action.to_string()))])
+				.exec()
+				.await
+				.unwrap()
+				.unwrap();
+
+			Status::try_from(job.status.unwrap()).unwrap()
+		}
+
+		async fn shutdown(self) {
+			self.job_system.shutdown().await;
+			self.task_system.shutdown().await;
+		}
+	}
+
+	#[tokio::test]
+	async fn stages_run_in_order() {
+		let setup = Setup::new().await;
+
+		let chain = JobChain::new(setup.script("echo 1 >> order"))
+			.then(setup.script("echo 2 >> order"))
+			.then(setup.script("echo 3 >> order"))
+			.with_action("test");
+		let chain_id = setup.dispatch(chain).await;
+
+		let outputs = setup.outputs(3).await;
+		assert_eq!(outputs[0], (chain_id, true));
+		assert!(outputs.iter().all(|(_, ok)| *ok));
+
+		assert_eq!(
+			fs::read_to_string(setup.location_path().join("order"))
+				.await
+				.unwrap(),
+			"1\n2\n3\n"
+		);
+		assert_eq!(setup.status_of_stage("test-2").await, Status::Completed);
+		assert!(setup.job_system.chain_progress(chain_id).await.is_none());
+
+		setup.shutdown().await;
+	}
+
+	#[tokio::test]
+	async fn failed_stage_cancels_the_next_ones() {
+		let setup = Setup::new().await;
+
+		let chain = JobChain::new(setup.script("echo 1 >> order"))
+			.then(setup.script("exit 3"))
+			.then(setup.script("echo 3 >> order"))
+			.with_action("test");
+		let chain_id = setup.dispatch(chain).await;
+
+		let outputs = setup.outputs(2).await;
+		assert_eq!(outputs[0], (chain_id, true));
+		assert!(!outputs[1].1);
+
+		assert_eq!(setup.status_of_stage("test-1").await, Status::Failed);
+		assert_eq!(setup.status_of_stage("test-2").await, Status::Canceled);
+		assert_eq!(
+			fs::read_to_string(setup.location_path().join("order"))
+				.await
+				.unwrap(),
+			"1\n"
+		);
+
+		setup.shutdown().await;
+	}
+
+	#[tokio::test]
+	async fn finished_stages_are_handed_to_the_context() {
+		let setup = Setup::new().await;
+
+		let chain = JobChain::new(setup.script("true")).then(setup.script("exit 3"));
+		let chain_id = setup.dispatch(chain).await;
+
+		let outputs = setup.outputs(2).await;
+
+		assert_eq!(
+			*setup.ctx.finished.lock().unwrap(),
+			vec![
+				(chain_id, Status::Completed, setup.location.id),
+				(outputs[1].0, Status::Failed, setup.location.id),
+			]
+		);
+
+		setup.shutdown().await;
+	}
+
+	#[tokio::test]
+	async fn progress_is_reported_by_stages() {
+		let setup = Setup::new().await;
+
+		let chain = JobChain::new(setup.script("while [ ! -f go ]; do sleep 0.05; done"))
+			.then(setup.script("echo 2 >> order"))
+			.then(setup.script("echo 3 >> order"));
+		let chain_id = setup.dispatch(chain).await;
+
+		let progress = setup.job_system.chain_progress(chain_id).await.unwrap();
+		assert_eq!(progress.chain_id, chain_id);
+		assert_eq!(progress.current_stage, JobName::ScriptRunner);
+		assert_eq!(progress.completed_stages, 0);
+		assert_eq!(progress.total_stages, 3);
+		assert_eq!(
+			progress.remaining_stages,
+			vec![JobName::ScriptRunner, JobName::ScriptRunner]
+		);
+
+		fs::write(setup.location_path().join("go"), b"")
+			.await
+			.unwrap();
+
+		assert_eq!(setup.outputs(3).await.len(), 3);
+		assert!(setup.job_system.chain_progress(chain_id).await.is_none());
+
+		setup.shutdown().await;
+	}
+}
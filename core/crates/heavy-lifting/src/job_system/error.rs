@@ -1,10 +1,11 @@
 use crate::Error;
 
+use sd_prisma::prisma::location;
 use sd_utils::error::FileIOError;
 
 use prisma_client_rust::QueryError;
 
-use super::{job::JobName, report::ReportError, JobId};
+use super::{job::JobName, report::ReportError, schedule::ScheduleError, JobId};
 
 #[derive(thiserror::Error, Debug)]
 pub enum JobSystemError {
@@ -35,6 +36,15 @@ pub enum JobSystemError {
 	#[error(transparent)]
 	Report(#[from] ReportError),
 
+	#[error(transparent)]
+	Schedule(#[from] ScheduleError),
+
+	#[error("failed to load location of scheduled job: {0}")]
+	ScheduledJobLocation(QueryError),
+
+	#[error("location of scheduled job not found: <location_id='{0}'>")]
+	ScheduledJobLocationNotFound(location::id::Type),
+
 	#[error(transparent)]
 	Processing(#[from] Error),
 }
@@ -54,6 +64,7 @@ impl From<JobSystemError> for rspc::Error {
 			}
 			JobSystemError::Processing(e) => e.into(),
 			JobSystemError::Report(e) => e.into(),
+			JobSystemError::Schedule(e) => e.into(),
 
 			_ => Self::with_cause(rspc::ErrorCode::InternalServerError, e.to_string(), e),
 		}
@@ -0,0 +1,103 @@
+use crate::{JobName, NonCriticalError};
+
+use sd_prisma::prisma::{file_path, job_error, location, PrismaClient, SortOrder};
+
+use chrono::{DateTime, FixedOffset, Utc};
+use prisma_client_rust::QueryError;
+use tracing::warn;
+
+/// Persists non-critical errors tied to file paths in the `job_error` table, so they outlive the
+/// job report and the failed file paths can be retried later. Each file path only keeps the last
+/// error a job had with it.
+pub async fn record_failures(
+	db: &PrismaClient,
+	job_name: JobName,
+	location_id: location::id::Type,
+	failures: Vec<(file_path::id::Type, NonCriticalError)>,
+) -> Result<(), QueryError> {
+	if failures.is_empty() {
+		return Ok(());
+	}
+
+	let job_name = job_name.to_string();
+	let date_created: DateTime<FixedOffset> = Utc::now().into();
+
+	let file_path_ids = failures
+		.iter()
+		.map(|(file_path_id, _)| *file_path_id)
+		.collect::<Vec<_>>();
+
+	let errors_to_create = failures
+		.into_iter()
+		.map(|(file_path_id, error)| {
+			job_error::create_unchecked(vec![
+				job_error::job_name::set(Some(job_name.clone())),
+				job_error::error::set(
+					rmp_serde::to_vec_named(&error)
+						.map_err(|e| warn!("Failed to serialize job error: {e:#?}"))
+						.ok(),
+				),
+				job_error::message::set(Some(error.to_string())),
+				job_error::location_id::set(Some(location_id)),
+				job_error::file_path_id::set(Some(file_path_id)),
+				job_error::date_created::set(Some(date_created)),
+			])
+		})
+		.collect();
+
+	db._batch((
+		db.job_error().delete_many(vec![
+			job_error::job_name::equals(Some(job_name)),
+			job_error::file_path_id::in_vec(file_path_ids),
+		]),
+		db.job_error().create_many(errors_to_create),
+	))
+	.await?;
+
+	Ok(())
+}
+
+/// Removes the errors a job had with file paths it just processed successfully
+pub async fn clear_failures(
+	db: &PrismaClient,
+	job_name: JobName,
+	file_path_ids: Vec<file_path::id::Type>,
+) -> Result<(), QueryError> {
+	if file_path_ids.is_empty() {
+		return Ok(());
+	}
+
+	db.job_error()
+		.delete_many(vec![
+			job_error::job_name::equals(Some(job_name.to_string())),
+			job_error::file_path_id::in_vec(file_path_ids),
+		])
+		.exec()
+		.await?;
+
+	Ok(())
+}
+
+/// File paths of a location that a job failed to process, oldest failures first
+pub async fn failed_file_path_ids(
+	db: &PrismaClient,
+	job_name: JobName,
+	location_id: location::id::Type,
+) -> Result<Vec<file_path::id::Type>, QueryError> {
+	db.job_error()
+		.find_many(vec![
+			job_error::job_name::equals(Some(job_name.to_string())),
+			job_error::location_id::equals(Some(location_id)),
+			job_error::file_path_id::not(None),
+		])
+		.order_by(job_error::id::order(SortOrder::Asc))
+		.select(job_error::select!({ file_path_id }))
+		.exec()
+		.await
+		.map(|errors| {
+			errors
+				.into_iter()
+				.filter_map(|job_error| job_error.file_path_id)
+				.collect()
+		})
+}
@@ -1,10 +1,14 @@
-use crate::{Error, NonCriticalError, UpdateEvent};
+use crate::{
+	crypto::KeyManager, duplicate_finder::DuplicatesReport, file_identifier::PriorityLane,
+	utils::io_throttle::IoThrottle, Error, NonCriticalError, UpdateEvent,
+};
 
 use sd_core_sync::Manager as SyncManager;
 
-use sd_prisma::prisma::PrismaClient;
+use sd_prisma::prisma::{location, PrismaClient};
 use sd_task_system::{
-	BaseTaskDispatcher, Task, TaskDispatcher, TaskHandle, TaskRemoteController, TaskSystemError,
+	BaseTaskDispatcher, ResourceProfile, Task, TaskDispatcher, TaskHandle, TaskRemoteController,
+	TaskSystemError,
 };
 
 use std::{
@@ -14,6 +18,7 @@ use std::{
 	path::Path,
 	pin::pin,
 	sync::Arc,
+	time::Duration,
 };
 
 use async_channel as chan;
@@ -30,7 +35,7 @@ use tokio::{
 	spawn,
 	sync::{watch, Mutex},
 };
-use tracing::{debug, error, info, warn};
+use tracing::{debug, error, info, info_span, warn, Instrument};
 use uuid::Uuid;
 
 use super::{
@@ -48,6 +53,30 @@ pub enum JobName {
 	Indexer,
 	FileIdentifier,
 	MediaProcessor,
+	DuplicateFinder,
+	VerifyIntegrity,
+	FileCopier,
+	FileMover,
+	Backup,
+	FolderSync,
+	ColdArchiver,
+	DiskUsageAnalyzer,
+	BulkRename,
+	Compressor,
+	Extractor,
+	FileEncryptor,
+	FileDecryptor,
+	ChecksumExporter,
+	ChecksumImporter,
+	TextExtractor,
+	TagRuleApplier,
+	ScriptRunner,
+	#[cfg(feature = "ai")]
+	ImageLabeler,
+	#[cfg(feature = "ai")]
+	Embedder,
+	#[cfg(feature = "transcription")]
+	Transcriber,
 	// TODO: Add more job names as needed
 }
 
@@ -62,6 +91,7 @@ pub enum ProgressUpdate {
 	CompletedTaskCount(u64),
 	Message(String),
 	Phase(String),
+	Metrics(JobProgressMetrics),
 }
 
 impl ProgressUpdate {
@@ -74,6 +104,75 @@ impl ProgressUpdate {
 	}
 }
 
+/// Throughput of a running job, so the UI can show more than task counts
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Type)]
+pub struct JobProgressMetrics {
+	pub files_per_second: f64,
+	/// Content size of hashed files per second, sampled hashing reads only a fraction of it
+	pub bytes_hashed_per_second: f64,
+	/// `None` until at least one file was processed
+	pub estimated_completion: Option<DateTime<Utc>>,
+	pub phases: Vec<PhaseMetrics>,
+}
+
+/// Time spent in one of the phases of a job, summed across all its tasks
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct PhaseMetrics {
+	pub name: String,
+	pub elapsed_secs: f64,
+}
+
+impl JobProgressMetrics {
+	/// Rates are averaged over `elapsed`, and the remaining files are expected to go at the same pace
+	#[must_use]
+	#[allow(clippy::cast_precision_loss)]
+	// SAFETY: f64 precision is more than enough for rates shown in the UI
+	pub fn new(
+		processed_files: u64,
+		total_files: u64,
+		hashed_bytes: u64,
+		elapsed: Duration,
+	) -> Self {
+		let elapsed_secs = elapsed.as_secs_f64();
+
+		let (files_per_second, bytes_hashed_per_second) = if elapsed_secs > 0.0 {
+			(
+				processed_files as f64 / elapsed_secs,
+				hashed_bytes as f64 / elapsed_secs,
+			)
+		} else {
+			(0.0, 0.0)
+		};
+
+		let estimated_completion = (files_per_second > 0.0)
+			.then(|| {
+				Duration::try_from_secs_f64(
+					total_files.saturating_sub(processed_files) as f64 / files_per_second,
+				)
+				.ok()
+			})
+			.flatten()
+			.and_then(|remaining| chrono::Duration::from_std(remaining).ok())
+			.and_then(|remaining| Utc::now().checked_add_signed(remaining));
+
+		Self {
+			files_per_second,
+			bytes_hashed_per_second,
+			estimated_completion,
+			phases: Vec::new(),
+		}
+	}
+
+	#[must_use]
+	pub fn with_phase(mut self, name: impl Into<String>, elapsed: Duration) -> Self {
+		self.phases.push(PhaseMetrics {
+			name: name.into(),
+			elapsed_secs: elapsed.as_secs_f64(),
+		});
+		self
+	}
+}
+
 pub trait OuterContext: Send + Sync + Clone + 'static {
 	fn id(&self) -> Uuid;
 	fn db(&self) -> &Arc<PrismaClient>;
@@ -86,6 +185,39 @@ pub trait OuterContext: Send + Sync + Clone + 'static {
 	}
 	fn report_update(&self, update: UpdateEvent);
 	fn get_data_directory(&self) -> &Path;
+	/// Keys of the library mounted with their passwords, that files are encrypted with
+	fn key_manager(&self) -> &Arc<KeyManager>;
+	/// Files up to this size get a `cas_id` of their entire content, see
+	/// [`CasIdAlgorithm::for_file_size`](crate::file_identifier::CasIdAlgorithm::for_file_size).
+	/// Set per library, so every job hashing files agrees with the `cas_id`s of the identifier.
+	fn deep_hash_threshold(&self) -> impl Future<Output = Option<u64>> + Send;
+	/// Disk read cap set on the node for jobs named `job_name`, the same bucket is handed to every
+	/// job it covers so they're capped together
+	#[allow(unused_variables)]
+	fn io_throttle(&self, job_name: JobName) -> IoThrottle {
+		IoThrottle::default()
+	}
+	/// The context handed to the job `id` when it starts or resumes, so the progress it reports
+	/// can be told apart from the one of other jobs sharing this context
+	#[allow(unused_variables)]
+	#[must_use]
+	fn for_job(&self, id: JobId) -> Self {
+		self.clone()
+	}
+	/// Hands over the lane of a file identifier running on `location_id` each time it starts or
+	/// resumes, so directories can be prioritized from outside the job system
+	#[allow(unused_variables)]
+	fn register_priority_lane(&self, location_id: location::id::Type, lane: PriorityLane) {}
+	/// Called once a job ran to a final status, completed, failed or canceled, with the location it
+	/// ran on, [`LIBRARY_WIDE`](super::LIBRARY_WIDE) for the whole library
+	#[allow(unused_variables)]
+	fn job_finished(
+		&self,
+		report: &Report,
+		location_id: location::id::Type,
+	) -> impl Future<Output = ()> + Send {
+		async {}
+	}
 }
 
 pub trait Job: Send + Sync + Hash + 'static {
@@ -259,6 +391,7 @@ impl JobOutput {
 #[derive(Debug, Serialize, Type)]
 pub enum JobOutputData {
 	Empty,
+	DuplicateFinder(DuplicatesReport),
 	// TODO: Add more types
 }
 
@@ -284,7 +417,7 @@ where
 			id: self.id,
 			job: self.job,
 			report: self.report_builder.build(),
-			next_jobs: VecDeque::new(),
+			next_jobs: self.next_jobs,
 			_ctx: PhantomData,
 		})
 	}
@@ -318,6 +451,13 @@ where
 		self
 	}
 
+	/// Caps how much of the machine the tasks of this job use, the profile of the whole system still
+	/// applies when it's lighter, e.g. on battery
+	#[must_use]
+	pub fn with_resource_profile(self, profile: ResourceProfile) -> Self {
+		self.with_metadata(ReportInputMetadata::ResourceProfile(profile))
+	}
+
 	#[must_use]
 	pub fn enqueue_next(mut self, next: impl Job + SerializableJob<Ctx>) -> Self {
 		let next_job_order = self.next_jobs.len() + 1;
@@ -414,6 +554,8 @@ impl<Ctx: OuterContext> JobHandle<Ctx> {
 			report.update(db).await?;
 		}
 
+		ctx.invalidate_query("jobs.reports");
+
 		// Registering children jobs
 		next_jobs
 			.iter_mut()
@@ -441,6 +583,7 @@ impl<Ctx: OuterContext> JobHandle<Ctx> {
 		let output = JobOutput::prepare_output_and_report(job_return, report);
 
 		report.update(ctx.db()).await?;
+		ctx.invalidate_query("jobs.reports");
 
 		Ok(output)
 	}
@@ -457,6 +600,7 @@ impl<Ctx: OuterContext> JobHandle<Ctx> {
 		report.completed_at = Some(Utc::now());
 
 		report.update(ctx.db()).await?;
+		ctx.invalidate_query("jobs.reports");
 
 		self.command_children(Command::Cancel).await
 	}
@@ -471,6 +615,7 @@ impl<Ctx: OuterContext> JobHandle<Ctx> {
 		report.status = Status::Paused;
 
 		report.update(ctx.db()).await?;
+		ctx.invalidate_query("jobs.reports");
 
 		self.command_children(Command::Pause).await
 	}
@@ -486,6 +631,7 @@ impl<Ctx: OuterContext> JobHandle<Ctx> {
 		report.completed_at = Some(Utc::now());
 
 		report.update(ctx.db()).await?;
+		ctx.invalidate_query("jobs.reports");
 
 		self.command_children(Command::Cancel).await
 	}
@@ -567,16 +713,21 @@ where
 		done_tx: chan::Sender<(JobId, Result<ReturnStatus, Error>)>,
 	) -> JobHandle<Ctx> {
 		let (commands_tx, commands_rx) = chan::bounded(8);
-
-		spawn(to_spawn_job(
-			self.id,
-			self.job,
-			ctx.clone(),
-			None,
-			base_dispatcher,
-			commands_rx,
-			done_tx,
-		));
+		let resource_profile = self.report.resource_profile();
+
+		spawn(
+			to_spawn_job(
+				self.id,
+				self.job,
+				ctx.clone(),
+				None,
+				base_dispatcher,
+				resource_profile,
+				commands_rx,
+				done_tx,
+			)
+			.instrument(info_span!("job", job_id = %self.id, job_name = %self.report.name)),
+		);
 
 		JobHandle {
 			next_jobs: self.next_jobs,
@@ -594,16 +745,21 @@ where
 		done_tx: chan::Sender<(JobId, Result<ReturnStatus, Error>)>,
 	) -> JobHandle<Ctx> {
 		let (commands_tx, commands_rx) = chan::bounded(8);
-
-		spawn(to_spawn_job(
-			self.id,
-			self.job,
-			ctx.clone(),
-			serialized_tasks,
-			base_dispatcher,
-			commands_rx,
-			done_tx,
-		));
+		let resource_profile = self.report.resource_profile();
+
+		spawn(
+			to_spawn_job(
+				self.id,
+				self.job,
+				ctx.clone(),
+				serialized_tasks,
+				base_dispatcher,
+				resource_profile,
+				commands_rx,
+				done_tx,
+			)
+			.instrument(info_span!("job", job_id = %self.id, job_name = %self.report.name)),
+		);
 
 		JobHandle {
 			next_jobs: self.next_jobs,
@@ -614,12 +770,13 @@ where
 	}
 }
 
-async fn to_spawn_job<Ctx: OuterContext>(
+async fn to_spawn_job<J: Job, Ctx: OuterContext>(
 	id: JobId,
-	mut job: impl Job,
+	mut job: J,
 	ctx: Ctx,
 	existing_tasks: Option<SerializedTasks>,
 	base_dispatcher: BaseTaskDispatcher<Error>,
+	resource_profile: ResourceProfile,
 	commands_rx: chan::Receiver<Command>,
 	done_tx: chan::Sender<(JobId, Result<ReturnStatus, Error>)>,
 ) {
@@ -629,12 +786,18 @@ async fn to_spawn_job<Ctx: OuterContext>(
 		Done(Result<ReturnStatus, Error>),
 	}
 
+	let ctx = ctx.for_job(id);
+
 	let mut remote_controllers = vec![];
 
 	let (running_state_tx, running_state_rx) = watch::channel(JobRunningState::Running);
 
-	let (dispatcher, remote_controllers_rx) =
-		JobTaskDispatcher::new(base_dispatcher, running_state_rx);
+	let (dispatcher, remote_controllers_rx) = JobTaskDispatcher::new(
+		base_dispatcher,
+		running_state_rx,
+		resource_profile,
+		ctx.io_throttle(J::NAME),
+	);
 
 	if let Some(existing_tasks) = existing_tasks {
 		if let Err(e) = job.resume_tasks(&dispatcher, &ctx, existing_tasks).await {
@@ -746,13 +909,19 @@ pub struct JobTaskDispatcher {
 	dispatcher: BaseTaskDispatcher<Error>,
 	remote_controllers_tx: chan::Sender<TaskRemoteController>,
 	running_state: Arc<Mutex<watch::Receiver<JobRunningState>>>,
+	concurrency_key: Option<Arc<str>>,
+	resource_profile: ResourceProfile,
+	/// Disk read cap of the profile when the job started or resumed, shared by all its tasks
+	profile_io_throttle: IoThrottle,
+	/// Disk read cap the node sets on this kind of job, shared with the other jobs it covers
+	node_io_throttle: IoThrottle,
 }
 
 impl TaskDispatcher<Error> for JobTaskDispatcher {
 	async fn dispatch_boxed(&self, boxed_task: Box<dyn Task<Error>>) -> TaskHandle<Error> {
 		self.wait_for_dispatch_approval().await;
 
-		let handle = self.dispatcher.dispatch_boxed(boxed_task).await;
+		let handle = self.dispatch_with_limit(boxed_task).await;
 
 		self.remote_controllers_tx
 			.send(handle.remote_controller())
@@ -766,6 +935,18 @@ impl TaskDispatcher<Error> for JobTaskDispatcher {
 		&self,
 		boxed_tasks: impl IntoIterator<Item = Box<dyn Task<Error>>> + Send,
 	) -> Vec<TaskHandle<Error>> {
+		if self.concurrency_key.is_some() || self.resource_profile() != ResourceProfile::Max {
+			// Dispatching one by one, as each task must wait for its own permit, and the job
+			// can be paused while we're waiting
+			let boxed_tasks = boxed_tasks.into_iter().collect::<Vec<_>>();
+			let mut handles = Vec::with_capacity(boxed_tasks.len());
+			for boxed_task in boxed_tasks {
+				handles.push(self.dispatch_boxed(boxed_task).await);
+			}
+
+			return handles;
+		}
+
 		self.wait_for_dispatch_approval().await;
 
 		let handles = self.dispatcher.dispatch_many_boxed(boxed_tasks).await;
@@ -786,19 +967,85 @@ impl JobTaskDispatcher {
 	fn new(
 		dispatcher: BaseTaskDispatcher<Error>,
 		running_state_rx: watch::Receiver<JobRunningState>,
+		resource_profile: ResourceProfile,
+		node_io_throttle: IoThrottle,
 	) -> (Self, chan::Receiver<TaskRemoteController>) {
 		let (remote_controllers_tx, remote_controllers_rx) = chan::unbounded();
 
+		let profile_io_throttle = IoThrottle::from(
+			dispatcher
+				.effective_resource_profile(resource_profile)
+				.io_bytes_per_sec(),
+		);
+
 		(
 			Self {
 				dispatcher,
 				remote_controllers_tx,
 				running_state: Arc::new(Mutex::new(running_state_rx)),
+				concurrency_key: None,
+				resource_profile,
+				profile_io_throttle,
+				node_io_throttle,
 			},
 			remote_controllers_rx,
 		)
 	}
 
+	/// Tags every task dispatched through the returned dispatcher with a concurrency key, so they're
+	/// throttled by the limit set on [`BaseTaskDispatcher::set_concurrency_limit`] for this key
+	#[must_use]
+	pub fn with_concurrency_key(&self, key: impl Into<Arc<str>>) -> Self {
+		Self {
+			concurrency_key: Some(key.into()),
+			..self.clone()
+		}
+	}
+
+	/// The profile the tasks of this job follow right now, lowered by the one of the whole system
+	#[must_use]
+	pub fn resource_profile(&self) -> ResourceProfile {
+		self.dispatcher
+			.effective_resource_profile(self.resource_profile)
+	}
+
+	/// The strictest of `job_throttle`, the disk read cap the node sets on this kind of job and the
+	/// one of the job's profile. The profile's cap is the one it had when the job started or
+	/// resumed, switching profiles doesn't change it for running jobs.
+	#[must_use]
+	pub fn io_throttle(&self, job_throttle: &IoThrottle) -> IoThrottle {
+		[
+			job_throttle,
+			&self.node_io_throttle,
+			&self.profile_io_throttle,
+		]
+		.into_iter()
+		.filter_map(|throttle| throttle.bytes_per_sec().map(|cap| (cap, throttle)))
+		.min_by_key(|(cap, _)| *cap)
+		.map_or_else(|| job_throttle.clone(), |(_, throttle)| throttle.clone())
+	}
+
+	async fn dispatch_with_limit(&self, boxed_task: Box<dyn Task<Error>>) -> TaskHandle<Error> {
+		let maybe_permit = if let Some(key) = &self.concurrency_key {
+			self.dispatcher.acquire_concurrency_permit(key).await
+		} else {
+			None
+		};
+
+		let maybe_profile_permit = self
+			.dispatcher
+			.acquire_resource_profile_permit(self.resource_profile)
+			.await;
+
+		let handle = self.dispatcher.dispatch_boxed(boxed_task).await;
+
+		if maybe_permit.is_some() || maybe_profile_permit.is_some() {
+			handle.hold_until_completion((maybe_permit, maybe_profile_permit))
+		} else {
+			handle
+		}
+	}
+
 	async fn wait_for_dispatch_approval(&self) {
 		self.running_state
 			.lock()
@@ -1,28 +1,59 @@
-use crate::Error;
-
-use sd_prisma::prisma::location;
-use sd_task_system::BaseTaskDispatcher;
+use crate::{
+	backup::Backup,
+	cold_archiver::{self, ColdArchiver},
+	disk_usage::DiskUsageAnalyzer,
+	file_identifier::{
+		self, CasIdAlgorithm, FileIdentifier, FileMetadataOptions, IdentifierSettings,
+	},
+	folder_sync::{self, FolderSync},
+	indexer::{self, job::Indexer},
+	tag_rules::TagRuleApplier,
+	text_extractor::TextExtractor,
+	verify_integrity::VerifyIntegrity,
+	Error,
+};
+
+#[cfg(feature = "transcription")]
+use crate::transcriber::Transcriber;
+#[cfg(feature = "ai")]
+use crate::{embedder::Embedder, image_labeler::ImageLabeler};
+
+use sd_core_prisma_helpers::location_with_indexer_rules;
+
+use sd_prisma::prisma::{location, PrismaClient};
+use sd_task_system::{BaseTaskDispatcher, PowerSource, ResourceProfile};
 use sd_utils::error::FileIOError;
 
-use std::{cell::RefCell, collections::hash_map::HashMap, path::Path, sync::Arc};
+use std::{
+	cell::RefCell,
+	collections::hash_map::HashMap,
+	path::{Path, PathBuf},
+	sync::Arc,
+};
 
 use async_channel as chan;
+use chrono::Utc;
 use futures::Stream;
 use futures_concurrency::future::{Join, TryJoin};
-use tokio::{fs, spawn, sync::oneshot, task::JoinHandle};
-use tracing::{error, info, trace, warn};
+use tokio::{fs, io, spawn, sync::oneshot, task::JoinHandle};
+use tracing::{debug, error, info, trace, warn};
 use uuid::Uuid;
 
+pub mod chain;
 mod error;
+pub mod failures;
 pub mod job;
 pub mod report;
 mod runner;
+pub mod schedule;
 mod store;
 pub mod utils;
 
-use error::JobSystemError;
+use chain::{ChainProgress, JobChain};
+pub use error::JobSystemError;
 use job::{IntoJob, Job, JobName, JobOutput, OuterContext};
 use runner::{run, JobSystemRunner, RunnerMessage};
+use schedule::{DueRun, ScheduledJob};
 use store::{load_jobs, StoredJobEntry};
 
 pub use store::{SerializableJob, SerializedTasks};
@@ -31,6 +62,10 @@ const PENDING_JOBS_FILE: &str = "pending_jobs.bin";
 
 pub type JobId = Uuid;
 
+/// Location id of the jobs that work on the whole library rather than on one of its locations,
+/// SQLite never gives it to a location
+pub const LIBRARY_WIDE: location::id::Type = 0;
+
 #[derive(Debug, Clone, Copy)]
 pub enum Command {
 	Pause,
@@ -39,17 +74,18 @@ pub enum Command {
 }
 
 pub struct JobSystem<Ctx: OuterContext> {
+	base_dispatcher: BaseTaskDispatcher<Error>,
 	msgs_tx: chan::Sender<RunnerMessage<Ctx>>,
 	job_outputs_rx: chan::Receiver<(JobId, Result<JobOutput, JobSystemError>)>,
+	store_jobs_file: Arc<PathBuf>,
 	runner_handle: RefCell<Option<JoinHandle<()>>>,
 }
 
 impl<Ctx: OuterContext> JobSystem<Ctx> {
-	pub async fn new(
+	pub fn new(
 		base_dispatcher: BaseTaskDispatcher<Error>,
-		data_directory: impl AsRef<Path> + Send,
-		previously_existing_contexts: &HashMap<Uuid, Ctx>,
-	) -> Result<Self, JobSystemError> {
+		data_directory: impl AsRef<Path>,
+	) -> Self {
 		let (job_outputs_tx, job_outputs_rx) = chan::unbounded();
 		let (job_return_status_tx, job_return_status_rx) = chan::bounded(16);
 		let (msgs_tx, msgs_rx) = chan::bounded(8);
@@ -58,6 +94,7 @@ impl<Ctx: OuterContext> JobSystem<Ctx> {
 
 		let runner_handle = RefCell::new(Some(spawn({
 			let store_jobs_file = Arc::clone(&store_jobs_file);
+			let base_dispatcher = base_dispatcher.clone();
 			async move {
 				trace!("Job System Runner starting...");
 				while let Err(e) = spawn({
@@ -97,18 +134,27 @@ impl<Ctx: OuterContext> JobSystem<Ctx> {
 			}
 		})));
 
-		load_stored_job_entries(
-			store_jobs_file.as_ref(),
-			previously_existing_contexts,
-			&msgs_tx,
-		)
-		.await?;
-
-		Ok(Self {
+		Self {
+			base_dispatcher,
 			msgs_tx,
 			job_outputs_rx,
+			store_jobs_file,
 			runner_handle,
-		})
+		}
+	}
+
+	/// Resumes the jobs stored on disk at the last shutdown, once the contexts they ran on are
+	/// available again. Jobs of contexts that don't exist anymore are dropped.
+	pub async fn init(
+		&self,
+		previously_existing_contexts: &HashMap<Uuid, Ctx>,
+	) -> Result<(), JobSystemError> {
+		load_stored_job_entries(
+			self.store_jobs_file.as_ref(),
+			previously_existing_contexts,
+			&self.msgs_tx,
+		)
+		.await
 	}
 
 	/// Checks if *any* of the desired jobs is running for the desired location
@@ -135,7 +181,11 @@ impl<Ctx: OuterContext> JobSystem<Ctx> {
 			.expect("ack channel closed before receiving check running job response")
 	}
 
-	/// Shutdown the job system
+	/// Shutdown the job system, storing the running jobs on disk to be resumed on the next start.
+	///
+	/// Jobs are only stored once their tasks are given back, so the task system must be shutdown
+	/// first, with [`TaskSystem::shutdown_with_timeout`](sd_task_system::TaskSystem::shutdown_with_timeout)
+	/// when the app is being closed and can't wait on tasks that don't suspend.
 	/// # Panics
 	/// Panics only happen if internal channels are unexpectedly closed
 	pub async fn shutdown(&self) {
@@ -165,7 +215,7 @@ impl<Ctx: OuterContext> JobSystem<Ctx> {
 	/// # Panics
 	/// Panics only happen if internal channels are unexpectedly closed
 	pub async fn dispatch<J: Job + SerializableJob<Ctx>>(
-		&mut self,
+		&self,
 		job: impl IntoJob<J, Ctx> + Send,
 		location_id: location::id::Type,
 		ctx: Ctx,
@@ -191,6 +241,332 @@ impl<Ctx: OuterContext> JobSystem<Ctx> {
 			.map(|()| id)
 	}
 
+	/// Dispatch the first stage of a chain, each next stage being dispatched once the previous one
+	/// completes. Returns the chain id, to be used with [`JobSystem::chain_progress`]
+	/// # Panics
+	/// Panics only happen if internal channels are unexpectedly closed
+	pub async fn dispatch_chain(
+		&self,
+		chain: JobChain<Ctx>,
+		location_id: location::id::Type,
+		ctx: Ctx,
+	) -> Result<JobId, JobSystemError> {
+		let dyn_job = chain.build();
+		let id = dyn_job.id();
+
+		let (ack_tx, ack_rx) = oneshot::channel();
+		self.msgs_tx
+			.send(RunnerMessage::NewJob {
+				id,
+				location_id,
+				dyn_job,
+				ctx,
+				ack_tx,
+			})
+			.await
+			.expect("runner msgs channel unexpectedly closed on new job chain request");
+
+		ack_rx
+			.await
+			.expect("ack channel closed before receiving new job chain request")
+			.map(|()| id)
+	}
+
+	/// Progress of a chain, `None` if none of its stages is running anymore
+	/// # Panics
+	/// Panics only happen if internal channels are unexpectedly closed
+	pub async fn chain_progress(&self, chain_id: JobId) -> Option<ChainProgress> {
+		let (ack_tx, ack_rx) = oneshot::channel();
+
+		self.msgs_tx
+			.send(RunnerMessage::ChainProgress { chain_id, ack_tx })
+			.await
+			.expect("runner msgs channel unexpectedly closed on chain progress request");
+
+		ack_rx
+			.await
+			.expect("ack channel closed before receiving chain progress response")
+	}
+
+	/// Waits for the job `id` to be done, returning right away if it isn't running. Jobs suspended
+	/// by a shutdown are done too, as far as this is concerned.
+	/// # Panics
+	/// Panics only happen if internal channels are unexpectedly closed
+	pub async fn wait(&self, id: JobId) {
+		let (ack_tx, ack_rx) = oneshot::channel();
+
+		self.msgs_tx
+			.send(RunnerMessage::Wait { id, ack_tx })
+			.await
+			.expect("runner msgs channel unexpectedly closed on wait request");
+
+		// The runner drops the sender of jobs that won't complete, as the ones being shutdown
+		ack_rx.await.ok();
+	}
+
+	/// Dispatches the jobs of the schedules that are due, see [`schedule::due`]. Meant to be
+	/// called when the node starts, catching up with occurrences missed while it was offline, and
+	/// then at each [`schedule::next_wake_up`].
+	///
+	/// Shallow runs aren't jobs, so they run in the background without a report. A schedule only
+	/// moves to its next occurrence once its run is dispatched, so runs failing to dispatch, e.g.
+	/// because a job is already running on the location, are tried again on the next call.
+	pub async fn run_due_schedules(
+		&self,
+		ctx: &Ctx,
+		cas_id_algorithm: CasIdAlgorithm,
+	) -> Result<Vec<JobId>, JobSystemError> {
+		let now = Utc::now();
+		let mut dispatched = vec![];
+
+		for run in schedule::due(ctx.db(), now).await? {
+			let DueRun {
+				schedule_id,
+				location_id,
+				job,
+				..
+			} = run;
+
+			debug!("Running job schedule <id='{schedule_id}', job={job:?}>");
+
+			match self
+				.dispatch_scheduled(job, location_id, cas_id_algorithm, ctx)
+				.await
+			{
+				Ok(maybe_id) => {
+					schedule::advance(ctx.db(), &run, now).await?;
+					dispatched.extend(maybe_id);
+				}
+				Err(e) => warn!("Failed to run job schedule <id='{schedule_id}'>: {e:#?}"),
+			}
+		}
+
+		Ok(dispatched)
+	}
+
+	async fn dispatch_scheduled(
+		&self,
+		job: ScheduledJob,
+		location_id: location::id::Type,
+		cas_id_algorithm: CasIdAlgorithm,
+		ctx: &Ctx,
+	) -> Result<Option<JobId>, JobSystemError> {
+		let db = ctx.db();
+
+		match job {
+			ScheduledJob::Indexer { shallow: true } => {
+				let location = find_location_with_indexer_rules(location_id, db).await?;
+				let dispatcher = self.base_dispatcher.clone();
+				let ctx = ctx.clone();
+
+				spawn(async move {
+					if let Err(e) = indexer::shallow(location, "", dispatcher, ctx).await {
+						error!(
+							"Scheduled shallow indexing failed <location_id={location_id}>: {e:#?}"
+						);
+					}
+				});
+
+				Ok(None)
+			}
+
+			ScheduledJob::Indexer { shallow: false } => self
+				.dispatch(
+					Indexer::new(
+						find_location_with_indexer_rules(location_id, db).await?,
+						None,
+					)
+					.map_err(Error::from)?,
+					location_id,
+					ctx.clone(),
+				)
+				.await
+				.map(Some),
+
+			ScheduledJob::FileIdentifier { shallow: true } => {
+				let location = find_location(location_id, db).await?;
+				let dispatcher = self.base_dispatcher.clone();
+				let ctx = ctx.clone();
+
+				let mut options = FileMetadataOptions {
+					cas_id_algorithm,
+					..Default::default()
+				};
+				IdentifierSettings::of_location(&location).apply(&mut options);
+
+				spawn(async move {
+					if let Err(e) =
+						file_identifier::shallow(location, "", options, dispatcher, ctx).await
+					{
+						error!("Scheduled shallow identification failed <location_id={location_id}>: {e:#?}");
+					}
+				});
+
+				Ok(None)
+			}
+
+			ScheduledJob::FileIdentifier { shallow: false } => self
+				.dispatch(
+					FileIdentifier::new(
+						find_location(location_id, db).await?,
+						None,
+						cas_id_algorithm,
+					)
+					.map_err(Error::from)?,
+					location_id,
+					ctx.clone(),
+				)
+				.await
+				.map(Some),
+
+			ScheduledJob::VerifyIntegrity => self
+				.dispatch(
+					VerifyIntegrity::new(
+						find_location(location_id, db).await?,
+						None,
+						cas_id_algorithm,
+					)
+					.map_err(Error::from)?,
+					location_id,
+					ctx.clone(),
+				)
+				.await
+				.map(Some),
+
+			ScheduledJob::TextExtractor => self
+				.dispatch(
+					TextExtractor::new(find_location(location_id, db).await?, None)
+						.map_err(Error::from)?,
+					location_id,
+					ctx.clone(),
+				)
+				.await
+				.map(Some),
+
+			ScheduledJob::TagRuleApplier => self
+				.dispatch(
+					TagRuleApplier::new(find_location(location_id, db).await?, None)
+						.map_err(Error::from)?,
+					location_id,
+					ctx.clone(),
+				)
+				.await
+				.map(Some),
+
+			ScheduledJob::Backup {
+				target_location_id,
+				deletion_policy,
+			} => self
+				.dispatch(
+					Backup::new(
+						find_location(location_id, db).await?,
+						&find_location(target_location_id, db).await?,
+						None,
+						deletion_policy,
+						cas_id_algorithm,
+					)
+					.map_err(Error::from)?,
+					location_id,
+					ctx.clone(),
+				)
+				.await
+				.map(Some),
+
+			ScheduledJob::FolderSync { folder_sync_id } => {
+				let (location_a, location_b) = folder_sync::locations(db, folder_sync_id)
+					.await
+					.map_err(Error::from)?;
+
+				self.dispatch(
+					FolderSync::new(folder_sync_id, &location_a, &location_b, cas_id_algorithm)
+						.map_err(Error::from)?,
+					location_id,
+					ctx.clone(),
+				)
+				.await
+				.map(Some)
+			}
+
+			ScheduledJob::ColdArchiver => {
+				let (policy, location, cold_location) = cold_archiver::locations(db, location_id)
+					.await
+					.map_err(Error::from)?;
+
+				self.dispatch(
+					ColdArchiver::new(policy, &location, &cold_location, cas_id_algorithm)
+						.map_err(Error::from)?,
+					location_id,
+					ctx.clone(),
+				)
+				.await
+				.map(Some)
+			}
+
+			ScheduledJob::DiskUsageAnalyzer => self
+				.dispatch(
+					DiskUsageAnalyzer::new(find_location(location_id, db).await?, None)
+						.map_err(Error::from)?,
+					location_id,
+					ctx.clone(),
+				)
+				.await
+				.map(Some),
+
+			#[cfg(feature = "ai")]
+			ScheduledJob::ImageLabeler => self
+				.dispatch(
+					ImageLabeler::new(find_location(location_id, db).await?, None)
+						.map_err(Error::from)?,
+					location_id,
+					ctx.clone(),
+				)
+				.await
+				.map(Some),
+
+			#[cfg(feature = "ai")]
+			ScheduledJob::Embedder => self
+				.dispatch(
+					Embedder::new(find_location(location_id, db).await?, None)
+						.map_err(Error::from)?,
+					location_id,
+					ctx.clone(),
+				)
+				.await
+				.map(Some),
+
+			#[cfg(feature = "transcription")]
+			ScheduledJob::Transcriber => self
+				.dispatch(
+					Transcriber::new(find_location(location_id, db).await?, None)
+						.map_err(Error::from)?,
+					location_id,
+					ctx.clone(),
+				)
+				.await
+				.map(Some),
+		}
+	}
+
+	/// The profile followed by every job, jobs selecting a lighter one with
+	/// [`JobBuilder::with_resource_profile`](job::JobBuilder::with_resource_profile) keep it
+	#[must_use]
+	pub fn resource_profile(&self) -> ResourceProfile {
+		self.base_dispatcher.resource_profile()
+	}
+
+	/// Switches the profile of every job at runtime, running tasks finish in their current one
+	pub fn set_resource_profile(&self, profile: ResourceProfile) {
+		self.base_dispatcher.set_resource_profile(profile);
+	}
+
+	/// Platform hook dropping every job to [`ResourceProfile::LowPower`] while on battery
+	pub fn follow_power_source(
+		&self,
+		power_source: impl Stream<Item = PowerSource> + Send + 'static,
+	) -> JoinHandle<()> {
+		self.base_dispatcher.follow_power_source(power_source)
+	}
+
 	pub fn receive_job_outputs(
 		&self,
 	) -> impl Stream<Item = (JobId, Result<JobOutput, JobSystemError>)> {
@@ -232,6 +608,31 @@ impl<Ctx: OuterContext> JobSystem<Ctx> {
 /// receiving `&self` which is called once, and we also use `try_borrow_mut` so we never panic
 unsafe impl<Ctx: OuterContext> Sync for JobSystem<Ctx> {}
 
+async fn find_location(
+	location_id: location::id::Type,
+	db: &PrismaClient,
+) -> Result<location::Data, JobSystemError> {
+	db.location()
+		.find_unique(location::id::equals(location_id))
+		.exec()
+		.await
+		.map_err(JobSystemError::ScheduledJobLocation)?
+		.ok_or(JobSystemError::ScheduledJobLocationNotFound(location_id))
+}
+
+async fn find_location_with_indexer_rules(
+	location_id: location::id::Type,
+	db: &PrismaClient,
+) -> Result<location_with_indexer_rules::Data, JobSystemError> {
+	db.location()
+		.find_unique(location::id::equals(location_id))
+		.include(location_with_indexer_rules::include())
+		.exec()
+		.await
+		.map_err(JobSystemError::ScheduledJobLocation)?
+		.ok_or(JobSystemError::ScheduledJobLocationNotFound(location_id))
+}
+
 async fn load_stored_job_entries<Ctx: OuterContext>(
 	store_jobs_file: impl AsRef<Path> + Send,
 	previously_existing_job_contexts: &HashMap<Uuid, Ctx>,
@@ -239,15 +640,21 @@ async fn load_stored_job_entries<Ctx: OuterContext>(
 ) -> Result<(), JobSystemError> {
 	let store_jobs_file = store_jobs_file.as_ref();
 
-	let stores_jobs_by_db = rmp_serde::from_slice::<HashMap<Uuid, Vec<StoredJobEntry>>>(
-		&fs::read(store_jobs_file).await.map_err(|e| {
-			JobSystemError::StoredJobs(FileIOError::from((
+	let stored_jobs = match fs::read(store_jobs_file).await {
+		Ok(stored_jobs) => stored_jobs,
+		// Nothing was running at the last shutdown
+		Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+		Err(e) => {
+			return Err(JobSystemError::StoredJobs(FileIOError::from((
 				store_jobs_file,
 				e,
 				"Failed to load jobs from disk",
-			)))
-		})?,
-	)?;
+			))))
+		}
+	};
+
+	let stores_jobs_by_db =
+		rmp_serde::from_slice::<HashMap<Uuid, Vec<StoredJobEntry>>>(&stored_jobs)?;
 
 	stores_jobs_by_db
 		.into_iter()
@@ -1,4 +1,5 @@
 use sd_prisma::prisma::{job, PrismaClient};
+use sd_task_system::ResourceProfile;
 use sd_utils::db::{maybe_missing, MissingFieldError};
 
 use std::{collections::HashMap, fmt, str::FromStr};
@@ -63,6 +64,14 @@ pub enum ReportMetadata {
 #[derive(Debug, Serialize, Deserialize, Type, Clone)]
 pub enum ReportInputMetadata {
 	Placeholder,
+	/// Position of the job in a [`JobChain`](super::chain::JobChain), starting at 1
+	ChainStage {
+		stage: u32,
+		total_stages: u32,
+	},
+	/// Profile the tasks of the job follow, see
+	/// [`JobBuilder::with_resource_profile`](super::job::JobBuilder::with_resource_profile)
+	ResourceProfile(#[specta(type = String)] ResourceProfile),
 	// TODO: Add more types
 }
 
@@ -178,6 +187,21 @@ impl Report {
 		}
 	}
 
+	/// The profile selected for the job, [`ResourceProfile::Max`] if none was, so it only follows
+	/// the one of the whole system
+	#[must_use]
+	pub fn resource_profile(&self) -> ResourceProfile {
+		self.metadata
+			.iter()
+			.find_map(|metadata| match metadata {
+				ReportMetadata::Input(ReportInputMetadata::ResourceProfile(profile)) => {
+					Some(*profile)
+				}
+				_ => None,
+			})
+			.unwrap_or(ResourceProfile::Max)
+	}
+
 	#[must_use]
 	pub fn get_action_name_and_group_key(&self) -> (String, Option<String>) {
 		// actions are formatted like "added_location" or "added_location-1"
@@ -5,7 +5,7 @@ use sd_task_system::BaseTaskDispatcher;
 use sd_utils::error::FileIOError;
 
 use std::{
-	collections::{hash_map::Entry, HashMap, HashSet},
+	collections::{HashMap, HashSet},
 	mem,
 	path::Path,
 	pin::pin,
@@ -22,10 +22,11 @@ use tokio::{
 	time::{interval_at, Instant},
 };
 use tokio_stream::wrappers::IntervalStream;
-use tracing::{debug, error, info, warn};
+use tracing::{debug, error, info};
 use uuid::Uuid;
 
 use super::{
+	chain::ChainProgress,
 	job::{DynJob, JobHandle, JobName, JobOutput, OuterContext, ReturnStatus},
 	report,
 	store::{StoredJob, StoredJobEntry},
@@ -56,6 +57,14 @@ pub(super) enum RunnerMessage<Ctx: OuterContext> {
 		command: Command,
 		ack_tx: oneshot::Sender<Result<(), JobSystemError>>,
 	},
+	ChainProgress {
+		chain_id: JobId,
+		ack_tx: oneshot::Sender<Option<ChainProgress>>,
+	},
+	Wait {
+		id: JobId,
+		ack_tx: oneshot::Sender<()>,
+	},
 	CheckIfJobAreRunning {
 		job_names: Vec<JobName>,
 		location_id: location::id::Type,
@@ -74,6 +83,7 @@ pub(super) struct JobSystemRunner<Ctx: OuterContext> {
 	jobs_to_store_by_ctx_id: HashMap<Uuid, Vec<StoredJobEntry>>,
 	job_return_status_tx: chan::Sender<(JobId, Result<ReturnStatus, Error>)>,
 	job_outputs_tx: chan::Sender<(JobId, Result<JobOutput, JobSystemError>)>,
+	waiters: HashMap<JobId, Vec<oneshot::Sender<()>>>,
 }
 
 impl<Ctx: OuterContext> JobSystemRunner<Ctx> {
@@ -92,6 +102,7 @@ impl<Ctx: OuterContext> JobSystemRunner<Ctx> {
 			jobs_to_store_by_ctx_id: HashMap::new(),
 			job_return_status_tx,
 			job_outputs_tx,
+			waiters: HashMap::new(),
 		}
 	}
 
@@ -196,6 +207,14 @@ impl<Ctx: OuterContext> JobSystemRunner<Ctx> {
 		self.handles.is_empty() && self.job_hashes.is_empty() && self.job_hashes_by_id.is_empty()
 	}
 
+	fn wait(&mut self, id: JobId, ack_tx: oneshot::Sender<()>) {
+		if self.handles.contains_key(&id) {
+			self.waiters.entry(id).or_default().push(ack_tx);
+		} else {
+			ack_tx.send(()).ok();
+		}
+	}
+
 	fn check_if_job_are_running(
 		&self,
 		job_names: Vec<JobName>,
@@ -212,14 +231,16 @@ impl<Ctx: OuterContext> JobSystemRunner<Ctx> {
 			job_hashes,
 			job_hashes_by_id,
 			job_outputs_tx,
-			job_return_status_tx,
-			base_dispatcher,
 			jobs_to_store_by_ctx_id,
 			running_jobs_by_job_id,
 			running_jobs_set,
+			waiters,
 			..
 		} = self;
 
+		// Dropped without a notification when the job doesn't complete
+		let job_waiters = waiters.remove(&job_id).unwrap_or_default();
+
 		let job_hash = job_hashes_by_id.remove(&job_id).expect("it must be here");
 		let (job_name, location_id) = running_jobs_by_job_id
 			.remove(&job_id)
@@ -229,15 +250,21 @@ impl<Ctx: OuterContext> JobSystemRunner<Ctx> {
 		assert!(job_hashes.remove(&job_hash).is_some());
 		let mut handle = handles.remove(&job_id).expect("it must be here");
 
+		let mut next_job = None;
+
 		let res = match status {
 			Ok(ReturnStatus::Completed(job_return)) => {
-				try_dispatch_next_job(
-					&mut handle,
-					base_dispatcher.clone(),
-					(job_hashes, job_hashes_by_id),
-					handles,
-					job_return_status_tx.clone(),
-				);
+				next_job = handle.next_jobs.pop_front().map(|mut next| {
+					assert!(
+						next.next_jobs().is_empty(),
+						"Only the root job will have next jobs, the rest will be empty and \
+						we will swap with remaining ones from the previous job"
+					);
+
+					next.set_next_jobs(mem::take(&mut handle.next_jobs));
+
+					next
+				});
 
 				handle.complete_job(job_return).await
 			}
@@ -313,10 +340,41 @@ impl<Ctx: OuterContext> JobSystemRunner<Ctx> {
 			Err(e) => handle.failed_job(&e).await.and_then(|()| Err(e.into())),
 		};
 
+		handle.ctx.job_finished(&handle.report, location_id).await;
+
 		job_outputs_tx
 			.send((job_id, res))
 			.await
 			.expect("job outputs channel unexpectedly closed on job completion");
+
+		for waiter in job_waiters {
+			waiter.send(()).ok();
+		}
+
+		// The next job only starts once the previous one is done, so they never run concurrently
+		if let Some(next) = next_job {
+			let next_id = next.id();
+			let next_name = next.job_name();
+
+			if let Err(e) = self
+				.new_job(next_id, location_id, next, handle.ctx, None)
+				.await
+			{
+				error!(
+					"Failed to dispatch next job: \
+					<previous_id='{job_id}', next_id='{next_id}', next_name='{next_name}'>: {e:#?}"
+				);
+			}
+		}
+	}
+
+	fn chain_progress(&self, chain_id: JobId) -> Option<ChainProgress> {
+		self.handles
+			.values()
+			.find(|handle| {
+				handle.report.id == chain_id || handle.report.parent_id == Some(chain_id)
+			})
+			.and_then(|handle| ChainProgress::new(&handle.report, &handle.next_jobs))
 	}
 
 	fn clean_memory(&mut self) {
@@ -375,43 +433,20 @@ impl<Ctx: OuterContext> JobSystemRunner<Ctx> {
 			return Ok(());
 		}
 
+		// Writing to a temporary file first and renaming it, so a process killed mid-write can't leave
+		// a truncated snapshot behind, the rename being atomic
+		let tmp_store_jobs_file = store_jobs_file.with_extension("tmp");
+
 		fs::write(
-			store_jobs_file,
+			&tmp_store_jobs_file,
 			rmp_serde::to_vec_named(&jobs_to_store_by_ctx_id)?,
 		)
 		.await
-		.map_err(|e| JobSystemError::StoredJobs(FileIOError::from((store_jobs_file, e))))
-	}
-}
+		.map_err(|e| JobSystemError::StoredJobs(FileIOError::from((&tmp_store_jobs_file, e))))?;
 
-fn try_dispatch_next_job<Ctx: OuterContext>(
-	handle: &mut JobHandle<Ctx>,
-	base_dispatcher: BaseTaskDispatcher<Error>,
-	(job_hashes, job_hashes_by_id): (&mut HashMap<u64, JobId>, &mut HashMap<JobId, u64>),
-	handles: &mut HashMap<JobId, JobHandle<Ctx>>,
-	job_return_status_tx: chan::Sender<(JobId, Result<ReturnStatus, Error>)>,
-) {
-	if let Some(next) = handle.next_jobs.pop_front() {
-		let next_id = next.id();
-		let next_hash = next.hash();
-		if let Entry::Vacant(e) = job_hashes.entry(next_hash) {
-			e.insert(next_id);
-			job_hashes_by_id.insert(next_id, next_hash);
-			let mut next_handle =
-				next.dispatch(base_dispatcher, handle.ctx.clone(), job_return_status_tx);
-
-			assert!(
-				next_handle.next_jobs.is_empty(),
-				"Only the root job will have next jobs, the rest will be empty and \
-				we will swap with remaining ones from the previous job"
-			);
-
-			next_handle.next_jobs = mem::take(&mut handle.next_jobs);
-
-			handles.insert(next_id, next_handle);
-		} else {
-			warn!("Unexpectedly found a job with the same hash as the next job: <id='{next_id}', name='{}'>", next.job_name());
-		}
+		fs::rename(&tmp_store_jobs_file, store_jobs_file)
+			.await
+			.map_err(|e| JobSystemError::StoredJobs(FileIOError::from((store_jobs_file, e))))
 	}
 }
 
@@ -488,17 +523,16 @@ pub(super) async fn run<Ctx: OuterContext>(
 			}
 
 			StreamMessage::RunnerMessage(RunnerMessage::Shutdown) => {
-				// Consuming all pending return status messages
-				loop {
-					while let Ok((job_id, status)) = job_return_status_rx_to_shutdown.try_recv() {
-						runner.process_return_status(job_id, status).await;
-					}
+				// Awaiting each job to return its status, as they serialize themselves once their
+				// tasks are suspended by the task system shutdown
+				while !runner.is_empty() {
+					debug!("Waiting for all jobs to complete before shutting down...");
 
-					if runner.is_empty() {
+					let Ok((job_id, status)) = job_return_status_rx_to_shutdown.recv().await else {
 						break;
-					}
+					};
 
-					debug!("Waiting for all jobs to complete before shutting down...");
+					runner.process_return_status(job_id, status).await;
 				}
 
 				// Now the runner can shutdown
@@ -519,6 +553,16 @@ pub(super) async fn run<Ctx: OuterContext>(
 					.expect("ack channel closed before sending resume job response");
 			}
 
+			StreamMessage::RunnerMessage(RunnerMessage::ChainProgress { chain_id, ack_tx }) => {
+				ack_tx
+					.send(runner.chain_progress(chain_id))
+					.expect("ack channel closed before sending chain progress response");
+			}
+
+			StreamMessage::RunnerMessage(RunnerMessage::Wait { id, ack_tx }) => {
+				runner.wait(id, ack_tx);
+			}
+
 			// Memory cleanup tick
 			StreamMessage::CleanMemoryTick => {
 				runner.clean_memory();
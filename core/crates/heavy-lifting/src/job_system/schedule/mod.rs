@@ -0,0 +1,413 @@
+use crate::backup::DeletionPolicy;
+
+use sd_prisma::prisma::{folder_sync, job_schedule, location, PrismaClient, SortOrder};
+
+use chrono::{DateTime, Duration, FixedOffset, Local, Utc};
+use prisma_client_rust::QueryError;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tracing::{debug, warn};
+
+mod recurrence;
+
+pub use recurrence::{Recurrence, RecurrenceError};
+
+/// Missed runs less late than this are still run with [`CatchUp::Skip`], so a node that was busy or
+/// asleep for a few minutes doesn't skip them
+const CATCH_UP_GRACE: Duration = Duration::minutes(10);
+
+#[derive(thiserror::Error, Debug)]
+pub enum ScheduleError {
+	#[error("job schedule not found: <id='{0}'>")]
+	NotFound(job_schedule::id::Type),
+	#[error("invalid recurrence: {0}")]
+	Recurrence(#[from] RecurrenceError),
+	#[error("recurrence never matches: <recurrence='{0}'>")]
+	NeverMatches(String),
+	#[error("database error: {0}")]
+	Database(#[from] QueryError),
+	#[error("failed to serialize scheduled job: {0}")]
+	Serialization(#[from] rmp_serde::encode::Error),
+}
+
+impl From<ScheduleError> for rspc::Error {
+	fn from(e: ScheduleError) -> Self {
+		match e {
+			ScheduleError::NotFound(_) => {
+				Self::with_cause(rspc::ErrorCode::NotFound, e.to_string(), e)
+			}
+
+			ScheduleError::Recurrence(_) | ScheduleError::NeverMatches(_) => {
+				Self::with_cause(rspc::ErrorCode::BadRequest, e.to_string(), e)
+			}
+
+			ScheduleError::Database(_) | ScheduleError::Serialization(_) => {
+				Self::with_cause(rspc::ErrorCode::InternalServerError, e.to_string(), e)
+			}
+		}
+	}
+}
+
+/// Job run on each occurrence of a schedule, over the whole location
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum ScheduledJob {
+	/// Indexes the location again, shallow only goes through its root directory
+	Indexer {
+		shallow: bool,
+	},
+	/// Identifies the orphans of the location, shallow only those in its root directory
+	FileIdentifier {
+		shallow: bool,
+	},
+	VerifyIntegrity,
+	/// Extracts the text of files that don't have it yet or whose content changed, in the default
+	/// OCR languages
+	TextExtractor,
+	/// Tags the objects of the location matched by the tag rules of the library
+	TagRuleApplier,
+	/// Backs the location up into another one, skipping the files already backed up
+	Backup {
+		target_location_id: location::id::Type,
+		deletion_policy: DeletionPolicy,
+	},
+	/// Syncs the location with the other one of a folder sync, both ways
+	FolderSync {
+		folder_sync_id: folder_sync::id::Type,
+	},
+	/// Moves the files of the location that went cold into the cold location of its archive policy
+	ColdArchiver,
+	/// Computes the usage of each directory of the location, kept up to date afterwards as it's
+	/// indexed and identified
+	DiskUsageAnalyzer,
+	/// Labels the images that the current labeling model hasn't labeled yet
+	#[cfg(feature = "ai")]
+	ImageLabeler,
+	/// Embeds the images that have no embedding of their current content yet, then updates the
+	/// index semantic search goes through
+	#[cfg(feature = "ai")]
+	Embedder,
+	/// Transcribes the audio and video files that the default Whisper model hasn't transcribed yet
+	#[cfg(feature = "transcription")]
+	Transcriber,
+}
+
+/// What to do with the occurrences missed while the node was offline
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum CatchUp {
+	/// Run once as soon as possible, no matter how many occurrences were missed
+	#[default]
+	RunOnce = 0,
+	/// Wait for the next occurrence
+	Skip = 1,
+}
+
+impl From<i32> for CatchUp {
+	fn from(value: i32) -> Self {
+		match value {
+			1 => Self::Skip,
+			_ => Self::RunOnce,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+pub struct JobSchedule {
+	pub id: job_schedule::id::Type,
+	pub location_id: location::id::Type,
+	pub job: ScheduledJob,
+	pub recurrence: String,
+	pub catch_up: CatchUp,
+	pub enabled: bool,
+	/// Only set while enabled
+	pub next_run_at: Option<DateTime<Utc>>,
+	pub last_run_at: Option<DateTime<Utc>>,
+}
+
+/// An occurrence of a schedule whose job must be dispatched now, moved to the next one with
+/// [`advance`] once dispatched
+#[derive(Debug, Clone, Copy)]
+pub struct DueRun {
+	pub schedule_id: job_schedule::id::Type,
+	pub location_id: location::id::Type,
+	pub job: ScheduledJob,
+	next_run_at: DateTime<FixedOffset>,
+}
+
+impl TryFrom<job_schedule::Data> for JobSchedule {
+	type Error = rmp_serde::decode::Error;
+
+	fn try_from(
+		job_schedule::Data {
+			id,
+			location_id,
+			job,
+			recurrence,
+			catch_up,
+			enabled,
+			next_run_at,
+			last_run_at,
+			..
+		}: job_schedule::Data,
+	) -> Result<Self, Self::Error> {
+		Ok(Self {
+			id,
+			location_id,
+			job: rmp_serde::from_slice(&job)?,
+			recurrence,
+			catch_up: catch_up.into(),
+			enabled,
+			next_run_at: next_run_at.map(Into::into),
+			last_run_at: last_run_at.map(Into::into),
+		})
+	}
+}
+
+/// Creates a schedule running `job` on the location on each occurrence of `recurrence`, a cron
+/// expression evaluated in the node's local time zone
+pub async fn create(
+	db: &PrismaClient,
+	location_id: location::id::Type,
+	job: ScheduledJob,
+	recurrence: &str,
+	catch_up: CatchUp,
+) -> Result<JobSchedule, ScheduleError> {
+	let parsed_recurrence = recurrence.parse::<Recurrence>()?;
+	let next_run_at = next_run_at(&parsed_recurrence, Utc::now())?;
+
+	let created = db
+		.job_schedule()
+		.create(
+			location::id::equals(location_id),
+			rmp_serde::to_vec_named(&job)?,
+			parsed_recurrence.to_string(),
+			vec![
+				job_schedule::catch_up::set(catch_up as i32),
+				job_schedule::next_run_at::set(Some(next_run_at)),
+				job_schedule::date_created::set(Some(Utc::now().into())),
+			],
+		)
+		.exec()
+		.await?;
+
+	Ok(JobSchedule {
+		id: created.id,
+		location_id,
+		job,
+		recurrence: created.recurrence,
+		catch_up,
+		enabled: true,
+		next_run_at: Some(next_run_at.into()),
+		last_run_at: None,
+	})
+}
+
+/// Schedules of a location, ignoring the ones we can't read anymore
+pub async fn list(
+	db: &PrismaClient,
+	location_id: location::id::Type,
+) -> Result<Vec<JobSchedule>, ScheduleError> {
+	Ok(db
+		.job_schedule()
+		.find_many(vec![job_schedule::location_id::equals(location_id)])
+		.order_by(job_schedule::id::order(SortOrder::Asc))
+		.exec()
+		.await?
+		.into_iter()
+		.filter_map(|schedule| {
+			let id = schedule.id;
+			JobSchedule::try_from(schedule)
+				.map_err(|e| warn!("Failed to read job schedule <id='{id}'>: {e:#?}"))
+				.ok()
+		})
+		.collect())
+}
+
+/// Occurrences missed while disabled are never caught up, the next run is computed from now on
+pub async fn set_enabled(
+	db: &PrismaClient,
+	id: job_schedule::id::Type,
+	enabled: bool,
+) -> Result<(), ScheduleError> {
+	let schedule = db
+		.job_schedule()
+		.find_unique(job_schedule::id::equals(id))
+		.select(job_schedule::select!({ recurrence }))
+		.exec()
+		.await?
+		.ok_or(ScheduleError::NotFound(id))?;
+
+	let next_run_at = if enabled {
+		Some(next_run_at(
+			&schedule.recurrence.parse::<Recurrence>()?,
+			Utc::now(),
+		)?)
+	} else {
+		None
+	};
+
+	db.job_schedule()
+		.update(
+			job_schedule::id::equals(id),
+			vec![
+				job_schedule::enabled::set(enabled),
+				job_schedule::next_run_at::set(next_run_at),
+			],
+		)
+		.exec()
+		.await?;
+
+	Ok(())
+}
+
+pub async fn delete(db: &PrismaClient, id: job_schedule::id::Type) -> Result<(), ScheduleError> {
+	let deleted = db
+		.job_schedule()
+		.delete_many(vec![job_schedule::id::equals(id)])
+		.exec()
+		.await?;
+
+	if deleted == 0 {
+		return Err(ScheduleError::NotFound(id));
+	}
+
+	Ok(())
+}
+
+/// When the earliest enabled schedule is due, to know when [`due`] must be called again
+pub async fn next_wake_up(db: &PrismaClient) -> Result<Option<DateTime<Utc>>, ScheduleError> {
+	Ok(db
+		.job_schedule()
+		.find_first(vec![
+			job_schedule::enabled::equals(true),
+			job_schedule::next_run_at::not(None),
+		])
+		.order_by(job_schedule::next_run_at::order(SortOrder::Asc))
+		.select(job_schedule::select!({ next_run_at }))
+		.exec()
+		.await?
+		.and_then(|schedule| schedule.next_run_at)
+		.map(Into::into))
+}
+
+/// The runs of the schedules due at `now`, each staying due until [`advance`]d, so a run failing
+/// to dispatch is tried again.
+///
+/// Several missed occurrences of a schedule only make it run once. Schedules with
+/// [`CatchUp::Skip`] that are late by more than a few minutes, e.g. because the node was offline,
+/// don't run at all and are moved to their next occurrence after `now`. Schedules we can't read
+/// anymore are disabled.
+pub async fn due(db: &PrismaClient, now: DateTime<Utc>) -> Result<Vec<DueRun>, ScheduleError> {
+	let now_fixed: DateTime<FixedOffset> = now.into();
+
+	let due = db
+		.job_schedule()
+		.find_many(vec![
+			job_schedule::enabled::equals(true),
+			job_schedule::next_run_at::lte(now_fixed),
+		])
+		.order_by(job_schedule::next_run_at::order(SortOrder::Asc))
+		.exec()
+		.await?;
+
+	if due.is_empty() {
+		return Ok(vec![]);
+	}
+
+	let mut runs = Vec::with_capacity(due.len());
+	let mut updates = Vec::with_capacity(due.len());
+
+	for schedule in due {
+		let id = schedule.id;
+
+		let (job, next_run_at) = match (
+			rmp_serde::from_slice::<ScheduledJob>(&schedule.job),
+			schedule
+				.recurrence
+				.parse::<Recurrence>()
+				.map_err(ScheduleError::from)
+				.and_then(|recurrence| next_run_at(&recurrence, now)),
+		) {
+			(Ok(job), Ok(next_run_at)) => (job, next_run_at),
+			(Err(e), _) => {
+				warn!("Disabling unreadable job schedule <id='{id}'>: {e:#?}");
+				updates.push(disable(db, id));
+				continue;
+			}
+			(_, Err(e)) => {
+				warn!("Disabling job schedule without next run <id='{id}'>: {e:#?}");
+				updates.push(disable(db, id));
+				continue;
+			}
+		};
+
+		let missed_by = schedule
+			.next_run_at
+			.map_or_else(Duration::zero, |scheduled| now_fixed - scheduled);
+
+		if CatchUp::from(schedule.catch_up) == CatchUp::Skip && missed_by > CATCH_UP_GRACE {
+			debug!(
+				"Skipping missed run of job schedule <id='{id}', missed_by={}min>",
+				missed_by.num_minutes()
+			);
+
+			updates.push(db.job_schedule().update(
+				job_schedule::id::equals(id),
+				vec![job_schedule::next_run_at::set(Some(next_run_at))],
+			));
+		} else {
+			runs.push(DueRun {
+				schedule_id: id,
+				location_id: schedule.location_id,
+				job,
+				next_run_at,
+			});
+		}
+	}
+
+	if !updates.is_empty() {
+		db._batch(updates).await?;
+	}
+
+	Ok(runs)
+}
+
+/// Moves the schedule of a run dispatched at `now` to its next occurrence
+pub async fn advance(
+	db: &PrismaClient,
+	run: &DueRun,
+	now: DateTime<Utc>,
+) -> Result<(), ScheduleError> {
+	db.job_schedule()
+		.update(
+			job_schedule::id::equals(run.schedule_id),
+			vec![
+				job_schedule::next_run_at::set(Some(run.next_run_at)),
+				job_schedule::last_run_at::set(Some(now.into())),
+			],
+		)
+		.exec()
+		.await?;
+
+	Ok(())
+}
+
+fn disable(db: &PrismaClient, id: job_schedule::id::Type) -> job_schedule::UpdateQuery<'_> {
+	db.job_schedule().update(
+		job_schedule::id::equals(id),
+		vec![
+			job_schedule::enabled::set(false),
+			job_schedule::next_run_at::set(None),
+		],
+	)
+}
+
+fn next_run_at(
+	recurrence: &Recurrence,
+	after: DateTime<Utc>,
+) -> Result<DateTime<FixedOffset>, ScheduleError> {
+	recurrence
+		.next_after(&after.with_timezone(&Local))
+		.map(|next_run_at| next_run_at.fixed_offset())
+		.ok_or_else(|| ScheduleError::NeverMatches(recurrence.to_string()))
+}
@@ -0,0 +1,376 @@
+use std::{fmt, str::FromStr};
+
+use chrono::{
+	DateTime, Datelike, Duration, LocalResult, NaiveDate, NaiveDateTime, TimeZone, Timelike,
+};
+
+/// How many years ahead we look for a matching time before giving up, enough for expressions
+/// matching only on leap days
+const MAX_YEARS_AHEAD: i32 = 8;
+
+const MONTH_NAMES: [&str; 12] = [
+	"jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAY_NAMES: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum RecurrenceError {
+	#[error("expected 5 fields (minute, hour, day of month, month, day of week), found {0}")]
+	FieldCount(usize),
+	#[error("unknown macro: <macro='{0}'>")]
+	UnknownMacro(String),
+	#[error("invalid {field} value: <value='{value}'>")]
+	InvalidValue { field: &'static str, value: String },
+	#[error("{field} value out of range: <value='{value}', min={min}, max={max}>")]
+	OutOfRange {
+		field: &'static str,
+		value: u32,
+		min: u32,
+		max: u32,
+	},
+}
+
+/// A cron expression in the classic 5 fields format, e.g. `0 3 * * SUN` for "Sundays at 3am", or
+/// one of the `@hourly`, `@daily`, `@weekly`, `@monthly` and `@yearly` macros.
+///
+/// Times are matched in the time zone they're computed in. As with cron, when both the day of month
+/// and the day of week are restricted, matching either is enough.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recurrence {
+	expression: String,
+	minutes: u64,
+	hours: u64,
+	days_of_month: u64,
+	months: u64,
+	days_of_week: u64,
+	restricted_day_of_month: bool,
+	restricted_day_of_week: bool,
+}
+
+struct Field {
+	name: &'static str,
+	min: u32,
+	max: u32,
+	names: &'static [&'static str],
+	/// Value of the first name, months start at 1 and weekdays at 0
+	names_offset: u32,
+}
+
+const MINUTE: Field = Field {
+	name: "minute",
+	min: 0,
+	max: 59,
+	names: &[],
+	names_offset: 0,
+};
+const HOUR: Field = Field {
+	name: "hour",
+	min: 0,
+	max: 23,
+	names: &[],
+	names_offset: 0,
+};
+const DAY_OF_MONTH: Field = Field {
+	name: "day of month",
+	min: 1,
+	max: 31,
+	names: &[],
+	names_offset: 0,
+};
+const MONTH: Field = Field {
+	name: "month",
+	min: 1,
+	max: 12,
+	names: &MONTH_NAMES,
+	names_offset: 1,
+};
+// 7 is also accepted for Sunday, folded into 0 after parsing
+const DAY_OF_WEEK: Field = Field {
+	name: "day of week",
+	min: 0,
+	max: 7,
+	names: &WEEKDAY_NAMES,
+	names_offset: 0,
+};
+
+impl Field {
+	fn parse(&self, expression: &str) -> Result<u64, RecurrenceError> {
+		let mut bits = 0;
+
+		for item in expression.split(',') {
+			let (range, step) = match item.split_once('/') {
+				Some((range, step)) => (
+					range,
+					step.parse::<u32>()
+						.ok()
+						.filter(|step| *step != 0)
+						.ok_or_else(|| self.invalid(item))?,
+				),
+				None => (item, 1),
+			};
+
+			let (start, end) = if range == "*" {
+				(self.min, self.max)
+			} else if let Some((start, end)) = range.split_once('-') {
+				(self.value(start)?, self.value(end)?)
+			} else {
+				let start = self.value(range)?;
+				// `5/15` means from 5 to the end, every 15
+				(start, if step == 1 { start } else { self.max })
+			};
+
+			if start > end {
+				return Err(self.invalid(item));
+			}
+
+			for value in (start..=end).step_by(step as usize) {
+				bits |= 1 << value;
+			}
+		}
+
+		Ok(bits)
+	}
+
+	fn value(&self, value: &str) -> Result<u32, RecurrenceError> {
+		let parsed = value.parse::<u32>().or_else(|_| {
+			self.names
+				.iter()
+				.position(|name| name.eq_ignore_ascii_case(value))
+				.and_then(|position| u32::try_from(position).ok())
+				.map(|position| position + self.names_offset)
+				.ok_or_else(|| self.invalid(value))
+		})?;
+
+		if (self.min..=self.max).contains(&parsed) {
+			Ok(parsed)
+		} else {
+			Err(RecurrenceError::OutOfRange {
+				field: self.name,
+				value: parsed,
+				min: self.min,
+				max: self.max,
+			})
+		}
+	}
+
+	fn invalid(&self, value: &str) -> RecurrenceError {
+		RecurrenceError::InvalidValue {
+			field: self.name,
+			value: value.to_string(),
+		}
+	}
+}
+
+impl FromStr for Recurrence {
+	type Err = RecurrenceError;
+
+	fn from_str(expression: &str) -> Result<Self, Self::Err> {
+		let expression = expression.trim();
+
+		let expanded = match expression {
+			"@hourly" => "0 * * * *",
+			"@daily" | "@midnight" => "0 0 * * *",
+			"@weekly" => "0 0 * * 0",
+			"@monthly" => "0 0 1 * *",
+			"@yearly" | "@annually" => "0 0 1 1 *",
+			expression if expression.starts_with('@') => {
+				return Err(RecurrenceError::UnknownMacro(expression.to_string()))
+			}
+			expression => expression,
+		};
+
+		let fields = expanded.split_whitespace().collect::<Vec<_>>();
+
+		let [minutes, hours, days_of_month, months, days_of_week] = fields[..] else {
+			return Err(RecurrenceError::FieldCount(fields.len()));
+		};
+
+		let mut days_of_week_bits = DAY_OF_WEEK.parse(days_of_week)?;
+		if days_of_week_bits & (1 << 7) != 0 {
+			days_of_week_bits = (days_of_week_bits | 1) & !(1 << 7);
+		}
+
+		Ok(Self {
+			expression: expression.to_string(),
+			minutes: MINUTE.parse(minutes)?,
+			hours: HOUR.parse(hours)?,
+			days_of_month: DAY_OF_MONTH.parse(days_of_month)?,
+			months: MONTH.parse(months)?,
+			days_of_week: days_of_week_bits,
+			restricted_day_of_month: !days_of_month.starts_with('*'),
+			restricted_day_of_week: !days_of_week.starts_with('*'),
+		})
+	}
+}
+
+impl fmt::Display for Recurrence {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(&self.expression)
+	}
+}
+
+impl Recurrence {
+	/// The first time matching this recurrence strictly after `after`, or `None` if it can't ever
+	/// match, e.g. on February 30th.
+	///
+	/// Local times skipped by a DST transition never match, and the earliest of repeated ones does.
+	pub fn next_after<Tz: TimeZone>(&self, after: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+		let timezone = after.timezone();
+		let last_year = after.naive_local().year() + MAX_YEARS_AHEAD;
+
+		let mut candidate = after
+			.naive_local()
+			.with_second(0)
+			.and_then(|time| time.with_nanosecond(0))?
+			+ Duration::minutes(1);
+
+		while candidate.year() <= last_year {
+			if !contains(self.months, candidate.month()) {
+				candidate = first_day_of_next_month(candidate)?;
+				continue;
+			}
+
+			if !self.matches_day(candidate.date()) {
+				candidate = candidate.date().succ_opt()?.and_hms_opt(0, 0, 0)?;
+				continue;
+			}
+
+			if !contains(self.hours, candidate.hour()) {
+				candidate = candidate.with_minute(0)? + Duration::hours(1);
+				continue;
+			}
+
+			if !contains(self.minutes, candidate.minute()) {
+				candidate += Duration::minutes(1);
+				continue;
+			}
+
+			match timezone.from_local_datetime(&candidate) {
+				LocalResult::Single(time) | LocalResult::Ambiguous(time, _) => return Some(time),
+				LocalResult::None => candidate += Duration::minutes(1),
+			}
+		}
+
+		None
+	}
+
+	fn matches_day(&self, date: NaiveDate) -> bool {
+		let day_of_month = contains(self.days_of_month, date.day());
+		let day_of_week = contains(self.days_of_week, date.weekday().num_days_from_sunday());
+
+		if self.restricted_day_of_month && self.restricted_day_of_week {
+			day_of_month || day_of_week
+		} else {
+			day_of_month && day_of_week
+		}
+	}
+}
+
+const fn contains(bits: u64, value: u32) -> bool {
+	bits & (1 << value) != 0
+}
+
+fn first_day_of_next_month(time: NaiveDateTime) -> Option<NaiveDateTime> {
+	let (year, month) = if time.month() == 12 {
+		(time.year() + 1, 1)
+	} else {
+		(time.year(), time.month() + 1)
+	};
+
+	NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(0, 0, 0)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use chrono::Utc;
+
+	fn at(time: &str) -> DateTime<Utc> {
+		DateTime::parse_from_rfc3339(time)
+			.expect("valid test time")
+			.with_timezone(&Utc)
+	}
+
+	fn next(expression: &str, after: &str) -> Option<DateTime<Utc>> {
+		expression
+			.parse::<Recurrence>()
+			.expect("valid test expression")
+			.next_after(&at(after))
+	}
+
+	#[test]
+	fn hourly() {
+		assert_eq!(
+			next("@hourly", "2024-06-20T10:15:42Z"),
+			Some(at("2024-06-20T11:00:00Z"))
+		);
+		assert_eq!(
+			next("0 * * * *", "2024-06-20T11:00:00Z"),
+			Some(at("2024-06-20T12:00:00Z"))
+		);
+	}
+
+	#[test]
+	fn weekly_on_sundays() {
+		// 2024-06-20 is a Thursday
+		assert_eq!(
+			next("0 3 * * SUN", "2024-06-20T10:00:00Z"),
+			Some(at("2024-06-23T03:00:00Z"))
+		);
+		assert_eq!(
+			next("0 3 * * 7", "2024-06-23T03:00:00Z"),
+			Some(at("2024-06-30T03:00:00Z"))
+		);
+	}
+
+	#[test]
+	fn steps_lists_and_ranges() {
+		assert_eq!(
+			next("*/15 9-17 * * mon-fri", "2024-06-21T17:50:00Z"),
+			Some(at("2024-06-24T09:00:00Z"))
+		);
+		assert_eq!(
+			next("30 1,13 * * *", "2024-06-20T02:00:00Z"),
+			Some(at("2024-06-20T13:30:00Z"))
+		);
+	}
+
+	#[test]
+	fn day_of_month_or_day_of_week() {
+		// Either the 1st or a Monday, 2024-06-24 is a Monday
+		assert_eq!(
+			next("0 0 1 * 1", "2024-06-20T00:00:00Z"),
+			Some(at("2024-06-24T00:00:00Z"))
+		);
+	}
+
+	#[test]
+	fn leap_days_and_impossible_dates() {
+		assert_eq!(
+			next("0 0 29 feb *", "2024-03-01T00:00:00Z"),
+			Some(at("2028-02-29T00:00:00Z"))
+		);
+		assert_eq!(next("0 0 30 2 *", "2024-03-01T00:00:00Z"), None);
+	}
+
+	#[test]
+	fn invalid_expressions() {
+		assert_eq!(
+			"0 3 * *".parse::<Recurrence>(),
+			Err(RecurrenceError::FieldCount(4))
+		);
+		assert!(matches!(
+			"60 * * * *".parse::<Recurrence>(),
+			Err(RecurrenceError::OutOfRange { .. })
+		));
+		assert!(matches!(
+			"*/0 * * * *".parse::<Recurrence>(),
+			Err(RecurrenceError::InvalidValue { .. })
+		));
+		assert!(matches!(
+			"@sometimes".parse::<Recurrence>(),
+			Err(RecurrenceError::UnknownMacro(_))
+		));
+	}
+}
@@ -1,4 +1,13 @@
-use crate::{file_identifier, indexer, media_processor};
+use crate::{
+	archiver, backup, bulk_rename, checksums, cold_archiver, crypto, disk_usage, duplicate_finder,
+	file_copier, file_identifier, file_mover, folder_sync, indexer, media_processor, script_runner,
+	tag_rules, text_extractor, verify_integrity,
+};
+
+#[cfg(feature = "transcription")]
+use crate::transcriber;
+#[cfg(feature = "ai")]
+use crate::{embedder, image_labeler};
 
 use sd_prisma::prisma::{job, location};
 use sd_utils::uuid_to_bytes;
@@ -79,7 +88,9 @@ pub async fn load_jobs<Ctx: OuterContext>(
 					     root_job: StoredJob { id, .. },
 					     next_jobs,
 					     ..
-					 }| { iter::once(*id).chain(next_jobs.iter().map(|StoredJob { id, .. }| *id)) },
+					 }| {
+						iter::once(*id).chain(next_jobs.iter().map(|StoredJob { id, .. }| *id))
+					},
 				)
 				.map(uuid_to_bytes)
 				.collect::<Vec<_>>(),
@@ -166,7 +177,7 @@ pub async fn load_jobs<Ctx: OuterContext>(
 }
 
 macro_rules! match_deserialize_job {
-	($stored_job:ident, $report:ident, $ctx:ident, $ctx_type:ty, [$($job_type:ty),+ $(,)?]) => {{
+	($stored_job:ident, $report:ident, $ctx:ident, $ctx_type:ty, [$($(#[$attr:meta])* $job_type:ty),+ $(,)?]) => {{
 		let StoredJob {
 			id,
 			name,
@@ -175,7 +186,7 @@ macro_rules! match_deserialize_job {
 
 
 		match name {
-			$(<$job_type as Job>::NAME => <$job_type as SerializableJob<$ctx_type>>::deserialize(
+			$($(#[$attr])* <$job_type as Job>::NAME => <$job_type as SerializableJob<$ctx_type>>::deserialize(
 					&serialized_job,
 					$ctx,
 				).await
@@ -214,6 +225,30 @@ async fn load_job<Ctx: OuterContext>(
 			indexer::job::Indexer,
 			file_identifier::job::FileIdentifier,
 			media_processor::job::MediaProcessor,
+			duplicate_finder::job::DuplicateFinder,
+			verify_integrity::job::VerifyIntegrity,
+			file_copier::FileCopier,
+			file_mover::FileMover,
+			backup::Backup,
+			folder_sync::FolderSync,
+			cold_archiver::ColdArchiver,
+			disk_usage::DiskUsageAnalyzer,
+			bulk_rename::BulkRename,
+			archiver::Compressor,
+			archiver::Extractor,
+			crypto::FileEncryptor,
+			crypto::FileDecryptor,
+			checksums::ChecksumExporter,
+			checksums::ChecksumImporter,
+			text_extractor::TextExtractor,
+			tag_rules::TagRuleApplier,
+			script_runner::ScriptRunner,
+			#[cfg(feature = "ai")]
+			image_labeler::ImageLabeler,
+			#[cfg(feature = "ai")]
+			embedder::Embedder,
+			#[cfg(feature = "transcription")]
+			transcriber::Transcriber,
 			// TODO: Add more jobs here
 		]
 	)
@@ -1,8 +1,10 @@
 use crate::Error;
 
+use sd_prisma::prisma::location;
 use sd_task_system::TaskHandle;
 
 use futures_concurrency::future::Join;
+use uuid::Uuid;
 
 pub async fn cancel_pending_tasks(
 	pending_tasks: impl IntoIterator<Item = &TaskHandle<Error>> + Send,
@@ -14,3 +16,12 @@ pub async fn cancel_pending_tasks(
 		.join()
 		.await;
 }
+
+/// Key used by jobs that read from a location's storage to share a concurrency limit, set one with
+/// [`BaseTaskDispatcher::set_concurrency_limit`](sd_task_system::BaseTaskDispatcher::set_concurrency_limit)
+/// to throttle slow backends like network drives independently from the rest of the system.
+/// Location ids are only unique within a library, so the key holds the library id as well.
+#[must_use]
+pub fn location_concurrency_key(library_id: Uuid, location_id: location::id::Type) -> String {
+	format!("{library_id}:location:{location_id}")
+}
@@ -35,17 +35,42 @@ use serde::{Deserialize, Serialize};
 use specta::Type;
 use thiserror::Error;
 
+pub mod archiver;
+pub mod backup;
+pub mod bulk_rename;
+pub mod checksums;
+pub mod cold_archiver;
+pub mod crypto;
+pub mod disk_usage;
+pub mod duplicate_finder;
+#[cfg(feature = "ai")]
+pub mod embedder;
+pub mod file_copier;
 pub mod file_identifier;
+pub mod file_mover;
+pub mod folder_sync;
+#[cfg(feature = "ai")]
+pub mod image_labeler;
 pub mod indexer;
 pub mod job_system;
 pub mod media_processor;
+pub mod script_runner;
+pub mod tag_rules;
+pub mod text_extractor;
+#[cfg(feature = "transcription")]
+pub mod transcriber;
 pub mod utils;
+pub mod verify_integrity;
 
 use media_processor::ThumbKey;
 
 pub use job_system::{
-	job::{IntoJob, JobBuilder, JobName, JobOutput, JobOutputData, OuterContext, ProgressUpdate},
-	JobId, JobSystem,
+	chain::{ChainProgress, JobChain},
+	job::{
+		IntoJob, Job, JobBuilder, JobName, JobOutput, JobOutputData, JobProgressMetrics,
+		OuterContext, PhaseMetrics, ProgressUpdate,
+	},
+	JobId, JobSystem, JobSystemError, SerializableJob,
 };
 
 #[derive(Error, Debug)]
@@ -56,6 +81,45 @@ pub enum Error {
 	FileIdentifier(#[from] file_identifier::Error),
 	#[error(transparent)]
 	MediaProcessor(#[from] media_processor::Error),
+	#[error(transparent)]
+	DuplicateFinder(#[from] duplicate_finder::Error),
+	#[error(transparent)]
+	VerifyIntegrity(#[from] verify_integrity::Error),
+	#[error(transparent)]
+	FileCopier(#[from] file_copier::Error),
+	#[error(transparent)]
+	FileMover(#[from] file_mover::Error),
+	#[error(transparent)]
+	BulkRename(#[from] bulk_rename::Error),
+	#[error(transparent)]
+	Archiver(#[from] archiver::Error),
+	#[error(transparent)]
+	Backup(#[from] backup::Error),
+	#[error(transparent)]
+	FolderSync(#[from] folder_sync::Error),
+	#[error(transparent)]
+	ColdArchiver(#[from] cold_archiver::Error),
+	#[error(transparent)]
+	DiskUsage(#[from] disk_usage::Error),
+	#[error(transparent)]
+	Crypto(#[from] crypto::Error),
+	#[error(transparent)]
+	Checksums(#[from] checksums::Error),
+	#[error(transparent)]
+	TextExtractor(#[from] text_extractor::Error),
+	#[error(transparent)]
+	TagRules(#[from] tag_rules::Error),
+	#[error(transparent)]
+	ScriptRunner(#[from] script_runner::Error),
+	#[cfg(feature = "ai")]
+	#[error(transparent)]
+	ImageLabeler(#[from] image_labeler::Error),
+	#[cfg(feature = "ai")]
+	#[error(transparent)]
+	Embedder(#[from] embedder::Error),
+	#[cfg(feature = "transcription")]
+	#[error(transparent)]
+	Transcriber(#[from] transcriber::Error),
 
 	#[error(transparent)]
 	TaskSystem(#[from] TaskSystemError),
@@ -67,6 +131,27 @@ impl From<Error> for rspc::Error {
 			Error::Indexer(e) => e.into(),
 			Error::FileIdentifier(e) => e.into(),
 			Error::MediaProcessor(e) => e.into(),
+			Error::DuplicateFinder(e) => e.into(),
+			Error::VerifyIntegrity(e) => e.into(),
+			Error::FileCopier(e) => e.into(),
+			Error::FileMover(e) => e.into(),
+			Error::BulkRename(e) => e.into(),
+			Error::Archiver(e) => e.into(),
+			Error::Backup(e) => e.into(),
+			Error::FolderSync(e) => e.into(),
+			Error::ColdArchiver(e) => e.into(),
+			Error::DiskUsage(e) => e.into(),
+			Error::Crypto(e) => e.into(),
+			Error::Checksums(e) => e.into(),
+			Error::TextExtractor(e) => e.into(),
+			Error::TagRules(e) => e.into(),
+			Error::ScriptRunner(e) => e.into(),
+			#[cfg(feature = "ai")]
+			Error::ImageLabeler(e) => e.into(),
+			#[cfg(feature = "ai")]
+			Error::Embedder(e) => e.into(),
+			#[cfg(feature = "transcription")]
+			Error::Transcriber(e) => e.into(),
 			Error::TaskSystem(e) => {
 				Self::with_cause(rspc::ErrorCode::InternalServerError, e.to_string(), e)
 			}
@@ -83,6 +168,35 @@ pub enum NonCriticalError {
 	FileIdentifier(#[from] file_identifier::NonCriticalError),
 	#[error(transparent)]
 	MediaProcessor(#[from] media_processor::NonCriticalError),
+	#[error(transparent)]
+	VerifyIntegrity(#[from] verify_integrity::NonCriticalError),
+	#[error(transparent)]
+	FileCopier(#[from] file_copier::NonCriticalError),
+	#[error(transparent)]
+	FileMover(#[from] file_mover::NonCriticalError),
+	#[error(transparent)]
+	Archiver(#[from] archiver::NonCriticalError),
+	#[error(transparent)]
+	Backup(#[from] backup::NonCriticalError),
+	#[error(transparent)]
+	FolderSync(#[from] folder_sync::NonCriticalError),
+	#[error(transparent)]
+	ColdArchiver(#[from] cold_archiver::NonCriticalError),
+	#[error(transparent)]
+	Crypto(#[from] crypto::NonCriticalError),
+	#[error(transparent)]
+	Checksums(#[from] checksums::NonCriticalError),
+	#[error(transparent)]
+	TextExtractor(#[from] text_extractor::NonCriticalError),
+	#[cfg(feature = "ai")]
+	#[error(transparent)]
+	ImageLabeler(#[from] image_labeler::NonCriticalError),
+	#[cfg(feature = "ai")]
+	#[error(transparent)]
+	Embedder(#[from] embedder::NonCriticalError),
+	#[cfg(feature = "transcription")]
+	#[error(transparent)]
+	Transcriber(#[from] transcriber::NonCriticalError),
 }
 
 #[repr(i32)]
@@ -102,4 +216,8 @@ pub enum UpdateEvent {
 	NewIdentifiedObjects {
 		file_path_ids: Vec<file_path::id::Type>,
 	},
+	/// File paths identified as copies of files kept in other locations
+	NewCrossLocationLinks {
+		links: Vec<file_identifier::CrossLocationLink>,
+	},
 }
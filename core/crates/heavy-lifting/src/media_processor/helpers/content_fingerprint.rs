@@ -0,0 +1,533 @@
+use crate::media_processor::{
+	self,
+	helpers::perceptual_hash::{distance, find_root, perceptual_hash},
+};
+
+use sd_file_ext::extensions::Extension;
+use sd_prisma::prisma::{content_fingerprint, object, PrismaClient};
+
+use std::{
+	collections::{HashMap, HashSet},
+	hash::Hash,
+	num::NonZeroU32,
+	time::Duration,
+};
+
+use image::{imageops::FilterType, DynamicImage};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+/// Only the beginning of audio files is fingerprinted, it's enough to tell songs apart and keeps
+/// decoding cheap for long recordings
+pub const AUDIO_FINGERPRINT_DURATION: Duration = Duration::from_secs(120);
+
+/// How many frames are sampled across a video's duration for its signature
+pub const VIDEO_SIGNATURE_FRAMES: NonZeroU32 = match NonZeroU32::new(32) {
+	Some(frames) => frames,
+	None => unreachable!(),
+};
+
+/// Bits used by each audio fingerprint code, 12 comparing pitch classes between themselves and 12
+/// comparing them with the previous frame
+const AUDIO_CODE_BITS: u32 = 24;
+
+/// Encodes of the same audio can start a bit earlier or later, so codes are compared with up to
+/// this many frames (~3 seconds) of shift between them
+const MAX_AUDIO_OFFSET: usize = 32;
+
+/// Shifted codes must still overlap this many frames (~6 seconds) to be compared at all
+const MIN_AUDIO_OVERLAP: usize = 64;
+
+/// Unrelated audio differs in about half of the bits, while re-encodes keep most of them
+const MAX_AUDIO_BIT_ERROR_RATE: f64 = 0.3;
+
+/// Audio files are only compared if they share at least this many exact codes, so we don't need
+/// to compare every file with all the others
+const MIN_SHARED_AUDIO_CODES: usize = 4;
+
+/// Codes shared by more than this fraction of the files are silence or noise-like, they don't tell
+/// anything about which files are alike
+const MAX_AUDIO_CODE_FREQUENCY: f64 = 0.05;
+
+/// Frames with a luma standard deviation below this are black, white or solid color frames, that
+/// would make any two videos look alike
+const FLAT_FRAME_DEVIATION: f64 = 8.0;
+
+/// Seeking lands on the closest keyframe, which differs between encodes, so sampled frames can be a
+/// few seconds apart from each other and we need a looser threshold than for images
+const MAX_VIDEO_FRAME_DISTANCE: u32 = 10;
+
+/// Videos need at least this many non flat frames at the same positions to be compared
+const MIN_INFORMATIVE_FRAMES: usize = 8;
+
+#[cfg(feature = "ffmpeg")]
+pub static AVAILABLE_EXTENSIONS: Lazy<Vec<Extension>> = Lazy::new(|| {
+	use super::ffmpeg_media_data::{can_extract_for_audio, can_extract_for_video};
+	use sd_file_ext::extensions::{ALL_AUDIO_EXTENSIONS, ALL_VIDEO_EXTENSIONS};
+
+	ALL_AUDIO_EXTENSIONS
+		.iter()
+		.copied()
+		.filter(|&ext| can_extract_for_audio(ext))
+		.map(Extension::Audio)
+		.chain(
+			ALL_VIDEO_EXTENSIONS
+				.iter()
+				.copied()
+				.filter(|&ext| can_extract_for_video(ext))
+				.map(Extension::Video),
+		)
+		.collect()
+});
+
+// Without ffmpeg we can't decode audio nor video, so there is nothing to fingerprint
+#[cfg(not(feature = "ffmpeg"))]
+pub static AVAILABLE_EXTENSIONS: Lazy<Vec<Extension>> = Lazy::new(Vec::new);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(i32)]
+pub enum FingerprintKind {
+	Audio = 0,
+	Video = 1,
+}
+
+impl TryFrom<i32> for FingerprintKind {
+	type Error = i32;
+
+	fn try_from(value: i32) -> Result<Self, Self::Error> {
+		match value {
+			0 => Ok(Self::Audio),
+			1 => Ok(Self::Video),
+			other => Err(other),
+		}
+	}
+}
+
+/// Content fingerprint of an audio or video file, which stays alike across different encodes of
+/// the same content
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fingerprint {
+	/// One code per chroma frame, see [`audio_fingerprint`]
+	Audio(Vec<u32>),
+	/// Perceptual hash of each sampled frame, 0 for flat ones, see [`video_signature`]
+	Video(Vec<u64>),
+}
+
+impl Fingerprint {
+	#[must_use]
+	pub const fn kind(&self) -> FingerprintKind {
+		match self {
+			Self::Audio(_) => FingerprintKind::Audio,
+			Self::Video(_) => FingerprintKind::Video,
+		}
+	}
+
+	/// Codes are stored as big endian bytes, same as perceptual hashes
+	#[must_use]
+	pub fn to_bytes(&self) -> Vec<u8> {
+		match self {
+			Self::Audio(codes) => codes.iter().flat_map(|code| code.to_be_bytes()).collect(),
+			Self::Video(hashes) => hashes.iter().flat_map(|hash| hash.to_be_bytes()).collect(),
+		}
+	}
+
+	#[must_use]
+	pub fn from_bytes(kind: FingerprintKind, bytes: &[u8]) -> Option<Self> {
+		match kind {
+			FingerprintKind::Audio => bytes
+				.chunks(4)
+				.map(|chunk| chunk.try_into().ok().map(u32::from_be_bytes))
+				.collect::<Option<_>>()
+				.map(Self::Audio),
+			FingerprintKind::Video => bytes
+				.chunks(8)
+				.map(|chunk| chunk.try_into().ok().map(u64::from_be_bytes))
+				.collect::<Option<_>>()
+				.map(Self::Video),
+		}
+	}
+
+	/// Fingerprints of different kinds are never similar, an audio file and the soundtrack of a
+	/// video aren't the same content
+	#[must_use]
+	pub fn is_similar(&self, other: &Self) -> bool {
+		match (self, other) {
+			(Self::Audio(a), Self::Audio(b)) => {
+				audio_bit_error_rate(a, b).is_some_and(|rate| rate <= MAX_AUDIO_BIT_ERROR_RATE)
+			}
+			(Self::Video(a), Self::Video(b)) => videos_match(a, b),
+			_ => false,
+		}
+	}
+}
+
+/// Chromaprint style fingerprint, turning each frame of chroma into a code of bits telling which
+/// pitch classes are louder than their neighbors and which got louder since the previous frame.
+///
+/// These relations survive re-encoding, resampling and volume changes, unlike the samples themselves.
+#[must_use]
+pub fn audio_fingerprint(chromas: &[[f32; 12]]) -> Vec<u32> {
+	// Averaging each frame with its neighbors smooths the noise out of the comparisons below
+	let smoothed = (0..chromas.len())
+		.map(|idx| {
+			let neighbors = &chromas[idx.saturating_sub(1)..(idx + 2).min(chromas.len())];
+			let mut chroma = [0.0f32; 12];
+			for neighbor in neighbors {
+				for (energy, neighbor_energy) in chroma.iter_mut().zip(neighbor) {
+					*energy += neighbor_energy;
+				}
+			}
+			chroma
+		})
+		.collect::<Vec<_>>();
+
+	smoothed
+		.windows(2)
+		.map(|pair| {
+			let (previous, current) = (&pair[0], &pair[1]);
+
+			(0..12).fold(0, |code, pitch_class| {
+				let mut code = code;
+				if current[pitch_class] > current[(pitch_class + 1) % 12] {
+					code |= 1 << pitch_class;
+				}
+				if current[pitch_class] > previous[pitch_class] {
+					code |= 1 << (pitch_class + 12);
+				}
+				code
+			})
+		})
+		.collect()
+}
+
+/// Lowest rate of differing bits between two audio fingerprints, trying every shift between them
+/// up to [`MAX_AUDIO_OFFSET`] frames. `None` if they're too short to be compared
+#[must_use]
+#[allow(clippy::cast_precision_loss)] // Fingerprints are way smaller than f64's mantissa
+pub fn audio_bit_error_rate(a: &[u32], b: &[u32]) -> Option<f64> {
+	let min_overlap = MIN_AUDIO_OVERLAP.min(a.len()).min(b.len()).max(1);
+
+	(0..=MAX_AUDIO_OFFSET)
+		.flat_map(|offset| [(offset, 0), (0, offset)])
+		.filter_map(|(a_offset, b_offset)| {
+			let (a, b) = (a.get(a_offset..)?, b.get(b_offset..)?);
+			let overlap = a.len().min(b.len());
+			if overlap < min_overlap {
+				return None;
+			}
+
+			let errors = a
+				.iter()
+				.zip(b)
+				.map(|(a, b)| u64::from(distance(u64::from(*a), u64::from(*b))))
+				.sum::<u64>();
+
+			Some(errors as f64 / (overlap as f64 * f64::from(AUDIO_CODE_BITS)))
+		})
+		.min_by(f64::total_cmp)
+}
+
+/// Perceptual hash of each sampled frame of a video, with flat frames, like black fades, as 0 so
+/// they're left out of comparisons
+#[must_use]
+pub fn video_signature(frames: &[DynamicImage]) -> Vec<u64> {
+	frames
+		.iter()
+		.map(|frame| {
+			if is_flat(frame) {
+				0
+			} else {
+				perceptual_hash(frame)
+			}
+		})
+		.collect()
+}
+
+#[allow(clippy::cast_precision_loss)] // Frames are shrunk to a few pixels before this
+fn is_flat(frame: &DynamicImage) -> bool {
+	let luma = frame
+		.resize_exact(16, 16, FilterType::Triangle)
+		.into_luma8();
+	let count = luma.pixels().len() as f64;
+
+	let mean = luma
+		.pixels()
+		.map(|pixel| f64::from(pixel.0[0]))
+		.sum::<f64>()
+		/ count;
+	let variance = luma
+		.pixels()
+		.map(|pixel| (f64::from(pixel.0[0]) - mean).powi(2))
+		.sum::<f64>()
+		/ count;
+
+	variance.sqrt() < FLAT_FRAME_DEVIATION
+}
+
+/// Videos match when at least half of the frames at the same positions look alike, as sampled
+/// frames don't land exactly on the same spots for different encodes
+fn videos_match(a: &[u64], b: &[u64]) -> bool {
+	if a.len() != b.len() {
+		return false;
+	}
+
+	let mut informative = 0;
+	let mut matched = 0;
+	for (a_hash, b_hash) in a.iter().zip(b) {
+		if *a_hash == 0 || *b_hash == 0 {
+			continue;
+		}
+
+		informative += 1;
+		if distance(*a_hash, *b_hash) <= MAX_VIDEO_FRAME_DISTANCE {
+			matched += 1;
+		} else if informative - matched > a.len() / 2 {
+			// Already too many mismatches, no need to look at the remaining frames
+			return false;
+		}
+	}
+
+	informative >= MIN_INFORMATIVE_FRAMES && matched * 2 >= informative
+}
+
+/// Groups items whose fingerprints are similar, transitively. Items without any similar one are
+/// left out.
+///
+/// Audio files are only compared with the ones sharing some exact codes with them, found through an
+/// inverted index of codes. Videos have a few dozen hashes each, so they're just compared pairwise,
+/// most pairs are discarded after a couple frames.
+#[must_use]
+pub fn group_similar_content<Id: Copy + Eq + Hash>(
+	fingerprints: &[(Id, Fingerprint)],
+) -> Vec<Vec<Id>> {
+	let mut parents = (0..fingerprints.len()).collect::<Vec<_>>();
+	let mut union = |a, b| {
+		let (a, b) = (find_root(&mut parents, a), find_root(&mut parents, b));
+		if a != b {
+			parents[a.max(b)] = a.min(b);
+		}
+	};
+
+	let (audios, videos) = fingerprints.iter().enumerate().fold(
+		(Vec::new(), Vec::new()),
+		|(mut audios, mut videos), (idx, (_, fingerprint))| {
+			match fingerprint {
+				Fingerprint::Audio(codes) => audios.push((idx, codes)),
+				Fingerprint::Video(hashes) => videos.push((idx, hashes)),
+			}
+			(audios, videos)
+		},
+	);
+
+	for (a_idx, (a, a_hashes)) in videos.iter().enumerate() {
+		for (b, b_hashes) in &videos[a_idx + 1..] {
+			if videos_match(a_hashes, b_hashes) {
+				union(*a, *b);
+			}
+		}
+	}
+
+	for (a, b) in audio_candidates(&audios) {
+		if fingerprints[a].1.is_similar(&fingerprints[b].1) {
+			union(a, b);
+		}
+	}
+
+	let mut groups = HashMap::<_, Vec<_>>::new();
+	for (idx, (id, _)) in fingerprints.iter().enumerate() {
+		groups
+			.entry(find_root(&mut parents, idx))
+			.or_default()
+			.push(*id);
+	}
+
+	groups
+		.into_values()
+		.filter(|group| group.len() > 1)
+		.collect()
+}
+
+/// Pairs of audio fingerprints sharing at least [`MIN_SHARED_AUDIO_CODES`] meaningful codes
+#[allow(
+	clippy::cast_possible_truncation,
+	clippy::cast_sign_loss,
+	clippy::cast_precision_loss
+)]
+fn audio_candidates(audios: &[(usize, &Vec<u32>)]) -> HashSet<(usize, usize)> {
+	let mut index = HashMap::<_, Vec<_>>::new();
+	for (idx, codes) in audios {
+		for code in codes.iter().copied().collect::<HashSet<_>>() {
+			index.entry(code).or_default().push(*idx);
+		}
+	}
+
+	let max_frequency = ((audios.len() as f64 * MAX_AUDIO_CODE_FREQUENCY) as usize).max(2);
+
+	let mut shared = HashMap::<_, usize>::new();
+	for (code, indices) in index {
+		// Silent frames have all bits unset
+		if code == 0 || indices.len() > max_frequency {
+			continue;
+		}
+
+		for (i, a) in indices.iter().enumerate() {
+			for b in &indices[i + 1..] {
+				*shared.entry((*a, *b)).or_default() += 1;
+			}
+		}
+	}
+
+	shared
+		.into_iter()
+		.filter(|(_, count)| *count >= MIN_SHARED_AUDIO_CODES)
+		.map(|(pair, _)| pair)
+		.collect()
+}
+
+pub async fn save(
+	fingerprints: Vec<(Fingerprint, object::id::Type)>,
+	db: &PrismaClient,
+) -> Result<u64, media_processor::Error> {
+	// Fingerprints are derived from the content on each device, so they aren't synced
+	db._batch(
+		fingerprints
+			.into_iter()
+			.map(|(fingerprint, object_id)| {
+				let kind = fingerprint.kind() as i32;
+				let bytes = fingerprint.to_bytes();
+
+				db.content_fingerprint().upsert(
+					content_fingerprint::object_id::equals(object_id),
+					content_fingerprint::create(
+						kind,
+						bytes.clone(),
+						object::id::equals(object_id),
+						vec![],
+					),
+					vec![
+						content_fingerprint::kind::set(kind),
+						content_fingerprint::fingerprint::set(bytes),
+					],
+				)
+			})
+			.collect::<Vec<_>>(),
+	)
+	.await
+	.map(|saved| saved.len() as u64)
+	.map_err(Into::into)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use image::{Rgb, RgbImage};
+
+	/// Pseudo random chromas, like music changing notes all the time
+	#[allow(clippy::cast_precision_loss)]
+	fn chromas(seed: u64, len: usize) -> Vec<[f32; 12]> {
+		(0..len as u64)
+			.map(|frame| {
+				let mut chroma = [0.0; 12];
+				for (pitch_class, energy) in (0..).zip(chroma.iter_mut()) {
+					let noise = (seed ^ ((frame / 3) << 8) ^ pitch_class)
+						.wrapping_mul(6_364_136_223_846_793_005)
+						.wrapping_add(1_442_695_040_888_963_407)
+						>> 44;
+					*energy = noise as f32;
+				}
+				chroma
+			})
+			.collect()
+	}
+
+	#[allow(clippy::cast_possible_truncation)]
+	fn frame(seed: u64) -> DynamicImage {
+		RgbImage::from_fn(64, 48, |x, y| {
+			let noise = (seed ^ u64::from(x / 8 + (y / 8) * 8))
+				.wrapping_mul(6_364_136_223_846_793_005)
+				.wrapping_add(1)
+				>> 40;
+			Rgb([noise as u8, (noise >> 8) as u8, (noise >> 16) as u8])
+		})
+		.into()
+	}
+
+	#[test]
+	fn shifted_audio_matches() {
+		let original = audio_fingerprint(&chromas(1, 500));
+		let shifted = audio_fingerprint(&chromas(1, 510)[10..]);
+		let unrelated = audio_fingerprint(&chromas(2, 500));
+
+		assert!(audio_bit_error_rate(&original, &shifted)
+			.is_some_and(|rate| rate <= MAX_AUDIO_BIT_ERROR_RATE));
+		assert!(audio_bit_error_rate(&original, &unrelated)
+			.is_some_and(|rate| rate > MAX_AUDIO_BIT_ERROR_RATE));
+	}
+
+	#[test]
+	fn flat_frames_are_left_out() {
+		let black = DynamicImage::from(RgbImage::new(64, 48));
+
+		let a = video_signature(&vec![black.clone(); 16]);
+		let b = video_signature(&vec![black; 16]);
+
+		assert!(a.iter().all(|hash| *hash == 0));
+		assert!(!Fingerprint::Video(a).is_similar(&Fingerprint::Video(b)));
+	}
+
+	#[test]
+	fn resized_videos_match() {
+		let frames = (0..16).map(frame).collect::<Vec<_>>();
+		let resized = frames
+			.iter()
+			.map(|frame| frame.resize_exact(32, 24, FilterType::Lanczos3))
+			.collect::<Vec<_>>();
+		let unrelated = (100..116).map(frame).collect::<Vec<_>>();
+
+		let original = Fingerprint::Video(video_signature(&frames));
+
+		assert!(original.is_similar(&Fingerprint::Video(video_signature(&resized))));
+		assert!(!original.is_similar(&Fingerprint::Video(video_signature(&unrelated))));
+	}
+
+	#[test]
+	fn content_groups_never_mix_kinds() {
+		let audio = audio_fingerprint(&chromas(1, 300));
+		let video = video_signature(&(0..16).map(frame).collect::<Vec<_>>());
+
+		let mut groups = group_similar_content(&[
+			(1, Fingerprint::Audio(audio.clone())),
+			(2, Fingerprint::Video(video.clone())),
+			(3, Fingerprint::Audio(audio)),
+			(4, Fingerprint::Video(video)),
+			(5, Fingerprint::Audio(audio_fingerprint(&chromas(2, 300)))),
+		]);
+
+		for group in &mut groups {
+			group.sort_unstable();
+		}
+		groups.sort_unstable();
+
+		assert_eq!(groups, vec![vec![1, 3], vec![2, 4]]);
+	}
+
+	#[test]
+	fn fingerprint_bytes_round_trip() {
+		let audio = Fingerprint::Audio(vec![0x00AB_CDEF, 0x0012_3456]);
+		let video = Fingerprint::Video(vec![0xDEAD_BEEF_CAFE_F00D, 0]);
+
+		assert_eq!(
+			Fingerprint::from_bytes(FingerprintKind::Audio, &audio.to_bytes()),
+			Some(audio)
+		);
+		assert_eq!(
+			Fingerprint::from_bytes(FingerprintKind::Video, &video.to_bytes()),
+			Some(video)
+		);
+		assert_eq!(
+			Fingerprint::from_bytes(FingerprintKind::Audio, &[1, 2, 3]),
+			None
+		);
+	}
+}
@@ -0,0 +1,49 @@
+use sd_file_ext::extensions::{DocumentExtension, Extension, ALL_DOCUMENT_EXTENSIONS};
+
+use once_cell::sync::Lazy;
+
+pub static AVAILABLE_EXTENSIONS: Lazy<Vec<Extension>> = Lazy::new(|| {
+	ALL_DOCUMENT_EXTENSIONS
+		.iter()
+		.copied()
+		.filter(|&ext| can_generate_preview(ext))
+		.map(Extension::Document)
+		.collect()
+});
+
+/// How a preview is obtained for each kind of document
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreviewSource {
+	/// The first page is rendered with pdfium
+	FirstPage,
+	/// The document is a zip container that already carries a thumbnail made by the app that
+	/// saved it, we just try each of these entries in order
+	EmbeddedThumbnail(&'static [&'static str]),
+}
+
+/// Office Open XML (docx, xlsx, pptx), only present when "save preview picture" is enabled
+const OOXML_THUMBNAIL_ENTRIES: &[&str] = &["docProps/thumbnail.jpeg", "docProps/thumbnail.png"];
+
+/// `OpenDocument` (odt, ods, odp), always written by `LibreOffice`
+const ODF_THUMBNAIL_ENTRIES: &[&str] = &["Thumbnails/thumbnail.png"];
+
+/// Apple iWork (pages, numbers, key), older versions used the `QuickLook` directory
+const IWORK_THUMBNAIL_ENTRIES: &[&str] = &["preview.jpg", "QuickLook/Thumbnail.jpg"];
+
+#[must_use]
+pub const fn preview_source(document_extension: DocumentExtension) -> Option<PreviewSource> {
+	use DocumentExtension::{Docx, Key, Numbers, Odp, Ods, Odt, Pages, Pdf, Pptx, Xlsx};
+
+	match document_extension {
+		Pdf => Some(PreviewSource::FirstPage),
+		Docx | Xlsx | Pptx => Some(PreviewSource::EmbeddedThumbnail(OOXML_THUMBNAIL_ENTRIES)),
+		Odt | Ods | Odp => Some(PreviewSource::EmbeddedThumbnail(ODF_THUMBNAIL_ENTRIES)),
+		Pages | Numbers | Key => Some(PreviewSource::EmbeddedThumbnail(IWORK_THUMBNAIL_ENTRIES)),
+		_ => None,
+	}
+}
+
+#[must_use]
+pub const fn can_generate_preview(document_extension: DocumentExtension) -> bool {
+	preview_source(document_extension).is_some()
+}
@@ -1,7 +1,7 @@
 use crate::media_processor::{self, media_data_extractor};
 
 use sd_file_ext::extensions::{Extension, ImageExtension, ALL_IMAGE_EXTENSIONS};
-use sd_media_metadata::ExifMetadata;
+use sd_media_metadata::{exif::MediaLocation, ExifMetadata};
 use sd_prisma::prisma::{exif_data, object, PrismaClient};
 
 use std::path::Path;
@@ -19,11 +19,21 @@ pub static AVAILABLE_EXTENSIONS: Lazy<Vec<Extension>> = Lazy::new(|| {
 
 pub const fn can_extract(image_extension: ImageExtension) -> bool {
 	use ImageExtension::{
-		Avci, Avcs, Avif, Dng, Heic, Heif, Heifs, Hif, Jpeg, Jpg, Png, Tiff, Webp,
+		Arw, Avci, Avcs, Avif, Cr2, Cr3, Dcr, Dng, Heic, Heif, Heifs, Hif, Jpeg, Jpg, Nef, Png,
+		Rw2, Tiff, Webp,
 	};
 	matches!(
 		image_extension,
-		Tiff | Dng | Jpeg | Jpg | Heif | Heifs | Heic | Avif | Avcs | Avci | Hif | Png | Webp
+		Tiff | Dng
+			| Cr2 | Cr3
+			| Dcr | Nef
+			| Arw | Rw2
+			| Jpeg | Jpg
+			| Heif | Heifs
+			| Heic | Avif
+			| Avcs | Avci
+			| Hif | Png
+			| Webp
 	)
 }
 
@@ -31,6 +41,9 @@ pub fn to_query(
 	mdi: ExifMetadata,
 	object_id: exif_data::object_id::Type,
 ) -> exif_data::CreateUnchecked {
+	let coordinates = mdi.location.as_ref().map(MediaLocation::coordinates);
+	let place = mdi.location.as_ref().and_then(MediaLocation::place);
+
 	exif_data::CreateUnchecked {
 		object_id,
 		_params: vec![
@@ -38,6 +51,10 @@ pub fn to_query(
 			exif_data::media_date::set(serde_json::to_vec(&mdi.date_taken).ok()),
 			exif_data::resolution::set(serde_json::to_vec(&mdi.resolution).ok()),
 			exif_data::media_location::set(serde_json::to_vec(&mdi.location).ok()),
+			exif_data::latitude::set(coordinates.map(|(latitude, _)| latitude)),
+			exif_data::longitude::set(coordinates.map(|(_, longitude)| longitude)),
+			exif_data::city::set(place.and_then(|place| place.city).map(ToString::to_string)),
+			exif_data::country::set(place.map(|place| place.country.to_string())),
 			exif_data::artist::set(mdi.artist),
 			exif_data::description::set(mdi.description),
 			exif_data::copyright::set(mdi.copyright),
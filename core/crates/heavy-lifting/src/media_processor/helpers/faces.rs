@@ -0,0 +1,331 @@
+use crate::media_processor;
+#[cfg(feature = "ai")]
+use crate::media_processor::helpers::thumbnailer::can_generate_thumbnail_for_image;
+
+#[cfg(feature = "ai")]
+use sd_file_ext::extensions::{Extension, ALL_IMAGE_EXTENSIONS};
+use sd_prisma::prisma::{face, person, PrismaClient};
+use sd_utils::uuid_to_bytes;
+
+use std::collections::HashMap;
+
+use chrono::Utc;
+use futures_concurrency::future::TryJoin;
+#[cfg(feature = "ai")]
+use once_cell::sync::Lazy;
+use uuid::Uuid;
+
+/// Faces of the same person usually have a cosine similarity above this, while faces of different
+/// people rarely do
+pub const SAME_PERSON_SIMILARITY: f32 = 0.45;
+
+/// Faces that don't look like any other are left alone until a second one shows up, otherwise every
+/// stranger in the background of a photo would become a person
+pub const MIN_FACES_PER_PERSON: usize = 2;
+
+#[cfg(feature = "ai")]
+pub static AVAILABLE_EXTENSIONS: Lazy<Vec<Extension>> = Lazy::new(|| {
+	ALL_IMAGE_EXTENSIONS
+		.iter()
+		.copied()
+		.filter(|&ext| can_generate_thumbnail_for_image(ext))
+		.map(Extension::Image)
+		.collect()
+});
+
+/// Embeddings are stored as little endian f32s
+#[must_use]
+pub fn embedding_to_bytes(embedding: &[f32]) -> Vec<u8> {
+	embedding.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+#[must_use]
+pub fn embedding_from_bytes(bytes: &[u8]) -> Vec<f32> {
+	bytes
+		.chunks_exact(4)
+		.map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+		.collect()
+}
+
+/// Cosine similarity of two L2 normalized embeddings, 1 means the same face
+#[must_use]
+pub fn similarity(a: &[f32], b: &[f32]) -> f32 {
+	a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+/// Mean of the embeddings, L2 normalized so it can be compared like any face
+#[must_use]
+pub fn centroid<'embedding>(
+	embeddings: impl IntoIterator<Item = &'embedding [f32]>,
+) -> Option<Vec<f32>> {
+	let mut sum = Vec::<f32>::new();
+
+	for embedding in embeddings {
+		if sum.is_empty() {
+			sum = embedding.to_vec();
+		} else {
+			sum.iter_mut().zip(embedding).for_each(|(sum, v)| *sum += v);
+		}
+	}
+
+	let norm = sum.iter().map(|v| v * v).sum::<f32>().sqrt();
+	if norm <= 0.0 {
+		return None;
+	}
+
+	sum.iter_mut().for_each(|v| *v /= norm);
+
+	Some(sum)
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Clustering<FaceId, PersonId> {
+	/// Faces close enough to a person that already exists
+	pub assigned: Vec<(FaceId, PersonId)>,
+	/// Faces alike each other but unlike anyone known, each group a new person
+	pub new_people: Vec<Vec<FaceId>>,
+}
+
+/// Assigns faces to the closest known person, and groups the others into new people.
+///
+/// Faces unlike any known person are clustered greedily, each one joining the closest group so far
+/// or starting a new one. Groups smaller than [`MIN_FACES_PER_PERSON`] are left out, so their faces
+/// are tried again next time.
+#[must_use]
+pub fn cluster<FaceId: Copy, PersonId: Copy>(
+	people: &[(PersonId, Vec<f32>)],
+	faces: &[(FaceId, Vec<f32>)],
+) -> Clustering<FaceId, PersonId> {
+	let mut assigned = Vec::new();
+	let mut groups = Vec::<(Vec<f32>, Vec<(FaceId, &[f32])>)>::new();
+
+	for (face_id, embedding) in faces {
+		if let Some((person_id, _)) = closest(
+			people
+				.iter()
+				.map(|(person_id, centroid)| (*person_id, centroid.as_slice())),
+			embedding,
+		) {
+			assigned.push((*face_id, person_id));
+			continue;
+		}
+
+		if let Some((group_idx, _)) = closest(
+			groups
+				.iter()
+				.enumerate()
+				.map(|(idx, (centroid, _))| (idx, centroid.as_slice())),
+			embedding,
+		) {
+			let (centroid, members) = &mut groups[group_idx];
+			members.push((*face_id, embedding.as_slice()));
+			if let Some(new_centroid) = self::centroid(members.iter().map(|(_, e)| *e)) {
+				*centroid = new_centroid;
+			}
+		} else {
+			groups.push((embedding.clone(), vec![(*face_id, embedding.as_slice())]));
+		}
+	}
+
+	Clustering {
+		assigned,
+		new_people: groups
+			.into_iter()
+			.filter(|(_, members)| members.len() >= MIN_FACES_PER_PERSON)
+			.map(|(_, members)| members.into_iter().map(|(face_id, _)| face_id).collect())
+			.collect(),
+	}
+}
+
+fn closest<'centroid, Id>(
+	candidates: impl Iterator<Item = (Id, &'centroid [f32])>,
+	embedding: &[f32],
+) -> Option<(Id, f32)> {
+	candidates
+		.map(|(id, centroid)| (id, similarity(centroid, embedding)))
+		.filter(|(_, similarity)| *similarity >= SAME_PERSON_SIMILARITY)
+		.max_by(|(_, a), (_, b)| a.total_cmp(b))
+}
+
+/// Groups the faces that don't belong to anyone yet, returning how many of them found a person
+pub async fn group_faces(db: &PrismaClient) -> Result<u64, media_processor::Error> {
+	let (people, faces) = (
+		db.person()
+			.find_many(vec![])
+			.select(person::select!({ id centroid }))
+			.exec(),
+		db.face()
+			.find_many(vec![face::person_id::equals(None)])
+			.select(face::select!({ id embedding }))
+			.exec(),
+	)
+		.try_join()
+		.await?;
+
+	if faces.is_empty() {
+		return Ok(0);
+	}
+
+	let Clustering {
+		assigned,
+		new_people,
+	} = cluster(
+		&people
+			.into_iter()
+			.map(|person| (person.id, embedding_from_bytes(&person.centroid)))
+			.collect::<Vec<_>>(),
+		&faces
+			.into_iter()
+			.map(|face| (face.id, embedding_from_bytes(&face.embedding)))
+			.collect::<Vec<_>>(),
+	);
+
+	let mut grouped = assigned.len() as u64;
+
+	let mut faces_by_person = HashMap::<_, Vec<_>>::new();
+	for (face_id, person_id) in assigned {
+		faces_by_person.entry(person_id).or_default().push(face_id);
+	}
+
+	for face_ids in new_people {
+		// Its centroid is set along with the other people that got new faces below
+		let person = db
+			.person()
+			.create(uuid_to_bytes(Uuid::new_v4()), vec![], vec![])
+			.select(person::select!({ id }))
+			.exec()
+			.await?;
+
+		grouped += face_ids.len() as u64;
+		faces_by_person.insert(person.id, face_ids);
+	}
+
+	let person_ids = faces_by_person.keys().copied().collect();
+
+	db._batch(
+		faces_by_person
+			.into_iter()
+			.map(|(person_id, face_ids)| {
+				db.face().update_many(
+					vec![face::id::in_vec(face_ids)],
+					vec![face::person_id::set(Some(person_id))],
+				)
+			})
+			.collect::<Vec<_>>(),
+	)
+	.await?;
+
+	refresh_people(person_ids, db).await?;
+
+	Ok(grouped)
+}
+
+/// Updates the centroids of people whose faces changed, deleting the ones left without any face
+pub async fn refresh_people(
+	person_ids: Vec<person::id::Type>,
+	db: &PrismaClient,
+) -> Result<(), media_processor::Error> {
+	let mut embeddings_by_person = person_ids
+		.iter()
+		.map(|person_id| (*person_id, Vec::new()))
+		.collect::<HashMap<_, _>>();
+
+	for face in db
+		.face()
+		.find_many(vec![face::person_id::in_vec(person_ids)])
+		.select(face::select!({ person_id embedding }))
+		.exec()
+		.await?
+	{
+		if let Some(person_id) = face.person_id {
+			embeddings_by_person
+				.entry(person_id)
+				.or_default()
+				.push(embedding_from_bytes(&face.embedding));
+		}
+	}
+
+	let (mut empty, mut updates) = (Vec::new(), Vec::new());
+	for (person_id, embeddings) in embeddings_by_person {
+		match centroid(embeddings.iter().map(Vec::as_slice)) {
+			Some(centroid) => updates.push(db.person().update(
+				person::id::equals(person_id),
+				vec![
+					person::centroid::set(embedding_to_bytes(&centroid)),
+					person::date_modified::set(Utc::now().into()),
+				],
+			)),
+			None => empty.push(person_id),
+		}
+	}
+
+	(
+		db._batch(updates),
+		db.person()
+			.delete_many(vec![person::id::in_vec(empty)])
+			.exec(),
+	)
+		.try_join()
+		.await?;
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Unit vector pointing mostly along `axis`, tilted a bit towards the next one
+	fn face(axis: usize, tilt: f32) -> Vec<f32> {
+		let mut embedding = vec![0.0; 4];
+		embedding[axis] = 1.0;
+		embedding[(axis + 1) % 4] = tilt;
+		centroid([embedding.as_slice()]).expect("not a zero vector")
+	}
+
+	#[test]
+	fn embedding_bytes_round_trip() {
+		let embedding = vec![0.5, -1.25, 3.0, f32::MIN_POSITIVE];
+
+		assert_eq!(
+			embedding_from_bytes(&embedding_to_bytes(&embedding)),
+			embedding
+		);
+	}
+
+	#[test]
+	fn centroid_is_normalized() {
+		let centroid = centroid([face(0, 0.0).as_slice(), face(1, 0.0).as_slice()])
+			.expect("not a zero vector");
+
+		assert!((similarity(&centroid, &centroid) - 1.0).abs() < 1e-6);
+		assert_eq!(centroid(Vec::<&[f32]>::new()), None);
+	}
+
+	#[test]
+	fn faces_join_known_people() {
+		let clustering = cluster(
+			&[(10, face(0, 0.0)), (20, face(2, 0.0))],
+			&[(1, face(0, 0.2)), (2, face(2, 0.1)), (3, face(1, 0.0))],
+		);
+
+		assert_eq!(clustering.assigned, vec![(1, 10), (2, 20)]);
+		assert!(clustering.new_people.is_empty());
+	}
+
+	#[test]
+	fn lone_faces_are_left_out() {
+		let clustering = cluster::<_, i32>(
+			&[],
+			&[
+				(1, face(1, 0.1)),
+				(2, face(3, 0.0)),
+				(3, face(1, 0.2)),
+				(4, face(1, 0.0)),
+			],
+		);
+
+		assert!(clustering.assigned.is_empty());
+		assert_eq!(clustering.new_people, vec![vec![1, 3, 4]]);
+	}
+}
@@ -1,3 +1,8 @@
+pub mod content_fingerprint;
+pub mod document_preview;
 pub mod exif_media_data;
+pub mod faces;
 pub mod ffmpeg_media_data;
+pub mod perceptual_hash;
 pub mod thumbnailer;
+pub mod waveform;
@@ -0,0 +1,320 @@
+use crate::media_processor::{self, helpers::thumbnailer::can_generate_thumbnail_for_image};
+
+use sd_file_ext::extensions::{Extension, ALL_IMAGE_EXTENSIONS};
+use sd_prisma::prisma::{object, perceptual_hash, PrismaClient};
+
+use std::{collections::HashMap, f64::consts::PI, hash::Hash};
+
+use image::{imageops::FilterType, DynamicImage};
+use once_cell::sync::Lazy;
+
+/// Images are shrunk to this side before the DCT, which is all the detail a perceptual hash needs
+const SAMPLE_SIDE: u32 = 32;
+
+/// Only the lowest frequencies of the DCT are kept, as a `HASH_SIDE` x `HASH_SIDE` block = 64 bits
+const HASH_SIDE: u32 = 8;
+
+/// Thresholds above this match way too many unrelated images to be useful
+pub const MAX_SIMILARITY_THRESHOLD: u32 = 16;
+
+/// Same images resized or re-encoded are usually within a handful of bits from each other
+pub const DEFAULT_SIMILARITY_THRESHOLD: u32 = 6;
+
+pub static AVAILABLE_EXTENSIONS: Lazy<Vec<Extension>> = Lazy::new(|| {
+	ALL_IMAGE_EXTENSIONS
+		.iter()
+		.copied()
+		.filter(|&ext| can_generate_thumbnail_for_image(ext))
+		.map(Extension::Image)
+		.collect()
+});
+
+/// DCT based perceptual hash, robust to resizing, re-encoding and small color adjustments.
+///
+/// The image is reduced to a 32x32 grayscale, and each bit of the hash tells if one of the 64 lowest
+/// frequencies of its DCT is above the median of them.
+#[must_use]
+pub fn perceptual_hash(img: &DynamicImage) -> u64 {
+	let luma = img
+		.resize_exact(SAMPLE_SIDE, SAMPLE_SIDE, FilterType::Triangle)
+		.into_luma8();
+
+	let pixels = luma
+		.pixels()
+		.map(|pixel| f64::from(pixel.0[0]))
+		.collect::<Vec<_>>();
+
+	// Cosines are the same for rows and columns, so we only compute them once
+	let cosines = (0..HASH_SIDE)
+		.map(|frequency| {
+			(0..SAMPLE_SIDE)
+				.map(|position| {
+					(2.0f64.mul_add(f64::from(position), 1.0) * f64::from(frequency) * PI
+						/ (2.0 * f64::from(SAMPLE_SIDE)))
+					.cos()
+				})
+				.collect::<Vec<_>>()
+		})
+		.collect::<Vec<_>>();
+
+	let mut coefficients = Vec::with_capacity((HASH_SIDE * HASH_SIDE) as usize);
+	for v in &cosines {
+		for u in &cosines {
+			coefficients.push(
+				pixels
+					.chunks_exact(SAMPLE_SIDE as usize)
+					.zip(v)
+					.map(|(row, cos_y)| {
+						row.iter()
+							.zip(u)
+							.map(|(pixel, cos_x)| pixel * cos_x)
+							.sum::<f64>() * cos_y
+					})
+					.sum::<f64>(),
+			);
+		}
+	}
+
+	// The DC coefficient is just the mean brightness, it would skew the median. That leaves 63
+	// coefficients, so the median is right in the middle of them
+	let mut sorted = coefficients[1..].to_vec();
+	sorted.sort_unstable_by(f64::total_cmp);
+	let median = sorted[sorted.len() / 2];
+
+	coefficients
+		.into_iter()
+		.enumerate()
+		.fold(0, |hash, (bit, coefficient)| {
+			if coefficient > median {
+				hash | (1 << bit)
+			} else {
+				hash
+			}
+		})
+}
+
+/// How many bits two hashes differ, 0 means they look the same
+#[must_use]
+pub const fn distance(a: u64, b: u64) -> u32 {
+	(a ^ b).count_ones()
+}
+
+/// Groups items whose hashes are within `threshold` bits of each other, transitively.
+///
+/// Hashes are indexed in a BK-tree, so each item is only compared with the few that can be within
+/// the threshold instead of all the others. Items without any similar one are left out.
+#[must_use]
+pub fn group_similar<Id: Copy + Eq + Hash>(hashes: &[(Id, u64)], threshold: u32) -> Vec<Vec<Id>> {
+	let tree = BkTree::new(hashes.iter().map(|(_, hash)| *hash));
+
+	let mut parents = (0..hashes.len()).collect::<Vec<_>>();
+
+	for (idx, (_, hash)) in hashes.iter().enumerate() {
+		for similar_idx in tree.find(*hash, threshold) {
+			let (a, b) = (
+				find_root(&mut parents, idx),
+				find_root(&mut parents, similar_idx),
+			);
+			if a != b {
+				parents[a.max(b)] = a.min(b);
+			}
+		}
+	}
+
+	let mut groups = HashMap::<_, Vec<_>>::new();
+	for (idx, (id, _)) in hashes.iter().enumerate() {
+		groups
+			.entry(find_root(&mut parents, idx))
+			.or_default()
+			.push(*id);
+	}
+
+	groups
+		.into_values()
+		.filter(|group| group.len() > 1)
+		.collect()
+}
+
+pub(super) fn find_root(parents: &mut [usize], mut idx: usize) -> usize {
+	while parents[idx] != idx {
+		parents[idx] = parents[parents[idx]];
+		idx = parents[idx];
+	}
+
+	idx
+}
+
+struct BkNode {
+	hash: u64,
+	idx: usize,
+	children: Vec<(u32, usize)>,
+}
+
+struct BkTree {
+	nodes: Vec<BkNode>,
+}
+
+impl BkTree {
+	fn new(hashes: impl IntoIterator<Item = u64>) -> Self {
+		let mut tree = Self { nodes: Vec::new() };
+
+		for (idx, hash) in hashes.into_iter().enumerate() {
+			tree.insert(idx, hash);
+		}
+
+		tree
+	}
+
+	fn insert(&mut self, idx: usize, hash: u64) {
+		let new_node = self.nodes.len();
+		self.nodes.push(BkNode {
+			hash,
+			idx,
+			children: Vec::new(),
+		});
+
+		if new_node == 0 {
+			return;
+		}
+
+		let mut current = 0;
+		loop {
+			let d = distance(self.nodes[current].hash, hash);
+			if let Some(&(_, child)) = self.nodes[current]
+				.children
+				.iter()
+				.find(|(child_d, _)| *child_d == d)
+			{
+				current = child;
+			} else {
+				self.nodes[current].children.push((d, new_node));
+				return;
+			}
+		}
+	}
+
+	fn find(&self, hash: u64, threshold: u32) -> Vec<usize> {
+		let mut found = Vec::new();
+
+		if self.nodes.is_empty() {
+			return found;
+		}
+
+		let mut to_visit = vec![0];
+		while let Some(current) = to_visit.pop() {
+			let node = &self.nodes[current];
+			let d = distance(node.hash, hash);
+
+			if d <= threshold {
+				found.push(node.idx);
+			}
+
+			// Triangle inequality, only these children can hold hashes within the threshold
+			to_visit.extend(
+				node.children
+					.iter()
+					.filter(|(child_d, _)| child_d.abs_diff(d) <= threshold)
+					.map(|(_, child)| *child),
+			);
+		}
+
+		found
+	}
+}
+
+pub async fn save(
+	hashes: Vec<(u64, object::id::Type)>,
+	db: &PrismaClient,
+) -> Result<u64, media_processor::Error> {
+	// Hashes are derived from the content on each device, so they aren't synced
+	db._batch(
+		hashes
+			.into_iter()
+			.map(|(hash, object_id)| {
+				db.perceptual_hash().upsert(
+					perceptual_hash::object_id::equals(object_id),
+					perceptual_hash::create(
+						hash.to_be_bytes().to_vec(),
+						object::id::equals(object_id),
+						vec![],
+					),
+					vec![perceptual_hash::hash::set(hash.to_be_bytes().to_vec())],
+				)
+			})
+			.collect::<Vec<_>>(),
+	)
+	.await
+	.map(|saved| saved.len() as u64)
+	.map_err(Into::into)
+}
+
+/// Hashes are stored as big endian bytes, as `SQLite` doesn't have unsigned 64 bits integers
+#[must_use]
+pub fn from_bytes(bytes: &[u8]) -> Option<u64> {
+	bytes.try_into().ok().map(u64::from_be_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use image::{Rgb, RgbImage};
+
+	/// Blocks of pseudo random colors, which have energy in all the low frequencies like photos do
+	#[allow(clippy::cast_possible_truncation)]
+	fn blocks(width: u32, height: u32) -> DynamicImage {
+		RgbImage::from_fn(width, height, |x, y| {
+			let block = u64::from(x * 12 / width + (y * 12 / height) * 12);
+			let noise = block
+				.wrapping_mul(6_364_136_223_846_793_005)
+				.wrapping_add(1)
+				>> 40;
+			Rgb([noise as u8, (noise >> 8) as u8, (noise >> 16) as u8])
+		})
+		.into()
+	}
+
+	#[test]
+	fn resized_images_hash_alike() {
+		let original = blocks(640, 480);
+		let resized = original.resize_exact(160, 120, FilterType::Lanczos3);
+
+		assert!(distance(perceptual_hash(&original), perceptual_hash(&resized)) <= 2);
+	}
+
+	#[test]
+	fn different_images_hash_apart() {
+		let a = blocks(640, 480);
+		let b = a.fliph().rotate90();
+
+		assert!(distance(perceptual_hash(&a), perceptual_hash(&b)) > DEFAULT_SIMILARITY_THRESHOLD);
+	}
+
+	#[test]
+	fn groups_are_transitive() {
+		let groups = group_similar(
+			&[
+				(1, 0b0000),
+				(2, 0b0011),
+				(3, 0b1111),
+				(4, u64::MAX),
+				(5, 0b0011),
+			],
+			2,
+		);
+
+		assert_eq!(groups.len(), 1);
+
+		let mut group = groups.into_iter().next().expect("just checked");
+		group.sort_unstable();
+		assert_eq!(group, vec![1, 2, 3, 5]);
+	}
+
+	#[test]
+	fn hash_bytes_round_trip() {
+		let hash: u64 = 0xDEAD_BEEF_CAFE_F00D;
+
+		assert_eq!(from_bytes(&hash.to_be_bytes()), Some(hash));
+		assert_eq!(from_bytes(&[1, 2, 3]), None);
+	}
+}
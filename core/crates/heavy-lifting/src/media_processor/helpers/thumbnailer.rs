@@ -1,12 +1,14 @@
 use once_cell::sync::Lazy;
-use sd_file_ext::extensions::{
-	DocumentExtension, Extension, ImageExtension, ALL_DOCUMENT_EXTENSIONS, ALL_IMAGE_EXTENSIONS,
-};
+use sd_file_ext::extensions::{DocumentExtension, Extension, ImageExtension, ALL_IMAGE_EXTENSIONS};
 
 #[cfg(feature = "ffmpeg")]
 use sd_file_ext::extensions::{VideoExtension, ALL_VIDEO_EXTENSIONS};
 
-use std::time::Duration;
+use std::{
+	num::NonZeroU32,
+	path::{Path, PathBuf},
+	time::Duration,
+};
 
 use serde::{Deserialize, Serialize};
 use specta::Type;
@@ -28,6 +30,19 @@ pub const TARGET_QUALITY: f32 = 60.0;
 /// How much time we allow for the thumbnail generation process to complete before we give up.
 pub const THUMBNAIL_GENERATION_TIMEOUT: Duration = Duration::from_secs(60);
 
+/// How many frames are taken across the duration of videos for their preview strips
+pub const PREVIEW_STRIP_FRAMES: NonZeroU32 = match NonZeroU32::new(10) {
+	Some(frames) => frames,
+	None => unreachable!(),
+};
+
+/// Size each frame of a preview strip is scaled to, smaller than thumbnails as they're only shown
+/// while hovering
+pub const PREVIEW_STRIP_FRAME_SIZE: u32 = 256;
+
+/// Preview strips are stored beside thumbnails, as `<cas_id>.strip.webp`
+pub const PREVIEW_STRIP_SUFFIX: &str = "strip";
+
 #[cfg(feature = "ffmpeg")]
 pub static THUMBNAILABLE_VIDEO_EXTENSIONS: Lazy<Vec<Extension>> = Lazy::new(|| {
 	ALL_VIDEO_EXTENSIONS
@@ -38,19 +53,14 @@ pub static THUMBNAILABLE_VIDEO_EXTENSIONS: Lazy<Vec<Extension>> = Lazy::new(|| {
 		.collect()
 });
 
+/// Documents aren't here as indexed ones are handled by the document previewer tasks, the
+/// thumbnailer only renders them for ephemeral thumbnails
 pub static THUMBNAILABLE_EXTENSIONS: Lazy<Vec<Extension>> = Lazy::new(|| {
 	ALL_IMAGE_EXTENSIONS
 		.iter()
 		.copied()
 		.filter(|&ext| can_generate_thumbnail_for_image(ext))
 		.map(Extension::Image)
-		.chain(
-			ALL_DOCUMENT_EXTENSIONS
-				.iter()
-				.copied()
-				.filter(|&ext| can_generate_thumbnail_for_document(ext))
-				.map(Extension::Document),
-		)
 		.collect()
 });
 
@@ -95,6 +105,46 @@ pub enum ThumbnailKind {
 	Indexed(Uuid),
 }
 
+/// Points the frontend to the preview strip of a video, a single image with its frames side by
+/// side, fetched like a thumbnail but with the [`PREVIEW_STRIP_SUFFIX`] before its extension
+#[derive(Debug, Serialize, Deserialize, Type)]
+pub struct PreviewStrip {
+	pub thumb_key: ThumbKey,
+	pub frames: u32,
+}
+
+/// This does not check if a thumbnail exists, it just returns the path that it would exist at
+#[must_use]
+pub fn thumbnail_path(
+	thumbnails_directory: impl AsRef<Path>,
+	cas_id: &str,
+	kind: &ThumbnailKind,
+) -> PathBuf {
+	let mut path = match kind {
+		ThumbnailKind::Ephemeral => thumbnails_directory.as_ref().join(EPHEMERAL_DIR),
+		ThumbnailKind::Indexed(library_id) => {
+			thumbnails_directory.as_ref().join(library_id.to_string())
+		}
+	};
+
+	path.push(get_shard_hex(cas_id));
+	path.push(cas_id);
+	path.set_extension(WEBP_EXTENSION);
+
+	path
+}
+
+/// Same as [`thumbnail_path`] for the preview strip of a video
+#[must_use]
+pub fn preview_strip_path(
+	thumbnails_directory: impl AsRef<Path>,
+	cas_id: &str,
+	kind: &ThumbnailKind,
+) -> PathBuf {
+	thumbnail_path(thumbnails_directory, cas_id, kind)
+		.with_extension(format!("{PREVIEW_STRIP_SUFFIX}.{WEBP_EXTENSION}"))
+}
+
 /// The practice of dividing files into hex coded folders, often called "sharding,"
 /// is mainly used to optimize file system performance. File systems can start to slow down
 /// as the number of files in a directory increases. Thus, it's often beneficial to split
@@ -119,13 +169,20 @@ pub const fn can_generate_thumbnail_for_video(video_extension: VideoExtension) -
 
 pub const fn can_generate_thumbnail_for_image(image_extension: ImageExtension) -> bool {
 	use ImageExtension::{
-		Avif, Bmp, Gif, Heic, Heics, Heif, Heifs, Ico, Jpeg, Jpg, Png, Svg, Webp,
+		Arw, Avci, Avcs, Avif, Bmp, Cr2, Cr3, Dcr, Dng, Gif, Heic, Heics, Heif, Heifs, Hif, Ico,
+		Jpeg, Jpg, Jxl, Nef, Png, Rw2, Svg, Webp,
 	};
 
-	matches!(
-		image_extension,
-		Jpg | Jpeg | Png | Webp | Gif | Svg | Heic | Heics | Heif | Heifs | Avif | Bmp | Ico
-	)
+	match image_extension {
+		Jpg | Jpeg | Png | Webp | Gif | Svg | Bmp | Ico | Dng | Cr2 | Cr3 | Dcr | Nef | Arw
+		| Rw2 => true,
+		// These depend on external decoders, so we only claim them when they're compiled in,
+		// otherwise they would just pile up as errors on every media processor run
+		Heic | Heics | Heif | Heifs | Hif | Avci | Avcs => cfg!(feature = "heif"),
+		Avif => cfg!(any(feature = "heif", feature = "avif")),
+		Jxl => cfg!(feature = "jxl"),
+		_ => false,
+	}
 }
 
 pub const fn can_generate_thumbnail_for_document(document_extension: DocumentExtension) -> bool {
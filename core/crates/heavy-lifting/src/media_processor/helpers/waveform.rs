@@ -0,0 +1,105 @@
+use crate::media_processor::helpers::thumbnailer::{thumbnail_path, ThumbnailKind};
+
+use sd_file_ext::extensions::Extension;
+
+use std::{
+	num::NonZeroU32,
+	path::{Path, PathBuf},
+};
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use uuid::Uuid;
+
+/// Waveforms are stored beside thumbnails, as `<cas_id>.waveform`
+pub const WAVEFORM_EXTENSION: &str = "waveform";
+
+/// How many peaks a waveform has, each one is stored as a single byte
+pub const WAVEFORM_PEAKS: NonZeroU32 = match NonZeroU32::new(256) {
+	Some(peaks) => peaks,
+	None => unreachable!(),
+};
+
+#[cfg(feature = "ffmpeg")]
+pub static AVAILABLE_EXTENSIONS: Lazy<Vec<Extension>> = Lazy::new(|| {
+	use super::ffmpeg_media_data::can_extract_for_audio;
+	use sd_file_ext::extensions::ALL_AUDIO_EXTENSIONS;
+
+	ALL_AUDIO_EXTENSIONS
+		.iter()
+		.copied()
+		.filter(|&ext| can_extract_for_audio(ext))
+		.map(Extension::Audio)
+		.collect()
+});
+
+// Without ffmpeg we can't decode audio, so there is nothing to extract waveforms from
+#[cfg(not(feature = "ffmpeg"))]
+pub static AVAILABLE_EXTENSIONS: Lazy<Vec<Extension>> = Lazy::new(Vec::new);
+
+/// Peak amplitudes of an audio file evenly spread across its duration, each one from 0 (silence)
+/// to 255 (full scale)
+#[derive(Debug, Serialize, Deserialize, Type)]
+pub struct Waveform {
+	pub peaks: Vec<u8>,
+}
+
+impl Waveform {
+	#[must_use]
+	pub fn from_amplitudes(amplitudes: &[f64]) -> Self {
+		Self {
+			peaks: amplitudes
+				.iter()
+				.map(|amplitude| {
+					#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+					{
+						// SAFETY: amplitudes are clamped between 0.0 and 1.0, so it fits in an u8
+						(amplitude.clamp(0.0, 1.0) * f64::from(u8::MAX)).round() as u8
+					}
+				})
+				.collect(),
+		}
+	}
+}
+
+/// This does not check if a waveform exists, it just returns the path that it would exist at
+#[must_use]
+pub fn waveform_path(
+	thumbnails_directory: impl AsRef<Path>,
+	cas_id: &str,
+	library_id: Uuid,
+) -> PathBuf {
+	thumbnail_path(
+		thumbnails_directory,
+		cas_id,
+		&ThumbnailKind::Indexed(library_id),
+	)
+	.with_extension(WAVEFORM_EXTENSION)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn waveform_from_amplitudes() {
+		assert_eq!(
+			Waveform::from_amplitudes(&[0.0, 0.5, 1.0, 1.3, -0.1]).peaks,
+			vec![0, 128, 255, 255, 0]
+		);
+	}
+
+	#[test]
+	fn waveform_path_is_beside_thumbnail() {
+		let library_id = Uuid::new_v4();
+
+		assert_eq!(
+			waveform_path("thumbnails", "abcdef", library_id),
+			Path::new("thumbnails")
+				.join(library_id.to_string())
+				.join("abc")
+				.join("abcdef.waveform")
+		);
+	}
+}
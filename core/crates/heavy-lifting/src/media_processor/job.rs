@@ -2,16 +2,21 @@ use crate::{
 	job_system::{
 		job::{Job, JobReturn, JobTaskDispatcher, ReturnStatus},
 		report::ReportOutputMetadata,
-		utils::cancel_pending_tasks,
+		utils::{cancel_pending_tasks, location_concurrency_key},
 		SerializableJob, SerializedTasks,
 	},
 	media_processor::{self, helpers::thumbnailer::THUMBNAIL_CACHE_DIR_NAME},
-	utils::sub_path::{self, maybe_get_iso_file_path_from_sub_path},
+	utils::{
+		network_share::ensure_location_mounted,
+		sub_path::{self, maybe_get_iso_file_path_from_sub_path},
+	},
 	Error, JobName, LocationScanState, OuterContext, ProgressUpdate,
 };
 use sd_core_file_path_helper::IsolatedFilePathData;
 use sd_core_prisma_helpers::file_path_for_media_processor;
 
+#[cfg(feature = "ai")]
+use sd_ai::faces::FaceModels;
 use sd_file_ext::extensions::Extension;
 use sd_prisma::prisma::{location, PrismaClient};
 use sd_task_system::{
@@ -25,7 +30,7 @@ use std::{
 	fmt,
 	hash::{Hash, Hasher},
 	mem,
-	path::PathBuf,
+	path::{Path, PathBuf},
 	sync::Arc,
 	time::Duration,
 };
@@ -40,7 +45,10 @@ use tracing::{debug, warn};
 
 use super::{
 	helpers,
-	tasks::{self, media_data_extractor, thumbnailer},
+	tasks::{
+		self, content_fingerprinter, document_previewer, media_data_extractor, perceptual_hasher,
+		thumbnailer, waveform_extractor,
+	},
 	NewThumbnailsReporter, BATCH_SIZE,
 };
 
@@ -48,6 +56,12 @@ use super::{
 enum TaskKind {
 	MediaDataExtractor,
 	Thumbnailer,
+	WaveformExtractor,
+	DocumentPreviewer,
+	PerceptualHasher,
+	ContentFingerprinter,
+	#[cfg(feature = "ai")]
+	FaceDetector,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
@@ -86,6 +100,10 @@ pub struct MediaProcessor {
 
 	phase: Phase,
 
+	// Loaded when the job runs, as models can't be serialized
+	#[cfg(feature = "ai")]
+	face_models: Option<Arc<FaceModels>>,
+
 	metadata: Metadata,
 
 	errors: Vec<crate::NonCriticalError>,
@@ -103,15 +121,34 @@ impl Job for MediaProcessor {
 		ctx: &impl OuterContext,
 		SerializedTasks(serialized_tasks): SerializedTasks,
 	) -> Result<(), Error> {
+		let dispatcher =
+			dispatcher.with_concurrency_key(location_concurrency_key(ctx.id(), self.location.id));
+
 		let reporter = Arc::new(NewThumbnailsReporter { ctx: ctx.clone() });
 
+		let serialized_tasks = rmp_serde::from_slice::<Vec<(TaskKind, Vec<u8>)>>(&serialized_tasks)
+			.map_err(media_processor::Error::from)?;
+
+		#[cfg(feature = "ai")]
+		let face_models = self.load_face_models().await;
+
+		// Models that failed to load now can't resume their tasks
+		#[cfg(feature = "ai")]
+		let serialized_tasks = serialized_tasks
+			.into_iter()
+			.filter(|(task_kind, _)| {
+				face_models.is_some() || !matches!(task_kind, TaskKind::FaceDetector)
+			})
+			.collect::<Vec<_>>();
+
 		self.pending_tasks_on_resume = dispatcher
 			.dispatch_many_boxed(
-				rmp_serde::from_slice::<Vec<(TaskKind, Vec<u8>)>>(&serialized_tasks)
-					.map_err(media_processor::Error::from)?
+				serialized_tasks
 					.into_iter()
 					.map(|(task_kind, task_bytes)| {
 						let reporter = Arc::clone(&reporter);
+						#[cfg(feature = "ai")]
+						let face_models = face_models.clone();
 						async move {
 							match task_kind {
 								TaskKind::MediaDataExtractor => {
@@ -129,6 +166,49 @@ impl Job for MediaProcessor {
 								)
 								.await
 								.map(IntoTask::into_task),
+
+								TaskKind::WaveformExtractor => {
+									tasks::WaveformExtractor::deserialize(&task_bytes, ())
+										.await
+										.map(IntoTask::into_task)
+								}
+
+								TaskKind::DocumentPreviewer => {
+									tasks::DocumentPreviewer::deserialize(
+										&task_bytes,
+										Arc::clone(&reporter),
+									)
+									.await
+									.map(IntoTask::into_task)
+								}
+
+								TaskKind::PerceptualHasher => tasks::PerceptualHasher::deserialize(
+									&task_bytes,
+									Arc::clone(ctx.db()),
+								)
+								.await
+								.map(IntoTask::into_task),
+
+								TaskKind::ContentFingerprinter => {
+									tasks::ContentFingerprinter::deserialize(
+										&task_bytes,
+										Arc::clone(ctx.db()),
+									)
+									.await
+									.map(IntoTask::into_task)
+								}
+
+								#[cfg(feature = "ai")]
+								TaskKind::FaceDetector => tasks::FaceDetector::deserialize(
+									&task_bytes,
+									(
+										face_models
+											.expect("tasks without models were filtered out"),
+										Arc::clone(ctx.db()),
+									),
+								)
+								.await
+								.map(IntoTask::into_task),
 							}
 						}
 					})
@@ -147,6 +227,9 @@ impl Job for MediaProcessor {
 		dispatcher: JobTaskDispatcher,
 		ctx: Ctx,
 	) -> Result<ReturnStatus, Error> {
+		let dispatcher =
+			dispatcher.with_concurrency_key(location_concurrency_key(ctx.id(), self.location.id));
+
 		let mut pending_running_tasks = FuturesUnordered::new();
 
 		self.init_or_resume(&mut pending_running_tasks, &ctx, &dispatcher)
@@ -163,6 +246,15 @@ impl Job for MediaProcessor {
 		}
 
 		// From this point onward, we are done with the job and it can't be interrupted anymore
+		#[cfg(feature = "ai")]
+		if self.metadata.face_detection_metrics.faces_found > 0 {
+			self.metadata.face_detection_metrics.grouped = media_processor::group_faces(ctx.db())
+				.await
+				.map_err(media_processor::Error::from)?;
+
+			ctx.invalidate_query("people.list");
+		}
+
 		let Self {
 			location,
 			metadata,
@@ -208,6 +300,8 @@ impl MediaProcessor {
 			total_thumbnailer_tasks: 0,
 			total_thumbnailer_files: 0,
 			phase: Phase::default(),
+			#[cfg(feature = "ai")]
+			face_models: None,
 			metadata: Metadata::default(),
 			errors: Vec::new(),
 			pending_tasks_on_resume: Vec::new(),
@@ -221,6 +315,13 @@ impl MediaProcessor {
 		ctx: &impl OuterContext,
 		dispatcher: &JobTaskDispatcher,
 	) -> Result<(), media_processor::Error> {
+		ensure_location_mounted(
+			self.location.id,
+			self.location.network_share.as_deref(),
+			&*self.location_path,
+		)
+		.await?;
+
 		// if we don't have any pending task, then this is a fresh job
 		if self.pending_tasks_on_resume.is_empty() {
 			let location_id = self.location.id;
@@ -279,6 +380,73 @@ impl MediaProcessor {
 			pending_running_tasks.extend(task_handles);
 
 			self.total_thumbnailer_tasks = total_thumbnailer_tasks;
+
+			// Waveforms are cheap compared to thumbnails, so they just run alongside them
+			pending_running_tasks.extend(
+				dispatch_waveform_extractor_tasks(
+					&iso_file_path,
+					self.regenerate_thumbnails,
+					&self.location_path,
+					dispatcher,
+					ctx,
+				)
+				.await?,
+			);
+
+			// Documents get their previews saved as thumbnails, but through their own tasks as
+			// they're rendered or pulled out of containers instead of decoded as images
+			pending_running_tasks.extend(
+				dispatch_document_previewer_tasks(
+					&iso_file_path,
+					self.regenerate_thumbnails,
+					&self.location_path,
+					dispatcher,
+					ctx,
+				)
+				.await?,
+			);
+
+			// Dispatched after the thumbnailer, so most images already have a thumbnail to be
+			// hashed from by the time these run
+			pending_running_tasks.extend(
+				dispatch_perceptual_hasher_tasks(
+					&iso_file_path,
+					self.regenerate_thumbnails,
+					&self.location_path,
+					dispatcher,
+					ctx,
+				)
+				.await?,
+			);
+
+			// Audio and video fingerprints decode the files themselves, they don't depend on
+			// anything generated above
+			pending_running_tasks.extend(
+				dispatch_content_fingerprinter_tasks(
+					&iso_file_path,
+					self.regenerate_thumbnails,
+					&self.location_path,
+					dispatcher,
+					ctx,
+				)
+				.await?,
+			);
+
+			// Also dispatched after the thumbnailer, to detect faces in thumbnails when possible
+			#[cfg(feature = "ai")]
+			if let Some(face_models) = self.load_face_models().await {
+				pending_running_tasks.extend(
+					dispatch_face_detector_tasks(
+						&iso_file_path,
+						self.regenerate_thumbnails,
+						&self.location_path,
+						face_models,
+						dispatcher,
+						ctx,
+					)
+					.await?,
+				);
+			}
 		} else {
 			pending_running_tasks.extend(mem::take(&mut self.pending_tasks_on_resume));
 		}
@@ -286,6 +454,19 @@ impl MediaProcessor {
 		Ok(())
 	}
 
+	/// Face models are bundled with the app, when they're missing faces just aren't detected
+	#[cfg(feature = "ai")]
+	async fn load_face_models(&mut self) -> Option<Arc<FaceModels>> {
+		if self.face_models.is_none() {
+			match FaceModels::load().await {
+				Ok(face_models) => self.face_models = Some(Arc::new(face_models)),
+				Err(e) => warn!("Faces won't be detected: {e:#?}"),
+			}
+		}
+
+		self.face_models.clone()
+	}
+
 	async fn process_handles(
 		&mut self,
 		pending_running_tasks: &mut FuturesUnordered<TaskHandle<Error>>,
@@ -334,6 +515,30 @@ impl MediaProcessor {
 		any_task_output: Box<dyn AnyTaskOutput>,
 		ctx: &impl OuterContext,
 	) {
+		// Not in the chain below, as its branch only exists with the feature
+		#[cfg(feature = "ai")]
+		if any_task_output.is::<tasks::face_detector::Output>() {
+			let tasks::face_detector::Output {
+				scanned,
+				faces_found,
+				skipped,
+				detection_time,
+				db_write_time,
+				errors,
+			} = *any_task_output.downcast().expect("just checked");
+
+			self.metadata.face_detection_metrics.scanned += scanned;
+			self.metadata.face_detection_metrics.faces_found += faces_found;
+			self.metadata.face_detection_metrics.skipped += skipped;
+			self.metadata.face_detection_metrics.detection_time += detection_time;
+			self.metadata.face_detection_metrics.db_write_time += db_write_time;
+			self.metadata.face_detection_metrics.total_successful_tasks += 1;
+
+			self.errors.extend(errors);
+
+			return;
+		}
+
 		if any_task_output.is::<media_data_extractor::Output>() {
 			let media_data_extractor::Output {
 				extracted,
@@ -421,6 +626,72 @@ impl MediaProcessor {
 		// 		)),
 		// 	]);
 		// }
+		} else if any_task_output.is::<waveform_extractor::Output>() {
+			let waveform_extractor::Output {
+				extracted,
+				skipped,
+				extraction_time,
+				errors,
+			} = *any_task_output.downcast().expect("just checked");
+
+			self.metadata.waveform_metrics.extracted += extracted;
+			self.metadata.waveform_metrics.skipped += skipped;
+			self.metadata.waveform_metrics.extraction_time += extraction_time;
+			self.metadata.waveform_metrics.total_successful_tasks += 1;
+
+			self.errors.extend(errors);
+		} else if any_task_output.is::<document_previewer::Output>() {
+			let document_previewer::Output {
+				generated,
+				skipped,
+				generation_time,
+				errors,
+			} = *any_task_output.downcast().expect("just checked");
+
+			self.metadata.document_preview_metrics.generated += generated;
+			self.metadata.document_preview_metrics.skipped += skipped;
+			self.metadata.document_preview_metrics.generation_time += generation_time;
+			self.metadata
+				.document_preview_metrics
+				.total_successful_tasks += 1;
+
+			self.errors.extend(errors);
+		} else if any_task_output.is::<perceptual_hasher::Output>() {
+			let perceptual_hasher::Output {
+				hashed,
+				skipped,
+				hashing_time,
+				db_write_time,
+				errors,
+			} = *any_task_output.downcast().expect("just checked");
+
+			self.metadata.perceptual_hash_metrics.hashed += hashed;
+			self.metadata.perceptual_hash_metrics.skipped += skipped;
+			self.metadata.perceptual_hash_metrics.hashing_time += hashing_time;
+			self.metadata.perceptual_hash_metrics.db_write_time += db_write_time;
+			self.metadata.perceptual_hash_metrics.total_successful_tasks += 1;
+
+			self.errors.extend(errors);
+		} else if any_task_output.is::<content_fingerprinter::Output>() {
+			let content_fingerprinter::Output {
+				fingerprinted,
+				skipped,
+				fingerprinting_time,
+				db_write_time,
+				errors,
+			} = *any_task_output.downcast().expect("just checked");
+
+			self.metadata.content_fingerprint_metrics.fingerprinted += fingerprinted;
+			self.metadata.content_fingerprint_metrics.skipped += skipped;
+			self.metadata
+				.content_fingerprint_metrics
+				.fingerprinting_time += fingerprinting_time;
+			self.metadata.content_fingerprint_metrics.db_write_time += db_write_time;
+			self.metadata
+				.content_fingerprint_metrics
+				.total_successful_tasks += 1;
+
+			self.errors.extend(errors);
 		} else {
 			unreachable!("Unexpected task output type: <id='{task_id}'>");
 		}
@@ -431,6 +702,16 @@ impl MediaProcessor {
 struct Metadata {
 	media_data_metrics: MediaExtractorMetrics,
 	thumbnailer_metrics_acc: ThumbnailerMetricsAccumulator,
+	#[serde(default)]
+	waveform_metrics: WaveformMetrics,
+	#[serde(default)]
+	document_preview_metrics: DocumentPreviewMetrics,
+	#[serde(default)]
+	perceptual_hash_metrics: PerceptualHashMetrics,
+	#[serde(default)]
+	content_fingerprint_metrics: ContentFingerprintMetrics,
+	#[serde(default)]
+	face_detection_metrics: FaceDetectionMetrics,
 }
 
 impl From<Metadata> for ReportOutputMetadata {
@@ -438,6 +719,11 @@ impl From<Metadata> for ReportOutputMetadata {
 		Metadata {
 			media_data_metrics,
 			thumbnailer_metrics_acc: thumbnailer_metrics_accumulator,
+			waveform_metrics,
+			document_preview_metrics,
+			perceptual_hash_metrics,
+			content_fingerprint_metrics,
+			face_detection_metrics,
 		}: Metadata,
 	) -> Self {
 		let thumbnailer_metrics = ThumbnailerMetrics::from(thumbnailer_metrics_accumulator);
@@ -454,6 +740,38 @@ impl From<Metadata> for ReportOutputMetadata {
 			// Thumbnailer
 			//
 			("thumbnailer_metrics".into(), json!(thumbnailer_metrics)),
+			//
+			// Waveform extractor
+			//
+			("waveform_metrics".into(), json!(waveform_metrics)),
+			//
+			// Document previewer
+			//
+			(
+				"document_preview_metrics".into(),
+				json!(document_preview_metrics),
+			),
+			//
+			// Perceptual hasher
+			//
+			(
+				"perceptual_hash_metrics".into(),
+				json!(perceptual_hash_metrics),
+			),
+			//
+			// Content fingerprinter
+			//
+			(
+				"content_fingerprint_metrics".into(),
+				json!(content_fingerprint_metrics),
+			),
+			//
+			// Face detector
+			//
+			(
+				"face_detection_metrics".into(),
+				json!(face_detection_metrics),
+			),
 		]))
 	}
 }
@@ -469,6 +787,52 @@ struct MediaExtractorMetrics {
 	total_successful_tasks: u64,
 }
 
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct WaveformMetrics {
+	extracted: u64,
+	skipped: u64,
+	extraction_time: Duration,
+	total_successful_tasks: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct DocumentPreviewMetrics {
+	generated: u64,
+	skipped: u64,
+	generation_time: Duration,
+	total_successful_tasks: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct PerceptualHashMetrics {
+	hashed: u64,
+	skipped: u64,
+	hashing_time: Duration,
+	db_write_time: Duration,
+	total_successful_tasks: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct ContentFingerprintMetrics {
+	fingerprinted: u64,
+	skipped: u64,
+	fingerprinting_time: Duration,
+	db_write_time: Duration,
+	total_successful_tasks: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct FaceDetectionMetrics {
+	scanned: u64,
+	faces_found: u64,
+	skipped: u64,
+	/// Faces that joined a person, known or new, after detection
+	grouped: u64,
+	detection_time: Duration,
+	db_write_time: Duration,
+	total_successful_tasks: u64,
+}
+
 #[derive(Debug, Serialize, Deserialize, Default)]
 struct ThumbnailerMetricsAccumulator {
 	generated: u64,
@@ -584,7 +948,42 @@ impl<Ctx: OuterContext> SerializableJob<Ctx> for MediaProcessor {
 								.serialize()
 								.await
 								.map(|bytes| (TaskKind::Thumbnailer, bytes))
+						} else if task.is::<tasks::WaveformExtractor>() {
+							task.downcast::<tasks::WaveformExtractor>()
+								.expect("just checked")
+								.serialize()
+								.await
+								.map(|bytes| (TaskKind::WaveformExtractor, bytes))
+						} else if task.is::<tasks::DocumentPreviewer<NewThumbnailsReporter<Ctx>>>()
+						{
+							task.downcast::<tasks::DocumentPreviewer<NewThumbnailsReporter<Ctx>>>()
+								.expect("just checked")
+								.serialize()
+								.await
+								.map(|bytes| (TaskKind::DocumentPreviewer, bytes))
+						} else if task.is::<tasks::PerceptualHasher>() {
+							task.downcast::<tasks::PerceptualHasher>()
+								.expect("just checked")
+								.serialize()
+								.await
+								.map(|bytes| (TaskKind::PerceptualHasher, bytes))
+						} else if task.is::<tasks::ContentFingerprinter>() {
+							task.downcast::<tasks::ContentFingerprinter>()
+								.expect("just checked")
+								.serialize()
+								.await
+								.map(|bytes| (TaskKind::ContentFingerprinter, bytes))
 						} else {
+							#[cfg(feature = "ai")]
+							if task.is::<tasks::FaceDetector>() {
+								return task
+									.downcast::<tasks::FaceDetector>()
+									.expect("just checked")
+									.serialize()
+									.await
+									.map(|bytes| (TaskKind::FaceDetector, bytes));
+							}
+
 							unreachable!("Unexpected task type")
 						}
 					})
@@ -625,6 +1024,8 @@ impl<Ctx: OuterContext> SerializableJob<Ctx> for MediaProcessor {
 				total_thumbnailer_tasks,
 				total_thumbnailer_files,
 				phase,
+				#[cfg(feature = "ai")]
+				face_models: None,
 				metadata,
 				errors,
 				pending_tasks_on_resume: Vec::new(),
@@ -716,6 +1117,7 @@ async fn get_all_children_files_by_extensions(
 			WHERE
 				location_id={{}}
 				AND cas_id IS NOT NULL
+				AND (in_archive IS NULL OR in_archive = 0)
 				AND LOWER(extension) IN ({})
 				AND materialized_path LIKE {{}}
 			ORDER BY materialized_path ASC",
@@ -823,3 +1225,242 @@ async fn dispatch_thumbnailer_tasks(
 			.await,
 	))
 }
+
+async fn dispatch_waveform_extractor_tasks(
+	parent_iso_file_path: &IsolatedFilePathData<'_>,
+	should_regenerate: bool,
+	location_path: &Path,
+	dispatcher: &JobTaskDispatcher,
+	ctx: &impl OuterContext,
+) -> Result<Vec<TaskHandle<Error>>, media_processor::Error> {
+	// Empty without ffmpeg, and the query below doesn't accept an empty extensions list
+	if helpers::waveform::AVAILABLE_EXTENSIONS.is_empty() {
+		return Ok(Vec::new());
+	}
+
+	let thumbnails_directory_path =
+		Arc::new(ctx.get_data_directory().join(THUMBNAIL_CACHE_DIR_NAME));
+	let location_id = parent_iso_file_path.location_id();
+	let library_id = ctx.id();
+
+	let file_paths = get_all_children_files_by_extensions(
+		ctx.db(),
+		parent_iso_file_path,
+		&helpers::waveform::AVAILABLE_EXTENSIONS,
+	)
+	.await?;
+
+	debug!(
+		"Dispatching {} audio files for waveform extraction",
+		file_paths.len()
+	);
+
+	Ok(dispatcher
+		.dispatch_many_boxed(
+			file_paths
+				.into_iter()
+				.chunks(BATCH_SIZE)
+				.into_iter()
+				.map(|chunk| {
+					tasks::WaveformExtractor::new(
+						Arc::clone(&thumbnails_directory_path),
+						&chunk.collect::<Vec<_>>(),
+						(location_id, location_path),
+						library_id,
+						should_regenerate,
+					)
+				})
+				.map(IntoTask::into_task)
+				.collect::<Vec<_>>(),
+		)
+		.await)
+}
+
+async fn dispatch_document_previewer_tasks(
+	parent_iso_file_path: &IsolatedFilePathData<'_>,
+	should_regenerate: bool,
+	location_path: &Path,
+	dispatcher: &JobTaskDispatcher,
+	ctx: &impl OuterContext,
+) -> Result<Vec<TaskHandle<Error>>, media_processor::Error> {
+	let thumbnails_directory_path =
+		Arc::new(ctx.get_data_directory().join(THUMBNAIL_CACHE_DIR_NAME));
+	let location_id = parent_iso_file_path.location_id();
+	let library_id = ctx.id();
+	let reporter = Arc::new(NewThumbnailsReporter { ctx: ctx.clone() });
+
+	let file_paths = get_all_children_files_by_extensions(
+		ctx.db(),
+		parent_iso_file_path,
+		&helpers::document_preview::AVAILABLE_EXTENSIONS,
+	)
+	.await?;
+
+	debug!(
+		"Dispatching {} documents for preview generation",
+		file_paths.len()
+	);
+
+	Ok(dispatcher
+		.dispatch_many_boxed(
+			file_paths
+				.into_iter()
+				.chunks(BATCH_SIZE)
+				.into_iter()
+				.map(|chunk| {
+					tasks::DocumentPreviewer::new(
+						Arc::clone(&thumbnails_directory_path),
+						&chunk.collect::<Vec<_>>(),
+						(location_id, location_path),
+						library_id,
+						should_regenerate,
+						false,
+						Arc::clone(&reporter),
+					)
+				})
+				.map(IntoTask::into_task)
+				.collect::<Vec<_>>(),
+		)
+		.await)
+}
+
+async fn dispatch_perceptual_hasher_tasks(
+	parent_iso_file_path: &IsolatedFilePathData<'_>,
+	should_regenerate: bool,
+	location_path: &Path,
+	dispatcher: &JobTaskDispatcher,
+	ctx: &impl OuterContext,
+) -> Result<Vec<TaskHandle<Error>>, media_processor::Error> {
+	let thumbnails_directory_path =
+		Arc::new(ctx.get_data_directory().join(THUMBNAIL_CACHE_DIR_NAME));
+	let location_id = parent_iso_file_path.location_id();
+	let library_id = ctx.id();
+	let db = ctx.db();
+
+	let file_paths = get_all_children_files_by_extensions(
+		db,
+		parent_iso_file_path,
+		&helpers::perceptual_hash::AVAILABLE_EXTENSIONS,
+	)
+	.await?;
+
+	debug!(
+		"Dispatching {} images for perceptual hashing",
+		file_paths.len()
+	);
+
+	Ok(dispatcher
+		.dispatch_many_boxed(
+			file_paths
+				.into_iter()
+				.chunks(BATCH_SIZE)
+				.into_iter()
+				.map(|chunk| {
+					tasks::PerceptualHasher::new(
+						Arc::clone(&thumbnails_directory_path),
+						&chunk.collect::<Vec<_>>(),
+						(location_id, location_path),
+						library_id,
+						should_regenerate,
+						Arc::clone(db),
+					)
+				})
+				.map(IntoTask::into_task)
+				.collect::<Vec<_>>(),
+		)
+		.await)
+}
+
+async fn dispatch_content_fingerprinter_tasks(
+	parent_iso_file_path: &IsolatedFilePathData<'_>,
+	should_regenerate: bool,
+	location_path: &Path,
+	dispatcher: &JobTaskDispatcher,
+	ctx: &impl OuterContext,
+) -> Result<Vec<TaskHandle<Error>>, media_processor::Error> {
+	// Empty without ffmpeg, and the query below doesn't accept an empty extensions list
+	if helpers::content_fingerprint::AVAILABLE_EXTENSIONS.is_empty() {
+		return Ok(Vec::new());
+	}
+
+	let location_id = parent_iso_file_path.location_id();
+	let db = ctx.db();
+
+	let file_paths = get_all_children_files_by_extensions(
+		db,
+		parent_iso_file_path,
+		&helpers::content_fingerprint::AVAILABLE_EXTENSIONS,
+	)
+	.await?;
+
+	debug!(
+		"Dispatching {} audio and video files for content fingerprinting",
+		file_paths.len()
+	);
+
+	Ok(dispatcher
+		.dispatch_many_boxed(
+			file_paths
+				.into_iter()
+				.chunks(BATCH_SIZE)
+				.into_iter()
+				.map(|chunk| {
+					tasks::ContentFingerprinter::new(
+						&chunk.collect::<Vec<_>>(),
+						(location_id, location_path),
+						should_regenerate,
+						Arc::clone(db),
+					)
+				})
+				.map(IntoTask::into_task)
+				.collect::<Vec<_>>(),
+		)
+		.await)
+}
+
+#[cfg(feature = "ai")]
+async fn dispatch_face_detector_tasks(
+	parent_iso_file_path: &IsolatedFilePathData<'_>,
+	should_regenerate: bool,
+	location_path: &Path,
+	face_models: Arc<FaceModels>,
+	dispatcher: &JobTaskDispatcher,
+	ctx: &impl OuterContext,
+) -> Result<Vec<TaskHandle<Error>>, media_processor::Error> {
+	let thumbnails_directory_path =
+		Arc::new(ctx.get_data_directory().join(THUMBNAIL_CACHE_DIR_NAME));
+	let location_id = parent_iso_file_path.location_id();
+	let library_id = ctx.id();
+	let db = ctx.db();
+
+	let file_paths = get_all_children_files_by_extensions(
+		db,
+		parent_iso_file_path,
+		&helpers::faces::AVAILABLE_EXTENSIONS,
+	)
+	.await?;
+
+	debug!("Dispatching {} images for face detection", file_paths.len());
+
+	Ok(dispatcher
+		.dispatch_many_boxed(
+			file_paths
+				.into_iter()
+				.chunks(BATCH_SIZE)
+				.into_iter()
+				.map(|chunk| {
+					tasks::FaceDetector::new(
+						Arc::clone(&thumbnails_directory_path),
+						&chunk.collect::<Vec<_>>(),
+						(location_id, location_path),
+						library_id,
+						should_regenerate,
+						Arc::clone(&face_models),
+						Arc::clone(db),
+					)
+				})
+				.map(IntoTask::into_task)
+				.collect::<Vec<_>>(),
+		)
+		.await)
+}
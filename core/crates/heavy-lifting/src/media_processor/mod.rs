@@ -1,4 +1,7 @@
-use crate::{utils::sub_path, OuterContext, UpdateEvent};
+use crate::{
+	utils::{network_share, sub_path},
+	OuterContext, UpdateEvent,
+};
 
 use sd_core_file_path_helper::FilePathError;
 
@@ -11,15 +14,42 @@ use specta::Type;
 
 mod helpers;
 pub mod job;
+mod on_demand;
 mod shallow;
 mod tasks;
 
 pub use tasks::{
+	content_fingerprinter::{self, ContentFingerprinter},
+	document_previewer::{self, DocumentPreviewer},
 	media_data_extractor::{self, MediaDataExtractor},
+	perceptual_hasher::{self, PerceptualHasher},
 	thumbnailer::{self, Thumbnailer},
+	waveform_extractor::{self, WaveformExtractor},
+};
+
+#[cfg(feature = "ai")]
+pub use tasks::face_detector::{self, FaceDetector};
+
+pub use helpers::faces::{
+	embedding_from_bytes, embedding_to_bytes, group_faces, refresh_people,
+	similarity as embedding_similarity,
 };
 
-pub use helpers::thumbnailer::{ThumbKey, ThumbnailKind};
+pub use helpers::content_fingerprint::{
+	audio_fingerprint, group_similar_content, video_signature, Fingerprint as ContentFingerprint,
+	FingerprintKind as ContentFingerprintKind, AUDIO_FINGERPRINT_DURATION, VIDEO_SIGNATURE_FRAMES,
+};
+pub use helpers::ffmpeg_media_data::{can_extract_for_audio, can_extract_for_video};
+pub use helpers::perceptual_hash::{
+	distance as perceptual_hash_distance, from_bytes as perceptual_hash_from_bytes, group_similar,
+	DEFAULT_SIMILARITY_THRESHOLD, MAX_SIMILARITY_THRESHOLD,
+};
+pub use helpers::thumbnailer::{
+	can_generate_thumbnail_for_image, preview_strip_path, thumbnail_path, PreviewStrip, ThumbKey,
+	ThumbnailKind, PREVIEW_STRIP_FRAMES, THUMBNAIL_CACHE_DIR_NAME,
+};
+pub use helpers::waveform::{waveform_path, Waveform, WAVEFORM_EXTENSION, WAVEFORM_PEAKS};
+pub use on_demand::{on_demand, MAX_ON_DEMAND_CAS_IDS};
 pub use shallow::shallow;
 
 use self::thumbnailer::NewThumbnailReporter;
@@ -39,6 +69,8 @@ pub enum Error {
 	FilePathError(#[from] FilePathError),
 	#[error(transparent)]
 	SubPath(#[from] sub_path::Error),
+	#[error(transparent)]
+	NetworkShare(#[from] network_share::Error),
 }
 
 impl From<Error> for rspc::Error {
@@ -49,10 +81,21 @@ impl From<Error> for rspc::Error {
 
 #[derive(thiserror::Error, Debug, Serialize, Deserialize, Type)]
 pub enum NonCriticalError {
+	#[error(transparent)]
+	ContentFingerprinter(#[from] content_fingerprinter::NonCriticalError),
+	#[error(transparent)]
+	DocumentPreviewer(#[from] document_previewer::NonCriticalError),
+	#[cfg(feature = "ai")]
+	#[error(transparent)]
+	FaceDetector(#[from] face_detector::NonCriticalError),
 	#[error(transparent)]
 	MediaDataExtractor(#[from] media_data_extractor::NonCriticalError),
 	#[error(transparent)]
+	PerceptualHasher(#[from] perceptual_hasher::NonCriticalError),
+	#[error(transparent)]
 	Thumbnailer(#[from] thumbnailer::NonCriticalError),
+	#[error(transparent)]
+	WaveformExtractor(#[from] waveform_extractor::NonCriticalError),
 }
 
 struct NewThumbnailsReporter<Ctx: OuterContext> {
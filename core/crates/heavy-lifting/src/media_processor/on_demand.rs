@@ -0,0 +1,155 @@
+use crate::{media_processor, Error, NonCriticalError, OuterContext};
+
+use sd_core_prisma_helpers::file_path_for_media_processor;
+
+use sd_prisma::prisma::{file_path, location};
+use sd_task_system::{
+	BaseTaskDispatcher, CancelTaskOnDrop, IntoTask, TaskDispatcher, TaskOutput, TaskStatus,
+};
+use sd_utils::db::maybe_missing;
+
+use std::{collections::HashSet, path::PathBuf, sync::Arc};
+
+use futures::StreamExt;
+use futures_concurrency::future::FutureGroup;
+use itertools::Itertools;
+use tracing::{debug, warn};
+
+use super::{
+	helpers::thumbnailer::{ALL_THUMBNAILABLE_EXTENSIONS, THUMBNAIL_CACHE_DIR_NAME},
+	tasks::{self, thumbnailer},
+	NewThumbnailsReporter, BATCH_SIZE,
+};
+
+/// How many `cas_ids` the frontend may ask for at once, a screen full of grid items is way less
+pub const MAX_ON_DEMAND_CAS_IDS: usize = 256;
+
+/// Generates thumbnails for the given `cas_ids` right away, instead of waiting for the media
+/// processor to get to them.
+///
+/// These tasks are dispatched with priority, so the task system suspends the bulk media processor
+/// tasks running on its workers to run them first, resuming those afterwards. Thumbnails that
+/// already exist are skipped, so the frontend can just ask for everything it's showing.
+#[allow(clippy::missing_panics_doc)] // SAFETY: It doesn't actually panics
+pub async fn on_demand(
+	cas_ids: Vec<String>,
+	dispatcher: BaseTaskDispatcher<Error>,
+	ctx: impl OuterContext,
+) -> Result<Vec<NonCriticalError>, Error> {
+	let cas_ids = cas_ids
+		.into_iter()
+		.take(MAX_ON_DEMAND_CAS_IDS)
+		.collect::<Vec<_>>();
+
+	if cas_ids.is_empty() {
+		return Ok(vec![]);
+	}
+
+	let db = ctx.db();
+	let thumbnails_directory_path =
+		Arc::new(ctx.get_data_directory().join(THUMBNAIL_CACHE_DIR_NAME));
+	let library_id = ctx.id();
+	let reporter = Arc::new(NewThumbnailsReporter { ctx: ctx.clone() });
+
+	let locations = db
+		.location()
+		.find_many(vec![location::file_paths::some(vec![
+			file_path::cas_id::in_vec(cas_ids.iter().cloned().map(Some).collect()),
+		])])
+		.exec()
+		.await
+		.map_err(media_processor::Error::from)?;
+
+	let thumbnailable_extensions = ALL_THUMBNAILABLE_EXTENSIONS
+		.iter()
+		.map(|extension| extension.to_string().to_lowercase())
+		.collect::<HashSet<_>>();
+
+	// The same content can live in many locations, one thumbnail is enough for all of them
+	let mut seen_cas_ids = HashSet::with_capacity(cas_ids.len());
+	let mut tasks = Vec::new();
+
+	for location in locations {
+		let location_path = maybe_missing(&location.path, "location.path")
+			.map(PathBuf::from)
+			.map_err(media_processor::Error::from)?;
+
+		let file_paths = db
+			.file_path()
+			.find_many(vec![
+				file_path::location_id::equals(Some(location.id)),
+				file_path::cas_id::in_vec(cas_ids.iter().cloned().map(Some).collect()),
+			])
+			.select(file_path_for_media_processor::select())
+			.exec()
+			.await
+			.map_err(media_processor::Error::from)?
+			.into_iter()
+			.filter(|file_path| {
+				file_path.extension.as_ref().is_some_and(|extension| {
+					thumbnailable_extensions.contains(&extension.to_lowercase())
+				}) && file_path
+					.cas_id
+					.as_ref()
+					.is_some_and(|cas_id| seen_cas_ids.insert(cas_id.clone()))
+			})
+			.collect::<Vec<_>>();
+
+		tasks.extend(
+			file_paths
+				.into_iter()
+				.chunks(BATCH_SIZE)
+				.into_iter()
+				.map(|chunk| {
+					tasks::Thumbnailer::new_indexed(
+						Arc::clone(&thumbnails_directory_path),
+						&chunk.collect::<Vec<_>>(),
+						(location.id, &location_path),
+						library_id,
+						false,
+						true,
+						Arc::clone(&reporter),
+					)
+				})
+				.map(IntoTask::into_task),
+		);
+	}
+
+	debug!(
+		"Dispatching {} on demand thumbnailer tasks for {} cas_ids",
+		tasks.len(),
+		seen_cas_ids.len()
+	);
+
+	let mut futures = dispatcher
+		.dispatch_many_boxed(tasks)
+		.await
+		.into_iter()
+		.map(CancelTaskOnDrop)
+		.collect::<FutureGroup<_>>();
+
+	let mut errors = vec![];
+
+	while let Some(res) = futures.next().await {
+		match res {
+			Ok(TaskStatus::Done((_, TaskOutput::Out(out)))) => {
+				errors.extend(
+					out.downcast::<thumbnailer::Output>()
+						.expect("on demand only dispatches thumbnailer tasks")
+						.errors,
+				);
+			}
+			Ok(TaskStatus::Done((_, TaskOutput::Empty))) => {
+				warn!("Task returned empty output on on demand thumbnails generation");
+			}
+			Ok(TaskStatus::Canceled | TaskStatus::ForcedAbortion | TaskStatus::Shutdown(_)) => {
+				return Ok(errors);
+			}
+			Ok(TaskStatus::Error(e)) => return Err(e),
+
+			Err(e) => return Err(e.into()),
+		}
+	}
+
+	Ok(errors)
+}
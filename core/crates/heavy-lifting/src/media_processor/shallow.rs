@@ -27,7 +27,7 @@ use tracing::{debug, warn};
 
 use super::{
 	helpers::{self, exif_media_data, ffmpeg_media_data, thumbnailer::THUMBNAIL_CACHE_DIR_NAME},
-	tasks::{self, media_data_extractor, thumbnailer},
+	tasks::{self, document_previewer, media_data_extractor, thumbnailer},
 	NewThumbnailsReporter, BATCH_SIZE,
 };
 
@@ -80,6 +80,12 @@ pub async fn shallow(
 			.into_iter()
 			.map(CancelTaskOnDrop),
 	)
+	.chain(
+		dispatch_document_previewer_tasks(&sub_iso_file_path, &location_path, &dispatcher, &ctx)
+			.await?
+			.into_iter()
+			.map(CancelTaskOnDrop),
+	)
 	.collect::<FutureGroup<_>>();
 
 	while let Some(res) = futures.next().await {
@@ -97,6 +103,12 @@ pub async fn shallow(
 							.expect("just checked")
 							.errors,
 					);
+				} else if out.is::<document_previewer::Output>() {
+					errors.extend(
+						out.downcast::<document_previewer::Output>()
+							.expect("just checked")
+							.errors,
+					);
 				} else {
 					unreachable!(
 						"Task returned unexpected output type on media processor shallow job"
@@ -188,6 +200,7 @@ async fn get_files_by_extensions(
 			WHERE
 				location_id={{}}
 				AND cas_id IS NOT NULL
+				AND (in_archive IS NULL OR in_archive = 0)
 				AND LOWER(extension) IN ({})
 				AND materialized_path = {{}}",
 			extensions
@@ -256,3 +269,48 @@ async fn dispatch_thumbnailer_tasks(
 
 	Ok(dispatcher.dispatch_many_boxed(tasks).await)
 }
+
+async fn dispatch_document_previewer_tasks(
+	parent_iso_file_path: &IsolatedFilePathData<'_>,
+	location_path: &PathBuf,
+	dispatcher: &BaseTaskDispatcher<Error>,
+	ctx: &impl OuterContext,
+) -> Result<Vec<TaskHandle<Error>>, media_processor::Error> {
+	let thumbnails_directory_path =
+		Arc::new(ctx.get_data_directory().join(THUMBNAIL_CACHE_DIR_NAME));
+	let location_id = parent_iso_file_path.location_id();
+	let library_id = ctx.id();
+	let reporter = Arc::new(NewThumbnailsReporter { ctx: ctx.clone() });
+
+	let file_paths = get_files_by_extensions(
+		ctx.db(),
+		parent_iso_file_path,
+		&helpers::document_preview::AVAILABLE_EXTENSIONS,
+	)
+	.await?;
+
+	let tasks = file_paths
+		.into_iter()
+		.chunks(BATCH_SIZE)
+		.into_iter()
+		.map(|chunk| {
+			tasks::DocumentPreviewer::new(
+				Arc::clone(&thumbnails_directory_path),
+				&chunk.collect::<Vec<_>>(),
+				(location_id, location_path),
+				library_id,
+				false,
+				true,
+				Arc::clone(&reporter),
+			)
+		})
+		.map(IntoTask::into_task)
+		.collect::<Vec<_>>();
+
+	debug!(
+		"Dispatching {} document previewer tasks with priority",
+		tasks.len()
+	);
+
+	Ok(dispatcher.dispatch_many_boxed(tasks).await)
+}
@@ -0,0 +1,318 @@
+use crate::{
+	media_processor::{
+		self,
+		helpers::content_fingerprint::{save, Fingerprint, FingerprintKind},
+	},
+	Error,
+};
+
+use sd_core_file_path_helper::IsolatedFilePathData;
+use sd_core_prisma_helpers::file_path_for_media_processor;
+
+use sd_file_ext::extensions::VideoExtension;
+use sd_prisma::prisma::{content_fingerprint, file_path, location, object, PrismaClient};
+use sd_task_system::{
+	check_interruption, ExecStatus, Interrupter, IntoAnyTaskOutput, SerializableTask, Task, TaskId,
+};
+
+use std::{
+	collections::HashSet,
+	mem,
+	path::{Path, PathBuf},
+	str::FromStr,
+	sync::Arc,
+	time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::time::Instant;
+use tracing::error;
+
+/// Fingerprints audio and video files by their content, to find different encodes of the same song,
+/// movie or episode
+#[derive(Debug)]
+pub struct ContentFingerprinter {
+	id: TaskId,
+	files: Vec<(object::id::Type, FingerprintKind, PathBuf)>,
+	should_regenerate: bool,
+	fetched_already_fingerprinted: bool,
+	fingerprints: Vec<(Fingerprint, object::id::Type)>,
+	db: Arc<PrismaClient>,
+	output: Output,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Output {
+	pub fingerprinted: u64,
+	pub skipped: u64,
+	pub fingerprinting_time: Duration,
+	pub db_write_time: Duration,
+	pub errors: Vec<crate::NonCriticalError>,
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize, Type)]
+pub enum NonCriticalError {
+	#[error("failed to extract isolated file path data from file path <id='{0}'>: {1}")]
+	FailedToExtractIsolatedFilePathData(file_path::id::Type, String),
+	#[error("file path missing object id: <file_path_id='{0}'>")]
+	FilePathMissingObjectId(file_path::id::Type),
+	#[error("failed to decode audio to fingerprint <path='{}'>: {1}", .0.display())]
+	DecodeAudio(PathBuf, String),
+	#[error("failed to decode video frames to fingerprint <path='{}'>: {1}", .0.display())]
+	DecodeVideo(PathBuf, String),
+}
+
+impl ContentFingerprinter {
+	#[must_use]
+	pub fn new(
+		file_paths: &[file_path_for_media_processor::Data],
+		(location_id, location_path): (location::id::Type, &Path),
+		should_regenerate: bool,
+		db: Arc<PrismaClient>,
+	) -> Self {
+		let mut errors = Vec::new();
+		let mut seen_objects = HashSet::with_capacity(file_paths.len());
+
+		Self {
+			id: TaskId::new_v4(),
+			files: file_paths
+				.iter()
+				.filter_map(|file_path| {
+					let Some(object_id) = file_path.object_id else {
+						errors.push(
+							media_processor::NonCriticalError::from(
+								NonCriticalError::FilePathMissingObjectId(file_path.id),
+							)
+							.into(),
+						);
+						return None;
+					};
+
+					// Many file paths can point to the same object, we fingerprint it just once
+					if !seen_objects.insert(object_id) {
+						return None;
+					}
+
+					IsolatedFilePathData::try_from((location_id, file_path))
+						.map_err(|e| {
+							errors.push(
+								media_processor::NonCriticalError::from(
+									NonCriticalError::FailedToExtractIsolatedFilePathData(
+										file_path.id,
+										e.to_string(),
+									),
+								)
+								.into(),
+							);
+						})
+						.ok()
+						.map(|iso_file_path| {
+							// Files are only fetched by the audio and video extensions in
+							// `helpers::content_fingerprint`
+							let kind =
+								if VideoExtension::from_str(iso_file_path.extension()).is_ok() {
+									FingerprintKind::Video
+								} else {
+									FingerprintKind::Audio
+								};
+
+							(object_id, kind, location_path.join(iso_file_path))
+						})
+				})
+				.collect(),
+			should_regenerate,
+			fetched_already_fingerprinted: false,
+			fingerprints: Vec::new(),
+			db,
+			output: Output {
+				errors,
+				..Default::default()
+			},
+		}
+	}
+}
+
+#[async_trait::async_trait]
+impl Task<Error> for ContentFingerprinter {
+	fn id(&self) -> TaskId {
+		self.id
+	}
+
+	async fn run(&mut self, interrupter: &Interrupter) -> Result<ExecStatus, Error> {
+		let Self {
+			files,
+			should_regenerate,
+			fetched_already_fingerprinted,
+			fingerprints,
+			db,
+			output:
+				Output {
+					fingerprinted,
+					skipped,
+					fingerprinting_time,
+					db_write_time,
+					errors,
+				},
+			..
+		} = self;
+
+		if !*should_regenerate && !*fetched_already_fingerprinted {
+			let already_fingerprinted = db
+				.content_fingerprint()
+				.find_many(vec![content_fingerprint::object_id::in_vec(
+					files.iter().map(|(object_id, _, _)| *object_id).collect(),
+				)])
+				.select(content_fingerprint::select!({ object_id }))
+				.exec()
+				.await
+				.map_err(media_processor::Error::from)?
+				.into_iter()
+				.map(|data| data.object_id)
+				.collect::<HashSet<_>>();
+
+			*skipped += already_fingerprinted.len() as u64;
+			files.retain(|(object_id, _, _)| !already_fingerprinted.contains(object_id));
+			*fetched_already_fingerprinted = true;
+		}
+
+		let start = Instant::now();
+
+		// Files are popped, so a resumed task only processes the remaining ones
+		while let Some((object_id, kind, path)) = files.pop() {
+			match fingerprint(kind, &path).await {
+				Ok(fingerprint) => fingerprints.push((fingerprint, object_id)),
+				Err(e) => {
+					error!("{e:#?}");
+					errors.push(media_processor::NonCriticalError::from(e).into());
+					*skipped += 1;
+				}
+			}
+
+			check_interruption!(interrupter, start, fingerprinting_time);
+		}
+
+		*fingerprinting_time += start.elapsed();
+
+		let db_write_start = Instant::now();
+		*fingerprinted = save(mem::take(fingerprints), db).await?;
+		*db_write_time = db_write_start.elapsed();
+
+		Ok(ExecStatus::Done(mem::take(&mut self.output).into_output()))
+	}
+}
+
+#[cfg(feature = "ffmpeg")]
+async fn fingerprint(kind: FingerprintKind, path: &Path) -> Result<Fingerprint, NonCriticalError> {
+	use crate::media_processor::helpers::content_fingerprint::{
+		audio_fingerprint, video_signature, AUDIO_FINGERPRINT_DURATION, VIDEO_SIGNATURE_FRAMES,
+	};
+
+	use sd_ffmpeg::ThumbnailSize;
+
+	/// Perceptual hashes only look at 32x32 pixels, so small frames are plenty and way cheaper
+	const FRAME_SIZE: u32 = 128;
+
+	match kind {
+		FingerprintKind::Audio => sd_ffmpeg::to_chroma(path, AUDIO_FINGERPRINT_DURATION)
+			.await
+			.map(|chromas| Fingerprint::Audio(audio_fingerprint(&chromas)))
+			.map_err(|e| NonCriticalError::DecodeAudio(path.to_path_buf(), e.to_string())),
+
+		FingerprintKind::Video => sd_ffmpeg::to_frame_samples(
+			path,
+			VIDEO_SIGNATURE_FRAMES,
+			ThumbnailSize::Scale(FRAME_SIZE),
+		)
+		.await
+		.map(|frames| Fingerprint::Video(video_signature(&frames)))
+		.map_err(|e| NonCriticalError::DecodeVideo(path.to_path_buf(), e.to_string())),
+	}
+}
+
+// Tasks are only dispatched for extensions that ffmpeg can decode, see `helpers::content_fingerprint`
+#[cfg(not(feature = "ffmpeg"))]
+#[allow(clippy::unused_async)]
+async fn fingerprint(kind: FingerprintKind, path: &Path) -> Result<Fingerprint, NonCriticalError> {
+	let reason = "ffmpeg is disabled".to_string();
+
+	Err(match kind {
+		FingerprintKind::Audio => NonCriticalError::DecodeAudio(path.to_path_buf(), reason),
+		FingerprintKind::Video => NonCriticalError::DecodeVideo(path.to_path_buf(), reason),
+	})
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SaveState {
+	id: TaskId,
+	files: Vec<(object::id::Type, FingerprintKind, PathBuf)>,
+	should_regenerate: bool,
+	fetched_already_fingerprinted: bool,
+	fingerprints: Vec<(FingerprintKind, Vec<u8>, object::id::Type)>,
+	output: Output,
+}
+
+impl SerializableTask<Error> for ContentFingerprinter {
+	type SerializeError = rmp_serde::encode::Error;
+
+	type DeserializeError = rmp_serde::decode::Error;
+
+	type DeserializeCtx = Arc<PrismaClient>;
+
+	async fn serialize(self) -> Result<Vec<u8>, Self::SerializeError> {
+		let Self {
+			id,
+			files,
+			should_regenerate,
+			fetched_already_fingerprinted,
+			fingerprints,
+			output,
+			..
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState {
+			id,
+			files,
+			should_regenerate,
+			fetched_already_fingerprinted,
+			// Fingerprints are kept the same way they're stored in the database
+			fingerprints: fingerprints
+				.into_iter()
+				.map(|(fingerprint, object_id)| {
+					(fingerprint.kind(), fingerprint.to_bytes(), object_id)
+				})
+				.collect(),
+			output,
+		})
+	}
+
+	async fn deserialize(
+		data: &[u8],
+		db: Self::DeserializeCtx,
+	) -> Result<Self, Self::DeserializeError> {
+		rmp_serde::from_slice(data).map(
+			|SaveState {
+			     id,
+			     files,
+			     should_regenerate,
+			     fetched_already_fingerprinted,
+			     fingerprints,
+			     output,
+			 }| Self {
+				id,
+				files,
+				should_regenerate,
+				fetched_already_fingerprinted,
+				fingerprints: fingerprints
+					.into_iter()
+					.filter_map(|(kind, bytes, object_id)| {
+						Fingerprint::from_bytes(kind, &bytes)
+							.map(|fingerprint| (fingerprint, object_id))
+					})
+					.collect(),
+				db,
+				output,
+			},
+		)
+	}
+}
@@ -0,0 +1,346 @@
+use crate::{
+	media_processor::{
+		self,
+		helpers::{
+			document_preview::{preview_source, PreviewSource},
+			thumbnailer::thumbnail_path,
+		},
+		tasks::thumbnailer::{
+			self, encode_thumbnail, exists, save_thumbnail, NewThumbnailReporter,
+		},
+		ThumbKey, ThumbnailKind,
+	},
+	Error,
+};
+
+use sd_core_file_path_helper::IsolatedFilePathData;
+use sd_core_prisma_helpers::file_path_for_media_processor;
+
+use sd_file_ext::extensions::DocumentExtension;
+use sd_images::format_image;
+use sd_prisma::prisma::{file_path, location};
+use sd_task_system::{
+	check_interruption, ExecStatus, Interrupter, IntoAnyTaskOutput, SerializableTask, Task, TaskId,
+};
+
+use std::{
+	fs::File,
+	io::Read,
+	mem,
+	path::{Path, PathBuf},
+	str::FromStr,
+	sync::Arc,
+	time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::{task::spawn_blocking, time::Instant};
+use tracing::{error, trace};
+use uuid::Uuid;
+use zip::{result::ZipError, ZipArchive};
+
+/// Embedded thumbnails bigger than this are surely not thumbnails, so we don't read them
+const MAX_EMBEDDED_THUMBNAIL_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Renders previews for documents, saving them as regular thumbnails so the frontend doesn't
+/// need to know how each of them was made
+#[derive(Debug)]
+pub struct DocumentPreviewer<Reporter: NewThumbnailReporter> {
+	id: TaskId,
+	reporter: Arc<Reporter>,
+	library_id: Uuid,
+	thumbnails_directory_path: Arc<PathBuf>,
+	files: Vec<(String, String, PathBuf)>,
+	should_regenerate: bool,
+	with_priority: bool,
+	output: Output,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Output {
+	pub generated: u64,
+	pub skipped: u64,
+	pub generation_time: Duration,
+	pub errors: Vec<crate::NonCriticalError>,
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize, Type)]
+pub enum NonCriticalError {
+	#[error("failed to extract isolated file path data from file path <id='{0}'>: {1}")]
+	FailedToExtractIsolatedFilePathData(file_path::id::Type, String),
+	#[error("failed to render document first page <path='{}'>: {1}", .0.display())]
+	RenderFirstPage(PathBuf, String),
+	#[error("failed to read document container <path='{}'>: {1}", .0.display())]
+	ReadContainer(PathBuf, String),
+	#[error("document has no embedded thumbnail <path='{}'>", .0.display())]
+	NoEmbeddedThumbnail(PathBuf),
+	#[error("failed to decode embedded thumbnail <path='{}'>: {1}", .0.display())]
+	DecodeEmbeddedThumbnail(PathBuf, String),
+	#[error("processing thread panicked while generating document preview <path='{}'>: {1}", .0.display())]
+	PanicWhileGeneratingPreview(PathBuf, String),
+	#[error(transparent)]
+	Thumbnail(#[from] thumbnailer::NonCriticalError),
+}
+
+impl<Reporter: NewThumbnailReporter> DocumentPreviewer<Reporter> {
+	#[must_use]
+	pub fn new(
+		thumbnails_directory_path: Arc<PathBuf>,
+		file_paths: &[file_path_for_media_processor::Data],
+		(location_id, location_path): (location::id::Type, &Path),
+		library_id: Uuid,
+		should_regenerate: bool,
+		with_priority: bool,
+		reporter: Arc<Reporter>,
+	) -> Self {
+		let mut errors = Vec::new();
+
+		Self {
+			id: TaskId::new_v4(),
+			reporter,
+			library_id,
+			thumbnails_directory_path,
+			files: file_paths
+				.iter()
+				.filter_map(|file_path| {
+					// Only file paths with cas_id are fetched for the media processor
+					let cas_id = file_path.cas_id.clone()?;
+
+					IsolatedFilePathData::try_from((location_id, file_path))
+						.map_err(|e| {
+							errors.push(
+								media_processor::NonCriticalError::from(
+									NonCriticalError::FailedToExtractIsolatedFilePathData(
+										file_path.id,
+										e.to_string(),
+									),
+								)
+								.into(),
+							);
+						})
+						.ok()
+						.map(|iso_file_path| {
+							(
+								cas_id,
+								iso_file_path.extension().to_string(),
+								location_path.join(iso_file_path),
+							)
+						})
+				})
+				.collect(),
+			should_regenerate,
+			with_priority,
+			output: Output {
+				errors,
+				..Default::default()
+			},
+		}
+	}
+}
+
+#[async_trait::async_trait]
+impl<Reporter: NewThumbnailReporter> Task<Error> for DocumentPreviewer<Reporter> {
+	fn id(&self) -> TaskId {
+		self.id
+	}
+
+	fn with_priority(&self) -> bool {
+		self.with_priority
+	}
+
+	async fn run(&mut self, interrupter: &Interrupter) -> Result<ExecStatus, Error> {
+		let Self {
+			reporter,
+			library_id,
+			thumbnails_directory_path,
+			files,
+			should_regenerate,
+			with_priority,
+			output: Output {
+				generated,
+				skipped,
+				generation_time,
+				errors,
+			},
+			..
+		} = self;
+
+		let start = Instant::now();
+		let kind = ThumbnailKind::Indexed(*library_id);
+
+		// Files are popped, so a resumed task only processes the remaining ones
+		while let Some((cas_id, extension, path)) = files.pop() {
+			let output_path = thumbnail_path(&**thumbnails_directory_path, &cas_id, &kind);
+
+			if !*should_regenerate && exists(&output_path).await {
+				trace!(
+					"Skipping document preview for {} because it already exists",
+					path.display()
+				);
+				*skipped += 1;
+			} else {
+				match generate_preview(&extension, &path, &output_path).await {
+					Ok(()) => {
+						*generated += 1;
+
+						// Same as the thumbnailer, only files in the currently opened directory
+						// are worth notifying the frontend about
+						if *with_priority {
+							reporter.new_thumbnail(ThumbKey::new(&cas_id, &kind));
+						}
+					}
+					Err(e) => {
+						error!("{e:#?}");
+						errors.push(media_processor::NonCriticalError::from(e).into());
+						*skipped += 1;
+					}
+				}
+			}
+
+			check_interruption!(interrupter, start, generation_time);
+		}
+
+		*generation_time += start.elapsed();
+
+		Ok(ExecStatus::Done(mem::take(&mut self.output).into_output()))
+	}
+}
+
+async fn generate_preview(
+	extension: &str,
+	path: &Path,
+	output_path: &Path,
+) -> Result<(), NonCriticalError> {
+	// Files are only fetched by the extensions in `helpers::document_preview`
+	let Some(source) = DocumentExtension::from_str(extension)
+		.ok()
+		.and_then(preview_source)
+	else {
+		return Err(NonCriticalError::NoEmbeddedThumbnail(path.to_path_buf()));
+	};
+
+	let webp = spawn_blocking({
+		let path = path.to_path_buf();
+
+		move || -> Result<_, NonCriticalError> {
+			let img = match source {
+				PreviewSource::FirstPage => format_image(&path)
+					.map_err(|e| NonCriticalError::RenderFirstPage(path.clone(), e.to_string()))?,
+
+				PreviewSource::EmbeddedThumbnail(entries) => {
+					let bytes = read_embedded_thumbnail(&path, entries)?;
+					image::load_from_memory(&bytes).map_err(|e| {
+						NonCriticalError::DecodeEmbeddedThumbnail(path.clone(), e.to_string())
+					})?
+				}
+			};
+
+			encode_thumbnail(img, &path).map_err(Into::into)
+		}
+	})
+	.await
+	.map_err(|e| {
+		NonCriticalError::PanicWhileGeneratingPreview(path.to_path_buf(), e.to_string())
+	})??;
+
+	save_thumbnail(path, output_path, &webp)
+		.await
+		.map_err(Into::into)
+}
+
+fn read_embedded_thumbnail(path: &Path, entries: &[&str]) -> Result<Vec<u8>, NonCriticalError> {
+	let read_container_failed =
+		|e: &dyn ToString| NonCriticalError::ReadContainer(path.to_path_buf(), e.to_string());
+
+	let mut archive = File::open(path)
+		.map_err(|e| read_container_failed(&e))
+		.and_then(|file| ZipArchive::new(file).map_err(|e| read_container_failed(&e)))?;
+
+	for entry in entries {
+		match archive.by_name(entry) {
+			Ok(file) if file.size() <= MAX_EMBEDDED_THUMBNAIL_SIZE => {
+				#[allow(clippy::cast_possible_truncation)]
+				// SAFETY: we just checked that the entry is small
+				let mut bytes = Vec::with_capacity(file.size() as usize);
+				file.take(MAX_EMBEDDED_THUMBNAIL_SIZE)
+					.read_to_end(&mut bytes)
+					.map_err(|e| read_container_failed(&e))?;
+
+				return Ok(bytes);
+			}
+			Ok(_) | Err(ZipError::FileNotFound) => {}
+			Err(e) => return Err(read_container_failed(&e)),
+		}
+	}
+
+	Err(NonCriticalError::NoEmbeddedThumbnail(path.to_path_buf()))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SaveState {
+	id: TaskId,
+	library_id: Uuid,
+	thumbnails_directory_path: Arc<PathBuf>,
+	files: Vec<(String, String, PathBuf)>,
+	should_regenerate: bool,
+	with_priority: bool,
+	output: Output,
+}
+
+impl<Reporter: NewThumbnailReporter> SerializableTask<Error> for DocumentPreviewer<Reporter> {
+	type SerializeError = rmp_serde::encode::Error;
+
+	type DeserializeError = rmp_serde::decode::Error;
+
+	type DeserializeCtx = Arc<Reporter>;
+
+	async fn serialize(self) -> Result<Vec<u8>, Self::SerializeError> {
+		let Self {
+			id,
+			library_id,
+			thumbnails_directory_path,
+			files,
+			should_regenerate,
+			with_priority,
+			output,
+			..
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState {
+			id,
+			library_id,
+			thumbnails_directory_path,
+			files,
+			should_regenerate,
+			with_priority,
+			output,
+		})
+	}
+
+	async fn deserialize(
+		data: &[u8],
+		reporter: Self::DeserializeCtx,
+	) -> Result<Self, Self::DeserializeError> {
+		rmp_serde::from_slice(data).map(
+			|SaveState {
+			     id,
+			     library_id,
+			     thumbnails_directory_path,
+			     files,
+			     should_regenerate,
+			     with_priority,
+			     output,
+			 }| Self {
+				id,
+				reporter,
+				library_id,
+				thumbnails_directory_path,
+				files,
+				should_regenerate,
+				with_priority,
+				output,
+			},
+		)
+	}
+}
@@ -0,0 +1,400 @@
+use crate::{
+	media_processor::{
+		self,
+		helpers::{faces::embedding_to_bytes, thumbnailer::thumbnail_path},
+		ThumbnailKind,
+	},
+	Error,
+};
+
+use sd_core_file_path_helper::IsolatedFilePathData;
+use sd_core_prisma_helpers::file_path_for_media_processor;
+
+use sd_ai::faces::{DetectedFace, FaceModels, FACE_MODELS_ID};
+use sd_images::{format_image, ConvertibleExtension};
+use sd_media_metadata::exif::Orientation;
+use sd_prisma::prisma::{face, face_scan, file_path, location, object, PrismaClient};
+use sd_task_system::{
+	check_interruption, ExecStatus, Interrupter, IntoAnyTaskOutput, SerializableTask, Task, TaskId,
+};
+
+use std::{
+	collections::HashSet,
+	mem,
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::{task::spawn_blocking, time::Instant};
+use tracing::{error, trace};
+use uuid::Uuid;
+
+/// Finds the faces in images and their embeddings, which are grouped into people once the media
+/// processor is done
+#[derive(Debug)]
+pub struct FaceDetector {
+	id: TaskId,
+	library_id: Uuid,
+	thumbnails_directory_path: Arc<PathBuf>,
+	files: Vec<(object::id::Type, String, PathBuf)>,
+	should_regenerate: bool,
+	fetched_already_scanned: bool,
+	detected: Vec<(object::id::Type, Vec<DetectedFace>)>,
+	models: Arc<FaceModels>,
+	db: Arc<PrismaClient>,
+	output: Output,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Output {
+	pub scanned: u64,
+	pub faces_found: u64,
+	pub skipped: u64,
+	pub detection_time: Duration,
+	pub db_write_time: Duration,
+	pub errors: Vec<crate::NonCriticalError>,
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize, Type)]
+pub enum NonCriticalError {
+	#[error("failed to extract isolated file path data from file path <id='{0}'>: {1}")]
+	FailedToExtractIsolatedFilePathData(file_path::id::Type, String),
+	#[error("file path missing object id: <file_path_id='{0}'>")]
+	FilePathMissingObjectId(file_path::id::Type),
+	#[error("failed to decode image to detect faces <path='{}'>: {1}", .0.display())]
+	FormatImage(PathBuf, String),
+	#[error("failed to detect faces <path='{}'>: {1}", .0.display())]
+	Detect(PathBuf, String),
+	#[error("processing thread panicked while detecting faces <path='{}'>: {1}", .0.display())]
+	PanicWhileDetecting(PathBuf, String),
+}
+
+impl FaceDetector {
+	#[must_use]
+	pub fn new(
+		thumbnails_directory_path: Arc<PathBuf>,
+		file_paths: &[file_path_for_media_processor::Data],
+		(location_id, location_path): (location::id::Type, &Path),
+		library_id: Uuid,
+		should_regenerate: bool,
+		models: Arc<FaceModels>,
+		db: Arc<PrismaClient>,
+	) -> Self {
+		let mut errors = Vec::new();
+		let mut seen_objects = HashSet::with_capacity(file_paths.len());
+
+		Self {
+			id: TaskId::new_v4(),
+			library_id,
+			thumbnails_directory_path,
+			files: file_paths
+				.iter()
+				.filter_map(|file_path| {
+					// Only file paths with cas_id are fetched for the media processor
+					let cas_id = file_path.cas_id.clone()?;
+
+					let Some(object_id) = file_path.object_id else {
+						errors.push(
+							media_processor::NonCriticalError::from(
+								NonCriticalError::FilePathMissingObjectId(file_path.id),
+							)
+							.into(),
+						);
+						return None;
+					};
+
+					// Many file paths can point to the same object, we scan it just once
+					if !seen_objects.insert(object_id) {
+						return None;
+					}
+
+					IsolatedFilePathData::try_from((location_id, file_path))
+						.map_err(|e| {
+							errors.push(
+								media_processor::NonCriticalError::from(
+									NonCriticalError::FailedToExtractIsolatedFilePathData(
+										file_path.id,
+										e.to_string(),
+									),
+								)
+								.into(),
+							);
+						})
+						.ok()
+						.map(|iso_file_path| (object_id, cas_id, location_path.join(iso_file_path)))
+				})
+				.collect(),
+			should_regenerate,
+			fetched_already_scanned: false,
+			detected: Vec::new(),
+			models,
+			db,
+			output: Output {
+				errors,
+				..Default::default()
+			},
+		}
+	}
+}
+
+#[async_trait::async_trait]
+impl Task<Error> for FaceDetector {
+	fn id(&self) -> TaskId {
+		self.id
+	}
+
+	async fn run(&mut self, interrupter: &Interrupter) -> Result<ExecStatus, Error> {
+		let Self {
+			library_id,
+			thumbnails_directory_path,
+			files,
+			should_regenerate,
+			fetched_already_scanned,
+			detected,
+			models,
+			db,
+			output:
+				Output {
+					scanned,
+					faces_found,
+					skipped,
+					detection_time,
+					db_write_time,
+					errors,
+				},
+			..
+		} = self;
+
+		if !*should_regenerate && !*fetched_already_scanned {
+			let already_scanned = db
+				.face_scan()
+				.find_many(vec![
+					face_scan::object_id::in_vec(
+						files.iter().map(|(object_id, _, _)| *object_id).collect(),
+					),
+					face_scan::model::equals(FACE_MODELS_ID.to_string()),
+				])
+				.select(face_scan::select!({ object_id }))
+				.exec()
+				.await
+				.map_err(media_processor::Error::from)?
+				.into_iter()
+				.map(|data| data.object_id)
+				.collect::<HashSet<_>>();
+
+			*skipped += already_scanned.len() as u64;
+			files.retain(|(object_id, _, _)| !already_scanned.contains(object_id));
+			*fetched_already_scanned = true;
+		}
+
+		let start = Instant::now();
+		let kind = ThumbnailKind::Indexed(*library_id);
+
+		// Files are popped, so a resumed task only processes the remaining ones
+		while let Some((object_id, cas_id, path)) = files.pop() {
+			let thumbnail = thumbnail_path(&**thumbnails_directory_path, &cas_id, &kind);
+
+			match detect_faces(path, thumbnail, Arc::clone(models)).await {
+				Ok(faces) => detected.push((object_id, faces)),
+				Err(e) => {
+					error!("{e:#?}");
+					errors.push(media_processor::NonCriticalError::from(e).into());
+					*skipped += 1;
+				}
+			}
+
+			check_interruption!(interrupter, start, detection_time);
+		}
+
+		*detection_time += start.elapsed();
+
+		let db_write_start = Instant::now();
+		(*scanned, *faces_found) = save(mem::take(detected), db).await?;
+		*db_write_time = db_write_start.elapsed();
+
+		Ok(ExecStatus::Done(mem::take(&mut self.output).into_output()))
+	}
+}
+
+/// Looks for faces in the thumbnail when there is one, as the detector works on way smaller images
+/// anyway and thumbnails already have their orientation corrected
+async fn detect_faces(
+	path: PathBuf,
+	thumbnail: PathBuf,
+	models: Arc<FaceModels>,
+) -> Result<Vec<DetectedFace>, NonCriticalError> {
+	spawn_blocking({
+		let path = path.clone();
+
+		move || {
+			let img = if let Ok(img) = image::open(&thumbnail) {
+				trace!("Detecting faces in thumbnail of {}", path.display());
+				img
+			} else {
+				let mut img = format_image(&path)
+					.map_err(|e| NonCriticalError::FormatImage(path.clone(), e.to_string()))?;
+
+				// Faces lying on their side aren't detected
+				if let Some(orientation) = Orientation::from_path(&path) {
+					if ConvertibleExtension::try_from(path.as_path())
+						.is_ok_and(|extension| extension.should_rotate())
+					{
+						img = orientation.correct_thumbnail(img);
+					}
+				}
+
+				img
+			};
+
+			models
+				.detect(&img)
+				.map_err(|e| NonCriticalError::Detect(path.clone(), e.to_string()))
+		}
+	})
+	.await
+	.map_err(|e| NonCriticalError::PanicWhileDetecting(path, e.to_string()))?
+}
+
+/// Replaces the faces of the scanned objects, returning how many objects were scanned and how many
+/// faces they have
+async fn save(
+	detected: Vec<(object::id::Type, Vec<DetectedFace>)>,
+	db: &PrismaClient,
+) -> Result<(u64, u64), media_processor::Error> {
+	let object_ids = detected
+		.iter()
+		.map(|(object_id, _)| *object_id)
+		.collect::<Vec<_>>();
+
+	let faces = detected
+		.into_iter()
+		.flat_map(|(object_id, faces)| {
+			faces.into_iter().map(
+				move |DetectedFace {
+				          bounding_box,
+				          score,
+				          embedding,
+				      }| {
+					face::create_unchecked(
+						f64::from(bounding_box.x),
+						f64::from(bounding_box.y),
+						f64::from(bounding_box.width),
+						f64::from(bounding_box.height),
+						f64::from(score),
+						embedding_to_bytes(&embedding),
+						object_id,
+						vec![],
+					)
+				},
+			)
+		})
+		.collect::<Vec<_>>();
+
+	let faces_count = faces.len() as u64;
+
+	// Faces of an object scanned again are replaced, even the ones already grouped, as they can't
+	// be matched with the new ones
+	db._batch((
+		db.face()
+			.delete_many(vec![face::object_id::in_vec(object_ids.clone())]),
+		db.face().create_many(faces),
+	))
+	.await?;
+
+	db._batch(
+		object_ids
+			.iter()
+			.map(|object_id| {
+				db.face_scan().upsert(
+					face_scan::object_id::equals(*object_id),
+					face_scan::create(
+						FACE_MODELS_ID.to_string(),
+						object::id::equals(*object_id),
+						vec![],
+					),
+					vec![face_scan::model::set(FACE_MODELS_ID.to_string())],
+				)
+			})
+			.collect::<Vec<_>>(),
+	)
+	.await?;
+
+	Ok((object_ids.len() as u64, faces_count))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SaveState {
+	id: TaskId,
+	library_id: Uuid,
+	thumbnails_directory_path: Arc<PathBuf>,
+	files: Vec<(object::id::Type, String, PathBuf)>,
+	should_regenerate: bool,
+	fetched_already_scanned: bool,
+	detected: Vec<(object::id::Type, Vec<DetectedFace>)>,
+	output: Output,
+}
+
+impl SerializableTask<Error> for FaceDetector {
+	type SerializeError = rmp_serde::encode::Error;
+
+	type DeserializeError = rmp_serde::decode::Error;
+
+	type DeserializeCtx = (Arc<FaceModels>, Arc<PrismaClient>);
+
+	async fn serialize(self) -> Result<Vec<u8>, Self::SerializeError> {
+		let Self {
+			id,
+			library_id,
+			thumbnails_directory_path,
+			files,
+			should_regenerate,
+			fetched_already_scanned,
+			detected,
+			output,
+			..
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState {
+			id,
+			library_id,
+			thumbnails_directory_path,
+			files,
+			should_regenerate,
+			fetched_already_scanned,
+			detected,
+			output,
+		})
+	}
+
+	async fn deserialize(
+		data: &[u8],
+		(models, db): Self::DeserializeCtx,
+	) -> Result<Self, Self::DeserializeError> {
+		rmp_serde::from_slice(data).map(
+			|SaveState {
+			     id,
+			     library_id,
+			     thumbnails_directory_path,
+			     files,
+			     should_regenerate,
+			     fetched_already_scanned,
+			     detected,
+			     output,
+			 }| Self {
+				id,
+				library_id,
+				thumbnails_directory_path,
+				files,
+				should_regenerate,
+				fetched_already_scanned,
+				detected,
+				models,
+				db,
+				output,
+			},
+		)
+	}
+}
@@ -1,5 +1,17 @@
+pub mod content_fingerprinter;
+pub mod document_previewer;
+#[cfg(feature = "ai")]
+pub mod face_detector;
 pub mod media_data_extractor;
+pub mod perceptual_hasher;
 pub mod thumbnailer;
+pub mod waveform_extractor;
 
+pub use content_fingerprinter::ContentFingerprinter;
+pub use document_previewer::DocumentPreviewer;
+#[cfg(feature = "ai")]
+pub use face_detector::FaceDetector;
 pub use media_data_extractor::MediaDataExtractor;
+pub use perceptual_hasher::PerceptualHasher;
 pub use thumbnailer::Thumbnailer;
+pub use waveform_extractor::WaveformExtractor;
@@ -0,0 +1,315 @@
+use crate::{
+	media_processor::{
+		self,
+		helpers::{
+			perceptual_hash::{perceptual_hash, save},
+			thumbnailer::thumbnail_path,
+		},
+		ThumbnailKind,
+	},
+	Error,
+};
+
+use sd_core_file_path_helper::IsolatedFilePathData;
+use sd_core_prisma_helpers::file_path_for_media_processor;
+
+use sd_images::{format_image, ConvertibleExtension};
+use sd_media_metadata::exif::Orientation;
+use sd_prisma::prisma::{file_path, location, object, perceptual_hash, PrismaClient};
+use sd_task_system::{
+	check_interruption, ExecStatus, Interrupter, IntoAnyTaskOutput, SerializableTask, Task, TaskId,
+};
+
+use std::{
+	collections::HashSet,
+	mem,
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::{task::spawn_blocking, time::Instant};
+use tracing::{error, trace};
+use uuid::Uuid;
+
+/// Computes perceptual hashes of images, to find the ones that look the same even when their
+/// contents differ
+#[derive(Debug)]
+pub struct PerceptualHasher {
+	id: TaskId,
+	library_id: Uuid,
+	thumbnails_directory_path: Arc<PathBuf>,
+	files: Vec<(object::id::Type, String, PathBuf)>,
+	should_regenerate: bool,
+	fetched_already_hashed: bool,
+	hashes: Vec<(u64, object::id::Type)>,
+	db: Arc<PrismaClient>,
+	output: Output,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Output {
+	pub hashed: u64,
+	pub skipped: u64,
+	pub hashing_time: Duration,
+	pub db_write_time: Duration,
+	pub errors: Vec<crate::NonCriticalError>,
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize, Type)]
+pub enum NonCriticalError {
+	#[error("failed to extract isolated file path data from file path <id='{0}'>: {1}")]
+	FailedToExtractIsolatedFilePathData(file_path::id::Type, String),
+	#[error("file path missing object id: <file_path_id='{0}'>")]
+	FilePathMissingObjectId(file_path::id::Type),
+	#[error("failed to decode image to hash <path='{}'>: {1}", .0.display())]
+	FormatImage(PathBuf, String),
+	#[error("processing thread panicked while hashing image <path='{}'>: {1}", .0.display())]
+	PanicWhileHashing(PathBuf, String),
+}
+
+impl PerceptualHasher {
+	#[must_use]
+	pub fn new(
+		thumbnails_directory_path: Arc<PathBuf>,
+		file_paths: &[file_path_for_media_processor::Data],
+		(location_id, location_path): (location::id::Type, &Path),
+		library_id: Uuid,
+		should_regenerate: bool,
+		db: Arc<PrismaClient>,
+	) -> Self {
+		let mut errors = Vec::new();
+		let mut seen_objects = HashSet::with_capacity(file_paths.len());
+
+		Self {
+			id: TaskId::new_v4(),
+			library_id,
+			thumbnails_directory_path,
+			files: file_paths
+				.iter()
+				.filter_map(|file_path| {
+					// Only file paths with cas_id are fetched for the media processor
+					let cas_id = file_path.cas_id.clone()?;
+
+					let Some(object_id) = file_path.object_id else {
+						errors.push(
+							media_processor::NonCriticalError::from(
+								NonCriticalError::FilePathMissingObjectId(file_path.id),
+							)
+							.into(),
+						);
+						return None;
+					};
+
+					// Many file paths can point to the same object, we hash it just once
+					if !seen_objects.insert(object_id) {
+						return None;
+					}
+
+					IsolatedFilePathData::try_from((location_id, file_path))
+						.map_err(|e| {
+							errors.push(
+								media_processor::NonCriticalError::from(
+									NonCriticalError::FailedToExtractIsolatedFilePathData(
+										file_path.id,
+										e.to_string(),
+									),
+								)
+								.into(),
+							);
+						})
+						.ok()
+						.map(|iso_file_path| (object_id, cas_id, location_path.join(iso_file_path)))
+				})
+				.collect(),
+			should_regenerate,
+			fetched_already_hashed: false,
+			hashes: Vec::new(),
+			db,
+			output: Output {
+				errors,
+				..Default::default()
+			},
+		}
+	}
+}
+
+#[async_trait::async_trait]
+impl Task<Error> for PerceptualHasher {
+	fn id(&self) -> TaskId {
+		self.id
+	}
+
+	async fn run(&mut self, interrupter: &Interrupter) -> Result<ExecStatus, Error> {
+		let Self {
+			library_id,
+			thumbnails_directory_path,
+			files,
+			should_regenerate,
+			fetched_already_hashed,
+			hashes,
+			db,
+			output:
+				Output {
+					hashed,
+					skipped,
+					hashing_time,
+					db_write_time,
+					errors,
+				},
+			..
+		} = self;
+
+		if !*should_regenerate && !*fetched_already_hashed {
+			let already_hashed = db
+				.perceptual_hash()
+				.find_many(vec![perceptual_hash::object_id::in_vec(
+					files.iter().map(|(object_id, _, _)| *object_id).collect(),
+				)])
+				.select(perceptual_hash::select!({ object_id }))
+				.exec()
+				.await
+				.map_err(media_processor::Error::from)?
+				.into_iter()
+				.map(|data| data.object_id)
+				.collect::<HashSet<_>>();
+
+			*skipped += already_hashed.len() as u64;
+			files.retain(|(object_id, _, _)| !already_hashed.contains(object_id));
+			*fetched_already_hashed = true;
+		}
+
+		let start = Instant::now();
+		let kind = ThumbnailKind::Indexed(*library_id);
+
+		// Files are popped, so a resumed task only processes the remaining ones
+		while let Some((object_id, cas_id, path)) = files.pop() {
+			let thumbnail = thumbnail_path(&**thumbnails_directory_path, &cas_id, &kind);
+
+			match hash_image(path, thumbnail).await {
+				Ok(hash) => hashes.push((hash, object_id)),
+				Err(e) => {
+					error!("{e:#?}");
+					errors.push(media_processor::NonCriticalError::from(e).into());
+					*skipped += 1;
+				}
+			}
+
+			check_interruption!(interrupter, start, hashing_time);
+		}
+
+		*hashing_time += start.elapsed();
+
+		let db_write_start = Instant::now();
+		*hashed = save(mem::take(hashes), db).await?;
+		*db_write_time = db_write_start.elapsed();
+
+		Ok(ExecStatus::Done(mem::take(&mut self.output).into_output()))
+	}
+}
+
+/// Hashes the thumbnail when there is one, as it's way cheaper to decode and perceptual hashes only
+/// look at the coarse structure of the image anyway
+async fn hash_image(path: PathBuf, thumbnail: PathBuf) -> Result<u64, NonCriticalError> {
+	spawn_blocking({
+		let path = path.clone();
+
+		move || {
+			if let Ok(img) = image::open(&thumbnail) {
+				trace!("Hashing thumbnail of {}", path.display());
+				return Ok(perceptual_hash(&img));
+			}
+
+			let mut img = format_image(&path)
+				.map_err(|e| NonCriticalError::FormatImage(path.clone(), e.to_string()))?;
+
+			// Same correction as thumbnails get, so both ways end up with the same hash
+			if let Some(orientation) = Orientation::from_path(&path) {
+				if ConvertibleExtension::try_from(path.as_path())
+					.is_ok_and(|extension| extension.should_rotate())
+				{
+					img = orientation.correct_thumbnail(img);
+				}
+			}
+
+			Ok(perceptual_hash(&img))
+		}
+	})
+	.await
+	.map_err(|e| NonCriticalError::PanicWhileHashing(path, e.to_string()))?
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SaveState {
+	id: TaskId,
+	library_id: Uuid,
+	thumbnails_directory_path: Arc<PathBuf>,
+	files: Vec<(object::id::Type, String, PathBuf)>,
+	should_regenerate: bool,
+	fetched_already_hashed: bool,
+	hashes: Vec<(u64, object::id::Type)>,
+	output: Output,
+}
+
+impl SerializableTask<Error> for PerceptualHasher {
+	type SerializeError = rmp_serde::encode::Error;
+
+	type DeserializeError = rmp_serde::decode::Error;
+
+	type DeserializeCtx = Arc<PrismaClient>;
+
+	async fn serialize(self) -> Result<Vec<u8>, Self::SerializeError> {
+		let Self {
+			id,
+			library_id,
+			thumbnails_directory_path,
+			files,
+			should_regenerate,
+			fetched_already_hashed,
+			hashes,
+			output,
+			..
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState {
+			id,
+			library_id,
+			thumbnails_directory_path,
+			files,
+			should_regenerate,
+			fetched_already_hashed,
+			hashes,
+			output,
+		})
+	}
+
+	async fn deserialize(
+		data: &[u8],
+		db: Self::DeserializeCtx,
+	) -> Result<Self, Self::DeserializeError> {
+		rmp_serde::from_slice(data).map(
+			|SaveState {
+			     id,
+			     library_id,
+			     thumbnails_directory_path,
+			     files,
+			     should_regenerate,
+			     fetched_already_hashed,
+			     hashes,
+			     output,
+			 }| Self {
+				id,
+				library_id,
+				thumbnails_directory_path,
+				files,
+				should_regenerate,
+				fetched_already_hashed,
+				hashes,
+				db,
+				output,
+			},
+		)
+	}
+}
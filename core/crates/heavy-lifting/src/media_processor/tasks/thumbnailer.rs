@@ -6,14 +6,15 @@
 //! │     └── <`cas_id`>.webp
 //! └── <`library_id`>/ # we segregate thumbnails by library
 //!    └── <`cas_id`>[0..3]/ # sharding
-//!       └── <`cas_id`>.webp
+//!       ├── <`cas_id`>.webp
+//!       └── <`cas_id`>.strip.webp # preview strips, only for indexed videos
 
 use crate::{
 	media_processor::{
 		self,
 		helpers::thumbnailer::{
-			can_generate_thumbnail_for_document, can_generate_thumbnail_for_image, get_shard_hex,
-			EPHEMERAL_DIR, TARGET_PX, TARGET_QUALITY, THUMBNAIL_GENERATION_TIMEOUT, WEBP_EXTENSION,
+			can_generate_thumbnail_for_document, can_generate_thumbnail_for_image, thumbnail_path,
+			TARGET_PX, TARGET_QUALITY, THUMBNAIL_GENERATION_TIMEOUT,
 		},
 		ThumbKey, ThumbnailKind,
 	},
@@ -232,6 +233,8 @@ pub enum NonCriticalError {
 	FailedToExtractIsolatedFilePathData(file_path::id::Type, String),
 	#[error("failed to generate video file thumbnail <path='{}'>: {1}", .0.display())]
 	VideoThumbnailGenerationFailed(PathBuf, String),
+	#[error("failed to generate video file preview strip <path='{}'>: {1}", .0.display())]
+	VideoPreviewStripGenerationFailed(PathBuf, String),
 	#[error("failed to format image <path='{}'>: {1}", .0.display())]
 	FormatImage(PathBuf, String),
 	#[error("failed to encode webp image <path='{}'>: {1}", .0.display())]
@@ -520,23 +523,33 @@ async fn generate_thumbnail(
 	trace!("Generating thumbnail for {}", path.display());
 	let start = Instant::now();
 
-	let mut output_path = match kind {
-		ThumbnailKind::Ephemeral => thumbnails_directory.join(EPHEMERAL_DIR),
-		ThumbnailKind::Indexed(library_id) => thumbnails_directory.join(library_id.to_string()),
-	};
+	let output_path = thumbnail_path(thumbnails_directory, cas_id, kind);
+
+	let thumbnail_exists = !should_regenerate && exists(&output_path).await;
 
-	output_path.push(get_shard_hex(cas_id));
-	output_path.push(cas_id);
-	output_path.set_extension(WEBP_EXTENSION);
+	// Only indexed videos get preview strips, ephemeral thumbnails don't live long enough for them
+	#[cfg(feature = "ffmpeg")]
+	let missing_strip_path = {
+		use crate::media_processor::helpers::thumbnailer::{
+			can_generate_thumbnail_for_video, preview_strip_path,
+		};
+		use sd_file_ext::extensions::VideoExtension;
 
-	if let Err(e) = fs::metadata(&*output_path).await {
-		if e.kind() != io::ErrorKind::NotFound {
-			error!(
-				"Failed to check if thumbnail exists, but we will try to generate it anyway: {e:#?}"
-			);
+		match (VideoExtension::from_str(extension), kind) {
+			(Ok(extension), ThumbnailKind::Indexed(_))
+				if can_generate_thumbnail_for_video(extension) =>
+			{
+				let strip_path = preview_strip_path(thumbnails_directory, cas_id, kind);
+				(should_regenerate || !exists(&strip_path).await).then_some(strip_path)
+			}
+			_ => None,
 		}
-	// Otherwise we good, thumbnail doesn't exist so we can generate it
-	} else if !should_regenerate {
+	};
+
+	#[cfg(not(feature = "ffmpeg"))]
+	let missing_strip_path: Option<PathBuf> = None;
+
+	if thumbnail_exists && missing_strip_path.is_none() {
 		trace!(
 			"Skipping thumbnail generation for {} because it already exists",
 			path.display()
@@ -568,8 +581,22 @@ async fn generate_thumbnail(
 
 		if let Ok(extension) = VideoExtension::from_str(extension) {
 			if can_generate_thumbnail_for_video(extension) {
-				if let Err(e) = generate_video_thumbnail(&path, &output_path).await {
-					return (start.elapsed(), Err(e));
+				if !thumbnail_exists {
+					if let Err(e) = generate_video_thumbnail(&path, &output_path).await {
+						return (start.elapsed(), Err(e));
+					}
+				}
+
+				if let Some(strip_path) = &missing_strip_path {
+					if let Err(e) = generate_video_preview_strip(&path, strip_path).await {
+						// A missing strip only disables scrubbing, so we don't throw away a
+						// thumbnail that we just generated because of it
+						if thumbnail_exists {
+							return (start.elapsed(), Err(e));
+						}
+
+						error!("{e:#?}");
+					}
 				}
 			}
 		}
@@ -596,21 +623,6 @@ async fn generate_image_thumbnail(
 			let mut img = format_image(&file_path)
 				.map_err(|e| NonCriticalError::FormatImage(file_path.clone(), e.to_string()))?;
 
-			let (w, h) = img.dimensions();
-
-			#[allow(clippy::cast_precision_loss)]
-			let (w_scaled, h_scaled) = scale_dimensions(w as f32, h as f32, TARGET_PX);
-
-			// Optionally, resize the existing photo and convert back into DynamicImage
-			if w != w_scaled && h != h_scaled {
-				img = DynamicImage::ImageRgba8(imageops::resize(
-					&img,
-					w_scaled,
-					h_scaled,
-					imageops::FilterType::Triangle,
-				));
-			}
-
 			// this corrects the rotation/flip of the image based on the *available* exif data
 			// not all images have exif data, so we don't error. we also don't rotate HEIF as that's against the spec
 			if let Some(orientation) = Orientation::from_path(&file_path) {
@@ -622,14 +634,7 @@ async fn generate_image_thumbnail(
 				}
 			}
 
-			// Create the WebP encoder for the above image
-			let encoder = Encoder::from_image(&img)
-				.map_err(|reason| NonCriticalError::WebPEncoding(file_path, reason.to_string()))?;
-
-			// Type `WebPMemory` is !Send, which makes the `Future` in this function `!Send`,
-			// this make us `deref` to have a `&[u8]` and then `to_owned` to make a `Vec<u8>`
-			// which implies on a unwanted clone...
-			Ok(encoder.encode(TARGET_QUALITY).deref().to_owned())
+			encode_thumbnail(img, &file_path)
 		}
 	})
 	.await
@@ -637,8 +642,47 @@ async fn generate_image_thumbnail(
 		NonCriticalError::PanicWhileGeneratingThumbnail(file_path.clone(), e.to_string())
 	})??;
 
-	let output_path = output_path.as_ref();
+	save_thumbnail(&file_path, output_path.as_ref(), &webp).await
+}
+
+/// Scales an already decoded image to [`TARGET_PX`] and encodes it as WebP, this is blocking so
+/// it must be called from a blocking thread
+pub(crate) fn encode_thumbnail(
+	mut img: DynamicImage,
+	file_path: &Path,
+) -> Result<Vec<u8>, NonCriticalError> {
+	let (w, h) = img.dimensions();
+
+	#[allow(clippy::cast_precision_loss)]
+	let (w_scaled, h_scaled) = scale_dimensions(w as f32, h as f32, TARGET_PX);
+
+	// Optionally, resize the existing photo and convert back into DynamicImage
+	if w != w_scaled && h != h_scaled {
+		img = DynamicImage::ImageRgba8(imageops::resize(
+			&img,
+			w_scaled,
+			h_scaled,
+			imageops::FilterType::Triangle,
+		));
+	}
+
+	// Create the WebP encoder for the above image
+	let encoder = Encoder::from_image(&img).map_err(|reason| {
+		NonCriticalError::WebPEncoding(file_path.to_path_buf(), reason.to_string())
+	})?;
 
+	// Type `WebPMemory` is !Send, which makes the `Future` in this function `!Send`,
+	// this make us `deref` to have a `&[u8]` and then `to_owned` to make a `Vec<u8>`
+	// which implies on a unwanted clone...
+	Ok(encoder.encode(TARGET_QUALITY).deref().to_owned())
+}
+
+/// Writes an encoded thumbnail to its sharded path, creating the shard directory if needed
+pub(crate) async fn save_thumbnail(
+	file_path: &Path,
+	output_path: &Path,
+	webp: &[u8],
+) -> Result<(), NonCriticalError> {
 	if let Some(shard_dir) = output_path.parent() {
 		fs::create_dir_all(shard_dir).await.map_err(|e| {
 			NonCriticalError::CreateShardDirectory(FileIOError::from((shard_dir, e)).to_string())
@@ -650,11 +694,29 @@ async fn generate_image_thumbnail(
 		);
 	}
 
-	fs::write(output_path, &webp).await.map_err(|e| {
-		NonCriticalError::SaveThumbnail(file_path, FileIOError::from((output_path, e)).to_string())
+	fs::write(output_path, webp).await.map_err(|e| {
+		NonCriticalError::SaveThumbnail(
+			file_path.to_path_buf(),
+			FileIOError::from((output_path, e)).to_string(),
+		)
 	})
 }
 
+/// Logs failures to check for a file, as we will try to generate it anyway
+pub(crate) async fn exists(path: &Path) -> bool {
+	match fs::metadata(path).await {
+		Ok(_) => true,
+		Err(e) => {
+			if e.kind() != io::ErrorKind::NotFound {
+				error!(
+					"Failed to check if thumbnail exists, but we will try to generate it anyway: {e:#?}"
+				);
+			}
+			false
+		}
+	}
+}
+
 #[cfg(feature = "ffmpeg")]
 async fn generate_video_thumbnail(
 	file_path: impl AsRef<Path> + Send,
@@ -675,3 +737,28 @@ async fn generate_video_thumbnail(
 		NonCriticalError::VideoThumbnailGenerationFailed(file_path.to_path_buf(), e.to_string())
 	})
 }
+
+#[cfg(feature = "ffmpeg")]
+async fn generate_video_preview_strip(
+	file_path: impl AsRef<Path> + Send,
+	output_path: impl AsRef<Path> + Send,
+) -> Result<(), NonCriticalError> {
+	use crate::media_processor::helpers::thumbnailer::{
+		PREVIEW_STRIP_FRAMES, PREVIEW_STRIP_FRAME_SIZE,
+	};
+	use sd_ffmpeg::{to_preview_strip, ThumbnailSize};
+
+	let file_path = file_path.as_ref();
+
+	to_preview_strip(
+		file_path,
+		output_path,
+		PREVIEW_STRIP_FRAMES,
+		ThumbnailSize::Scale(PREVIEW_STRIP_FRAME_SIZE),
+		TARGET_QUALITY,
+	)
+	.await
+	.map_err(|e| {
+		NonCriticalError::VideoPreviewStripGenerationFailed(file_path.to_path_buf(), e.to_string())
+	})
+}
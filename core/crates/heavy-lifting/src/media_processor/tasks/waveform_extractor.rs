@@ -0,0 +1,250 @@
+use crate::{
+	media_processor::{
+		self,
+		helpers::waveform::{waveform_path, Waveform},
+	},
+	Error,
+};
+
+use sd_core_file_path_helper::IsolatedFilePathData;
+use sd_core_prisma_helpers::file_path_for_media_processor;
+
+use sd_prisma::prisma::{file_path, location};
+use sd_task_system::{
+	check_interruption, ExecStatus, Interrupter, IntoAnyTaskOutput, SerializableTask, Task, TaskId,
+};
+use sd_utils::error::FileIOError;
+
+use std::{
+	mem,
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::{fs, time::Instant};
+use tracing::{error, trace};
+use uuid::Uuid;
+
+/// Decodes audio files to cache their waveforms under the thumbnails directory
+#[derive(Debug)]
+pub struct WaveformExtractor {
+	id: TaskId,
+	library_id: Uuid,
+	thumbnails_directory_path: Arc<PathBuf>,
+	files: Vec<(String, PathBuf)>,
+	should_regenerate: bool,
+	output: Output,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Output {
+	pub extracted: u64,
+	pub skipped: u64,
+	pub extraction_time: Duration,
+	pub errors: Vec<crate::NonCriticalError>,
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize, Type)]
+pub enum NonCriticalError {
+	#[error("failed to extract isolated file path data from file path <id='{0}'>: {1}")]
+	FailedToExtractIsolatedFilePathData(file_path::id::Type, String),
+	#[error("failed to extract waveform <path='{}'>: {1}", .0.display())]
+	ExtractionFailed(PathBuf, String),
+	#[error("failed to save waveform: {0}")]
+	SaveWaveform(String),
+}
+
+impl WaveformExtractor {
+	#[must_use]
+	pub fn new(
+		thumbnails_directory_path: Arc<PathBuf>,
+		file_paths: &[file_path_for_media_processor::Data],
+		(location_id, location_path): (location::id::Type, &Path),
+		library_id: Uuid,
+		should_regenerate: bool,
+	) -> Self {
+		let mut errors = Vec::new();
+
+		Self {
+			id: TaskId::new_v4(),
+			library_id,
+			thumbnails_directory_path,
+			files: file_paths
+				.iter()
+				.filter_map(|file_path| {
+					// Only file paths with cas_id are fetched for the media processor
+					let cas_id = file_path.cas_id.clone()?;
+
+					IsolatedFilePathData::try_from((location_id, file_path))
+						.map_err(|e| {
+							errors.push(
+								media_processor::NonCriticalError::from(
+									NonCriticalError::FailedToExtractIsolatedFilePathData(
+										file_path.id,
+										e.to_string(),
+									),
+								)
+								.into(),
+							);
+						})
+						.ok()
+						.map(|iso_file_path| (cas_id, location_path.join(iso_file_path)))
+				})
+				.collect(),
+			should_regenerate,
+			output: Output {
+				errors,
+				..Default::default()
+			},
+		}
+	}
+}
+
+#[async_trait::async_trait]
+impl Task<Error> for WaveformExtractor {
+	fn id(&self) -> TaskId {
+		self.id
+	}
+
+	async fn run(&mut self, interrupter: &Interrupter) -> Result<ExecStatus, Error> {
+		let Self {
+			library_id,
+			thumbnails_directory_path,
+			files,
+			should_regenerate,
+			output: Output {
+				extracted,
+				skipped,
+				extraction_time,
+				errors,
+			},
+			..
+		} = self;
+
+		let start = Instant::now();
+
+		// Files are popped, so a resumed task only processes the remaining ones
+		while let Some((cas_id, path)) = files.pop() {
+			let output_path = waveform_path(&**thumbnails_directory_path, &cas_id, *library_id);
+
+			if !*should_regenerate && fs::metadata(&output_path).await.is_ok() {
+				trace!(
+					"Skipping waveform extraction for {} because it already exists",
+					path.display()
+				);
+				*skipped += 1;
+			} else {
+				match extract_waveform(&path, &output_path).await {
+					Ok(()) => *extracted += 1,
+					Err(e) => {
+						error!("{e:#?}");
+						errors.push(media_processor::NonCriticalError::from(e).into());
+					}
+				}
+			}
+
+			check_interruption!(interrupter, start, extraction_time);
+		}
+
+		*extraction_time += start.elapsed();
+
+		Ok(ExecStatus::Done(mem::take(&mut self.output).into_output()))
+	}
+}
+
+async fn extract_waveform(path: &Path, output_path: &Path) -> Result<(), NonCriticalError> {
+	let Waveform { peaks } = Waveform::from_amplitudes(&decode_amplitudes(path).await?);
+
+	if let Some(shard_dir) = output_path.parent() {
+		fs::create_dir_all(shard_dir).await.map_err(|e| {
+			NonCriticalError::SaveWaveform(FileIOError::from((shard_dir, e)).to_string())
+		})?;
+	}
+
+	fs::write(output_path, peaks).await.map_err(|e| {
+		NonCriticalError::SaveWaveform(FileIOError::from((output_path, e)).to_string())
+	})
+}
+
+#[cfg(feature = "ffmpeg")]
+async fn decode_amplitudes(path: &Path) -> Result<Vec<f64>, NonCriticalError> {
+	use crate::media_processor::helpers::waveform::WAVEFORM_PEAKS;
+
+	sd_ffmpeg::to_waveform(path, WAVEFORM_PEAKS)
+		.await
+		.map_err(|e| NonCriticalError::ExtractionFailed(path.to_path_buf(), e.to_string()))
+}
+
+// Tasks are only dispatched for extensions that ffmpeg can decode, see `helpers::waveform`
+#[cfg(not(feature = "ffmpeg"))]
+#[allow(clippy::unused_async)]
+async fn decode_amplitudes(path: &Path) -> Result<Vec<f64>, NonCriticalError> {
+	Err(NonCriticalError::ExtractionFailed(
+		path.to_path_buf(),
+		"ffmpeg is disabled".to_string(),
+	))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SaveState {
+	id: TaskId,
+	library_id: Uuid,
+	thumbnails_directory_path: Arc<PathBuf>,
+	files: Vec<(String, PathBuf)>,
+	should_regenerate: bool,
+	output: Output,
+}
+
+impl SerializableTask<Error> for WaveformExtractor {
+	type SerializeError = rmp_serde::encode::Error;
+
+	type DeserializeError = rmp_serde::decode::Error;
+
+	type DeserializeCtx = ();
+
+	async fn serialize(self) -> Result<Vec<u8>, Self::SerializeError> {
+		let Self {
+			id,
+			library_id,
+			thumbnails_directory_path,
+			files,
+			should_regenerate,
+			output,
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState {
+			id,
+			library_id,
+			thumbnails_directory_path,
+			files,
+			should_regenerate,
+			output,
+		})
+	}
+
+	async fn deserialize(
+		data: &[u8],
+		(): Self::DeserializeCtx,
+	) -> Result<Self, Self::DeserializeError> {
+		rmp_serde::from_slice(data).map(
+			|SaveState {
+			     id,
+			     library_id,
+			     thumbnails_directory_path,
+			     files,
+			     should_regenerate,
+			     output,
+			 }| Self {
+				id,
+				library_id,
+				thumbnails_directory_path,
+				files,
+				should_regenerate,
+				output,
+			},
+		)
+	}
+}
@@ -0,0 +1,298 @@
+use crate::{
+	job_system::{
+		job::{Job, JobReturn, JobTaskDispatcher, ReturnStatus},
+		report::ReportOutputMetadata,
+		utils::cancel_pending_tasks,
+		SerializableJob, SerializedTasks,
+	},
+	script_runner,
+	utils::relink::LocationRoot,
+	Error, JobName, OuterContext, ProgressUpdate,
+};
+
+use sd_prisma::prisma::location;
+use sd_task_system::{
+	IntoTask, SerializableTask, Task, TaskDispatcher, TaskHandle, TaskOutput, TaskStatus,
+};
+use sd_utils::db::maybe_missing;
+
+use std::{
+	collections::HashMap,
+	hash::{Hash, Hasher},
+	mem,
+	path::PathBuf,
+	sync::Arc,
+	time::Duration,
+};
+
+use futures::{stream::FuturesUnordered, StreamExt};
+use futures_concurrency::future::TryJoin;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{debug, warn};
+
+use super::{
+	tasks::{script, Script},
+	ScriptCommand,
+};
+
+/// Runs a [`ScriptCommand`] from a location, or from a sub path of it. Meant as a stage of a
+/// [`JobChain`](crate::JobChain), after the stages that scanned the location.
+#[derive(Debug)]
+pub struct ScriptRunner {
+	location: LocationRoot,
+	sub_path: Option<PathBuf>,
+	command: ScriptCommand,
+
+	metadata: Metadata,
+
+	pending_tasks_on_resume: Vec<TaskHandle<Error>>,
+	tasks_for_shutdown: Vec<Box<dyn Task<Error>>>,
+}
+
+impl Hash for ScriptRunner {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.location.id.hash(state);
+		self.sub_path.hash(state);
+		self.command.hash(state);
+	}
+}
+
+impl Job for ScriptRunner {
+	const NAME: JobName = JobName::ScriptRunner;
+
+	async fn resume_tasks(
+		&mut self,
+		dispatcher: &JobTaskDispatcher,
+		_: &impl OuterContext,
+		SerializedTasks(serialized_tasks): SerializedTasks,
+	) -> Result<(), Error> {
+		self.pending_tasks_on_resume = dispatcher
+			.dispatch_many_boxed(
+				rmp_serde::from_slice::<Vec<Vec<u8>>>(&serialized_tasks)
+					.map_err(script_runner::Error::from)?
+					.into_iter()
+					.map(|task_bytes| async move {
+						Script::deserialize(&task_bytes, ())
+							.await
+							.map(IntoTask::into_task)
+					})
+					.collect::<Vec<_>>()
+					.try_join()
+					.await
+					.map_err(script_runner::Error::from)?,
+			)
+			.await;
+
+		Ok(())
+	}
+
+	async fn run<Ctx: OuterContext>(
+		mut self,
+		dispatcher: JobTaskDispatcher,
+		ctx: Ctx,
+	) -> Result<ReturnStatus, Error> {
+		let mut pending_running_tasks = FuturesUnordered::new();
+
+		self.init_or_resume(&mut pending_running_tasks, &ctx, &dispatcher)
+			.await;
+
+		while let Some(task) = pending_running_tasks.next().await {
+			match task {
+				Ok(TaskStatus::Done((_, TaskOutput::Out(out)))) => {
+					let script::Output { run_time } = *out
+						.downcast::<script::Output>()
+						.expect("the script runner job only dispatches script tasks");
+
+					self.metadata.run_time += run_time;
+
+					ctx.progress(vec![ProgressUpdate::CompletedTaskCount(1)]);
+				}
+
+				Ok(TaskStatus::Done((task_id, TaskOutput::Empty))) => {
+					warn!("Task <id='{task_id}'> returned an empty output");
+				}
+
+				Ok(TaskStatus::Shutdown(task)) => {
+					self.tasks_for_shutdown.push(task);
+				}
+
+				Ok(TaskStatus::Error(e)) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e);
+				}
+
+				Ok(TaskStatus::Canceled | TaskStatus::ForcedAbortion) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Ok(ReturnStatus::Canceled);
+				}
+
+				Err(e) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e.into());
+				}
+			}
+		}
+
+		if !self.tasks_for_shutdown.is_empty() {
+			return Ok(ReturnStatus::Shutdown(
+				SerializableJob::<Ctx>::serialize(self).await,
+			));
+		}
+
+		Ok(ReturnStatus::Completed(
+			JobReturn::builder().with_metadata(self.metadata).build(),
+		))
+	}
+}
+
+impl ScriptRunner {
+	pub fn new(
+		location: &location::Data,
+		sub_path: Option<PathBuf>,
+		command: ScriptCommand,
+	) -> Result<Self, script_runner::Error> {
+		Ok(Self {
+			location: LocationRoot {
+				id: location.id,
+				pub_id: location.pub_id.clone(),
+				path: maybe_missing(&location.path, "location.path")
+					.map(PathBuf::from)
+					.map(Arc::new)?,
+			},
+			sub_path,
+			command,
+			metadata: Metadata::default(),
+			pending_tasks_on_resume: Vec::new(),
+			tasks_for_shutdown: Vec::new(),
+		})
+	}
+
+	async fn init_or_resume(
+		&mut self,
+		pending_running_tasks: &mut FuturesUnordered<TaskHandle<Error>>,
+		ctx: &impl OuterContext,
+		dispatcher: &JobTaskDispatcher,
+	) {
+		// Resumed tasks were already dispatched by `resume_tasks`
+		if !self.pending_tasks_on_resume.is_empty() {
+			pending_running_tasks.extend(mem::take(&mut self.pending_tasks_on_resume));
+
+			return;
+		}
+
+		// Joining an absolute sub path keeps it as is
+		let working_dir = self.sub_path.as_ref().map_or_else(
+			|| self.location.path.to_path_buf(),
+			|sub_path| self.location.path.join(sub_path),
+		);
+
+		debug!(
+			"Running script <program='{}'> on location {} from <path='{}'>",
+			self.command.program,
+			self.location.id,
+			working_dir.display()
+		);
+
+		ctx.progress(vec![
+			ProgressUpdate::TaskCount(1),
+			ProgressUpdate::Message(format!("Running {}", self.command.program)),
+		]);
+
+		pending_running_tasks.push(
+			dispatcher
+				.dispatch(Script::new(
+					self.command.clone(),
+					working_dir,
+					ctx.id(),
+					self.location.id,
+					&self.location.path,
+				))
+				.await,
+		);
+	}
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Metadata {
+	run_time: Duration,
+}
+
+impl From<Metadata> for ReportOutputMetadata {
+	fn from(value: Metadata) -> Self {
+		Self::Metrics(HashMap::from([("run_time".into(), json!(value.run_time))]))
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+struct SaveState {
+	location: LocationRoot,
+	sub_path: Option<PathBuf>,
+	command: ScriptCommand,
+
+	metadata: Metadata,
+
+	tasks_for_shutdown_bytes: Option<SerializedTasks>,
+}
+
+impl<Ctx: OuterContext> SerializableJob<Ctx> for ScriptRunner {
+	async fn serialize(self) -> Result<Option<Vec<u8>>, rmp_serde::encode::Error> {
+		let Self {
+			location,
+			sub_path,
+			command,
+			metadata,
+			tasks_for_shutdown,
+			..
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState {
+			location,
+			sub_path,
+			command,
+			metadata,
+			tasks_for_shutdown_bytes: Some(SerializedTasks(rmp_serde::to_vec_named(
+				&tasks_for_shutdown
+					.into_iter()
+					.map(|task| async move {
+						task.downcast::<Script>()
+							.expect("the script runner job only dispatches script tasks")
+							.serialize()
+							.await
+					})
+					.collect::<Vec<_>>()
+					.try_join()
+					.await?,
+			)?)),
+		})
+		.map(Some)
+	}
+
+	async fn deserialize(
+		serialized_job: &[u8],
+		_: &Ctx,
+	) -> Result<Option<(Self, Option<SerializedTasks>)>, rmp_serde::decode::Error> {
+		let SaveState {
+			location,
+			sub_path,
+			command,
+			metadata,
+			tasks_for_shutdown_bytes,
+		} = rmp_serde::from_slice::<SaveState>(serialized_job)?;
+
+		Ok(Some((
+			Self {
+				location,
+				sub_path,
+				command,
+				metadata,
+				pending_tasks_on_resume: Vec::new(),
+				tasks_for_shutdown: Vec::new(),
+			},
+			tasks_for_shutdown_bytes,
+		)))
+	}
+}
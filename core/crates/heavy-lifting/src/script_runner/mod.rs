@@ -0,0 +1,43 @@
+use sd_utils::{db::MissingFieldError, error::FileIOError};
+
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+pub mod job;
+mod tasks;
+
+pub use job::ScriptRunner;
+pub use tasks::script;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("missing field on database: {0}")]
+	MissingField(#[from] MissingFieldError),
+	#[error("failed to deserialized stored tasks for job resume: {0}")]
+	DeserializeTasks(#[from] rmp_serde::decode::Error),
+	#[error("failed to start script: {0}")]
+	Spawn(#[from] FileIOError),
+	#[error("script <program='{program}'> exited with {status}: {stderr}")]
+	Failed {
+		program: String,
+		status: String,
+		stderr: String,
+	},
+}
+
+impl From<Error> for rspc::Error {
+	fn from(err: Error) -> Self {
+		Self::with_cause(ErrorCode::InternalServerError, err.to_string(), err)
+	}
+}
+
+/// A program run on a location, e.g. as the last stage of a [`JobChain`](crate::JobChain) to
+/// upload or post-process what the previous stages indexed. It runs from the location root, or
+/// from the sub path the chain was run on, without a shell, so `args` aren't expanded.
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct ScriptCommand {
+	pub program: String,
+	#[serde(default)]
+	pub args: Vec<String>,
+}
@@ -0,0 +1,3 @@
+pub mod script;
+
+pub use script::Script;
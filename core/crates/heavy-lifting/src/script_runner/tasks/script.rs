@@ -0,0 +1,199 @@
+use crate::{
+	script_runner::{self, ScriptCommand},
+	Error,
+};
+
+use sd_prisma::prisma::location;
+use sd_task_system::{
+	ExecStatus, Interrupter, InterruptionKind, IntoAnyTaskOutput, SerializableTask, Task, TaskId,
+};
+use sd_utils::error::FileIOError;
+
+use std::{
+	future::IntoFuture,
+	io, mem,
+	path::{Path, PathBuf},
+	process::Stdio,
+	time::Duration,
+};
+
+use futures::FutureExt;
+use futures_concurrency::future::Race;
+use serde::{Deserialize, Serialize};
+use tokio::{
+	process::{Child, Command},
+	time::Instant,
+};
+use uuid::Uuid;
+
+/// How much of the end of stderr we keep in the error of a failed script
+const STDERR_TAIL_LEN: usize = 1024;
+
+/// Runs a [`ScriptCommand`] to completion. The script can't be paused, so pausing kills it and
+/// resuming runs it again from the start.
+#[derive(Debug)]
+pub struct Script {
+	id: TaskId,
+	command: ScriptCommand,
+	working_dir: PathBuf,
+	envs: Vec<(String, String)>,
+	output: Output,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Output {
+	pub run_time: Duration,
+}
+
+impl Script {
+	/// The script gets the location it runs on in `SD_LIBRARY_ID`, `SD_LOCATION_ID` and
+	/// `SD_LOCATION_PATH`
+	#[must_use]
+	pub fn new(
+		command: ScriptCommand,
+		working_dir: PathBuf,
+		library_id: Uuid,
+		location_id: location::id::Type,
+		location_path: &Path,
+	) -> Self {
+		Self {
+			id: TaskId::new_v4(),
+			command,
+			working_dir,
+			envs: vec![
+				("SD_LIBRARY_ID".into(), library_id.to_string()),
+				("SD_LOCATION_ID".into(), location_id.to_string()),
+				(
+					"SD_LOCATION_PATH".into(),
+					location_path.to_string_lossy().into_owned(),
+				),
+			],
+			output: Output::default(),
+		}
+	}
+
+	fn spawn(&self) -> Result<Child, script_runner::Error> {
+		Command::new(&self.command.program)
+			.args(&self.command.args)
+			.envs(self.envs.iter().map(|(k, v)| (k, v)))
+			.current_dir(&self.working_dir)
+			.stdin(Stdio::null())
+			.stdout(Stdio::null())
+			.stderr(Stdio::piped())
+			// Dropping the child when interrupted kills the script
+			.kill_on_drop(true)
+			.spawn()
+			.map_err(|e| FileIOError::from((&self.working_dir, e, "Failed to start script")).into())
+	}
+}
+
+#[async_trait::async_trait]
+impl Task<Error> for Script {
+	fn id(&self) -> TaskId {
+		self.id
+	}
+
+	async fn run(&mut self, interrupter: &Interrupter) -> Result<ExecStatus, Error> {
+		enum RaceOutput {
+			Exited(io::Result<std::process::Output>),
+			Interrupted(InterruptionKind),
+		}
+
+		let start = Instant::now();
+		let child = self.spawn()?;
+
+		let output = (
+			child.wait_with_output().map(RaceOutput::Exited),
+			interrupter.into_future().map(RaceOutput::Interrupted),
+		)
+			.race()
+			.await;
+
+		self.output.run_time += start.elapsed();
+
+		match output {
+			RaceOutput::Exited(Ok(output)) if output.status.success() => {
+				Ok(ExecStatus::Done(mem::take(&mut self.output).into_output()))
+			}
+
+			RaceOutput::Exited(Ok(output)) => {
+				let tail_start = output.stderr.len().saturating_sub(STDERR_TAIL_LEN);
+
+				Err(script_runner::Error::Failed {
+					program: self.command.program.clone(),
+					status: output.status.to_string(),
+					stderr: String::from_utf8_lossy(&output.stderr[tail_start..])
+						.trim()
+						.to_string(),
+				}
+				.into())
+			}
+
+			RaceOutput::Exited(Err(e)) => Err(script_runner::Error::from(FileIOError::from((
+				&self.working_dir,
+				e,
+				"Failed to wait for script",
+			)))
+			.into()),
+
+			RaceOutput::Interrupted(InterruptionKind::Pause) => Ok(ExecStatus::Paused),
+			RaceOutput::Interrupted(InterruptionKind::Cancel) => Ok(ExecStatus::Canceled),
+		}
+	}
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SaveState {
+	id: TaskId,
+	command: ScriptCommand,
+	working_dir: PathBuf,
+	envs: Vec<(String, String)>,
+	output: Output,
+}
+
+impl SerializableTask<Error> for Script {
+	type SerializeError = rmp_serde::encode::Error;
+
+	type DeserializeError = rmp_serde::decode::Error;
+
+	type DeserializeCtx = ();
+
+	async fn serialize(self) -> Result<Vec<u8>, Self::SerializeError> {
+		let Self {
+			id,
+			command,
+			working_dir,
+			envs,
+			output,
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState {
+			id,
+			command,
+			working_dir,
+			envs,
+			output,
+		})
+	}
+
+	async fn deserialize(
+		data: &[u8],
+		(): Self::DeserializeCtx,
+	) -> Result<Self, Self::DeserializeError> {
+		rmp_serde::from_slice(data).map(
+			|SaveState {
+			     id,
+			     command,
+			     working_dir,
+			     envs,
+			     output,
+			 }| Self {
+				id,
+				command,
+				working_dir,
+				envs,
+				output,
+			},
+		)
+	}
+}
@@ -0,0 +1,374 @@
+use crate::{
+	file_identifier,
+	job_system::{
+		job::{Job, JobReturn, JobTaskDispatcher, ReturnStatus},
+		report::ReportOutputMetadata,
+		utils::{cancel_pending_tasks, location_concurrency_key},
+		SerializableJob, SerializedTasks,
+	},
+	tag_rules,
+	utils::sub_path::{self, maybe_get_iso_file_path_from_sub_path},
+	Error, JobName, OuterContext, ProgressUpdate,
+};
+
+use sd_core_file_path_helper::IsolatedFilePathData;
+
+use sd_prisma::prisma::{file_path, location, SortOrder};
+use sd_task_system::{
+	IntoTask, SerializableTask, Task, TaskDispatcher, TaskHandle, TaskOutput, TaskStatus,
+};
+use sd_utils::db::maybe_missing;
+
+use std::{
+	collections::HashMap,
+	hash::{Hash, Hasher},
+	mem,
+	path::PathBuf,
+	sync::Arc,
+	time::Duration,
+};
+
+use futures::{stream::FuturesUnordered, StreamExt};
+use futures_concurrency::future::TryJoin;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{debug, warn};
+
+use super::{
+	tasks::{tagger, Tagger},
+	TagRules, BATCH_SIZE,
+};
+
+/// Applies the tag rules of the library to the objects already identified in a location, or in a
+/// directory of it. New objects are tagged by the file identifier as they're identified.
+#[derive(Debug)]
+pub struct TagRuleApplier {
+	location: Arc<location::Data>,
+	location_path: Arc<PathBuf>,
+	sub_path: Option<PathBuf>,
+
+	metadata: Metadata,
+
+	pending_tasks_on_resume: Vec<TaskHandle<Error>>,
+	tasks_for_shutdown: Vec<Box<dyn Task<Error>>>,
+}
+
+impl Hash for TagRuleApplier {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.location.id.hash(state);
+		if let Some(ref sub_path) = self.sub_path {
+			sub_path.hash(state);
+		}
+	}
+}
+
+impl Job for TagRuleApplier {
+	const NAME: JobName = JobName::TagRuleApplier;
+
+	async fn resume_tasks(
+		&mut self,
+		dispatcher: &JobTaskDispatcher,
+		ctx: &impl OuterContext,
+		SerializedTasks(serialized_tasks): SerializedTasks,
+	) -> Result<(), Error> {
+		self.pending_tasks_on_resume = dispatcher
+			.with_concurrency_key(location_concurrency_key(ctx.id(), self.location.id))
+			.dispatch_many_boxed(
+				rmp_serde::from_slice::<Vec<Vec<u8>>>(&serialized_tasks)
+					.map_err(tag_rules::Error::from)?
+					.into_iter()
+					.map(|task_bytes| async move {
+						Tagger::deserialize(
+							&task_bytes,
+							(Arc::clone(ctx.db()), Arc::clone(ctx.sync())),
+						)
+						.await
+						.map(IntoTask::into_task)
+					})
+					.collect::<Vec<_>>()
+					.try_join()
+					.await
+					.map_err(tag_rules::Error::from)?,
+			)
+			.await;
+
+		Ok(())
+	}
+
+	async fn run<Ctx: OuterContext>(
+		mut self,
+		dispatcher: JobTaskDispatcher,
+		ctx: Ctx,
+	) -> Result<ReturnStatus, Error> {
+		let dispatcher =
+			dispatcher.with_concurrency_key(location_concurrency_key(ctx.id(), self.location.id));
+
+		let mut pending_running_tasks = FuturesUnordered::new();
+
+		self.init_or_resume(&mut pending_running_tasks, &ctx, &dispatcher)
+			.await?;
+
+		while let Some(task) = pending_running_tasks.next().await {
+			match task {
+				Ok(TaskStatus::Done((_, TaskOutput::Out(out)))) => {
+					self.process_tagger_output(
+						*out.downcast::<tagger::Output>()
+							.expect("the tag rule applier job only dispatches tagger tasks"),
+						&ctx,
+					);
+				}
+
+				Ok(TaskStatus::Done((task_id, TaskOutput::Empty))) => {
+					warn!("Task <id='{task_id}'> returned an empty output");
+				}
+
+				Ok(TaskStatus::Shutdown(task)) => {
+					self.tasks_for_shutdown.push(task);
+				}
+
+				Ok(TaskStatus::Error(e)) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e);
+				}
+
+				Ok(TaskStatus::Canceled | TaskStatus::ForcedAbortion) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Ok(ReturnStatus::Canceled);
+				}
+
+				Err(e) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e.into());
+				}
+			}
+		}
+
+		if !self.tasks_for_shutdown.is_empty() {
+			return Ok(ReturnStatus::Shutdown(
+				SerializableJob::<Ctx>::serialize(self).await,
+			));
+		}
+
+		if self.metadata.assigned_tags > 0 {
+			ctx.invalidate_query("tags.getForObject");
+			ctx.invalidate_query("tags.getWithObjects");
+			ctx.invalidate_query("search.objects");
+		}
+
+		Ok(ReturnStatus::Completed(
+			JobReturn::builder().with_metadata(self.metadata).build(),
+		))
+	}
+}
+
+impl TagRuleApplier {
+	pub fn new(
+		location: location::Data,
+		sub_path: Option<PathBuf>,
+	) -> Result<Self, tag_rules::Error> {
+		Ok(Self {
+			location_path: maybe_missing(&location.path, "location.path")
+				.map(PathBuf::from)
+				.map(Arc::new)?,
+			location: Arc::new(location),
+			sub_path,
+			metadata: Metadata::default(),
+			pending_tasks_on_resume: Vec::new(),
+			tasks_for_shutdown: Vec::new(),
+		})
+	}
+
+	async fn init_or_resume(
+		&mut self,
+		pending_running_tasks: &mut FuturesUnordered<TaskHandle<Error>>,
+		ctx: &impl OuterContext,
+		dispatcher: &JobTaskDispatcher,
+	) -> Result<(), tag_rules::Error> {
+		// Resumed tasks were already dispatched by `resume_tasks`
+		if !self.pending_tasks_on_resume.is_empty() {
+			pending_running_tasks.extend(mem::take(&mut self.pending_tasks_on_resume));
+
+			return Ok(());
+		}
+
+		let db = ctx.db();
+		let location_id = self.location.id;
+		let location_path = &*self.location_path;
+
+		// Nothing would be tagged, so there is no need to go through the files
+		if TagRules::load(db).await?.is_empty() {
+			debug!("No enabled tag rules, skipping location {location_id}");
+
+			return Ok(());
+		}
+
+		let iso_file_path =
+			maybe_get_iso_file_path_from_sub_path(location_id, &self.sub_path, location_path, db)
+				.await?
+				.map_or_else(
+					|| {
+						IsolatedFilePathData::new(location_id, location_path, location_path, true)
+							.map_err(sub_path::Error::from)
+					},
+					Ok,
+				)?;
+
+		debug!("Applying tag rules in location {location_id} at directory \"{iso_file_path}\"");
+
+		let file_path_ids = db
+			.file_path()
+			.find_many(vec![
+				file_path::location_id::equals(Some(location_id)),
+				file_path::object_id::not(None),
+				file_identifier::not_in_archive(),
+				file_path::materialized_path::starts_with(
+					iso_file_path
+						.materialized_path_for_children()
+						.expect("sub path iso_file_path must be a directory"),
+				),
+			])
+			.order_by(file_path::id::order(SortOrder::Asc))
+			.select(file_path::select!({ id }))
+			.exec()
+			.await?
+			.into_iter()
+			.map(|file_path| file_path.id)
+			.collect::<Vec<_>>();
+
+		self.metadata.total_files = file_path_ids.len() as u64;
+
+		pending_running_tasks.extend(
+			dispatcher
+				.dispatch_many(
+					file_path_ids
+						.chunks(BATCH_SIZE)
+						.map(|chunk| {
+							Tagger::new(chunk.to_vec(), Arc::clone(db), Arc::clone(ctx.sync()))
+						})
+						.collect::<Vec<_>>(),
+				)
+				.await,
+		);
+
+		ctx.progress(vec![
+			ProgressUpdate::TaskCount(self.metadata.total_files),
+			ProgressUpdate::Message(format!(
+				"Applying tag rules to {} files in {} chunks",
+				self.metadata.total_files,
+				pending_running_tasks.len()
+			)),
+		]);
+
+		Ok(())
+	}
+
+	fn process_tagger_output(
+		&mut self,
+		tagger::Output {
+			checked_files,
+			assigned_tags,
+			tagging_time,
+		}: tagger::Output,
+		ctx: &impl OuterContext,
+	) {
+		self.metadata.checked_files += checked_files;
+		self.metadata.assigned_tags += assigned_tags;
+		self.metadata.tagging_time += tagging_time;
+
+		ctx.progress(vec![ProgressUpdate::CompletedTaskCount(
+			self.metadata.checked_files,
+		)]);
+	}
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Metadata {
+	total_files: u64,
+	checked_files: u64,
+	assigned_tags: u64,
+	tagging_time: Duration,
+}
+
+impl From<Metadata> for ReportOutputMetadata {
+	fn from(value: Metadata) -> Self {
+		Self::Metrics(HashMap::from([
+			("total_files".into(), json!(value.total_files)),
+			("checked_files".into(), json!(value.checked_files)),
+			("assigned_tags".into(), json!(value.assigned_tags)),
+			("tagging_time".into(), json!(value.tagging_time)),
+		]))
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+struct SaveState {
+	location: Arc<location::Data>,
+	location_path: Arc<PathBuf>,
+	sub_path: Option<PathBuf>,
+
+	metadata: Metadata,
+
+	tasks_for_shutdown_bytes: Option<SerializedTasks>,
+}
+
+impl<Ctx: OuterContext> SerializableJob<Ctx> for TagRuleApplier {
+	async fn serialize(self) -> Result<Option<Vec<u8>>, rmp_serde::encode::Error> {
+		let Self {
+			location,
+			location_path,
+			sub_path,
+			metadata,
+			tasks_for_shutdown,
+			..
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState {
+			location,
+			location_path,
+			sub_path,
+			metadata,
+			tasks_for_shutdown_bytes: Some(SerializedTasks(rmp_serde::to_vec_named(
+				&tasks_for_shutdown
+					.into_iter()
+					.map(|task| async move {
+						task.downcast::<Tagger>()
+							.expect("the tag rule applier job only dispatches tagger tasks")
+							.serialize()
+							.await
+					})
+					.collect::<Vec<_>>()
+					.try_join()
+					.await?,
+			)?)),
+		})
+		.map(Some)
+	}
+
+	async fn deserialize(
+		serialized_job: &[u8],
+		_: &Ctx,
+	) -> Result<Option<(Self, Option<SerializedTasks>)>, rmp_serde::decode::Error> {
+		let SaveState {
+			location,
+			location_path,
+			sub_path,
+			metadata,
+			tasks_for_shutdown_bytes,
+		} = rmp_serde::from_slice::<SaveState>(serialized_job)?;
+
+		Ok(Some((
+			Self {
+				location,
+				location_path,
+				sub_path,
+				metadata,
+				pending_tasks_on_resume: Vec::new(),
+				tasks_for_shutdown: Vec::new(),
+			},
+			tasks_for_shutdown_bytes,
+		)))
+	}
+}
@@ -0,0 +1,421 @@
+use sd_core_sync::Manager as SyncManager;
+
+use sd_file_ext::kind::ObjectKind;
+use sd_prisma::{
+	prisma::{file_path, location, tag, tag_on_object, tag_rule, PrismaClient},
+	prisma_sync,
+};
+use sd_sync::OperationFactory;
+use sd_utils::db::{size_in_bytes_from_db, MissingFieldError};
+
+use std::collections::HashSet;
+
+use chrono::{DateTime, FixedOffset, Utc};
+use prisma_client_rust::QueryError;
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tracing::{trace, warn};
+
+pub mod job;
+mod tasks;
+
+pub use job::TagRuleApplier;
+pub use tasks::tagger;
+
+const MIB: u64 = 1024 * 1024;
+
+// Matching is cheap, so tasks get big batches of file paths
+const BATCH_SIZE: usize = 1000;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("missing field on database: {0}")]
+	MissingField(#[from] MissingFieldError),
+	#[error("database error: {0}")]
+	Database(#[from] QueryError),
+	#[error("failed to deserialized stored tasks for job resume: {0}")]
+	DeserializeTasks(#[from] rmp_serde::decode::Error),
+	#[error("failed to serialize tag rule conditions: {0}")]
+	SerializeConditions(#[from] rmp_serde::encode::Error),
+	#[error("invalid tag rule: {0}")]
+	InvalidRule(&'static str),
+
+	#[error(transparent)]
+	SubPath(#[from] crate::utils::sub_path::Error),
+}
+
+impl From<Error> for rspc::Error {
+	fn from(err: Error) -> Self {
+		match err {
+			Error::SubPath(sub_path_err) => sub_path_err.into(),
+
+			Error::InvalidRule(_) => Self::with_cause(ErrorCode::BadRequest, err.to_string(), err),
+
+			_ => Self::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+/// What a file must be for a tag rule to assign its tag to the file's object. A rule only applies
+/// when all of its conditions match.
+///
+/// Stored msgpack encoded in `tag_rule.conditions`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum Condition {
+	/// Any of these kinds
+	Kind(Vec<ObjectKind>),
+	/// Any of these extensions, without the leading dot, case insensitive
+	Extensions(Vec<String>),
+	/// Case insensitive, matched against the path of the file relative to its location root, e.g.
+	/// `/Screenshots/`
+	PathContains(String),
+	/// Case insensitive, matched against the name of the file with its extension
+	NameContains(String),
+	/// In any of these locations
+	Locations(Vec<location::id::Type>),
+	/// Files of at least this many MiB
+	MinSizeMiB(u32),
+	/// Files of at most this many MiB
+	MaxSizeMiB(u32),
+}
+
+impl Condition {
+	fn matches(&self, file: &Candidate<'_>) -> bool {
+		match self {
+			Self::Kind(kinds) => kinds.iter().any(|&kind| kind as i32 == file.kind),
+			Self::Extensions(extensions) => extensions.iter().any(|extension| {
+				extension
+					.trim_start_matches('.')
+					.eq_ignore_ascii_case(file.extension)
+			}),
+			Self::PathContains(needle) => file
+				.relative_path()
+				.to_lowercase()
+				.contains(&needle.to_lowercase()),
+			Self::NameContains(needle) => file
+				.full_name()
+				.to_lowercase()
+				.contains(&needle.to_lowercase()),
+			Self::Locations(location_ids) => location_ids.contains(&file.location_id),
+			Self::MinSizeMiB(min_size) => file.size >= u64::from(*min_size) * MIB,
+			Self::MaxSizeMiB(max_size) => file.size <= u64::from(*max_size) * MIB,
+		}
+	}
+}
+
+/// Rejects rules that would tag every file, or whose conditions can never match
+pub fn validate(conditions: &[Condition]) -> Result<(), Error> {
+	if conditions.is_empty() {
+		return Err(Error::InvalidRule("a rule needs at least one condition"));
+	}
+
+	for condition in conditions {
+		let is_valid = match condition {
+			Condition::Kind(kinds) => !kinds.is_empty(),
+			Condition::Locations(location_ids) => !location_ids.is_empty(),
+			Condition::Extensions(extensions) => extensions
+				.iter()
+				.all(|extension| !extension.trim_start_matches('.').is_empty()),
+			Condition::PathContains(needle) | Condition::NameContains(needle) => !needle.is_empty(),
+			Condition::MinSizeMiB(_) | Condition::MaxSizeMiB(_) => true,
+		};
+
+		if !is_valid {
+			return Err(Error::InvalidRule("conditions can't be empty"));
+		}
+	}
+
+	Ok(())
+}
+
+pub fn conditions_to_bytes(conditions: &[Condition]) -> Result<Vec<u8>, Error> {
+	rmp_serde::to_vec_named(conditions).map_err(Into::into)
+}
+
+pub fn conditions_from_bytes(bytes: &[u8]) -> Result<Vec<Condition>, Error> {
+	rmp_serde::from_slice(bytes).map_err(Into::into)
+}
+
+/// What rules are matched against, taken from a file path and its object
+#[derive(Debug)]
+struct Candidate<'a> {
+	location_id: location::id::Type,
+	materialized_path: &'a str,
+	name: &'a str,
+	extension: &'a str,
+	kind: i32,
+	size: u64,
+}
+
+impl Candidate<'_> {
+	fn full_name(&self) -> String {
+		if self.extension.is_empty() {
+			self.name.to_string()
+		} else {
+			format!("{}.{}", self.name, self.extension)
+		}
+	}
+
+	fn relative_path(&self) -> String {
+		format!("{}{}", self.materialized_path, self.full_name())
+	}
+}
+
+#[derive(Debug)]
+struct Rule {
+	tag_id: tag::id::Type,
+	tag_pub_id: tag::pub_id::Type,
+	conditions: Vec<Condition>,
+}
+
+impl Rule {
+	fn matches(&self, file: &Candidate<'_>) -> bool {
+		self.conditions
+			.iter()
+			.all(|condition| condition.matches(file))
+	}
+}
+
+/// The enabled tag rules of a library, ready to be matched against file paths
+#[derive(Debug)]
+pub struct TagRules(Vec<Rule>);
+
+impl TagRules {
+	/// Rules whose conditions can't be read are left out, as one broken rule shouldn't stop the
+	/// others from being applied. So are synced rules whose tag or conditions didn't arrive yet.
+	pub async fn load(db: &PrismaClient) -> Result<Self, Error> {
+		Ok(Self(
+			db.tag_rule()
+				.find_many(vec![
+					tag_rule::enabled::equals(true),
+					tag_rule::conditions::not(None),
+					tag_rule::tag_id::not(None),
+				])
+				.include(tag_rule::include!({ tag: select { id pub_id } }))
+				.exec()
+				.await?
+				.into_iter()
+				.filter_map(|rule| {
+					let tag = rule.tag?;
+
+					conditions_from_bytes(rule.conditions.as_deref()?)
+						.map_err(|e| {
+							warn!(
+								"Ignoring tag rule with invalid conditions <id={}>: {e:#?}",
+								rule.id
+							);
+						})
+						.ok()
+						.filter(|conditions| validate(conditions).is_ok())
+						.map(|conditions| Rule {
+							tag_id: tag.id,
+							tag_pub_id: tag.pub_id,
+							conditions,
+						})
+				})
+				.collect(),
+		))
+	}
+
+	#[must_use]
+	pub fn is_empty(&self) -> bool {
+		self.0.is_empty()
+	}
+
+	/// Tags the objects of the file paths matched by any rule, returning how many tags were
+	/// assigned. File paths without an object yet are left for when they're identified.
+	pub async fn apply(
+		&self,
+		file_path_ids: &[file_path::id::Type],
+		db: &PrismaClient,
+		sync: &SyncManager,
+	) -> Result<u64, Error> {
+		if self.is_empty() || file_path_ids.is_empty() {
+			return Ok(0);
+		}
+
+		let file_paths = db
+			.file_path()
+			.find_many(vec![
+				file_path::id::in_vec(file_path_ids.to_vec()),
+				file_path::object_id::not(None),
+			])
+			.select(file_path::select!({
+				location_id
+				materialized_path
+				name
+				extension
+				size_in_bytes_bytes
+				object: select { id pub_id kind }
+			}))
+			.exec()
+			.await?;
+
+		let date_created: DateTime<FixedOffset> = Utc::now().into();
+
+		let mut assigned = HashSet::new();
+		let mut sync_ops = vec![];
+		let mut db_creates = vec![];
+
+		for file_path in &file_paths {
+			let Some(object) = &file_path.object else {
+				continue;
+			};
+
+			let candidate = Candidate {
+				location_id: file_path.location_id.unwrap_or_default(),
+				materialized_path: file_path.materialized_path.as_deref().unwrap_or("/"),
+				name: file_path.name.as_deref().unwrap_or_default(),
+				extension: file_path.extension.as_deref().unwrap_or_default(),
+				kind: object.kind.unwrap_or_default(),
+				size: file_path
+					.size_in_bytes_bytes
+					.as_deref()
+					.map_or(0, size_in_bytes_from_db),
+			};
+
+			for Rule {
+				tag_id, tag_pub_id, ..
+			} in self.0.iter().filter(|rule| rule.matches(&candidate))
+			{
+				// Many file paths of the same object can match the same rule
+				if !assigned.insert((*tag_id, object.id)) {
+					continue;
+				}
+
+				sync_ops.extend(sync.relation_create(
+					prisma_sync::tag_on_object::SyncId {
+						tag: prisma_sync::tag::SyncId {
+							pub_id: tag_pub_id.clone(),
+						},
+						object: prisma_sync::object::SyncId {
+							pub_id: object.pub_id.clone(),
+						},
+					},
+					[],
+				));
+
+				db_creates.push(tag_on_object::CreateUnchecked {
+					tag_id: *tag_id,
+					object_id: object.id,
+					_params: vec![tag_on_object::date_created::set(Some(date_created))],
+				});
+			}
+		}
+
+		if db_creates.is_empty() {
+			return Ok(0);
+		}
+
+		let assigned_tags_count = sync
+			.write_ops(
+				db,
+				(
+					sync_ops,
+					db.tag_on_object().create_many(db_creates).skip_duplicates(),
+				),
+			)
+			.await?;
+
+		trace!("Assigned {assigned_tags_count} Tags by tag rules");
+
+		#[allow(clippy::cast_sign_loss)] // SAFETY: We're sure the value is positive
+		Ok(assigned_tags_count as u64)
+	}
+}
+
+/// Loads the rules of the library and applies them to the file paths, see [`TagRules::apply`]
+pub async fn apply(
+	file_path_ids: &[file_path::id::Type],
+	db: &PrismaClient,
+	sync: &SyncManager,
+) -> Result<u64, Error> {
+	if file_path_ids.is_empty() {
+		return Ok(0);
+	}
+
+	TagRules::load(db)
+		.await?
+		.apply(file_path_ids, db, sync)
+		.await
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn screenshot() -> Candidate<'static> {
+		Candidate {
+			location_id: 1,
+			materialized_path: "/Pictures/Screenshots/",
+			name: "Screen Shot 2024-07-01",
+			extension: "PNG",
+			kind: ObjectKind::Image as i32,
+			size: 2 * MIB,
+		}
+	}
+
+	#[test]
+	fn all_conditions_must_match() {
+		let rule = |conditions| Rule {
+			tag_id: 1,
+			tag_pub_id: vec![],
+			conditions,
+		};
+
+		assert!(rule(vec![
+			Condition::Kind(vec![ObjectKind::Image]),
+			Condition::PathContains("/screenshots/".to_string()),
+		])
+		.matches(&screenshot()));
+
+		assert!(!rule(vec![
+			Condition::Kind(vec![ObjectKind::Video]),
+			Condition::PathContains("/Screenshots/".to_string()),
+		])
+		.matches(&screenshot()));
+	}
+
+	#[test]
+	fn extensions_and_names_ignore_case() {
+		let file = screenshot();
+
+		assert!(Condition::Extensions(vec![".png".to_string()]).matches(&file));
+		assert!(!Condition::Extensions(vec!["jpg".to_string()]).matches(&file));
+		assert!(Condition::NameContains("shot 2024-07-01.png".to_string()).matches(&file));
+		assert!(!Condition::NameContains("Pictures".to_string()).matches(&file));
+	}
+
+	#[test]
+	fn sizes_are_inclusive() {
+		let file = screenshot();
+
+		assert!(Condition::MinSizeMiB(2).matches(&file));
+		assert!(Condition::MaxSizeMiB(2).matches(&file));
+		assert!(!Condition::MinSizeMiB(3).matches(&file));
+		assert!(!Condition::MaxSizeMiB(1).matches(&file));
+	}
+
+	#[test]
+	fn rules_that_tag_everything_are_rejected() {
+		assert!(validate(&[]).is_err());
+		assert!(validate(&[Condition::PathContains(String::new())]).is_err());
+		assert!(validate(&[Condition::Extensions(vec![".".to_string()])]).is_err());
+		assert!(validate(&[Condition::Kind(vec![ObjectKind::Image])]).is_ok());
+	}
+
+	#[test]
+	fn conditions_survive_a_round_trip() {
+		let conditions = vec![
+			Condition::Kind(vec![ObjectKind::Image, ObjectKind::Video]),
+			Condition::PathContains("/Screenshots/".to_string()),
+		];
+
+		assert_eq!(
+			conditions_from_bytes(&conditions_to_bytes(&conditions).expect("serializes"))
+				.expect("deserializes"),
+			conditions
+		);
+	}
+}
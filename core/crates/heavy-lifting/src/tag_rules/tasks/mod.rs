@@ -0,0 +1,3 @@
+pub mod tagger;
+
+pub use tagger::Tagger;
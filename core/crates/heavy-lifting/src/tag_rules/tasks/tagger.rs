@@ -0,0 +1,110 @@
+use crate::{tag_rules, Error};
+
+use sd_core_sync::Manager as SyncManager;
+
+use sd_prisma::prisma::{file_path, PrismaClient};
+use sd_task_system::{ExecStatus, Interrupter, IntoAnyTaskOutput, SerializableTask, Task, TaskId};
+
+use std::{mem, sync::Arc, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+/// Applies the tag rules of the library to a batch of file paths
+#[derive(Debug)]
+pub struct Tagger {
+	id: TaskId,
+	file_path_ids: Vec<file_path::id::Type>,
+	db: Arc<PrismaClient>,
+	sync: Arc<SyncManager>,
+	output: Output,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Output {
+	pub checked_files: u64,
+	pub assigned_tags: u64,
+	pub tagging_time: Duration,
+}
+
+impl Tagger {
+	#[must_use]
+	pub fn new(
+		file_path_ids: Vec<file_path::id::Type>,
+		db: Arc<PrismaClient>,
+		sync: Arc<SyncManager>,
+	) -> Self {
+		Self {
+			id: TaskId::new_v4(),
+			file_path_ids,
+			db,
+			sync,
+			output: Output::default(),
+		}
+	}
+}
+
+#[async_trait::async_trait]
+impl Task<Error> for Tagger {
+	fn id(&self) -> TaskId {
+		self.id
+	}
+
+	async fn run(&mut self, _: &Interrupter) -> Result<ExecStatus, Error> {
+		let Self {
+			file_path_ids,
+			db,
+			sync,
+			output: Output {
+				checked_files,
+				assigned_tags,
+				tagging_time,
+			},
+			..
+		} = self;
+
+		let start = Instant::now();
+
+		// Rules are loaded again on each batch, so edits made while the job runs are picked up
+		*assigned_tags = tag_rules::apply(file_path_ids, db, sync).await?;
+		*checked_files = file_path_ids.len() as u64;
+		*tagging_time = start.elapsed();
+
+		Ok(ExecStatus::Done(mem::take(&mut self.output).into_output()))
+	}
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SaveState {
+	id: TaskId,
+	file_path_ids: Vec<file_path::id::Type>,
+}
+
+impl SerializableTask<Error> for Tagger {
+	type SerializeError = rmp_serde::encode::Error;
+
+	type DeserializeError = rmp_serde::decode::Error;
+
+	type DeserializeCtx = (Arc<PrismaClient>, Arc<SyncManager>);
+
+	async fn serialize(self) -> Result<Vec<u8>, Self::SerializeError> {
+		let Self {
+			id, file_path_ids, ..
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState { id, file_path_ids })
+	}
+
+	async fn deserialize(
+		data: &[u8],
+		(db, sync): Self::DeserializeCtx,
+	) -> Result<Self, Self::DeserializeError> {
+		rmp_serde::from_slice(data).map(|SaveState { id, file_path_ids }| Self {
+			id,
+			file_path_ids,
+			db,
+			sync,
+			output: Output::default(),
+		})
+	}
+}
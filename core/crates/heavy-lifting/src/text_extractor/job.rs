@@ -0,0 +1,457 @@
+use crate::{
+	job_system::{
+		job::{Job, JobReturn, JobTaskDispatcher, ReturnStatus},
+		report::ReportOutputMetadata,
+		utils::{cancel_pending_tasks, location_concurrency_key},
+		SerializableJob, SerializedTasks,
+	},
+	text_extractor,
+	utils::sub_path::{self, maybe_get_iso_file_path_from_sub_path},
+	Error, JobName, NonCriticalError, OuterContext, ProgressUpdate,
+};
+
+use sd_core_file_path_helper::IsolatedFilePathData;
+use sd_core_prisma_helpers::file_path_for_media_processor;
+
+use sd_prisma::prisma::{location, PrismaClient};
+use sd_task_system::{
+	IntoTask, SerializableTask, Task, TaskDispatcher, TaskHandle, TaskOutput, TaskStatus,
+};
+use sd_utils::db::maybe_missing;
+
+use std::{
+	collections::HashMap,
+	hash::{Hash, Hasher},
+	mem,
+	path::PathBuf,
+	sync::Arc,
+	time::Duration,
+};
+
+use futures::{stream::FuturesUnordered, StreamExt};
+use futures_concurrency::future::TryJoin;
+use itertools::Itertools;
+use prisma_client_rust::{raw, PrismaValue};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{debug, warn};
+
+use super::{
+	tasks::{text_recognizer, TextRecognizer},
+	AVAILABLE_EXTENSIONS, BATCH_SIZE, DEFAULT_OCR_LANGUAGES,
+};
+
+/// Extracts the text of plain text files, source code, documents and images in a location, or in a
+/// directory of it, so they can be found by their content with full-text search.
+///
+/// Objects whose content changed since their text was extracted get it extracted again.
+#[derive(Debug)]
+pub struct TextExtractor {
+	location: Arc<location::Data>,
+	location_path: Arc<PathBuf>,
+	sub_path: Option<PathBuf>,
+	languages: Arc<String>,
+	regenerate: bool,
+
+	metadata: Metadata,
+
+	errors: Vec<NonCriticalError>,
+
+	pending_tasks_on_resume: Vec<TaskHandle<Error>>,
+	tasks_for_shutdown: Vec<Box<dyn Task<Error>>>,
+}
+
+impl Hash for TextExtractor {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.location.id.hash(state);
+		if let Some(ref sub_path) = self.sub_path {
+			sub_path.hash(state);
+		}
+	}
+}
+
+impl Job for TextExtractor {
+	const NAME: JobName = JobName::TextExtractor;
+
+	async fn resume_tasks(
+		&mut self,
+		dispatcher: &JobTaskDispatcher,
+		ctx: &impl OuterContext,
+		SerializedTasks(serialized_tasks): SerializedTasks,
+	) -> Result<(), Error> {
+		self.pending_tasks_on_resume = dispatcher
+			.with_concurrency_key(location_concurrency_key(ctx.id(), self.location.id))
+			.dispatch_many_boxed(
+				rmp_serde::from_slice::<Vec<Vec<u8>>>(&serialized_tasks)
+					.map_err(text_extractor::Error::from)?
+					.into_iter()
+					.map(|task_bytes| async move {
+						TextRecognizer::deserialize(&task_bytes, Arc::clone(ctx.db()))
+							.await
+							.map(IntoTask::into_task)
+					})
+					.collect::<Vec<_>>()
+					.try_join()
+					.await
+					.map_err(text_extractor::Error::from)?,
+			)
+			.await;
+
+		Ok(())
+	}
+
+	async fn run<Ctx: OuterContext>(
+		mut self,
+		dispatcher: JobTaskDispatcher,
+		ctx: Ctx,
+	) -> Result<ReturnStatus, Error> {
+		let dispatcher =
+			dispatcher.with_concurrency_key(location_concurrency_key(ctx.id(), self.location.id));
+
+		let mut pending_running_tasks = FuturesUnordered::new();
+
+		self.init_or_resume(&mut pending_running_tasks, &ctx, &dispatcher)
+			.await?;
+
+		while let Some(task) = pending_running_tasks.next().await {
+			match task {
+				Ok(TaskStatus::Done((_, TaskOutput::Out(out)))) => {
+					self.process_recognizer_output(
+						*out.downcast::<text_recognizer::Output>()
+							.expect("the text extractor job only dispatches recognizer tasks"),
+						&ctx,
+					);
+				}
+
+				Ok(TaskStatus::Done((task_id, TaskOutput::Empty))) => {
+					warn!("Task <id='{task_id}'> returned an empty output");
+				}
+
+				Ok(TaskStatus::Shutdown(task)) => {
+					self.tasks_for_shutdown.push(task);
+				}
+
+				Ok(TaskStatus::Error(e)) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e);
+				}
+
+				Ok(TaskStatus::Canceled | TaskStatus::ForcedAbortion) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Ok(ReturnStatus::Canceled);
+				}
+
+				Err(e) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e.into());
+				}
+			}
+		}
+
+		if !self.tasks_for_shutdown.is_empty() {
+			return Ok(ReturnStatus::Shutdown(
+				SerializableJob::<Ctx>::serialize(self).await,
+			));
+		}
+
+		let Self {
+			metadata, errors, ..
+		} = self;
+
+		Ok(ReturnStatus::Completed(
+			JobReturn::builder()
+				.with_metadata(metadata)
+				.with_non_critical_errors(errors)
+				.build(),
+		))
+	}
+}
+
+impl TextExtractor {
+	pub fn new(
+		location: location::Data,
+		sub_path: Option<PathBuf>,
+	) -> Result<Self, text_extractor::Error> {
+		Ok(Self {
+			location_path: maybe_missing(&location.path, "location.path")
+				.map(PathBuf::from)
+				.map(Arc::new)?,
+			location: Arc::new(location),
+			sub_path,
+			languages: Arc::new(DEFAULT_OCR_LANGUAGES.to_string()),
+			regenerate: false,
+			metadata: Metadata::default(),
+			errors: Vec::new(),
+			pending_tasks_on_resume: Vec::new(),
+			tasks_for_shutdown: Vec::new(),
+		})
+	}
+
+	/// Tesseract's languages to recognize, like `eng+por`, whose trained data must be installed
+	#[must_use]
+	pub fn with_languages(mut self, languages: String) -> Self {
+		self.languages = Arc::new(languages);
+		self
+	}
+
+	/// Extracts again the text of objects that already have it, like after installing languages
+	#[must_use]
+	pub const fn with_regenerate(mut self, regenerate: bool) -> Self {
+		self.regenerate = regenerate;
+		self
+	}
+
+	async fn init_or_resume(
+		&mut self,
+		pending_running_tasks: &mut FuturesUnordered<TaskHandle<Error>>,
+		ctx: &impl OuterContext,
+		dispatcher: &JobTaskDispatcher,
+	) -> Result<(), text_extractor::Error> {
+		// Resumed tasks were already dispatched by `resume_tasks`
+		if !self.pending_tasks_on_resume.is_empty() {
+			pending_running_tasks.extend(mem::take(&mut self.pending_tasks_on_resume));
+
+			return Ok(());
+		}
+
+		let db = ctx.db();
+		let location_id = self.location.id;
+		let location_path = &*self.location_path;
+
+		let iso_file_path =
+			maybe_get_iso_file_path_from_sub_path(location_id, &self.sub_path, location_path, db)
+				.await?
+				.map_or_else(
+					|| {
+						IsolatedFilePathData::new(location_id, location_path, location_path, true)
+							.map_err(sub_path::Error::from)
+					},
+					Ok,
+				)?;
+
+		debug!(
+			"Extracting text of files in location {location_id} at directory \"{iso_file_path}\""
+		);
+
+		let file_paths = get_files_to_extract(db, &iso_file_path, self.regenerate).await?;
+
+		self.metadata.total_files = file_paths.len() as u64;
+
+		pending_running_tasks.extend(
+			dispatcher
+				.dispatch_many(
+					file_paths
+						.into_iter()
+						.chunks(BATCH_SIZE)
+						.into_iter()
+						.map(|chunk| {
+							TextRecognizer::new(
+								&chunk.collect::<Vec<_>>(),
+								(location_id, location_path),
+								Arc::clone(&self.languages),
+								Arc::clone(db),
+							)
+						})
+						.collect::<Vec<_>>(),
+				)
+				.await,
+		);
+
+		ctx.progress(vec![
+			ProgressUpdate::TaskCount(self.metadata.total_files),
+			ProgressUpdate::Message(format!(
+				"Extracting text of {} files in {} chunks",
+				self.metadata.total_files,
+				pending_running_tasks.len()
+			)),
+		]);
+
+		Ok(())
+	}
+
+	fn process_recognizer_output(
+		&mut self,
+		text_recognizer::Output {
+			extracted,
+			recognized_pages,
+			skipped,
+			extraction_time,
+			db_write_time,
+			errors,
+		}: text_recognizer::Output,
+		ctx: &impl OuterContext,
+	) {
+		self.metadata.extracted += extracted;
+		self.metadata.recognized_pages += recognized_pages;
+		self.metadata.skipped += skipped;
+		self.metadata.extraction_time += extraction_time;
+		self.metadata.db_write_time += db_write_time;
+
+		self.errors.extend(errors);
+
+		ctx.progress(vec![ProgressUpdate::CompletedTaskCount(
+			self.metadata.extracted + self.metadata.skipped,
+		)]);
+	}
+}
+
+async fn get_files_to_extract(
+	db: &PrismaClient,
+	parent_iso_file_path: &IsolatedFilePathData<'_>,
+	regenerate: bool,
+) -> Result<Vec<file_path_for_media_processor::Data>, text_extractor::Error> {
+	// FIXME: Had to use format! macro because PCR doesn't support IN with Vec for SQLite
+	// We have no data coming from the user, so this is sql injection safe
+	db._query_raw(raw!(
+		&format!(
+			"SELECT id, materialized_path, is_dir, name, extension, cas_id, object_id
+			FROM file_path
+			WHERE
+				location_id={{}}
+				AND object_id IS NOT NULL
+				AND (in_archive IS NULL OR in_archive = 0)
+				AND LOWER(extension) IN ({})
+				AND materialized_path LIKE {{}}
+				{}
+			GROUP BY object_id
+			ORDER BY materialized_path ASC",
+			AVAILABLE_EXTENSIONS
+				.iter()
+				.map(|ext| format!("LOWER('{ext}')"))
+				.collect::<Vec<_>>()
+				.join(","),
+			// Texts are extracted again when the content they came from changed
+			if regenerate {
+				""
+			} else {
+				"AND NOT EXISTS (
+					SELECT 1 FROM object_text
+					WHERE object_text.object_id = file_path.object_id
+						AND object_text.cas_id IS file_path.cas_id
+				)"
+			}
+		),
+		PrismaValue::Int(parent_iso_file_path.location_id()),
+		PrismaValue::String(format!(
+			"{}%",
+			parent_iso_file_path
+				.materialized_path_for_children()
+				.expect("sub path iso_file_path must be a directory")
+		))
+	))
+	.exec()
+	.await
+	.map_err(Into::into)
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Metadata {
+	total_files: u64,
+	extracted: u64,
+	recognized_pages: u64,
+	skipped: u64,
+	extraction_time: Duration,
+	db_write_time: Duration,
+}
+
+impl From<Metadata> for ReportOutputMetadata {
+	fn from(value: Metadata) -> Self {
+		Self::Metrics(HashMap::from([
+			("total_files".into(), json!(value.total_files)),
+			("extracted_texts".into(), json!(value.extracted)),
+			("recognized_pages".into(), json!(value.recognized_pages)),
+			("skipped_files".into(), json!(value.skipped)),
+			("extraction_time".into(), json!(value.extraction_time)),
+			("db_write_time".into(), json!(value.db_write_time)),
+		]))
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+struct SaveState {
+	location: Arc<location::Data>,
+	location_path: Arc<PathBuf>,
+	sub_path: Option<PathBuf>,
+	languages: Arc<String>,
+	regenerate: bool,
+
+	metadata: Metadata,
+
+	errors: Vec<NonCriticalError>,
+
+	tasks_for_shutdown_bytes: Option<SerializedTasks>,
+}
+
+impl<Ctx: OuterContext> SerializableJob<Ctx> for TextExtractor {
+	async fn serialize(self) -> Result<Option<Vec<u8>>, rmp_serde::encode::Error> {
+		let Self {
+			location,
+			location_path,
+			sub_path,
+			languages,
+			regenerate,
+			metadata,
+			errors,
+			tasks_for_shutdown,
+			..
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState {
+			location,
+			location_path,
+			sub_path,
+			languages,
+			regenerate,
+			metadata,
+			errors,
+			tasks_for_shutdown_bytes: Some(SerializedTasks(rmp_serde::to_vec_named(
+				&tasks_for_shutdown
+					.into_iter()
+					.map(|task| async move {
+						task.downcast::<TextRecognizer>()
+							.expect("the text extractor job only dispatches recognizer tasks")
+							.serialize()
+							.await
+					})
+					.collect::<Vec<_>>()
+					.try_join()
+					.await?,
+			)?)),
+		})
+		.map(Some)
+	}
+
+	async fn deserialize(
+		serialized_job: &[u8],
+		_: &Ctx,
+	) -> Result<Option<(Self, Option<SerializedTasks>)>, rmp_serde::decode::Error> {
+		let SaveState {
+			location,
+			location_path,
+			sub_path,
+			languages,
+			regenerate,
+			metadata,
+			errors,
+			tasks_for_shutdown_bytes,
+		} = rmp_serde::from_slice::<SaveState>(serialized_job)?;
+
+		Ok(Some((
+			Self {
+				location,
+				location_path,
+				sub_path,
+				languages,
+				regenerate,
+				metadata,
+				errors,
+				pending_tasks_on_resume: Vec::new(),
+				tasks_for_shutdown: Vec::new(),
+			},
+			tasks_for_shutdown_bytes,
+		)))
+	}
+}
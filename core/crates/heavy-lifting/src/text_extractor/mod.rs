@@ -0,0 +1,312 @@
+use crate::utils::sub_path;
+
+use sd_core_file_path_helper::FilePathError;
+
+use sd_file_ext::extensions::{
+	DocumentExtension, Extension, ImageExtension, TextExtension, ALL_CODE_EXTENSIONS,
+	ALL_CONFIG_EXTENSIONS,
+};
+use sd_prisma::prisma::{object, object_text, PrismaClient};
+use sd_utils::db::MissingFieldError;
+
+use once_cell::sync::Lazy;
+use prisma_client_rust::{raw, PrismaValue, QueryError};
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+pub mod job;
+mod tasks;
+
+pub use job::TextExtractor;
+pub use tasks::text_recognizer;
+
+// Text recognition is slow, so each task gets only a few files to be interrupted often enough
+const BATCH_SIZE: usize = 10;
+
+/// Pages of a PDF read at most, long documents are still found by their first pages
+const MAX_PDF_PAGES: usize = 50;
+
+/// Bytes of a plain text file read at most, the beginning of huge logs and dumps is enough to
+/// find them by
+const MAX_TEXT_BYTES: u64 = 4 * 1024 * 1024;
+
+/// Tesseract's languages used when none are given, many can be joined with `+`, like `eng+por`
+pub const DEFAULT_OCR_LANGUAGES: &str = "eng";
+
+pub static AVAILABLE_EXTENSIONS: Lazy<Vec<Extension>> = Lazy::new(|| {
+	// PDFs with embedded text don't need an OCR engine, only scanned ones do
+	let mut extensions = vec![
+		Extension::Document(DocumentExtension::Pdf),
+		Extension::Document(DocumentExtension::Docx),
+	];
+
+	// RTF is left out, its markup would be indexed along with the text
+	extensions.extend(
+		[
+			TextExtension::Txt,
+			TextExtension::Md,
+			TextExtension::Markdown,
+		]
+		.map(Extension::Text),
+	);
+	extensions.extend(ALL_CODE_EXTENSIONS.iter().copied().map(Extension::Code));
+	extensions.extend(ALL_CONFIG_EXTENSIONS.iter().copied().map(Extension::Config));
+
+	if cfg!(feature = "ocr") {
+		use ImageExtension::{Bmp, Jpeg, Jpg, Png, Tiff, Webp};
+
+		extensions.extend([Jpg, Jpeg, Png, Bmp, Tiff, Webp].map(Extension::Image));
+	}
+
+	extensions
+});
+
+/// Where the text of an object came from, stored in `object_text.source`
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum TextSource {
+	/// Text embedded in a document, exact as written
+	Embedded = 0,
+	/// Text recognized in the pixels of an image or scanned page, may have mistakes
+	Ocr = 1,
+	/// Speech transcribed from audio or video, may have mistakes too
+	Transcript = 2,
+}
+
+impl TryFrom<i32> for TextSource {
+	type Error = i32;
+
+	fn try_from(value: i32) -> Result<Self, Self::Error> {
+		match value {
+			0 => Ok(Self::Embedded),
+			1 => Ok(Self::Ocr),
+			2 => Ok(Self::Transcript),
+			_ => Err(value),
+		}
+	}
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("missing field on database: {0}")]
+	MissingField(#[from] MissingFieldError),
+	#[error("database error: {0}")]
+	Database(#[from] QueryError),
+	#[error("failed to deserialized stored tasks for job resume: {0}")]
+	DeserializeTasks(#[from] rmp_serde::decode::Error),
+
+	#[error(transparent)]
+	FilePathError(#[from] FilePathError),
+	#[error(transparent)]
+	SubPath(#[from] sub_path::Error),
+}
+
+impl From<Error> for rspc::Error {
+	fn from(err: Error) -> Self {
+		match err {
+			Error::SubPath(sub_path_err) => sub_path_err.into(),
+
+			_ => Self::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize, Type)]
+pub enum NonCriticalError {
+	#[error(transparent)]
+	TextRecognizer(#[from] text_recognizer::NonCriticalError),
+}
+
+/// Collapses the runs of whitespace that page layouts and OCR engines leave behind
+fn normalize_text(text: &str) -> String {
+	text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Text of the paragraphs in the `word/document.xml` of a DOCX, with the markup stripped
+fn docx_text(xml: &str) -> String {
+	let mut text = String::with_capacity(xml.len() / 4);
+	let mut rest = xml;
+
+	while let Some(tag_start) = rest.find('<') {
+		text.push_str(&rest[..tag_start]);
+
+		let Some(tag_end) = rest[tag_start..].find('>') else {
+			break;
+		};
+
+		let tag = &rest[tag_start + 1..tag_start + tag_end];
+		let name = tag
+			.trim_end_matches('/')
+			.split_whitespace()
+			.next()
+			.unwrap_or_default();
+
+		// Words of different paragraphs, cells or lines would be glued together otherwise
+		if matches!(name, "/w:p" | "w:tab" | "w:br" | "w:cr") {
+			text.push(' ');
+		}
+
+		rest = &rest[tag_start + tag_end + 1..];
+	}
+
+	text.replace("&lt;", "<")
+		.replace("&gt;", ">")
+		.replace("&quot;", "\"")
+		.replace("&apos;", "'")
+		.replace("&amp;", "&")
+}
+
+async fn save(
+	texts: Vec<(object::id::Type, Option<String>, TextSource, String)>,
+	db: &PrismaClient,
+) -> Result<u64, Error> {
+	// Text is extracted from the content on each device, so it isn't synced
+	db._batch(
+		texts
+			.into_iter()
+			.map(|(object_id, cas_id, source, text)| {
+				db.object_text().upsert(
+					object_text::object_id::equals(object_id),
+					object_text::create(
+						text.clone(),
+						source as i32,
+						object::id::equals(object_id),
+						vec![object_text::cas_id::set(cas_id.clone())],
+					),
+					vec![
+						object_text::text::set(text),
+						object_text::source::set(source as i32),
+						object_text::cas_id::set(cas_id),
+					],
+				)
+			})
+			.collect::<Vec<_>>(),
+	)
+	.await
+	.map(|saved| saved.len() as u64)
+	.map_err(Into::into)
+}
+
+/// Turns user input into an FTS5 query matching every word, in any order.
+///
+/// Each word is quoted, so characters that mean something to FTS5 (like `-`, `:` or `*`) are
+/// searched literally instead of failing the whole query, except for a trailing `*` which keeps
+/// working as a prefix search.
+#[must_use]
+pub fn fts_query(input: &str) -> Option<String> {
+	let terms = input
+		.split_whitespace()
+		.filter_map(|word| {
+			let (word, prefix) = word
+				.strip_suffix('*')
+				.map_or((word, false), |word| (word, true));
+
+			(!word.is_empty()).then(|| {
+				format!(
+					"\"{}\"{}",
+					word.replace('"', "\"\""),
+					if prefix { "*" } else { "" }
+				)
+			})
+		})
+		.collect::<Vec<_>>();
+
+	(!terms.is_empty()).then(|| terms.join(" "))
+}
+
+#[derive(Deserialize)]
+struct TextMatch {
+	object_id: object::id::Type,
+}
+
+/// Objects whose extracted text matches all words of `input`, best matches first
+pub async fn search(
+	input: &str,
+	take: i64,
+	db: &PrismaClient,
+) -> Result<Vec<object::id::Type>, QueryError> {
+	let Some(query) = fts_query(input) else {
+		return Ok(vec![]);
+	};
+
+	db._query_raw::<TextMatch>(raw!(
+		"SELECT object_text.object_id AS object_id
+		FROM object_text_fts
+		INNER JOIN object_text ON object_text.id = object_text_fts.rowid
+		WHERE object_text_fts MATCH {}
+		ORDER BY rank
+		LIMIT {}",
+		PrismaValue::String(query),
+		PrismaValue::BigInt(take)
+	))
+	.exec()
+	.await
+	.map(|matches| matches.into_iter().map(|m| m.object_id).collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn words_are_quoted() {
+		assert_eq!(
+			fts_query("invoice  2024-03"),
+			Some("\"invoice\" \"2024-03\"".to_string())
+		);
+		assert_eq!(
+			fts_query("say \"hi\""),
+			Some("\"say\" \"\"\"hi\"\"\"".to_string())
+		);
+	}
+
+	#[test]
+	fn trailing_star_is_a_prefix_search() {
+		assert_eq!(fts_query("recei*"), Some("\"recei\"*".to_string()));
+		assert_eq!(fts_query("*"), None);
+	}
+
+	#[test]
+	fn blank_input_has_no_query() {
+		assert_eq!(fts_query("   "), None);
+	}
+
+	#[test]
+	fn snippet_matches_are_highlighted() {
+		assert_eq!(
+			snippet_parts("…the \u{1}lazy\u{2} dog and \u{1}fox\u{2}"),
+			vec![
+				SnippetPart {
+					text: "…the ".to_string(),
+					highlighted: false,
+				},
+				SnippetPart {
+					text: "lazy".to_string(),
+					highlighted: true,
+				},
+				SnippetPart {
+					text: " dog and ".to_string(),
+					highlighted: false,
+				},
+				SnippetPart {
+					text: "fox".to_string(),
+					highlighted: true,
+				},
+			]
+		);
+		assert!(snippet_parts("").is_empty());
+	}
+
+	#[test]
+	fn docx_markup_is_stripped() {
+		let xml = r#"<w:body><w:p w:rsidR="1"><w:pPr/><w:r><w:t>Fish &amp; chips</w:t></w:r></w:p><w:p><w:r><w:t xml:space="preserve">for </w:t><w:tab/><w:t>&lt;two&gt;</w:t></w:r></w:p></w:body>"#;
+
+		assert_eq!(normalize_text(&docx_text(xml)), "Fish & chips for <two>");
+	}
+
+	#[test]
+	fn whitespace_is_collapsed() {
+		assert_eq!(normalize_text("  Total:\n\n\t42,00 €  "), "Total: 42,00 €");
+	}
+}
@@ -0,0 +1,3 @@
+pub mod text_recognizer;
+
+pub use text_recognizer::TextRecognizer;
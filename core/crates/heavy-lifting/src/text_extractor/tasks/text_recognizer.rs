@@ -0,0 +1,391 @@
+use crate::{
+	text_extractor::{
+		self, docx_text, normalize_text, save, TextSource, MAX_PDF_PAGES, MAX_TEXT_BYTES,
+	},
+	Error,
+};
+
+use sd_core_file_path_helper::IsolatedFilePathData;
+use sd_core_prisma_helpers::file_path_for_media_processor;
+
+use sd_file_ext::extensions::Extension;
+use sd_images::{format_image, pdf_pages, DynamicImage, PdfPageContent};
+use sd_prisma::prisma::{file_path, location, object, PrismaClient};
+use sd_task_system::{
+	check_interruption, ExecStatus, Interrupter, IntoAnyTaskOutput, SerializableTask, Task, TaskId,
+};
+
+use std::{
+	collections::HashSet,
+	fs::File,
+	io::{Read, Seek},
+	mem,
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::Duration,
+};
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::{task::spawn_blocking, time::Instant};
+use tracing::{error, trace};
+use zip::ZipArchive;
+
+/// Bytes looked at to tell binary files apart from text, as git does
+const BINARY_CHECK_BYTES: usize = 8 * 1024;
+
+/// Files whose name has one of these extensions are read as plain text, the ones with the others
+/// are documents or images
+static PLAIN_TEXT_EXTENSIONS: Lazy<HashSet<String>> = Lazy::new(|| {
+	text_extractor::AVAILABLE_EXTENSIONS
+		.iter()
+		.filter(|ext| {
+			matches!(
+				ext,
+				Extension::Text(_) | Extension::Code(_) | Extension::Config(_)
+			)
+		})
+		.map(|ext| ext.to_string().to_lowercase())
+		.collect()
+});
+
+/// Extracts the text of a batch of files, reading plain text and source code as is, the text
+/// embedded in PDFs and DOCX documents, and recognizing it with OCR in images and scanned pages
+#[derive(Debug)]
+pub struct TextRecognizer {
+	id: TaskId,
+	files: Vec<(object::id::Type, Option<String>, PathBuf)>,
+	languages: Arc<String>,
+	texts: Vec<(object::id::Type, Option<String>, TextSource, String)>,
+	db: Arc<PrismaClient>,
+	output: Output,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Output {
+	pub extracted: u64,
+	/// Pages and images that went through OCR, as it's way slower than reading embedded text
+	pub recognized_pages: u64,
+	pub skipped: u64,
+	pub extraction_time: Duration,
+	pub db_write_time: Duration,
+	pub errors: Vec<crate::NonCriticalError>,
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize, Type)]
+pub enum NonCriticalError {
+	#[error("failed to extract isolated file path data from file path <id='{0}'>: {1}")]
+	FailedToExtractIsolatedFilePathData(file_path::id::Type, String),
+	#[error("file path missing object id: <file_path_id='{0}'>")]
+	FilePathMissingObjectId(file_path::id::Type),
+	#[error("failed to read document pages <path='{}'>: {1}", .0.display())]
+	ReadDocument(PathBuf, String),
+	#[error("failed to read text file <path='{}'>: {1}", .0.display())]
+	ReadText(PathBuf, String),
+	#[error("failed to decode image to recognize text <path='{}'>: {1}", .0.display())]
+	FormatImage(PathBuf, String),
+	#[error("failed to recognize text <path='{}'>: {1}", .0.display())]
+	Recognize(PathBuf, String),
+	#[error("processing thread panicked while extracting text <path='{}'>: {1}", .0.display())]
+	PanicWhileExtracting(PathBuf, String),
+}
+
+impl TextRecognizer {
+	#[must_use]
+	pub fn new(
+		file_paths: &[file_path_for_media_processor::Data],
+		(location_id, location_path): (location::id::Type, &Path),
+		languages: Arc<String>,
+		db: Arc<PrismaClient>,
+	) -> Self {
+		let mut errors = Vec::new();
+		let mut seen_objects = HashSet::with_capacity(file_paths.len());
+
+		Self {
+			id: TaskId::new_v4(),
+			files: file_paths
+				.iter()
+				.filter_map(|file_path| {
+					let Some(object_id) = file_path.object_id else {
+						errors.push(
+							text_extractor::NonCriticalError::from(
+								NonCriticalError::FilePathMissingObjectId(file_path.id),
+							)
+							.into(),
+						);
+						return None;
+					};
+
+					// Many file paths can point to the same object, we extract its text just once
+					if !seen_objects.insert(object_id) {
+						return None;
+					}
+
+					IsolatedFilePathData::try_from((location_id, file_path))
+						.map_err(|e| {
+							errors.push(
+								text_extractor::NonCriticalError::from(
+									NonCriticalError::FailedToExtractIsolatedFilePathData(
+										file_path.id,
+										e.to_string(),
+									),
+								)
+								.into(),
+							);
+						})
+						.ok()
+						.map(|iso_file_path| {
+							(
+								object_id,
+								file_path.cas_id.clone(),
+								location_path.join(iso_file_path),
+							)
+						})
+				})
+				.collect(),
+			languages,
+			texts: Vec::new(),
+			db,
+			output: Output {
+				errors,
+				..Default::default()
+			},
+		}
+	}
+}
+
+#[async_trait::async_trait]
+impl Task<Error> for TextRecognizer {
+	fn id(&self) -> TaskId {
+		self.id
+	}
+
+	async fn run(&mut self, interrupter: &Interrupter) -> Result<ExecStatus, Error> {
+		let Self {
+			files,
+			languages,
+			texts,
+			db,
+			output:
+				Output {
+					extracted,
+					recognized_pages,
+					skipped,
+					extraction_time,
+					db_write_time,
+					errors,
+				},
+			..
+		} = self;
+
+		let start = Instant::now();
+
+		// Files are popped, so a resumed task only processes the remaining ones
+		while let Some((object_id, cas_id, path)) = files.pop() {
+			match extract_text(path, Arc::clone(languages)).await {
+				Ok((source, text, recognized)) => {
+					texts.push((object_id, cas_id, source, text));
+					*recognized_pages += recognized;
+				}
+				Err(e) => {
+					error!("{e:#?}");
+					errors.push(text_extractor::NonCriticalError::from(e).into());
+					*skipped += 1;
+				}
+			}
+
+			check_interruption!(interrupter, start, extraction_time);
+		}
+
+		*extraction_time += start.elapsed();
+
+		let db_write_start = Instant::now();
+		*extracted = save(mem::take(texts), db).await?;
+		*db_write_time = db_write_start.elapsed();
+
+		Ok(ExecStatus::Done(mem::take(&mut self.output).into_output()))
+	}
+}
+
+/// Returns the text of a file, where it came from and how many pages went through OCR
+async fn extract_text(
+	path: PathBuf,
+	languages: Arc<String>,
+) -> Result<(TextSource, String, u64), NonCriticalError> {
+	spawn_blocking({
+		let path = path.clone();
+
+		move || {
+			let extension = path
+				.extension()
+				.map(|extension| extension.to_string_lossy().to_lowercase())
+				.unwrap_or_default();
+
+			match extension.as_str() {
+				"pdf" => read_pdf(&path, &languages),
+				"docx" => read_docx(&path)
+					.map(|text| (TextSource::Embedded, normalize_text(&text), 0))
+					.map_err(|e| NonCriticalError::ReadDocument(path.clone(), e)),
+				_ if PLAIN_TEXT_EXTENSIONS.contains(&extension) => read_plain_text(&path)
+					.map(|text| (TextSource::Embedded, normalize_text(&text), 0))
+					.map_err(|e| NonCriticalError::ReadText(path.clone(), e.to_string())),
+				_ => {
+					let img = format_image(&path)
+						.map_err(|e| NonCriticalError::FormatImage(path.clone(), e.to_string()))?;
+
+					recognize(&img, &languages, &path)
+						.map(|text| (TextSource::Ocr, normalize_text(&text), 1))
+				}
+			}
+		}
+	})
+	.await
+	.map_err(|e| NonCriticalError::PanicWhileExtracting(path, e.to_string()))?
+}
+
+fn read_pdf(path: &Path, languages: &str) -> Result<(TextSource, String, u64), NonCriticalError> {
+	let mut source = TextSource::Embedded;
+	let mut recognized = 0;
+	let mut text = String::new();
+
+	for page in pdf_pages(path, MAX_PDF_PAGES)
+		.map_err(|e| NonCriticalError::ReadDocument(path.to_path_buf(), e.to_string()))?
+	{
+		match page {
+			PdfPageContent::Text(page_text) => text.push_str(&page_text),
+			PdfPageContent::Scan(img) => {
+				trace!("Recognizing text of a scanned page of {}", path.display());
+				text.push_str(&recognize(&img, languages, path)?);
+				source = TextSource::Ocr;
+				recognized += 1;
+			}
+		}
+
+		text.push('\n');
+	}
+
+	Ok((source, normalize_text(&text), recognized))
+}
+
+/// DOCX documents are zip files, with the text of the body in `word/document.xml`
+fn read_docx(path: &Path) -> Result<String, String> {
+	let mut archive = File::open(path)
+		.map_err(|e| e.to_string())
+		.and_then(|file| ZipArchive::new(file).map_err(|e| e.to_string()))?;
+
+	let mut xml = String::new();
+	archive
+		.by_name("word/document.xml")
+		.map_err(|e| e.to_string())?
+		.read_to_string(&mut xml)
+		.map_err(|e| e.to_string())?;
+
+	Ok(docx_text(&xml))
+}
+
+/// Reads the beginning of a text file, giving nothing for binary files that happen to have a
+/// text extension, like compiled `.js` bundles with embedded data or mislabeled files
+fn read_plain_text(path: &Path) -> std::io::Result<String> {
+	let mut file = File::open(path)?;
+
+	let mut head = vec![0; BINARY_CHECK_BYTES];
+	let read = file.read(&mut head)?;
+	if head[..read].contains(&0) {
+		trace!(
+			"Skipping binary file with a text extension: {}",
+			path.display()
+		);
+		return Ok(String::new());
+	}
+
+	file.rewind()?;
+
+	let mut bytes = Vec::new();
+	file.take(MAX_TEXT_BYTES).read_to_end(&mut bytes)?;
+
+	// Text files carry no encoding, so invalid UTF-8 is replaced instead of failing the file
+	Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+#[cfg(feature = "ocr")]
+fn recognize(img: &DynamicImage, languages: &str, path: &Path) -> Result<String, NonCriticalError> {
+	// Tesseract binarizes images anyway, grayscale is all it needs
+	let img = img.to_luma8();
+
+	let (Ok(width), Ok(height)) = (i32::try_from(img.width()), i32::try_from(img.height())) else {
+		return Err(NonCriticalError::Recognize(
+			path.to_path_buf(),
+			"image is too large".to_string(),
+		));
+	};
+
+	tesseract::ocr_from_frame(img.as_raw(), width, height, 1, width, languages)
+		.map_err(|e| NonCriticalError::Recognize(path.to_path_buf(), e.to_string()))
+}
+
+// Images aren't dispatched without an OCR engine, but scanned pages of PDFs can't be read either
+#[cfg(not(feature = "ocr"))]
+fn recognize(_: &DynamicImage, _: &str, path: &Path) -> Result<String, NonCriticalError> {
+	Err(NonCriticalError::Recognize(
+		path.to_path_buf(),
+		"OCR is disabled".to_string(),
+	))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SaveState {
+	id: TaskId,
+	files: Vec<(object::id::Type, Option<String>, PathBuf)>,
+	languages: Arc<String>,
+	texts: Vec<(object::id::Type, Option<String>, TextSource, String)>,
+	output: Output,
+}
+
+impl SerializableTask<Error> for TextRecognizer {
+	type SerializeError = rmp_serde::encode::Error;
+
+	type DeserializeError = rmp_serde::decode::Error;
+
+	type DeserializeCtx = Arc<PrismaClient>;
+
+	async fn serialize(self) -> Result<Vec<u8>, Self::SerializeError> {
+		let Self {
+			id,
+			files,
+			languages,
+			texts,
+			output,
+			..
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState {
+			id,
+			files,
+			languages,
+			texts,
+			output,
+		})
+	}
+
+	async fn deserialize(
+		data: &[u8],
+		db: Self::DeserializeCtx,
+	) -> Result<Self, Self::DeserializeError> {
+		rmp_serde::from_slice(data).map(
+			|SaveState {
+			     id,
+			     files,
+			     languages,
+			     texts,
+			     output,
+			 }| Self {
+				id,
+				files,
+				languages,
+				texts,
+				db,
+				output,
+			},
+		)
+	}
+}
@@ -0,0 +1,501 @@
+use crate::{
+	job_system::{
+		job::{Job, JobReturn, JobTaskDispatcher, ReturnStatus},
+		report::ReportOutputMetadata,
+		utils::{cancel_pending_tasks, location_concurrency_key},
+		SerializableJob, SerializedTasks,
+	},
+	transcriber,
+	utils::sub_path::{self, maybe_get_iso_file_path_from_sub_path},
+	Error, JobName, NonCriticalError, OuterContext, ProgressUpdate,
+};
+
+use sd_core_file_path_helper::IsolatedFilePathData;
+use sd_core_prisma_helpers::file_path_for_media_processor;
+
+use sd_prisma::prisma::{location, PrismaClient};
+use sd_task_system::{
+	IntoTask, SerializableTask, Task, TaskDispatcher, TaskHandle, TaskOutput, TaskStatus,
+};
+use sd_utils::db::maybe_missing;
+
+use std::{
+	collections::HashMap,
+	hash::{Hash, Hasher},
+	mem,
+	path::PathBuf,
+	sync::Arc,
+	time::Duration,
+};
+
+use futures::{stream::FuturesUnordered, StreamExt};
+use futures_concurrency::future::TryJoin;
+use itertools::Itertools;
+use prisma_client_rust::{raw, PrismaValue};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{debug, warn};
+
+use super::{
+	tasks::{speech_recognizer, SpeechRecognizer},
+	LoadedWhisper, WhisperModel, AVAILABLE_EXTENSIONS, BATCH_SIZE,
+};
+
+/// Transcribes the speech of the audio and video files in a location, or in a directory of it, so
+/// they can be read along and found by what is said in them
+#[derive(Debug)]
+pub struct Transcriber {
+	location: Arc<location::Data>,
+	location_path: Arc<PathBuf>,
+	sub_path: Option<PathBuf>,
+	model_kind: WhisperModel,
+	regenerate: bool,
+
+	// Loaded when the job runs, as a model can't be serialized
+	model: Option<Arc<LoadedWhisper>>,
+
+	metadata: Metadata,
+
+	errors: Vec<NonCriticalError>,
+
+	pending_tasks_on_resume: Vec<TaskHandle<Error>>,
+	tasks_for_shutdown: Vec<Box<dyn Task<Error>>>,
+}
+
+impl Hash for Transcriber {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.location.id.hash(state);
+		if let Some(ref sub_path) = self.sub_path {
+			sub_path.hash(state);
+		}
+	}
+}
+
+impl Job for Transcriber {
+	const NAME: JobName = JobName::Transcriber;
+
+	async fn resume_tasks(
+		&mut self,
+		dispatcher: &JobTaskDispatcher,
+		ctx: &impl OuterContext,
+		SerializedTasks(serialized_tasks): SerializedTasks,
+	) -> Result<(), Error> {
+		let model = self.load_model(ctx).await?;
+
+		self.pending_tasks_on_resume = dispatcher
+			.with_concurrency_key(location_concurrency_key(ctx.id(), self.location.id))
+			.dispatch_many_boxed(
+				rmp_serde::from_slice::<Vec<Vec<u8>>>(&serialized_tasks)
+					.map_err(transcriber::Error::from)?
+					.into_iter()
+					.map(|task_bytes| {
+						let model = Arc::clone(&model);
+						async move {
+							SpeechRecognizer::deserialize(
+								&task_bytes,
+								(model, Arc::clone(ctx.db())),
+							)
+							.await
+							.map(IntoTask::into_task)
+						}
+					})
+					.collect::<Vec<_>>()
+					.try_join()
+					.await
+					.map_err(transcriber::Error::from)?,
+			)
+			.await;
+
+		Ok(())
+	}
+
+	async fn run<Ctx: OuterContext>(
+		mut self,
+		dispatcher: JobTaskDispatcher,
+		ctx: Ctx,
+	) -> Result<ReturnStatus, Error> {
+		let dispatcher =
+			dispatcher.with_concurrency_key(location_concurrency_key(ctx.id(), self.location.id));
+
+		let mut pending_running_tasks = FuturesUnordered::new();
+
+		self.init_or_resume(&mut pending_running_tasks, &ctx, &dispatcher)
+			.await?;
+
+		while let Some(task) = pending_running_tasks.next().await {
+			match task {
+				Ok(TaskStatus::Done((_, TaskOutput::Out(out)))) => {
+					self.process_recognizer_output(
+						*out.downcast::<speech_recognizer::Output>()
+							.expect("the transcriber job only dispatches speech recognizer tasks"),
+						&ctx,
+					);
+				}
+
+				Ok(TaskStatus::Done((task_id, TaskOutput::Empty))) => {
+					warn!("Task <id='{task_id}'> returned an empty output");
+				}
+
+				Ok(TaskStatus::Shutdown(task)) => {
+					self.tasks_for_shutdown.push(task);
+				}
+
+				Ok(TaskStatus::Error(e)) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e);
+				}
+
+				Ok(TaskStatus::Canceled | TaskStatus::ForcedAbortion) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Ok(ReturnStatus::Canceled);
+				}
+
+				Err(e) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e.into());
+				}
+			}
+		}
+
+		if !self.tasks_for_shutdown.is_empty() {
+			return Ok(ReturnStatus::Shutdown(
+				SerializableJob::<Ctx>::serialize(self).await,
+			));
+		}
+
+		if self.metadata.transcribed > 0 {
+			ctx.invalidate_query("files.getTranscript");
+		}
+
+		let Self {
+			metadata, errors, ..
+		} = self;
+
+		Ok(ReturnStatus::Completed(
+			JobReturn::builder()
+				.with_metadata(metadata)
+				.with_non_critical_errors(errors)
+				.build(),
+		))
+	}
+}
+
+impl Transcriber {
+	pub fn new(
+		location: location::Data,
+		sub_path: Option<PathBuf>,
+	) -> Result<Self, transcriber::Error> {
+		Ok(Self {
+			location_path: maybe_missing(&location.path, "location.path")
+				.map(PathBuf::from)
+				.map(Arc::new)?,
+			location: Arc::new(location),
+			sub_path,
+			model_kind: WhisperModel::default(),
+			regenerate: false,
+			model: None,
+			metadata: Metadata::default(),
+			errors: Vec::new(),
+			pending_tasks_on_resume: Vec::new(),
+			tasks_for_shutdown: Vec::new(),
+		})
+	}
+
+	/// Transcribes with another model, downloading it if needed. Objects transcribed by other
+	/// models are transcribed again
+	#[must_use]
+	pub const fn with_model(mut self, model_kind: WhisperModel) -> Self {
+		self.model_kind = model_kind;
+		self
+	}
+
+	/// Transcribes again the objects that were already transcribed by the same model
+	#[must_use]
+	pub const fn with_regenerate(mut self, regenerate: bool) -> Self {
+		self.regenerate = regenerate;
+		self
+	}
+
+	async fn load_model(
+		&mut self,
+		ctx: &impl OuterContext,
+	) -> Result<Arc<LoadedWhisper>, transcriber::Error> {
+		if let Some(model) = &self.model {
+			return Ok(Arc::clone(model));
+		}
+
+		let model = Arc::new(LoadedWhisper::load(self.model_kind, ctx.get_data_directory()).await?);
+
+		self.model = Some(Arc::clone(&model));
+
+		Ok(model)
+	}
+
+	async fn init_or_resume(
+		&mut self,
+		pending_running_tasks: &mut FuturesUnordered<TaskHandle<Error>>,
+		ctx: &impl OuterContext,
+		dispatcher: &JobTaskDispatcher,
+	) -> Result<(), transcriber::Error> {
+		// Resumed tasks were already dispatched by `resume_tasks`
+		if !self.pending_tasks_on_resume.is_empty() {
+			pending_running_tasks.extend(mem::take(&mut self.pending_tasks_on_resume));
+
+			return Ok(());
+		}
+
+		let db = ctx.db();
+		let location_id = self.location.id;
+		let location_path = &*self.location_path;
+
+		let iso_file_path =
+			maybe_get_iso_file_path_from_sub_path(location_id, &self.sub_path, location_path, db)
+				.await?
+				.map_or_else(
+					|| {
+						IsolatedFilePathData::new(location_id, location_path, location_path, true)
+							.map_err(sub_path::Error::from)
+					},
+					Ok,
+				)?;
+
+		let file_paths = get_files_to_transcribe(
+			db,
+			&iso_file_path,
+			(!self.regenerate).then(|| self.model_kind.id()),
+		)
+		.await?;
+
+		self.metadata.total_files = file_paths.len() as u64;
+
+		// Nothing to transcribe, so the model isn't even downloaded
+		if file_paths.is_empty() {
+			return Ok(());
+		}
+
+		ctx.progress_msg(format!("Loading Whisper model {}", self.model_kind.id()));
+
+		let model = self.load_model(ctx).await?;
+
+		debug!(
+			"Transcribing files in location {location_id} at directory \"{iso_file_path}\" with {}",
+			model.id()
+		);
+
+		pending_running_tasks.extend(
+			dispatcher
+				.dispatch_many(
+					file_paths
+						.into_iter()
+						.chunks(BATCH_SIZE)
+						.into_iter()
+						.map(|chunk| {
+							SpeechRecognizer::new(
+								&chunk.collect::<Vec<_>>(),
+								(location_id, location_path),
+								Arc::clone(&model),
+								Arc::clone(db),
+							)
+						})
+						.collect::<Vec<_>>(),
+				)
+				.await,
+		);
+
+		ctx.progress(vec![
+			ProgressUpdate::TaskCount(self.metadata.total_files),
+			ProgressUpdate::Message(format!(
+				"Transcribing {} files in {} chunks",
+				self.metadata.total_files,
+				pending_running_tasks.len()
+			)),
+		]);
+
+		Ok(())
+	}
+
+	fn process_recognizer_output(
+		&mut self,
+		speech_recognizer::Output {
+			transcribed,
+			segments,
+			audio_duration,
+			skipped,
+			transcription_time,
+			db_write_time,
+			errors,
+		}: speech_recognizer::Output,
+		ctx: &impl OuterContext,
+	) {
+		self.metadata.transcribed += transcribed;
+		self.metadata.segments += segments;
+		self.metadata.audio_duration += audio_duration;
+		self.metadata.skipped += skipped;
+		self.metadata.transcription_time += transcription_time;
+		self.metadata.db_write_time += db_write_time;
+
+		self.errors.extend(errors);
+
+		ctx.progress(vec![ProgressUpdate::CompletedTaskCount(
+			self.metadata.transcribed + self.metadata.skipped,
+		)]);
+	}
+}
+
+/// Audio and video files of the directory, one for each object, leaving out the ones already
+/// transcribed by `skip_transcribed_by` when given
+async fn get_files_to_transcribe(
+	db: &PrismaClient,
+	parent_iso_file_path: &IsolatedFilePathData<'_>,
+	skip_transcribed_by: Option<&str>,
+) -> Result<Vec<file_path_for_media_processor::Data>, transcriber::Error> {
+	// FIXME: Had to use format! macro because PCR doesn't support IN with Vec for SQLite
+	// We have no data coming from the user, so this is sql injection safe
+	db._query_raw(raw!(
+		&format!(
+			"SELECT id, materialized_path, is_dir, name, extension, cas_id, object_id
+			FROM file_path
+			WHERE
+				location_id={{}}
+				AND object_id IS NOT NULL
+				AND (in_archive IS NULL OR in_archive = 0)
+				AND LOWER(extension) IN ({})
+				AND materialized_path LIKE {{}}
+				AND object_id NOT IN (SELECT object_id FROM transcript WHERE model = {{}})
+			GROUP BY object_id
+			ORDER BY materialized_path ASC",
+			AVAILABLE_EXTENSIONS
+				.iter()
+				.map(|ext| format!("LOWER('{ext}')"))
+				.collect::<Vec<_>>()
+				.join(","),
+		),
+		PrismaValue::Int(parent_iso_file_path.location_id()),
+		PrismaValue::String(format!(
+			"{}%",
+			parent_iso_file_path
+				.materialized_path_for_children()
+				.expect("sub path iso_file_path must be a directory")
+		)),
+		// No model is named like that, so nothing is left out when regenerating
+		PrismaValue::String(skip_transcribed_by.unwrap_or_default().to_string())
+	))
+	.exec()
+	.await
+	.map_err(Into::into)
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Metadata {
+	total_files: u64,
+	transcribed: u64,
+	segments: u64,
+	audio_duration: Duration,
+	skipped: u64,
+	transcription_time: Duration,
+	db_write_time: Duration,
+}
+
+impl From<Metadata> for ReportOutputMetadata {
+	fn from(value: Metadata) -> Self {
+		Self::Metrics(HashMap::from([
+			("total_files".into(), json!(value.total_files)),
+			("transcribed_files".into(), json!(value.transcribed)),
+			("transcript_segments".into(), json!(value.segments)),
+			("audio_duration".into(), json!(value.audio_duration)),
+			("skipped_files".into(), json!(value.skipped)),
+			("transcription_time".into(), json!(value.transcription_time)),
+			("db_write_time".into(), json!(value.db_write_time)),
+		]))
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+struct SaveState {
+	location: Arc<location::Data>,
+	location_path: Arc<PathBuf>,
+	sub_path: Option<PathBuf>,
+	model_kind: WhisperModel,
+	regenerate: bool,
+
+	metadata: Metadata,
+
+	errors: Vec<NonCriticalError>,
+
+	tasks_for_shutdown_bytes: Option<SerializedTasks>,
+}
+
+impl<Ctx: OuterContext> SerializableJob<Ctx> for Transcriber {
+	async fn serialize(self) -> Result<Option<Vec<u8>>, rmp_serde::encode::Error> {
+		let Self {
+			location,
+			location_path,
+			sub_path,
+			model_kind,
+			regenerate,
+			metadata,
+			errors,
+			tasks_for_shutdown,
+			..
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState {
+			location,
+			location_path,
+			sub_path,
+			model_kind,
+			regenerate,
+			metadata,
+			errors,
+			tasks_for_shutdown_bytes: Some(SerializedTasks(rmp_serde::to_vec_named(
+				&tasks_for_shutdown
+					.into_iter()
+					.map(|task| async move {
+						task.downcast::<SpeechRecognizer>()
+							.expect("the transcriber job only dispatches speech recognizer tasks")
+							.serialize()
+							.await
+					})
+					.collect::<Vec<_>>()
+					.try_join()
+					.await?,
+			)?)),
+		})
+		.map(Some)
+	}
+
+	async fn deserialize(
+		serialized_job: &[u8],
+		_: &Ctx,
+	) -> Result<Option<(Self, Option<SerializedTasks>)>, rmp_serde::decode::Error> {
+		let SaveState {
+			location,
+			location_path,
+			sub_path,
+			model_kind,
+			regenerate,
+			metadata,
+			errors,
+			tasks_for_shutdown_bytes,
+		} = rmp_serde::from_slice::<SaveState>(serialized_job)?;
+
+		Ok(Some((
+			Self {
+				location,
+				location_path,
+				sub_path,
+				model_kind,
+				regenerate,
+				model: None,
+				metadata,
+				errors,
+				pending_tasks_on_resume: Vec::new(),
+				tasks_for_shutdown: Vec::new(),
+			},
+			tasks_for_shutdown_bytes,
+		)))
+	}
+}
@@ -0,0 +1,240 @@
+use crate::{
+	media_processor::{can_extract_for_audio, can_extract_for_video},
+	text_extractor::TextSource,
+	utils::sub_path,
+};
+
+use sd_core_file_path_helper::FilePathError;
+
+use sd_file_ext::extensions::{Extension, ALL_AUDIO_EXTENSIONS, ALL_VIDEO_EXTENSIONS};
+use sd_prisma::prisma::{object, object_text, transcript, transcript_segment, PrismaClient};
+use sd_utils::{db::MissingFieldError, error::FileIOError};
+
+use std::time::Duration;
+
+use chrono::Utc;
+use once_cell::sync::Lazy;
+use prisma_client_rust::QueryError;
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use whisper_rs::WhisperError;
+
+pub mod job;
+mod model;
+mod tasks;
+
+pub use job::Transcriber;
+pub use model::{LoadedWhisper, WhisperModel};
+pub use tasks::speech_recognizer;
+
+// Transcribing takes about as long as listening on slower machines, so each task gets a single file
+const BATCH_SIZE: usize = 1;
+
+/// Audio transcribed at most, longer media only have their beginning transcribed as the whole
+/// decoded audio is kept in memory
+const MAX_TRANSCRIBED_DURATION: Duration = Duration::from_secs(60 * 60);
+
+pub static AVAILABLE_EXTENSIONS: Lazy<Vec<Extension>> = Lazy::new(|| {
+	ALL_AUDIO_EXTENSIONS
+		.iter()
+		.copied()
+		.filter(|&ext| can_extract_for_audio(ext))
+		.map(Extension::Audio)
+		.chain(
+			ALL_VIDEO_EXTENSIONS
+				.iter()
+				.copied()
+				.filter(|&ext| can_extract_for_video(ext))
+				.map(Extension::Video),
+		)
+		.collect()
+});
+
+/// Speech of a file, split in segments of a sentence or so as the model transcribed them
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Transcription {
+	/// ISO 639-1 code of the spoken language, detected from the first seconds of speech
+	pub language: Option<String>,
+	pub segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct Segment {
+	/// Milliseconds from the start of the media
+	pub start_ms: i32,
+	pub end_ms: i32,
+	pub text: String,
+}
+
+impl Transcription {
+	/// Whole text of the transcription, as it's indexed for full-text search
+	#[must_use]
+	pub fn text(&self) -> String {
+		self.segments
+			.iter()
+			.map(|segment| segment.text.as_str())
+			.collect::<Vec<_>>()
+			.join(" ")
+	}
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("missing field on database: {0}")]
+	MissingField(#[from] MissingFieldError),
+	#[error("database error: {0}")]
+	Database(#[from] QueryError),
+	#[error("failed to deserialized stored tasks for job resume: {0}")]
+	DeserializeTasks(#[from] rmp_serde::decode::Error),
+	#[error("failed to download the Whisper model: {0}")]
+	DownloadModel(#[from] reqwest::Error),
+	#[error("failed to download the Whisper model, server responded with: {0}")]
+	DownloadModelStatus(reqwest::StatusCode),
+	#[error("failed to load the Whisper model: {0}")]
+	LoadModel(#[from] WhisperError),
+
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+	#[error(transparent)]
+	FilePathError(#[from] FilePathError),
+	#[error(transparent)]
+	SubPath(#[from] sub_path::Error),
+}
+
+impl From<Error> for rspc::Error {
+	fn from(err: Error) -> Self {
+		match err {
+			Error::SubPath(sub_path_err) => sub_path_err.into(),
+
+			_ => Self::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize, Type)]
+pub enum NonCriticalError {
+	#[error(transparent)]
+	SpeechRecognizer(#[from] speech_recognizer::NonCriticalError),
+}
+
+/// Replaces the transcripts of the objects, returning how many segments were saved
+async fn save(
+	transcriptions: Vec<(object::id::Type, Transcription)>,
+	model: &str,
+	db: &PrismaClient,
+) -> Result<u64, Error> {
+	let mut saved_segments = 0;
+
+	// Transcripts come from the content on each device, so they aren't synced
+	for (object_id, transcription) in transcriptions {
+		let text = transcription.text();
+		let Transcription { language, segments } = transcription;
+
+		let transcript = db
+			.transcript()
+			.upsert(
+				transcript::object_id::equals(object_id),
+				transcript::create(
+					model.to_string(),
+					object::id::equals(object_id),
+					vec![transcript::language::set(language.clone())],
+				),
+				vec![
+					transcript::model::set(model.to_string()),
+					transcript::language::set(language),
+					transcript::date_created::set(Utc::now().into()),
+				],
+			)
+			.select(transcript::select!({ id }))
+			.exec()
+			.await?;
+
+		saved_segments += segments.len() as u64;
+
+		db._batch((
+			db.transcript_segment()
+				.delete_many(vec![transcript_segment::transcript_id::equals(
+					transcript.id,
+				)]),
+			db.transcript_segment().create_many(
+				segments
+					.into_iter()
+					.map(
+						|Segment {
+						     start_ms,
+						     end_ms,
+						     text,
+						 }| {
+							transcript_segment::create_unchecked(
+								start_ms,
+								end_ms,
+								text,
+								transcript.id,
+								vec![],
+							)
+						},
+					)
+					.collect(),
+			),
+		))
+		.await?;
+
+		// Media only gets text from its speech, so a silent one has nothing to be found by
+		if text.is_empty() {
+			db.object_text()
+				.delete_many(vec![
+					object_text::object_id::equals(object_id),
+					object_text::source::equals(TextSource::Transcript as i32),
+				])
+				.exec()
+				.await?;
+		} else {
+			db.object_text()
+				.upsert(
+					object_text::object_id::equals(object_id),
+					object_text::create(
+						text.clone(),
+						TextSource::Transcript as i32,
+						object::id::equals(object_id),
+						vec![],
+					),
+					vec![
+						object_text::text::set(text),
+						object_text::source::set(TextSource::Transcript as i32),
+					],
+				)
+				.exec()
+				.await?;
+		}
+	}
+
+	Ok(saved_segments)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn text_joins_segments() {
+		let transcription = Transcription {
+			language: Some("en".to_string()),
+			segments: vec![
+				Segment {
+					start_ms: 0,
+					end_ms: 1_500,
+					text: "Hello there.".to_string(),
+				},
+				Segment {
+					start_ms: 1_500,
+					end_ms: 3_200,
+					text: "General Kenobi!".to_string(),
+				},
+			],
+		};
+
+		assert_eq!(transcription.text(), "Hello there. General Kenobi!");
+		assert_eq!(Transcription::default().text(), "");
+	}
+}
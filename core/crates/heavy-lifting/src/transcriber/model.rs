@@ -0,0 +1,231 @@
+use sd_utils::error::FileIOError;
+
+use std::path::{Path, PathBuf};
+
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::{
+	fs,
+	io::{self, AsyncWriteExt},
+};
+use tracing::info;
+use whisper_rs::{
+	get_lang_str, FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters,
+	WhisperError,
+};
+
+use super::{Error, Segment, Transcription};
+
+/// Where the ggml builds of the Whisper models are downloaded from
+const MODELS_URL: &str = "https://huggingface.co/ggerganov/whisper.cpp/resolve/main";
+
+/// Directory inside the data directory where models are downloaded to
+const MODELS_DIR_NAME: &str = "models";
+
+/// Multilingual Whisper models, bigger ones make fewer mistakes but are way slower
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum WhisperModel {
+	Tiny,
+	#[default]
+	Base,
+	Small,
+	Medium,
+	LargeV3Turbo,
+}
+
+impl WhisperModel {
+	/// Name of the model file, also stored with the transcripts to know which model made them
+	#[must_use]
+	pub const fn id(self) -> &'static str {
+		match self {
+			Self::Tiny => "ggml-tiny",
+			Self::Base => "ggml-base",
+			Self::Small => "ggml-small",
+			Self::Medium => "ggml-medium",
+			Self::LargeV3Turbo => "ggml-large-v3-turbo",
+		}
+	}
+
+	fn url(self) -> String {
+		format!("{MODELS_URL}/{}.bin", self.id())
+	}
+}
+
+/// A Whisper model loaded by whisper.cpp, which can transcribe many files at once as each
+/// transcription gets its own state
+pub struct LoadedWhisper {
+	model: WhisperModel,
+	context: WhisperContext,
+}
+
+impl LoadedWhisper {
+	/// Loads the model from `data_dir`, downloading it first if it isn't there yet.
+	///
+	/// The model runs on the GPU when whisper.cpp was built with a GPU backend and finds a device
+	/// for it, otherwise it falls back to the CPU.
+	pub async fn load(model: WhisperModel, data_dir: impl AsRef<Path>) -> Result<Self, Error> {
+		let model_path = download_model(model, data_dir.as_ref()).await?;
+
+		let buffer = fs::read(&model_path).await.map_err(|e| {
+			FileIOError::from((&model_path, e, "Failed to read the Whisper model file"))
+		})?;
+
+		let mut params = WhisperContextParameters::default();
+		params.use_gpu(true);
+
+		let context = WhisperContext::new_from_buffer_with_params(&buffer, params)?;
+
+		info!("Loaded Whisper model: {}", model.id());
+
+		Ok(Self { model, context })
+	}
+
+	#[must_use]
+	pub const fn id(&self) -> &'static str {
+		self.model.id()
+	}
+
+	/// Transcribes mono samples at [`SPEECH_SAMPLE_RATE`](sd_ffmpeg::SPEECH_SAMPLE_RATE),
+	/// detecting the spoken language on the way
+	pub fn transcribe(&self, samples: &[f32]) -> Result<Transcription, WhisperError> {
+		let mut state = self.context.create_state()?;
+
+		let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
+		params.set_language(Some("auto"));
+		params.set_print_special(false);
+		params.set_print_progress(false);
+		params.set_print_realtime(false);
+		params.set_print_timestamps(false);
+		// Whisper makes up words over silence and music otherwise
+		params.set_suppress_blank(true);
+		params.set_suppress_non_speech_tokens(true);
+
+		state.full(params, samples)?;
+
+		let language = state
+			.full_lang_id_from_state()
+			.ok()
+			.and_then(get_lang_str)
+			.map(ToString::to_string);
+
+		let mut segments = Vec::new();
+		for idx in 0..state.full_n_segments()? {
+			segments.extend(segment(
+				state.full_get_segment_t0(idx)?,
+				state.full_get_segment_t1(idx)?,
+				&state.full_get_segment_text_lossy(idx)?,
+			));
+		}
+
+		Ok(Transcription { language, segments })
+	}
+}
+
+impl std::fmt::Debug for LoadedWhisper {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("LoadedWhisper")
+			.field("model", &self.model)
+			.finish_non_exhaustive()
+	}
+}
+
+/// Whisper gives times in centiseconds, and marks what isn't speech between brackets, like
+/// `[Music]` or `(applause)`, which isn't worth keeping
+fn segment(t0: i64, t1: i64, text: &str) -> Option<Segment> {
+	let text = text.trim();
+
+	let is_annotation = (text.starts_with('[') && text.ends_with(']'))
+		|| (text.starts_with('(') && text.ends_with(')'));
+
+	(!text.is_empty() && !is_annotation).then(|| Segment {
+		start_ms: i32::try_from(t0.saturating_mul(10)).unwrap_or(i32::MAX),
+		end_ms: i32::try_from(t1.saturating_mul(10)).unwrap_or(i32::MAX),
+		text: text.to_string(),
+	})
+}
+
+async fn download_model(model: WhisperModel, data_dir: &Path) -> Result<PathBuf, Error> {
+	let models_dir = data_dir.join(MODELS_DIR_NAME).join("whisper");
+	let model_path = models_dir.join(format!("{}.bin", model.id()));
+
+	match fs::metadata(&model_path).await {
+		Ok(_) => return Ok(model_path),
+		Err(e) if e.kind() != io::ErrorKind::NotFound => {
+			return Err(FileIOError::from((
+				model_path,
+				e,
+				"Failed to get metadata for the Whisper model file",
+			))
+			.into());
+		}
+		Err(_) => {}
+	}
+
+	fs::create_dir_all(&models_dir)
+		.await
+		.map_err(|e| FileIOError::from((&models_dir, e, "Failed to create models directory")))?;
+
+	let url = model.url();
+	info!(
+		"Downloading Whisper model from: {url} to {}",
+		model_path.display()
+	);
+
+	let response = reqwest::get(&url).await?;
+	if !response.status().is_success() {
+		return Err(Error::DownloadModelStatus(response.status()));
+	}
+
+	// Written aside and renamed when complete, so an interrupted download isn't taken as the model
+	let part_path = model_path.with_extension("bin.part");
+
+	let mut file = fs::File::create(&part_path).await.map_err(|e| {
+		FileIOError::from((&part_path, e, "Failed to create the Whisper model file"))
+	})?;
+
+	let mut body = response.bytes_stream();
+	while let Some(chunk) = body.next().await {
+		file.write_all(&chunk?).await.map_err(|e| {
+			FileIOError::from((&part_path, e, "Failed to write the Whisper model file"))
+		})?;
+	}
+
+	file.flush().await.map_err(|e| {
+		FileIOError::from((&part_path, e, "Failed to write the Whisper model file"))
+	})?;
+
+	fs::rename(&part_path, &model_path).await.map_err(|e| {
+		FileIOError::from((
+			&model_path,
+			e,
+			"Failed to move the downloaded Whisper model",
+		))
+	})?;
+
+	Ok(model_path)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn segment_times_are_in_milliseconds() {
+		assert_eq!(
+			segment(150, 420, "  Hello there.  "),
+			Some(Segment {
+				start_ms: 1_500,
+				end_ms: 4_200,
+				text: "Hello there.".to_string(),
+			})
+		);
+	}
+
+	#[test]
+	fn annotations_are_dropped() {
+		assert_eq!(segment(0, 100, "[Music]"), None);
+		assert_eq!(segment(0, 100, " (applause) "), None);
+		assert_eq!(segment(0, 100, "   "), None);
+	}
+}
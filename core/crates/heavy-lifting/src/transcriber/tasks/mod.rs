@@ -0,0 +1,3 @@
+pub mod speech_recognizer;
+
+pub use speech_recognizer::SpeechRecognizer;
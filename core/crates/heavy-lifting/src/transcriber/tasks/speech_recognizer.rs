@@ -0,0 +1,270 @@
+use crate::{
+	transcriber::{self, save, LoadedWhisper, Transcription, MAX_TRANSCRIBED_DURATION},
+	Error,
+};
+
+use sd_core_file_path_helper::IsolatedFilePathData;
+use sd_core_prisma_helpers::file_path_for_media_processor;
+
+use sd_ffmpeg::{to_speech_samples, FFmpegError, SPEECH_SAMPLE_RATE};
+use sd_prisma::prisma::{file_path, location, object, PrismaClient};
+use sd_task_system::{
+	check_interruption, ExecStatus, Interrupter, IntoAnyTaskOutput, SerializableTask, Task, TaskId,
+};
+
+use std::{
+	collections::HashSet,
+	mem,
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::{task::spawn_blocking, time::Instant};
+use tracing::{error, trace};
+
+/// Transcribes the speech of a batch of audio and video files
+#[derive(Debug)]
+pub struct SpeechRecognizer {
+	id: TaskId,
+	files: Vec<(object::id::Type, PathBuf)>,
+	transcriptions: Vec<(object::id::Type, Transcription)>,
+	model: Arc<LoadedWhisper>,
+	db: Arc<PrismaClient>,
+	output: Output,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Output {
+	pub transcribed: u64,
+	pub segments: u64,
+	/// Duration of the audio transcribed, to compare with how long it took
+	pub audio_duration: Duration,
+	pub skipped: u64,
+	pub transcription_time: Duration,
+	pub db_write_time: Duration,
+	pub errors: Vec<crate::NonCriticalError>,
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize, Type)]
+pub enum NonCriticalError {
+	#[error("failed to extract isolated file path data from file path <id='{0}'>: {1}")]
+	FailedToExtractIsolatedFilePathData(file_path::id::Type, String),
+	#[error("file path missing object id: <file_path_id='{0}'>")]
+	FilePathMissingObjectId(file_path::id::Type),
+	#[error("failed to decode audio to transcribe <path='{}'>: {1}", .0.display())]
+	DecodeAudio(PathBuf, String),
+	#[error("failed to transcribe speech <path='{}'>: {1}", .0.display())]
+	Transcribe(PathBuf, String),
+	#[error("processing thread panicked while transcribing <path='{}'>: {1}", .0.display())]
+	PanicWhileTranscribing(PathBuf, String),
+}
+
+impl SpeechRecognizer {
+	#[must_use]
+	pub fn new(
+		file_paths: &[file_path_for_media_processor::Data],
+		(location_id, location_path): (location::id::Type, &Path),
+		model: Arc<LoadedWhisper>,
+		db: Arc<PrismaClient>,
+	) -> Self {
+		let mut errors = Vec::new();
+		let mut seen_objects = HashSet::with_capacity(file_paths.len());
+
+		Self {
+			id: TaskId::new_v4(),
+			files: file_paths
+				.iter()
+				.filter_map(|file_path| {
+					let Some(object_id) = file_path.object_id else {
+						errors.push(
+							transcriber::NonCriticalError::from(
+								NonCriticalError::FilePathMissingObjectId(file_path.id),
+							)
+							.into(),
+						);
+						return None;
+					};
+
+					// Many file paths can point to the same object, we transcribe it just once
+					if !seen_objects.insert(object_id) {
+						return None;
+					}
+
+					IsolatedFilePathData::try_from((location_id, file_path))
+						.map_err(|e| {
+							errors.push(
+								transcriber::NonCriticalError::from(
+									NonCriticalError::FailedToExtractIsolatedFilePathData(
+										file_path.id,
+										e.to_string(),
+									),
+								)
+								.into(),
+							);
+						})
+						.ok()
+						.map(|iso_file_path| (object_id, location_path.join(iso_file_path)))
+				})
+				.collect(),
+			transcriptions: Vec::new(),
+			model,
+			db,
+			output: Output {
+				errors,
+				..Default::default()
+			},
+		}
+	}
+}
+
+#[async_trait::async_trait]
+impl Task<Error> for SpeechRecognizer {
+	fn id(&self) -> TaskId {
+		self.id
+	}
+
+	async fn run(&mut self, interrupter: &Interrupter) -> Result<ExecStatus, Error> {
+		let Self {
+			files,
+			transcriptions,
+			model,
+			db,
+			output:
+				Output {
+					transcribed,
+					segments,
+					audio_duration,
+					skipped,
+					transcription_time,
+					db_write_time,
+					errors,
+				},
+			..
+		} = self;
+
+		let start = Instant::now();
+
+		// Files are popped, so a resumed task only processes the remaining ones
+		while let Some((object_id, path)) = files.pop() {
+			match transcribe(path, Arc::clone(model)).await {
+				Ok((transcription, duration)) => {
+					*segments += transcription.segments.len() as u64;
+					*audio_duration += duration;
+					transcriptions.push((object_id, transcription));
+				}
+				Err(e) => {
+					error!("{e:#?}");
+					errors.push(transcriber::NonCriticalError::from(e).into());
+					*skipped += 1;
+				}
+			}
+
+			check_interruption!(interrupter, start, transcription_time);
+		}
+
+		*transcription_time += start.elapsed();
+
+		*transcribed = transcriptions.len() as u64;
+
+		let db_write_start = Instant::now();
+		save(mem::take(transcriptions), model.id(), db).await?;
+		*db_write_time = db_write_start.elapsed();
+
+		Ok(ExecStatus::Done(mem::take(&mut self.output).into_output()))
+	}
+}
+
+/// Returns the transcription of a file and the duration of the audio transcribed
+async fn transcribe(
+	path: PathBuf,
+	model: Arc<LoadedWhisper>,
+) -> Result<(Transcription, Duration), NonCriticalError> {
+	let samples = match to_speech_samples(&path, MAX_TRANSCRIBED_DURATION).await {
+		Ok(samples) => samples,
+
+		// Videos without an audio track have nothing to transcribe, which is still a transcription
+		// so they aren't tried again
+		Err(
+			sd_ffmpeg::Error::NoAudioSamples
+			| sd_ffmpeg::Error::FFmpeg(FFmpegError::StreamNotFound),
+		) => {
+			trace!("No audio to transcribe in {}", path.display());
+			return Ok((Transcription::default(), Duration::ZERO));
+		}
+
+		Err(e) => return Err(NonCriticalError::DecodeAudio(path, e.to_string())),
+	};
+
+	#[allow(clippy::cast_precision_loss)]
+	let duration = Duration::from_secs_f64(samples.len() as f64 / f64::from(SPEECH_SAMPLE_RATE));
+
+	spawn_blocking({
+		let path = path.clone();
+
+		move || {
+			model
+				.transcribe(&samples)
+				.map(|transcription| (transcription, duration))
+				.map_err(|e| NonCriticalError::Transcribe(path, e.to_string()))
+		}
+	})
+	.await
+	.map_err(|e| NonCriticalError::PanicWhileTranscribing(path, e.to_string()))?
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SaveState {
+	id: TaskId,
+	files: Vec<(object::id::Type, PathBuf)>,
+	transcriptions: Vec<(object::id::Type, Transcription)>,
+	output: Output,
+}
+
+impl SerializableTask<Error> for SpeechRecognizer {
+	type SerializeError = rmp_serde::encode::Error;
+
+	type DeserializeError = rmp_serde::decode::Error;
+
+	type DeserializeCtx = (Arc<LoadedWhisper>, Arc<PrismaClient>);
+
+	async fn serialize(self) -> Result<Vec<u8>, Self::SerializeError> {
+		let Self {
+			id,
+			files,
+			transcriptions,
+			output,
+			..
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState {
+			id,
+			files,
+			transcriptions,
+			output,
+		})
+	}
+
+	async fn deserialize(
+		data: &[u8],
+		(model, db): Self::DeserializeCtx,
+	) -> Result<Self, Self::DeserializeError> {
+		rmp_serde::from_slice(data).map(
+			|SaveState {
+			     id,
+			     files,
+			     transcriptions,
+			     output,
+			 }| Self {
+				id,
+				files,
+				transcriptions,
+				model,
+				db,
+				output,
+			},
+		)
+	}
+}
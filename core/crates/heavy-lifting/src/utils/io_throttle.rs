@@ -0,0 +1,101 @@
+use std::{
+	num::NonZeroU64,
+	sync::{Arc, Mutex, PoisonError},
+	time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::time::{sleep, Instant};
+
+/// Caps how many bytes per second a job reads from disk, so a full re-identification or integrity
+/// check in the background doesn't make the rest of the system crawl on a laptop.
+///
+/// Clones share the same budget, so every task of a job, or every job sharing a throttle, are
+/// capped together. The default throttle is unlimited. Only the cap is persisted when a job is
+/// paused, a resumed job starts with a fresh budget.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(from = "Option<NonZeroU64>", into = "Option<NonZeroU64>")]
+pub struct IoThrottle(Option<Arc<TokenBucket>>);
+
+#[derive(Debug)]
+struct TokenBucket {
+	bytes_per_sec: NonZeroU64,
+	state: Mutex<BucketState>,
+}
+
+#[derive(Debug)]
+struct BucketState {
+	/// Negative when reads got ahead of the budget, the next ones wait for it to be paid back
+	available_bytes: f64,
+	last_refill: Instant,
+}
+
+impl IoThrottle {
+	#[must_use]
+	#[allow(clippy::cast_precision_loss)] // SAFETY: an approximate budget is fine
+	pub fn new(bytes_per_sec: NonZeroU64) -> Self {
+		Self(Some(Arc::new(TokenBucket {
+			bytes_per_sec,
+			state: Mutex::new(BucketState {
+				// Allows bursts of up to a second worth of reads
+				available_bytes: bytes_per_sec.get() as f64,
+				last_refill: Instant::now(),
+			}),
+		})))
+	}
+
+	#[must_use]
+	pub fn bytes_per_sec(&self) -> Option<NonZeroU64> {
+		self.0.as_ref().map(|bucket| bucket.bytes_per_sec)
+	}
+
+	/// Accounts for `bytes` read from disk, waiting as long as needed to keep under the cap.
+	/// Returns right away for unlimited throttles.
+	#[allow(clippy::cast_precision_loss)] // SAFETY: an approximate budget is fine
+	pub async fn consume(&self, bytes: u64) {
+		let Some(bucket) = &self.0 else {
+			return;
+		};
+
+		let wait = {
+			let mut state = bucket.state.lock().unwrap_or_else(PoisonError::into_inner);
+			let rate = bucket.bytes_per_sec.get() as f64;
+			let now = Instant::now();
+
+			state.available_bytes = now
+				.duration_since(state.last_refill)
+				.as_secs_f64()
+				.mul_add(rate, state.available_bytes)
+				.min(rate);
+			state.last_refill = now;
+			state.available_bytes -= bytes as f64;
+
+			(state.available_bytes < 0.0)
+				.then(|| Duration::from_secs_f64(-state.available_bytes / rate))
+		};
+
+		if let Some(wait) = wait {
+			sleep(wait).await;
+		}
+	}
+}
+
+impl PartialEq for IoThrottle {
+	fn eq(&self, other: &Self) -> bool {
+		self.bytes_per_sec() == other.bytes_per_sec()
+	}
+}
+
+impl Eq for IoThrottle {}
+
+impl From<Option<NonZeroU64>> for IoThrottle {
+	fn from(bytes_per_sec: Option<NonZeroU64>) -> Self {
+		bytes_per_sec.map_or_else(Self::default, Self::new)
+	}
+}
+
+impl From<IoThrottle> for Option<NonZeroU64> {
+	fn from(throttle: IoThrottle) -> Self {
+		throttle.bytes_per_sec()
+	}
+}
@@ -1 +1,4 @@
+pub mod io_throttle;
+pub mod network_share;
+pub mod relink;
 pub mod sub_path;
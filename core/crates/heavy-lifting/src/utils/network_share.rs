@@ -0,0 +1,354 @@
+use rspc::ErrorCode;
+use sd_prisma::prisma::location;
+use sd_utils::error::FileIOError;
+
+use std::{
+	io,
+	path::{Path, PathBuf},
+	time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::{fs, process::Command, time::sleep};
+use tracing::{debug, warn};
+
+/// How long we wait between checks while a disconnected share doesn't come back
+const RECONNECT_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("failed to decode network share of location <id='{0}'>: {1}")]
+	Decode(location::id::Type, rmp_serde::decode::Error),
+	#[error("failed to mount network share <remote='{remote}'>: {message}")]
+	Mount { remote: String, message: String },
+	#[error("network share location path isn't a directory: <path='{}'>", .0.display())]
+	NotADirectory(Box<Path>),
+
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+}
+
+impl From<Error> for rspc::Error {
+	fn from(err: Error) -> Self {
+		match err {
+			Error::Mount { .. } | Error::NotADirectory(_) => {
+				Self::with_cause(ErrorCode::PreconditionFailed, err.to_string(), err)
+			}
+
+			_ => Self::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum NetworkShareProtocol {
+	Smb,
+	Nfs,
+}
+
+/// Connection info of a location living in a SMB or NFS share, stored msgpack encoded on
+/// `location.network_share`, so jobs can mount the share on the location path before touching it.
+///
+/// Passwords are never stored, SMB shares authenticate with the credentials saved by the system
+/// (keychain, credential manager or a `credentials=` file on `mount_options`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct NetworkShare {
+	pub protocol: NetworkShareProtocol,
+	pub host: String,
+	/// Share name for SMB, exported path for NFS
+	pub share: String,
+	pub username: Option<String>,
+	/// Passed as they are to the platform's mount command
+	pub mount_options: Option<String>,
+}
+
+impl NetworkShare {
+	pub fn from_db(
+		location_id: location::id::Type,
+		network_share: Option<&[u8]>,
+	) -> Result<Option<Self>, Error> {
+		network_share
+			.map(|bytes| rmp_serde::from_slice(bytes).map_err(|e| Error::Decode(location_id, e)))
+			.transpose()
+	}
+
+	/// # Panics
+	/// Will panic if msgpack fails to encode it, which can't happen for plain strings and enums
+	#[must_use]
+	pub fn to_db(&self) -> Vec<u8> {
+		rmp_serde::to_vec_named(self).expect("network share is always serializable")
+	}
+
+	/// `//host/share` for SMB and `host:/export` for NFS
+	#[must_use]
+	pub fn remote(&self) -> String {
+		match self.protocol {
+			NetworkShareProtocol::Smb => {
+				format!("//{}/{}", self.host, self.share.trim_start_matches('/'))
+			}
+			NetworkShareProtocol::Nfs => {
+				format!("{}:/{}", self.host, self.share.trim_start_matches('/'))
+			}
+		}
+	}
+
+	/// Mounts the share on `mount_point` if it isn't already mounted there, and checks that we
+	/// end up with a directory we can read.
+	pub async fn ensure_mounted(&self, mount_point: impl AsRef<Path> + Send) -> Result<(), Error> {
+		let mount_point = mount_point.as_ref();
+
+		if is_mounted(mount_point).await {
+			return ensure_is_dir(mount_point).await;
+		}
+
+		debug!(
+			"Mounting network share <remote='{}'> at <path='{}'>",
+			self.remote(),
+			mount_point.display()
+		);
+
+		#[cfg(not(target_os = "windows"))]
+		{
+			fs::create_dir_all(mount_point)
+				.await
+				.map_err(|e| FileIOError::from((mount_point, e)))?;
+		}
+
+		let output = self
+			.mount_command(mount_point)?
+			.output()
+			.await
+			.map_err(|e| FileIOError::from((mount_point, e, "Failed to run mount command")))?;
+
+		if !output.status.success() {
+			return Err(Error::Mount {
+				remote: self.remote(),
+				message: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+			});
+		}
+
+		ensure_is_dir(mount_point).await
+	}
+
+	#[cfg(target_os = "linux")]
+	fn mount_command(&self, mount_point: &Path) -> Result<Command, Error> {
+		let mut cmd = Command::new("mount");
+
+		let mut options = self.mount_options.iter().cloned().collect::<Vec<_>>();
+
+		match self.protocol {
+			NetworkShareProtocol::Smb => {
+				cmd.args(["-t", "cifs"]);
+				if let Some(username) = &self.username {
+					options.push(format!("username={username}"));
+				}
+			}
+			NetworkShareProtocol::Nfs => {
+				cmd.args(["-t", "nfs"]);
+			}
+		}
+
+		if !options.is_empty() {
+			cmd.arg("-o").arg(options.join(","));
+		}
+
+		cmd.arg(self.remote()).arg(mount_point);
+
+		Ok(cmd)
+	}
+
+	#[cfg(target_os = "macos")]
+	fn mount_command(&self, mount_point: &Path) -> Result<Command, Error> {
+		let mut cmd = match self.protocol {
+			NetworkShareProtocol::Smb => Command::new("mount_smbfs"),
+			NetworkShareProtocol::Nfs => Command::new("mount_nfs"),
+		};
+
+		if let Some(options) = &self.mount_options {
+			cmd.arg("-o").arg(options);
+		}
+
+		match (&self.protocol, &self.username) {
+			(NetworkShareProtocol::Smb, Some(username)) => cmd.arg(format!(
+				"//{username}@{}/{}",
+				self.host,
+				self.share.trim_start_matches('/')
+			)),
+			_ => cmd.arg(self.remote()),
+		};
+
+		cmd.arg(mount_point);
+
+		Ok(cmd)
+	}
+
+	#[cfg(target_os = "windows")]
+	fn mount_command(&self, mount_point: &Path) -> Result<Command, Error> {
+		use std::path::{Component, Prefix};
+
+		if self.protocol == NetworkShareProtocol::Nfs {
+			return Err(Error::Mount {
+				remote: self.remote(),
+				message: "NFS shares must be mounted through the Windows NFS client".to_string(),
+			});
+		}
+
+		let remote = format!(r"\\{}\{}", self.host, self.share.trim_start_matches('/'));
+
+		let mut cmd = Command::new("net");
+		cmd.arg("use");
+
+		// Locations on a mapped drive get it mapped again, UNC paths only need the connection
+		if let Some(Component::Prefix(prefix)) = mount_point.components().next() {
+			if let Prefix::Disk(letter) = prefix.kind() {
+				cmd.arg(format!("{}:", char::from(letter)));
+			}
+		}
+
+		cmd.arg(remote);
+
+		if let Some(username) = &self.username {
+			cmd.arg(format!("/user:{username}"));
+		}
+
+		if let Some(options) = &self.mount_options {
+			cmd.args(options.split_whitespace());
+		}
+
+		Ok(cmd)
+	}
+
+	#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+	fn mount_command(&self, _: &Path) -> Result<Command, Error> {
+		Err(Error::Mount {
+			remote: self.remote(),
+			message: "mounting network shares isn't supported on this platform".to_string(),
+		})
+	}
+}
+
+/// Mounts the location's network share, if it has one, see [`NetworkShare::ensure_mounted`]
+pub async fn ensure_location_mounted(
+	location_id: location::id::Type,
+	network_share: Option<&[u8]>,
+	location_path: impl AsRef<Path> + Send,
+) -> Result<(), Error> {
+	if let Some(network_share) = NetworkShare::from_db(location_id, network_share)? {
+		network_share.ensure_mounted(location_path).await?;
+	}
+
+	Ok(())
+}
+
+/// Waits until the location's share is reachable again, trying to mount it back in case the
+/// system unmounted it when the connection dropped.
+pub async fn wait_until_available(
+	location_id: location::id::Type,
+	network_share: Option<&[u8]>,
+	location_path: impl AsRef<Path> + Send,
+) {
+	let location_path = location_path.as_ref();
+
+	loop {
+		sleep(RECONNECT_CHECK_INTERVAL).await;
+
+		match ensure_location_mounted(location_id, network_share, location_path).await {
+			Ok(()) if fs::read_dir(location_path).await.is_ok() => {
+				debug!("Network share of location <id={location_id}> is available again");
+				return;
+			}
+			Ok(()) => {}
+			Err(e) => {
+				warn!("Network share of location <id={location_id}> still unavailable: {e:#?}");
+			}
+		}
+	}
+}
+
+/// Tells if an IO error means we lost the connection with the share the file lives in,
+/// instead of something wrong with the file itself.
+#[must_use]
+pub fn is_disconnection(e: &io::Error) -> bool {
+	if matches!(
+		e.kind(),
+		io::ErrorKind::NotConnected
+			| io::ErrorKind::ConnectionReset
+			| io::ErrorKind::ConnectionAborted
+			| io::ErrorKind::TimedOut
+	) {
+		return true;
+	}
+
+	e.raw_os_error().map_or(false, is_disconnection_os_error)
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+const fn is_disconnection_os_error(code: i32) -> bool {
+	matches!(
+		code,
+		libc::ENOTCONN
+			| libc::EHOSTDOWN
+			| libc::EHOSTUNREACH
+			| libc::ENETDOWN
+			| libc::ENETUNREACH
+			| libc::ENETRESET
+			| libc::ESTALE
+	)
+}
+
+#[cfg(target_os = "windows")]
+fn is_disconnection_os_error(code: i32) -> bool {
+	use windows::Win32::Foundation::{
+		ERROR_BAD_NETPATH, ERROR_BAD_NET_NAME, ERROR_NETNAME_DELETED, ERROR_NETWORK_UNREACHABLE,
+		ERROR_SEM_TIMEOUT, ERROR_UNEXP_NET_ERR,
+	};
+
+	[
+		ERROR_BAD_NETPATH,
+		ERROR_BAD_NET_NAME,
+		ERROR_NETNAME_DELETED,
+		ERROR_NETWORK_UNREACHABLE,
+		ERROR_SEM_TIMEOUT,
+		ERROR_UNEXP_NET_ERR,
+	]
+	.into_iter()
+	.any(|err| i32::try_from(err.0).map_or(false, |err| err == code))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+const fn is_disconnection_os_error(_: i32) -> bool {
+	false
+}
+
+#[cfg(unix)]
+async fn is_mounted(mount_point: &Path) -> bool {
+	use std::os::unix::fs::MetadataExt;
+
+	// An unmounted mount point is just an empty directory in the same device as its parent
+	let Some(parent) = mount_point.parent() else {
+		return true;
+	};
+
+	match (fs::metadata(mount_point).await, fs::metadata(parent).await) {
+		(Ok(mount_point), Ok(parent)) => mount_point.dev() != parent.dev(),
+		_ => false,
+	}
+}
+
+#[cfg(not(unix))]
+async fn is_mounted(mount_point: &Path) -> bool {
+	fs::metadata(mount_point).await.is_ok()
+}
+
+async fn ensure_is_dir(path: &Path) -> Result<(), Error> {
+	let metadata = fs::metadata(path)
+		.await
+		.map_err(|e| FileIOError::from((path, e)))?;
+
+	if metadata.is_dir() {
+		Ok(())
+	} else {
+		Err(Error::NotADirectory(PathBuf::from(path).into_boxed_path()))
+	}
+}
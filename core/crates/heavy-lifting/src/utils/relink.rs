@@ -0,0 +1,221 @@
+use crate::file_identifier::{archive_entries_root, statistics, ArchiveFormat};
+
+use sd_core_file_path_helper::{FilePathError, IsolatedFilePathData};
+use sd_core_sync::Manager as SyncManager;
+
+use sd_prisma::{
+	prisma::{file_path, location, PrismaClient},
+	prisma_sync,
+};
+use sd_sync::{CRDTOperation, OperationFactory};
+use sd_utils::{chain_optional_iter, msgpack};
+
+use std::{
+	path::{Path, PathBuf},
+	sync::Arc,
+};
+
+use prisma_client_rust::QueryError;
+use serde::{Deserialize, Serialize};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("database error: {0}")]
+	Database(#[from] QueryError),
+
+	#[error(transparent)]
+	IsoFilePath(#[from] FilePathError),
+}
+
+/// A location a file path is relinked from or to, with what's needed to sync the relink
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LocationRoot {
+	pub id: location::id::Type,
+	pub pub_id: Vec<u8>,
+	pub path: Arc<PathBuf>,
+}
+
+/// A file path being relinked, keeping its id and so its object
+#[derive(Debug, Clone, Copy)]
+pub struct Relinked<'a> {
+	pub file_path_id: file_path::id::Type,
+	pub pub_id: &'a [u8],
+	pub is_dir: bool,
+}
+
+/// Database updates pointing file paths somewhere else, built by [`relink`] and written in one go
+/// by [`Relink::write`]
+#[derive(Debug, Default)]
+pub struct Relink {
+	sync_ops: Vec<CRDTOperation>,
+	updates: Vec<(file_path::id::Type, Vec<file_path::SetParam>)>,
+}
+
+impl Relink {
+	/// Appends the updates of `other`, applied after the ones already here
+	pub fn extend(&mut self, other: Self) {
+		self.sync_ops.extend(other.sync_ops);
+		self.updates.extend(other.updates);
+	}
+
+	pub async fn write(self, db: &PrismaClient, sync: &SyncManager) -> Result<(), QueryError> {
+		if self.updates.is_empty() {
+			return Ok(());
+		}
+
+		let ids = &self.updates.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+
+		db._transaction()
+			.with_timeout(30 * 1000)
+			.run(|db| async move {
+				let before =
+					statistics::snapshot(vec![file_path::id::in_vec(ids.clone())], &db).await?;
+
+				sync.write_ops(
+					&db,
+					(
+						self.sync_ops,
+						self.updates
+							.into_iter()
+							.map(|(id, params)| {
+								db.file_path()
+									.update(file_path::id::equals(id), params)
+									.select(file_path::select!({ id }))
+							})
+							.collect::<Vec<_>>(),
+					),
+				)
+				.await?;
+
+				statistics::snapshot(vec![file_path::id::in_vec(ids.clone())], &db)
+					.await?
+					.since(before)
+					.commit(&db)
+					.await
+			})
+			.await
+	}
+}
+
+/// Updates pointing a file path, and everything under it for directories, from `from_path` in
+/// `from` to `to_path` in `to`. Descendants are looked up when called, so when relinking nested
+/// file paths in one write, the deepest ones must come first.
+pub async fn relink(
+	file_path: Relinked<'_>,
+	(from, from_path): (&LocationRoot, &Path),
+	(to, to_path): (&LocationRoot, &Path),
+	db: &PrismaClient,
+	sync: &SyncManager,
+) -> Result<Relink, Error> {
+	let old = IsolatedFilePathData::new(from.id, &*from.path, from_path, file_path.is_dir)?;
+	let new = IsolatedFilePathData::new(to.id, &*to.path, to_path, file_path.is_dir)?;
+	let new_parts = new.to_parts();
+
+	let location_update = || {
+		(from.id != to.id).then(|| {
+			(
+				(
+					file_path::location::NAME,
+					msgpack!(prisma_sync::location::SyncId {
+						pub_id: to.pub_id.clone()
+					}),
+				),
+				file_path::location::connect(location::id::equals(to.id)),
+			)
+		})
+	};
+
+	let mut updates = vec![(
+		file_path.file_path_id,
+		file_path.pub_id.to_vec(),
+		chain_optional_iter(
+			[
+				(
+					(
+						file_path::materialized_path::NAME,
+						msgpack!(new_parts.materialized_path),
+					),
+					file_path::materialized_path::set(Some(
+						new_parts.materialized_path.to_string(),
+					)),
+				),
+				(
+					(file_path::name::NAME, msgpack!(new_parts.name)),
+					file_path::name::set(Some(new_parts.name.to_string())),
+				),
+				(
+					(file_path::extension::NAME, msgpack!(new_parts.extension)),
+					file_path::extension::set(Some(new_parts.extension.to_string())),
+				),
+			],
+			[location_update()],
+		),
+	)];
+
+	// Files don't have children, besides the entries walked from archives, which follow them
+	let prefixes = if file_path.is_dir {
+		old.materialized_path_for_children()
+			.zip(new.materialized_path_for_children())
+	} else if ArchiveFormat::from_name(&old.full_name()).is_some() {
+		Some((
+			archive_entries_root(old.to_parts().materialized_path, &old.full_name()),
+			archive_entries_root(new_parts.materialized_path, &new.full_name()),
+		))
+	} else {
+		None
+	};
+
+	if let Some((old_prefix, new_prefix)) = prefixes {
+		let children = db
+			.file_path()
+			.find_many(vec![
+				file_path::location_id::equals(Some(from.id)),
+				file_path::materialized_path::starts_with(old_prefix.clone()),
+			])
+			.select(file_path::select!({ id pub_id materialized_path }))
+			.exec()
+			.await?;
+
+		updates.extend(children.into_iter().filter_map(|child| {
+			let materialized_path = child
+				.materialized_path?
+				.replacen(&old_prefix, &new_prefix, 1);
+
+			Some((
+				child.id,
+				child.pub_id,
+				chain_optional_iter(
+					[(
+						(
+							file_path::materialized_path::NAME,
+							msgpack!(&materialized_path),
+						),
+						file_path::materialized_path::set(Some(materialized_path)),
+					)],
+					[location_update()],
+				),
+			))
+		}));
+	}
+
+	let mut relink = Relink::default();
+
+	for (id, pub_id, fields) in updates {
+		let (sync_fields, db_fields) = fields.into_iter().unzip::<_, _, Vec<_>, Vec<_>>();
+
+		relink
+			.sync_ops
+			.extend(sync_fields.into_iter().map(|(field, value)| {
+				sync.shared_update(
+					prisma_sync::file_path::SyncId {
+						pub_id: pub_id.clone(),
+					},
+					field,
+					value,
+				)
+			}));
+		relink.updates.push((id, db_fields));
+	}
+
+	Ok(relink)
+}
@@ -0,0 +1,470 @@
+use crate::{
+	file_identifier::{self, CasIdAlgorithm},
+	job_system::{
+		job::{Job, JobReturn, JobTaskDispatcher, ReturnStatus},
+		report::ReportOutputMetadata,
+		utils::{cancel_pending_tasks, location_concurrency_key},
+		SerializableJob, SerializedTasks,
+	},
+	utils::{
+		io_throttle::IoThrottle,
+		sub_path::{self, maybe_get_iso_file_path_from_sub_path},
+	},
+	verify_integrity, Error, JobName, NonCriticalError, OuterContext, ProgressUpdate,
+};
+
+use sd_core_file_path_helper::IsolatedFilePathData;
+use sd_core_prisma_helpers::file_path_for_integrity_verifier;
+
+use sd_prisma::prisma::{file_path, location, SortOrder};
+use sd_task_system::{
+	IntoTask, SerializableTask, Task, TaskDispatcher, TaskHandle, TaskOutput, TaskStatus,
+};
+use sd_utils::db::maybe_missing;
+
+use std::{
+	collections::HashMap,
+	hash::{Hash, Hasher},
+	mem,
+	path::PathBuf,
+	sync::Arc,
+	time::Duration,
+};
+
+use futures::{stream::FuturesUnordered, StreamExt};
+use futures_concurrency::future::TryJoin;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{debug, warn};
+
+use super::{
+	tasks::{integrity_verifier, IntegrityVerifier},
+	BATCH_SIZE,
+};
+
+/// Re-reads the files of a location, or only some of them, recomputing their `cas_id` to detect
+/// content that changed silently on disk, like bit rot
+#[derive(Debug)]
+pub struct VerifyIntegrity {
+	location: Arc<location::Data>,
+	location_path: Arc<PathBuf>,
+	sub_path: Option<PathBuf>,
+	file_path_ids: Option<Vec<file_path::id::Type>>,
+	cas_id_algorithm: CasIdAlgorithm,
+	io_throttle: IoThrottle,
+
+	metadata: Metadata,
+
+	errors: Vec<NonCriticalError>,
+
+	pending_tasks_on_resume: Vec<TaskHandle<Error>>,
+	tasks_for_shutdown: Vec<Box<dyn Task<Error>>>,
+}
+
+impl Hash for VerifyIntegrity {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.location.id.hash(state);
+		if let Some(ref sub_path) = self.sub_path {
+			sub_path.hash(state);
+		}
+		if let Some(ref file_path_ids) = self.file_path_ids {
+			file_path_ids.hash(state);
+		}
+	}
+}
+
+impl Job for VerifyIntegrity {
+	const NAME: JobName = JobName::VerifyIntegrity;
+
+	async fn resume_tasks(
+		&mut self,
+		dispatcher: &JobTaskDispatcher,
+		ctx: &impl OuterContext,
+		SerializedTasks(serialized_tasks): SerializedTasks,
+	) -> Result<(), Error> {
+		let dispatcher =
+			dispatcher.with_concurrency_key(location_concurrency_key(ctx.id(), self.location.id));
+		let io_throttle = &dispatcher.io_throttle(&self.io_throttle);
+
+		self.pending_tasks_on_resume = dispatcher
+			.dispatch_many_boxed(
+				rmp_serde::from_slice::<Vec<Vec<u8>>>(&serialized_tasks)
+					.map_err(verify_integrity::Error::from)?
+					.into_iter()
+					.map(|task_bytes| async move {
+						IntegrityVerifier::deserialize(
+							&task_bytes,
+							(Arc::clone(ctx.db()), Arc::clone(ctx.sync())),
+						)
+						.await
+						// Tasks share the job's budget again, instead of one each
+						.map(|task| task.with_io_throttle(io_throttle.clone()))
+						.map(IntoTask::into_task)
+					})
+					.collect::<Vec<_>>()
+					.try_join()
+					.await
+					.map_err(verify_integrity::Error::from)?,
+			)
+			.await;
+
+		Ok(())
+	}
+
+	async fn run<Ctx: OuterContext>(
+		mut self,
+		dispatcher: JobTaskDispatcher,
+		ctx: Ctx,
+	) -> Result<ReturnStatus, Error> {
+		let dispatcher =
+			dispatcher.with_concurrency_key(location_concurrency_key(ctx.id(), self.location.id));
+
+		let mut pending_running_tasks = FuturesUnordered::new();
+
+		self.init_or_resume(&mut pending_running_tasks, &ctx, &dispatcher)
+			.await?;
+
+		while let Some(task) = pending_running_tasks.next().await {
+			match task {
+				Ok(TaskStatus::Done((_, TaskOutput::Out(out)))) => {
+					self.process_verifier_output(
+						*out.downcast::<integrity_verifier::Output>().expect(
+							"the integrity verification job only dispatches verifier tasks",
+						),
+						&ctx,
+					);
+				}
+
+				Ok(TaskStatus::Done((task_id, TaskOutput::Empty))) => {
+					warn!("Task <id='{task_id}'> returned an empty output");
+				}
+
+				Ok(TaskStatus::Shutdown(task)) => {
+					self.tasks_for_shutdown.push(task);
+				}
+
+				Ok(TaskStatus::Error(e)) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e);
+				}
+
+				Ok(TaskStatus::Canceled | TaskStatus::ForcedAbortion) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Ok(ReturnStatus::Canceled);
+				}
+
+				Err(e) => {
+					cancel_pending_tasks(&pending_running_tasks).await;
+
+					return Err(e.into());
+				}
+			}
+		}
+
+		if !self.tasks_for_shutdown.is_empty() {
+			return Ok(ReturnStatus::Shutdown(
+				SerializableJob::<Ctx>::serialize(self).await,
+			));
+		}
+
+		let Self {
+			metadata, errors, ..
+		} = self;
+
+		Ok(ReturnStatus::Completed(
+			JobReturn::builder()
+				.with_metadata(metadata)
+				.with_non_critical_errors(errors)
+				.build(),
+		))
+	}
+}
+
+impl VerifyIntegrity {
+	/// `cas_id_algorithm` must be the one the library's `cas_id`s were generated with, otherwise
+	/// every file is reported as a mismatch
+	pub fn new(
+		location: location::Data,
+		sub_path: Option<PathBuf>,
+		cas_id_algorithm: CasIdAlgorithm,
+	) -> Result<Self, verify_integrity::Error> {
+		Ok(Self {
+			location_path: maybe_missing(&location.path, "location.path")
+				.map(PathBuf::from)
+				.map(Arc::new)?,
+			location: Arc::new(location),
+			sub_path,
+			file_path_ids: None,
+			cas_id_algorithm,
+			io_throttle: IoThrottle::default(),
+			metadata: Metadata::default(),
+			errors: Vec::new(),
+			pending_tasks_on_resume: Vec::new(),
+			tasks_for_shutdown: Vec::new(),
+		})
+	}
+
+	/// Caps how fast files are read, shared by every verifier task of the job
+	#[must_use]
+	pub fn with_io_throttle(mut self, io_throttle: IoThrottle) -> Self {
+		self.io_throttle = io_throttle;
+		self
+	}
+
+	/// Only verifies the selected file paths, instead of every file in the location
+	#[must_use]
+	pub fn with_file_paths(mut self, file_path_ids: Vec<file_path::id::Type>) -> Self {
+		self.file_path_ids = Some(file_path_ids);
+		self
+	}
+
+	async fn init_or_resume(
+		&mut self,
+		pending_running_tasks: &mut FuturesUnordered<TaskHandle<Error>>,
+		ctx: &impl OuterContext,
+		dispatcher: &JobTaskDispatcher,
+	) -> Result<(), verify_integrity::Error> {
+		// Resumed tasks were already dispatched by `resume_tasks`
+		if !self.pending_tasks_on_resume.is_empty() {
+			pending_running_tasks.extend(mem::take(&mut self.pending_tasks_on_resume));
+
+			return Ok(());
+		}
+
+		let deep_hash_threshold = ctx.deep_hash_threshold().await;
+
+		let db = ctx.db();
+		let location_id = self.location.id;
+		let location_path = &*self.location_path;
+
+		let iso_file_path =
+			maybe_get_iso_file_path_from_sub_path(location_id, &self.sub_path, location_path, db)
+				.await?
+				.map_or_else(
+					|| {
+						IsolatedFilePathData::new(location_id, location_path, location_path, true)
+							.map_err(sub_path::Error::from)
+					},
+					Ok,
+				)?;
+
+		debug!("Verifying integrity of files in location {location_id} at directory \"{iso_file_path}\"");
+
+		let io_throttle = dispatcher.io_throttle(&self.io_throttle);
+		let mut last_file_path_id = None;
+
+		loop {
+			#[allow(clippy::cast_possible_wrap)]
+			// SAFETY: we know that BATCH_SIZE is a valid i64
+			let file_paths = db
+				.file_path()
+				.find_many(sd_utils::chain_optional_iter(
+					[
+						file_path::location_id::equals(Some(location_id)),
+						file_path::is_dir::equals(Some(false)),
+						file_path::cas_id::not(None),
+						file_identifier::not_in_archive(),
+						file_path::materialized_path::starts_with(
+							iso_file_path
+								.materialized_path_for_children()
+								.expect("sub path iso_file_path must be a directory"),
+						),
+					],
+					[
+						last_file_path_id.map(file_path::id::gt),
+						self.file_path_ids.clone().map(file_path::id::in_vec),
+					],
+				))
+				.order_by(file_path::id::order(SortOrder::Asc))
+				.take(BATCH_SIZE as i64)
+				.select(file_path_for_integrity_verifier::select())
+				.exec()
+				.await?;
+
+			let Some(last_file_path) = file_paths.last() else {
+				break;
+			};
+
+			last_file_path_id = Some(last_file_path.id);
+			self.metadata.total_files += file_paths.len() as u64;
+
+			pending_running_tasks.push(
+				dispatcher
+					.dispatch(
+						IntegrityVerifier::new(
+							location_id,
+							Arc::clone(&self.location_path),
+							file_paths,
+							self.cas_id_algorithm,
+							deep_hash_threshold,
+							Arc::clone(db),
+							Arc::clone(ctx.sync()),
+						)
+						.with_io_throttle(io_throttle.clone()),
+					)
+					.await,
+			);
+		}
+
+		ctx.progress(vec![
+			ProgressUpdate::TaskCount(self.metadata.total_files),
+			ProgressUpdate::Message(format!(
+				"Verifying {} files in {} chunks",
+				self.metadata.total_files,
+				pending_running_tasks.len()
+			)),
+		]);
+
+		Ok(())
+	}
+
+	fn process_verifier_output(
+		&mut self,
+		integrity_verifier::Output {
+			valid,
+			mismatched,
+			unreadable,
+			modified,
+			verification_time,
+			db_write_time,
+			errors,
+		}: integrity_verifier::Output,
+		ctx: &impl OuterContext,
+	) {
+		self.metadata.valid += valid;
+		self.metadata.mismatched += mismatched;
+		self.metadata.unreadable += unreadable;
+		self.metadata.modified += modified;
+		self.metadata.verification_time += verification_time;
+		self.metadata.db_write_time += db_write_time;
+
+		self.errors.extend(errors);
+
+		ctx.progress(vec![ProgressUpdate::CompletedTaskCount(
+			self.metadata.valid
+				+ self.metadata.mismatched
+				+ self.metadata.unreadable
+				+ self.metadata.modified,
+		)]);
+	}
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Metadata {
+	total_files: u64,
+	valid: u64,
+	mismatched: u64,
+	unreadable: u64,
+	#[serde(default)]
+	modified: u64,
+	verification_time: Duration,
+	db_write_time: Duration,
+}
+
+impl From<Metadata> for ReportOutputMetadata {
+	fn from(value: Metadata) -> Self {
+		Self::Metrics(HashMap::from([
+			("total_files".into(), json!(value.total_files)),
+			("valid_files".into(), json!(value.valid)),
+			("mismatched_files".into(), json!(value.mismatched)),
+			("unreadable_files".into(), json!(value.unreadable)),
+			("modified_files".into(), json!(value.modified)),
+			("verification_time".into(), json!(value.verification_time)),
+			("db_write_time".into(), json!(value.db_write_time)),
+		]))
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+struct SaveState {
+	location: Arc<location::Data>,
+	location_path: Arc<PathBuf>,
+	sub_path: Option<PathBuf>,
+	file_path_ids: Option<Vec<file_path::id::Type>>,
+	cas_id_algorithm: CasIdAlgorithm,
+	#[serde(default)]
+	io_throttle: IoThrottle,
+
+	metadata: Metadata,
+
+	errors: Vec<NonCriticalError>,
+
+	tasks_for_shutdown_bytes: Option<SerializedTasks>,
+}
+
+impl<Ctx: OuterContext> SerializableJob<Ctx> for VerifyIntegrity {
+	async fn serialize(self) -> Result<Option<Vec<u8>>, rmp_serde::encode::Error> {
+		let Self {
+			location,
+			location_path,
+			sub_path,
+			file_path_ids,
+			cas_id_algorithm,
+			io_throttle,
+			metadata,
+			errors,
+			tasks_for_shutdown,
+			..
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState {
+			location,
+			location_path,
+			sub_path,
+			file_path_ids,
+			cas_id_algorithm,
+			io_throttle,
+			metadata,
+			errors,
+			tasks_for_shutdown_bytes: Some(SerializedTasks(rmp_serde::to_vec_named(
+				&tasks_for_shutdown
+					.into_iter()
+					.map(|task| async move {
+						task.downcast::<IntegrityVerifier>()
+							.expect("the integrity verification job only dispatches verifier tasks")
+							.serialize()
+							.await
+					})
+					.collect::<Vec<_>>()
+					.try_join()
+					.await?,
+			)?)),
+		})
+		.map(Some)
+	}
+
+	async fn deserialize(
+		serialized_job: &[u8],
+		_: &Ctx,
+	) -> Result<Option<(Self, Option<SerializedTasks>)>, rmp_serde::decode::Error> {
+		let SaveState {
+			location,
+			location_path,
+			sub_path,
+			file_path_ids,
+			cas_id_algorithm,
+			io_throttle,
+			metadata,
+			errors,
+			tasks_for_shutdown_bytes,
+		} = rmp_serde::from_slice::<SaveState>(serialized_job)?;
+
+		Ok(Some((
+			Self {
+				location,
+				location_path,
+				sub_path,
+				file_path_ids,
+				cas_id_algorithm,
+				io_throttle,
+				metadata,
+				errors,
+				pending_tasks_on_resume: Vec::new(),
+				tasks_for_shutdown: Vec::new(),
+			},
+			tasks_for_shutdown_bytes,
+		)))
+	}
+}
@@ -0,0 +1,65 @@
+use crate::utils::sub_path;
+
+use sd_core_file_path_helper::FilePathError;
+
+use sd_utils::db::MissingFieldError;
+
+use prisma_client_rust::QueryError;
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+pub mod job;
+mod tasks;
+
+pub use job::VerifyIntegrity;
+pub use tasks::integrity_verifier;
+
+// How many file paths each verification task re-reads
+const BATCH_SIZE: usize = 100;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("missing field on database: {0}")]
+	MissingField(#[from] MissingFieldError),
+	#[error("database error: {0}")]
+	Database(#[from] QueryError),
+	#[error("failed to deserialized stored tasks for job resume: {0}")]
+	DeserializeTasks(#[from] rmp_serde::decode::Error),
+
+	#[error(transparent)]
+	FilePathError(#[from] FilePathError),
+	#[error(transparent)]
+	SubPath(#[from] sub_path::Error),
+}
+
+impl From<Error> for rspc::Error {
+	fn from(err: Error) -> Self {
+		match err {
+			Error::SubPath(sub_path_err) => sub_path_err.into(),
+
+			_ => Self::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize, Type)]
+pub enum NonCriticalError {
+	#[error(transparent)]
+	IntegrityVerifier(#[from] integrity_verifier::NonCriticalError),
+}
+
+/// Outcome of the last integrity verification of a file path, stored in `file_path.integrity_status`
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum IntegrityStatus {
+	/// The content still matches the stored `cas_id`
+	Valid = 0,
+	/// The content changed without Spacedrive noticing, likely bit rot
+	Mismatch = 1,
+	/// The file couldn't be read
+	Unreadable = 2,
+	/// The file was modified since its `cas_id` was computed, so it wasn't verified until it's
+	/// identified again
+	Modified = 3,
+}
@@ -0,0 +1,383 @@
+use crate::{
+	file_identifier::{generate_cas_id, CasIdAlgorithm},
+	folder_sync::MODIFIED_TOLERANCE_MS,
+	utils::io_throttle::IoThrottle,
+	verify_integrity::{self, IntegrityStatus},
+	Error,
+};
+
+use sd_core_file_path_helper::IsolatedFilePathData;
+use sd_core_prisma_helpers::file_path_for_integrity_verifier;
+use sd_core_sync::Manager as SyncManager;
+
+use sd_prisma::{
+	prisma::{file_path, location, PrismaClient},
+	prisma_sync,
+};
+use sd_sync::OperationFactory;
+use sd_task_system::{
+	check_interruption, ExecStatus, Interrupter, IntoAnyTaskOutput, SerializableTask, Task, TaskId,
+};
+use sd_utils::msgpack;
+
+use std::{
+	mem,
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::{Duration, SystemTime},
+};
+
+use chrono::{DateTime, FixedOffset, Utc};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::{fs, io, time::Instant};
+use tracing::{error, warn};
+
+/// Re-reads a batch of files, comparing their freshly computed `cas_id` with the stored one. Files
+/// modified since they were indexed are expected to differ, so they're flagged without being read.
+#[derive(Debug)]
+pub struct IntegrityVerifier {
+	id: TaskId,
+	location_id: location::id::Type,
+	location_path: Arc<PathBuf>,
+	file_paths: Vec<file_path_for_integrity_verifier::Data>,
+	cas_id_algorithm: CasIdAlgorithm,
+	deep_hash_threshold: Option<u64>,
+	io_throttle: IoThrottle,
+	statuses: Vec<(Vec<u8>, IntegrityStatus)>,
+	db: Arc<PrismaClient>,
+	sync: Arc<SyncManager>,
+	output: Output,
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize, Type)]
+pub enum NonCriticalError {
+	#[error("content doesn't match the stored cas_id: <file='{}', expected='{1}', found='{2}'>", .0.display())]
+	CasIdMismatch(PathBuf, String, String),
+	#[error("failed to read file for verification: <file='{}'>: {1}", .0.display())]
+	FailedToReadFile(PathBuf, String),
+	#[error("failed to construct isolated file path data: <file_path_id='{0}'>: {1}")]
+	FailedToConstructIsolatedFilePathData(file_path::id::Type, String),
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Output {
+	pub valid: u64,
+	pub mismatched: u64,
+	pub unreadable: u64,
+	#[serde(default)]
+	pub modified: u64,
+	pub verification_time: Duration,
+	pub db_write_time: Duration,
+	pub errors: Vec<crate::NonCriticalError>,
+}
+
+impl IntegrityVerifier {
+	#[must_use]
+	pub fn new(
+		location_id: location::id::Type,
+		location_path: Arc<PathBuf>,
+		file_paths: Vec<file_path_for_integrity_verifier::Data>,
+		cas_id_algorithm: CasIdAlgorithm,
+		deep_hash_threshold: Option<u64>,
+		db: Arc<PrismaClient>,
+		sync: Arc<SyncManager>,
+	) -> Self {
+		Self {
+			id: TaskId::new_v4(),
+			location_id,
+			location_path,
+			statuses: Vec::with_capacity(file_paths.len()),
+			file_paths,
+			cas_id_algorithm,
+			deep_hash_threshold,
+			io_throttle: IoThrottle::default(),
+			db,
+			sync,
+			output: Output::default(),
+		}
+	}
+
+	#[must_use]
+	pub fn with_io_throttle(mut self, io_throttle: IoThrottle) -> Self {
+		self.io_throttle = io_throttle;
+		self
+	}
+}
+
+#[async_trait::async_trait]
+impl Task<Error> for IntegrityVerifier {
+	fn id(&self) -> TaskId {
+		self.id
+	}
+
+	async fn run(&mut self, interrupter: &Interrupter) -> Result<ExecStatus, Error> {
+		let Self {
+			location_id,
+			location_path,
+			file_paths,
+			cas_id_algorithm,
+			deep_hash_threshold,
+			io_throttle,
+			statuses,
+			db,
+			sync,
+			output:
+				Output {
+					valid,
+					mismatched,
+					unreadable,
+					modified,
+					verification_time,
+					db_write_time,
+					errors,
+				},
+			..
+		} = self;
+
+		let start = Instant::now();
+
+		// Verified file paths are popped, so a resumed task only re-reads the remaining ones
+		while let Some(file_path) = file_paths.pop() {
+			let Some(expected_cas_id) = &file_path.cas_id else {
+				continue;
+			};
+
+			let path = match IsolatedFilePathData::try_from((*location_id, &file_path)) {
+				Ok(iso_file_path) => location_path.join(iso_file_path),
+				Err(e) => {
+					errors.push(
+						verify_integrity::NonCriticalError::from(
+							NonCriticalError::FailedToConstructIsolatedFilePathData(
+								file_path.id,
+								e.to_string(),
+							),
+						)
+						.into(),
+					);
+					continue;
+				}
+			};
+
+			let computed = compute_cas_id(
+				&path,
+				file_path.date_modified,
+				*cas_id_algorithm,
+				*deep_hash_threshold,
+				io_throttle,
+			)
+			.await;
+
+			let status = match computed {
+				Ok(None) => {
+					*modified += 1;
+					IntegrityStatus::Modified
+				}
+
+				Ok(Some(cas_id)) if &cas_id == expected_cas_id => {
+					*valid += 1;
+					IntegrityStatus::Valid
+				}
+
+				Ok(Some(cas_id)) => {
+					warn!(
+						"Integrity mismatch <path='{}', expected='{expected_cas_id}', found='{cas_id}'>",
+						path.display()
+					);
+					*mismatched += 1;
+					errors.push(
+						verify_integrity::NonCriticalError::from(NonCriticalError::CasIdMismatch(
+							path,
+							expected_cas_id.clone(),
+							cas_id,
+						))
+						.into(),
+					);
+					IntegrityStatus::Mismatch
+				}
+
+				Err(e) => {
+					error!("Failed to read file <path='{}'>: {e:#?}", path.display());
+					*unreadable += 1;
+					errors.push(
+						verify_integrity::NonCriticalError::from(
+							NonCriticalError::FailedToReadFile(path, e.to_string()),
+						)
+						.into(),
+					);
+					IntegrityStatus::Unreadable
+				}
+			};
+
+			statuses.push((file_path.pub_id, status));
+
+			check_interruption!(interrupter, start, verification_time);
+		}
+
+		*verification_time += start.elapsed();
+
+		let start = Instant::now();
+		save_statuses(mem::take(statuses), db, sync).await?;
+		*db_write_time = start.elapsed();
+
+		Ok(ExecStatus::Done(mem::take(&mut self.output).into_output()))
+	}
+}
+
+/// `None` when the file was modified since it was indexed, as its stored `cas_id` is stale
+async fn compute_cas_id(
+	path: &Path,
+	indexed_date_modified: Option<DateTime<FixedOffset>>,
+	cas_id_algorithm: CasIdAlgorithm,
+	deep_hash_threshold: Option<u64>,
+	io_throttle: &IoThrottle,
+) -> Result<Option<String>, io::Error> {
+	let metadata = fs::metadata(path).await?;
+
+	if modified_since(indexed_date_modified, metadata.modified().ok()) {
+		return Ok(None);
+	}
+
+	let size = metadata.len();
+
+	generate_cas_id(
+		path,
+		size,
+		cas_id_algorithm.for_file_size(size, deep_hash_threshold),
+		io_throttle,
+	)
+	.await
+	.map(Some)
+}
+
+fn modified_since(
+	indexed_date_modified: Option<DateTime<FixedOffset>>,
+	modified: Option<SystemTime>,
+) -> bool {
+	indexed_date_modified
+		.zip(modified)
+		.map_or(false, |(indexed, modified)| {
+			(indexed.with_timezone(&Utc) - DateTime::<Utc>::from(modified))
+				.num_milliseconds()
+				.abs() >= MODIFIED_TOLERANCE_MS
+		})
+}
+
+async fn save_statuses(
+	statuses: Vec<(Vec<u8>, IntegrityStatus)>,
+	db: &PrismaClient,
+	sync: &SyncManager,
+) -> Result<(), verify_integrity::Error> {
+	if statuses.is_empty() {
+		return Ok(());
+	}
+
+	sync.write_ops(
+		db,
+		statuses
+			.into_iter()
+			.map(|(pub_id, status)| {
+				let status = status as i32;
+
+				(
+					sync.shared_update(
+						prisma_sync::file_path::SyncId {
+							pub_id: pub_id.clone(),
+						},
+						file_path::integrity_status::NAME,
+						msgpack!(status),
+					),
+					db.file_path()
+						.update(
+							file_path::pub_id::equals(pub_id),
+							vec![file_path::integrity_status::set(Some(status))],
+						)
+						.select(file_path::select!({ id })),
+				)
+			})
+			.unzip::<_, _, Vec<_>, Vec<_>>(),
+	)
+	.await?;
+
+	Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SaveState {
+	id: TaskId,
+	location_id: location::id::Type,
+	location_path: Arc<PathBuf>,
+	file_paths: Vec<file_path_for_integrity_verifier::Data>,
+	cas_id_algorithm: CasIdAlgorithm,
+	deep_hash_threshold: Option<u64>,
+	#[serde(default)]
+	io_throttle: IoThrottle,
+	statuses: Vec<(Vec<u8>, IntegrityStatus)>,
+	output: Output,
+}
+
+impl SerializableTask<Error> for IntegrityVerifier {
+	type SerializeError = rmp_serde::encode::Error;
+
+	type DeserializeError = rmp_serde::decode::Error;
+
+	type DeserializeCtx = (Arc<PrismaClient>, Arc<SyncManager>);
+
+	async fn serialize(self) -> Result<Vec<u8>, Self::SerializeError> {
+		let Self {
+			id,
+			location_id,
+			location_path,
+			file_paths,
+			cas_id_algorithm,
+			deep_hash_threshold,
+			io_throttle,
+			statuses,
+			output,
+			..
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState {
+			id,
+			location_id,
+			location_path,
+			file_paths,
+			cas_id_algorithm,
+			deep_hash_threshold,
+			io_throttle,
+			statuses,
+			output,
+		})
+	}
+
+	async fn deserialize(
+		data: &[u8],
+		(db, sync): Self::DeserializeCtx,
+	) -> Result<Self, Self::DeserializeError> {
+		rmp_serde::from_slice(data).map(
+			|SaveState {
+			     id,
+			     location_id,
+			     location_path,
+			     file_paths,
+			     cas_id_algorithm,
+			     deep_hash_threshold,
+			     io_throttle,
+			     statuses,
+			     output,
+			 }| Self {
+				id,
+				location_id,
+				location_path,
+				file_paths,
+				cas_id_algorithm,
+				deep_hash_threshold,
+				io_throttle,
+				statuses,
+				db,
+				sync,
+				output,
+			},
+		)
+	}
+}
@@ -0,0 +1,3 @@
+pub mod integrity_verifier;
+
+pub use integrity_verifier::IntegrityVerifier;
@@ -471,6 +471,15 @@ impl IndexerRuler {
 		indexer.extend(iter);
 	}
 
+	/// A new ruler with the rules of this one layered with the contents from an iterator of rules,
+	/// leaving this one untouched as it may be shared with other walkers
+	pub async fn layered(&self, iter: impl IntoIterator<Item = IndexerRule> + Send) -> Self {
+		let mut rules = self.rules.read().await.clone();
+		rules.extend(iter);
+
+		Self::new(rules)
+	}
+
 	pub async fn has_system(&self, rule: &SystemIndexerRule) -> bool {
 		let rules = self.rules.read().await;
 
@@ -869,6 +878,47 @@ mod tests {
 		assert!(check_rule(&rule, not_project).await);
 	}
 
+	#[tokio::test]
+	async fn test_sd_ignore() {
+		let root = tempdir().unwrap();
+
+		let project = root.path().join("project");
+		let other = root.path().join("other");
+
+		fs::create_dir_all(project.join("node_modules"))
+			.await
+			.unwrap();
+		fs::create_dir_all(other.join("node_modules"))
+			.await
+			.unwrap();
+
+		fs::write(root.path().join(seed::SD_IGNORE_FILE_NAME), "*.log\n")
+			.await
+			.unwrap();
+		fs::write(project.join(seed::SD_IGNORE_FILE_NAME), "node_modules/\n")
+			.await
+			.unwrap();
+
+		let project_rule = IndexerRule::from(
+			seed::SdIgnoreRules::get_rules(root.path(), &project)
+				.await
+				.unwrap()
+				.unwrap(),
+		);
+		let other_rule = IndexerRule::from(
+			seed::SdIgnoreRules::get_rules(root.path(), &other)
+				.await
+				.unwrap()
+				.unwrap(),
+		);
+
+		assert!(!check_rule(&project_rule, project.join("node_modules")).await);
+		assert!(!check_rule(&project_rule, project.join("debug.log")).await);
+		assert!(check_rule(&project_rule, project.join("main.rs")).await);
+		assert!(check_rule(&other_rule, other.join("node_modules")).await);
+		assert!(!check_rule(&other_rule, other.join("debug.log")).await);
+	}
+
 	impl PartialEq for RulePerKind {
 		fn eq(&self, other: &Self) -> bool {
 			match (self, other) {
@@ -125,6 +125,84 @@ impl From<GitIgnoreRules> for IndexerRule {
 	}
 }
 
+/// Ignore files, in gitignore syntax, that can be dropped in any directory of a location to keep
+/// entries below it out of the index, without touching the location settings
+pub const SD_IGNORE_FILE_NAME: &str = ".sdignore";
+
+#[derive(Debug)]
+pub struct SdIgnoreRules {
+	rules: RulePerKind,
+}
+
+impl SdIgnoreRules {
+	/// Collects the `.sdignore` files from `current` up to `location_root`, each one only applying
+	/// to the entries below its own directory, like `.gitignore` files
+	pub async fn get_rules(
+		location_root: &Path,
+		current: &Path,
+	) -> Option<Result<Self, SeederError>> {
+		let mut ignores = Vec::new();
+
+		for ancestor in current
+			.ancestors()
+			.take_while(|&path| path.starts_with(location_root))
+		{
+			let sd_ignore = ancestor.join(SD_IGNORE_FILE_NAME);
+
+			if matches!(fs::try_exists(&sd_ignore).await, Ok(true)) {
+				ignores.push(sd_ignore);
+			}
+		}
+
+		if ignores.is_empty() {
+			return None;
+		}
+
+		Some(Self::parse_sd_ignores(location_root.to_owned(), ignores).await)
+	}
+
+	async fn parse_sd_ignores(
+		location_root: PathBuf,
+		sd_ignores: Vec<PathBuf>,
+	) -> Result<Self, SeederError> {
+		tokio::task::spawn_blocking(move || {
+			let mut buf = Vec::with_capacity(30);
+			let mut search = Search::default();
+
+			// Ancestors were collected from the deepest one, but the last pattern lists take
+			// precedence, so the closest `.sdignore` can negate the patterns of the outer ones
+			for sd_ignore in sd_ignores.into_iter().rev() {
+				// Passing the location root so each list is based on its own directory
+				if let Some(patterns) =
+					List::from_file(sd_ignore, Some(&location_root), true, &mut buf)
+						.map_err(|_| SeederError::InhirentedExternalRules)?
+				{
+					search.patterns.push(patterns);
+				}
+			}
+
+			Ok(Self {
+				rules: RulePerKind::IgnoredByGit(location_root, search),
+			})
+		})
+		.await
+		.map_err(|_| SeederError::InhirentedExternalRules)?
+	}
+}
+
+impl From<SdIgnoreRules> for IndexerRule {
+	fn from(sd_ignore: SdIgnoreRules) -> Self {
+		Self {
+			id: None,
+			name: ".sdignore'd".to_owned(),
+			default: true,
+			date_created: Utc::now(),
+			date_modified: Utc::now(),
+			rules: vec![sd_ignore.rules],
+		}
+	}
+}
+
 #[derive(Debug)]
 pub struct SystemIndexerRule {
 	name: &'static str,
@@ -28,7 +28,7 @@
 #![forbid(deprecated_in_future)]
 #![allow(clippy::missing_errors_doc, clippy::module_name_repetitions)]
 
-use sd_prisma::prisma::{file_path, job, label, location, object};
+use sd_prisma::prisma::{face, file_path, job, label, location, object};
 
 // File Path selectables!
 file_path::select!(file_path_pub_id { pub_id });
@@ -46,6 +46,14 @@ file_path::select!(file_path_for_file_identifier {
 	name
 	extension
 	object_id
+	location_id
+	size_in_bytes_bytes
+});
+file_path::select!(file_path_for_statistics {
+	location_id
+	materialized_path
+	size_in_bytes_bytes
+	object: select { kind }
 });
 file_path::select!(file_path_for_object_validator {
 	pub_id
@@ -55,6 +63,90 @@ file_path::select!(file_path_for_object_validator {
 	extension
 	integrity_checksum
 });
+file_path::select!(file_path_for_integrity_verifier {
+	id
+	pub_id
+	materialized_path
+	is_dir
+	name
+	extension
+	cas_id
+	date_modified
+});
+file_path::select!(file_path_for_backup {
+	id
+	materialized_path
+	is_dir
+	name
+	extension
+	size_in_bytes_bytes
+	cas_id
+});
+file_path::select!(file_path_for_cold_archiver {
+	id
+	pub_id
+	materialized_path
+	is_dir
+	name
+	extension
+	size_in_bytes_bytes
+	date_modified
+	object: select { date_accessed }
+});
+file_path::select!(file_path_for_folder_sync {
+	id
+	materialized_path
+	is_dir
+	name
+	extension
+	size_in_bytes_bytes
+	date_modified
+	cas_id
+});
+file_path::select!(file_path_for_file_copier {
+	id
+	materialized_path
+	is_dir
+	name
+	extension
+	size_in_bytes_bytes
+});
+file_path::select!(file_path_for_file_mover {
+	id
+	pub_id
+	materialized_path
+	is_dir
+	name
+	extension
+	size_in_bytes_bytes
+});
+file_path::select!(file_path_for_bulk_rename {
+	id
+	pub_id
+	materialized_path
+	is_dir
+	name
+	extension
+	date_created
+	object: select {
+		exif_data: select {
+			media_date
+			camera_data
+		}
+	}
+});
+file_path::select!(file_path_for_kind_reidentifier {
+	id
+	materialized_path
+	is_dir
+	name
+	extension
+	object: select {
+		pub_id
+		kind
+		custom_kind
+	}
+});
 file_path::select!(file_path_for_media_processor {
 	id
 	materialized_path
@@ -137,6 +229,19 @@ file_path::select!(file_path_to_full_path {
 		path
 	}
 });
+file_path::select!(file_path_for_duplicate_finder {
+	id
+	pub_id
+	location_id
+	materialized_path
+	is_dir
+	name
+	extension
+	cas_id
+	size_in_bytes_bytes
+	physical_size_bytes
+	object_id
+});
 
 // File Path includes!
 file_path::include!(file_path_with_object { object });
@@ -158,8 +263,9 @@ file_path::include!(file_path_for_frontend {
 
 // Object selectables!
 object::select!(object_for_file_identifier {
+	id
 	pub_id
-	file_paths: select { pub_id cas_id extension is_dir materialized_path name }
+	file_paths: select { pub_id cas_id extension is_dir materialized_path name location_id }
 });
 
 // Object includes!
@@ -242,11 +348,36 @@ impl From<location_with_indexer_rules::Data> for location::Data {
 			generate_preview_media: data.generate_preview_media,
 			sync_preview_media: data.sync_preview_media,
 			hidden: data.hidden,
+			is_snapshot: data.is_snapshot,
 			date_created: data.date_created,
+			identifier_rules: data.identifier_rules,
+			identifier_settings: data.identifier_settings,
 			scan_state: data.scan_state,
+			usn_journal_cursor: data.usn_journal_cursor,
+			network_share: data.network_share,
+			s3_config: data.s3_config,
+			webdav_config: data.webdav_config,
+			cloud_config: data.cloud_config,
+			mtp_config: data.mtp_config,
+			sync_policy: data.sync_policy,
+			versioning: data.versioning,
 			file_paths: None,
 			indexer_rules: None,
 			instance: None,
+			job_errors: None,
+			identification_statistics: None,
+			job_schedules: None,
+			job_history: None,
+			provider_hashes: None,
+			capacities: None,
+			trashed_file_paths: None,
+			folder_syncs_a: None,
+			folder_syncs_b: None,
+			file_versions: None,
+			archive_policy: None,
+			cold_archive_policies: None,
+			archive_stubs: None,
+			directory_usages: None,
 		}
 	}
 }
@@ -266,11 +397,36 @@ impl From<&location_with_indexer_rules::Data> for location::Data {
 			generate_preview_media: data.generate_preview_media,
 			sync_preview_media: data.sync_preview_media,
 			hidden: data.hidden,
+			is_snapshot: data.is_snapshot,
 			date_created: data.date_created,
+			identifier_rules: data.identifier_rules.clone(),
+			identifier_settings: data.identifier_settings.clone(),
 			scan_state: data.scan_state,
+			usn_journal_cursor: data.usn_journal_cursor.clone(),
+			network_share: data.network_share.clone(),
+			s3_config: data.s3_config.clone(),
+			webdav_config: data.webdav_config.clone(),
+			cloud_config: data.cloud_config.clone(),
+			mtp_config: data.mtp_config.clone(),
+			sync_policy: data.sync_policy,
+			versioning: data.versioning.clone(),
 			file_paths: None,
 			indexer_rules: None,
 			instance: None,
+			job_errors: None,
+			identification_statistics: None,
+			job_schedules: None,
+			job_history: None,
+			provider_hashes: None,
+			capacities: None,
+			trashed_file_paths: None,
+			folder_syncs_a: None,
+			folder_syncs_b: None,
+			file_versions: None,
+			archive_policy: None,
+			cold_archive_policies: None,
+			archive_stubs: None,
+			directory_usages: None,
 		}
 	}
 }
@@ -284,3 +440,15 @@ label::include!((take: i64) => label_with_objects {
 		}
 	}
 });
+
+// Face selectables!
+face::select!(face_for_frontend {
+	id
+	x
+	y
+	width
+	height
+	score
+	object_id
+	person: select { id name }
+});
@@ -1,481 +1,993 @@
-use std::future::Future;
+//! Generates the operations of the records that existed before sync was enabled, a page at a time
+//! so a backfill can be paused, resumed and spread over time, see [`backfill_page`].
+//!
+//! A backfill can also be limited to a [`Scope`], the records of a location whose sync policy
+//! started syncing them again.
 
 use sd_prisma::{
 	prisma::{
-		crdt_operation, exif_data, file_path, label, label_on_object, location, object, tag,
-		tag_on_object, PrismaClient, SortOrder,
+		collection, collection_on_object, crdt_operation, custom_field, custom_field_value,
+		exif_data, file_path, label, label_on_object, location, note_version, object, tag,
+		tag_on_object, tag_rule, PrismaClient, SortOrder,
 	},
 	prisma_sync,
 };
-use sd_sync::{option_sync_entry, OperationFactory};
+use sd_sync::{option_sync_entry, sync_entry, CRDTOperation, OperationFactory};
 use sd_utils::{chain_optional_iter, msgpack};
 
-use crate::crdt_op_unchecked_db;
+use prisma_client_rust::{
+	operator::{and, or},
+	QueryError,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{crdt_op_unchecked_db, SyncPolicy};
+
+/// Records of a model backfilled at once
+pub const PAGE_SIZE: usize = 1000;
+
+/// The models backfilled one after the other, in [`Stage::ORDER`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Stage {
+	Tags,
+	Locations,
+	Objects,
+	ExifData,
+	FilePaths,
+	TagsOnObjects,
+	Labels,
+	Collections,
+	CollectionsOnObjects,
+	NoteVersions,
+	CustomFields,
+	CustomFieldValues,
+	LabelsOnObjects,
+	TagRules,
+}
+
+impl Stage {
+	/// Records come before the relations and records that link to them
+	const ORDER: [Self; 14] = [
+		Self::Tags,
+		Self::Locations,
+		Self::Objects,
+		Self::ExifData,
+		Self::FilePaths,
+		Self::TagsOnObjects,
+		Self::Labels,
+		Self::Collections,
+		Self::CollectionsOnObjects,
+		Self::NoteVersions,
+		Self::CustomFields,
+		Self::CustomFieldValues,
+		Self::LabelsOnObjects,
+		Self::TagRules,
+	];
+
+	fn next(self, scope: Option<&Scope>) -> Option<Self> {
+		Self::ORDER
+			.iter()
+			.skip_while(|stage| **stage != self)
+			.skip(1)
+			.find(|stage| scope.map_or(true, |scope| scope.covers(**stage)))
+			.copied()
+	}
+
+	/// Model of the records of the stage, if they live in locations so sync policies apply to them
+	fn located_model(self) -> Option<u16> {
+		match self {
+			Self::Objects => Some(prisma_sync::object::MODEL_ID),
+			Self::ExifData => Some(prisma_sync::exif_data::MODEL_ID),
+			Self::FilePaths => Some(prisma_sync::file_path::MODEL_ID),
+			Self::TagsOnObjects => Some(prisma_sync::tag_on_object::MODEL_ID),
+			Self::CollectionsOnObjects => Some(prisma_sync::collection_on_object::MODEL_ID),
+			Self::CustomFieldValues => Some(prisma_sync::custom_field_value::MODEL_ID),
+			Self::LabelsOnObjects => Some(prisma_sync::label_on_object::MODEL_ID),
+			_ => None,
+		}
+	}
+
+	async fn count(self, db: &PrismaClient, scope: Option<&Scope>) -> Result<i64, QueryError> {
+		let objects = || scope.map(Scope::objects);
+
+		match self {
+			Self::Tags => db.tag().count(vec![]).exec().await,
+			Self::Locations => db.location().count(vec![]).exec().await,
+			Self::Objects => {
+				db.object()
+					.count(objects().into_iter().collect())
+					.exec()
+					.await
+			}
+			Self::ExifData => {
+				db.exif_data()
+					.count(chain_optional_iter(
+						[],
+						[objects().map(|objects| exif_data::object::is(vec![objects]))],
+					))
+					.exec()
+					.await
+			}
+			Self::FilePaths => {
+				db.file_path()
+					.count(scope.map(Scope::file_paths).into_iter().collect())
+					.exec()
+					.await
+			}
+			Self::TagsOnObjects => {
+				db.tag_on_object()
+					.count(chain_optional_iter(
+						[],
+						[objects().map(|objects| tag_on_object::object::is(vec![objects]))],
+					))
+					.exec()
+					.await
+			}
+			Self::Labels => db.label().count(vec![]).exec().await,
+			Self::Collections => db.collection().count(vec![]).exec().await,
+			Self::CollectionsOnObjects => {
+				db.collection_on_object()
+					.count(chain_optional_iter(
+						[],
+						[
+							objects()
+								.map(|objects| collection_on_object::object::is(vec![objects])),
+						],
+					))
+					.exec()
+					.await
+			}
+			Self::NoteVersions => db.note_version().count(vec![]).exec().await,
+			Self::CustomFields => db.custom_field().count(vec![]).exec().await,
+			Self::CustomFieldValues => {
+				db.custom_field_value()
+					.count(chain_optional_iter(
+						[],
+						[objects().map(|objects| custom_field_value::object::is(vec![objects]))],
+					))
+					.exec()
+					.await
+			}
+			Self::LabelsOnObjects => {
+				db.label_on_object()
+					.count(chain_optional_iter(
+						[],
+						[objects().map(|objects| label_on_object::object::is(vec![objects]))],
+					))
+					.exec()
+					.await
+			}
+			Self::TagRules => db.tag_rule().count(vec![]).exec().await,
+		}
+	}
+}
+
+/// The records of a location that its new sync policy syncs, but its previous one didn't
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Scope {
+	pub location_id: location::id::Type,
+	pub previous: SyncPolicy,
+	pub policy: SyncPolicy,
+}
+
+impl Scope {
+	fn covers(&self, stage: Stage) -> bool {
+		stage.located_model().map_or(false, |model| {
+			self.policy.syncs(model) && !self.previous.syncs(model)
+		})
+	}
+
+	/// Where the backfill starts, `None` if the new policy doesn't sync anything more
+	#[must_use]
+	pub fn start(&self) -> Option<Cursor> {
+		Stage::ORDER
+			.into_iter()
+			.find(|stage| self.covers(*stage))
+			.map(Cursor::start)
+	}
+
+	fn file_paths(&self) -> file_path::WhereParam {
+		file_path::location_id::equals(Some(self.location_id))
+	}
+
+	/// Objects are in scope if any of their file paths is, even when other locations already
+	/// synced them
+	fn objects(&self) -> object::WhereParam {
+		object::file_paths::some(vec![self.file_paths()])
+	}
+}
+
+/// Where a backfill is at, the last record backfilled of the current stage. Relations are keyed
+/// by the ids of both of their sides, other records only use the first one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cursor {
+	pub stage: Stage,
+	id: (i32, i32),
+}
+
+impl Cursor {
+	fn start(stage: Stage) -> Self {
+		Self {
+			stage,
+			id: (-1, -1),
+		}
+	}
+}
+
+impl Default for Cursor {
+	fn default() -> Self {
+		Self::start(Stage::Tags)
+	}
+}
+
+#[derive(Debug)]
+pub struct Page {
+	/// Records backfilled
+	pub records: usize,
+	/// Size of the operations written for them
+	pub bytes: usize,
+	/// Where to carry on from, `None` once every record was backfilled
+	pub next: Option<Cursor>,
+}
+
+/// Removes the operations of this instance, which a backfill generates again
+pub async fn clear(db: &PrismaClient, instance_id: i32) -> Result<(), QueryError> {
+	db.crdt_operation()
+		.delete_many(vec![crdt_operation::instance_id::equals(instance_id)])
+		.exec()
+		.await?;
+
+	Ok(())
+}
+
+/// How many records a backfill goes through
+pub async fn count(db: &PrismaClient, scope: Option<&Scope>) -> Result<u64, QueryError> {
+	let mut total = 0;
+
+	for stage in Stage::ORDER {
+		if scope.map_or(true, |scope| scope.covers(stage)) {
+			total += stage.count(db, scope).await?;
+		}
+	}
+
+	Ok(total as u64)
+}
+
+/// Generates the operations of the page of records after `cursor`
+pub async fn backfill_page(
+	db: &PrismaClient,
+	sync: &crate::Manager,
+	instance_id: i32,
+	cursor: Cursor,
+	scope: Option<&Scope>,
+) -> Result<Page, QueryError> {
+	let Cursor {
+		stage,
+		id: (group_id, item_id),
+	} = cursor;
+
+	let objects = || scope.map(Scope::objects);
 
-/// Takes all the syncable data in the database and generates CRDTOperations for it.
-/// This is a requirement before the library can sync.
-pub async fn backfill_operations(db: &PrismaClient, sync: &crate::Manager, instance_id: i32) {
 	let lock = sync.timestamp_lock.acquire().await;
 
-	db._transaction()
-		.with_timeout(9999999999)
-		.run(|db| async move {
-			println!("backfill started");
-			db.crdt_operation()
-				.delete_many(vec![crdt_operation::instance_id::equals(instance_id)])
+	let (ops, last_id, records): (Vec<CRDTOperation>, _, _) = match stage {
+		Stage::Tags => {
+			let tags = db
+				.tag()
+				.find_many(vec![tag::id::gt(group_id)])
+				.order_by(tag::id::order(SortOrder::Asc))
+				.take(PAGE_SIZE as i64)
 				.exec()
 				.await?;
 
-			paginate(
-				|cursor| {
-					db.tag()
-						.find_many(vec![tag::id::gt(cursor)])
-						.order_by(tag::id::order(SortOrder::Asc))
-						.exec()
-				},
-				|tag| tag.id,
-				|tags| {
-					db.crdt_operation()
-						.create_many(
-							tags.into_iter()
-								.flat_map(|t| {
-									sync.shared_create(
-										prisma_sync::tag::SyncId { pub_id: t.pub_id },
-										chain_optional_iter(
-											[],
-											[
-												t.name.map(|v| (tag::name::NAME, msgpack!(v))),
-												t.color.map(|v| (tag::color::NAME, msgpack!(v))),
-												t.date_created.map(|v| {
-													(tag::date_created::NAME, msgpack!(v))
-												}),
-												t.date_modified.map(|v| {
-													(tag::date_modified::NAME, msgpack!(v))
-												}),
-											],
-										),
-									)
-								})
-								.map(|o| crdt_op_unchecked_db(&o, instance_id))
-								.collect(),
+			(
+				tags.iter()
+					.cloned()
+					.flat_map(|t| {
+						sync.shared_create(
+							prisma_sync::tag::SyncId { pub_id: t.pub_id },
+							chain_optional_iter(
+								[],
+								[
+									t.name.map(|v| (tag::name::NAME, msgpack!(v))),
+									t.color.map(|v| (tag::color::NAME, msgpack!(v))),
+									t.date_created
+										.map(|v| (tag::date_created::NAME, msgpack!(v))),
+									t.date_modified
+										.map(|v| (tag::date_modified::NAME, msgpack!(v))),
+								],
+							),
 						)
-						.exec()
-				},
+					})
+					.collect(),
+				tags.last().map(|t| (t.id, -1)),
+				tags.len(),
 			)
-			.await?;
-
-			paginate(
-				|cursor| {
-					db.location()
-						.find_many(vec![location::id::gt(cursor)])
-						.order_by(location::id::order(SortOrder::Asc))
-						.take(1000)
-						.include(location::include!({
-							instance: select {
-								id
-								pub_id
-							}
-						}))
-						.exec()
-				},
-				|location| location.id,
-				|locations| {
-					db.crdt_operation()
-						.create_many(
-							locations
-								.into_iter()
-								.flat_map(|l| {
-									use location::*;
-
-									sync.shared_create(
-										prisma_sync::location::SyncId { pub_id: l.pub_id },
-										chain_optional_iter(
-											[],
-											[
-												option_sync_entry!(l.name, name),
-												option_sync_entry!(l.path, path),
-												option_sync_entry!(
-													l.total_capacity,
-													total_capacity
-												),
-												option_sync_entry!(
-													l.available_capacity,
-													available_capacity
-												),
-												option_sync_entry!(l.size_in_bytes, size_in_bytes),
-												option_sync_entry!(l.is_archived, is_archived),
-												option_sync_entry!(
-													l.generate_preview_media,
-													generate_preview_media
-												),
-												option_sync_entry!(
-													l.sync_preview_media,
-													sync_preview_media
-												),
-												option_sync_entry!(l.hidden, hidden),
-												option_sync_entry!(l.date_created, date_created),
-												option_sync_entry!(
-													l.instance.map(|i| {
-														prisma_sync::instance::SyncId {
-															pub_id: i.pub_id,
-														}
-													}),
-													instance
-												),
-											],
-										),
-									)
-								})
-								.map(|o| crdt_op_unchecked_db(&o, instance_id))
-								.collect(),
+		}
+
+		Stage::Locations => {
+			let locations = db
+				.location()
+				.find_many(vec![location::id::gt(group_id)])
+				.order_by(location::id::order(SortOrder::Asc))
+				.take(PAGE_SIZE as i64)
+				.include(location::include!({
+					instance: select {
+						id
+						pub_id
+					}
+				}))
+				.exec()
+				.await?;
+
+			(
+				locations
+					.iter()
+					.cloned()
+					.flat_map(|l| {
+						use location::*;
+
+						sync.shared_create(
+							prisma_sync::location::SyncId { pub_id: l.pub_id },
+							chain_optional_iter(
+								[],
+								[
+									option_sync_entry!(l.name, name),
+									option_sync_entry!(l.path, path),
+									option_sync_entry!(l.total_capacity, total_capacity),
+									option_sync_entry!(l.available_capacity, available_capacity),
+									option_sync_entry!(l.size_in_bytes, size_in_bytes),
+									option_sync_entry!(l.is_archived, is_archived),
+									option_sync_entry!(
+										l.generate_preview_media,
+										generate_preview_media
+									),
+									option_sync_entry!(l.sync_preview_media, sync_preview_media),
+									option_sync_entry!(l.hidden, hidden),
+									option_sync_entry!(l.identifier_rules, identifier_rules),
+									option_sync_entry!(l.date_created, date_created),
+									option_sync_entry!(
+										l.instance.map(|i| {
+											prisma_sync::instance::SyncId { pub_id: i.pub_id }
+										}),
+										instance
+									),
+								],
+							),
 						)
-						.exec()
-				},
+					})
+					.collect(),
+				locations.last().map(|l| (l.id, -1)),
+				locations.len(),
 			)
-			.await?;
-
-			paginate(
-				|cursor| {
-					db.object()
-						.find_many(vec![object::id::gt(cursor)])
-						.order_by(object::id::order(SortOrder::Asc))
-						.take(1000)
-						.exec()
-				},
-				|object| object.id,
-				|objects| {
-					db.crdt_operation()
-						.create_many(
-							objects
-								.into_iter()
-								.flat_map(|o| {
-									use object::*;
-
-									sync.shared_create(
-										prisma_sync::object::SyncId { pub_id: o.pub_id },
-										chain_optional_iter(
-											[],
-											[
-												option_sync_entry!(o.kind, kind),
-												option_sync_entry!(o.hidden, hidden),
-												option_sync_entry!(o.favorite, favorite),
-												option_sync_entry!(o.important, important),
-												option_sync_entry!(o.note, note),
-												option_sync_entry!(o.date_created, date_created),
-												option_sync_entry!(o.date_accessed, date_accessed),
-											],
-										),
-									)
-								})
-								.map(|o| crdt_op_unchecked_db(&o, instance_id))
-								.collect(),
+		}
+
+		Stage::Objects => {
+			let objects = db
+				.object()
+				.find_many(chain_optional_iter([object::id::gt(group_id)], [objects()]))
+				.order_by(object::id::order(SortOrder::Asc))
+				.take(PAGE_SIZE as i64)
+				.exec()
+				.await?;
+
+			(
+				objects
+					.iter()
+					.cloned()
+					.flat_map(|o| {
+						use object::*;
+
+						sync.shared_create(
+							prisma_sync::object::SyncId { pub_id: o.pub_id },
+							chain_optional_iter(
+								[],
+								[
+									option_sync_entry!(o.kind, kind),
+									option_sync_entry!(o.custom_kind, custom_kind),
+									option_sync_entry!(o.quick_metadata, quick_metadata),
+									option_sync_entry!(o.hidden, hidden),
+									option_sync_entry!(o.favorite, favorite),
+									option_sync_entry!(o.important, important),
+									option_sync_entry!(o.note, note),
+									option_sync_entry!(o.date_created, date_created),
+									option_sync_entry!(o.date_accessed, date_accessed),
+								],
+							),
 						)
-						.exec()
-				},
+					})
+					.collect(),
+				objects.last().map(|o| (o.id, -1)),
+				objects.len(),
 			)
-			.await?;
-
-			paginate(
-				|cursor| {
-					db.exif_data()
-						.find_many(vec![exif_data::id::gt(cursor)])
-						.order_by(exif_data::id::order(SortOrder::Asc))
-						.take(1000)
-						.include(exif_data::include!({
-							object: select { pub_id }
-						}))
-						.exec()
-				},
-				|o| o.id,
-				|media_datas| {
-					db.crdt_operation()
-						.create_many(
-							media_datas
-								.into_iter()
-								.flat_map(|md| {
-									use exif_data::*;
-
-									sync.shared_create(
-										prisma_sync::exif_data::SyncId {
-											object: prisma_sync::object::SyncId {
-												pub_id: md.object.pub_id,
-											},
-										},
-										chain_optional_iter(
-											[],
-											[
-												option_sync_entry!(md.resolution, resolution),
-												option_sync_entry!(md.media_date, media_date),
-												option_sync_entry!(
-													md.media_location,
-													media_location
-												),
-												option_sync_entry!(md.camera_data, camera_data),
-												option_sync_entry!(md.artist, artist),
-												option_sync_entry!(md.description, description),
-												option_sync_entry!(md.copyright, copyright),
-												option_sync_entry!(md.exif_version, exif_version),
-												option_sync_entry!(md.epoch_time, epoch_time),
-											],
-										),
-									)
-								})
-								.map(|o| crdt_op_unchecked_db(&o, instance_id))
-								.collect(),
+		}
+
+		Stage::ExifData => {
+			let media_datas = db
+				.exif_data()
+				.find_many(chain_optional_iter(
+					[exif_data::id::gt(group_id)],
+					[objects().map(|objects| exif_data::object::is(vec![objects]))],
+				))
+				.order_by(exif_data::id::order(SortOrder::Asc))
+				.take(PAGE_SIZE as i64)
+				.include(exif_data::include!({
+					object: select { pub_id }
+				}))
+				.exec()
+				.await?;
+
+			(
+				media_datas
+					.iter()
+					.cloned()
+					.flat_map(|md| {
+						use exif_data::*;
+
+						sync.shared_create(
+							prisma_sync::exif_data::SyncId {
+								object: prisma_sync::object::SyncId {
+									pub_id: md.object.pub_id,
+								},
+							},
+							chain_optional_iter(
+								[],
+								[
+									option_sync_entry!(md.resolution, resolution),
+									option_sync_entry!(md.media_date, media_date),
+									option_sync_entry!(md.media_location, media_location),
+									option_sync_entry!(md.latitude, latitude),
+									option_sync_entry!(md.longitude, longitude),
+									option_sync_entry!(md.city, city),
+									option_sync_entry!(md.country, country),
+									option_sync_entry!(md.camera_data, camera_data),
+									option_sync_entry!(md.artist, artist),
+									option_sync_entry!(md.description, description),
+									option_sync_entry!(md.copyright, copyright),
+									option_sync_entry!(md.exif_version, exif_version),
+									option_sync_entry!(md.epoch_time, epoch_time),
+								],
+							),
 						)
-						.exec()
-				},
+					})
+					.collect(),
+				media_datas.last().map(|md| (md.id, -1)),
+				media_datas.len(),
 			)
-			.await?;
-
-			paginate(
-				|cursor| {
-					db.file_path()
-						.find_many(vec![file_path::id::gt(cursor)])
-						.order_by(file_path::id::order(SortOrder::Asc))
-						.include(file_path::include!({
-							location: select { pub_id }
-							object: select { pub_id }
-						}))
-						.exec()
-				},
-				|o| o.id,
-				|file_paths| {
-					db.crdt_operation()
-						.create_many(
-							file_paths
-								.into_iter()
-								.flat_map(|fp| {
-									use file_path::*;
-
-									sync.shared_create(
-										prisma_sync::file_path::SyncId { pub_id: fp.pub_id },
-										chain_optional_iter(
-											[],
-											[
-												option_sync_entry!(fp.is_dir, is_dir),
-												option_sync_entry!(fp.cas_id, cas_id),
-												option_sync_entry!(
-													fp.integrity_checksum,
-													integrity_checksum
-												),
-												option_sync_entry!(
-													fp.location.map(|l| {
-														prisma_sync::location::SyncId {
-															pub_id: l.pub_id,
-														}
-													}),
-													location
-												),
-												option_sync_entry!(
-													fp.object.map(|o| {
-														prisma_sync::object::SyncId {
-															pub_id: o.pub_id,
-														}
-													}),
-													object
-												),
-												option_sync_entry!(
-													fp.materialized_path,
-													materialized_path
-												),
-												option_sync_entry!(fp.name, name),
-												option_sync_entry!(fp.extension, extension),
-												option_sync_entry!(fp.hidden, hidden),
-												option_sync_entry!(
-													fp.size_in_bytes_bytes,
-													size_in_bytes_bytes
-												),
-												option_sync_entry!(fp.inode, inode),
-												option_sync_entry!(fp.date_created, date_created),
-												option_sync_entry!(fp.date_modified, date_modified),
-												option_sync_entry!(fp.date_indexed, date_indexed),
-											],
-										),
-									)
-								})
-								.map(|o| crdt_op_unchecked_db(&o, instance_id))
-								.collect(),
+		}
+
+		Stage::FilePaths => {
+			let file_paths = db
+				.file_path()
+				.find_many(chain_optional_iter(
+					[file_path::id::gt(group_id)],
+					[scope.map(Scope::file_paths)],
+				))
+				.order_by(file_path::id::order(SortOrder::Asc))
+				.take(PAGE_SIZE as i64)
+				.include(file_path::include!({
+					location: select { pub_id }
+					object: select { pub_id }
+				}))
+				.exec()
+				.await?;
+
+			(
+				file_paths
+					.iter()
+					.cloned()
+					.flat_map(|fp| {
+						use file_path::*;
+
+						sync.shared_create(
+							prisma_sync::file_path::SyncId { pub_id: fp.pub_id },
+							chain_optional_iter(
+								[],
+								[
+									option_sync_entry!(fp.is_dir, is_dir),
+									option_sync_entry!(fp.cas_id, cas_id),
+									option_sync_entry!(fp.integrity_checksum, integrity_checksum),
+									option_sync_entry!(fp.integrity_status, integrity_status),
+									option_sync_entry!(
+										fp.location.map(|l| {
+											prisma_sync::location::SyncId { pub_id: l.pub_id }
+										}),
+										location
+									),
+									option_sync_entry!(
+										fp.object.map(|o| {
+											prisma_sync::object::SyncId { pub_id: o.pub_id }
+										}),
+										object
+									),
+									option_sync_entry!(fp.materialized_path, materialized_path),
+									option_sync_entry!(fp.name, name),
+									option_sync_entry!(fp.extension, extension),
+									option_sync_entry!(fp.hidden, hidden),
+									option_sync_entry!(fp.size_in_bytes_bytes, size_in_bytes_bytes),
+									option_sync_entry!(fp.physical_size_bytes, physical_size_bytes),
+									option_sync_entry!(
+										fp.allocated_size_bytes,
+										allocated_size_bytes
+									),
+									option_sync_entry!(fp.inode, inode),
+									option_sync_entry!(fp.link_target, link_target),
+									option_sync_entry!(fp.remote_only, remote_only),
+									option_sync_entry!(fp.in_archive, in_archive),
+									option_sync_entry!(
+										fp.identification_skipped,
+										identification_skipped
+									),
+									option_sync_entry!(fp.date_created, date_created),
+									option_sync_entry!(fp.date_modified, date_modified),
+									option_sync_entry!(fp.date_indexed, date_indexed),
+									option_sync_entry!(fp.date_trashed, date_trashed),
+								],
+							),
 						)
-						.exec()
-				},
+					})
+					.collect(),
+				file_paths.last().map(|fp| (fp.id, -1)),
+				file_paths.len(),
 			)
-			.await?;
+		}
 
-			paginate_relation(
-				|group_id, item_id| {
-					db.tag_on_object()
-						.find_many(vec![
-							tag_on_object::tag_id::gt(group_id),
+		Stage::TagsOnObjects => {
+			let tag_on_objects = db
+				.tag_on_object()
+				.find_many(chain_optional_iter(
+					[or(vec![
+						tag_on_object::tag_id::gt(group_id),
+						and(vec![
+							tag_on_object::tag_id::equals(group_id),
 							tag_on_object::object_id::gt(item_id),
-						])
-						.order_by(tag_on_object::tag_id::order(SortOrder::Asc))
-						.order_by(tag_on_object::object_id::order(SortOrder::Asc))
-						.include(tag_on_object::include!({
-							tag: select { pub_id }
-							object: select { pub_id }
-						}))
-						.exec()
-				},
-				|t_o| (t_o.tag_id, t_o.object_id),
-				|tag_on_objects| {
-					db.crdt_operation()
-						.create_many(
-							tag_on_objects
-								.into_iter()
-								.flat_map(|t_o| {
-									sync.relation_create(
-										prisma_sync::tag_on_object::SyncId {
-											tag: prisma_sync::tag::SyncId {
-												pub_id: t_o.tag.pub_id,
-											},
-											object: prisma_sync::object::SyncId {
-												pub_id: t_o.object.pub_id,
-											},
-										},
-										chain_optional_iter(
-											[],
-											[option_sync_entry!(
-												t_o.date_created,
-												tag_on_object::date_created
-											)],
-										),
-									)
-								})
-								.map(|o| crdt_op_unchecked_db(&o, instance_id))
-								.collect(),
+						]),
+					])],
+					[objects().map(|objects| tag_on_object::object::is(vec![objects]))],
+				))
+				.order_by(tag_on_object::tag_id::order(SortOrder::Asc))
+				.order_by(tag_on_object::object_id::order(SortOrder::Asc))
+				.take(PAGE_SIZE as i64)
+				.include(tag_on_object::include!({
+					tag: select { pub_id }
+					object: select { pub_id }
+				}))
+				.exec()
+				.await?;
+
+			(
+				tag_on_objects
+					.iter()
+					.cloned()
+					.flat_map(|t_o| {
+						sync.relation_create(
+							prisma_sync::tag_on_object::SyncId {
+								tag: prisma_sync::tag::SyncId {
+									pub_id: t_o.tag.pub_id,
+								},
+								object: prisma_sync::object::SyncId {
+									pub_id: t_o.object.pub_id,
+								},
+							},
+							chain_optional_iter(
+								[],
+								[option_sync_entry!(
+									t_o.date_created,
+									tag_on_object::date_created
+								)],
+							),
+						)
+					})
+					.collect(),
+				tag_on_objects.last().map(|t_o| (t_o.tag_id, t_o.object_id)),
+				tag_on_objects.len(),
+			)
+		}
+
+		Stage::Labels => {
+			let labels = db
+				.label()
+				.find_many(vec![label::id::gt(group_id)])
+				.order_by(label::id::order(SortOrder::Asc))
+				.take(PAGE_SIZE as i64)
+				.exec()
+				.await?;
+
+			(
+				labels
+					.iter()
+					.cloned()
+					.flat_map(|l| {
+						sync.shared_create(
+							prisma_sync::label::SyncId { name: l.name },
+							[
+								(label::date_created::NAME, msgpack!(l.date_created)),
+								(label::date_modified::NAME, msgpack!(l.date_modified)),
+							],
+						)
+					})
+					.collect(),
+				labels.last().map(|l| (l.id, -1)),
+				labels.len(),
+			)
+		}
+
+		Stage::Collections => {
+			let collections = db
+				.collection()
+				.find_many(vec![collection::id::gt(group_id)])
+				.order_by(collection::id::order(SortOrder::Asc))
+				.take(PAGE_SIZE as i64)
+				.exec()
+				.await?;
+
+			(
+				collections
+					.iter()
+					.cloned()
+					.flat_map(|c| {
+						sync.shared_create(
+							prisma_sync::collection::SyncId { pub_id: c.pub_id },
+							chain_optional_iter(
+								[],
+								[
+									option_sync_entry!(c.name, collection::name),
+									option_sync_entry!(c.description, collection::description),
+									option_sync_entry!(c.icon, collection::icon),
+									option_sync_entry!(c.query, collection::query),
+									option_sync_entry!(c.date_created, collection::date_created),
+									option_sync_entry!(c.date_modified, collection::date_modified),
+								],
+							),
+						)
+					})
+					.collect(),
+				collections.last().map(|c| (c.id, -1)),
+				collections.len(),
+			)
+		}
+
+		Stage::CollectionsOnObjects => {
+			let collection_on_objects = db
+				.collection_on_object()
+				.find_many(chain_optional_iter(
+					[or(vec![
+						collection_on_object::collection_id::gt(group_id),
+						and(vec![
+							collection_on_object::collection_id::equals(group_id),
+							collection_on_object::object_id::gt(item_id),
+						]),
+					])],
+					[objects().map(|objects| collection_on_object::object::is(vec![objects]))],
+				))
+				.order_by(collection_on_object::collection_id::order(SortOrder::Asc))
+				.order_by(collection_on_object::object_id::order(SortOrder::Asc))
+				.take(PAGE_SIZE as i64)
+				.include(collection_on_object::include!({
+					collection: select { pub_id }
+					object: select { pub_id }
+				}))
+				.exec()
+				.await?;
+
+			(
+				collection_on_objects
+					.iter()
+					.cloned()
+					.flat_map(|c_o| {
+						sync.relation_create(
+							prisma_sync::collection_on_object::SyncId {
+								collection: prisma_sync::collection::SyncId {
+									pub_id: c_o.collection.pub_id,
+								},
+								object: prisma_sync::object::SyncId {
+									pub_id: c_o.object.pub_id,
+								},
+							},
+							chain_optional_iter(
+								[],
+								[option_sync_entry!(
+									c_o.date_created,
+									collection_on_object::date_created
+								)],
+							),
+						)
+					})
+					.collect(),
+				collection_on_objects
+					.last()
+					.map(|c_o| (c_o.collection_id, c_o.object_id)),
+				collection_on_objects.len(),
+			)
+		}
+
+		Stage::NoteVersions => {
+			let versions = db
+				.note_version()
+				.find_many(vec![note_version::id::gt(group_id)])
+				.order_by(note_version::id::order(SortOrder::Asc))
+				.take(PAGE_SIZE as i64)
+				.include(note_version::include!({
+					object: select { pub_id }
+				}))
+				.exec()
+				.await?;
+
+			(
+				versions
+					.iter()
+					.cloned()
+					.flat_map(|v| {
+						sync.shared_create(
+							prisma_sync::note_version::SyncId { pub_id: v.pub_id },
+							chain_optional_iter(
+								[],
+								[
+									option_sync_entry!(v.content, note_version::content),
+									option_sync_entry!(v.parents, note_version::parents),
+									option_sync_entry!(
+										v.instance_pub_id,
+										note_version::instance_pub_id
+									),
+									option_sync_entry!(v.date_created, note_version::date_created),
+									option_sync_entry!(
+										v.object.map(|o| {
+											prisma_sync::object::SyncId { pub_id: o.pub_id }
+										}),
+										note_version::object
+									),
+								],
+							),
 						)
-						.exec()
-				},
+					})
+					.collect(),
+				versions.last().map(|v| (v.id, -1)),
+				versions.len(),
 			)
-			.await?;
-
-			paginate(
-				|cursor| {
-					db.label()
-						.find_many(vec![label::id::gt(cursor)])
-						.order_by(label::id::order(SortOrder::Asc))
-						.exec()
-				},
-				|label| label.id,
-				|labels| {
-					db.crdt_operation()
-						.create_many(
-							labels
-								.into_iter()
-								.flat_map(|l| {
-									sync.shared_create(
-										prisma_sync::label::SyncId { name: l.name },
-										[
-											(label::date_created::NAME, msgpack!(l.date_created)),
-											(label::date_modified::NAME, msgpack!(l.date_modified)),
-										],
-									)
-								})
-								.map(|o| crdt_op_unchecked_db(&o, instance_id))
-								.collect(),
+		}
+
+		Stage::CustomFields => {
+			let fields = db
+				.custom_field()
+				.find_many(vec![custom_field::id::gt(group_id)])
+				.order_by(custom_field::id::order(SortOrder::Asc))
+				.take(PAGE_SIZE as i64)
+				.exec()
+				.await?;
+
+			(
+				fields
+					.iter()
+					.cloned()
+					.flat_map(|f| {
+						sync.shared_create(
+							prisma_sync::custom_field::SyncId { pub_id: f.pub_id },
+							chain_optional_iter(
+								[],
+								[
+									option_sync_entry!(f.name, custom_field::name),
+									option_sync_entry!(f.kind, custom_field::kind),
+									option_sync_entry!(f.options, custom_field::options),
+									option_sync_entry!(f.date_created, custom_field::date_created),
+									option_sync_entry!(
+										f.date_modified,
+										custom_field::date_modified
+									),
+								],
+							),
 						)
-						.exec()
-				},
+					})
+					.collect(),
+				fields.last().map(|f| (f.id, -1)),
+				fields.len(),
+			)
+		}
+
+		Stage::CustomFieldValues => {
+			let custom_field_values = db
+				.custom_field_value()
+				.find_many(chain_optional_iter(
+					[or(vec![
+						custom_field_value::custom_field_id::gt(group_id),
+						and(vec![
+							custom_field_value::custom_field_id::equals(group_id),
+							custom_field_value::object_id::gt(item_id),
+						]),
+					])],
+					[objects().map(|objects| custom_field_value::object::is(vec![objects]))],
+				))
+				.order_by(custom_field_value::custom_field_id::order(SortOrder::Asc))
+				.order_by(custom_field_value::object_id::order(SortOrder::Asc))
+				.take(PAGE_SIZE as i64)
+				.include(custom_field_value::include!({
+					custom_field: select { pub_id }
+					object: select { pub_id }
+				}))
+				.exec()
+				.await?;
+
+			(
+				custom_field_values
+					.iter()
+					.cloned()
+					.flat_map(|c_v| {
+						let sync_id = prisma_sync::custom_field_value::SyncId {
+							custom_field: prisma_sync::custom_field::SyncId {
+								pub_id: c_v.custom_field.pub_id,
+							},
+							object: prisma_sync::object::SyncId {
+								pub_id: c_v.object.pub_id,
+							},
+						};
+
+						// Relations are created without their fields, so the value goes after it
+						let values = [
+							(custom_field_value::text::NAME, msgpack!(c_v.text)),
+							(custom_field_value::number::NAME, msgpack!(c_v.number)),
+							(
+								custom_field_value::date_modified::NAME,
+								msgpack!(c_v.date_modified),
+							),
+						]
+						.into_iter()
+						.map(|(field, value)| sync.relation_update(sync_id.clone(), field, value))
+						.collect::<Vec<_>>();
+
+						sync.relation_create(sync_id, []).into_iter().chain(values)
+					})
+					.collect(),
+				custom_field_values
+					.last()
+					.map(|c_v| (c_v.custom_field_id, c_v.object_id)),
+				custom_field_values.len(),
 			)
-			.await?;
+		}
 
-			let res = paginate_relation(
-				|group_id, item_id| {
-					db.label_on_object()
-						.find_many(vec![
-							label_on_object::label_id::gt(group_id),
+		Stage::LabelsOnObjects => {
+			let label_on_objects = db
+				.label_on_object()
+				.find_many(chain_optional_iter(
+					[or(vec![
+						label_on_object::label_id::gt(group_id),
+						and(vec![
+							label_on_object::label_id::equals(group_id),
 							label_on_object::object_id::gt(item_id),
-						])
-						.order_by(label_on_object::label_id::order(SortOrder::Asc))
-						.order_by(label_on_object::object_id::order(SortOrder::Asc))
-						.include(label_on_object::include!({
-							object: select { pub_id }
-							label: select { name }
-						}))
-						.exec()
-				},
-				|l_o| (l_o.label_id, l_o.object_id),
-				|label_on_objects| {
-					db.crdt_operation()
-						.create_many(
-							label_on_objects
-								.into_iter()
-								.flat_map(|l_o| {
-									sync.relation_create(
-										prisma_sync::label_on_object::SyncId {
-											label: prisma_sync::label::SyncId {
-												name: l_o.label.name,
-											},
-											object: prisma_sync::object::SyncId {
-												pub_id: l_o.object.pub_id,
-											},
-										},
-										[],
-									)
-								})
-								.map(|o| crdt_op_unchecked_db(&o, instance_id))
-								.collect(),
+						]),
+					])],
+					[objects().map(|objects| label_on_object::object::is(vec![objects]))],
+				))
+				.order_by(label_on_object::label_id::order(SortOrder::Asc))
+				.order_by(label_on_object::object_id::order(SortOrder::Asc))
+				.take(PAGE_SIZE as i64)
+				.include(label_on_object::include!({
+					object: select { pub_id }
+					label: select { name }
+				}))
+				.exec()
+				.await?;
+
+			(
+				label_on_objects
+					.iter()
+					.cloned()
+					.flat_map(|l_o| {
+						let sync_id = prisma_sync::label_on_object::SyncId {
+							label: prisma_sync::label::SyncId {
+								name: l_o.label.name,
+							},
+							object: prisma_sync::object::SyncId {
+								pub_id: l_o.object.pub_id,
+							},
+						};
+
+						// Relations are created without their fields, so the confidence of labels
+						// found by models goes after it
+						let confidence = l_o.confidence.map(|confidence| {
+							sync.relation_update(
+								sync_id.clone(),
+								label_on_object::confidence::NAME,
+								msgpack!(confidence),
+							)
+						});
+
+						sync.relation_create(sync_id, [])
+							.into_iter()
+							.chain(confidence)
+					})
+					.collect(),
+				label_on_objects
+					.last()
+					.map(|l_o| (l_o.label_id, l_o.object_id)),
+				label_on_objects.len(),
+			)
+		}
+
+		Stage::TagRules => {
+			let rules = db
+				.tag_rule()
+				.find_many(vec![tag_rule::id::gt(group_id)])
+				.order_by(tag_rule::id::order(SortOrder::Asc))
+				.take(PAGE_SIZE as i64)
+				.include(tag_rule::include!({
+					tag: select { pub_id }
+				}))
+				.exec()
+				.await?;
+
+			(
+				rules
+					.iter()
+					.cloned()
+					.flat_map(|r| {
+						use tag_rule::*;
+
+						sync.shared_create(
+							prisma_sync::tag_rule::SyncId { pub_id: r.pub_id },
+							chain_optional_iter(
+								[
+									sync_entry!(r.enabled, enabled),
+									sync_entry!(r.date_created, date_created),
+									sync_entry!(r.date_modified, date_modified),
+								],
+								[
+									option_sync_entry!(r.name, name),
+									option_sync_entry!(r.conditions, conditions),
+									option_sync_entry!(
+										r.tag
+											.map(|t| prisma_sync::tag::SyncId { pub_id: t.pub_id }),
+										tag
+									),
+								],
+							),
 						)
-						.exec()
-				},
+					})
+					.collect(),
+				rules.last().map(|r| (r.id, -1)),
+				rules.len(),
 			)
-			.await;
+		}
+	};
 
-			println!("backfill ended");
+	// Left out like they are when written, if their location's sync policy doesn't sync them
+	let ops = sync
+		.policies
+		.filter(db, ops)
+		.await?
+		.iter()
+		.map(|op| crdt_op_unchecked_db(op, instance_id))
+		.collect::<Vec<_>>();
 
-			res
-		})
-		.await
-		.unwrap();
+	let bytes = ops
+		.iter()
+		.map(|op| op.data.len() + op.record_id.len())
+		.sum();
+
+	db.crdt_operation().create_many(ops).exec().await?;
 
 	drop(lock);
+
+	Ok(Page {
+		records,
+		bytes,
+		next: match last_id {
+			Some(id) if records == PAGE_SIZE => Some(Cursor { stage, id }),
+			_ => stage.next(scope).map(Cursor::start),
+		},
+	})
 }
 
-async fn paginate<
-	T,
-	E: std::fmt::Debug,
-	TGetter: Future<Output = Result<Vec<T>, E>>,
-	TOperations: Future<Output = Result<i64, E>>,
->(
-	getter: impl Fn(i32) -> TGetter,
-	id: impl Fn(&T) -> i32,
-	operations: impl Fn(Vec<T>) -> TOperations,
-) -> Result<(), E> {
-	let mut next_cursor = Some(-1);
-	loop {
-		let Some(cursor) = next_cursor else {
-			break;
-		};
-
-		let items = getter(cursor).await?;
-		next_cursor = items.last().map(&id);
-		operations(items).await?;
-	}
+#[cfg(test)]
+mod tests {
+	use super::*;
 
-	Ok(())
-}
+	#[test]
+	fn stages_run_in_order_once() {
+		let mut stages = vec![Cursor::default().stage];
 
-async fn paginate_relation<
-	T,
-	E: std::fmt::Debug,
-	TGetter: Future<Output = Result<Vec<T>, E>>,
-	TOperations: Future<Output = Result<i64, E>>,
->(
-	getter: impl Fn(i32, i32) -> TGetter,
-	id: impl Fn(&T) -> (i32, i32),
-	operations: impl Fn(Vec<T>) -> TOperations,
-) -> Result<(), E> {
-	let mut next_cursor = Some((-1, -1));
-	loop {
-		let Some(cursor) = next_cursor else {
-			break;
-		};
-
-		let items = getter(cursor.0, cursor.1).await?;
-		next_cursor = items.last().map(&id);
-		operations(items).await?;
-	}
+		while let Some(next) = stages.last().and_then(|stage| stage.next()) {
+			stages.push(next);
+		}
 
-	Ok(())
+		assert_eq!(stages, Stage::ORDER);
+	}
 }
@@ -0,0 +1,201 @@
+//! Concurrent writes to the same field of a record, which sync settles by keeping the newest one.
+//!
+//! An instance can't know what another one had seen when it wrote, so only writes that lost to a
+//! newer write of another instance they didn't know about are logged: those of an older timestamp
+//! than the value they arrive to, see [`loses_to`]. They're logged by the instances that ingest
+//! them, and resolving one writes the picked value again, which syncs like any other write.
+
+use crate::db_operation::crdt_include;
+
+use sd_prisma::prisma::{sync_conflict, PrismaClient, SortOrder};
+use sd_sync::CRDTOperationData;
+
+use prisma_client_rust::chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use uhlc::NTP64;
+use uuid::Uuid;
+
+#[derive(Debug, Serialize, Type)]
+pub struct SyncConflict {
+	pub id: sync_conflict::id::Type,
+	pub model: u16,
+	#[specta(type = serde_json::Value)]
+	pub record_id: rmpv::Value,
+	pub field: String,
+	/// The write sync kept
+	pub winner: ConflictingWrite,
+	pub loser: ConflictingWrite,
+	pub date_detected: DateTime<FixedOffset>,
+	pub date_resolved: Option<DateTime<FixedOffset>>,
+}
+
+#[derive(Debug, Serialize, Type)]
+pub struct ConflictingWrite {
+	pub instance: Uuid,
+	#[specta(type = u32)]
+	pub timestamp: NTP64,
+	#[specta(type = serde_json::Value)]
+	pub value: rmpv::Value,
+}
+
+/// Which of the writes of a conflict to keep
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Type)]
+pub enum ConflictSide {
+	Winner,
+	Loser,
+}
+
+impl SyncConflict {
+	#[must_use]
+	pub fn from_db(data: sync_conflict::Data) -> Self {
+		let write = |instance: &[u8], timestamp: i64, value: &[u8]| ConflictingWrite {
+			instance: Uuid::from_slice(instance).unwrap(),
+			timestamp: NTP64(timestamp as u64),
+			value: rmp_serde::from_slice(value).unwrap(),
+		};
+
+		Self {
+			id: data.id,
+			model: data.model as u16,
+			record_id: rmp_serde::from_slice(&data.record_id).unwrap(),
+			winner: write(
+				&data.winner_instance_pub_id,
+				data.winner_timestamp,
+				&data.winner_value,
+			),
+			loser: write(
+				&data.loser_instance_pub_id,
+				data.loser_timestamp,
+				&data.loser_value,
+			),
+			field: data.field,
+			date_detected: data.date_detected,
+			date_resolved: data.date_resolved,
+		}
+	}
+
+	#[must_use]
+	pub fn kept(&self, side: ConflictSide) -> &ConflictingWrite {
+		match side {
+			ConflictSide::Winner => &self.winner,
+			ConflictSide::Loser => &self.loser,
+		}
+	}
+}
+
+/// Whether an update of `field` to `value` by `instance` lost to `newer`, a later write to the
+/// same field it couldn't have seen. Writes of the same value don't conflict.
+pub(crate) fn loses_to(
+	instance: Uuid,
+	field: &str,
+	value: &rmpv::Value,
+	newer: (Uuid, &CRDTOperationData),
+) -> bool {
+	let (newer_instance, newer_data) = newer;
+
+	newer_instance != instance
+		&& matches!(
+			newer_data,
+			CRDTOperationData::Update { field: newer_field, value: newer_value }
+				if newer_field == field && newer_value != value
+		)
+}
+
+/// The log entry of the update of `field` to `value` that lost to `newer`
+pub(crate) fn entry(
+	model: u16,
+	record_id: &rmpv::Value,
+	(instance, timestamp, field, value): (Uuid, NTP64, &str, &rmpv::Value),
+	newer: &crdt_include::Data,
+) -> sync_conflict::CreateUnchecked {
+	sync_conflict::CreateUnchecked {
+		model: i32::from(model),
+		record_id: rmp_serde::to_vec(record_id).unwrap(),
+		field: field.to_string(),
+		winner_instance_pub_id: newer.instance.pub_id.clone(),
+		winner_timestamp: newer.timestamp,
+		winner_value: newer_value(newer),
+		loser_instance_pub_id: instance.as_bytes().to_vec(),
+		loser_timestamp: timestamp.as_u64() as i64,
+		loser_value: rmp_serde::to_vec(value).unwrap(),
+		_params: vec![],
+	}
+}
+
+fn newer_value(newer: &crdt_include::Data) -> Vec<u8> {
+	match rmp_serde::from_slice(&newer.data).unwrap() {
+		CRDTOperationData::Update { value, .. } => rmp_serde::to_vec(&value).unwrap(),
+		_ => unreachable!("conflicts are only logged against updates"),
+	}
+}
+
+/// Unresolved conflicts first, then the most recently detected
+pub async fn list(db: &PrismaClient) -> prisma_client_rust::Result<Vec<SyncConflict>> {
+	let (unresolved, resolved) = db
+		.sync_conflict()
+		.find_many(vec![])
+		.order_by(sync_conflict::date_detected::order(SortOrder::Desc))
+		.exec()
+		.await?
+		.into_iter()
+		.map(SyncConflict::from_db)
+		.partition::<Vec<_>, _>(|conflict| conflict.date_resolved.is_none());
+
+	Ok(unresolved.into_iter().chain(resolved).collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn update(field: &str, value: &str) -> CRDTOperationData {
+		CRDTOperationData::Update {
+			field: field.to_string(),
+			value: rmpv::Value::from(value),
+		}
+	}
+
+	#[test]
+	fn writes_of_another_instance_conflict() {
+		let (local, remote) = (Uuid::new_v4(), Uuid::new_v4());
+
+		assert!(loses_to(
+			remote,
+			"name",
+			&rmpv::Value::from("Holiday"),
+			(local, &update("name", "Trip"))
+		));
+	}
+
+	#[test]
+	fn same_instance_or_value_or_field_doesnt_conflict() {
+		let (local, remote) = (Uuid::new_v4(), Uuid::new_v4());
+		let value = rmpv::Value::from("Holiday");
+
+		assert!(!loses_to(
+			local,
+			"name",
+			&value,
+			(local, &update("name", "Trip"))
+		));
+		assert!(!loses_to(
+			remote,
+			"name",
+			&value,
+			(local, &update("name", "Holiday"))
+		));
+		assert!(!loses_to(
+			remote,
+			"name",
+			&value,
+			(local, &update("note", "Trip"))
+		));
+		assert!(!loses_to(
+			remote,
+			"name",
+			&value,
+			(local, &CRDTOperationData::Delete)
+		));
+	}
+}
@@ -19,7 +19,8 @@ use uuid::Uuid;
 
 use crate::{
 	actor::{create_actor_io, ActorIO, ActorTypes},
-	db_operation::write_crdt_op_to_db,
+	conflict,
+	db_operation::{crdt_include, write_crdt_op_to_db},
 	wait, SharedState,
 };
 
@@ -361,6 +362,7 @@ impl Actor {
 									),
 								])
 								.order_by(crdt_operation::timestamp::order(SortOrder::Desc))
+								.include(crdt_include::include())
 						})
 						.collect::<Vec<_>>(),
 				))
@@ -370,13 +372,31 @@ impl Actor {
 				return Ok(());
 			}
 
+			let mut conflicts = vec![];
+
 			// does the same thing as processing ops one-by-one and returning early if a newer op was found
 			for (update, key) in updates
 				.into_iter()
 				.zip(data.keys().cloned().collect::<Vec<_>>())
 			{
-				if update.is_some() {
-					data.remove(&key);
+				if let Some(newer) = update {
+					if let Some((value, timestamp)) = data.remove(&key) {
+						let newer_data = rmp_serde::from_slice(&newer.data).unwrap();
+
+						if conflict::loses_to(
+							instance,
+							&key,
+							&value,
+							(newer.instance(), &newer_data),
+						) {
+							conflicts.push(conflict::entry(
+								model,
+								&record_id,
+								(instance, timestamp, &key, &value),
+								&newer,
+							));
+						}
+					}
 				}
 			}
 
@@ -400,6 +420,10 @@ impl Actor {
 					.exec(&db)
 					.await?;
 
+					for conflict in conflicts {
+						conflict.to_query(&db).exec().await?;
+					}
+
 					// need to only apply ops that haven't been filtered out
 					for (field, (value, timestamp)) in data {
 						write_crdt_op_to_db(
@@ -2,9 +2,11 @@
 
 mod actor;
 pub mod backfill;
+pub mod conflict;
 mod db_operation;
 pub mod ingest;
 mod manager;
+mod policy;
 
 use sd_prisma::prisma::{crdt_operation, instance, PrismaClient};
 use sd_sync::CRDTOperation;
@@ -16,6 +18,7 @@ use std::{
 
 pub use ingest::*;
 pub use manager::*;
+pub use policy::{Policies, SyncPolicy};
 pub use uhlc::NTP64;
 
 #[derive(Clone, Debug)]
@@ -1,7 +1,17 @@
-use crate::{crdt_op_db, db_operation::*, ingest, SharedState, SyncMessage, NTP64};
+use crate::{
+	conflict::{ConflictSide, SyncConflict},
+	crdt_op_db,
+	db_operation::*,
+	ingest, Policies, SharedState, SyncMessage, SyncPolicy, NTP64,
+};
 
-use sd_prisma::prisma::{cloud_crdt_operation, crdt_operation, instance, PrismaClient, SortOrder};
-use sd_sync::{CRDTOperation, OperationFactory};
+use sd_prisma::{
+	prisma::{
+		cloud_crdt_operation, crdt_operation, instance, sync_conflict, PrismaClient, SortOrder,
+	},
+	prisma_sync::{self, ModelSyncData},
+};
+use sd_sync::{CRDTOperation, CRDTOperationData, OperationFactory};
 use sd_utils::uuid_to_bytes;
 
 use std::{
@@ -15,7 +25,9 @@ use std::{
 	},
 };
 
+use prisma_client_rust::chrono::Utc;
 use tokio::sync::{broadcast, RwLock};
+use tracing::error;
 use uhlc::{HLCBuilder, HLC};
 use uuid::Uuid;
 
@@ -25,6 +37,8 @@ pub struct Manager {
 	pub ingest: ingest::Handler,
 	pub shared: Arc<SharedState>,
 	pub timestamp_lock: tokio::sync::Semaphore,
+	/// Locations that aren't fully synced, operations on their records are dropped as they're written
+	pub policies: Policies,
 }
 
 impl fmt::Debug for Manager {
@@ -70,12 +84,18 @@ impl Manager {
 
 		let ingest = ingest::Actor::declare(shared.clone()).await;
 
+		let policies = Policies::load(db).await.unwrap_or_else(|e| {
+			error!(?e, "Failed to load location sync policies;");
+			Policies::default()
+		});
+
 		New {
 			manager: Self {
 				tx,
 				ingest,
 				shared,
 				timestamp_lock: tokio::sync::Semaphore::new(1),
+				policies,
 			},
 			rx,
 		}
@@ -88,9 +108,11 @@ impl Manager {
 	pub async fn write_ops<'item, I: prisma_client_rust::BatchItem<'item>>(
 		&self,
 		tx: &PrismaClient,
-		(mut ops, queries): (Vec<CRDTOperation>, I),
+		(ops, queries): (Vec<CRDTOperation>, I),
 	) -> prisma_client_rust::Result<<I as prisma_client_rust::BatchItemParent>::ReturnValue> {
 		let ret = if self.emit_messages_flag.load(atomic::Ordering::Relaxed) {
+			let mut ops = self.policies.filter(tx, ops).await?;
+
 			let lock = self.timestamp_lock.acquire().await;
 
 			ops.iter_mut().for_each(|op| {
@@ -133,7 +155,9 @@ impl Manager {
 		mut op: CRDTOperation,
 		query: Q,
 	) -> prisma_client_rust::Result<<Q as prisma_client_rust::BatchItemParent>::ReturnValue> {
-		let ret = if self.emit_messages_flag.load(atomic::Ordering::Relaxed) {
+		let ret = if self.emit_messages_flag.load(atomic::Ordering::Relaxed)
+			&& !self.policies.filter(tx, vec![op.clone()]).await?.is_empty()
+		{
 			let lock = self.timestamp_lock.acquire().await;
 
 			op.timestamp = *self.get_clock().new_timestamp().get_time();
@@ -158,6 +182,155 @@ impl Manager {
 		Ok(ret)
 	}
 
+	/// Changes the sync policy of a location, returning the one it had. Operations of this instance
+	/// on records the location doesn't sync anymore are removed, and other instances are told to
+	/// delete them. Objects themselves are left alone on other instances, as they may be in other
+	/// locations there. Records of a location that syncs again must be backfilled, see
+	/// [`backfill::Scope`](crate::backfill::Scope).
+	pub async fn set_location_policy(
+		&self,
+		location_pub_id: Vec<u8>,
+		policy: SyncPolicy,
+	) -> prisma_client_rust::Result<SyncPolicy> {
+		const CHUNK_SIZE: usize = 1000;
+
+		let previous = self.policies.set(location_pub_id.clone(), policy).await;
+
+		let pruned = self
+			.policies
+			.prune(&self.db, self.instance, location_pub_id)
+			.await?;
+
+		let ops = pruned
+			.file_paths
+			.into_iter()
+			.map(|pub_id| self.shared_delete(prisma_sync::file_path::SyncId { pub_id }))
+			.chain(pruned.exif_data.into_iter().map(|pub_id| {
+				self.shared_delete(prisma_sync::exif_data::SyncId {
+					object: prisma_sync::object::SyncId { pub_id },
+				})
+			}))
+			.chain(pruned.labels_on_objects.into_iter().map(|(name, pub_id)| {
+				self.relation_delete(prisma_sync::label_on_object::SyncId {
+					label: prisma_sync::label::SyncId { name },
+					object: prisma_sync::object::SyncId { pub_id },
+				})
+			}))
+			.collect::<Vec<_>>();
+
+		if ops.is_empty() || !self.emit_messages_flag.load(atomic::Ordering::Relaxed) {
+			return Ok(previous);
+		}
+
+		// Unfiltered, as these records are exactly the ones the policy doesn't sync anymore
+		let lock = self.timestamp_lock.acquire().await;
+
+		for chunk in ops.chunks(CHUNK_SIZE) {
+			let chunk = chunk
+				.iter()
+				.cloned()
+				.map(|mut op| {
+					op.timestamp = *self.get_clock().new_timestamp().get_time();
+					op
+				})
+				.collect::<Vec<_>>();
+
+			self.db
+				._batch(
+					chunk
+						.iter()
+						.map(|op| crdt_op_db(op).to_query(&self.db))
+						.collect::<Vec<_>>(),
+				)
+				.await?;
+
+			if let Some(last) = chunk.last() {
+				self.shared
+					.timestamps
+					.write()
+					.await
+					.insert(self.instance, last.timestamp);
+			}
+		}
+
+		self.tx.send(SyncMessage::Created).ok();
+
+		drop(lock);
+
+		Ok(previous)
+	}
+
+	/// Resolves a conflict by writing the value of the kept write again, as a newer write that
+	/// every instance settles on
+	pub async fn resolve_conflict(
+		&self,
+		conflict: &SyncConflict,
+		side: ConflictSide,
+	) -> prisma_client_rust::Result<()> {
+		let mut op = CRDTOperation {
+			instance: self.instance,
+			timestamp: NTP64(0),
+			model: conflict.model,
+			record_id: conflict.record_id.clone(),
+			data: CRDTOperationData::Update {
+				field: conflict.field.clone(),
+				value: conflict.kept(side).value.clone(),
+			},
+		};
+
+		let syncs = self.emit_messages_flag.load(atomic::Ordering::Relaxed)
+			&& !self
+				.policies
+				.filter(&self.db, vec![op.clone()])
+				.await?
+				.is_empty();
+
+		let lock = self.timestamp_lock.acquire().await;
+
+		op.timestamp = *self.get_clock().new_timestamp().get_time();
+		let timestamp = op.timestamp;
+		let conflict_id = conflict.id;
+
+		self.db
+			._transaction()
+			.with_timeout(30 * 1000)
+			.run(|db| async move {
+				ModelSyncData::from_op(op.clone())
+					.unwrap()
+					.exec(&db)
+					.await?;
+
+				if syncs {
+					write_crdt_op_to_db(&op, &db).await?;
+				}
+
+				db.sync_conflict()
+					.update(
+						sync_conflict::id::equals(conflict_id),
+						vec![sync_conflict::date_resolved::set(Some(Utc::now().into()))],
+					)
+					.exec()
+					.await?;
+
+				Ok(())
+			})
+			.await?;
+
+		if syncs {
+			self.shared
+				.timestamps
+				.write()
+				.await
+				.insert(self.instance, timestamp);
+
+			self.tx.send(SyncMessage::Created).ok();
+		}
+
+		drop(lock);
+
+		Ok(())
+	}
+
 	pub async fn get_instance_ops(
 		&self,
 		count: u32,
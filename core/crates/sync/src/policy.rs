@@ -0,0 +1,490 @@
+//! Per location sync policies, so a huge scratch location on one device doesn't flood the
+//! databases of every other one.
+//!
+//! Operations are filtered as they're written, see [`Manager::write_ops`](crate::Manager), by
+//! finding the locations the records they change live in. Objects are synced if any of their
+//! locations allows it.
+
+use sd_prisma::{
+	prisma::{
+		crdt_operation, file_path, instance, label_on_object, location, object, PrismaClient,
+		SortOrder,
+	},
+	prisma_sync,
+};
+use sd_sync::{CRDTOperation, CRDTOperationData};
+use sd_utils::uuid_to_bytes;
+
+use std::collections::{HashMap, HashSet};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use specta::Type;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Type)]
+pub enum SyncPolicy {
+	/// Everything about the files of the location is synced
+	#[default]
+	Full,
+	/// The files of the location are synced, but not what was extracted from their contents, like
+	/// media metadata and labels
+	MetadataOnly,
+	/// Nothing about the files of the location leaves this device
+	Excluded,
+}
+
+impl SyncPolicy {
+	/// Models only synced with [`SyncPolicy::Full`]
+	const CONTENT_MODELS: [u16; 2] = [
+		prisma_sync::exif_data::MODEL_ID,
+		prisma_sync::label_on_object::MODEL_ID,
+	];
+
+	#[must_use]
+	pub fn from_db(value: Option<i32>) -> Self {
+		match value {
+			Some(1) => Self::MetadataOnly,
+			Some(2) => Self::Excluded,
+			_ => Self::Full,
+		}
+	}
+
+	#[must_use]
+	pub fn to_db(self) -> Option<i32> {
+		match self {
+			Self::Full => None,
+			Self::MetadataOnly => Some(1),
+			Self::Excluded => Some(2),
+		}
+	}
+
+	/// Whether operations on `model` are synced for records in locations with this policy
+	#[must_use]
+	pub fn syncs(self, model: u16) -> bool {
+		match self {
+			Self::Full => true,
+			Self::MetadataOnly => !Self::CONTENT_MODELS.contains(&model),
+			Self::Excluded => false,
+		}
+	}
+}
+
+/// What other instances must drop after the policy of a location changed
+#[derive(Debug, Default)]
+pub(crate) struct Pruned {
+	pub file_paths: Vec<Vec<u8>>,
+	/// Objects whose media metadata isn't synced anymore
+	pub exif_data: Vec<Vec<u8>>,
+	/// Label name and object `pub_id` of labels that aren't synced anymore
+	pub labels_on_objects: Vec<(String, Vec<u8>)>,
+}
+
+/// Policies of the locations that aren't fully synced, by location `pub_id`
+#[derive(Debug, Default)]
+pub struct Policies(RwLock<HashMap<Vec<u8>, SyncPolicy>>);
+
+impl Policies {
+	pub(crate) async fn load(db: &PrismaClient) -> prisma_client_rust::Result<Self> {
+		Ok(Self(RwLock::new(
+			db.location()
+				.find_many(vec![location::sync_policy::not(None)])
+				.select(location::select!({ pub_id sync_policy }))
+				.exec()
+				.await?
+				.into_iter()
+				.map(|location| (location.pub_id, SyncPolicy::from_db(location.sync_policy)))
+				.collect(),
+		)))
+	}
+
+	/// Returns the policy the location had
+	pub(crate) async fn set(&self, location_pub_id: Vec<u8>, policy: SyncPolicy) -> SyncPolicy {
+		let mut policies = self.0.write().await;
+
+		if policy == SyncPolicy::Full {
+			policies.remove(&location_pub_id)
+		} else {
+			policies.insert(location_pub_id, policy)
+		}
+		.unwrap_or_default()
+	}
+
+	/// The operations that may be synced, in the same order
+	pub(crate) async fn filter(
+		&self,
+		db: &PrismaClient,
+		ops: Vec<CRDTOperation>,
+	) -> prisma_client_rust::Result<Vec<CRDTOperation>> {
+		let policies = self.0.read().await;
+
+		if policies.is_empty() {
+			return Ok(ops);
+		}
+
+		let locations = Locations::of(db, &ops).await?;
+
+		Ok(ops
+			.into_iter()
+			.filter(|op| {
+				let syncs = |location_pub_id: &Vec<u8>| {
+					policies
+						.get(location_pub_id)
+						.map_or(true, |policy| policy.syncs(op.model))
+				};
+
+				match Record::of(op) {
+					Some(Record::FilePath(pub_id)) => {
+						locations.file_paths.get(&pub_id).map_or(true, syncs)
+					}
+					Some(Record::Object(pub_id)) => locations
+						.objects
+						.get(&pub_id)
+						.map_or(true, |location_pub_ids| location_pub_ids.iter().any(syncs)),
+					None => true,
+				}
+			})
+			.collect())
+	}
+
+	/// Removes the operations of this instance on records the policy of a location doesn't sync
+	/// anymore, so they aren't sent to instances that didn't get them yet
+	pub(crate) async fn prune(
+		&self,
+		db: &PrismaClient,
+		instance: Uuid,
+		location_pub_id: Vec<u8>,
+	) -> prisma_client_rust::Result<Pruned> {
+		const PAGE_SIZE: i64 = 10_000;
+
+		let policies = self.0.read().await;
+
+		let Some(policy) = policies.get(&location_pub_id).copied() else {
+			return Ok(Pruned::default());
+		};
+
+		let file_paths = db
+			.file_path()
+			.find_many(vec![file_path::location::is(vec![
+				location::pub_id::equals(location_pub_id),
+			])])
+			.select(file_path::select!({ pub_id object: select { pub_id } }))
+			.exec()
+			.await?;
+
+		let object_pub_ids = file_paths
+			.iter()
+			.filter_map(|file_path| file_path.object.as_ref())
+			.map(|object| object.pub_id.clone())
+			.collect::<HashSet<_>>();
+
+		let mut object_locations = HashMap::<Vec<u8>, HashSet<Vec<u8>>>::new();
+		for file_path in db
+			.file_path()
+			.find_many(vec![file_path::object::is(vec![object::pub_id::in_vec(
+				object_pub_ids.iter().cloned().collect(),
+			)])])
+			.select(file_path::select!({ location: select { pub_id } object: select { pub_id } }))
+			.exec()
+			.await?
+		{
+			if let (Some(location), Some(object)) = (file_path.location, file_path.object) {
+				object_locations
+					.entry(object.pub_id)
+					.or_default()
+					.insert(location.pub_id);
+			}
+		}
+
+		let object_syncs = |object_pub_id: &Vec<u8>, model: u16| {
+			object_locations
+				.get(object_pub_id)
+				.map_or(true, |location_pub_ids| {
+					location_pub_ids.iter().any(|location_pub_id| {
+						policies
+							.get(location_pub_id)
+							.map_or(true, |policy| policy.syncs(model))
+					})
+				})
+		};
+
+		let pruned_file_paths = if policy.syncs(prisma_sync::file_path::MODEL_ID) {
+			HashSet::new()
+		} else {
+			file_paths
+				.into_iter()
+				.map(|file_path| file_path.pub_id)
+				.collect::<HashSet<_>>()
+		};
+
+		let mut ids = vec![];
+		let mut cursor = 0;
+
+		loop {
+			let ops = db
+				.crdt_operation()
+				.find_many(vec![
+					crdt_operation::instance::is(vec![instance::pub_id::equals(uuid_to_bytes(
+						instance,
+					))]),
+					crdt_operation::model::in_vec(
+						OBJECT_MODELS
+							.iter()
+							.chain([&prisma_sync::file_path::MODEL_ID])
+							.map(|model| i32::from(*model))
+							.collect(),
+					),
+					crdt_operation::id::gt(cursor),
+				])
+				.order_by(crdt_operation::id::order(SortOrder::Asc))
+				.take(PAGE_SIZE)
+				.select(crdt_operation::select!({ id model record_id }))
+				.exec()
+				.await?;
+
+			let Some(last) = ops.last() else {
+				break;
+			};
+			cursor = last.id;
+
+			ids.extend(ops.into_iter().filter_map(|op| {
+				let model = u16::try_from(op.model).ok()?;
+				let record_id = rmp_serde::from_slice::<rmpv::Value>(&op.record_id).ok()?;
+
+				match Record::from_record_id(model, &record_id)? {
+					Record::FilePath(pub_id) => pruned_file_paths.contains(&pub_id),
+					Record::Object(pub_id) => {
+						object_pub_ids.contains(&pub_id) && !object_syncs(&pub_id, model)
+					}
+				}
+				.then_some(op.id)
+			}));
+		}
+
+		for ids in ids.chunks(PAGE_SIZE as usize) {
+			db.crdt_operation()
+				.delete_many(vec![crdt_operation::id::in_vec(ids.to_vec())])
+				.exec()
+				.await?;
+		}
+
+		let unlabeled_objects = object_pub_ids
+			.iter()
+			.filter(|pub_id| !object_syncs(pub_id, prisma_sync::label_on_object::MODEL_ID))
+			.cloned()
+			.collect::<Vec<_>>();
+
+		let labels_on_objects = db
+			.label_on_object()
+			.find_many(vec![label_on_object::object::is(vec![
+				object::pub_id::in_vec(unlabeled_objects),
+			])])
+			.select(label_on_object::select!({ label: select { name } object: select { pub_id } }))
+			.exec()
+			.await?
+			.into_iter()
+			.map(|label_on_object| (label_on_object.label.name, label_on_object.object.pub_id))
+			.collect();
+
+		Ok(Pruned {
+			file_paths: pruned_file_paths.into_iter().collect(),
+			exif_data: object_pub_ids
+				.iter()
+				.filter(|pub_id| !object_syncs(pub_id, prisma_sync::exif_data::MODEL_ID))
+				.cloned()
+				.collect(),
+			labels_on_objects,
+		})
+	}
+}
+
+/// Models of records that belong to an object
+const OBJECT_MODELS: [u16; 6] = [
+	prisma_sync::object::MODEL_ID,
+	prisma_sync::exif_data::MODEL_ID,
+	prisma_sync::tag_on_object::MODEL_ID,
+	prisma_sync::label_on_object::MODEL_ID,
+	prisma_sync::collection_on_object::MODEL_ID,
+	prisma_sync::custom_field_value::MODEL_ID,
+];
+
+/// The record of an operation that lives in a location
+enum Record {
+	FilePath(Vec<u8>),
+	Object(Vec<u8>),
+}
+
+impl Record {
+	fn of(op: &CRDTOperation) -> Option<Self> {
+		Self::from_record_id(op.model, &op.record_id)
+	}
+
+	fn from_record_id(model: u16, record_id: &rmpv::Value) -> Option<Self> {
+		fn decode<T: DeserializeOwned>(record_id: &rmpv::Value) -> Option<T> {
+			rmpv::ext::from_value(record_id.clone()).ok()
+		}
+
+		match model {
+			prisma_sync::file_path::MODEL_ID => decode::<prisma_sync::file_path::SyncId>(record_id)
+				.map(|id| Self::FilePath(id.pub_id)),
+			prisma_sync::object::MODEL_ID => {
+				decode::<prisma_sync::object::SyncId>(record_id).map(|id| Self::Object(id.pub_id))
+			}
+			prisma_sync::exif_data::MODEL_ID => decode::<prisma_sync::exif_data::SyncId>(record_id)
+				.map(|id| Self::Object(id.object.pub_id)),
+			prisma_sync::tag_on_object::MODEL_ID => {
+				decode::<prisma_sync::tag_on_object::SyncId>(record_id)
+					.map(|id| Self::Object(id.object.pub_id))
+			}
+			prisma_sync::label_on_object::MODEL_ID => {
+				decode::<prisma_sync::label_on_object::SyncId>(record_id)
+					.map(|id| Self::Object(id.object.pub_id))
+			}
+			prisma_sync::collection_on_object::MODEL_ID => {
+				decode::<prisma_sync::collection_on_object::SyncId>(record_id)
+					.map(|id| Self::Object(id.object.pub_id))
+			}
+			prisma_sync::custom_field_value::MODEL_ID => {
+				decode::<prisma_sync::custom_field_value::SyncId>(record_id)
+					.map(|id| Self::Object(id.object.pub_id))
+			}
+			_ => None,
+		}
+	}
+}
+
+/// Locations of the records changed by a batch of operations, including the ones it creates
+#[derive(Default)]
+struct Locations {
+	file_paths: HashMap<Vec<u8>, Vec<u8>>,
+	objects: HashMap<Vec<u8>, HashSet<Vec<u8>>>,
+}
+
+impl Locations {
+	async fn of(db: &PrismaClient, ops: &[CRDTOperation]) -> prisma_client_rust::Result<Self> {
+		let mut this = Self::default();
+
+		let mut file_path_pub_ids = HashSet::new();
+		let mut object_pub_ids = HashSet::new();
+		// Objects linked to file paths by this batch, which the database doesn't know about yet
+		let mut linked_objects = vec![];
+
+		for op in ops {
+			match Record::of(op) {
+				Some(Record::FilePath(pub_id)) => {
+					for (field, value) in fields(&op.data) {
+						match field {
+							"location" => {
+								if let Ok(location) = rmpv::ext::from_value::<
+									prisma_sync::location::SyncId,
+								>(value.clone())
+								{
+									this.file_paths.insert(pub_id.clone(), location.pub_id);
+								}
+							}
+							"object" => {
+								if let Ok(object) = rmpv::ext::from_value::<
+									prisma_sync::object::SyncId,
+								>(value.clone())
+								{
+									linked_objects.push((pub_id.clone(), object.pub_id));
+								}
+							}
+							_ => {}
+						}
+					}
+
+					file_path_pub_ids.insert(pub_id);
+				}
+				Some(Record::Object(pub_id)) => {
+					object_pub_ids.insert(pub_id);
+				}
+				None => {}
+			}
+		}
+
+		let unknown_file_paths = file_path_pub_ids
+			.into_iter()
+			.filter(|pub_id| !this.file_paths.contains_key(pub_id))
+			.collect::<Vec<_>>();
+
+		let (file_paths, objects) = db
+			._batch((
+				db.file_path()
+					.find_many(vec![file_path::pub_id::in_vec(unknown_file_paths)])
+					.select(file_path::select!({ pub_id location: select { pub_id } })),
+				db.file_path()
+					.find_many(vec![file_path::object::is(vec![object::pub_id::in_vec(
+						object_pub_ids.into_iter().collect(),
+					)])])
+					.select(file_path::select!({
+						location: select { pub_id }
+						object: select { pub_id }
+					})),
+			))
+			.await?;
+
+		for file_path in file_paths {
+			if let Some(location) = file_path.location {
+				this.file_paths.insert(file_path.pub_id, location.pub_id);
+			}
+		}
+
+		for file_path in objects {
+			if let (Some(location), Some(object)) = (file_path.location, file_path.object) {
+				this.objects
+					.entry(object.pub_id)
+					.or_default()
+					.insert(location.pub_id);
+			}
+		}
+
+		for (file_path_pub_id, object_pub_id) in linked_objects {
+			if let Some(location_pub_id) = this.file_paths.get(&file_path_pub_id) {
+				this.objects
+					.entry(object_pub_id)
+					.or_default()
+					.insert(location_pub_id.clone());
+			}
+		}
+
+		Ok(this)
+	}
+}
+
+fn fields(data: &CRDTOperationData) -> Vec<(&str, &rmpv::Value)> {
+	match data {
+		CRDTOperationData::Create(values) => values
+			.iter()
+			.map(|(field, value)| (field.as_str(), value))
+			.collect(),
+		CRDTOperationData::Update { field, value } => vec![(field.as_str(), value)],
+		CRDTOperationData::Delete => vec![],
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn policies_round_trip_the_database() {
+		for policy in [
+			SyncPolicy::Full,
+			SyncPolicy::MetadataOnly,
+			SyncPolicy::Excluded,
+		] {
+			assert_eq!(SyncPolicy::from_db(policy.to_db()), policy);
+		}
+	}
+
+	#[test]
+	fn metadata_only_keeps_file_paths_but_not_their_contents() {
+		let policy = SyncPolicy::MetadataOnly;
+
+		assert!(policy.syncs(prisma_sync::file_path::MODEL_ID));
+		assert!(policy.syncs(prisma_sync::object::MODEL_ID));
+		assert!(!policy.syncs(prisma_sync::exif_data::MODEL_ID));
+		assert!(!SyncPolicy::Excluded.syncs(prisma_sync::file_path::MODEL_ID));
+	}
+}
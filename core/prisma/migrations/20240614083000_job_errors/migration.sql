@@ -0,0 +1,18 @@
+-- CreateTable
+CREATE TABLE "job_error" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "job_name" TEXT,
+    "error" BLOB,
+    "message" TEXT,
+    "location_id" INTEGER,
+    "file_path_id" INTEGER,
+    "date_created" DATETIME,
+    CONSTRAINT "job_error_location_id_fkey" FOREIGN KEY ("location_id") REFERENCES "location" ("id") ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT "job_error_file_path_id_fkey" FOREIGN KEY ("file_path_id") REFERENCES "file_path" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE INDEX "job_error_location_id_idx" ON "job_error"("location_id");
+
+-- CreateIndex
+CREATE INDEX "job_error_file_path_id_idx" ON "job_error"("file_path_id");
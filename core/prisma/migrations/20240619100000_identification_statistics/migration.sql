@@ -0,0 +1,13 @@
+-- CreateTable
+CREATE TABLE "identification_statistics" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "location_id" INTEGER NOT NULL,
+    "kind" INTEGER NOT NULL,
+    "identified_count" INTEGER NOT NULL DEFAULT 0,
+    "empty_file_count" INTEGER NOT NULL DEFAULT 0,
+    "total_bytes" BIGINT NOT NULL DEFAULT 0,
+    CONSTRAINT "identification_statistics_location_id_fkey" FOREIGN KEY ("location_id") REFERENCES "location" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "identification_statistics_location_id_kind_key" ON "identification_statistics"("location_id", "kind");
@@ -0,0 +1,5 @@
+-- AlterTable
+ALTER TABLE "location" ADD COLUMN "identifier_rules" BLOB;
+
+-- AlterTable
+ALTER TABLE "file_path" ADD COLUMN "identification_skipped" BOOLEAN;
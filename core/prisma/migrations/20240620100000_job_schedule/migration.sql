@@ -0,0 +1,19 @@
+-- CreateTable
+CREATE TABLE "job_schedule" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "location_id" INTEGER NOT NULL,
+    "job" BLOB NOT NULL,
+    "recurrence" TEXT NOT NULL,
+    "catch_up" INTEGER NOT NULL DEFAULT 0,
+    "enabled" BOOLEAN NOT NULL DEFAULT true,
+    "next_run_at" DATETIME,
+    "last_run_at" DATETIME,
+    "date_created" DATETIME,
+    CONSTRAINT "job_schedule_location_id_fkey" FOREIGN KEY ("location_id") REFERENCES "location" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE INDEX "job_schedule_location_id_idx" ON "job_schedule"("location_id");
+
+-- CreateIndex
+CREATE INDEX "job_schedule_next_run_at_idx" ON "job_schedule"("next_run_at");
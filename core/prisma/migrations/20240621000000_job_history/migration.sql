@@ -0,0 +1,25 @@
+-- CreateTable
+CREATE TABLE "job_history" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "job_id" BLOB NOT NULL,
+    "name" TEXT NOT NULL,
+    "action" TEXT,
+    "location_id" INTEGER,
+    "status" INTEGER NOT NULL,
+    "trigger" INTEGER,
+    "parameters" BLOB,
+    "metadata" BLOB,
+    "errors" BLOB,
+    "task_count" INTEGER NOT NULL,
+    "completed_task_count" INTEGER NOT NULL,
+    "date_started" DATETIME,
+    "date_completed" DATETIME NOT NULL,
+    "duration_ms" BIGINT,
+    CONSTRAINT "job_history_location_id_fkey" FOREIGN KEY ("location_id") REFERENCES "location" ("id") ON DELETE SET NULL ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE INDEX "job_history_location_id_date_completed_idx" ON "job_history"("location_id", "date_completed");
+
+-- CreateIndex
+CREATE INDEX "job_history_name_date_completed_idx" ON "job_history"("name", "date_completed");
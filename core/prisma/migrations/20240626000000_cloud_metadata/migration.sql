@@ -0,0 +1,20 @@
+-- AlterTable
+ALTER TABLE "location" ADD COLUMN "cloud_config" BLOB;
+
+-- CreateTable
+CREATE TABLE "provider_hash" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "file_path_pub_id" BLOB NOT NULL,
+    "location_id" INTEGER NOT NULL,
+    "kind" INTEGER NOT NULL,
+    "hash" TEXT NOT NULL,
+    "item_id" TEXT NOT NULL,
+    "cas_id" TEXT,
+    CONSTRAINT "provider_hash_location_id_fkey" FOREIGN KEY ("location_id") REFERENCES "location" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "provider_hash_file_path_pub_id_key" ON "provider_hash"("file_path_pub_id");
+
+-- CreateIndex
+CREATE INDEX "provider_hash_kind_hash_idx" ON "provider_hash"("kind", "hash");
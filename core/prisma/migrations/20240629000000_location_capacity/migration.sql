@@ -0,0 +1,13 @@
+-- CreateTable
+CREATE TABLE "location_capacity" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "location_id" INTEGER NOT NULL,
+    "date_captured" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "total_bytes" BIGINT NOT NULL,
+    "free_bytes" BIGINT NOT NULL,
+    "file_system" TEXT,
+    CONSTRAINT "location_capacity_location_id_fkey" FOREIGN KEY ("location_id") REFERENCES "location" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE INDEX "location_capacity_location_id_date_captured_idx" ON "location_capacity"("location_id", "date_captured");
@@ -0,0 +1,16 @@
+-- AlterTable
+ALTER TABLE "file_path" ADD COLUMN "date_trashed" DATETIME;
+
+-- CreateTable
+CREATE TABLE "trashed_file_path" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "file_path_id" INTEGER NOT NULL,
+    "location_id" INTEGER NOT NULL,
+    "original_path" TEXT NOT NULL,
+    "date_trashed" DATETIME NOT NULL,
+    CONSTRAINT "trashed_file_path_file_path_id_fkey" FOREIGN KEY ("file_path_id") REFERENCES "file_path" ("id") ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT "trashed_file_path_location_id_fkey" FOREIGN KEY ("location_id") REFERENCES "location" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "trashed_file_path_file_path_id_key" ON "trashed_file_path"("file_path_id");
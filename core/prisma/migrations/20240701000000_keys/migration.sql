@@ -0,0 +1,13 @@
+-- CreateTable
+CREATE TABLE "key" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "pub_id" BLOB NOT NULL,
+    "name" TEXT,
+    "hashing_algorithm" BLOB NOT NULL,
+    "salt" BLOB NOT NULL,
+    "verification" BLOB NOT NULL,
+    "date_created" DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "key_pub_id_key" ON "key"("pub_id");
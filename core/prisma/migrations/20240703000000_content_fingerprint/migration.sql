@@ -0,0 +1,12 @@
+-- CreateTable
+CREATE TABLE "content_fingerprint" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "kind" INTEGER NOT NULL,
+    "fingerprint" BLOB NOT NULL,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "object_id" INTEGER NOT NULL,
+    CONSTRAINT "content_fingerprint_object_id_fkey" FOREIGN KEY ("object_id") REFERENCES "object" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "content_fingerprint_object_id_key" ON "content_fingerprint"("object_id");
@@ -0,0 +1,18 @@
+-- AlterTable
+ALTER TABLE "exif_data" ADD COLUMN "latitude" REAL;
+ALTER TABLE "exif_data" ADD COLUMN "longitude" REAL;
+ALTER TABLE "exif_data" ADD COLUMN "city" TEXT;
+ALTER TABLE "exif_data" ADD COLUMN "country" TEXT;
+
+-- Backfill coordinates from the already extracted locations, places are labelled on the next extraction
+UPDATE "exif_data"
+SET
+    "latitude" = json_extract(CAST("media_location" AS TEXT), '$.latitude'),
+    "longitude" = json_extract(CAST("media_location" AS TEXT), '$.longitude')
+WHERE "media_location" IS NOT NULL AND json_valid(CAST("media_location" AS TEXT));
+
+-- CreateIndex
+CREATE INDEX "exif_data_city_idx" ON "exif_data"("city");
+
+-- CreateIndex
+CREATE INDEX "exif_data_country_idx" ON "exif_data"("country");
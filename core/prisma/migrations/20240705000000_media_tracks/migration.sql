@@ -0,0 +1,7 @@
+-- Backfill chapter titles, which were only kept inside their metadata
+UPDATE "ffmpeg_media_chapter"
+SET "title" = json_extract(CAST("metadata" AS TEXT), '$.title')
+WHERE "title" IS NULL AND "metadata" IS NOT NULL AND json_valid(CAST("metadata" AS TEXT));
+
+-- CreateIndex
+CREATE INDEX "ffmpeg_media_stream_language_idx" ON "ffmpeg_media_stream"("language");
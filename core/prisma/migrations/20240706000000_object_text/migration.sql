@@ -0,0 +1,35 @@
+-- CreateTable
+CREATE TABLE "object_text" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "text" TEXT NOT NULL,
+    "source" INTEGER NOT NULL,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "object_id" INTEGER NOT NULL,
+    CONSTRAINT "object_text_object_id_fkey" FOREIGN KEY ("object_id") REFERENCES "object" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "object_text_object_id_key" ON "object_text"("object_id");
+
+-- Full-text index over "object_text", storing only the index as the text is already in that table.
+-- Prisma doesn't know about virtual tables, so it's only queried with raw SQL
+CREATE VIRTUAL TABLE "object_text_fts" USING fts5(
+    "text",
+    content='object_text',
+    content_rowid='id',
+    tokenize='unicode61 remove_diacritics 2'
+);
+
+-- Keep the index in sync with "object_text", external content tables must be told what is removed
+CREATE TRIGGER "object_text_after_insert" AFTER INSERT ON "object_text" BEGIN
+    INSERT INTO "object_text_fts"("rowid", "text") VALUES (new."id", new."text");
+END;
+
+CREATE TRIGGER "object_text_after_delete" AFTER DELETE ON "object_text" BEGIN
+    INSERT INTO "object_text_fts"("object_text_fts", "rowid", "text") VALUES ('delete', old."id", old."text");
+END;
+
+CREATE TRIGGER "object_text_after_update" AFTER UPDATE ON "object_text" BEGIN
+    INSERT INTO "object_text_fts"("object_text_fts", "rowid", "text") VALUES ('delete', old."id", old."text");
+    INSERT INTO "object_text_fts"("rowid", "text") VALUES (new."id", new."text");
+END;
@@ -0,0 +1,14 @@
+-- AlterTable
+ALTER TABLE "label_on_object" ADD COLUMN "confidence" REAL;
+
+-- CreateTable
+CREATE TABLE "labeled_object" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "model" TEXT NOT NULL,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "object_id" INTEGER NOT NULL,
+    CONSTRAINT "labeled_object_object_id_fkey" FOREIGN KEY ("object_id") REFERENCES "object" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "labeled_object_object_id_key" ON "labeled_object"("object_id");
@@ -0,0 +1,46 @@
+-- CreateTable
+CREATE TABLE "face" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "x" REAL NOT NULL,
+    "y" REAL NOT NULL,
+    "width" REAL NOT NULL,
+    "height" REAL NOT NULL,
+    "score" REAL NOT NULL,
+    "embedding" BLOB NOT NULL,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "object_id" INTEGER NOT NULL,
+    "person_id" INTEGER,
+    CONSTRAINT "face_object_id_fkey" FOREIGN KEY ("object_id") REFERENCES "object" ("id") ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT "face_person_id_fkey" FOREIGN KEY ("person_id") REFERENCES "person" ("id") ON DELETE SET NULL ON UPDATE CASCADE
+);
+
+-- CreateTable
+CREATE TABLE "person" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "pub_id" BLOB NOT NULL,
+    "name" TEXT,
+    "centroid" BLOB NOT NULL,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "date_modified" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- CreateTable
+CREATE TABLE "face_scan" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "model" TEXT NOT NULL,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "object_id" INTEGER NOT NULL,
+    CONSTRAINT "face_scan_object_id_fkey" FOREIGN KEY ("object_id") REFERENCES "object" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE INDEX "face_object_id_idx" ON "face"("object_id");
+
+-- CreateIndex
+CREATE INDEX "face_person_id_idx" ON "face"("person_id");
+
+-- CreateIndex
+CREATE UNIQUE INDEX "person_pub_id_key" ON "person"("pub_id");
+
+-- CreateIndex
+CREATE UNIQUE INDEX "face_scan_object_id_key" ON "face_scan"("object_id");
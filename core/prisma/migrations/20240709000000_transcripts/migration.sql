@@ -0,0 +1,25 @@
+-- CreateTable
+CREATE TABLE "transcript" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "model" TEXT NOT NULL,
+    "language" TEXT,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "object_id" INTEGER NOT NULL,
+    CONSTRAINT "transcript_object_id_fkey" FOREIGN KEY ("object_id") REFERENCES "object" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateTable
+CREATE TABLE "transcript_segment" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "start_ms" INTEGER NOT NULL,
+    "end_ms" INTEGER NOT NULL,
+    "text" TEXT NOT NULL,
+    "transcript_id" INTEGER NOT NULL,
+    CONSTRAINT "transcript_segment_transcript_id_fkey" FOREIGN KEY ("transcript_id") REFERENCES "transcript" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "transcript_object_id_key" ON "transcript"("object_id");
+
+-- CreateIndex
+CREATE INDEX "transcript_segment_transcript_id_idx" ON "transcript_segment"("transcript_id");
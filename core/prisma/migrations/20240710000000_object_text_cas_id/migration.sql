@@ -0,0 +1,9 @@
+-- AlterTable
+ALTER TABLE "object_text" ADD COLUMN "cas_id" TEXT;
+
+-- Texts extracted so far came from the current content of their objects
+UPDATE "object_text" SET "cas_id" = (
+    SELECT "cas_id" FROM "file_path"
+    WHERE "file_path"."object_id" = "object_text"."object_id" AND "file_path"."cas_id" IS NOT NULL
+    LIMIT 1
+);
@@ -0,0 +1,39 @@
+-- CreateTable
+CREATE TABLE "tag_rule" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "pub_id" BLOB NOT NULL,
+    "name" TEXT,
+    "conditions" BLOB,
+    "enabled" BOOLEAN NOT NULL DEFAULT true,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "date_modified" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "tag_id" INTEGER,
+    CONSTRAINT "tag_rule_tag_id_fkey" FOREIGN KEY ("tag_id") REFERENCES "tag" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- RedefineTables
+PRAGMA foreign_keys=OFF;
+CREATE TABLE "new_tag" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "pub_id" BLOB NOT NULL,
+    "name" TEXT,
+    "color" TEXT,
+    "is_hidden" BOOLEAN,
+    "date_created" DATETIME,
+    "date_modified" DATETIME,
+    "parent_id" INTEGER,
+    CONSTRAINT "tag_parent_id_fkey" FOREIGN KEY ("parent_id") REFERENCES "tag" ("id") ON DELETE SET NULL ON UPDATE CASCADE
+);
+INSERT INTO "new_tag" ("color", "date_created", "date_modified", "id", "is_hidden", "name", "pub_id") SELECT "color", "date_created", "date_modified", "id", "is_hidden", "name", "pub_id" FROM "tag";
+DROP TABLE "tag";
+ALTER TABLE "new_tag" RENAME TO "tag";
+CREATE UNIQUE INDEX "tag_pub_id_key" ON "tag"("pub_id");
+CREATE INDEX "tag_parent_id_idx" ON "tag"("parent_id");
+PRAGMA foreign_key_check;
+PRAGMA foreign_keys=ON;
+
+-- CreateIndex
+CREATE UNIQUE INDEX "tag_rule_pub_id_key" ON "tag_rule"("pub_id");
+
+-- CreateIndex
+CREATE INDEX "tag_rule_tag_id_idx" ON "tag_rule"("tag_id");
@@ -0,0 +1,25 @@
+-- CreateTable
+CREATE TABLE "collection" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "pub_id" BLOB NOT NULL,
+    "name" TEXT,
+    "description" TEXT,
+    "icon" TEXT,
+    "query" TEXT,
+    "date_created" DATETIME,
+    "date_modified" DATETIME
+);
+
+-- CreateTable
+CREATE TABLE "collection_on_object" (
+    "object_id" INTEGER NOT NULL,
+    "collection_id" INTEGER NOT NULL,
+    "date_created" DATETIME,
+
+    PRIMARY KEY ("collection_id", "object_id"),
+    CONSTRAINT "collection_on_object_object_id_fkey" FOREIGN KEY ("object_id") REFERENCES "object" ("id") ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT "collection_on_object_collection_id_fkey" FOREIGN KEY ("collection_id") REFERENCES "collection" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "collection_pub_id_key" ON "collection"("pub_id");
@@ -0,0 +1,17 @@
+-- CreateTable
+CREATE TABLE "note_version" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "pub_id" BLOB NOT NULL,
+    "content" TEXT,
+    "parents" BLOB,
+    "instance_pub_id" BLOB,
+    "date_created" DATETIME,
+    "object_id" INTEGER,
+    CONSTRAINT "note_version_object_id_fkey" FOREIGN KEY ("object_id") REFERENCES "object" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "note_version_pub_id_key" ON "note_version"("pub_id");
+
+-- CreateIndex
+CREATE INDEX "note_version_object_id_idx" ON "note_version"("object_id");
@@ -0,0 +1,29 @@
+-- CreateTable
+CREATE TABLE "custom_field" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "pub_id" BLOB NOT NULL,
+    "name" TEXT,
+    "kind" INTEGER,
+    "options" BLOB,
+    "date_created" DATETIME,
+    "date_modified" DATETIME
+);
+
+-- CreateTable
+CREATE TABLE "custom_field_value" (
+    "object_id" INTEGER NOT NULL,
+    "custom_field_id" INTEGER NOT NULL,
+    "text" TEXT,
+    "number" REAL,
+    "date_modified" DATETIME,
+
+    PRIMARY KEY ("custom_field_id", "object_id"),
+    CONSTRAINT "custom_field_value_object_id_fkey" FOREIGN KEY ("object_id") REFERENCES "object" ("id") ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT "custom_field_value_custom_field_id_fkey" FOREIGN KEY ("custom_field_id") REFERENCES "custom_field" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "custom_field_pub_id_key" ON "custom_field"("pub_id");
+
+-- CreateIndex
+CREATE INDEX "custom_field_value_object_id_idx" ON "custom_field_value"("object_id");
@@ -0,0 +1,25 @@
+-- CreateTable
+CREATE TABLE "kind_statistics" (
+    "location_id" INTEGER NOT NULL,
+    "kind" INTEGER NOT NULL,
+    "object_count" INTEGER NOT NULL DEFAULT 0,
+    "total_bytes" BIGINT NOT NULL DEFAULT 0,
+
+    PRIMARY KEY ("location_id", "kind"),
+    CONSTRAINT "kind_statistics_location_id_fkey" FOREIGN KEY ("location_id") REFERENCES "location" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateTable
+CREATE TABLE "tag_statistics" (
+    "tag_id" INTEGER NOT NULL PRIMARY KEY,
+    "object_count" INTEGER NOT NULL DEFAULT 0,
+    "total_bytes" BIGINT NOT NULL DEFAULT 0,
+    CONSTRAINT "tag_statistics_tag_id_fkey" FOREIGN KEY ("tag_id") REFERENCES "tag" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateTable
+CREATE TABLE "statistics_history" (
+    "date" DATETIME NOT NULL PRIMARY KEY,
+    "object_count" INTEGER NOT NULL,
+    "total_bytes" BIGINT NOT NULL
+);
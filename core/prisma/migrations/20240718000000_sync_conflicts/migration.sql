@@ -0,0 +1,18 @@
+-- CreateTable
+CREATE TABLE "sync_conflict" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "model" INTEGER NOT NULL,
+    "record_id" BLOB NOT NULL,
+    "field" TEXT NOT NULL,
+    "winner_instance_pub_id" BLOB NOT NULL,
+    "winner_timestamp" BIGINT NOT NULL,
+    "winner_value" BLOB NOT NULL,
+    "loser_instance_pub_id" BLOB NOT NULL,
+    "loser_timestamp" BIGINT NOT NULL,
+    "loser_value" BLOB NOT NULL,
+    "date_detected" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "date_resolved" DATETIME
+);
+
+-- CreateIndex
+CREATE INDEX "sync_conflict_date_resolved_idx" ON "sync_conflict"("date_resolved");
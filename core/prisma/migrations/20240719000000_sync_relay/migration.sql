@@ -0,0 +1,7 @@
+-- CreateTable
+CREATE TABLE "sync_relay" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "storage" BLOB NOT NULL,
+    "key" BLOB NOT NULL,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,28 @@
+-- CreateTable
+CREATE TABLE "folder_sync" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "location_a_id" INTEGER NOT NULL,
+    "location_b_id" INTEGER NOT NULL,
+    "date_created" DATETIME,
+    "date_last_synced" DATETIME,
+    CONSTRAINT "folder_sync_location_a_id_fkey" FOREIGN KEY ("location_a_id") REFERENCES "location" ("id") ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT "folder_sync_location_b_id_fkey" FOREIGN KEY ("location_b_id") REFERENCES "location" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateTable
+CREATE TABLE "folder_sync_entry" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "folder_sync_id" INTEGER NOT NULL,
+    "path" TEXT NOT NULL,
+    "is_dir" BOOLEAN NOT NULL DEFAULT false,
+    "cas_id" TEXT,
+    "conflict" BOOLEAN NOT NULL DEFAULT false,
+    "resolution" INTEGER,
+    CONSTRAINT "folder_sync_entry_folder_sync_id_fkey" FOREIGN KEY ("folder_sync_id") REFERENCES "folder_sync" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "folder_sync_location_a_id_location_b_id_key" ON "folder_sync"("location_a_id", "location_b_id");
+
+-- CreateIndex
+CREATE UNIQUE INDEX "folder_sync_entry_folder_sync_id_path_key" ON "folder_sync_entry"("folder_sync_id", "path");
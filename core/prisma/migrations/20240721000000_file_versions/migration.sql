@@ -0,0 +1,17 @@
+-- AlterTable
+ALTER TABLE "location" ADD COLUMN "versioning" BLOB;
+
+-- CreateTable
+CREATE TABLE "file_version" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "location_id" INTEGER NOT NULL,
+    "path" TEXT NOT NULL,
+    "cas_id" TEXT NOT NULL,
+    "size_in_bytes_bytes" BLOB NOT NULL,
+    "date_modified" DATETIME NOT NULL,
+    "date_replaced" DATETIME,
+    CONSTRAINT "file_version_location_id_fkey" FOREIGN KEY ("location_id") REFERENCES "location" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE INDEX "file_version_location_id_path_idx" ON "file_version"("location_id", "path");
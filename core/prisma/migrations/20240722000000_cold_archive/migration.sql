@@ -0,0 +1,34 @@
+-- CreateTable
+CREATE TABLE "archive_policy" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "location_id" INTEGER NOT NULL,
+    "cold_location_id" INTEGER NOT NULL,
+    "not_accessed_months" INTEGER,
+    "kinds" BLOB,
+    "date_created" DATETIME,
+    "date_last_run" DATETIME,
+    CONSTRAINT "archive_policy_location_id_fkey" FOREIGN KEY ("location_id") REFERENCES "location" ("id") ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT "archive_policy_cold_location_id_fkey" FOREIGN KEY ("cold_location_id") REFERENCES "location" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateTable
+CREATE TABLE "archive_stub" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "file_path_id" INTEGER NOT NULL,
+    "location_id" INTEGER NOT NULL,
+    "materialized_path" TEXT NOT NULL,
+    "name" TEXT NOT NULL,
+    "extension" TEXT NOT NULL,
+    "date_archived" DATETIME NOT NULL,
+    CONSTRAINT "archive_stub_file_path_id_fkey" FOREIGN KEY ("file_path_id") REFERENCES "file_path" ("id") ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT "archive_stub_location_id_fkey" FOREIGN KEY ("location_id") REFERENCES "location" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "archive_policy_location_id_key" ON "archive_policy"("location_id");
+
+-- CreateIndex
+CREATE UNIQUE INDEX "archive_stub_file_path_id_key" ON "archive_stub"("file_path_id");
+
+-- CreateIndex
+CREATE INDEX "archive_stub_location_id_idx" ON "archive_stub"("location_id");
@@ -0,0 +1,12 @@
+-- CreateTable
+CREATE TABLE "directory_usage" (
+    "location_id" INTEGER NOT NULL,
+    "path" TEXT NOT NULL,
+    "file_count" INTEGER NOT NULL DEFAULT 0,
+    "total_bytes" BIGINT NOT NULL DEFAULT 0,
+    "identified_file_count" INTEGER NOT NULL DEFAULT 0,
+    "identified_bytes" BIGINT NOT NULL DEFAULT 0,
+
+    PRIMARY KEY ("location_id", "path"),
+    CONSTRAINT "directory_usage_location_id_fkey" FOREIGN KEY ("location_id") REFERENCES "location" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
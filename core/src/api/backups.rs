@@ -33,7 +33,7 @@ use tokio::{
 use tracing::{error, info};
 use uuid::Uuid;
 
-use super::{utils::library, Ctx, R};
+use super::{utils::library_mut, Ctx, R};
 
 pub(crate) fn mount() -> AlphaRouter<Ctx> {
 	R.router()
@@ -115,7 +115,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 			})
 		})
 		.procedure("backup", {
-			R.with2(library())
+			R.with2(library_mut())
 				.mutation(
 					|(node, library), _: ()| async move { Ok(start_backup(node, library).await) },
 				)
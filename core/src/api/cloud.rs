@@ -6,7 +6,10 @@ use serde::de::DeserializeOwned;
 
 use uuid::Uuid;
 
-use super::{utils::library, Ctx, R};
+use super::{
+	utils::{library, library_mut},
+	Ctx, R,
+};
 
 #[allow(unused)]
 async fn parse_json_body<T: DeserializeOwned>(response: Response) -> Result<T, rspc::Error> {
@@ -69,7 +72,7 @@ mod library {
 				})
 			})
 			.procedure("create", {
-				R.with2(library())
+				R.with2(library_mut())
 					.mutation(|(node, library), _: ()| async move {
 						let node_config = node.config.get().await;
 						let cloud_library = sd_cloud_api::library::create(
@@ -85,11 +88,18 @@ mod library {
 						.await?;
 						node.libraries
 							.edit(
+								&node,
 								library.id,
 								None,
 								MaybeUndefined::Undefined,
 								MaybeUndefined::Value(cloud_library.id),
 								None,
+								None,
+								MaybeUndefined::Undefined,
+								None,
+								None,
+								None,
+								None,
 							)
 							.await?;
 
@@ -129,11 +139,18 @@ mod library {
 						.await?;
 					node.libraries
 						.edit(
+							&node,
 							library.id,
 							None,
 							MaybeUndefined::Undefined,
 							MaybeUndefined::Value(cloud_library.id),
 							None,
+							None,
+							MaybeUndefined::Undefined,
+							None,
+							None,
+							None,
+							None,
 						)
 						.await?;
 
@@ -172,7 +189,7 @@ mod library {
 				})
 			})
 			.procedure("sync", {
-				R.with2(library())
+				R.with2(library_mut())
 					.mutation(|(_, library), _: ()| async move {
 						library.do_cloud_sync();
 						Ok(())
@@ -0,0 +1,548 @@
+use crate::{
+	api::{
+		locations::ExplorerItem,
+		search::{object_items, query::Query},
+		utils::{library, library_mut, InvalidateOperationEvent, SingleInvalidateOperationEvent},
+		CoreEvent,
+	},
+	invalidate_query,
+	library::Library,
+	Node,
+};
+
+use sd_prisma::{
+	prisma::{collection, collection_on_object, object, PrismaClient},
+	prisma_sync,
+};
+use sd_sync::{option_sync_db_entry, sync_db_entry, OperationFactory};
+use sd_utils::{chain_optional_iter, msgpack, uuid_to_bytes};
+
+use std::{collections::BTreeSet, time::Duration};
+
+use async_stream::stream;
+use chrono::{DateTime, FixedOffset, Utc};
+use rspc::{alpha::AlphaRouter, ErrorCode};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::time::{sleep, timeout};
+use tracing::error;
+use uuid::Uuid;
+
+use super::{Ctx, R};
+
+/// Objects of a collection that are tracked at most by membership updates, the first ones created
+const MAX_MEMBERS: usize = 10_000;
+
+/// Jobs commit in bursts, which are let to settle before evaluating the membership again
+const MEMBERSHIP_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Queries with relative dates, like `added:this-week`, change without any file changing, so
+/// rule-based collections are evaluated again every so often anyway
+const MEMBERSHIP_REFRESH_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Queries invalidated when the objects of a collection may have changed
+const MEMBERSHIP_TRIGGER_QUERIES: &[&str] = &[
+	"collections.objects",
+	"collections.get",
+	"search.paths",
+	"search.objects",
+	"tags.getForObject",
+	"labels.getForObject",
+];
+
+#[derive(Serialize, Type, Debug)]
+#[serde(tag = "type", content = "data", rename_all = "camelCase")]
+enum MembershipUpdate {
+	/// Every object of the collection, sent first
+	Initial(Vec<ExplorerItem>),
+	/// What changed since the previous update
+	Changed {
+		added: Vec<ExplorerItem>,
+		removed: Vec<object::id::Type>,
+	},
+}
+
+#[derive(Type, Deserialize)]
+pub struct CollectionObjectsArgs {
+	pub id: collection::id::Type,
+	pub object_ids: Vec<object::id::Type>,
+}
+
+pub(crate) fn mount() -> AlphaRouter<Ctx> {
+	R.router()
+		.procedure("list", {
+			R.with2(library()).query(|(_, library), _: ()| async move {
+				Ok(library.db.collection().find_many(vec![]).exec().await?)
+			})
+		})
+		.procedure("get", {
+			R.with2(library()).query(
+				|(_, library), collection_id: collection::id::Type| async move {
+					Ok(library
+						.db
+						.collection()
+						.find_unique(collection::id::equals(collection_id))
+						.exec()
+						.await?)
+				},
+			)
+		})
+		.procedure("objects", {
+			R.with2(library()).query(
+				|(node, library), collection_id: collection::id::Type| async move {
+					let collection = find_collection(&library.db, collection_id).await?;
+
+					let members = members(&library.db, &collection).await?;
+					let mut items = object_items(&node, &library, members.clone()).await?;
+
+					Ok(members
+						.into_iter()
+						.filter_map(|id| items.remove(&id))
+						.collect::<Vec<_>>())
+				},
+			)
+		})
+		.procedure("create", {
+			#[derive(Type, Deserialize)]
+			pub struct CollectionCreateArgs {
+				pub name: String,
+				#[specta(optional)]
+				pub description: Option<String>,
+				#[specta(optional)]
+				pub icon: Option<String>,
+				/// Makes the collection rule-based, with the objects matched by this query
+				#[specta(optional)]
+				pub query: Option<String>,
+			}
+
+			R.with2(library_mut())
+				.mutation(|(_, library), args: CollectionCreateArgs| async move {
+					let Library { db, sync, .. } = library.as_ref();
+
+					let query = args.query.filter(|query| !query.trim().is_empty());
+					if let Some(query) = &query {
+						Query::parse(query)?;
+					}
+
+					let pub_id = uuid_to_bytes(Uuid::new_v4());
+					let date_created: DateTime<FixedOffset> = Utc::now().into();
+
+					let (sync_params, db_params): (Vec<_>, Vec<_>) = chain_optional_iter(
+						[
+							sync_db_entry!(args.name, collection::name),
+							sync_db_entry!(date_created, collection::date_created),
+						],
+						[
+							option_sync_db_entry!(args.description, collection::description),
+							option_sync_db_entry!(args.icon, collection::icon),
+							option_sync_db_entry!(query, collection::query),
+						],
+					)
+					.into_iter()
+					.unzip();
+
+					let created = sync
+						.write_ops(
+							db,
+							(
+								sync.shared_create(
+									prisma_sync::collection::SyncId {
+										pub_id: pub_id.clone(),
+									},
+									sync_params,
+								),
+								db.collection().create(pub_id, db_params),
+							),
+						)
+						.await?;
+
+					invalidate_query!(library, "collections.list");
+
+					Ok(created)
+				})
+		})
+		.procedure("update", {
+			R.with2(library_mut()).mutation({
+				collection::partial_unchecked!(CollectionUpdateArgs {
+					name
+					description
+					icon
+					query
+				});
+
+				|(_, library), (id, args): (collection::id::Type, CollectionUpdateArgs)| async move {
+					let Library { db, sync, .. } = library.as_ref();
+
+					let collection = find_collection(db, id).await?;
+
+					// An empty query turns the collection back into a hand curated one
+					let query = args
+						.query
+						.map(|query| query.filter(|query| !query.trim().is_empty()));
+					if let Some(Some(query)) = &query {
+						Query::parse(query)?;
+					}
+
+					let date_modified: DateTime<FixedOffset> = Utc::now().into();
+
+					let (sync_ops, db_params): (Vec<_>, Vec<_>) = chain_optional_iter(
+						[sync_db_entry!(date_modified, collection::date_modified)],
+						[
+							option_sync_db_entry!(args.name.flatten(), collection::name),
+							args.description.map(|description| {
+								(
+									(collection::description::NAME, msgpack!(description)),
+									collection::description::set(description),
+								)
+							}),
+							args.icon.map(|icon| {
+								(
+									(collection::icon::NAME, msgpack!(icon)),
+									collection::icon::set(icon),
+								)
+							}),
+							query.map(|query| {
+								(
+									(collection::query::NAME, msgpack!(query)),
+									collection::query::set(query),
+								)
+							}),
+						],
+					)
+					.into_iter()
+					.map(|((k, v), p)| {
+						(
+							sync.shared_update(
+								prisma_sync::collection::SyncId {
+									pub_id: collection.pub_id.clone(),
+								},
+								k,
+								v,
+							),
+							p,
+						)
+					})
+					.unzip();
+
+					sync.write_ops(
+						db,
+						(
+							sync_ops,
+							db.collection()
+								.update_unchecked(collection::id::equals(id), db_params),
+						),
+					)
+					.await?;
+
+					invalidate_query!(library, "collections.list");
+					invalidate_query!(library, "collections.get");
+					invalidate_query!(library, "collections.objects");
+
+					Ok(())
+				}
+			})
+		})
+		.procedure("delete", {
+			R.with2(library_mut()).mutation(
+				|(_, library), collection_id: collection::id::Type| async move {
+					let Library { db, sync, .. } = library.as_ref();
+
+					let collection = find_collection(db, collection_id).await?;
+
+					// Its objects are left out of it by the database, on every device
+					sync.write_op(
+						db,
+						sync.shared_delete(prisma_sync::collection::SyncId {
+							pub_id: collection.pub_id,
+						}),
+						db.collection()
+							.delete(collection::id::equals(collection_id)),
+					)
+					.await?;
+
+					invalidate_query!(library, "collections.list");
+
+					Ok(())
+				},
+			)
+		})
+		.procedure("addObjects", {
+			R.with2(library_mut()).mutation(
+				|(_, library), args: CollectionObjectsArgs| async move {
+					let Library { db, sync, .. } = library.as_ref();
+
+					let collection = find_curated_collection(db, args.id).await?;
+
+					let objects = db
+						.object()
+						.find_many(vec![object::id::in_vec(args.object_ids)])
+						.select(object::select!({ id pub_id }))
+						.exec()
+						.await?;
+
+					let date_created: DateTime<FixedOffset> = Utc::now().into();
+
+					let (sync_ops, db_creates): (Vec<_>, Vec<_>) = objects
+						.into_iter()
+						.map(|object| {
+							(
+								sync.relation_create(
+									prisma_sync::collection_on_object::SyncId {
+										collection: prisma_sync::collection::SyncId {
+											pub_id: collection.pub_id.clone(),
+										},
+										object: prisma_sync::object::SyncId {
+											pub_id: object.pub_id,
+										},
+									},
+									[(
+										collection_on_object::date_created::NAME,
+										msgpack!(date_created),
+									)],
+								),
+								collection_on_object::CreateUnchecked {
+									collection_id: collection.id,
+									object_id: object.id,
+									_params: vec![collection_on_object::date_created::set(Some(
+										date_created,
+									))],
+								},
+							)
+						})
+						.unzip();
+
+					sync.write_ops(
+						db,
+						(
+							sync_ops.into_iter().flatten().collect(),
+							db.collection_on_object()
+								.create_many(db_creates)
+								.skip_duplicates(),
+						),
+					)
+					.await?;
+
+					invalidate_query!(library, "collections.objects");
+
+					Ok(())
+				},
+			)
+		})
+		.procedure("removeObjects", {
+			R.with2(library_mut()).mutation(
+				|(_, library), args: CollectionObjectsArgs| async move {
+					let Library { db, sync, .. } = library.as_ref();
+
+					let collection = find_curated_collection(db, args.id).await?;
+
+					let objects = db
+						.object()
+						.find_many(vec![object::id::in_vec(args.object_ids)])
+						.select(object::select!({ id pub_id }))
+						.exec()
+						.await?;
+
+					sync.write_ops(
+						db,
+						(
+							objects
+								.iter()
+								.map(|object| {
+									sync.relation_delete(
+										prisma_sync::collection_on_object::SyncId {
+											collection: prisma_sync::collection::SyncId {
+												pub_id: collection.pub_id.clone(),
+											},
+											object: prisma_sync::object::SyncId {
+												pub_id: object.pub_id.clone(),
+											},
+										},
+									)
+								})
+								.collect(),
+							db.collection_on_object().delete_many(vec![
+								collection_on_object::collection_id::equals(collection.id),
+								collection_on_object::object_id::in_vec(
+									objects.iter().map(|object| object.id).collect(),
+								),
+							]),
+						),
+					)
+					.await?;
+
+					invalidate_query!(library, "collections.objects");
+
+					Ok(())
+				},
+			)
+		})
+		.procedure("membership", {
+			// Sends the objects of a collection and then which ones join or leave it, as they're
+			// added by hand or, for rule-based ones, as files change
+			R.with2(library()).subscription(
+				|(node, library), collection_id: collection::id::Type| async move {
+					// TODO: Only listen to events of this library, like other subscriptions
+					let mut event_bus_rx = node.event_bus.0.subscribe();
+
+					find_collection(&library.db, collection_id).await?;
+
+					Ok(stream! {
+						let mut current = None::<BTreeSet<object::id::Type>>;
+
+						loop {
+							let collection = match library
+								.db
+								.collection()
+								.find_unique(collection::id::equals(collection_id))
+								.exec()
+								.await
+							{
+								Ok(Some(collection)) => collection,
+								// The collection was deleted, there's nothing left to follow
+								Ok(None) => break,
+								Err(e) => {
+									error!(?e, "Failed to fetch collection;");
+									break;
+								}
+							};
+
+							match membership_update(&node, &library, &collection, current.as_ref())
+								.await
+							{
+								Ok((members, update)) => {
+									current = Some(members);
+
+									if let Some(update) = update {
+										yield update;
+									}
+								}
+								Err(e) => error!(?e, "Failed to evaluate collection membership;"),
+							}
+
+							// Waits for something to change, or for relative dates to move on
+							let _ = timeout(MEMBERSHIP_REFRESH_INTERVAL, async {
+								loop {
+									match event_bus_rx.recv().await {
+										Ok(event) if triggers_membership_update(&event) => break,
+										Ok(_) => {}
+										// Missed events may have been relevant ones
+										Err(_) => break,
+									}
+								}
+							})
+							.await;
+
+							sleep(MEMBERSHIP_DEBOUNCE).await;
+
+							// Changes during the debounce are covered by the next evaluation
+							while event_bus_rx.try_recv().is_ok() {}
+						}
+					})
+				},
+			)
+		})
+}
+
+async fn find_collection(
+	db: &PrismaClient,
+	id: collection::id::Type,
+) -> Result<collection::Data, rspc::Error> {
+	db.collection()
+		.find_unique(collection::id::equals(id))
+		.exec()
+		.await?
+		.ok_or_else(|| rspc::Error::new(ErrorCode::NotFound, "collection not found".into()))
+}
+
+/// Objects are added and removed by hand only in collections without a query
+async fn find_curated_collection(
+	db: &PrismaClient,
+	id: collection::id::Type,
+) -> Result<collection::Data, rspc::Error> {
+	let collection = find_collection(db, id).await?;
+
+	if collection.query.is_some() {
+		return Err(rspc::Error::new(
+			ErrorCode::BadRequest,
+			"rule-based collections get their objects from their query".into(),
+		));
+	}
+
+	Ok(collection)
+}
+
+/// Objects in the collection, matched by its query for rule-based ones
+async fn members(
+	db: &PrismaClient,
+	collection: &collection::Data,
+) -> Result<Vec<object::id::Type>, rspc::Error> {
+	let mut members = if let Some(query) = &collection.query {
+		Query::parse(query)?.object_ids(db).await?
+	} else {
+		db.collection_on_object()
+			.find_many(vec![collection_on_object::collection_id::equals(
+				collection.id,
+			)])
+			.select(collection_on_object::select!({ object_id }))
+			.exec()
+			.await?
+			.into_iter()
+			.map(|member| member.object_id)
+			.collect()
+	};
+
+	members.sort_unstable();
+	members.truncate(MAX_MEMBERS);
+
+	Ok(members)
+}
+
+fn triggers_membership_update(event: &CoreEvent) -> bool {
+	match event {
+		CoreEvent::NewIdentifiedObjects { .. }
+		| CoreEvent::InvalidateOperation(InvalidateOperationEvent::All) => true,
+		CoreEvent::InvalidateOperation(InvalidateOperationEvent::Single(
+			SingleInvalidateOperationEvent { key, .. },
+		)) => MEMBERSHIP_TRIGGER_QUERIES.contains(key),
+		_ => false,
+	}
+}
+
+/// Evaluates the membership again, returning the objects of the collection and what changed since
+/// `previous`, if anything did
+async fn membership_update(
+	node: &Node,
+	library: &Library,
+	collection: &collection::Data,
+	previous: Option<&BTreeSet<object::id::Type>>,
+) -> Result<(BTreeSet<object::id::Type>, Option<MembershipUpdate>), rspc::Error> {
+	let members = members(&library.db, collection).await?;
+
+	let items = |ids: Vec<object::id::Type>| async move {
+		let mut items = object_items(node, library, ids.clone()).await?;
+
+		Ok::<_, rspc::Error>(
+			ids.into_iter()
+				.filter_map(|id| items.remove(&id))
+				.collect::<Vec<_>>(),
+		)
+	};
+
+	let members = members.into_iter().collect::<BTreeSet<_>>();
+
+	let Some(previous) = previous else {
+		let initial = items(members.iter().copied().collect()).await?;
+		return Ok((members, Some(MembershipUpdate::Initial(initial))));
+	};
+
+	let added = members.difference(previous).copied().collect::<Vec<_>>();
+	let removed = previous.difference(&members).copied().collect::<Vec<_>>();
+
+	if added.is_empty() && removed.is_empty() {
+		return Ok((members, None));
+	}
+
+	let added = items(added).await?;
+
+	Ok((members, Some(MembershipUpdate::Changed { added, removed })))
+}
@@ -0,0 +1,364 @@
+use crate::{
+	invalidate_query,
+	library::Library,
+	object::custom_field::{
+		options_from_bytes, options_to_bytes, CustomFieldCreateArgs, FieldKind, FieldValue,
+	},
+};
+
+use sd_prisma::{
+	prisma::{custom_field, custom_field_value, object},
+	prisma_sync,
+};
+use sd_sync::OperationFactory;
+use sd_utils::msgpack;
+
+use chrono::{DateTime, FixedOffset, Utc};
+use int_enum::IntEnum;
+use rspc::{alpha::AlphaRouter, ErrorCode};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use super::{
+	utils::{library, library_mut},
+	Ctx, R,
+};
+
+#[derive(Serialize, Type, Debug)]
+pub struct CustomField {
+	pub id: custom_field::id::Type,
+	pub pub_id: Vec<u8>,
+	pub name: Option<String>,
+	/// `null` for fields of kinds this version doesn't know about
+	pub kind: Option<FieldKind>,
+	pub options: Vec<String>,
+	pub date_created: Option<DateTime<FixedOffset>>,
+	pub date_modified: Option<DateTime<FixedOffset>>,
+}
+
+impl From<custom_field::Data> for CustomField {
+	fn from(field: custom_field::Data) -> Self {
+		Self {
+			id: field.id,
+			pub_id: field.pub_id,
+			name: field.name,
+			kind: field.kind.and_then(|kind| FieldKind::from_int(kind).ok()),
+			options: options_from_bytes(field.options.as_deref()),
+			date_created: field.date_created,
+			date_modified: field.date_modified,
+		}
+	}
+}
+
+#[derive(Serialize, Type, Debug)]
+pub struct ObjectFieldValue {
+	pub field_id: custom_field::id::Type,
+	pub value: FieldValue,
+}
+
+pub(crate) fn mount() -> AlphaRouter<Ctx> {
+	R.router()
+		.procedure("list", {
+			R.with2(library()).query(|(_, library), _: ()| async move {
+				Ok(library
+					.db
+					.custom_field()
+					.find_many(vec![])
+					.exec()
+					.await?
+					.into_iter()
+					.map(CustomField::from)
+					.collect::<Vec<_>>())
+			})
+		})
+		.procedure("getForObject", {
+			R.with2(library())
+				.query(|(_, library), object_id: object::id::Type| async move {
+					Ok(library
+						.db
+						.custom_field_value()
+						.find_many(vec![custom_field_value::object_id::equals(object_id)])
+						.include(custom_field_value::include!({ custom_field: select { kind } }))
+						.exec()
+						.await?
+						.into_iter()
+						.filter_map(|value| {
+							let kind = FieldKind::from_int(value.custom_field.kind?).ok()?;
+
+							Some(ObjectFieldValue {
+								field_id: value.custom_field_id,
+								value: FieldValue::from_columns(kind, value.text, value.number)?,
+							})
+						})
+						.collect::<Vec<_>>())
+				})
+		})
+		.procedure("create", {
+			R.with2(library_mut()).mutation(
+				|(_, library), args: CustomFieldCreateArgs| async move {
+					if args.name.trim().is_empty() {
+						return Err(rspc::Error::new(
+							ErrorCode::BadRequest,
+							"fields must have a name".into(),
+						));
+					}
+
+					if args.kind == FieldKind::Select && args.options.is_empty() {
+						return Err(rspc::Error::new(
+							ErrorCode::BadRequest,
+							"select fields must have options to select".into(),
+						));
+					}
+
+					let field = args.exec(&library).await?;
+
+					invalidate_query!(library, "customFields.list");
+
+					Ok(CustomField::from(field))
+				},
+			)
+		})
+		.procedure("update", {
+			#[derive(Type, Deserialize)]
+			pub struct CustomFieldUpdateArgs {
+				pub id: custom_field::id::Type,
+				pub name: Option<String>,
+				/// Objects keep values of options that are removed until they're set again
+				pub options: Option<Vec<String>>,
+			}
+
+			R.with2(library_mut()).mutation(
+				|(_, library), args: CustomFieldUpdateArgs| async move {
+					let Library { db, sync, .. } = library.as_ref();
+
+					let field = find_field(&library, args.id).await?;
+
+					let date_modified: DateTime<FixedOffset> = Utc::now().into();
+
+					let (sync_ops, db_params): (Vec<_>, Vec<_>) = [
+						args.name.map(|name| {
+							(
+								(custom_field::name::NAME, msgpack!(name)),
+								custom_field::name::set(Some(name)),
+							)
+						}),
+						args.options
+							.filter(|_| {
+								field.kind.and_then(|kind| FieldKind::from_int(kind).ok())
+									== Some(FieldKind::Select)
+							})
+							.map(|options| {
+								let options = options_to_bytes(&options)
+									.expect("a list of strings is always serializable");
+
+								(
+									(custom_field::options::NAME, msgpack!(options)),
+									custom_field::options::set(Some(options)),
+								)
+							}),
+						Some((
+							(custom_field::date_modified::NAME, msgpack!(date_modified)),
+							custom_field::date_modified::set(Some(date_modified)),
+						)),
+					]
+					.into_iter()
+					.flatten()
+					.map(|((k, v), p)| {
+						(
+							sync.shared_update(
+								prisma_sync::custom_field::SyncId {
+									pub_id: field.pub_id.clone(),
+								},
+								k,
+								v,
+							),
+							p,
+						)
+					})
+					.unzip();
+
+					sync.write_ops(
+						db,
+						(
+							sync_ops,
+							db.custom_field()
+								.update(custom_field::id::equals(args.id), db_params),
+						),
+					)
+					.await?;
+
+					invalidate_query!(library, "customFields.list");
+
+					Ok(())
+				},
+			)
+		})
+		.procedure("delete", {
+			R.with2(library_mut()).mutation(
+				|(_, library), field_id: custom_field::id::Type| async move {
+					let Library { db, sync, .. } = library.as_ref();
+
+					let field = find_field(&library, field_id).await?;
+
+					// Its values go with it, on every device
+					sync.write_op(
+						db,
+						sync.shared_delete(prisma_sync::custom_field::SyncId {
+							pub_id: field.pub_id,
+						}),
+						db.custom_field().delete(custom_field::id::equals(field_id)),
+					)
+					.await?;
+
+					invalidate_query!(library, "customFields.list");
+					invalidate_query!(library, "customFields.getForObject");
+					invalidate_query!(library, "search.objects");
+					invalidate_query!(library, "search.paths");
+
+					Ok(())
+				},
+			)
+		})
+		.procedure("set", {
+			#[derive(Type, Deserialize)]
+			pub struct CustomFieldSetArgs {
+				pub object_ids: Vec<object::id::Type>,
+				pub field_id: custom_field::id::Type,
+				/// `null` clears the field
+				pub value: Option<FieldValue>,
+			}
+
+			R.with2(library_mut())
+				.mutation(|(_, library), args: CustomFieldSetArgs| async move {
+					let Library { db, sync, .. } = library.as_ref();
+
+					let field = find_field(&library, args.field_id).await?;
+
+					let objects = db
+						.object()
+						.find_many(vec![object::id::in_vec(args.object_ids)])
+						.select(object::select!({ id pub_id }))
+						.exec()
+						.await?;
+
+					let sync_id =
+						|object_pub_id: Vec<u8>| prisma_sync::custom_field_value::SyncId {
+							custom_field: prisma_sync::custom_field::SyncId {
+								pub_id: field.pub_id.clone(),
+							},
+							object: prisma_sync::object::SyncId {
+								pub_id: object_pub_id,
+							},
+						};
+
+					let Some(value) = args.value else {
+						sync.write_ops(
+							db,
+							(
+								objects
+									.iter()
+									.map(|object| {
+										sync.relation_delete(sync_id(object.pub_id.clone()))
+									})
+									.collect(),
+								db.custom_field_value().delete_many(vec![
+									custom_field_value::custom_field_id::equals(field.id),
+									custom_field_value::object_id::in_vec(
+										objects.iter().map(|object| object.id).collect(),
+									),
+								]),
+							),
+						)
+						.await?;
+
+						invalidate_field_values(&library);
+
+						return Ok(());
+					};
+
+					let kind = field
+						.kind
+						.and_then(|kind| FieldKind::from_int(kind).ok())
+						.ok_or_else(|| {
+							rspc::Error::new(
+								ErrorCode::BadRequest,
+								"the field is of a kind this version doesn't know about".into(),
+							)
+						})?;
+
+					value.validate(kind, &options_from_bytes(field.options.as_deref()))?;
+
+					let (text, number) = value.into_columns();
+					let date_modified: DateTime<FixedOffset> = Utc::now().into();
+
+					let mut sync_ops = Vec::with_capacity(objects.len() * 4);
+
+					let db_upserts = objects
+						.into_iter()
+						.map(|object| {
+							let sync_id = sync_id(object.pub_id);
+
+							// Relations are created without their fields on other devices, so the
+							// value goes in its own operations
+							sync_ops.extend(sync.relation_create(sync_id.clone(), []));
+							sync_ops.extend([
+								sync.relation_update(
+									sync_id.clone(),
+									custom_field_value::text::NAME,
+									msgpack!(text),
+								),
+								sync.relation_update(
+									sync_id.clone(),
+									custom_field_value::number::NAME,
+									msgpack!(number),
+								),
+								sync.relation_update(
+									sync_id,
+									custom_field_value::date_modified::NAME,
+									msgpack!(date_modified),
+								),
+							]);
+
+							let params = || {
+								vec![
+									custom_field_value::text::set(text.clone()),
+									custom_field_value::number::set(number),
+									custom_field_value::date_modified::set(Some(date_modified)),
+								]
+							};
+
+							db.custom_field_value().upsert(
+								custom_field_value::custom_field_id_object_id(field.id, object.id),
+								custom_field_value::create_unchecked(field.id, object.id, params()),
+								params(),
+							)
+						})
+						.collect::<Vec<_>>();
+
+					sync.write_ops(db, (sync_ops, db_upserts)).await?;
+
+					invalidate_field_values(&library);
+
+					Ok(())
+				})
+		})
+}
+
+async fn find_field(
+	library: &Library,
+	id: custom_field::id::Type,
+) -> Result<custom_field::Data, rspc::Error> {
+	library
+		.db
+		.custom_field()
+		.find_unique(custom_field::id::equals(id))
+		.exec()
+		.await?
+		.ok_or_else(|| rspc::Error::new(ErrorCode::NotFound, "custom field not found".into()))
+}
+
+fn invalidate_field_values(library: &Library) {
+	invalidate_query!(library, "customFields.getForObject");
+	invalidate_query!(library, "search.objects");
+	invalidate_query!(library, "search.paths");
+}
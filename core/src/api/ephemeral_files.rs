@@ -1,7 +1,7 @@
 use crate::{
 	api::{
 		files::{create_file, MediaData},
-		utils::library,
+		utils::{library, library_mut},
 	},
 	invalidate_query,
 	library::Library,
@@ -12,6 +12,7 @@ use crate::{
 };
 
 use sd_core_file_path_helper::IsolatedFilePathData;
+use sd_core_heavy_lifting::file_identifier::{self, FileMetadataOptions};
 use sd_file_ext::{
 	extensions::{Extension, ImageExtension},
 	kind::ObjectKind,
@@ -106,13 +107,26 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				}
 			})
 		})
+		.procedure("identify", {
+			R.with2(library())
+				.query(|(_, library), paths: Vec<PathBuf>| async move {
+					Ok(file_identifier::ephemeral_identify(
+						paths,
+						&FileMetadataOptions {
+							cas_id_algorithm: library.config().await.cas_id_algorithm,
+							..Default::default()
+						},
+					)
+					.await)
+				})
+		})
 		.procedure("createFolder", {
 			#[derive(Type, Deserialize)]
 			pub struct CreateEphemeralFolderArgs {
 				pub path: PathBuf,
 				pub name: Option<String>,
 			}
-			R.with2(library()).mutation(
+			R.with2(library_mut()).mutation(
 				|(_, library),
 				 CreateEphemeralFolderArgs { mut path, name }: CreateEphemeralFolderArgs| async move {
 					path.push(name.as_deref().unwrap_or(UNTITLED_FOLDER_STR));
@@ -128,7 +142,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				pub context: EphemeralFileCreateContextTypes,
 				pub name: Option<String>,
 			}
-			R.with2(library()).mutation(
+			R.with2(library_mut()).mutation(
 				|(_, library),
 				 CreateEphemeralFileArgs {
 				     mut path,
@@ -149,7 +163,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 			)
 		})
 		.procedure("deleteFiles", {
-			R.with2(library())
+			R.with2(library_mut())
 				.mutation(|(_, library), paths: Vec<PathBuf>| async move {
 					paths
 						.into_iter()
@@ -179,7 +193,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				})
 		})
 		.procedure("moveToTrash", {
-			R.with2(library())
+			R.with2(library_mut())
 				.mutation(|(_, library), paths: Vec<PathBuf>| async move {
 					if cfg!(target_os = "ios") || cfg!(target_os = "android") {
 						return Err(rspc::Error::new(
@@ -216,16 +230,14 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				})
 		})
 		.procedure("copyFiles", {
-			R.with2(library())
-				.mutation(|(_, library), args: EphemeralFileSystemOps| async move {
-					args.copy(&library).await
-				})
+			R.with2(library_mut()).mutation(
+				|(_, library), args: EphemeralFileSystemOps| async move { args.copy(&library).await },
+			)
 		})
 		.procedure("cutFiles", {
-			R.with2(library())
-				.mutation(|(_, library), args: EphemeralFileSystemOps| async move {
-					args.cut(&library).await
-				})
+			R.with2(library_mut()).mutation(
+				|(_, library), args: EphemeralFileSystemOps| async move { args.cut(&library).await },
+			)
 		})
 		.procedure("renameFile", {
 			#[derive(Type, Deserialize)]
@@ -390,7 +402,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				}
 			}
 
-			R.with2(library()).mutation(
+			R.with2(library_mut()).mutation(
 				|(_, library), EphemeralRenameFileArgs { kind }: EphemeralRenameFileArgs| async move {
 					let res = match kind {
 						EphemeralRenameKind::One(one) => {
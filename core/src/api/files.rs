@@ -1,20 +1,42 @@
 use crate::{
-	api::utils::library,
+	api::utils::{library, library_mut},
+	context::NodeContext,
 	invalidate_query,
 	library::Library,
-	location::{get_location_path_from_location_id, LocationError},
+	location::{find_location, get_location_path_from_location_id, LocationError},
 	object::{
 		fs::{
-			error::FileSystemJobsError, find_available_filename_for_duplicate,
-			old_copy::OldFileCopierJobInit, old_cut::OldFileCutterJobInit,
-			old_delete::OldFileDeleterJobInit, old_erase::OldFileEraserJobInit,
+			erase::erase_caveats,
+			error::FileSystemJobsError,
+			find_available_filename_for_duplicate, get_many_files_datas,
+			old_delete::{DeleteMode, OldFileDeleterJobInit},
+			old_erase::OldFileEraserJobInit,
+			old_hydrate::OldFileHydratorJobInit,
+			trash::{move_to_trash, restore_from_trash},
 		},
-		media::{exif_media_data_from_prisma_data, ffmpeg_data_from_prisma_data},
+		media::{
+			exif_media_data_from_prisma_data, ffmpeg_data_from_prisma_data,
+			old_thumbnail::{BatchToProcess, GenerateThumbnailArgs, ALL_THUMBNAILABLE_EXTENSIONS},
+		},
+		note,
 	},
 	old_job::Job,
 };
 
 use sd_core_file_path_helper::{FilePathError, IsolatedFilePathData};
+use sd_core_heavy_lifting::{
+	archiver::{CompressionFormat, Compressor, Extractor},
+	bulk_rename::{self, BulkRename, RenamePattern},
+	checksums::{ChecksumExporter, ChecksumFormat, ChecksumImporter},
+	crypto::{FileDecryptor, FileEncryptor},
+	file_copier::{ConflictPolicy, FileCopier},
+	file_identifier,
+	file_mover::FileMover,
+	media_processor::{
+		preview_strip_path, waveform_path, PreviewStrip, ThumbKey, ThumbnailKind, Waveform,
+		MAX_ON_DEMAND_CAS_IDS, PREVIEW_STRIP_FRAMES, THUMBNAIL_CACHE_DIR_NAME,
+	},
+};
 use sd_core_prisma_helpers::{
 	file_path_to_isolate, file_path_to_isolate_with_id, object_with_file_paths,
 	object_with_media_data,
@@ -24,13 +46,14 @@ use sd_file_ext::kind::ObjectKind;
 use sd_images::ConvertibleExtension;
 use sd_media_metadata::{ExifMetadata, FFmpegMetadata};
 use sd_prisma::{
-	prisma::{file_path, location, object},
+	prisma::{file_path, location, object, transcript, transcript_segment, SortOrder},
 	prisma_sync,
 };
 use sd_sync::OperationFactory;
 use sd_utils::{db::maybe_missing, error::FileIOError, msgpack};
 
 use std::{
+	collections::HashSet,
 	ffi::OsString,
 	path::{Path, PathBuf},
 	sync::Arc,
@@ -44,8 +67,7 @@ use serde::{Deserialize, Serialize};
 use specta::Type;
 use tokio::{fs, io, task::spawn_blocking};
 use tracing::{error, warn};
-#[cfg(not(any(target_os = "ios", target_os = "android")))]
-use trash;
+use uuid::Uuid;
 
 use super::{Ctx, R};
 
@@ -145,6 +167,211 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 						})
 				})
 		})
+		.procedure("getMediaTracks", {
+			R.with2(library())
+				.query(|(_, library), object_id: object::id::Type| async move {
+					library
+						.db
+						.object()
+						.find_unique(object::id::equals(object_id))
+						.include(object_with_media_data::include())
+						.exec()
+						.await?
+						.and_then(|obj| obj.ffmpeg_data)
+						.map(|ffmpeg_data| ffmpeg_data_from_prisma_data(ffmpeg_data).tracks())
+						.ok_or_else(|| {
+							rspc::Error::new(
+								ErrorCode::NotFound,
+								"Object has no media tracks".to_string(),
+							)
+						})
+				})
+		})
+		.procedure("getPreviewStrip", {
+			R.with2(library())
+				.query(|(node, library), cas_id: String| async move {
+					// The cas_id ends up in a path, so we only accept what a cas_id can look like
+					if cas_id.len() < 3 || !cas_id.chars().all(|c| c.is_ascii_alphanumeric()) {
+						return Err(rspc::Error::new(
+							ErrorCode::BadRequest,
+							"Invalid cas_id".to_string(),
+						));
+					}
+
+					let kind = ThumbnailKind::Indexed(library.id);
+					let strip_path = preview_strip_path(
+						node.config.data_directory().join(THUMBNAIL_CACHE_DIR_NAME),
+						&cas_id,
+						&kind,
+					);
+
+					match fs::metadata(&strip_path).await {
+						Ok(_) => Ok(Some(PreviewStrip {
+							thumb_key: ThumbKey::new(&cas_id, &kind),
+							frames: PREVIEW_STRIP_FRAMES.get(),
+						})),
+						Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+						Err(e) => Err(FileIOError::from((strip_path, e)).into()),
+					}
+				})
+		})
+		.procedure("getWaveform", {
+			R.with2(library())
+				.query(|(node, library), cas_id: String| async move {
+					// The cas_id ends up in a path, so we only accept what a cas_id can look like
+					if cas_id.len() < 3 || !cas_id.chars().all(|c| c.is_ascii_alphanumeric()) {
+						return Err(rspc::Error::new(
+							ErrorCode::BadRequest,
+							"Invalid cas_id".to_string(),
+						));
+					}
+
+					let waveform_path = waveform_path(
+						node.config.data_directory().join(THUMBNAIL_CACHE_DIR_NAME),
+						&cas_id,
+						library.id,
+					);
+
+					match fs::read(&waveform_path).await {
+						Ok(peaks) => Ok(Some(Waveform { peaks })),
+						Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+						Err(e) => Err(FileIOError::from((waveform_path, e)).into()),
+					}
+				})
+		})
+		.procedure("getTranscript", {
+			#[derive(Serialize, Type, Debug)]
+			#[serde(rename_all = "camelCase")]
+			struct TranscriptSegment {
+				/// Milliseconds from the start of the media
+				start_ms: i32,
+				end_ms: i32,
+				text: String,
+			}
+
+			#[derive(Serialize, Type, Debug)]
+			struct Transcript {
+				/// ISO 639-1 code of the spoken language, like `en`
+				language: Option<String>,
+				/// Whisper model that transcribed it
+				model: String,
+				segments: Vec<TranscriptSegment>,
+			}
+
+			R.with2(library())
+				.query(|(_, library), object_id: object::id::Type| async move {
+					let Library { db, .. } = library.as_ref();
+
+					let Some(transcript) = db
+						.transcript()
+						.find_unique(transcript::object_id::equals(object_id))
+						.exec()
+						.await?
+					else {
+						return Ok(None);
+					};
+
+					Ok(Some(Transcript {
+						segments: db
+							.transcript_segment()
+							.find_many(vec![transcript_segment::transcript_id::equals(
+								transcript.id,
+							)])
+							.order_by(transcript_segment::start_ms::order(SortOrder::Asc))
+							.exec()
+							.await?
+							.into_iter()
+							.map(|segment| TranscriptSegment {
+								start_ms: segment.start_ms,
+								end_ms: segment.end_ms,
+								text: segment.text,
+							})
+							.collect(),
+						language: transcript.language,
+						model: transcript.model,
+					}))
+				})
+		})
+		.procedure("requestThumbnails", {
+			R.with2(library_mut())
+				.mutation(|(node, library), cas_ids: Vec<String>| async move {
+					if cas_ids.len() > MAX_ON_DEMAND_CAS_IDS {
+						return Err(rspc::Error::new(
+							ErrorCode::BadRequest,
+							format!("Can't request more than {MAX_ON_DEMAND_CAS_IDS} thumbnails at once"),
+						));
+					}
+
+					let thumbnailable_extensions = ALL_THUMBNAILABLE_EXTENSIONS
+						.iter()
+						.map(|extension| extension.to_string().to_lowercase())
+						.collect::<HashSet<_>>();
+
+					let file_paths = library
+						.db
+						.file_path()
+						.find_many(vec![file_path::cas_id::in_vec(
+							cas_ids.into_iter().map(Some).collect(),
+						)])
+						.with(file_path::location::fetch())
+						.exec()
+						.await?;
+
+					// The same content can live in many locations, one thumbnail is enough for all of them
+					let mut seen_cas_ids = HashSet::with_capacity(file_paths.len());
+
+					let batch = file_paths
+						.iter()
+						.filter(|file_path| {
+							file_path.extension.as_ref().is_some_and(|extension| {
+								thumbnailable_extensions.contains(&extension.to_lowercase())
+							})
+						})
+						.filter_map(|file_path| {
+							let cas_id = file_path.cas_id.clone()?;
+							let location_path = file_path
+								.location
+								.as_ref()
+								.and_then(|location| location.as_ref())
+								.and_then(|location| location.path.as_ref())?;
+
+							if !seen_cas_ids.insert(cas_id.clone()) {
+								return None;
+							}
+
+							IsolatedFilePathData::try_from(file_path)
+								.map_err(|e| {
+									error!(
+										"Failed to extract isolated file path data from file path <id='{}'>: {e:#?}",
+										file_path.id
+									);
+								})
+								.ok()
+								.map(|iso_file_path| {
+									GenerateThumbnailArgs::new(
+										iso_file_path.extension().to_string(),
+										cas_id,
+										Path::new(location_path).join(&iso_file_path),
+									)
+								})
+						})
+						.collect::<Vec<_>>();
+
+					// Foreground batches go to the front of the thumbnailer queue and stop the
+					// batch being processed, which is resumed as leftovers after these are done.
+					// Existing thumbnails are skipped, so asking for everything visible is cheap
+					if !batch.is_empty() {
+						node.thumbnailer
+							.new_indexed_thumbnails_batch(
+								BatchToProcess::new(batch, false, false),
+								library.id,
+							)
+							.await;
+					}
+
+					Ok(())
+				})
+		})
 		.procedure("getPath", {
 			R.with2(library())
 				.query(|(_, library), id: i32| async move {
@@ -177,39 +404,13 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				pub note: Option<String>,
 			}
 
-			R.with2(library())
+			R.with2(library_mut())
 				.mutation(|(_, library), args: SetNoteArgs| async move {
-					let Library { db, sync, .. } = library.as_ref();
-
-					let object = db
-						.object()
-						.find_unique(object::id::equals(args.id))
-						.select(object::select!({ pub_id }))
-						.exec()
-						.await?
-						.ok_or_else(|| {
-							rspc::Error::new(
-								rspc::ErrorCode::NotFound,
-								"Object not found".to_string(),
-							)
-						})?;
-
-					sync.write_op(
-						db,
-						sync.shared_update(
-							prisma_sync::object::SyncId {
-								pub_id: object.pub_id,
-							},
-							object::note::NAME,
-							msgpack!(&args.note),
-						),
-						db.object().update(
-							object::id::equals(args.id),
-							vec![object::note::set(args.note)],
-						),
-					)
-					.await?;
+					// Written over whatever the note is now, see `notes.set` to merge edits instead
+					note::save(&library, args.id, args.note, None).await?;
 
+					invalidate_query!(library, "notes.get");
+					invalidate_query!(library, "notes.history");
 					invalidate_query!(library, "search.paths");
 					invalidate_query!(library, "search.objects");
 
@@ -223,7 +424,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				pub favorite: bool,
 			}
 
-			R.with2(library())
+			R.with2(library_mut())
 				.mutation(|(_, library), args: SetFavoriteArgs| async move {
 					let Library { sync, db, .. } = library.as_ref();
 
@@ -269,7 +470,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				pub sub_path: Option<PathBuf>,
 				pub name: Option<String>,
 			}
-			R.with2(library()).mutation(
+			R.with2(library_mut()).mutation(
 				|(_, library),
 				 CreateFolderArgs {
 				     location_id,
@@ -300,7 +501,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				pub name: Option<String>,
 				pub context: FileCreateContextTypes,
 			}
-			R.with2(library()).mutation(
+			R.with2(library_mut()).mutation(
 				|(_, library),
 				 CreateFileArgs {
 				     location_id,
@@ -332,7 +533,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 			)
 		})
 		.procedure("updateAccessTime", {
-			R.with2(library())
+			R.with2(library_mut())
 				.mutation(|(_, library), ids: Vec<i32>| async move {
 					let Library { sync, db, .. } = library.as_ref();
 
@@ -377,7 +578,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				})
 		})
 		.procedure("removeAccessTime", {
-			R.with2(library())
+			R.with2(library_mut())
 				.mutation(|(_, library), object_ids: Vec<i32>| async move {
 					let Library { db, sync, .. } = library.as_ref();
 
@@ -418,24 +619,148 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 					Ok(())
 				})
 		})
-		// .procedure("encryptFiles", {
-		// 	R.with2(library())
-		// 		.mutation(|(node, library), args: FileEncryptorJobInit| async move {
-		// 			Job::new(args).spawn(&node, &library).await.map_err(Into::into)
-		// 		})
-		// })
-		// .procedure("decryptFiles", {
-		// 	R.with2(library())
-		// 		.mutation(|(node, library), args: FileDecryptorJobInit| async move {
-		// 			Job::new(args).spawn(&node, &library).await.map_err(Into::into)
-		// 		})
-		// })
-		.procedure("deleteFiles", {
+		.procedure("hydrateFiles", {
+			R.with2(library_mut()).mutation(
+				|(node, library), args: OldFileHydratorJobInit| async move {
+					Job::new(args)
+						.spawn(&node, &library)
+						.await
+						.map_err(Into::into)
+				},
+			)
+		})
+		.procedure("encryptFiles", {
+			#[derive(Type, Deserialize)]
+			pub struct EncryptFilesArgs {
+				pub location_id: location::id::Type,
+				pub file_path_ids: Vec<file_path::id::Type>,
+				pub key_pub_id: Uuid,
+			}
+
+			R.with2(library_mut()).mutation(
+				|(node, library),
+				 EncryptFilesArgs {
+				     location_id,
+				     file_path_ids,
+				     key_pub_id,
+				 }: EncryptFilesArgs| async move {
+					let location = find_location(&library, location_id)
+						.exec()
+						.await?
+						.ok_or(LocationError::IdNotFound(location_id))?;
+
+					let encryptor = FileEncryptor::new(location, file_path_ids, key_pub_id)?;
+
+					NodeContext::dispatch(&node, &library, encryptor, location_id)
+						.await
+						.map(|_| ())
+				},
+			)
+		})
+		.procedure("decryptFiles", {
+			#[derive(Type, Deserialize)]
+			pub struct DecryptFilesArgs {
+				pub location_id: location::id::Type,
+				pub file_path_ids: Vec<file_path::id::Type>,
+			}
+
+			R.with2(library_mut()).mutation(
+				|(node, library),
+				 DecryptFilesArgs {
+				     location_id,
+				     file_path_ids,
+				 }: DecryptFilesArgs| async move {
+					let location = find_location(&library, location_id)
+						.exec()
+						.await?
+						.ok_or(LocationError::IdNotFound(location_id))?;
+
+					let decryptor = FileDecryptor::new(location, file_path_ids)?;
+
+					NodeContext::dispatch(&node, &library, decryptor, location_id)
+						.await
+						.map(|_| ())
+				},
+			)
+		})
+		.procedure("exportChecksums", {
+			#[derive(Type, Deserialize)]
+			pub struct ExportChecksumsArgs {
+				pub location_id: location::id::Type,
+				pub sub_path: Option<PathBuf>,
+				/// Exports only these file paths instead of every file in `sub_path`
+				pub file_path_ids: Option<Vec<file_path::id::Type>>,
+				#[serde(default)]
+				pub format: ChecksumFormat,
+			}
+
+			R.with2(library_mut()).mutation(
+				|(node, library),
+				 ExportChecksumsArgs {
+				     location_id,
+				     sub_path,
+				     file_path_ids,
+				     format,
+				 }: ExportChecksumsArgs| async move {
+					let location = find_location(&library, location_id)
+						.exec()
+						.await?
+						.ok_or(LocationError::IdNotFound(location_id))?;
+
+					let mut exporter = ChecksumExporter::new(location, sub_path, format)?;
+					if let Some(file_path_ids) = file_path_ids {
+						exporter = exporter.with_file_paths(file_path_ids);
+					}
+
+					NodeContext::dispatch(&node, &library, exporter, location_id)
+						.await
+						.map(|_| ())
+				},
+			)
+		})
+		.procedure("verifyChecksums", {
+			#[derive(Type, Deserialize)]
+			pub struct VerifyChecksumsArgs {
+				pub location_id: location::id::Type,
+				/// The `SHA256SUMS` or `.sfv` manifest to verify the files beside it against
+				pub manifest_file_path_id: file_path::id::Type,
+			}
+
+			R.with2(library_mut()).mutation(
+				|(node, library),
+				 VerifyChecksumsArgs {
+				     location_id,
+				     manifest_file_path_id,
+				 }: VerifyChecksumsArgs| async move {
+					let location = find_location(&library, location_id)
+						.exec()
+						.await?
+						.ok_or(LocationError::IdNotFound(location_id))?;
+
+					let importer = ChecksumImporter::new(location, manifest_file_path_id)?;
+
+					NodeContext::dispatch(&node, &library, importer, location_id)
+						.await
+						.map(|_| ())
+				},
+			)
+		})
+		.procedure("eraseCaveats", {
 			R.with2(library())
-				.mutation(|(node, library), args: OldFileDeleterJobInit| async move {
+				.query(|(_, library), location_id: location::id::Type| async move {
+					Ok(erase_caveats(
+						get_location_path_from_location_id(&library.db, location_id).await?,
+					)
+					.await)
+				})
+		})
+		.procedure("deleteFiles", {
+			R.with2(library_mut()).mutation(
+				|(node, library), args: OldFileDeleterJobInit| async move {
 					match args.file_path_ids.len() {
 						0 => Ok(()),
-						1 => {
+						// Other modes take long enough to be better off in a job
+						1 if args.mode == DeleteMode::Delete => {
 							let (maybe_location, maybe_file_path) = library
 								.db
 								._batch((
@@ -479,11 +804,24 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 										"File not found in the file system, will remove from database: {}",
 										full_path.display()
 									);
+									let file_path_id = args.file_path_ids[0];
+
 									library
 										.db
-										.file_path()
-										.delete(file_path::id::equals(args.file_path_ids[0]))
-										.exec()
+										._transaction()
+										.with_timeout(30 * 1000)
+										.run(|db| async move {
+											file_identifier::statistics::forget(
+												vec![file_path::id::equals(file_path_id)],
+												&db,
+											)
+											.await?;
+
+											db.file_path()
+												.delete(file_path::id::equals(file_path_id))
+												.exec()
+												.await
+										})
 										.await
 										.map_err(LocationError::from)?;
 
@@ -500,11 +838,12 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 							.await
 							.map_err(Into::into),
 					}
-				})
+				},
+			)
 		})
 		.procedure("moveToTrash", {
-			R.with2(library())
-				.mutation(|(node, library), args: OldFileDeleterJobInit| async move {
+			R.with2(library_mut()).mutation(
+				|(node, library), args: OldFileDeleterJobInit| async move {
 					if cfg!(target_os = "ios") || cfg!(target_os = "android") {
 						return Err(rspc::Error::new(
 							ErrorCode::MethodNotSupported,
@@ -512,41 +851,29 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 						));
 					}
 
+					let args = OldFileDeleterJobInit {
+						mode: DeleteMode::Trash,
+						..args
+					};
+
 					match args.file_path_ids.len() {
 						0 => Ok(()),
 						1 => {
-							let (maybe_location, maybe_file_path) = library
-								.db
-								._batch((
-									library
-										.db
-										.location()
-										.find_unique(location::id::equals(args.location_id))
-										.select(location::select!({ path })),
-									library
-										.db
-										.file_path()
-										.find_unique(file_path::id::equals(args.file_path_ids[0]))
-										.select(file_path_to_isolate::select()),
-								))
-								.await?;
-
-							let location_path = maybe_location
-								.ok_or(LocationError::IdNotFound(args.location_id))?
-								.path
-								.ok_or(LocationError::MissingPath(args.location_id))?;
+							let Library { db, sync, .. } = library.as_ref();
 
-							let file_path = maybe_file_path.ok_or(LocationError::FilePath(
-								FilePathError::IdNotFound(args.file_path_ids[0]),
-							))?;
+							let file_data = get_many_files_datas(
+								db,
+								get_location_path_from_location_id(db, args.location_id).await?,
+								&args.file_path_ids,
+							)
+							.await?
+							.into_iter()
+							.next()
+							.expect("a single file path was asked for");
 
-							let full_path = Path::new(&location_path).join(
-								IsolatedFilePathData::try_from(&file_path)
-									.map_err(LocationError::MissingField)?,
-							);
+							move_to_trash(db, sync, args.location_id, &file_data).await?;
 
-							#[cfg(not(any(target_os = "ios", target_os = "android")))]
-							trash::delete(full_path).unwrap();
+							invalidate_query!(library, "search.paths");
 
 							Ok(())
 						}
@@ -555,7 +882,22 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 							.await
 							.map_err(Into::into),
 					}
-				})
+				},
+			)
+		})
+		.procedure("restoreFromTrash", {
+			R.with2(library_mut()).mutation(
+				|(_, library), file_path_id: file_path::id::Type| async move {
+					let Library { db, sync, .. } = library.as_ref();
+
+					let res = restore_from_trash(db, sync, file_path_id).await;
+
+					// Even when failing, file paths no longer in the trash were removed
+					invalidate_query!(library, "search.paths");
+
+					res.map_err(Into::into)
+				},
+			)
 		})
 		.procedure("convertImage", {
 			#[derive(Type, Deserialize)]
@@ -566,7 +908,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				desired_extension: ConvertibleExtension,
 				quality_percentage: Option<i32>, // 1% - 125%
 			}
-			R.with2(library())
+			R.with2(library_mut())
 				.mutation(|(_, library), args: ConvertImageArgs| async move {
 					// TODO:(fogodev) I think this will have to be a Job due to possibly being too much CPU Bound for rspc
 
@@ -694,32 +1036,185 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 			R.query(|_, _: ()| async move { Ok(sd_images::all_compatible_extensions()) })
 		})
 		.procedure("eraseFiles", {
-			R.with2(library())
-				.mutation(|(node, library), args: OldFileEraserJobInit| async move {
+			R.with2(library_mut()).mutation(
+				|(node, library), args: OldFileEraserJobInit| async move {
 					Job::new(args)
 						.spawn(&node, &library)
 						.await
 						.map_err(Into::into)
-				})
+				},
+			)
 		})
 		.procedure("copyFiles", {
-			R.with2(library())
-				.mutation(|(node, library), args: OldFileCopierJobInit| async move {
-					Job::new(args)
-						.spawn(&node, &library)
+			R.with2(library_mut())
+				.mutation(|(node, library), args: TransferFilesArgs| async move {
+					let (source_location, target_location) =
+						find_transfer_locations(&library, &args).await?;
+
+					let copier = FileCopier::new(
+						source_location,
+						&target_location,
+						args.target_location_relative_directory_path,
+						args.sources_file_path_ids,
+						library.config().await.cas_id_algorithm,
+					)?
+					.with_conflict_policy(args.conflict_policy);
+
+					NodeContext::dispatch(&node, &library, copier, target_location.id)
 						.await
-						.map_err(Into::into)
+						.map(|_| ())
 				})
 		})
 		.procedure("cutFiles", {
-			R.with2(library())
-				.mutation(|(node, library), args: OldFileCutterJobInit| async move {
-					Job::new(args)
-						.spawn(&node, &library)
+			R.with2(library_mut())
+				.mutation(|(node, library), args: TransferFilesArgs| async move {
+					let (source_location, target_location) =
+						find_transfer_locations(&library, &args).await?;
+
+					let mover = FileMover::new(
+						&source_location,
+						&target_location,
+						args.target_location_relative_directory_path,
+						args.sources_file_path_ids,
+						library.config().await.cas_id_algorithm,
+					)?
+					.with_conflict_policy(args.conflict_policy);
+
+					NodeContext::dispatch(&node, &library, mover, target_location.id)
 						.await
-						.map_err(Into::into)
+						.map(|_| ())
 				})
 		})
+		.procedure("compress", {
+			#[derive(Type, Deserialize)]
+			pub struct CompressArgs {
+				pub location_id: location::id::Type,
+				pub file_path_ids: Vec<file_path::id::Type>,
+				pub target_location_relative_directory_path: PathBuf,
+				pub name: String,
+				#[serde(default)]
+				pub format: CompressionFormat,
+			}
+
+			R.with2(library_mut()).mutation(
+				|(node, library),
+				 CompressArgs {
+				     location_id,
+				     file_path_ids,
+				     target_location_relative_directory_path,
+				     name,
+				     format,
+				 }: CompressArgs| async move {
+					let location = find_location(&library, location_id)
+						.exec()
+						.await?
+						.ok_or(LocationError::IdNotFound(location_id))?;
+
+					let compressor = Compressor::new(
+						location,
+						file_path_ids,
+						target_location_relative_directory_path,
+						name,
+						format,
+						library.config().await.cas_id_algorithm,
+					)?;
+
+					NodeContext::dispatch(&node, &library, compressor, location_id)
+						.await
+						.map(|_| ())
+				},
+			)
+		})
+		.procedure("extract", {
+			#[derive(Type, Deserialize)]
+			pub struct ExtractArgs {
+				pub location_id: location::id::Type,
+				pub file_path_id: file_path::id::Type,
+				pub target_location_relative_directory_path: PathBuf,
+				#[serde(default)]
+				pub conflict_policy: ConflictPolicy,
+				pub password: Option<String>,
+			}
+
+			R.with2(library_mut()).mutation(
+				|(node, library),
+				 ExtractArgs {
+				     location_id,
+				     file_path_id,
+				     target_location_relative_directory_path,
+				     conflict_policy,
+				     password,
+				 }: ExtractArgs| async move {
+					let location = find_location(&library, location_id)
+						.exec()
+						.await?
+						.ok_or(LocationError::IdNotFound(location_id))?;
+
+					let mut extractor = Extractor::new(
+						location,
+						file_path_id,
+						target_location_relative_directory_path,
+					)?
+					.with_conflict_policy(conflict_policy);
+
+					if let Some(password) = password {
+						extractor = extractor.with_password(password);
+					}
+
+					NodeContext::dispatch(&node, &library, extractor, location_id)
+						.await
+						.map(|_| ())
+				},
+			)
+		})
+		.procedure("provideArchivePassword", {
+			/// Passwords of encrypted archives aren't stored, so an extraction resumed after a
+			/// shutdown waits for its password to be given again here
+			#[derive(Type, Deserialize)]
+			pub struct ProvideArchivePasswordArgs {
+				pub job_id: Uuid,
+				pub location_id: location::id::Type,
+				pub file_path_id: file_path::id::Type,
+				pub password: String,
+			}
+
+			R.with2(library_mut()).mutation(
+				|(node, library),
+				 ProvideArchivePasswordArgs {
+				     job_id,
+				     location_id,
+				     file_path_id,
+				     password,
+				 }: ProvideArchivePasswordArgs| async move {
+					let location_path =
+						get_location_path_from_location_id(&library.db, location_id).await?;
+
+					let file_path = library
+						.db
+						.file_path()
+						.find_first(vec![
+							file_path::location_id::equals(Some(location_id)),
+							file_path::id::equals(file_path_id),
+						])
+						.select(file_path_to_isolate::select())
+						.exec()
+						.await?
+						.ok_or(LocationError::FilePath(FilePathError::IdNotFound(
+							file_path_id,
+						)))?;
+
+					library.key_manager.provide_archive_password(
+						location_path.join(
+							IsolatedFilePathData::try_from(&file_path)
+								.map_err(LocationError::MissingField)?,
+						),
+						password,
+					);
+
+					node.job_system.resume(job_id).await.map_err(Into::into)
+				},
+			)
+		})
 		.procedure("renameFile", {
 			#[derive(Type, Deserialize)]
 			pub struct RenameOne {
@@ -902,7 +1397,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				}
 			}
 
-			R.with2(library()).mutation(
+			R.with2(library_mut()).mutation(
 				|(_, library), RenameFileArgs { location_id, kind }: RenameFileArgs| async move {
 					let location_path =
 						get_location_path_from_location_id(&library.db, location_id).await?;
@@ -923,6 +1418,53 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				},
 			)
 		})
+		.procedure("bulkRenamePreview", {
+			R.with2(library()).query(
+				|(_, library),
+				 BulkRenameArgs {
+				     location_id,
+				     file_path_ids,
+				     pattern,
+				 }: BulkRenameArgs| async move {
+					let location_path =
+						get_location_path_from_location_id(&library.db, location_id).await?;
+
+					bulk_rename::preview(
+						&library.db,
+						location_id,
+						location_path,
+						&file_path_ids,
+						&pattern,
+					)
+					.await
+					.map_err(Into::into)
+				},
+			)
+		})
+		.procedure("bulkRename", {
+			R.with2(library_mut()).mutation(
+				|(node, library),
+				 BulkRenameArgs {
+				     location_id,
+				     file_path_ids,
+				     pattern,
+				 }: BulkRenameArgs| async move {
+					let location = find_location(&library, location_id)
+						.exec()
+						.await?
+						.ok_or(LocationError::IdNotFound(location_id))?;
+
+					NodeContext::dispatch(
+						&node,
+						&library,
+						BulkRename::new(&location, file_path_ids, pattern)?,
+						location_id,
+					)
+					.await
+					.map(|_| ())
+				},
+			)
+		})
 }
 
 pub(super) async fn create_directory(
@@ -1003,6 +1545,44 @@ pub(super) async fn create_file(
 		.to_string())
 }
 
+/// File paths of a location renamed following a pattern, previewed before renaming them
+#[derive(Type, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkRenameArgs {
+	pub location_id: location::id::Type,
+	pub file_path_ids: Vec<file_path::id::Type>,
+	pub pattern: RenamePattern,
+}
+
+/// File paths of a location copied or moved into a directory of another location (or the same one)
+#[derive(Type, Deserialize)]
+pub struct TransferFilesArgs {
+	pub source_location_id: location::id::Type,
+	pub target_location_id: location::id::Type,
+	pub sources_file_path_ids: Vec<file_path::id::Type>,
+	pub target_location_relative_directory_path: PathBuf,
+	#[serde(default)]
+	pub conflict_policy: ConflictPolicy,
+}
+
+async fn find_transfer_locations(
+	library: &Library,
+	args: &TransferFilesArgs,
+) -> Result<(location::Data, location::Data), rspc::Error> {
+	let (source_location, target_location) = library
+		.db
+		._batch((
+			find_location(library, args.source_location_id),
+			find_location(library, args.target_location_id),
+		))
+		.await?;
+
+	Ok((
+		source_location.ok_or(LocationError::IdNotFound(args.source_location_id))?,
+		target_location.ok_or(LocationError::IdNotFound(args.target_location_id))?,
+	))
+}
+
 #[derive(Type, Deserialize)]
 pub struct FromPattern {
 	pub pattern: String,
@@ -1,34 +1,57 @@
 use crate::{
+	context::NodeContext,
 	invalidate_query,
+	library::Library,
 	location::{find_location, LocationError},
 	object::{
-		media::OldMediaProcessorJobInit,
-		old_file_identifier::old_file_identifier_job::OldFileIdentifierJobInit,
+		fs::old_cleanup::OldCleanupJobInit, old_kind_reidentifier::OldKindReidentifierJobInit,
+		old_orphan_remover::OldOrphanRemoverJobInit,
 		validation::old_validator_job::OldObjectValidatorJobInit,
 	},
-	old_job::{Job, JobReport, JobStatus, OldJobs},
+	old_job::{Job, JobHistoryEntry, JobManagerError, JobReport, JobStatus, JobTrigger, OldJobs},
+	p2p::operations::{delegate_job, location_owner, DelegatedJob},
+	util::export_job_trace,
+	Node,
 };
 
-use sd_core_prisma_helpers::job_without_data;
+#[cfg(feature = "transcription")]
+use sd_core_heavy_lifting::transcriber::Transcriber;
+#[cfg(feature = "ai")]
+use sd_core_heavy_lifting::{embedder::Embedder, image_labeler::ImageLabeler};
+use sd_core_heavy_lifting::{
+	file_identifier::FileIdentifier,
+	indexer::Indexer,
+	media_processor::MediaProcessor,
+	script_runner::{ScriptCommand, ScriptRunner},
+	text_extractor::TextExtractor,
+	Job as HeavyJob, JobChain, SerializableJob,
+};
+use sd_core_prisma_helpers::{job_without_data, location_with_indexer_rules};
+use sd_file_ext::custom_kind::KindRegistry;
+use sd_task_system::ResourceProfile;
 
-use sd_prisma::prisma::{job, location, SortOrder};
+use sd_prisma::prisma::{job, job_history, location, SortOrder};
 
 use std::{
 	collections::{hash_map::Entry, BTreeMap, HashMap, VecDeque},
 	path::PathBuf,
+	sync::Arc,
 	time::Instant,
 };
 
 use chrono::{DateTime, Utc};
 use prisma_client_rust::or;
-use rspc::alpha::AlphaRouter;
+use rspc::{alpha::AlphaRouter, ErrorCode};
 use serde::{Deserialize, Serialize};
 use specta::Type;
 use tokio::time::Duration;
 use tracing::{info, trace};
 use uuid::Uuid;
 
-use super::{utils::library, CoreEvent, Ctx, R};
+use super::{
+	utils::{library, library_mut},
+	CoreEvent, Ctx, R,
+};
 
 pub(crate) fn mount() -> AlphaRouter<Ctx> {
 	R.router()
@@ -161,6 +184,87 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 					Ok(groups_vec)
 				})
 		})
+		.procedure("history", {
+			// Finished job runs, newest first, paginated by the id of the last run received
+			const MAX_TAKE: u8 = 100;
+
+			#[derive(Deserialize, Type, Debug)]
+			#[serde(rename_all = "camelCase")]
+			pub struct JobHistoryArgs {
+				#[specta(optional)]
+				location_id: Option<location::id::Type>,
+				#[specta(optional)]
+				name: Option<String>,
+				#[specta(optional)]
+				status: Option<JobStatus>,
+				#[specta(optional)]
+				take: Option<u8>,
+				#[specta(optional)]
+				cursor: Option<job_history::id::Type>,
+			}
+
+			#[derive(Serialize, Type, Debug)]
+			pub struct JobHistoryPage {
+				items: Vec<JobHistoryEntry>,
+				cursor: Option<job_history::id::Type>,
+			}
+
+			R.with2(library()).query(
+				|(_, library),
+				 JobHistoryArgs {
+				     location_id,
+				     name,
+				     status,
+				     take,
+				     cursor,
+				 }: JobHistoryArgs| async move {
+					let take = take.unwrap_or(MAX_TAKE).min(MAX_TAKE);
+
+					let mut items = library
+						.db
+						.job_history()
+						.find_many(
+							[
+								location_id.map(|id| job_history::location_id::equals(Some(id))),
+								name.map(job_history::name::equals),
+								status.map(|status| job_history::status::equals(status as i32)),
+								cursor.map(job_history::id::lt),
+							]
+							.into_iter()
+							.flatten()
+							.collect(),
+						)
+						.order_by(job_history::id::order(SortOrder::Desc))
+						.take(i64::from(take) + 1)
+						.exec()
+						.await?
+						.into_iter()
+						.map(JobHistoryEntry::from)
+						.collect::<Vec<_>>();
+
+					// We fetched one more than asked to know if there is a next page
+					let cursor = if items.len() > usize::from(take) {
+						items.truncate(take.into());
+						items.last().map(|item| item.id)
+					} else {
+						None
+					};
+
+					Ok(JobHistoryPage { items, cursor })
+				},
+			)
+		})
+		.procedure("trace", {
+			// Spans of a job run since the node started, as a Chrome trace to be opened in a profiler
+			R.with2(library()).query(|_, id: Uuid| async move {
+				export_job_trace(id).ok_or_else(|| {
+					rspc::Error::new(
+						ErrorCode::NotFound,
+						format!("No trace recorded for job <id='{id}'>"),
+					)
+				})
+			})
+		})
 		.procedure("isActive", {
 			R.with2(library())
 				.query(|(node, library), _: ()| async move {
@@ -168,7 +272,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				})
 		})
 		.procedure("clear", {
-			R.with2(library())
+			R.with2(library_mut())
 				.mutation(|(_, library), id: Uuid| async move {
 					library
 						.db
@@ -182,7 +286,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				})
 		})
 		.procedure("clearAll", {
-			R.with2(library())
+			R.with2(library_mut())
 				.mutation(|(_, library), _: ()| async move {
 					info!("Clearing all jobs");
 					library
@@ -203,33 +307,166 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 		})
 		// pause job
 		.procedure("pause", {
-			R.with2(library())
+			R.with2(library_mut())
 				.mutation(|(node, library), id: Uuid| async move {
-					let ret = OldJobs::pause(&node.old_jobs, id).await.map_err(Into::into);
+					// Jobs unknown to the old job system may be running on the new one
+					let ret = match OldJobs::pause(&node.old_jobs, id).await {
+						Err(JobManagerError::NotFound(_)) => {
+							node.job_system.pause(id).await.map_err(Into::into)
+						}
+						ret => ret.map_err(Into::into),
+					};
 					invalidate_query!(library, "jobs.reports");
 					ret
 				})
 		})
 		.procedure("resume", {
-			R.with2(library())
+			R.with2(library_mut())
 				.mutation(|(node, library), id: Uuid| async move {
-					let ret = OldJobs::resume(&node.old_jobs, id)
-						.await
-						.map_err(Into::into);
+					let ret = match OldJobs::resume(&node.old_jobs, id).await {
+						Err(JobManagerError::NotFound(_)) => {
+							node.job_system.resume(id).await.map_err(Into::into)
+						}
+						ret => ret.map_err(Into::into),
+					};
 					invalidate_query!(library, "jobs.reports");
 					ret
 				})
 		})
 		.procedure("cancel", {
-			R.with2(library())
+			R.with2(library_mut())
 				.mutation(|(node, library), id: Uuid| async move {
-					let ret = OldJobs::cancel(&node.old_jobs, id)
-						.await
-						.map_err(Into::into);
+					let ret = match OldJobs::cancel(&node.old_jobs, id).await {
+						Err(JobManagerError::NotFound(_)) => {
+							node.job_system.cancel(id).await.map_err(Into::into)
+						}
+						ret => ret.map_err(Into::into),
+					};
 					invalidate_query!(library, "jobs.reports");
 					ret
 				})
 		})
+		.procedure("runChain", {
+			/// A job run as a stage of a chain, on the location and sub path of the chain
+			#[derive(Type, Deserialize)]
+			pub enum ChainStage {
+				Index,
+				Identify,
+				ProcessMedia,
+				Script(ScriptCommand),
+			}
+
+			#[derive(Type, Deserialize)]
+			#[serde(rename_all = "camelCase")]
+			pub struct RunChainArgs {
+				pub location_id: location::id::Type,
+				#[serde(default)]
+				pub sub_path: Option<PathBuf>,
+				pub stages: Vec<ChainStage>,
+				/// Caps how much of the machine the stages use, below the profile of the node
+				#[serde(default)]
+				#[specta(type = Option<String>)]
+				pub resource_profile: Option<ResourceProfile>,
+			}
+
+			fn push<J: HeavyJob + SerializableJob<NodeContext>>(
+				chain: Option<JobChain<NodeContext>>,
+				stage: J,
+			) -> Option<JobChain<NodeContext>> {
+				Some(match chain {
+					Some(chain) => chain.then(stage),
+					None => JobChain::new(stage),
+				})
+			}
+
+			// Stages run one after the other, a failed stage cancels the ones after it. Returns the
+			// id of the chain, to follow it with `jobs.chainProgress`.
+			R.with2(library_mut()).mutation(
+				|(node, library),
+				 RunChainArgs {
+				     location_id,
+				     sub_path,
+				     stages,
+				     resource_profile,
+				 }: RunChainArgs| async move {
+					let Some(location) = find_location(&library, location_id)
+						.include(location_with_indexer_rules::include())
+						.exec()
+						.await?
+					else {
+						return Err(LocationError::IdNotFound(location_id).into());
+					};
+
+					let location_data = location::Data::from(&location);
+
+					if location_owner(&library, &location_data).await?.is_some() {
+						return Err(rspc::Error::new(
+							ErrorCode::BadRequest,
+							"Chains can only run on locations attached to this node".to_string(),
+						));
+					}
+
+					let config = library.config().await;
+					let mut chain = None;
+
+					for stage in stages {
+						chain = match stage {
+							ChainStage::Index => {
+								push(chain, Indexer::new(location.clone(), sub_path.clone())?)
+							}
+							ChainStage::Identify => {
+								let job = FileIdentifier::new(
+									location_data.clone(),
+									sub_path.clone(),
+									config.cas_id_algorithm,
+								)?
+								.with_kind_registry(KindRegistry::new(config.custom_kinds.clone()))
+								.with_batch_size_bounds(config.identifier_batch_size_bounds);
+
+								push(chain, job)
+							}
+							ChainStage::ProcessMedia => push(
+								chain,
+								MediaProcessor::new(
+									location_data.clone(),
+									sub_path.clone(),
+									false,
+								)?,
+							),
+							ChainStage::Script(command) => push(
+								chain,
+								ScriptRunner::new(&location_data, sub_path.clone(), command)?,
+							),
+						};
+					}
+
+					let Some(mut chain) = chain else {
+						return Err(rspc::Error::new(
+							ErrorCode::BadRequest,
+							"Chains need at least one stage".to_string(),
+						));
+					};
+
+					if let Some(resource_profile) = resource_profile {
+						chain = chain.with_resource_profile(resource_profile);
+					}
+
+					let chain_id =
+						NodeContext::dispatch_chain(&node, &library, chain, location_id).await?;
+
+					invalidate_query!(library, "jobs.reports");
+
+					Ok(chain_id)
+				},
+			)
+		})
+		.procedure("chainProgress", {
+			// `null` once none of the stages of the chain is running anymore
+			R.with2(library())
+				.query(|(node, _), chain_id: Uuid| async move {
+					Ok(node.job_system.chain_progress(chain_id).await)
+				})
+		})
 		.procedure("generateThumbsForLocation", {
 			#[derive(Type, Deserialize)]
 			pub struct GenerateThumbsForLocationArgs {
@@ -239,7 +476,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				pub regenerate: bool,
 			}
 
-			R.with2(library()).mutation(
+			R.with2(library_mut()).mutation(
 				|(node, library),
 				 GenerateThumbsForLocationArgs {
 				     id,
@@ -250,15 +487,17 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 						return Err(LocationError::IdNotFound(id).into());
 					};
 
-					Job::new(OldMediaProcessorJobInit {
+					spawn_or_delegate(
+						&node,
+						&library,
 						location,
-						sub_path: Some(path),
-						regenerate_thumbnails: regenerate,
-						regenerate_labels: false,
-					})
-					.spawn(&node, &library)
+						path,
+						DelegatedJob::MediaProcessor {
+							regenerate_thumbnails: regenerate,
+							regenerate_labels: false,
+						},
+					)
 					.await
-					.map_err(Into::into)
 				},
 			)
 		})
@@ -271,26 +510,158 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				pub regenerate: bool,
 			}
 
-			R.with2(library()).mutation(
+			R.with2(library_mut()).mutation(
 				|(node, library),
 				 GenerateLabelsForLocationArgs {
 				     id,
 				     path,
 				     regenerate,
 				 }: GenerateLabelsForLocationArgs| async move {
+					#[cfg(not(feature = "ai"))]
+					{
+						let _ = (node, library, id, path, regenerate);
+
+						return Err::<(), _>(rspc::Error::new(
+							ErrorCode::MethodNotSupported,
+							"AI feature is not available".to_string(),
+						));
+					}
+
+					#[cfg(feature = "ai")]
+					{
+						let Some(location) = find_location(&library, id).exec().await? else {
+							return Err(LocationError::IdNotFound(id).into());
+						};
+
+						let mut labeler =
+							ImageLabeler::new(location, Some(path))?.with_regenerate(regenerate);
+						if let Some(version) = node.config.get().await.image_labeler_version {
+							labeler = labeler.with_model_version(version);
+						}
+
+						NodeContext::dispatch(&node, &library, labeler, id)
+							.await
+							.map(|_| ())
+					}
+				},
+			)
+		})
+		.procedure("extractTextForLocation", {
+			#[derive(Type, Deserialize)]
+			pub struct ExtractTextForLocationArgs {
+				pub id: location::id::Type,
+				pub path: PathBuf,
+				#[serde(default)]
+				pub regenerate: bool,
+				/// Tesseract's languages to recognize, like `eng+por`, English when missing
+				pub languages: Option<String>,
+			}
+
+			R.with2(library_mut()).mutation(
+				|(node, library),
+				 ExtractTextForLocationArgs {
+				     id,
+				     path,
+				     regenerate,
+				     languages,
+				 }: ExtractTextForLocationArgs| async move {
 					let Some(location) = find_location(&library, id).exec().await? else {
 						return Err(LocationError::IdNotFound(id).into());
 					};
 
-					Job::new(OldMediaProcessorJobInit {
-						location,
-						sub_path: Some(path),
-						regenerate_thumbnails: false,
-						regenerate_labels: regenerate,
-					})
-					.spawn(&node, &library)
-					.await
-					.map_err(Into::into)
+					let mut extractor =
+						TextExtractor::new(location, Some(path))?.with_regenerate(regenerate);
+					if let Some(languages) = languages {
+						extractor = extractor.with_languages(languages);
+					}
+
+					NodeContext::dispatch(&node, &library, extractor, id)
+						.await
+						.map(|_| ())
+				},
+			)
+		})
+		.procedure("transcribeForLocation", {
+			#[derive(Type, Deserialize)]
+			pub struct TranscribeForLocationArgs {
+				pub id: location::id::Type,
+				pub path: PathBuf,
+				#[serde(default)]
+				pub regenerate: bool,
+			}
+
+			R.with2(library_mut()).mutation(
+				|(node, library),
+				 TranscribeForLocationArgs {
+				     id,
+				     path,
+				     regenerate,
+				 }: TranscribeForLocationArgs| async move {
+					#[cfg(not(feature = "transcription"))]
+					{
+						let _ = (node, library, id, path, regenerate);
+
+						return Err::<(), _>(rspc::Error::new(
+							ErrorCode::MethodNotSupported,
+							"Transcription feature is not available".to_string(),
+						));
+					}
+
+					#[cfg(feature = "transcription")]
+					{
+						let Some(location) = find_location(&library, id).exec().await? else {
+							return Err(LocationError::IdNotFound(id).into());
+						};
+
+						let transcriber =
+							Transcriber::new(location, Some(path))?.with_regenerate(regenerate);
+
+						NodeContext::dispatch(&node, &library, transcriber, id)
+							.await
+							.map(|_| ())
+					}
+				},
+			)
+		})
+		.procedure("embedForLocation", {
+			#[derive(Type, Deserialize)]
+			pub struct EmbedForLocationArgs {
+				pub id: location::id::Type,
+				pub path: PathBuf,
+				#[serde(default)]
+				pub regenerate: bool,
+			}
+
+			R.with2(library_mut()).mutation(
+				|(node, library),
+				 EmbedForLocationArgs {
+				     id,
+				     path,
+				     regenerate,
+				 }: EmbedForLocationArgs| async move {
+					#[cfg(not(feature = "ai"))]
+					{
+						let _ = (node, library, id, path, regenerate);
+
+						return Err::<(), _>(rspc::Error::new(
+							ErrorCode::MethodNotSupported,
+							"AI feature is not available".to_string(),
+						));
+					}
+
+					#[cfg(feature = "ai")]
+					{
+						let Some(location) = find_location(&library, id).exec().await? else {
+							return Err(LocationError::IdNotFound(id).into());
+						};
+
+						let embedder =
+							Embedder::new(location, Some(path))?.with_regenerate(regenerate);
+
+						NodeContext::dispatch(&node, &library, embedder, id)
+							.await
+							.map(|_| ())
+					}
 				},
 			)
 		})
@@ -301,8 +672,8 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				pub path: PathBuf,
 			}
 
-			R.with2(library())
-				.mutation(|(node, library), args: ObjectValidatorArgs| async move {
+			R.with2(library_mut()).mutation(
+				|(node, library), args: ObjectValidatorArgs| async move {
 					let Some(location) = find_location(&library, args.id).exec().await? else {
 						return Err(LocationError::IdNotFound(args.id).into());
 					};
@@ -314,22 +685,98 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 					.spawn(&node, &library)
 					.await
 					.map_err(Into::into)
-				})
+				},
+			)
 		})
 		.procedure("identifyUniqueFiles", {
 			#[derive(Type, Deserialize)]
 			pub struct IdentifyUniqueFilesArgs {
 				pub id: location::id::Type,
 				pub path: PathBuf,
+				/// Only reports the objects that would be created and linked, without writing
+				/// anything, once the identification is done
+				#[serde(default)]
+				pub dry_run: bool,
 			}
 
-			R.with2(library()).mutation(
+			R.with2(library_mut()).mutation(
 				|(node, library), args: IdentifyUniqueFilesArgs| async move {
 					let Some(location) = find_location(&library, args.id).exec().await? else {
 						return Err(LocationError::IdNotFound(args.id).into());
 					};
 
-					Job::new(OldFileIdentifierJobInit {
+					if !args.dry_run {
+						return spawn_or_delegate(
+							&node,
+							&library,
+							location,
+							args.path,
+							DelegatedJob::FileIdentifier,
+						)
+						.await
+						.map(|()| None);
+					}
+
+					if location_owner(&library, &location).await?.is_some() {
+						return Err(rspc::Error::new(
+							ErrorCode::BadRequest,
+							"Dry runs can only identify locations attached to this node"
+								.to_string(),
+						));
+					}
+
+					let config = library.config().await;
+					let job =
+						FileIdentifier::new(location, Some(args.path), config.cas_id_algorithm)?
+							.with_kind_registry(KindRegistry::new(config.custom_kinds))
+							.with_dry_run();
+
+					NodeContext::dispatch_and_wait(&node, &library, job, args.id).await
+				},
+			)
+		})
+		.procedure("importOsTags", {
+			#[derive(Type, Deserialize)]
+			pub struct ImportOsTagsArgs {
+				pub id: location::id::Type,
+				pub path: PathBuf,
+			}
+
+			R.with2(library_mut()).mutation(
+				|(node, library), ImportOsTagsArgs { id, path }: ImportOsTagsArgs| async move {
+					let Some(location) = find_location(&library, id).exec().await? else {
+						return Err(LocationError::IdNotFound(id).into());
+					};
+
+					let config = library.config().await;
+					let job = FileIdentifier::new_os_tags_import(
+						location,
+						Some(path),
+						config.cas_id_algorithm,
+					)?
+					.with_kind_registry(KindRegistry::new(config.custom_kinds))
+					.with_batch_size_bounds(config.identifier_batch_size_bounds);
+
+					NodeContext::dispatch(&node, &library, job, id).await?;
+
+					Ok(())
+				},
+			)
+		})
+		.procedure("reidentifyKinds", {
+			#[derive(Type, Deserialize)]
+			pub struct ReidentifyKindsArgs {
+				pub id: location::id::Type,
+				pub path: PathBuf,
+			}
+
+			R.with2(library_mut()).mutation(
+				|(node, library), args: ReidentifyKindsArgs| async move {
+					let Some(location) = find_location(&library, args.id).exec().await? else {
+						return Err(LocationError::IdNotFound(args.id).into());
+					};
+
+					Job::new(OldKindReidentifierJobInit {
 						location,
 						sub_path: Some(args.path),
 					})
@@ -339,6 +786,25 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				},
 			)
 		})
+		.procedure("cleanupLocation", {
+			R.with2(library_mut())
+				.mutation(|(node, library), args: OldCleanupJobInit| async move {
+					Job::new(args)
+						.spawn(&node, &library)
+						.await
+						.map_err(Into::into)
+				})
+		})
+		.procedure("removeOrphanObjects", {
+			R.with2(library_mut()).mutation(
+				|(node, library), args: OldOrphanRemoverJobInit| async move {
+					Job::new(args)
+						.spawn(&node, &library)
+						.await
+						.map_err(Into::into)
+				},
+			)
+		})
 		.procedure("newThumbnail", {
 			R.with2(library())
 				.subscription(|(node, _), _: ()| async move {
@@ -370,3 +836,28 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				})
 		})
 }
+
+/// Spawns `job` here, or on the node `location` is attached to so it doesn't read every file over
+/// the network
+async fn spawn_or_delegate(
+	node: &Arc<Node>,
+	library: &Arc<Library>,
+	location: location::Data,
+	sub_path: PathBuf,
+	job: DelegatedJob,
+) -> Result<(), rspc::Error> {
+	if let Some(owner) = location_owner(library, &location).await? {
+		return delegate_job(node, library, owner, &location, Some(sub_path), job)
+			.await
+			.map_err(|e| {
+				rspc::Error::new(
+					ErrorCode::InternalServerError,
+					format!("Failed to delegate job to the node the location is attached to: {e}"),
+				)
+			});
+	}
+
+	job.spawn(node, library, location, Some(sub_path), JobTrigger::User)
+		.await
+		.map_err(Into::into)
+}
@@ -14,7 +14,11 @@ use std::collections::BTreeMap;
 
 use rspc::alpha::AlphaRouter;
 
-use super::{locations::ExplorerItem, utils::library, Ctx, R};
+use super::{
+	locations::ExplorerItem,
+	utils::{library, library_mut},
+	Ctx, R,
+};
 
 pub(crate) fn mount() -> AlphaRouter<Ctx> {
 	R.router()
@@ -114,7 +118,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 		})
 		.procedure(
 			"delete",
-			R.with2(library())
+			R.with2(library_mut())
 				.mutation(|(_, library), label_id: i32| async move {
 					let Library { db, sync, .. } = library.as_ref();
 
@@ -1,6 +1,12 @@
 use crate::{
+	api::notifications::{NotificationData, NotificationKind},
 	invalidate_query,
-	library::{update_library_statistics, Library, LibraryConfig, LibraryName},
+	library::{
+		archive, encryption,
+		rollups::{self, LibraryRollups},
+		update_library_statistics, ConcurrencyLimit, Library, LibraryAccessMode, LibraryConfig,
+		LibraryName,
+	},
 	location::{scan_location, LocationCreateArgs, ScanState},
 	util::MaybeUndefined,
 	Node,
@@ -8,15 +14,17 @@ use crate::{
 
 use futures::StreamExt;
 use prisma_client_rust::raw;
-use sd_file_ext::kind::ObjectKind;
+use sd_core_heavy_lifting::file_identifier::{BatchSizeBounds, CasIdAlgorithm};
+use sd_file_ext::{custom_kind::CustomKind, kind::ObjectKind};
 use sd_p2p::RemoteIdentity;
-use sd_prisma::prisma::{indexer_rule, object, statistics};
+use sd_prisma::prisma::{indexer_rule, statistics};
 use tokio_stream::wrappers::IntervalStream;
 use tracing::{info, warn};
 
 use std::{
 	collections::{hash_map::Entry, HashMap},
 	convert::identity,
+	path::PathBuf,
 	pin::pin,
 	sync::Arc,
 	time::Duration,
@@ -38,7 +46,10 @@ use tokio::{
 use tracing::{debug, error};
 use uuid::Uuid;
 
-use super::{utils::library, Ctx, R};
+use super::{
+	utils::{library, library_mut},
+	Ctx, R,
+};
 
 const ONE_MINUTE: Duration = Duration::from_secs(60);
 const TWO_MINUTES: Duration = Duration::from_secs(60 * 2);
@@ -90,9 +101,11 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 			})
 		})
 		.procedure("statistics", {
-			#[derive(Serialize, Deserialize, Type)]
+			#[derive(Serialize, Type)]
 			pub struct StatisticsResponse {
 				statistics: Option<statistics::Data>,
+				/// Objects and bytes by kind, location and tag, and how they grew
+				rollups: LibraryRollups,
 			}
 			R.with2(library())
 				.query(|(node, library), _: ()| async move {
@@ -117,7 +130,10 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 						}
 					}
 
-					Ok(StatisticsResponse { statistics })
+					Ok(StatisticsResponse {
+						statistics,
+						rollups: rollups::fetch(&library.db).await?,
+					})
 				})
 		})
 		.procedure("kindStatistics", {
@@ -133,26 +149,83 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				statistics: Vec<KindStatistic>,
 			}
 			R.with2(library()).query(|(_, library), _: ()| async move {
-				let mut statistics: Vec<KindStatistic> = vec![];
-				for kind in ObjectKind::iter() {
-					let count = library
-						.db
-						.object()
-						.count(vec![object::kind::equals(Some(kind as i32))])
-						.exec()
-						.await?;
-
-					statistics.push(KindStatistic {
-						kind: kind as i32,
-						name: kind.to_string(),
-						count: count as i32,
-						total_bytes: "0".to_string(),
-					});
-				}
+				let mut by_kind = rollups::fetch(&library.db)
+					.await?
+					.by_kind
+					.into_iter()
+					.map(|rollup| (rollup.kind, rollup))
+					.collect::<HashMap<_, _>>();
+
+				let statistics = ObjectKind::iter()
+					.map(|kind| {
+						let rollup = by_kind.remove(&(kind as i32));
+
+						KindStatistic {
+							kind: kind as i32,
+							name: kind.to_string(),
+							count: rollup
+								.as_ref()
+								.map_or(0, |rollup| rollup.object_count as i32),
+							total_bytes: rollup
+								.map_or_else(|| "0".to_string(), |rollup| rollup.total_bytes),
+						}
+					})
+					.collect();
 
 				Ok(KindStatistics { statistics })
 			})
 		})
+		.procedure("thumbnailCacheUsage", {
+			R.with2(library())
+				.query(|(node, library), _: ()| async move {
+					let budget = node
+						.config
+						.get()
+						.await
+						.preferences
+						.thumbnailer
+						.library_cache_budget();
+
+					node.thumbnailer
+						.indexed_cache_usage(library.id, budget)
+						.await
+						.map_err(|e| {
+							rspc::Error::with_cause(
+								ErrorCode::InternalServerError,
+								"Failed to read thumbnail cache usage".to_string(),
+								e,
+							)
+						})
+				})
+		})
+		.procedure("cleanUpThumbnailCache", {
+			R.with2(library_mut())
+				.mutation(|(node, library), _: ()| async move {
+					let budget = node
+						.config
+						.get()
+						.await
+						.preferences
+						.thumbnailer
+						.library_cache_budget();
+
+					let eviction = node
+						.thumbnailer
+						.clean_up_indexed_cache(library.id, Arc::clone(&library.db), budget)
+						.await
+						.map_err(|e| {
+							rspc::Error::with_cause(
+								ErrorCode::InternalServerError,
+								"Failed to clean up thumbnail cache".to_string(),
+								e,
+							)
+						})?;
+
+					invalidate_query!(library, "library.thumbnailCacheUsage");
+
+					Ok(eviction)
+				})
+		})
 		.procedure("create", {
 			#[derive(Deserialize, Type, Default)]
 			pub struct DefaultLocations {
@@ -237,6 +310,11 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 								path,
 								dry_run: false,
 								indexer_rules_ids,
+								network_share: None,
+								s3: None,
+								webdav: None,
+								cloud_metadata: None,
+								mtp: None,
 							}
 							.create(&node, &library)
 							.await
@@ -317,6 +395,19 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				pub id: Uuid,
 				pub name: Option<LibraryName>,
 				pub description: MaybeUndefined<String>,
+				#[serde(default)]
+				pub cas_id_algorithm: Option<CasIdAlgorithm>,
+				/// As a string as it may not fit in a JS number, `null` hashes every file sampled
+				#[serde(default)]
+				pub deep_hash_threshold: MaybeUndefined<String>,
+				#[serde(default)]
+				pub custom_kinds: Option<Vec<CustomKind>>,
+				#[serde(default)]
+				pub access_mode: Option<LibraryAccessMode>,
+				#[serde(default)]
+				pub concurrency_limits: Option<Vec<ConcurrencyLimit>>,
+				#[serde(default)]
+				pub identifier_batch_size_bounds: Option<BatchSizeBounds>,
 			}
 
 			R.mutation(
@@ -325,10 +416,43 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				     id,
 				     name,
 				     description,
+				     cas_id_algorithm,
+				     deep_hash_threshold,
+				     custom_kinds,
+				     access_mode,
+				     concurrency_limits,
+				     identifier_batch_size_bounds,
 				 }: EditLibraryArgs| async move {
+					let deep_hash_threshold = match deep_hash_threshold {
+						MaybeUndefined::Undefined => MaybeUndefined::Undefined,
+						MaybeUndefined::Null => MaybeUndefined::Null,
+						MaybeUndefined::Value(threshold) => {
+							MaybeUndefined::Value(threshold.parse::<u64>().map_err(|e| {
+								rspc::Error::with_cause(
+									ErrorCode::BadRequest,
+									"Invalid deep hash threshold".to_string(),
+									e,
+								)
+							})?)
+						}
+					};
+
 					Ok(node
 						.libraries
-						.edit(id, name, description, MaybeUndefined::Undefined, None)
+						.edit(
+							&node,
+							id,
+							name,
+							description,
+							MaybeUndefined::Undefined,
+							None,
+							cas_id_algorithm,
+							deep_hash_threshold,
+							custom_kinds,
+							access_mode,
+							concurrency_limits,
+							identifier_batch_size_bounds,
+						)
 						.await?)
 				},
 			)
@@ -339,6 +463,146 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				node.libraries.delete(&id).await.map_err(Into::into)
 			}),
 		)
+		.procedure("export", {
+			R.with2(library())
+				.mutation(|(node, library), path: PathBuf| async move {
+					spawn(async move {
+						let name = library.config().await.name.to_string();
+
+						let notification = match archive::export(&node, &library, &path).await {
+							Ok(_) => {
+								info!("Exported library '{}' to '{}'", library.id, path.display());
+
+								NotificationData {
+									title: format!("{name} exported"),
+									content: format!("Saved to {}", path.display()),
+									kind: NotificationKind::Success,
+								}
+							}
+							Err(e) => {
+								error!(?e, library_id = %library.id, "Failed to export library;");
+
+								NotificationData {
+									title: format!("Failed to export {name}"),
+									content: e.to_string(),
+									kind: NotificationKind::Error,
+								}
+							}
+						};
+
+						node.emit_notification(notification, None).await;
+					});
+
+					Ok(())
+				})
+		})
+		.procedure("import", {
+			R.mutation(|node, path: PathBuf| async move {
+				// Archives that can't be imported are refused right away, unpacking them takes a while
+				let manifest = archive::inspect(&node, &path).await?;
+
+				spawn({
+					let manifest = manifest.clone();
+
+					async move {
+						let notification = match archive::import(&node, &path).await {
+							Ok(_) => {
+								info!(
+									"Imported library '{}' from '{}'",
+									manifest.library_id,
+									path.display()
+								);
+
+								NotificationData {
+									title: format!("{} imported", manifest.library_name),
+									content: "The library is ready to use".to_string(),
+									kind: NotificationKind::Success,
+								}
+							}
+							Err(e) => {
+								error!(
+									?e,
+									library_id = %manifest.library_id,
+									"Failed to import library;"
+								);
+
+								NotificationData {
+									title: format!("Failed to import {}", manifest.library_name),
+									content: e.to_string(),
+									kind: NotificationKind::Error,
+								}
+							}
+						};
+
+						node.emit_notification(notification, None).await;
+					}
+				});
+
+				Ok(manifest)
+			})
+		})
+		.procedure("encryptionStatus", {
+			R.with2(library())
+				.query(|(node, library), _: ()| async move {
+					let db_path = node
+						.libraries
+						.libraries_dir
+						.join(format!("{}.db", library.id));
+
+					Ok(encryption::status(library.id, &db_path).await?)
+				})
+		})
+		.procedure("encrypt", {
+			R.with2(library_mut())
+				.mutation(|(node, library), passphrase: String| async move {
+					let db_path = node
+						.libraries
+						.libraries_dir
+						.join(format!("{}.db", library.id));
+
+					encryption::encrypt(library.id, &db_path, &passphrase).await?;
+
+					info!(
+						"Set passphrase for library '{}', it will be encrypted on next load",
+						library.id
+					);
+
+					invalidate_query!(library, "library.encryptionStatus");
+
+					Ok(())
+				})
+		})
+		.procedure("locked", {
+			R.query(|node, _: ()| async move {
+				let loaded = node
+					.libraries
+					.get_all()
+					.await
+					.into_iter()
+					.map(|library| library.id)
+					.collect();
+
+				Ok(encryption::locked_libraries(&node.libraries.libraries_dir, &loaded).await?)
+			})
+		})
+		.procedure("unlock", {
+			#[derive(Deserialize, Type)]
+			pub struct UnlockLibraryArgs {
+				pub id: Uuid,
+				pub passphrase: String,
+			}
+
+			R.mutation(
+				|node, UnlockLibraryArgs { id, passphrase }: UnlockLibraryArgs| async move {
+					let library = encryption::unlock(&node, id, &passphrase).await?;
+
+					invalidate_query!(node; node, "library.list");
+					invalidate_query!(node; node, "library.locked");
+
+					Ok(LibraryConfigWrapped::from_library(&library).await)
+				},
+			)
+		})
 		.procedure(
 			"actors",
 			R.with2(library()).subscription(|(_, library), _: ()| {
@@ -357,7 +621,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 		)
 		.procedure(
 			"startActor",
-			R.with2(library())
+			R.with2(library_mut())
 				.mutation(|(_, library), name: String| async move {
 					library.actors.start(&name).await;
 
@@ -366,7 +630,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 		)
 		.procedure(
 			"stopActor",
-			R.with2(library())
+			R.with2(library_mut())
 				.mutation(|(_, library), name: String| async move {
 					library.actors.stop(&name).await;
 
@@ -375,7 +639,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 		)
 		.procedure(
 			"vaccumDb",
-			R.with2(library())
+			R.with2(library_mut())
 				.mutation(|(_, library), _: ()| async move {
 					// We retry a few times because if the DB is being actively used, the vacuum will fail
 					for _ in 0..5 {
@@ -1,24 +1,46 @@
 use crate::{
+	context::NodeContext,
 	invalidate_query,
+	library::sync_backfill::OldSyncBackfillJobInit,
 	location::{
-		delete_location, find_location, indexer::OldIndexerJobInit, light_scan_location,
-		non_indexed::NonIndexedPathItem, relink_location, scan_location, scan_location_sub_path,
+		capacity, delete_location, find_location, get_location_path_from_location_id,
+		indexer::OldIndexerJobInit,
+		is_suspended_snapshot, light_scan_location, mtp,
+		non_indexed::NonIndexedPathItem,
+		reconcile_snapshot_location, relink_location, scan_location, scan_location_sub_path,
+		versioning::{self, VersioningPolicy},
 		LocationCreateArgs, LocationError, LocationUpdateArgs, ScanState,
 	},
 	object::old_file_identifier::old_file_identifier_job::OldFileIdentifierJobInit,
-	old_job::StatefulJob,
+	old_job::{Job, StatefulJob},
 	p2p::PeerMetadata,
 	util::AbortOnDrop,
 };
 
+use sd_core_heavy_lifting::{
+	backup::{self, Backup, BackupSide, DeletionPolicy},
+	cold_archiver::{self, ArchiveRules, ColdArchiver},
+	disk_usage::{self, DiskUsageAnalyzer},
+	file_identifier::{self, FileIdentifier, IdentifierRule, IdentifierSettings},
+	folder_sync::{self, ConflictResolution, FolderSync},
+	job_system::schedule::{self, CatchUp, ScheduledJob},
+	verify_integrity::VerifyIntegrity,
+};
 use sd_core_indexer_rules::IndexerRuleCreateArgs;
 use sd_core_prisma_helpers::{
 	file_path_for_frontend, label_with_objects, location_with_indexer_rules, object_with_file_paths,
 };
+use sd_core_sync::{backfill, SyncPolicy};
 
-use sd_prisma::prisma::{file_path, indexer_rule, indexer_rules_in_location, location, SortOrder};
+use sd_file_ext::custom_kind::KindRegistry;
+use sd_prisma::prisma::{
+	file_path, indexer_rule, indexer_rules_in_location, job_error, location, SortOrder,
+};
 
-use std::path::{Path, PathBuf};
+use std::{
+	path::{Path, PathBuf},
+	sync::atomic::Ordering,
+};
 
 use chrono::{DateTime, FixedOffset, Utc};
 use directories::UserDirs;
@@ -27,7 +49,10 @@ use serde::{Deserialize, Serialize};
 use specta::Type;
 use tracing::{debug, error};
 
-use super::{utils::library, Ctx, R};
+use super::{
+	utils::{library, library_mut},
+	Ctx, R,
+};
 
 // it includes the shard hex formatted as ([["f02", "cab34a76fbf3469f"]])
 // Will be None if no thumbnail exists
@@ -209,6 +234,213 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 						.await?)
 				})
 		})
+		.procedure("listFailures", {
+			// Failures of the location, newest first, paginated by the id of the last one received
+			const MAX_TAKE: u8 = 100;
+
+			#[derive(Deserialize, Type, Debug)]
+			#[serde(rename_all = "camelCase")]
+			pub struct ListFailuresArgs {
+				location_id: location::id::Type,
+				#[specta(optional)]
+				take: Option<u8>,
+				#[specta(optional)]
+				cursor: Option<job_error::id::Type>,
+			}
+
+			#[derive(Serialize, Type, Debug)]
+			pub struct JobErrorPage {
+				items: Vec<job_error::Data>,
+				cursor: Option<job_error::id::Type>,
+			}
+
+			R.with2(library()).query(
+				|(_, library),
+				 ListFailuresArgs {
+				     location_id,
+				     take,
+				     cursor,
+				 }: ListFailuresArgs| async move {
+					let take = take.unwrap_or(MAX_TAKE).min(MAX_TAKE);
+
+					let mut items = library
+						.db
+						.job_error()
+						.find_many(
+							[
+								Some(job_error::location_id::equals(Some(location_id))),
+								cursor.map(job_error::id::lt),
+							]
+							.into_iter()
+							.flatten()
+							.collect(),
+						)
+						.order_by(job_error::id::order(SortOrder::Desc))
+						.take(i64::from(take) + 1)
+						.exec()
+						.await?;
+
+					// We fetched one more than asked to know if there is a next page
+					let cursor = if items.len() > usize::from(take) {
+						items.truncate(take.into());
+						items.last().map(|item| item.id)
+					} else {
+						None
+					};
+
+					Ok(JobErrorPage { items, cursor })
+				},
+			)
+		})
+		.procedure("retryFailures", {
+			R.with2(library_mut()).mutation(
+				|(node, library), location_id: location::id::Type| async move {
+					let Some(location) = find_location(&library, location_id).exec().await? else {
+						return Err(LocationError::IdNotFound(location_id).into());
+					};
+
+					let config = library.config().await;
+					let job = FileIdentifier::new_retry_failed(location, config.cas_id_algorithm)?
+						.with_kind_registry(KindRegistry::new(config.custom_kinds))
+						.with_batch_size_bounds(config.identifier_batch_size_bounds);
+
+					NodeContext::dispatch(&node, &library, job, location_id).await?;
+
+					invalidate_query!(library, "locations.listFailures");
+
+					Ok(())
+				},
+			)
+		})
+		.procedure("identificationStatistics", {
+			R.with2(library())
+				.query(|(_, library), location_id: location::id::Type| async move {
+					Ok(file_identifier::statistics::fetch(location_id, &library.db).await?)
+				})
+		})
+		.procedure("recomputeIdentificationStatistics", {
+			R.with2(library_mut()).mutation(
+				|(_, library), location_id: location::id::Type| async move {
+					file_identifier::statistics::recompute(location_id, &library.db).await?;
+
+					invalidate_query!(library, "locations.identificationStatistics");
+
+					Ok(())
+				},
+			)
+		})
+		.procedure("diskUsage", {
+			#[derive(Type, Deserialize)]
+			pub struct DiskUsageArgs {
+				pub location_id: location::id::Type,
+				/// Materialized path of the children of the directory, the root of the location if
+				/// `None`
+				#[serde(default)]
+				pub path: Option<String>,
+				/// Levels of subdirectories to send, [`disk_usage::DEFAULT_TREE_DEPTH`] if `None`
+				#[serde(default)]
+				pub depth: Option<u8>,
+			}
+
+			R.with2(library()).query(
+				|(_, library),
+				 DiskUsageArgs {
+				     location_id,
+				     path,
+				     depth,
+				 }: DiskUsageArgs| async move {
+					Ok(disk_usage::fetch(
+						&library.db,
+						location_id,
+						path.as_deref().unwrap_or("/"),
+						depth.unwrap_or(disk_usage::DEFAULT_TREE_DEPTH),
+					)
+					.await?)
+				},
+			)
+		})
+		.procedure("analyzeDiskUsage", {
+			#[derive(Type, Deserialize)]
+			pub struct AnalyzeDiskUsageArgs {
+				pub location_id: location::id::Type,
+				/// Relative to the root of the location, the whole location if `None`
+				#[serde(default)]
+				pub sub_path: Option<PathBuf>,
+			}
+
+			R.with2(library_mut()).mutation(
+				|(node, library),
+				 AnalyzeDiskUsageArgs {
+				     location_id,
+				     sub_path,
+				 }: AnalyzeDiskUsageArgs| async move {
+					let location = find_location(&library, location_id)
+						.exec()
+						.await?
+						.ok_or(LocationError::IdNotFound(location_id))?;
+
+					let analyzer = DiskUsageAnalyzer::new(location, sub_path)?;
+
+					NodeContext::dispatch(&node, &library, analyzer, location_id)
+						.await
+						.map(|_| ())
+				},
+			)
+		})
+		.procedure("verifyIntegrity", {
+			#[derive(Type, Deserialize)]
+			pub struct VerifyIntegrityArgs {
+				pub location_id: location::id::Type,
+				/// Relative to the root of the location, the whole location if `None`
+				#[serde(default)]
+				pub sub_path: Option<PathBuf>,
+				/// Only verifies these file paths, instead of every file under `sub_path`
+				#[serde(default)]
+				pub file_path_ids: Option<Vec<file_path::id::Type>>,
+			}
+
+			R.with2(library_mut()).mutation(
+				|(node, library),
+				 VerifyIntegrityArgs {
+				     location_id,
+				     sub_path,
+				     file_path_ids,
+				 }: VerifyIntegrityArgs| async move {
+					let location = find_location(&library, location_id)
+						.exec()
+						.await?
+						.ok_or(LocationError::IdNotFound(location_id))?;
+
+					let mut verifier = VerifyIntegrity::new(
+						location,
+						sub_path,
+						library.config().await.cas_id_algorithm,
+					)?;
+					if let Some(file_path_ids) = file_path_ids {
+						verifier = verifier.with_file_paths(file_path_ids);
+					}
+
+					NodeContext::dispatch(&node, &library, verifier, location_id)
+						.await
+						.map(|_| ())
+				},
+			)
+		})
+		.procedure("capacityHistory", {
+			#[derive(Deserialize, Type, Debug)]
+			#[serde(rename_all = "camelCase")]
+			pub struct CapacityHistoryArgs {
+				location_id: location::id::Type,
+				#[specta(optional)]
+				since: Option<DateTime<Utc>>,
+			}
+
+			R.with2(library()).query(
+				|(_, library), CapacityHistoryArgs { location_id, since }: CapacityHistoryArgs| async move {
+					Ok(capacity::capacity_history(&library, location_id, since).await?)
+				},
+			)
+		})
 		.procedure("getWithRules", {
 			#[derive(Type, Serialize)]
 			struct LocationWithIndexerRule {
@@ -223,13 +455,20 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				pub generate_preview_media: Option<bool>,
 				pub sync_preview_media: Option<bool>,
 				pub hidden: Option<bool>,
+				pub is_snapshot: Option<bool>,
 				pub date_created: Option<DateTime<FixedOffset>>,
 				pub instance_id: Option<i32>,
 				pub indexer_rules: Vec<indexer_rule::Data>,
+				pub identifier_rules: Vec<IdentifierRule>,
+				pub identifier_settings: IdentifierSettings,
 			}
 
 			impl LocationWithIndexerRule {
 				pub fn from_db(value: location_with_indexer_rules::Data) -> Self {
+					let location = (&value).into();
+					let identifier_rules = IdentifierRule::of_location(&location);
+					let identifier_settings = IdentifierSettings::of_location(&location);
+
 					Self {
 						id: value.id,
 						pub_id: value.pub_id,
@@ -242,6 +481,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 						generate_preview_media: value.generate_preview_media,
 						sync_preview_media: value.sync_preview_media,
 						hidden: value.hidden,
+						is_snapshot: value.is_snapshot,
 						date_created: value.date_created,
 						instance_id: value.instance_id,
 						indexer_rules: value
@@ -249,6 +489,8 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 							.into_iter()
 							.map(|i| i.indexer_rule)
 							.collect::<Vec<_>>(),
+						identifier_rules,
+						identifier_settings,
 					}
 				}
 			}
@@ -266,8 +508,8 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				})
 		})
 		.procedure("create", {
-			R.with2(library())
-				.mutation(|(node, library), args: LocationCreateArgs| async move {
+			R.with2(library_mut()).mutation(
+				|(node, library), args: LocationCreateArgs| async move {
 					if let Some(location) = args.create(&node, &library).await? {
 						let id = Some(location.id);
 						scan_location(&node, &library, location, ScanState::Pending).await?;
@@ -276,18 +518,20 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 					} else {
 						Ok(None)
 					}
-				})
+				},
+			)
 		})
 		.procedure("update", {
-			R.with2(library())
-				.mutation(|(node, library), args: LocationUpdateArgs| async move {
+			R.with2(library_mut()).mutation(
+				|(node, library), args: LocationUpdateArgs| async move {
 					let ret = args.update(&node, &library).await.map_err(Into::into);
 					invalidate_query!(library, "locations.list");
 					ret
-				})
+				},
+			)
 		})
 		.procedure("delete", {
-			R.with2(library()).mutation(
+			R.with2(library_mut()).mutation(
 				|(node, library), location_id: location::id::Type| async move {
 					delete_location(&node, &library, location_id).await?;
 					invalidate_query!(library, "locations.list");
@@ -296,16 +540,72 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 			)
 		})
 		.procedure("relink", {
-			R.with2(library())
+			R.with2(library_mut())
 				.mutation(|(_, library), location_path: PathBuf| async move {
 					relink_location(&library, location_path)
 						.await
 						.map_err(Into::into)
 				})
 		})
+		.procedure("setSyncPolicy", {
+			#[derive(Type, Deserialize)]
+			pub struct SetSyncPolicyArgs {
+				pub id: location::id::Type,
+				pub policy: SyncPolicy,
+			}
+
+			R.with2(library_mut()).mutation(
+				|(node, library), SetSyncPolicyArgs { id, policy }: SetSyncPolicyArgs| async move {
+					// Local only, other devices choose what they sync of their own locations
+					let location = library
+						.db
+						.location()
+						.update(
+							location::id::equals(id),
+							vec![location::sync_policy::set(policy.to_db())],
+						)
+						.select(location::select!({ pub_id }))
+						.exec()
+						.await?;
+
+					let previous = library
+						.sync
+						.set_location_policy(location.pub_id, policy)
+						.await?;
+
+					// Records the location didn't sync before were never sent, so they're sent now
+					// instead of waiting for their next change
+					let scope = backfill::Scope {
+						location_id: id,
+						previous,
+						policy,
+					};
+
+					if scope.start().is_some()
+						&& library
+							.config()
+							.await
+							.generate_sync_operations
+							.load(Ordering::Relaxed)
+					{
+						Job::new(OldSyncBackfillJobInit {
+							bytes_per_second: None,
+							scope: Some(scope),
+						})
+						.spawn(&node, &library)
+						.await?;
+					}
+
+					invalidate_query!(library, "locations.list");
+					invalidate_query!(library, "locations.get");
+
+					Ok(())
+				},
+			)
+		})
 		.procedure("addLibrary", {
-			R.with2(library())
-				.mutation(|(node, library), args: LocationCreateArgs| async move {
+			R.with2(library_mut()).mutation(
+				|(node, library), args: LocationCreateArgs| async move {
 					if let Some(location) = args.add_library(&node, &library).await? {
 						let id = location.id;
 						let location_scan_state = ScanState::try_from(location.scan_state)?;
@@ -315,7 +615,8 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 					} else {
 						Ok(None)
 					}
-				})
+				},
+			)
 		})
 		.procedure("fullRescan", {
 			#[derive(Type, Deserialize)]
@@ -323,7 +624,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				pub location_id: location::id::Type,
 				pub reidentify_objects: bool,
 			}
-			R.with2(library()).mutation(
+			R.with2(library_mut()).mutation(
 				|(node, library),
 				 FullRescanArgs {
 				     location_id,
@@ -367,6 +668,29 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				},
 			)
 		})
+		.procedure("reconcileSnapshot", {
+			R.with2(library_mut()).mutation(
+				|(node, library), location_id: location::id::Type| async move {
+					let location = find_location(&library, location_id)
+						.include(location_with_indexer_rules::include())
+						.exec()
+						.await?
+						.ok_or(LocationError::IdNotFound(location_id))?;
+
+					if location.is_snapshot != Some(true) {
+						return Err(LocationError::NotSnapshot(location_id).into());
+					}
+
+					if is_suspended_snapshot(&node, location.is_snapshot, &location.pub_id).await {
+						return Err(LocationError::SnapshotOffline(location_id).into());
+					}
+
+					reconcile_snapshot_location(&node, &library, location)
+						.await
+						.map_err(Into::into)
+				},
+			)
+		})
 		.procedure("subPathRescan", {
 			#[derive(Clone, Serialize, Deserialize, Type, Debug)]
 			pub struct RescanArgs {
@@ -374,7 +698,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				pub sub_path: String,
 			}
 
-			R.with2(library()).mutation(
+			R.with2(library_mut()).mutation(
 				|(node, library),
 				 RescanArgs {
 				     location_id,
@@ -408,10 +732,14 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				     location_id,
 				     sub_path,
 				 }: LightScanArgs| async move {
+					// The user opened this directory, so a file identifier running on the location
+					// identifies it first
+					library.prioritize_directory(location_id, &sub_path);
+
 					if node
 						.old_jobs
 						.has_job_running(|job_identity| {
-							job_identity.target_location == location_id
+							job_identity.target_location == Some(location_id)
 								&& (job_identity.name == <OldIndexerJobInit as StatefulJob>::NAME
 									|| job_identity.name
 										== <OldFileIdentifierJobInit as StatefulJob>::NAME)
@@ -467,23 +795,423 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				})
 			})
 		})
+		.procedure("backupPreview", {
+			#[derive(Type, Deserialize)]
+			pub struct BackupPreviewArgs {
+				pub source_location_id: location::id::Type,
+				pub target_location_id: location::id::Type,
+				/// The whole source location if `None`
+				#[serde(default)]
+				pub file_path_ids: Option<Vec<file_path::id::Type>>,
+				#[serde(default)]
+				pub deletion_policy: DeletionPolicy,
+			}
+
+			R.with2(library()).query(
+				|(_, library),
+				 BackupPreviewArgs {
+				     source_location_id,
+				     target_location_id,
+				     file_path_ids,
+				     deletion_policy,
+				 }: BackupPreviewArgs| async move {
+					let source_path =
+						get_location_path_from_location_id(&library.db, source_location_id).await?;
+					let target_path =
+						get_location_path_from_location_id(&library.db, target_location_id).await?;
+
+					backup::preview(
+						&library.db,
+						BackupSide {
+							location_id: source_location_id,
+							path: &source_path,
+						},
+						BackupSide {
+							location_id: target_location_id,
+							path: &target_path,
+						},
+						file_path_ids.as_deref(),
+						deletion_policy,
+						library.config().await.cas_id_algorithm,
+					)
+					.await
+					.map_err(Into::into)
+				},
+			)
+		})
+		.procedure("backup", {
+			#[derive(Type, Deserialize)]
+			pub struct BackupArgs {
+				pub source_location_id: location::id::Type,
+				pub target_location_id: location::id::Type,
+				/// The whole source location if `None`
+				#[serde(default)]
+				pub file_path_ids: Option<Vec<file_path::id::Type>>,
+				#[serde(default)]
+				pub deletion_policy: DeletionPolicy,
+			}
+
+			R.with2(library_mut()).mutation(
+				|(node, library),
+				 BackupArgs {
+				     source_location_id,
+				     target_location_id,
+				     file_path_ids,
+				     deletion_policy,
+				 }: BackupArgs| async move {
+					let source_location = find_location(&library, source_location_id)
+						.exec()
+						.await?
+						.ok_or(LocationError::IdNotFound(source_location_id))?;
+					let target_location = find_location(&library, target_location_id)
+						.exec()
+						.await?
+						.ok_or(LocationError::IdNotFound(target_location_id))?;
+
+					let backup = Backup::new(
+						source_location,
+						&target_location,
+						file_path_ids,
+						deletion_policy,
+						library.config().await.cas_id_algorithm,
+					)?;
+
+					NodeContext::dispatch(&node, &library, backup, source_location_id)
+						.await
+						.map(|_| ())
+				},
+			)
+		})
+		.procedure("mtpDevices", {
+			R.query(|_, _: ()| async move { mtp::connected_devices().await.map_err(Into::into) })
+		})
 		.merge("indexer_rules.", mount_indexer_rule_routes())
+		.merge("schedules.", mount_schedule_routes())
+		.merge("folderSync.", mount_folder_sync_routes())
+		.merge("versions.", mount_version_routes())
+		.merge("coldArchive.", mount_cold_archive_routes())
 }
 
-fn mount_indexer_rule_routes() -> AlphaRouter<Ctx> {
+fn mount_cold_archive_routes() -> AlphaRouter<Ctx> {
 	R.router()
+		.procedure("policy", {
+			R.with2(library())
+				.query(|(_, library), location_id: location::id::Type| async move {
+					Ok(cold_archiver::policy(&library.db, location_id).await?)
+				})
+		})
+		.procedure("setPolicy", {
+			#[derive(Type, Deserialize)]
+			pub struct SetArchivePolicyArgs {
+				pub location_id: location::id::Type,
+				/// Where the cold files of the location are moved
+				pub cold_location_id: location::id::Type,
+				pub rules: ArchiveRules,
+			}
+
+			R.with2(library_mut())
+				.mutation(|(_, library), args: SetArchivePolicyArgs| async move {
+					let location = find_location(&library, args.location_id)
+						.exec()
+						.await?
+						.ok_or(LocationError::IdNotFound(args.location_id))?;
+					let cold_location = find_location(&library, args.cold_location_id)
+						.exec()
+						.await?
+						.ok_or(LocationError::IdNotFound(args.cold_location_id))?;
+
+					let policy = cold_archiver::set_policy(
+						&library.db,
+						&location,
+						&cold_location,
+						args.rules,
+					)
+					.await?;
+
+					invalidate_query!(library, "locations.coldArchive.policy");
+
+					Ok(policy)
+				})
+		})
+		.procedure("deletePolicy", {
+			R.with2(library_mut()).mutation(
+				|(_, library), location_id: location::id::Type| async move {
+					cold_archiver::delete_policy(&library.db, location_id).await?;
+
+					invalidate_query!(library, "locations.coldArchive.policy");
+
+					Ok(())
+				},
+			)
+		})
+		.procedure("run", {
+			R.with2(library_mut()).mutation(
+				|(node, library), location_id: location::id::Type| async move {
+					let (policy, location, cold_location) =
+						cold_archiver::locations(&library.db, location_id).await?;
+
+					let archiver = ColdArchiver::new(
+						policy,
+						&location,
+						&cold_location,
+						library.config().await.cas_id_algorithm,
+					)?;
+
+					NodeContext::dispatch(&node, &library, archiver, location_id)
+						.await
+						.map(|_| ())
+				},
+			)
+		})
+		.procedure("stubs", {
+			R.with2(library())
+				.query(|(_, library), location_id: location::id::Type| async move {
+					Ok(cold_archiver::stubs(&library.db, location_id).await?)
+				})
+		})
+}
+
+fn mount_version_routes() -> AlphaRouter<Ctx> {
+	R.router()
+		.procedure("policy", {
+			R.with2(library())
+				.query(|(_, library), location_id: location::id::Type| async move {
+					let location = find_location(&library, location_id)
+						.select(location::select!({ versioning }))
+						.exec()
+						.await?
+						.ok_or(LocationError::IdNotFound(location_id))?;
+
+					Ok(VersioningPolicy::from_db(
+						location_id,
+						location.versioning.as_deref(),
+					)?)
+				})
+		})
+		.procedure("setPolicy", {
+			#[derive(Type, Deserialize)]
+			pub struct SetVersioningPolicyArgs {
+				pub location_id: location::id::Type,
+				/// Stops versioning the location if `None`, dropping its versions
+				pub policy: Option<VersioningPolicy>,
+			}
+
+			R.with2(library_mut()).mutation(
+				|(node, library), args: SetVersioningPolicyArgs| async move {
+					versioning::set_policy(&node, &library, args.location_id, args.policy).await?;
+
+					invalidate_query!(library, "locations.versions.policy");
+					invalidate_query!(library, "locations.versions.list");
+
+					Ok(())
+				},
+			)
+		})
+		.procedure("list", {
+			#[derive(Type, Deserialize)]
+			pub struct ListVersionsArgs {
+				pub location_id: location::id::Type,
+				/// Relative to the root of the location, every file of it if `None`
+				#[serde(default)]
+				pub path: Option<PathBuf>,
+			}
+
+			R.with2(library())
+				.query(|(_, library), args: ListVersionsArgs| async move {
+					Ok(
+						versioning::list(&library.db, args.location_id, args.path.as_deref())
+							.await?,
+					)
+				})
+		})
+		.procedure("restore", {
+			R.with2(library_mut())
+				.mutation(|(node, library), id: i32| async move {
+					Ok(versioning::restore(&node, &library, id).await?)
+				})
+		})
+}
+
+fn mount_folder_sync_routes() -> AlphaRouter<Ctx> {
+	R.router()
+		.procedure("list", {
+			R.with2(library()).query(|(_, library), _: ()| async move {
+				Ok(folder_sync::list(&library.db).await?)
+			})
+		})
 		.procedure("create", {
+			#[derive(Type, Deserialize)]
+			pub struct CreateFolderSyncArgs {
+				pub location_a_id: location::id::Type,
+				pub location_b_id: location::id::Type,
+			}
+
+			R.with2(library_mut())
+				.mutation(|(_, library), args: CreateFolderSyncArgs| async move {
+					let location_a = find_location(&library, args.location_a_id)
+						.exec()
+						.await?
+						.ok_or(LocationError::IdNotFound(args.location_a_id))?;
+					let location_b = find_location(&library, args.location_b_id)
+						.exec()
+						.await?
+						.ok_or(LocationError::IdNotFound(args.location_b_id))?;
+
+					let created =
+						folder_sync::create(&library.db, &location_a, &location_b).await?;
+
+					invalidate_query!(library, "locations.folderSync.list");
+
+					Ok(created)
+				})
+		})
+		.procedure("delete", {
+			R.with2(library_mut())
+				.mutation(|(_, library), id: i32| async move {
+					folder_sync::delete(&library.db, id).await?;
+
+					invalidate_query!(library, "locations.folderSync.list");
+
+					Ok(())
+				})
+		})
+		.procedure("conflicts", {
+			R.with2(library())
+				.query(|(_, library), id: i32| async move {
+					Ok(folder_sync::conflicts(&library.db, id).await?)
+				})
+		})
+		.procedure("resolve", {
+			#[derive(Type, Deserialize)]
+			pub struct ResolveFolderSyncConflictArgs {
+				pub id: i32,
+				/// Relative to the roots of the locations
+				pub path: PathBuf,
+				pub resolution: ConflictResolution,
+			}
+
+			R.with2(library_mut()).mutation(
+				|(_, library), args: ResolveFolderSyncConflictArgs| async move {
+					folder_sync::resolve(&library.db, args.id, &args.path, args.resolution).await?;
+
+					invalidate_query!(library, "locations.folderSync.conflicts");
+
+					Ok(())
+				},
+			)
+		})
+		.procedure("run", {
+			R.with2(library_mut())
+				.mutation(|(node, library), id: i32| async move {
+					let (location_a, location_b) = folder_sync::locations(&library.db, id).await?;
+
+					let folder_sync = FolderSync::new(
+						id,
+						&location_a,
+						&location_b,
+						library.config().await.cas_id_algorithm,
+					)?;
+
+					NodeContext::dispatch(&node, &library, folder_sync, location_a.id)
+						.await
+						.map(|_| ())
+				})
+		})
+		.procedure("preview", {
+			R.with2(library())
+				.query(|(_, library), id: i32| async move {
+					let config = library.config().await;
+
+					Ok(folder_sync::preview(
+						&library.db,
+						id,
+						config.cas_id_algorithm,
+						config.deep_hash_threshold,
+					)
+					.await?)
+				})
+		})
+}
+
+fn mount_schedule_routes() -> AlphaRouter<Ctx> {
+	R.router()
+		.procedure("list", {
 			R.with2(library())
-				.mutation(|(_, library), args: IndexerRuleCreateArgs| async move {
+				.query(|(_, library), location_id: location::id::Type| async move {
+					Ok(schedule::list(&library.db, location_id).await?)
+				})
+		})
+		.procedure("create", {
+			#[derive(Type, Deserialize)]
+			pub struct CreateScheduleArgs {
+				pub location_id: location::id::Type,
+				pub job: ScheduledJob,
+				/// Cron expression, e.g. `0 3 * * SUN` for Sundays at 3am
+				pub recurrence: String,
+				#[serde(default)]
+				pub catch_up: CatchUp,
+			}
+
+			R.with2(library_mut())
+				.mutation(|(_, library), args: CreateScheduleArgs| async move {
+					let created = schedule::create(
+						&library.db,
+						args.location_id,
+						args.job,
+						&args.recurrence,
+						args.catch_up,
+					)
+					.await?;
+
+					invalidate_query!(library, "locations.schedules.list");
+
+					Ok(created)
+				})
+		})
+		.procedure("setEnabled", {
+			#[derive(Type, Deserialize)]
+			pub struct SetScheduleEnabledArgs {
+				pub id: i32,
+				pub enabled: bool,
+			}
+
+			R.with2(library_mut()).mutation(
+				|(_, library), args: SetScheduleEnabledArgs| async move {
+					schedule::set_enabled(&library.db, args.id, args.enabled).await?;
+
+					invalidate_query!(library, "locations.schedules.list");
+
+					Ok(())
+				},
+			)
+		})
+		.procedure("delete", {
+			R.with2(library_mut())
+				.mutation(|(_, library), id: i32| async move {
+					schedule::delete(&library.db, id).await?;
+
+					invalidate_query!(library, "locations.schedules.list");
+
+					Ok(())
+				})
+		})
+}
+
+fn mount_indexer_rule_routes() -> AlphaRouter<Ctx> {
+	R.router()
+		.procedure("create", {
+			R.with2(library_mut()).mutation(
+				|(_, library), args: IndexerRuleCreateArgs| async move {
 					if args.create(&library.db).await?.is_some() {
 						invalidate_query!(library, "locations.indexer_rules.list");
 					}
 
 					Ok(())
-				})
+				},
+			)
 		})
 		.procedure("delete", {
-			R.with2(library())
+			R.with2(library_mut())
 				.mutation(|(_, library), indexer_rule_id: i32| async move {
 					let indexer_rule_db = library.db.indexer_rule();
 
@@ -5,6 +5,7 @@ use crate::{
 		get_hardware_model_name, HardwareModel,
 	},
 	old_job::JobProgressEvent,
+	volume::health::DeviceHealthAlert,
 	Node,
 };
 
@@ -22,6 +23,8 @@ use uuid::Uuid;
 mod auth;
 mod backups;
 mod cloud;
+mod collections;
+mod custom_fields;
 // mod categories;
 mod ephemeral_files;
 mod files;
@@ -32,8 +35,10 @@ mod libraries;
 pub mod locations;
 mod models;
 mod nodes;
+mod notes;
 pub mod notifications;
 mod p2p;
+mod people;
 mod preferences;
 pub(crate) mod search;
 mod sync;
@@ -61,6 +66,7 @@ pub enum CoreEvent {
 	},
 	JobProgress(JobProgressEvent),
 	InvalidateOperation(InvalidateOperationEvent),
+	DeviceHealthAlert(DeviceHealthAlert),
 }
 
 /// All of the feature flags provided by the core itself. The frontend has it's own set of feature flags!
@@ -196,15 +202,19 @@ pub(crate) fn mount() -> Arc<Router> {
 		.merge("volumes.", volumes::mount())
 		.merge("tags.", tags::mount())
 		.merge("labels.", labels::mount())
+		.merge("collections.", collections::mount())
+		.merge("customFields.", custom_fields::mount())
 		// .merge("categories.", categories::mount())
-		// .merge("keys.", keys::mount())
+		.merge("keys.", keys::mount())
 		.merge("locations.", locations::mount())
 		.merge("ephemeralFiles.", ephemeral_files::mount())
 		.merge("files.", files::mount())
 		.merge("jobs.", jobs::mount())
 		.merge("p2p.", p2p::mount())
+		.merge("people.", people::mount())
 		.merge("models.", models::mount())
 		.merge("nodes.", nodes::mount())
+		.merge("notes.", notes::mount())
 		.merge("sync.", sync::mount())
 		.merge("preferences.", preferences::mount())
 		.merge("notifications.", notifications::mount())
@@ -2,10 +2,16 @@ use std::collections::HashSet;
 
 use crate::{
 	invalidate_query,
-	node::config::{P2PDiscoveryState, Port},
+	node::{
+		bandwidth::BandwidthLimit,
+		config::{P2PDiscoveryState, Port},
+		io_throttle::IoThrottleLimit,
+	},
+	p2p::NetworkPolicy,
 };
 
 use sd_prisma::prisma::{instance, location};
+use sd_task_system::ResourceProfile;
 
 use rspc::{alpha::AlphaRouter, ErrorCode};
 use serde::Deserialize;
@@ -28,6 +34,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				pub p2p_discovery: Option<P2PDiscoveryState>,
 				pub p2p_remote_access: Option<bool>,
 				pub p2p_manual_peers: Option<HashSet<String>>,
+				pub p2p_network_policy: Option<NetworkPolicy>,
 				pub image_labeler_version: Option<String>,
 			}
 			R.mutation(|node, args: ChangeNodeNameArgs| async move {
@@ -40,6 +47,13 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 					}
 				}
 
+				if let Some(Err(subnet)) = args.p2p_network_policy.as_ref().map(NetworkPolicy::validate) {
+					return Err(rspc::Error::new(
+						ErrorCode::BadRequest,
+						format!("invalid subnet '{subnet}', expected CIDR notation"),
+					));
+				}
+
 				#[cfg(feature = "ai")]
 				let mut new_model = None;
 
@@ -70,6 +84,9 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 						if let Some(manual_peers) = args.p2p_manual_peers {
 							config.p2p.manual_peers = manual_peers;
 						};
+						if let Some(network_policy) = args.p2p_network_policy {
+							config.p2p.network_policy = network_policy;
+						};
 
 						#[cfg(feature = "ai")]
 						if let Some(version) = args.image_labeler_version {
@@ -210,4 +227,119 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				},
 			)
 		})
+		.procedure("updateThumbnailCacheBudget", {
+			// Per library, in MiB, `None` lets the thumbnails directory grow unbounded
+			R.mutation(|node, library_cache_budget_mib: Option<u32>| async move {
+				node.config
+					.update_preferences(|preferences| {
+						preferences
+							.thumbnailer
+							.set_library_cache_budget_mib(library_cache_budget_mib);
+					})
+					.await
+					.map_err(|e| {
+						error!("failed to update thumbnail cache budget: {e:#?}");
+						rspc::Error::with_cause(
+							ErrorCode::InternalServerError,
+							"Failed to update thumbnail cache budget".to_string(),
+							e,
+						)
+					})
+			})
+		})
+		.procedure("updateBandwidthLimits", {
+			R.mutation(|node, limits: Vec<BandwidthLimit>| async move {
+				if limits.iter().any(|limit| {
+					limit.bytes_per_second == 0
+						|| limit.schedule.as_ref().map_or(false, |schedule| {
+							schedule.start_hour > 23 || schedule.end_hour > 23
+						})
+				}) {
+					return Err(rspc::Error::new(
+						ErrorCode::BadRequest,
+						"bandwidth limits must be above 0 and their hours between 0 and 23"
+							.to_string(),
+					));
+				}
+
+				node.config
+					.update_preferences(|preferences| {
+						preferences.bandwidth.limits = limits;
+					})
+					.await
+					.map_err(|e| {
+						error!("failed to update bandwidth limits: {e:#?}");
+						rspc::Error::with_cause(
+							ErrorCode::InternalServerError,
+							"Failed to update bandwidth limits".to_string(),
+							e,
+						)
+					})?;
+
+				invalidate_query!(node; node, "nodeState");
+
+				Ok(())
+			})
+		})
+		.procedure("updateResourceProfile", {
+			#[derive(Deserialize, Type)]
+			pub struct UpdateResourceProfileArgs {
+				#[specta(type = String)]
+				pub profile: ResourceProfile,
+			}
+
+			// Running tasks keep their slots, the profile applies to the next ones
+			R.mutation(
+				|node, UpdateResourceProfileArgs { profile }: UpdateResourceProfileArgs| async move {
+					node.config
+						.update_preferences(|preferences| {
+							preferences.resource_profile = profile;
+						})
+						.await
+						.map_err(|e| {
+							error!("failed to update resource profile: {e:#?}");
+							rspc::Error::with_cause(
+								ErrorCode::InternalServerError,
+								"Failed to update resource profile".to_string(),
+								e,
+							)
+						})?;
+
+					node.task_system.set_resource_profile(profile);
+
+					invalidate_query!(node; node, "nodeState");
+
+					Ok(())
+				},
+			)
+		})
+		.procedure("updateIoThrottleLimits", {
+			// Jobs already running keep the limits they started with
+			R.mutation(|node, limits: Vec<IoThrottleLimit>| async move {
+				if limits.iter().any(|limit| limit.bytes_per_second == 0) {
+					return Err(rspc::Error::new(
+						ErrorCode::BadRequest,
+						"disk read limits must be above 0".to_string(),
+					));
+				}
+
+				node.config
+					.update_preferences(|preferences| {
+						preferences.io_throttle.limits = limits;
+					})
+					.await
+					.map_err(|e| {
+						error!("failed to update disk read limits: {e:#?}");
+						rspc::Error::with_cause(
+							ErrorCode::InternalServerError,
+							"Failed to update disk read limits".to_string(),
+							e,
+						)
+					})?;
+
+				invalidate_query!(node; node, "nodeState");
+
+				Ok(())
+			})
+		})
 }
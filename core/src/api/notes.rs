@@ -0,0 +1,145 @@
+use crate::{
+	invalidate_query,
+	object::note::{self, merge},
+	p2p::PeerMetadata,
+};
+
+use sd_prisma::prisma::{instance, object};
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, FixedOffset};
+use rspc::alpha::AlphaRouter;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use super::{
+	utils::{library, library_mut},
+	Ctx, R,
+};
+
+/// The note of an object as it reads after merging the edits of every device
+#[derive(Serialize, Type, Debug)]
+pub struct ObjectNote {
+	pub note: Option<String>,
+	/// Versions the note was last written as, to pass as the parents of the next edit
+	pub heads: Vec<Vec<u8>>,
+	pub versions: u32,
+}
+
+#[derive(Serialize, Type, Debug)]
+pub struct NoteHistoryEntry {
+	pub pub_id: Vec<u8>,
+	pub content: Option<String>,
+	/// Versions this one was written over, more than one where concurrent edits were merged
+	pub parents: Vec<Vec<u8>>,
+	pub date_created: Option<DateTime<FixedOffset>>,
+	/// Name of the device that wrote the version, if it's known here
+	pub device: Option<String>,
+}
+
+pub(crate) fn mount() -> AlphaRouter<Ctx> {
+	R.router()
+		.procedure("get", {
+			R.with2(library())
+				.query(|(_, library), object_id: object::id::Type| async move {
+					let versions = note::versions(&library.db, object_id).await?;
+
+					// Notes written before they had versions are only in the object
+					if versions.is_empty() {
+						return Ok(ObjectNote {
+							note: library
+								.db
+								.object()
+								.find_unique(object::id::equals(object_id))
+								.select(object::select!({ note }))
+								.exec()
+								.await?
+								.and_then(|object| object.note),
+							heads: vec![],
+							versions: 0,
+						});
+					}
+
+					let versions = note::to_merge_versions(&versions);
+
+					Ok(ObjectNote {
+						note: merge::resolve(&versions),
+						heads: merge::heads(&versions)
+							.into_iter()
+							.map(|head| head.pub_id.clone())
+							.collect(),
+						versions: versions.len() as u32,
+					})
+				})
+		})
+		.procedure("history", {
+			R.with2(library())
+				.query(|(_, library), object_id: object::id::Type| async move {
+					let versions = note::versions(&library.db, object_id).await?;
+
+					let devices = library
+						.db
+						.instance()
+						.find_many(vec![instance::pub_id::in_vec(
+							versions
+								.iter()
+								.filter_map(|version| version.instance_pub_id.clone())
+								.collect(),
+						)])
+						.select(instance::select!({ pub_id metadata }))
+						.exec()
+						.await?
+						.into_iter()
+						.filter_map(|instance| {
+							let metadata = serde_json::from_slice::<HashMap<String, String>>(
+								instance.metadata.as_deref()?,
+							)
+							.ok()?;
+
+							PeerMetadata::from_hashmap(&metadata)
+								.ok()
+								.map(|metadata| (instance.pub_id, metadata.name))
+						})
+						.collect::<HashMap<_, _>>();
+
+					Ok(versions
+						.iter()
+						.rev()
+						.map(|version| NoteHistoryEntry {
+							pub_id: version.pub_id.clone(),
+							content: version.content.clone(),
+							parents: note::parents(version),
+							date_created: version.date_created,
+							device: version
+								.instance_pub_id
+								.as_ref()
+								.and_then(|pub_id| devices.get(pub_id).cloned()),
+						})
+						.collect::<Vec<_>>())
+				})
+		})
+		.procedure("set", {
+			#[derive(Type, Deserialize)]
+			pub struct NoteSetArgs {
+				pub object_id: object::id::Type,
+				pub note: Option<String>,
+				/// Heads of the note the edit was made over, from `notes.get`. Edits made over
+				/// older heads are merged with the ones written since
+				pub parents: Vec<Vec<u8>>,
+			}
+
+			R.with2(library_mut())
+				.mutation(|(_, library), args: NoteSetArgs| async move {
+					let note =
+						note::save(&library, args.object_id, args.note, Some(args.parents)).await?;
+
+					invalidate_query!(library, "notes.get");
+					invalidate_query!(library, "notes.history");
+					invalidate_query!(library, "search.paths");
+					invalidate_query!(library, "search.objects");
+
+					Ok(note)
+				})
+		})
+}
@@ -1,4 +1,7 @@
-use crate::p2p::{operations, ConnectionMethod, DiscoveryMethod, Header, P2PEvent, PeerMetadata};
+use crate::{
+	invalidate_query,
+	p2p::{operations, ConnectionMethod, DiscoveryMethod, Header, P2PEvent, PeerMetadata},
+};
 
 use sd_p2p::{PeerConnectionCandidate, RemoteIdentity};
 
@@ -79,6 +82,11 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 					.clone())
 			})
 		})
+		.procedure("reachability", {
+			R.query(|node, identity: RemoteIdentity| async move {
+				Ok(node.p2p.reachability(identity).await)
+			})
+		})
 		.procedure("debugConnect", {
 			R.mutation(|node, identity: RemoteIdentity| async move {
 				let peer = { node.p2p.p2p.peers().get(&identity).cloned() };
@@ -141,6 +149,18 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				Ok(())
 			})
 		})
+		.procedure("spacedropHistory", {
+			R.query(|node, _: ()| async move { Ok(node.p2p.spacedrop_history.list().await) })
+		})
+		.procedure("clearSpacedropHistory", {
+			R.mutation(|node, _: ()| async move {
+				node.p2p.spacedrop_history.clear().await;
+
+				invalidate_query!(node; node, "p2p.spacedropHistory");
+
+				Ok(())
+			})
+		})
 		.procedure("cancelSpacedrop", {
 			R.mutation(|node, id: Uuid| async move {
 				node.p2p.cancel_spacedrop(id).await;
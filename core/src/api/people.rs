@@ -0,0 +1,245 @@
+use crate::{
+	api::utils::{library, library_mut},
+	invalidate_query,
+	library::Library,
+	object::media::old_thumbnail::get_indexed_thumb_key,
+};
+
+use sd_core_heavy_lifting::media_processor::refresh_people;
+use sd_core_prisma_helpers::face_for_frontend;
+
+use sd_prisma::prisma::{face, object, person, SortOrder};
+use sd_utils::uuid_to_bytes;
+
+use chrono::Utc;
+use prisma_client_rust::raw;
+use rspc::{alpha::AlphaRouter, ErrorCode};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use uuid::Uuid;
+
+use super::{Ctx, R};
+
+pub(crate) fn mount() -> AlphaRouter<Ctx> {
+	R.router()
+		.procedure("list", {
+			#[derive(Deserialize)]
+			struct PersonRow {
+				id: person::id::Type,
+				name: Option<String>,
+				faces_count: i32,
+				cover_cas_id: Option<String>,
+			}
+
+			#[derive(Serialize, Type, Debug)]
+			#[serde(rename_all = "camelCase")]
+			struct PersonItem {
+				id: person::id::Type,
+				name: Option<String>,
+				faces_count: i32,
+				/// Thumbnail of the image with the clearest face of the person
+				thumbnail: Option<Vec<String>>,
+			}
+
+			R.with2(library()).query(|(_, library), _: ()| async move {
+				Ok(library
+					.db
+					._query_raw::<PersonRow>(raw!(
+						"SELECT
+							person.id,
+							person.name,
+							COUNT(face.id) AS faces_count,
+							(
+								SELECT file_path.cas_id
+								FROM face AS cover
+								INNER JOIN file_path ON file_path.object_id = cover.object_id
+								WHERE cover.person_id = person.id AND file_path.cas_id IS NOT NULL
+								ORDER BY cover.score DESC
+								LIMIT 1
+							) AS cover_cas_id
+						FROM person
+						INNER JOIN face ON face.person_id = person.id
+						GROUP BY person.id
+						ORDER BY faces_count DESC"
+					))
+					.exec()
+					.await?
+					.into_iter()
+					.map(
+						|PersonRow {
+						     id,
+						     name,
+						     faces_count,
+						     cover_cas_id,
+						 }| PersonItem {
+							id,
+							name,
+							faces_count,
+							thumbnail: cover_cas_id
+								.map(|cas_id| get_indexed_thumb_key(&cas_id, library.id)),
+						},
+					)
+					.collect::<Vec<_>>())
+			})
+		})
+		.procedure("getFaces", {
+			R.with2(library())
+				.query(|(_, library), person_id: person::id::Type| async move {
+					Ok(library
+						.db
+						.face()
+						.find_many(vec![face::person_id::equals(Some(person_id))])
+						.order_by(face::score::order(SortOrder::Desc))
+						.select(face_for_frontend::select())
+						.exec()
+						.await?)
+				})
+		})
+		.procedure("getForObject", {
+			R.with2(library())
+				.query(|(_, library), object_id: object::id::Type| async move {
+					Ok(library
+						.db
+						.face()
+						.find_many(vec![face::object_id::equals(object_id)])
+						.order_by(face::x::order(SortOrder::Asc))
+						.select(face_for_frontend::select())
+						.exec()
+						.await?)
+				})
+		})
+		.procedure("rename", {
+			#[derive(Deserialize, Type, Debug)]
+			struct RenamePersonArgs {
+				id: person::id::Type,
+				name: Option<String>,
+			}
+
+			R.with2(library_mut()).mutation(
+				|(_, library), RenamePersonArgs { id, name }: RenamePersonArgs| async move {
+					library
+						.db
+						.person()
+						.update(
+							person::id::equals(id),
+							vec![
+								person::name::set(
+									name.map(|name| name.trim().to_string())
+										.filter(|name| !name.is_empty()),
+								),
+								person::date_modified::set(Utc::now().into()),
+							],
+						)
+						.exec()
+						.await?;
+
+					invalidate_query!(library, "people.list");
+					invalidate_query!(library, "people.getForObject");
+
+					Ok(())
+				},
+			)
+		})
+		.procedure("merge", {
+			#[derive(Deserialize, Type, Debug)]
+			struct MergePeopleArgs {
+				/// Person that keeps the faces of the others
+				into: person::id::Type,
+				people: Vec<person::id::Type>,
+			}
+
+			R.with2(library_mut()).mutation(
+				|(_, library), MergePeopleArgs { into, people }: MergePeopleArgs| async move {
+					let Library { db, .. } = library.as_ref();
+
+					let people = people
+						.into_iter()
+						.filter(|person_id| *person_id != into)
+						.collect::<Vec<_>>();
+
+					db.face()
+						.update_many(
+							vec![face::person_id::in_vec(people.clone())],
+							vec![face::person_id::set(Some(into))],
+						)
+						.exec()
+						.await?;
+
+					// Merged people are left without faces, so they're deleted here
+					refresh_people(people.into_iter().chain([into]).collect(), db).await?;
+
+					invalidate_query!(library, "people.list");
+					invalidate_query!(library, "people.getFaces");
+					invalidate_query!(library, "people.getForObject");
+
+					Ok(())
+				},
+			)
+		})
+		.procedure("split", {
+			#[derive(Deserialize, Type, Debug)]
+			struct SplitPersonArgs {
+				/// Faces that belong to someone else, they become a new person
+				faces: Vec<face::id::Type>,
+				name: Option<String>,
+			}
+
+			R.with2(library_mut()).mutation(
+				|(_, library), SplitPersonArgs { faces, name }: SplitPersonArgs| async move {
+					let Library { db, .. } = library.as_ref();
+
+					if faces.is_empty() {
+						return Err(rspc::Error::new(
+							ErrorCode::BadRequest,
+							"No faces to split".to_string(),
+						));
+					}
+
+					let previous_people = db
+						.face()
+						.find_many(vec![face::id::in_vec(faces.clone())])
+						.select(face::select!({ person_id }))
+						.exec()
+						.await?
+						.into_iter()
+						.filter_map(|face| face.person_id)
+						.collect::<Vec<_>>();
+
+					// Its centroid is set from its faces right below
+					let new_person = db
+						.person()
+						.create(
+							uuid_to_bytes(Uuid::new_v4()),
+							vec![],
+							vec![person::name::set(
+								name.map(|name| name.trim().to_string())
+									.filter(|name| !name.is_empty()),
+							)],
+						)
+						.select(person::select!({ id }))
+						.exec()
+						.await?;
+
+					db.face()
+						.update_many(
+							vec![face::id::in_vec(faces)],
+							vec![face::person_id::set(Some(new_person.id))],
+						)
+						.exec()
+						.await?;
+
+					refresh_people(
+						previous_people.into_iter().chain([new_person.id]).collect(),
+						db,
+					)
+					.await?;
+
+					invalidate_query!(library, "people.list");
+					invalidate_query!(library, "people.getFaces");
+					invalidate_query!(library, "people.getForObject");
+
+					Ok(new_person.id)
+				},
+			)
+		})
+}
@@ -2,12 +2,15 @@ use crate::preferences::LibraryPreferences;
 
 use rspc::alpha::AlphaRouter;
 
-use super::{utils::library, Ctx, R};
+use super::{
+	utils::{library, library_mut},
+	Ctx, R,
+};
 
 pub(crate) fn mount() -> AlphaRouter<Ctx> {
 	R.router()
 		.procedure("update", {
-			R.with2(library())
+			R.with2(library_mut())
 				.mutation(|(_, library), args: LibraryPreferences| async move {
 					args.write(&library.db).await?;
 
@@ -0,0 +1,107 @@
+//! Searching every library of the node at once, for when the file could be in any of them.
+
+use super::query::Query;
+
+use sd_prisma::prisma::{file_path, PrismaClient};
+
+use prisma_client_rust::{PrismaValue, Raw};
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+pub(super) struct RankedMatch {
+	pub id: file_path::id::Type,
+	pub rank: i64,
+}
+
+/// The best `take` file paths of a library matching `query`, best first, see [`Query::rank`]
+pub(super) async fn ranked_matches(
+	query: &Query,
+	take: u8,
+	db: &PrismaClient,
+) -> Result<Vec<RankedMatch>, prisma_client_rust::QueryError> {
+	let (rank, mut params) = query.rank();
+	let (conditions, condition_params) = query.compile();
+
+	params.extend(condition_params);
+	params.push(PrismaValue::BigInt(i64::from(take)));
+
+	db._query_raw::<RankedMatch>(Raw::new(
+		&format!(
+			"SELECT file_path.id AS id, {rank} AS rank
+			FROM file_path
+			LEFT JOIN object ON object.id = file_path.object_id
+			WHERE {conditions}
+			ORDER BY rank DESC, file_path.date_modified DESC, file_path.id ASC
+			LIMIT {{}}"
+		),
+		params,
+	))
+	.exec()
+	.await
+}
+
+/// Merges the matches of each library, best first, into the best `take` of all of them with the
+/// index of the library they're from. Matches ranked the same are taken in turns from each
+/// library, so no library crowds out the others.
+pub(super) fn merge_ranked<T>(matches: Vec<Vec<(T, i64)>>, take: usize) -> Vec<(usize, T)> {
+	let mut merged = matches
+		.into_iter()
+		.enumerate()
+		.flat_map(|(library, matches)| {
+			matches
+				.into_iter()
+				.enumerate()
+				.map(move |(position, (item, rank))| (rank, position, library, item))
+		})
+		.collect::<Vec<_>>();
+
+	merged.sort_by(|a, b| {
+		b.0.cmp(&a.0)
+			.then_with(|| a.1.cmp(&b.1))
+			.then_with(|| a.2.cmp(&b.2))
+	});
+
+	merged
+		.into_iter()
+		.take(take)
+		.map(|(_, _, library, item)| (library, item))
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn better_ranks_come_first_whatever_the_library() {
+		let merged = merge_ranked(
+			vec![
+				vec![("work/report", 2), ("work/notes", 1)],
+				vec![("personal/report", 3)],
+			],
+			10,
+		);
+
+		assert_eq!(
+			merged,
+			vec![
+				(1, "personal/report"),
+				(0, "work/report"),
+				(0, "work/notes")
+			]
+		);
+	}
+
+	#[test]
+	fn equal_ranks_take_turns() {
+		let merged = merge_ranked(
+			vec![
+				vec![("a1", 1), ("a2", 1), ("a3", 1)],
+				vec![("b1", 1), ("b2", 1)],
+			],
+			4,
+		);
+
+		assert_eq!(merged, vec![(0, "a1"), (1, "b1"), (0, "a2"), (1, "b2")]);
+	}
+}
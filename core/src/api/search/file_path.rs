@@ -70,6 +70,9 @@ pub enum FilePathFilterArgs {
 	ModifiedAt(Range<DateTime<Utc>>),
 	IndexedAt(Range<DateTime<Utc>>),
 	Hidden(bool),
+	// Moved to the trash from Spacedrive, file paths in the trash being left out without this filter
+	Trashed(bool),
+	LinkTarget(TextMatch),
 }
 
 impl FilePathFilterArgs {
@@ -152,6 +155,17 @@ impl FilePathFilterArgs {
 			Self::Hidden(v) => {
 				vec![hidden::equals(Some(v))]
 			}
+			Self::Trashed(true) => vec![date_trashed::not(None)],
+			Self::Trashed(false) => vec![date_trashed::equals(None)],
+			Self::LinkTarget(v) => v
+				.into_param(
+					link_target::contains,
+					link_target::starts_with,
+					link_target::ends_with,
+					|s| link_target::equals(Some(s)),
+				)
+				.map(|v| vec![v])
+				.unwrap_or_default(),
 		})
 	}
 }
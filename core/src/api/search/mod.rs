@@ -1,28 +1,52 @@
 use crate::{
-	api::{locations::ExplorerItem, utils::library},
+	api::{
+		locations::ExplorerItem,
+		utils::{library, library_mut},
+	},
+	context::NodeContext,
 	library::Library,
 	location::{non_indexed, LocationError},
 	object::media::old_thumbnail::get_indexed_thumb_key,
 	util::{unsafe_streamed_query, BatchedStream},
+	Node,
 };
 
-use prisma_client_rust::Operator;
+use prisma_client_rust::{raw, Operator, PrismaValue, Raw};
+use sd_core_heavy_lifting::{
+	duplicate_finder::{
+		fetch_duplicates_page, fetch_similar_content_groups, fetch_similar_groups, DuplicateFinder,
+		DuplicateGroup, DuplicatesPage,
+	},
+	job_system::LIBRARY_WIDE,
+	media_processor::DEFAULT_SIMILARITY_THRESHOLD,
+	text_extractor::{self, SnippetPart, TextSearchHit, TextSource},
+};
 use sd_core_prisma_helpers::{file_path_for_frontend, object_with_file_paths};
 use sd_prisma::prisma::{self, PrismaClient};
 
-use std::path::PathBuf;
+use std::{
+	collections::{BTreeMap, HashMap},
+	path::PathBuf,
+};
 
 use async_stream::stream;
 use futures::StreamExt;
+use futures_concurrency::future::Join;
 use itertools::Either;
 use rspc::{alpha::AlphaRouter, ErrorCode};
 use serde::{Deserialize, Serialize};
 use specta::Type;
+use tracing::error;
+use uuid::Uuid;
 
+mod all_libraries;
 pub mod exif_data;
 pub mod file_path;
 pub mod object;
+pub mod query;
 pub mod saved;
+#[cfg(feature = "ai")]
+mod semantic;
 mod utils;
 
 pub use self::{file_path::*, object::*, utils::*};
@@ -53,7 +77,7 @@ impl SearchFilterArgs {
 	) -> Result<(), rspc::Error> {
 		match self {
 			Self::FilePath(v) => file_path.extend(v.into_params(db).await?),
-			Self::Object(v) => object.extend(v.into_params()),
+			Self::Object(v) => object.extend(v.into_params(db).await?),
 		};
 		Ok(())
 	}
@@ -183,8 +207,11 @@ pub fn mount() -> AlphaRouter<Ctx> {
 					let Library { db, .. } = library.as_ref();
 
 					let params = {
+						let untrashed = untrashed_filter(&filters);
 						let (mut fp, obj) = merge_filters(filters, db).await?;
 
+						fp.extend(untrashed);
+
 						if !obj.is_empty() {
 							fp.push(prisma::file_path::object::is(obj));
 						}
@@ -260,8 +287,11 @@ pub fn mount() -> AlphaRouter<Ctx> {
 					Ok(db
 						.file_path()
 						.count({
+							let untrashed = untrashed_filter(&filters);
 							let (mut fp, obj) = merge_filters(filters, db).await?;
 
+							fp.extend(untrashed);
+
 							if !obj.is_empty() {
 								fp.push(prisma::file_path::object::is(obj));
 							}
@@ -387,6 +417,437 @@ pub fn mount() -> AlphaRouter<Ctx> {
 						.await? as u32)
 				})
 		})
+		.procedure("duplicates", {
+			#[derive(Deserialize, Type, Debug)]
+			#[serde(rename_all = "camelCase")]
+			struct DuplicatesArgs {
+				#[specta(optional)]
+				take: Option<u8>,
+				#[specta(optional)]
+				cursor: Option<prisma::object::id::Type>,
+			}
+
+			#[derive(Serialize, Type, Debug)]
+			#[serde(rename_all = "camelCase")]
+			struct DuplicatesData {
+				groups: Vec<DuplicateGroup>,
+				reclaimable_bytes: String,
+				cursor: Option<prisma::object::id::Type>,
+			}
+
+			R.with2(library())
+				.query(|(_, library), DuplicatesArgs { take, cursor }| async move {
+					let DuplicatesPage {
+						groups,
+						reclaimable_bytes,
+						next_cursor,
+					} = fetch_duplicates_page(
+						&library.db,
+						cursor,
+						take.unwrap_or(MAX_TAKE).min(MAX_TAKE).into(),
+					)
+					.await?;
+
+					Ok(DuplicatesData {
+						groups,
+						reclaimable_bytes: reclaimable_bytes.to_string(),
+						cursor: next_cursor,
+					})
+				})
+		})
+		.procedure("findDuplicates", {
+			R.with2(library_mut())
+				.mutation(|(node, library), _: ()| async move {
+					NodeContext::dispatch_and_wait(
+						&node,
+						&library,
+						DuplicateFinder::default(),
+						LIBRARY_WIDE,
+					)
+					.await
+				})
+		})
+		.procedure("similar", {
+			#[derive(Deserialize, Type, Debug)]
+			#[serde(rename_all = "camelCase")]
+			struct SimilarArgs {
+				/// How many bits of the perceptual hashes may differ, higher finds looser matches
+				#[specta(optional)]
+				threshold: Option<u32>,
+				#[specta(optional)]
+				take: Option<u8>,
+			}
+
+			R.with2(library())
+				.query(|(_, library), SimilarArgs { threshold, take }| async move {
+					let mut groups = fetch_similar_groups(
+						&library.db,
+						threshold.unwrap_or(DEFAULT_SIMILARITY_THRESHOLD),
+					)
+					.await?;
+
+					groups.truncate(take.unwrap_or(MAX_TAKE).min(MAX_TAKE).into());
+
+					Ok(groups)
+				})
+		})
+		.procedure("similarContent", {
+			#[derive(Deserialize, Type, Debug)]
+			#[serde(rename_all = "camelCase")]
+			struct SimilarContentArgs {
+				#[specta(optional)]
+				take: Option<u8>,
+			}
+
+			R.with2(library())
+				.query(|(_, library), SimilarContentArgs { take }| async move {
+					let mut groups = fetch_similar_content_groups(&library.db).await?;
+
+					groups.truncate(take.unwrap_or(MAX_TAKE).min(MAX_TAKE).into());
+
+					Ok(groups)
+				})
+		})
+		.procedure("query", {
+			#[derive(Deserialize, Type, Debug, Clone, Copy)]
+			#[serde(rename_all = "camelCase", tag = "field", content = "value")]
+			enum QueryOrder {
+				Name(SortOrder),
+				SizeInBytes(SortOrder),
+				DateCreated(SortOrder),
+				DateModified(SortOrder),
+				DateIndexed(SortOrder),
+			}
+
+			#[derive(Deserialize, Type, Debug)]
+			#[serde(rename_all = "camelCase")]
+			struct QueryArgs {
+				/// Written in the search query language, like `kind:video size:>1GB "project x"`
+				query: String,
+				#[specta(optional)]
+				take: Option<u8>,
+				/// How many results to skip, the cursor returned with the previous page
+				#[specta(optional)]
+				skip: Option<u32>,
+				#[specta(optional)]
+				order: Option<QueryOrder>,
+				#[serde(default)]
+				group_directories: bool,
+			}
+
+			#[derive(Serialize, Type, Debug)]
+			struct QueryData {
+				items: Vec<ExplorerItem>,
+				/// Where the next page starts, none on the last page
+				cursor: Option<u32>,
+			}
+
+			#[derive(Deserialize)]
+			struct QueryMatch {
+				id: prisma::file_path::id::Type,
+			}
+
+			R.with2(library()).query(
+				|(node, library),
+				 QueryArgs {
+				     query: input,
+				     take,
+				     skip,
+				     order,
+				     group_directories,
+				 }| async move {
+					let Library { db, .. } = library.as_ref();
+
+					let (conditions, mut params) = query::Query::parse(&input)?.compile();
+
+					let (column, direction) = match order
+						.unwrap_or(QueryOrder::Name(SortOrder::Asc))
+					{
+						QueryOrder::Name(order) => ("file_path.name COLLATE NOCASE", order),
+						QueryOrder::SizeInBytes(order) => ("file_path.size_in_bytes_bytes", order),
+						QueryOrder::DateCreated(order) => ("file_path.date_created", order),
+						QueryOrder::DateModified(order) => ("file_path.date_modified", order),
+						QueryOrder::DateIndexed(order) => ("file_path.date_indexed", order),
+					};
+
+					let take = take.unwrap_or(MAX_TAKE).min(MAX_TAKE);
+					let skip = skip.unwrap_or(0);
+
+					// One more than asked for, to know if there's a next page
+					params.push(PrismaValue::BigInt(i64::from(take) + 1));
+					params.push(PrismaValue::BigInt(i64::from(skip)));
+
+					let mut ids = db
+						._query_raw::<QueryMatch>(Raw::new(
+							&format!(
+								"SELECT file_path.id AS id
+								FROM file_path
+								LEFT JOIN object ON object.id = file_path.object_id
+								WHERE {conditions}
+								ORDER BY {}{column} {}, file_path.id ASC
+								LIMIT {{}} OFFSET {{}}",
+								if group_directories {
+									"file_path.is_dir DESC, "
+								} else {
+									""
+								},
+								match direction {
+									SortOrder::Asc => "ASC",
+									SortOrder::Desc => "DESC",
+								}
+							),
+							params,
+						))
+						.exec()
+						.await?
+						.into_iter()
+						.map(|QueryMatch { id }| id)
+						.collect::<Vec<_>>();
+
+					let cursor = (ids.len() > usize::from(take)).then(|| {
+						ids.truncate(take.into());
+						skip + u32::from(take)
+					});
+
+					Ok(QueryData {
+						items: path_items(&node, &library, ids).await?,
+						cursor,
+					})
+				},
+			)
+		})
+		.procedure("allLibraries", {
+			#[derive(Deserialize, Type, Debug)]
+			#[serde(rename_all = "camelCase")]
+			struct AllLibrariesSearchArgs {
+				/// Written in the search query language, like `search.query`
+				query: String,
+				#[specta(optional)]
+				take: Option<u8>,
+			}
+
+			#[derive(Serialize, Type, Debug)]
+			#[serde(rename_all = "camelCase")]
+			struct LibrarySearchItem {
+				library_id: Uuid,
+				library_name: String,
+				item: ExplorerItem,
+			}
+
+			R.query(|node, AllLibrariesSearchArgs { query, take }| async move {
+				let query = query::Query::parse(&query)?;
+				let take = take.unwrap_or(MAX_TAKE).min(MAX_TAKE);
+
+				let libraries = node.libraries.get_all().await;
+
+				let matches = libraries
+					.iter()
+					.map(|library| {
+						let query = &query;
+						async move {
+							all_libraries::ranked_matches(query, take, &library.db)
+								.await
+								.map(|matches| {
+									matches
+										.into_iter()
+										.map(|ranked| (ranked.id, ranked.rank))
+										.collect()
+								})
+								.unwrap_or_else(|e| {
+									// One library failing shouldn't hide the matches of the rest
+									error!(
+										?e,
+										library_id = %library.id,
+										"Failed to search library;"
+									);
+									vec![]
+								})
+						}
+					})
+					.collect::<Vec<_>>()
+					.join()
+					.await;
+
+				let merged = all_libraries::merge_ranked(matches, take.into());
+
+				let mut ids_by_library = BTreeMap::<usize, Vec<_>>::new();
+				for (library, id) in &merged {
+					ids_by_library.entry(*library).or_default().push(*id);
+				}
+
+				let mut items = HashMap::with_capacity(merged.len());
+				for (index, ids) in ids_by_library {
+					let library = &libraries[index];
+					let library_name = library.config().await.name.to_string();
+
+					for item in path_items(&node, library, ids).await? {
+						if let ExplorerItem::Path {
+							item: file_path, ..
+						} = &item
+						{
+							items.insert(
+								(index, file_path.id),
+								LibrarySearchItem {
+									library_id: library.id,
+									library_name: library_name.clone(),
+									item,
+								},
+							);
+						}
+					}
+				}
+
+				// Items keep the order they were ranked in
+				Ok(merged
+					.into_iter()
+					.filter_map(|key| items.remove(&key))
+					.collect::<Vec<_>>())
+			})
+		})
+		.procedure("text", {
+			#[derive(Deserialize, Type, Debug)]
+			#[serde(rename_all = "camelCase")]
+			struct TextSearchArgs {
+				query: String,
+				#[specta(optional)]
+				take: Option<u8>,
+			}
+
+			#[derive(Serialize, Type, Debug)]
+			#[serde(rename_all = "camelCase")]
+			struct TextSearchItem {
+				item: ExplorerItem,
+				source: TextSource,
+				/// Passage of the text around the matches, which are highlighted
+				snippet: Vec<SnippetPart>,
+			}
+
+			R.with2(library()).query(
+				|(node, library), TextSearchArgs { query, take }| async move {
+					let hits = text_extractor::search_with_snippets(
+						&query,
+						take.unwrap_or(MAX_TAKE).min(MAX_TAKE).into(),
+						&library.db,
+					)
+					.await?;
+
+					let mut items = object_items(
+						&node,
+						&library,
+						hits.iter().map(|hit| hit.object_id).collect(),
+					)
+					.await?;
+
+					// Hits come best match first, the order is kept
+					Ok(hits
+						.into_iter()
+						.filter_map(
+							|TextSearchHit {
+							     object_id,
+							     source,
+							     snippet,
+							 }| {
+								items.remove(&object_id).map(|item| TextSearchItem {
+									item,
+									source,
+									snippet,
+								})
+							},
+						)
+						.collect())
+				},
+			)
+		})
+		.procedure("semantic", {
+			#[derive(Deserialize, Type, Debug)]
+			#[serde(rename_all = "camelCase")]
+			struct SemanticSearchArgs {
+				/// What the images look like, like `beach sunset with dog`
+				query: String,
+				#[specta(optional)]
+				take: Option<u8>,
+			}
+
+			#[derive(Serialize, Type, Debug)]
+			#[serde(rename_all = "camelCase")]
+			struct SemanticSearchItem {
+				item: ExplorerItem,
+				/// Cosine similarity between the image and the query
+				similarity: f32,
+			}
+
+			R.with2(library()).query(
+				|(node, library), SemanticSearchArgs { query, take }| async move {
+					#[cfg(not(feature = "ai"))]
+					{
+						let _ = (node, library, query, take);
+
+						return Err::<Vec<SemanticSearchItem>, _>(rspc::Error::new(
+							ErrorCode::MethodNotSupported,
+							"AI feature is not available".to_string(),
+						));
+					}
+
+					#[cfg(feature = "ai")]
+					{
+						let query = query.trim();
+						if query.is_empty() {
+							return Ok(vec![]);
+						}
+
+						let matches = semantic::search(
+							&node,
+							&library,
+							query,
+							take.unwrap_or(MAX_TAKE).min(MAX_TAKE).into(),
+						)
+						.await?;
+
+						let mut items = object_items(
+							&node,
+							&library,
+							matches.iter().map(|(object_id, _)| *object_id).collect(),
+						)
+						.await?;
+
+						// Matches come most similar first, the order is kept
+						Ok(matches
+							.into_iter()
+							.filter_map(|(object_id, similarity)| {
+								items
+									.remove(&object_id)
+									.map(|item| SemanticSearchItem { item, similarity })
+							})
+							.collect())
+					}
+				},
+			)
+		})
+		.procedure("places", {
+			#[derive(Deserialize, Serialize, Type, Debug)]
+			#[serde(rename_all = "camelCase")]
+			struct PlaceCount {
+				country: String,
+				city: Option<String>,
+				count: i32,
+			}
+
+			R.with2(library()).query(|(_, library), _: ()| async move {
+				// Every known place with how many objects were taken there, to build city and
+				// country filters from
+				Ok(library
+					.db
+					._query_raw::<PlaceCount>(raw!(
+						"SELECT country, city, COUNT(*) AS count
+						FROM exif_data
+						WHERE country IS NOT NULL
+						GROUP BY country, city
+						ORDER BY count DESC"
+					))
+					.exec()
+					.await?)
+			})
+		})
 		.merge("saved.", saved::mount())
 }
 
@@ -410,6 +871,102 @@ async fn merge_filters(
 	Ok((fp, obj))
 }
 
+/// Explorer items of the file paths with `ids`, in the same order
+async fn path_items(
+	node: &Node,
+	library: &Library,
+	ids: Vec<prisma::file_path::id::Type>,
+) -> Result<Vec<ExplorerItem>, rspc::Error> {
+	let mut file_paths = library
+		.db
+		.file_path()
+		.find_many(vec![prisma::file_path::id::in_vec(ids.clone())])
+		.include(file_path_for_frontend::include())
+		.exec()
+		.await?
+		.into_iter()
+		.map(|file_path| (file_path.id, file_path))
+		.collect::<HashMap<_, _>>();
+
+	let mut items = Vec::with_capacity(ids.len());
+
+	for file_path in ids.into_iter().filter_map(|id| file_paths.remove(&id)) {
+		let has_created_thumbnail = if let Some(cas_id) = &file_path.cas_id {
+			library
+				.thumbnail_exists(node, cas_id)
+				.await
+				.map_err(LocationError::from)?
+		} else {
+			false
+		};
+
+		items.push(ExplorerItem::Path {
+			thumbnail: file_path
+				.cas_id
+				.as_ref()
+				.map(|i| get_indexed_thumb_key(i, library.id)),
+			has_created_thumbnail,
+			item: Box::new(file_path),
+		})
+	}
+
+	Ok(items)
+}
+
+pub(crate) async fn object_items(
+	node: &Node,
+	library: &Library,
+	ids: Vec<prisma::object::id::Type>,
+) -> Result<HashMap<prisma::object::id::Type, ExplorerItem>, rspc::Error> {
+	let objects = library
+		.db
+		.object()
+		.find_many(vec![prisma::object::id::in_vec(ids)])
+		.include(object_with_file_paths::include())
+		.exec()
+		.await?;
+
+	let mut items = HashMap::with_capacity(objects.len());
+
+	for object in objects {
+		let cas_id = object.file_paths.iter().find_map(|fp| fp.cas_id.as_ref());
+
+		let has_created_thumbnail = if let Some(cas_id) = cas_id {
+			library.thumbnail_exists(node, cas_id).await.map_err(|e| {
+				rspc::Error::with_cause(
+					ErrorCode::InternalServerError,
+					"Failed to check that thumbnail exists".to_string(),
+					e,
+				)
+			})?
+		} else {
+			false
+		};
+
+		items.insert(
+			object.id,
+			ExplorerItem::Object {
+				thumbnail: cas_id.map(|cas_id| get_indexed_thumb_key(cas_id, library.id)),
+				has_created_thumbnail,
+				item: object,
+			},
+		);
+	}
+
+	Ok(items)
+}
+
+/// File paths in the trash are left out of searches, unless filtering on them
+fn untrashed_filter(filters: &[SearchFilterArgs]) -> Option<prisma::file_path::WhereParam> {
+	(!filters.iter().any(|filter| {
+		matches!(
+			filter,
+			SearchFilterArgs::FilePath(FilePathFilterArgs::Trashed(_))
+		)
+	}))
+	.then(|| prisma::file_path::date_trashed::equals(None))
+}
+
 /// PCR 0.6.x's AND does { AND: [{ ...}] } instead of { AND: [{ ... }, { ... }, { ... }] },
 /// this works around it.
 fn andify<T: From<Operator<T>>>(params: Vec<T>) -> Vec<T> {
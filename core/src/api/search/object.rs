@@ -1,6 +1,11 @@
 // use crate::library::Category;
+use crate::object::{custom_field::date_to_number, tag::hierarchy};
 
-use sd_prisma::prisma::{self, label_on_object, object, tag_on_object};
+use sd_core_heavy_lifting::text_extractor;
+use sd_prisma::prisma::{
+	self, custom_field, custom_field_value, ffmpeg_data, ffmpeg_media_codec, ffmpeg_media_program,
+	ffmpeg_media_stream, label_on_object, object, tag_on_object,
+};
 
 use chrono::{DateTime, FixedOffset};
 use prisma_client_rust::{not, or, OrderByQuery, PaginatedQuery, WhereQuery};
@@ -12,6 +17,9 @@ use super::{
 	utils::{self, *},
 };
 
+/// Text searches only keep the best matches, as they're sent to the database as a list of ids
+const MAX_TEXT_MATCHES: i64 = 1000;
+
 #[derive(Deserialize, Type, Debug)]
 #[serde(rename_all = "camelCase")]
 pub enum ObjectCursor {
@@ -115,22 +123,91 @@ pub enum ObjectFilterArgs {
 	Tags(InOrNotIn<i32>),
 	Labels(InOrNotIn<i32>),
 	DateAccessed(Range<chrono::DateTime<FixedOffset>>),
+	City(InOrNotIn<String>),
+	Country(InOrNotIn<String>),
+	AudioLanguage(InOrNotIn<String>),
+	SubtitleLanguage(InOrNotIn<String>),
+	/// Words in the text extracted from documents and images, or transcribed from speech, see
+	/// [`text_extractor`]
+	Text(String),
+	CustomField(CustomFieldFilter),
+}
+
+/// Objects by the value they have for a custom field, see [`crate::object::custom_field`]
+#[derive(Serialize, Deserialize, Type, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomFieldFilter {
+	pub field_id: custom_field::id::Type,
+	pub condition: CustomFieldCondition,
+}
+
+#[derive(Serialize, Deserialize, Type, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub enum CustomFieldCondition {
+	/// Whether the object has a value for the field at all
+	IsSet(bool),
+	Text(TextMatch),
+	/// Any of these options of a select field
+	OneOf(Vec<String>),
+	/// Numbers and ratings
+	Number(Range<f64>),
+	Boolean(bool),
+	Date(Range<DateTime<FixedOffset>>),
+}
+
+impl CustomFieldFilter {
+	fn into_param(self) -> Option<object::WhereParam> {
+		use custom_field_value::{custom_field_id, number, text};
+
+		let field = custom_field_id::equals(self.field_id);
+
+		let condition = match self.condition {
+			CustomFieldCondition::IsSet(true) => {
+				return Some(object::custom_fields::some(vec![field]))
+			}
+			CustomFieldCondition::IsSet(false) => {
+				return Some(object::custom_fields::none(vec![field]))
+			}
+			CustomFieldCondition::Text(v) => {
+				v.into_param(text::contains, text::starts_with, text::ends_with, |v| {
+					text::equals(Some(v))
+				})?
+			}
+			CustomFieldCondition::OneOf(v) => text::in_vec(v),
+			CustomFieldCondition::Number(Range::From(v)) => number::gte(v),
+			CustomFieldCondition::Number(Range::To(v)) => number::lte(v),
+			CustomFieldCondition::Boolean(v) => number::equals(Some(if v { 1.0 } else { 0.0 })),
+			CustomFieldCondition::Date(Range::From(v)) => number::gte(date_to_number(v)),
+			CustomFieldCondition::Date(Range::To(v)) => number::lte(date_to_number(v)),
+		};
+
+		Some(object::custom_fields::some(vec![field, condition]))
+	}
 }
 
 impl ObjectFilterArgs {
-	pub fn into_params(self) -> Vec<object::WhereParam> {
+	pub async fn into_params(
+		self,
+		db: &prisma::PrismaClient,
+	) -> Result<Vec<object::WhereParam>, rspc::Error> {
 		use object::*;
 
-		match self {
+		Ok(match self {
 			Self::Favorite(v) => vec![favorite::equals(Some(v))],
 			Self::Hidden(v) => v.to_param().map(|v| vec![v]).unwrap_or_default(),
-			Self::Tags(v) => v
-				.into_param(
-					|v| tags::some(vec![tag_on_object::tag_id::in_vec(v)]),
-					|v| tags::none(vec![tag_on_object::tag_id::in_vec(v)]),
-				)
-				.map(|v| vec![v])
-				.unwrap_or_default(),
+			// Tags nested under the filtered ones count as them too
+			Self::Tags(v) => match v {
+				InOrNotIn::In(ids) => InOrNotIn::In(hierarchy::with_descendants(ids, db).await?),
+				InOrNotIn::NotIn(ids) => {
+					InOrNotIn::NotIn(hierarchy::with_descendants(ids, db).await?)
+				}
+			}
+			.into_param(
+				|v| tags::some(vec![tag_on_object::tag_id::in_vec(v)]),
+				|v| tags::none(vec![tag_on_object::tag_id::in_vec(v)]),
+			)
+			.map(|v| vec![v])
+			.unwrap_or_default(),
 			Self::Labels(v) => v
 				.into_param(
 					|v| labels::some(vec![label_on_object::label_id::in_vec(v)]),
@@ -151,10 +228,52 @@ impl ObjectFilterArgs {
 					},
 				]
 			}
-		}
+			Self::City(v) => v
+				.into_param(
+					|v| exif_data::is(vec![prisma::exif_data::city::in_vec(v)]),
+					|v| not![exif_data::is(vec![prisma::exif_data::city::in_vec(v)])],
+				)
+				.map(|v| vec![v])
+				.unwrap_or_default(),
+			Self::Country(v) => v
+				.into_param(
+					|v| exif_data::is(vec![prisma::exif_data::country::in_vec(v)]),
+					|v| not![exif_data::is(vec![prisma::exif_data::country::in_vec(v)])],
+				)
+				.map(|v| vec![v])
+				.unwrap_or_default(),
+			Self::AudioLanguage(v) => v
+				.into_param(
+					|v| ffmpeg_data::is(vec![track_language_in("audio", v)]),
+					|v| not![ffmpeg_data::is(vec![track_language_in("audio", v)])],
+				)
+				.map(|v| vec![v])
+				.unwrap_or_default(),
+			Self::SubtitleLanguage(v) => v
+				.into_param(
+					|v| ffmpeg_data::is(vec![track_language_in("subtitle", v)]),
+					|v| not![ffmpeg_data::is(vec![track_language_in("subtitle", v)])],
+				)
+				.map(|v| vec![v])
+				.unwrap_or_default(),
+			Self::Text(v) => vec![id::in_vec(
+				text_extractor::search(&v, MAX_TEXT_MATCHES, db).await?,
+			)],
+			Self::CustomField(v) => v.into_param().map(|v| vec![v]).unwrap_or_default(),
+		})
 	}
 }
 
+/// Media with a stream of the `kind` codec (like `audio` or `subtitle`) in one of the `languages`
+fn track_language_in(kind: &str, languages: Vec<String>) -> ffmpeg_data::WhereParam {
+	ffmpeg_data::programs::some(vec![ffmpeg_media_program::streams::some(vec![
+		ffmpeg_media_stream::language::in_vec(languages),
+		ffmpeg_media_stream::codec::is(vec![ffmpeg_media_codec::kind::equals(Some(
+			kind.to_string(),
+		))]),
+	])])
+}
+
 pub type OrderAndPagination =
 	utils::OrderAndPagination<object::id::Type, ObjectOrder, ObjectCursor>;
 
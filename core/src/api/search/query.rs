@@ -0,0 +1,775 @@
+//! Query language of the search bar, like
+//! `kind:video size:>1GB tag:work created:2023..2024 "project x"`.
+//!
+//! Words and quoted phrases match file names or the text extracted from the files, while
+//! `key:value` pairs filter on a property, all of them having to match. A leading `-` negates a
+//! term, like `-tag:archived`. Pairs with an unknown key are searched as words, so `12:30` finds
+//! files named after the time.
+//!
+//! Sizes and dates take a value, a range with `..` (each side optional) or a comparison with
+//! `>`, `>=`, `<` or `<=`. Sizes accept `B`, `KB`, `MB`, `GB` and `TB` units, counted in powers of
+//! 1024. Dates are a year, a month or a day, like `2023`, `2023-05` or `2023-05-12`, and cover the
+//! whole period: `created:2023..2024` includes files created on the last day of 2024. They can be
+//! relative too, with `today`, `yesterday`, `this-week`, `this-month`, `this-year` or the last
+//! days or weeks, like `added:7d` or `modified:2w`.
+
+use sd_core_heavy_lifting::text_extractor::fts_query;
+use sd_file_ext::kind::ObjectKind;
+use sd_prisma::prisma::{file_path, object, PrismaClient};
+
+use chrono::{DateTime, Datelike, Days, FixedOffset, Months, NaiveDate, Utc};
+use prisma_client_rust::{PrismaValue, Raw};
+use rspc::ErrorCode;
+use serde::Deserialize;
+use strum::IntoEnumIterator;
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum QueryError {
+	#[error("unknown kind '{0}'")]
+	UnknownKind(String),
+	#[error("invalid size '{0}', sizes look like 500KB or 1.5GB")]
+	InvalidSize(String),
+	#[error("invalid date '{0}', dates look like 2023, 2023-05 or 2023-05-12")]
+	InvalidDate(String),
+	#[error("unknown property '{0}', it can be favorite, hidden or trashed")]
+	UnknownProperty(String),
+}
+
+impl From<QueryError> for rspc::Error {
+	fn from(err: QueryError) -> Self {
+		Self::with_cause(ErrorCode::BadRequest, err.to_string(), err)
+	}
+}
+
+/// Half-open bounds of sizes or dates, from included and to excluded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bounds<T> {
+	pub from: Option<T>,
+	pub to: Option<T>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Property {
+	Favorite,
+	Hidden,
+	Trashed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Term {
+	/// Matches the file name or the text extracted from the file
+	Text(String),
+	Name(String),
+	/// Matches only the text extracted from the file, see `text_extractor`
+	Content(String),
+	Kind(ObjectKind),
+	Extension(String),
+	Size(Bounds<u64>),
+	Tag(String),
+	Label(String),
+	Location(String),
+	Created(Bounds<DateTime<Utc>>),
+	Modified(Bounds<DateTime<Utc>>),
+	/// When the file was added to the library
+	Indexed(Bounds<DateTime<Utc>>),
+	Is(Property),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Filter {
+	pub term: Term,
+	pub negated: bool,
+}
+
+/// A parsed search query, every filter has to match
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Query {
+	pub filters: Vec<Filter>,
+}
+
+impl Query {
+	pub fn parse(input: &str) -> Result<Self, QueryError> {
+		Self::parse_at(input, Utc::now())
+	}
+
+	/// Parses the query with relative dates, like `today` or `7d`, relative to `now`
+	pub fn parse_at(input: &str, now: DateTime<Utc>) -> Result<Self, QueryError> {
+		tokenize(input)
+			.into_iter()
+			.map(|token| {
+				Ok(Filter {
+					negated: token.negated,
+					term: if token.literal {
+						Term::Text(token.text)
+					} else {
+						parse_term(token.text, now)?
+					},
+				})
+			})
+			.collect::<Result<_, _>>()
+			.map(|filters| Self { filters })
+	}
+
+	/// SQL conditions on `file_path` (with `object` left joined) matching the query, with `{}`
+	/// placeholders for the values, which are never written in the SQL itself
+	pub fn compile(&self) -> (String, Vec<PrismaValue>) {
+		let mut conditions = Vec::with_capacity(self.filters.len() + 1);
+		let mut params = Vec::new();
+
+		// Trashed files are left out, unless the query is about them
+		if !self
+			.filters
+			.iter()
+			.any(|filter| filter.term == Term::Is(Property::Trashed))
+		{
+			conditions.push("file_path.date_trashed IS NULL".to_string());
+		}
+
+		for Filter { term, negated } in &self.filters {
+			let Some(condition) = compile_term(term, &mut params) else {
+				continue;
+			};
+
+			conditions.push(if *negated {
+				// A plain NOT would leave out rows where the condition is NULL, like files
+				// without an object when negating a kind
+				format!("(({condition}) IS NOT 1)")
+			} else {
+				format!("({condition})")
+			});
+		}
+
+		(conditions.join(" AND "), params)
+	}
+
+	/// SQL expression ranking a matching file path by how well its name matches the words of the
+	/// query: 3 for each word that's the whole name, 2 for one it starts with and 1 for one it
+	/// contains, files only matched by their text ranking last. Its values come before those of
+	/// [`Query::compile`] when it's selected along the conditions.
+	pub fn rank(&self) -> (String, Vec<PrismaValue>) {
+		let mut params = Vec::new();
+
+		let ranks = self
+			.filters
+			.iter()
+			.filter_map(|filter| match &filter.term {
+				Term::Text(word) | Term::Name(word) if !filter.negated => Some(word),
+				_ => None,
+			})
+			.map(|word| {
+				params.extend([
+					PrismaValue::String(word.clone()),
+					PrismaValue::String(format!("{}%", escape_like(word))),
+					PrismaValue::String(like_pattern(word)),
+				]);
+
+				"(CASE
+					WHEN file_path.name = {} COLLATE NOCASE THEN 3
+					WHEN file_path.name LIKE {} ESCAPE '\\' THEN 2
+					WHEN file_path.name LIKE {} ESCAPE '\\' THEN 1
+					ELSE 0
+				END)"
+			})
+			.collect::<Vec<_>>();
+
+		if ranks.is_empty() {
+			return ("0".to_string(), params);
+		}
+
+		(ranks.join(" + "), params)
+	}
+
+	/// Ids of every file path matching the query
+	pub async fn file_path_ids(
+		&self,
+		db: &PrismaClient,
+	) -> Result<Vec<file_path::id::Type>, prisma_client_rust::QueryError> {
+		#[derive(Deserialize)]
+		struct QueryMatch {
+			id: file_path::id::Type,
+		}
+
+		let (conditions, params) = self.compile();
+
+		db._query_raw::<QueryMatch>(Raw::new(
+			&format!(
+				"SELECT file_path.id AS id
+				FROM file_path
+				LEFT JOIN object ON object.id = file_path.object_id
+				WHERE {conditions}"
+			),
+			params,
+		))
+		.exec()
+		.await
+		.map(|matches| matches.into_iter().map(|QueryMatch { id }| id).collect())
+	}
+
+	/// Ids of the objects of the file paths matching the query
+	pub async fn object_ids(
+		&self,
+		db: &PrismaClient,
+	) -> Result<Vec<object::id::Type>, prisma_client_rust::QueryError> {
+		#[derive(Deserialize)]
+		struct QueryMatch {
+			id: object::id::Type,
+		}
+
+		let (conditions, params) = self.compile();
+
+		db._query_raw::<QueryMatch>(Raw::new(
+			&format!(
+				"SELECT DISTINCT object.id AS id
+				FROM file_path
+				INNER JOIN object ON object.id = file_path.object_id
+				WHERE {conditions}"
+			),
+			params,
+		))
+		.exec()
+		.await
+		.map(|matches| matches.into_iter().map(|QueryMatch { id }| id).collect())
+	}
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Token {
+	text: String,
+	negated: bool,
+	/// Started with a quote, so it's searched as is even if it looks like `key:value`
+	literal: bool,
+}
+
+/// Splits the input on whitespace, except inside double quotes
+fn tokenize(input: &str) -> Vec<Token> {
+	let mut tokens = Vec::new();
+	let mut chars = input.chars().peekable();
+
+	loop {
+		while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+		let Some(&first) = chars.peek() else {
+			break;
+		};
+
+		let negated = first == '-' && {
+			chars.next();
+			chars.peek().is_some_and(|c| !c.is_whitespace())
+		};
+
+		let literal = chars.peek() == Some(&'"');
+		let mut text = String::new();
+		let mut in_quotes = false;
+
+		while let Some(c) = chars.next_if(|&c| in_quotes || !c.is_whitespace()) {
+			if c == '"' {
+				in_quotes = !in_quotes;
+			} else {
+				text.push(c);
+			}
+		}
+
+		if !text.is_empty() {
+			tokens.push(Token {
+				text,
+				negated,
+				literal,
+			});
+		} else if first == '-' && !negated {
+			// A lone dash is a word like any other
+			tokens.push(Token {
+				text: "-".to_string(),
+				negated: false,
+				literal: false,
+			});
+		}
+	}
+
+	tokens
+}
+
+fn parse_term(text: String, now: DateTime<Utc>) -> Result<Term, QueryError> {
+	let period = |value: &str| parse_period(value, now);
+
+	let Some((key, value)) = text.split_once(':').filter(|(_, value)| !value.is_empty()) else {
+		return Ok(Term::Text(text));
+	};
+
+	Ok(match key.to_lowercase().as_str() {
+		"name" => Term::Name(value.to_string()),
+		"text" | "content" => Term::Content(value.to_string()),
+		"kind" => Term::Kind(
+			ObjectKind::iter()
+				.find(|kind| kind.to_string().eq_ignore_ascii_case(value))
+				.ok_or_else(|| QueryError::UnknownKind(value.to_string()))?,
+		),
+		"ext" | "extension" => Term::Extension(value.trim_start_matches('.').to_lowercase()),
+		"size" => Term::Size(
+			parse_bounds(value, parse_size)
+				.ok_or_else(|| QueryError::InvalidSize(value.to_string()))?,
+		),
+		"tag" => Term::Tag(value.to_string()),
+		"label" => Term::Label(value.to_string()),
+		"location" | "in" => Term::Location(value.to_string()),
+		"created" => Term::Created(
+			parse_bounds(value, period)
+				.ok_or_else(|| QueryError::InvalidDate(value.to_string()))?,
+		),
+		"modified" => Term::Modified(
+			parse_bounds(value, period)
+				.ok_or_else(|| QueryError::InvalidDate(value.to_string()))?,
+		),
+		"added" | "indexed" => Term::Indexed(
+			parse_bounds(value, period)
+				.ok_or_else(|| QueryError::InvalidDate(value.to_string()))?,
+		),
+		"is" => Term::Is(match value.to_lowercase().as_str() {
+			"favorite" | "favourite" | "fav" => Property::Favorite,
+			"hidden" => Property::Hidden,
+			"trashed" | "trash" => Property::Trashed,
+			_ => return Err(QueryError::UnknownProperty(value.to_string())),
+		}),
+		_ => Term::Text(text),
+	})
+}
+
+/// Parses a value, a range or a comparison, where `parse_one` gives the half-open span a single
+/// value covers, like the whole day of a date
+fn parse_bounds<T: Copy>(
+	value: &str,
+	parse_one: impl Fn(&str) -> Option<(T, T)>,
+) -> Option<Bounds<T>> {
+	let bounds = if let Some(value) = value.strip_prefix(">=") {
+		Bounds {
+			from: Some(parse_one(value)?.0),
+			to: None,
+		}
+	} else if let Some(value) = value.strip_prefix('>') {
+		Bounds {
+			from: Some(parse_one(value)?.1),
+			to: None,
+		}
+	} else if let Some(value) = value.strip_prefix("<=") {
+		Bounds {
+			from: None,
+			to: Some(parse_one(value)?.1),
+		}
+	} else if let Some(value) = value.strip_prefix('<') {
+		Bounds {
+			from: None,
+			to: Some(parse_one(value)?.0),
+		}
+	} else if let Some((start, end)) = value.split_once("..") {
+		Bounds {
+			from: if start.is_empty() {
+				None
+			} else {
+				Some(parse_one(start)?.0)
+			},
+			to: if end.is_empty() {
+				None
+			} else {
+				Some(parse_one(end)?.1)
+			},
+		}
+	} else {
+		let (from, to) = parse_one(value)?;
+		Bounds {
+			from: Some(from),
+			to: Some(to),
+		}
+	};
+
+	(bounds.from.is_some() || bounds.to.is_some()).then_some(bounds)
+}
+
+fn parse_size(value: &str) -> Option<(u64, u64)> {
+	let value = value.trim().to_lowercase();
+	let number_end = value
+		.find(|c: char| !c.is_ascii_digit() && c != '.')
+		.unwrap_or(value.len());
+
+	let (number, unit) = value.split_at(number_end);
+	let number = number.parse::<f64>().ok().filter(|n| n.is_finite())?;
+
+	let multiplier = match unit.trim() {
+		"" | "b" => 1u64,
+		"k" | "kb" | "kib" => 1 << 10,
+		"m" | "mb" | "mib" => 1 << 20,
+		"g" | "gb" | "gib" => 1 << 30,
+		"t" | "tb" | "tib" => 1 << 40,
+		_ => return None,
+	};
+
+	#[allow(
+		clippy::cast_precision_loss,
+		clippy::cast_possible_truncation,
+		clippy::cast_sign_loss
+	)]
+	let size = (number * multiplier as f64) as u64;
+
+	Some((size, size.saturating_add(1)))
+}
+
+/// Span of a year, a month or a day, or one relative to `now`, as UTC as there's no time zone to
+/// go by
+fn parse_period(value: &str, now: DateTime<Utc>) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+	let today = now.date_naive();
+
+	let (start, end) = match value.to_lowercase().as_str() {
+		"today" => (today, today.succ_opt()?),
+		"yesterday" => (today.pred_opt()?, today),
+		"this-week" => {
+			let start = today - Days::new(today.weekday().num_days_from_monday().into());
+			(start, start + Days::new(7))
+		}
+		"this-month" => {
+			let start = today.with_day(1)?;
+			(start, start.checked_add_months(Months::new(1))?)
+		}
+		"this-year" => {
+			let start = NaiveDate::from_ymd_opt(today.year(), 1, 1)?;
+			(start, start.checked_add_months(Months::new(12))?)
+		}
+		value => {
+			// The last days or weeks, like `7d` or `2w`, until the end of today
+			let last_days = value
+				.strip_suffix('d')
+				.and_then(|days| days.parse::<u64>().ok())
+				.or_else(|| {
+					value
+						.strip_suffix('w')
+						.and_then(|weeks| weeks.parse::<u64>().ok())
+						.map(|weeks| weeks * 7)
+				});
+
+			if let Some(days) = last_days {
+				(today.checked_sub_days(Days::new(days))?, today.succ_opt()?)
+			} else {
+				absolute_period(value)?
+			}
+		}
+	};
+
+	Some((
+		start.and_hms_opt(0, 0, 0)?.and_utc(),
+		end.and_hms_opt(0, 0, 0)?.and_utc(),
+	))
+}
+
+fn absolute_period(value: &str) -> Option<(NaiveDate, NaiveDate)> {
+	let parts = value
+		.split('-')
+		.map(|part| part.parse::<u32>().ok())
+		.collect::<Option<Vec<_>>>()?;
+
+	match parts[..] {
+		[year] => {
+			let start = NaiveDate::from_ymd_opt(i32::try_from(year).ok()?, 1, 1)?;
+			Some((start, start.checked_add_months(Months::new(12))?))
+		}
+		[year, month] => {
+			let start = NaiveDate::from_ymd_opt(i32::try_from(year).ok()?, month, 1)?;
+			Some((start, start.checked_add_months(Months::new(1))?))
+		}
+		[year, month, day] => {
+			let start = NaiveDate::from_ymd_opt(i32::try_from(year).ok()?, month, day)?;
+			Some((start, start.succ_opt()?))
+		}
+		_ => None,
+	}
+}
+
+/// Escapes the wildcards of `LIKE`, so names are matched literally
+fn like_pattern(text: &str) -> String {
+	format!("%{}%", escape_like(text))
+}
+
+fn escape_like(text: &str) -> String {
+	text.replace('\\', "\\\\")
+		.replace('%', "\\%")
+		.replace('_', "\\_")
+}
+
+fn compile_term(term: &Term, params: &mut Vec<PrismaValue>) -> Option<String> {
+	const NAME_MATCHES: &str = "file_path.name LIKE {} ESCAPE '\\'";
+	const CONTENT_MATCHES: &str = "file_path.object_id IN (
+		SELECT object_text.object_id
+		FROM object_text_fts
+		INNER JOIN object_text ON object_text.id = object_text_fts.rowid
+		WHERE object_text_fts MATCH {}
+	)";
+
+	Some(match term {
+		Term::Text(text) => {
+			params.push(PrismaValue::String(like_pattern(text)));
+
+			match fts_query(text) {
+				Some(query) => {
+					params.push(PrismaValue::String(query));
+					format!("{NAME_MATCHES} OR {CONTENT_MATCHES}")
+				}
+				None => NAME_MATCHES.to_string(),
+			}
+		}
+		Term::Name(name) => {
+			params.push(PrismaValue::String(like_pattern(name)));
+			NAME_MATCHES.to_string()
+		}
+		Term::Content(text) => {
+			params.push(PrismaValue::String(fts_query(text)?));
+			CONTENT_MATCHES.to_string()
+		}
+		Term::Kind(kind) => {
+			params.push(PrismaValue::Int(*kind as i32));
+			"object.kind = {}".to_string()
+		}
+		Term::Extension(extension) => {
+			params.push(PrismaValue::String(extension.clone()));
+			"LOWER(file_path.extension) = {}".to_string()
+		}
+		// Sizes are stored as 8 big-endian bytes, which compare like the numbers they hold
+		Term::Size(bounds) => {
+			compile_bounds("file_path.size_in_bytes_bytes", bounds, params, |size| {
+				PrismaValue::Bytes(size.to_be_bytes().to_vec())
+			})
+		}
+		// Tags nested under the named one count as it too
+		Term::Tag(name) => {
+			params.push(PrismaValue::String(name.clone()));
+			"file_path.object_id IN (
+				WITH RECURSIVE tagged(id) AS (
+					SELECT id FROM tag WHERE name = {} COLLATE NOCASE
+					UNION
+					SELECT tag.id FROM tag INNER JOIN tagged ON tag.parent_id = tagged.id
+				)
+				SELECT tag_on_object.object_id
+				FROM tag_on_object
+				INNER JOIN tagged ON tagged.id = tag_on_object.tag_id
+			)"
+			.to_string()
+		}
+		Term::Label(name) => {
+			params.push(PrismaValue::String(name.clone()));
+			"file_path.object_id IN (
+				SELECT label_on_object.object_id
+				FROM label_on_object
+				INNER JOIN label ON label.id = label_on_object.label_id
+				WHERE label.name = {} COLLATE NOCASE
+			)"
+			.to_string()
+		}
+		Term::Location(name) => {
+			params.push(PrismaValue::String(name.clone()));
+			"file_path.location_id IN (SELECT id FROM location WHERE name = {} COLLATE NOCASE)"
+				.to_string()
+		}
+		Term::Created(bounds) => {
+			compile_bounds("file_path.date_created", bounds, params, date_value)
+		}
+		Term::Modified(bounds) => {
+			compile_bounds("file_path.date_modified", bounds, params, date_value)
+		}
+		Term::Indexed(bounds) => {
+			compile_bounds("file_path.date_indexed", bounds, params, date_value)
+		}
+		Term::Is(Property::Favorite) => "object.favorite = 1".to_string(),
+		Term::Is(Property::Hidden) => "file_path.hidden = 1".to_string(),
+		Term::Is(Property::Trashed) => "file_path.date_trashed IS NOT NULL".to_string(),
+	})
+}
+
+fn compile_bounds<T: Copy>(
+	column: &str,
+	Bounds { from, to }: &Bounds<T>,
+	params: &mut Vec<PrismaValue>,
+	value: fn(T) -> PrismaValue,
+) -> String {
+	let mut conditions = vec![];
+
+	if let Some(from) = from {
+		params.push(value(*from));
+		conditions.push(format!("{column} >= {{}}"));
+	}
+
+	if let Some(to) = to {
+		params.push(value(*to));
+		conditions.push(format!("{column} < {{}}"));
+	}
+
+	conditions.join(" AND ")
+}
+
+fn date_value(date: DateTime<Utc>) -> PrismaValue {
+	PrismaValue::DateTime(DateTime::<FixedOffset>::from(date))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn text(text: &str) -> Filter {
+		Filter {
+			term: Term::Text(text.to_string()),
+			negated: false,
+		}
+	}
+
+	fn day(year: i32, month: u32, day: u32) -> DateTime<Utc> {
+		NaiveDate::from_ymd_opt(year, month, day)
+			.and_then(|date| date.and_hms_opt(0, 0, 0))
+			.expect("valid date")
+			.and_utc()
+	}
+
+	#[test]
+	fn parses_the_example_query() {
+		assert_eq!(
+			Query::parse(r#"kind:video size:>1GB tag:work created:2023..2024 "project x""#),
+			Ok(Query {
+				filters: vec![
+					Filter {
+						term: Term::Kind(ObjectKind::Video),
+						negated: false,
+					},
+					Filter {
+						term: Term::Size(Bounds {
+							from: Some((1 << 30) + 1),
+							to: None,
+						}),
+						negated: false,
+					},
+					Filter {
+						term: Term::Tag("work".to_string()),
+						negated: false,
+					},
+					Filter {
+						term: Term::Created(Bounds {
+							from: Some(day(2023, 1, 1)),
+							to: Some(day(2025, 1, 1)),
+						}),
+						negated: false,
+					},
+					text("project x"),
+				],
+			})
+		);
+	}
+
+	#[test]
+	fn negation_and_quotes() {
+		assert_eq!(
+			Query::parse(r#"-tag:"old stuff" "kind:video" - 12:30"#),
+			Ok(Query {
+				filters: vec![
+					Filter {
+						term: Term::Tag("old stuff".to_string()),
+						negated: true,
+					},
+					text("kind:video"),
+					text("-"),
+					text("12:30"),
+				],
+			})
+		);
+	}
+
+	#[test]
+	fn size_comparisons() {
+		assert_eq!(
+			parse_bounds("<=1.5kb", parse_size),
+			Some(Bounds {
+				from: None,
+				to: Some(1537),
+			})
+		);
+		assert_eq!(
+			parse_bounds("10MB..", parse_size),
+			Some(Bounds {
+				from: Some(10 << 20),
+				to: None,
+			})
+		);
+		assert_eq!(parse_bounds("..", parse_size), None);
+		assert_eq!(parse_bounds("lots", parse_size), None);
+	}
+
+	#[test]
+	fn periods_cover_whole_months_and_days() {
+		assert_eq!(
+			parse_bounds("2023-12", |value| parse_period(value, Utc::now())),
+			Some(Bounds {
+				from: Some(day(2023, 12, 1)),
+				to: Some(day(2024, 1, 1)),
+			})
+		);
+		assert_eq!(
+			parse_bounds("<2024-02-29", |value| parse_period(value, Utc::now())),
+			Some(Bounds {
+				from: None,
+				to: Some(day(2024, 2, 29)),
+			})
+		);
+		assert_eq!(
+			parse_bounds("2023-02-30", |value| parse_period(value, Utc::now())),
+			None
+		);
+	}
+
+	#[test]
+	fn relative_periods() {
+		// A Wednesday afternoon
+		let now = day(2024, 7, 10) + chrono::Duration::hours(15);
+
+		assert_eq!(
+			parse_period("this-week", now),
+			Some((day(2024, 7, 8), day(2024, 7, 15)))
+		);
+		assert_eq!(
+			parse_period("7d", now),
+			Some((day(2024, 7, 3), day(2024, 7, 11)))
+		);
+		assert_eq!(
+			parse_period("yesterday", now),
+			Some((day(2024, 7, 9), day(2024, 7, 10)))
+		);
+	}
+
+	#[test]
+	fn invalid_values_are_errors() {
+		assert_eq!(
+			Query::parse("kind:spaceship"),
+			Err(QueryError::UnknownKind("spaceship".to_string()))
+		);
+		assert_eq!(
+			Query::parse("is:sparkly"),
+			Err(QueryError::UnknownProperty("sparkly".to_string()))
+		);
+	}
+
+	#[test]
+	fn compiled_conditions_keep_values_apart() {
+		let (sql, params) = Query::parse("-kind:image 100%")
+			.expect("valid query")
+			.compile();
+
+		assert!(sql.starts_with("file_path.date_trashed IS NULL AND ((object.kind = {}) IS NOT 1)"));
+		assert_eq!(params.len(), 3);
+		assert!(!sql.contains("100"));
+	}
+
+	#[test]
+	fn rank_scores_the_words_of_the_name() {
+		let (sql, params) = Query::parse("kind:image report_ -draft name:q3")
+			.expect("valid query")
+			.rank();
+
+		assert_eq!(sql.matches("CASE").count(), 2);
+		assert_eq!(params.len(), 6);
+		assert!(matches!(&params[1], PrismaValue::String(prefix) if prefix == "report\\_%"));
+		assert!(matches!(&params[5], PrismaValue::String(pattern) if pattern == "%q3%"));
+
+		let (sql, params) = Query::parse("kind:image").expect("valid query").rank();
+		assert_eq!((sql.as_str(), params.len()), ("0", 0));
+	}
+}
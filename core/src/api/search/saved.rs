@@ -1,19 +1,66 @@
-use std::str::FromStr;
+use std::{collections::BTreeSet, str::FromStr, time::Duration};
 
-use crate::{api::utils::library, invalidate_query, library::Library};
+use crate::{
+	api::{
+		locations::ExplorerItem,
+		utils::{library, library_mut, InvalidateOperationEvent, SingleInvalidateOperationEvent},
+		CoreEvent,
+	},
+	invalidate_query,
+	library::Library,
+	Node,
+};
 
-use sd_prisma::{prisma::saved_search, prisma_sync};
+use sd_prisma::{
+	prisma::{file_path, saved_search, PrismaClient},
+	prisma_sync,
+};
 use sd_sync::{option_sync_db_entry, sync_db_entry, OperationFactory};
 use sd_utils::chain_optional_iter;
 
+use async_stream::stream;
 use chrono::{DateTime, FixedOffset, Utc};
-use rspc::alpha::AlphaRouter;
-use serde::{de::IgnoredAny, Deserialize};
+use rspc::{alpha::AlphaRouter, ErrorCode};
+use serde::{de::IgnoredAny, Deserialize, Serialize};
 use specta::Type;
+use tokio::time::{sleep, timeout};
 use tracing::error;
 use uuid::Uuid;
 
-use super::{Ctx, R};
+use super::{
+	andify, merge_filters, path_items, query::Query, untrashed_filter, Ctx, SearchFilterArgs, R,
+};
+
+/// Matches of a live saved search that are tracked at most, the ones first indexed
+const MAX_LIVE_MATCHES: usize = 10_000;
+
+/// Jobs commit in bursts, which are let to settle before evaluating a live search again
+const LIVE_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Searches with relative dates, like `added:this-week`, change without any file changing, so live
+/// searches are evaluated again every so often anyway
+const LIVE_REFRESH_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Queries invalidated when files, their objects or the saved searches themselves change
+const LIVE_TRIGGER_QUERIES: &[&str] = &[
+	"search.paths",
+	"search.objects",
+	"search.saved.get",
+	"tags.getForObject",
+	"labels.getForObject",
+];
+
+#[derive(Serialize, Type, Debug)]
+#[serde(tag = "type", content = "data", rename_all = "camelCase")]
+enum LiveSearchUpdate {
+	/// Every match, sent first
+	Initial(Vec<ExplorerItem>),
+	/// What changed since the previous update
+	Changed {
+		added: Vec<ExplorerItem>,
+		removed: Vec<file_path::id::Type>,
+	},
+}
 
 #[derive(Type, Deserialize, Clone, Debug, Default)]
 #[serde(rename_all = "camelCase")]
@@ -47,7 +94,7 @@ impl FromStr for SearchTarget {
 pub(crate) fn mount() -> AlphaRouter<Ctx> {
 	R.router()
 		.procedure("create", {
-			R.with2(library()).mutation({
+			R.with2(library_mut()).mutation({
 				#[derive(Type, Deserialize, Clone, Debug)]
 				#[specta(inline)]
 				pub struct Args {
@@ -140,8 +187,83 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 					.await?)
 			})
 		})
+		.procedure("live", {
+			// Sends the matches of a saved search and then what changes as files are indexed,
+			// identified or edited, so smart folders stay up to date without polling
+			R.with2(library()).subscription(
+				|(node, library), search_id: saved_search::id::Type| async move {
+					// TODO: Only listen to events of this library, like other subscriptions
+					let mut event_bus_rx = node.event_bus.0.subscribe();
+
+					if library
+						.db
+						.saved_search()
+						.find_unique(saved_search::id::equals(search_id))
+						.exec()
+						.await?
+						.is_none()
+					{
+						return Err(rspc::Error::new(
+							ErrorCode::NotFound,
+							"search not found".into(),
+						));
+					}
+
+					Ok(stream! {
+						let mut current = None::<BTreeSet<file_path::id::Type>>;
+
+						loop {
+							let search = match library
+								.db
+								.saved_search()
+								.find_unique(saved_search::id::equals(search_id))
+								.exec()
+								.await
+							{
+								Ok(Some(search)) => search,
+								// The search was deleted, there's nothing left to follow
+								Ok(None) => break,
+								Err(e) => {
+									error!(?e, "Failed to fetch saved search;");
+									break;
+								}
+							};
+
+							match live_update(&node, &library, &search, current.as_ref()).await {
+								Ok((matches, update)) => {
+									current = Some(matches);
+
+									if let Some(update) = update {
+										yield update;
+									}
+								}
+								Err(e) => error!(?e, "Failed to evaluate live saved search;"),
+							}
+
+							// Waits for something to change, or for relative dates to move on
+							let _ = timeout(LIVE_REFRESH_INTERVAL, async {
+								loop {
+									match event_bus_rx.recv().await {
+										Ok(event) if triggers_live_search(&event) => break,
+										Ok(_) => {}
+										// Missed events may have been relevant ones
+										Err(_) => break,
+									}
+								}
+							})
+							.await;
+
+							sleep(LIVE_DEBOUNCE).await;
+
+							// Changes during the debounce are covered by the next evaluation
+							while event_bus_rx.try_recv().is_ok() {}
+						}
+					})
+				},
+			)
+		})
 		.procedure("update", {
-			R.with2(library()).mutation({
+			R.with2(library_mut()).mutation({
 				saved_search::partial_unchecked!(Args {
 					name
 					description
@@ -207,7 +329,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 			})
 		})
 		.procedure("delete", {
-			R.with2(library())
+			R.with2(library_mut())
 				.mutation(|(_, library), search_id: i32| async move {
 					let Library { db, sync, .. } = library.as_ref();
 
@@ -239,3 +361,110 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				})
 		})
 }
+
+fn triggers_live_search(event: &CoreEvent) -> bool {
+	match event {
+		CoreEvent::NewIdentifiedObjects { .. }
+		| CoreEvent::InvalidateOperation(InvalidateOperationEvent::All) => true,
+		CoreEvent::InvalidateOperation(InvalidateOperationEvent::Single(
+			SingleInvalidateOperationEvent { key, .. },
+		)) => LIVE_TRIGGER_QUERIES.contains(key),
+		_ => false,
+	}
+}
+
+/// Evaluates the search again, returning its matches and what changed since `previous`, if
+/// anything did
+async fn live_update(
+	node: &Node,
+	library: &Library,
+	search: &saved_search::Data,
+	previous: Option<&BTreeSet<file_path::id::Type>>,
+) -> Result<(BTreeSet<file_path::id::Type>, Option<LiveSearchUpdate>), rspc::Error> {
+	let matches = matching_file_paths(&library.db, search).await?;
+
+	let Some(previous) = previous else {
+		let items = path_items(node, library, matches.iter().copied().collect()).await?;
+		return Ok((matches, Some(LiveSearchUpdate::Initial(items))));
+	};
+
+	let added = matches.difference(previous).copied().collect::<Vec<_>>();
+	let removed = previous.difference(&matches).copied().collect::<Vec<_>>();
+
+	if added.is_empty() && removed.is_empty() {
+		return Ok((matches, None));
+	}
+
+	let added = path_items(node, library, added).await?;
+
+	Ok((matches, Some(LiveSearchUpdate::Changed { added, removed })))
+}
+
+/// File paths matching the query language in `search` and the `filters`, both of them when the
+/// search has both. Object searches match the file paths of their objects.
+async fn matching_file_paths(
+	db: &PrismaClient,
+	search: &saved_search::Data,
+) -> Result<BTreeSet<file_path::id::Type>, rspc::Error> {
+	let filters = search
+		.filters
+		.as_deref()
+		.map(serde_json::from_str::<Vec<SearchFilterArgs>>)
+		.transpose()
+		.map_err(|e| {
+			rspc::Error::with_cause(
+				ErrorCode::BadRequest,
+				"invalid saved search filters".into(),
+				e,
+			)
+		})?
+		.unwrap_or_default();
+
+	let mut matches = match search.search.as_deref().map(str::trim) {
+		Some(input) if !input.is_empty() => Some(
+			Query::parse(input)?
+				.file_path_ids(db)
+				.await?
+				.into_iter()
+				.collect::<BTreeSet<_>>(),
+		),
+		_ => None,
+	};
+
+	// A search with only a query doesn't need to go through every file path again
+	if !filters.is_empty() || matches.is_none() {
+		let params = {
+			let untrashed = untrashed_filter(&filters);
+			let (mut fp, obj) = merge_filters(filters, db).await?;
+
+			fp.extend(untrashed);
+
+			if !obj.is_empty() {
+				fp.push(file_path::object::is(obj));
+			}
+
+			fp
+		};
+
+		let filtered = db
+			.file_path()
+			.find_many(andify(params))
+			.select(file_path::select!({ id }))
+			.exec()
+			.await?
+			.into_iter()
+			.map(|file_path| file_path.id)
+			.collect::<BTreeSet<_>>();
+
+		matches = Some(match matches {
+			Some(matches) => matches.intersection(&filtered).copied().collect(),
+			None => filtered,
+		});
+	}
+
+	Ok(matches
+		.unwrap_or_default()
+		.into_iter()
+		.take(MAX_LIVE_MATCHES)
+		.collect())
+}
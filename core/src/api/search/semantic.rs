@@ -0,0 +1,114 @@
+use crate::{library::Library, Node};
+
+use sd_core_heavy_lifting::embedder::{index_path, EmbeddingIndex, TextEmbedder, CLIP_MODEL_ID};
+use sd_prisma::prisma::object;
+
+use std::{collections::HashMap, sync::Arc, time::SystemTime};
+
+use once_cell::sync::Lazy;
+use rspc::ErrorCode;
+use tokio::{
+	fs,
+	sync::{Mutex, OnceCell},
+	task::spawn_blocking,
+};
+use uuid::Uuid;
+
+/// Images below this cosine similarity to a description rarely have anything to do with it
+pub const MIN_SIMILARITY: f32 = 0.2;
+
+// Loaded on the first search and kept, as loading the model takes a while
+static TEXT_EMBEDDER: OnceCell<Arc<TextEmbedder>> = OnceCell::const_new();
+
+// Indexes are loaded again only when the embedder job saves a new one
+static INDEXES: Lazy<Mutex<HashMap<Uuid, (SystemTime, Arc<EmbeddingIndex>)>>> =
+	Lazy::new(Mutex::default);
+
+/// Objects whose images look the most like the description, with their similarity to it, most
+/// similar first
+pub async fn search(
+	node: &Node,
+	library: &Library,
+	description: &str,
+	take: usize,
+) -> Result<Vec<(object::id::Type, f32)>, rspc::Error> {
+	// Nothing was embedded in the library yet
+	let Some(index) = load_index(node, library).await? else {
+		return Ok(vec![]);
+	};
+
+	let embedder = TEXT_EMBEDDER
+		.get_or_try_init(|| async { TextEmbedder::load(&node.data_dir).await.map(Arc::new) })
+		.await
+		.map_err(|e| {
+			rspc::Error::with_cause(
+				ErrorCode::InternalServerError,
+				"Failed to load the text embeddings model".to_string(),
+				e,
+			)
+		})?;
+
+	let query = spawn_blocking({
+		let embedder = Arc::clone(embedder);
+		let description = description.to_string();
+		move || embedder.embed(&description)
+	})
+	.await
+	.map_err(|e| {
+		rspc::Error::with_cause(
+			ErrorCode::InternalServerError,
+			"Text embedding thread panicked".to_string(),
+			e,
+		)
+	})?
+	.map_err(|e| {
+		rspc::Error::with_cause(
+			ErrorCode::InternalServerError,
+			"Failed to embed the description".to_string(),
+			e,
+		)
+	})?;
+
+	Ok(index
+		.search(&query, take)
+		.into_iter()
+		.filter(|(_, similarity)| *similarity >= MIN_SIMILARITY)
+		.collect())
+}
+
+async fn load_index(
+	node: &Node,
+	library: &Library,
+) -> Result<Option<Arc<EmbeddingIndex>>, rspc::Error> {
+	let path = index_path(&node.data_dir, library.id);
+
+	let Ok(modified) = fs::metadata(&path)
+		.await
+		.and_then(|metadata| metadata.modified())
+	else {
+		return Ok(None);
+	};
+
+	let mut indexes = INDEXES.lock().await;
+
+	if let Some((loaded_modified, index)) = indexes.get(&library.id) {
+		if *loaded_modified == modified {
+			return Ok(Some(Arc::clone(index)));
+		}
+	}
+
+	let Some(index) = EmbeddingIndex::load(&path).await? else {
+		return Ok(None);
+	};
+
+	// Embeddings of another model can't be compared with the ones of the description
+	if index.model() != CLIP_MODEL_ID {
+		indexes.remove(&library.id);
+		return Ok(None);
+	}
+
+	let index = Arc::new(index);
+	indexes.insert(library.id, (modified, Arc::clone(&index)));
+
+	Ok(Some(index))
+}
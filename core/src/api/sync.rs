@@ -1,10 +1,24 @@
-use rspc::alpha::AlphaRouter;
-use sd_core_sync::GetOpsArgs;
+use rspc::{alpha::AlphaRouter, ErrorCode};
+use sd_core_sync::{
+	conflict::{self, ConflictSide, SyncConflict},
+	GetOpsArgs,
+};
+use sd_prisma::prisma::sync_conflict;
+use serde::Deserialize;
+use specta::Type;
 use std::sync::atomic::Ordering;
 
-use crate::util::MaybeUndefined;
+use crate::{
+	cloud::{self, relay},
+	invalidate_query,
+	library::sync_backfill::OldSyncBackfillJobInit,
+	old_job::Job,
+};
 
-use super::{utils::library, Ctx, R};
+use super::{
+	utils::{library, library_mut},
+	Ctx, R,
+};
 
 pub(crate) fn mount() -> AlphaRouter<Ctx> {
 	R.router()
@@ -34,9 +48,48 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 					.await?)
 			})
 		})
-		.procedure("backfill", {
+		.procedure("conflicts", {
 			R.with2(library())
-				.mutation(|(node, library), _: ()| async move {
+				.query(|(_, library), _: ()| async move { Ok(conflict::list(&library.db).await?) })
+		})
+		.procedure("resolveConflict", {
+			#[derive(Deserialize, Type)]
+			pub struct ResolveConflictArgs {
+				pub id: sync_conflict::id::Type,
+				pub keep: ConflictSide,
+			}
+
+			R.with2(library_mut()).mutation(
+				|(_, library), ResolveConflictArgs { id, keep }: ResolveConflictArgs| async move {
+					let conflict = library
+						.db
+						.sync_conflict()
+						.find_unique(sync_conflict::id::equals(id))
+						.exec()
+						.await?
+						.map(SyncConflict::from_db)
+						.ok_or_else(|| {
+							rspc::Error::new(ErrorCode::NotFound, "Sync conflict not found".into())
+						})?;
+
+					if conflict.date_resolved.is_some() {
+						return Err(rspc::Error::new(
+							ErrorCode::Conflict,
+							"Sync conflict was already resolved".into(),
+						));
+					}
+
+					library.sync.resolve_conflict(&conflict, keep).await?;
+
+					invalidate_query!(library, "sync.conflicts");
+
+					Ok(())
+				},
+			)
+		})
+		.procedure("backfill", {
+			R.with2(library_mut()).mutation(
+				|(node, library), args: OldSyncBackfillJobInit| async move {
 					if library
 						.config()
 						.await
@@ -46,22 +99,65 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 						return Ok(());
 					}
 
-					sd_core_sync::backfill::backfill_operations(
-						&library.db,
-						&library.sync,
-						library.config().await.instance_id,
-					)
-					.await;
-
-					node.libraries
-						.edit(
-							library.id,
-							None,
-							MaybeUndefined::Undefined,
-							MaybeUndefined::Undefined,
-							Some(true),
-						)
-						.await?;
+					// Sync is enabled by the job once it completes
+					Job::new(args)
+						.spawn(&node, &library)
+						.await
+						.map_err(Into::into)
+				},
+			)
+		})
+		.procedure("relay", {
+			R.with2(library()).query(|(_, library), _: ()| async move {
+				Ok(relay::get(&library.db)
+					.await?
+					.map(|(storage, _)| storage.redacted()))
+			})
+		})
+		.procedure("setRelay", {
+			#[derive(Deserialize, Type)]
+			pub struct SetRelayArgs {
+				pub storage: relay::RelayStorage,
+				pub passphrase: String,
+			}
+
+			R.with2(library_mut()).mutation(
+				|(_, library),
+				 SetRelayArgs {
+				     storage,
+				     passphrase,
+				 }: SetRelayArgs| async move {
+					if !library
+						.config()
+						.await
+						.generate_sync_operations
+						.load(Ordering::Relaxed)
+					{
+						return Err(rspc::Error::new(
+							ErrorCode::PreconditionFailed,
+							"Sync must be enabled to relay it".into(),
+						));
+					}
+
+					relay::set(&library, storage, &passphrase).await?;
+
+					library.actors.start(relay::ACTOR_NAME).await;
+					library.actors.start(cloud::sync::INGEST_ACTOR_NAME).await;
+
+					invalidate_query!(library, "sync.relay");
+
+					Ok(())
+				},
+			)
+		})
+		.procedure("removeRelay", {
+			R.with2(library_mut())
+				.mutation(|(_, library), _: ()| async move {
+					library.actors.stop(relay::ACTOR_NAME).await;
+
+					relay::remove(&library.db).await?;
+
+					invalidate_query!(library, "sync.relay");
 
 					Ok(())
 				})
@@ -85,6 +181,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 						cloud_send: bool,
 						cloud_receive: bool,
 						cloud_ingest: bool,
+						relay: bool,
 					}
 
 					async_stream::stream! {
@@ -97,6 +194,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 								cloud_send: cloud_sync.send_active.load(Ordering::Relaxed),
 								cloud_receive: cloud_sync.receive_active.load(Ordering::Relaxed),
 								cloud_ingest: cloud_sync.ingest_active.load(Ordering::Relaxed),
+								relay: cloud_sync.relay_active.load(Ordering::Relaxed),
 							};
 
 							tokio::select! {
@@ -1,22 +1,36 @@
-use crate::{invalidate_query, library::Library, object::tag::TagCreateArgs};
+use crate::{
+	context::NodeContext,
+	invalidate_query,
+	library::{rollups, Library},
+	object::tag::{hierarchy, TagCreateArgs},
+	Node,
+};
+
+use sd_core_heavy_lifting::tag_rules::{self, Condition, TagRuleApplier};
 
 use sd_prisma::{
-	prisma::{file_path, object, tag, tag_on_object},
+	prisma::{file_path, location, object, tag, tag_on_object, tag_rule, PrismaClient},
 	prisma_sync,
 };
-use sd_sync::{option_sync_db_entry, option_sync_entry, sync_entry, OperationFactory};
-use sd_utils::{msgpack, uuid_to_bytes};
+use sd_sync::{
+	option_sync_db_entry, option_sync_entry, sync_db_entry, sync_entry, OperationFactory,
+};
+use sd_utils::{db::maybe_missing, msgpack, uuid_to_bytes};
 
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, sync::Arc};
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, FixedOffset, Utc};
 use itertools::{Either, Itertools};
 use rspc::{alpha::AlphaRouter, ErrorCode};
 use serde::{Deserialize, Serialize};
 use specta::Type;
+use tracing::error;
 use uuid::Uuid;
 
-use super::{utils::library, Ctx, R};
+use super::{
+	utils::{library, library_mut},
+	Ctx, R,
+};
 
 pub(crate) fn mount() -> AlphaRouter<Ctx> {
 	R.router()
@@ -86,7 +100,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				})
 		})
 		.procedure("create", {
-			R.with2(library())
+			R.with2(library_mut())
 				.mutation(|(_, library), args: TagCreateArgs| async move {
 					// Check if tag with the same name already exists
 					let existing_tag = library
@@ -127,7 +141,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				unassign: bool,
 			}
 
-			R.with2(library())
+			R.with2(library_mut())
 				.mutation(|(_, library), args: TagAssignArgs| async move {
 					let Library { db, sync, .. } = library.as_ref();
 
@@ -286,9 +300,13 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 						.await?;
 					}
 
+					// Only jobs refresh the statistics rollups otherwise
+					rollups::refresh_tags(&library.db).await?;
+
 					invalidate_query!(library, "tags.getForObject");
 					invalidate_query!(library, "tags.getWithObjects");
 					invalidate_query!(library, "search.objects");
+					invalidate_query!(library, "library.statistics");
 
 					Ok(())
 				})
@@ -301,7 +319,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				pub color: Option<String>,
 			}
 
-			R.with2(library())
+			R.with2(library_mut())
 				.mutation(|(_, library), args: TagUpdateArgs| async move {
 					let Library { sync, db, .. } = library.as_ref();
 
@@ -357,9 +375,96 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 					Ok(())
 				})
 		})
+		.procedure("setParent", {
+			#[derive(Type, Deserialize)]
+			pub struct TagSetParentArgs {
+				pub id: tag::id::Type,
+				/// `None` moves the tag back to the top level
+				pub parent_id: Option<tag::id::Type>,
+			}
+
+			R.with2(library_mut())
+				.mutation(|(_, library), args: TagSetParentArgs| async move {
+					let Library { sync, db, .. } = library.as_ref();
+
+					let tag = db
+						.tag()
+						.find_unique(tag::id::equals(args.id))
+						.select(tag::select!({ pub_id }))
+						.exec()
+						.await?
+						.ok_or(rspc::Error::new(
+							ErrorCode::NotFound,
+							"Error finding tag in db".into(),
+						))?;
+
+					let sync_id = prisma_sync::tag::SyncId {
+						pub_id: tag.pub_id.clone(),
+					};
+
+					let (parent_op, parent_param) = if let Some(parent_id) = args.parent_id {
+						if hierarchy::would_cycle(
+							&hierarchy::parents(db).await?,
+							args.id,
+							parent_id,
+						) {
+							return Err(rspc::Error::new(
+								ErrorCode::BadRequest,
+								"A tag can't be nested under itself or a tag nested under it"
+									.to_string(),
+							));
+						}
+
+						let parent = db
+							.tag()
+							.find_unique(tag::id::equals(parent_id))
+							.select(tag::select!({ pub_id }))
+							.exec()
+							.await?
+							.ok_or(rspc::Error::new(
+								ErrorCode::NotFound,
+								"Error finding parent tag in db".into(),
+							))?;
+
+						(
+							sync.shared_update(
+								sync_id,
+								tag::parent::NAME,
+								msgpack!(prisma_sync::tag::SyncId {
+									pub_id: parent.pub_id
+								}),
+							),
+							tag::parent::connect(tag::id::equals(parent_id)),
+						)
+					} else {
+						(
+							sync.shared_update(sync_id, tag::parent::NAME, msgpack!(nil)),
+							tag::parent::disconnect(),
+						)
+					};
+
+					sync.write_op(
+						db,
+						parent_op,
+						db.tag().update(
+							tag::id::equals(args.id),
+							vec![
+								parent_param,
+								tag::date_modified::set(Some(Utc::now().into())),
+							],
+						),
+					)
+					.await?;
+
+					invalidate_query!(library, "tags.list");
+					invalidate_query!(library, "search.objects");
+
+					Ok(())
+				})
+		})
 		.procedure(
 			"delete",
-			R.with2(library())
+			R.with2(library_mut())
 				.mutation(|(_, library), tag_id: i32| async move {
 					library
 						.db
@@ -380,4 +485,269 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 					Ok(())
 				}),
 		)
+		.merge("rules.", mount_rule_routes())
+}
+
+/// A tag rule with its conditions decoded
+#[derive(Serialize, Type)]
+pub struct TagRule {
+	pub id: tag_rule::id::Type,
+	pub name: String,
+	pub tag_id: tag::id::Type,
+	pub conditions: Vec<Condition>,
+	pub enabled: bool,
+	pub date_created: DateTime<FixedOffset>,
+	pub date_modified: DateTime<FixedOffset>,
+}
+
+impl TryFrom<tag_rule::Data> for TagRule {
+	type Error = tag_rules::Error;
+
+	fn try_from(rule: tag_rule::Data) -> Result<Self, Self::Error> {
+		Ok(Self {
+			conditions: tag_rules::conditions_from_bytes(&maybe_missing(
+				rule.conditions,
+				"tag_rule.conditions",
+			)?)?,
+			id: rule.id,
+			name: maybe_missing(rule.name, "tag_rule.name")?,
+			tag_id: maybe_missing(rule.tag_id, "tag_rule.tag_id")?,
+			enabled: rule.enabled,
+			date_created: rule.date_created,
+			date_modified: rule.date_modified,
+		})
+	}
+}
+
+fn mount_rule_routes() -> AlphaRouter<Ctx> {
+	R.router()
+		.procedure("list", {
+			R.with2(library()).query(|(_, library), _: ()| async move {
+				Ok(library
+					.db
+					.tag_rule()
+					.find_many(vec![])
+					.exec()
+					.await?
+					.into_iter()
+					.map(TagRule::try_from)
+					.collect::<Result<Vec<_>, _>>()?)
+			})
+		})
+		.procedure("create", {
+			#[derive(Type, Deserialize)]
+			pub struct TagRuleCreateArgs {
+				pub tag_id: tag::id::Type,
+				pub name: String,
+				pub conditions: Vec<Condition>,
+			}
+
+			R.with2(library_mut())
+				.mutation(|(node, library), args: TagRuleCreateArgs| async move {
+					let Library { db, sync, .. } = library.as_ref();
+
+					tag_rules::validate(&args.conditions)?;
+
+					let tag = db
+						.tag()
+						.find_unique(tag::id::equals(args.tag_id))
+						.select(tag::select!({ pub_id }))
+						.exec()
+						.await?
+						.ok_or_else(|| {
+							rspc::Error::new(ErrorCode::NotFound, "Tag not found".to_string())
+						})?;
+
+					let pub_id = uuid_to_bytes(Uuid::new_v4());
+					let date_created: DateTime<FixedOffset> = Utc::now().into();
+
+					// Rules are synced, but each device applies them to the files it identifies
+					let (sync_params, db_params): (Vec<_>, Vec<_>) = [
+						sync_db_entry!(args.name, tag_rule::name),
+						sync_db_entry!(
+							tag_rules::conditions_to_bytes(&args.conditions)?,
+							tag_rule::conditions
+						),
+						(
+							sync_entry!(true, tag_rule::enabled),
+							tag_rule::enabled::set(true),
+						),
+						(
+							sync_entry!(date_created, tag_rule::date_created),
+							tag_rule::date_created::set(date_created),
+						),
+						(
+							sync_entry!(date_created, tag_rule::date_modified),
+							tag_rule::date_modified::set(date_created),
+						),
+						(
+							sync_entry!(
+								prisma_sync::tag::SyncId { pub_id: tag.pub_id },
+								tag_rule::tag
+							),
+							tag_rule::tag::connect(tag::id::equals(args.tag_id)),
+						),
+					]
+					.into_iter()
+					.unzip();
+
+					let created = sync
+						.write_ops(
+							db,
+							(
+								sync.shared_create(
+									prisma_sync::tag_rule::SyncId {
+										pub_id: pub_id.clone(),
+									},
+									sync_params,
+								),
+								db.tag_rule().create(pub_id, db_params),
+							),
+						)
+						.await?;
+
+					invalidate_query!(library, "tags.rules.list");
+
+					backfill_rules(&node, &library).await?;
+
+					Ok(TagRule::try_from(created)?)
+				})
+		})
+		.procedure("update", {
+			#[derive(Type, Deserialize)]
+			pub struct TagRuleUpdateArgs {
+				pub id: tag_rule::id::Type,
+				pub name: Option<String>,
+				pub conditions: Option<Vec<Condition>>,
+				pub enabled: Option<bool>,
+			}
+
+			R.with2(library_mut())
+				.mutation(|(node, library), args: TagRuleUpdateArgs| async move {
+					let Library { db, sync, .. } = library.as_ref();
+
+					// Objects the rule didn't match before may match it now
+					let backfill = args.conditions.is_some() || args.enabled == Some(true);
+
+					let conditions = args
+						.conditions
+						.map(|conditions| {
+							tag_rules::validate(&conditions)?;
+							tag_rules::conditions_to_bytes(&conditions)
+						})
+						.transpose()?;
+
+					let pub_id = find_rule_pub_id(db, args.id).await?;
+
+					let date_modified: DateTime<FixedOffset> = Utc::now().into();
+
+					let (sync_params, db_params): (Vec<_>, Vec<_>) = [
+						option_sync_db_entry!(args.name, tag_rule::name),
+						option_sync_db_entry!(conditions, tag_rule::conditions),
+						args.enabled.map(|enabled| {
+							(
+								sync_entry!(enabled, tag_rule::enabled),
+								tag_rule::enabled::set(enabled),
+							)
+						}),
+						Some((
+							sync_entry!(date_modified, tag_rule::date_modified),
+							tag_rule::date_modified::set(date_modified),
+						)),
+					]
+					.into_iter()
+					.flatten()
+					.unzip();
+
+					sync.write_ops(
+						db,
+						(
+							sync_params
+								.into_iter()
+								.map(|(k, v)| {
+									sync.shared_update(
+										prisma_sync::tag_rule::SyncId {
+											pub_id: pub_id.clone(),
+										},
+										k,
+										v,
+									)
+								})
+								.collect(),
+							db.tag_rule()
+								.update(tag_rule::id::equals(args.id), db_params),
+						),
+					)
+					.await?;
+
+					invalidate_query!(library, "tags.rules.list");
+
+					if backfill {
+						backfill_rules(&node, &library).await?;
+					}
+
+					Ok(())
+				})
+		})
+		.procedure("delete", {
+			R.with2(library_mut())
+				.mutation(|(_, library), id: tag_rule::id::Type| async move {
+					let Library { db, sync, .. } = library.as_ref();
+
+					let pub_id = find_rule_pub_id(db, id).await?;
+
+					sync.write_op(
+						db,
+						sync.shared_delete(prisma_sync::tag_rule::SyncId { pub_id }),
+						db.tag_rule().delete(tag_rule::id::equals(id)),
+					)
+					.await?;
+
+					invalidate_query!(library, "tags.rules.list");
+
+					Ok(())
+				})
+		})
+}
+
+async fn find_rule_pub_id(
+	db: &PrismaClient,
+	id: tag_rule::id::Type,
+) -> Result<tag_rule::pub_id::Type, rspc::Error> {
+	db.tag_rule()
+		.find_unique(tag_rule::id::equals(id))
+		.select(tag_rule::select!({ pub_id }))
+		.exec()
+		.await?
+		.map(|rule| rule.pub_id)
+		.ok_or_else(|| rspc::Error::new(ErrorCode::NotFound, "Tag rule not found".to_string()))
+}
+
+/// Tags the objects identified before a rule was created or changed, with a tag rule applier for
+/// each location of this node. Locations already being backfilled are left to their running one.
+async fn backfill_rules(node: &Arc<Node>, library: &Arc<Library>) -> Result<(), rspc::Error> {
+	let instance_id = library.config().await.instance_id;
+
+	for location in library
+		.db
+		.location()
+		.find_many(vec![location::instance_id::equals(Some(instance_id))])
+		.exec()
+		.await?
+	{
+		let location_id = location.id;
+
+		if let Err(e) = NodeContext::dispatch(
+			node,
+			library,
+			TagRuleApplier::new(location, None)?,
+			location_id,
+		)
+		.await
+		{
+			error!(?e, location_id, "Failed to dispatch the tag rule applier;");
+		}
+	}
+
+	Ok(())
 }
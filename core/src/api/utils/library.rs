@@ -34,17 +34,33 @@ impl MwArgMapper for LibraryArgsLike {
 
 pub(crate) fn library() -> impl MwV3<Ctx, NewCtx = (Ctx, Arc<Library>)> {
 	MwArgMapperMiddleware::<LibraryArgsLike>::new().mount(|mw, ctx: Ctx, library_id| async move {
-		let library = ctx
-			.libraries
-			.get_library(&library_id)
-			.await
-			.ok_or_else(|| {
-				rspc::Error::new(
-					ErrorCode::BadRequest,
-					"You must specify a valid library to use this operation.".to_string(),
-				)
-			})?;
+		let library = get_library(&ctx, &library_id).await?;
 
 		Ok(mw.next((ctx, library)))
 	})
 }
+
+/// Like [`library`] for procedures that change the library, refusing libraries that are read-only.
+pub(crate) fn library_mut() -> impl MwV3<Ctx, NewCtx = (Ctx, Arc<Library>)> {
+	MwArgMapperMiddleware::<LibraryArgsLike>::new().mount(|mw, ctx: Ctx, library_id| async move {
+		let library = get_library(&ctx, &library_id).await?;
+
+		if library.is_read_only().await {
+			return Err(rspc::Error::new(
+				ErrorCode::Forbidden,
+				"This library is read-only on this node.".to_string(),
+			));
+		}
+
+		Ok(mw.next((ctx, library)))
+	})
+}
+
+async fn get_library(ctx: &Ctx, library_id: &Uuid) -> Result<Arc<Library>, rspc::Error> {
+	ctx.libraries.get_library(library_id).await.ok_or_else(|| {
+		rspc::Error::new(
+			ErrorCode::BadRequest,
+			"You must specify a valid library to use this operation.".to_string(),
+		)
+	})
+}
@@ -2,10 +2,27 @@ use crate::volume::get_volumes;
 
 use rspc::alpha::AlphaRouter;
 
-use super::{Ctx, R};
+use super::{CoreEvent, Ctx, R};
 
 pub(crate) fn mount() -> AlphaRouter<Ctx> {
-	R.router().procedure("list", {
-		R.query(|_, _: ()| async move { Ok(get_volumes().await) })
-	})
+	R.router()
+		.procedure("list", {
+			R.query(|_, _: ()| async move { Ok(get_volumes().await) })
+		})
+		.procedure("health", {
+			R.query(|node, _: ()| async move { Ok(node.device_health.list().await) })
+		})
+		.procedure("healthAlerts", {
+			R.subscription(|node, _: ()| async move {
+				let mut event_bus_rx = node.event_bus.0.subscribe();
+				async_stream::stream! {
+					while let Ok(event) = event_bus_rx.recv().await {
+						match event {
+							CoreEvent::DeviceHealthAlert(alert) => yield alert,
+							_ => {}
+						}
+					}
+				}
+			})
+		})
 }
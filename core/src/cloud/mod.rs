@@ -4,6 +4,7 @@ use uuid::Uuid;
 
 use crate::Node;
 
+pub mod relay;
 pub mod sync;
 
 #[derive(Default)]
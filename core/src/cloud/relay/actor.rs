@@ -0,0 +1,450 @@
+use crate::{cloud::sync::receive, library::Libraries, Node};
+
+use sd_core_sync::{SyncMessage, NTP64};
+use sd_p2p::RemoteIdentity;
+use sd_prisma::prisma::{cloud_crdt_operation, instance, PrismaClient, SortOrder};
+use sd_sync::CompressedCRDTOperations;
+use sd_utils::uuid_to_bytes;
+
+use std::{
+	collections::HashMap,
+	future::pending,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc,
+	},
+	time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::{
+	select,
+	sync::{broadcast::error::RecvError, Notify},
+	time::sleep,
+};
+use tracing::{debug, error};
+use uuid::Uuid;
+
+use super::{
+	queue::{self, Entry},
+	seal, RelayError, Storage,
+};
+
+/// Operations written per message
+const OPS_PER_MESSAGE: u32 = 1000;
+/// Messages an instance lets pile up before compacting them into one
+const COMPACT_AFTER: usize = 16;
+/// How often the messages of the other instances are looked for, when we have nothing to send
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Serialize, Deserialize)]
+struct Message {
+	/// What the other instances need to know of the writer, which they may have never seen
+	instance: RelayedInstance,
+	ops: CompressedCRDTOperations,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RelayedInstance {
+	identity: RemoteIdentity,
+	node_id: Uuid,
+	node_remote_identity: RemoteIdentity,
+	metadata: HashMap<String, String>,
+}
+
+struct Relay<'a> {
+	storage: Storage,
+	key: Vec<u8>,
+	library_id: Uuid,
+	instance_uuid: Uuid,
+	sync: &'a sd_core_sync::Manager,
+	db: &'a PrismaClient,
+}
+
+// Responsible for sending our sync operations to the relay and picking up the ones of the other
+// instances, which the cloud ingester then applies
+
+#[allow(clippy::too_many_arguments)]
+pub async fn run_actor(
+	libraries: Arc<Libraries>,
+	db: Arc<PrismaClient>,
+	library_id: Uuid,
+	instance_uuid: Uuid,
+	sync: Arc<sd_core_sync::Manager>,
+	ingest_notify: Arc<Notify>,
+	node: Arc<Node>,
+	active: Arc<AtomicBool>,
+	active_notify: Arc<Notify>,
+) {
+	loop {
+		active.store(true, Ordering::Relaxed);
+		active_notify.notify_waiters();
+
+		// recreate subscription each time so that existing messages are dropped
+		let mut rx = sync.subscribe();
+
+		if let Err(e) = relay(
+			&libraries,
+			&db,
+			library_id,
+			instance_uuid,
+			&sync,
+			&ingest_notify,
+			&node,
+		)
+		.await
+		{
+			error!(?e, "Failed to relay sync operations;");
+		}
+
+		active.store(false, Ordering::Relaxed);
+		active_notify.notify_waiters();
+
+		select! {
+			() = async {
+				loop {
+					match rx.recv().await {
+						Ok(SyncMessage::Created) => break,
+						Err(RecvError::Closed) => pending().await,
+						_ => {}
+					}
+				}
+			} => sleep(Duration::from_millis(1000)).await,
+			() = sleep(POLL_INTERVAL) => {}
+		}
+	}
+}
+
+async fn relay(
+	libraries: &Libraries,
+	db: &PrismaClient,
+	library_id: Uuid,
+	instance_uuid: Uuid,
+	sync: &sd_core_sync::Manager,
+	ingest_notify: &Notify,
+	node: &Node,
+) -> Result<(), RelayError> {
+	let Some((storage, key)) = super::get(db).await? else {
+		return Ok(());
+	};
+
+	let relay = Relay {
+		storage: Storage::connect(storage).await?,
+		key,
+		library_id,
+		instance_uuid,
+		sync,
+		db,
+	};
+
+	let mut entries = relay
+		.storage
+		.list()
+		.await?
+		.iter()
+		.filter_map(|name| Entry::parse(name))
+		.collect::<Vec<_>>();
+
+	entries.extend(relay.push(&entries, node).await?);
+
+	if relay.pull(&entries, libraries).await? {
+		ingest_notify.notify_waiters();
+	}
+
+	relay.ack().await?;
+
+	relay.compact(&entries, node).await
+}
+
+impl Relay<'_> {
+	fn own_messages<'e>(&self, entries: &'e [Entry]) -> impl Iterator<Item = &'e Entry> {
+		let instance_uuid = self.instance_uuid;
+
+		entries.iter().filter(
+			move |entry| matches!(entry, Entry::Message { instance, .. } if *instance == instance_uuid),
+		)
+	}
+
+	/// Writes our operations the relay doesn't have yet, returning the new messages
+	async fn push(&self, entries: &[Entry], node: &Node) -> Result<Vec<Entry>, RelayError> {
+		let mut relayed = self
+			.own_messages(entries)
+			.filter_map(|entry| match entry {
+				Entry::Message { end, .. } => Some(*end),
+				Entry::Ack { .. } => None,
+			})
+			.max()
+			.unwrap_or_default();
+
+		let mut written = vec![];
+
+		loop {
+			let ops = self
+				.sync
+				.get_instance_ops(OPS_PER_MESSAGE, self.instance_uuid, relayed)
+				.await?;
+
+			let (Some(first), Some(last)) = (ops.first(), ops.last()) else {
+				break;
+			};
+
+			let entry = Entry::Message {
+				instance: self.instance_uuid,
+				start: first.timestamp,
+				end: last.timestamp,
+			};
+
+			relayed = last.timestamp;
+
+			self.write_message(&entry, CompressedCRDTOperations::new(ops), node)
+				.await?;
+
+			written.push(entry);
+		}
+
+		debug!("Relayed {} messages", written.len());
+
+		Ok(written)
+	}
+
+	/// Hands the operations of the other instances we didn't receive yet to the cloud ingester,
+	/// returning whether there were any
+	async fn pull(&self, entries: &[Entry], libraries: &Libraries) -> Result<bool, RelayError> {
+		let mut messages = entries
+			.iter()
+			.filter_map(|entry| match entry {
+				Entry::Message {
+					instance,
+					start,
+					end,
+				} if *instance != self.instance_uuid => Some((*instance, *start, *end, entry)),
+				_ => None,
+			})
+			.collect::<Vec<_>>();
+
+		messages.sort_by_key(|(instance, start, ..)| (*instance, *start));
+
+		let mut watermarks = HashMap::new();
+		let mut pulled = false;
+
+		for (instance, _, end, entry) in messages {
+			let watermark = match watermarks.get(&instance) {
+				Some(watermark) => *watermark,
+				None => self.received(instance).await?,
+			};
+
+			if end <= watermark {
+				watermarks.insert(instance, watermark);
+				continue;
+			}
+
+			let name = entry.name();
+
+			let Message {
+				instance: relayed_instance,
+				ops,
+			} = self.read(&name).await?;
+
+			if !self.sync.timestamps.read().await.contains_key(&instance) {
+				receive::upsert_instance(
+					self.library_id,
+					self.db,
+					self.sync,
+					libraries,
+					instance,
+					relayed_instance.identity,
+					relayed_instance.node_id,
+					relayed_instance.node_remote_identity,
+					relayed_instance.metadata,
+				)
+				.await?;
+			}
+
+			// Compacted messages may hold operations we already received
+			let ops = ops
+				.into_ops()
+				.into_iter()
+				.filter(|op| op.instance == instance && op.timestamp > watermark)
+				.collect::<Vec<_>>();
+
+			debug!("Received {} operations of instance {instance}", ops.len());
+
+			receive::write_cloud_ops_to_db(ops, self.db).await?;
+
+			watermarks.insert(instance, end);
+			pulled = true;
+		}
+
+		Ok(pulled)
+	}
+
+	/// Newest operation of `instance` we ingested or are about to
+	async fn received(&self, instance: Uuid) -> Result<NTP64, RelayError> {
+		let ingested = self
+			.sync
+			.timestamps
+			.read()
+			.await
+			.get(&instance)
+			.copied()
+			.unwrap_or_default();
+
+		let pending = self
+			.db
+			.cloud_crdt_operation()
+			.find_first(vec![cloud_crdt_operation::instance::is(vec![
+				instance::pub_id::equals(uuid_to_bytes(instance)),
+			])])
+			.order_by(cloud_crdt_operation::timestamp::order(SortOrder::Desc))
+			.exec()
+			.await?
+			.map(|op| NTP64(op.timestamp as u64))
+			.unwrap_or_default();
+
+		Ok(Ord::max(ingested, pending))
+	}
+
+	/// Tells the other instances what we ingested of them
+	async fn ack(&self) -> Result<(), RelayError> {
+		let name = Entry::Ack {
+			instance: self.instance_uuid,
+		}
+		.name();
+
+		let timestamps = self.sync.timestamps.read().await.clone();
+
+		self.storage
+			.write(
+				&name,
+				seal::seal(
+					&self.key,
+					&name,
+					&rmp_serde::to_vec_named(&timestamps)
+						.expect("timestamps are always serializable"),
+				),
+			)
+			.await
+	}
+
+	/// Merges our messages into one once they pile up, see [`queue::compact`]
+	async fn compact(&self, entries: &[Entry], node: &Node) -> Result<(), RelayError> {
+		let own_messages = self.own_messages(entries).collect::<Vec<_>>();
+
+		if own_messages.len() <= COMPACT_AFTER {
+			return Ok(());
+		}
+
+		let mut acks = HashMap::new();
+
+		for entry in entries {
+			if let Entry::Ack { instance } = entry {
+				if *instance != self.instance_uuid {
+					acks.insert(*instance, self.read(&entry.name()).await?);
+				}
+			}
+		}
+
+		let instances = self
+			.db
+			.instance()
+			.find_many(vec![])
+			.select(instance::select!({ pub_id }))
+			.exec()
+			.await?
+			.into_iter()
+			.filter_map(|instance| Uuid::from_slice(&instance.pub_id).ok());
+
+		let acked = queue::acked_by_all(self.instance_uuid, instances, &acks);
+
+		let mut ops = vec![];
+		let (mut start, mut end) = (NTP64(u64::MAX), NTP64(0));
+
+		for entry in &own_messages {
+			if let Entry::Message {
+				start: message_start,
+				end: message_end,
+				..
+			} = entry
+			{
+				let message: Message = self.read(&entry.name()).await?;
+
+				ops.extend(message.ops.into_ops());
+				start = Ord::min(start, *message_start);
+				end = Ord::max(end, *message_end);
+			}
+		}
+
+		let ops_count = ops.len();
+		let ops = queue::compact(ops, acked);
+
+		let compacted = Entry::Message {
+			instance: self.instance_uuid,
+			start,
+			end,
+		};
+
+		self.write_message(&compacted, CompressedCRDTOperations::new(ops), node)
+			.await?;
+
+		for entry in own_messages {
+			if *entry != compacted {
+				self.storage.remove(&entry.name()).await?;
+			}
+		}
+
+		debug!("Compacted {ops_count} relayed operations");
+
+		Ok(())
+	}
+
+	async fn write_message(
+		&self,
+		entry: &Entry,
+		ops: CompressedCRDTOperations,
+		node: &Node,
+	) -> Result<(), RelayError> {
+		let name = entry.name();
+
+		let identity = self
+			.db
+			.instance()
+			.find_unique(instance::pub_id::equals(uuid_to_bytes(self.instance_uuid)))
+			.select(instance::select!({ remote_identity }))
+			.exec()
+			.await?
+			.and_then(|instance| RemoteIdentity::from_bytes(&instance.remote_identity).ok())
+			.ok_or(RelayError::MissingInstance(self.instance_uuid))?;
+
+		let node_config = node.config.get().await;
+
+		let message = Message {
+			instance: RelayedInstance {
+				identity,
+				node_id: node_config.id,
+				node_remote_identity: node_config.identity.to_remote_identity(),
+				metadata: node.p2p.peer_metadata(),
+			},
+			ops,
+		};
+
+		self.storage
+			.write(
+				&name,
+				seal::seal(
+					&self.key,
+					&name,
+					&rmp_serde::to_vec_named(&message).expect("messages are always serializable"),
+				),
+			)
+			.await
+	}
+
+	async fn read<T: for<'de> Deserialize<'de>>(&self, name: &str) -> Result<T, RelayError> {
+		rmp_serde::from_slice(&seal::open(
+			&self.key,
+			name,
+			&self.storage.read(name).await?,
+		)?)
+		.map_err(|e| RelayError::Malformed(name.to_string(), e))
+	}
+}
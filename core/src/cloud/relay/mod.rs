@@ -0,0 +1,210 @@
+//! Relaying sync through a storage endpoint the user controls, a S3 bucket or a WebDAV folder, so
+//! instances that are never online at the same time still converge.
+//!
+//! Each instance parks its own operations on the endpoint, sealed with a key only the instances of
+//! the library have (see [`seal`]), picks up the ones of the others for the cloud ingester and
+//! acks what it ingested, which lets the queue shrink over time (see [`queue::compact`]).
+
+use crate::{
+	library::Library,
+	location::{
+		s3::{S3Error, S3Location, S3LocationConfig},
+		webdav::{WebDavError, WebDavLocation, WebDavLocationConfig},
+	},
+};
+
+use sd_prisma::prisma::PrismaClient;
+
+use prisma_client_rust::QueryError;
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use thiserror::Error;
+use uuid::Uuid;
+
+mod actor;
+pub mod queue;
+mod seal;
+
+pub use actor::run_actor;
+pub use seal::MIN_PASSPHRASE_LEN;
+
+pub const ACTOR_NAME: &str = "Sync Relay";
+
+#[derive(Error, Debug)]
+pub enum RelayError {
+	#[error("relay passphrase must be at least {MIN_PASSPHRASE_LEN} bytes long")]
+	InvalidPassphrase,
+	#[error("relay passphrase doesn't match the one of the other instances of the library")]
+	WrongPassphrase,
+	#[error(
+		"relay message '{0}' couldn't be opened, it's from another passphrase or was tampered with"
+	)]
+	Unsealable(String),
+	#[error("instance '{0}' of the library is missing")]
+	MissingInstance(Uuid),
+	#[error("malformed relay message '{0}': {1}")]
+	Malformed(String, rmp_serde::decode::Error),
+	#[error("failed to decode relay storage config: {0}")]
+	Config(rmp_serde::decode::Error),
+	#[error(transparent)]
+	S3(#[from] S3Error),
+	#[error(transparent)]
+	WebDav(#[from] WebDavError),
+	#[error("database error: {0}")]
+	Database(#[from] QueryError),
+}
+
+impl From<RelayError> for rspc::Error {
+	fn from(err: RelayError) -> Self {
+		match err {
+			RelayError::InvalidPassphrase | RelayError::WrongPassphrase => {
+				Self::with_cause(ErrorCode::BadRequest, err.to_string(), err)
+			}
+			RelayError::S3(e) => e.into(),
+			RelayError::WebDav(e) => e.into(),
+			_ => Self::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+/// Where the relay queue lives, a folder that holds nothing else
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum RelayStorage {
+	S3(S3LocationConfig),
+	WebDav(WebDavLocationConfig),
+}
+
+impl RelayStorage {
+	/// Without the WebDAV password, for showing to the user
+	#[must_use]
+	pub fn redacted(mut self) -> Self {
+		if let Self::WebDav(config) = &mut self {
+			config.password = None;
+		}
+
+		self
+	}
+}
+
+enum Storage {
+	S3(S3Location),
+	WebDav(WebDavLocation),
+}
+
+impl Storage {
+	async fn connect(config: RelayStorage) -> Result<Self, RelayError> {
+		Ok(match config {
+			RelayStorage::S3(config) => Self::S3(S3Location::connect(config).await),
+			RelayStorage::WebDav(config) => Self::WebDav(WebDavLocation::connect(config)?),
+		})
+	}
+
+	/// Names of the files in the folder
+	async fn list(&self) -> Result<Vec<String>, RelayError> {
+		Ok(match self {
+			Self::S3(location) => location
+				.list("")
+				.await?
+				.into_iter()
+				.map(|object| object.key)
+				.collect(),
+			Self::WebDav(location) => location
+				.list("")
+				.await?
+				.into_iter()
+				.filter(|entry| !entry.is_dir)
+				.map(|entry| entry.path)
+				.collect(),
+		})
+	}
+
+	async fn read(&self, name: &str) -> Result<Vec<u8>, RelayError> {
+		Ok(match self {
+			Self::S3(location) => location.read(name).await?,
+			Self::WebDav(location) => location.read(name).await?,
+		})
+	}
+
+	async fn write(&self, name: &str, contents: Vec<u8>) -> Result<(), RelayError> {
+		match self {
+			Self::S3(location) => location.write(name, contents).await?,
+			Self::WebDav(location) => location.write(name, contents).await?,
+		}
+
+		Ok(())
+	}
+
+	async fn remove(&self, name: &str) -> Result<(), RelayError> {
+		match self {
+			Self::S3(location) => location.remove(name).await?,
+			Self::WebDav(location) => location.remove(name).await?,
+		}
+
+		Ok(())
+	}
+}
+
+/// The relay of the library, if it has one
+pub async fn get(db: &PrismaClient) -> Result<Option<(RelayStorage, Vec<u8>)>, RelayError> {
+	db.sync_relay()
+		.find_first(vec![])
+		.exec()
+		.await?
+		.map(|relay| {
+			rmp_serde::from_slice(&relay.storage)
+				.map(|storage| (storage, relay.key))
+				.map_err(RelayError::Config)
+		})
+		.transpose()
+}
+
+/// Relays the library's sync through `storage`, with the key derived from `passphrase`. As every
+/// instance must use the same passphrase, it's checked against the messages already relayed.
+pub async fn set(
+	library: &Library,
+	storage: RelayStorage,
+	passphrase: &str,
+) -> Result<(), RelayError> {
+	let key = seal::derive_key(passphrase, library.id)?;
+
+	let connected = Storage::connect(storage.clone()).await?;
+
+	let relayed = connected
+		.list()
+		.await?
+		.into_iter()
+		.find(|name| queue::Entry::parse(name).is_some());
+
+	if let Some(name) = relayed {
+		seal::open(&key, &name, &connected.read(&name).await?)
+			.map_err(|_| RelayError::WrongPassphrase)?;
+	}
+
+	library
+		.db
+		._transaction()
+		.run(|db| async move {
+			db.sync_relay().delete_many(vec![]).exec().await?;
+
+			db.sync_relay()
+				.create(
+					rmp_serde::to_vec_named(&storage)
+						.expect("relay storage is always serializable"),
+					key,
+					vec![],
+				)
+				.exec()
+				.await
+		})
+		.await?;
+
+	Ok(())
+}
+
+/// Stops relaying the library's sync, leaving what was relayed on the endpoint
+pub async fn remove(db: &PrismaClient) -> Result<(), RelayError> {
+	db.sync_relay().delete_many(vec![]).exec().await?;
+
+	Ok(())
+}
@@ -0,0 +1,243 @@
+//! Layout of the relay queue, a flat folder where each instance writes its own messages and acks.
+//!
+//! Messages are named after the instance that wrote them and the timestamps of the first and last
+//! operations they ever held, so instances know which ones they're missing from the listing alone.
+//! An ack holds the timestamps an instance ingested of every other instance, which tells the
+//! writer of the messages when every instance is past a tombstone, see [`compact`].
+
+use sd_sync::{CRDTOperation, CRDTOperationData, NTP64};
+
+use std::collections::HashMap;
+
+use uuid::Uuid;
+
+const MESSAGE_EXTENSION: &str = "msg";
+const ACK_EXTENSION: &str = "ack";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Entry {
+	Message {
+		instance: Uuid,
+		start: NTP64,
+		end: NTP64,
+	},
+	Ack {
+		instance: Uuid,
+	},
+}
+
+impl Entry {
+	pub fn name(&self) -> String {
+		match self {
+			Self::Message {
+				instance,
+				start,
+				end,
+			} => format!(
+				"{instance}.{}-{}.{MESSAGE_EXTENSION}",
+				start.as_u64(),
+				end.as_u64()
+			),
+			Self::Ack { instance } => format!("{instance}.{ACK_EXTENSION}"),
+		}
+	}
+
+	/// Files that aren't ours, like the ones some servers leave around, are skipped
+	pub fn parse(name: &str) -> Option<Self> {
+		let (instance, rest) = name.split_once('.')?;
+		let instance = Uuid::parse_str(instance).ok()?;
+
+		if rest == ACK_EXTENSION {
+			return Some(Self::Ack { instance });
+		}
+
+		let (start, end) = rest
+			.strip_suffix(MESSAGE_EXTENSION)?
+			.strip_suffix('.')?
+			.split_once('-')?;
+
+		Some(Self::Message {
+			instance,
+			start: NTP64(start.parse().ok()?),
+			end: NTP64(end.parse().ok()?),
+		})
+	}
+}
+
+/// The newest timestamp of `instance` every other instance of the library acked, up to which its
+/// tombstones can go. Nothing can go while an instance didn't ack yet.
+pub fn acked_by_all(
+	instance: Uuid,
+	others: impl IntoIterator<Item = Uuid>,
+	acks: &HashMap<Uuid, HashMap<Uuid, NTP64>>,
+) -> NTP64 {
+	others
+		.into_iter()
+		.filter(|other| *other != instance)
+		.map(|other| {
+			acks.get(&other)
+				.and_then(|ack| ack.get(&instance))
+				.copied()
+				.unwrap_or_default()
+		})
+		.min()
+		.unwrap_or_default()
+}
+
+/// Drops the operations of an instance that no longer change how its records end up: the ones
+/// before the deletion of their record and the updates overwritten by a newer one to the same
+/// field. Deletions themselves, the tombstones, are dropped once every instance acked them, as
+/// they're kept only for the instances that still have the record.
+///
+/// Instances that join the library after a tombstone went get the deletion through pairing, like
+/// the rest of the library, not through the relay.
+pub fn compact(mut ops: Vec<CRDTOperation>, acked: NTP64) -> Vec<CRDTOperation> {
+	ops.sort_by_key(|op| op.timestamp);
+
+	let record = |op: &CRDTOperation| {
+		(
+			op.model,
+			rmp_serde::to_vec(&op.record_id).expect("record ids are always serializable"),
+		)
+	};
+
+	let mut last_deletes = HashMap::new();
+	let mut last_updates = HashMap::new();
+
+	for op in &ops {
+		match &op.data {
+			CRDTOperationData::Delete => {
+				last_deletes.insert(record(op), op.timestamp);
+			}
+			CRDTOperationData::Update { field, .. } => {
+				last_updates.insert((record(op), field.clone()), op.timestamp);
+			}
+			CRDTOperationData::Create(_) => {}
+		}
+	}
+
+	ops.into_iter()
+		.filter(|op| {
+			let record = record(op);
+
+			if last_deletes
+				.get(&record)
+				.map_or(false, |deleted_at| *deleted_at > op.timestamp)
+			{
+				return false;
+			}
+
+			match &op.data {
+				CRDTOperationData::Delete => op.timestamp > acked,
+				CRDTOperationData::Update { field, .. } => {
+					last_updates.get(&(record, field.clone())) == Some(&op.timestamp)
+				}
+				CRDTOperationData::Create(_) => true,
+			}
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn op(timestamp: u64, record: &str, data: CRDTOperationData) -> CRDTOperation {
+		CRDTOperation {
+			instance: Uuid::nil(),
+			timestamp: NTP64(timestamp),
+			model: 5,
+			record_id: rmpv::Value::from(record),
+			data,
+		}
+	}
+
+	fn update(field: &str, value: &str) -> CRDTOperationData {
+		CRDTOperationData::Update {
+			field: field.to_string(),
+			value: rmpv::Value::from(value),
+		}
+	}
+
+	fn timestamps(ops: &[CRDTOperation]) -> Vec<u64> {
+		ops.iter().map(|op| op.timestamp.as_u64()).collect()
+	}
+
+	#[test]
+	fn names_round_trip() {
+		let instance = Uuid::new_v4();
+
+		for entry in [
+			Entry::Message {
+				instance,
+				start: NTP64(10),
+				end: NTP64(20),
+			},
+			Entry::Ack { instance },
+		] {
+			assert_eq!(Entry::parse(&entry.name()), Some(entry));
+		}
+
+		assert_eq!(Entry::parse(".DS_Store"), None);
+		assert_eq!(Entry::parse(&format!("{instance}.10-20.msg.sdpart")), None);
+	}
+
+	#[test]
+	fn overwritten_updates_go() {
+		let ops = compact(
+			vec![
+				op(3, "a", update("name", "Trip")),
+				op(1, "a", CRDTOperationData::create()),
+				op(2, "a", update("name", "Holiday")),
+				op(4, "a", update("color", "red")),
+				op(5, "b", update("name", "Work")),
+			],
+			NTP64(0),
+		);
+
+		assert_eq!(timestamps(&ops), vec![1, 3, 4, 5]);
+	}
+
+	#[test]
+	fn deleted_records_keep_only_their_tombstone_until_acked() {
+		let ops = vec![
+			op(1, "a", CRDTOperationData::create()),
+			op(2, "a", update("name", "Trip")),
+			op(3, "a", CRDTOperationData::Delete),
+			op(4, "b", CRDTOperationData::create()),
+		];
+
+		assert_eq!(timestamps(&compact(ops.clone(), NTP64(2))), vec![3, 4]);
+		assert_eq!(timestamps(&compact(ops, NTP64(3))), vec![4]);
+	}
+
+	#[test]
+	fn recreated_records_keep_what_came_after() {
+		let ops = compact(
+			vec![
+				op(1, "a", CRDTOperationData::create()),
+				op(2, "a", CRDTOperationData::Delete),
+				op(3, "a", CRDTOperationData::create()),
+			],
+			NTP64(0),
+		);
+
+		assert_eq!(timestamps(&ops), vec![2, 3]);
+	}
+
+	#[test]
+	fn tombstones_wait_for_every_instance() {
+		let (instance, acked, lagging) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+
+		let mut acks = HashMap::from([(acked, HashMap::from([(instance, NTP64(10))]))]);
+
+		assert_eq!(acked_by_all(instance, [acked, lagging], &acks), NTP64(0));
+
+		acks.insert(lagging, HashMap::from([(instance, NTP64(5))]));
+
+		assert_eq!(
+			acked_by_all(instance, [instance, acked, lagging], &acks),
+			NTP64(5)
+		);
+	}
+}
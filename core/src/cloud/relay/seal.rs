@@ -0,0 +1,105 @@
+//! Messages are sealed with XChaCha20-Poly1305 before they leave the instance, with a key derived
+//! from a passphrase the user sets on every instance, so the storage endpoint only ever sees
+//! ciphertext. Their name is authenticated along, so one can't be passed off as another.
+
+use argon2::Argon2;
+use chacha20poly1305::{
+	aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+	XChaCha20Poly1305, XNonce,
+};
+use uuid::Uuid;
+
+use super::RelayError;
+
+const NONCE_LEN: usize = 24;
+pub const KEY_LEN: usize = 32;
+
+pub const MIN_PASSPHRASE_LEN: usize = 8;
+
+/// Every instance of the library derives the same key from the same passphrase, salted by the
+/// library so the key of one library says nothing of another's
+pub fn derive_key(passphrase: &str, library_id: Uuid) -> Result<Vec<u8>, RelayError> {
+	if passphrase.len() < MIN_PASSPHRASE_LEN {
+		return Err(RelayError::InvalidPassphrase);
+	}
+
+	let mut key = vec![0; KEY_LEN];
+
+	Argon2::default()
+		.hash_password_into(passphrase.as_bytes(), library_id.as_bytes(), &mut key)
+		.map_err(|_| RelayError::InvalidPassphrase)?;
+
+	Ok(key)
+}
+
+pub fn seal(key: &[u8], name: &str, contents: &[u8]) -> Vec<u8> {
+	let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+	let ciphertext = XChaCha20Poly1305::new(key.into())
+		.encrypt(
+			&nonce,
+			Payload {
+				msg: contents,
+				aad: name.as_bytes(),
+			},
+		)
+		.expect("encryption only fails on messages larger than we ever write");
+
+	nonce.into_iter().chain(ciphertext).collect()
+}
+
+/// Fails if `sealed` wasn't sealed under `name` with `key`, be it a different passphrase or a
+/// message tampered with
+pub fn open(key: &[u8], name: &str, sealed: &[u8]) -> Result<Vec<u8>, RelayError> {
+	if sealed.len() < NONCE_LEN {
+		return Err(RelayError::Unsealable(name.to_string()));
+	}
+
+	let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+
+	XChaCha20Poly1305::new(key.into())
+		.decrypt(
+			XNonce::from_slice(nonce),
+			Payload {
+				msg: ciphertext,
+				aad: name.as_bytes(),
+			},
+		)
+		.map_err(|_| RelayError::Unsealable(name.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn only_the_same_passphrase_and_name_open() {
+		let library_id = Uuid::new_v4();
+		let key = derive_key("correct horse battery", library_id).expect("valid passphrase");
+
+		let sealed = seal(&key, "a.msg", b"operations");
+
+		assert_eq!(
+			open(&key, "a.msg", &sealed).expect("same key and name"),
+			b"operations"
+		);
+		assert!(open(&key, "b.msg", &sealed).is_err());
+		assert!(open(
+			&derive_key("wrong horse battery", library_id).expect("valid passphrase"),
+			"a.msg",
+			&sealed
+		)
+		.is_err());
+		assert!(open(
+			&derive_key("correct horse battery", Uuid::new_v4()).expect("valid passphrase"),
+			"a.msg",
+			&sealed
+		)
+		.is_err());
+	}
+
+	#[test]
+	fn short_passphrases_are_refused() {
+		assert!(derive_key("short", Uuid::new_v4()).is_err());
+	}
+}
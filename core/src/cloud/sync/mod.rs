@@ -6,17 +6,20 @@ use std::sync::{
 use tokio::sync::Notify;
 use uuid::Uuid;
 
-use crate::Node;
+use crate::{cloud::relay, Node};
 
 pub mod ingest;
 pub mod receive;
 pub mod send;
 
+pub const INGEST_ACTOR_NAME: &str = "Cloud Sync Ingest";
+
 #[derive(Default)]
 pub struct State {
 	pub send_active: Arc<AtomicBool>,
 	pub receive_active: Arc<AtomicBool>,
 	pub ingest_active: Arc<AtomicBool>,
+	pub relay_active: Arc<AtomicBool>,
 	pub notifier: Arc<Notify>,
 }
 
@@ -32,6 +35,7 @@ pub async fn declare_actors(
 	let state = State::default();
 
 	let autorun = node.cloud_sync_flag.load(atomic::Ordering::Relaxed);
+	let relayed = matches!(relay::get(&db).await, Ok(Some(_)));
 
 	actors
 		.declare(
@@ -48,6 +52,35 @@ pub async fn declare_actors(
 		)
 		.await;
 
+	actors
+		.declare(
+			relay::ACTOR_NAME,
+			{
+				let sync = sync.clone();
+				let node = node.clone();
+				let db = db.clone();
+				let ingest_notify = ingest_notify.clone();
+				let active_notifier = state.notifier.clone();
+				let active = state.relay_active.clone();
+
+				move || {
+					relay::run_actor(
+						node.libraries.clone(),
+						db.clone(),
+						library_id,
+						instance_uuid,
+						sync,
+						ingest_notify,
+						node,
+						active,
+						active_notifier,
+					)
+				}
+			},
+			relayed,
+		)
+		.await;
+
 	actors
 		.declare(
 			"Cloud Sync Receiver",
@@ -76,16 +109,17 @@ pub async fn declare_actors(
 		)
 		.await;
 
+	// Also applies what the relay receives
 	actors
 		.declare(
-			"Cloud Sync Ingest",
+			INGEST_ACTOR_NAME,
 			{
 				let active = state.ingest_active.clone();
 				let active_notifier = state.notifier.clone();
 
 				move || ingest::run_actor(sync.clone(), ingest_notify, active, active_notifier)
 			},
-			autorun,
+			autorun || relayed,
 		)
 		.await;
 
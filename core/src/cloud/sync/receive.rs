@@ -219,7 +219,7 @@ pub async fn run_actor(
 	}
 }
 
-async fn write_cloud_ops_to_db(
+pub(crate) async fn write_cloud_ops_to_db(
 	ops: Vec<CRDTOperation>,
 	db: &PrismaClient,
 ) -> Result<(), prisma_client_rust::QueryError> {
@@ -0,0 +1,275 @@
+use crate::{
+	api::{utils::InvalidateOperationEvent, CoreEvent},
+	library::Library,
+	old_job::{record_job_system_run, JobManagerError, JobProgressEvent, JobReport},
+	sync::Manager as SyncManager,
+	Node,
+};
+
+use sd_core_heavy_lifting::{
+	crypto::KeyManager, file_identifier::PriorityLane, job_system::report::Report,
+	utils::io_throttle::IoThrottle, IntoJob, Job, JobChain, JobId, JobName, JobProgressMetrics,
+	OuterContext, ProgressUpdate, SerializableJob, UpdateEvent,
+};
+
+use sd_core_prisma_helpers::job_without_data;
+
+use sd_prisma::prisma::{job, location, PrismaClient};
+
+use std::{
+	path::Path,
+	sync::{Arc, Mutex, PoisonError},
+};
+
+use chrono::{DateTime, Utc};
+use tracing::error;
+use uuid::Uuid;
+
+/// What the jobs of the job system get from the node, one for each library. Jobs get their own
+/// copy when they start, which their progress is reported with.
+#[derive(Clone)]
+pub struct NodeContext {
+	pub node: Arc<Node>,
+	pub library: Arc<Library>,
+	job: Option<Arc<JobProgress>>,
+}
+
+struct JobProgress {
+	id: JobId,
+	started_at: DateTime<Utc>,
+	state: Mutex<JobProgressState>,
+}
+
+#[derive(Default)]
+struct JobProgressState {
+	task_count: u64,
+	completed_task_count: u64,
+	phase: String,
+	message: String,
+	metrics: Option<JobProgressMetrics>,
+}
+
+impl NodeContext {
+	pub fn new(node: Arc<Node>, library: Arc<Library>) -> Self {
+		Self {
+			node,
+			library,
+			job: None,
+		}
+	}
+
+	/// Dispatches `job` on the job system of the node, refusing it if the library is read-only
+	pub async fn dispatch<J: Job + SerializableJob<Self>>(
+		node: &Arc<Node>,
+		library: &Arc<Library>,
+		job: impl IntoJob<J, Self> + Send,
+		location_id: location::id::Type,
+	) -> Result<JobId, rspc::Error> {
+		if library.is_read_only().await {
+			return Err(JobManagerError::ReadOnlyLibrary(library.id).into());
+		}
+
+		node.job_system
+			.dispatch(
+				job,
+				location_id,
+				Self::new(Arc::clone(node), Arc::clone(library)),
+			)
+			.await
+			.map_err(Into::into)
+	}
+
+	/// Dispatches the stages of `chain` on the job system of the node, refusing them if the library
+	/// is read-only, see [`NodeContext::dispatch`]
+	pub async fn dispatch_chain(
+		node: &Arc<Node>,
+		library: &Arc<Library>,
+		chain: JobChain<Self>,
+		location_id: location::id::Type,
+	) -> Result<JobId, rspc::Error> {
+		if library.is_read_only().await {
+			return Err(JobManagerError::ReadOnlyLibrary(library.id).into());
+		}
+
+		node.job_system
+			.dispatch_chain(
+				chain,
+				location_id,
+				Self::new(Arc::clone(node), Arc::clone(library)),
+			)
+			.await
+			.map_err(Into::into)
+	}
+
+	/// Same as [`NodeContext::dispatch`], waiting for the job to finish to return its report, if
+	/// it was stored
+	pub async fn dispatch_and_wait<J: Job + SerializableJob<Self>>(
+		node: &Arc<Node>,
+		library: &Arc<Library>,
+		job: impl IntoJob<J, Self> + Send,
+		location_id: location::id::Type,
+	) -> Result<Option<JobReport>, rspc::Error> {
+		let job_id = Self::dispatch(node, library, job, location_id).await?;
+
+		node.job_system.wait(job_id).await;
+
+		Ok(library
+			.db
+			.job()
+			.find_unique(job::id::equals(job_id.as_bytes().to_vec()))
+			.select(job_without_data::select())
+			.exec()
+			.await?
+			.and_then(|job| JobReport::try_from(job).ok()))
+	}
+}
+
+impl OuterContext for NodeContext {
+	fn id(&self) -> Uuid {
+		self.library.id
+	}
+
+	fn db(&self) -> &Arc<PrismaClient> {
+		&self.library.db
+	}
+
+	fn sync(&self) -> &Arc<SyncManager> {
+		&self.library.sync
+	}
+
+	fn invalidate_query(&self, query: &'static str) {
+		invalidate_query(&self.library, query);
+	}
+
+	fn query_invalidator(&self) -> impl Fn(&'static str) + Send + Sync {
+		let library = Arc::clone(&self.library);
+		move |query| invalidate_query(&library, query)
+	}
+
+	fn progress(&self, updates: Vec<ProgressUpdate>) {
+		// Only jobs report progress, the shallow runs sharing this context have nowhere to show it
+		let Some(JobProgress {
+			id,
+			started_at,
+			state,
+		}) = self.job.as_deref()
+		else {
+			return;
+		};
+
+		let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+
+		for update in updates {
+			match update {
+				ProgressUpdate::TaskCount(task_count) => state.task_count = task_count,
+				ProgressUpdate::CompletedTaskCount(completed_task_count) => {
+					state.completed_task_count = completed_task_count;
+				}
+				ProgressUpdate::Message(message) => state.message = message,
+				ProgressUpdate::Phase(phase) => state.phase = phase,
+				ProgressUpdate::Metrics(metrics) => state.metrics = Some(metrics),
+			}
+		}
+
+		let estimated_completion = state
+			.metrics
+			.as_ref()
+			.and_then(|metrics| metrics.estimated_completion)
+			.unwrap_or_else(|| {
+				// Same estimate as the old job system, for jobs without metrics
+				let now = Utc::now();
+				let remaining_task_count =
+					state.task_count.saturating_sub(state.completed_task_count);
+
+				i32::try_from(state.completed_task_count + 1)
+					.ok()
+					.zip(i32::try_from(remaining_task_count).ok())
+					.and_then(|(done, remaining)| {
+						now.checked_add_signed((now - *started_at) / done * remaining)
+					})
+					.unwrap_or(now)
+			});
+
+		self.library.emit(CoreEvent::JobProgress(JobProgressEvent {
+			id: *id,
+			library_id: self.library.id,
+			task_count: i32::try_from(state.task_count).unwrap_or(i32::MAX),
+			completed_task_count: i32::try_from(state.completed_task_count).unwrap_or(i32::MAX),
+			phase: state.phase.clone(),
+			message: state.message.clone(),
+			estimated_completion,
+			metrics: state.metrics.clone(),
+		}));
+	}
+
+	fn report_update(&self, update: UpdateEvent) {
+		match update {
+			UpdateEvent::NewThumbnailEvent { thumb_key } => {
+				self.node.emit(CoreEvent::NewThumbnail {
+					thumb_key: vec![
+						thumb_key.base_directory_str,
+						thumb_key.shard_hex,
+						thumb_key.cas_id,
+					],
+				});
+			}
+			UpdateEvent::NewIdentifiedObjects { file_path_ids } => {
+				self.node
+					.emit(CoreEvent::NewIdentifiedObjects { file_path_ids });
+			}
+			UpdateEvent::NewCrossLocationLinks { .. } => {
+				invalidate_query(&self.library, "search.paths");
+			}
+		}
+	}
+
+	fn get_data_directory(&self) -> &Path {
+		&self.node.data_dir
+	}
+
+	fn key_manager(&self) -> &Arc<KeyManager> {
+		&self.library.key_manager
+	}
+
+	async fn deep_hash_threshold(&self) -> Option<u64> {
+		self.library.config().await.deep_hash_threshold
+	}
+
+	fn io_throttle(&self, job_name: JobName) -> IoThrottle {
+		self.node.io_throttles.get(job_name)
+	}
+
+	fn for_job(&self, id: JobId) -> Self {
+		Self {
+			job: Some(Arc::new(JobProgress {
+				id,
+				started_at: Utc::now(),
+				state: Mutex::default(),
+			})),
+			..self.clone()
+		}
+	}
+
+	fn register_priority_lane(&self, location_id: location::id::Type, lane: PriorityLane) {
+		self.library.register_priority_lane(location_id, lane);
+	}
+
+	async fn job_finished(&self, report: &Report, location_id: location::id::Type) {
+		if let Err(e) = record_job_system_run(&self.library.db, report, location_id).await {
+			error!(
+				"Failed to record Job<id='{}', name='{}'> in history: {e:#?}",
+				report.id, report.name
+			);
+		}
+
+		invalidate_query(&self.library, "jobs.history");
+	}
+}
+
+// Queries invalidated by the job system aren't known at compile time, so they can't go through
+// `invalidate_query!`
+fn invalidate_query(library: &Library, query: &'static str) {
+	library.emit(CoreEvent::InvalidateOperation(
+		InvalidateOperationEvent::dangerously_create(query, serde_json::Value::Null, None),
+	));
+}
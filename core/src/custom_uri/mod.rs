@@ -1,7 +1,7 @@
 use crate::{
 	api::{utils::InvalidateOperationEvent, CoreEvent},
 	library::Library,
-	object::media::old_thumbnail::WEBP_EXTENSION,
+	object::media::old_thumbnail::{touch_thumbnail, WEBP_EXTENSION},
 	p2p::operations::{self, request_file},
 	util::InfallibleResponse,
 	Node,
@@ -246,6 +246,10 @@ pub fn base_router() -> Router<LocalState> {
 							})
 							.body(body::boxed(Full::from("")))
 					})?;
+
+					// Keeps it out of the thumbnails cache eviction for a while
+					tokio::spawn(touch_thumbnail(path));
+
 					let metadata = file.metadata().await;
 					serve_file(
 						file,
@@ -430,9 +434,24 @@ pub fn router(node: Arc<Node>) -> Router<()> {
 						}
 					};
 
-					*request.uri_mut() = format!("/{rest}")
-						.parse()
-						.expect("url was validated by Axum");
+					// Queries carry their input in the query string, but our auth token must stay here
+					let query = request
+						.uri()
+						.query()
+						.unwrap_or_default()
+						.split('&')
+						.filter(|pair| !pair.is_empty() && !pair.starts_with("token="))
+						.collect::<Vec<_>>()
+						.join("&");
+
+					*request.uri_mut() = if query.is_empty() {
+						format!("/{rest}")
+					} else {
+						format!("/{rest}?{query}")
+					}
+					.parse()
+					.expect("url was validated by Axum");
+					request.headers_mut().remove(header::AUTHORIZATION);
 
 					request_to_remote_node(state.node.p2p.p2p.clone(), identity, request).await
 				},
@@ -526,6 +545,8 @@ async fn infer_the_mime_type(
 		"avci" => "image/avci",
 		// AVC in HEIF images sequence (animated)
 		"avcs" => "image/avcs",
+		// JPEG XL images
+		"jxl" => "image/jxl",
 		_ => "text/plain",
 	};
 
@@ -3,24 +3,31 @@
 
 use crate::{
 	api::{CoreEvent, Router},
+	context::NodeContext,
 	location::LocationManagerError,
 	object::media::old_thumbnail::old_actor::OldThumbnailer,
+	util::JobTraceLayer,
 };
 
 #[cfg(feature = "ai")]
 use sd_ai::old_image_labeler::{DownloadModelError, OldImageLabeler, YoloV8};
+use sd_core_heavy_lifting::{JobSystem, JobSystemError};
+use sd_task_system::TaskSystem;
 use sd_utils::error::FileIOError;
 
 use api::notifications::{Notification, NotificationData, NotificationId};
 use chrono::{DateTime, Utc};
-use node::config;
+use futures::StreamExt;
+use node::{bandwidth::Bandwidth, config, io_throttle::IoThrottles, power};
 use notifications::Notifications;
 use reqwest::{RequestBuilder, Response};
 
 use std::{
 	fmt,
 	path::{Path, PathBuf},
+	pin::pin,
 	sync::{atomic::AtomicBool, Arc},
+	time::Duration,
 };
 
 use thiserror::Error;
@@ -34,6 +41,7 @@ use tracing_subscriber::{filter::FromEnvError, prelude::*, EnvFilter};
 
 pub mod api;
 mod cloud;
+mod context;
 #[cfg(feature = "crypto")]
 pub(crate) mod crypto;
 pub mod custom_uri;
@@ -56,6 +64,10 @@ use object::media::old_thumbnail::get_ephemeral_thumbnail_path;
 
 pub(crate) use sd_core_sync as sync;
 
+/// How long running tasks have to suspend on shutdown, so closing the app doesn't hang on a task
+/// stuck in a slow read, e.g. on a network share
+const TASKS_SUSPEND_TIMEOUT: Duration = Duration::from_secs(5);
+
 /// Represents a single running instance of the Spacedrive core.
 /// Holds references to all the services that make up the Spacedrive core.
 pub struct Node {
@@ -63,8 +75,13 @@ pub struct Node {
 	pub config: Arc<config::Manager>,
 	pub libraries: Arc<library::Libraries>,
 	pub old_jobs: Arc<old_job::OldJobs>,
+	pub task_system: TaskSystem<sd_core_heavy_lifting::Error>,
+	pub job_system: JobSystem<NodeContext>,
 	pub locations: location::Locations,
 	pub p2p: Arc<p2p::P2PManager>,
+	pub bandwidth: Arc<Bandwidth>,
+	pub io_throttles: IoThrottles,
+	pub device_health: volume::health::DeviceHealthHistory,
 	pub event_bus: (broadcast::Sender<CoreEvent>, broadcast::Receiver<CoreEvent>),
 	pub notifications: Notifications,
 	pub thumbnailer: OldThumbnailer,
@@ -117,17 +134,27 @@ impl Node {
 
 		let (locations, locations_actor) = location::Locations::new();
 		let (old_jobs, jobs_actor) = old_job::OldJobs::new();
+		let task_system = TaskSystem::new();
+		let job_system = JobSystem::new(task_system.get_dispatcher(), data_dir);
 		let libraries = library::Libraries::new(data_dir.join("libraries")).await?;
 
-		let (p2p, start_p2p) = p2p::P2PManager::new(config.clone(), libraries.clone())
-			.await
-			.map_err(NodeError::P2PManager)?;
+		let bandwidth = Arc::new(Bandwidth::new(config.preferences_watcher()));
+
+		let (p2p, start_p2p) =
+			p2p::P2PManager::new(config.clone(), libraries.clone(), bandwidth.clone())
+				.await
+				.map_err(NodeError::P2PManager)?;
 		let node = Arc::new(Node {
 			data_dir: data_dir.to_path_buf(),
 			old_jobs,
+			task_system,
+			job_system,
 			locations,
 			notifications: notifications::Notifications::new(),
 			p2p,
+			bandwidth,
+			io_throttles: IoThrottles::new(config.preferences_watcher()),
+			device_health: volume::health::DeviceHealthHistory::load(data_dir).await,
 			thumbnailer: OldThumbnailer::new(
 				data_dir,
 				libraries.clone(),
@@ -170,6 +197,32 @@ impl Node {
 		locations_actor.start(node.clone());
 		node.libraries.init(&node).await?;
 		jobs_actor.start(node.clone());
+		node.task_system
+			.set_resource_profile(node.config.get().await.preferences.resource_profile);
+		node.task_system.follow_power_source(power::power_source());
+		node.job_system
+			.init(
+				&node
+					.libraries
+					.get_all()
+					.await
+					.into_iter()
+					.map(|library| (library.id, NodeContext::new(node.clone(), library)))
+					.collect(),
+			)
+			.await?;
+		tokio::spawn({
+			let job_outputs = node.job_system.receive_job_outputs();
+			async move {
+				let mut job_outputs = pin!(job_outputs);
+				while let Some((job_id, output)) = job_outputs.next().await {
+					if let Err(e) = output {
+						error!("Job <id='{job_id}'> failed: {e:#?}");
+					}
+				}
+			}
+		});
+		volume::health::spawn_monitor(node.clone());
 		start_p2p(
 			node.clone(),
 			axum::Router::new()
@@ -236,6 +289,7 @@ impl Node {
 					.with_writer(std::io::stdout)
 					.with_filter(EnvFilter::from_default_env()),
 			)
+			.with(JobTraceLayer.with_filter(JobTraceLayer::filter()))
 			.init();
 
 		std::panic::set_hook(Box::new(move |panic| {
@@ -257,6 +311,12 @@ impl Node {
 		info!("Spacedrive shutting down...");
 		self.thumbnailer.shutdown().await;
 		self.old_jobs.shutdown().await;
+		// Jobs are stored to be resumed once the task system gave their tasks back, tasks that
+		// don't suspend in time being aborted
+		self.task_system
+			.shutdown_with_timeout(TASKS_SUSPEND_TIMEOUT)
+			.await;
+		self.job_system.shutdown().await;
 		self.p2p.shutdown().await;
 		#[cfg(feature = "ai")]
 		if let Some(image_labeller) = &self.old_image_labeller {
@@ -362,6 +422,8 @@ pub enum NodeError {
 	FailedToInitializeLibraryManager(#[from] library::LibraryManagerError),
 	#[error("failed to initialize location manager: {0}")]
 	LocationManager(#[from] LocationManagerError),
+	#[error("failed to initialize job system: {0}")]
+	JobSystem(#[from] JobSystemError),
 	#[error("failed to initialize p2p manager: {0}")]
 	P2PManager(String),
 	#[error("invalid platform integer: {0}")]
@@ -0,0 +1,454 @@
+//! Libraries packed into a single file, to move them to another machine or keep them as a backup
+//! without copying the files of a library that may be in use.
+//!
+//! An archive starts with [`MAGIC`] and the version of its format, followed by a length prefixed
+//! JSON [`ArchiveManifest`] and a tar.gz with the config of the library, a snapshot of its
+//! database and its thumbnails.
+
+use crate::Node;
+
+use sd_utils::error::{FileIOError, NonUtf8PathError};
+
+use std::{
+	io,
+	path::{Path, PathBuf},
+	sync::Arc,
+};
+
+use chrono::{DateTime, Utc};
+use flate2::{bufread::GzDecoder, write::GzEncoder, Compression};
+use prisma_client_rust::{raw, PrismaValue, QueryError};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tar::{Archive, Builder};
+use tempfile::tempdir_in;
+use thiserror::Error;
+use tokio::{
+	fs::{self, File},
+	io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+	task::spawn_blocking,
+};
+use tracing::error;
+use uuid::Uuid;
+
+use super::{Library, LibraryManagerError};
+
+pub const MAGIC: &[u8; 5] = b"sdlib";
+
+/// Bumped when the layout of archives changes, so older versions refuse them instead of
+/// misreading them
+pub const FORMAT_VERSION: u8 = 1;
+
+/// Latest database migration this version knows about, see `build.rs`
+pub const LATEST_MIGRATION: &str = env!("SD_LATEST_MIGRATION");
+
+const MAX_MANIFEST_LEN: u32 = 1024 * 1024;
+
+const CONFIG_ENTRY: &str = "library.sdlibrary";
+const DB_ENTRY: &str = "library.db";
+const THUMBNAILS_ENTRY: &str = "thumbnails";
+
+#[derive(Serialize, Deserialize, Type, Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveManifest {
+	pub library_id: Uuid,
+	pub library_name: String,
+	pub created_at: DateTime<Utc>,
+	/// Version of Spacedrive the archive was exported with
+	pub core_version: String,
+	/// Latest migration applied to the database in the archive. Databases migrated further than
+	/// [`LATEST_MIGRATION`] were exported by a newer version and can't be opened by this one
+	pub migration: Option<String>,
+}
+
+#[derive(Error, Debug)]
+pub enum ArchiveError {
+	#[error("not a library archive")]
+	NotAnArchive,
+	#[error(
+		"library archive format version {0} is not supported, only version {FORMAT_VERSION} is"
+	)]
+	UnsupportedFormat(u8),
+	#[error("malformed library archive manifest: {0}")]
+	MalformedManifest(#[from] serde_json::Error),
+	#[error(
+		"library archive was exported by a newer version of Spacedrive, update to import it \
+		(database migration '{0}')"
+	)]
+	NewerSchema(String),
+	#[error("library '{0}' already exists, please remove it and try again")]
+	LibraryAlreadyExists(Uuid),
+	#[error("library archive is missing its '{0}'")]
+	MissingEntry(&'static str),
+
+	#[error("database error: {0}")]
+	Database(#[from] QueryError),
+	#[error("library manager error: {0}")]
+	LibraryManager(#[from] LibraryManagerError),
+	#[error(transparent)]
+	NonUtf8Path(#[from] NonUtf8PathError),
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+}
+
+impl From<ArchiveError> for rspc::Error {
+	fn from(e: ArchiveError) -> Self {
+		match e {
+			ArchiveError::NotAnArchive
+			| ArchiveError::UnsupportedFormat(_)
+			| ArchiveError::MalformedManifest(_)
+			| ArchiveError::NewerSchema(_)
+			| ArchiveError::MissingEntry(_) => {
+				Self::with_cause(rspc::ErrorCode::BadRequest, e.to_string(), e)
+			}
+			ArchiveError::LibraryAlreadyExists(_) => {
+				Self::with_cause(rspc::ErrorCode::Conflict, e.to_string(), e)
+			}
+			_ => Self::with_cause(rspc::ErrorCode::InternalServerError, e.to_string(), e),
+		}
+	}
+}
+
+impl ArchiveManifest {
+	async fn write(&self, file: &mut (impl AsyncWrite + Unpin)) -> Result<(), io::Error> {
+		let manifest = serde_json::to_vec(self)?;
+
+		file.write_all(MAGIC).await?;
+		file.write_all(&[FORMAT_VERSION]).await?;
+		file.write_all(&(manifest.len() as u32).to_le_bytes())
+			.await?;
+		file.write_all(&manifest).await
+	}
+
+	async fn read(
+		file: &mut (impl AsyncRead + Unpin),
+		path: impl AsRef<Path>,
+	) -> Result<Self, ArchiveError> {
+		let path = path.as_ref();
+		let read_err = |e: io::Error| match e.kind() {
+			io::ErrorKind::UnexpectedEof => ArchiveError::NotAnArchive,
+			_ => FileIOError::from((path, e, "Failed to read library archive manifest")).into(),
+		};
+
+		let mut header = [0u8; MAGIC.len() + 1 + 4];
+		file.read_exact(&mut header).await.map_err(read_err)?;
+
+		let (magic, rest) = header.split_at(MAGIC.len());
+		if magic != MAGIC {
+			return Err(ArchiveError::NotAnArchive);
+		}
+
+		let (version, len) = rest.split_at(1);
+		if version[0] != FORMAT_VERSION {
+			return Err(ArchiveError::UnsupportedFormat(version[0]));
+		}
+
+		let len = u32::from_le_bytes(len.try_into().map_err(|_| ArchiveError::NotAnArchive)?);
+		if len > MAX_MANIFEST_LEN {
+			return Err(ArchiveError::NotAnArchive);
+		}
+
+		let mut manifest = vec![0; len as usize];
+		file.read_exact(&mut manifest).await.map_err(read_err)?;
+
+		Ok(serde_json::from_slice(&manifest)?)
+	}
+}
+
+/// Whether a database migrated up to `migration` can be opened by this version. Migrations are
+/// named after the time they were written, so anything after [`LATEST_MIGRATION`] is newer.
+pub fn is_known_migration(migration: &str) -> bool {
+	migration <= LATEST_MIGRATION
+}
+
+/// Packs `library` into an archive at `path`, overwriting it. A partially written archive is
+/// removed if it fails.
+pub async fn export(
+	node: &Node,
+	library: &Library,
+	path: impl AsRef<Path>,
+) -> Result<ArchiveManifest, ArchiveError> {
+	#[derive(Deserialize)]
+	struct AppliedMigration {
+		migration_name: String,
+	}
+
+	let path = path.as_ref();
+	let libraries_dir = &node.libraries.libraries_dir;
+
+	// Next to the libraries, as a database snapshot may not fit in the system's temporary directory
+	let temp_dir = tempdir_in(libraries_dir).map_err(|e| {
+		FileIOError::from((
+			libraries_dir,
+			e,
+			"Failed to create a temporary directory to export a library",
+		))
+	})?;
+
+	// Unlike copying the database file, this can't catch it halfway through a write
+	let db_snapshot_path = temp_dir.path().join(DB_ENTRY);
+	library
+		.db
+		._execute_raw(raw!(
+			"VACUUM INTO {}",
+			PrismaValue::String(
+				db_snapshot_path
+					.to_str()
+					.ok_or_else(|| NonUtf8PathError(db_snapshot_path.clone().into()))?
+					.to_string()
+			)
+		))
+		.exec()
+		.await?;
+
+	let manifest = ArchiveManifest {
+		library_id: library.id,
+		library_name: library.config().await.name.to_string(),
+		created_at: Utc::now(),
+		core_version: env!("CARGO_PKG_VERSION").to_string(),
+		migration: library
+			.db
+			._query_raw::<AppliedMigration>(raw!(
+				"SELECT migration_name FROM _prisma_migrations \
+				WHERE finished_at IS NOT NULL ORDER BY migration_name DESC LIMIT 1"
+			))
+			.exec()
+			.await?
+			.into_iter()
+			.next()
+			.map(|applied| applied.migration_name),
+	};
+
+	let config_path = libraries_dir.join(format!("{}.sdlibrary", library.id));
+	let thumbnails_dir = node.thumbnailer.indexed_thumbnails_directory(library.id);
+
+	let mut file = File::create(path)
+		.await
+		.map_err(|e| FileIOError::from((path, e, "Failed to create library archive")))?;
+
+	let res = async {
+		manifest.write(&mut file).await.map_err(|e| {
+			FileIOError::from((path, e, "Failed to write library archive manifest"))
+		})?;
+
+		let file = file.into_std().await;
+		let has_thumbnails = fs::metadata(&thumbnails_dir).await.is_ok();
+
+		spawn_blocking(move || {
+			let mut tar = Builder::new(GzEncoder::new(
+				io::BufWriter::new(file),
+				Compression::default(),
+			));
+
+			tar.append_path_with_name(&config_path, CONFIG_ENTRY)?;
+			tar.append_path_with_name(&db_snapshot_path, DB_ENTRY)?;
+			if has_thumbnails {
+				tar.append_dir_all(THUMBNAILS_ENTRY, &thumbnails_dir)?;
+			}
+
+			tar.into_inner()?
+				.finish()?
+				.into_inner()
+				.map_err(io::IntoInnerError::into_error)?
+				.sync_all()
+		})
+		.await
+		.expect("library archive writer panicked")
+		.map_err(|e| FileIOError::from((path, e, "Failed to write library archive")))?;
+
+		Ok::<_, ArchiveError>(())
+	}
+	.await;
+
+	if res.is_err() {
+		if let Err(e) = fs::remove_file(path).await {
+			error!(?e, "Failed to remove partially written library archive;");
+		}
+	}
+
+	res.map(|()| manifest)
+}
+
+/// Reads the manifest of the archive at `path`, checking it can be imported here
+pub async fn inspect(node: &Node, path: impl AsRef<Path>) -> Result<ArchiveManifest, ArchiveError> {
+	let path = path.as_ref();
+
+	let mut file = File::open(path)
+		.await
+		.map_err(|e| FileIOError::from((path, e, "Failed to open library archive")))?;
+
+	let manifest = ArchiveManifest::read(&mut file, path).await?;
+	check(node, &manifest).await?;
+
+	Ok(manifest)
+}
+
+async fn check(node: &Node, manifest: &ArchiveManifest) -> Result<(), ArchiveError> {
+	if let Some(migration) = &manifest.migration {
+		if !is_known_migration(migration) {
+			return Err(ArchiveError::NewerSchema(migration.clone()));
+		}
+	}
+
+	// TODO: Import as a copy of the library, with new ids, to allow importing a library that exists
+	if node
+		.libraries
+		.get_library(&manifest.library_id)
+		.await
+		.is_some()
+	{
+		return Err(ArchiveError::LibraryAlreadyExists(manifest.library_id));
+	}
+
+	Ok(())
+}
+
+/// Unpacks the archive at `path` as a library of this node and loads it. Databases exported by
+/// older versions are migrated as they're loaded, like those of libraries that were already here.
+pub async fn import(
+	node: &Arc<Node>,
+	path: impl AsRef<Path>,
+) -> Result<ArchiveManifest, ArchiveError> {
+	let path = path.as_ref();
+
+	let mut file = File::open(path)
+		.await
+		.map_err(|e| FileIOError::from((path, e, "Failed to open library archive")))?;
+
+	let manifest = ArchiveManifest::read(&mut file, path).await?;
+	check(node, &manifest).await?;
+
+	let libraries_dir = &node.libraries.libraries_dir;
+
+	// Unpacked next to the libraries, so they can be moved into place instead of copied
+	let temp_dir = tempdir_in(libraries_dir).map_err(|e| {
+		FileIOError::from((
+			libraries_dir,
+			e,
+			"Failed to create a temporary directory to import a library",
+		))
+	})?;
+
+	let file = file.into_std().await;
+	let unpack_dir = temp_dir.path().to_path_buf();
+	spawn_blocking(move || {
+		Archive::new(GzDecoder::new(io::BufReader::new(file))).unpack(unpack_dir)
+	})
+	.await
+	.expect("library archive reader panicked")
+	.map_err(|e| FileIOError::from((path, e, "Failed to unpack library archive")))?;
+
+	let id = manifest.library_id;
+	let config_path = libraries_dir.join(format!("{id}.sdlibrary"));
+	let db_path = libraries_dir.join(format!("{id}.db"));
+
+	for (entry, destination) in [(CONFIG_ENTRY, &config_path), (DB_ENTRY, &db_path)] {
+		let unpacked = temp_dir.path().join(entry);
+		if fs::metadata(&unpacked).await.is_err() {
+			return Err(ArchiveError::MissingEntry(entry));
+		}
+
+		move_into_place(unpacked, destination).await?;
+	}
+
+	let unpacked_thumbnails = temp_dir.path().join(THUMBNAILS_ENTRY);
+	if fs::metadata(&unpacked_thumbnails).await.is_ok() {
+		let thumbnails_dir = node.thumbnailer.indexed_thumbnails_directory(id);
+
+		// Left behind by a library with the same id that was deleted
+		if let Err(e) = fs::remove_dir_all(&thumbnails_dir).await {
+			if e.kind() != io::ErrorKind::NotFound {
+				return Err(FileIOError::from((
+					&thumbnails_dir,
+					e,
+					"Failed to remove old thumbnails of imported library",
+				))
+				.into());
+			}
+		}
+
+		if let Some(parent) = thumbnails_dir.parent() {
+			fs::create_dir_all(parent)
+				.await
+				.map_err(|e| FileIOError::from((parent, e)))?;
+		}
+
+		move_into_place(unpacked_thumbnails, &thumbnails_dir).await?;
+	}
+
+	if let Err(e) = node
+		.libraries
+		.load(id, &db_path, &config_path, None, true, node)
+		.await
+	{
+		// Otherwise the library would be loaded, and fail, every time the node starts
+		for path in [&config_path, &db_path] {
+			if let Err(e) = fs::remove_file(path).await {
+				error!(
+					?e,
+					path = %path.display(),
+					"Failed to remove a file of a library that failed to import;"
+				);
+			}
+		}
+
+		return Err(e.into());
+	}
+
+	Ok(manifest)
+}
+
+async fn move_into_place(from: PathBuf, to: &Path) -> Result<(), FileIOError> {
+	fs::rename(&from, to)
+		.await
+		.map_err(|e| FileIOError::from((to, e, "Failed to move imported library file into place")))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[tokio::test]
+	async fn manifest_round_trip() {
+		let original = ArchiveManifest {
+			library_id: Uuid::new_v4(),
+			library_name: "Test Library".to_string(),
+			created_at: Utc::now(),
+			core_version: "0.1.0".to_string(),
+			migration: Some(LATEST_MIGRATION.to_string()),
+		};
+
+		let mut buf = Vec::new();
+		original.write(&mut buf).await.unwrap();
+
+		let decoded = ArchiveManifest::read(&mut buf.as_slice(), "")
+			.await
+			.unwrap();
+		assert_eq!(original, decoded);
+	}
+
+	#[tokio::test]
+	async fn other_files_and_formats_are_refused() {
+		assert!(matches!(
+			ArchiveManifest::read(&mut b"sdbkp1 not an archive".as_slice(), "").await,
+			Err(ArchiveError::NotAnArchive)
+		));
+		assert!(matches!(
+			ArchiveManifest::read(&mut b"sdlib".as_slice(), "").await,
+			Err(ArchiveError::NotAnArchive)
+		));
+
+		let mut future = b"sdlib".to_vec();
+		future.extend([FORMAT_VERSION + 1, 0, 0, 0, 0]);
+		assert!(matches!(
+			ArchiveManifest::read(&mut future.as_slice(), "").await,
+			Err(ArchiveError::UnsupportedFormat(version)) if version == FORMAT_VERSION + 1
+		));
+	}
+
+	#[test]
+	fn newer_migrations_are_unknown() {
+		assert!(is_known_migration(LATEST_MIGRATION));
+		assert!(is_known_migration("20230101000000_init"));
+		assert!(!is_known_migration("99990101000000_from_the_future"));
+	}
+}
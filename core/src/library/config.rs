@@ -3,11 +3,17 @@ use crate::{
 	util::version_manager::{Kind, ManagedVersion, VersionManager, VersionManagerError},
 };
 
+use sd_core_heavy_lifting::file_identifier::{
+	BatchSizeBounds, CasIdAlgorithm, FileMetadataOptions,
+};
+
+use sd_file_ext::custom_kind::{CustomKind, KindRegistry};
 use sd_p2p::{Identity, RemoteIdentity};
 use sd_prisma::prisma::{file_path, indexer_rule, instance, location, node, PrismaClient};
 use sd_utils::{db::maybe_missing, error::FileIOError};
 
 use std::{
+	num::NonZeroU32,
 	path::Path,
 	sync::{atomic::AtomicBool, Arc},
 };
@@ -18,6 +24,7 @@ use prisma_client_rust::not;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Map, Value};
 use serde_repr::{Deserialize_repr, Serialize_repr};
+use serde_with::{serde_as, DisplayFromStr};
 use specta::Type;
 use thiserror::Error;
 use tokio::fs;
@@ -27,6 +34,7 @@ use uuid::Uuid;
 use super::name::LibraryName;
 
 /// LibraryConfig holds the configuration for a specific library. This is stored as a '{uuid}.sdlibrary' file.
+#[serde_as]
 #[derive(Debug, Clone, Serialize, Deserialize, Type)]
 pub struct LibraryConfig {
 	/// name is the display name of the library. This is used in the UI and is set by the user.
@@ -43,9 +51,48 @@ pub struct LibraryConfig {
 	// true = sync is enabled as either the library is new or it has been manually toggled on
 	#[serde(default)]
 	pub generate_sync_operations: Arc<AtomicBool>,
+	/// cas_id_algorithm is the hashing scheme used to generate content addressable ids for this library.
+	/// Changing it requires re-identifying every location, so existing cas_ids are comparable.
+	#[serde(default)]
+	pub cas_id_algorithm: CasIdAlgorithm,
+	/// deep_hash_threshold is the size up to which files get a cas_id of their entire content instead of a sampled one,
+	/// trading identification speed for exact deduplication. Every job hashing files reads it from here.
+	/// As a string as it may not fit in a JS number.
+	#[serde_as(as = "Option<DisplayFromStr>")]
+	#[specta(type = Option<String>)]
+	pub deep_hash_threshold: Option<u64>,
+	/// custom_kinds are object kinds defined by the user on top of the builtin ones, resolved by the file identifier.
+	#[serde(default)]
+	pub custom_kinds: Vec<CustomKind>,
+	/// access_mode is whether the library can be changed from this node, read-only libraries refuse every mutation and job.
+	#[serde(default)]
+	pub access_mode: LibraryAccessMode,
+	/// concurrency_limits are groups of locations on the same slow device, like a network drive, whose job tasks share a budget.
+	#[serde(default)]
+	pub concurrency_limits: Vec<ConcurrencyLimit>,
+	/// identifier_batch_size_bounds are how few and how many files the file identifier commits at once, adapting to the database speed in between.
+	#[serde(default)]
+	pub identifier_batch_size_bounds: BatchSizeBounds,
 	version: LibraryConfigVersion,
 }
 
+/// At most `limit` tasks of jobs on any of these locations run at the same time, so reads on a
+/// spinning or network drive don't interleave with the ones on faster storage
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct ConcurrencyLimit {
+	pub location_ids: Vec<location::id::Type>,
+	pub limit: NonZeroU32,
+}
+
+/// Read-only libraries can be shared with another node, like a family member's, without it
+/// changing them. Changes synced from other nodes still apply.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum LibraryAccessMode {
+	#[default]
+	ReadWrite,
+	ReadOnly,
+}
+
 #[derive(
 	IntEnum,
 	Debug,
@@ -98,6 +145,12 @@ impl LibraryConfig {
 			cloud_id: None,
 			// will always be `true` eventually
 			generate_sync_operations: Arc::new(AtomicBool::new(generate_sync_operations)),
+			cas_id_algorithm: CasIdAlgorithm::default(),
+			deep_hash_threshold: None,
+			custom_kinds: Vec::new(),
+			access_mode: LibraryAccessMode::default(),
+			concurrency_limits: Vec::new(),
+			identifier_batch_size_bounds: BatchSizeBounds::default(),
 		};
 
 		this.save(path).await.map(|()| this)
@@ -483,6 +536,16 @@ impl LibraryConfig {
 			.await
 			.map_err(|e| FileIOError::from((path, e)).into())
 	}
+
+	/// How files of this library are analyzed by the file identifier, whichever job or scan runs it
+	pub fn file_metadata_options(&self) -> FileMetadataOptions {
+		FileMetadataOptions {
+			cas_id_algorithm: self.cas_id_algorithm,
+			deep_hash_threshold: self.deep_hash_threshold,
+			kind_registry: KindRegistry::new(self.custom_kinds.clone()),
+			..Default::default()
+		}
+	}
 }
 
 #[derive(Error, Debug)]
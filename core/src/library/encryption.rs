@@ -0,0 +1,650 @@
+//! Encryption at rest of library databases, with SQLCipher in builds with the `encryption` feature.
+//!
+//! The passphrase of an encrypted library is kept in the OS keychain, SQLCipher derives the key of
+//! the database from it with the salt stored at the start of the file. Every connection SQLite
+//! opens to the database, including the ones Prisma opens to migrate it, is keyed by an auto
+//! extension before anything else runs on it.
+//!
+//! Libraries are encrypted in place the next time they're loaded after a passphrase is set, as the
+//! database can't be swapped from under a library in use, see [`prepare`].
+
+use crate::Node;
+
+use sd_utils::error::{FileIOError, NonUtf8PathError};
+
+use std::{collections::HashSet, io, path::Path, sync::Arc};
+
+use prisma_client_rust::QueryError;
+use serde::Serialize;
+use specta::Type;
+use thiserror::Error;
+use tokio::{fs::File, io::AsyncReadExt};
+use uuid::Uuid;
+
+use super::{Library, LibraryManagerError};
+
+/// First bytes of every SQLite database that isn't encrypted
+const PLAINTEXT_HEADER: &[u8; 16] = b"SQLite format 3\0";
+
+const MIN_PASSPHRASE_LEN: usize = 8;
+/// The most the keychain will store for us
+const MAX_PASSPHRASE_LEN: usize = 128;
+
+#[derive(Error, Debug)]
+pub enum EncryptionError {
+	#[error("this build of Spacedrive can't encrypt libraries")]
+	Unsupported,
+	#[error(
+		"passphrase must be between {MIN_PASSPHRASE_LEN} and {MAX_PASSPHRASE_LEN} bytes long \
+		and can't contain null characters"
+	)]
+	InvalidPassphrase,
+	#[error("library is already encrypted")]
+	AlreadyEncrypted,
+	#[error("library is encrypted and its passphrase isn't in the keychain")]
+	Locked,
+	#[error("wrong passphrase for library '{0}'")]
+	WrongPassphrase(Uuid),
+	#[error("library '{0}' is already loaded")]
+	AlreadyLoaded(Uuid),
+	#[error("library '{0}' isn't encrypted")]
+	NotEncrypted(Uuid),
+
+	#[cfg(all(
+		feature = "encryption",
+		any(target_os = "linux", target_os = "macos", target_os = "ios")
+	))]
+	#[error("keychain error: {0}")]
+	Keychain(#[from] sd_crypto::Error),
+	#[error("failed to open the database: {0}")]
+	Client(#[from] Box<prisma_client_rust::NewClientError>),
+	#[error("database error: {0}")]
+	Database(#[from] QueryError),
+	#[error("library manager error: {0}")]
+	LibraryManager(Box<LibraryManagerError>),
+	#[error(transparent)]
+	NonUtf8Path(#[from] NonUtf8PathError),
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+}
+
+impl From<EncryptionError> for rspc::Error {
+	fn from(e: EncryptionError) -> Self {
+		match e {
+			EncryptionError::Unsupported => {
+				Self::with_cause(rspc::ErrorCode::MethodNotSupported, e.to_string(), e)
+			}
+			EncryptionError::InvalidPassphrase
+			| EncryptionError::WrongPassphrase(_)
+			| EncryptionError::NotEncrypted(_) => {
+				Self::with_cause(rspc::ErrorCode::BadRequest, e.to_string(), e)
+			}
+			EncryptionError::AlreadyEncrypted | EncryptionError::AlreadyLoaded(_) => {
+				Self::with_cause(rspc::ErrorCode::Conflict, e.to_string(), e)
+			}
+			EncryptionError::Locked => {
+				Self::with_cause(rspc::ErrorCode::Unauthorized, e.to_string(), e)
+			}
+			_ => Self::with_cause(rspc::ErrorCode::InternalServerError, e.to_string(), e),
+		}
+	}
+}
+
+#[derive(Serialize, Type, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncryptionStatus {
+	Plaintext,
+	/// A passphrase was set, the database is encrypted the next time the library is loaded
+	Pending,
+	Encrypted,
+}
+
+pub fn validate_passphrase(passphrase: &str) -> Result<(), EncryptionError> {
+	if (MIN_PASSPHRASE_LEN..=MAX_PASSPHRASE_LEN).contains(&passphrase.len())
+		&& !passphrase.contains('\0')
+	{
+		Ok(())
+	} else {
+		Err(EncryptionError::InvalidPassphrase)
+	}
+}
+
+/// Whether the database at `db_path` is encrypted. Databases that don't exist yet aren't.
+pub async fn is_encrypted(db_path: impl AsRef<Path>) -> Result<bool, FileIOError> {
+	let db_path = db_path.as_ref();
+
+	let mut header = [0; PLAINTEXT_HEADER.len()];
+
+	let read = async {
+		File::open(db_path)
+			.await?
+			.read_exact(&mut header)
+			.await
+			.map(|_| ())
+	};
+
+	match read.await {
+		Ok(()) => Ok(&header != PLAINTEXT_HEADER),
+		Err(e)
+			if matches!(
+				e.kind(),
+				io::ErrorKind::NotFound | io::ErrorKind::UnexpectedEof
+			) =>
+		{
+			Ok(false)
+		}
+		Err(e) => Err(FileIOError::from((db_path, e))),
+	}
+}
+
+pub async fn status(library_id: Uuid, db_path: &Path) -> Result<EncryptionStatus, EncryptionError> {
+	Ok(if is_encrypted(db_path).await? {
+		EncryptionStatus::Encrypted
+	} else if sqlcipher::stored_passphrase(library_id)
+		.await
+		.ok()
+		.flatten()
+		.is_some()
+	{
+		EncryptionStatus::Pending
+	} else {
+		EncryptionStatus::Plaintext
+	})
+}
+
+/// Sets the passphrase of a library that isn't encrypted yet, its database is encrypted the next
+/// time it's loaded
+pub async fn encrypt(
+	library_id: Uuid,
+	db_path: &Path,
+	passphrase: &str,
+) -> Result<(), EncryptionError> {
+	validate_passphrase(passphrase)?;
+
+	if is_encrypted(db_path).await? {
+		return Err(EncryptionError::AlreadyEncrypted);
+	}
+
+	sqlcipher::store_passphrase(library_id, passphrase).await
+}
+
+/// Called before any connection to the database of a library is opened. Keys the connections
+/// to an encrypted database and encrypts the database if a passphrase was set since it was last
+/// loaded.
+pub(crate) async fn prepare(library_id: Uuid, db_path: &Path) -> Result<(), EncryptionError> {
+	// New libraries are encrypted once they're loaded again
+	if !db_path.exists() {
+		return Ok(());
+	}
+
+	let encrypted = is_encrypted(db_path).await?;
+
+	let passphrase = match sqlcipher::stored_passphrase(library_id).await {
+		Ok(Some(passphrase)) => passphrase,
+		Ok(None) if encrypted => return Err(EncryptionError::Locked),
+		Err(e) if encrypted => return Err(e),
+		// Libraries that were never encrypted load without a keychain
+		Ok(None) | Err(_) => return Ok(()),
+	};
+
+	if !encrypted {
+		sqlcipher::encrypt_in_place(db_path, &passphrase).await?;
+	}
+
+	sqlcipher::register(db_path, passphrase)
+}
+
+/// Loads a library that is locked as it was encrypted on another machine, or its passphrase was
+/// removed from the keychain. The passphrase is kept in the keychain if it opens the library.
+pub async fn unlock(
+	node: &Arc<Node>,
+	library_id: Uuid,
+	passphrase: &str,
+) -> Result<Arc<Library>, EncryptionError> {
+	validate_passphrase(passphrase)?;
+
+	if node.libraries.get_library(&library_id).await.is_some() {
+		return Err(EncryptionError::AlreadyLoaded(library_id));
+	}
+
+	let libraries_dir = &node.libraries.libraries_dir;
+	let db_path = libraries_dir.join(format!("{library_id}.db"));
+	let config_path = libraries_dir.join(format!("{library_id}.sdlibrary"));
+
+	if !is_encrypted(&db_path).await? {
+		return Err(EncryptionError::NotEncrypted(library_id));
+	}
+
+	sqlcipher::register(&db_path, passphrase.to_string())?;
+
+	if !sqlcipher::opens(&db_path).await {
+		sqlcipher::unregister(&db_path);
+		return Err(EncryptionError::WrongPassphrase(library_id));
+	}
+
+	sqlcipher::store_passphrase(library_id, passphrase).await?;
+
+	node.libraries
+		.load(library_id, &db_path, config_path, None, true, node)
+		.await
+		.map_err(|e| EncryptionError::LibraryManager(Box::new(e)))
+}
+
+/// Forgets the passphrase of a library that is being deleted
+pub(crate) async fn forget(library_id: Uuid, db_path: &Path) {
+	sqlcipher::unregister(db_path);
+	sqlcipher::forget_passphrase(library_id).await;
+}
+
+/// Libraries in `libraries_dir` that aren't loaded as their database is encrypted
+pub async fn locked_libraries(
+	libraries_dir: &Path,
+	loaded: &HashSet<Uuid>,
+) -> Result<Vec<Uuid>, FileIOError> {
+	let mut read_dir = tokio::fs::read_dir(libraries_dir)
+		.await
+		.map_err(|e| FileIOError::from((libraries_dir, e)))?;
+
+	let mut locked = vec![];
+
+	while let Some(entry) = read_dir
+		.next_entry()
+		.await
+		.map_err(|e| FileIOError::from((libraries_dir, e)))?
+	{
+		let db_path = entry.path();
+
+		let Some(library_id) = db_path
+			.extension()
+			.is_some_and(|ext| ext == "db")
+			.then(|| db_path.file_stem())
+			.flatten()
+			.and_then(|stem| stem.to_str())
+			.and_then(|stem| Uuid::parse_str(stem).ok())
+		else {
+			continue;
+		};
+
+		if !loaded.contains(&library_id) && is_encrypted(&db_path).await? {
+			locked.push(library_id);
+		}
+	}
+
+	locked.sort();
+
+	Ok(locked)
+}
+
+#[cfg(all(
+	feature = "encryption",
+	any(target_os = "linux", target_os = "macos", target_os = "ios")
+))]
+mod sqlcipher {
+	use super::EncryptionError;
+
+	use sd_crypto::{
+		keyring::{Identifier, Keyring, KeyringBackend},
+		Protected,
+	};
+	use sd_prisma::prisma::PrismaClient;
+	use sd_utils::error::{FileIOError, NonUtf8PathError};
+
+	use std::{
+		collections::HashMap,
+		ffi::{c_char, c_int, CStr, CString},
+		io,
+		path::{Path, PathBuf},
+		ptr,
+		sync::{Once, RwLock},
+	};
+
+	use libsqlite3_sys::{
+		sqlite3, sqlite3_api_routines, sqlite3_auto_extension, sqlite3_db_filename, sqlite3_exec,
+		SQLITE_OK,
+	};
+	use once_cell::sync::Lazy;
+	use prisma_client_rust::{raw, PrismaValue};
+	use tokio::{fs, task::spawn_blocking};
+	use tracing::{error, warn};
+	use uuid::Uuid;
+
+	/// Statements keying the connections to each encrypted database, by its canonical path
+	static KEYS: Lazy<RwLock<HashMap<PathBuf, CString>>> = Lazy::new(Default::default);
+
+	static AUTO_EXTENSION: Once = Once::new();
+
+	/// Where encrypted copies are written before they replace the database
+	const ENCRYPTING_EXTENSION: &str = "db.encrypting";
+
+	fn keyring() -> Result<Keyring, sd_crypto::Error> {
+		#[cfg(target_os = "linux")]
+		let backend = KeyringBackend::Linux(sd_crypto::keyring::LinuxKeyring::SecretService);
+		#[cfg(target_os = "macos")]
+		let backend = KeyringBackend::MacOS;
+		#[cfg(target_os = "ios")]
+		let backend = KeyringBackend::Ios;
+
+		Keyring::new(backend)
+	}
+
+	fn identifier(library_id: Uuid) -> Identifier {
+		Identifier::new(&library_id.to_string(), "Library passphrase", "Spacedrive")
+	}
+
+	pub(super) async fn stored_passphrase(
+		library_id: Uuid,
+	) -> Result<Option<String>, EncryptionError> {
+		spawn_blocking(move || {
+			let keyring = keyring()?;
+			let identifier = identifier(library_id);
+
+			if !keyring.contains_key(&identifier) {
+				return Ok(None);
+			}
+
+			let passphrase = keyring.get(&identifier)?;
+
+			// Only valid passphrases are ever stored
+			Ok(String::from_utf8(passphrase.expose().clone()).ok())
+		})
+		.await
+		.expect("keychain task panicked")
+	}
+
+	pub(super) async fn store_passphrase(
+		library_id: Uuid,
+		passphrase: &str,
+	) -> Result<(), EncryptionError> {
+		let passphrase = Protected::new(passphrase.as_bytes().to_vec());
+
+		spawn_blocking(move || {
+			let keyring = keyring()?;
+			let identifier = identifier(library_id);
+
+			if keyring.contains_key(&identifier) {
+				keyring.remove(&identifier)?;
+			}
+
+			keyring.insert(&identifier, passphrase).map_err(Into::into)
+		})
+		.await
+		.expect("keychain task panicked")
+	}
+
+	pub(super) async fn forget_passphrase(library_id: Uuid) {
+		let res = spawn_blocking(move || {
+			let keyring = keyring()?;
+			let identifier = identifier(library_id);
+
+			if keyring.contains_key(&identifier) {
+				keyring.remove(&identifier)?;
+			}
+
+			Ok::<_, sd_crypto::Error>(())
+		})
+		.await
+		.expect("keychain task panicked");
+
+		if let Err(e) = res {
+			warn!(?e, %library_id, "Failed to remove library passphrase from the keychain;");
+		}
+	}
+
+	/// Keys every connection opened to `db_path` from now on with `passphrase`
+	pub(super) fn register(db_path: &Path, passphrase: String) -> Result<(), EncryptionError> {
+		AUTO_EXTENSION.call_once(|| {
+			// SAFETY: `key_connection` has the signature SQLite calls auto extensions with, the
+			// bindings just erase it
+			let res = unsafe {
+				sqlite3_auto_extension(Some(std::mem::transmute::<
+					unsafe extern "C" fn(
+						*mut sqlite3,
+						*mut *mut c_char,
+						*const sqlite3_api_routines,
+					) -> c_int,
+					unsafe extern "C" fn(),
+				>(key_connection)))
+			};
+
+			if res != SQLITE_OK {
+				error!(
+					code = res,
+					"Failed to register the SQLCipher auto extension;"
+				);
+			}
+		});
+
+		let canonical =
+			std::fs::canonicalize(db_path).map_err(|e| FileIOError::from((db_path, e)))?;
+		let pragma = CString::new(key_pragma(&passphrase))
+			.map_err(|_| EncryptionError::InvalidPassphrase)?;
+
+		KEYS.write()
+			.expect("encryption keys lock poisoned")
+			.insert(canonical, pragma);
+
+		Ok(())
+	}
+
+	pub(super) fn unregister(db_path: &Path) {
+		if let Ok(db_path) = std::fs::canonicalize(db_path) {
+			KEYS.write()
+				.expect("encryption keys lock poisoned")
+				.remove(&db_path);
+		}
+	}
+
+	/// Whether the database can be read with the key it was registered with
+	pub(super) async fn opens(db_path: &Path) -> bool {
+		let Ok(db_path) = db_path_str(db_path) else {
+			return false;
+		};
+
+		let Ok(db) = PrismaClient::_builder()
+			.with_url(format!("file:{db_path}"))
+			.build()
+			.await
+		else {
+			return false;
+		};
+
+		db._query_raw::<serde_json::Value>(raw!("SELECT count(*) FROM sqlite_master"))
+			.exec()
+			.await
+			.is_ok()
+	}
+
+	/// Auto extension run by SQLite as each connection is opened, keys it if it's to an encrypted
+	/// library database
+	unsafe extern "C" fn key_connection(
+		db: *mut sqlite3,
+		_err: *mut *mut c_char,
+		_api: *const sqlite3_api_routines,
+	) -> c_int {
+		let filename = sqlite3_db_filename(db, b"main\0".as_ptr().cast());
+		if filename.is_null() {
+			return SQLITE_OK;
+		}
+
+		let Ok(filename) = CStr::from_ptr(filename).to_str() else {
+			return SQLITE_OK;
+		};
+
+		let Ok(keys) = KEYS.read() else {
+			return SQLITE_OK;
+		};
+
+		let path = Path::new(filename);
+		let pragma = keys.get(path).or_else(|| {
+			std::fs::canonicalize(path)
+				.ok()
+				.and_then(|path| keys.get(&path))
+		});
+
+		match pragma {
+			Some(pragma) => {
+				sqlite3_exec(db, pragma.as_ptr(), None, ptr::null_mut(), ptr::null_mut())
+			}
+			None => SQLITE_OK,
+		}
+	}
+
+	/// Writes an encrypted copy of the database next to it and replaces the database with it.
+	/// Nothing else may have the database open.
+	pub(super) async fn encrypt_in_place(
+		db_path: &Path,
+		passphrase: &str,
+	) -> Result<(), EncryptionError> {
+		let encrypted_path = db_path.with_extension(ENCRYPTING_EXTENSION);
+
+		// Left behind if encrypting was interrupted
+		remove_if_exists(&encrypted_path).await?;
+
+		{
+			let db = PrismaClient::_builder()
+				.with_url(format!("file:{}", db_path_str(db_path)?))
+				.build()
+				.await
+				.map_err(Box::new)?;
+
+			db._execute_raw(raw!(
+				"ATTACH DATABASE {} AS encrypted KEY {}",
+				PrismaValue::String(db_path_str(&encrypted_path)?.to_string()),
+				PrismaValue::String(passphrase.to_string())
+			))
+			.exec()
+			.await?;
+
+			db._query_raw::<serde_json::Value>(raw!("SELECT sqlcipher_export('encrypted')"))
+				.exec()
+				.await?;
+
+			db._execute_raw(raw!("DETACH DATABASE encrypted"))
+				.exec()
+				.await?;
+		}
+
+		fs::rename(&encrypted_path, db_path)
+			.await
+			.map_err(|e| FileIOError::from((db_path, e)))?;
+
+		// The journal of the plaintext database doesn't belong to the encrypted one
+		for suffix in ["-wal", "-shm", "-journal"] {
+			let mut journal_path = db_path.as_os_str().to_owned();
+			journal_path.push(suffix);
+			remove_if_exists(Path::new(&journal_path)).await?;
+		}
+
+		Ok(())
+	}
+
+	/// The statement keying a new connection with `passphrase`
+	pub(super) fn key_pragma(passphrase: &str) -> String {
+		format!("PRAGMA key = '{}';", passphrase.replace('\'', "''"))
+	}
+
+	fn db_path_str(db_path: &Path) -> Result<&str, NonUtf8PathError> {
+		db_path
+			.to_str()
+			.ok_or_else(|| NonUtf8PathError(db_path.into()))
+	}
+
+	async fn remove_if_exists(path: &Path) -> Result<(), FileIOError> {
+		match fs::remove_file(path).await {
+			Ok(()) => Ok(()),
+			Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+			Err(e) => Err(FileIOError::from((path, e))),
+		}
+	}
+}
+
+/// Builds without the `encryption` feature, or for platforms without a keychain we support, load
+/// plaintext libraries only
+#[cfg(not(all(
+	feature = "encryption",
+	any(target_os = "linux", target_os = "macos", target_os = "ios")
+)))]
+mod sqlcipher {
+	use super::EncryptionError;
+
+	use std::path::Path;
+
+	use uuid::Uuid;
+
+	pub(super) async fn stored_passphrase(
+		_library_id: Uuid,
+	) -> Result<Option<String>, EncryptionError> {
+		Err(EncryptionError::Unsupported)
+	}
+
+	pub(super) async fn store_passphrase(
+		_library_id: Uuid,
+		_passphrase: &str,
+	) -> Result<(), EncryptionError> {
+		Err(EncryptionError::Unsupported)
+	}
+
+	pub(super) async fn forget_passphrase(_library_id: Uuid) {}
+
+	pub(super) fn register(_db_path: &Path, _passphrase: String) -> Result<(), EncryptionError> {
+		Err(EncryptionError::Unsupported)
+	}
+
+	pub(super) fn unregister(_db_path: &Path) {}
+
+	pub(super) async fn opens(_db_path: &Path) -> bool {
+		false
+	}
+
+	pub(super) async fn encrypt_in_place(
+		_db_path: &Path,
+		_passphrase: &str,
+	) -> Result<(), EncryptionError> {
+		Err(EncryptionError::Unsupported)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	#[cfg(all(
+		feature = "encryption",
+		any(target_os = "linux", target_os = "macos", target_os = "ios")
+	))]
+	fn passphrases_are_quoted() {
+		assert_eq!(
+			sqlcipher::key_pragma("it's secret"),
+			"PRAGMA key = 'it''s secret';"
+		);
+	}
+
+	#[test]
+	fn passphrases_are_validated() {
+		assert!(validate_passphrase("correct horse").is_ok());
+		assert!(validate_passphrase("short").is_err());
+		assert!(validate_passphrase("null\0character").is_err());
+		assert!(validate_passphrase(&"a".repeat(MAX_PASSPHRASE_LEN + 1)).is_err());
+	}
+
+	#[tokio::test]
+	async fn missing_and_plaintext_databases_are_not_encrypted() {
+		let dir = tempfile::tempdir().expect("failed to create temp dir");
+
+		let missing = dir.path().join("missing.db");
+		assert!(!is_encrypted(&missing).await.expect("failed to read header"));
+
+		let plaintext = dir.path().join("plaintext.db");
+		std::fs::write(&plaintext, [&PLAINTEXT_HEADER[..], &[0; 84]].concat())
+			.expect("failed to write database");
+		assert!(!is_encrypted(&plaintext)
+			.await
+			.expect("failed to read header"));
+
+		let encrypted = dir.path().join("encrypted.db");
+		std::fs::write(&encrypted, [0x5a; 100]).expect("failed to write database");
+		assert!(is_encrypted(&encrypted)
+			.await
+			.expect("failed to read header"));
+	}
+}
@@ -3,6 +3,7 @@ use crate::{
 };
 
 use sd_core_file_path_helper::IsolatedFilePathData;
+use sd_core_heavy_lifting::{crypto::KeyManager, file_identifier::PriorityLane};
 use sd_core_prisma_helpers::file_path_to_full_path;
 
 use sd_p2p::Identity;
@@ -13,14 +14,15 @@ use std::{
 	collections::HashMap,
 	fmt::{Debug, Formatter},
 	path::{Path, PathBuf},
-	sync::Arc,
+	sync::{Arc, Mutex, PoisonError},
 };
 
+use async_channel as chan;
 use tokio::{fs, io, sync::broadcast, sync::RwLock};
 use tracing::warn;
 use uuid::Uuid;
 
-use super::{LibraryConfig, LibraryManagerError};
+use super::{LibraryAccessMode, LibraryConfig, LibraryManagerError};
 
 // TODO: Finish this
 // pub enum LibraryNew {
@@ -41,10 +43,9 @@ pub struct Library {
 	pub sync: Arc<sync::Manager>,
 	pub cloud: cloud::State,
 	/// key manager that provides encryption keys to functions that require them
-	// pub key_manager: Arc<KeyManager>,
+	pub key_manager: Arc<KeyManager>,
 	/// p2p identity
 	pub identity: Arc<Identity>,
-	// pub orphan_remover: OrphanRemoverActor,
 	// The UUID which matches `config.instance_id`'s primary key.
 	pub instance_uuid: Uuid,
 
@@ -56,6 +57,9 @@ pub struct Library {
 	event_bus_tx: broadcast::Sender<CoreEvent>,
 
 	pub actors: Arc<sd_actors::Actors>,
+
+	/// Lanes of the file identifiers running on each location, see [`Self::prioritize_directory`]
+	priority_lanes: Mutex<HashMap<location::id::Type, Vec<PriorityLane>>>,
 }
 
 impl Debug for Library {
@@ -91,21 +95,69 @@ impl Library {
 			sync,
 			cloud,
 			db: db.clone(),
-			// key_manager,
+			key_manager: Arc::new(KeyManager::new()),
 			identity,
-			// orphan_remover: OrphanRemoverActor::spawn(db),
 			instance_uuid,
 			do_cloud_sync,
 			env: node.env.clone(),
 			event_bus_tx: node.event_bus.0.clone(),
 			actors,
+			priority_lanes: Mutex::default(),
 		})
 	}
 
+	/// Keeps the lane of a file identifier running on `location_id`, so directories opened in the
+	/// Explorer can be identified first. Jobs register theirs again each time they resume.
+	pub fn register_priority_lane(&self, location_id: location::id::Type, lane: PriorityLane) {
+		self.priority_lanes
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.entry(location_id)
+			.or_default()
+			.push(lane);
+	}
+
+	/// Registers a lane for an identifier running outside the job system on `location_id`, the
+	/// old file identifier job or a light scan, which receives the prioritized directories itself
+	pub fn open_priority_lane(&self, location_id: location::id::Type) -> chan::Receiver<PathBuf> {
+		let (lane, prioritized_rx) = PriorityLane::channel();
+		self.register_priority_lane(location_id, lane);
+
+		prioritized_rx
+	}
+
+	/// Asks the file identifiers running on `location_id`, if any, to identify the orphans of
+	/// `directory` ahead of the remaining ones
+	pub fn prioritize_directory(
+		&self,
+		location_id: location::id::Type,
+		directory: impl Into<PathBuf>,
+	) {
+		let directory = directory.into();
+		let mut priority_lanes = self
+			.priority_lanes
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner);
+
+		if let Some(lanes) = priority_lanes.get_mut(&location_id) {
+			// Lanes of identifiers that aren't running anymore are closed
+			lanes.retain(|lane| lane.prioritize(directory.clone()));
+
+			if lanes.is_empty() {
+				priority_lanes.remove(&location_id);
+			}
+		}
+	}
+
 	pub async fn config(&self) -> LibraryConfig {
 		self.config.read().await.clone()
 	}
 
+	/// Whether changes to the library must be refused, see [`LibraryAccessMode`]
+	pub async fn is_read_only(&self) -> bool {
+		self.config.read().await.access_mode == LibraryAccessMode::ReadOnly
+	}
+
 	pub async fn update_config(
 		&self,
 		update_fn: impl FnOnce(&mut LibraryConfig),
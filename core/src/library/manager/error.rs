@@ -1,5 +1,9 @@
-use crate::{library::LibraryConfigError, location::LocationManagerError};
+use crate::{
+	library::{encryption::EncryptionError, LibraryConfigError},
+	location::LocationManagerError,
+};
 
+use sd_core_heavy_lifting::{file_identifier, JobSystemError};
 use sd_core_indexer_rules::seed::SeederError;
 
 use sd_p2p::IdentityErr;
@@ -41,11 +45,19 @@ pub enum LibraryManagerError {
 	CurrentInstanceNotFound(String),
 	#[error("missing-field: {0}")]
 	MissingField(#[from] MissingFieldError),
+	#[error("library encryption error: {0}")]
+	Encryption(#[from] EncryptionError),
 
 	#[error(transparent)]
 	FileIO(#[from] FileIOError),
 	#[error(transparent)]
 	LibraryConfig(#[from] LibraryConfigError),
+	#[error("failed to migrate the library's cas_ids: {0}")]
+	CasIdMigration(#[from] file_identifier::Error),
+	#[error("failed to dispatch the migration of the library's cas_ids: {0}")]
+	CasIdMigrationDispatch(String),
+	#[error(transparent)]
+	JobSystem(#[from] JobSystemError),
 }
 
 impl From<LibraryManagerError> for rspc::Error {
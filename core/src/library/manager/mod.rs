@@ -1,15 +1,27 @@
 use crate::{
 	api::{utils::InvalidateOperationEvent, CoreEvent},
-	cloud, invalidate_query,
-	location::metadata::{LocationMetadataError, SpacedriveLocationMetadataFile},
-	object::tag,
+	cloud,
+	context::NodeContext,
+	invalidate_query,
+	location::{
+		capacity::spawn_capacity_capture,
+		metadata::{LocationMetadataError, SpacedriveLocationMetadataFile},
+		schedule::spawn_job_schedules,
+		versioning::spawn_version_pruning,
+	},
+	object::{custom_field, tag},
 	p2p, sync,
 	util::{mpscrr, MaybeUndefined},
 	Node,
 };
 
 use futures::future::join_all;
+use sd_core_heavy_lifting::{
+	file_identifier::{BatchSizeBounds, CasIdAlgorithm, FileIdentifier},
+	job_system::utils::location_concurrency_key,
+};
 use sd_core_sync::SyncMessage;
+use sd_file_ext::custom_kind::{CustomKind, KindRegistry};
 use sd_p2p::{Identity, RemoteIdentity};
 use sd_prisma::prisma::{crdt_operation, instance, location, SortOrder};
 use sd_utils::{
@@ -20,6 +32,7 @@ use sd_utils::{
 
 use std::{
 	collections::HashMap,
+	num::NonZeroUsize,
 	path::{Path, PathBuf},
 	str::FromStr,
 	sync::{
@@ -39,7 +52,10 @@ use tokio::{
 use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
-use super::{Library, LibraryConfig, LibraryName};
+use super::{
+	encryption::{self, EncryptionError},
+	ConcurrencyLimit, Library, LibraryAccessMode, LibraryConfig, LibraryName,
+};
 
 mod error;
 
@@ -130,9 +146,21 @@ impl Libraries {
 					Err(e) => return Err(FileIOError::from((db_path, e)).into()),
 				}
 
-				let _library_arc = self
+				let _library_arc = match self
 					.load(library_id, &db_path, config_path, None, true, node)
-					.await?;
+					.await
+				{
+					Ok(library) => library,
+					Err(LibraryManagerError::Encryption(EncryptionError::Locked)) => {
+						warn!(
+							%library_id,
+							"Library is encrypted and its passphrase isn't in the keychain, \
+							it must be unlocked. Skipping...",
+						);
+						continue;
+					}
+					Err(e) => return Err(e),
+				};
 
 				// FIX-ME: Linux releases crashes with *** stack smashing detected *** if spawn_volume_watcher is enabled
 				// No idea why, but this will be irrelevant after the UDisk API is implemented, so let's leave it disabled for now
@@ -229,6 +257,7 @@ impl Libraries {
 
 		if should_seed {
 			tag::seed::new_library(&library).await?;
+			custom_field::seed::new_library(&library).await?;
 			sd_core_indexer_rules::seed::new_or_existing_library(&library.db).await?;
 			debug!("Seeded library '{id:?}'");
 		}
@@ -248,13 +277,23 @@ impl Libraries {
 			.collect()
 	}
 
+	/// Migrates the `cas_id`s of every location of the library when how they're generated
+	/// changes, dispatching a [`FileIdentifier::new_cas_id_migration`] job for each one
+	#[allow(clippy::too_many_arguments)]
 	pub(crate) async fn edit(
 		&self,
+		node: &Arc<Node>,
 		id: Uuid,
 		name: Option<LibraryName>,
 		description: MaybeUndefined<String>,
 		cloud_id: MaybeUndefined<String>,
 		enable_sync: Option<bool>,
+		cas_id_algorithm: Option<CasIdAlgorithm>,
+		deep_hash_threshold: MaybeUndefined<u64>,
+		custom_kinds: Option<Vec<CustomKind>>,
+		access_mode: Option<LibraryAccessMode>,
+		concurrency_limits: Option<Vec<ConcurrencyLimit>>,
+		identifier_batch_size_bounds: Option<BatchSizeBounds>,
 	) -> Result<(), LibraryManagerError> {
 		// check library is valid
 		let libraries = self.libraries.read().await;
@@ -264,6 +303,9 @@ impl Libraries {
 				.ok_or(LibraryManagerError::LibraryNotFound)?,
 		);
 
+		let previous_config = library.config().await;
+		let previous_cas_ids = cas_id_scheme(&previous_config);
+
 		library
 			.update_config(
 				|config| {
@@ -289,11 +331,49 @@ impl Libraries {
 							.generate_sync_operations
 							.store(value, Ordering::SeqCst),
 					}
+					if let Some(cas_id_algorithm) = cas_id_algorithm {
+						config.cas_id_algorithm = cas_id_algorithm;
+					}
+					match deep_hash_threshold {
+						MaybeUndefined::Undefined => {}
+						MaybeUndefined::Null => config.deep_hash_threshold = None,
+						MaybeUndefined::Value(threshold) => {
+							config.deep_hash_threshold = Some(threshold)
+						}
+					}
+					if let Some(custom_kinds) = custom_kinds {
+						config.custom_kinds = custom_kinds;
+					}
+					if let Some(access_mode) = access_mode {
+						config.access_mode = access_mode;
+					}
+					if let Some(concurrency_limits) = concurrency_limits {
+						config.concurrency_limits = concurrency_limits;
+					}
+					if let Some(bounds) = identifier_batch_size_bounds {
+						config.identifier_batch_size_bounds = bounds;
+					}
 				},
 				self.libraries_dir.join(format!("{id}.sdlibrary")),
 			)
 			.await?;
 
+		let config = library.config().await;
+
+		if config.concurrency_limits != previous_config.concurrency_limits {
+			apply_concurrency_limits(
+				node,
+				id,
+				&previous_config.concurrency_limits,
+				&config.concurrency_limits,
+			);
+		}
+
+		let cas_ids = cas_id_scheme(&config);
+		if cas_ids_changed(previous_cas_ids, cas_ids) {
+			migrate_cas_ids(node, &library, cas_ids.0).await?;
+		}
+
 		self.tx
 			.emit(LibraryManagerEvent::Edit(Arc::clone(&library)))
 			.await;
@@ -351,6 +431,8 @@ impl Libraries {
 		let db_path = self.libraries_dir.join(format!("{}.db", library.id));
 		let sd_lib_path = self.libraries_dir.join(format!("{}.sdlibrary", library.id));
 
+		encryption::forget(library.id, &db_path).await;
+
 		(
 			async {
 				fs::remove_file(&db_path)
@@ -440,6 +522,9 @@ impl Libraries {
 				LibraryManagerError::NonUtf8Path(NonUtf8PathError(db_path.into()))
 			})?
 		);
+
+		encryption::prepare(id, db_path).await?;
+
 		let db = Arc::new(db::load_and_migrate(&db_url).await?);
 
 		if let Some(create) = create {
@@ -550,13 +635,14 @@ impl Libraries {
 
 		let cloud = crate::cloud::start(node, &actors, id, instance_id, &sync_manager, &db).await;
 
+		apply_concurrency_limits(node, id, &[], &config.concurrency_limits);
+
 		let (tx, mut rx) = broadcast::channel(10);
 		let library = Library::new(
 			id,
 			config,
 			instance_id,
 			identity,
-			// key_manager,
 			db,
 			node,
 			sync_manager,
@@ -602,6 +688,10 @@ impl Libraries {
 			error!("Failed to resume jobs for library. {:#?}", e);
 		}
 
+		spawn_capacity_capture(node.clone(), &library);
+		spawn_version_pruning(node.clone(), &library);
+		spawn_job_schedules(node.clone(), &library);
+
 		tokio::spawn({
 			let this = self.clone();
 			let node = node.clone();
@@ -707,11 +797,18 @@ impl Libraries {
 
 									let _ = this
 										.edit(
+											&node,
 											library.id,
 											None,
 											MaybeUndefined::Undefined,
 											MaybeUndefined::Null,
 											None,
+											None,
+											MaybeUndefined::Undefined,
+											None,
+											None,
+											None,
+											None,
 										)
 										.await;
 								}
@@ -750,6 +847,69 @@ impl Libraries {
 	}
 }
 
+fn cas_id_scheme(config: &LibraryConfig) -> (CasIdAlgorithm, Option<u64>) {
+	(config.cas_id_algorithm, config.deep_hash_threshold)
+}
+
+/// Whether files get other `cas_id`s after an edit, the deep hash threshold only applies to
+/// sampled hashing
+fn cas_ids_changed(
+	(previous_algorithm, previous_threshold): (CasIdAlgorithm, Option<u64>),
+	(algorithm, threshold): (CasIdAlgorithm, Option<u64>),
+) -> bool {
+	previous_algorithm != algorithm
+		|| (algorithm == CasIdAlgorithm::Blake3Sampled && previous_threshold != threshold)
+}
+
+/// Gives each group of locations of the library its own budget on the task system, replacing the
+/// `previous` ones
+fn apply_concurrency_limits(
+	node: &Node,
+	library_id: Uuid,
+	previous: &[ConcurrencyLimit],
+	current: &[ConcurrencyLimit],
+) {
+	let dispatcher = node.task_system.get_dispatcher();
+
+	for location_id in previous.iter().flat_map(|limit| &limit.location_ids) {
+		dispatcher.remove_concurrency_limit(&location_concurrency_key(library_id, *location_id));
+	}
+
+	for ConcurrencyLimit {
+		location_ids,
+		limit,
+	} in current
+	{
+		dispatcher.set_concurrency_limit(
+			location_ids
+				.iter()
+				.map(|location_id| location_concurrency_key(library_id, *location_id)),
+			NonZeroUsize::try_from(*limit).unwrap_or(NonZeroUsize::MAX),
+		);
+	}
+}
+
+async fn migrate_cas_ids(
+	node: &Arc<Node>,
+	library: &Arc<Library>,
+	cas_id_algorithm: CasIdAlgorithm,
+) -> Result<(), LibraryManagerError> {
+	for location in library.db.location().find_many(vec![]).exec().await? {
+		let location_id = location.id;
+
+		let config = library.config().await;
+		let job = FileIdentifier::new_cas_id_migration(location, cas_id_algorithm)?
+			.with_kind_registry(KindRegistry::new(config.custom_kinds))
+			.with_batch_size_bounds(config.identifier_batch_size_bounds);
+
+		NodeContext::dispatch(node, library, job, location_id)
+			.await
+			.map_err(|e| LibraryManagerError::CasIdMigrationDispatch(format!("{e:?}")))?;
+	}
+
+	Ok(())
+}
+
 async fn sync_rx_actor(
 	library: Arc<Library>,
 	node: Arc<Node>,
@@ -771,3 +931,32 @@ async fn sync_rx_actor(
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn cas_ids_change_with_algorithm() {
+		assert!(cas_ids_changed(
+			(CasIdAlgorithm::Blake3Sampled, None),
+			(CasIdAlgorithm::Sha256, None)
+		));
+		assert!(!cas_ids_changed(
+			(CasIdAlgorithm::Sha256, None),
+			(CasIdAlgorithm::Sha256, None)
+		));
+	}
+
+	#[test]
+	fn cas_ids_change_with_threshold_only_when_sampled() {
+		assert!(cas_ids_changed(
+			(CasIdAlgorithm::Blake3Sampled, None),
+			(CasIdAlgorithm::Blake3Sampled, Some(1024 * 1024))
+		));
+		assert!(!cas_ids_changed(
+			(CasIdAlgorithm::Blake3Full, None),
+			(CasIdAlgorithm::Blake3Full, Some(1024 * 1024))
+		));
+	}
+}
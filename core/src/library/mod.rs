@@ -1,9 +1,13 @@
+pub mod archive;
 mod config;
+pub mod encryption;
 #[allow(clippy::module_inception)]
 mod library;
 mod manager;
 mod name;
+pub mod rollups;
 mod statistics;
+pub mod sync_backfill;
 
 pub use config::*;
 pub use library::*;
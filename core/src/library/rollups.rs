@@ -0,0 +1,376 @@
+//! Objects and bytes of a library by kind, location and tag, and how they grew over time.
+//!
+//! Rollups are refreshed for the location a job ran on as it finishes, see [`on_job_committed`],
+//! so reading them is a few small queries however big the library is.
+
+use crate::{invalidate_query, library::Library};
+
+use sd_file_ext::kind::ObjectKind;
+use sd_prisma::prisma::{
+	file_path, kind_statistics, location, object, statistics_history, tag, tag_on_object,
+	tag_statistics, PrismaClient, SortOrder,
+};
+use sd_utils::db::size_in_bytes_from_db;
+
+use std::collections::{BTreeMap, HashSet};
+
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+use prisma_client_rust::QueryError;
+use serde::Serialize;
+use specta::Type;
+use tracing::error;
+
+/// How far back the history of the library goes
+const HISTORY_DAYS: i64 = 365;
+
+/// Objects in more than one location are counted once for each of them
+#[derive(Debug, Serialize, Type)]
+pub struct LibraryRollups {
+	pub object_count: u32,
+	/// As a string as it may not fit in a JS number
+	pub total_bytes: String,
+	pub by_kind: Vec<KindRollup>,
+	pub by_location: Vec<LocationRollup>,
+	/// Objects tagged directly, not through the tags nested under each tag
+	pub by_tag: Vec<TagRollup>,
+	/// Oldest first, one entry for each day the library changed
+	pub history: Vec<RollupHistoryEntry>,
+}
+
+#[derive(Debug, Serialize, Type)]
+pub struct KindRollup {
+	/// Enum: `sd_file_ext::kind::ObjectKind`
+	pub kind: i32,
+	pub object_count: u32,
+	pub total_bytes: String,
+}
+
+#[derive(Debug, Serialize, Type)]
+pub struct LocationRollup {
+	pub location_id: location::id::Type,
+	pub object_count: u32,
+	pub total_bytes: String,
+}
+
+#[derive(Debug, Serialize, Type)]
+pub struct TagRollup {
+	pub tag_id: tag::id::Type,
+	pub object_count: u32,
+	pub total_bytes: String,
+}
+
+#[derive(Debug, Serialize, Type)]
+pub struct RollupHistoryEntry {
+	/// Midnight UTC of the day
+	pub date: DateTime<Utc>,
+	pub object_count: u32,
+	pub total_bytes: String,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Rollup {
+	object_count: i32,
+	total_bytes: i64,
+}
+
+impl Rollup {
+	fn add(&mut self, object_count: i32, total_bytes: i64) {
+		self.object_count += object_count;
+		self.total_bytes += total_bytes;
+	}
+
+	fn counts(self) -> (u32, String) {
+		(
+			self.object_count as u32,
+			(self.total_bytes as u64).to_string(),
+		)
+	}
+}
+
+/// Recounts the objects of a location by kind, replacing its previous rollups
+pub async fn refresh_location(
+	db: &PrismaClient,
+	location_id: location::id::Type,
+) -> Result<(), QueryError> {
+	let mut by_kind = BTreeMap::<i32, (HashSet<object::id::Type>, i64)>::new();
+
+	for file_path in db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(Some(location_id)),
+			file_path::is_dir::equals(Some(false)),
+			file_path::object_id::not(None),
+		])
+		.select(file_path::select!({ object_id size_in_bytes_bytes object: select { kind } }))
+		.exec()
+		.await?
+	{
+		let Some(object_id) = file_path.object_id else {
+			continue;
+		};
+
+		let kind = file_path
+			.object
+			.and_then(|object| object.kind)
+			.unwrap_or(ObjectKind::Unknown as i32);
+
+		let (objects, total_bytes) = by_kind.entry(kind).or_default();
+
+		objects.insert(object_id);
+		// Every copy of an object in the location takes space
+		*total_bytes += file_path
+			.size_in_bytes_bytes
+			.as_deref()
+			.map_or(0, size_in_bytes_from_db) as i64;
+	}
+
+	db._batch((
+		db.kind_statistics()
+			.delete_many(vec![kind_statistics::location_id::equals(location_id)]),
+		by_kind
+			.into_iter()
+			.map(|(kind, (objects, total_bytes))| {
+				db.kind_statistics().create_unchecked(
+					location_id,
+					kind,
+					vec![
+						kind_statistics::object_count::set(objects.len() as i32),
+						kind_statistics::total_bytes::set(total_bytes),
+					],
+				)
+			})
+			.collect::<Vec<_>>(),
+	))
+	.await?;
+
+	Ok(())
+}
+
+/// Recounts the objects of every tag, replacing the previous rollups
+pub async fn refresh_tags(db: &PrismaClient) -> Result<(), QueryError> {
+	let mut by_tag = BTreeMap::<tag::id::Type, Rollup>::new();
+
+	for tag_on_object in db
+		.tag_on_object()
+		.find_many(vec![])
+		.select(tag_on_object::select!({
+			tag_id
+			object: select { file_paths: select { size_in_bytes_bytes } }
+		}))
+		.exec()
+		.await?
+	{
+		// An object takes as much space as any one of its copies
+		let total_bytes = tag_on_object
+			.object
+			.file_paths
+			.first()
+			.and_then(|file_path| file_path.size_in_bytes_bytes.as_deref())
+			.map_or(0, size_in_bytes_from_db) as i64;
+
+		by_tag
+			.entry(tag_on_object.tag_id)
+			.or_default()
+			.add(1, total_bytes);
+	}
+
+	db._batch((
+		db.tag_statistics().delete_many(vec![]),
+		by_tag
+			.into_iter()
+			.map(|(tag_id, rollup)| {
+				db.tag_statistics().create_unchecked(
+					tag_id,
+					vec![
+						tag_statistics::object_count::set(rollup.object_count),
+						tag_statistics::total_bytes::set(rollup.total_bytes),
+					],
+				)
+			})
+			.collect::<Vec<_>>(),
+	))
+	.await?;
+
+	Ok(())
+}
+
+/// Stores the current totals of the library as those of today
+pub async fn record_history(db: &PrismaClient) -> Result<(), QueryError> {
+	use statistics_history::{create, date, object_count, total_bytes};
+
+	let mut totals = Rollup::default();
+	for row in db.kind_statistics().find_many(vec![]).exec().await? {
+		totals.add(row.object_count, row.total_bytes);
+	}
+
+	let today = today();
+
+	db.statistics_history()
+		.upsert(
+			date::equals(today),
+			create(today, totals.object_count, totals.total_bytes, vec![]),
+			vec![
+				object_count::set(totals.object_count),
+				total_bytes::set(totals.total_bytes),
+			],
+		)
+		.exec()
+		.await?;
+
+	Ok(())
+}
+
+/// Refreshes the rollups a job that ran on `location_id` may have changed. Called by the job
+/// worker once a job is done, even if it didn't complete, as it may have committed changes before
+pub async fn on_job_committed(library: &Library, location_id: Option<location::id::Type>) {
+	let Some(location_id) = location_id else {
+		return;
+	};
+
+	if let Err(e) = refresh(&library.db, [location_id]).await {
+		error!(?e, %location_id, "Failed to refresh library statistics rollups;");
+		return;
+	}
+
+	invalidate_query!(library, "library.statistics");
+	invalidate_query!(library, "library.kindStatistics");
+}
+
+async fn refresh(
+	db: &PrismaClient,
+	location_ids: impl IntoIterator<Item = location::id::Type>,
+) -> Result<(), QueryError> {
+	for location_id in location_ids {
+		refresh_location(db, location_id).await?;
+	}
+
+	refresh_tags(db).await?;
+	record_history(db).await
+}
+
+/// The rollups of a library. Libraries indexed before there were rollups are counted first.
+pub async fn fetch(db: &PrismaClient) -> Result<LibraryRollups, QueryError> {
+	if db
+		.kind_statistics()
+		.find_first(vec![])
+		.exec()
+		.await?
+		.is_none()
+		&& db
+			.file_path()
+			.find_first(vec![file_path::object_id::not(None)])
+			.select(file_path::select!({ id }))
+			.exec()
+			.await?
+			.is_some()
+	{
+		let location_ids = db
+			.location()
+			.find_many(vec![])
+			.select(location::select!({ id }))
+			.exec()
+			.await?
+			.into_iter()
+			.map(|location| location.id);
+
+		refresh(db, location_ids).await?;
+	}
+
+	let since: DateTime<FixedOffset> = today() - Duration::days(HISTORY_DAYS);
+
+	let (kind_rows, tag_rows, history) = db
+		._batch((
+			db.kind_statistics().find_many(vec![]),
+			db.tag_statistics()
+				.find_many(vec![])
+				.order_by(tag_statistics::object_count::order(SortOrder::Desc)),
+			db.statistics_history()
+				.find_many(vec![statistics_history::date::gte(since)])
+				.order_by(statistics_history::date::order(SortOrder::Asc)),
+		))
+		.await?;
+
+	let mut totals = Rollup::default();
+	let mut by_kind = BTreeMap::<i32, Rollup>::new();
+	let mut by_location = BTreeMap::<location::id::Type, Rollup>::new();
+
+	for row in kind_rows {
+		totals.add(row.object_count, row.total_bytes);
+		by_kind
+			.entry(row.kind)
+			.or_default()
+			.add(row.object_count, row.total_bytes);
+		by_location
+			.entry(row.location_id)
+			.or_default()
+			.add(row.object_count, row.total_bytes);
+	}
+
+	let (object_count, total_bytes) = totals.counts();
+
+	Ok(LibraryRollups {
+		object_count,
+		total_bytes,
+		by_kind: by_kind
+			.into_iter()
+			.map(|(kind, rollup)| {
+				let (object_count, total_bytes) = rollup.counts();
+				KindRollup {
+					kind,
+					object_count,
+					total_bytes,
+				}
+			})
+			.collect(),
+		by_location: by_location
+			.into_iter()
+			.map(|(location_id, rollup)| {
+				let (object_count, total_bytes) = rollup.counts();
+				LocationRollup {
+					location_id,
+					object_count,
+					total_bytes,
+				}
+			})
+			.collect(),
+		by_tag: tag_rows
+			.into_iter()
+			.map(|row| {
+				let (object_count, total_bytes) = Rollup {
+					object_count: row.object_count,
+					total_bytes: row.total_bytes,
+				}
+				.counts();
+				TagRollup {
+					tag_id: row.tag_id,
+					object_count,
+					total_bytes,
+				}
+			})
+			.collect(),
+		history: history
+			.into_iter()
+			.map(|row| {
+				let (object_count, total_bytes) = Rollup {
+					object_count: row.object_count,
+					total_bytes: row.total_bytes,
+				}
+				.counts();
+				RollupHistoryEntry {
+					date: row.date.into(),
+					object_count,
+					total_bytes,
+				}
+			})
+			.collect(),
+	})
+}
+
+fn today() -> DateTime<FixedOffset> {
+	Utc::now()
+		.date_naive()
+		.and_hms_opt(0, 0, 0)
+		.expect("midnight is a valid time")
+		.and_utc()
+		.into()
+}
@@ -0,0 +1,186 @@
+use crate::{
+	invalidate_query,
+	library::Library,
+	node::bandwidth::BandwidthProtocol,
+	old_job::{
+		CurrentStep, JobError, JobInitOutput, JobReportUpdate, JobResult, JobRunMetadata,
+		JobStepOutput, StatefulJob, WorkerContext,
+	},
+	util::MaybeUndefined,
+};
+
+use sd_core_sync::backfill::{self, Cursor, Scope};
+
+use sd_prisma::prisma::location;
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use specta::Type;
+use tokio::time::sleep;
+use tracing::{error, info};
+
+/// `OldSyncBackfillJobInit` generates the sync operations of the records that existed before
+/// sync was enabled on the library, a page at a time so pairing a device with a large library can
+/// be followed, paused and resumed. Sync is only enabled once it completes, so canceling it never
+/// leaves other devices with part of the library.
+///
+/// It also backfills the records of a location whose sync policy changed to sync more of them.
+#[derive(Serialize, Deserialize, Type, Clone, Debug, Hash)]
+pub struct OldSyncBackfillJobInit {
+	/// Limits the size of the operations generated per second, unlimited if `None`
+	pub bytes_per_second: Option<u32>,
+	/// Only set by sync policy changes, every record is backfilled if `None`
+	#[serde(default)]
+	#[specta(skip)]
+	pub scope: Option<Scope>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct OldSyncBackfillJobRunMetadata {
+	records_synced: usize,
+	total_records: usize,
+}
+
+impl JobRunMetadata for OldSyncBackfillJobRunMetadata {
+	fn update(&mut self, new_data: Self) {
+		self.records_synced += new_data.records_synced;
+	}
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for OldSyncBackfillJobInit {
+	type Data = ();
+	type Step = Cursor;
+	type RunMetadata = OldSyncBackfillJobRunMetadata;
+
+	const NAME: &'static str = "sync_backfill";
+
+	fn target_location(&self) -> Option<location::id::Type> {
+		self.scope.map(|scope| scope.location_id)
+	}
+
+	async fn init(
+		&self,
+		ctx: &WorkerContext,
+		data: &mut Option<Self::Data>,
+	) -> Result<JobInitOutput<Self::RunMetadata, Self::Step>, JobError> {
+		if self.scope.is_none() {
+			// Cleared while sync is still disabled, as no operations are being written then
+			backfill::clear(&ctx.library.db, ctx.library.config().await.instance_id).await?;
+		}
+
+		let total_records = backfill::count(&ctx.library.db, self.scope.as_ref()).await? as usize;
+
+		*data = Some(());
+
+		ctx.progress(vec![
+			JobReportUpdate::TaskCount(total_records),
+			JobReportUpdate::Message(format!("Found {total_records} records to sync")),
+		]);
+
+		Ok((
+			OldSyncBackfillJobRunMetadata {
+				total_records,
+				..Default::default()
+			},
+			match &self.scope {
+				Some(scope) => scope.start().into_iter().collect(),
+				None => vec![Cursor::default()],
+			},
+		)
+			.into())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: &WorkerContext,
+		CurrentStep { step: cursor, .. }: CurrentStep<'_, Self::Step>,
+		_data: &Self::Data,
+		run_metadata: &Self::RunMetadata,
+	) -> Result<JobStepOutput<Self::Step, Self::RunMetadata>, JobError> {
+		let Library { db, sync, .. } = &*ctx.library;
+
+		let page = backfill::backfill_page(
+			db,
+			sync,
+			ctx.library.config().await.instance_id,
+			*cursor,
+			self.scope.as_ref(),
+		)
+		.await?;
+
+		// Records added since we counted them go past the total
+		let records_synced = run_metadata.records_synced + page.records;
+
+		ctx.progress(vec![
+			JobReportUpdate::CompletedTaskCount(records_synced.min(run_metadata.total_records)),
+			JobReportUpdate::Message(format!(
+				"Synced {records_synced} of {} records",
+				run_metadata.total_records
+			)),
+		]);
+
+		if let Some(bytes_per_second) = self.bytes_per_second.filter(|limit| *limit > 0) {
+			sleep(Duration::from_secs_f64(
+				page.bytes as f64 / f64::from(bytes_per_second),
+			))
+			.await;
+		}
+
+		ctx.node
+			.bandwidth
+			.throttle(BandwidthProtocol::SyncBackfill, page.bytes)
+			.await;
+
+		let run_metadata = OldSyncBackfillJobRunMetadata {
+			records_synced: page.records,
+			..Default::default()
+		};
+
+		Ok(match page.next {
+			Some(next) => (vec![next], run_metadata).into(),
+			None => run_metadata.into(),
+		})
+	}
+
+	async fn finalize(
+		&self,
+		ctx: &WorkerContext,
+		_data: &Option<Self::Data>,
+		run_metadata: &Self::RunMetadata,
+	) -> JobResult {
+		let init = self;
+		info!("Finalizing sync backfill job: {run_metadata:?}");
+
+		// Scoped backfills only run while sync is enabled
+		if self.scope.is_none() {
+			ctx.node
+				.libraries
+				.edit(
+					&ctx.node,
+					ctx.library.id,
+					None,
+					MaybeUndefined::Undefined,
+					MaybeUndefined::Undefined,
+					Some(true),
+					None,
+					MaybeUndefined::Undefined,
+					None,
+					None,
+					None,
+					None,
+				)
+				.await
+				.map_err(|e| {
+					error!("Failed to enable sync after backfilling: {e:#?}");
+					JobError::Critical("failed to enable sync after backfilling")
+				})?;
+
+			invalidate_query!(ctx.library, "sync.enabled");
+		}
+
+		Ok(Some(json!({ "init": init, "run_metadata": run_metadata })))
+	}
+}
@@ -0,0 +1,183 @@
+use crate::{library::Library, volume::Volume, Node};
+
+use sd_prisma::prisma::{location, location_capacity, SortOrder};
+
+use std::{
+	path::Path,
+	sync::{Arc, Weak},
+	time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_with::{serde_as, DisplayFromStr};
+use specta::Type;
+use tokio::time::{interval_at, Instant, MissedTickBehavior};
+use tracing::{debug, error};
+use uuid::Uuid;
+
+use super::LocationError;
+
+const CAPTURE_INTERVAL: Duration = Duration::from_secs(60 * 60); // 1 hour
+/// How long captures are kept, enough to chart a year of growth
+const RETENTION: chrono::Duration = chrono::Duration::days(365);
+
+/// Size and free space of the volume holding a location at some point in time
+#[serde_as]
+#[derive(Serialize, Type, Debug)]
+pub struct LocationCapacity {
+	pub date_captured: DateTime<Utc>,
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub total_bytes: u64,
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub free_bytes: u64,
+	pub file_system: Option<String>,
+}
+
+impl From<location_capacity::Data> for LocationCapacity {
+	fn from(data: location_capacity::Data) -> Self {
+		Self {
+			date_captured: data.date_captured.into(),
+			total_bytes: data.total_bytes as u64,
+			free_bytes: data.free_bytes as u64,
+			file_system: data.file_system,
+		}
+	}
+}
+
+/// Captures of a location, oldest first
+pub async fn capacity_history(
+	library: &Library,
+	location_id: location::id::Type,
+	since: Option<DateTime<Utc>>,
+) -> Result<Vec<LocationCapacity>, LocationError> {
+	Ok(library
+		.db
+		.location_capacity()
+		.find_many(
+			[
+				Some(location_capacity::location_id::equals(location_id)),
+				since.map(|since| location_capacity::date_captured::gte(since.into())),
+			]
+			.into_iter()
+			.flatten()
+			.collect(),
+		)
+		.order_by(location_capacity::date_captured::order(SortOrder::Asc))
+		.exec()
+		.await?
+		.into_iter()
+		.map(LocationCapacity::from)
+		.collect())
+}
+
+/// Free bytes of the volume holding `path` right now, `None` if no mounted volume holds it
+pub async fn free_bytes_at(path: impl AsRef<Path>) -> Option<u64> {
+	Volume::for_path(&crate::volume::get_volumes().await, path)
+		.map(|volume| volume.available_capacity)
+}
+
+/// Whether both paths are in the same volume, so moving between them doesn't take space
+pub async fn same_volume(a: impl AsRef<Path>, b: impl AsRef<Path>) -> bool {
+	let volumes = crate::volume::get_volumes().await;
+
+	matches!(
+		(Volume::for_path(&volumes, a), Volume::for_path(&volumes, b)),
+		(Some(a), Some(b)) if a == b
+	)
+}
+
+/// Records the capacity of the volume holding each online location of this node, skipping the
+/// ones in object storage, WebDAV, cloud drives or MTP devices, as their path is just a mirror
+pub async fn capture_capacities(node: &Node, library: &Library) -> Result<(), LocationError> {
+	let instance_id = library.config().await.instance_id;
+
+	let locations = library
+		.db
+		.location()
+		.find_many(vec![
+			location::instance_id::equals(Some(instance_id)),
+			location::s3_config::equals(None),
+			location::webdav_config::equals(None),
+			location::cloud_config::equals(None),
+			location::mtp_config::equals(None),
+		])
+		.select(location::select!({ id pub_id path }))
+		.exec()
+		.await?;
+
+	let volumes = crate::volume::get_volumes().await;
+
+	let mut captures = Vec::with_capacity(locations.len());
+
+	for location in locations {
+		let Some(path) = location.path else {
+			continue;
+		};
+
+		// An unplugged drive would be taken as the volume holding its mount point
+		let Ok(pub_id) = Uuid::from_slice(&location.pub_id) else {
+			continue;
+		};
+
+		if !node.locations.is_online(&pub_id).await {
+			continue;
+		}
+
+		let Some(volume) = Volume::for_path(&volumes, &path) else {
+			debug!("No volume found holding location <id='{}'>", location.id);
+			continue;
+		};
+
+		captures.push(library.db.location_capacity().create(
+			location::id::equals(location.id),
+			volume.total_capacity as i64,
+			volume.available_capacity as i64,
+			vec![location_capacity::file_system::set(
+				volume.file_system.clone(),
+			)],
+		));
+	}
+
+	if !captures.is_empty() {
+		library.db._batch(captures).await?;
+	}
+
+	library
+		.db
+		.location_capacity()
+		.delete_many(vec![location_capacity::date_captured::lt(
+			(Utc::now() - RETENTION).into(),
+		)])
+		.exec()
+		.await?;
+
+	Ok(())
+}
+
+/// Captures location capacities every [`CAPTURE_INTERVAL`] until the library is unloaded
+pub fn spawn_capacity_capture(node: Arc<Node>, library: &Arc<Library>) {
+	let library = Arc::downgrade(library);
+
+	tokio::spawn(async move {
+		let mut interval = interval_at(Instant::now() + Duration::from_secs(60), CAPTURE_INTERVAL);
+		interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+		loop {
+			interval.tick().await;
+
+			let Some(library) = Weak::upgrade(&library) else {
+				debug!("Library unloaded, stopping location capacity capture");
+				break;
+			};
+
+			if let Err(e) = capture_capacities(&node, &library).await {
+				error!("Failed to capture location capacities: {e:#?}");
+			} else {
+				crate::invalidate_query!(library, "locations.capacityHistory");
+			}
+		}
+	});
+}
@@ -0,0 +1,562 @@
+//! Locations made only of the metadata of files in Google Drive or OneDrive.
+//!
+//! The indexer ingests file listings from the Drive and Graph APIs, and the file identifier derives
+//! `cas_id`s from the content hashes the providers compute (`md5Checksum` and `quickXorHash`),
+//! which are kept on the `provider_hash` table. Files are never downloaded, they stay remote only
+//! until the user hydrates them into the location's local mirror directory.
+
+use crate::node::bandwidth::{Bandwidth, BandwidthProtocol};
+
+use sd_prisma::prisma::location;
+use sd_utils::error::FileIOError;
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use int_enum::IntEnum;
+use reqwest::{Client, RequestBuilder, StatusCode};
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use specta::Type;
+use tokio::{fs, io::AsyncWriteExt};
+use tracing::debug;
+
+const GOOGLE_TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+const MICROSOFT_TOKEN_URL: &str = "https://login.microsoftonline.com/common/oauth2/v2.0/token";
+const DRIVE_FILES_URL: &str = "https://www.googleapis.com/drive/v3/files";
+const GRAPH_DRIVE_URL: &str = "https://graph.microsoft.com/v1.0/me/drive";
+
+const DRIVE_FOLDER_MIME_TYPE: &str = "application/vnd.google-apps.folder";
+/// Docs, Sheets and the like, which only exist inside Google Drive and have no content to hash
+const DRIVE_NATIVE_MIME_TYPE_PREFIX: &str = "application/vnd.google-apps.";
+
+#[derive(thiserror::Error, Debug)]
+pub enum CloudMetadataError {
+	#[error("failed to decode cloud metadata config of location <id='{0}'>: {1}")]
+	Decode(location::id::Type, rmp_serde::decode::Error),
+	#[error("failed to authenticate with {provider:?}: {message}")]
+	Auth {
+		provider: CloudProvider,
+		message: String,
+	},
+	#[error("{provider:?} request failed <url='{url}'>: {source}")]
+	Request {
+		provider: CloudProvider,
+		url: String,
+		source: reqwest::Error,
+	},
+	#[error("{provider:?} answered with status {status} <url='{url}'>")]
+	Status {
+		provider: CloudProvider,
+		url: String,
+		status: StatusCode,
+	},
+	#[error("unexpected {provider:?} response <url='{url}'>: {message}")]
+	Response {
+		provider: CloudProvider,
+		url: String,
+		message: String,
+	},
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+}
+
+impl From<CloudMetadataError> for rspc::Error {
+	fn from(err: CloudMetadataError) -> Self {
+		match err {
+			// Usually revoked credentials or an unreachable provider
+			CloudMetadataError::Auth { .. }
+			| CloudMetadataError::Request { .. }
+			| CloudMetadataError::Status { .. } => {
+				Self::with_cause(ErrorCode::PreconditionFailed, err.to_string(), err)
+			}
+
+			_ => Self::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum CloudProvider {
+	GoogleDrive,
+	OneDrive,
+}
+
+/// What a `provider_hash` row holds, stored on its `kind` column
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, IntEnum)]
+pub enum ProviderHashKind {
+	/// Google Drive's `md5Checksum`
+	Md5 = 0,
+	/// OneDrive's `quickXorHash`
+	QuickXor = 1,
+	/// Files the provider doesn't hash get their item id instead, so they're still identified,
+	/// but never deduplicated
+	ItemId = 2,
+}
+
+impl ProviderHashKind {
+	/// The `cas_id` files get from the provider's hash of their content, until one of them is
+	/// hydrated and we learn the real one
+	pub fn derived_cas_id(self, hash: &str) -> String {
+		let mut hasher = blake3::Hasher::new();
+		hasher.update(&(self as i32).to_le_bytes());
+		hasher.update(hash.as_bytes());
+
+		hasher.finalize().to_hex()[..16].to_string()
+	}
+}
+
+/// OAuth refresh token the location lists files with, from an OAuth client of the user's own
+/// Google Cloud or Azure project
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct OAuthCredentials {
+	pub client_id: String,
+	/// Only Google requires it, even for desktop clients
+	pub client_secret: Option<String>,
+	pub refresh_token: String,
+}
+
+/// Drive or folder of a cloud metadata location, stored msgpack encoded on
+/// `location.cloud_config`, which is local only as it holds the credentials.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct CloudMetadataLocationConfig {
+	pub provider: CloudProvider,
+	/// Id of the folder to add as a location, the whole drive if missing
+	pub folder_id: Option<String>,
+	pub credentials: OAuthCredentials,
+}
+
+impl CloudMetadataLocationConfig {
+	pub fn from_db(
+		location_id: location::id::Type,
+		cloud_config: Option<&[u8]>,
+	) -> Result<Option<Self>, CloudMetadataError> {
+		cloud_config
+			.map(|bytes| {
+				rmp_serde::from_slice(bytes).map_err(|e| CloudMetadataError::Decode(location_id, e))
+			})
+			.transpose()
+	}
+
+	pub fn to_db(&self) -> Vec<u8> {
+		rmp_serde::to_vec_named(self).expect("cloud metadata config is always serializable")
+	}
+
+	/// Local directory holding the directory skeleton and the hydrated files, which is used as
+	/// the location path
+	pub fn mirror_path(&self, data_dir: impl AsRef<Path>) -> PathBuf {
+		let mut hasher = blake3::Hasher::new();
+		hasher.update(self.credentials.refresh_token.as_bytes());
+		hasher.update(self.folder_id.as_deref().unwrap_or_default().as_bytes());
+
+		data_dir.as_ref().join("cloud").join(format!(
+			"{}-{}",
+			match self.provider {
+				CloudProvider::GoogleDrive => "google-drive",
+				CloudProvider::OneDrive => "onedrive",
+			},
+			&hasher.finalize().to_hex()[..8]
+		))
+	}
+}
+
+/// A file or folder listed from the provider
+#[derive(Debug, Clone)]
+pub struct CloudItem {
+	pub id: String,
+	pub name: String,
+	pub is_dir: bool,
+	pub size: u64,
+	pub created_at: DateTime<Utc>,
+	pub modified_at: DateTime<Utc>,
+	pub hash: Option<(ProviderHashKind, String)>,
+}
+
+impl CloudItem {
+	/// Providers don't expose inodes, but file paths must be unique by them inside their location
+	pub fn pseudo_inode(&self) -> u64 {
+		let hash = blake3::hash(self.id.as_bytes());
+		let mut bytes = [0; 8];
+		bytes.copy_from_slice(&hash.as_bytes()[..8]);
+
+		u64::from_le_bytes(bytes)
+	}
+
+	/// The provider's hash of the file content, or its item id for files without one
+	pub fn provider_hash(&self) -> (ProviderHashKind, String) {
+		self.hash
+			.clone()
+			.unwrap_or_else(|| (ProviderHashKind::ItemId, self.id.clone()))
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct CloudMetadataLocation {
+	client: Client,
+	access_token: String,
+	config: CloudMetadataLocationConfig,
+}
+
+impl CloudMetadataLocation {
+	/// Exchanges the refresh token for an access token, which lasts about an hour, enough for a
+	/// job. Access tokens are never stored.
+	pub async fn connect(config: CloudMetadataLocationConfig) -> Result<Self, CloudMetadataError> {
+		let client = Client::new();
+		let provider = config.provider;
+
+		let token_url = match provider {
+			CloudProvider::GoogleDrive => GOOGLE_TOKEN_URL,
+			CloudProvider::OneDrive => MICROSOFT_TOKEN_URL,
+		};
+
+		let mut form = vec![
+			("grant_type", "refresh_token"),
+			("client_id", config.credentials.client_id.as_str()),
+			("refresh_token", config.credentials.refresh_token.as_str()),
+		];
+
+		if let Some(client_secret) = &config.credentials.client_secret {
+			form.push(("client_secret", client_secret));
+		}
+
+		let response = client
+			.post(token_url)
+			.form(&form)
+			.send()
+			.await
+			.map_err(|source| CloudMetadataError::Request {
+				provider,
+				url: token_url.to_string(),
+				source,
+			})?;
+
+		let status = response.status();
+		let body =
+			response
+				.json::<Value>()
+				.await
+				.map_err(|source| CloudMetadataError::Request {
+					provider,
+					url: token_url.to_string(),
+					source,
+				})?;
+
+		let Some(access_token) = body["access_token"]
+			.as_str()
+			.filter(|_| status.is_success())
+		else {
+			return Err(CloudMetadataError::Auth {
+				provider,
+				message: body["error_description"]
+					.as_str()
+					.or_else(|| body["error"].as_str())
+					.unwrap_or("no access token received")
+					.to_string(),
+			});
+		};
+
+		Ok(Self {
+			access_token: access_token.to_string(),
+			client,
+			config,
+		})
+	}
+
+	/// Connects to the location's provider, if it's a cloud metadata location
+	pub async fn for_location(
+		location_id: location::id::Type,
+		cloud_config: Option<&[u8]>,
+	) -> Result<Option<Self>, CloudMetadataError> {
+		Ok(
+			match CloudMetadataLocationConfig::from_db(location_id, cloud_config)? {
+				Some(config) => Some(Self::connect(config).await?),
+				None => None,
+			},
+		)
+	}
+
+	pub const fn provider(&self) -> CloudProvider {
+		self.config.provider
+	}
+
+	/// Id of the location's root folder
+	pub fn root_id(&self) -> &str {
+		self.config.folder_id.as_deref().unwrap_or("root")
+	}
+
+	/// Finds the folder at `sub_path`, relative to the location's root, by walking its components
+	pub async fn resolve_folder(
+		&self,
+		sub_path: &Path,
+	) -> Result<Option<String>, CloudMetadataError> {
+		let mut folder_id = self.root_id().to_string();
+
+		for component in sub_path.components() {
+			let name = component.as_os_str().to_string_lossy();
+
+			let Some(child) = self
+				.list(&folder_id)
+				.await?
+				.into_iter()
+				.find(|item| item.is_dir && item.name == name)
+			else {
+				return Ok(None);
+			};
+
+			folder_id = child.id;
+		}
+
+		Ok(Some(folder_id))
+	}
+
+	/// Lists the direct children of the folder with `folder_id`
+	pub async fn list(&self, folder_id: &str) -> Result<Vec<CloudItem>, CloudMetadataError> {
+		let items = match self.config.provider {
+			CloudProvider::GoogleDrive => self.list_drive(folder_id).await?,
+			CloudProvider::OneDrive => self.list_graph(folder_id).await?,
+		};
+
+		debug!(
+			"Listed {} items from {:?} <folder_id='{folder_id}'>",
+			items.len(),
+			self.config.provider
+		);
+
+		Ok(items)
+	}
+
+	/// Downloads the file with `item_id` to `path`, making it available locally
+	pub async fn download(
+		&self,
+		item_id: &str,
+		path: impl AsRef<Path>,
+		bandwidth: &Bandwidth,
+	) -> Result<(), CloudMetadataError> {
+		let path = path.as_ref();
+
+		let url = match self.config.provider {
+			CloudProvider::GoogleDrive => format!("{DRIVE_FILES_URL}/{item_id}?alt=media"),
+			CloudProvider::OneDrive => format!("{GRAPH_DRIVE_URL}/items/{item_id}/content"),
+		};
+
+		let mut response = self.send(self.client.get(&url), &url).await?;
+
+		if let Some(parent) = path.parent() {
+			fs::create_dir_all(parent)
+				.await
+				.map_err(|e| FileIOError::from((parent, e)))?;
+		}
+
+		// Downloading to a temporary file, so an interrupted download is never taken as the file
+		let tmp_path = {
+			let mut tmp_path = path.to_path_buf().into_os_string();
+			tmp_path.push(".sdpart");
+			PathBuf::from(tmp_path)
+		};
+
+		let mut file = fs::File::create(&tmp_path)
+			.await
+			.map_err(|e| FileIOError::from((&tmp_path, e)))?;
+
+		while let Some(chunk) = response
+			.chunk()
+			.await
+			.map_err(|source| self.request_error(&url, source))?
+		{
+			bandwidth
+				.throttle(BandwidthProtocol::CloudHydration, chunk.len())
+				.await;
+
+			file.write_all(&chunk)
+				.await
+				.map_err(|e| FileIOError::from((&tmp_path, e, "Failed to download file")))?;
+		}
+
+		file.flush()
+			.await
+			.map_err(|e| FileIOError::from((&tmp_path, e)))?;
+
+		fs::rename(&tmp_path, path)
+			.await
+			.map_err(|e| FileIOError::from((path, e)))?;
+
+		debug!(
+			"Hydrated {:?} file <item_id='{item_id}'> to <path='{}'>",
+			self.config.provider,
+			path.display()
+		);
+
+		Ok(())
+	}
+
+	async fn list_drive(&self, folder_id: &str) -> Result<Vec<CloudItem>, CloudMetadataError> {
+		let query = format!(
+			"'{}' in parents and trashed = false",
+			folder_id.replace('\'', "\\'")
+		);
+
+		let mut items = vec![];
+		let mut page_token = None::<String>;
+
+		loop {
+			let mut request = self.client.get(DRIVE_FILES_URL).query(&[
+				("q", query.as_str()),
+				(
+					"fields",
+					"nextPageToken,files(id,name,mimeType,size,createdTime,modifiedTime,md5Checksum)",
+				),
+				("pageSize", "1000"),
+			]);
+
+			if let Some(page_token) = &page_token {
+				request = request.query(&[("pageToken", page_token)]);
+			}
+
+			let page = self.json(request, DRIVE_FILES_URL).await?;
+
+			let Some(files) = page["files"].as_array() else {
+				return Err(self.response_error(DRIVE_FILES_URL, "missing files list"));
+			};
+
+			items.extend(files.iter().filter_map(|file| {
+				let mime_type = file["mimeType"].as_str().unwrap_or_default();
+				let is_dir = mime_type == DRIVE_FOLDER_MIME_TYPE;
+
+				if !is_dir && mime_type.starts_with(DRIVE_NATIVE_MIME_TYPE_PREFIX) {
+					return None;
+				}
+
+				Some(CloudItem {
+					id: file["id"].as_str()?.to_string(),
+					name: file["name"].as_str()?.to_string(),
+					is_dir,
+					// Drive sends sizes as strings, as they may not fit in a JavaScript number
+					size: file["size"]
+						.as_str()
+						.and_then(|size| size.parse().ok())
+						.unwrap_or_default(),
+					created_at: parse_date(&file["createdTime"]),
+					modified_at: parse_date(&file["modifiedTime"]),
+					hash: file["md5Checksum"]
+						.as_str()
+						.map(|md5| (ProviderHashKind::Md5, md5.to_string())),
+				})
+			}));
+
+			match page["nextPageToken"].as_str() {
+				Some(next_page_token) => page_token = Some(next_page_token.to_string()),
+				None => break,
+			}
+		}
+
+		Ok(items)
+	}
+
+	async fn list_graph(&self, folder_id: &str) -> Result<Vec<CloudItem>, CloudMetadataError> {
+		let mut url = if folder_id == "root" {
+			format!("{GRAPH_DRIVE_URL}/root/children")
+		} else {
+			format!("{GRAPH_DRIVE_URL}/items/{folder_id}/children")
+		};
+		url.push_str(
+			"?$top=1000&$select=id,name,size,folder,file,createdDateTime,lastModifiedDateTime",
+		);
+
+		let mut items = vec![];
+
+		loop {
+			let page = self.json(self.client.get(&url), &url).await?;
+
+			let Some(values) = page["value"].as_array() else {
+				return Err(self.response_error(&url, "missing value list"));
+			};
+
+			items.extend(values.iter().filter_map(|item| {
+				let is_dir = item["folder"].is_object();
+
+				// Packages and other special items are neither files nor folders
+				if !is_dir && !item["file"].is_object() {
+					return None;
+				}
+
+				Some(CloudItem {
+					id: item["id"].as_str()?.to_string(),
+					name: item["name"].as_str()?.to_string(),
+					is_dir,
+					size: if is_dir {
+						0
+					} else {
+						item["size"].as_u64().unwrap_or_default()
+					},
+					created_at: parse_date(&item["createdDateTime"]),
+					modified_at: parse_date(&item["lastModifiedDateTime"]),
+					hash: item["file"]["hashes"]["quickXorHash"]
+						.as_str()
+						.map(|quick_xor| (ProviderHashKind::QuickXor, quick_xor.to_string())),
+				})
+			}));
+
+			match page["@odata.nextLink"].as_str() {
+				Some(next_link) => url = next_link.to_string(),
+				None => break,
+			}
+		}
+
+		Ok(items)
+	}
+
+	async fn json(&self, request: RequestBuilder, url: &str) -> Result<Value, CloudMetadataError> {
+		self.send(request, url)
+			.await?
+			.json()
+			.await
+			.map_err(|source| self.request_error(url, source))
+	}
+
+	async fn send(
+		&self,
+		request: RequestBuilder,
+		url: &str,
+	) -> Result<reqwest::Response, CloudMetadataError> {
+		let response = request
+			.bearer_auth(&self.access_token)
+			.send()
+			.await
+			.map_err(|source| self.request_error(url, source))?;
+
+		if response.status().is_success() {
+			Ok(response)
+		} else {
+			Err(CloudMetadataError::Status {
+				provider: self.config.provider,
+				url: url.to_string(),
+				status: response.status(),
+			})
+		}
+	}
+
+	fn request_error(&self, url: &str, source: reqwest::Error) -> CloudMetadataError {
+		CloudMetadataError::Request {
+			provider: self.config.provider,
+			url: url.to_string(),
+			source,
+		}
+	}
+
+	fn response_error(&self, url: &str, message: &str) -> CloudMetadataError {
+		CloudMetadataError::Response {
+			provider: self.config.provider,
+			url: url.to_string(),
+			message: message.to_string(),
+		}
+	}
+}
+
+fn parse_date(value: &Value) -> DateTime<Utc> {
+	value
+		.as_str()
+		.and_then(|date| DateTime::parse_from_rfc3339(date).ok())
+		.map_or_else(Utc::now, |date| date.with_timezone(&Utc))
+}
@@ -1,4 +1,5 @@
 use sd_core_file_path_helper::FilePathError;
+use sd_core_heavy_lifting::utils::network_share;
 
 use sd_prisma::prisma::location;
 use sd_utils::{
@@ -12,7 +13,10 @@ use rspc::ErrorCode;
 use thiserror::Error;
 use uuid::Uuid;
 
-use super::{manager::LocationManagerError, metadata::LocationMetadataError};
+use super::{
+	cloud_metadata::CloudMetadataError, manager::LocationManagerError,
+	metadata::LocationMetadataError, mtp::MtpError, s3::S3Error, webdav::WebDavError,
+};
 
 /// Error type for location related errors
 #[derive(Error, Debug)]
@@ -50,8 +54,22 @@ pub enum LocationError {
 	LocationAlreadyExists(Box<Path>),
 	#[error("nested location currently not supported <path='{}'>", .0.display())]
 	NestedLocation(Box<Path>),
+	#[error("location isn't a snapshot <id='{0}'>")]
+	NotSnapshot(location::id::Type),
+	#[error("drive of snapshot location isn't connected <id='{0}'>")]
+	SnapshotOffline(location::id::Type),
 	#[error(transparent)]
 	NonUtf8Path(#[from] NonUtf8PathError),
+	#[error(transparent)]
+	NetworkShare(#[from] network_share::Error),
+	#[error(transparent)]
+	S3(#[from] S3Error),
+	#[error(transparent)]
+	WebDav(#[from] WebDavError),
+	#[error(transparent)]
+	CloudMetadata(#[from] CloudMetadataError),
+	#[error(transparent)]
+	Mtp(#[from] MtpError),
 
 	// Internal Errors
 	#[error(transparent)]
@@ -78,6 +96,8 @@ pub enum LocationError {
 	MissingField(#[from] MissingFieldError),
 	#[error("invalid location scan state value: {0}")]
 	InvalidScanStateValue(i32),
+	#[error("failed to serialize identifier rules: {0}")]
+	IdentifierRulesSerialization(#[from] rmp_serde::encode::Error),
 }
 
 impl From<LocationError> for rspc::Error {
@@ -94,10 +114,14 @@ impl From<LocationError> for rspc::Error {
 			}
 
 			// User's fault errors
-			NotDirectory(_) | NestedLocation(_) | LocationAlreadyExists(_) => {
+			NotDirectory(_) | NestedLocation(_) | LocationAlreadyExists(_) | NotSnapshot(_) => {
 				Self::with_cause(ErrorCode::BadRequest, err.to_string(), err)
 			}
 
+			SnapshotOffline(_) => {
+				Self::with_cause(ErrorCode::PreconditionFailed, err.to_string(), err)
+			}
+
 			// Custom error message is used to differentiate these errors in the frontend
 			// TODO: A better solution would be for rspc to support sending custom data alongside errors
 			NeedRelink { .. } => {
@@ -109,6 +133,11 @@ impl From<LocationError> for rspc::Error {
 
 			// Internal errors
 			MissingField(missing_error) => missing_error.into(),
+			NetworkShare(network_share_err) => network_share_err.into(),
+			S3(s3_err) => s3_err.into(),
+			WebDav(webdav_err) => webdav_err.into(),
+			CloudMetadata(cloud_metadata_err) => cloud_metadata_err.into(),
+			Mtp(mtp_err) => mtp_err.into(),
 			_ => Self::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
 		}
 	}
@@ -0,0 +1,204 @@
+use crate::location::cloud_metadata::{CloudMetadataLocation, ProviderHashKind};
+
+use sd_core_file_path_helper::FilePathMetadata;
+use sd_core_indexer_rules::IndexerRule;
+use sd_core_prisma_helpers::{file_path_pub_and_cas_ids, file_path_walker};
+
+use sd_prisma::prisma::{file_path, location, provider_hash, PrismaClient};
+use sd_utils::{error::FileIOError, uuid_to_bytes};
+
+use std::{
+	collections::{HashMap, HashSet, VecDeque},
+	future::Future,
+	path::{Path, PathBuf},
+};
+
+use tokio::fs;
+use tracing::warn;
+
+use super::{
+	old_walk::{filter_existing_paths, WalkResult, WalkedEntry},
+	s3_walk::{accepted_by_glob_rules, is_hidden, objects_to_remove, push_entry},
+	IndexerError,
+};
+
+/// Same as [`webdav_walk`](super::webdav_walk::webdav_walk), but listing folders of a Google
+/// Drive or OneDrive location through their APIs, and keeping the content hashes they send for
+/// files on the `provider_hash` table, for the file identifier to use instead of downloading them.
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn cloud_metadata_walk<FilePathDBFetcherFut>(
+	location_id: location::id::Type,
+	location_path: impl AsRef<Path>,
+	current_dir: impl AsRef<Path>,
+	indexer_rules: &[IndexerRule],
+	cloud: &CloudMetadataLocation,
+	recursive: bool,
+	mut update_notifier: impl FnMut(&Path, usize),
+	file_paths_db_fetcher: impl Fn(Vec<file_path::WhereParam>) -> FilePathDBFetcherFut,
+	db: &PrismaClient,
+) -> Result<
+	WalkResult<
+		impl Iterator<Item = WalkedEntry>,
+		impl Iterator<Item = WalkedEntry>,
+		impl Iterator<Item = file_path_pub_and_cas_ids::Data>,
+	>,
+	IndexerError,
+>
+where
+	FilePathDBFetcherFut: Future<Output = Result<Vec<file_path_walker::Data>, IndexerError>>,
+{
+	let location_path = location_path.as_ref();
+	let current_dir = current_dir.as_ref();
+
+	let Some(current_dir_id) = cloud
+		.resolve_folder(
+			current_dir
+				.strip_prefix(location_path)
+				.unwrap_or(Path::new("")),
+		)
+		.await?
+	else {
+		return Err(IndexerError::SubPathNotFound(current_dir.into()));
+	};
+
+	let mut to_list = VecDeque::from([(current_dir.to_path_buf(), current_dir_id)]);
+	let mut indexed_paths = HashSet::new();
+	let mut paths_and_sizes = HashMap::<PathBuf, u64>::new();
+	let mut provider_hashes = HashMap::<PathBuf, (String, ProviderHashKind, String)>::new();
+	let mut errors = vec![];
+	let mut all_listed = true;
+
+	while let Some((dir, folder_id)) = to_list.pop_front() {
+		let items = match cloud.list(&folder_id).await {
+			Ok(items) => items,
+			Err(e) => {
+				errors.push(e.into());
+				all_listed = false;
+				continue;
+			}
+		};
+
+		let last_indexed_count = indexed_paths.len();
+
+		for item in items {
+			let path = dir.join(&item.name);
+
+			if item.is_dir {
+				// Keeps the mirror browsable, and the sub path checks working, without downloading anything
+				if let Err(e) = fs::create_dir_all(&path).await {
+					errors.push(FileIOError::from((&path, e)).into());
+				}
+
+				if recursive {
+					to_list.push_back((path.clone(), item.id.clone()));
+				}
+			} else {
+				if !accepted_by_glob_rules(&path, indexer_rules) {
+					continue;
+				}
+
+				for ancestor in path.ancestors().skip(1) {
+					if !ancestor.starts_with(current_dir) {
+						break;
+					}
+
+					*paths_and_sizes.entry(ancestor.to_path_buf()).or_default() += item.size;
+				}
+
+				let (kind, hash) = item.provider_hash();
+				provider_hashes.insert(path.clone(), (item.id.clone(), kind, hash));
+			}
+
+			push_entry(
+				&mut indexed_paths,
+				&mut errors,
+				location_id,
+				location_path,
+				&path,
+				item.is_dir,
+				FilePathMetadata {
+					inode: item.pseudo_inode(),
+					size_in_bytes: item.size,
+					created_at: item.created_at,
+					modified_at: item.modified_at,
+					hidden: is_hidden(&path),
+				},
+			);
+		}
+
+		update_notifier(&dir, indexed_paths.len() - last_indexed_count);
+	}
+
+	// A folder we failed to list would have all its file paths taken as removed
+	let to_remove = if all_listed {
+		objects_to_remove(
+			location_id,
+			location_path,
+			current_dir,
+			&indexed_paths,
+			recursive,
+			db,
+		)
+		.await?
+	} else {
+		warn!("Skipping removal of file paths, as some cloud folders failed to be listed");
+		vec![]
+	};
+
+	let (walked, to_update) = filter_existing_paths(indexed_paths, file_paths_db_fetcher).await?;
+	let (walked, to_update) = (walked.collect::<Vec<_>>(), to_update.collect::<Vec<_>>());
+
+	// Unchanged files already have their hashes, and changed ones had their cas_id cleared by the
+	// indexer, so the file identifier picks the new hash up
+	db._batch(
+		walked
+			.iter()
+			.chain(&to_update)
+			.filter_map(|entry| {
+				let (item_id, kind, hash) =
+					provider_hashes.get(&location_path.join(&entry.iso_file_path))?;
+				let pub_id = uuid_to_bytes(entry.pub_id);
+
+				Some(db.provider_hash().upsert(
+					provider_hash::file_path_pub_id::equals(pub_id.clone()),
+					provider_hash::create_unchecked(
+						pub_id,
+						location_id,
+						*kind as i32,
+						hash.clone(),
+						item_id.clone(),
+						vec![],
+					),
+					vec![
+						provider_hash::kind::set(*kind as i32),
+						provider_hash::hash::set(hash.clone()),
+						provider_hash::item_id::set(item_id.clone()),
+						provider_hash::cas_id::set(None),
+					],
+				))
+			})
+			.collect::<Vec<_>>(),
+	)
+	.await?;
+
+	if !to_remove.is_empty() {
+		db.provider_hash()
+			.delete_many(vec![provider_hash::file_path_pub_id::in_vec(
+				to_remove
+					.iter()
+					.map(|file_path| file_path.pub_id.clone())
+					.collect(),
+			)])
+			.exec()
+			.await?;
+	}
+
+	Ok(WalkResult {
+		walked: walked.into_iter(),
+		to_update: to_update.into_iter(),
+		to_walk: VecDeque::new(),
+		to_remove: to_remove.into_iter(),
+		errors,
+		paths_and_sizes,
+	})
+}
@@ -1,6 +1,12 @@
-use crate::library::Library;
+use crate::{
+	library::Library,
+	location::{
+		cloud_metadata::CloudMetadataError, mtp::MtpError, s3::S3Error, webdav::WebDavError,
+	},
+};
 
 use sd_core_file_path_helper::{FilePathError, IsolatedFilePathData, IsolatedFilePathDataParts};
+use sd_core_heavy_lifting::file_identifier;
 use sd_core_indexer_rules::IndexerRuleError;
 use sd_core_prisma_helpers::file_path_pub_and_cas_ids;
 
@@ -24,9 +30,13 @@ use tracing::{trace, warn};
 
 use super::location_with_indexer_rules;
 
+mod cloud_metadata_walk;
+mod mtp_walk;
 pub mod old_indexer_job;
 mod old_shallow;
 mod old_walk;
+mod s3_walk;
+mod webdav_walk;
 
 use old_walk::WalkedEntry;
 
@@ -61,6 +71,14 @@ pub enum IndexerError {
 	FileIO(#[from] FileIOError),
 	#[error(transparent)]
 	FilePath(#[from] FilePathError),
+	#[error(transparent)]
+	S3(#[from] S3Error),
+	#[error(transparent)]
+	WebDav(#[from] WebDavError),
+	#[error(transparent)]
+	CloudMetadata(#[from] CloudMetadataError),
+	#[error(transparent)]
+	Mtp(#[from] MtpError),
 
 	// Mixed errors
 	#[error(transparent)]
@@ -239,13 +257,50 @@ async fn execute_indexer_update_step(
 		.into_iter()
 		.unzip();
 
-	let updated = sync
-		.write_ops(
-			db,
-			(sync_stuff.into_iter().flatten().collect(), paths_to_update),
-		)
+	let pub_ids = &update_step
+		.to_update
+		.iter()
+		.map(|entry| sd_utils::uuid_to_bytes(entry.pub_id))
+		.collect::<Vec<_>>();
+
+	let updated = db
+		._transaction()
+		.with_timeout(30 * 1000)
+		.run(|db| async move {
+			let before = file_identifier::statistics::snapshot(
+				vec![file_path::pub_id::in_vec(pub_ids.clone())],
+				&db,
+			)
+			.await?;
+
+			let updated = sync
+				.write_ops(
+					&db,
+					(sync_stuff.into_iter().flatten().collect(), paths_to_update),
+				)
+				.await?;
+
+			file_identifier::statistics::snapshot(
+				vec![file_path::pub_id::in_vec(pub_ids.clone())],
+				&db,
+			)
+			.await?
+			.since(before)
+			.commit(&db)
+			.await?;
+
+			Ok::<_, IndexerError>(updated)
+		})
 		.await?;
 
+	// The content of these files changed, so entries walked from archives among them are stale
+	file_identifier::remove_archive_entries(
+		updated.iter().map(|file_path| file_path.id).collect(),
+		db,
+		sync,
+	)
+	.await?;
+
 	trace!("Updated {updated:?} records");
 
 	Ok(updated.len() as i64)
@@ -265,6 +320,15 @@ async fn remove_non_existing_file_paths(
 	db: &PrismaClient,
 	sync: &sd_core_sync::Manager,
 ) -> Result<u64, IndexerError> {
+	let to_remove = to_remove.into_iter().collect::<Vec<_>>();
+
+	file_identifier::remove_archive_entries(
+		to_remove.iter().map(|file_path| file_path.id).collect(),
+		db,
+		sync,
+	)
+	.await?;
+
 	let (sync_params, db_params): (Vec<_>, Vec<_>) = to_remove
 		.into_iter()
 		.map(|d| {
@@ -275,15 +339,26 @@ async fn remove_non_existing_file_paths(
 		})
 		.unzip();
 
-	sync.write_ops(
-		db,
-		(
-			sync_params,
-			db.file_path()
-				.delete_many(vec![file_path::id::in_vec(db_params)]),
-		),
-	)
-	.await?;
+	db._transaction()
+		.with_timeout(30 * 1000)
+		.run(|db| async move {
+			file_identifier::statistics::forget(
+				vec![file_path::id::in_vec(db_params.clone())],
+				&db,
+			)
+			.await?;
+
+			sync.write_ops(
+				&db,
+				(
+					sync_params,
+					db.file_path()
+						.delete_many(vec![file_path::id::in_vec(db_params)]),
+				),
+			)
+			.await
+		})
+		.await?;
 
 	Ok(0)
 }
@@ -0,0 +1,147 @@
+use crate::location::mtp::MtpLocation;
+
+use sd_core_file_path_helper::FilePathMetadata;
+use sd_core_indexer_rules::IndexerRule;
+use sd_core_prisma_helpers::{file_path_pub_and_cas_ids, file_path_walker};
+
+use sd_prisma::prisma::{file_path, location, PrismaClient};
+use sd_utils::error::FileIOError;
+
+use std::{
+	collections::{HashMap, HashSet, VecDeque},
+	future::Future,
+	path::{Path, PathBuf},
+};
+
+use tokio::fs;
+use tracing::warn;
+
+use super::{
+	old_walk::{filter_existing_paths, WalkResult, WalkedEntry},
+	s3_walk::{accepted_by_glob_rules, is_hidden, objects_to_remove, push_entry},
+	IndexerError,
+};
+
+/// Same as [`webdav_walk`](super::webdav_walk::webdav_walk), but listing folders of a storage of
+/// an MTP device, which only gets the directory skeleton created in the location's mirror.
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn mtp_walk<FilePathDBFetcherFut>(
+	location_id: location::id::Type,
+	location_path: impl AsRef<Path>,
+	current_dir: impl AsRef<Path>,
+	indexer_rules: &[IndexerRule],
+	mtp: &MtpLocation,
+	recursive: bool,
+	mut update_notifier: impl FnMut(&Path, usize),
+	file_paths_db_fetcher: impl Fn(Vec<file_path::WhereParam>) -> FilePathDBFetcherFut,
+	db: &PrismaClient,
+) -> Result<
+	WalkResult<
+		impl Iterator<Item = WalkedEntry>,
+		impl Iterator<Item = WalkedEntry>,
+		impl Iterator<Item = file_path_pub_and_cas_ids::Data>,
+	>,
+	IndexerError,
+>
+where
+	FilePathDBFetcherFut: Future<Output = Result<Vec<file_path_walker::Data>, IndexerError>>,
+{
+	let location_path = location_path.as_ref();
+	let current_dir = current_dir.as_ref();
+
+	let mut to_list = VecDeque::from([current_dir.to_path_buf()]);
+	let mut indexed_paths = HashSet::new();
+	let mut paths_and_sizes = HashMap::<PathBuf, u64>::new();
+	let mut errors = vec![];
+	let mut all_listed = true;
+
+	while let Some(dir) = to_list.pop_front() {
+		let entries = match mtp
+			.list(dir.strip_prefix(location_path).unwrap_or(Path::new("")))
+			.await
+		{
+			Ok(Some(entries)) => entries,
+			Ok(None) => return Err(IndexerError::SubPathNotFound(dir.into())),
+			Err(e) => {
+				errors.push(e.into());
+				all_listed = false;
+				continue;
+			}
+		};
+
+		let last_indexed_count = indexed_paths.len();
+
+		for entry in entries {
+			let path = location_path.join(&entry.path);
+
+			if entry.object.is_folder {
+				// Keeps the mirror browsable, and the sub path checks working, without copying anything
+				if let Err(e) = fs::create_dir_all(&path).await {
+					errors.push(FileIOError::from((&path, e)).into());
+				}
+
+				if recursive {
+					to_list.push_back(path.clone());
+				}
+			} else {
+				if !accepted_by_glob_rules(&path, indexer_rules) {
+					continue;
+				}
+
+				for ancestor in path.ancestors().skip(1) {
+					if !ancestor.starts_with(current_dir) {
+						break;
+					}
+
+					*paths_and_sizes.entry(ancestor.to_path_buf()).or_default() +=
+						entry.object.size;
+				}
+			}
+
+			push_entry(
+				&mut indexed_paths,
+				&mut errors,
+				location_id,
+				location_path,
+				&path,
+				entry.object.is_folder,
+				FilePathMetadata {
+					inode: entry.pseudo_inode(),
+					size_in_bytes: entry.object.size,
+					created_at: entry.object.modified_at,
+					modified_at: entry.object.modified_at,
+					hidden: is_hidden(&path),
+				},
+			);
+		}
+
+		update_notifier(&dir, indexed_paths.len() - last_indexed_count);
+	}
+
+	// A folder we failed to list would have all its file paths taken as removed
+	let to_remove = if all_listed {
+		objects_to_remove(
+			location_id,
+			location_path,
+			current_dir,
+			&indexed_paths,
+			recursive,
+			db,
+		)
+		.await?
+	} else {
+		warn!("Skipping removal of file paths, as some MTP folders failed to be listed");
+		vec![]
+	};
+
+	let (walked, to_update) = filter_existing_paths(indexed_paths, file_paths_db_fetcher).await?;
+
+	Ok(WalkResult {
+		walked,
+		to_update,
+		to_walk: VecDeque::new(),
+		to_remove: to_remove.into_iter(),
+		errors,
+		paths_and_sizes,
+	})
+}
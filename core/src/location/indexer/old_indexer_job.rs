@@ -1,7 +1,10 @@
 use crate::{
 	file_paths_db_fetcher_fn, invalidate_query,
 	library::Library,
-	location::{location_with_indexer_rules, update_location_size, ScanState},
+	location::{
+		cloud_metadata::CloudMetadataLocation, location_with_indexer_rules, mtp::MtpLocation,
+		s3::S3Location, update_location_size, webdav::WebDavLocation, ScanState,
+	},
 	old_job::{
 		CurrentStep, JobError, JobInitOutput, JobReportUpdate, JobResult, JobRunMetadata,
 		JobStepOutput, StatefulJob, WorkerContext,
@@ -38,10 +41,14 @@ use tokio::time::Instant;
 use tracing::{debug, info, warn};
 
 use super::{
+	cloud_metadata_walk::cloud_metadata_walk,
 	execute_indexer_save_step, execute_indexer_update_step, iso_file_path_factory,
+	mtp_walk::mtp_walk,
 	old_walk::{keep_walking, walk, ToWalkEntry, WalkResult},
-	remove_non_existing_file_paths, reverse_update_directories_sizes, IndexerError,
-	OldIndexerJobSaveStep, OldIndexerJobUpdateStep,
+	remove_non_existing_file_paths, reverse_update_directories_sizes,
+	s3_walk::s3_walk,
+	webdav_walk::webdav_walk,
+	IndexerError, OldIndexerJobSaveStep, OldIndexerJobUpdateStep,
 };
 
 /// BATCH_SIZE is the number of files to index at each step, writing the chunk of files metadata in the database.
@@ -151,8 +158,8 @@ impl StatefulJob for OldIndexerJobInit {
 	const NAME: &'static str = "indexer";
 	const IS_BATCHED: bool = true;
 
-	fn target_location(&self) -> location::id::Type {
-		self.location.id
+	fn target_location(&self) -> Option<location::id::Type> {
+		Some(self.location.id)
 	}
 
 	/// Creates a vector of valid path buffers from a directory, chunked into batches of `BATCH_SIZE`.
@@ -200,26 +207,166 @@ impl StatefulJob for OldIndexerJobInit {
 		};
 
 		let scan_start = Instant::now();
-		let WalkResult {
-			walked,
-			to_update,
-			to_walk,
-			to_remove,
-			errors,
-			paths_and_sizes,
-		} = walk(
-			&location_path,
-			&to_walk_path,
-			&indexer_rules,
-			update_notifier_fn(ctx),
-			file_paths_db_fetcher_fn!(&db),
-			to_remove_db_fetcher_fn!(location_id, &db),
-			iso_file_path_factory(location_id, location_path),
-			50_000,
-		)
-		.await?;
+		let (walked, to_update, to_walk, to_remove, errors, paths_and_sizes) = if let Some(s3) =
+			S3Location::for_location(location_id, init.location.s3_config.as_deref())
+				.await
+				.map_err(IndexerError::from)?
+		{
+			let WalkResult {
+				walked,
+				to_update,
+				to_walk,
+				to_remove,
+				errors,
+				paths_and_sizes,
+			} = s3_walk(
+				location_id,
+				&location_path,
+				&to_walk_path,
+				&indexer_rules,
+				&s3,
+				update_notifier_fn(ctx),
+				file_paths_db_fetcher_fn!(&db),
+				&db,
+			)
+			.await?;
+
+			(
+				walked.collect::<Vec<_>>(),
+				to_update.collect::<Vec<_>>(),
+				to_walk,
+				to_remove.collect::<Vec<_>>(),
+				errors,
+				paths_and_sizes,
+			)
+		} else if let Some(webdav) =
+			WebDavLocation::for_location(location_id, init.location.webdav_config.as_deref())
+				.map_err(IndexerError::from)?
+		{
+			let WalkResult {
+				walked,
+				to_update,
+				to_walk,
+				to_remove,
+				errors,
+				paths_and_sizes,
+			} = webdav_walk(
+				location_id,
+				&location_path,
+				&to_walk_path,
+				&indexer_rules,
+				&webdav,
+				true,
+				update_notifier_fn(ctx),
+				file_paths_db_fetcher_fn!(&db),
+				&db,
+			)
+			.await?;
+
+			(
+				walked.collect::<Vec<_>>(),
+				to_update.collect::<Vec<_>>(),
+				to_walk,
+				to_remove.collect::<Vec<_>>(),
+				errors,
+				paths_and_sizes,
+			)
+		} else if let Some(cloud) =
+			CloudMetadataLocation::for_location(location_id, init.location.cloud_config.as_deref())
+				.await
+				.map_err(IndexerError::from)?
+		{
+			let WalkResult {
+				walked,
+				to_update,
+				to_walk,
+				to_remove,
+				errors,
+				paths_and_sizes,
+			} = cloud_metadata_walk(
+				location_id,
+				&location_path,
+				&to_walk_path,
+				&indexer_rules,
+				&cloud,
+				true,
+				update_notifier_fn(ctx),
+				file_paths_db_fetcher_fn!(&db),
+				&db,
+			)
+			.await?;
+
+			(
+				walked.collect::<Vec<_>>(),
+				to_update.collect::<Vec<_>>(),
+				to_walk,
+				to_remove.collect::<Vec<_>>(),
+				errors,
+				paths_and_sizes,
+			)
+		} else if let Some(mtp) =
+			MtpLocation::for_location(location_id, init.location.mtp_config.as_deref())
+				.await
+				.map_err(IndexerError::from)?
+		{
+			let WalkResult {
+				walked,
+				to_update,
+				to_walk,
+				to_remove,
+				errors,
+				paths_and_sizes,
+			} = mtp_walk(
+				location_id,
+				&location_path,
+				&to_walk_path,
+				&indexer_rules,
+				&mtp,
+				true,
+				update_notifier_fn(ctx),
+				file_paths_db_fetcher_fn!(&db),
+				&db,
+			)
+			.await?;
+
+			(
+				walked.collect::<Vec<_>>(),
+				to_update.collect::<Vec<_>>(),
+				to_walk,
+				to_remove.collect::<Vec<_>>(),
+				errors,
+				paths_and_sizes,
+			)
+		} else {
+			let WalkResult {
+				walked,
+				to_update,
+				to_walk,
+				to_remove,
+				errors,
+				paths_and_sizes,
+			} = walk(
+				&location_path,
+				&to_walk_path,
+				&indexer_rules,
+				update_notifier_fn(ctx),
+				file_paths_db_fetcher_fn!(&db),
+				to_remove_db_fetcher_fn!(location_id, &db),
+				iso_file_path_factory(location_id, location_path),
+				50_000,
+			)
+			.await?;
+
+			(
+				walked.collect::<Vec<_>>(),
+				to_update.collect::<Vec<_>>(),
+				to_walk,
+				to_remove.collect::<Vec<_>>(),
+				errors,
+				paths_and_sizes,
+			)
+		};
 		let scan_read_time = scan_start.elapsed();
-		let to_remove = to_remove.collect::<Vec<_>>();
 
 		debug!(
 			"Walker at indexer job found {} file_paths to be removed",
@@ -249,6 +396,7 @@ impl StatefulJob for OldIndexerJobInit {
 		let to_update_chunks = &mut 0;
 
 		let steps = walked
+			.into_iter()
 			.chunks(BATCH_SIZE)
 			.into_iter()
 			.enumerate()
@@ -265,6 +413,7 @@ impl StatefulJob for OldIndexerJobInit {
 			})
 			.chain(
 				to_update
+					.into_iter()
 					.chunks(BATCH_SIZE)
 					.into_iter()
 					.enumerate()
@@ -2,10 +2,14 @@ use crate::{
 	file_paths_db_fetcher_fn, invalidate_query,
 	library::Library,
 	location::{
+		cloud_metadata::CloudMetadataLocation,
 		indexer::{
 			execute_indexer_update_step, reverse_update_directories_sizes, OldIndexerJobUpdateStep,
 		},
+		mtp::MtpLocation,
+		s3::S3Location,
 		scan_location_sub_path, update_location_size,
+		webdav::WebDavLocation,
 	},
 	old_job::JobError,
 	to_remove_db_fetcher_fn, Node,
@@ -30,8 +34,14 @@ use itertools::Itertools;
 use tracing::{debug, error};
 
 use super::{
+	cloud_metadata_walk::cloud_metadata_walk,
 	execute_indexer_save_step, iso_file_path_factory, location_with_indexer_rules,
-	old_walk::walk_single_dir, remove_non_existing_file_paths, IndexerError, OldIndexerJobSaveStep,
+	mtp_walk::mtp_walk,
+	old_walk::{walk_single_dir, WalkResult},
+	remove_non_existing_file_paths,
+	s3_walk::s3_walk,
+	webdav_walk::webdav_walk,
+	IndexerError, OldIndexerJobSaveStep,
 };
 
 /// BATCH_SIZE is the number of files to index at each step, writing the chunk of files metadata in the database.
@@ -77,8 +87,127 @@ pub async fn old_shallow(
 		(false, location_path.to_path_buf())
 	};
 
-	let (walked, to_update, to_remove, errors, _s) = {
-		walk_single_dir(
+	let maybe_s3 = S3Location::for_location(location_id, location.s3_config.as_deref())
+		.await
+		.map_err(IndexerError::from)?;
+
+	let maybe_webdav = WebDavLocation::for_location(location_id, location.webdav_config.as_deref())
+		.map_err(IndexerError::from)?;
+
+	let maybe_cloud =
+		CloudMetadataLocation::for_location(location_id, location.cloud_config.as_deref())
+			.await
+			.map_err(IndexerError::from)?;
+
+	let maybe_mtp = MtpLocation::for_location(location_id, location.mtp_config.as_deref())
+		.await
+		.map_err(IndexerError::from)?;
+
+	let (walked, to_update, to_remove, errors) = if let Some(s3) = &maybe_s3 {
+		let WalkResult {
+			walked,
+			to_update,
+			to_remove,
+			errors,
+			..
+		} = s3_walk(
+			location_id,
+			location_path,
+			&to_walk_path,
+			&indexer_rules,
+			s3,
+			|_, _| {},
+			file_paths_db_fetcher_fn!(&db),
+			&db,
+		)
+		.await?;
+
+		(
+			walked.collect::<Vec<_>>(),
+			to_update.collect::<Vec<_>>(),
+			to_remove.collect::<Vec<_>>(),
+			errors,
+		)
+	} else if let Some(webdav) = &maybe_webdav {
+		let WalkResult {
+			walked,
+			to_update,
+			to_remove,
+			errors,
+			..
+		} = webdav_walk(
+			location_id,
+			location_path,
+			&to_walk_path,
+			&indexer_rules,
+			webdav,
+			false,
+			|_, _| {},
+			file_paths_db_fetcher_fn!(&db),
+			&db,
+		)
+		.await?;
+
+		(
+			walked.collect::<Vec<_>>(),
+			to_update.collect::<Vec<_>>(),
+			to_remove.collect::<Vec<_>>(),
+			errors,
+		)
+	} else if let Some(cloud) = &maybe_cloud {
+		let WalkResult {
+			walked,
+			to_update,
+			to_remove,
+			errors,
+			..
+		} = cloud_metadata_walk(
+			location_id,
+			location_path,
+			&to_walk_path,
+			&indexer_rules,
+			cloud,
+			false,
+			|_, _| {},
+			file_paths_db_fetcher_fn!(&db),
+			&db,
+		)
+		.await?;
+
+		(
+			walked.collect::<Vec<_>>(),
+			to_update.collect::<Vec<_>>(),
+			to_remove.collect::<Vec<_>>(),
+			errors,
+		)
+	} else if let Some(mtp) = &maybe_mtp {
+		let WalkResult {
+			walked,
+			to_update,
+			to_remove,
+			errors,
+			..
+		} = mtp_walk(
+			location_id,
+			location_path,
+			&to_walk_path,
+			&indexer_rules,
+			mtp,
+			false,
+			|_, _| {},
+			file_paths_db_fetcher_fn!(&db),
+			&db,
+		)
+		.await?;
+
+		(
+			walked.collect::<Vec<_>>(),
+			to_update.collect::<Vec<_>>(),
+			to_remove.collect::<Vec<_>>(),
+			errors,
+		)
+	} else {
+		let (walked, to_update, to_remove, errors, _s) = walk_single_dir(
 			location_path,
 			&to_walk_path,
 			&indexer_rules,
@@ -87,7 +216,14 @@ pub async fn old_shallow(
 			iso_file_path_factory(location_id, location_path),
 			add_root,
 		)
-		.await?
+		.await?;
+
+		(
+			walked.collect::<Vec<_>>(),
+			to_update.collect::<Vec<_>>(),
+			to_remove,
+			errors,
+		)
 	};
 
 	let to_remove_count = to_remove.len();
@@ -111,6 +247,7 @@ pub async fn old_shallow(
 	let mut to_create_count = 0;
 
 	let save_steps = walked
+		.into_iter()
 		.chunks(BATCH_SIZE)
 		.into_iter()
 		.enumerate()
@@ -118,14 +255,17 @@ pub async fn old_shallow(
 			let walked = chunk.collect::<Vec<_>>();
 			to_create_count += walked.len();
 
-			walked
-				.iter()
-				.filter_map(|walked_entry| {
-					walked_entry.iso_file_path.materialized_path_for_children()
-				})
-				.for_each(|new_dir| {
-					new_directories_to_scan.insert(new_dir);
-				});
+			// Buckets are listed all the way down, so there are no new directories left to scan
+			if maybe_s3.is_none() {
+				walked
+					.iter()
+					.filter_map(|walked_entry| {
+						walked_entry.iso_file_path.materialized_path_for_children()
+					})
+					.for_each(|new_dir| {
+						new_directories_to_scan.insert(new_dir);
+					});
+			}
 
 			OldIndexerJobSaveStep {
 				chunk_idx: i,
@@ -153,6 +293,7 @@ pub async fn old_shallow(
 	let mut to_update_count = 0;
 
 	let update_steps = to_update
+		.into_iter()
 		.chunks(BATCH_SIZE)
 		.into_iter()
 		.enumerate()
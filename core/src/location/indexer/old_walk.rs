@@ -1,6 +1,6 @@
 use sd_core_file_path_helper::{FilePathMetadata, IsolatedFilePathData};
 use sd_core_indexer_rules::{
-	seed::{GitIgnoreRules, GITIGNORE},
+	seed::{GitIgnoreRules, SdIgnoreRules, GITIGNORE},
 	IndexerRule, RuleKind,
 };
 use sd_core_prisma_helpers::{file_path_pub_and_cas_ids, file_path_walker};
@@ -46,9 +46,9 @@ pub struct ToWalkEntry {
 }
 
 #[derive(Debug)]
-struct WalkingEntry {
-	iso_file_path: IsolatedFilePathData<'static>,
-	maybe_metadata: Option<FilePathMetadata>,
+pub(super) struct WalkingEntry {
+	pub(super) iso_file_path: IsolatedFilePathData<'static>,
+	pub(super) maybe_metadata: Option<FilePathMetadata>,
 }
 
 impl From<WalkingEntry> for WalkedEntry {
@@ -341,7 +341,7 @@ where
 	Ok((walked, to_update, to_remove, errors, root_size))
 }
 
-async fn filter_existing_paths<F>(
+pub(super) async fn filter_existing_paths<F>(
 	indexed_paths: HashSet<WalkingEntry>,
 	file_paths_db_fetcher: impl Fn(Vec<file_path::WhereParam>) -> F,
 ) -> Result<
@@ -486,6 +486,10 @@ where
 		}
 	}
 
+	if let Some(pat) = SdIgnoreRules::get_rules(library_root.as_ref(), path).await {
+		rules.extend(pat.into_iter().map(Into::into));
+	}
+
 	let current_dir = current_dir.as_ref();
 
 	// Just to make sure...
@@ -0,0 +1,277 @@
+use crate::location::s3::{object_key, S3Location, S3Object};
+
+use sd_core_file_path_helper::{FilePathMetadata, IsolatedFilePathData};
+use sd_core_indexer_rules::{IndexerRule, RulePerKind};
+use sd_core_prisma_helpers::{file_path_pub_and_cas_ids, file_path_walker};
+
+use sd_prisma::prisma::{file_path, location, PrismaClient};
+use sd_utils::error::FileIOError;
+
+use std::{
+	collections::{HashMap, HashSet, VecDeque},
+	future::Future,
+	path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use tokio::fs;
+
+use super::{
+	old_walk::{filter_existing_paths, WalkResult, WalkedEntry, WalkingEntry},
+	IndexerError,
+};
+
+/// Same as [`walk`](super::old_walk::walk), but listing the objects of a S3 location's bucket
+/// instead of reading its local mirror directory, which only gets the directory skeleton created.
+///
+/// Buckets are flat, so directories are made up from the objects' key prefixes, and every object
+/// is listed at once, leaving nothing else to be walked. Only glob rules apply to objects, as
+/// there are no directories to look into.
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn s3_walk<FilePathDBFetcherFut>(
+	location_id: location::id::Type,
+	location_path: impl AsRef<Path>,
+	current_dir: impl AsRef<Path>,
+	indexer_rules: &[IndexerRule],
+	s3: &S3Location,
+	mut update_notifier: impl FnMut(&Path, usize),
+	file_paths_db_fetcher: impl Fn(Vec<file_path::WhereParam>) -> FilePathDBFetcherFut,
+	db: &PrismaClient,
+) -> Result<
+	WalkResult<
+		impl Iterator<Item = WalkedEntry>,
+		impl Iterator<Item = WalkedEntry>,
+		impl Iterator<Item = file_path_pub_and_cas_ids::Data>,
+	>,
+	IndexerError,
+>
+where
+	FilePathDBFetcherFut: Future<Output = Result<Vec<file_path_walker::Data>, IndexerError>>,
+{
+	let location_path = location_path.as_ref();
+	let current_dir = current_dir.as_ref();
+
+	let sub_path = object_key(
+		current_dir
+			.strip_prefix(location_path)
+			.unwrap_or(Path::new("")),
+	);
+
+	let objects = s3.list(&sub_path).await?;
+
+	let mut files = Vec::with_capacity(objects.len());
+	// Directory path to the latest modification of its objects, so they don't look updated on every scan
+	let mut dirs = HashMap::<PathBuf, DateTime<Utc>>::new();
+	let mut paths_and_sizes = HashMap::<PathBuf, u64>::new();
+
+	for object in objects {
+		let path = location_path.join(object.key.trim_end_matches('/'));
+
+		if !object.is_dir_marker() {
+			if !accepted_by_glob_rules(&path, indexer_rules) {
+				continue;
+			}
+
+			files.push((path.clone(), object.clone()));
+		}
+
+		let mut maybe_ancestor = if object.is_dir_marker() {
+			Some(path.as_path())
+		} else {
+			path.parent()
+		};
+
+		while let Some(ancestor) = maybe_ancestor {
+			if !ancestor.starts_with(current_dir) {
+				break;
+			}
+
+			*paths_and_sizes.entry(ancestor.to_path_buf()).or_default() += object.size;
+
+			// The walked directory itself is already on database
+			if ancestor != current_dir {
+				let last_modified = dirs
+					.entry(ancestor.to_path_buf())
+					.or_insert(object.last_modified);
+				*last_modified = (*last_modified).max(object.last_modified);
+			}
+
+			maybe_ancestor = ancestor.parent();
+		}
+	}
+
+	update_notifier(current_dir, files.len() + dirs.len());
+
+	let mut errors = vec![];
+	let mut indexed_paths = HashSet::with_capacity(files.len() + dirs.len());
+
+	for (path, last_modified) in &dirs {
+		// Keeps the mirror browsable, and the sub path checks working, without downloading anything
+		if let Err(e) = fs::create_dir_all(path).await {
+			errors.push(FileIOError::from((path, e)).into());
+		}
+
+		let dir_key = path.strip_prefix(location_path).unwrap_or(path);
+
+		push_entry(
+			&mut indexed_paths,
+			&mut errors,
+			location_id,
+			location_path,
+			path,
+			true,
+			FilePathMetadata {
+				inode: S3Object {
+					key: format!("{}/", object_key(dir_key)),
+					size: 0,
+					last_modified: *last_modified,
+					etag: None,
+				}
+				.pseudo_inode(),
+				size_in_bytes: 0,
+				created_at: *last_modified,
+				modified_at: *last_modified,
+				hidden: is_hidden(path),
+			},
+		);
+	}
+
+	for (path, object) in &files {
+		push_entry(
+			&mut indexed_paths,
+			&mut errors,
+			location_id,
+			location_path,
+			path,
+			false,
+			FilePathMetadata {
+				inode: object.pseudo_inode(),
+				size_in_bytes: object.size,
+				created_at: object.last_modified,
+				modified_at: object.last_modified,
+				hidden: is_hidden(path),
+			},
+		);
+	}
+
+	let to_remove = objects_to_remove(
+		location_id,
+		location_path,
+		current_dir,
+		&indexed_paths,
+		true,
+		db,
+	)
+	.await?;
+
+	let (walked, to_update) = filter_existing_paths(indexed_paths, file_paths_db_fetcher).await?;
+
+	Ok(WalkResult {
+		walked,
+		to_update,
+		to_walk: VecDeque::new(),
+		to_remove: to_remove.into_iter(),
+		errors,
+		paths_and_sizes,
+	})
+}
+
+pub(super) fn push_entry(
+	indexed_paths: &mut HashSet<WalkingEntry>,
+	errors: &mut Vec<IndexerError>,
+	location_id: location::id::Type,
+	location_path: &Path,
+	path: &Path,
+	is_dir: bool,
+	metadata: FilePathMetadata,
+) {
+	match IsolatedFilePathData::new(location_id, location_path, path, is_dir) {
+		Ok(iso_file_path) => {
+			indexed_paths.insert(WalkingEntry {
+				iso_file_path,
+				maybe_metadata: Some(metadata),
+			});
+		}
+		Err(e) => errors.push(e.into()),
+	}
+}
+
+/// File paths under the walked directory, or only its direct children if it wasn't walked
+/// `recursive`ly, which weren't listed anymore
+pub(super) async fn objects_to_remove(
+	location_id: location::id::Type,
+	location_path: &Path,
+	current_dir: &Path,
+	indexed_paths: &HashSet<WalkingEntry>,
+	recursive: bool,
+	db: &PrismaClient,
+) -> Result<Vec<file_path_pub_and_cas_ids::Data>, IndexerError> {
+	let children_materialized_path =
+		IsolatedFilePathData::new(location_id, location_path, current_dir, true)?
+			.materialized_path_for_children()
+			.expect("the walked path is always a directory");
+
+	let indexed = indexed_paths
+		.iter()
+		.map(|entry| {
+			let parts = entry.iso_file_path.to_parts();
+			(
+				parts.materialized_path.to_string(),
+				parts.name.to_string(),
+				parts.extension.to_string(),
+			)
+		})
+		.collect::<HashSet<_>>();
+
+	Ok(db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(Some(location_id)),
+			if recursive {
+				file_path::materialized_path::starts_with(children_materialized_path)
+			} else {
+				file_path::materialized_path::equals(Some(children_materialized_path))
+			},
+		])
+		.select(file_path::select!({ id pub_id cas_id materialized_path name extension }))
+		.exec()
+		.await?
+		.into_iter()
+		.filter(|file_path| {
+			!indexed.contains(&(
+				file_path.materialized_path.clone().unwrap_or_default(),
+				file_path.name.clone().unwrap_or_default(),
+				file_path.extension.clone().unwrap_or_default(),
+			))
+		})
+		.map(|file_path| file_path_pub_and_cas_ids::Data {
+			id: file_path.id,
+			pub_id: file_path.pub_id,
+			cas_id: file_path.cas_id,
+		})
+		.collect())
+}
+
+pub(super) fn accepted_by_glob_rules(path: &Path, indexer_rules: &[IndexerRule]) -> bool {
+	let rules = indexer_rules.iter().flat_map(|rule| &rule.rules);
+
+	let rejected = rules.clone().any(|rule| {
+		matches!(rule, RulePerKind::RejectFilesByGlob(_, reject_glob_set) if reject_glob_set.is_match(path))
+	});
+
+	let mut accept_glob_sets = rules
+		.filter_map(|rule| match rule {
+			RulePerKind::AcceptFilesByGlob(_, accept_glob_set) => Some(accept_glob_set),
+			_ => None,
+		})
+		.peekable();
+
+	!rejected
+		&& (accept_glob_sets.peek().is_none()
+			|| accept_glob_sets.any(|accept_glob_set| accept_glob_set.is_match(path)))
+}
+
+pub(super) fn is_hidden(path: &Path) -> bool {
+	path.file_name()
+		.map_or(false, |name| name.to_string_lossy().starts_with('.'))
+}
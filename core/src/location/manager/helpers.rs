@@ -1,8 +1,16 @@
 use crate::{
+	api::notifications::{NotificationData, NotificationKind},
 	library::{Library, LibraryId},
+	location::{
+		find_location,
+		mtp::{self, MtpLocationConfig},
+		scan_location, ScanState,
+	},
 	Node,
 };
 
+use sd_core_prisma_helpers::location_with_indexer_rules;
+
 use sd_prisma::prisma::location;
 use sd_utils::db::maybe_missing;
 
@@ -34,6 +42,20 @@ pub(super) async fn check_online(
 
 	// TODO(N): This isn't gonna work with removable media and this will likely permanently break if the DB is restored from a backup.
 	if location.instance_id == Some(library.config().await.instance_id) {
+		// The mirror directory of devices is always there, they're online while plugged in
+		if let Some(mtp) = MtpLocationConfig::from_db(location.id, location.mtp_config.as_deref())?
+		{
+			let is_connected = mtp::is_connected(&mtp).await?;
+
+			if is_connected {
+				node.locations.add_online(pub_id).await;
+			} else {
+				node.locations.remove_online(&pub_id).await;
+			}
+
+			return Ok(is_connected);
+		}
+
 		match fs::metadata(&location_path).await {
 			Ok(_) => {
 				node.locations.add_online(pub_id).await;
@@ -55,6 +77,48 @@ pub(super) async fn check_online(
 	}
 }
 
+/// Devices get scanned as soon as they're plugged in, which imports their new photos and videos
+/// if the location has an import destination
+pub(super) async fn scan_plugged_in_device(
+	location_id: location::id::Type,
+	node: &Arc<Node>,
+	library: &Arc<Library>,
+) {
+	let location = match find_location(library, location_id)
+		.include(location_with_indexer_rules::include())
+		.exec()
+		.await
+	{
+		Ok(Some(location)) => location,
+		Ok(None) => return,
+		Err(e) => {
+			error!("Failed to fetch plugged in device location <id='{location_id}'>: {e:#?}");
+			return;
+		}
+	};
+
+	if let Err(e) = scan_location(node, library, location, ScanState::Pending).await {
+		error!("Failed to scan plugged in device location <id='{location_id}'>: {e:#?}");
+	}
+}
+
+/// Changes made to the drive of a snapshot location while it was away are only picked up by a
+/// reconcile, which users run when they want to
+pub(super) async fn notify_snapshot_reattached(location: &location::Data, node: &Node) {
+	let name = location.name.as_deref().unwrap_or("Snapshot location");
+
+	node.emit_notification(
+		NotificationData {
+			title: format!("{name} is connected"),
+			content: "Reconcile the location to pick up changes made while its drive was away"
+				.to_string(),
+			kind: NotificationKind::Info,
+		},
+		None,
+	)
+	.await;
+}
+
 pub(super) async fn location_check_sleep(
 	location_id: location::id::Type,
 	library: Arc<Library>,
@@ -75,6 +139,15 @@ pub(super) fn watch_location(
 		return;
 	};
 
+	// Remote locations are never watched, see the location manager's add handling
+	if location.s3_config.is_some()
+		|| location.webdav_config.is_some()
+		|| location.cloud_config.is_some()
+		|| location.mtp_config.is_some()
+	{
+		return;
+	}
+
 	if let Some(mut watcher) = locations_unwatched.remove(&(location_id, library_id)) {
 		if watcher.check_path(location_path) {
 			watcher.watch();
@@ -1,5 +1,6 @@
 use crate::{
 	library::{Library, LibraryManagerEvent},
+	location::mtp::MtpError,
 	old_job::JobManagerError,
 	Node,
 };
@@ -102,6 +103,8 @@ pub enum LocationManagerError {
 	JobManager(#[from] JobManagerError),
 	#[error("missing-field")]
 	MissingField(#[from] MissingFieldError),
+	#[error(transparent)]
+	Mtp(#[from] MtpError),
 
 	#[error(transparent)]
 	FileIO(#[from] FileIOError),
@@ -361,7 +364,8 @@ impl Locations {
 		use helpers::{
 			check_online, drop_location, get_location, handle_ignore_path_request,
 			handle_reinit_watcher_request, handle_remove_location_request,
-			handle_stop_watcher_request, location_check_sleep, unwatch_location, watch_location,
+			handle_stop_watcher_request, location_check_sleep, notify_snapshot_reattached,
+			scan_plugged_in_device, unwatch_location, watch_location,
 		};
 		use watcher::LocationWatcher;
 
@@ -388,11 +392,17 @@ impl Locations {
 							if let Some(location) = get_location(location_id, &library).await {
 								match check_online(&location, &node, &library).await {
 									Ok(is_online) => {
+										let is_remote = location.s3_config.is_some()
+											|| location.webdav_config.is_some()
+											|| location.cloud_config.is_some()
+											|| location.mtp_config.is_some();
 
 										LocationWatcher::new(location, library.clone(), node.clone())
 										.await
 										.map(|mut watcher| {
-											if is_online {
+											// Remote locations only change through their servers, and their
+											// mirror directory gets written by our own read-throughs and hydrations
+											if is_online && !is_remote {
 												watcher.watch();
 												locations_watched.insert(
 													(location_id, library.id),
@@ -498,6 +508,11 @@ impl Locations {
 					} else if let Some(location) = get_location(location_id, &library).await {
 						// TODO(N): This isn't gonna work with removable media and this will likely permanently break if the DB is restored from a backup.
 						if location.instance_id == Some(library.config().await.instance_id) {
+							let was_online = match Uuid::from_slice(&location.pub_id) {
+								Ok(pub_id) => node.locations.is_online(&pub_id).await,
+								Err(_) => false,
+							};
+
 							let is_online = match check_online(&location, &node, &library).await {
 								Ok(is_online) => is_online,
 								Err(e) => {
@@ -506,6 +521,14 @@ impl Locations {
 								}
 							};
 
+							if is_online && !was_online {
+								if location.mtp_config.is_some() {
+									scan_plugged_in_device(location_id, &node, &library).await;
+								} else if location.is_snapshot == Some(true) {
+									notify_snapshot_reattached(&location, &node).await;
+								}
+							}
+
 							if is_online
 								&& !forced_unwatch.contains(&key)
 							{
@@ -21,7 +21,10 @@ use tokio::{fs, time::Instant};
 use tracing::{debug, error, trace};
 
 use super::{
-	utils::{create_dir, recalculate_directories_size, remove, rename, update_file},
+	utils::{
+		create_dir, recalculate_directories_size, remove, rename, scan_queued_paths, update_file,
+		ToScan,
+	},
 	EventHandler, HUNDRED_MILLIS, ONE_SECOND,
 };
 
@@ -36,6 +39,7 @@ pub(super) struct AndroidEventHandler<'lib> {
 	files_to_update: HashMap<PathBuf, Instant>,
 	reincident_to_update_files: HashMap<PathBuf, Instant>,
 	to_recalculate_size: HashMap<PathBuf, Instant>,
+	to_scan: ToScan,
 	path_and_instant_buffer: Vec<(PathBuf, Instant)>,
 }
 
@@ -56,6 +60,7 @@ impl<'lib> EventHandler<'lib> for AndroidEventHandler<'lib> {
 			files_to_update: HashMap::new(),
 			reincident_to_update_files: HashMap::new(),
 			to_recalculate_size: HashMap::new(),
+			to_scan: ToScan::default(),
 			path_and_instant_buffer: Vec::new(),
 		}
 	}
@@ -92,8 +97,8 @@ impl<'lib> EventHandler<'lib> for AndroidEventHandler<'lib> {
 			EventKind::Create(CreateKind::Folder) => {
 				let path = &paths[0];
 
-				// Don't need to dispatch a recalculate directory event as `create_dir` dispatches
-				// a `scan_location_sub_path` function, which recalculates the size already
+				// Don't need to dispatch a recalculate directory event as `create_dir` queues the
+				// directory to be scanned, which recalculates the size already
 
 				create_dir(
 					self.location_id,
@@ -101,8 +106,8 @@ impl<'lib> EventHandler<'lib> for AndroidEventHandler<'lib> {
 					&fs::metadata(path)
 						.await
 						.map_err(|e| FileIOError::from((path, e)))?,
-					self.node,
 					self.library,
+					&mut self.to_scan,
 				)
 				.await?;
 			}
@@ -164,6 +169,15 @@ impl<'lib> EventHandler<'lib> for AndroidEventHandler<'lib> {
 			self.recently_renamed_from
 				.retain(|_, instant| instant.elapsed() < HUNDRED_MILLIS);
 
+			if !self.to_scan.is_empty() {
+				if let Err(e) =
+					scan_queued_paths(&mut self.to_scan, self.location_id, self.node, self.library)
+						.await
+				{
+					error!("Failed to scan queued paths: {e:#?}");
+				}
+			}
+
 			if !self.to_recalculate_size.is_empty() {
 				if let Err(e) = recalculate_directories_size(
 					&mut self.to_recalculate_size,
@@ -198,7 +212,14 @@ impl AndroidEventHandler<'_> {
 					}
 				}
 				self.reincident_to_update_files.remove(&path);
-				update_file(self.location_id, &path, self.node, self.library).await?;
+				update_file(
+					self.location_id,
+					&path,
+					self.node,
+					self.library,
+					&mut self.to_scan,
+				)
+				.await?;
 				should_invalidate = true;
 			}
 		}
@@ -222,7 +243,14 @@ impl AndroidEventHandler<'_> {
 					}
 				}
 				self.files_to_update.remove(&path);
-				update_file(self.location_id, &path, self.node, self.library).await?;
+				update_file(
+					self.location_id,
+					&path,
+					self.node,
+					self.library,
+					&mut self.to_scan,
+				)
+				.await?;
 				should_invalidate = true;
 			}
 		}
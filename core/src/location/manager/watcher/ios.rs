@@ -26,7 +26,7 @@ use tracing::{debug, error, trace, warn};
 use super::{
 	utils::{
 		create_dir, create_file, extract_inode_from_path, extract_location_path,
-		recalculate_directories_size, remove, rename, update_file,
+		recalculate_directories_size, remove, rename, scan_queued_paths, update_file, ToScan,
 	},
 	EventHandler, INode, InstantAndPath, HUNDRED_MILLIS, ONE_SECOND,
 };
@@ -44,6 +44,7 @@ pub(super) struct IosEventHandler<'lib> {
 	new_paths_map: HashMap<INode, InstantAndPath>,
 	paths_map_buffer: Vec<(INode, InstantAndPath)>,
 	to_recalculate_size: HashMap<PathBuf, Instant>,
+	to_scan: ToScan,
 	path_and_instant_buffer: Vec<(PathBuf, Instant)>,
 }
 
@@ -69,6 +70,7 @@ impl<'lib> EventHandler<'lib> for IosEventHandler<'lib> {
 			new_paths_map: HashMap::new(),
 			paths_map_buffer: Vec::new(),
 			to_recalculate_size: HashMap::new(),
+			to_scan: ToScan::default(),
 			path_and_instant_buffer: Vec::new(),
 		}
 	}
@@ -88,8 +90,8 @@ impl<'lib> EventHandler<'lib> for IosEventHandler<'lib> {
 					&fs::metadata(path)
 						.await
 						.map_err(|e| FileIOError::from((path, e)))?,
-					self.node,
 					self.library,
+					&mut self.to_scan,
 				)
 				.await?;
 				self.latest_created_dir = Some(paths.remove(0));
@@ -158,6 +160,15 @@ impl<'lib> EventHandler<'lib> for IosEventHandler<'lib> {
 				error!("Failed to remove file_path: {e:#?}");
 			}
 
+			if !self.to_scan.is_empty() {
+				if let Err(e) =
+					scan_queued_paths(&mut self.to_scan, self.location_id, self.node, self.library)
+						.await
+				{
+					error!("Failed to scan queued paths: {e:#?}");
+				}
+			}
+
 			if !self.to_recalculate_size.is_empty() {
 				if let Err(e) = recalculate_directories_size(
 					&mut self.to_recalculate_size,
@@ -192,7 +203,14 @@ impl IosEventHandler<'_> {
 					}
 				}
 				self.reincident_to_update_files.remove(&path);
-				update_file(self.location_id, &path, self.node, self.library).await?;
+				update_file(
+					self.location_id,
+					&path,
+					self.node,
+					self.library,
+					&mut self.to_scan,
+				)
+				.await?;
 				should_invalidate = true;
 			}
 		}
@@ -216,7 +234,14 @@ impl IosEventHandler<'_> {
 					}
 				}
 				self.files_to_update.remove(&path);
-				update_file(self.location_id, &path, self.node, self.library).await?;
+				update_file(
+					self.location_id,
+					&path,
+					self.node,
+					self.library,
+					&mut self.to_scan,
+				)
+				.await?;
 				should_invalidate = true;
 			}
 		}
@@ -244,10 +269,16 @@ impl IosEventHandler<'_> {
 						.map_err(|e| FileIOError::from((&path, e)))?;
 
 					if metadata.is_dir() {
-						// Don't need to dispatch a recalculate directory event as `create_dir` dispatches
-						// a `scan_location_sub_path` function, which recalculates the size already
-						create_dir(self.location_id, &path, &metadata, self.node, self.library)
-							.await?;
+						// Don't need to dispatch a recalculate directory event as `create_dir` queues the
+						// directory to be scanned, which recalculates the size already
+						create_dir(
+							self.location_id,
+							&path,
+							&metadata,
+							self.library,
+							&mut self.to_scan,
+						)
+						.await?;
 					} else {
 						if let Some(parent) = path.parent() {
 							if parent != Path::new("") {
@@ -255,8 +286,15 @@ impl IosEventHandler<'_> {
 									.insert(parent.to_path_buf(), Instant::now());
 							}
 						}
-						create_file(self.location_id, &path, &metadata, self.node, self.library)
-							.await?;
+						create_file(
+							self.location_id,
+							&path,
+							&metadata,
+							self.node,
+							self.library,
+							&mut self.to_scan,
+						)
+						.await?;
 					}
 
 					trace!("Created file_path due timeout: {}", path.display());
@@ -26,7 +26,10 @@ use tokio::{fs, time::Instant};
 use tracing::{error, trace};
 
 use super::{
-	utils::{create_dir, recalculate_directories_size, remove, rename, update_file},
+	utils::{
+		create_dir, recalculate_directories_size, remove, rename, scan_queued_paths, update_file,
+		ToScan,
+	},
 	EventHandler, HUNDRED_MILLIS, ONE_SECOND,
 };
 
@@ -41,6 +44,7 @@ pub(super) struct LinuxEventHandler<'lib> {
 	files_to_update: HashMap<PathBuf, Instant>,
 	reincident_to_update_files: HashMap<PathBuf, Instant>,
 	to_recalculate_size: HashMap<PathBuf, Instant>,
+	to_scan: ToScan,
 	path_and_instant_buffer: Vec<(PathBuf, Instant)>,
 }
 
@@ -61,6 +65,7 @@ impl<'lib> EventHandler<'lib> for LinuxEventHandler<'lib> {
 			files_to_update: HashMap::new(),
 			reincident_to_update_files: HashMap::new(),
 			to_recalculate_size: HashMap::new(),
+			to_scan: ToScan::default(),
 			path_and_instant_buffer: Vec::new(),
 		}
 	}
@@ -97,8 +102,8 @@ impl<'lib> EventHandler<'lib> for LinuxEventHandler<'lib> {
 			EventKind::Create(CreateKind::Folder) => {
 				let path = &paths[0];
 
-				// Don't need to dispatch a recalculate directory event as `create_dir` dispatches
-				// a `scan_location_sub_path` function, which recalculates the size already
+				// Don't need to dispatch a recalculate directory event as `create_dir` queues the
+				// directory to be scanned, which recalculates the size already
 
 				create_dir(
 					self.location_id,
@@ -106,8 +111,8 @@ impl<'lib> EventHandler<'lib> for LinuxEventHandler<'lib> {
 					&fs::metadata(path)
 						.await
 						.map_err(|e| FileIOError::from((path, e)))?,
-					self.node,
 					self.library,
+					&mut self.to_scan,
 				)
 				.await?;
 			}
@@ -169,6 +174,15 @@ impl<'lib> EventHandler<'lib> for LinuxEventHandler<'lib> {
 			self.recently_renamed_from
 				.retain(|_, instant| instant.elapsed() < HUNDRED_MILLIS);
 
+			if !self.to_scan.is_empty() {
+				if let Err(e) =
+					scan_queued_paths(&mut self.to_scan, self.location_id, self.node, self.library)
+						.await
+				{
+					error!("Failed to scan queued paths: {e:#?}");
+				}
+			}
+
 			if !self.to_recalculate_size.is_empty() {
 				if let Err(e) = recalculate_directories_size(
 					&mut self.to_recalculate_size,
@@ -203,7 +217,14 @@ impl LinuxEventHandler<'_> {
 					}
 				}
 				self.reincident_to_update_files.remove(&path);
-				update_file(self.location_id, &path, self.node, self.library).await?;
+				update_file(
+					self.location_id,
+					&path,
+					self.node,
+					self.library,
+					&mut self.to_scan,
+				)
+				.await?;
 				should_invalidate = true;
 			}
 		}
@@ -227,7 +248,14 @@ impl LinuxEventHandler<'_> {
 					}
 				}
 				self.files_to_update.remove(&path);
-				update_file(self.location_id, &path, self.node, self.library).await?;
+				update_file(
+					self.location_id,
+					&path,
+					self.node,
+					self.library,
+					&mut self.to_scan,
+				)
+				.await?;
 				should_invalidate = true;
 			}
 		}
@@ -8,12 +8,23 @@
 //! way we have to handle like a file deletion, and the same applies for when a file is moved to our
 //! current location from anywhere else, we just receive the new path rename event, which means a
 //! creation.
-
-use crate::{invalidate_query, library::Library, location::manager::LocationManagerError, Node};
+//!
+//! FSEvents may also ask us to rescan a whole subtree, when it had to drop events or the volume
+//! was remounted, so we can't trust that we saw every change in there. Those requests are
+//! coalesced and each reported subtree is scanned again recursively, unless it is inside another
+//! one being scanned.
+
+use crate::{
+	invalidate_query,
+	library::Library,
+	location::{find_location, manager::LocationManagerError, scan_location_sub_path},
+	Node,
+};
 
 use sd_core_file_path_helper::{
 	check_file_path_exists, get_inode, FilePathError, IsolatedFilePathData,
 };
+use sd_core_prisma_helpers::location_with_indexer_rules;
 
 use sd_prisma::prisma::location;
 use sd_utils::error::FileIOError;
@@ -30,12 +41,12 @@ use notify::{
 	Event, EventKind,
 };
 use tokio::{fs, io, time::Instant};
-use tracing::{error, trace, warn};
+use tracing::{debug, error, trace, warn};
 
 use super::{
 	utils::{
 		create_dir, create_file, extract_inode_from_path, extract_location_path,
-		recalculate_directories_size, remove, rename, update_file,
+		recalculate_directories_size, remove, rename, scan_queued_paths, update_file, ToScan,
 	},
 	EventHandler, INode, InstantAndPath, HUNDRED_MILLIS, ONE_SECOND,
 };
@@ -53,6 +64,8 @@ pub(super) struct MacOsEventHandler<'lib> {
 	new_paths_map: HashMap<INode, InstantAndPath>,
 	paths_map_buffer: Vec<(INode, InstantAndPath)>,
 	to_recalculate_size: HashMap<PathBuf, Instant>,
+	to_scan: ToScan,
+	to_rescan: HashMap<PathBuf, Instant>,
 	path_and_instant_buffer: Vec<(PathBuf, Instant)>,
 }
 
@@ -78,6 +91,8 @@ impl<'lib> EventHandler<'lib> for MacOsEventHandler<'lib> {
 			new_paths_map: HashMap::new(),
 			paths_map_buffer: Vec::new(),
 			to_recalculate_size: HashMap::new(),
+			to_scan: ToScan::default(),
+			to_rescan: HashMap::new(),
 			path_and_instant_buffer: Vec::new(),
 		}
 	}
@@ -85,6 +100,22 @@ impl<'lib> EventHandler<'lib> for MacOsEventHandler<'lib> {
 	async fn handle_event(&mut self, event: Event) -> Result<(), LocationManagerError> {
 		trace!("Received MacOS event: {:#?}", event);
 
+		if event.need_rescan() {
+			// A rescan request without paths means the whole location must be rescanned
+			let subtrees = if event.paths.is_empty() {
+				vec![extract_location_path(self.location_id, self.library).await?]
+			} else {
+				event.paths
+			};
+
+			// Each new request resets the timer, so bursts of them are handled together
+			for subtree in subtrees {
+				self.to_rescan.insert(subtree, Instant::now());
+			}
+
+			return Ok(());
+		}
+
 		let Event {
 			kind, mut paths, ..
 		} = event;
@@ -102,8 +133,8 @@ impl<'lib> EventHandler<'lib> for MacOsEventHandler<'lib> {
 					}
 				}
 
-				// Don't need to dispatch a recalculate directory event as `create_dir` dispatches
-				// a `scan_location_sub_path` function, which recalculates the size already
+				// Don't need to dispatch a recalculate directory event as `create_dir` queues the
+				// directory to be scanned, which recalculates the size already
 
 				create_dir(
 					self.location_id,
@@ -111,8 +142,8 @@ impl<'lib> EventHandler<'lib> for MacOsEventHandler<'lib> {
 					&fs::metadata(path)
 						.await
 						.map_err(|e| FileIOError::from((path, e)))?,
-					self.node,
 					self.library,
+					&mut self.to_scan,
 				)
 				.await?;
 				self.latest_created_dir = Some(paths.remove(0));
@@ -177,6 +208,21 @@ impl<'lib> EventHandler<'lib> for MacOsEventHandler<'lib> {
 				error!("Failed to remove file_path: {e:#?}");
 			}
 
+			if !self.to_scan.is_empty() {
+				if let Err(e) =
+					scan_queued_paths(&mut self.to_scan, self.location_id, self.node, self.library)
+						.await
+				{
+					error!("Failed to scan queued paths: {e:#?}");
+				}
+			}
+
+			if !self.to_rescan.is_empty() {
+				if let Err(e) = self.handle_rescan_eviction().await {
+					error!("Failed to rescan subtrees reported by FSEvents: {e:#?}");
+				}
+			}
+
 			if !self.to_recalculate_size.is_empty() {
 				if let Err(e) = recalculate_directories_size(
 					&mut self.to_recalculate_size,
@@ -196,6 +242,65 @@ impl<'lib> EventHandler<'lib> for MacOsEventHandler<'lib> {
 }
 
 impl MacOsEventHandler<'_> {
+	async fn handle_rescan_eviction(&mut self) -> Result<(), LocationManagerError> {
+		// Waiting for FSEvents to stop asking for rescans, as they tend to come in bursts
+		if self
+			.to_rescan
+			.values()
+			.max()
+			.map_or(true, |instant| instant.elapsed() < ONE_SECOND)
+		{
+			return Ok(());
+		}
+
+		let location = find_location(self.library, self.location_id)
+			.include(location_with_indexer_rules::include())
+			.exec()
+			.await?
+			.ok_or(LocationManagerError::MissingLocation(self.location_id))?;
+
+		let mut subtrees = Vec::with_capacity(self.to_rescan.len());
+		for (path, _) in self.to_rescan.drain() {
+			// A subtree that is gone must be removed from its parent's entries
+			if fs::metadata(&path)
+				.await
+				.map_or(false, |metadata| metadata.is_dir())
+			{
+				subtrees.push(path);
+			} else if let Some(parent) = path.parent() {
+				subtrees.push(parent.to_path_buf());
+			}
+		}
+
+		// Ancestors sort right before their descendants, which their scan already covers
+		subtrees.sort();
+		subtrees.dedup_by(|subtree, ancestor| subtree.starts_with(ancestor));
+
+		debug!(
+			"Rescanning {} subtrees reported by FSEvents in location <id='{}'>",
+			subtrees.len(),
+			self.location_id
+		);
+
+		let location_path = extract_location_path(self.location_id, self.library).await?;
+
+		for subtree in subtrees {
+			// Sub paths are taken relative to the location root
+			let sub_path = subtree.strip_prefix(&location_path).unwrap_or(&subtree);
+
+			if let Err(e) =
+				scan_location_sub_path(self.node, self.library, location.clone(), sub_path).await
+			{
+				error!(
+					"Failed to rescan subtree reported by FSEvents <path='{}'>: {e:#?}",
+					subtree.display()
+				);
+			}
+		}
+
+		Ok(())
+	}
+
 	async fn handle_to_update_eviction(&mut self) -> Result<(), LocationManagerError> {
 		self.path_and_instant_buffer.clear();
 		let mut should_invalidate = false;
@@ -211,7 +316,14 @@ impl MacOsEventHandler<'_> {
 					}
 				}
 				self.reincident_to_update_files.remove(&path);
-				update_file(self.location_id, &path, self.node, self.library).await?;
+				update_file(
+					self.location_id,
+					&path,
+					self.node,
+					self.library,
+					&mut self.to_scan,
+				)
+				.await?;
 				should_invalidate = true;
 			}
 		}
@@ -235,7 +347,14 @@ impl MacOsEventHandler<'_> {
 					}
 				}
 				self.files_to_update.remove(&path);
-				update_file(self.location_id, &path, self.node, self.library).await?;
+				update_file(
+					self.location_id,
+					&path,
+					self.node,
+					self.library,
+					&mut self.to_scan,
+				)
+				.await?;
 				should_invalidate = true;
 			}
 		}
@@ -263,10 +382,16 @@ impl MacOsEventHandler<'_> {
 						.map_err(|e| FileIOError::from((&path, e)))?;
 
 					if metadata.is_dir() {
-						// Don't need to dispatch a recalculate directory event as `create_dir` dispatches
-						// a `scan_location_sub_path` function, which recalculates the size already
-						create_dir(self.location_id, &path, &metadata, self.node, self.library)
-							.await?;
+						// Don't need to dispatch a recalculate directory event as `create_dir` queues the
+						// directory to be scanned, which recalculates the size already
+						create_dir(
+							self.location_id,
+							&path,
+							&metadata,
+							self.library,
+							&mut self.to_scan,
+						)
+						.await?;
 					} else {
 						if let Some(parent) = path.parent() {
 							if parent != Path::new("") {
@@ -274,8 +399,15 @@ impl MacOsEventHandler<'_> {
 									.insert(parent.to_path_buf(), Instant::now());
 							}
 						}
-						create_file(self.location_id, &path, &metadata, self.node, self.library)
-							.await?;
+						create_file(
+							self.location_id,
+							&path,
+							&metadata,
+							self.node,
+							self.library,
+							&mut self.to_scan,
+						)
+						.await?;
 					}
 
 					trace!("Created file_path due timeout: {}", path.display());
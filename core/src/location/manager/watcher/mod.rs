@@ -56,6 +56,8 @@ type InstantAndPath = (Instant, PathBuf);
 
 const ONE_SECOND: Duration = Duration::from_secs(1);
 const HUNDRED_MILLIS: Duration = Duration::from_millis(100);
+/// How long directories created by the watcher wait to be scanned together in a single batch
+const INCREMENTAL_SCAN_INTERVAL: Duration = Duration::from_secs(5);
 
 #[async_trait]
 trait EventHandler<'lib> {
@@ -4,7 +4,7 @@ use crate::{
 	location::{
 		create_file_path, delete_directory, find_location,
 		indexer::reverse_update_directories_sizes, location_with_indexer_rules,
-		manager::LocationManagerError, scan_location_sub_path, update_location_size,
+		manager::LocationManagerError, scan_location_sub_paths, update_location_size, versioning,
 	},
 	object::{
 		media::{
@@ -28,6 +28,7 @@ use sd_core_file_path_helper::{
 	loose_find_existing_file_path_params, path_is_hidden, FilePathError, FilePathMetadata,
 	IsolatedFilePathData, MetadataExt,
 };
+use sd_core_heavy_lifting::{disk_usage, file_identifier};
 use sd_core_prisma_helpers::file_path_with_object;
 
 use sd_file_ext::{
@@ -65,13 +66,12 @@ use notify::Event;
 use tokio::{
 	fs,
 	io::{self, ErrorKind},
-	spawn,
 	time::Instant,
 };
 use tracing::{debug, error, trace, warn};
 use uuid::Uuid;
 
-use super::{INode, HUNDRED_MILLIS};
+use super::{INode, HUNDRED_MILLIS, INCREMENTAL_SCAN_INTERVAL};
 
 pub(super) fn check_event(event: &Event, ignore_paths: &HashSet<PathBuf>) -> bool {
 	// if path includes .DS_Store, .spacedrive file creation or is in the `ignore_paths` set, we ignore
@@ -87,26 +87,20 @@ pub(super) async fn create_dir(
 	location_id: location::id::Type,
 	path: impl AsRef<Path>,
 	metadata: &Metadata,
-	node: &Arc<Node>,
 	library: &Arc<Library>,
+	to_scan: &mut ToScan,
 ) -> Result<(), LocationManagerError> {
-	let location = find_location(library, location_id)
-		.include(location_with_indexer_rules::include())
-		.exec()
-		.await?
-		.ok_or(LocationManagerError::MissingLocation(location_id))?;
-
 	let path = path.as_ref();
 
-	let location_path = maybe_missing(&location.path, "location.path")?;
+	let location_path = extract_location_path(location_id, library).await?;
 
 	trace!(
 		"Location: <root_path ='{}'> creating directory: {}",
-		location_path,
+		location_path.display(),
 		path.display()
 	);
 
-	let iso_file_path = IsolatedFilePathData::new(location.id, location_path, path, true)?;
+	let iso_file_path = IsolatedFilePathData::new(location_id, &location_path, path, true)?;
 
 	let parent_iso_file_path = iso_file_path.parent();
 	if !parent_iso_file_path.is_root()
@@ -133,8 +127,8 @@ pub(super) async fn create_dir(
 	)
 	.await?;
 
-	// scan the new directory
-	scan_location_sub_path(node, library, location, &children_materialized_path).await?;
+	// the new directory is scanned along with other directories created around the same time
+	to_scan.queue_index(children_materialized_path);
 
 	invalidate_query!(library, "search.paths");
 	invalidate_query!(library, "search.objects");
@@ -148,6 +142,7 @@ pub(super) async fn create_file(
 	metadata: &Metadata,
 	node: &Arc<Node>,
 	library: &Arc<Library>,
+	to_scan: &mut ToScan,
 ) -> Result<(), LocationManagerError> {
 	inner_create_file(
 		location_id,
@@ -156,6 +151,7 @@ pub(super) async fn create_file(
 		metadata,
 		node,
 		library,
+		to_scan,
 	)
 	.await
 }
@@ -166,12 +162,8 @@ async fn inner_create_file(
 	path: impl AsRef<Path>,
 	metadata: &Metadata,
 	node: &Arc<Node>,
-	library @ Library {
-		id: library_id,
-		db,
-		sync,
-		..
-	}: &Library,
+	library @ Library { db, .. }: &Library,
+	to_scan: &mut ToScan,
 ) -> Result<(), LocationManagerError> {
 	let path = path.as_ref();
 	let location_path = location_path.as_ref();
@@ -184,7 +176,6 @@ async fn inner_create_file(
 
 	let iso_file_path = IsolatedFilePathData::new(location_id, location_path, path, false)?;
 	let iso_file_path_parts = iso_file_path.to_parts();
-	let extension = iso_file_path_parts.extension.to_string();
 
 	let metadata = FilePathMetadata::from_path(path, metadata)?;
 
@@ -201,7 +192,16 @@ async fn inner_create_file(
 		.await?
 	{
 		trace!("File already exists with that inode: {}", iso_file_path);
-		return inner_update_file(location_path, &file_path, path, node, library, None).await;
+		return inner_update_file(
+			location_path,
+			&file_path,
+			path,
+			node,
+			library,
+			None,
+			to_scan,
+		)
+		.await;
 
 	// If we can't find an existing file with the same inode, we check if there is a file with the same path
 	} else if let Some(file_path) = db
@@ -227,6 +227,7 @@ async fn inner_create_file(
 			node,
 			library,
 			Some(metadata.inode),
+			to_scan,
 		)
 		.await;
 	}
@@ -239,183 +240,31 @@ async fn inner_create_file(
 		return Ok(());
 	};
 
-	// generate provisional object
 	let FileMetadata {
 		cas_id,
-		kind,
 		fs_metadata,
-	} = FileMetadata::new(&location_path, &iso_file_path).await?;
-
-	debug!("Creating path: {}", iso_file_path);
-
-	let created_file =
-		create_file_path(library, iso_file_path_parts, cas_id.clone(), metadata).await?;
-
-	object::select!(object_ids { id pub_id });
-
-	let existing_object = db
-		.object()
-		.find_first(vec![object::file_paths::some(vec![
-			file_path::cas_id::equals(cas_id.clone()),
-			file_path::pub_id::not(created_file.pub_id.clone()),
-		])])
-		.select(object_ids::select())
-		.exec()
-		.await?;
-
-	let object_ids::Data {
-		id: object_id,
-		pub_id: object_pub_id,
-	} = if let Some(object) = existing_object {
-		object
-	} else {
-		let pub_id = uuid_to_bytes(Uuid::new_v4());
-		let date_created: DateTime<FixedOffset> =
-			DateTime::<Local>::from(fs_metadata.created_or_now()).into();
-		let int_kind = kind as i32;
-		sync.write_ops(
-			db,
-			(
-				sync.shared_create(
-					prisma_sync::object::SyncId {
-						pub_id: pub_id.clone(),
-					},
-					[
-						(object::date_created::NAME, msgpack!(date_created)),
-						(object::kind::NAME, msgpack!(int_kind)),
-					],
-				),
-				db.object()
-					.create(
-						pub_id.to_vec(),
-						vec![
-							object::date_created::set(Some(date_created)),
-							object::kind::set(Some(int_kind)),
-						],
-					)
-					.select(object_ids::select()),
-			),
-		)
-		.await?
-	};
-
-	sync.write_op(
-		db,
-		sync.shared_update(
-			prisma_sync::location::SyncId {
-				pub_id: created_file.pub_id.clone(),
-			},
-			file_path::object::NAME,
-			msgpack!(prisma_sync::object::SyncId {
-				pub_id: object_pub_id.clone()
-			}),
-		),
-		db.file_path().update(
-			file_path::pub_id::equals(created_file.pub_id.clone()),
-			vec![file_path::object::connect(object::pub_id::equals(
-				object_pub_id.clone(),
-			))],
-		),
+		..
+	} = FileMetadata::new(
+		&location_path,
+		&iso_file_path,
+		&library.config().await.file_metadata_options(),
 	)
 	.await?;
 
-	if !extension.is_empty()
-		&& matches!(
-			kind,
-			ObjectKind::Image | ObjectKind::Video | ObjectKind::Audio
-		) {
-		// Running in a detached task as thumbnail generation can take a while and we don't want to block the watcher
-		if matches!(kind, ObjectKind::Image | ObjectKind::Video) {
-			if let Some(cas_id) = cas_id {
-				spawn({
-					let extension = extension.clone();
-					let path = path.to_path_buf();
-					let node = node.clone();
-					let library_id = *library_id;
-
-					async move {
-						if let Err(e) = node
-							.thumbnailer
-							.generate_single_indexed_thumbnail(&extension, cas_id, path, library_id)
-							.await
-						{
-							error!("Failed to generate thumbnail in the watcher: {e:#?}");
-						}
-					}
-				});
-			}
-		}
-
-		match kind {
-			ObjectKind::Image => {
-				if let Ok(image_extension) = ImageExtension::from_str(&extension) {
-					if can_extract_exif_data_for_image(&image_extension) {
-						if let Ok(Some(exif_data)) = extract_exif_data(path)
-							.await
-							.map_err(|e| error!("Failed to extract media data: {e:#?}"))
-						{
-							let (sync_params, db_params) =
-								exif_data_image_to_query_params(exif_data);
-
-							sync.write_ops(
-								db,
-								(
-									sync.shared_create(
-										prisma_sync::exif_data::SyncId {
-											object: prisma_sync::object::SyncId {
-												pub_id: object_pub_id.clone(),
-											},
-										},
-										sync_params,
-									),
-									db.exif_data().upsert(
-										exif_data::object_id::equals(object_id),
-										exif_data::create(
-											object::id::equals(object_id),
-											db_params.clone(),
-										),
-										db_params,
-									),
-								),
-							)
-							.await?;
-						}
-					}
-				}
-			}
+	debug!("Creating path: {}", iso_file_path);
 
-			ObjectKind::Audio => {
-				if let Ok(audio_extension) = AudioExtension::from_str(&extension) {
-					if can_extract_ffmpeg_data_for_audio(&audio_extension) {
-						if let Ok(ffmpeg_data) = extract_ffmpeg_data(path)
-							.await
-							.map_err(|e| error!("Failed to extract media data: {e:#?}"))
-						{
-							save_ffmpeg_data([(ffmpeg_data, object_id)], db).await?;
-						}
-					}
-				}
-			}
+	let materialized_path = iso_file_path_parts.materialized_path.to_string();
 
-			ObjectKind::Video => {
-				if let Ok(video_extension) = VideoExtension::from_str(&extension) {
-					if can_extract_ffmpeg_data_for_video(&video_extension) {
-						if let Ok(ffmpeg_data) = extract_ffmpeg_data(path)
-							.await
-							.map_err(|e| error!("Failed to extract media data: {e:#?}"))
-						{
-							save_ffmpeg_data([(ffmpeg_data, object_id)], db).await?;
-						}
-					}
-				}
-			}
+	create_file_path(library, iso_file_path_parts, cas_id.clone(), metadata).await?;
 
-			_ => {
-				// Do nothing
-			}
-		}
+	if let Some(cas_id) = &cas_id {
+		keep_version(node, library, &iso_file_path, path, cas_id, &fs_metadata).await;
 	}
 
+	// Objects, thumbnails and media data are left to the file identifier and media processor, run
+	// once for all files created around the same time
+	to_scan.queue_identify(materialized_path);
+
 	invalidate_query!(library, "search.paths");
 	invalidate_query!(library, "search.objects");
 
@@ -427,6 +276,7 @@ pub(super) async fn update_file(
 	full_path: impl AsRef<Path>,
 	node: &Arc<Node>,
 	library: &Arc<Library>,
+	to_scan: &mut ToScan,
 ) -> Result<(), LocationManagerError> {
 	let full_path = full_path.as_ref();
 
@@ -452,7 +302,16 @@ pub(super) async fn update_file(
 		.exec()
 		.await?
 	{
-		inner_update_file(location_path, file_path, full_path, node, library, None).await
+		inner_update_file(
+			location_path,
+			file_path,
+			full_path,
+			node,
+			library,
+			None,
+			to_scan,
+		)
+		.await
 	} else {
 		inner_create_file(
 			location_id,
@@ -461,6 +320,7 @@ pub(super) async fn update_file(
 			&metadata,
 			node,
 			library,
+			to_scan,
 		)
 		.await
 	}
@@ -477,6 +337,7 @@ async fn inner_update_file(
 	node: &Arc<Node>,
 	library @ Library { db, sync, .. }: &Library,
 	maybe_new_inode: Option<INode>,
+	to_scan: &mut ToScan,
 ) -> Result<(), LocationManagerError> {
 	let full_path = full_path.as_ref();
 	let location_path = location_path.as_ref();
@@ -496,7 +357,34 @@ async fn inner_update_file(
 		cas_id,
 		fs_metadata,
 		kind,
-	} = FileMetadata::new(&location_path, &iso_file_path).await?;
+		remote_only,
+	} = FileMetadata::new(
+		&location_path,
+		&iso_file_path,
+		&library.config().await.file_metadata_options(),
+	)
+	.await?;
+
+	if remote_only {
+		// Its content is in the cloud, it's identified again once downloaded
+		trace!(
+			"Skipping update of cloud placeholder: {}",
+			full_path.display()
+		);
+		return Ok(());
+	}
+
+	if let Some(cas_id) = &cas_id {
+		keep_version(
+			node,
+			library,
+			&iso_file_path,
+			full_path,
+			cas_id,
+			&fs_metadata,
+		)
+		.await;
+	}
 
 	let inode = if let Some(inode) = maybe_new_inode {
 		inode
@@ -515,6 +403,9 @@ async fn inner_update_file(
 
 	let is_hidden = path_is_hidden(full_path, &fs_metadata);
 	if file_path.cas_id != cas_id {
+		// Entries walked from the archive's previous content are stale
+		file_identifier::remove_archive_entries(vec![file_path.id], db, sync).await?;
+
 		let (sync_params, db_params): (Vec<_>, Vec<_>) = {
 			use file_path::*;
 
@@ -587,28 +478,48 @@ async fn inner_update_file(
 		};
 
 		// file content changed
-		sync.write_ops(
-			db,
-			(
-				sync_params
-					.into_iter()
-					.map(|(field, value)| {
-						sync.shared_update(
-							prisma_sync::file_path::SyncId {
-								pub_id: file_path.pub_id.clone(),
-							},
-							field,
-							value,
-						)
-					})
-					.collect(),
-				db.file_path().update(
-					file_path::pub_id::equals(file_path.pub_id.clone()),
-					db_params,
-				),
-			),
-		)
-		.await?;
+		db._transaction()
+			.with_timeout(30 * 1000)
+			.run(|db| async move {
+				let before = file_identifier::statistics::snapshot(
+					vec![file_path::id::equals(file_path.id)],
+					&db,
+				)
+				.await?;
+
+				sync.write_ops(
+					&db,
+					(
+						sync_params
+							.into_iter()
+							.map(|(field, value)| {
+								sync.shared_update(
+									prisma_sync::file_path::SyncId {
+										pub_id: file_path.pub_id.clone(),
+									},
+									field,
+									value,
+								)
+							})
+							.collect(),
+						db.file_path().update(
+							file_path::pub_id::equals(file_path.pub_id.clone()),
+							db_params,
+						),
+					),
+				)
+				.await?;
+
+				file_identifier::statistics::snapshot(
+					vec![file_path::id::equals(file_path.id)],
+					&db,
+				)
+				.await?
+				.since(before)
+				.commit(&db)
+				.await
+			})
+			.await?;
 
 		if let Some(ref object) = file_path.object {
 			let int_kind = kind as i32;
@@ -620,21 +531,41 @@ async fn inner_update_file(
 				.await? == 1
 			{
 				if object.kind.map(|k| k != int_kind).unwrap_or_default() {
-					sync.write_op(
-						db,
-						sync.shared_update(
-							prisma_sync::object::SyncId {
-								pub_id: object.pub_id.clone(),
-							},
-							object::kind::NAME,
-							msgpack!(int_kind),
-						),
-						db.object().update(
-							object::id::equals(object.id),
-							vec![object::kind::set(Some(int_kind))],
-						),
-					)
-					.await?;
+					db._transaction()
+						.with_timeout(30 * 1000)
+						.run(|db| async move {
+							let before = file_identifier::statistics::snapshot(
+								vec![file_path::object_id::equals(Some(object.id))],
+								&db,
+							)
+							.await?;
+
+							sync.write_op(
+								&db,
+								sync.shared_update(
+									prisma_sync::object::SyncId {
+										pub_id: object.pub_id.clone(),
+									},
+									object::kind::NAME,
+									msgpack!(int_kind),
+								),
+								db.object().update(
+									object::id::equals(object.id),
+									vec![object::kind::set(Some(int_kind))],
+								),
+							)
+							.await?;
+
+							file_identifier::statistics::snapshot(
+								vec![file_path::object_id::equals(Some(object.id))],
+								&db,
+							)
+							.await?
+							.since(before)
+							.commit(&db)
+							.await
+						})
+						.await?;
 				}
 			} else {
 				let pub_id = uuid_to_bytes(Uuid::new_v4());
@@ -664,63 +595,58 @@ async fn inner_update_file(
 				)
 				.await?;
 
-				sync.write_op(
-					db,
-					sync.shared_update(
-						prisma_sync::location::SyncId {
-							pub_id: file_path.pub_id.clone(),
-						},
-						file_path::object::NAME,
-						msgpack!(prisma_sync::object::SyncId {
-							pub_id: pub_id.clone()
-						}),
-					),
-					db.file_path().update(
-						file_path::pub_id::equals(file_path.pub_id.clone()),
-						vec![file_path::object::connect(object::pub_id::equals(pub_id))],
-					),
-				)
-				.await?;
+				db._transaction()
+					.with_timeout(30 * 1000)
+					.run(|db| async move {
+						let before = file_identifier::statistics::snapshot(
+							vec![file_path::id::equals(file_path.id)],
+							&db,
+						)
+						.await?;
+
+						sync.write_op(
+							&db,
+							sync.shared_update(
+								prisma_sync::location::SyncId {
+									pub_id: file_path.pub_id.clone(),
+								},
+								file_path::object::NAME,
+								msgpack!(prisma_sync::object::SyncId {
+									pub_id: pub_id.clone()
+								}),
+							),
+							db.file_path().update(
+								file_path::pub_id::equals(file_path.pub_id.clone()),
+								vec![file_path::object::connect(object::pub_id::equals(pub_id))],
+							),
+						)
+						.await?;
+
+						file_identifier::statistics::snapshot(
+							vec![file_path::id::equals(file_path.id)],
+							&db,
+						)
+						.await?
+						.since(before)
+						.commit(&db)
+						.await
+					})
+					.await?;
 			}
 
 			if let Some(old_cas_id) = &file_path.cas_id {
-				// if this file had a thumbnail previously, we update it to match the new content
+				// if this file had a thumbnail previously, it's replaced by one of the new content
+				// by the media processor, run once for all files changed around the same time
 				if library.thumbnail_exists(node, old_cas_id).await? {
-					if let Some(ext) = file_path.extension.clone() {
-						// Running in a detached task as thumbnail generation can take a while and we don't want to block the watcher
-						if let Some(cas_id) = cas_id {
-							let node = Arc::clone(node);
-							let path = full_path.to_path_buf();
-							let library_id = library.id;
-							let old_cas_id = old_cas_id.clone();
-							spawn(async move {
-								let was_overwritten = old_cas_id == cas_id;
-								if let Err(e) = node
-									.thumbnailer
-									.generate_single_indexed_thumbnail(
-										&ext, cas_id, path, library_id,
-									)
-									.await
-								{
-									error!("Failed to generate thumbnail in the watcher: {e:#?}");
-								}
-
-								// If only a few bytes changed, cas_id will probably remains intact
-								// so we overwrote our previous thumbnail, so we can't remove it
-								if !was_overwritten {
-									// remove the old thumbnail as we're generating a new one
-									let thumb_path =
-										get_indexed_thumbnail_path(&node, &old_cas_id, library_id);
-									if let Err(e) = fs::remove_file(&thumb_path).await {
-										error!(
-											"Failed to remove old thumbnail: {:#?}",
-											FileIOError::from((thumb_path, e))
-										);
-									}
-								}
-							});
-						}
+					let thumb_path = get_indexed_thumbnail_path(node, old_cas_id, library.id);
+					if let Err(e) = fs::remove_file(&thumb_path).await {
+						error!(
+							"Failed to remove old thumbnail: {:#?}",
+							FileIOError::from((thumb_path, e))
+						);
 					}
+
+					to_scan.queue_identify(iso_file_path.to_parts().materialized_path.to_string());
 				}
 			}
 
@@ -823,6 +749,33 @@ async fn inner_update_file(
 	Ok(())
 }
 
+/// Failing to keep a version shouldn't keep the index from following the file
+async fn keep_version(
+	node: &Node,
+	library: &Library,
+	iso_file_path: &IsolatedFilePathData<'_>,
+	full_path: &Path,
+	cas_id: &str,
+	fs_metadata: &Metadata,
+) {
+	if let Err(e) = versioning::record(
+		node,
+		library,
+		iso_file_path,
+		full_path,
+		cas_id,
+		fs_metadata.len(),
+		DateTime::<Utc>::from(fs_metadata.modified_or_now()),
+	)
+	.await
+	{
+		error!(
+			"Failed to keep version of <path='{}'>: {e:#?}",
+			full_path.display()
+		);
+	}
+}
+
 pub(super) async fn rename(
 	location_id: location::id::Type,
 	new_path: impl AsRef<Path>,
@@ -1030,14 +983,27 @@ pub(super) async fn remove_by_file_path(
 				)
 				.await?;
 			} else {
-				sync.write_op(
-					db,
-					sync.shared_delete(prisma_sync::file_path::SyncId {
-						pub_id: file_path.pub_id.clone(),
-					}),
-					db.file_path().delete(file_path::id::equals(file_path.id)),
-				)
-				.await?;
+				file_identifier::remove_archive_entries(vec![file_path.id], db, sync).await?;
+
+				db._transaction()
+					.with_timeout(30 * 1000)
+					.run(|db| async move {
+						file_identifier::statistics::forget(
+							vec![file_path::id::equals(file_path.id)],
+							&db,
+						)
+						.await?;
+
+						sync.write_op(
+							&db,
+							sync.shared_delete(prisma_sync::file_path::SyncId {
+								pub_id: file_path.pub_id.clone(),
+							}),
+							db.file_path().delete(file_path::id::equals(file_path.id)),
+						)
+						.await
+					})
+					.await?;
 
 				if let Some(object_id) = file_path.object_id {
 					db.object()
@@ -1110,6 +1076,88 @@ pub(super) async fn extract_location_path(
 		)
 }
 
+/// Paths waiting for [`scan_queued_paths`]: directories created by the watcher, to be indexed,
+/// and directories where the watcher created or changed files, to be identified and processed
+#[derive(Default)]
+pub(super) struct ToScan {
+	to_index: HashMap<String, Instant>,
+	to_identify: HashMap<String, Instant>,
+}
+
+impl ToScan {
+	pub(super) fn queue_index(&mut self, materialized_path: String) {
+		self.to_index
+			.entry(materialized_path)
+			.or_insert_with(Instant::now);
+	}
+
+	pub(super) fn queue_identify(&mut self, materialized_path: String) {
+		self.to_identify
+			.entry(materialized_path)
+			.or_insert_with(Instant::now);
+	}
+
+	pub(super) fn is_empty(&self) -> bool {
+		self.to_index.is_empty() && self.to_identify.is_empty()
+	}
+
+	fn oldest(&self) -> Option<&Instant> {
+		self.to_index
+			.values()
+			.chain(self.to_identify.values())
+			.min()
+	}
+}
+
+/// Scans all paths queued since the last scan in a single batch, once the oldest of them waited
+/// for [`INCREMENTAL_SCAN_INTERVAL`]. So bulk operations like a `git checkout` or an `rsync` don't
+/// dispatch a scan for each directory they create, nor identify each file one by one.
+pub(super) async fn scan_queued_paths(
+	to_scan: &mut ToScan,
+	location_id: location::id::Type,
+	node: &Arc<Node>,
+	library: &Arc<Library>,
+) -> Result<(), LocationManagerError> {
+	if to_scan.oldest().map_or(true, |instant| {
+		instant.elapsed() < INCREMENTAL_SCAN_INTERVAL
+	}) {
+		return Ok(());
+	}
+
+	let location = find_location(library, location_id)
+		.include(location_with_indexer_rules::include())
+		.exec()
+		.await?
+		.ok_or(LocationManagerError::MissingLocation(location_id))?;
+
+	let mut to_index = to_scan
+		.to_index
+		.drain()
+		.map(|(path, _)| path)
+		.collect::<Vec<_>>();
+
+	// Once sorted, sub directories come right after their parent, and are covered by its scan
+	to_index.sort();
+	to_index.dedup_by(|sub_path, parent| sub_path.starts_with(parent.as_str()));
+
+	let to_identify = to_scan
+		.to_identify
+		.drain()
+		.map(|(path, _)| path)
+		.collect::<Vec<_>>();
+
+	debug!(
+		"Scanning {} directories created and {} directories with changed files in location \
+		<id='{location_id}'>",
+		to_index.len(),
+		to_identify.len(),
+	);
+
+	scan_location_sub_paths(node, library, location, to_index, to_identify).await?;
+
+	Ok(())
+}
+
 pub(super) async fn recalculate_directories_size(
 	candidates: &mut HashMap<PathBuf, Instant>,
 	buffer: &mut Vec<(PathBuf, Instant)>,
@@ -1119,6 +1167,7 @@ pub(super) async fn recalculate_directories_size(
 	let mut location_path_cache = None;
 	let mut should_invalidate = false;
 	let mut should_update_location_size = false;
+	let mut changed_directories = vec![];
 	buffer.clear();
 
 	for (path, instant) in candidates.drain() {
@@ -1136,6 +1185,17 @@ pub(super) async fn recalculate_directories_size(
 			}
 
 			if let Some(location_path) = &location_path_cache {
+				match IsolatedFilePathData::new(location_id, location_path, &path, true) {
+					Ok(iso_file_path) => {
+						changed_directories.extend(iso_file_path.materialized_path_for_children());
+					}
+					Err(e) => {
+						error!(
+							"Failed to get the materialized path of a changed directory: {e:#?}"
+						);
+					}
+				}
+
 				if path != *location_path {
 					trace!(
 						"Reverse calculating directory sizes starting at {} until {}",
@@ -1158,6 +1218,16 @@ pub(super) async fn recalculate_directories_size(
 		update_location_size(location_id, library).await?;
 	}
 
+	if !changed_directories.is_empty() {
+		if let Err(e) =
+			disk_usage::refresh_directories(&library.db, location_id, changed_directories).await
+		{
+			error!("Failed to update the disk usage of changed directories: {e:#?}");
+		} else {
+			invalidate_query!(library, "locations.diskUsage");
+		}
+	}
+
 	if should_invalidate {
 		invalidate_query!(library, "search.paths");
 		invalidate_query!(library, "search.objects");
@@ -31,7 +31,7 @@ use tracing::{error, trace};
 use super::{
 	utils::{
 		create_dir, extract_inode_from_path, recalculate_directories_size, remove, rename,
-		update_file,
+		scan_queued_paths, update_file, ToScan,
 	},
 	EventHandler, INode, InstantAndPath, HUNDRED_MILLIS, ONE_SECOND,
 };
@@ -50,6 +50,7 @@ pub(super) struct WindowsEventHandler<'lib> {
 	files_to_update: HashMap<PathBuf, Instant>,
 	reincident_to_update_files: HashMap<PathBuf, Instant>,
 	to_recalculate_size: HashMap<PathBuf, Instant>,
+	to_scan: ToScan,
 	path_and_instant_buffer: Vec<(PathBuf, Instant)>,
 }
 
@@ -75,6 +76,7 @@ impl<'lib> EventHandler<'lib> for WindowsEventHandler<'lib> {
 			files_to_update: HashMap::new(),
 			reincident_to_update_files: HashMap::new(),
 			to_recalculate_size: HashMap::new(),
+			to_scan: ToScan::default(),
 			path_and_instant_buffer: Vec::new(),
 		}
 	}
@@ -132,10 +134,16 @@ impl<'lib> EventHandler<'lib> for WindowsEventHandler<'lib> {
 						.map_err(|e| FileIOError::from((&path, e)))?;
 
 					if metadata.is_dir() {
-						// Don't need to dispatch a recalculate directory event as `create_dir` dispatches
-						// a `scan_location_sub_path` function, which recalculates the size already
-						create_dir(self.location_id, path, &metadata, self.node, self.library)
-							.await?;
+						// Don't need to dispatch a recalculate directory event as `create_dir` queues the
+						// directory to be scanned, which recalculates the size already
+						create_dir(
+							self.location_id,
+							path,
+							&metadata,
+							self.library,
+							&mut self.to_scan,
+						)
+						.await?;
 					} else if self.files_to_update.contains_key(&path) {
 						if let Some(old_instant) =
 							self.files_to_update.insert(path.clone(), Instant::now())
@@ -246,6 +254,15 @@ impl<'lib> EventHandler<'lib> for WindowsEventHandler<'lib> {
 				error!("Failed to remove file_path: {e:#?}");
 			}
 
+			if !self.to_scan.is_empty() {
+				if let Err(e) =
+					scan_queued_paths(&mut self.to_scan, self.location_id, self.node, self.library)
+						.await
+				{
+					error!("Failed to scan queued paths: {e:#?}");
+				}
+			}
+
 			if !self.to_recalculate_size.is_empty() {
 				if let Err(e) = recalculate_directories_size(
 					&mut self.to_recalculate_size,
@@ -280,6 +297,7 @@ impl WindowsEventHandler<'_> {
 					self.node,
 					&mut self.to_recalculate_size,
 					self.library,
+					&mut self.to_scan,
 				)
 				.await?;
 				should_invalidate = true;
@@ -305,6 +323,7 @@ impl WindowsEventHandler<'_> {
 					self.node,
 					&mut self.to_recalculate_size,
 					self.library,
+					&mut self.to_scan,
 				)
 				.await?;
 				should_invalidate = true;
@@ -358,6 +377,7 @@ async fn handle_update<'lib>(
 	node: &'lib Arc<Node>,
 	to_recalculate_size: &mut HashMap<PathBuf, Instant>,
 	library: &'lib Arc<Library>,
+	to_scan: &mut ToScan,
 ) -> Result<(), LocationManagerError> {
 	let metadata = fs::metadata(&path)
 		.await
@@ -368,7 +388,7 @@ async fn handle_update<'lib>(
 				to_recalculate_size.insert(parent.to_path_buf(), Instant::now());
 			}
 		}
-		update_file(location_id, path, node, library).await?;
+		update_file(location_id, path, node, library, to_scan).await?;
 	}
 
 	Ok(())
@@ -2,16 +2,22 @@ use crate::{
 	invalidate_query,
 	library::Library,
 	object::{
+		fs::trash::remove_trashed_from_location,
 		media::{old_media_processor, OldMediaProcessorJobInit},
 		old_file_identifier::{self, old_file_identifier_job::OldFileIdentifierJobInit},
+		old_mtp_importer::OldMtpImporterJobInit,
 	},
-	old_job::{JobBuilder, JobError, JobManagerError},
+	old_job::{JobBuilder, JobError, JobManagerError, JobTrigger},
 	Node,
 };
 
 use sd_core_file_path_helper::{
 	filter_existing_file_path_params, IsolatedFilePathData, IsolatedFilePathDataParts,
 };
+use sd_core_heavy_lifting::{
+	file_identifier::{self, IdentifierRule, IdentifierSettings},
+	utils::network_share::NetworkShare,
+};
 use sd_core_prisma_helpers::location_with_indexer_rules;
 
 use sd_prisma::{
@@ -42,16 +48,27 @@ use tokio::{fs, io, time::Instant};
 use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
+pub mod capacity;
+pub mod cloud_metadata;
 mod error;
 pub mod indexer;
 mod manager;
 pub mod metadata;
+pub mod mtp;
 pub mod non_indexed;
+pub mod s3;
+pub mod schedule;
+pub mod versioning;
+pub mod webdav;
 
+use cloud_metadata::CloudMetadataLocationConfig;
 pub use error::LocationError;
 use indexer::OldIndexerJobInit;
 pub use manager::{LocationManagerError, Locations};
 use metadata::SpacedriveLocationMetadataFile;
+use mtp::MtpLocationConfig;
+use s3::S3LocationConfig;
+use webdav::WebDavLocationConfig;
 
 pub type LocationPubId = Uuid;
 
@@ -86,20 +103,65 @@ pub struct LocationCreateArgs {
 	pub path: PathBuf,
 	pub dry_run: bool,
 	pub indexer_rules_ids: Vec<i32>,
+	/// Connection info for locations in a SMB or NFS share, which gets mounted on `path`
+	#[serde(default)]
+	pub network_share: Option<NetworkShare>,
+	/// Bucket of locations in S3 compatible object storage, which use a local mirror directory
+	/// as their path instead of the received one
+	#[serde(default)]
+	pub s3: Option<S3LocationConfig>,
+	/// Folder of locations in a WebDAV server, which use a local mirror directory as their path
+	/// instead of the received one
+	#[serde(default)]
+	pub webdav: Option<WebDavLocationConfig>,
+	/// Google Drive or OneDrive folder of locations made only of file metadata, which use a
+	/// local mirror directory as their path instead of the received one
+	#[serde(default)]
+	pub cloud_metadata: Option<CloudMetadataLocationConfig>,
+	/// Storage of a phone or camera connected over MTP/PTP, which use a local mirror directory as
+	/// their path instead of the received one
+	#[serde(default)]
+	pub mtp: Option<MtpLocationConfig>,
 }
 
 impl LocationCreateArgs {
 	pub async fn create(
-		self,
+		mut self,
 		node: &Node,
 		library: &Arc<Library>,
 	) -> Result<Option<location_with_indexer_rules::Data>, LocationError> {
+		if let Some(mirror_path) = self
+			.s3
+			.as_ref()
+			.map(|s3| s3.mirror_path(&node.data_dir))
+			.or_else(|| {
+				self.webdav
+					.as_ref()
+					.map(|webdav| webdav.mirror_path(&node.data_dir))
+			})
+			.or_else(|| {
+				self.cloud_metadata
+					.as_ref()
+					.map(|cloud_metadata| cloud_metadata.mirror_path(&node.data_dir))
+			})
+			.or_else(|| self.mtp.as_ref().map(|mtp| mtp.mirror_path(&node.data_dir)))
+		{
+			self.path = mirror_path;
+			fs::create_dir_all(&self.path)
+				.await
+				.map_err(|e| FileIOError::from((&self.path, e)))?;
+		}
+
 		let Some(path_str) = self.path.to_str().map(str::to_string) else {
 			return Err(LocationError::NonUtf8Path(NonUtf8PathError(
 				self.path.into_boxed_path(),
 			)));
 		};
 
+		if let Some(network_share) = &self.network_share {
+			network_share.ensure_mounted(&self.path).await?;
+		}
+
 		let path_metadata = match fs::metadata(&self.path).await {
 			Ok(metadata) => metadata,
 			Err(e) if e.kind() == io::ErrorKind::NotFound => {
@@ -179,7 +241,77 @@ impl LocationCreateArgs {
 		)
 		.await?;
 
-		if let Some(location) = location {
+		if let Some(mut location) = location {
+			if let Some(network_share) = &self.network_share {
+				// Local only, as the share is mounted by each device on its own
+				location.data = library
+					.db
+					.location()
+					.update(
+						location::id::equals(location.data.id),
+						vec![location::network_share::set(Some(network_share.to_db()))],
+					)
+					.include(location_with_indexer_rules::include())
+					.exec()
+					.await?;
+			}
+
+			if let Some(s3) = &self.s3 {
+				// Local only, as each device keeps its own mirror of the bucket
+				location.data = library
+					.db
+					.location()
+					.update(
+						location::id::equals(location.data.id),
+						vec![location::s3_config::set(Some(s3.to_db()))],
+					)
+					.include(location_with_indexer_rules::include())
+					.exec()
+					.await?;
+			}
+
+			if let Some(webdav) = &self.webdav {
+				// Local only, as it holds the password
+				location.data = library
+					.db
+					.location()
+					.update(
+						location::id::equals(location.data.id),
+						vec![location::webdav_config::set(Some(webdav.to_db()))],
+					)
+					.include(location_with_indexer_rules::include())
+					.exec()
+					.await?;
+			}
+
+			if let Some(cloud_metadata) = &self.cloud_metadata {
+				// Local only, as it holds the refresh token
+				location.data = library
+					.db
+					.location()
+					.update(
+						location::id::equals(location.data.id),
+						vec![location::cloud_config::set(Some(cloud_metadata.to_db()))],
+					)
+					.include(location_with_indexer_rules::include())
+					.exec()
+					.await?;
+			}
+
+			if let Some(mtp) = &self.mtp {
+				// Local only, as devices are plugged into this node
+				location.data = library
+					.db
+					.location()
+					.update(
+						location::id::equals(location.data.id),
+						vec![location::mtp_config::set(Some(mtp.to_db()))],
+					)
+					.include(location_with_indexer_rules::include())
+					.exec()
+					.await?;
+			}
+
 			// Write location metadata to a .spacedrive file
 			if let Err(err) = SpacedriveLocationMetadataFile::create_and_save(
 				library.id,
@@ -299,6 +431,17 @@ pub struct LocationUpdateArgs {
 	hidden: Option<bool>,
 	indexer_rules_ids: Vec<i32>,
 	path: Option<String>,
+	/// Replaces the location's identifier rules, files skipped by the old ones are identified again
+	identifier_rules: Option<Vec<IdentifierRule>>,
+	/// Replaces how the file identifier treats the location's files, from its next scan on
+	#[serde(default)]
+	identifier_settings: Option<IdentifierSettings>,
+	/// Replaces the location's network share connection info
+	#[serde(default)]
+	network_share: Option<NetworkShare>,
+	/// Snapshot locations keep their catalog while their drive is unplugged, suspending jobs
+	#[serde(default)]
+	snapshot: Option<bool>,
 }
 
 impl LocationUpdateArgs {
@@ -313,6 +456,20 @@ impl LocationUpdateArgs {
 
 		let name = self.name.clone();
 
+		let identifier_rules = self
+			.identifier_rules
+			.as_ref()
+			.filter(|rules| **rules != IdentifierRule::of_location(&(&location).into()))
+			.map(rmp_serde::to_vec_named)
+			.transpose()?;
+		let identifier_rules_changed = identifier_rules.is_some();
+
+		let identifier_settings = self
+			.identifier_settings
+			.filter(|settings| *settings != IdentifierSettings::of_location(&(&location).into()))
+			.map(|settings| rmp_serde::to_vec_named(&settings))
+			.transpose()?;
+
 		let (sync_params, db_params): (Vec<_>, Vec<_>) = [
 			self.name
 				.filter(|name| location.name.as_ref() != Some(name))
@@ -340,12 +497,30 @@ impl LocationUpdateArgs {
 					location::hidden::set(Some(v)),
 				)
 			}),
+			self.snapshot.map(|v| {
+				(
+					(location::is_snapshot::NAME, msgpack!(v)),
+					location::is_snapshot::set(Some(v)),
+				)
+			}),
 			self.path.clone().map(|v| {
 				(
 					(location::path::NAME, msgpack!(v)),
 					location::path::set(Some(v)),
 				)
 			}),
+			identifier_rules.map(|v| {
+				(
+					(location::identifier_rules::NAME, msgpack!(v)),
+					location::identifier_rules::set(Some(v)),
+				)
+			}),
+			identifier_settings.map(|v| {
+				(
+					(location::identifier_settings::NAME, msgpack!(v)),
+					location::identifier_settings::set(Some(v)),
+				)
+			}),
 		]
 		.into_iter()
 		.flatten()
@@ -390,6 +565,21 @@ impl LocationUpdateArgs {
 				node.locations.remove(self.id, library.clone()).await?;
 				node.locations.add(self.id, library.clone()).await?;
 			}
+
+			if identifier_rules_changed {
+				file_identifier::clear_skipped(self.id, db, sync).await?;
+			}
+		}
+
+		if let Some(network_share) = self.network_share {
+			// Local only, as the share is mounted by each device on its own
+			db.location()
+				.update(
+					location::id::equals(self.id),
+					vec![location::network_share::set(Some(network_share.to_db()))],
+				)
+				.exec()
+				.await?;
 		}
 
 		let current_rules_ids = location
@@ -461,6 +651,18 @@ async fn link_location_and_indexer_rules(
 	Ok(())
 }
 
+/// Whether a location is a snapshot whose drive isn't connected, so no jobs must run on it
+pub async fn is_suspended_snapshot(node: &Node, is_snapshot: Option<bool>, pub_id: &[u8]) -> bool {
+	if is_snapshot != Some(true) {
+		return false;
+	}
+
+	match Uuid::from_slice(pub_id) {
+		Ok(pub_id) => !node.locations.is_online(&pub_id).await,
+		Err(_) => false,
+	}
+}
+
 pub async fn scan_location(
 	node: &Arc<Node>,
 	library: &Arc<Library>,
@@ -472,13 +674,28 @@ pub async fn scan_location(
 		return Ok(());
 	}
 
+	if is_suspended_snapshot(node, location.is_snapshot, &location.pub_id).await {
+		debug!(
+			"Skipping scan of snapshot location <id='{}'>, as its drive isn't connected",
+			location.id
+		);
+		return Ok(());
+	}
+
 	let location_base_data = location::Data::from(&location);
 
+	// Phones and cameras get their new photos and videos imported after every scan
+	let imports = MtpLocationConfig::from_db(location.id, location.mtp_config.as_deref())
+		.map_err(|e| error!("{e:#?}"))
+		.ok()
+		.flatten()
+		.is_some_and(|mtp| mtp.import.is_some());
+
 	debug!("Scanning location with state: {location_scan_state:?}");
 
 	match location_scan_state {
 		ScanState::Pending | ScanState::Completed => {
-			JobBuilder::new(OldIndexerJobInit {
+			let job = JobBuilder::new(OldIndexerJobInit {
 				location,
 				sub_path: None,
 			})
@@ -490,13 +707,21 @@ pub async fn scan_location(
 				sub_path: None,
 			})
 			.queue_next(OldMediaProcessorJobInit {
-				location: location_base_data,
+				location: location_base_data.clone(),
 				sub_path: None,
 				regenerate_thumbnails: false,
 				regenerate_labels: false,
-			})
-			.spawn(node, library)
-			.await
+			});
+
+			let job = if imports {
+				job.queue_next(OldMtpImporterJobInit {
+					location: location_base_data,
+				})
+			} else {
+				job
+			};
+
+			job.spawn(node, library).await
 		}
 		ScanState::Indexed => {
 			JobBuilder::new(OldFileIdentifierJobInit {
@@ -532,6 +757,38 @@ pub async fn scan_location(
 	.map_err(Into::into)
 }
 
+/// Indexes a snapshot location again once its drive is back, which picks up files added, changed
+/// or removed while it was away, then identifies the changed ones and processes their media.
+///
+/// The caller must make sure the drive is connected, see [`is_suspended_snapshot`].
+pub async fn reconcile_snapshot_location(
+	node: &Arc<Node>,
+	library: &Arc<Library>,
+	location: location_with_indexer_rules::Data,
+) -> Result<(), JobManagerError> {
+	let location_base_data = location::Data::from(&location);
+
+	JobBuilder::new(OldIndexerJobInit {
+		location,
+		sub_path: None,
+	})
+	.with_action("reconcile_snapshot")
+	.with_metadata(json!({"location": location_base_data.clone()}))
+	.build()
+	.queue_next(OldFileIdentifierJobInit {
+		location: location_base_data.clone(),
+		sub_path: None,
+	})
+	.queue_next(OldMediaProcessorJobInit {
+		location: location_base_data,
+		sub_path: None,
+		regenerate_thumbnails: false,
+		regenerate_labels: false,
+	})
+	.spawn(node, library)
+	.await
+}
+
 pub async fn scan_location_sub_path(
 	node: &Arc<Node>,
 	library: &Arc<Library>,
@@ -545,6 +802,14 @@ pub async fn scan_location_sub_path(
 		return Ok(());
 	}
 
+	if is_suspended_snapshot(node, location.is_snapshot, &location.pub_id).await {
+		debug!(
+			"Skipping sub path scan of snapshot location <id='{}'>, as its drive isn't connected",
+			location.id
+		);
+		return Ok(());
+	}
+
 	let location_base_data = location::Data::from(&location);
 
 	JobBuilder::new(OldIndexerJobInit {
@@ -572,6 +837,104 @@ pub async fn scan_location_sub_path(
 	.map_err(Into::into)
 }
 
+/// Indexes each one of `to_index`, then identifies their files and processes their media in a
+/// single run over the closest common ancestor of them and of `to_identify`, directories where
+/// files were created or changed. Both the file identifier and the media processor skip what was
+/// already processed, so a broader sub path only costs a few more queries.
+pub async fn scan_location_sub_paths(
+	node: &Arc<Node>,
+	library: &Arc<Library>,
+	location: location_with_indexer_rules::Data,
+	to_index: Vec<String>,
+	to_identify: Vec<String>,
+) -> Result<(), JobManagerError> {
+	// TODO(N): This isn't gonna work with removable media and this will likely permanently break if the DB is restored from a backup.
+	if (to_index.is_empty() && to_identify.is_empty())
+		|| location.instance_id != Some(library.config().await.instance_id)
+	{
+		return Ok(());
+	}
+
+	let location_base_data = location::Data::from(&location);
+	let common_sub_path = PathBuf::from(common_materialized_path(
+		&to_index
+			.iter()
+			.chain(&to_identify)
+			.cloned()
+			.collect::<Vec<_>>(),
+	));
+
+	let file_identifier = OldFileIdentifierJobInit {
+		location: location_base_data.clone(),
+		sub_path: Some(common_sub_path.clone()),
+	};
+	let media_processor = OldMediaProcessorJobInit {
+		location: location_base_data.clone(),
+		sub_path: Some(common_sub_path.clone()),
+		regenerate_thumbnails: false,
+		regenerate_labels: false,
+	};
+	let metadata = json!({
+		"location": location_base_data,
+		"sub_path": common_sub_path,
+	});
+
+	let mut to_index = to_index.into_iter().map(PathBuf::from);
+
+	if let Some(first_sub_path) = to_index.next() {
+		let mut job = JobBuilder::new(OldIndexerJobInit {
+			location: location.clone(),
+			sub_path: Some(first_sub_path),
+		})
+		.with_action("scan_location_sub_paths")
+		.with_metadata(metadata)
+		.build();
+
+		for sub_path in to_index {
+			job = job.queue_next(OldIndexerJobInit {
+				location: location.clone(),
+				sub_path: Some(sub_path),
+			});
+		}
+
+		job.queue_next(file_identifier)
+			.queue_next(media_processor)
+			.spawn(node, library)
+			.await
+	} else {
+		JobBuilder::new(file_identifier)
+			.with_action("scan_location_sub_paths")
+			.with_metadata(metadata)
+			.build()
+			.queue_next(media_processor)
+			.spawn(node, library)
+			.await
+	}
+	.map_err(Into::into)
+}
+
+/// Longest materialized path that is an ancestor of all `materialized_paths`
+fn common_materialized_path(materialized_paths: &[String]) -> &str {
+	materialized_paths
+		.iter()
+		.fold(None, |common: Option<&str>, path| {
+			let Some(common) = common else {
+				return Some(path.as_str());
+			};
+
+			let common_len = common
+				.char_indices()
+				.zip(path.chars())
+				.take_while(|((_, a), b)| a == b)
+				.last()
+				.map_or(0, |((idx, c), _)| idx + c.len_utf8());
+
+			// Materialized paths always start and end with a separator, so we cut after the last one
+			Some(&common[..=common[..common_len].rfind('/').unwrap_or(0)])
+		})
+		.unwrap_or("/")
+}
+
 pub async fn light_scan_location(
 	node: Arc<Node>,
 	library: Arc<Library>,
@@ -585,10 +948,24 @@ pub async fn light_scan_location(
 		return Ok(());
 	}
 
+	if is_suspended_snapshot(&node, location.is_snapshot, &location.pub_id).await {
+		return Ok(());
+	}
+
 	let location_base_data = location::Data::from(&location);
 
+	// Directories opened while we scan are identified right after this one
+	let prioritized_rx = library.open_priority_lane(location.id);
+
 	indexer::old_shallow(&location, &sub_path, &node, &library).await?;
-	old_file_identifier::old_shallow(&location_base_data, &sub_path, &library).await?;
+	old_file_identifier::old_shallow(&location_base_data, &sub_path, &node, &library).await?;
+	old_file_identifier::identify_prioritized_directories(
+		&prioritized_rx,
+		&location_base_data,
+		&node,
+		&library,
+	)
+	.await;
 	old_media_processor::old_shallow(
 		&location_base_data,
 		&sub_path,
@@ -720,7 +1097,8 @@ async fn create_location(
 		.location()
 		.count(vec![location::path::equals(Some(path.clone()))])
 		.exec()
-		.await? > 0
+		.await?
+		> 0
 	{
 		return Err(LocationError::LocationAlreadyExists(location_path.into()));
 	}
@@ -810,6 +1188,7 @@ pub async fn delete_location(
 
 	let start = Instant::now();
 	delete_directory(library, location_id, None).await?;
+	remove_trashed_from_location(db, location_id).await?;
 	debug!(
 		"Elapsed time to delete location file paths: {:?}",
 		start.elapsed()
@@ -900,21 +1279,30 @@ pub async fn delete_directory(
 
 	// This is NOT sync-compatible!
 	// Sync requires having sync ids available.
-	let children_params = sd_utils::chain_optional_iter(
-		[file_path::location_id::equals(Some(location_id))],
-		[parent_iso_file_path.and_then(|parent| {
-			parent
-				.materialized_path_for_children()
-				.map(|materialized_path| {
-					or![
-						and(filter_existing_file_path_params(parent)),
-						file_path::materialized_path::starts_with(materialized_path),
-					]
-				})
-		})],
-	);
+	let children_params = || {
+		sd_utils::chain_optional_iter(
+			[file_path::location_id::equals(Some(location_id))],
+			[parent_iso_file_path.and_then(|parent| {
+				parent
+					.materialized_path_for_children()
+					.map(|materialized_path| {
+						or![
+							and(filter_existing_file_path_params(parent)),
+							file_path::materialized_path::starts_with(materialized_path),
+						]
+					})
+			})],
+		)
+	};
 
-	db.file_path().delete_many(children_params).exec().await?;
+	db._transaction()
+		.with_timeout(30 * 1000)
+		.run(|db| async move {
+			file_identifier::statistics::forget(children_params(), &db).await?;
+
+			db.file_path().delete_many(children_params()).exec().await
+		})
+		.await?;
 
 	// library.orphan_remover.invoke().await;
 
@@ -1153,3 +1541,31 @@ pub async fn create_file_path(
 
 	Ok(created_path)
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn common_materialized_path_of_sub_paths() {
+		let paths = |paths: &[&str]| paths.iter().map(ToString::to_string).collect::<Vec<_>>();
+
+		assert_eq!(common_materialized_path(&[]), "/");
+		assert_eq!(common_materialized_path(&paths(&["/a/b/"])), "/a/b/");
+		assert_eq!(common_materialized_path(&paths(&["/a/b/", "/a/c/"])), "/a/");
+		assert_eq!(common_materialized_path(&paths(&["/a/", "/a/b/c/"])), "/a/");
+		assert_eq!(common_materialized_path(&paths(&["/x/", "/y/"])), "/");
+
+		// Directories sharing the start of their names aren't ancestors of each other
+		assert_eq!(
+			common_materialized_path(&paths(&["/a/photos/", "/a/photos_old/", "/a/phone/"])),
+			"/a/"
+		);
+
+		// Multi-byte characters are never split
+		assert_eq!(
+			common_materialized_path(&paths(&["/ação/é/", "/ação/ê/"])),
+			"/ação/"
+		);
+	}
+}
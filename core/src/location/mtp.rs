@@ -0,0 +1,441 @@
+//! Locations backed by a storage of a phone or camera connected over MTP/PTP.
+//!
+//! Same as WebDAV locations, they have a local mirror directory as their path, holding the
+//! directory skeleton of the storage and the files we had to copy. Devices are only reachable
+//! while plugged in, so the location manager takes these locations as online when their device is
+//! connected, and scans them, importing new photos and videos if configured, right as it shows up.
+
+use crate::object::cas::{generate_cas_id_from_samples, sampled_ranges};
+
+use sd_mtp::{Device, Object, Storage};
+use sd_prisma::prisma::location;
+use sd_utils::error::FileIOError;
+
+use std::{
+	collections::HashMap,
+	path::{Component, Path, PathBuf},
+	sync::{Arc, Mutex},
+	time::SystemTime,
+};
+
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+use specta::Type;
+use tokio::{fs, task::JoinError};
+use tracing::debug;
+
+#[derive(thiserror::Error, Debug)]
+pub enum MtpError {
+	#[error("failed to decode MTP config of location <id='{0}'>: {1}")]
+	Decode(location::id::Type, rmp_serde::decode::Error),
+	#[error(transparent)]
+	Device(#[from] sd_mtp::Error),
+	#[error("storage not found on MTP device <serial_number='{serial_number}', storage_id='{storage_id:08x}'>")]
+	StorageNotFound {
+		serial_number: String,
+		storage_id: u32,
+	},
+	#[error("file not found on MTP device <path='{}'>", .0.display())]
+	NotFound(PathBuf),
+	#[error("MTP device task failed: {0}")]
+	Join(#[from] JoinError),
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+}
+
+impl From<MtpError> for rspc::Error {
+	fn from(err: MtpError) -> Self {
+		match err {
+			// Usually an unplugged device, or a phone which wasn't unlocked to allow file transfers
+			MtpError::Device(sd_mtp::Error::NotConnected(_) | sd_mtp::Error::Unsupported)
+			| MtpError::StorageNotFound { .. } => {
+				Self::with_cause(ErrorCode::PreconditionFailed, err.to_string(), err)
+			}
+
+			MtpError::NotFound(_) => Self::with_cause(ErrorCode::NotFound, err.to_string(), err),
+
+			_ => Self::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+/// Storage of an MTP device where a location lives, stored msgpack encoded on
+/// `location.mtp_config`, which is local only as devices are plugged into a single node.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct MtpLocationConfig {
+	pub serial_number: String,
+	pub storage_id: u32,
+	/// Where new photos and videos are copied to after every scan of the device
+	#[serde(default)]
+	pub import: Option<MtpImportConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct MtpImportConfig {
+	pub location_id: location::id::Type,
+	/// Directory of the destination location, relative to its path, defaults to its root
+	pub sub_path: Option<PathBuf>,
+}
+
+impl MtpLocationConfig {
+	pub fn from_db(
+		location_id: location::id::Type,
+		mtp_config: Option<&[u8]>,
+	) -> Result<Option<Self>, MtpError> {
+		mtp_config
+			.map(|bytes| rmp_serde::from_slice(bytes).map_err(|e| MtpError::Decode(location_id, e)))
+			.transpose()
+	}
+
+	pub fn to_db(&self) -> Vec<u8> {
+		rmp_serde::to_vec_named(self).expect("MTP config is always serializable")
+	}
+
+	/// Local directory mirroring the device storage, which is used as the location path
+	pub fn mirror_path(&self, data_dir: impl AsRef<Path>) -> PathBuf {
+		data_dir
+			.as_ref()
+			.join("mtp")
+			.join(format!("{}-{:08x}", self.serial_number, self.storage_id))
+	}
+}
+
+/// A connected device with its storages, for users to pick the one to add as a location
+#[derive(Debug, Clone, Serialize, Type)]
+pub struct MtpDevice {
+	pub serial_number: String,
+	pub name: String,
+	pub storages: Vec<MtpStorage>,
+}
+
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Type)]
+pub struct MtpStorage {
+	pub id: u32,
+	pub description: Option<String>,
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub max_capacity: u64,
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub free_space: u64,
+}
+
+impl From<Storage> for MtpStorage {
+	fn from(
+		Storage {
+			id,
+			description,
+			max_capacity,
+			free_space,
+			..
+		}: Storage,
+	) -> Self {
+		Self {
+			id,
+			description,
+			max_capacity,
+			free_space,
+		}
+	}
+}
+
+/// All devices connected to this node, skipping the ones we failed to open, which usually are
+/// locked phones
+pub async fn connected_devices() -> Result<Vec<MtpDevice>, MtpError> {
+	tokio::task::spawn_blocking(|| {
+		Ok::<_, MtpError>(
+			sd_mtp::connected_serial_numbers()?
+				.into_iter()
+				.filter_map(|serial_number| {
+					let device = Device::open(&serial_number)
+						.map_err(|e| debug!("Skipping MTP device: {e:#?}"))
+						.ok()?;
+
+					Some(MtpDevice {
+						serial_number,
+						name: device.name().to_string(),
+						storages: device
+							.storages()
+							.map_err(|e| debug!("Skipping MTP device storages: {e:#?}"))
+							.ok()?
+							.into_iter()
+							.map(Into::into)
+							.collect(),
+					})
+				})
+				.collect(),
+		)
+	})
+	.await?
+}
+
+/// Whether the device of a location is connected, without opening it
+pub async fn is_connected(config: &MtpLocationConfig) -> Result<bool, MtpError> {
+	let serial_number = config.serial_number.clone();
+
+	Ok(
+		tokio::task::spawn_blocking(sd_mtp::connected_serial_numbers)
+			.await??
+			.contains(&serial_number),
+	)
+}
+
+/// A file or folder listed from the device, with its path relative to the storage root
+#[derive(Debug, Clone)]
+pub struct MtpEntry {
+	pub path: PathBuf,
+	pub object: Object,
+}
+
+impl MtpEntry {
+	/// Object handles aren't stable across connections, but file paths must be unique by inode
+	/// inside their location
+	pub fn pseudo_inode(&self) -> u64 {
+		let hash = blake3::hash(self.path.to_string_lossy().as_bytes());
+		let mut bytes = [0; 8];
+		bytes.copy_from_slice(&hash.as_bytes()[..8]);
+
+		u64::from_le_bytes(bytes)
+	}
+}
+
+pub struct MtpLocation {
+	device: Arc<Device>,
+	config: MtpLocationConfig,
+	/// Folders listed so far by their path relative to the storage root, as MTP can only list
+	/// folders by their handles, which we have to find from the root every time
+	listings: Mutex<HashMap<PathBuf, Arc<Vec<Object>>>>,
+}
+
+impl MtpLocation {
+	pub async fn connect(config: MtpLocationConfig) -> Result<Self, MtpError> {
+		let serial_number = config.serial_number.clone();
+
+		let device = tokio::task::spawn_blocking(move || Device::open(&serial_number)).await??;
+
+		let storages = {
+			let device = Arc::clone(&device);
+			tokio::task::spawn_blocking(move || device.storages()).await??
+		};
+
+		if !storages
+			.iter()
+			.any(|storage| storage.id == config.storage_id)
+		{
+			return Err(MtpError::StorageNotFound {
+				serial_number: config.serial_number,
+				storage_id: config.storage_id,
+			});
+		}
+
+		Ok(Self {
+			device,
+			config,
+			listings: Mutex::default(),
+		})
+	}
+
+	/// Opens the location's device, if it's an MTP location
+	pub async fn for_location(
+		location_id: location::id::Type,
+		mtp_config: Option<&[u8]>,
+	) -> Result<Option<Self>, MtpError> {
+		match MtpLocationConfig::from_db(location_id, mtp_config)? {
+			Some(config) => Self::connect(config).await.map(Some),
+			None => Ok(None),
+		}
+	}
+
+	pub const fn config(&self) -> &MtpLocationConfig {
+		&self.config
+	}
+
+	/// Lists the direct children of the folder at `sub_path`, relative to the storage root,
+	/// or `None` if there's no such folder
+	pub async fn list(
+		&self,
+		sub_path: impl AsRef<Path>,
+	) -> Result<Option<Vec<MtpEntry>>, MtpError> {
+		let sub_path = sub_path.as_ref();
+
+		Ok(self.listing(sub_path).await?.map(|objects| {
+			objects
+				.iter()
+				.map(|object| MtpEntry {
+					path: sub_path.join(&object.name),
+					object: object.clone(),
+				})
+				.collect()
+		}))
+	}
+
+	/// The object at `path`, relative to the storage root
+	pub async fn find(&self, path: impl AsRef<Path>) -> Result<Option<Object>, MtpError> {
+		let path = path.as_ref();
+
+		let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+			return Ok(None);
+		};
+
+		Ok(self.listing(parent).await?.and_then(|objects| {
+			objects
+				.iter()
+				.find(|object| object.name.as_str() == name)
+				.cloned()
+		}))
+	}
+
+	/// The `cas_id` a local copy of the file would get, reading only the sampled parts of it, or
+	/// `None` if the device can't read parts of files. Empty files don't get a `cas_id`, the same
+	/// as empty local files.
+	pub async fn cas_id(&self, object: &Object) -> Result<Option<String>, MtpError> {
+		if object.size == 0 || !self.device.supports_partial_reads() {
+			return Ok(None);
+		}
+
+		let (device, id, size) = (Arc::clone(&self.device), object.id, object.size);
+
+		tokio::task::spawn_blocking(move || {
+			let samples = sampled_ranges(size)
+				.into_iter()
+				.map(|range| {
+					// Samples are a few KiB at most
+					let len = u32::try_from(range.end - range.start).unwrap_or(u32::MAX);
+					device.read_partial(id, range.start, len)
+				})
+				.collect::<Result<Vec<_>, _>>()?;
+
+			Ok(Some(generate_cas_id_from_samples(size, samples)))
+		})
+		.await?
+	}
+
+	/// Makes the file at `path`, relative to the storage root, available in the location's mirror
+	/// directory and returns its local path.
+	///
+	/// Mirrored copies keep the modification date of the object, so they're only copied again if
+	/// the object changed since.
+	pub async fn read_through(
+		&self,
+		location_path: impl AsRef<Path>,
+		path: impl AsRef<Path>,
+	) -> Result<PathBuf, MtpError> {
+		let path = path.as_ref();
+		let local_path = location_path.as_ref().join(path);
+
+		let object = self
+			.find(path)
+			.await?
+			.ok_or_else(|| MtpError::NotFound(path.to_path_buf()))?;
+
+		if let Ok(metadata) = fs::metadata(&local_path).await {
+			if metadata.len() == object.size
+				&& metadata.modified().ok() == Some(SystemTime::from(object.modified_at))
+			{
+				return Ok(local_path);
+			}
+		}
+
+		self.download(&object, &local_path).await?;
+
+		Ok(local_path)
+	}
+
+	/// Copies `object` from the device to `path`
+	pub async fn download(&self, object: &Object, path: impl AsRef<Path>) -> Result<(), MtpError> {
+		let path = path.as_ref();
+
+		if let Some(parent) = path.parent() {
+			fs::create_dir_all(parent)
+				.await
+				.map_err(|e| FileIOError::from((parent, e)))?;
+		}
+
+		// Copying to a temporary file, so an interrupted copy is never taken as the file
+		let tmp_path = {
+			let mut tmp_path = path.to_path_buf().into_os_string();
+			tmp_path.push(".sdpart");
+			PathBuf::from(tmp_path)
+		};
+
+		let (device, id) = (Arc::clone(&self.device), object.id);
+		let download_path = tmp_path.clone();
+		tokio::task::spawn_blocking(move || device.download(id, download_path)).await??;
+
+		fs::File::options()
+			.write(true)
+			.open(&tmp_path)
+			.await
+			.map_err(|e| FileIOError::from((&tmp_path, e)))?
+			.into_std()
+			.await
+			.set_modified(SystemTime::from(object.modified_at))
+			.map_err(|e| FileIOError::from((&tmp_path, e, "Failed to set modified date")))?;
+
+		fs::rename(&tmp_path, path)
+			.await
+			.map_err(|e| FileIOError::from((path, e)))?;
+
+		debug!(
+			"Copied MTP object <id='{}'> to <path='{}'>",
+			object.id,
+			path.display()
+		);
+
+		Ok(())
+	}
+
+	async fn listing(&self, sub_path: &Path) -> Result<Option<Arc<Vec<Object>>>, MtpError> {
+		let mut current = PathBuf::new();
+		let mut parent_id = None;
+
+		for component in sub_path.components() {
+			let Component::Normal(name) = component else {
+				continue;
+			};
+
+			let objects = self.listing_of(&current, parent_id).await?;
+
+			let Some(folder) = objects
+				.iter()
+				.find(|object| object.is_folder && object.name.as_str() == name)
+			else {
+				return Ok(None);
+			};
+
+			parent_id = Some(folder.id);
+			current.push(name);
+		}
+
+		self.listing_of(&current, parent_id).await.map(Some)
+	}
+
+	async fn listing_of(
+		&self,
+		path: &Path,
+		parent_id: Option<u32>,
+	) -> Result<Arc<Vec<Object>>, MtpError> {
+		if let Some(objects) = self
+			.listings
+			.lock()
+			.unwrap_or_else(|poisoned| poisoned.into_inner())
+			.get(path)
+		{
+			return Ok(Arc::clone(objects));
+		}
+
+		let (device, storage_id) = (Arc::clone(&self.device), self.config.storage_id);
+		let objects = Arc::new(
+			tokio::task::spawn_blocking(move || device.list(storage_id, parent_id)).await??,
+		);
+
+		self.listings
+			.lock()
+			.unwrap_or_else(|poisoned| poisoned.into_inner())
+			.insert(path.to_path_buf(), Arc::clone(&objects));
+
+		Ok(objects)
+	}
+}
@@ -0,0 +1,434 @@
+//! Locations backed by a bucket in S3 or any S3 compatible object storage.
+//!
+//! These locations have a local mirror directory as their path, which holds the directory
+//! skeleton of the bucket and the objects we had to download, as the indexer lists the objects
+//! through the S3 API and the file identifier works from their `ETag`s, without reading them.
+//! Objects are only downloaded, on demand, when something needs their content, like thumbnails.
+
+use sd_prisma::prisma::location;
+use sd_utils::error::FileIOError;
+
+use std::{
+	collections::HashMap,
+	path::{Path, PathBuf},
+};
+
+use aws_config::{BehaviorVersion, Region};
+use aws_sdk_s3::{error::DisplayErrorContext, primitives::ByteStream, Client};
+use chrono::{DateTime, TimeZone, Utc};
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::{fs, io};
+use tracing::debug;
+
+/// User metadata key where uploaders can store the Spacedrive `cas_id` of an object, so it
+/// deduplicates with local copies of the same file
+pub const CAS_ID_METADATA_KEY: &str = "sd-cas-id";
+
+#[derive(thiserror::Error, Debug)]
+pub enum S3Error {
+	#[error("failed to decode S3 config of location <id='{0}'>: {1}")]
+	Decode(location::id::Type, rmp_serde::decode::Error),
+	#[error("failed to list objects <bucket='{bucket}', prefix='{prefix}'>: {message}")]
+	List {
+		bucket: String,
+		prefix: String,
+		message: String,
+	},
+	#[error("failed to fetch object <bucket='{bucket}', key='{key}'>: {message}")]
+	Object {
+		bucket: String,
+		key: String,
+		message: String,
+	},
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+}
+
+impl From<S3Error> for rspc::Error {
+	fn from(err: S3Error) -> Self {
+		match err {
+			// Usually wrong bucket, endpoint or credentials
+			S3Error::List { .. } | S3Error::Object { .. } => {
+				Self::with_cause(ErrorCode::PreconditionFailed, err.to_string(), err)
+			}
+
+			_ => Self::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+/// Where the objects of a S3 location live, stored msgpack encoded on `location.s3_config`.
+///
+/// Credentials are never stored, they come from the AWS default credentials chain (environment
+/// variables, shared credentials file, SSO or instance metadata), optionally from a named profile.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct S3LocationConfig {
+	pub bucket: String,
+	/// Only objects under this key prefix belong to the location
+	#[serde(default)]
+	pub prefix: String,
+	pub region: String,
+	/// Endpoint of S3 compatible services, like MinIO, Cloudflare R2 or Backblaze B2
+	pub endpoint: Option<String>,
+	/// AWS profile to take credentials from
+	pub profile: Option<String>,
+	/// Addresses buckets as `endpoint/bucket` instead of `bucket.endpoint`, as most self hosted
+	/// services require
+	#[serde(default)]
+	pub force_path_style: bool,
+}
+
+impl S3LocationConfig {
+	pub fn from_db(
+		location_id: location::id::Type,
+		s3_config: Option<&[u8]>,
+	) -> Result<Option<Self>, S3Error> {
+		s3_config
+			.map(|bytes| rmp_serde::from_slice(bytes).map_err(|e| S3Error::Decode(location_id, e)))
+			.transpose()
+	}
+
+	pub fn to_db(&self) -> Vec<u8> {
+		rmp_serde::to_vec_named(self).expect("S3 config is always serializable")
+	}
+
+	/// Local directory mirroring the bucket, which is used as the location path
+	pub fn mirror_path(&self, data_dir: impl AsRef<Path>) -> PathBuf {
+		let mut hasher = blake3::Hasher::new();
+		hasher.update(self.endpoint.as_deref().unwrap_or_default().as_bytes());
+		hasher.update(self.bucket.as_bytes());
+		hasher.update(self.prefix.as_bytes());
+
+		data_dir.as_ref().join("s3").join(format!(
+			"{}-{}",
+			self.bucket,
+			&hasher.finalize().to_hex()[..8]
+		))
+	}
+
+	fn prefix(&self) -> &str {
+		self.prefix.trim_matches('/')
+	}
+
+	fn full_key(&self, key: &str) -> String {
+		match self.prefix() {
+			"" => key.to_string(),
+			prefix => format!("{prefix}/{key}"),
+		}
+	}
+}
+
+/// Object key of a path relative to the location, as keys always use forward slashes
+pub fn object_key(relative_path: impl AsRef<Path>) -> String {
+	relative_path
+		.as_ref()
+		.components()
+		.map(|component| component.as_os_str().to_string_lossy())
+		.collect::<Vec<_>>()
+		.join("/")
+}
+
+/// An object listed from the bucket, with its key relative to the location's prefix
+#[derive(Debug, Clone)]
+pub struct S3Object {
+	pub key: String,
+	pub size: u64,
+	pub last_modified: DateTime<Utc>,
+	pub etag: Option<String>,
+}
+
+impl S3Object {
+	/// Keys ending with a slash are the empty objects some clients create to represent folders
+	pub fn is_dir_marker(&self) -> bool {
+		self.key.ends_with('/')
+	}
+
+	/// Objects don't have inodes, but file paths must be unique by them inside their location
+	pub fn pseudo_inode(&self) -> u64 {
+		let hash = blake3::hash(self.key.as_bytes());
+		let mut bytes = [0; 8];
+		bytes.copy_from_slice(&hash.as_bytes()[..8]);
+
+		u64::from_le_bytes(bytes)
+	}
+}
+
+/// What the file identifier uses to identify an object, instead of the file's [`std::fs::Metadata`]
+#[derive(Debug, Clone)]
+pub struct S3ObjectIdentity {
+	pub size: u64,
+	pub etag: Option<String>,
+	pub user_metadata: HashMap<String, String>,
+}
+
+impl S3ObjectIdentity {
+	/// The `cas_id` stored by the uploader in the object's user metadata, or one derived from its
+	/// `ETag`, which changes whenever the content does. Empty objects don't get a `cas_id`, the
+	/// same as empty local files.
+	pub fn cas_id(&self) -> Option<String> {
+		if self.size == 0 {
+			return None;
+		}
+
+		if let Some(cas_id) = self.user_metadata.get(CAS_ID_METADATA_KEY) {
+			return Some(cas_id.clone());
+		}
+
+		self.etag.as_ref().map(|etag| {
+			let mut hasher = blake3::Hasher::new();
+			hasher.update(&self.size.to_le_bytes());
+			hasher.update(etag.trim_matches('"').as_bytes());
+
+			hasher.finalize().to_hex()[..16].to_string()
+		})
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct S3Location {
+	client: Client,
+	config: S3LocationConfig,
+}
+
+impl S3Location {
+	pub async fn connect(config: S3LocationConfig) -> Self {
+		let mut loader = aws_config::defaults(BehaviorVersion::latest())
+			.region(Region::new(config.region.clone()));
+
+		if let Some(profile) = &config.profile {
+			loader = loader.profile_name(profile);
+		}
+
+		if let Some(endpoint) = &config.endpoint {
+			loader = loader.endpoint_url(endpoint);
+		}
+
+		let client = Client::from_conf(
+			aws_sdk_s3::config::Builder::from(&loader.load().await)
+				.force_path_style(config.force_path_style)
+				.build(),
+		);
+
+		Self { client, config }
+	}
+
+	/// Connects to the location's bucket, if it's a S3 location
+	pub async fn for_location(
+		location_id: location::id::Type,
+		s3_config: Option<&[u8]>,
+	) -> Result<Option<Self>, S3Error> {
+		Ok(match S3LocationConfig::from_db(location_id, s3_config)? {
+			Some(config) => Some(Self::connect(config).await),
+			None => None,
+		})
+	}
+
+	/// Lists every object under `sub_path`, relative to the location's prefix
+	pub async fn list(&self, sub_path: &str) -> Result<Vec<S3Object>, S3Error> {
+		let prefix = match self.config.full_key(sub_path.trim_matches('/')) {
+			prefix if prefix.is_empty() => prefix,
+			prefix => format!("{prefix}/"),
+		};
+
+		let key_offset = match self.config.prefix() {
+			"" => 0,
+			location_prefix => location_prefix.len() + 1,
+		};
+
+		let mut pages = self
+			.client
+			.list_objects_v2()
+			.bucket(&self.config.bucket)
+			.prefix(&prefix)
+			.into_paginator()
+			.send();
+
+		let mut objects = vec![];
+
+		while let Some(page) = pages.next().await {
+			let page = page.map_err(|e| S3Error::List {
+				bucket: self.config.bucket.clone(),
+				prefix: prefix.clone(),
+				message: DisplayErrorContext(e).to_string(),
+			})?;
+
+			objects.extend(page.contents().iter().filter_map(|object| {
+				let key = object.key()?.get(key_offset..)?;
+
+				(!key.is_empty()).then(|| S3Object {
+					key: key.to_string(),
+					size: object
+						.size()
+						.and_then(|size| u64::try_from(size).ok())
+						.unwrap_or_default(),
+					last_modified: object
+						.last_modified()
+						.and_then(|date| {
+							Utc.timestamp_opt(date.secs(), date.subsec_nanos()).single()
+						})
+						.unwrap_or_else(Utc::now),
+					etag: object.e_tag().map(str::to_string),
+				})
+			}));
+		}
+
+		debug!(
+			"Listed {} objects <bucket='{}', prefix='{prefix}'>",
+			objects.len(),
+			self.config.bucket
+		);
+
+		Ok(objects)
+	}
+
+	pub async fn identity(&self, key: &str) -> Result<S3ObjectIdentity, S3Error> {
+		let full_key = self.config.full_key(key);
+
+		let head = self
+			.client
+			.head_object()
+			.bucket(&self.config.bucket)
+			.key(&full_key)
+			.send()
+			.await
+			.map_err(|e| S3Error::Object {
+				bucket: self.config.bucket.clone(),
+				key: full_key,
+				message: DisplayErrorContext(e).to_string(),
+			})?;
+
+		Ok(S3ObjectIdentity {
+			size: head
+				.content_length()
+				.and_then(|size| u64::try_from(size).ok())
+				.unwrap_or_default(),
+			etag: head.e_tag().map(str::to_string),
+			user_metadata: head.metadata().cloned().unwrap_or_default(),
+		})
+	}
+
+	/// Reads the whole object at `key`
+	pub async fn read(&self, key: &str) -> Result<Vec<u8>, S3Error> {
+		let full_key = self.config.full_key(key);
+		let object_error = |message: String| S3Error::Object {
+			bucket: self.config.bucket.clone(),
+			key: full_key.clone(),
+			message,
+		};
+
+		let object = self
+			.client
+			.get_object()
+			.bucket(&self.config.bucket)
+			.key(&full_key)
+			.send()
+			.await
+			.map_err(|e| object_error(DisplayErrorContext(e).to_string()))?;
+
+		object
+			.body
+			.collect()
+			.await
+			.map(|body| body.into_bytes().to_vec())
+			.map_err(|e| object_error(e.to_string()))
+	}
+
+	pub async fn write(&self, key: &str, contents: Vec<u8>) -> Result<(), S3Error> {
+		let full_key = self.config.full_key(key);
+
+		self.client
+			.put_object()
+			.bucket(&self.config.bucket)
+			.key(&full_key)
+			.body(ByteStream::from(contents))
+			.send()
+			.await
+			.map(|_| ())
+			.map_err(|e| S3Error::Object {
+				bucket: self.config.bucket.clone(),
+				key: full_key,
+				message: DisplayErrorContext(e).to_string(),
+			})
+	}
+
+	pub async fn remove(&self, key: &str) -> Result<(), S3Error> {
+		let full_key = self.config.full_key(key);
+
+		self.client
+			.delete_object()
+			.bucket(&self.config.bucket)
+			.key(&full_key)
+			.send()
+			.await
+			.map(|_| ())
+			.map_err(|e| S3Error::Object {
+				bucket: self.config.bucket.clone(),
+				key: full_key,
+				message: DisplayErrorContext(e).to_string(),
+			})
+	}
+
+	/// Makes the object available in the location's mirror directory, downloading it only if the
+	/// mirrored copy is missing or, when we know the object's size, doesn't match it. Returns the
+	/// object's local path.
+	pub async fn read_through(
+		&self,
+		location_path: impl AsRef<Path>,
+		key: &str,
+		expected_size: Option<u64>,
+	) -> Result<PathBuf, S3Error> {
+		let path = location_path.as_ref().join(key);
+
+		if fs::metadata(&path).await.map_or(false, |metadata| {
+			metadata.is_file() && expected_size.map_or(true, |size| metadata.len() == size)
+		}) {
+			return Ok(path);
+		}
+
+		let full_key = self.config.full_key(key);
+
+		let object = self
+			.client
+			.get_object()
+			.bucket(&self.config.bucket)
+			.key(&full_key)
+			.send()
+			.await
+			.map_err(|e| S3Error::Object {
+				bucket: self.config.bucket.clone(),
+				key: full_key,
+				message: DisplayErrorContext(e).to_string(),
+			})?;
+
+		if let Some(parent) = path.parent() {
+			fs::create_dir_all(parent)
+				.await
+				.map_err(|e| FileIOError::from((parent, e)))?;
+		}
+
+		// Downloading to a temporary file, so an interrupted download is never taken as the object
+		let tmp_path = {
+			let mut tmp_path = path.clone().into_os_string();
+			tmp_path.push(".sdpart");
+			PathBuf::from(tmp_path)
+		};
+
+		let mut file = fs::File::create(&tmp_path)
+			.await
+			.map_err(|e| FileIOError::from((&tmp_path, e)))?;
+
+		io::copy(&mut object.body.into_async_read(), &mut file)
+			.await
+			.map_err(|e| FileIOError::from((&tmp_path, e, "Failed to download object")))?;
+
+		fs::rename(&tmp_path, &path)
+			.await
+			.map_err(|e| FileIOError::from((&path, e)))?;
+
+		debug!("Downloaded object <key='{key}'> to the location mirror");
+
+		Ok(path)
+	}
+}
@@ -0,0 +1,62 @@
+use crate::{context::NodeContext, library::Library, Node};
+
+use sd_core_heavy_lifting::job_system::schedule;
+
+use std::{
+	sync::{Arc, Weak},
+	time::Duration,
+};
+
+use chrono::Utc;
+use tokio::time::sleep;
+use tracing::{debug, error};
+
+/// Schedules are checked at least this often, so ones created or enabled since the last check, and
+/// runs that failed to dispatch, don't wait for the next wake up
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+/// Runs the job schedules of the library as they're due until it's unloaded, starting with the
+/// occurrences missed while the node was offline
+pub fn spawn_job_schedules(node: Arc<Node>, library: &Arc<Library>) {
+	let library = Arc::downgrade(library);
+
+	tokio::spawn(async move {
+		loop {
+			let Some(library) = Weak::upgrade(&library) else {
+				debug!("Library unloaded, stopping job schedules");
+				break;
+			};
+
+			if !library.is_read_only().await {
+				let cas_id_algorithm = library.config().await.cas_id_algorithm;
+
+				if let Err(e) = node
+					.job_system
+					.run_due_schedules(
+						&NodeContext::new(Arc::clone(&node), Arc::clone(&library)),
+						cas_id_algorithm,
+					)
+					.await
+				{
+					error!("Failed to run job schedules: {e:#?}");
+				}
+			}
+
+			let sleep_for = match schedule::next_wake_up(&library.db).await {
+				Ok(Some(wake_up)) => (wake_up - Utc::now())
+					.to_std()
+					// Already due, so a run failed to dispatch
+					.map_or(MAX_SLEEP, |until_wake_up| until_wake_up.min(MAX_SLEEP)),
+				Ok(None) => MAX_SLEEP,
+				Err(e) => {
+					error!("Failed to fetch the next job schedule wake up: {e:#?}");
+					MAX_SLEEP
+				}
+			};
+
+			drop(library);
+
+			sleep(sleep_for).await;
+		}
+	});
+}
@@ -0,0 +1,759 @@
+//! Keeping the previous contents of the files of a location as they get modified, so they can be
+//! restored.
+//!
+//! The watcher only learns of a modification once the new content is on disk, so the current
+//! content of each file is kept as well, becoming a version when the watcher sees it replaced.
+//! Contents are stored once per `cas_id` in the version store of the library, in the node's data
+//! directory, and removed once no version refers to them anymore.
+
+use crate::{invalidate_query, library::Library, object::old_file_identifier::FileMetadata, Node};
+
+use sd_core_file_path_helper::IsolatedFilePathData;
+use sd_core_prisma_helpers::file_path_to_isolate;
+
+use sd_prisma::prisma::{file_path, file_version, location, PrismaClient, SortOrder};
+use sd_utils::{
+	db::{maybe_missing, size_in_bytes_from_db, size_in_bytes_to_db, MissingFieldError},
+	error::FileIOError,
+};
+
+use std::{
+	collections::{HashMap, HashSet},
+	io,
+	path::{Path, PathBuf},
+	sync::{Arc, Weak},
+	time::Duration,
+};
+
+use chrono::{DateTime, FixedOffset, Utc};
+use prisma_client_rust::QueryError;
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+use specta::Type;
+use tokio::{
+	fs,
+	time::{interval_at, Instant, MissedTickBehavior},
+};
+use tracing::{debug, error};
+use uuid::Uuid;
+
+const VERSIONS_DIR_NAME: &str = "versions";
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60 * 24); // 1 day
+
+#[derive(thiserror::Error, Debug)]
+pub enum VersioningError {
+	#[error("failed to decode versioning policy of location <id='{0}'>: {1}")]
+	Decode(location::id::Type, rmp_serde::decode::Error),
+	#[error("location not found <id='{0}'>")]
+	LocationNotFound(location::id::Type),
+	#[error("file version not found <id='{0}'>")]
+	NotFound(file_version::id::Type),
+	#[error("content of the version is gone from the version store <cas_id='{0}'>")]
+	MissingContent(String),
+	#[error("database error: {0}")]
+	Database(#[from] QueryError),
+	#[error(transparent)]
+	MissingField(#[from] MissingFieldError),
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+}
+
+impl From<VersioningError> for rspc::Error {
+	fn from(err: VersioningError) -> Self {
+		match err {
+			VersioningError::LocationNotFound(_) | VersioningError::NotFound(_) => {
+				Self::with_cause(ErrorCode::NotFound, err.to_string(), err)
+			}
+
+			VersioningError::MissingContent(_) => {
+				Self::with_cause(ErrorCode::PreconditionFailed, err.to_string(), err)
+			}
+
+			_ => Self::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+/// How long the previous contents of the files of a location are kept, stored msgpack encoded on
+/// `location.versioning`, which is local only as the version store lives on this node. A location
+/// without a policy isn't versioned.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct VersioningPolicy {
+	/// Versions kept for each file, the oldest ones going first, every version if `None`
+	#[serde(default)]
+	pub max_versions: Option<u32>,
+	/// Versions replaced more than this many days ago go, whatever their number
+	#[serde(default)]
+	pub max_age_days: Option<u32>,
+	/// Files bigger than this many megabytes aren't versioned
+	#[serde(default)]
+	pub max_file_size_mb: Option<u32>,
+}
+
+impl VersioningPolicy {
+	pub fn from_db(
+		location_id: location::id::Type,
+		versioning: Option<&[u8]>,
+	) -> Result<Option<Self>, VersioningError> {
+		versioning
+			.map(|bytes| {
+				rmp_serde::from_slice(bytes).map_err(|e| VersioningError::Decode(location_id, e))
+			})
+			.transpose()
+	}
+
+	pub fn to_db(&self) -> Vec<u8> {
+		rmp_serde::to_vec_named(self).expect("versioning policy is always serializable")
+	}
+
+	fn allows_size(&self, size: u64) -> bool {
+		self.max_file_size_mb
+			.map_or(true, |max| size <= u64::from(max) * 1024 * 1024)
+	}
+
+	/// Versions to drop out of `versions`, sorted newest first
+	fn expired(
+		&self,
+		versions: &[(file_version::id::Type, String, DateTime<Utc>)],
+		now: DateTime<Utc>,
+	) -> Vec<file_version::id::Type> {
+		let cutoff = self
+			.max_age_days
+			.map(|days| now - chrono::Duration::days(days.into()));
+
+		let mut kept = HashMap::<&str, u32>::new();
+
+		versions
+			.iter()
+			.filter_map(|(id, path, date_replaced)| {
+				let kept = kept.entry(path.as_str()).or_default();
+
+				let too_old = cutoff.map_or(false, |cutoff| *date_replaced < cutoff);
+				let too_many = self.max_versions.map_or(false, |max| *kept >= max);
+
+				if too_old || too_many {
+					Some(*id)
+				} else {
+					*kept += 1;
+					None
+				}
+			})
+			.collect()
+	}
+}
+
+/// A previous content of a file, which can be restored over the current one
+#[serde_as]
+#[derive(Debug, Serialize, Type)]
+pub struct FileVersion {
+	pub id: file_version::id::Type,
+	/// Relative to the root of the location
+	pub path: PathBuf,
+	pub cas_id: String,
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub size_in_bytes: u64,
+	pub date_modified: DateTime<Utc>,
+	pub date_replaced: DateTime<Utc>,
+}
+
+impl TryFrom<file_version::Data> for FileVersion {
+	type Error = MissingFieldError;
+
+	fn try_from(data: file_version::Data) -> Result<Self, Self::Error> {
+		Ok(Self {
+			id: data.id,
+			path: PathBuf::from(data.path),
+			cas_id: data.cas_id,
+			size_in_bytes: size_in_bytes_from_db(&data.size_in_bytes_bytes),
+			date_modified: data.date_modified.into(),
+			date_replaced: maybe_missing(data.date_replaced, "file_version.date_replaced")?.into(),
+		})
+	}
+}
+
+/// Directory holding the contents of the versions of a library
+pub fn store_path(data_dir: impl AsRef<Path>, library_id: Uuid) -> PathBuf {
+	data_dir
+		.as_ref()
+		.join(VERSIONS_DIR_NAME)
+		.join(library_id.to_string())
+}
+
+fn content_path(store: &Path, cas_id: &str) -> PathBuf {
+	// Sharded like thumbnails, so no directory ends up with millions of files
+	store.join(cas_id.get(0..3).unwrap_or(cas_id)).join(cas_id)
+}
+
+async fn policy(
+	db: &PrismaClient,
+	location_id: location::id::Type,
+) -> Result<Option<VersioningPolicy>, VersioningError> {
+	let location = db
+		.location()
+		.find_unique(location::id::equals(location_id))
+		.select(location::select!({ versioning }))
+		.exec()
+		.await?
+		.ok_or(VersioningError::LocationNotFound(location_id))?;
+
+	VersioningPolicy::from_db(location_id, location.versioning.as_deref())
+}
+
+/// Copies `full_path` into the store, unless a content with the same `cas_id` is already there
+async fn store_content(store: &Path, cas_id: &str, full_path: &Path) -> Result<(), FileIOError> {
+	let content = content_path(store, cas_id);
+
+	if fs::metadata(&content).await.is_ok() {
+		return Ok(());
+	}
+
+	if let Some(parent) = content.parent() {
+		fs::create_dir_all(parent)
+			.await
+			.map_err(|e| FileIOError::from((parent, e, "Failed to create version store")))?;
+	}
+
+	// Copied under another name first, so a content cut short is never taken for a complete one
+	let partial = content.with_extension("part");
+
+	fs::copy(full_path, &partial)
+		.await
+		.map_err(|e| FileIOError::from((full_path, e, "Failed to copy file into version store")))?;
+
+	fs::rename(&partial, &content)
+		.await
+		.map_err(|e| FileIOError::from((&partial, e, "Failed to complete stored version")))
+}
+
+/// Called by the watcher with the content it found in `full_path`. In a versioned location, a
+/// content other than the one we kept for the file replaces it, which becomes a version.
+pub(crate) async fn record(
+	node: &Node,
+	library: &Library,
+	iso_file_path: &IsolatedFilePathData<'_>,
+	full_path: &Path,
+	cas_id: &str,
+	size: u64,
+	date_modified: DateTime<Utc>,
+) -> Result<(), VersioningError> {
+	let db = &library.db;
+	let location_id = iso_file_path.location_id();
+
+	let Some(policy) = policy(db, location_id).await? else {
+		return Ok(());
+	};
+
+	if !policy.allows_size(size) {
+		return Ok(());
+	}
+
+	let path = iso_file_path.as_ref().to_string_lossy().to_string();
+
+	let current = db
+		.file_version()
+		.find_first(vec![
+			file_version::location_id::equals(location_id),
+			file_version::path::equals(path.clone()),
+			file_version::date_replaced::equals(None),
+		])
+		.exec()
+		.await?;
+
+	if current
+		.as_ref()
+		.map_or(false, |current| current.cas_id == cas_id)
+	{
+		return Ok(());
+	}
+
+	let store = store_path(&node.data_dir, library.id);
+
+	store_content(&store, cas_id, full_path).await?;
+
+	let date_replaced: DateTime<FixedOffset> = Utc::now().into();
+
+	if let Some(current) = &current {
+		db.file_version()
+			.update(
+				file_version::id::equals(current.id),
+				vec![file_version::date_replaced::set(Some(date_replaced))],
+			)
+			.exec()
+			.await?;
+	}
+
+	db.file_version()
+		.create(
+			location::id::equals(location_id),
+			path.clone(),
+			cas_id.to_string(),
+			size_in_bytes_to_db(size),
+			date_modified.into(),
+			vec![],
+		)
+		.exec()
+		.await?;
+
+	if current.is_some() {
+		debug!("Kept previous version of <path='{}'>", full_path.display());
+
+		apply_retention(db, &store, location_id, Some(path), &policy).await?;
+
+		invalidate_query!(library, "locations.versions.list");
+	}
+
+	Ok(())
+}
+
+/// Versions of the files of a location, or of the file at `path` of it, newest first
+pub async fn list(
+	db: &PrismaClient,
+	location_id: location::id::Type,
+	path: Option<&Path>,
+) -> Result<Vec<FileVersion>, VersioningError> {
+	db.file_version()
+		.find_many(
+			[
+				Some(file_version::location_id::equals(location_id)),
+				Some(file_version::date_replaced::not(None)),
+				path.map(|path| file_version::path::equals(path.to_string_lossy().to_string())),
+			]
+			.into_iter()
+			.flatten()
+			.collect(),
+		)
+		.order_by(file_version::date_replaced::order(SortOrder::Desc))
+		.exec()
+		.await?
+		.into_iter()
+		.map(|data| FileVersion::try_from(data).map_err(Into::into))
+		.collect()
+}
+
+/// Writes the content of a version over the file it was a version of, which the watcher then keeps
+/// as a version in turn, so restoring can be undone
+pub async fn restore(
+	node: &Node,
+	library: &Library,
+	id: file_version::id::Type,
+) -> Result<PathBuf, VersioningError> {
+	let version = library
+		.db
+		.file_version()
+		.find_unique(file_version::id::equals(id))
+		.with(file_version::location::fetch())
+		.exec()
+		.await?
+		.ok_or(VersioningError::NotFound(id))?;
+
+	let location_path = maybe_missing(
+		&maybe_missing(&version.location, "file_version.location")?.path,
+		"location.path",
+	)
+	.map(PathBuf::from)?;
+
+	let content = content_path(&store_path(&node.data_dir, library.id), &version.cas_id);
+
+	if fs::metadata(&content).await.is_err() {
+		return Err(VersioningError::MissingContent(version.cas_id));
+	}
+
+	let target = location_path.join(&version.path);
+
+	if let Some(parent) = target.parent() {
+		fs::create_dir_all(parent)
+			.await
+			.map_err(|e| FileIOError::from((parent, e, "Failed to recreate version directory")))?;
+	}
+
+	fs::copy(&content, &target)
+		.await
+		.map_err(|e| FileIOError::from((&target, e, "Failed to restore version")))?;
+
+	Ok(target)
+}
+
+/// Sets how long versions are kept in a location, `None` to stop versioning it, which drops its
+/// versions. Versioning a location keeps the current content of its files, so their first
+/// modification already has a version to go back to.
+pub async fn set_policy(
+	node: &Arc<Node>,
+	library: &Arc<Library>,
+	location_id: location::id::Type,
+	policy: Option<VersioningPolicy>,
+) -> Result<(), VersioningError> {
+	let db = &library.db;
+	let store = store_path(&node.data_dir, library.id);
+
+	// Local only, the version store lives on this node
+	db.location()
+		.update(
+			location::id::equals(location_id),
+			vec![location::versioning::set(
+				policy.as_ref().map(VersioningPolicy::to_db),
+			)],
+		)
+		.exec()
+		.await?;
+
+	let Some(policy) = policy else {
+		let dropped = db
+			.file_version()
+			.find_many(vec![file_version::location_id::equals(location_id)])
+			.select(file_version::select!({ cas_id }))
+			.exec()
+			.await?;
+
+		db.file_version()
+			.delete_many(vec![file_version::location_id::equals(location_id)])
+			.exec()
+			.await?;
+
+		collect_garbage(
+			db,
+			&store,
+			dropped.into_iter().map(|version| version.cas_id),
+		)
+		.await?;
+
+		return Ok(());
+	};
+
+	apply_retention(db, &store, location_id, None, &policy).await?;
+
+	let node = Arc::clone(node);
+	let library = Arc::clone(library);
+
+	tokio::spawn(async move {
+		if let Err(e) = keep_current_contents(&node, &library, location_id, &policy).await {
+			error!("Failed to keep current contents of versioned location <id='{location_id}'>: {e:#?}");
+		}
+	});
+
+	Ok(())
+}
+
+/// Keeps the current content of the files of a location that don't have one kept yet
+async fn keep_current_contents(
+	node: &Node,
+	library: &Library,
+	location_id: location::id::Type,
+	policy: &VersioningPolicy,
+) -> Result<(), VersioningError> {
+	let db = &library.db;
+
+	let location = db
+		.location()
+		.find_unique(location::id::equals(location_id))
+		.select(location::select!({ path }))
+		.exec()
+		.await?
+		.ok_or(VersioningError::LocationNotFound(location_id))?;
+
+	let location_path = maybe_missing(location.path, "location.path").map(PathBuf::from)?;
+
+	let options = library.config().await.file_metadata_options();
+
+	let kept = db
+		.file_version()
+		.find_many(vec![
+			file_version::location_id::equals(location_id),
+			file_version::date_replaced::equals(None),
+		])
+		.select(file_version::select!({ path }))
+		.exec()
+		.await?
+		.into_iter()
+		.map(|version| PathBuf::from(version.path))
+		.collect::<HashSet<_>>();
+
+	let file_paths = db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(Some(location_id)),
+			file_path::is_dir::equals(Some(false)),
+		])
+		.select(file_path_to_isolate::select())
+		.exec()
+		.await?;
+
+	for file_path in file_paths {
+		let iso_file_path = match IsolatedFilePathData::try_from(&file_path) {
+			Ok(iso_file_path) => iso_file_path,
+			Err(e) => {
+				error!("Skipping file path we can't keep a version of: {e:#?}");
+				continue;
+			}
+		};
+
+		if kept.contains(iso_file_path.as_ref()) {
+			continue;
+		}
+
+		// Hashing again rather than trusting the index, which may be behind the disk
+		let Ok(FileMetadata {
+			cas_id: Some(cas_id),
+			fs_metadata,
+			..
+		}) = FileMetadata::new(&location_path, &iso_file_path, &options).await
+		else {
+			continue;
+		};
+
+		if !policy.allows_size(fs_metadata.len()) {
+			continue;
+		}
+
+		record(
+			node,
+			library,
+			&iso_file_path,
+			&location_path.join(&iso_file_path),
+			&cas_id,
+			fs_metadata.len(),
+			fs_metadata
+				.modified()
+				.map_or_else(|_| Utc::now(), DateTime::<Utc>::from),
+		)
+		.await?;
+	}
+
+	Ok(())
+}
+
+/// Drops the versions of a location, or of the file at `path` of it, that the policy doesn't keep
+async fn apply_retention(
+	db: &PrismaClient,
+	store: &Path,
+	location_id: location::id::Type,
+	path: Option<String>,
+	policy: &VersioningPolicy,
+) -> Result<(), VersioningError> {
+	let versions = db
+		.file_version()
+		.find_many(
+			[
+				Some(file_version::location_id::equals(location_id)),
+				Some(file_version::date_replaced::not(None)),
+				path.map(file_version::path::equals),
+			]
+			.into_iter()
+			.flatten()
+			.collect(),
+		)
+		.order_by(file_version::date_replaced::order(SortOrder::Desc))
+		.select(file_version::select!({ id path cas_id date_replaced }))
+		.exec()
+		.await?;
+
+	let expired = policy
+		.expired(
+			&versions
+				.iter()
+				.filter_map(|version| {
+					version
+						.date_replaced
+						.map(|date| (version.id, version.path.clone(), date.into()))
+				})
+				.collect::<Vec<_>>(),
+			Utc::now(),
+		)
+		.into_iter()
+		.collect::<HashSet<_>>();
+
+	if expired.is_empty() {
+		return Ok(());
+	}
+
+	db.file_version()
+		.delete_many(vec![file_version::id::in_vec(
+			expired.iter().copied().collect(),
+		)])
+		.exec()
+		.await?;
+
+	collect_garbage(
+		db,
+		store,
+		versions
+			.into_iter()
+			.filter(|version| expired.contains(&version.id))
+			.map(|version| version.cas_id),
+	)
+	.await
+}
+
+/// Removes the contents no version refers to anymore out of `cas_ids`
+async fn collect_garbage(
+	db: &PrismaClient,
+	store: &Path,
+	cas_ids: impl IntoIterator<Item = String>,
+) -> Result<(), VersioningError> {
+	for cas_id in cas_ids.into_iter().collect::<HashSet<_>>() {
+		if db
+			.file_version()
+			.count(vec![file_version::cas_id::equals(cas_id.clone())])
+			.exec()
+			.await? > 0
+		{
+			continue;
+		}
+
+		let content = content_path(store, &cas_id);
+
+		match fs::remove_file(&content).await {
+			Ok(()) => {}
+			Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+			Err(e) => {
+				return Err(
+					FileIOError::from((&content, e, "Failed to remove stored version")).into(),
+				)
+			}
+		}
+	}
+
+	Ok(())
+}
+
+/// Applies the policies of the versioned locations of this node. The current contents of files
+/// deleted from the disk become versions, to be dropped with the others once they expire.
+pub async fn prune(node: &Node, library: &Library) -> Result<(), VersioningError> {
+	let db = &library.db;
+	let store = store_path(&node.data_dir, library.id);
+
+	let locations = db
+		.location()
+		.find_many(vec![location::versioning::not(None)])
+		.select(location::select!({ id path versioning }))
+		.exec()
+		.await?;
+
+	for location in locations {
+		let Some(policy) = VersioningPolicy::from_db(location.id, location.versioning.as_deref())?
+		else {
+			continue;
+		};
+
+		let location_path = maybe_missing(location.path, "location.path").map(PathBuf::from)?;
+
+		// An unmounted drive would make every file look deleted
+		if fs::metadata(&location_path).await.is_err() {
+			continue;
+		}
+
+		let mut deleted = vec![];
+
+		for current in db
+			.file_version()
+			.find_many(vec![
+				file_version::location_id::equals(location.id),
+				file_version::date_replaced::equals(None),
+			])
+			.select(file_version::select!({ id path }))
+			.exec()
+			.await?
+		{
+			if fs::metadata(location_path.join(&current.path))
+				.await
+				.is_err()
+			{
+				deleted.push(current.id);
+			}
+		}
+
+		if !deleted.is_empty() {
+			db.file_version()
+				.update_many(
+					vec![file_version::id::in_vec(deleted)],
+					vec![file_version::date_replaced::set(Some(Utc::now().into()))],
+				)
+				.exec()
+				.await?;
+		}
+
+		apply_retention(db, &store, location.id, None, &policy).await?;
+	}
+
+	Ok(())
+}
+
+/// Prunes versions every [`PRUNE_INTERVAL`] until the library is unloaded
+pub fn spawn_version_pruning(node: Arc<Node>, library: &Arc<Library>) {
+	let library = Arc::downgrade(library);
+
+	tokio::spawn(async move {
+		let mut interval = interval_at(Instant::now() + Duration::from_secs(60), PRUNE_INTERVAL);
+		interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+		loop {
+			interval.tick().await;
+
+			let Some(library) = Weak::upgrade(&library) else {
+				debug!("Library unloaded, stopping version pruning");
+				break;
+			};
+
+			if let Err(e) = prune(&node, &library).await {
+				error!("Failed to prune file versions: {e:#?}");
+			} else {
+				invalidate_query!(library, "locations.versions.list");
+			}
+		}
+	});
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn expired_versions() {
+		let now = Utc::now();
+		let days_ago = |days| now - chrono::Duration::days(days);
+
+		let versions = vec![
+			(5, "notes.md".to_string(), days_ago(1)),
+			(4, "todo.md".to_string(), days_ago(2)),
+			(3, "notes.md".to_string(), days_ago(3)),
+			(2, "notes.md".to_string(), days_ago(10)),
+			(1, "todo.md".to_string(), days_ago(40)),
+		];
+
+		assert!(VersioningPolicy::default()
+			.expired(&versions, now)
+			.is_empty());
+
+		assert_eq!(
+			VersioningPolicy {
+				max_versions: Some(2),
+				..Default::default()
+			}
+			.expired(&versions, now),
+			vec![2]
+		);
+
+		assert_eq!(
+			VersioningPolicy {
+				max_versions: Some(1),
+				max_age_days: Some(30),
+				..Default::default()
+			}
+			.expired(&versions, now),
+			vec![3, 2, 1]
+		);
+	}
+
+	#[test]
+	fn file_size_limit() {
+		let policy = VersioningPolicy {
+			max_file_size_mb: Some(1),
+			..Default::default()
+		};
+
+		assert!(policy.allows_size(1024 * 1024));
+		assert!(!policy.allows_size(1024 * 1024 + 1));
+		assert!(VersioningPolicy::default().allows_size(u64::MAX));
+	}
+}
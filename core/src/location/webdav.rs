@@ -0,0 +1,602 @@
+//! Locations backed by a folder in a WebDAV server, like Nextcloud or ownCloud.
+//!
+//! Same as S3 locations, they have a local mirror directory as their path, holding the directory
+//! skeleton of the server and the files we had to download. The indexer walks the server with
+//! `PROPFIND`, the file identifier samples `cas_id`s through ranged `GET`s and mirrored copies are
+//! refreshed with conditional `GET`s, so files join the library without a local sync copy.
+
+use crate::object::cas::{generate_cas_id_from_samples, sampled_ranges};
+
+use sd_prisma::prisma::location;
+use sd_utils::error::FileIOError;
+
+use std::{
+	ops::Range,
+	path::{Path, PathBuf},
+	time::SystemTime,
+};
+
+use chrono::{DateTime, Utc};
+use futures::future::try_join_all;
+use percent_encoding::percent_decode_str;
+use quick_xml::{events::Event, Reader};
+use reqwest::{header, Client, Method, RequestBuilder, StatusCode, Url};
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::{fs, io::AsyncWriteExt};
+use tracing::debug;
+
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:">
+	<d:prop>
+		<d:resourcetype/>
+		<d:getcontentlength/>
+		<d:getlastmodified/>
+		<d:getetag/>
+	</d:prop>
+</d:propfind>"#;
+
+#[derive(thiserror::Error, Debug)]
+pub enum WebDavError {
+	#[error("failed to decode WebDAV config of location <id='{0}'>: {1}")]
+	Decode(location::id::Type, rmp_serde::decode::Error),
+	#[error("invalid WebDAV url <url='{url}'>: {message}")]
+	InvalidUrl { url: String, message: String },
+	#[error("WebDAV request failed <url='{url}'>: {source}")]
+	Request { url: String, source: reqwest::Error },
+	#[error("WebDAV server answered with status {status} <url='{url}'>")]
+	Status { url: String, status: StatusCode },
+	#[error("WebDAV server doesn't support ranged reads <url='{url}'>")]
+	RangesNotSupported { url: String },
+	#[error("failed to parse WebDAV response <url='{url}'>: {message}")]
+	Xml { url: String, message: String },
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+}
+
+impl From<WebDavError> for rspc::Error {
+	fn from(err: WebDavError) -> Self {
+		match err {
+			WebDavError::InvalidUrl { .. } => {
+				Self::with_cause(ErrorCode::BadRequest, err.to_string(), err)
+			}
+
+			// Usually an unreachable server or wrong credentials
+			WebDavError::Request { .. } | WebDavError::Status { .. } => {
+				Self::with_cause(ErrorCode::PreconditionFailed, err.to_string(), err)
+			}
+
+			_ => Self::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+/// Folder of a WebDAV server where a location lives, stored msgpack encoded on
+/// `location.webdav_config`, which is local only as it holds the password.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct WebDavLocationConfig {
+	/// Url of the folder, like `https://cloud.example.com/remote.php/dav/files/<user>/Photos`
+	pub url: String,
+	pub username: Option<String>,
+	/// Better an app password, which Nextcloud and ownCloud let users revoke for each device
+	pub password: Option<String>,
+}
+
+impl WebDavLocationConfig {
+	pub fn from_db(
+		location_id: location::id::Type,
+		webdav_config: Option<&[u8]>,
+	) -> Result<Option<Self>, WebDavError> {
+		webdav_config
+			.map(|bytes| {
+				rmp_serde::from_slice(bytes).map_err(|e| WebDavError::Decode(location_id, e))
+			})
+			.transpose()
+	}
+
+	pub fn to_db(&self) -> Vec<u8> {
+		rmp_serde::to_vec_named(self).expect("WebDAV config is always serializable")
+	}
+
+	/// Local directory mirroring the server folder, which is used as the location path
+	pub fn mirror_path(&self, data_dir: impl AsRef<Path>) -> PathBuf {
+		let host = Url::parse(&self.url)
+			.ok()
+			.and_then(|url| url.host_str().map(str::to_string))
+			.unwrap_or_else(|| "server".to_string());
+
+		data_dir.as_ref().join("webdav").join(format!(
+			"{host}-{}",
+			&blake3::hash(self.url.trim_end_matches('/').as_bytes()).to_hex()[..8]
+		))
+	}
+}
+
+/// A file or directory listed from the server, with its path relative to the location's folder
+#[derive(Debug, Clone)]
+pub struct WebDavEntry {
+	pub path: String,
+	pub is_dir: bool,
+	pub size: u64,
+	pub last_modified: DateTime<Utc>,
+	pub etag: Option<String>,
+}
+
+impl WebDavEntry {
+	/// WebDAV doesn't expose inodes, but file paths must be unique by them inside their location
+	pub fn pseudo_inode(&self) -> u64 {
+		let hash = blake3::hash(self.path.as_bytes());
+		let mut bytes = [0; 8];
+		bytes.copy_from_slice(&hash.as_bytes()[..8]);
+
+		u64::from_le_bytes(bytes)
+	}
+}
+
+/// Properties of a single `response` of a `PROPFIND` multistatus, before we know if they're usable
+#[derive(Default)]
+struct PropfindResponse {
+	href: Option<String>,
+	is_dir: bool,
+	size: Option<u64>,
+	last_modified: Option<DateTime<Utc>>,
+	etag: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct WebDavLocation {
+	client: Client,
+	base_url: Url,
+	config: WebDavLocationConfig,
+}
+
+impl WebDavLocation {
+	pub fn connect(config: WebDavLocationConfig) -> Result<Self, WebDavError> {
+		let mut base_url = Url::parse(&config.url).map_err(|e| WebDavError::InvalidUrl {
+			url: config.url.clone(),
+			message: e.to_string(),
+		})?;
+
+		// Paths are joined as segments of the folder, so it must end with a slash
+		if !base_url.path().ends_with('/') {
+			base_url.set_path(&format!("{}/", base_url.path()));
+		}
+
+		Ok(Self {
+			client: Client::new(),
+			base_url,
+			config,
+		})
+	}
+
+	/// Connects to the location's server, if it's a WebDAV location
+	pub fn for_location(
+		location_id: location::id::Type,
+		webdav_config: Option<&[u8]>,
+	) -> Result<Option<Self>, WebDavError> {
+		WebDavLocationConfig::from_db(location_id, webdav_config)?
+			.map(Self::connect)
+			.transpose()
+	}
+
+	/// Lists the direct children of the directory at `sub_path`, relative to the location's folder
+	pub async fn list(&self, sub_path: &str) -> Result<Vec<WebDavEntry>, WebDavError> {
+		let url = self.url_for(sub_path, true);
+
+		let response = self
+			.request(
+				Method::from_bytes(b"PROPFIND").expect("PROPFIND is a valid method"),
+				&url,
+			)
+			.header("Depth", "1")
+			.header(header::CONTENT_TYPE, "application/xml; charset=utf-8")
+			.body(PROPFIND_BODY)
+			.send()
+			.await
+			.map_err(|source| WebDavError::Request {
+				url: url.to_string(),
+				source,
+			})?;
+
+		if response.status() != StatusCode::MULTI_STATUS {
+			return Err(WebDavError::Status {
+				url: url.to_string(),
+				status: response.status(),
+			});
+		}
+
+		let body = response
+			.text()
+			.await
+			.map_err(|source| WebDavError::Request {
+				url: url.to_string(),
+				source,
+			})?;
+
+		let sub_path = sub_path.trim_matches('/');
+
+		let entries = self
+			.parse_multistatus(&url, &body)?
+			.into_iter()
+			// The listed directory comes along with its children
+			.filter(|entry| entry.path != sub_path)
+			.collect::<Vec<_>>();
+
+		debug!("Listed {} WebDAV entries <url='{url}'>", entries.len());
+
+		Ok(entries)
+	}
+
+	/// Reads only `range` of the file at `path`
+	pub async fn read_range(&self, path: &str, range: Range<u64>) -> Result<Vec<u8>, WebDavError> {
+		if range.is_empty() {
+			return Ok(vec![]);
+		}
+
+		let url = self.url_for(path, false);
+
+		let response = self
+			.request(Method::GET, &url)
+			.header(
+				header::RANGE,
+				format!("bytes={}-{}", range.start, range.end - 1),
+			)
+			.send()
+			.await
+			.map_err(|source| WebDavError::Request {
+				url: url.to_string(),
+				source,
+			})?;
+
+		match response.status() {
+			StatusCode::PARTIAL_CONTENT => {}
+			// Some servers answer with the whole file when the range covers all of it
+			StatusCode::OK if range.start == 0 && response.content_length() == Some(range.end) => {}
+			StatusCode::OK => {
+				return Err(WebDavError::RangesNotSupported {
+					url: url.to_string(),
+				})
+			}
+			status => {
+				return Err(WebDavError::Status {
+					url: url.to_string(),
+					status,
+				})
+			}
+		}
+
+		response
+			.bytes()
+			.await
+			.map(|bytes| bytes.to_vec())
+			.map_err(|source| WebDavError::Request {
+				url: url.to_string(),
+				source,
+			})
+	}
+
+	/// Reads the whole file at `path`
+	pub async fn read(&self, path: &str) -> Result<Vec<u8>, WebDavError> {
+		let url = self.url_for(path, false);
+
+		let response = self.send(self.request(Method::GET, &url), &url).await?;
+
+		response
+			.bytes()
+			.await
+			.map(|bytes| bytes.to_vec())
+			.map_err(|source| WebDavError::Request {
+				url: url.to_string(),
+				source,
+			})
+	}
+
+	/// Creates or replaces the file at `path`
+	pub async fn write(&self, path: &str, contents: Vec<u8>) -> Result<(), WebDavError> {
+		let url = self.url_for(path, false);
+
+		self.send(self.request(Method::PUT, &url).body(contents), &url)
+			.await
+			.map(|_| ())
+	}
+
+	pub async fn remove(&self, path: &str) -> Result<(), WebDavError> {
+		let url = self.url_for(path, false);
+
+		match self.send(self.request(Method::DELETE, &url), &url).await {
+			// Already gone, which is all we wanted
+			Err(WebDavError::Status {
+				status: StatusCode::NOT_FOUND,
+				..
+			}) => Ok(()),
+			res => res.map(|_| ()),
+		}
+	}
+
+	/// The `cas_id` a local copy of the file would get, reading only the sampled parts of it.
+	/// Empty files don't get a `cas_id`, the same as empty local files.
+	pub async fn cas_id(&self, path: &str, size: u64) -> Result<Option<String>, WebDavError> {
+		if size == 0 {
+			return Ok(None);
+		}
+
+		let samples = try_join_all(
+			sampled_ranges(size)
+				.into_iter()
+				.map(|range| self.read_range(path, range)),
+		)
+		.await?;
+
+		Ok(Some(generate_cas_id_from_samples(size, samples)))
+	}
+
+	/// Makes the file available in the location's mirror directory and returns its local path.
+	///
+	/// Mirrored copies keep the modification date the server sent, so the conditional `GET` only
+	/// downloads the file again if it changed since.
+	pub async fn read_through(
+		&self,
+		location_path: impl AsRef<Path>,
+		path: &str,
+	) -> Result<PathBuf, WebDavError> {
+		let local_path = location_path.as_ref().join(path);
+		let url = self.url_for(path, false);
+
+		let mut request = self.request(Method::GET, &url);
+
+		if let Ok(modified) = fs::metadata(&local_path)
+			.await
+			.and_then(|metadata| metadata.modified())
+		{
+			request = request.header(
+				header::IF_MODIFIED_SINCE,
+				DateTime::<Utc>::from(modified)
+					.format("%a, %d %b %Y %H:%M:%S GMT")
+					.to_string(),
+			);
+		}
+
+		let mut response = request
+			.send()
+			.await
+			.map_err(|source| WebDavError::Request {
+				url: url.to_string(),
+				source,
+			})?;
+
+		match response.status() {
+			StatusCode::NOT_MODIFIED => return Ok(local_path),
+			status if status.is_success() => {}
+			status => {
+				return Err(WebDavError::Status {
+					url: url.to_string(),
+					status,
+				})
+			}
+		}
+
+		let last_modified = response
+			.headers()
+			.get(header::LAST_MODIFIED)
+			.and_then(|value| value.to_str().ok())
+			.and_then(|value| DateTime::parse_from_rfc2822(value).ok());
+
+		if let Some(parent) = local_path.parent() {
+			fs::create_dir_all(parent)
+				.await
+				.map_err(|e| FileIOError::from((parent, e)))?;
+		}
+
+		// Downloading to a temporary file, so an interrupted download is never taken as the file
+		let tmp_path = {
+			let mut tmp_path = local_path.clone().into_os_string();
+			tmp_path.push(".sdpart");
+			PathBuf::from(tmp_path)
+		};
+
+		let mut file = fs::File::create(&tmp_path)
+			.await
+			.map_err(|e| FileIOError::from((&tmp_path, e)))?;
+
+		while let Some(chunk) = response
+			.chunk()
+			.await
+			.map_err(|source| WebDavError::Request {
+				url: url.to_string(),
+				source,
+			})? {
+			file.write_all(&chunk)
+				.await
+				.map_err(|e| FileIOError::from((&tmp_path, e, "Failed to download file")))?;
+		}
+
+		file.flush()
+			.await
+			.map_err(|e| FileIOError::from((&tmp_path, e)))?;
+
+		if let Some(last_modified) = last_modified {
+			file.into_std()
+				.await
+				.set_modified(SystemTime::from(last_modified))
+				.map_err(|e| FileIOError::from((&tmp_path, e, "Failed to set modified date")))?;
+		}
+
+		fs::rename(&tmp_path, &local_path)
+			.await
+			.map_err(|e| FileIOError::from((&local_path, e)))?;
+
+		debug!("Downloaded WebDAV file <path='{path}'> to the location mirror");
+
+		Ok(local_path)
+	}
+
+	/// Sends `request`, taking any status but a success as an error
+	async fn send(
+		&self,
+		request: RequestBuilder,
+		url: &Url,
+	) -> Result<reqwest::Response, WebDavError> {
+		let response = request
+			.send()
+			.await
+			.map_err(|source| WebDavError::Request {
+				url: url.to_string(),
+				source,
+			})?;
+
+		if !response.status().is_success() {
+			return Err(WebDavError::Status {
+				url: url.to_string(),
+				status: response.status(),
+			});
+		}
+
+		Ok(response)
+	}
+
+	fn request(&self, method: Method, url: &Url) -> RequestBuilder {
+		let request = self.client.request(method, url.clone());
+
+		match &self.config.username {
+			Some(username) => request.basic_auth(username, self.config.password.as_ref()),
+			None => request,
+		}
+	}
+
+	fn url_for(&self, path: &str, is_dir: bool) -> Url {
+		let mut url = self.base_url.clone();
+
+		if let Ok(mut segments) = url.path_segments_mut() {
+			segments
+				.pop_if_empty()
+				.extend(path.split('/').filter(|segment| !segment.is_empty()));
+
+			if is_dir {
+				segments.push("");
+			}
+		}
+
+		url
+	}
+
+	/// Path relative to the location's folder of an `href` from a multistatus response, which
+	/// servers send either as an absolute path or as a full url
+	fn relative_path(&self, href: &str) -> Option<String> {
+		let url = self.base_url.join(href).ok()?;
+
+		let path = percent_decode_str(url.path()).decode_utf8_lossy();
+		let base_path = percent_decode_str(self.base_url.path()).decode_utf8_lossy();
+
+		if path.trim_end_matches('/') == base_path.trim_end_matches('/') {
+			return Some(String::new());
+		}
+
+		path.strip_prefix(base_path.as_ref())
+			.map(|relative| relative.trim_matches('/').to_string())
+	}
+
+	fn parse_multistatus(&self, url: &Url, body: &str) -> Result<Vec<WebDavEntry>, WebDavError> {
+		let xml_error = |message: String| WebDavError::Xml {
+			url: url.to_string(),
+			message,
+		};
+
+		let mut reader = Reader::from_str(body);
+		reader.trim_text(true);
+
+		let mut entries = vec![];
+		let mut maybe_response = None::<PropfindResponse>;
+		// Local name of the element whose text we may be about to read
+		let mut current_element = vec![];
+
+		loop {
+			match reader.read_event() {
+				Ok(Event::Start(element)) => {
+					current_element = element.local_name().as_ref().to_vec();
+
+					match current_element.as_slice() {
+						b"response" => maybe_response = Some(PropfindResponse::default()),
+						b"collection" => {
+							if let Some(response) = &mut maybe_response {
+								response.is_dir = true;
+							}
+						}
+						_ => {}
+					}
+				}
+
+				Ok(Event::Empty(element)) => {
+					if let (b"collection", Some(response)) =
+						(element.local_name().as_ref(), &mut maybe_response)
+					{
+						response.is_dir = true;
+					}
+				}
+
+				Ok(Event::Text(text)) => {
+					let Some(response) = &mut maybe_response else {
+						continue;
+					};
+
+					let text = text
+						.unescape()
+						.map_err(|e| xml_error(e.to_string()))?
+						.into_owned();
+
+					match current_element.as_slice() {
+						b"href" => response.href = Some(text),
+						b"getcontentlength" => response.size = text.parse().ok(),
+						b"getlastmodified" => {
+							response.last_modified = DateTime::parse_from_rfc2822(&text)
+								.ok()
+								.map(|date| date.with_timezone(&Utc));
+						}
+						b"getetag" => response.etag = Some(text),
+						_ => {}
+					}
+				}
+
+				Ok(Event::End(element)) => {
+					current_element.clear();
+
+					if element.local_name().as_ref() == b"response" {
+						if let Some(entry) = maybe_response
+							.take()
+							.and_then(|response| self.entry_from_response(response))
+						{
+							entries.push(entry);
+						}
+					}
+				}
+
+				Ok(Event::Eof) => break,
+
+				Err(e) => return Err(xml_error(e.to_string())),
+
+				_ => {}
+			}
+		}
+
+		Ok(entries)
+	}
+
+	fn entry_from_response(
+		&self,
+		PropfindResponse {
+			href,
+			is_dir,
+			size,
+			last_modified,
+			etag,
+		}: PropfindResponse,
+	) -> Option<WebDavEntry> {
+		Some(WebDavEntry {
+			path: self.relative_path(&href?)?,
+			is_dir,
+			size: if is_dir { 0 } else { size.unwrap_or_default() },
+			last_modified: last_modified.unwrap_or_else(Utc::now),
+			etag,
+		})
+	}
+}
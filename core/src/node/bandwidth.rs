@@ -0,0 +1,329 @@
+//! Caps on the bandwidth the node's background transfers use, so a big Spacedrop or a sync backfill
+//! doesn't saturate the link while the user needs it.
+//!
+//! Each limit applies to some protocols, optionally at some hours of the day only, and every
+//! transfer of a protocol shares its limit, so two Spacedrops at once go at half the rate each.
+
+use super::config::NodePreferences;
+
+use std::{
+	collections::HashMap,
+	future::Future,
+	io,
+	pin::Pin,
+	sync::{Arc, Mutex, PoisonError},
+	task::{ready, Context, Poll},
+	time::Duration,
+};
+
+use chrono::{Datelike, Local, NaiveDateTime, Timelike, Weekday};
+use pin_project_lite::pin_project;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::{
+	io::{AsyncRead, AsyncSeek, AsyncWrite, ReadBuf},
+	sync::watch,
+	time::{sleep_until, Instant},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Type)]
+pub enum BandwidthProtocol {
+	Spacedrop,
+	SyncBackfill,
+	CloudHydration,
+}
+
+/// The hours of the day a limit applies at, in the node's local time. An `end_hour` before the
+/// `start_hour` wraps past midnight and equal hours cover the whole day.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct BandwidthSchedule {
+	pub start_hour: u8, // 0-23
+	pub end_hour: u8,   // 0-23
+	/// Leaves Saturdays and Sundays unlimited
+	#[serde(default)]
+	pub weekdays_only: bool,
+}
+
+impl BandwidthSchedule {
+	pub fn contains(&self, time: NaiveDateTime) -> bool {
+		if self.weekdays_only && matches!(time.weekday(), Weekday::Sat | Weekday::Sun) {
+			return false;
+		}
+
+		let hour = time.hour() as u8;
+
+		match self.start_hour.cmp(&self.end_hour) {
+			std::cmp::Ordering::Less => self.start_hour <= hour && hour < self.end_hour,
+			std::cmp::Ordering::Greater => self.start_hour <= hour || hour < self.end_hour,
+			std::cmp::Ordering::Equal => true,
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct BandwidthLimit {
+	/// Every protocol if empty
+	pub protocols: Vec<BandwidthProtocol>,
+	pub bytes_per_second: u32,
+	/// Always applies if `None`
+	pub schedule: Option<BandwidthSchedule>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct BandwidthPreferences {
+	pub limits: Vec<BandwidthLimit>,
+}
+
+impl BandwidthPreferences {
+	/// The tightest limit on `protocol` at `time`, `None` if it's unlimited
+	pub fn limit(&self, protocol: BandwidthProtocol, time: NaiveDateTime) -> Option<u32> {
+		self.limits
+			.iter()
+			.filter(|limit| limit.protocols.is_empty() || limit.protocols.contains(&protocol))
+			.filter(|limit| {
+				limit
+					.schedule
+					.as_ref()
+					.map_or(true, |schedule| schedule.contains(time))
+			})
+			.map(|limit| limit.bytes_per_second)
+			.min()
+	}
+}
+
+pub struct Bandwidth {
+	preferences: watch::Receiver<NodePreferences>,
+	/// When each protocol is done with the bytes it already sent, which is when the next ones go
+	next_free: Mutex<HashMap<BandwidthProtocol, Instant>>,
+}
+
+impl Bandwidth {
+	pub fn new(preferences: watch::Receiver<NodePreferences>) -> Self {
+		Self {
+			preferences,
+			next_free: Mutex::default(),
+		}
+	}
+
+	/// Waits until `bytes` of `protocol` fit in its limit, returning right away if it has none
+	pub async fn throttle(&self, protocol: BandwidthProtocol, bytes: usize) {
+		let Some(bytes_per_second) = self
+			.preferences
+			.borrow()
+			.bandwidth
+			.limit(protocol, Local::now().naive_local())
+		else {
+			return;
+		};
+
+		let until = {
+			let mut next_free = self
+				.next_free
+				.lock()
+				.unwrap_or_else(PoisonError::into_inner);
+
+			// Idle time isn't saved up for a burst later
+			let now = Instant::now();
+			let start = next_free
+				.get(&protocol)
+				.copied()
+				.filter(|next_free| *next_free > now)
+				.unwrap_or(now);
+
+			let until =
+				start + Duration::from_secs_f64(bytes as f64 / f64::from(bytes_per_second.max(1)));
+			next_free.insert(protocol, until);
+
+			until
+		};
+
+		sleep_until(until).await;
+	}
+}
+
+pin_project! {
+	/// Reads and writes `inner` as traffic of `protocol`, waiting after each read or write until it
+	/// fits in the protocol's limit
+	pub struct Throttled<T> {
+		#[pin]
+		inner: T,
+		bandwidth: Arc<Bandwidth>,
+		protocol: BandwidthProtocol,
+		wait: Option<Pin<Box<dyn Future<Output = ()> + Send>>>,
+	}
+}
+
+impl<T> Throttled<T> {
+	pub fn new(inner: T, bandwidth: Arc<Bandwidth>, protocol: BandwidthProtocol) -> Self {
+		Self {
+			inner,
+			bandwidth,
+			protocol,
+			wait: None,
+		}
+	}
+}
+
+fn poll_wait(
+	wait: &mut Option<Pin<Box<dyn Future<Output = ()> + Send>>>,
+	cx: &mut Context<'_>,
+) -> Poll<()> {
+	if let Some(future) = wait {
+		ready!(future.as_mut().poll(cx));
+		*wait = None;
+	}
+
+	Poll::Ready(())
+}
+
+fn charge(
+	wait: &mut Option<Pin<Box<dyn Future<Output = ()> + Send>>>,
+	bandwidth: &Arc<Bandwidth>,
+	protocol: BandwidthProtocol,
+	bytes: usize,
+) {
+	if bytes > 0 {
+		let bandwidth = bandwidth.clone();
+		*wait = Some(Box::pin(async move {
+			bandwidth.throttle(protocol, bytes).await
+		}));
+	}
+}
+
+impl<T: AsyncRead> AsyncRead for Throttled<T> {
+	fn poll_read(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &mut ReadBuf<'_>,
+	) -> Poll<io::Result<()>> {
+		let this = self.project();
+		ready!(poll_wait(this.wait, cx));
+
+		let filled = buf.filled().len();
+		ready!(this.inner.poll_read(cx, buf))?;
+		charge(
+			this.wait,
+			this.bandwidth,
+			*this.protocol,
+			buf.filled().len() - filled,
+		);
+
+		Poll::Ready(Ok(()))
+	}
+}
+
+impl<T: AsyncWrite> AsyncWrite for Throttled<T> {
+	fn poll_write(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &[u8],
+	) -> Poll<io::Result<usize>> {
+		let this = self.project();
+		ready!(poll_wait(this.wait, cx));
+
+		let written = ready!(this.inner.poll_write(cx, buf))?;
+		charge(this.wait, this.bandwidth, *this.protocol, written);
+
+		Poll::Ready(Ok(written))
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		let this = self.project();
+		ready!(poll_wait(this.wait, cx));
+
+		this.inner.poll_flush(cx)
+	}
+
+	fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		let this = self.project();
+		ready!(poll_wait(this.wait, cx));
+
+		this.inner.poll_shutdown(cx)
+	}
+}
+
+impl<T: AsyncSeek> AsyncSeek for Throttled<T> {
+	fn start_seek(self: Pin<&mut Self>, position: io::SeekFrom) -> io::Result<()> {
+		self.project().inner.start_seek(position)
+	}
+
+	fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+		self.project().inner.poll_complete(cx)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use chrono::NaiveDate;
+
+	use super::*;
+
+	fn at(day: u32, hour: u32) -> NaiveDateTime {
+		// 2024-07-01 is a Monday
+		NaiveDate::from_ymd_opt(2024, 7, day)
+			.and_then(|date| date.and_hms_opt(hour, 0, 0))
+			.expect("valid date")
+	}
+
+	#[test]
+	fn schedules_wrap_past_midnight() {
+		let work_hours = BandwidthSchedule {
+			start_hour: 9,
+			end_hour: 17,
+			weekdays_only: true,
+		};
+
+		assert!(work_hours.contains(at(1, 9)));
+		assert!(!work_hours.contains(at(1, 17)));
+		assert!(!work_hours.contains(at(6, 12)));
+
+		let night = BandwidthSchedule {
+			start_hour: 22,
+			end_hour: 6,
+			weekdays_only: false,
+		};
+
+		assert!(night.contains(at(1, 23)));
+		assert!(night.contains(at(2, 5)));
+		assert!(!night.contains(at(1, 12)));
+	}
+
+	#[test]
+	fn the_tightest_limit_applies() {
+		let preferences = BandwidthPreferences {
+			limits: vec![
+				BandwidthLimit {
+					protocols: vec![],
+					bytes_per_second: 10_000_000,
+					schedule: None,
+				},
+				BandwidthLimit {
+					protocols: vec![BandwidthProtocol::Spacedrop],
+					bytes_per_second: 1_000_000,
+					schedule: Some(BandwidthSchedule {
+						start_hour: 9,
+						end_hour: 17,
+						weekdays_only: true,
+					}),
+				},
+			],
+		};
+
+		assert_eq!(
+			preferences.limit(BandwidthProtocol::Spacedrop, at(1, 10)),
+			Some(1_000_000)
+		);
+		assert_eq!(
+			preferences.limit(BandwidthProtocol::Spacedrop, at(1, 20)),
+			Some(10_000_000)
+		);
+		assert_eq!(
+			preferences.limit(BandwidthProtocol::SyncBackfill, at(1, 10)),
+			Some(10_000_000)
+		);
+		assert_eq!(
+			BandwidthPreferences::default().limit(BandwidthProtocol::CloudHydration, at(1, 10)),
+			None
+		);
+	}
+}
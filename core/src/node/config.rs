@@ -1,10 +1,13 @@
 use crate::{
 	api::{notifications::Notification, BackendFeature},
+	node::{bandwidth::BandwidthPreferences, io_throttle::IoThrottlePreferences},
 	object::media::old_thumbnail::preferences::ThumbnailerPreferences,
+	p2p::NetworkPolicy,
 	util::version_manager::{Kind, ManagedVersion, VersionManager, VersionManagerError},
 };
 
 use sd_p2p::Identity;
+use sd_task_system::ResourceProfile;
 use sd_utils::error::FileIOError;
 
 use std::{
@@ -94,6 +97,16 @@ pub struct NodeConfigP2P {
 	/// which is why we use `String` not `SocketAddr`
 	#[serde(default)]
 	pub manual_peers: HashSet<String>,
+	#[serde(default)]
+	pub network_policy: NetworkPolicy,
+}
+
+impl NodeConfigP2P {
+	/// Relayed peers have no address to hold against the network policy, so a restricted one
+	/// disables the relay
+	pub fn is_relay_enabled(&self) -> bool {
+		!self.disabled && !self.disable_relay && !self.network_policy.is_restricted()
+	}
 }
 
 impl Default for NodeConfigP2P {
@@ -106,6 +119,7 @@ impl Default for NodeConfigP2P {
 			disable_relay: true,
 			enable_remote_access: false,
 			manual_peers: Default::default(),
+			network_policy: Default::default(),
 		}
 	}
 }
@@ -170,6 +184,14 @@ mod identity_serde {
 #[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq, Type)]
 pub struct NodePreferences {
 	pub thumbnailer: ThumbnailerPreferences,
+	#[serde(default)]
+	pub bandwidth: BandwidthPreferences,
+	#[serde(default)]
+	pub io_throttle: IoThrottlePreferences,
+	/// How much of the machine the tasks of every job use, lowered to `LowPower` while on battery
+	#[serde(default)]
+	#[specta(type = String)]
+	pub resource_profile: ResourceProfile,
 }
 
 #[derive(
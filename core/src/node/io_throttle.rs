@@ -0,0 +1,85 @@
+//! Caps on the disk reads of the node's background jobs, so a full re-identification or integrity
+//! check doesn't make the rest of the system crawl on a laptop.
+//!
+//! Each limit applies to some jobs, and every running job a limit covers shares its budget, so two
+//! identifier jobs at once read at half the rate each.
+
+use super::config::NodePreferences;
+
+use sd_core_heavy_lifting::{utils::io_throttle::IoThrottle, JobName};
+
+use std::{
+	num::NonZeroU64,
+	sync::{Mutex, PoisonError},
+};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::sync::watch;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct IoThrottleLimit {
+	/// Every job if empty
+	pub jobs: Vec<JobName>,
+	pub bytes_per_second: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct IoThrottlePreferences {
+	pub limits: Vec<IoThrottleLimit>,
+}
+
+pub struct IoThrottles {
+	preferences: watch::Receiver<NodePreferences>,
+	/// The budget of each limit, kept as long as the limit doesn't change
+	buckets: Mutex<Vec<(IoThrottleLimit, IoThrottle)>>,
+}
+
+impl IoThrottles {
+	pub fn new(preferences: watch::Receiver<NodePreferences>) -> Self {
+		Self {
+			preferences,
+			buckets: Mutex::default(),
+		}
+	}
+
+	/// The budget of the tightest limit on `job_name`, unlimited if it has none
+	pub fn get(&self, job_name: JobName) -> IoThrottle {
+		let preferences = self.preferences.borrow();
+		let limits = &preferences.io_throttle.limits;
+
+		let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+
+		if buckets.len() != limits.len()
+			|| buckets
+				.iter()
+				.zip(limits)
+				.any(|((bucket_limit, _), limit)| bucket_limit != limit)
+		{
+			// Jobs already running keep the budget of the limits they started with
+			*buckets = limits
+				.iter()
+				.map(|limit| {
+					buckets
+						.iter()
+						.find(|(bucket_limit, _)| bucket_limit == limit)
+						.cloned()
+						.unwrap_or_else(|| {
+							(
+								limit.clone(),
+								IoThrottle::from(NonZeroU64::new(u64::from(
+									limit.bytes_per_second,
+								))),
+							)
+						})
+				})
+				.collect();
+		}
+
+		buckets
+			.iter()
+			.filter(|(limit, _)| limit.jobs.is_empty() || limit.jobs.contains(&job_name))
+			.min_by_key(|(limit, _)| limit.bytes_per_second)
+			.map_or_else(IoThrottle::default, |(_, bucket)| bucket.clone())
+	}
+}
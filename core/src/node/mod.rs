@@ -1,6 +1,9 @@
+pub mod bandwidth;
 pub mod config;
 mod hardware;
+pub mod io_throttle;
 mod platform;
+pub mod power;
 
 pub use hardware::*;
 pub use platform::*;
@@ -0,0 +1,93 @@
+//! Where the machine draws its power from, so the task system drops to
+//! [`ResourceProfile::LowPower`](sd_task_system::ResourceProfile::LowPower) while on battery.
+//!
+//! Platforms don't share a notification for it, so the power source is polled.
+
+use sd_task_system::PowerSource;
+
+use std::time::Duration;
+
+use async_stream::stream;
+use futures::Stream;
+use tokio::time::{interval, MissedTickBehavior};
+
+/// How often we check if the machine was plugged in or out
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Power source of the machine, checked every [`POLL_INTERVAL`]. Ends right away on platforms and
+/// machines we can't tell it on, e.g. desktops without a battery.
+pub fn power_source() -> impl Stream<Item = PowerSource> + Send + 'static {
+	stream! {
+		let mut poll = interval(POLL_INTERVAL);
+		poll.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+		loop {
+			poll.tick().await;
+
+			let Some(source) = current().await else {
+				break;
+			};
+
+			yield source;
+		}
+	}
+}
+
+/// Reads the `power_supply` class, plugged in if any mains or USB supply is online
+#[cfg(any(target_os = "linux", target_os = "android"))]
+async fn current() -> Option<PowerSource> {
+	use tokio::fs;
+
+	let mut supplies = fs::read_dir("/sys/class/power_supply").await.ok()?;
+	let mut has_battery = false;
+
+	while let Ok(Some(supply)) = supplies.next_entry().await {
+		let path = supply.path();
+		let Ok(kind) = fs::read_to_string(path.join("type")).await else {
+			continue;
+		};
+
+		match kind.trim() {
+			"Battery" => has_battery = true,
+			"Mains" | "USB" => {
+				if fs::read_to_string(path.join("online"))
+					.await
+					.is_ok_and(|online| online.trim() == "1")
+				{
+					return Some(PowerSource::Ac);
+				}
+			}
+			_ => {}
+		}
+	}
+
+	has_battery.then_some(PowerSource::Battery)
+}
+
+/// Reads the first line of `pmset -g batt`, e.g. "Now drawing from 'Battery Power'"
+#[cfg(target_os = "macos")]
+async fn current() -> Option<PowerSource> {
+	let output = tokio::process::Command::new("pmset")
+		.args(["-g", "batt"])
+		.output()
+		.await
+		.ok()?;
+
+	let first_line = String::from_utf8_lossy(&output.stdout)
+		.lines()
+		.next()?
+		.to_string();
+
+	if first_line.contains("'Battery Power'") {
+		Some(PowerSource::Battery)
+	} else if first_line.contains("'AC Power'") {
+		Some(PowerSource::Ac)
+	} else {
+		None
+	}
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos")))]
+async fn current() -> Option<PowerSource> {
+	None
+}
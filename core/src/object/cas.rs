@@ -1,4 +1,4 @@
-use std::path::Path;
+use std::{ops::Range, path::Path};
 
 use blake3::Hasher;
 use static_assertions::const_assert;
@@ -60,3 +60,70 @@ pub async fn generate_cas_id(path: impl AsRef<Path>, size: u64) -> Result<String
 
 	Ok(hasher.finalize().to_hex()[..16].to_string())
 }
+
+/// Byte ranges of a file with `size` bytes that [`generate_cas_id`] hashes, in order, for files we
+/// can't seek through locally and have to request in parts
+pub fn sampled_ranges(size: u64) -> Vec<Range<u64>> {
+	if size <= MINIMUM_FILE_SIZE {
+		return vec![0..size];
+	}
+
+	let seek_jump = (size - HEADER_OR_FOOTER_SIZE * 2) / SAMPLE_COUNT;
+
+	[0..HEADER_OR_FOOTER_SIZE]
+		.into_iter()
+		.chain((0..SAMPLE_COUNT).map(|sample| {
+			let start = HEADER_OR_FOOTER_SIZE + seek_jump * sample;
+			start..start + SAMPLE_SIZE
+		}))
+		.chain([size - HEADER_OR_FOOTER_SIZE..size])
+		.collect()
+}
+
+/// Same `cas_id` as [`generate_cas_id`], from the content of the [`sampled_ranges`] of the file
+pub fn generate_cas_id_from_samples(
+	size: u64,
+	samples: impl IntoIterator<Item = impl AsRef<[u8]>>,
+) -> String {
+	let mut hasher = Hasher::new();
+	hasher.update(&size.to_le_bytes());
+
+	for sample in samples {
+		hasher.update(sample.as_ref());
+	}
+
+	hasher.finalize().to_hex()[..16].to_string()
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+	use super::*;
+	use tempfile::tempdir;
+
+	#[tokio::test]
+	async fn samples_match_local_cas_id() {
+		let dir = tempdir().unwrap();
+
+		for size in [
+			0,
+			1024,
+			MINIMUM_FILE_SIZE,
+			MINIMUM_FILE_SIZE + 1,
+			1024 * 1024 + 7,
+		] {
+			let content = (0..size).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+			let path = dir.path().join(format!("{size}.bin"));
+			fs::write(&path, &content).await.unwrap();
+
+			let samples = sampled_ranges(size)
+				.into_iter()
+				.map(|range| &content[range.start as usize..range.end as usize]);
+
+			assert_eq!(
+				generate_cas_id(&path, size).await.unwrap(),
+				generate_cas_id_from_samples(size, samples)
+			);
+		}
+	}
+}
@@ -0,0 +1,255 @@
+use crate::library::Library;
+
+use sd_prisma::{prisma::custom_field, prisma_sync};
+use sd_sync::{sync_db_entry, OperationFactory};
+
+use chrono::{DateTime, FixedOffset, TimeZone, Utc};
+use int_enum::IntEnum;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use uuid::Uuid;
+
+pub mod seed;
+
+/// Highest rating an object can have, in stars
+pub const MAX_RATING: u8 = 5;
+
+#[derive(IntEnum, Serialize, Deserialize, Type, Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum FieldKind {
+	Text = 0,
+	Number = 1,
+	Boolean = 2,
+	Date = 3,
+	/// From 0 to [`MAX_RATING`] stars
+	Rating = 4,
+	/// One of the options of the field, like the status of a task
+	Select = 5,
+}
+
+#[derive(Serialize, Deserialize, Type, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", content = "value")]
+pub enum FieldValue {
+	Text(String),
+	Number(f64),
+	Boolean(bool),
+	Date(DateTime<Utc>),
+	Rating(u8),
+	Select(String),
+}
+
+#[derive(Type, Deserialize, Clone)]
+pub struct CustomFieldCreateArgs {
+	pub name: String,
+	pub kind: FieldKind,
+	/// Values a select field can take, ignored for other kinds
+	#[serde(default)]
+	pub options: Vec<String>,
+}
+
+impl CustomFieldCreateArgs {
+	pub async fn exec(
+		self,
+		Library { db, sync, .. }: &Library,
+	) -> prisma_client_rust::Result<custom_field::Data> {
+		let pub_id = Uuid::new_v4().as_bytes().to_vec();
+		let date_created: DateTime<FixedOffset> = Utc::now().into();
+
+		let options = match self.kind {
+			FieldKind::Select => Some(
+				options_to_bytes(&self.options).expect("a list of strings is always serializable"),
+			),
+			_ => None,
+		};
+
+		let (sync_params, db_params): (Vec<_>, Vec<_>) = [
+			Some(sync_db_entry!(self.name, custom_field::name)),
+			Some(sync_db_entry!(self.kind.int_value(), custom_field::kind)),
+			options.map(|options| sync_db_entry!(options, custom_field::options)),
+			Some(sync_db_entry!(date_created, custom_field::date_created)),
+		]
+		.into_iter()
+		.flatten()
+		.unzip();
+
+		sync.write_ops(
+			db,
+			(
+				sync.shared_create(
+					prisma_sync::custom_field::SyncId {
+						pub_id: pub_id.clone(),
+					},
+					sync_params,
+				),
+				db.custom_field().create(pub_id, db_params),
+			),
+		)
+		.await
+	}
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum FieldValueError {
+	#[error("a {kind:?} field can't hold a {value:?} value")]
+	KindMismatch { kind: FieldKind, value: FieldValue },
+	#[error("ratings go from 0 to {MAX_RATING} stars, not {0}")]
+	InvalidRating(u8),
+	#[error("numbers must be finite")]
+	InvalidNumber,
+	#[error("\"{0}\" isn't one of the options of the field")]
+	UnknownOption(String),
+}
+
+impl From<FieldValueError> for rspc::Error {
+	fn from(e: FieldValueError) -> Self {
+		Self::with_cause(rspc::ErrorCode::BadRequest, e.to_string(), e)
+	}
+}
+
+impl FieldValue {
+	pub fn kind(&self) -> FieldKind {
+		match self {
+			Self::Text(_) => FieldKind::Text,
+			Self::Number(_) => FieldKind::Number,
+			Self::Boolean(_) => FieldKind::Boolean,
+			Self::Date(_) => FieldKind::Date,
+			Self::Rating(_) => FieldKind::Rating,
+			Self::Select(_) => FieldKind::Select,
+		}
+	}
+
+	/// Checks that the value fits a field of `kind`, which for select fields means being one of
+	/// their `options`
+	pub fn validate(&self, kind: FieldKind, options: &[String]) -> Result<(), FieldValueError> {
+		if self.kind() != kind {
+			return Err(FieldValueError::KindMismatch {
+				kind,
+				value: self.clone(),
+			});
+		}
+
+		match self {
+			Self::Rating(stars) if *stars > MAX_RATING => {
+				Err(FieldValueError::InvalidRating(*stars))
+			}
+			Self::Number(number) if !number.is_finite() => Err(FieldValueError::InvalidNumber),
+			Self::Select(option) if !options.contains(option) => {
+				Err(FieldValueError::UnknownOption(option.clone()))
+			}
+			_ => Ok(()),
+		}
+	}
+
+	/// The `text` and `number` columns of `custom_field_value` the value is stored in
+	pub fn into_columns(self) -> (Option<String>, Option<f64>) {
+		match self {
+			Self::Text(text) | Self::Select(text) => (Some(text), None),
+			Self::Number(number) => (None, Some(number)),
+			Self::Boolean(boolean) => (None, Some(if boolean { 1.0 } else { 0.0 })),
+			Self::Date(date) => (None, Some(date_to_number(date))),
+			Self::Rating(stars) => (None, Some(f64::from(stars))),
+		}
+	}
+
+	/// Reads back a value stored by [`FieldValue::into_columns`], if it's there
+	pub fn from_columns(
+		kind: FieldKind,
+		text: Option<String>,
+		number: Option<f64>,
+	) -> Option<Self> {
+		Some(match kind {
+			FieldKind::Text => Self::Text(text?),
+			FieldKind::Select => Self::Select(text?),
+			FieldKind::Number => Self::Number(number?),
+			FieldKind::Boolean => Self::Boolean(number? != 0.0),
+			FieldKind::Date => Self::Date(Utc.timestamp_millis_opt(number? as i64).single()?),
+			FieldKind::Rating => Self::Rating(number?.clamp(0.0, f64::from(MAX_RATING)) as u8),
+		})
+	}
+}
+
+/// How dates are stored in the `number` column, to filter them as numbers
+pub fn date_to_number(date: DateTime<impl TimeZone>) -> f64 {
+	date.timestamp_millis() as f64
+}
+
+pub fn options_to_bytes(options: &[String]) -> Result<Vec<u8>, rmp_serde::encode::Error> {
+	rmp_serde::to_vec(options)
+}
+
+pub fn options_from_bytes(bytes: Option<&[u8]>) -> Vec<String> {
+	bytes
+		.and_then(|bytes| rmp_serde::from_slice(bytes).ok())
+		.unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn values_round_trip_through_columns() {
+		let date = Utc.timestamp_millis_opt(1_720_000_000_123).unwrap();
+
+		for value in [
+			FieldValue::Text("hello".into()),
+			FieldValue::Number(-1.5),
+			FieldValue::Boolean(true),
+			FieldValue::Boolean(false),
+			FieldValue::Date(date),
+			FieldValue::Rating(4),
+			FieldValue::Select("Done".into()),
+		] {
+			let kind = value.kind();
+			let (text, number) = value.clone().into_columns();
+
+			assert_eq!(FieldValue::from_columns(kind, text, number), Some(value));
+		}
+
+		assert_eq!(
+			FieldValue::from_columns(FieldKind::Rating, None, None),
+			None
+		);
+	}
+
+	#[test]
+	fn values_must_fit_their_field() {
+		let options = vec!["To do".to_string(), "Done".to_string()];
+
+		assert_eq!(
+			FieldValue::Rating(5).validate(FieldKind::Rating, &[]),
+			Ok(())
+		);
+		assert_eq!(
+			FieldValue::Rating(6).validate(FieldKind::Rating, &[]),
+			Err(FieldValueError::InvalidRating(6))
+		);
+		assert_eq!(
+			FieldValue::Select("Done".into()).validate(FieldKind::Select, &options),
+			Ok(())
+		);
+		assert_eq!(
+			FieldValue::Select("Later".into()).validate(FieldKind::Select, &options),
+			Err(FieldValueError::UnknownOption("Later".into()))
+		);
+		assert_eq!(
+			FieldValue::Number(f64::NAN).validate(FieldKind::Number, &[]),
+			Err(FieldValueError::InvalidNumber)
+		);
+		assert!(matches!(
+			FieldValue::Text("5".into()).validate(FieldKind::Rating, &[]),
+			Err(FieldValueError::KindMismatch { .. })
+		));
+	}
+
+	#[test]
+	fn options_round_trip() {
+		let options = vec!["To do".to_string(), "Done".to_string()];
+
+		assert_eq!(
+			options_from_bytes(Some(&options_to_bytes(&options).unwrap())),
+			options
+		);
+		assert!(options_from_bytes(None).is_empty());
+	}
+}
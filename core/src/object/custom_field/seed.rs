@@ -0,0 +1,30 @@
+use crate::library::Library;
+
+use super::{CustomFieldCreateArgs, FieldKind};
+
+/// Seeds the fields most libraries want in a new library.
+/// Shouldn't be called more than once!
+pub async fn new_library(library: &Library) -> prisma_client_rust::Result<()> {
+	let fields = [
+		CustomFieldCreateArgs {
+			name: "Rating".to_string(),
+			kind: FieldKind::Rating,
+			options: vec![],
+		},
+		CustomFieldCreateArgs {
+			name: "Status".to_string(),
+			kind: FieldKind::Select,
+			options: vec![
+				"To do".to_string(),
+				"In progress".to_string(),
+				"Done".to_string(),
+			],
+		},
+	];
+
+	for field in fields {
+		field.exec(library).await?;
+	}
+
+	Ok(())
+}
@@ -0,0 +1,153 @@
+use crate::volume::{get_volumes, DiskType, Volume};
+
+use sd_utils::error::FileIOError;
+
+use std::path::Path;
+
+use rand::{rngs::StdRng, RngCore, SeedableRng};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::{
+	fs::{self, OpenOptions},
+	io::{AsyncSeekExt, AsyncWriteExt},
+};
+
+const CHUNK_SIZE: usize = 64 * 1024; // 64 KiB
+
+/// File systems writing new contents somewhere else instead of over the old ones, compared case
+/// insensitively with what volumes report
+const COPY_ON_WRITE_FILE_SYSTEMS: [&str; 5] = ["apfs", "btrfs", "zfs", "refs", "bcachefs"];
+
+/// Why overwriting files in a volume may leave their old contents readable, making erasing them
+/// best effort only
+#[derive(Serialize, Deserialize, Type, Debug, Clone, PartialEq, Eq)]
+pub enum EraseCaveat {
+	/// The file system writes new contents to other blocks, old ones staying on disk until reused
+	CopyOnWrite(String),
+	/// SSDs and flash drives spread writes over other cells for wear leveling, and only their
+	/// firmware can wipe the old ones
+	FlashStorage,
+	/// No mounted volume holds the path, like with network shares, so nothing is known about it
+	UnknownVolume,
+}
+
+/// What makes erasing files at `path` best effort, nothing if overwriting them is reliable
+pub async fn erase_caveats(path: impl AsRef<Path>) -> Vec<EraseCaveat> {
+	let volumes = get_volumes().await;
+
+	let Some(volume) = Volume::for_path(&volumes, path) else {
+		return vec![EraseCaveat::UnknownVolume];
+	};
+
+	let mut caveats = Vec::new();
+
+	if let Some(file_system) = volume.file_system.as_ref().filter(|file_system| {
+		COPY_ON_WRITE_FILE_SYSTEMS
+			.iter()
+			.any(|cow| file_system.eq_ignore_ascii_case(cow))
+	}) {
+		caveats.push(EraseCaveat::CopyOnWrite(file_system.clone()));
+	}
+
+	// Removable drives are mostly flash nowadays
+	if matches!(volume.disk_type, DiskType::SSD | DiskType::Removable) {
+		caveats.push(EraseCaveat::FlashStorage);
+	}
+
+	caveats
+}
+
+/// Overwrites the contents of a file, or of every file in a directory, `passes` times with random
+/// data before removing it.
+///
+/// Symbolic links are removed without touching what they point to. See [`erase_caveats`] for
+/// when overwritten contents may still be recovered.
+pub async fn erase(path: impl AsRef<Path>, is_dir: bool, passes: usize) -> Result<(), FileIOError> {
+	let path = path.as_ref();
+
+	if !is_dir {
+		overwrite(path, passes).await?;
+
+		return fs::remove_file(path)
+			.await
+			.map_err(|e| FileIOError::from((path, e)));
+	}
+
+	let mut dirs = vec![path.to_path_buf()];
+
+	while let Some(dir) = dirs.pop() {
+		let mut entries = fs::read_dir(&dir)
+			.await
+			.map_err(|e| FileIOError::from((&dir, e)))?;
+
+		while let Some(entry) = entries
+			.next_entry()
+			.await
+			.map_err(|e| FileIOError::from((&dir, e)))?
+		{
+			let entry_path = entry.path();
+			let file_type = entry
+				.file_type()
+				.await
+				.map_err(|e| FileIOError::from((&entry_path, e)))?;
+
+			if file_type.is_dir() {
+				dirs.push(entry_path);
+			} else if file_type.is_file() {
+				overwrite(&entry_path, passes).await?;
+			}
+		}
+	}
+
+	fs::remove_dir_all(path)
+		.await
+		.map_err(|e| FileIOError::from((path, e)))
+}
+
+async fn overwrite(path: &Path, passes: usize) -> Result<(), FileIOError> {
+	let mut file = OpenOptions::new()
+		.write(true)
+		.open(path)
+		.await
+		.map_err(|e| FileIOError::from((path, e)))?;
+
+	let len = file
+		.metadata()
+		.await
+		.map_err(|e| FileIOError::from((path, e)))?
+		.len();
+
+	let mut rng = StdRng::from_entropy();
+	let mut buf = vec![0; CHUNK_SIZE];
+
+	for _ in 0..passes {
+		file.rewind()
+			.await
+			.map_err(|e| FileIOError::from((path, e)))?;
+
+		let mut remaining = len;
+		while remaining > 0 {
+			// Never bigger than `CHUNK_SIZE`, so the cast can't truncate
+			let chunk = &mut buf[..remaining.min(CHUNK_SIZE as u64) as usize];
+			rng.fill_bytes(chunk);
+
+			file.write_all(chunk)
+				.await
+				.map_err(|e| FileIOError::from((path, e)))?;
+
+			remaining -= chunk.len() as u64;
+		}
+
+		// Each pass must reach the disk, otherwise only the last one may be written
+		file.sync_data()
+			.await
+			.map_err(|e| FileIOError::from((path, e)))?;
+	}
+
+	file.set_len(0)
+		.await
+		.map_err(|e| FileIOError::from((path, e)))?;
+	file.sync_all()
+		.await
+		.map_err(|e| FileIOError::from((path, e)))
+}
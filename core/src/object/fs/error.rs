@@ -42,10 +42,32 @@ pub enum FileSystemJobsError {
 	NonUTF8Path(#[from] NonUtf8PathError),
 	#[error("failed to find an available name to avoid duplication: <path='{}'>", .0.display())]
 	FailedToFindAvailableName(Box<Path>),
+	#[error("not enough free space in destination: <needed='{needed}', available='{available}'>")]
+	WouldOverflowDestination { needed: u64, available: u64 },
+	#[error("moving to and restoring from the trash isn't supported on this platform")]
+	TrashUnsupported,
+	#[error("failed to move to the trash: <path='{}'>: {1}", .0.display())]
+	Trash(Box<Path>, String),
+	#[error("failed to restore from the trash: <path='{}'>: {1}", .0.display())]
+	Restore(Box<Path>, String),
+	#[error("not in the trash anymore, removed from the library: <path='{}'>", .0.display())]
+	NotInTrash(Box<Path>),
+	#[error("file_path isn't in the trash: <id='{0}'>")]
+	FilePathNotTrashed(file_path::id::Type),
+	#[error("file_path wasn't indexed from its cloud provider yet: <id='{0}'>")]
+	NotIndexedInCloud(file_path::id::Type),
 }
 
 impl From<FileSystemJobsError> for rspc::Error {
 	fn from(e: FileSystemJobsError) -> Self {
-		Self::with_cause(rspc::ErrorCode::InternalServerError, e.to_string(), e)
+		match e {
+			FileSystemJobsError::TrashUnsupported => {
+				Self::with_cause(rspc::ErrorCode::MethodNotSupported, e.to_string(), e)
+			}
+			FileSystemJobsError::NotInTrash(_) | FileSystemJobsError::FilePathNotTrashed(_) => {
+				Self::with_cause(rspc::ErrorCode::NotFound, e.to_string(), e)
+			}
+			_ => Self::with_cause(rspc::ErrorCode::InternalServerError, e.to_string(), e),
+		}
 	}
 }
@@ -1,11 +1,11 @@
-use crate::location::LocationError;
+use crate::location::{capacity, LocationError};
 
 use sd_core_file_path_helper::IsolatedFilePathData;
 use sd_core_prisma_helpers::file_path_with_object;
 
 use sd_prisma::prisma::{file_path, location, PrismaClient};
 use sd_utils::{
-	db::maybe_missing,
+	db::{maybe_missing, size_in_bytes_from_db},
 	error::{FileIOError, NonUtf8PathError},
 };
 
@@ -18,8 +18,12 @@ use once_cell::sync::Lazy;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 
+pub mod erase;
+pub mod old_cleanup;
 pub mod old_delete;
 pub mod old_erase;
+pub mod old_hydrate;
+pub mod trash;
 
 pub mod old_copy;
 pub mod old_cut;
@@ -108,6 +112,27 @@ pub async fn get_file_data_from_isolated_file_path(
 		})
 }
 
+/// Refuses to put the files in `target_path` when their sizes, with directories holding the size
+/// of their whole contents, add up to more than the free space of the volume holding it
+pub async fn check_destination_capacity<'a>(
+	files_datas: impl IntoIterator<Item = &'a FileData>,
+	target_path: impl AsRef<Path>,
+) -> Result<(), FileSystemJobsError> {
+	let needed = files_datas
+		.into_iter()
+		.filter_map(|file_data| file_data.file_path.size_in_bytes_bytes.as_deref())
+		.map(size_in_bytes_from_db)
+		.sum::<u64>();
+
+	match capacity::free_bytes_at(target_path).await {
+		Some(available) if needed > available => {
+			Err(FileSystemJobsError::WouldOverflowDestination { needed, available })
+		}
+		// Without a volume to ask we let the copies fail by themselves if they don't fit
+		_ => Ok(()),
+	}
+}
+
 pub async fn fetch_source_and_target_location_paths(
 	db: &PrismaClient,
 	source_location_id: location::id::Type,
@@ -0,0 +1,526 @@
+use crate::{
+	invalidate_query,
+	library::Library,
+	location::get_location_path_from_location_id,
+	old_job::{
+		CurrentStep, JobError, JobInitOutput, JobReportUpdate, JobResult, JobRunErrors,
+		JobRunMetadata, JobStepOutput, StatefulJob, WorkerContext,
+	},
+};
+
+use sd_core_file_path_helper::{
+	ensure_file_path_exists, ensure_sub_path_is_directory, ensure_sub_path_is_in_location,
+	IsolatedFilePathData,
+};
+use sd_core_heavy_lifting::file_identifier;
+use sd_core_sync::Manager as SyncManager;
+
+use sd_prisma::{
+	prisma::{file_path, location, PrismaClient},
+	prisma_sync,
+};
+use sd_sync::OperationFactory;
+use sd_utils::{
+	chain_optional_iter,
+	db::{maybe_missing, size_in_bytes_from_db},
+	error::FileIOError,
+};
+
+use std::{
+	collections::HashSet,
+	hash::Hash,
+	path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use specta::Type;
+use tokio::{fs, io};
+use tracing::{debug, info};
+
+use super::{error::FileSystemJobsError, get_many_files_datas, trash::move_to_trash, FileData};
+
+/// `OldCleanupJobInit` looks for leftovers in a location, or starting from a `sub_path`: empty
+/// directories, symbolic links to nothing and empty files. Each one is checked again on disk
+/// before being listed in the job report, and removed according to `mode`.
+#[derive(Serialize, Deserialize, Hash, Type, Debug)]
+pub struct OldCleanupJobInit {
+	pub location_id: location::id::Type,
+	/// The whole location if `None`
+	#[serde(default)]
+	pub sub_path: Option<PathBuf>,
+	/// Every kind of leftover if `None`
+	#[serde(default)]
+	pub kinds: Option<Vec<LeftoverKind>>,
+	#[serde(default)]
+	pub mode: CleanupMode,
+}
+
+#[derive(Serialize, Deserialize, Hash, Type, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeftoverKind {
+	/// Directories with no files under them, only the outermost one being listed
+	EmptyDirectory,
+	/// Symbolic links whose target doesn't exist anymore
+	BrokenLink,
+	/// Files of zero bytes
+	EmptyFile,
+}
+
+#[derive(Serialize, Deserialize, Hash, Type, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CleanupMode {
+	/// Only lists leftovers in the job report
+	#[default]
+	Report,
+	/// Moves leftovers to the platform trash, from where `files.restoreFromTrash` brings them back
+	Trash,
+	/// Unlinks leftovers, for good, and removes them from the library
+	Delete,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Leftover {
+	kind: LeftoverKind,
+	file: FileData,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FoundLeftover {
+	file_path_id: file_path::id::Type,
+	kind: LeftoverKind,
+	path: PathBuf,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct OldCleanupJobRunMetadata {
+	leftovers: Vec<FoundLeftover>,
+	removed_count: usize,
+}
+
+impl JobRunMetadata for OldCleanupJobRunMetadata {
+	fn update(&mut self, new_data: Self) {
+		self.leftovers.extend(new_data.leftovers);
+		self.removed_count += new_data.removed_count;
+	}
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for OldCleanupJobInit {
+	type Data = ();
+	type Step = Leftover;
+	type RunMetadata = OldCleanupJobRunMetadata;
+
+	const NAME: &'static str = "cleanup";
+
+	fn target_location(&self) -> Option<location::id::Type> {
+		Some(self.location_id)
+	}
+
+	async fn init(
+		&self,
+		ctx: &WorkerContext,
+		data: &mut Option<Self::Data>,
+	) -> Result<JobInitOutput<Self::RunMetadata, Self::Step>, JobError> {
+		let init = self;
+		let Library { db, .. } = &*ctx.library;
+
+		let location_path = get_location_path_from_location_id(db, init.location_id).await?;
+
+		let maybe_sub_iso_file_path = match &init.sub_path {
+			Some(sub_path) if sub_path != Path::new("") => {
+				let full_path = ensure_sub_path_is_in_location(&location_path, sub_path)
+					.await
+					.map_err(FileSystemJobsError::from)?;
+				ensure_sub_path_is_directory(&location_path, sub_path)
+					.await
+					.map_err(FileSystemJobsError::from)?;
+
+				let sub_iso_file_path =
+					IsolatedFilePathData::new(init.location_id, &location_path, &full_path, true)
+						.map_err(FileSystemJobsError::from)?;
+
+				ensure_file_path_exists(
+					sub_path,
+					&sub_iso_file_path,
+					db,
+					FileSystemJobsError::FilePathNotFound,
+				)
+				.await?;
+
+				Some(sub_iso_file_path)
+			}
+			_ => None,
+		};
+
+		let entries = db
+			.file_path()
+			.find_many(chain_optional_iter(
+				[
+					file_path::location_id::equals(Some(init.location_id)),
+					// Archive entries aren't on disk, they'd all look like leftovers
+					file_identifier::not_in_archive(),
+				],
+				[maybe_sub_iso_file_path.as_ref().map(|sub_iso_file_path| {
+					file_path::materialized_path::starts_with(
+						sub_iso_file_path
+							.materialized_path_for_children()
+							.expect("sub path iso_file_path must be a directory"),
+					)
+				})],
+			))
+			.select(file_path::select!({
+				id
+				materialized_path
+				name
+				is_dir
+				size_in_bytes_bytes
+				link_target
+			}))
+			.exec()
+			.await?
+			.into_iter()
+			.filter_map(|file_path| {
+				Some(Entry {
+					id: file_path.id,
+					materialized_path: file_path.materialized_path?,
+					name: file_path.name?,
+					is_dir: file_path.is_dir?,
+					is_link: file_path.link_target.is_some(),
+					size: file_path
+						.size_in_bytes_bytes
+						.as_deref()
+						.map_or(0, size_in_bytes_from_db),
+				})
+			})
+			.collect::<Vec<_>>();
+
+		let (file_path_ids, kinds) = find_leftovers(&entries)
+			.into_iter()
+			.filter(|(_, kind)| {
+				init.kinds
+					.as_ref()
+					.map_or(true, |kinds| kinds.contains(kind))
+			})
+			.unzip::<_, _, Vec<_>, Vec<_>>();
+
+		// Must fill in the data, otherwise the job will not run
+		*data = Some(());
+
+		if file_path_ids.is_empty() {
+			return Err(JobError::EarlyFinish {
+				name: <Self as StatefulJob>::NAME.to_string(),
+				reason: "Found no leftovers".to_string(),
+			});
+		}
+
+		let steps = get_many_files_datas(db, &location_path, &file_path_ids)
+			.await?
+			.into_iter()
+			.zip(kinds)
+			.map(|(file, kind)| Leftover { kind, file })
+			.collect::<Vec<_>>();
+
+		debug!("Found {} possible leftovers to check on disk", steps.len());
+
+		ctx.progress(vec![
+			JobReportUpdate::TaskCount(steps.len()),
+			JobReportUpdate::Message(format!("Checking {} possible leftovers", steps.len())),
+		]);
+
+		Ok(steps.into())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: &WorkerContext,
+		CurrentStep {
+			step: Leftover { kind, file },
+			step_number,
+		}: CurrentStep<'_, Self::Step>,
+		_: &Self::Data,
+		_: &Self::RunMetadata,
+	) -> Result<JobStepOutput<Self::Step, Self::RunMetadata>, JobError> {
+		let Library { db, sync, .. } = ctx.library.as_ref();
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(step_number + 1)]);
+
+		// The index may be out of date, or leave out files ignored by indexer rules
+		if !is_leftover_on_disk(*kind, &file.full_path)
+			.await
+			.map_err(|e| FileIOError::from((&file.full_path, e)))?
+		{
+			debug!(
+				"Not a leftover on disk, skipping: <path='{}'>",
+				file.full_path.display()
+			);
+
+			return Ok(OldCleanupJobRunMetadata::default().into());
+		}
+
+		let removed = match self.mode {
+			CleanupMode::Report => Ok(false),
+			CleanupMode::Trash => move_to_trash(db, sync, self.location_id, file)
+				.await
+				.map(|()| true),
+			CleanupMode::Delete => match remove_from_disk(*kind, &file.full_path).await {
+				Ok(()) => remove_file_paths(db, sync, self.location_id, file)
+					.await
+					.map(|()| true),
+				Err(e) => Err(FileIOError::from((&file.full_path, e)).into()),
+			},
+		};
+
+		match removed {
+			Ok(removed) => Ok(OldCleanupJobRunMetadata {
+				leftovers: vec![FoundLeftover {
+					file_path_id: file.file_path.id,
+					kind: *kind,
+					path: file.full_path.clone(),
+				}],
+				removed_count: usize::from(removed),
+			}
+			.into()),
+			Err(e) => Ok(JobRunErrors(vec![e.to_string()]).into()),
+		}
+	}
+
+	async fn finalize(
+		&self,
+		ctx: &WorkerContext,
+		_data: &Option<Self::Data>,
+		run_metadata: &Self::RunMetadata,
+	) -> JobResult {
+		let init = self;
+		info!(
+			"Finalizing cleanup job: found {} leftovers, removed {}",
+			run_metadata.leftovers.len(),
+			run_metadata.removed_count
+		);
+
+		if run_metadata.removed_count > 0 {
+			invalidate_query!(ctx.library, "search.paths");
+		}
+
+		Ok(Some(json!({ "init": init, "run_metadata": run_metadata })))
+	}
+}
+
+struct Entry {
+	id: file_path::id::Type,
+	materialized_path: String,
+	name: String,
+	is_dir: bool,
+	is_link: bool,
+	size: u64,
+}
+
+/// Leftovers as far as the index knows, to be checked on disk. Directories holding only empty
+/// directories are empty too, so only the outermost one is listed.
+fn find_leftovers(entries: &[Entry]) -> Vec<(file_path::id::Type, LeftoverKind)> {
+	// Every directory holding a file, directly or not
+	let mut with_files = HashSet::new();
+	for entry in entries.iter().filter(|entry| !entry.is_dir) {
+		let mut dir = entry.materialized_path.as_str();
+		while with_files.insert(dir) {
+			let Some(parent) = dir
+				.strip_suffix('/')
+				.and_then(|trimmed| trimmed.rfind('/'))
+				.map(|idx| &dir[..=idx])
+			else {
+				break;
+			};
+			dir = parent;
+		}
+	}
+
+	let empty_dirs = entries
+		.iter()
+		.filter(|entry| entry.is_dir)
+		.map(|entry| format!("{}{}/", entry.materialized_path, entry.name))
+		.filter(|children_path| !with_files.contains(children_path.as_str()))
+		.collect::<HashSet<_>>();
+
+	entries
+		.iter()
+		.filter_map(|entry| {
+			let kind = if entry.is_dir {
+				// Removing the outermost empty directory takes the ones inside it along
+				(empty_dirs.contains(&format!("{}{}/", entry.materialized_path, entry.name))
+					&& !empty_dirs.contains(&entry.materialized_path))
+				.then_some(LeftoverKind::EmptyDirectory)?
+			} else if entry.is_link {
+				LeftoverKind::BrokenLink
+			} else {
+				(entry.size == 0).then_some(LeftoverKind::EmptyFile)?
+			};
+
+			Some((entry.id, kind))
+		})
+		.collect()
+}
+
+async fn is_leftover_on_disk(kind: LeftoverKind, path: &Path) -> Result<bool, io::Error> {
+	let metadata = match fs::symlink_metadata(path).await {
+		Ok(metadata) => metadata,
+		Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+		Err(e) => return Err(e),
+	};
+
+	match kind {
+		LeftoverKind::EmptyFile => Ok(metadata.is_file() && metadata.len() == 0),
+
+		LeftoverKind::BrokenLink => Ok(metadata.is_symlink()
+			&& matches!(fs::metadata(path).await, Err(e) if e.kind() == io::ErrorKind::NotFound)),
+
+		LeftoverKind::EmptyDirectory => {
+			if !metadata.is_dir() {
+				return Ok(false);
+			}
+
+			let mut to_read = vec![path.to_path_buf()];
+			while let Some(dir) = to_read.pop() {
+				let mut read_dir = fs::read_dir(&dir).await?;
+				while let Some(entry) = read_dir.next_entry().await? {
+					if entry.file_type().await?.is_dir() {
+						to_read.push(entry.path());
+					} else {
+						return Ok(false);
+					}
+				}
+			}
+
+			Ok(true)
+		}
+	}
+}
+
+/// Empty directories are removed from the bottom up, one by one, so one that got a file since it
+/// was checked is left in place
+async fn remove_from_disk(kind: LeftoverKind, path: &Path) -> Result<(), io::Error> {
+	match kind {
+		LeftoverKind::EmptyDirectory => {
+			let mut dirs = vec![path.to_path_buf()];
+
+			let mut to_read = vec![path.to_path_buf()];
+			while let Some(dir) = to_read.pop() {
+				let mut read_dir = fs::read_dir(&dir).await?;
+				while let Some(entry) = read_dir.next_entry().await? {
+					if entry.file_type().await?.is_dir() {
+						dirs.push(entry.path());
+						to_read.push(entry.path());
+					}
+				}
+			}
+
+			// Every directory comes after its parent
+			for dir in dirs.iter().rev() {
+				fs::remove_dir(dir).await?;
+			}
+
+			Ok(())
+		}
+
+		LeftoverKind::BrokenLink | LeftoverKind::EmptyFile => fs::remove_file(path).await,
+	}
+}
+
+/// Removes the file paths of a leftover deleted from disk, along with the ones of the directories
+/// inside it, so the library doesn't wait on the watcher or the next scan to forget them
+async fn remove_file_paths(
+	db: &PrismaClient,
+	sync: &SyncManager,
+	location_id: location::id::Type,
+	FileData { file_path, .. }: &FileData,
+) -> Result<(), FileSystemJobsError> {
+	let mut removed = vec![(file_path.id, file_path.pub_id.clone())];
+
+	if maybe_missing(file_path.is_dir, "file_path.is_dir")? {
+		removed.extend(
+			db.file_path()
+				.find_many(vec![
+					file_path::location_id::equals(Some(location_id)),
+					file_path::materialized_path::starts_with(format!(
+						"{}{}/",
+						maybe_missing(&file_path.materialized_path, "file_path.materialized_path")?,
+						maybe_missing(&file_path.name, "file_path.name")?,
+					)),
+				])
+				.select(file_path::select!({ id pub_id }))
+				.exec()
+				.await?
+				.into_iter()
+				.map(|child| (child.id, child.pub_id)),
+		);
+	}
+
+	let ids = || removed.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+
+	file_identifier::remove_archive_entries(ids(), db, sync).await?;
+
+	let (removed, ids) = (&removed, &ids);
+
+	db._transaction()
+		.with_timeout(30 * 1000)
+		.run(|db| async move {
+			file_identifier::statistics::forget(vec![file_path::id::in_vec(ids())], &db).await?;
+
+			sync.write_ops(
+				&db,
+				(
+					removed
+						.iter()
+						.map(|(_, pub_id)| {
+							sync.shared_delete(prisma_sync::file_path::SyncId {
+								pub_id: pub_id.clone(),
+							})
+						})
+						.collect(),
+					db.file_path()
+						.delete_many(vec![file_path::id::in_vec(ids())]),
+				),
+			)
+			.await
+		})
+		.await?;
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn entry(id: i32, materialized_path: &str, name: &str, is_dir: bool, size: u64) -> Entry {
+		Entry {
+			id,
+			materialized_path: materialized_path.to_string(),
+			name: name.to_string(),
+			is_dir,
+			is_link: false,
+			size,
+		}
+	}
+
+	#[test]
+	fn leftovers() {
+		let mut entries = vec![
+			entry(1, "/", "Photos", true, 0),
+			entry(2, "/Photos/", "a", false, 10),
+			entry(3, "/Photos/", "Empty", true, 0),
+			entry(4, "/Photos/Empty/", "Nested", true, 0),
+			entry(5, "/", "Music", true, 0),
+			entry(6, "/Music/", "Deeper", true, 0),
+			entry(7, "/Music/Deeper/", "blank", false, 0),
+			entry(8, "/", "link", false, 0),
+		];
+		entries[7].is_link = true;
+
+		assert_eq!(
+			find_leftovers(&entries),
+			[
+				(3, LeftoverKind::EmptyDirectory),
+				(7, LeftoverKind::EmptyFile),
+				(8, LeftoverKind::BrokenLink),
+			]
+		);
+	}
+}
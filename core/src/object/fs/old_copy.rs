@@ -22,9 +22,9 @@ use tokio::{fs, io};
 use tracing::{trace, warn};
 
 use super::{
-	construct_target_filename, error::FileSystemJobsError, fetch_source_and_target_location_paths,
-	find_available_filename_for_duplicate, get_file_data_from_isolated_file_path,
-	get_many_files_datas, FileData,
+	check_destination_capacity, construct_target_filename, error::FileSystemJobsError,
+	fetch_source_and_target_location_paths, find_available_filename_for_duplicate,
+	get_file_data_from_isolated_file_path, get_many_files_datas, FileData,
 };
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -54,8 +54,8 @@ impl StatefulJob for OldFileCopierJobInit {
 
 	const NAME: &'static str = "file_copier";
 
-	fn target_location(&self) -> location::id::Type {
-		self.target_location_id
+	fn target_location(&self) -> Option<location::id::Type> {
+		Some(self.target_location_id)
 	}
 
 	async fn init(
@@ -100,6 +100,12 @@ impl StatefulJob for OldFileCopierJobInit {
 			.try_join()
 			.await?;
 
+		check_destination_capacity(
+			steps.iter().map(|step| &step.source_file_data),
+			&targets_location_path,
+		)
+		.await?;
+
 		*data = Some(OldFileCopierJobData {
 			sources_location_path,
 		});
@@ -1,6 +1,7 @@
 use crate::{
 	invalidate_query,
 	library::Library,
+	location::capacity,
 	object::fs::{construct_target_filename, error::FileSystemJobsError},
 	old_job::{
 		CurrentStep, JobError, JobInitOutput, JobResult, JobRunErrors, JobStepOutput, StatefulJob,
@@ -21,7 +22,10 @@ use specta::Type;
 use tokio::{fs, io};
 use tracing::{trace, warn};
 
-use super::{fetch_source_and_target_location_paths, get_many_files_datas, FileData};
+use super::{
+	check_destination_capacity, fetch_source_and_target_location_paths, get_many_files_datas,
+	FileData,
+};
 
 #[derive(Serialize, Deserialize, Hash, Type, Debug)]
 pub struct OldFileCutterJobInit {
@@ -44,8 +48,8 @@ impl StatefulJob for OldFileCutterJobInit {
 
 	const NAME: &'static str = "file_cutter";
 
-	fn target_location(&self) -> location::id::Type {
-		self.target_location_id
+	fn target_location(&self) -> Option<location::id::Type> {
+		Some(self.target_location_id)
 	}
 
 	async fn init(
@@ -76,6 +80,11 @@ impl StatefulJob for OldFileCutterJobInit {
 		let steps =
 			get_many_files_datas(db, &sources_location_path, &init.sources_file_path_ids).await?;
 
+		// Moving within a volume is just a rename, it only takes space when crossing volumes
+		if !capacity::same_volume(&sources_location_path, &targets_location_path).await {
+			check_destination_capacity(&steps, &targets_location_path).await?;
+		}
+
 		Ok(steps.into())
 	}
 
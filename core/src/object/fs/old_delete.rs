@@ -7,6 +7,8 @@ use crate::{
 	},
 };
 
+use sd_core_heavy_lifting::file_identifier;
+
 use sd_prisma::{
 	prisma::{file_path, location},
 	prisma_sync,
@@ -18,16 +20,42 @@ use std::hash::Hash;
 
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use serde_with::{serde_as, DisplayFromStr};
 use specta::Type;
 use tokio::{fs, io};
 use tracing::warn;
 
-use super::{error::FileSystemJobsError, get_many_files_datas, FileData};
+use super::{
+	erase::{erase, erase_caveats},
+	error::FileSystemJobsError,
+	get_many_files_datas,
+	trash::move_to_trash,
+	FileData,
+};
 
 #[derive(Serialize, Deserialize, Hash, Type, Debug)]
 pub struct OldFileDeleterJobInit {
 	pub location_id: location::id::Type,
 	pub file_path_ids: Vec<file_path::id::Type>,
+	#[serde(default)]
+	pub mode: DeleteMode,
+}
+
+#[serde_as]
+#[derive(Serialize, Deserialize, Hash, Type, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DeleteMode {
+	/// Unlinks files, for good
+	#[default]
+	Delete,
+	/// Moves files to the platform trash, keeping their file paths in the library to restore them
+	Trash,
+	/// Overwrites file contents before unlinking them, which is best effort only on some volumes,
+	/// see [`erase_caveats`]
+	Erase {
+		#[specta(type = String)]
+		#[serde_as(as = "DisplayFromStr")]
+		passes: usize,
+	},
 }
 
 #[async_trait::async_trait]
@@ -38,8 +66,8 @@ impl StatefulJob for OldFileDeleterJobInit {
 
 	const NAME: &'static str = "file_deleter";
 
-	fn target_location(&self) -> location::id::Type {
-		self.location_id
+	fn target_location(&self) -> Option<location::id::Type> {
+		Some(self.location_id)
 	}
 
 	async fn init(
@@ -76,26 +104,48 @@ impl StatefulJob for OldFileDeleterJobInit {
 
 		let Library { db, sync, .. } = ctx.library.as_ref();
 
-		match if maybe_missing(step.file_path.is_dir, "file_path.is_dir")? {
-			fs::remove_dir_all(&step.full_path).await
-		} else {
-			fs::remove_file(&step.full_path).await
+		let is_dir = maybe_missing(step.file_path.is_dir, "file_path.is_dir")?;
+
+		match match self.mode {
+			DeleteMode::Delete if is_dir => fs::remove_dir_all(&step.full_path).await,
+			DeleteMode::Delete => fs::remove_file(&step.full_path).await,
+			// Checked beforehand, so missing files are only removed from the database
+			DeleteMode::Trash | DeleteMode::Erase { .. } => {
+				fs::metadata(&step.full_path).await.map(|_| ())
+			}
 		} {
-			Ok(()) => { /*	Everything is awesome! */ }
+			Ok(()) => match self.mode {
+				DeleteMode::Delete => { /*	Everything is awesome! */ }
+				DeleteMode::Trash => move_to_trash(db, sync, self.location_id, step).await?,
+				DeleteMode::Erase { passes } => erase(&step.full_path, is_dir, passes).await?,
+			},
 			Err(e) if e.kind() == io::ErrorKind::NotFound => {
 				warn!(
 					"File not found in the file system, will remove from database: {}",
 					step.full_path.display()
 				);
-				sync.write_op(
-					db,
-					sync.shared_delete(prisma_sync::file_path::SyncId {
-						pub_id: step.file_path.pub_id.clone(),
-					}),
-					db.file_path()
-						.delete(file_path::id::equals(step.file_path.id)),
-				)
-				.await?;
+				file_identifier::remove_archive_entries(vec![step.file_path.id], db, sync).await?;
+
+				db._transaction()
+					.with_timeout(30 * 1000)
+					.run(|db| async move {
+						file_identifier::statistics::forget(
+							vec![file_path::id::equals(step.file_path.id)],
+							&db,
+						)
+						.await?;
+
+						sync.write_op(
+							&db,
+							sync.shared_delete(prisma_sync::file_path::SyncId {
+								pub_id: step.file_path.pub_id.clone(),
+							}),
+							db.file_path()
+								.delete(file_path::id::equals(step.file_path.id)),
+						)
+						.await
+					})
+					.await?;
 			}
 			Err(e) => {
 				return Err(JobError::from(FileIOError::from((&step.full_path, e))));
@@ -116,6 +166,22 @@ impl StatefulJob for OldFileDeleterJobInit {
 
 		// ctx.library.orphan_remover.invoke().await;
 
+		if let DeleteMode::Erase { .. } = init.mode {
+			let caveats = erase_caveats(
+				get_location_path_from_location_id(&ctx.library.db, init.location_id).await?,
+			)
+			.await;
+
+			if !caveats.is_empty() {
+				warn!(
+					"Erased files of location <id='{}'> may still be recoverable: {caveats:?}",
+					init.location_id
+				);
+			}
+
+			return Ok(Some(json!({ "init": init, "erase_caveats": caveats })));
+		}
+
 		Ok(Some(json!({ "init": init })))
 	}
 }
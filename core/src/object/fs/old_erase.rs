@@ -19,15 +19,12 @@ use futures::future::try_join_all;
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DisplayFromStr};
 use specta::Type;
-use tokio::{
-	fs::{self, OpenOptions},
-	io::AsyncWriteExt,
-};
+use tokio::fs;
 use tracing::trace;
 
 use super::{
-	error::FileSystemJobsError, get_file_data_from_isolated_file_path, get_many_files_datas,
-	FileData,
+	erase::erase, error::FileSystemJobsError, get_file_data_from_isolated_file_path,
+	get_many_files_datas, FileData,
 };
 
 #[serde_as]
@@ -65,8 +62,8 @@ impl StatefulJob for OldFileEraserJobInit {
 
 	const NAME: &'static str = "file_eraser";
 
-	fn target_location(&self) -> location::id::Type {
-		self.location_id
+	fn target_location(&self) -> Option<location::id::Type> {
+		Some(self.location_id)
 	}
 
 	async fn init(
@@ -141,41 +138,13 @@ impl StatefulJob for OldFileEraserJobInit {
 
 			Ok((more_steps, new_metadata).into())
 		} else {
-			{
-				let mut file = OpenOptions::new()
-					.read(true)
-					.write(true)
-					.open(&step.full_path)
-					.await
-					.map_err(|e| FileIOError::from((&step.full_path, e)))?;
-				// let file_len = file
-				// 	.metadata()
-				// 	.await
-				// 	.map_err(|e| FileIOError::from((&step.full_path, e)))?
-				// 	.len();
-
-				trace!(
-					"Overwriting file: {} with {} passes",
-					step.full_path.display(),
-					init.passes
-				);
+			trace!(
+				"Overwriting file: {} with {} passes",
+				step.full_path.display(),
+				init.passes
+			);
 
-				#[cfg(feature = "crypto")]
-				// sd_crypto::fs::erase::erase_async(&mut file, file_len as usize, init.passes).await?;
-				#[cfg(not(feature = "crypto"))]
-				warn!("File not fully erased due to missing crypto module");
-
-				file.set_len(0)
-					.await
-					.map_err(|e| FileIOError::from((&step.full_path, e)))?;
-				file.flush()
-					.await
-					.map_err(|e| FileIOError::from((&step.full_path, e)))?;
-			}
-
-			fs::remove_file(&step.full_path)
-				.await
-				.map_err(|e| FileIOError::from((&step.full_path, e)))?;
+			erase(&step.full_path, false, init.passes).await?;
 
 			Ok(None.into())
 		}
@@ -0,0 +1,214 @@
+use crate::{
+	invalidate_query,
+	library::Library,
+	location::{
+		cloud_metadata::CloudMetadataLocation, get_location_path_from_location_id, LocationError,
+	},
+	node::bandwidth::{BandwidthProtocol, Throttled},
+	old_job::{
+		CurrentStep, JobError, JobInitOutput, JobResult, JobStepOutput, StatefulJob, WorkerContext,
+	},
+};
+
+use sd_core_file_path_helper::IsolatedFilePathData;
+use sd_core_prisma_helpers::file_path_to_isolate_with_pub_id;
+
+use sd_prisma::{
+	prisma::{file_path, location, provider_hash},
+	prisma_sync,
+};
+use sd_sync::OperationFactory;
+use sd_utils::{error::FileIOError, msgpack};
+
+use std::{hash::Hash, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::{fs, io};
+use tracing::trace;
+
+use super::error::FileSystemJobsError;
+
+/// `OldFileHydratorJobInit` downloads the content of remote only file paths, either by reading
+/// on-demand placeholders so their cloud provider fetches them, or from the provider of cloud
+/// metadata locations. Hydrated file paths are left without a `cas_id`, so the next file
+/// identifier run hashes them and links them to their objects.
+#[derive(Serialize, Deserialize, Hash, Type, Debug)]
+pub struct OldFileHydratorJobInit {
+	pub location_id: location::id::Type,
+	pub file_path_ids: Vec<file_path::id::Type>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OldFileHydratorJobData {
+	cloud_config: Option<Vec<u8>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OldFileHydratorJobStep {
+	file_path_id: file_path::id::Type,
+	pub_id: file_path::pub_id::Type,
+	full_path: PathBuf,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for OldFileHydratorJobInit {
+	type Data = OldFileHydratorJobData;
+	type Step = OldFileHydratorJobStep;
+	type RunMetadata = ();
+
+	const NAME: &'static str = "file_hydrator";
+
+	fn target_location(&self) -> Option<location::id::Type> {
+		Some(self.location_id)
+	}
+
+	async fn init(
+		&self,
+		ctx: &WorkerContext,
+		data: &mut Option<Self::Data>,
+	) -> Result<JobInitOutput<Self::RunMetadata, Self::Step>, JobError> {
+		let init = self;
+		let Library { db, .. } = &*ctx.library;
+
+		let location_path = get_location_path_from_location_id(db, init.location_id).await?;
+
+		let cloud_config = db
+			.location()
+			.find_unique(location::id::equals(init.location_id))
+			.select(location::select!({ cloud_config }))
+			.exec()
+			.await?
+			.and_then(|location| location.cloud_config);
+
+		// Only file paths of the location we were given, the ids come straight from the frontend
+		let steps = db
+			._batch(
+				init.file_path_ids
+					.iter()
+					.map(|file_path_id| {
+						db.file_path()
+							.find_first(vec![
+								file_path::id::equals(*file_path_id),
+								file_path::location_id::equals(Some(init.location_id)),
+							])
+							.select(file_path_to_isolate_with_pub_id::select())
+					})
+					.collect::<Vec<_>>(),
+			)
+			.await?
+			.into_iter()
+			.zip(init.file_path_ids.iter())
+			.map(|(maybe_file_path, file_path_id)| {
+				let file_path = maybe_file_path
+					.ok_or(FileSystemJobsError::FilePathIdNotFound(*file_path_id))?;
+
+				Ok(OldFileHydratorJobStep {
+					file_path_id: *file_path_id,
+					full_path: location_path.join(IsolatedFilePathData::try_from(&file_path)?),
+					pub_id: file_path.pub_id,
+				})
+			})
+			.collect::<Result<Vec<_>, FileSystemJobsError>>()?;
+
+		*data = Some(OldFileHydratorJobData { cloud_config });
+
+		Ok(steps.into())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: &WorkerContext,
+		CurrentStep { step, .. }: CurrentStep<'_, Self::Step>,
+		data: &Self::Data,
+		_: &Self::RunMetadata,
+	) -> Result<JobStepOutput<Self::Step, Self::RunMetadata>, JobError> {
+		let init = self;
+		let Library { db, sync, .. } = &*ctx.library;
+
+		trace!("Hydrating file: {}", step.full_path.display());
+
+		if let Some(cloud) =
+			CloudMetadataLocation::for_location(init.location_id, data.cloud_config.as_deref())
+				.await
+				.map_err(LocationError::from)?
+		{
+			// Cloud metadata locations only have the directory skeleton locally
+			let provider_hash = db
+				.provider_hash()
+				.find_unique(provider_hash::file_path_pub_id::equals(step.pub_id.clone()))
+				.exec()
+				.await?
+				.ok_or(FileSystemJobsError::NotIndexedInCloud(step.file_path_id))?;
+
+			cloud
+				.download(&provider_hash.item_id, &step.full_path, &ctx.node.bandwidth)
+				.await
+				.map_err(LocationError::from)?;
+		} else {
+			// Reading the whole content makes the cloud provider download it
+			let mut file = Throttled::new(
+				fs::File::open(&step.full_path).await.map_err(|e| {
+					FileIOError::from((
+						&step.full_path,
+						e,
+						"Failed to open on-demand file to hydrate",
+					))
+				})?,
+				ctx.node.bandwidth.clone(),
+				BandwidthProtocol::CloudHydration,
+			);
+			io::copy(&mut file, &mut io::sink()).await.map_err(|e| {
+				FileIOError::from((
+					&step.full_path,
+					e,
+					"Failed to read on-demand file to hydrate",
+				))
+			})?;
+		}
+
+		// Without a cas_id and the remote only flag, the next file identifier run hashes it and
+		// links it to its object
+		sync.write_ops(
+			db,
+			(
+				[
+					(file_path::remote_only::NAME, msgpack!(false)),
+					(file_path::cas_id::NAME, msgpack!(nil)),
+				]
+				.into_iter()
+				.map(|(field, value)| {
+					sync.shared_update(
+						prisma_sync::file_path::SyncId {
+							pub_id: step.pub_id.clone(),
+						},
+						field,
+						value,
+					)
+				})
+				.collect(),
+				db.file_path().update(
+					file_path::pub_id::equals(step.pub_id.clone()),
+					vec![
+						file_path::remote_only::set(Some(false)),
+						file_path::cas_id::set(None),
+					],
+				),
+			),
+		)
+		.await?;
+
+		Ok(().into())
+	}
+
+	async fn finalize(
+		&self,
+		ctx: &WorkerContext,
+		_data: &Option<Self::Data>,
+		_run_metadata: &Self::RunMetadata,
+	) -> JobResult {
+		invalidate_query!(ctx.library, "search.paths");
+
+		Ok(Some(serde_json::to_value(self)?))
+	}
+}
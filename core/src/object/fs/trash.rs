@@ -0,0 +1,393 @@
+use crate::location::LocationError;
+
+use sd_core_heavy_lifting::file_identifier;
+use sd_core_sync::Manager as SyncManager;
+
+use sd_prisma::{
+	prisma::{file_path, location, trashed_file_path, PrismaClient},
+	prisma_sync,
+};
+use sd_sync::OperationFactory;
+use sd_utils::{db::maybe_missing, msgpack};
+
+use std::path::PathBuf;
+
+use chrono::{DateTime, FixedOffset, Utc};
+use prisma_client_rust::QueryError;
+use tokio::task::spawn_blocking;
+use tracing::error;
+
+use super::{error::FileSystemJobsError, FileData};
+
+/// Moves a file or directory to the platform trash, keeping its file paths in the library, detached
+/// from their location, so it can be restored with [`restore_from_trash`].
+///
+/// File paths are detached before touching the disk, so the watcher doesn't remove them when the
+/// file disappears from the location.
+pub async fn move_to_trash(
+	db: &PrismaClient,
+	sync: &SyncManager,
+	location_id: location::id::Type,
+	FileData {
+		file_path,
+		full_path,
+	}: &FileData,
+) -> Result<(), FileSystemJobsError> {
+	let date_trashed: DateTime<FixedOffset> = Utc::now().into();
+
+	let mut trashed = vec![(file_path.id, file_path.pub_id.clone())];
+
+	if maybe_missing(file_path.is_dir, "file_path.is_dir")? {
+		trashed.extend(
+			db.file_path()
+				.find_many(vec![
+					file_path::location_id::equals(Some(location_id)),
+					file_path::materialized_path::starts_with(children_prefix(
+						maybe_missing(&file_path.materialized_path, "file_path.materialized_path")?,
+						maybe_missing(&file_path.name, "file_path.name")?,
+					)),
+				])
+				.select(file_path::select!({ id pub_id }))
+				.exec()
+				.await?
+				.into_iter()
+				.map(|child| (child.id, child.pub_id)),
+		);
+	}
+
+	set_trashed(db, sync, &trashed, Some(date_trashed), None).await?;
+
+	if let Err(e) = send_to_trash(full_path.clone()).await {
+		if let Err(e) = set_trashed(db, sync, &trashed, None, Some(location_id)).await {
+			error!(
+				"Failed to reattach file paths of <path='{}'> after failing to trash it: {e:#?}",
+				full_path.display()
+			);
+		}
+
+		return Err(e);
+	}
+
+	db.trashed_file_path()
+		.create(
+			file_path::id::equals(file_path.id),
+			location::id::equals(location_id),
+			full_path.to_string_lossy().to_string(),
+			date_trashed,
+			vec![],
+		)
+		.exec()
+		.await?;
+
+	Ok(())
+}
+
+/// Puts a file or directory moved to the trash by [`move_to_trash`] back where it was, and its file
+/// paths back in their location.
+///
+/// If it isn't in the trash anymore, its file paths are removed from the library.
+pub async fn restore_from_trash(
+	db: &PrismaClient,
+	sync: &SyncManager,
+	file_path_id: file_path::id::Type,
+) -> Result<(), FileSystemJobsError> {
+	let trashed_file_path = db
+		.trashed_file_path()
+		.find_unique(trashed_file_path::file_path_id::equals(file_path_id))
+		.include(trashed_file_path::include!({
+			file_path: select { pub_id is_dir materialized_path name }
+		}))
+		.exec()
+		.await?
+		.ok_or(FileSystemJobsError::FilePathNotTrashed(file_path_id))?;
+
+	let original_path = PathBuf::from(&trashed_file_path.original_path);
+	let file_path = &trashed_file_path.file_path;
+
+	let mut trashed = vec![(file_path_id, file_path.pub_id.clone())];
+
+	if maybe_missing(file_path.is_dir, "file_path.is_dir")? {
+		trashed.extend(
+			db.file_path()
+				.find_many(vec![
+					file_path::location_id::equals(None),
+					file_path::date_trashed::equals(Some(trashed_file_path.date_trashed)),
+					file_path::materialized_path::starts_with(children_prefix(
+						maybe_missing(&file_path.materialized_path, "file_path.materialized_path")?,
+						maybe_missing(&file_path.name, "file_path.name")?,
+					)),
+				])
+				.select(file_path::select!({ id pub_id }))
+				.exec()
+				.await?
+				.into_iter()
+				.map(|child| (child.id, child.pub_id)),
+		);
+	}
+
+	// Reattached before restoring, so the watcher finds them already there
+	set_trashed(
+		db,
+		sync,
+		&trashed,
+		None,
+		Some(trashed_file_path.location_id),
+	)
+	.await?;
+
+	match restore_item(original_path.clone(), trashed_file_path.date_trashed).await {
+		Ok(()) => {
+			db.trashed_file_path()
+				.delete(trashed_file_path::id::equals(trashed_file_path.id))
+				.exec()
+				.await?;
+
+			Ok(())
+		}
+
+		Err(FileSystemJobsError::NotInTrash(path)) => {
+			// Emptied from the trash, nothing left to restore
+			let trashed = &trashed;
+
+			db._transaction()
+				.with_timeout(30 * 1000)
+				.run(|db| async move {
+					let ids = || trashed.iter().map(|(id, _)| *id).collect();
+
+					file_identifier::statistics::forget(vec![file_path::id::in_vec(ids())], &db)
+						.await?;
+
+					sync.write_ops(
+						&db,
+						(
+							trashed
+								.iter()
+								.map(|(_, pub_id)| {
+									sync.shared_delete(prisma_sync::file_path::SyncId {
+										pub_id: pub_id.clone(),
+									})
+								})
+								.collect(),
+							db.file_path()
+								.delete_many(vec![file_path::id::in_vec(ids())]),
+						),
+					)
+					.await
+				})
+				.await?;
+
+			Err(FileSystemJobsError::NotInTrash(path))
+		}
+
+		Err(e) => {
+			if let Err(e) = set_trashed(
+				db,
+				sync,
+				&trashed,
+				Some(trashed_file_path.date_trashed),
+				None,
+			)
+			.await
+			{
+				error!(
+					"Failed to detach file paths of <path='{}'> after failing to restore it: {e:#?}",
+					original_path.display()
+				);
+			}
+
+			Err(e)
+		}
+	}
+}
+
+/// Removes file paths trashed from a location being deleted, which aren't found through it anymore.
+/// They're removed locally, as deleting the location already removes them elsewhere.
+pub async fn remove_trashed_from_location(
+	db: &PrismaClient,
+	location_id: location::id::Type,
+) -> Result<(), QueryError> {
+	// Descendants of trashed directories have no mapping, but share their trashing date
+	let dates_trashed = db
+		.trashed_file_path()
+		.find_many(vec![trashed_file_path::location_id::equals(location_id)])
+		.select(trashed_file_path::select!({ date_trashed }))
+		.exec()
+		.await?
+		.into_iter()
+		.map(|trashed_file_path| trashed_file_path.date_trashed)
+		.collect::<Vec<_>>();
+
+	if dates_trashed.is_empty() {
+		return Ok(());
+	}
+
+	db.file_path()
+		.delete_many(vec![
+			file_path::location_id::equals(None),
+			file_path::date_trashed::in_vec(dates_trashed),
+		])
+		.exec()
+		.await?;
+
+	Ok(())
+}
+
+fn children_prefix(materialized_path: &str, name: &str) -> String {
+	format!("{materialized_path}{name}/")
+}
+
+/// Sets `date_trashed` on file paths, detaching them from their location while trashed and
+/// attaching them to `location_id` otherwise
+async fn set_trashed(
+	db: &PrismaClient,
+	sync: &SyncManager,
+	file_paths: &[(file_path::id::Type, Vec<u8>)],
+	date_trashed: Option<DateTime<FixedOffset>>,
+	location_id: Option<location::id::Type>,
+) -> Result<(), FileSystemJobsError> {
+	let location_pub_id = match location_id {
+		Some(location_id) => Some(
+			db.location()
+				.find_unique(location::id::equals(location_id))
+				.select(location::select!({ pub_id }))
+				.exec()
+				.await?
+				.ok_or(LocationError::IdNotFound(location_id))?
+				.pub_id,
+		),
+		None => None,
+	};
+
+	let (sync_ops, queries): (Vec<_>, Vec<_>) = file_paths
+		.iter()
+		.map(|(id, pub_id)| {
+			let sync_id = || prisma_sync::file_path::SyncId {
+				pub_id: pub_id.clone(),
+			};
+
+			let (location_op, location_param) = match (location_id, &location_pub_id) {
+				(Some(location_id), Some(location_pub_id)) => (
+					sync.shared_update(
+						sync_id(),
+						file_path::location::NAME,
+						msgpack!(prisma_sync::location::SyncId {
+							pub_id: location_pub_id.clone()
+						}),
+					),
+					file_path::location::connect(location::id::equals(location_id)),
+				),
+				_ => (
+					sync.shared_update(sync_id(), file_path::location::NAME, msgpack!(nil)),
+					file_path::location::disconnect(),
+				),
+			};
+
+			(
+				[
+					sync.shared_update(
+						sync_id(),
+						file_path::date_trashed::NAME,
+						msgpack!(date_trashed),
+					),
+					location_op,
+				],
+				db.file_path()
+					.update(
+						file_path::id::equals(*id),
+						vec![file_path::date_trashed::set(date_trashed), location_param],
+					)
+					.select(file_path::select!({ id })),
+			)
+		})
+		.unzip();
+
+	let ids = &file_paths.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+
+	// Trashed file paths leave the statistics of their location until restored
+	db._transaction()
+		.with_timeout(30 * 1000)
+		.run(|db| async move {
+			let before = file_identifier::statistics::snapshot(
+				vec![file_path::id::in_vec(ids.clone())],
+				&db,
+			)
+			.await?;
+
+			sync.write_ops(&db, (sync_ops.into_iter().flatten().collect(), queries))
+				.await?;
+
+			file_identifier::statistics::snapshot(vec![file_path::id::in_vec(ids.clone())], &db)
+				.await?
+				.since(before)
+				.commit(&db)
+				.await
+		})
+		.await?;
+
+	Ok(())
+}
+
+#[cfg(not(any(target_os = "ios", target_os = "android")))]
+async fn send_to_trash(path: PathBuf) -> Result<(), FileSystemJobsError> {
+	let task_path = path.clone();
+
+	spawn_blocking(move || trash::delete(task_path).map_err(|e| e.to_string()))
+		.await
+		.map_err(|e| e.to_string())
+		.and_then(|res| res)
+		.map_err(|e| FileSystemJobsError::Trash(path.into_boxed_path(), e))
+}
+
+#[cfg(any(target_os = "ios", target_os = "android"))]
+async fn send_to_trash(_: PathBuf) -> Result<(), FileSystemJobsError> {
+	Err(FileSystemJobsError::TrashUnsupported)
+}
+
+/// Restores the trash item that was at `original_path`, the one trashed closest to `date_trashed`
+/// if it was trashed more than once
+#[cfg(any(
+	target_os = "windows",
+	all(
+		unix,
+		not(any(target_os = "macos", target_os = "ios", target_os = "android"))
+	)
+))]
+async fn restore_item(
+	original_path: PathBuf,
+	date_trashed: DateTime<FixedOffset>,
+) -> Result<(), FileSystemJobsError> {
+	let task_path = original_path.clone();
+
+	spawn_blocking(move || {
+		let Some(item) = trash::os_limited::list()
+			.map_err(|e| e.to_string())?
+			.into_iter()
+			.filter(|item| item.original_path() == task_path)
+			.min_by_key(|item| item.time_deleted.abs_diff(date_trashed.timestamp()))
+		else {
+			return Ok(false);
+		};
+
+		trash::os_limited::restore_all([item])
+			.map(|()| true)
+			.map_err(|e| e.to_string())
+	})
+	.await
+	.map_err(|e| e.to_string())
+	.and_then(|res| res)
+	.map_err(|e| FileSystemJobsError::Restore(original_path.clone().into_boxed_path(), e))?
+	.then_some(())
+	.ok_or_else(|| FileSystemJobsError::NotInTrash(original_path.into_boxed_path()))
+}
+
+// The macOS trash can't be listed nor restored from, outside of Finder
+#[cfg(not(any(
+	target_os = "windows",
+	all(
+		unix,
+		not(any(target_os = "macos", target_os = "ios", target_os = "android"))
+	)
+)))]
+async fn restore_item(_: PathBuf, _: DateTime<FixedOffset>) -> Result<(), FileSystemJobsError> {
+	Err(FileSystemJobsError::TrashUnsupported)
+}
@@ -45,7 +45,16 @@ pub const fn can_extract_exif_data_for_image(image_extension: &ImageExtension) -
 	use ImageExtension::*;
 	matches!(
 		image_extension,
-		Tiff | Dng | Jpeg | Jpg | Heif | Heifs | Heic | Avif | Avcs | Avci | Hif | Png | Webp
+		Tiff | Dng
+			| Cr2 | Cr3
+			| Dcr | Nef
+			| Arw | Rw2
+			| Jpeg | Jpg
+			| Heif | Heifs
+			| Heic | Avif
+			| Avcs | Avci
+			| Hif | Png
+			| Webp
 	)
 }
 
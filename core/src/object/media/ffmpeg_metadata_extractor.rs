@@ -74,12 +74,15 @@ pub const fn can_extract_ffmpeg_data_for_audio(audio_extension: &AudioExtension)
 	matches!(
 		audio_extension,
 		Mp3 | Mp2
-			| M4a | Wav | Aiff
-			| Aif | Flac | Ogg
-			| Oga | Opus | Wma
-			| Amr | Aac | Wv
-			| Voc | Tta | Loas
-			| Caf | Aptx | Adts
+			| M4a | Wav
+			| Aiff | Aif
+			| Flac | Ogg
+			| Oga | Opus
+			| Wma | Amr
+			| Aac | Wv
+			| Voc | Tta
+			| Loas | Caf
+			| Aptx | Adts
 			| Ast | Mid
 	)
 }
@@ -90,14 +93,18 @@ pub const fn can_extract_ffmpeg_data_for_video(video_extension: &VideoExtension)
 	matches!(
 		video_extension,
 		Avi | Avifs
-			| Qt | Mov | Swf
-			| Mjpeg | Ts | Mts
-			| Mpeg | Mxf | M2v
-			| Mpg | Mpe | M2ts
-			| Flv | Wm | _3gp
-			| M4v | Wmv | Asf
-			| Mp4 | Webm | Mkv
-			| Vob | Ogv | Wtv
+			| Qt | Mov
+			| Swf | Mjpeg
+			| Ts | Mts
+			| Mpeg | Mxf
+			| M2v | Mpg
+			| Mpe | M2ts
+			| Flv | Wm
+			| _3gp | M4v
+			| Wmv | Asf
+			| Mp4 | Webm
+			| Mkv | Vob
+			| Ogv | Wtv
 			| Hevc | F4v
 	)
 }
@@ -310,14 +317,19 @@ async fn create_ffmpeg_chapters(
 						time_base_den,
 						time_base_num,
 						ffmpeg_data_id,
-						_params: vec![ffmpeg_media_chapter::metadata::set(
-							serde_json::to_vec(&metadata)
-								.map_err(|err| {
-									error!("Error reading FFmpegMediaChapter metadata: {err:#?}");
-									err
-								})
-								.ok(),
-						)],
+						_params: vec![
+							ffmpeg_media_chapter::title::set(metadata.title.clone()),
+							ffmpeg_media_chapter::metadata::set(
+								serde_json::to_vec(&metadata)
+									.map_err(|err| {
+										error!(
+											"Error reading FFmpegMediaChapter metadata: {err:#?}"
+										);
+										err
+									})
+									.ok(),
+							),
+						],
 					},
 				)
 				.collect(),
@@ -1,5 +1,6 @@
 use sd_core_prisma_helpers::object_with_media_data;
 use sd_media_metadata::{
+	exif::MediaLocation,
 	ffmpeg::{
 		audio_props::AudioProps,
 		chapter::Chapter,
@@ -23,6 +24,9 @@ pub use old_media_processor::OldMediaProcessorJobInit;
 use sd_utils::db::ffmpeg_data_field_from_db;
 
 pub fn exif_data_image_to_query(mdi: ExifMetadata, object_id: object_id::Type) -> CreateUnchecked {
+	let coordinates = mdi.location.as_ref().map(MediaLocation::coordinates);
+	let place = mdi.location.as_ref().and_then(MediaLocation::place);
+
 	CreateUnchecked {
 		object_id,
 		_params: vec![
@@ -30,6 +34,10 @@ pub fn exif_data_image_to_query(mdi: ExifMetadata, object_id: object_id::Type) -
 			media_date::set(serde_json::to_vec(&mdi.date_taken).ok()),
 			resolution::set(serde_json::to_vec(&mdi.resolution).ok()),
 			media_location::set(serde_json::to_vec(&mdi.location).ok()),
+			latitude::set(coordinates.map(|(latitude, _)| latitude)),
+			longitude::set(coordinates.map(|(_, longitude)| longitude)),
+			city::set(place.and_then(|place| place.city).map(ToString::to_string)),
+			country::set(place.map(|place| place.country.to_string())),
 			artist::set(mdi.artist),
 			description::set(mdi.description),
 			copyright::set(mdi.copyright),
@@ -45,12 +53,22 @@ pub fn exif_data_image_to_query_params(
 	use sd_sync::option_sync_db_entry;
 	use sd_utils::chain_optional_iter;
 
+	let coordinates = mdi.location.as_ref().map(MediaLocation::coordinates);
+	let place = mdi.location.as_ref().and_then(MediaLocation::place);
+
 	chain_optional_iter(
 		[],
 		[
 			option_sync_db_entry!(serde_json::to_vec(&mdi.camera_data).ok(), camera_data),
 			option_sync_db_entry!(serde_json::to_vec(&mdi.date_taken).ok(), media_date),
 			option_sync_db_entry!(serde_json::to_vec(&mdi.location).ok(), media_location),
+			option_sync_db_entry!(coordinates.map(|(latitude, _)| latitude), latitude),
+			option_sync_db_entry!(coordinates.map(|(_, longitude)| longitude), longitude),
+			option_sync_db_entry!(
+				place.and_then(|place| place.city).map(ToString::to_string),
+				city
+			),
+			option_sync_db_entry!(place.map(|place| place.country.to_string()), country),
 			option_sync_db_entry!(mdi.artist, artist),
 			option_sync_db_entry!(mdi.description, description),
 			option_sync_db_entry!(mdi.copyright, copyright),
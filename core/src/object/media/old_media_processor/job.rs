@@ -48,8 +48,8 @@ use tracing::{debug, error, info, trace, warn};
 use super::{
 	exif_metadata_extractor,
 	old_thumbnail::{self, GenerateThumbnailArgs},
-	process_audio_and_video, process_images, BatchToProcess, MediaProcessorError,
-	OldMediaProcessorMetadata,
+	process_audio_and_video, process_images, read_through_remote_files, BatchToProcess,
+	MediaProcessorError, OldMediaProcessorMetadata,
 };
 
 const BATCH_SIZE: usize = 10;
@@ -102,8 +102,8 @@ impl StatefulJob for OldMediaProcessorJobInit {
 	const NAME: &'static str = "media_processor";
 	const IS_BATCHED: bool = true;
 
-	fn target_location(&self) -> location::id::Type {
-		self.location.id
+	fn target_location(&self) -> Option<location::id::Type> {
+		Some(self.location.id)
 	}
 
 	async fn init(
@@ -157,7 +157,7 @@ impl StatefulJob for OldMediaProcessorJobInit {
 		);
 
 		let thumbs_to_process_count = dispatch_thumbnails_for_processing(
-			location_id,
+			&self.location,
 			&location_path,
 			&iso_file_path,
 			&ctx.library,
@@ -469,7 +469,7 @@ impl StatefulJob for OldMediaProcessorJobInit {
 }
 
 async fn dispatch_thumbnails_for_processing(
-	location_id: location::id::Type,
+	location: &location::Data,
 	location_path: impl AsRef<Path>,
 	parent_iso_file_path: &IsolatedFilePathData<'_>,
 	library: &Library,
@@ -478,6 +478,7 @@ async fn dispatch_thumbnails_for_processing(
 ) -> Result<u32, MediaProcessorError> {
 	let Library { db, .. } = library;
 
+	let location_id = location.id;
 	let location_path = location_path.as_ref();
 
 	let mut file_paths = get_all_children_files_by_extensions(
@@ -491,6 +492,12 @@ async fn dispatch_thumbnails_for_processing(
 		return Ok(0);
 	}
 
+	read_through_remote_files(location, location_path, &mut file_paths).await?;
+
+	if file_paths.is_empty() {
+		return Ok(0);
+	}
+
 	let first_materialized_path = file_paths[0].materialized_path.clone();
 
 	// Only the first materialized_path should be processed in foreground
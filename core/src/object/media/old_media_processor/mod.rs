@@ -1,6 +1,14 @@
-use crate::old_job::{JobRunErrors, JobRunMetadata};
+use crate::{
+	location::{
+		cloud_metadata::{CloudMetadataError, CloudMetadataLocationConfig},
+		mtp::{MtpError, MtpLocation},
+		s3::{object_key, S3Error, S3Location},
+		webdav::{WebDavError, WebDavLocation},
+	},
+	old_job::{JobRunErrors, JobRunMetadata},
+};
 
-use sd_core_file_path_helper::FilePathError;
+use sd_core_file_path_helper::{FilePathError, IsolatedFilePathData};
 use sd_core_prisma_helpers::file_path_for_media_processor;
 
 use sd_prisma::prisma::{location, PrismaClient};
@@ -9,6 +17,7 @@ use std::path::Path;
 
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
+use tokio::fs;
 use tracing::error;
 
 use super::{
@@ -39,6 +48,14 @@ pub enum MediaProcessorError {
 	ExifMediaDataExtractor(#[from] ExifDataError),
 	#[error(transparent)]
 	FFmpegDataExtractor(#[from] FFmpegDataError),
+	#[error(transparent)]
+	S3(#[from] S3Error),
+	#[error(transparent)]
+	WebDav(#[from] WebDavError),
+	#[error(transparent)]
+	CloudMetadata(#[from] CloudMetadataError),
+	#[error(transparent)]
+	Mtp(#[from] MtpError),
 }
 
 #[derive(Debug, Serialize, Deserialize, Default)]
@@ -82,6 +99,76 @@ impl JobRunMetadata for OldMediaProcessorMetadata {
 	}
 }
 
+/// S3, WebDAV and MTP locations only mirror the files that were needed so far, so we download the ones
+/// about to be processed. Files failing to download are just left to fail in their processing.
+///
+/// Cloud metadata locations never download anything on their own, so their files that weren't
+/// hydrated are dropped instead.
+async fn read_through_remote_files(
+	location: &location::Data,
+	location_path: impl AsRef<Path>,
+	file_paths: &mut Vec<file_path_for_media_processor::Data>,
+) -> Result<(), MediaProcessorError> {
+	let location_path = location_path.as_ref();
+
+	if CloudMetadataLocationConfig::from_db(location.id, location.cloud_config.as_deref())?
+		.is_some()
+	{
+		let mut hydrated = Vec::with_capacity(file_paths.len());
+
+		for file_path in file_paths.drain(..) {
+			let Ok(iso_file_path) = IsolatedFilePathData::try_from((location.id, &file_path))
+			else {
+				continue;
+			};
+
+			if fs::try_exists(location_path.join(&iso_file_path))
+				.await
+				.unwrap_or(false)
+			{
+				hydrated.push(file_path);
+			}
+		}
+
+		*file_paths = hydrated;
+
+		return Ok(());
+	}
+
+	let maybe_s3 = S3Location::for_location(location.id, location.s3_config.as_deref()).await?;
+	let maybe_webdav =
+		WebDavLocation::for_location(location.id, location.webdav_config.as_deref())?;
+	let maybe_mtp = MtpLocation::for_location(location.id, location.mtp_config.as_deref()).await?;
+
+	if maybe_s3.is_none() && maybe_webdav.is_none() && maybe_mtp.is_none() {
+		return Ok(());
+	}
+
+	for file_path in file_paths.iter() {
+		let Ok(iso_file_path) = IsolatedFilePathData::try_from((location.id, file_path)) else {
+			continue;
+		};
+
+		let key = object_key(&iso_file_path);
+
+		if let Some(s3) = &maybe_s3 {
+			if let Err(e) = s3.read_through(location_path, &key, None).await {
+				error!("Failed to download S3 object for media processing: {e:#?}");
+			}
+		} else if let Some(webdav) = &maybe_webdav {
+			if let Err(e) = webdav.read_through(location_path, &key).await {
+				error!("Failed to download WebDAV file for media processing: {e:#?}");
+			}
+		} else if let Some(mtp) = &maybe_mtp {
+			if let Err(e) = mtp.read_through(location_path, &key).await {
+				error!("Failed to copy MTP file for media processing: {e:#?}");
+			}
+		}
+	}
+
+	Ok(())
+}
+
 pub async fn process_images(
 	files_paths: &[file_path_for_media_processor::Data],
 	location_id: location::id::Type,
@@ -33,7 +33,7 @@ use futures::StreamExt;
 use super::{
 	exif_metadata_extractor, ffmpeg_metadata_extractor,
 	old_thumbnail::{self, BatchToProcess, GenerateThumbnailArgs},
-	MediaProcessorError, OldMediaProcessorMetadata,
+	read_through_remote_files, MediaProcessorError, OldMediaProcessorMetadata,
 };
 
 const BATCH_SIZE: usize = 10;
@@ -82,7 +82,7 @@ pub async fn old_shallow(
 	debug!("Searching for media in location {location_id} at path {iso_file_path}");
 
 	dispatch_thumbnails_for_processing(
-		location.id,
+		location,
 		&location_path,
 		&iso_file_path,
 		library,
@@ -275,7 +275,7 @@ async fn get_files_for_labeling(
 }
 
 async fn dispatch_thumbnails_for_processing(
-	location_id: location::id::Type,
+	location: &location::Data,
 	location_path: impl AsRef<Path>,
 	parent_iso_file_path: &IsolatedFilePathData<'_>,
 	library: &Library,
@@ -284,15 +284,18 @@ async fn dispatch_thumbnails_for_processing(
 ) -> Result<(), MediaProcessorError> {
 	let Library { db, .. } = library;
 
+	let location_id = location.id;
 	let location_path = location_path.as_ref();
 
-	let file_paths = get_files_by_extensions(
+	let mut file_paths = get_files_by_extensions(
 		db,
 		parent_iso_file_path,
 		&old_thumbnail::ALL_THUMBNAILABLE_EXTENSIONS,
 	)
 	.await?;
 
+	read_through_remote_files(location, location_path, &mut file_paths).await?;
+
 	let current_batch = file_paths
 		.into_iter()
 		.filter_map(|file_path| {
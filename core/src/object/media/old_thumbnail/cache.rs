@@ -0,0 +1,201 @@
+use sd_core_heavy_lifting::media_processor::WAVEFORM_EXTENSION;
+use sd_utils::error::FileIOError;
+
+use std::{
+	fs::File,
+	path::{Path, PathBuf},
+	time::{Duration, SystemTime},
+};
+
+use serde::Serialize;
+use specta::Type;
+use tokio::{fs, task::spawn_blocking};
+use tracing::{debug, error, trace};
+
+use super::{ThumbnailerError, WEBP_EXTENSION};
+
+/// Serving a thumbnail only bumps its last access if the previous bump is older than this, so
+/// scrolling through a big grid doesn't turn every request into a write
+const ACCESS_TIME_RESOLUTION: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Serialize, Type)]
+pub struct ThumbnailCacheUsage {
+	pub files_count: u32,
+	pub total_bytes: String,
+	pub budget_bytes: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Type)]
+pub struct ThumbnailCacheEviction {
+	pub evicted_count: u32,
+	pub freed_bytes: String,
+}
+
+struct CacheEntry {
+	path: PathBuf,
+	size: u64,
+	last_access: SystemTime,
+}
+
+/// Marks a thumbnail as recently used, which is what the eviction uses to decide what goes first.
+///
+/// We store it as the file's modified time, as access times are disabled or coarse on most
+/// filesystems and thumbnails are never modified after being generated anyway.
+pub async fn touch_thumbnail(path: PathBuf) {
+	let now = SystemTime::now();
+
+	let Ok(modified) = fs::metadata(&path)
+		.await
+		.and_then(|metadata| metadata.modified())
+	else {
+		return;
+	};
+
+	if now
+		.duration_since(modified)
+		.map_or(true, |elapsed| elapsed < ACCESS_TIME_RESOLUTION)
+	{
+		return;
+	}
+
+	match spawn_blocking({
+		let path = path.clone();
+		move || File::options().write(true).open(&path)?.set_modified(now)
+	})
+	.await
+	{
+		Ok(Ok(())) => trace!("Bumped thumbnail last access: {}", path.display()),
+		Ok(Err(e)) => error!(
+			"Failed to bump thumbnail last access: {:#?}",
+			FileIOError::from((path, e))
+		),
+		Err(e) => error!("Join error on thumbnail last access bump: {e:#?}"),
+	}
+}
+
+pub(super) async fn cache_usage(
+	library_thumbs_dir: impl AsRef<Path>,
+	budget: Option<u64>,
+) -> Result<ThumbnailCacheUsage, ThumbnailerError> {
+	let entries = list_entries(library_thumbs_dir.as_ref()).await?;
+
+	Ok(ThumbnailCacheUsage {
+		files_count: entries.len() as u32,
+		total_bytes: entries
+			.iter()
+			.map(|entry| entry.size)
+			.sum::<u64>()
+			.to_string(),
+		budget_bytes: budget.map(|budget| budget.to_string()),
+	})
+}
+
+/// Removes the least recently used thumbnails, preview strips and waveforms of a library until
+/// it fits in `budget` bytes.
+///
+/// We go a bit below the budget, otherwise each new thumbnail would need another eviction.
+/// Evicted thumbnails are generated again when the frontend asks for them.
+pub(super) async fn evict_to_budget(
+	library_thumbs_dir: impl AsRef<Path>,
+	budget: u64,
+) -> Result<ThumbnailCacheEviction, ThumbnailerError> {
+	let mut entries = list_entries(library_thumbs_dir.as_ref()).await?;
+
+	let mut total = entries.iter().map(|entry| entry.size).sum::<u64>();
+	if total <= budget {
+		return Ok(ThumbnailCacheEviction::default());
+	}
+
+	let target = budget / 10 * 9;
+
+	entries.sort_unstable_by_key(|entry| entry.last_access);
+
+	let mut evicted_count = 0;
+	let mut freed_bytes = 0;
+
+	for CacheEntry { path, size, .. } in entries {
+		if total <= target {
+			break;
+		}
+
+		match fs::remove_file(&path).await {
+			Ok(()) => {
+				debug!("Evicted thumbnail from cache: {}", path.display());
+				total -= size;
+				freed_bytes += size;
+				evicted_count += 1;
+			}
+			Err(e) => error!(
+				"Failed to evict thumbnail from cache: {:#?}",
+				FileIOError::from((path, e))
+			),
+		}
+	}
+
+	Ok(ThumbnailCacheEviction {
+		evicted_count,
+		freed_bytes: freed_bytes.to_string(),
+	})
+}
+
+async fn list_entries(library_thumbs_dir: &Path) -> Result<Vec<CacheEntry>, ThumbnailerError> {
+	let mut entries = vec![];
+
+	let mut read_library_thumbs_dir = fs::read_dir(library_thumbs_dir)
+		.await
+		.map_err(|e| FileIOError::from((library_thumbs_dir, e)))?;
+
+	while let Some(shard_entry) = read_library_thumbs_dir
+		.next_entry()
+		.await
+		.map_err(|e| FileIOError::from((library_thumbs_dir, e)))?
+	{
+		let shard_path = shard_entry.path();
+		if !shard_entry
+			.file_type()
+			.await
+			.map_err(|e| FileIOError::from((&shard_path, e)))?
+			.is_dir()
+		{
+			continue;
+		}
+
+		let mut read_shard_dir = fs::read_dir(&shard_path)
+			.await
+			.map_err(|e| FileIOError::from((&shard_path, e)))?;
+
+		while let Some(thumb_entry) = read_shard_dir
+			.next_entry()
+			.await
+			.map_err(|e| FileIOError::from((&shard_path, e)))?
+		{
+			let path = thumb_entry.path();
+			if path.extension() != Some(WEBP_EXTENSION.as_ref())
+				&& path.extension() != Some(WAVEFORM_EXTENSION.as_ref())
+			{
+				continue;
+			}
+
+			let metadata = thumb_entry
+				.metadata()
+				.await
+				.map_err(|e| FileIOError::from((&path, e)))?;
+
+			// Where access times are working we take them too, it doesn't hurt
+			let last_access = metadata
+				.modified()
+				.into_iter()
+				.chain(metadata.accessed())
+				.max()
+				.unwrap_or(SystemTime::UNIX_EPOCH);
+
+			entries.push(CacheEntry {
+				path,
+				size: metadata.len(),
+				last_access,
+			});
+		}
+	}
+
+	Ok(entries)
+}
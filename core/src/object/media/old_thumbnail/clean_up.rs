@@ -1,5 +1,7 @@
 use crate::library::LibraryId;
 
+use sd_core_heavy_lifting::media_processor::WAVEFORM_EXTENSION;
+
 use sd_prisma::prisma::{file_path, PrismaClient};
 use sd_utils::error::FileIOError;
 
@@ -99,11 +101,15 @@ pub(super) async fn process_indexed_clean_up(
 					.exec()
 					.await?
 					.into_iter()
-					.map(|file_path| {
-						OsString::from(format!(
-							"{}.webp",
-							file_path.cas_id.expect("we filtered right")
-						))
+					.flat_map(|file_path| {
+						let cas_id = file_path.cas_id.expect("we filtered right");
+						// Videos also have their preview strips beside their thumbnails, and audio
+						// files their waveforms
+						[
+							OsString::from(format!("{cas_id}.webp")),
+							OsString::from(format!("{cas_id}.strip.webp")),
+							OsString::from(format!("{cas_id}.{WAVEFORM_EXTENSION}")),
+						]
 					})
 					.collect::<HashSet<_>>();
 
@@ -135,7 +141,8 @@ pub(super) async fn process_indexed_clean_up(
 							.map_err(|e| FileIOError::from((&shard_path, e)))?
 						{
 							let thumb_path = thumb_entry.path();
-							if thumb_path.extension() == Some(WEBP_EXTENSION.as_ref())
+							if (thumb_path.extension() == Some(WEBP_EXTENSION.as_ref())
+								|| thumb_path.extension() == Some(WAVEFORM_EXTENSION.as_ref()))
 								&& !existing_thumbs.contains(&thumb_entry.file_name())
 							{
 								to_remove.push(async move {
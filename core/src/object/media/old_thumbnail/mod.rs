@@ -19,6 +19,7 @@ use thiserror::Error;
 use tokio::task;
 use tracing::error;
 
+mod cache;
 mod clean_up;
 mod directory;
 pub mod old_actor;
@@ -28,6 +29,7 @@ mod shard;
 mod state;
 mod worker;
 
+pub use cache::{touch_thumbnail, ThumbnailCacheEviction, ThumbnailCacheUsage};
 pub use process::{BatchToProcess, GenerateThumbnailArgs};
 pub use shard::get_shard_hex;
 
@@ -132,7 +134,7 @@ pub(super) static THUMBNAILABLE_EXTENSIONS: Lazy<Vec<Extension>> = Lazy::new(||
 		.collect()
 });
 
-pub(super) static ALL_THUMBNAILABLE_EXTENSIONS: Lazy<Vec<Extension>> = Lazy::new(|| {
+pub(crate) static ALL_THUMBNAILABLE_EXTENSIONS: Lazy<Vec<Extension>> = Lazy::new(|| {
 	#[cfg(feature = "ffmpeg")]
 	return THUMBNAILABLE_EXTENSIONS
 		.iter()
@@ -192,10 +194,16 @@ pub const fn can_generate_thumbnail_for_video(video_extension: &VideoExtension)
 pub const fn can_generate_thumbnail_for_image(image_extension: &ImageExtension) -> bool {
 	use ImageExtension::*;
 
-	matches!(
-		image_extension,
-		Jpg | Jpeg | Png | Webp | Gif | Svg | Heic | Heics | Heif | Heifs | Avif | Bmp | Ico
-	)
+	match image_extension {
+		Jpg | Jpeg | Png | Webp | Gif | Svg | Bmp | Ico | Dng | Cr2 | Cr3 | Dcr | Nef | Arw
+		| Rw2 => true,
+		// These depend on external decoders, so we only claim them when they're compiled in,
+		// otherwise they would just pile up as errors on every media processor run
+		Heic | Heics | Heif | Heifs | Hif | Avci | Avcs => cfg!(feature = "heif"),
+		Avif => cfg!(any(feature = "heif", feature = "avif")),
+		Jxl => cfg!(feature = "jxl"),
+		_ => false,
+	}
 }
 
 pub const fn can_generate_thumbnail_for_document(document_extension: &DocumentExtension) -> bool {
@@ -24,11 +24,14 @@ use tracing::{error, trace};
 use uuid::Uuid;
 
 use super::{
+	cache::{cache_usage, evict_to_budget},
+	clean_up::process_indexed_clean_up,
 	directory::init_thumbnail_dir,
 	process::{generate_thumbnail, ThumbData},
 	state::RegisterReporter,
 	worker::{old_worker, WorkerChannels},
-	BatchToProcess, ThumbnailKind, ThumbnailerError, ONE_SEC, THUMBNAIL_CACHE_DIR_NAME,
+	BatchToProcess, ThumbnailCacheEviction, ThumbnailCacheUsage, ThumbnailKind, ThumbnailerError,
+	ONE_SEC, THUMBNAIL_CACHE_DIR_NAME,
 };
 
 static AVAILABLE_PARALLELISM: OnceCell<usize> = OnceCell::new();
@@ -273,6 +276,44 @@ impl OldThumbnailer {
 			.await
 	}
 
+	/// Where the thumbnails of the objects of a library are kept
+	pub fn indexed_thumbnails_directory(&self, library_id: LibraryId) -> PathBuf {
+		self.thumbnails_directory.join(library_id.to_string())
+	}
+
+	pub async fn indexed_cache_usage(
+		&self,
+		library_id: LibraryId,
+		budget: Option<u64>,
+	) -> Result<ThumbnailCacheUsage, ThumbnailerError> {
+		cache_usage(self.indexed_thumbnails_directory(library_id), budget).await
+	}
+
+	/// Removes stale thumbnails of a library right away and, if a `budget` is given, evicts the
+	/// least recently used ones until they fit in it
+	pub async fn clean_up_indexed_cache(
+		&self,
+		library_id: LibraryId,
+		db: Arc<PrismaClient>,
+		budget: Option<u64>,
+	) -> Result<ThumbnailCacheEviction, ThumbnailerError> {
+		process_indexed_clean_up(
+			Arc::clone(&self.thumbnails_directory),
+			vec![(library_id, db)],
+		)
+		.await;
+
+		if let Some(budget) = budget {
+			evict_to_budget(
+				self.thumbnails_directory.join(library_id.to_string()),
+				budget,
+			)
+			.await
+		} else {
+			Ok(ThumbnailCacheEviction::default())
+		}
+	}
+
 	#[inline]
 	pub async fn shutdown(&self) {
 		let (tx, rx) = oneshot::channel();
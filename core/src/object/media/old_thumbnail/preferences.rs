@@ -4,12 +4,16 @@ use specta::Type;
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, Type)]
 pub struct ThumbnailerPreferences {
 	background_processing_percentage: u8, // 0-100
+	/// Maximum size of each library's thumbnails directory, `None` means unbounded
+	#[serde(default)]
+	library_cache_budget_mib: Option<u32>,
 }
 
 impl Default for ThumbnailerPreferences {
 	fn default() -> Self {
 		Self {
 			background_processing_percentage: 50, // 50% of CPU cores available
+			library_cache_budget_mib: None,
 		}
 	}
 }
@@ -31,4 +35,18 @@ impl ThumbnailerPreferences {
 
 		self
 	}
+
+	pub fn library_cache_budget(&self) -> Option<u64> {
+		self.library_cache_budget_mib
+			.map(|budget_mib| u64::from(budget_mib) * 1024 * 1024)
+	}
+
+	pub fn set_library_cache_budget_mib(
+		&mut self,
+		library_cache_budget_mib: Option<u32>,
+	) -> &mut Self {
+		self.library_cache_budget_mib = library_cache_budget_mib;
+
+		self
+	}
 }
@@ -18,6 +18,7 @@ use tokio_stream::{
 use tracing::{debug, error, trace};
 
 use super::{
+	cache::evict_to_budget,
 	clean_up::{process_ephemeral_clean_up, process_indexed_clean_up},
 	old_actor::DatabaseMessage,
 	preferences::ThumbnailerPreferences,
@@ -163,13 +164,41 @@ pub(super) async fn old_worker(
 			StreamMessage::RemovalTick => {
 				// For any of them we process a clean up if a time since the last one already passed
 				if !databases.is_empty() {
-					spawn(process_indexed_clean_up(
-						thumbnails_directory.clone(),
-						databases
+					let libraries_ids_and_databases = databases
+						.iter()
+						.map(|(id, db)| (*id, Arc::clone(db)))
+						.collect::<Vec<_>>();
+					let library_cache_budget = thumbnailer_preferences.library_cache_budget();
+					let thumbnails_directory = thumbnails_directory.clone();
+
+					spawn(async move {
+						let libraries_ids = libraries_ids_and_databases
 							.iter()
-							.map(|(id, db)| (*id, Arc::clone(db)))
-							.collect::<Vec<_>>(),
-					));
+							.map(|(id, _)| *id)
+							.collect::<Vec<_>>();
+
+						// Stale thumbnails go first, so we don't evict used ones in their place
+						process_indexed_clean_up(
+							Arc::clone(&thumbnails_directory),
+							libraries_ids_and_databases,
+						)
+						.await;
+
+						if let Some(budget) = library_cache_budget {
+							for library_id in libraries_ids {
+								if let Err(e) = evict_to_budget(
+									thumbnails_directory.join(library_id.to_string()),
+									budget,
+								)
+								.await
+								{
+									error!(
+										"Failed to evict thumbnails over the cache budget: {e:#?}"
+									);
+								}
+							}
+						}
+					});
 				}
 
 				if !ephemeral_file_names.is_empty() {
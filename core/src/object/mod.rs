@@ -4,9 +4,13 @@ use serde::{Deserialize, Serialize};
 use specta::Type;
 
 pub mod cas;
+pub mod custom_field;
 pub mod fs;
 pub mod media;
+pub mod note;
 pub mod old_file_identifier;
+pub mod old_kind_reidentifier;
+pub mod old_mtp_importer;
 pub mod old_orphan_remover;
 pub mod tag;
 pub mod validation;
@@ -0,0 +1,164 @@
+/// Length of a markdown note as it reads once rendered, so link targets, emphasis markers and the
+/// like don't eat into the limit. Text inside code blocks and code spans counts as is.
+pub fn visible_len(markdown: &str) -> usize {
+	let mut in_code_block = false;
+
+	markdown
+		.lines()
+		.map(|line| {
+			let trimmed = line.trim_start();
+
+			if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+				in_code_block = !in_code_block;
+				return 0;
+			}
+
+			if in_code_block {
+				return line.chars().count();
+			}
+
+			inline_len(strip_block_markers(trimmed))
+		})
+		.sum()
+}
+
+/// Headings, quotes, list items and task boxes
+fn strip_block_markers(mut line: &str) -> &str {
+	loop {
+		let stripped = line
+			.strip_prefix('>')
+			.or_else(|| {
+				let hashes = line.len() - line.trim_start_matches('#').len();
+				(1..=6)
+					.contains(&hashes)
+					.then(|| line[hashes..].strip_prefix(' '))
+					.flatten()
+			})
+			.or_else(|| {
+				["- ", "* ", "+ "]
+					.into_iter()
+					.find_map(|marker| line.strip_prefix(marker))
+			})
+			.or_else(|| {
+				let digits =
+					line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+				(digits > 0)
+					.then(|| {
+						line[digits..]
+							.strip_prefix(". ")
+							.or_else(|| line[digits..].strip_prefix(") "))
+					})
+					.flatten()
+			})
+			.or_else(|| {
+				["[ ] ", "[x] ", "[X] "]
+					.into_iter()
+					.find_map(|marker| line.strip_prefix(marker))
+			});
+
+		match stripped {
+			Some(stripped) => line = stripped.trim_start(),
+			None => return line,
+		}
+	}
+}
+
+fn inline_len(line: &str) -> usize {
+	let chars = line.chars().collect::<Vec<_>>();
+	let mut len = 0;
+	let mut i = 0;
+
+	while i < chars.len() {
+		match chars[i] {
+			'\\' if i + 1 < chars.len() => {
+				len += 1;
+				i += 2;
+			}
+			'`' => {
+				let ticks = chars[i..].iter().take_while(|&&c| c == '`').count();
+				let code = &chars[i + ticks..];
+
+				// The span ends at the same number of backticks, or it's just backticks
+				match (0..code.len())
+					.find(|&start| code[start..].iter().take_while(|&&c| c == '`').count() == ticks)
+				{
+					Some(end) => {
+						len += end;
+						i += ticks * 2 + end;
+					}
+					None => {
+						len += ticks;
+						i += ticks;
+					}
+				}
+			}
+			'*' | '_' | '~' => i += 1,
+			'!' if chars.get(i + 1) == Some(&'[') => i += 1,
+			'[' => match link_text_end(&chars[i..]) {
+				// Only the text of the link counts, the target is skipped
+				Some((text_end, link_end)) => {
+					len += inline_len(&chars[i + 1..i + text_end].iter().collect::<String>());
+					i += link_end;
+				}
+				None => {
+					len += 1;
+					i += 1;
+				}
+			},
+			_ => {
+				len += 1;
+				i += 1;
+			}
+		}
+	}
+
+	len
+}
+
+/// For `[text](target)` at the start of `chars`, where the text ends and where the whole link does
+fn link_text_end(chars: &[char]) -> Option<(usize, usize)> {
+	let text_end = chars.iter().position(|&c| c == ']')?;
+
+	if chars.get(text_end + 1) != Some(&'(') {
+		return None;
+	}
+
+	let target_end = chars[text_end + 1..].iter().position(|&c| c == ')')?;
+
+	Some((text_end, text_end + 1 + target_end + 1))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn plain_text_counts_every_char() {
+		assert_eq!(visible_len("hello world"), 11);
+		assert_eq!(visible_len("olá, 世界"), 7);
+	}
+
+	#[test]
+	fn syntax_doesnt_count() {
+		assert_eq!(visible_len("# Title"), 5);
+		assert_eq!(visible_len("**bold** and _it_"), 11);
+		assert_eq!(visible_len("- [x] done"), 4);
+		assert_eq!(visible_len("> 1. quoted"), 6);
+		assert_eq!(visible_len("~~gone~~"), 4);
+	}
+
+	#[test]
+	fn only_link_text_counts() {
+		assert_eq!(visible_len("[docs](https://spacedrive.com/docs)"), 4);
+		assert_eq!(visible_len("![a cat](cat.png)"), 5);
+		assert_eq!(visible_len("[not a link] (here)"), 19);
+	}
+
+	#[test]
+	fn code_counts_as_is() {
+		assert_eq!(visible_len("`**x**`"), 5);
+		assert_eq!(visible_len("```rust\nlet _x = 1;\n```"), 11);
+		assert_eq!(visible_len("a ` b"), 5);
+		assert_eq!(visible_len(r"\*"), 1);
+	}
+}
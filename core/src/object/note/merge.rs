@@ -0,0 +1,270 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, FixedOffset};
+
+/// A version of a note, as far as merging it with others goes
+#[derive(Debug, Clone)]
+pub struct Version {
+	pub pub_id: Vec<u8>,
+	pub parents: Vec<Vec<u8>>,
+	pub content: Option<String>,
+	pub date_created: Option<DateTime<FixedOffset>>,
+}
+
+/// Versions no other version was written over, oldest first. There's more than one when the note
+/// was edited on devices that hadn't synced each other's edits yet.
+pub fn heads(versions: &[Version]) -> Vec<&Version> {
+	let overwritten = versions
+		.iter()
+		.flat_map(|version| &version.parents)
+		.collect::<HashSet<_>>();
+
+	let mut heads = versions
+		.iter()
+		.filter(|version| !overwritten.contains(&version.pub_id))
+		.collect::<Vec<_>>();
+
+	// Every device must merge the heads in the same order to read the same note
+	heads.sort_by(|a, b| (a.date_created, &a.pub_id).cmp(&(b.date_created, &b.pub_id)));
+
+	heads
+}
+
+/// The note as it reads after merging all of its heads, each one against the latest version they
+/// were all written over
+pub fn resolve(versions: &[Version]) -> Option<String> {
+	let heads = heads(versions);
+	let (first, rest) = heads.split_first()?;
+
+	let by_pub_id = versions
+		.iter()
+		.map(|version| (&version.pub_id, version))
+		.collect::<HashMap<_, _>>();
+
+	let mut merged = content(first).to_string();
+	let mut common = ancestors(first, &by_pub_id);
+
+	for head in rest {
+		let head_ancestors = ancestors(head, &by_pub_id);
+		common.retain(|pub_id| head_ancestors.contains(pub_id));
+
+		let base = common
+			.iter()
+			.filter_map(|pub_id| by_pub_id.get(pub_id))
+			.max_by(|a, b| (a.date_created, &a.pub_id).cmp(&(b.date_created, &b.pub_id)))
+			.map_or("", |base| content(base));
+
+		merged = merge3(base, &merged, content(head));
+	}
+
+	(!merged.is_empty()).then_some(merged)
+}
+
+/// `version` and every version it was written over, directly or not
+fn ancestors<'a>(
+	version: &'a Version,
+	by_pub_id: &HashMap<&'a Vec<u8>, &'a Version>,
+) -> HashSet<&'a Vec<u8>> {
+	let mut found = HashSet::from([&version.pub_id]);
+	let mut pending = vec![version];
+
+	while let Some(version) = pending.pop() {
+		for parent in &version.parents {
+			// Parents may not have synced yet, they're just skipped until they do
+			if let Some(parent) = by_pub_id.get(parent) {
+				if found.insert(&parent.pub_id) {
+					pending.push(parent);
+				}
+			}
+		}
+	}
+
+	found
+}
+
+fn content(version: &Version) -> &str {
+	version.content.as_deref().unwrap_or_default()
+}
+
+/// Line based three-way merge of two edits of `base`. Lines changed in only one of them are taken
+/// from it, and where both changed the same lines differently both are kept, `ours` first, so
+/// nothing written on either device is lost.
+pub fn merge3(base: &str, ours: &str, theirs: &str) -> String {
+	let base = base.split_inclusive('\n').collect::<Vec<_>>();
+	let ours = ours.split_inclusive('\n').collect::<Vec<_>>();
+	let theirs = theirs.split_inclusive('\n').collect::<Vec<_>>();
+
+	let in_ours = matches(&base, &ours);
+	let in_theirs = matches(&base, &theirs);
+
+	let mut merged = Vec::with_capacity(ours.len().max(theirs.len()));
+	let (mut b, mut o, mut t) = (0, 0, 0);
+
+	loop {
+		// Lines left untouched by both
+		while b < base.len() && in_ours[b] == Some(o) && in_theirs[b] == Some(t) {
+			merged.push(base[b]);
+			b += 1;
+			o += 1;
+			t += 1;
+		}
+
+		// The next line both kept bounds the lines that changed since the previous one
+		let stable = (b..base.len()).find(|&i| in_ours[i].is_some() && in_theirs[i].is_some());
+		let (base_end, ours_end, theirs_end) = match stable {
+			Some(i) => (
+				i,
+				in_ours[i].expect("stable lines are in both"),
+				in_theirs[i].expect("stable lines are in both"),
+			),
+			None => (base.len(), ours.len(), theirs.len()),
+		};
+
+		let base_chunk = &base[b..base_end];
+		let ours_chunk = &ours[o..ours_end];
+		let theirs_chunk = &theirs[t..theirs_end];
+
+		if ours_chunk == base_chunk || ours_chunk == theirs_chunk {
+			merged.extend_from_slice(theirs_chunk);
+		} else if theirs_chunk == base_chunk {
+			merged.extend_from_slice(ours_chunk);
+		} else {
+			merged.extend_from_slice(ours_chunk);
+			// A last line without a newline would be glued to the first line of theirs
+			if ours_chunk.last().is_some_and(|line| !line.ends_with('\n')) {
+				merged.push("\n");
+			}
+			merged.extend_from_slice(theirs_chunk);
+		}
+
+		if stable.is_none() {
+			break;
+		}
+
+		(b, o, t) = (base_end, ours_end, theirs_end);
+	}
+
+	merged.concat()
+}
+
+/// For each line of `base`, where it is in `other` if it was kept, after a longest common
+/// subsequence of both
+fn matches(base: &[&str], other: &[&str]) -> Vec<Option<usize>> {
+	let (n, m) = (base.len(), other.len());
+
+	// lengths[i][j] is the length of the longest common subsequence of base[i..] and other[j..]
+	let mut lengths = vec![vec![0_usize; m + 1]; n + 1];
+	for i in (0..n).rev() {
+		for j in (0..m).rev() {
+			lengths[i][j] = if base[i] == other[j] {
+				lengths[i + 1][j + 1] + 1
+			} else {
+				lengths[i + 1][j].max(lengths[i][j + 1])
+			};
+		}
+	}
+
+	let mut matches = vec![None; n];
+	let (mut i, mut j) = (0, 0);
+	while i < n && j < m {
+		if base[i] == other[j] {
+			matches[i] = Some(j);
+			i += 1;
+			j += 1;
+		} else if lengths[i + 1][j] >= lengths[i][j + 1] {
+			i += 1;
+		} else {
+			j += 1;
+		}
+	}
+
+	matches
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use chrono::TimeZone;
+
+	fn version(id: u8, parents: &[u8], content: &str) -> Version {
+		Version {
+			pub_id: vec![id],
+			parents: parents.iter().map(|&parent| vec![parent]).collect(),
+			content: Some(content.to_string()),
+			date_created: Some(
+				FixedOffset::east_opt(0)
+					.expect("valid offset")
+					.timestamp_opt(i64::from(id), 0)
+					.unwrap(),
+			),
+		}
+	}
+
+	#[test]
+	fn edits_of_different_lines_are_both_kept() {
+		assert_eq!(merge3("a\nb\nc\n", "A\nb\nc\n", "a\nb\nC\n"), "A\nb\nC\n");
+		assert_eq!(
+			merge3("a\nb\n", "a\nb\nours\n", "theirs\na\nb\n"),
+			"theirs\na\nb\nours\n"
+		);
+	}
+
+	#[test]
+	fn same_edit_is_taken_once() {
+		assert_eq!(merge3("a\nb\n", "a\nB\n", "a\nB\n"), "a\nB\n");
+		assert_eq!(merge3("a\nb\n", "a\n", "a\n"), "a\n");
+	}
+
+	#[test]
+	fn conflicting_edits_keep_both_sides() {
+		assert_eq!(
+			merge3("a\nb\nc\n", "a\nours\nc\n", "a\ntheirs\nc\n"),
+			"a\nours\ntheirs\nc\n"
+		);
+		assert_eq!(merge3("draft", "ours", "theirs"), "ours\ntheirs");
+		assert_eq!(merge3("", "ours\n", "theirs\n"), "ours\ntheirs\n");
+	}
+
+	#[test]
+	fn deletions_merge_with_edits_elsewhere() {
+		assert_eq!(
+			merge3("a\nb\nc\nd\n", "a\nc\nd\n", "a\nb\nc\nD\n"),
+			"a\nc\nD\n"
+		);
+	}
+
+	#[test]
+	fn single_head_is_the_note() {
+		let versions = [version(1, &[], "one"), version(2, &[1], "two")];
+
+		assert_eq!(heads(&versions).len(), 1);
+		assert_eq!(resolve(&versions).as_deref(), Some("two"));
+		assert_eq!(resolve(&[]), None);
+	}
+
+	#[test]
+	fn concurrent_heads_are_merged_over_their_common_version() {
+		let versions = [
+			version(1, &[], "title\nbody\n"),
+			version(2, &[1], "Title\nbody\n"),
+			version(3, &[1], "title\nbody\nmore\n"),
+		];
+
+		assert_eq!(heads(&versions).len(), 2);
+		assert_eq!(resolve(&versions).as_deref(), Some("Title\nbody\nmore\n"));
+
+		// Writing over both heads leaves a single one again
+		let mut versions = versions.to_vec();
+		versions.push(version(4, &[2, 3], "done"));
+
+		assert_eq!(resolve(&versions).as_deref(), Some("done"));
+	}
+
+	#[test]
+	fn versions_with_unsynced_parents_are_heads() {
+		let versions = [version(2, &[1], "two"), version(3, &[1], "three")];
+
+		assert_eq!(resolve(&versions).as_deref(), Some("two\nthree"));
+	}
+}
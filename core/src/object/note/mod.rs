@@ -0,0 +1,204 @@
+use crate::library::Library;
+
+use sd_prisma::{
+	prisma::{note_version, object, PrismaClient, SortOrder},
+	prisma_sync,
+};
+use sd_sync::OperationFactory;
+use sd_utils::{msgpack, uuid_to_bytes};
+
+use chrono::{DateTime, FixedOffset, Utc};
+use uuid::Uuid;
+
+pub mod markdown;
+pub mod merge;
+
+/// How long a note can read, see [`markdown::visible_len`]
+pub const MAX_NOTE_LEN: usize = 10_000;
+
+/// Size of the markdown itself, so syntax that doesn't count towards [`MAX_NOTE_LEN`] stays bounded
+pub const MAX_NOTE_BYTES: usize = 256 * 1024;
+
+#[derive(thiserror::Error, Debug)]
+pub enum NoteError {
+	#[error("note is {0} characters long, notes can be {MAX_NOTE_LEN} characters long at most")]
+	TooLong(usize),
+	#[error("note is {0} bytes long, notes can be {MAX_NOTE_BYTES} bytes long at most")]
+	TooLarge(usize),
+	#[error("object not found: <id='{0}'>")]
+	ObjectNotFound(object::id::Type),
+	#[error("database error: {0}")]
+	Database(#[from] prisma_client_rust::QueryError),
+	#[error("failed to encode the parents of a note version: {0}")]
+	EncodeParents(#[from] rmp_serde::encode::Error),
+}
+
+impl From<NoteError> for rspc::Error {
+	fn from(e: NoteError) -> Self {
+		match e {
+			NoteError::TooLong(_) | NoteError::TooLarge(_) => {
+				Self::with_cause(rspc::ErrorCode::BadRequest, e.to_string(), e)
+			}
+			NoteError::ObjectNotFound(_) => {
+				Self::with_cause(rspc::ErrorCode::NotFound, e.to_string(), e)
+			}
+			_ => Self::with_cause(rspc::ErrorCode::InternalServerError, e.to_string(), e),
+		}
+	}
+}
+
+pub fn check_length(content: &str) -> Result<(), NoteError> {
+	if content.len() > MAX_NOTE_BYTES {
+		return Err(NoteError::TooLarge(content.len()));
+	}
+
+	match markdown::visible_len(content) {
+		len if len > MAX_NOTE_LEN => Err(NoteError::TooLong(len)),
+		_ => Ok(()),
+	}
+}
+
+/// Every version of the note of an object, oldest first
+pub async fn versions(
+	db: &PrismaClient,
+	object_id: object::id::Type,
+) -> Result<Vec<note_version::Data>, prisma_client_rust::QueryError> {
+	db.note_version()
+		.find_many(vec![note_version::object_id::equals(Some(object_id))])
+		.order_by(note_version::date_created::order(SortOrder::Asc))
+		.exec()
+		.await
+}
+
+pub fn parents(version: &note_version::Data) -> Vec<Vec<u8>> {
+	version
+		.parents
+		.as_deref()
+		.and_then(|parents| rmp_serde::from_slice(parents).ok())
+		.unwrap_or_default()
+}
+
+pub fn to_merge_versions(versions: &[note_version::Data]) -> Vec<merge::Version> {
+	versions
+		.iter()
+		.map(|version| merge::Version {
+			pub_id: version.pub_id.clone(),
+			parents: parents(version),
+			content: version.content.clone(),
+			date_created: version.date_created,
+		})
+		.collect()
+}
+
+/// Records an edit of the note of an object as a new version written over `parents`, the current
+/// heads if not given, and returns the note as it reads after it.
+///
+/// Edits made on another device over the same versions, before this one synced them, are new heads
+/// too, which get merged with this one instead of one of them replacing the other.
+pub async fn save(
+	library: &Library,
+	object_id: object::id::Type,
+	content: Option<String>,
+	parents: Option<Vec<Vec<u8>>>,
+) -> Result<Option<String>, NoteError> {
+	let Library { db, sync, .. } = library;
+
+	let content = content.filter(|content| !content.is_empty());
+	if let Some(content) = &content {
+		check_length(content)?;
+	}
+
+	let object = db
+		.object()
+		.find_unique(object::id::equals(object_id))
+		.select(object::select!({ pub_id note }))
+		.exec()
+		.await?
+		.ok_or(NoteError::ObjectNotFound(object_id))?;
+
+	let mut versions = to_merge_versions(&versions(db, object_id).await?);
+
+	let parents = match parents {
+		// Versions of other objects or that were never synced here can't be merged over
+		Some(parents) => parents
+			.into_iter()
+			.filter(|parent| versions.iter().any(|version| &version.pub_id == parent))
+			.collect(),
+		None => merge::heads(&versions)
+			.into_iter()
+			.map(|head| head.pub_id.clone())
+			.collect::<Vec<_>>(),
+	};
+
+	let pub_id = uuid_to_bytes(Uuid::new_v4());
+	let instance_pub_id = uuid_to_bytes(library.instance_uuid);
+	let date_created: DateTime<FixedOffset> = Utc::now().into();
+	let encoded_parents = rmp_serde::to_vec(&parents)?;
+
+	versions.push(merge::Version {
+		pub_id: pub_id.clone(),
+		parents,
+		content: content.clone(),
+		date_created: Some(date_created),
+	});
+
+	let note = merge::resolve(&versions);
+
+	sync.write_ops(
+		db,
+		(
+			sync.shared_create(
+				prisma_sync::note_version::SyncId {
+					pub_id: pub_id.clone(),
+				},
+				[
+					(note_version::content::NAME, msgpack!(content)),
+					(note_version::parents::NAME, msgpack!(encoded_parents)),
+					(
+						note_version::instance_pub_id::NAME,
+						msgpack!(instance_pub_id),
+					),
+					(note_version::date_created::NAME, msgpack!(date_created)),
+					(
+						note_version::object::NAME,
+						msgpack!(prisma_sync::object::SyncId {
+							pub_id: object.pub_id.clone()
+						}),
+					),
+				],
+			),
+			db.note_version().create(
+				pub_id,
+				vec![
+					note_version::content::set(content),
+					note_version::parents::set(Some(encoded_parents)),
+					note_version::instance_pub_id::set(Some(instance_pub_id)),
+					note_version::date_created::set(Some(date_created)),
+					note_version::object::connect(object::id::equals(object_id)),
+				],
+			),
+		),
+	)
+	.await?;
+
+	// Kept in the object too, for search and older clients
+	if note != object.note {
+		sync.write_op(
+			db,
+			sync.shared_update(
+				prisma_sync::object::SyncId {
+					pub_id: object.pub_id,
+				},
+				object::note::NAME,
+				msgpack!(&note),
+			),
+			db.object().update(
+				object::id::equals(object_id),
+				vec![object::note::set(note.clone())],
+			),
+		)
+		.await?;
+	}
+
+	Ok(note)
+}
@@ -1,25 +1,52 @@
-use crate::{library::Library, object::cas::generate_cas_id, old_job::JobError};
+use crate::{
+	context::NodeContext,
+	library::Library,
+	location::{
+		cloud_metadata::{CloudMetadataError, CloudMetadataLocationConfig, ProviderHashKind},
+		mtp::{MtpError, MtpLocation},
+		s3::{object_key, S3Error, S3Location},
+		webdav::{WebDavError, WebDavLocation},
+	},
+	old_job::JobError,
+	Node,
+};
 
 use sd_core_file_path_helper::{FilePathError, IsolatedFilePathData};
+use sd_core_heavy_lifting::{
+	file_identifier::{
+		self, generate_cas_id, is_placeholder, resolve_kind, statistics, CasIdAlgorithm,
+		FileMetadataOptions, Identified, IdentifierSettings, OnDemandFilePolicy,
+	},
+	job_system::failures,
+	tag_rules, JobName, NonCriticalError,
+};
 use sd_core_prisma_helpers::{file_path_for_file_identifier, object_for_file_identifier};
 
-use sd_file_ext::{extensions::Extension, kind::ObjectKind};
+use sd_file_ext::{
+	custom_kind::KindRegistry, extensions::Extension, kind::ObjectKind, magic::ExtensionPossibility,
+};
 use sd_prisma::{
-	prisma::{file_path, location, object, PrismaClient},
+	prisma::{file_path, location, object, provider_hash, PrismaClient},
 	prisma_sync,
 };
 use sd_sync::{CRDTOperation, OperationFactory};
-use sd_utils::{db::maybe_missing, error::FileIOError, msgpack, uuid_to_bytes};
+use sd_utils::{
+	db::{maybe_missing, size_in_bytes_from_db},
+	error::FileIOError,
+	msgpack, uuid_to_bytes,
+};
 
 use std::{
 	collections::{HashMap, HashSet},
 	fmt::Debug,
 	path::Path,
+	sync::Arc,
 };
 
 use futures::future::join_all;
+use int_enum::IntEnum;
 use tokio::fs;
-use tracing::{error, trace};
+use tracing::{error, trace, warn};
 use uuid::Uuid;
 
 pub mod old_file_identifier_job;
@@ -40,6 +67,18 @@ pub enum FileIdentifierJobError {
 	FilePathError(#[from] FilePathError),
 	#[error("database error: {0}")]
 	Database(#[from] prisma_client_rust::QueryError),
+	#[error(transparent)]
+	S3(#[from] S3Error),
+	#[error(transparent)]
+	WebDav(#[from] WebDavError),
+	#[error(transparent)]
+	CloudMetadata(#[from] CloudMetadataError),
+	#[error(transparent)]
+	Mtp(#[from] MtpError),
+	#[error(transparent)]
+	TagRules(#[from] tag_rules::Error),
+	#[error(transparent)]
+	Identifier(#[from] sd_core_heavy_lifting::Error),
 }
 
 #[derive(Debug, Clone)]
@@ -47,13 +86,18 @@ pub struct FileMetadata {
 	pub cas_id: Option<String>,
 	pub kind: ObjectKind,
 	pub fs_metadata: std::fs::Metadata,
+	/// Cloud placeholder left unread, see [`OnDemandFilePolicy`]
+	pub remote_only: bool,
 }
 
 impl FileMetadata {
-	/// Assembles `create_unchecked` params for a given file path
+	/// Assembles `create_unchecked` params for a given file path, hashing it with the `cas_id`
+	/// algorithm and deep hash threshold of the library's `options` and sniffing its kind within
+	/// their read budget
 	pub async fn new(
 		location_path: impl AsRef<Path>,
 		iso_file_path: &IsolatedFilePathData<'_>, // TODO: use dedicated CreateUnchecked type
+		options: &FileMetadataOptions,
 	) -> Result<FileMetadata, FileIOError> {
 		let path = location_path.as_ref().join(iso_file_path);
 
@@ -66,17 +110,46 @@ impl FileMetadata {
 			"We can't generate cas_id for directories"
 		);
 
-		// derive Object kind
-		let kind = Extension::resolve_conflicting(&path, false)
-			.await
-			.map(Into::into)
-			.unwrap_or(ObjectKind::Unknown);
+		if options.on_demand_policy == OnDemandFilePolicy::RecordAsRemote
+			&& is_placeholder(&fs_metadata)
+		{
+			// Reading its content would download it, so its kind comes from its extension only
+			let kind = match Extension::from_str(iso_file_path.extension()) {
+				Some(ExtensionPossibility::Known(extension)) => extension.into(),
+				Some(ExtensionPossibility::Conflicts(_)) | None => ObjectKind::Unknown,
+			};
+
+			trace!("Left cloud placeholder unread: {path:?} {kind:?}");
+
+			return Ok(FileMetadata {
+				cas_id: None,
+				kind,
+				fs_metadata,
+				remote_only: true,
+			});
+		}
+
+		// derive Object kind, sniffing the content of files whose extension doesn't tell it
+		let (kind, _) = resolve_kind(
+			&path,
+			fs_metadata.len(),
+			options.sniff_read_budget,
+			&KindRegistry::default(),
+		)
+		.await;
 
 		let cas_id = if fs_metadata.len() != 0 {
-			generate_cas_id(&path, fs_metadata.len())
-				.await
-				.map(Some)
-				.map_err(|e| FileIOError::from((&path, e)))?
+			generate_cas_id(
+				&path,
+				fs_metadata.len(),
+				options
+					.cas_id_algorithm
+					.for_file_size(fs_metadata.len(), options.deep_hash_threshold),
+				&options.io_throttle,
+			)
+			.await
+			.map(Some)
+			.map_err(|e| FileIOError::from((&path, e)))?
 		} else {
 			// We can't do shit with empty files
 			None
@@ -88,60 +161,78 @@ impl FileMetadata {
 			cas_id,
 			kind,
 			fs_metadata,
+			remote_only: false,
 		})
 	}
 }
 
+/// What identifying a file path comes down to, whether it's a local file or an object in a bucket
+#[derive(Debug)]
+struct FileIdentity {
+	cas_id: Option<String>,
+	kind: ObjectKind,
+	/// Identified only from its provider's metadata, its content isn't available locally
+	remote_only: bool,
+}
+
+impl From<FileMetadata> for FileIdentity {
+	fn from(
+		FileMetadata {
+			cas_id,
+			kind,
+			remote_only,
+			..
+		}: FileMetadata,
+	) -> Self {
+		Self {
+			cas_id,
+			kind,
+			remote_only,
+		}
+	}
+}
+
 async fn identifier_job_step(
-	Library { db, sync, .. }: &Library,
+	node: &Arc<Node>,
+	library: &Arc<Library>,
 	location: &location::Data,
 	file_paths: &[file_path_for_file_identifier::Data],
 ) -> Result<(usize, usize), JobError> {
+	let Library { db, sync, .. } = &**library;
+
 	let location_path = maybe_missing(&location.path, "location.path").map(Path::new)?;
 
-	let file_paths_metadatas = join_all(
-		file_paths
-			.iter()
-			.filter_map(|file_path| {
-				IsolatedFilePathData::try_from((location.id, file_path))
-					.map(|iso_file_path| (iso_file_path, file_path))
-					.map_err(|e| error!("Failed to extract isolated file path data: {e:#?}"))
-					.ok()
-			})
-			.map(|(iso_file_path, file_path)| async move {
-				FileMetadata::new(&location_path, &iso_file_path)
-					.await
-					.map(|metadata| {
-						(
-							// SAFETY: This should never happen
-							Uuid::from_slice(&file_path.pub_id)
-								.expect("file_path.pub_id is invalid!"),
-							(metadata, file_path),
-						)
-					})
-					.map_err(|e| {
-						#[cfg(target_os = "windows")]
-						{
-							// Handle case where file is on-demand (NTFS only)
-							if e.source.raw_os_error().map_or(false, |code| code == 362) {
-								error!("Failed to extract metadata from on-demand file: {e:#?}");
-							} else {
-								error!("Failed to extract file metadata: {e:#?}")
-							}
-						}
+	let mut options = library.config().await.file_metadata_options();
+	IdentifierSettings::of_location(location).apply(&mut options);
 
-						#[cfg(not(target_os = "windows"))]
-						{
-							error!("Failed to extract file metadata: {e:#?}");
-						}
-					})
-					.ok()
-			}),
-	)
-	.await
-	.into_iter()
-	.flatten()
-	.collect::<HashMap<_, _>>();
+	let file_paths_metadatas = if let Some(s3) =
+		S3Location::for_location(location.id, location.s3_config.as_deref())
+			.await
+			.map_err(FileIdentifierJobError::from)?
+	{
+		identify_s3_objects(&s3, location.id, location_path, file_paths, &options).await
+	} else if let Some(webdav) =
+		WebDavLocation::for_location(location.id, location.webdav_config.as_deref())
+			.map_err(FileIdentifierJobError::from)?
+	{
+		identify_webdav_files(&webdav, location.id, location_path, file_paths, &options).await
+	} else if CloudMetadataLocationConfig::from_db(location.id, location.cloud_config.as_deref())
+		.map_err(FileIdentifierJobError::from)?
+		.is_some()
+	{
+		identify_cloud_metadata_files(db, location.id, location_path, file_paths, &options)
+			.await
+			.map_err(FileIdentifierJobError::from)?
+	} else if let Some(mtp) = MtpLocation::for_location(location.id, location.mtp_config.as_deref())
+		.await
+		.map_err(FileIdentifierJobError::from)?
+	{
+		identify_mtp_files(&mtp, location.id, location_path, file_paths, &options).await
+	} else {
+		return identify_local_files(node, library, location, file_paths, options).await;
+	};
+
+	update_persisted_failures(db, location.id, file_paths, &file_paths_metadatas).await;
 
 	let unique_cas_ids = file_paths_metadatas
 		.values()
@@ -151,29 +242,94 @@ async fn identifier_job_step(
 		.collect();
 
 	// Assign cas_id to each file path
-	sync.write_ops(
-		db,
-		file_paths_metadatas
+	sync.write_ops(db, {
+		let (sync_ops, db_ops): (Vec<_>, Vec<_>) = file_paths_metadatas
 			.iter()
 			.map(|(pub_id, (metadata, _))| {
+				let (sync_params, db_params): (Vec<_>, Vec<_>) = [
+					Some((
+						(file_path::cas_id::NAME, msgpack!(&metadata.cas_id)),
+						file_path::cas_id::set(metadata.cas_id.clone()),
+					)),
+					metadata.remote_only.then(|| {
+						(
+							(file_path::remote_only::NAME, msgpack!(true)),
+							file_path::remote_only::set(Some(true)),
+						)
+					}),
+				]
+				.into_iter()
+				.flatten()
+				.unzip();
+
 				(
-					sync.shared_update(
-						prisma_sync::file_path::SyncId {
-							pub_id: sd_utils::uuid_to_bytes(*pub_id),
-						},
-						file_path::cas_id::NAME,
-						msgpack!(&metadata.cas_id),
-					),
+					sync_params
+						.into_iter()
+						.map(|(field, value)| {
+							sync.shared_update(
+								prisma_sync::file_path::SyncId {
+									pub_id: sd_utils::uuid_to_bytes(*pub_id),
+								},
+								field,
+								value,
+							)
+						})
+						.collect::<Vec<_>>(),
 					db.file_path().update(
 						file_path::pub_id::equals(sd_utils::uuid_to_bytes(*pub_id)),
-						vec![file_path::cas_id::set(metadata.cas_id.clone())],
+						db_params,
 					),
 				)
 			})
-			.unzip::<_, _, _, Vec<_>>(),
-	)
+			.unzip();
+
+		(sync_ops.into_iter().flatten().collect::<Vec<_>>(), db_ops)
+	})
 	.await?;
 
+	let file_path_ids = file_paths
+		.iter()
+		.map(|file_path| file_path.id)
+		.collect::<Vec<_>>();
+
+	// Statistics are taken before and after in the same transaction, so they're counted once
+	let ids = &file_path_ids;
+	let (total_created, total_linked) = db
+		._transaction()
+		.with_timeout(30 * 1000)
+		.run(|db| async move {
+			let before =
+				statistics::snapshot(vec![file_path::id::in_vec(ids.clone())], &db).await?;
+
+			let counts =
+				link_file_paths_to_objects(&db, sync, unique_cas_ids, file_paths_metadatas).await?;
+
+			statistics::snapshot(vec![file_path::id::in_vec(ids.clone())], &db)
+				.await?
+				.since(before)
+				.commit(&db)
+				.await?;
+
+			Ok::<_, FileIdentifierJobError>(counts)
+		})
+		.await?;
+
+	// Objects exist now for every file path of the step, both new and linked ones
+	tag_rules::apply(&file_path_ids, db, sync)
+		.await
+		.map_err(FileIdentifierJobError::from)?;
+
+	Ok((total_created, total_linked))
+}
+
+/// Links file paths to the objects with the same `cas_id`, creating the missing ones. Returns how
+/// many objects were created and how many file paths were linked to existing ones.
+async fn link_file_paths_to_objects(
+	db: &PrismaClient,
+	sync: &crate::sync::Manager,
+	unique_cas_ids: Vec<String>,
+	file_paths_metadatas: HashMap<Uuid, (FileIdentity, &file_path_for_file_identifier::Data)>,
+) -> Result<(usize, usize), FileIdentifierJobError> {
 	// Retrieves objects that are already connected to file paths with the same id
 	let existing_objects = db
 		.object()
@@ -242,7 +398,7 @@ async fn identifier_job_step(
 	// extract objects that don't already exist in the database
 	let file_paths_requiring_new_object = file_paths_metadatas
 		.into_iter()
-		.filter(|(_, (FileMetadata { cas_id, .. }, _))| {
+		.filter(|(_, (FileIdentity { cas_id, .. }, _))| {
 			cas_id
 				.as_ref()
 				.map(|cas_id| !existing_object_cas_ids.contains(cas_id))
@@ -263,7 +419,7 @@ async fn identifier_job_step(
 					|(
 						file_path_pub_id,
 						(
-							FileMetadata { kind, .. },
+							FileIdentity { kind, .. },
 							file_path_for_file_identifier::Data { date_created, .. },
 						),
 					)| {
@@ -346,6 +502,584 @@ async fn identifier_job_step(
 	Ok((total_created, updated_file_paths.len()))
 }
 
+/// Persists the file paths of remote locations that failed to be identified and clears the
+/// failures of the ones identified this time, so they can be listed and retried like the ones of
+/// local files. Their errors are only logged. It's best effort, a failure to persist them doesn't
+/// stop the identification.
+async fn update_persisted_failures(
+	db: &PrismaClient,
+	location_id: location::id::Type,
+	file_paths: &[file_path_for_file_identifier::Data],
+	file_paths_metadatas: &HashMap<Uuid, (FileIdentity, &file_path_for_file_identifier::Data)>,
+) {
+	let identified_file_path_ids = file_paths_metadatas
+		.values()
+		.map(|(_, file_path)| file_path.id)
+		.collect::<Vec<_>>();
+
+	let failed: Vec<(file_path::id::Type, NonCriticalError)> = file_paths
+		.iter()
+		.filter(|file_path| !identified_file_path_ids.contains(&file_path.id))
+		.map(|file_path| {
+			(
+				file_path.id,
+				file_identifier::NonCriticalError::FailedToExtractFileMetadata(
+					"failed to identify file on its location".to_string(),
+				)
+				.into(),
+			)
+		})
+		.collect();
+
+	if let Err(e) =
+		failures::record_failures(db, JobName::FileIdentifier, location_id, failed).await
+	{
+		warn!("Failed to persist file identifier failures: {e:#?}");
+	}
+
+	if let Err(e) =
+		failures::clear_failures(db, JobName::FileIdentifier, identified_file_path_ids).await
+	{
+		warn!("Failed to clear file identifier failures: {e:#?}");
+	}
+}
+
+/// Local files go through the tasks of the file identifier job, so they're analyzed with every
+/// option of the library, hashed the same way and get the same metadata saved as when that job
+/// identifies them
+async fn identify_local_files(
+	node: &Arc<Node>,
+	library: &Arc<Library>,
+	location: &location::Data,
+	file_paths: &[file_path_for_file_identifier::Data],
+	options: FileMetadataOptions,
+) -> Result<(usize, usize), JobError> {
+	let Identified {
+		created_objects_count,
+		linked_objects_count,
+		errors,
+	} = file_identifier::identify(
+		location.clone(),
+		file_paths.to_vec(),
+		options,
+		node.task_system.get_dispatcher(),
+		NodeContext::new(Arc::clone(node), Arc::clone(library)),
+	)
+	.await
+	.map_err(FileIdentifierJobError::from)?;
+
+	for e in errors {
+		error!("Failed to identify file: {e:#?}");
+	}
+
+	Ok((
+		created_objects_count as usize,
+		linked_objects_count as usize,
+	))
+}
+
+/// Identifies objects of a S3 location from their `ETag` and user metadata, without downloading
+/// them, except for the few extensions shared by different kinds, which need their magic bytes
+async fn identify_s3_objects<'file_path>(
+	s3: &S3Location,
+	location_id: location::id::Type,
+	location_path: &Path,
+	file_paths: &'file_path [file_path_for_file_identifier::Data],
+	options: &FileMetadataOptions,
+) -> HashMap<
+	Uuid,
+	(
+		FileIdentity,
+		&'file_path file_path_for_file_identifier::Data,
+	),
+> {
+	join_all(
+		file_paths
+			.iter()
+			.filter_map(|file_path| {
+				IsolatedFilePathData::try_from((location_id, file_path))
+					.map(|iso_file_path| (iso_file_path, file_path))
+					.map_err(|e| error!("Failed to extract isolated file path data: {e:#?}"))
+					.ok()
+			})
+			.map(|(iso_file_path, file_path)| async move {
+				let key = object_key(&iso_file_path);
+
+				let identity = s3
+					.identity(&key)
+					.await
+					.map_err(|e| error!("Failed to fetch S3 object metadata: {e:#?}"))
+					.ok()?;
+
+				// Only the sampled `cas_id` can be told from the object's metadata
+				let cas_id = if is_sampled(identity.size, options) {
+					identity.cas_id()
+				} else {
+					match s3
+						.read_through(location_path, &key, Some(identity.size))
+						.await
+					{
+						Ok(path) => local_copy_cas_id(path, identity.size, options).await?,
+						Err(e) => {
+							error!("Failed to download S3 object to hash it: {e:#?}");
+							return None;
+						}
+					}
+				};
+
+				let kind = match Extension::from_str(iso_file_path.extension()) {
+					Some(ExtensionPossibility::Known(extension)) => extension.into(),
+					Some(ExtensionPossibility::Conflicts(_)) => {
+						match s3
+							.read_through(location_path, &key, Some(identity.size))
+							.await
+						{
+							Ok(path) => Extension::resolve_conflicting(path, false)
+								.await
+								.map(Into::into)
+								.unwrap_or(ObjectKind::Unknown),
+							Err(e) => {
+								error!("Failed to download S3 object to resolve its kind: {e:#?}");
+								ObjectKind::Unknown
+							}
+						}
+					}
+					None => ObjectKind::Unknown,
+				};
+
+				trace!("Analyzed S3 object: {key} {cas_id:?} {kind:?}");
+
+				Some((
+					// SAFETY: This should never happen
+					Uuid::from_slice(&file_path.pub_id).expect("file_path.pub_id is invalid!"),
+					(
+						FileIdentity {
+							cas_id,
+							kind,
+							remote_only: false,
+						},
+						file_path,
+					),
+				))
+			}),
+	)
+	.await
+	.into_iter()
+	.flatten()
+	.collect()
+}
+
+/// Identifies files of a WebDAV location through ranged reads of the parts sampled for `cas_id`s,
+/// without downloading them, except for the few extensions shared by different kinds, which need
+/// their magic bytes
+async fn identify_webdav_files<'file_path>(
+	webdav: &WebDavLocation,
+	location_id: location::id::Type,
+	location_path: &Path,
+	file_paths: &'file_path [file_path_for_file_identifier::Data],
+	options: &FileMetadataOptions,
+) -> HashMap<
+	Uuid,
+	(
+		FileIdentity,
+		&'file_path file_path_for_file_identifier::Data,
+	),
+> {
+	join_all(
+		file_paths
+			.iter()
+			.filter_map(|file_path| {
+				IsolatedFilePathData::try_from((location_id, file_path))
+					.map(|iso_file_path| (iso_file_path, file_path))
+					.map_err(|e| error!("Failed to extract isolated file path data: {e:#?}"))
+					.ok()
+			})
+			.map(|(iso_file_path, file_path)| async move {
+				let path = object_key(&iso_file_path);
+
+				let size = file_path
+					.size_in_bytes_bytes
+					.as_deref()
+					.map(size_in_bytes_from_db)
+					.unwrap_or_default();
+
+				let cas_id = if is_sampled(size, options) {
+					webdav
+						.cas_id(&path, size)
+						.await
+						.map_err(|e| error!("Failed to sample WebDAV file: {e:#?}"))
+						.ok()?
+				} else {
+					match webdav.read_through(location_path, &path).await {
+						Ok(local_path) => local_copy_cas_id(local_path, size, options).await?,
+						Err(e) => {
+							error!("Failed to download WebDAV file to hash it: {e:#?}");
+							return None;
+						}
+					}
+				};
+
+				let kind =
+					match Extension::from_str(iso_file_path.extension()) {
+						Some(ExtensionPossibility::Known(extension)) => extension.into(),
+						Some(ExtensionPossibility::Conflicts(_)) => {
+							match webdav.read_through(location_path, &path).await {
+								Ok(local_path) => Extension::resolve_conflicting(local_path, false)
+									.await
+									.map(Into::into)
+									.unwrap_or(ObjectKind::Unknown),
+								Err(e) => {
+									error!("Failed to download WebDAV file to resolve its kind: {e:#?}");
+									ObjectKind::Unknown
+								}
+							}
+						}
+						None => ObjectKind::Unknown,
+					};
+
+				trace!("Analyzed WebDAV file: {path} {cas_id:?} {kind:?}");
+
+				Some((
+					// SAFETY: This should never happen
+					Uuid::from_slice(&file_path.pub_id).expect("file_path.pub_id is invalid!"),
+					(
+						FileIdentity {
+							cas_id,
+							kind,
+							remote_only: false,
+						},
+						file_path,
+					),
+				))
+			}),
+	)
+	.await
+	.into_iter()
+	.flatten()
+	.collect()
+}
+
+/// Identifies files of an MTP location sampling their `cas_id`s through partial reads, or copying
+/// them to the location's mirror directory on devices which can't read parts of files, as many
+/// cameras.
+async fn identify_mtp_files<'file_path>(
+	mtp: &MtpLocation,
+	location_id: location::id::Type,
+	location_path: &Path,
+	file_paths: &'file_path [file_path_for_file_identifier::Data],
+	options: &FileMetadataOptions,
+) -> HashMap<
+	Uuid,
+	(
+		FileIdentity,
+		&'file_path file_path_for_file_identifier::Data,
+	),
+> {
+	join_all(
+		file_paths
+			.iter()
+			.filter_map(|file_path| {
+				IsolatedFilePathData::try_from((location_id, file_path))
+					.map(|iso_file_path| (iso_file_path, file_path))
+					.map_err(|e| error!("Failed to extract isolated file path data: {e:#?}"))
+					.ok()
+			})
+			.map(|(iso_file_path, file_path)| async move {
+				let path = object_key(&iso_file_path);
+
+				let object = match mtp.find(&path).await {
+					Ok(Some(object)) => object,
+					Ok(None) => {
+						warn!("MTP file vanished before being identified: {path}");
+						return None;
+					}
+					Err(e) => {
+						error!("Failed to find MTP file: {e:#?}");
+						return None;
+					}
+				};
+
+				let sampled_cas_id = if is_sampled(object.size, options) {
+					mtp.cas_id(&object).await
+				} else {
+					// Files hashed entirely are identified from a local copy instead
+					Ok(None)
+				};
+
+				let cas_id = match sampled_cas_id {
+					Ok(cas_id) if cas_id.is_some() || object.size == 0 => cas_id,
+					// The device can't read parts of files, so we identify a local copy instead
+					Ok(_) => {
+						return match mtp.read_through(location_path, &path).await {
+							Ok(_) => FileMetadata::new(location_path, &iso_file_path, options)
+								.await
+								.map(|metadata| {
+									(
+										// SAFETY: This should never happen
+										Uuid::from_slice(&file_path.pub_id)
+											.expect("file_path.pub_id is invalid!"),
+										(metadata.into(), file_path),
+									)
+								})
+								.map_err(|e| error!("Failed to extract file metadata: {e:#?}"))
+								.ok(),
+							Err(e) => {
+								error!("Failed to copy MTP file to identify it: {e:#?}");
+								None
+							}
+						};
+					}
+					Err(e) => {
+						error!("Failed to sample MTP file: {e:#?}");
+						return None;
+					}
+				};
+
+				let kind = match Extension::from_str(iso_file_path.extension()) {
+					Some(ExtensionPossibility::Known(extension)) => extension.into(),
+					Some(ExtensionPossibility::Conflicts(_)) => {
+						match mtp.read_through(location_path, &path).await {
+							Ok(local_path) => Extension::resolve_conflicting(local_path, false)
+								.await
+								.map(Into::into)
+								.unwrap_or(ObjectKind::Unknown),
+							Err(e) => {
+								error!("Failed to copy MTP file to resolve its kind: {e:#?}");
+								ObjectKind::Unknown
+							}
+						}
+					}
+					None => ObjectKind::Unknown,
+				};
+
+				trace!("Analyzed MTP file: {path} {cas_id:?} {kind:?}");
+
+				Some((
+					// SAFETY: This should never happen
+					Uuid::from_slice(&file_path.pub_id).expect("file_path.pub_id is invalid!"),
+					(
+						FileIdentity {
+							cas_id,
+							kind,
+							remote_only: false,
+						},
+						file_path,
+					),
+				))
+			}),
+	)
+	.await
+	.into_iter()
+	.flatten()
+	.collect()
+}
+
+/// Identifies files of a cloud metadata location from the content hashes their provider sent to
+/// the indexer, without downloading anything. Hashes already seen on a hydrated file map to its
+/// real `cas_id`, so remote copies get deduplicated with local ones, the rest get a `cas_id`
+/// derived from the hash itself.
+///
+/// Hydrated files are identified like local ones, teaching their real `cas_id` to the
+/// `provider_hash` table.
+async fn identify_cloud_metadata_files<'file_path>(
+	db: &PrismaClient,
+	location_id: location::id::Type,
+	location_path: &Path,
+	file_paths: &'file_path [file_path_for_file_identifier::Data],
+	options: &FileMetadataOptions,
+) -> Result<
+	HashMap<
+		Uuid,
+		(
+			FileIdentity,
+			&'file_path file_path_for_file_identifier::Data,
+		),
+	>,
+	prisma_client_rust::QueryError,
+> {
+	let provider_hashes = db
+		.provider_hash()
+		.find_many(vec![provider_hash::file_path_pub_id::in_vec(
+			file_paths
+				.iter()
+				.map(|file_path| file_path.pub_id.clone())
+				.collect(),
+		)])
+		.exec()
+		.await?
+		.into_iter()
+		.map(|provider_hash| (provider_hash.file_path_pub_id.clone(), provider_hash))
+		.collect::<HashMap<_, _>>();
+
+	let known_cas_ids = db
+		.provider_hash()
+		.find_many(vec![
+			provider_hash::hash::in_vec(
+				provider_hashes
+					.values()
+					.map(|provider_hash| provider_hash.hash.clone())
+					.collect(),
+			),
+			provider_hash::cas_id::not(None),
+		])
+		.exec()
+		.await?
+		.into_iter()
+		.filter_map(|provider_hash| {
+			provider_hash
+				.cas_id
+				.map(|cas_id| ((provider_hash.kind, provider_hash.hash), cas_id))
+		})
+		.collect::<HashMap<_, _>>();
+
+	let (identities, learned_cas_ids): (HashMap<_, _>, Vec<_>) = join_all(
+		file_paths
+			.iter()
+			.filter_map(|file_path| {
+				IsolatedFilePathData::try_from((location_id, file_path))
+					.map(|iso_file_path| (iso_file_path, file_path))
+					.map_err(|e| error!("Failed to extract isolated file path data: {e:#?}"))
+					.ok()
+			})
+			.map(|(iso_file_path, file_path)| {
+				let provider_hashes = &provider_hashes;
+				let known_cas_ids = &known_cas_ids;
+
+				async move {
+					let size = file_path
+						.size_in_bytes_bytes
+						.as_deref()
+						.map(size_in_bytes_from_db)
+						.unwrap_or_default();
+
+					let provider_hash = provider_hashes.get(&file_path.pub_id);
+
+					let hydrated = fs::metadata(location_path.join(&iso_file_path))
+						.await
+						.is_ok_and(|metadata| metadata.len() == size);
+
+					let (identity, learned_cas_id) = if hydrated {
+						let identity = FileMetadata::new(location_path, &iso_file_path, options)
+							.await
+							.map(FileIdentity::from)
+							.map_err(|e| error!("Failed to extract file metadata: {e:#?}"))
+							.ok()?;
+
+						let learned_cas_id = provider_hash
+							.zip(identity.cas_id.clone())
+							.map(|(provider_hash, cas_id)| (provider_hash.id, cas_id));
+
+						(identity, learned_cas_id)
+					} else {
+						let Some(provider_hash) = provider_hash else {
+							error!(
+								"Missing provider hash for cloud file <id='{}'>",
+								file_path.id
+							);
+							return None;
+						};
+
+						let cas_id = known_cas_ids
+							.get(&(provider_hash.kind, provider_hash.hash.clone()))
+							.cloned()
+							.or_else(|| {
+								ProviderHashKind::from_int(provider_hash.kind)
+									.map(|kind| kind.derived_cas_id(&provider_hash.hash))
+									.map_err(|e| error!("Invalid provider hash kind: {e:#?}"))
+									.ok()
+							});
+
+						// Conflicting extensions would need the magic bytes of the content
+						let kind = match Extension::from_str(iso_file_path.extension()) {
+							Some(ExtensionPossibility::Known(extension)) => extension.into(),
+							_ => ObjectKind::Unknown,
+						};
+
+						(
+							FileIdentity {
+								cas_id,
+								kind,
+								remote_only: true,
+							},
+							None,
+						)
+					};
+
+					trace!(
+						"Analyzed cloud file: {} {:?} {:?} <hydrated={hydrated}>",
+						file_path.id,
+						identity.cas_id,
+						identity.kind
+					);
+
+					Some((
+						(
+							// SAFETY: This should never happen
+							Uuid::from_slice(&file_path.pub_id)
+								.expect("file_path.pub_id is invalid!"),
+							(identity, file_path),
+						),
+						learned_cas_id,
+					))
+				}
+			}),
+	)
+	.await
+	.into_iter()
+	.flatten()
+	.unzip();
+
+	db._batch(
+		learned_cas_ids
+			.into_iter()
+			.flatten()
+			.map(|(provider_hash_id, cas_id)| {
+				db.provider_hash().update(
+					provider_hash::id::equals(provider_hash_id),
+					vec![provider_hash::cas_id::set(Some(cas_id))],
+				)
+			})
+			.collect::<Vec<_>>(),
+	)
+	.await?;
+
+	Ok(identities)
+}
+
+/// Whether a remote file of `size` bytes gets a sampled `cas_id`, which we can generate without
+/// downloading it. Files up to the deep hash threshold are hashed entirely, like local ones.
+fn is_sampled(size: u64, options: &FileMetadataOptions) -> bool {
+	options
+		.cas_id_algorithm
+		.for_file_size(size, options.deep_hash_threshold)
+		== CasIdAlgorithm::Blake3Sampled
+}
+
+/// `cas_id` of the local copy of a remote file, for the algorithms hashing whole files, which
+/// can't be sampled from a few ranged reads
+async fn local_copy_cas_id(
+	path: impl AsRef<Path> + Send,
+	size: u64,
+	options: &FileMetadataOptions,
+) -> Option<Option<String>> {
+	if size == 0 {
+		return Some(None);
+	}
+
+	generate_cas_id(
+		path,
+		size,
+		options
+			.cas_id_algorithm
+			.for_file_size(size, options.deep_hash_threshold),
+		&options.io_throttle,
+	)
+	.await
+	.map(Some)
+	.map_err(|e| error!("Failed to hash local copy of remote file: {e:#?}"))
+	.ok()
+}
+
 fn connect_file_path_to_object<'db>(
 	file_path_id: Uuid,
 	object_id: Uuid,
@@ -379,7 +1113,8 @@ async fn process_identifier_file_paths(
 	file_paths: &[file_path_for_file_identifier::Data],
 	step_number: usize,
 	cursor: file_path::id::Type,
-	library: &Library,
+	node: &Arc<Node>,
+	library: &Arc<Library>,
 	orphan_count: usize,
 ) -> Result<(usize, usize, file_path::id::Type), JobError> {
 	trace!(
@@ -390,7 +1125,7 @@ async fn process_identifier_file_paths(
 	);
 
 	let (total_objects_created, total_objects_linked) =
-		identifier_job_step(library, location, file_paths).await?;
+		identifier_job_step(node, library, location, file_paths).await?;
 
 	Ok((
 		total_objects_created,
@@ -12,22 +12,28 @@ use sd_core_file_path_helper::{
 	ensure_file_path_exists, ensure_sub_path_is_directory, ensure_sub_path_is_in_location,
 	IsolatedFilePathData,
 };
+use sd_core_heavy_lifting::file_identifier::{self, IdentifierRules, RulesScope};
 use sd_core_prisma_helpers::file_path_for_file_identifier;
 
 use sd_prisma::prisma::{file_path, location, PrismaClient, SortOrder};
-use sd_utils::db::maybe_missing;
+use sd_utils::db::{maybe_missing, size_in_bytes_from_db};
 
 use std::{
 	hash::{Hash, Hasher},
 	path::{Path, PathBuf},
+	sync::OnceLock,
 };
 
+use async_channel as chan;
 use prisma_client_rust::or;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use tracing::{debug, info, trace};
 
-use super::{process_identifier_file_paths, FileIdentifierJobError, CHUNK_SIZE};
+use super::{
+	identify_prioritized_directories, process_identifier_file_paths, FileIdentifierJobError,
+	CHUNK_SIZE,
+};
 
 /// `FileIdentifierJobInit` takes file_paths without an object_id from a location
 /// or starting from a `sub_path` getting every descendent from this `sub_path`
@@ -53,6 +59,10 @@ impl Hash for OldFileIdentifierJobInit {
 pub struct OldFileIdentifierJobData {
 	location_path: PathBuf,
 	maybe_sub_iso_file_path: Option<IsolatedFilePathData<'static>>,
+	/// Directories to identify first, the lane is opened on the first step after starting or
+	/// resuming, see [`Library::prioritize_directory`]
+	#[serde(skip)]
+	prioritized_rx: OnceLock<chan::Receiver<PathBuf>>,
 }
 
 #[derive(Serialize, Deserialize, Default, Debug)]
@@ -83,8 +93,8 @@ impl StatefulJob for OldFileIdentifierJobInit {
 	const NAME: &'static str = "file_identifier";
 	const IS_BATCHED: bool = true;
 
-	fn target_location(&self) -> location::id::Type {
-		self.location.id
+	fn target_location(&self) -> Option<location::id::Type> {
+		Some(self.location.id)
 	}
 
 	async fn init(
@@ -93,7 +103,7 @@ impl StatefulJob for OldFileIdentifierJobInit {
 		data: &mut Option<Self::Data>,
 	) -> Result<JobInitOutput<Self::RunMetadata, Self::Step>, JobError> {
 		let init = self;
-		let Library { db, .. } = &*ctx.library;
+		let Library { db, sync, .. } = &*ctx.library;
 
 		debug!("Identifying orphan File Paths...");
 
@@ -127,6 +137,21 @@ impl StatefulJob for OldFileIdentifierJobInit {
 			_ => None,
 		};
 
+		let materialized_path = maybe_sub_iso_file_path
+			.as_ref()
+			.and_then(IsolatedFilePathData::materialized_path_for_children);
+
+		file_identifier::skip_matching(
+			&IdentifierRules::from_location(&init.location),
+			location_id,
+			materialized_path
+				.as_deref()
+				.map_or(RulesScope::Location, RulesScope::Descendants),
+			db,
+			sync,
+		)
+		.await?;
+
 		let orphan_count =
 			count_orphan_file_paths(db, location_id, &maybe_sub_iso_file_path).await?;
 
@@ -134,6 +159,7 @@ impl StatefulJob for OldFileIdentifierJobInit {
 		*data = Some(OldFileIdentifierJobData {
 			location_path: location_path.to_path_buf(),
 			maybe_sub_iso_file_path,
+			prioritized_rx: OnceLock::new(),
 		});
 
 		let data = data.as_ref().expect("we just set it");
@@ -193,6 +219,16 @@ impl StatefulJob for OldFileIdentifierJobInit {
 
 		let mut new_metadata = Self::RunMetadata::default();
 
+		// Before the chunk, so it doesn't hold orphans identified by a prioritized directory
+		identify_prioritized_directories(
+			data.prioritized_rx
+				.get_or_init(|| ctx.library.open_priority_lane(location.id)),
+			location,
+			&ctx.node,
+			&ctx.library,
+		)
+		.await;
+
 		// get chunk of orphans to process
 		let file_paths = get_orphan_file_paths(
 			&ctx.library.db,
@@ -203,7 +239,8 @@ impl StatefulJob for OldFileIdentifierJobInit {
 		.await?;
 
 		// if no file paths found, abort entire job early, there is nothing to do
-		// if we hit this error, there is something wrong with the data/query
+		// either prioritized directories took the remaining orphans or there is something wrong
+		// with the data/query
 		if file_paths.is_empty() {
 			return Err(JobError::EarlyFinish {
 				name: <Self as StatefulJob>::NAME.to_string(),
@@ -218,6 +255,7 @@ impl StatefulJob for OldFileIdentifierJobInit {
 				&file_paths,
 				step_number,
 				run_metadata.cursor,
+				&ctx.node,
 				&ctx.library,
 				run_metadata.total_orphan_paths,
 			)
@@ -234,6 +272,13 @@ impl StatefulJob for OldFileIdentifierJobInit {
 
 		ctx.progress(vec![
 			JobReportUpdate::CompletedTaskCount(step_number * CHUNK_SIZE + file_paths.len()),
+			JobReportUpdate::HashedBytes(
+				file_paths
+					.iter()
+					.filter_map(|file_path| file_path.size_in_bytes_bytes.as_deref())
+					.map(size_in_bytes_from_db)
+					.sum(),
+			),
 			JobReportUpdate::Message(format!(
 				"Processed {} of {} orphan Paths",
 				step_number * CHUNK_SIZE,
@@ -282,6 +327,7 @@ fn orphan_path_filters(
 			file_path::is_dir::equals(Some(false)),
 			file_path::location_id::equals(Some(location_id)),
 			file_path::size_in_bytes_bytes::not(Some(0u64.to_be_bytes().to_vec())),
+			file_identifier::not_skipped(),
 		],
 		[
 			// this is a workaround for the cursor not working properly
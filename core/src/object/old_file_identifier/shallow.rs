@@ -1,16 +1,21 @@
-use crate::{invalidate_query, library::Library, old_job::JobError};
+use crate::{invalidate_query, library::Library, old_job::JobError, Node};
 
 use sd_core_file_path_helper::{
 	ensure_file_path_exists, ensure_sub_path_is_directory, ensure_sub_path_is_in_location,
 	IsolatedFilePathData,
 };
+use sd_core_heavy_lifting::file_identifier::{self, IdentifierRules, RulesScope};
 use sd_core_prisma_helpers::file_path_for_file_identifier;
 
 use sd_prisma::prisma::{file_path, location, PrismaClient, SortOrder};
 use sd_utils::db::maybe_missing;
 
-use std::path::{Path, PathBuf};
+use std::{
+	path::{Path, PathBuf},
+	sync::Arc,
+};
 
+use async_channel as chan;
 use prisma_client_rust::or;
 use serde::{Deserialize, Serialize};
 use tracing::{trace, warn};
@@ -26,9 +31,10 @@ pub struct ShallowFileIdentifierJobState {
 pub async fn old_shallow(
 	location: &location::Data,
 	sub_path: &PathBuf,
-	library: &Library,
+	node: &Arc<Node>,
+	library: &Arc<Library>,
 ) -> Result<(), JobError> {
-	let Library { db, .. } = library;
+	let Library { db, sync, .. } = &**library;
 
 	warn!("Identifying orphan File Paths...");
 
@@ -61,6 +67,17 @@ pub async fn old_shallow(
 			.map_err(FileIdentifierJobError::from)?
 	};
 
+	if let Some(materialized_path) = sub_iso_file_path.materialized_path_for_children() {
+		file_identifier::skip_matching(
+			&IdentifierRules::from_location(location),
+			location_id,
+			RulesScope::Children(&materialized_path),
+			db,
+			sync,
+		)
+		.await?;
+	}
+
 	let orphan_count = count_orphan_file_paths(db, location_id, &sub_iso_file_path).await?;
 
 	if orphan_count == 0 {
@@ -106,6 +123,7 @@ pub async fn old_shallow(
 			&file_paths,
 			step_number,
 			*cursor,
+			node,
 			library,
 			orphan_count,
 		)
@@ -119,6 +137,24 @@ pub async fn old_shallow(
 	Ok(())
 }
 
+/// Identifies the directories prioritized through `prioritized_rx` since the last call, see
+/// [`Library::prioritize_directory`]. A directory failing to be identified doesn't stop the caller.
+pub async fn identify_prioritized_directories(
+	prioritized_rx: &chan::Receiver<PathBuf>,
+	location: &location::Data,
+	node: &Arc<Node>,
+	library: &Arc<Library>,
+) {
+	while let Ok(directory) = prioritized_rx.try_recv() {
+		if let Err(e) = old_shallow(location, &directory, node, library).await {
+			warn!(
+				"Failed to identify prioritized directory <path='{}'>: {e:#?}",
+				directory.display()
+			);
+		}
+	}
+}
+
 fn orphan_path_filters(
 	location_id: location::id::Type,
 	file_path_id: Option<file_path::id::Type>,
@@ -138,6 +174,7 @@ fn orphan_path_filters(
 					.expect("sub path for shallow identifier must be a directory"),
 			)),
 			file_path::size_in_bytes_bytes::not(Some(0u64.to_be_bytes().to_vec())),
+			file_identifier::not_skipped(),
 		],
 		[file_path_id.map(file_path::id::gte)],
 	)
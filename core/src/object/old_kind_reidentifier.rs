@@ -0,0 +1,410 @@
+use crate::{
+	invalidate_query,
+	library::Library,
+	old_job::{
+		CurrentStep, JobError, JobInitOutput, JobReportUpdate, JobResult, JobRunMetadata,
+		JobStepOutput, StatefulJob, WorkerContext,
+	},
+};
+
+use sd_core_file_path_helper::{
+	ensure_file_path_exists, ensure_sub_path_is_directory, ensure_sub_path_is_in_location,
+	FilePathError, IsolatedFilePathData,
+};
+use sd_core_heavy_lifting::file_identifier::{resolve_kind, DEFAULT_SNIFF_READ_BUDGET};
+use sd_core_prisma_helpers::file_path_for_kind_reidentifier;
+
+use sd_file_ext::custom_kind::KindRegistry;
+use sd_prisma::{
+	prisma::{file_path, location, object, SortOrder},
+	prisma_sync,
+};
+use sd_sync::OperationFactory;
+use sd_utils::{chain_optional_iter, db::maybe_missing, msgpack};
+
+use std::{
+	collections::HashMap,
+	hash::{Hash, Hasher},
+	path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::fs;
+use tracing::{debug, info, trace, warn};
+
+// we break these jobs into chunks of 100 to improve performance
+const CHUNK_SIZE: usize = 100;
+
+#[derive(thiserror::Error, Debug)]
+pub enum KindReidentifierError {
+	#[error("sub path not found: <path='{}'>", .0.display())]
+	SubPathNotFound(Box<Path>),
+
+	// Internal errors
+	#[error("database error: {0}")]
+	Database(#[from] prisma_client_rust::QueryError),
+	#[error(transparent)]
+	FilePath(#[from] FilePathError),
+}
+
+/// `OldKindReidentifierJobInit` resolves again the kind of every identified file path in a
+/// location, or starting from a `sub_path`, updating the kind of their objects when it changed.
+/// Files aren't hashed again, so it is a cheap way to pick up new extension to kind mappings
+/// from `sd-file-ext` or custom kinds added to the library.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct OldKindReidentifierJobInit {
+	pub location: location::Data,
+	pub sub_path: Option<PathBuf>,
+}
+
+impl Hash for OldKindReidentifierJobInit {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.location.id.hash(state);
+		if let Some(ref sub_path) = self.sub_path {
+			sub_path.hash(state);
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OldKindReidentifierJobData {
+	location_path: PathBuf,
+	maybe_sub_iso_file_path: Option<IsolatedFilePathData<'static>>,
+	kind_registry: KindRegistry,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct OldKindReidentifierJobRunMetadata {
+	cursor: file_path::id::Type,
+	total_file_paths: usize,
+	total_objects_updated: usize,
+}
+
+impl JobRunMetadata for OldKindReidentifierJobRunMetadata {
+	fn update(&mut self, new_data: Self) {
+		self.total_file_paths += new_data.total_file_paths;
+		self.total_objects_updated += new_data.total_objects_updated;
+		self.cursor = new_data.cursor;
+	}
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for OldKindReidentifierJobInit {
+	type Data = OldKindReidentifierJobData;
+	type Step = ();
+	type RunMetadata = OldKindReidentifierJobRunMetadata;
+
+	const NAME: &'static str = "kind_reidentifier";
+	const IS_BATCHED: bool = true;
+
+	fn target_location(&self) -> Option<location::id::Type> {
+		Some(self.location.id)
+	}
+
+	async fn init(
+		&self,
+		ctx: &WorkerContext,
+		data: &mut Option<Self::Data>,
+	) -> Result<JobInitOutput<Self::RunMetadata, Self::Step>, JobError> {
+		let init = self;
+		let Library { db, .. } = &*ctx.library;
+
+		let location_id = init.location.id;
+
+		let location_path = maybe_missing(&init.location.path, "location.path").map(Path::new)?;
+
+		let maybe_sub_iso_file_path = match &init.sub_path {
+			Some(sub_path) if sub_path != Path::new("") => {
+				let full_path = ensure_sub_path_is_in_location(location_path, sub_path)
+					.await
+					.map_err(KindReidentifierError::from)?;
+				ensure_sub_path_is_directory(location_path, sub_path)
+					.await
+					.map_err(KindReidentifierError::from)?;
+
+				let sub_iso_file_path =
+					IsolatedFilePathData::new(location_id, location_path, &full_path, true)
+						.map_err(KindReidentifierError::from)?;
+
+				ensure_file_path_exists(
+					sub_path,
+					&sub_iso_file_path,
+					db,
+					KindReidentifierError::SubPathNotFound,
+				)
+				.await?;
+
+				Some(sub_iso_file_path)
+			}
+			_ => None,
+		};
+
+		let file_paths_count = db
+			.file_path()
+			.count(identified_path_filters(
+				location_id,
+				None,
+				&maybe_sub_iso_file_path,
+			))
+			.exec()
+			.await? as usize;
+
+		// Initializing `state.data` here because we need a complete state in case of early finish
+		*data = Some(OldKindReidentifierJobData {
+			location_path: location_path.to_path_buf(),
+			maybe_sub_iso_file_path,
+			kind_registry: KindRegistry::new(ctx.library.config().await.custom_kinds),
+		});
+
+		if file_paths_count == 0 {
+			return Err(JobError::EarlyFinish {
+				name: <Self as StatefulJob>::NAME.to_string(),
+				reason: "Found no identified file paths to process".to_string(),
+			});
+		}
+
+		let task_count = file_paths_count.div_ceil(CHUNK_SIZE);
+		debug!(
+			"Found {file_paths_count} identified file paths. Will execute {task_count} tasks..."
+		);
+
+		ctx.progress(vec![
+			JobReportUpdate::TaskCount(file_paths_count),
+			JobReportUpdate::Message(format!(
+				"Found {file_paths_count} files to resolve their kind again"
+			)),
+		]);
+
+		Ok((
+			OldKindReidentifierJobRunMetadata {
+				total_file_paths: file_paths_count,
+				..Default::default()
+			},
+			vec![(); task_count],
+		)
+			.into())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: &WorkerContext,
+		CurrentStep { step_number, .. }: CurrentStep<'_, Self::Step>,
+		data: &Self::Data,
+		run_metadata: &Self::RunMetadata,
+	) -> Result<JobStepOutput<Self::Step, Self::RunMetadata>, JobError> {
+		let init = self;
+		let Library { db, .. } = &*ctx.library;
+
+		let file_paths = db
+			.file_path()
+			.find_many(identified_path_filters(
+				init.location.id,
+				Some(run_metadata.cursor),
+				&data.maybe_sub_iso_file_path,
+			))
+			.order_by(file_path::id::order(SortOrder::Asc))
+			.take(CHUNK_SIZE as i64)
+			.select(file_path_for_kind_reidentifier::select())
+			.exec()
+			.await?;
+
+		let Some(last_file_path) = file_paths.last() else {
+			// File paths were removed since we counted them, nothing left to do
+			return Ok(OldKindReidentifierJobRunMetadata {
+				cursor: run_metadata.cursor,
+				..Default::default()
+			}
+			.into());
+		};
+
+		let cursor = last_file_path.id + 1;
+
+		let total_objects_updated = update_objects_kinds(
+			init.location.id,
+			&data.location_path,
+			&data.kind_registry,
+			&file_paths,
+			&ctx.library,
+		)
+		.await?;
+
+		ctx.progress(vec![
+			JobReportUpdate::CompletedTaskCount(step_number * CHUNK_SIZE + file_paths.len()),
+			JobReportUpdate::Message(format!(
+				"Processed {} of {} file paths",
+				step_number * CHUNK_SIZE + file_paths.len(),
+				run_metadata.total_file_paths
+			)),
+		]);
+
+		Ok(OldKindReidentifierJobRunMetadata {
+			cursor,
+			total_objects_updated,
+			..Default::default()
+		}
+		.into())
+	}
+
+	async fn finalize(
+		&self,
+		ctx: &WorkerContext,
+		_data: &Option<Self::Data>,
+		run_metadata: &Self::RunMetadata,
+	) -> JobResult {
+		let init = self;
+		info!("Finalizing kind reidentifier job: {run_metadata:?}");
+
+		if run_metadata.total_objects_updated > 0 {
+			invalidate_query!(ctx.library, "search.objects");
+			invalidate_query!(ctx.library, "search.paths");
+		}
+
+		Ok(Some(json!({ "init": init, "run_metadata": run_metadata })))
+	}
+}
+
+fn identified_path_filters(
+	location_id: location::id::Type,
+	file_path_id: Option<file_path::id::Type>,
+	maybe_sub_iso_file_path: &Option<IsolatedFilePathData<'_>>,
+) -> Vec<file_path::WhereParam> {
+	chain_optional_iter(
+		[
+			file_path::location_id::equals(Some(location_id)),
+			file_path::is_dir::equals(Some(false)),
+			file_path::object_id::not(None),
+			// Symbolic links recorded as links keep their kind
+			file_path::link_target::equals(None),
+		],
+		[
+			file_path_id.map(file_path::id::gte),
+			maybe_sub_iso_file_path.as_ref().map(|sub_iso_file_path| {
+				file_path::materialized_path::starts_with(
+					sub_iso_file_path
+						.materialized_path_for_children()
+						.expect("sub path iso_file_path must be a directory"),
+				)
+			}),
+		],
+	)
+}
+
+/// Resolves the kind of each file path again, writing the objects whose kind changed in a single
+/// batch. Returns how many objects were updated.
+async fn update_objects_kinds(
+	location_id: location::id::Type,
+	location_path: &Path,
+	kind_registry: &KindRegistry,
+	file_paths: &[file_path_for_kind_reidentifier::Data],
+	Library { db, sync, .. }: &Library,
+) -> Result<usize, KindReidentifierError> {
+	// Many file paths can share an object, so we key the updates by object
+	let mut updates_by_object = HashMap::new();
+
+	for file_path in file_paths {
+		let Some(object) = &file_path.object else {
+			continue;
+		};
+
+		if updates_by_object.contains_key(&object.pub_id) {
+			continue;
+		}
+
+		let path = match IsolatedFilePathData::try_from((location_id, file_path)) {
+			Ok(iso_file_path) => location_path.join(iso_file_path),
+			Err(e) => {
+				warn!("Failed to extract isolated file path data: {e:#?}");
+				continue;
+			}
+		};
+
+		// Files that vanished since they were indexed would resolve to an unknown kind
+		let size = match fs::metadata(&path).await {
+			Ok(metadata) => metadata.len(),
+			Err(e) => {
+				warn!(
+					"Failed to read file metadata <path='{}'>: {e:#?}",
+					path.display()
+				);
+				continue;
+			}
+		};
+
+		let (kind, custom_kind) =
+			resolve_kind(&path, size, DEFAULT_SNIFF_READ_BUDGET, kind_registry).await;
+		let kind = kind as i32;
+
+		if object.kind == Some(kind) && object.custom_kind == custom_kind {
+			continue;
+		}
+
+		trace!(
+			"Object kind changed <path='{}', old_kind={:?}, new_kind={kind}, custom_kind={custom_kind:?}>",
+			path.display(),
+			object.kind
+		);
+
+		updates_by_object.insert(object.pub_id.clone(), (object, kind, custom_kind));
+	}
+
+	if updates_by_object.is_empty() {
+		return Ok(0);
+	}
+
+	let (sync_stuff, objects_to_update) = updates_by_object
+		.into_iter()
+		.map(|(pub_id, (object, kind, custom_kind))| {
+			let (sync_params, db_params) = chain_optional_iter(
+				[],
+				[
+					(object.kind != Some(kind)).then(|| {
+						(
+							(object::kind::NAME, msgpack!(kind)),
+							object::kind::set(Some(kind)),
+						)
+					}),
+					(object.custom_kind != custom_kind).then(|| {
+						(
+							(object::custom_kind::NAME, msgpack!(custom_kind)),
+							object::custom_kind::set(custom_kind),
+						)
+					}),
+				],
+			)
+			.into_iter()
+			.unzip::<_, _, Vec<_>, Vec<_>>();
+
+			(
+				sync_params
+					.into_iter()
+					.map(|(field, value)| {
+						sync.shared_update(
+							prisma_sync::object::SyncId {
+								pub_id: pub_id.clone(),
+							},
+							field,
+							value,
+						)
+					})
+					.collect::<Vec<_>>(),
+				db.object()
+					.update(object::pub_id::equals(pub_id), db_params)
+					.select(object::select!({ id })),
+			)
+		})
+		.unzip::<_, _, Vec<_>, Vec<_>>();
+
+	let total_objects_updated = objects_to_update.len();
+
+	sync.write_ops(
+		db,
+		(
+			sync_stuff.into_iter().flatten().collect(),
+			objects_to_update,
+		),
+	)
+	.await?;
+
+	Ok(total_objects_updated)
+}
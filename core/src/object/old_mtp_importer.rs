@@ -0,0 +1,336 @@
+use crate::{
+	library::Library,
+	location::{
+		find_location,
+		mtp::{MtpError, MtpLocation, MtpLocationConfig},
+		s3::object_key,
+		scan_location_sub_path,
+	},
+	old_job::{
+		CurrentStep, JobError, JobInitOutput, JobReportUpdate, JobResult, JobRunErrors,
+		JobRunMetadata, JobStepOutput, StatefulJob, WorkerContext,
+	},
+};
+
+use sd_core_file_path_helper::IsolatedFilePathData;
+use sd_core_prisma_helpers::{file_path_for_file_identifier, location_with_indexer_rules};
+
+use sd_file_ext::kind::ObjectKind;
+use sd_prisma::prisma::{file_path, location, object, SortOrder};
+use sd_utils::{
+	chain_optional_iter,
+	db::{maybe_missing, size_in_bytes_from_db},
+};
+
+use std::{
+	hash::{Hash, Hasher},
+	path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::fs;
+use tracing::{debug, error, info, trace};
+
+// we break these jobs into chunks of 100 to improve performance
+const CHUNK_SIZE: usize = 100;
+
+#[derive(thiserror::Error, Debug)]
+pub enum MtpImporterError {
+	#[error("import destination location not found: <id='{0}'>")]
+	DestinationNotFound(location::id::Type),
+	#[error("location isn't an MTP location: <id='{0}'>")]
+	NotMtpLocation(location::id::Type),
+
+	// Internal errors
+	#[error("database error: {0}")]
+	Database(#[from] prisma_client_rust::QueryError),
+	#[error(transparent)]
+	Mtp(#[from] MtpError),
+}
+
+/// `OldMtpImporterJobInit` copies the photos and videos of an MTP location which aren't in its
+/// import destination yet, keeping their folders, so plugging in a phone or camera is enough to
+/// get new pictures into the library. Runs after the media processor on every scan of locations
+/// with an import destination configured, which gets scanned once the copies are done.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct OldMtpImporterJobInit {
+	pub location: location::Data,
+}
+
+impl Hash for OldMtpImporterJobInit {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.location.id.hash(state);
+	}
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OldMtpImporterJobData {
+	destination_location_id: location::id::Type,
+	destination_sub_path: PathBuf,
+	destination_path: PathBuf,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct OldMtpImporterJobRunMetadata {
+	cursor: file_path::id::Type,
+	total_file_paths: usize,
+	total_imported: usize,
+	total_bytes_imported: u64,
+}
+
+impl JobRunMetadata for OldMtpImporterJobRunMetadata {
+	fn update(&mut self, new_data: Self) {
+		self.total_file_paths += new_data.total_file_paths;
+		self.total_imported += new_data.total_imported;
+		self.total_bytes_imported += new_data.total_bytes_imported;
+		self.cursor = new_data.cursor;
+	}
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for OldMtpImporterJobInit {
+	type Data = OldMtpImporterJobData;
+	type Step = ();
+	type RunMetadata = OldMtpImporterJobRunMetadata;
+
+	const NAME: &'static str = "mtp_importer";
+	const IS_BATCHED: bool = true;
+
+	fn target_location(&self) -> Option<location::id::Type> {
+		Some(self.location.id)
+	}
+
+	async fn init(
+		&self,
+		ctx: &WorkerContext,
+		data: &mut Option<Self::Data>,
+	) -> Result<JobInitOutput<Self::RunMetadata, Self::Step>, JobError> {
+		let init = self;
+		let Library { db, .. } = &*ctx.library;
+
+		let Some(import) =
+			MtpLocationConfig::from_db(init.location.id, init.location.mtp_config.as_deref())
+				.map_err(MtpImporterError::from)?
+				.and_then(|config| config.import)
+		else {
+			return Err(JobError::EarlyFinish {
+				name: <Self as StatefulJob>::NAME.to_string(),
+				reason: "Location has no import destination".to_string(),
+			});
+		};
+
+		let destination = find_location(&ctx.library, import.location_id)
+			.select(location::select!({ path }))
+			.exec()
+			.await?
+			.ok_or(MtpImporterError::DestinationNotFound(import.location_id))?;
+
+		let destination_sub_path = import.sub_path.unwrap_or_default();
+		let destination_path = Path::new(maybe_missing(&destination.path, "location.path")?)
+			.join(&destination_sub_path);
+
+		let file_paths_count = db
+			.file_path()
+			.count(importable_path_filters(init.location.id, None))
+			.exec()
+			.await? as usize;
+
+		// Initializing `state.data` here because we need a complete state in case of early finish
+		*data = Some(OldMtpImporterJobData {
+			destination_location_id: import.location_id,
+			destination_sub_path,
+			destination_path,
+		});
+
+		if file_paths_count == 0 {
+			return Err(JobError::EarlyFinish {
+				name: <Self as StatefulJob>::NAME.to_string(),
+				reason: "Found no photos or videos to import".to_string(),
+			});
+		}
+
+		let task_count = file_paths_count.div_ceil(CHUNK_SIZE);
+		debug!("Found {file_paths_count} photos and videos. Will execute {task_count} tasks...");
+
+		ctx.progress(vec![
+			JobReportUpdate::TaskCount(file_paths_count),
+			JobReportUpdate::Message(format!(
+				"Found {file_paths_count} photos and videos to check for import"
+			)),
+		]);
+
+		Ok((
+			OldMtpImporterJobRunMetadata {
+				total_file_paths: file_paths_count,
+				..Default::default()
+			},
+			vec![(); task_count],
+		)
+			.into())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: &WorkerContext,
+		CurrentStep { step_number, .. }: CurrentStep<'_, Self::Step>,
+		data: &Self::Data,
+		run_metadata: &Self::RunMetadata,
+	) -> Result<JobStepOutput<Self::Step, Self::RunMetadata>, JobError> {
+		let init = self;
+		let Library { db, .. } = &*ctx.library;
+
+		let file_paths = db
+			.file_path()
+			.find_many(importable_path_filters(
+				init.location.id,
+				Some(run_metadata.cursor),
+			))
+			.order_by(file_path::id::order(SortOrder::Asc))
+			.take(CHUNK_SIZE as i64)
+			.select(file_path_for_file_identifier::select())
+			.exec()
+			.await?;
+
+		let Some(last_file_path) = file_paths.last() else {
+			// File paths were removed since we counted them, nothing left to do
+			return Ok(OldMtpImporterJobRunMetadata {
+				cursor: run_metadata.cursor,
+				..Default::default()
+			}
+			.into());
+		};
+
+		let cursor = last_file_path.id + 1;
+
+		let mtp = MtpLocation::for_location(init.location.id, init.location.mtp_config.as_deref())
+			.await
+			.map_err(MtpImporterError::from)?
+			.ok_or(MtpImporterError::NotMtpLocation(init.location.id))?;
+
+		let mut total_imported = 0;
+		let mut total_bytes_imported = 0;
+		let mut errors = vec![];
+
+		for file_path in &file_paths {
+			let iso_file_path = match IsolatedFilePathData::try_from((init.location.id, file_path))
+			{
+				Ok(iso_file_path) => iso_file_path,
+				Err(e) => {
+					errors.push(format!("Failed to extract isolated file path data: {e}"));
+					continue;
+				}
+			};
+
+			let size = file_path
+				.size_in_bytes_bytes
+				.as_deref()
+				.map(size_in_bytes_from_db)
+				.unwrap_or_default();
+
+			let destination = data.destination_path.join(&iso_file_path);
+
+			// Same size in the same place is taken as an earlier import, even if since edited
+			if fs::metadata(&destination)
+				.await
+				.map_or(false, |metadata| metadata.len() == size)
+			{
+				trace!(
+					"Skipping already imported <path='{}'>",
+					destination.display()
+				);
+				continue;
+			}
+
+			let key = object_key(&iso_file_path);
+
+			match mtp.find(&key).await {
+				Ok(Some(object)) => match mtp.download(&object, &destination).await {
+					Ok(()) => {
+						total_imported += 1;
+						total_bytes_imported += object.size;
+					}
+					Err(e) => errors.push(format!("Failed to import <path='{key}'>: {e}")),
+				},
+				Ok(None) => trace!("Skipping MTP file which vanished <path='{key}'>"),
+				Err(e) => errors.push(format!("Failed to find MTP file <path='{key}'>: {e}")),
+			}
+		}
+
+		ctx.progress(vec![
+			JobReportUpdate::CompletedTaskCount(step_number * CHUNK_SIZE + file_paths.len()),
+			JobReportUpdate::Message(format!(
+				"Checked {} of {} photos and videos",
+				step_number * CHUNK_SIZE + file_paths.len(),
+				run_metadata.total_file_paths
+			)),
+		]);
+
+		Ok((
+			OldMtpImporterJobRunMetadata {
+				cursor,
+				total_imported,
+				total_bytes_imported,
+				..Default::default()
+			},
+			JobRunErrors(errors),
+		)
+			.into())
+	}
+
+	async fn finalize(
+		&self,
+		ctx: &WorkerContext,
+		data: &Option<Self::Data>,
+		run_metadata: &Self::RunMetadata,
+	) -> JobResult {
+		let init = self;
+		info!("Finalizing MTP importer job: {run_metadata:?}");
+
+		if let (Some(data), true) = (data, run_metadata.total_imported > 0) {
+			match find_location(&ctx.library, data.destination_location_id)
+				.include(location_with_indexer_rules::include())
+				.exec()
+				.await
+			{
+				Ok(Some(destination)) => {
+					if let Err(e) = scan_location_sub_path(
+						&ctx.node,
+						&ctx.library,
+						destination,
+						&data.destination_sub_path,
+					)
+					.await
+					{
+						error!("Failed to scan MTP import destination: {e:#?}");
+					}
+				}
+				Ok(None) => error!(
+					"{}",
+					MtpImporterError::DestinationNotFound(data.destination_location_id)
+				),
+				Err(e) => error!("Failed to fetch MTP import destination: {e:#?}"),
+			}
+		}
+
+		Ok(Some(json!({ "init": init, "run_metadata": run_metadata })))
+	}
+}
+
+/// Identified photos and videos, as the file identifier runs before the importer
+fn importable_path_filters(
+	location_id: location::id::Type,
+	file_path_id: Option<file_path::id::Type>,
+) -> Vec<file_path::WhereParam> {
+	chain_optional_iter(
+		[
+			file_path::location_id::equals(Some(location_id)),
+			file_path::is_dir::equals(Some(false)),
+			file_path::object::is(vec![object::kind::in_vec(vec![
+				ObjectKind::Image as i32,
+				ObjectKind::Video as i32,
+			])]),
+		],
+		[file_path_id.map(file_path::id::gte)],
+	)
+}
@@ -1,101 +1,435 @@
-use sd_prisma::prisma::{object, tag_on_object, PrismaClient};
+use crate::{
+	invalidate_query,
+	library::Library,
+	object::media::old_thumbnail::get_indexed_thumbnail_path,
+	old_job::{
+		CurrentStep, JobError, JobInitOutput, JobReportUpdate, JobResult, JobRunMetadata,
+		JobStepOutput, StatefulJob, WorkerContext,
+	},
+};
 
-use std::{sync::Arc, time::Duration};
+use sd_core_heavy_lifting::media_processor::WAVEFORM_EXTENSION;
 
-use tokio::{
-	select,
-	sync::mpsc,
-	time::{interval_at, Instant, MissedTickBehavior},
+use sd_prisma::{
+	prisma::{
+		crdt_operation, exif_data, ffmpeg_data, file_path, label_on_object, object,
+		object_embedding, object_in_album, object_in_space, object_text, tag_on_object, transcript,
+		SortOrder,
+	},
+	prisma_sync,
 };
-use tracing::{error, trace};
+use sd_sync::OperationFactory;
+use sd_utils::{error::FileIOError, msgpack};
+
+use std::collections::{HashMap, HashSet};
 
-const TEN_SECONDS: Duration = Duration::from_secs(10);
-const ONE_MINUTE: Duration = Duration::from_secs(60);
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use specta::Type;
+use tokio::{fs, io};
+use tracing::{debug, info, warn};
 
-// Actor that can be invoked to find and delete objects with no matching file paths
-#[derive(Clone)]
-#[allow(dead_code)]
-pub struct OrphanRemoverActor {
-	tx: mpsc::Sender<()>,
+// Objects are removed in batches, each one in a few queries
+const BATCH_SIZE: usize = 512;
+
+/// `OldOrphanRemoverJobInit` removes the objects of the library left without any file path, along
+/// with their metadata and the thumbnails of their content. Objects other instances never heard
+/// of, like the ones of excluded locations, are removed from this instance only. The others are
+/// removed with synced deletions, so other instances drop them too, but only while no other
+/// instance exists: their file paths in locations they don't sync could still use them, so those
+/// objects are kept. With `dry_run`, orphans are only counted in the job report.
+#[derive(Serialize, Deserialize, Hash, Type, Debug)]
+pub struct OldOrphanRemoverJobInit {
+	#[serde(default)]
+	pub dry_run: bool,
 }
 
-impl OrphanRemoverActor {
-	#[allow(dead_code)]
-	pub fn spawn(db: Arc<PrismaClient>) -> Self {
-		let (tx, mut rx) = mpsc::channel(4);
-
-		tokio::spawn(async move {
-			let mut last_checked = Instant::now();
-
-			let mut check_interval = interval_at(Instant::now() + ONE_MINUTE, ONE_MINUTE);
-			check_interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
-
-			loop {
-				// Here we wait for a signal or for the tick interval to be reached
-				select! {
-					_ =  check_interval.tick() => {}
-					signal = rx.recv() => {
-						if signal.is_none() {
-							break;
-						}
-					}
-				}
+/// Rows removed along with the objects
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct ReclaimedMetadata {
+	tags: usize,
+	labels: usize,
+	exif_data: usize,
+	ffmpeg_data: usize,
+	texts: usize,
+	embeddings: usize,
+	transcripts: usize,
+}
 
-				// For any of them we process a clean up if a time since the last one already passed
-				if last_checked.elapsed() > TEN_SECONDS {
-					Self::process_clean_up(&db).await;
-					last_checked = Instant::now();
-				}
-			}
-		});
+impl ReclaimedMetadata {
+	fn add(&mut self, other: Self) {
+		self.tags += other.tags;
+		self.labels += other.labels;
+		self.exif_data += other.exif_data;
+		self.ffmpeg_data += other.ffmpeg_data;
+		self.texts += other.texts;
+		self.embeddings += other.embeddings;
+		self.transcripts += other.transcripts;
+	}
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct OldOrphanRemoverJobRunMetadata {
+	cursor: object::id::Type,
+	total_orphans: usize,
+	/// Orphans here that other instances may still use
+	kept_objects: usize,
+	removed_objects: usize,
+	reclaimed_metadata: ReclaimedMetadata,
+	removed_thumbnails: usize,
+	reclaimed_thumbnail_bytes: u64,
+}
 
-		Self { tx }
+impl JobRunMetadata for OldOrphanRemoverJobRunMetadata {
+	fn update(&mut self, new_data: Self) {
+		self.cursor = new_data.cursor;
+		self.total_orphans += new_data.total_orphans;
+		self.kept_objects += new_data.kept_objects;
+		self.removed_objects += new_data.removed_objects;
+		self.reclaimed_metadata.add(new_data.reclaimed_metadata);
+		self.removed_thumbnails += new_data.removed_thumbnails;
+		self.reclaimed_thumbnail_bytes += new_data.reclaimed_thumbnail_bytes;
 	}
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for OldOrphanRemoverJobInit {
+	type Data = ();
+	type Step = ();
+	type RunMetadata = OldOrphanRemoverJobRunMetadata;
 
-	#[allow(dead_code)]
-	pub async fn invoke(&self) {
-		self.tx.send(()).await.ok();
+	const NAME: &'static str = "orphan_remover";
+	const IS_BATCHED: bool = true;
+
+	fn target_location(&self) -> Option<sd_prisma::prisma::location::id::Type> {
+		None
 	}
 
-	#[allow(dead_code)]
-	async fn process_clean_up(db: &PrismaClient) {
-		loop {
-			let Ok(objects_ids) = db
-				.object()
-				.find_many(vec![object::file_paths::none(vec![])])
-				.take(512)
-				.select(object::select!({ id }))
-				.exec()
-				.await
-				.map(|objects| {
-					objects
-						.into_iter()
-						.map(|object| object.id)
-						.collect::<Vec<_>>()
-				})
-				.map_err(|e| error!("Failed to fetch orphaned objects: {e:#?}"))
-			else {
-				break;
-			};
-
-			if objects_ids.is_empty() {
-				break;
+	async fn init(
+		&self,
+		ctx: &WorkerContext,
+		data: &mut Option<Self::Data>,
+	) -> Result<JobInitOutput<Self::RunMetadata, Self::Step>, JobError> {
+		let Library { db, .. } = &*ctx.library;
+
+		// Must fill in the data, otherwise the job will not run
+		*data = Some(());
+
+		// Operations received from other instances but not ingested yet may link file paths to
+		// objects that look orphaned here
+		if db.cloud_crdt_operation().count(vec![]).exec().await? > 0 {
+			return Err(JobError::EarlyFinish {
+				name: <Self as StatefulJob>::NAME.to_string(),
+				reason: "Sync operations are still waiting to be ingested".to_string(),
+			});
+		}
+
+		let orphans_count = db
+			.object()
+			.count(vec![object::file_paths::none(vec![])])
+			.exec()
+			.await? as usize;
+
+		if orphans_count == 0 {
+			return Err(JobError::EarlyFinish {
+				name: <Self as StatefulJob>::NAME.to_string(),
+				reason: "Found no orphan objects".to_string(),
+			});
+		}
+
+		let task_count = orphans_count.div_ceil(BATCH_SIZE);
+		debug!("Found {orphans_count} orphan objects. Will execute {task_count} tasks...");
+
+		ctx.progress(vec![
+			JobReportUpdate::TaskCount(orphans_count),
+			JobReportUpdate::Message(format!("Found {orphans_count} orphan objects")),
+		]);
+
+		Ok(vec![(); task_count].into())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: &WorkerContext,
+		CurrentStep { step_number, .. }: CurrentStep<'_, Self::Step>,
+		_: &Self::Data,
+		run_metadata: &Self::RunMetadata,
+	) -> Result<JobStepOutput<Self::Step, Self::RunMetadata>, JobError> {
+		let Library { db, sync, id, .. } = &*ctx.library;
+
+		let orphans = db
+			.object()
+			.find_many(vec![
+				object::file_paths::none(vec![]),
+				object::id::gte(run_metadata.cursor),
+			])
+			.order_by(object::id::order(SortOrder::Asc))
+			.take(BATCH_SIZE as i64)
+			.select(object::select!({
+				id
+				pub_id
+				tags: select { tag: select { pub_id } }
+				labels: select { label: select { name } }
+			}))
+			.exec()
+			.await?;
+
+		let Some(last_orphan) = orphans.last() else {
+			// Orphans were linked or removed since we counted them, nothing left to do
+			return Ok(OldOrphanRemoverJobRunMetadata {
+				cursor: run_metadata.cursor,
+				..Default::default()
+			}
+			.into());
+		};
+
+		let cursor = last_orphan.id + 1;
+		let total_orphans = orphans.len();
+
+		let record_ids = orphans
+			.iter()
+			.map(|orphan| {
+				(
+					rmp_serde::to_vec(&msgpack!(prisma_sync::object::SyncId {
+						pub_id: orphan.pub_id.clone()
+					}))
+					.expect("failed to serialize msgpack"),
+					orphan.pub_id.clone(),
+				)
+			})
+			.collect::<HashMap<_, _>>();
+
+		// Objects without operations never left this instance
+		let synced = db
+			.crdt_operation()
+			.find_many(vec![
+				crdt_operation::model::equals(i32::from(prisma_sync::object::MODEL_ID)),
+				crdt_operation::record_id::in_vec(record_ids.keys().cloned().collect()),
+			])
+			.select(crdt_operation::select!({ record_id }))
+			.exec()
+			.await?
+			.into_iter()
+			.filter_map(|op| record_ids.get(&op.record_id).cloned())
+			.collect::<HashSet<_>>();
+
+		// Other instances don't sync the file paths of their excluded locations, which may use
+		// objects orphaned here, so only without other instances are they orphaned everywhere
+		let orphaned_everywhere = db.instance().count(vec![]).exec().await? <= 1;
+
+		let (orphans, kept) = orphans.into_iter().partition::<Vec<_>, _>(|orphan| {
+			orphaned_everywhere || !synced.contains(&orphan.pub_id)
+		});
+
+		if !kept.is_empty() {
+			debug!(
+				"Keeping {} orphan objects other instances may still use",
+				kept.len()
+			);
+		}
+
+		let object_ids = orphans.iter().map(|orphan| orphan.id).collect::<Vec<_>>();
+
+		let (exif_data, ffmpeg_data, texts, embeddings, transcripts) = db
+			._batch((
+				db.exif_data()
+					.count(vec![exif_data::object_id::in_vec(object_ids.clone())]),
+				db.ffmpeg_data()
+					.count(vec![ffmpeg_data::object_id::in_vec(object_ids.clone())]),
+				db.object_text()
+					.find_many(vec![object_text::object_id::in_vec(object_ids.clone())])
+					.select(object_text::select!({ cas_id })),
+				db.object_embedding()
+					.find_many(vec![object_embedding::object_id::in_vec(
+						object_ids.clone(),
+					)])
+					.select(object_embedding::select!({ cas_id })),
+				db.transcript()
+					.count(vec![transcript::object_id::in_vec(object_ids.clone())]),
+			))
+			.await?;
+
+		let reclaimed_metadata = ReclaimedMetadata {
+			tags: orphans.iter().map(|orphan| orphan.tags.len()).sum(),
+			labels: orphans.iter().map(|orphan| orphan.labels.len()).sum(),
+			exif_data: exif_data as usize,
+			ffmpeg_data: ffmpeg_data as usize,
+			texts: texts.len(),
+			embeddings: embeddings.len(),
+			transcripts: transcripts as usize,
+		};
+
+		// Objects don't know their content, only what was extracted from it does
+		let cas_ids = texts
+			.into_iter()
+			.filter_map(|text| text.cas_id)
+			.chain(
+				embeddings
+					.into_iter()
+					.filter_map(|embedding| embedding.cas_id),
+			)
+			.collect::<HashSet<_>>();
+
+		// Thumbnails are shared by every file path with the same content
+		let still_used = db
+			.file_path()
+			.find_many(vec![file_path::cas_id::in_vec(
+				cas_ids.iter().cloned().collect(),
+			)])
+			.select(file_path::select!({ cas_id }))
+			.exec()
+			.await?
+			.into_iter()
+			.filter_map(|file_path| file_path.cas_id)
+			.collect::<HashSet<_>>();
+
+		let mut thumbnails = vec![];
+		for cas_id in cas_ids.difference(&still_used) {
+			let thumbnail_path = get_indexed_thumbnail_path(&ctx.node, cas_id, *id);
+			// Videos also have their preview strips beside their thumbnails, and audio files their
+			// waveforms
+			for path in [
+				thumbnail_path.clone(),
+				thumbnail_path.with_file_name(format!("{cas_id}.strip.webp")),
+				thumbnail_path.with_file_name(format!("{cas_id}.{WAVEFORM_EXTENSION}")),
+			] {
+				match fs::metadata(&path).await {
+					Ok(metadata) => thumbnails.push((path, metadata.len())),
+					Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+					Err(e) => return Err(FileIOError::from((&path, e)).into()),
+				}
 			}
+		}
+
+		let removed_objects = if self.dry_run || orphans.is_empty() {
+			0
+		} else {
+			let (tag_ops, label_ops) = orphans
+				.iter()
+				.filter(|orphan| synced.contains(&orphan.pub_id))
+				.fold((vec![], vec![]), |(mut tag_ops, mut label_ops), orphan| {
+					let object = || prisma_sync::object::SyncId {
+						pub_id: orphan.pub_id.clone(),
+					};
+
+					tag_ops.extend(orphan.tags.iter().map(|tag_on_object| {
+						sync.relation_delete(prisma_sync::tag_on_object::SyncId {
+							tag: prisma_sync::tag::SyncId {
+								pub_id: tag_on_object.tag.pub_id.clone(),
+							},
+							object: object(),
+						})
+					}));
 
-			trace!("Removing {} orphaned objects", objects_ids.len());
+					label_ops.extend(orphan.labels.iter().map(|label_on_object| {
+						sync.relation_delete(prisma_sync::label_on_object::SyncId {
+							label: prisma_sync::label::SyncId {
+								name: label_on_object.label.name.clone(),
+							},
+							object: object(),
+						})
+					}));
 
-			if let Err(e) = db
-				._batch((
+					(tag_ops, label_ops)
+				});
+
+			// Objects can't go while tagged, labeled or in a space or album
+			sync.write_ops(
+				db,
+				(
+					tag_ops,
 					db.tag_on_object()
-						.delete_many(vec![tag_on_object::object_id::in_vec(objects_ids.clone())]),
-					db.object()
-						.delete_many(vec![object::id::in_vec(objects_ids)]),
-				))
-				.await
-			{
-				error!("Failed to remove orphaned objects: {e:#?}");
-				break;
+						.delete_many(vec![tag_on_object::object_id::in_vec(object_ids.clone())]),
+				),
+			)
+			.await?;
+
+			sync.write_ops(
+				db,
+				(
+					label_ops,
+					db.label_on_object()
+						.delete_many(vec![label_on_object::object_id::in_vec(object_ids.clone())]),
+				),
+			)
+			.await?;
+
+			db._batch((
+				db.object_in_space()
+					.delete_many(vec![object_in_space::object_id::in_vec(object_ids.clone())]),
+				db.object_in_album()
+					.delete_many(vec![object_in_album::object_id::in_vec(object_ids.clone())]),
+			))
+			.await?;
+
+			// Deletions are kept as tombstones, so other instances remove the objects as well
+			let removed_objects = sync
+				.write_ops(
+					db,
+					(
+						orphans
+							.iter()
+							.filter(|orphan| synced.contains(&orphan.pub_id))
+							.map(|orphan| {
+								sync.shared_delete(prisma_sync::object::SyncId {
+									pub_id: orphan.pub_id.clone(),
+								})
+							})
+							.collect(),
+						db.object().delete_many(vec![
+							object::id::in_vec(object_ids),
+							object::file_paths::none(vec![]),
+						]),
+					),
+				)
+				.await? as usize;
+
+			for (path, _) in &thumbnails {
+				if let Err(e) = fs::remove_file(path).await {
+					warn!(
+						"Failed to remove thumbnail of orphan object <path='{}'>: {e:#?}",
+						path.display()
+					);
+				}
 			}
+
+			removed_objects
+		};
+
+		let completed = step_number * BATCH_SIZE + total_orphans;
+
+		ctx.progress(vec![
+			JobReportUpdate::CompletedTaskCount(completed),
+			JobReportUpdate::Message(format!("Went through {completed} orphan objects")),
+		]);
+
+		Ok(OldOrphanRemoverJobRunMetadata {
+			cursor,
+			total_orphans,
+			kept_objects: kept.len(),
+			removed_objects,
+			reclaimed_metadata,
+			removed_thumbnails: thumbnails.len(),
+			reclaimed_thumbnail_bytes: thumbnails.iter().map(|(_, size)| size).sum(),
 		}
+		.into())
+	}
+
+	async fn finalize(
+		&self,
+		ctx: &WorkerContext,
+		_data: &Option<Self::Data>,
+		run_metadata: &Self::RunMetadata,
+	) -> JobResult {
+		let init = self;
+		info!("Finalizing orphan remover job: {run_metadata:?}");
+
+		if run_metadata.removed_objects > 0 {
+			invalidate_query!(ctx.library, "search.objects");
+			invalidate_query!(ctx.library, "tags.list");
+			invalidate_query!(ctx.library, "labels.list");
+		}
+
+		Ok(Some(json!({ "init": init, "run_metadata": run_metadata })))
 	}
 }
@@ -0,0 +1,121 @@
+use sd_prisma::prisma::{tag, PrismaClient};
+
+use std::collections::{HashMap, HashSet};
+
+/// The tags with every tag nested under them, at any depth
+pub async fn with_descendants(
+	tag_ids: Vec<tag::id::Type>,
+	db: &PrismaClient,
+) -> prisma_client_rust::Result<Vec<tag::id::Type>> {
+	if tag_ids.is_empty() {
+		return Ok(tag_ids);
+	}
+
+	Ok(descendants(&parents(db).await?, &tag_ids))
+}
+
+/// Parent of every tag of the library
+pub async fn parents(
+	db: &PrismaClient,
+) -> prisma_client_rust::Result<Vec<(tag::id::Type, Option<tag::id::Type>)>> {
+	Ok(db
+		.tag()
+		.find_many(vec![])
+		.select(tag::select!({ id parent_id }))
+		.exec()
+		.await?
+		.into_iter()
+		.map(|tag| (tag.id, tag.parent_id))
+		.collect())
+}
+
+/// `roots` and the tags nested under them, at any depth
+pub fn descendants(
+	parents: &[(tag::id::Type, Option<tag::id::Type>)],
+	roots: &[tag::id::Type],
+) -> Vec<tag::id::Type> {
+	let mut children = HashMap::<_, Vec<_>>::new();
+	for &(id, parent_id) in parents {
+		if let Some(parent_id) = parent_id {
+			children.entry(parent_id).or_default().push(id);
+		}
+	}
+
+	let mut found = roots.iter().copied().collect::<HashSet<_>>();
+	let mut pending = roots.to_vec();
+
+	while let Some(id) = pending.pop() {
+		for &child in children.get(&id).into_iter().flatten() {
+			// Inserting first also keeps a cycle in a corrupt hierarchy from looping forever
+			if found.insert(child) {
+				pending.push(child);
+			}
+		}
+	}
+
+	let mut found = found.into_iter().collect::<Vec<_>>();
+	found.sort_unstable();
+	found
+}
+
+/// Whether nesting `tag_id` under `parent_id` would make a tag its own ancestor
+pub fn would_cycle(
+	parents: &[(tag::id::Type, Option<tag::id::Type>)],
+	tag_id: tag::id::Type,
+	parent_id: tag::id::Type,
+) -> bool {
+	let parents = parents.iter().copied().collect::<HashMap<_, _>>();
+
+	let mut seen = HashSet::new();
+	let mut current = Some(parent_id);
+
+	while let Some(id) = current {
+		if id == tag_id || !seen.insert(id) {
+			return true;
+		}
+
+		current = parents.get(&id).copied().flatten();
+	}
+
+	false
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// 1 ─┬─ 2 ── 3
+	//    └─ 4
+	// 5
+	const TAGS: &[(i32, Option<i32>)] = &[
+		(1, None),
+		(2, Some(1)),
+		(3, Some(2)),
+		(4, Some(1)),
+		(5, None),
+	];
+
+	#[test]
+	fn descendants_are_found_at_any_depth() {
+		assert_eq!(descendants(TAGS, &[1]), vec![1, 2, 3, 4]);
+		assert_eq!(descendants(TAGS, &[2, 5]), vec![2, 3, 5]);
+		assert_eq!(descendants(TAGS, &[3]), vec![3]);
+	}
+
+	#[test]
+	fn tags_cant_nest_under_themselves() {
+		assert!(would_cycle(TAGS, 1, 1));
+		assert!(would_cycle(TAGS, 1, 3));
+		assert!(would_cycle(TAGS, 2, 3));
+		assert!(!would_cycle(TAGS, 3, 4));
+		assert!(!would_cycle(TAGS, 5, 3));
+	}
+
+	#[test]
+	fn corrupt_hierarchies_dont_loop() {
+		let tags = [(1, Some(2)), (2, Some(1)), (3, None)];
+
+		assert_eq!(descendants(&tags, &[1]), vec![1, 2]);
+		assert!(would_cycle(&tags, 3, 1));
+	}
+}
@@ -10,6 +10,7 @@ use serde::Deserialize;
 use specta::Type;
 use uuid::Uuid;
 
+pub mod hierarchy;
 pub mod seed;
 
 #[derive(Type, Deserialize, Clone)]
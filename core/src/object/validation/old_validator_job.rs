@@ -63,8 +63,8 @@ impl StatefulJob for OldObjectValidatorJobInit {
 
 	const NAME: &'static str = "object_validator";
 
-	fn target_location(&self) -> location::id::Type {
-		self.location.id
+	fn target_location(&self) -> Option<location::id::Type> {
+		Some(self.location.id)
 	}
 
 	async fn init(
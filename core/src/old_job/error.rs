@@ -2,7 +2,8 @@ use crate::{
 	location::{indexer::IndexerError, LocationError},
 	object::{
 		fs::error::FileSystemJobsError, media::old_media_processor::MediaProcessorError,
-		old_file_identifier::FileIdentifierJobError, validation::ValidatorError,
+		old_file_identifier::FileIdentifierJobError, old_kind_reidentifier::KindReidentifierError,
+		old_mtp_importer::MtpImporterError, validation::ValidatorError,
 	},
 };
 
@@ -65,6 +66,10 @@ pub enum JobError {
 	#[error(transparent)]
 	Validator(#[from] ValidatorError),
 	#[error(transparent)]
+	KindReidentifier(#[from] KindReidentifierError),
+	#[error(transparent)]
+	MtpImporter(#[from] MtpImporterError),
+	#[error(transparent)]
 	FileSystemJobsError(#[from] FileSystemJobsError),
 	// #[error(transparent)]
 	// CryptoError(#[from] CryptoError),
@@ -93,6 +98,9 @@ pub enum JobManagerError {
 
 	#[error("missing-field: {0}")]
 	MissingField(#[from] MissingFieldError),
+
+	#[error("library <id='{0}'> is read-only")]
+	ReadOnlyLibrary(Uuid),
 }
 
 impl From<JobManagerError> for rspc::Error {
@@ -118,6 +126,11 @@ impl From<JobManagerError> for rspc::Error {
 				"Missing field".to_string(),
 				value,
 			),
+			JobManagerError::ReadOnlyLibrary(_) => Self::with_cause(
+				rspc::ErrorCode::Forbidden,
+				"This library is read-only on this node.".to_string(),
+				value,
+			),
 		}
 	}
 }
@@ -0,0 +1,220 @@
+use crate::library::Library;
+
+use sd_core_heavy_lifting::job_system::{report::Report, LIBRARY_WIDE};
+
+use sd_prisma::prisma::{job_history, location, PrismaClient};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tracing::error;
+use uuid::Uuid;
+
+use super::{DynJob, JobError, JobReport, JobStatus};
+
+/// Who or what started a job, kept in the report metadata under the `trigger` key so it survives
+/// pauses and is inherited by the queued children
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, Type, Eq, PartialEq)]
+pub enum JobTrigger {
+	/// Started from the interface, the default as almost every job is
+	#[default]
+	User = 0,
+	/// Started by the location watcher reacting to changes on disk
+	Watcher = 1,
+	/// Delegated by a paired node, as the location is attached to this one
+	Peer = 2,
+}
+
+impl TryFrom<i32> for JobTrigger {
+	type Error = i32;
+
+	fn try_from(value: i32) -> Result<Self, Self::Error> {
+		match value {
+			0 => Ok(Self::User),
+			1 => Ok(Self::Watcher),
+			2 => Ok(Self::Peer),
+			_ => Err(value),
+		}
+	}
+}
+
+/// A finished job run, as stored in the `job_history` table
+#[derive(Debug, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct JobHistoryEntry {
+	pub id: job_history::id::Type,
+	pub job_id: Uuid,
+	pub name: String,
+	pub action: Option<String>,
+	pub location_id: Option<location::id::Type>,
+	pub status: JobStatus,
+	pub trigger: Option<JobTrigger>,
+	/// The init of the job, e.g. the location and sub path it ran on
+	pub parameters: Option<serde_json::Value>,
+	/// The report metadata, with the output counts of the job under the `output` key, or the list of
+	/// input and output metadata for jobs of the job system
+	pub metadata: Option<serde_json::Value>,
+	pub errors: Vec<String>,
+	pub task_count: i32,
+	pub completed_task_count: i32,
+	pub started_at: Option<DateTime<Utc>>,
+	pub completed_at: DateTime<Utc>,
+	pub duration_ms: Option<f64>,
+}
+
+impl From<job_history::Data> for JobHistoryEntry {
+	fn from(data: job_history::Data) -> Self {
+		fn from_json<T: Default + serde::de::DeserializeOwned>(
+			bytes: Option<Vec<u8>>,
+			field: &str,
+		) -> T {
+			bytes
+				.map(|bytes| {
+					serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+						error!("Failed to deserialize job history {field}: {e:#?}");
+						T::default()
+					})
+				})
+				.unwrap_or_default()
+		}
+
+		Self {
+			id: data.id,
+			job_id: Uuid::from_slice(&data.job_id).expect("corrupted database"),
+			name: data.name,
+			action: data.action,
+			location_id: data.location_id,
+			status: JobStatus::try_from(data.status).expect("corrupted database"),
+			trigger: data
+				.trigger
+				.and_then(|trigger| JobTrigger::try_from(trigger).ok()),
+			parameters: from_json(data.parameters, "parameters"),
+			metadata: from_json(data.metadata, "metadata"),
+			errors: from_json(data.errors, "errors"),
+			task_count: data.task_count,
+			completed_task_count: data.completed_task_count,
+			started_at: data.date_started.map(DateTime::into),
+			completed_at: data.date_completed.into(),
+			duration_ms: data.duration_ms.map(|duration_ms| duration_ms as f64),
+		}
+	}
+}
+
+/// Stores a run of `job` that reached a final status, `critical_error` being the error it
+/// failed with, if any
+pub(super) async fn record(
+	job: &dyn DynJob,
+	report: &JobReport,
+	critical_error: Option<String>,
+	library: &Library,
+) -> Result<(), JobError> {
+	let completed_at = report.completed_at.unwrap_or_else(Utc::now);
+
+	let errors = report
+		.errors_text
+		.iter()
+		.cloned()
+		.chain(critical_error)
+		.collect::<Vec<_>>();
+
+	library
+		.db
+		.job_history()
+		.create(
+			report.id.as_bytes().to_vec(),
+			report.name.clone(),
+			report.status as i32,
+			report.task_count,
+			report.completed_task_count,
+			completed_at.into(),
+			vec![
+				job_history::action::set(report.action.clone()),
+				job_history::trigger::set(report.trigger().map(|trigger| trigger as i32)),
+				job_history::parameters::set(job.parameters().map(serde_json::to_vec).transpose()?),
+				job_history::metadata::set(
+					report
+						.metadata
+						.as_ref()
+						.map(serde_json::to_vec)
+						.transpose()?,
+				),
+				job_history::errors::set(
+					(!errors.is_empty())
+						.then(|| serde_json::to_vec(&errors))
+						.transpose()?,
+				),
+				job_history::date_started::set(report.started_at.map(Into::into)),
+				job_history::duration_ms::set(
+					report
+						.started_at
+						.map(|started_at| (completed_at - started_at).num_milliseconds()),
+				),
+			]
+			.into_iter()
+			.chain(job.target_location().map(|location_id| {
+				job_history::location::connect(location::id::equals(location_id))
+			}))
+			.collect(),
+		)
+		.exec()
+		.await?;
+
+	Ok(())
+}
+
+/// Stores a run of the job system that reached a final status, on `location_id` unless it ran
+/// [`LIBRARY_WIDE`]
+pub(crate) async fn record_job_system_run(
+	db: &PrismaClient,
+	report: &Report,
+	location_id: location::id::Type,
+) -> Result<(), JobError> {
+	let completed_at = report.completed_at.unwrap_or_else(Utc::now);
+
+	let errors = report
+		.non_critical_errors
+		.iter()
+		.cloned()
+		.chain(report.critical_error.clone())
+		.collect::<Vec<_>>();
+
+	db.job_history()
+		.create(
+			report.id.as_bytes().to_vec(),
+			report.name.to_string(),
+			report.status as i32,
+			report.task_count,
+			report.completed_task_count,
+			completed_at.into(),
+			vec![
+				job_history::action::set(report.action.clone()),
+				job_history::metadata::set(
+					(!report.metadata.is_empty())
+						.then(|| serde_json::to_vec(&report.metadata))
+						.transpose()?,
+				),
+				job_history::errors::set(
+					(!errors.is_empty())
+						.then(|| serde_json::to_vec(&errors))
+						.transpose()?,
+				),
+				job_history::date_started::set(report.started_at.map(Into::into)),
+				job_history::duration_ms::set(
+					report
+						.started_at
+						.map(|started_at| (completed_at - started_at).num_milliseconds()),
+				),
+			]
+			.into_iter()
+			.chain(
+				(location_id != LIBRARY_WIDE)
+					.then(|| job_history::location::connect(location::id::equals(location_id))),
+			)
+			.collect(),
+		)
+		.exec()
+		.await?;
+
+	Ok(())
+}
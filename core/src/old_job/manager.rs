@@ -1,13 +1,17 @@
 use crate::{
-	library::Library,
+	library::{sync_backfill::OldSyncBackfillJobInit, Library},
 	location::indexer::old_indexer_job::OldIndexerJobInit,
 	object::{
 		fs::{
-			old_copy::OldFileCopierJobInit, old_cut::OldFileCutterJobInit,
-			old_delete::OldFileDeleterJobInit, old_erase::OldFileEraserJobInit,
+			old_cleanup::OldCleanupJobInit, old_copy::OldFileCopierJobInit,
+			old_cut::OldFileCutterJobInit, old_delete::OldFileDeleterJobInit,
+			old_erase::OldFileEraserJobInit, old_hydrate::OldFileHydratorJobInit,
 		},
 		media::old_media_processor::OldMediaProcessorJobInit,
 		old_file_identifier::old_file_identifier_job::OldFileIdentifierJobInit,
+		old_kind_reidentifier::OldKindReidentifierJobInit,
+		old_mtp_importer::OldMtpImporterJobInit,
+		old_orphan_remover::OldOrphanRemoverJobInit,
 		validation::old_validator_job::OldObjectValidatorJobInit,
 	},
 	old_job::{worker::Worker, DynJob, Job, JobError},
@@ -104,6 +108,10 @@ impl OldJobs {
 		library: &Arc<Library>,
 		job: Box<Job<impl StatefulJob>>,
 	) -> Result<(), JobManagerError> {
+		if library.is_read_only().await {
+			return Err(JobManagerError::ReadOnlyLibrary(library.id));
+		}
+
 		let job_hash = job.hash();
 
 		if self.current_jobs_hashes.read().await.contains(&job_hash) {
@@ -271,6 +279,12 @@ impl OldJobs {
 		node: &Arc<Node>,
 		library: &Arc<Library>,
 	) -> Result<(), JobManagerError> {
+		// Left as they are, they're resumed once the library can be changed again
+		if library.is_read_only().await {
+			info!("Not resuming jobs of read-only library: {}", library.id);
+			return Ok(());
+		}
+
 		// Include the Queued status in the initial find condition
 		let find_condition = vec![or(vec![
 			job::status::equals(Some(JobStatus::Paused as i32)),
@@ -400,10 +414,16 @@ fn initialize_resumable_job(
 			OldIndexerJobInit,
 			OldFileIdentifierJobInit,
 			OldObjectValidatorJobInit,
+			OldKindReidentifierJobInit,
+			OldMtpImporterJobInit,
 			OldFileCutterJobInit,
 			OldFileCopierJobInit,
 			OldFileDeleterJobInit,
 			OldFileEraserJobInit,
+			OldFileHydratorJobInit,
+			OldCleanupJobInit,
+			OldOrphanRemoverJobInit,
+			OldSyncBackfillJobInit,
 		]
 	)
 }
@@ -20,15 +20,18 @@ use tokio::{
 	spawn,
 	task::{JoinError, JoinHandle},
 };
-use tracing::{debug, error, info, trace, warn};
+use tracing::{debug, debug_span, error, info, trace, warn, Instrument};
 use uuid::Uuid;
 
 mod error;
+mod history;
 mod manager;
 mod report;
 mod worker;
 
 pub use error::*;
+pub(crate) use history::record_job_system_run;
+pub use history::{JobHistoryEntry, JobTrigger};
 pub use manager::*;
 pub use report::*;
 pub use worker::*;
@@ -40,7 +43,7 @@ pub type JobMetadata = Option<serde_json::Value>;
 pub struct JobIdentity {
 	pub id: Uuid,
 	pub name: &'static str,
-	pub target_location: location::id::Type,
+	pub target_location: Option<location::id::Type>,
 	pub status: JobStatus,
 }
 
@@ -101,8 +104,8 @@ pub trait StatefulJob:
 		data: &mut Option<Self::Data>,
 	) -> Result<JobInitOutput<Self::RunMetadata, Self::Step>, JobError>;
 
-	/// The location id where this job will act upon
-	fn target_location(&self) -> location::id::Type;
+	/// The location id where this job will act upon, if it acts upon one
+	fn target_location(&self) -> Option<location::id::Type>;
 
 	/// is called for each step in the job. These steps are created in the `Self::init` method.
 	async fn execute_step(
@@ -136,6 +139,10 @@ pub trait DynJob: Send + Sync {
 	fn report(&self) -> &Option<JobReport>;
 	fn report_mut(&mut self) -> &mut Option<JobReport>;
 	fn name(&self) -> &'static str;
+	/// The location id where this job acts upon, see [`StatefulJob::target_location`]
+	fn target_location(&self) -> Option<location::id::Type>;
+	/// The init of the job as JSON, recorded in its history
+	fn parameters(&self) -> Option<&serde_json::Value>;
 	async fn run(
 		&mut self,
 		ctx: WorkerContext,
@@ -160,6 +167,8 @@ impl<SJob: StatefulJob> JobBuilder<SJob> {
 		Box::new(Job::<SJob> {
 			id: self.id,
 			hash: <SJob as StatefulJob>::hash(&self.init),
+			target_location: self.init.target_location(),
+			parameters: serde_json::to_value(&self.init).ok(),
 			report: Some(self.report_builder.build()),
 			state: Some(JobState {
 				init: self.init,
@@ -195,11 +204,18 @@ impl<SJob: StatefulJob> JobBuilder<SJob> {
 		self.report_builder = self.report_builder.with_metadata(metadata);
 		self
 	}
+
+	pub fn with_trigger(mut self, trigger: JobTrigger) -> Self {
+		self.report_builder = self.report_builder.with_trigger(trigger);
+		self
+	}
 }
 
 pub struct Job<SJob: StatefulJob> {
 	id: Uuid,
 	hash: u64,
+	target_location: Option<location::id::Type>,
+	parameters: Option<serde_json::Value>,
 	report: Option<JobReport>,
 	state: Option<JobState<SJob>>,
 	next_jobs: VecDeque<Box<dyn DynJob>>,
@@ -223,6 +239,10 @@ impl<SJob: StatefulJob> Job<SJob> {
 				child_job_builder =
 					child_job_builder.with_action(format!("{parent_action}-{next_job_order}"));
 			}
+
+			if let Some(trigger) = parent_report.trigger() {
+				child_job_builder = child_job_builder.with_trigger(trigger);
+			}
 		}
 
 		self.next_jobs.push_back(child_job_builder.build());
@@ -245,6 +265,8 @@ impl<SJob: StatefulJob> Job<SJob> {
 		Ok(Box::new(Self {
 			id: report.id,
 			hash: <SJob as StatefulJob>::hash(&state.init),
+			target_location: state.init.target_location(),
+			parameters: serde_json::to_value(&state.init).ok(),
 			state: Some(state),
 			report: Some(report),
 			next_jobs: next_jobs.unwrap_or_default(),
@@ -460,6 +482,14 @@ impl<SJob: StatefulJob> DynJob for Job<SJob> {
 		<SJob as StatefulJob>::NAME
 	}
 
+	fn target_location(&self) -> Option<location::id::Type> {
+		self.target_location
+	}
+
+	fn parameters(&self) -> Option<&serde_json::Value> {
+		self.parameters.as_ref()
+	}
+
 	async fn run(
 		&mut self,
 		ctx: WorkerContext,
@@ -498,18 +528,21 @@ impl<SJob: StatefulJob> DynJob for Job<SJob> {
 			let init_time = Instant::now();
 			let init_task = {
 				let ctx = Arc::clone(&ctx);
-				spawn(async move {
-					let mut new_data = None;
-					let res = stateful_job.init(&ctx, &mut new_data).await;
-
-					if let Ok(res) = res.as_ref() {
-						if !<SJob as StatefulJob>::IS_BATCHED {
-							ctx.progress(vec![JobReportUpdate::TaskCount(res.steps.len())]);
+				spawn(
+					async move {
+						let mut new_data = None;
+						let res = stateful_job.init(&ctx, &mut new_data).await;
+
+						if let Ok(res) = res.as_ref() {
+							if !<SJob as StatefulJob>::IS_BATCHED {
+								ctx.progress(vec![JobReportUpdate::TaskCount(res.steps.len())]);
+							}
 						}
-					}
 
-					(stateful_job, new_data, res)
-				})
+						(stateful_job, new_data, res)
+					}
+					.instrument(debug_span!("init")),
+				)
 			};
 
 			let InitPhaseOutput {
@@ -572,19 +605,22 @@ impl<SJob: StatefulJob> DynJob for Job<SJob> {
 					let working_data = Arc::clone(&working_data_arc);
 					let step = Arc::clone(&step);
 					let stateful_job = Arc::clone(&stateful_job);
-					spawn(async move {
-						stateful_job
-							.execute_step(
-								&ctx,
-								CurrentStep {
-									step: &step,
-									step_number,
-								},
-								&working_data,
-								&run_metadata,
-							)
-							.await
-					})
+					spawn(
+						async move {
+							stateful_job
+								.execute_step(
+									&ctx,
+									CurrentStep {
+										step: &step,
+										step_number,
+									},
+									&working_data,
+									&run_metadata,
+								)
+								.await
+						}
+						.instrument(debug_span!("step", step_number)),
+					)
 				};
 
 				let JobStepsPhaseOutput {
@@ -778,7 +814,7 @@ struct JobRunWorkTable {
 	id: Uuid,
 	name: &'static str,
 	init_time: Instant,
-	target_location: location::id::Type,
+	target_location: Option<location::id::Type>,
 }
 
 type InitTaskOutput<SJob> = (
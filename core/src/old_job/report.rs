@@ -12,11 +12,12 @@ use std::{
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use specta::Type;
 use tracing::error;
 use uuid::Uuid;
 
-use super::JobError;
+use super::{JobError, JobTrigger};
 
 #[derive(Debug)]
 pub enum JobReportUpdate {
@@ -24,6 +25,8 @@ pub enum JobReportUpdate {
 	CompletedTaskCount(usize),
 	Message(String),
 	Phase(String),
+	/// Size of the content hashed since the last update, for the throughput of the job
+	HashedBytes(u64),
 }
 
 #[derive(Debug, Serialize, Deserialize, Type, Clone)]
@@ -186,6 +189,14 @@ impl JobReport {
 		(action_name, Some(group_key))
 	}
 
+	/// Who or what started the job, `None` for jobs created before triggers were recorded
+	pub fn trigger(&self) -> Option<JobTrigger> {
+		self.metadata
+			.as_ref()
+			.and_then(|metadata| metadata.get("trigger"))
+			.and_then(|trigger| serde_json::from_value(trigger.clone()).ok())
+	}
+
 	pub async fn create(&mut self, library: &Library) -> Result<(), JobError> {
 		let now = Utc::now();
 
@@ -292,10 +303,21 @@ pub struct JobReportBuilder {
 	pub action: Option<String>,
 	pub metadata: Option<serde_json::Value>,
 	pub parent_id: Option<Uuid>,
+	pub trigger: JobTrigger,
 }
 
 impl JobReportBuilder {
 	pub fn build(self) -> JobReport {
+		let metadata = match self.metadata {
+			Some(serde_json::Value::Object(mut metadata)) => {
+				metadata.insert("trigger".to_string(), json!(self.trigger));
+				serde_json::Value::Object(metadata)
+			}
+			// Non object metadata is kept as is, the trigger is only lost for these
+			Some(metadata) => metadata,
+			None => json!({ "trigger": self.trigger }),
+		};
+
 		JobReport {
 			id: self.id,
 			name: self.name,
@@ -307,7 +329,7 @@ impl JobReportBuilder {
 			errors_text: vec![],
 			task_count: 0,
 			data: None,
-			metadata: self.metadata,
+			metadata: Some(metadata),
 			parent_id: self.parent_id,
 			completed_task_count: 0,
 			phase: String::new(),
@@ -323,6 +345,7 @@ impl JobReportBuilder {
 			action: None,
 			metadata: None,
 			parent_id: None,
+			trigger: JobTrigger::default(),
 		}
 	}
 
@@ -340,4 +363,9 @@ impl JobReportBuilder {
 		self.parent_id = Some(parent_id);
 		self
 	}
+
+	pub fn with_trigger(mut self, trigger: JobTrigger) -> Self {
+		self.trigger = trigger;
+		self
+	}
 }
@@ -1,4 +1,11 @@
-use crate::{api::CoreEvent, invalidate_query, library::Library, Node};
+use crate::{
+	api::CoreEvent,
+	invalidate_query,
+	library::{rollups, Library},
+	Node,
+};
+
+use sd_core_heavy_lifting::JobProgressMetrics;
 
 use std::{
 	fmt,
@@ -24,11 +31,11 @@ use tokio::{
 	time::{interval, timeout, Instant, MissedTickBehavior},
 };
 use tokio_stream::wrappers::IntervalStream;
-use tracing::{debug, error, info, trace, warn};
+use tracing::{debug, error, info, info_span, trace, warn, Instrument};
 use uuid::Uuid;
 
 use super::{
-	DynJob, JobError, JobIdentity, JobReport, JobReportUpdate, JobRunErrors, JobRunOutput,
+	history, DynJob, JobError, JobIdentity, JobReport, JobReportUpdate, JobRunErrors, JobRunOutput,
 	JobStatus, OldJobs,
 };
 
@@ -44,6 +51,17 @@ pub struct JobProgressEvent {
 	pub phase: String,
 	pub message: String,
 	pub estimated_completion: DateTime<Utc>,
+	/// Throughput of the job, the old job system counts tasks as files, which most of its jobs
+	/// go through one per task
+	pub metrics: Option<JobProgressMetrics>,
+}
+
+/// What the metrics of a job from the old job system are computed from, across its updates
+#[derive(Default)]
+struct MetricsState {
+	hashed_bytes: u64,
+	/// Phases the job went through, with the time each one started at
+	phases: Vec<(String, Instant)>,
 }
 
 // used to update the worker state from inside the worker thread
@@ -268,6 +286,7 @@ impl Worker {
 		last_report_watch_update: &mut Instant,
 		report_watch_tx: &watch::Sender<JobReport>,
 		start_time: DateTime<Utc>,
+		metrics: &mut MetricsState,
 		updates: Vec<JobReportUpdate>,
 		library: &Library,
 	) {
@@ -295,8 +314,12 @@ impl Worker {
 						report.id,
 						report.phase
 					);
+					metrics.phases.push((phase.clone(), Instant::now()));
 					report.phase = phase;
 				}
+				JobReportUpdate::HashedBytes(bytes) => {
+					metrics.hashed_bytes += bytes;
+				}
 			}
 		}
 
@@ -326,6 +349,23 @@ impl Worker {
 			*last_report_watch_update = Instant::now();
 		}
 
+		let progress_metrics = metrics.phases.iter().enumerate().fold(
+			JobProgressMetrics::new(
+				completed_task_count as u64,
+				task_count as u64,
+				metrics.hashed_bytes,
+				elapsed.to_std().unwrap_or_default(),
+			),
+			|job_metrics, (i, (phase, started_at))| {
+				let ended_at = metrics
+					.phases
+					.get(i + 1)
+					.map_or_else(Instant::now, |(_, next_started_at)| *next_started_at);
+
+				job_metrics.with_phase(phase, ended_at - *started_at)
+			},
+		);
+
 		// emit a CoreEvent
 		library.emit(CoreEvent::JobProgress(JobProgressEvent {
 			id: report.id,
@@ -335,6 +375,7 @@ impl Worker {
 			estimated_completion: report.estimated_completion,
 			phase: report.phase.clone(),
 			message: report.message.clone(),
+			metrics: Some(progress_metrics),
 		}));
 	}
 
@@ -360,6 +401,7 @@ impl Worker {
 		let mut last_update_received_at = Instant::now();
 
 		let mut last_reporter_watch_update = Instant::now();
+		let mut metrics = MetricsState::default();
 		invalidate_query!(library, "jobs.reports");
 
 		let mut finalized_events_rx = pin!(events_rx.clone());
@@ -368,20 +410,24 @@ impl Worker {
 
 		let mut run_task = {
 			let library = Arc::clone(&library);
-			spawn(async move {
-				let job_result = job
-					.run(
-						WorkerContext {
-							library,
-							node,
-							events_tx,
-						},
-						commands_rx,
-					)
-					.await;
-
-				(job, job_result)
-			})
+			let span = info_span!("job", job_id = %report.id, job_name = %report.name);
+			spawn(
+				async move {
+					let job_result = job
+						.run(
+							WorkerContext {
+								library,
+								node,
+								events_tx,
+							},
+							commands_rx,
+						)
+						.await;
+
+					(job, job_result)
+				}
+				.instrument(span),
+			)
 		};
 
 		type RunOutput = (Box<dyn DynJob>, Result<JobRunOutput, JobError>);
@@ -419,6 +465,7 @@ impl Worker {
 								&mut last_reporter_watch_update,
 								&report_watch_tx,
 								start_time,
+								&mut metrics,
 								updates,
 								&library,
 							);
@@ -445,6 +492,7 @@ impl Worker {
 						&mut last_reporter_watch_update,
 						&report_watch_tx,
 						start_time,
+						&mut metrics,
 						updates,
 						&library,
 					);
@@ -532,6 +580,9 @@ impl Worker {
 
 				debug!("{report}");
 
+				Self::record_history(&*job, report, None, library).await;
+				rollups::on_job_committed(library, job.target_location()).await;
+
 				invalidate_queries(library);
 
 				return next_job;
@@ -565,6 +616,9 @@ impl Worker {
 
 				debug!("{report}");
 
+				Self::record_history(&*job, report, None, library).await;
+				rollups::on_job_committed(library, job.target_location()).await;
+
 				invalidate_queries(library);
 
 				return next_job;
@@ -615,6 +669,9 @@ impl Worker {
 
 				debug!("{report}");
 
+				Self::record_history(&*job, report, None, library).await;
+				rollups::on_job_committed(library, job.target_location()).await;
+
 				invalidate_queries(library);
 
 				signal_tx.send(()).ok();
@@ -637,12 +694,29 @@ impl Worker {
 
 				warn!("{report}");
 
+				Self::record_history(&*job, report, Some(e.to_string()), library).await;
+				rollups::on_job_committed(library, job.target_location()).await;
+
 				invalidate_queries(library);
 			}
 		}
 
 		None
 	}
+
+	async fn record_history(
+		job: &dyn DynJob,
+		report: &JobReport,
+		critical_error: Option<String>,
+		library: &Library,
+	) {
+		if let Err(e) = history::record(job, report, critical_error, library).await {
+			error!(
+				"Failed to record Job<id='{}', name='{}'> in history: {e:#?}",
+				report.id, report.name
+			);
+		}
+	}
 }
 
 struct JobWorkTable {
@@ -655,4 +729,5 @@ struct JobWorkTable {
 fn invalidate_queries(library: &Library) {
 	invalidate_query!(library, "jobs.isActive");
 	invalidate_query!(library, "jobs.reports");
+	invalidate_query!(library, "jobs.history");
 }
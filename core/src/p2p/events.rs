@@ -3,6 +3,7 @@ use std::{collections::HashSet, net::SocketAddr, sync::Arc};
 use sd_p2p::{
 	flume::bounded, hooks::QuicHandle, HookEvent, PeerConnectionCandidate, RemoteIdentity, P2P,
 };
+use sd_p2p_block::Progress;
 use serde::Serialize;
 use specta::Type;
 use tokio::sync::broadcast;
@@ -59,6 +60,9 @@ pub enum P2PEvent {
 	SpacedropProgress {
 		id: Uuid,
 		percent: u8,
+		/// Index of the file being transferred
+		file: u32,
+		file_percent: u8,
 	},
 	SpacedropTimedOut {
 		id: Uuid,
@@ -68,6 +72,17 @@ pub enum P2PEvent {
 	},
 }
 
+impl P2PEvent {
+	pub(crate) fn spacedrop_progress(id: Uuid, progress: Progress) -> Self {
+		Self::SpacedropProgress {
+			id,
+			percent: progress.percent,
+			file: progress.file as u32,
+			file_percent: progress.file_percent,
+		}
+	}
+}
+
 /// A P2P hook which listens for events and sends them over a channel which can be connected to the frontend.
 pub struct P2PEvents {
 	events: (broadcast::Sender<P2PEvent>, broadcast::Receiver<P2PEvent>),
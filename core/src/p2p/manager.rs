@@ -1,11 +1,12 @@
 use crate::{
 	node::{
+		bandwidth::Bandwidth,
 		config::{self, P2PDiscoveryState},
 		get_hardware_model_name, HardwareModel,
 	},
 	p2p::{
-		libraries::libraries_hook, operations, sync::SyncMessage, Header, OperatingSystem,
-		SPACEDRIVE_APP_ID,
+		libraries::libraries_hook, operations, sync::SyncMessage, Header, LocalNetworks,
+		OperatingSystem, Reachability, SPACEDRIVE_APP_ID,
 	},
 	Node,
 };
@@ -15,7 +16,7 @@ use axum::routing::IntoMakeService;
 use sd_p2p::{
 	flume::{bounded, Receiver},
 	hooks::{Libp2pPeerId, Mdns, QuicHandle, QuicTransport, RelayServerEntry},
-	Peer, RemoteIdentity, UnicastStream, P2P,
+	Peer, PeerConnectionCandidate, RemoteIdentity, UnicastStream, P2P,
 };
 use sd_p2p_tunnel::Tunnel;
 use serde::Serialize;
@@ -24,11 +25,14 @@ use specta::Type;
 use std::{
 	collections::HashMap,
 	convert::Infallible,
-	sync::{atomic::AtomicBool, Arc, Mutex, PoisonError},
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc, Mutex, PoisonError,
+	},
 	time::Duration,
 };
 use tower_service::Service;
-use tracing::error;
+use tracing::{debug, error};
 
 use tokio::sync::{oneshot, Notify};
 use tracing::info;
@@ -36,6 +40,10 @@ use uuid::Uuid;
 
 use super::{P2PEvents, PeerMetadata};
 
+/// How often the networks the node is on are checked against the network policy, as moving to
+/// another network doesn't change the config
+const NETWORK_POLICY_INTERVAL: Duration = Duration::from_secs(30);
+
 #[derive(Default, Clone, Serialize, Type)]
 #[serde(tag = "type")]
 pub enum ListenerState {
@@ -64,16 +72,21 @@ pub struct P2PManager {
 	pub(crate) events: P2PEvents,
 	pub(super) spacedrop_pairing_reqs: Arc<Mutex<HashMap<Uuid, oneshot::Sender<Option<String>>>>>,
 	pub(super) spacedrop_cancellations: Arc<Mutex<HashMap<Uuid, Arc<AtomicBool>>>>,
+	pub(crate) spacedrop_history: operations::spacedrop::History,
+	pub(crate) bandwidth: Arc<Bandwidth>,
 	pub(crate) node_config: Arc<config::Manager>,
 	pub listeners: Mutex<Listeners>,
 	relay_config: Mutex<Vec<RelayServerEntry>>,
 	trigger_relay_config_update: Notify,
+	/// Whether the node was on a trusted network when the network policy was last applied
+	on_trusted_network: AtomicBool,
 }
 
 impl P2PManager {
 	pub async fn new(
 		node_config: Arc<config::Manager>,
 		libraries: Arc<crate::library::Libraries>,
+		bandwidth: Arc<Bandwidth>,
 	) -> Result<
 		(
 			Arc<P2PManager>,
@@ -94,10 +107,14 @@ impl P2PManager {
 			quic_transport: quic,
 			spacedrop_pairing_reqs: Default::default(),
 			spacedrop_cancellations: Default::default(),
+			spacedrop_history: operations::spacedrop::History::load(node_config.data_directory())
+				.await,
+			bandwidth,
 			node_config,
 			listeners: Default::default(),
 			relay_config: Default::default(),
 			trigger_relay_config_update: Default::default(),
+			on_trusted_network: AtomicBool::new(true),
 		});
 		this.on_node_config_change().await;
 
@@ -110,6 +127,7 @@ impl P2PManager {
 
 		Ok((this.clone(), |node: Arc<Node>, router| {
 			tokio::spawn(start(this.clone(), node.clone(), rx, router));
+			tokio::spawn(watch_network_policy(this.clone()));
 
 			// TODO: Cleanup this thread on p2p shutdown.
 			tokio::spawn(async move {
@@ -136,16 +154,12 @@ impl P2PManager {
 											.unwrap_or_else(PoisonError::into_inner)
 											.clone_from(&config);
 
-										let config = {
-											let node_config = node.config.get().await;
-											if !node_config.p2p.disabled
-												&& !node_config.p2p.disable_relay
-											{
+										let config =
+											if node.config.get().await.p2p.is_relay_enabled() {
 												config
 											} else {
 												vec![]
-											}
-										};
+											};
 										let no_relays = config.len();
 
 										this.listeners
@@ -199,6 +213,13 @@ impl P2PManager {
 
 		let config = self.node_config.get().await;
 
+		let on_trusted_network = config
+			.p2p
+			.network_policy
+			.is_on_trusted_network(&LocalNetworks::current());
+		self.on_trusted_network
+			.store(on_trusted_network, Ordering::Relaxed);
+
 		if config.p2p.discovery == P2PDiscoveryState::ContactsOnly {
 			PeerMetadata::remove(&mut self.p2p.metadata_mut());
 
@@ -263,7 +284,11 @@ impl P2PManager {
 		self.quic_transport
 			.set_manual_peer_addrs(config.p2p.manual_peers);
 
-		let should_revert = match (config.p2p.disabled, config.p2p.discovery) {
+		// We don't advertise ourselves on networks we don't trust
+		let should_revert = match (
+			config.p2p.disabled || !on_trusted_network,
+			config.p2p.discovery,
+		) {
 			(true, _) | (_, P2PDiscoveryState::Disabled) => {
 				let mdns = {
 					let mut mdns = self.mdns.lock().unwrap_or_else(PoisonError::into_inner);
@@ -307,6 +332,36 @@ impl P2PManager {
 		}
 	}
 
+	/// Whether we can talk with the peer `identity` and if not, why
+	pub async fn reachability(&self, identity: RemoteIdentity) -> Reachability {
+		let config = self.node_config.get().await.p2p;
+
+		if config.disabled {
+			return Reachability::Disabled;
+		}
+
+		let Some(peer) = self.p2p.peers().get(&identity).cloned() else {
+			return Reachability::NotDiscovered;
+		};
+
+		let addrs = if self.quic.is_relayed(identity) {
+			vec![]
+		} else {
+			peer.connection_candidates()
+				.into_iter()
+				.filter_map(|candidate| match candidate {
+					PeerConnectionCandidate::SocketAddr(addr)
+					| PeerConnectionCandidate::Manual(addr) => Some(addr),
+					PeerConnectionCandidate::Relay => None,
+				})
+				.collect::<Vec<_>>()
+		};
+
+		config
+			.network_policy
+			.check(&LocalNetworks::current(), &addrs)
+	}
+
 	pub fn get_library_instances(&self, library: &Uuid) -> Vec<(RemoteIdentity, Arc<Peer>)> {
 		let library_id = library.to_string();
 		self.p2p
@@ -372,6 +427,15 @@ async fn start(
 		let mut service = unwrap_infallible(service.call(()).await);
 
 		tokio::spawn(async move {
+			let remote = stream.remote_identity();
+			let reachability = this.reachability(remote).await;
+			if reachability != Reachability::Reachable {
+				debug!(
+					"Refusing stream from peer '{remote}' as it's unreachable: {reachability:?}"
+				);
+				return;
+			}
+
 			let Ok(header) = Header::from_stream(&mut stream).await.map_err(|err| {
 				error!("Failed to read header from stream: {}", err);
 			}) else {
@@ -445,6 +509,14 @@ async fn start(
 
 					error!("Failed to handling library file request with {remote:?} for {file_path_id}: {err:?}");
 				}
+				Header::DelegateJob => {
+					let remote = stream.remote_identity();
+					let Err(err) = operations::remote_job::receiver(stream, &node).await else {
+						return;
+					};
+
+					error!("Failed to handling delegated job request with {remote:?}: {err:?}");
+				}
 			};
 		});
 	}
@@ -458,3 +530,26 @@ fn unwrap_infallible<T>(result: Result<T, Infallible>) -> T {
 		Err(err) => match err {},
 	}
 }
+
+/// Applies the network policy again when the node moves to or from a trusted network
+async fn watch_network_policy(this: Arc<P2PManager>) {
+	loop {
+		tokio::time::sleep(NETWORK_POLICY_INTERVAL).await;
+
+		let on_trusted_network = this
+			.node_config
+			.get()
+			.await
+			.p2p
+			.network_policy
+			.is_on_trusted_network(&LocalNetworks::current());
+
+		if on_trusted_network != this.on_trusted_network.load(Ordering::Relaxed) {
+			info!(
+				"Moved to a network that is {}trusted by the network policy",
+				if on_trusted_network { "" } else { "not " }
+			);
+			this.on_node_config_change().await;
+		}
+	}
+}
@@ -5,6 +5,7 @@ mod events;
 pub(super) mod libraries;
 mod manager;
 mod metadata;
+mod network_policy;
 pub mod operations;
 mod protocol;
 pub mod sync;
@@ -12,6 +13,7 @@ pub mod sync;
 pub use events::*;
 pub use manager::*;
 pub use metadata::*;
+pub use network_policy::*;
 pub use protocol::*;
 
 pub(super) const SPACEDRIVE_APP_ID: &str = "sd";
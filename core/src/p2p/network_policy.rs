@@ -0,0 +1,267 @@
+//! Restricting where the node looks for peers and which ones it talks to, so a laptop only finds
+//! the other devices at home and is invisible on a café's Wi-Fi.
+//!
+//! A restricted policy disables the relay, as relayed connections have no address to check. mDNS
+//! only runs while the node is on a trusted network, and peers must have an address the policy
+//! allows, which the ones we didn't discover on the local network don't have.
+
+use std::net::{IpAddr, SocketAddr};
+
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tracing::warn;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct NetworkPolicy {
+	/// Names of the interfaces (eg. `en0`) whose networks are used, every interface if empty
+	#[serde(default)]
+	pub interfaces: Vec<String>,
+	/// Networks we trust, in CIDR notation (eg. `192.168.1.0/24`). Discovery only runs while the
+	/// node is on one and peers must be in one. Every network if empty.
+	#[serde(default)]
+	pub trusted_subnets: Vec<String>,
+	/// Only reach peers through private addresses
+	#[serde(default)]
+	pub lan_only: bool,
+}
+
+/// Why a peer is or isn't reachable
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Type)]
+#[serde(tag = "type")]
+pub enum Reachability {
+	Reachable,
+	/// P2P is disabled on this node
+	Disabled,
+	/// The peer wasn't discovered, so we don't know how to reach it
+	NotDiscovered,
+	/// The node isn't on a trusted network, so it doesn't look for peers
+	UntrustedNetwork,
+	/// We only know of the peer through the relay, which the policy doesn't allow
+	NoDirectAddress,
+	/// None of the addresses of the peer are on a network the policy allows
+	OutsidePolicy {
+		addresses: Vec<String>,
+	},
+}
+
+/// The networks the node is on, one per address of its interfaces
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LocalNetworks(Vec<(String, IpNet)>);
+
+impl LocalNetworks {
+	pub fn current() -> Self {
+		match if_addrs::get_if_addrs() {
+			Ok(interfaces) => Self(
+				interfaces
+					.into_iter()
+					.filter(|interface| !interface.is_loopback())
+					.filter_map(|interface| {
+						let network = match &interface.addr {
+							if_addrs::IfAddr::V4(addr) => {
+								IpNet::with_netmask(addr.ip.into(), addr.netmask.into())
+							}
+							if_addrs::IfAddr::V6(addr) => {
+								IpNet::with_netmask(addr.ip.into(), addr.netmask.into())
+							}
+						};
+
+						network.ok().map(|network| (interface.name, network))
+					})
+					.collect(),
+			),
+			Err(e) => {
+				warn!(?e, "Failed to list network interfaces;");
+				Self::default()
+			}
+		}
+	}
+}
+
+impl NetworkPolicy {
+	pub fn is_restricted(&self) -> bool {
+		self.lan_only || !self.interfaces.is_empty() || !self.trusted_subnets.is_empty()
+	}
+
+	/// Fails with the first subnet that isn't in CIDR notation
+	pub fn validate(&self) -> Result<(), String> {
+		self.trusted_subnets
+			.iter()
+			.find(|subnet| subnet.parse::<IpNet>().is_err())
+			.map_or(Ok(()), |subnet| Err(subnet.clone()))
+	}
+
+	fn subnets(&self) -> Vec<IpNet> {
+		self.trusted_subnets
+			.iter()
+			.filter_map(|subnet| subnet.parse().ok())
+			.collect()
+	}
+
+	fn networks<'a>(&'a self, local: &'a LocalNetworks) -> impl Iterator<Item = &'a IpNet> {
+		local
+			.0
+			.iter()
+			.filter(|(name, _)| self.interfaces.is_empty() || self.interfaces.contains(name))
+			.map(|(_, network)| network)
+	}
+
+	pub fn is_on_trusted_network(&self, local: &LocalNetworks) -> bool {
+		let subnets = self.subnets();
+		let mut networks = self.networks(local).peekable();
+
+		if !self.interfaces.is_empty() && networks.peek().is_none() {
+			return false;
+		}
+
+		subnets.is_empty()
+			|| networks.any(|network| {
+				subnets
+					.iter()
+					.any(|subnet| subnet.contains(&network.addr()))
+			})
+	}
+
+	fn allows(&self, local: &LocalNetworks, subnets: &[IpNet], ip: IpAddr) -> bool {
+		(!self.lan_only || is_private(ip))
+			&& (self.interfaces.is_empty()
+				|| self.networks(local).any(|network| network.contains(&ip)))
+			&& (subnets.is_empty() || subnets.iter().any(|subnet| subnet.contains(&ip)))
+	}
+
+	/// Whether a peer with `addrs` can be reached while the node is on `local`
+	pub fn check(&self, local: &LocalNetworks, addrs: &[SocketAddr]) -> Reachability {
+		if !self.is_restricted() {
+			return Reachability::Reachable;
+		}
+
+		if !self.is_on_trusted_network(local) {
+			return Reachability::UntrustedNetwork;
+		}
+
+		if addrs.is_empty() {
+			return Reachability::NoDirectAddress;
+		}
+
+		let subnets = self.subnets();
+
+		if addrs
+			.iter()
+			.any(|addr| self.allows(local, &subnets, addr.ip()))
+		{
+			Reachability::Reachable
+		} else {
+			Reachability::OutsidePolicy {
+				addresses: addrs.iter().map(ToString::to_string).collect(),
+			}
+		}
+	}
+}
+
+fn is_private(ip: IpAddr) -> bool {
+	match ip {
+		IpAddr::V4(ip) => ip.is_private() || ip.is_link_local() || ip.is_loopback(),
+		IpAddr::V6(ip) => {
+			let first = ip.segments()[0];
+
+			// Unique local and link local addresses
+			ip.is_loopback() || first & 0xfe00 == 0xfc00 || first & 0xffc0 == 0xfe80
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn local() -> LocalNetworks {
+		LocalNetworks(vec![
+			(
+				"en0".to_string(),
+				"192.168.1.20/24".parse().expect("valid network"),
+			),
+			(
+				"utun3".to_string(),
+				"10.8.0.2/16".parse().expect("valid network"),
+			),
+		])
+	}
+
+	fn addr(addr: &str) -> SocketAddr {
+		addr.parse().expect("valid address")
+	}
+
+	#[test]
+	fn unrestricted_policies_reach_everyone() {
+		assert_eq!(
+			NetworkPolicy::default().check(&LocalNetworks::default(), &[]),
+			Reachability::Reachable
+		);
+	}
+
+	#[test]
+	fn lan_only_refuses_public_addresses() {
+		let policy = NetworkPolicy {
+			lan_only: true,
+			..Default::default()
+		};
+
+		assert_eq!(
+			policy.check(&local(), &[addr("192.168.1.30:7373")]),
+			Reachability::Reachable
+		);
+		assert_eq!(
+			policy.check(&local(), &[addr("[fe80::1]:7373")]),
+			Reachability::Reachable
+		);
+		assert_eq!(
+			policy.check(&local(), &[addr("1.1.1.1:7373")]),
+			Reachability::OutsidePolicy {
+				addresses: vec!["1.1.1.1:7373".to_string()]
+			}
+		);
+		assert_eq!(policy.check(&local(), &[]), Reachability::NoDirectAddress);
+	}
+
+	#[test]
+	fn trusted_subnets_and_interfaces() {
+		let policy = NetworkPolicy {
+			interfaces: vec!["en0".to_string()],
+			trusted_subnets: vec!["192.168.1.0/24".to_string()],
+			..Default::default()
+		};
+
+		assert!(policy.is_on_trusted_network(&local()));
+		assert_eq!(
+			policy.check(
+				&local(),
+				&[addr("10.8.0.5:7373"), addr("192.168.1.30:7373")]
+			),
+			Reachability::Reachable
+		);
+		assert!(matches!(
+			policy.check(&local(), &[addr("10.8.0.5:7373")]),
+			Reachability::OutsidePolicy { .. }
+		));
+
+		let cafe = LocalNetworks(vec![(
+			"en0".to_string(),
+			"172.16.4.9/22".parse().expect("valid network"),
+		)]);
+
+		assert!(!policy.is_on_trusted_network(&cafe));
+		assert_eq!(
+			policy.check(&cafe, &[addr("192.168.1.30:7373")]),
+			Reachability::UntrustedNetwork
+		);
+
+		assert_eq!(
+			NetworkPolicy {
+				trusted_subnets: vec!["192.168.1.0/33".to_string()],
+				..Default::default()
+			}
+			.validate(),
+			Err("192.168.1.0/33".to_string())
+		);
+	}
+}
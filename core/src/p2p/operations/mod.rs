@@ -1,8 +1,10 @@
 pub mod library;
 pub mod ping;
+pub mod remote_job;
 pub mod rspc;
 pub mod spacedrop;
 
 pub use library::request_file;
+pub use remote_job::{delegate_job, location_owner, DelegatedJob};
 pub use rspc::remote_rspc;
 pub use spacedrop::spacedrop;
@@ -0,0 +1,222 @@
+use crate::{
+	library::Library,
+	location::LocationError,
+	object::{
+		media::OldMediaProcessorJobInit,
+		old_file_identifier::old_file_identifier_job::OldFileIdentifierJobInit,
+	},
+	old_job::{JobBuilder, JobManagerError, JobTrigger},
+	p2p::Header,
+	Node,
+};
+
+use sd_p2p::{RemoteIdentity, UnicastStream};
+use sd_p2p_proto::{decode, encode};
+use sd_p2p_tunnel::Tunnel;
+use sd_prisma::prisma::{instance, location};
+
+use std::{error::Error, path::PathBuf, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncWriteExt};
+use tracing::debug;
+use uuid::Uuid;
+
+/// Jobs that can run on the node a location is attached to instead of reading it over the network.
+/// Their results reach the other nodes through library sync, like any other change.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum DelegatedJob {
+	FileIdentifier,
+	/// Thumbnails stay in the data directory of the node running it, only media data and labels
+	/// are synced back
+	MediaProcessor {
+		regenerate_thumbnails: bool,
+		regenerate_labels: bool,
+	},
+}
+
+impl DelegatedJob {
+	/// Spawns the job on this node, which must be the one `location` is attached to
+	pub async fn spawn(
+		self,
+		node: &Arc<Node>,
+		library: &Arc<Library>,
+		location: location::Data,
+		sub_path: Option<PathBuf>,
+		trigger: JobTrigger,
+	) -> Result<(), JobManagerError> {
+		match self {
+			Self::FileIdentifier => {
+				JobBuilder::new(OldFileIdentifierJobInit { location, sub_path })
+					.with_trigger(trigger)
+					.build()
+					.spawn(node, library)
+					.await
+			}
+			Self::MediaProcessor {
+				regenerate_thumbnails,
+				regenerate_labels,
+			} => {
+				JobBuilder::new(OldMediaProcessorJobInit {
+					location,
+					sub_path,
+					regenerate_thumbnails,
+					regenerate_labels,
+				})
+				.with_trigger(trigger)
+				.build()
+				.spawn(node, library)
+				.await
+			}
+		}
+	}
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DelegateJobRequest {
+	// Location ids are local to each instance, so we go by the synced `pub_id`
+	location_pub_id: Uuid,
+	sub_path: Option<PathBuf>,
+	job: DelegatedJob,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum DelegateJobResponse {
+	Spawned,
+	Rejected(String),
+}
+
+async fn read_msg<T: for<'de> Deserialize<'de>>(
+	stream: &mut (impl AsyncRead + Unpin),
+) -> Result<T, Box<dyn Error>> {
+	Ok(rmp_serde::from_slice(&decode::buf(stream).await?)?)
+}
+
+fn msg_to_bytes(msg: &impl Serialize) -> Result<Vec<u8>, Box<dyn Error>> {
+	let mut buf = vec![];
+	encode::buf(&mut buf, &rmp_serde::to_vec_named(msg)?);
+	Ok(buf)
+}
+
+/// The node `location` is attached to, if it isn't this one
+pub async fn location_owner(
+	library: &Library,
+	location: &location::Data,
+) -> Result<Option<RemoteIdentity>, LocationError> {
+	let Some(instance_id) = location.instance_id else {
+		return Ok(None);
+	};
+
+	if instance_id == library.config().await.instance_id {
+		return Ok(None);
+	}
+
+	Ok(library
+		.db
+		.instance()
+		.find_unique(instance::id::equals(instance_id))
+		.select(instance::select!({ node_remote_identity }))
+		.exec()
+		.await?
+		.and_then(|instance| instance.node_remote_identity)
+		.and_then(|identity| RemoteIdentity::from_bytes(&identity).ok()))
+}
+
+/// Asks `owner`, the node `location` is attached to, to run `job` on it
+pub async fn delegate_job(
+	node: &Arc<Node>,
+	library: &Library,
+	owner: RemoteIdentity,
+	location: &location::Data,
+	sub_path: Option<PathBuf>,
+	job: DelegatedJob,
+) -> Result<(), Box<dyn Error>> {
+	let peer = node
+		.p2p
+		.p2p
+		.peers()
+		.get(&owner)
+		.filter(|peer| peer.is_connected())
+		.ok_or("The node this location is attached to is offline")?
+		.clone();
+
+	let mut stream = peer.new_stream().await?;
+	stream.write_all(&Header::DelegateJob.to_bytes()).await?;
+
+	let mut tunnel = Tunnel::initiator(stream, &library.identity).await?;
+
+	tunnel
+		.write_all(&msg_to_bytes(&DelegateJobRequest {
+			location_pub_id: Uuid::from_slice(&location.pub_id)?,
+			sub_path,
+			job,
+		})?)
+		.await?;
+	tunnel.flush().await?;
+
+	match read_msg(&mut tunnel).await? {
+		DelegateJobResponse::Spawned => Ok(()),
+		DelegateJobResponse::Rejected(reason) => Err(reason.into()),
+	}
+}
+
+pub(crate) async fn receiver(
+	stream: UnicastStream,
+	node: &Arc<Node>,
+) -> Result<(), Box<dyn Error>> {
+	debug!(
+		"Received delegated job request from peer '{}'",
+		stream.remote_identity()
+	);
+
+	// The tunnel takes care of authentication, only nodes with the library can delegate jobs on it
+	let mut tunnel = Tunnel::responder(stream).await?;
+
+	let library = node
+		.libraries
+		.get_library_for_instance(&tunnel.library_remote_identity())
+		.await
+		.ok_or_else(|| format!("Library not found: {:?}", tunnel.library_remote_identity()))?;
+
+	let DelegateJobRequest {
+		location_pub_id,
+		sub_path,
+		job,
+	} = read_msg(&mut tunnel).await?;
+
+	let response = match spawn_delegated(node, &library, location_pub_id, sub_path, job).await {
+		Ok(()) => DelegateJobResponse::Spawned,
+		Err(e) => DelegateJobResponse::Rejected(e.to_string()),
+	};
+
+	tunnel.write_all(&msg_to_bytes(&response)?).await?;
+	tunnel.flush().await?;
+
+	Ok(())
+}
+
+async fn spawn_delegated(
+	node: &Arc<Node>,
+	library: &Arc<Library>,
+	location_pub_id: Uuid,
+	sub_path: Option<PathBuf>,
+	job: DelegatedJob,
+) -> Result<(), Box<dyn Error>> {
+	let location = library
+		.db
+		.location()
+		.find_unique(location::pub_id::equals(
+			location_pub_id.as_bytes().to_vec(),
+		))
+		.exec()
+		.await?
+		.ok_or_else(|| format!("Location {location_pub_id} not found in {:?}", library.id))?;
+
+	if location.instance_id != Some(library.config().await.instance_id) {
+		return Err(format!("Location {location_pub_id} isn't attached to this node").into());
+	}
+
+	job.spawn(node, library, location, sub_path, JobTrigger::Peer)
+		.await
+		.map_err(Into::into)
+}
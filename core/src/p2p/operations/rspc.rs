@@ -1,10 +1,21 @@
-use std::{error::Error, sync::Arc};
+use std::{collections::HashSet, convert::Infallible, error::Error, str::FromStr, sync::Arc};
 
-use axum::{body::Body, http, Router};
-use hyper::{server::conn::Http, Response};
+use axum::{
+	body::{Body, BoxBody},
+	http::{self, header, Method, StatusCode},
+	response::IntoResponse,
+	Router,
+};
+use hyper::{server::conn::Http, service::service_fn, Response};
+use percent_encoding::percent_decode_str;
 use sd_p2p::{RemoteIdentity, UnicastStream, P2P};
+use sd_prisma::prisma::instance;
+use serde::Deserialize;
+use serde_json::Value;
 use tokio::io::AsyncWriteExt;
-use tracing::debug;
+use tower_service::Service;
+use tracing::{debug, error};
+use uuid::Uuid;
 
 use crate::{p2p::Header, Node};
 
@@ -27,34 +38,299 @@ pub async fn remote_rspc(
 	let (mut sender, conn) = hyper::client::conn::handshake(stream).await?;
 	tokio::task::spawn(async move {
 		if let Err(err) = conn.await {
-			println!("Connection error: {:?}", err);
+			error!(?err, "Remote rspc connection error;");
 		}
 	});
 
 	sender.send_request(request).await.map_err(Into::into)
 }
 
+/// Read-only queries a paired node can make, each on a library it's paired with us in. Anything
+/// else, mutations, subscriptions and node-level queries included, is refused.
+const REMOTE_QUERIES: [&str; 14] = [
+	"files.get",
+	"files.getMediaData",
+	"labels.getForObject",
+	"labels.list",
+	"library.kindStatistics",
+	"library.statistics",
+	"locations.get",
+	"locations.list",
+	"search.objects",
+	"search.objectsCount",
+	"search.paths",
+	"search.pathsCount",
+	"tags.getForObject",
+	"tags.list",
+];
+
+/// Lists our libraries, of which a paired node only sees the ones it's paired with us in
+const LIBRARY_LIST: &str = "library.list";
+
+/// Serves the node to a remote node browsing it. Only nodes we are paired with, the ones with an
+/// instance in one of our libraries, are let in and only when the user enabled remote access.
 pub(crate) async fn receiver(
 	stream: UnicastStream,
 	service: &mut Router,
 	node: &Node,
 ) -> Result<(), Box<dyn Error>> {
-	debug!(
-		"Received http request from peer '{}'",
-		stream.remote_identity(),
-	);
+	let remote = stream.remote_identity();
+
+	debug!("Received http request from peer '{remote}'");
+
+	let libraries = if node.config.get().await.p2p.enable_remote_access {
+		paired_libraries(node, remote).await
+	} else {
+		HashSet::new()
+	};
 
-	// TODO: Authentication
-	#[allow(clippy::todo)]
-	if !node.config.get().await.p2p.enable_remote_access {
-		todo!("No way buddy!");
+	if libraries.is_empty() {
+		debug!("Refusing remote access to peer '{remote}' as we aren't paired with it");
 	}
 
+	let libraries = Arc::new(libraries);
+	let service = service.clone();
+
 	Http::new()
 		.http1_only(true)
 		.http1_keep_alive(true)
-		.serve_connection(stream, service)
+		.serve_connection(
+			stream,
+			service_fn(move |request: http::Request<Body>| {
+				let mut service = service.clone();
+				let libraries = Arc::clone(&libraries);
+
+				async move {
+					match access(&request, &libraries) {
+						Access::Denied => {
+							Ok::<_, Infallible>(StatusCode::FORBIDDEN.into_response())
+						}
+						Access::Allowed => service.call(request).await,
+						Access::PairedLibraries => {
+							let response = service.call(request).await?;
+
+							Ok(only_paired_libraries(response, &libraries).await)
+						}
+					}
+				}
+			}),
+		)
 		.with_upgrades()
 		.await
 		.map_err(Into::into)
 }
+
+/// The libraries `remote` has an instance in
+async fn paired_libraries(node: &Node, remote: RemoteIdentity) -> HashSet<Uuid> {
+	let mut libraries = HashSet::new();
+
+	for library in node.libraries.get_all().await {
+		match library
+			.db
+			.instance()
+			.count(vec![instance::node_remote_identity::equals(Some(
+				remote.get_bytes().to_vec(),
+			))])
+			.exec()
+			.await
+		{
+			Ok(0) => {}
+			Ok(_) => {
+				libraries.insert(library.id);
+			}
+			Err(e) => error!(?e, library_id = %library.id, "Failed to look for paired instances;"),
+		}
+	}
+
+	libraries
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Access {
+	Denied,
+	Allowed,
+	/// Allowed, but the response must be stripped of the libraries the node isn't paired with
+	PairedLibraries,
+}
+
+/// What of the node a paired node can browse: the [`REMOTE_QUERIES`] and the thumbnails and
+/// location files of the libraries it's paired with us in. Files are never served by their path,
+/// as that would reach outside the locations.
+fn access(request: &http::Request<Body>, libraries: &HashSet<Uuid>) -> Access {
+	let paired = |library_id: &str| {
+		Uuid::from_str(library_id).is_ok_and(|library_id| libraries.contains(&library_id))
+	};
+
+	if libraries.is_empty() || request.method() != Method::GET {
+		return Access::Denied;
+	}
+
+	let mut segments = request.uri().path().trim_start_matches('/').split('/');
+
+	match (segments.next(), segments.next(), segments.next()) {
+		(Some("rspc"), Some(LIBRARY_LIST), None) => Access::PairedLibraries,
+		(Some("rspc"), Some(key), None)
+			if REMOTE_QUERIES.contains(&key)
+				&& input_library(request.uri()).is_some_and(|id| libraries.contains(&id)) =>
+		{
+			Access::Allowed
+		}
+		(Some("uri"), Some("thumbnail" | "file"), Some(library_id)) if paired(library_id) => {
+			Access::Allowed
+		}
+		_ => Access::Denied,
+	}
+}
+
+/// The library a query is made on, from its `input` query parameter
+fn input_library(uri: &http::Uri) -> Option<Uuid> {
+	#[derive(Deserialize)]
+	struct LibraryArgs {
+		library_id: Uuid,
+	}
+
+	let input = uri
+		.query()?
+		.split('&')
+		.find_map(|pair| pair.strip_prefix("input="))?
+		.replace('+', " ");
+
+	serde_json::from_str::<LibraryArgs>(&percent_decode_str(&input).decode_utf8().ok()?)
+		.ok()
+		.map(|args| args.library_id)
+}
+
+/// Drops the libraries the node isn't paired with us in from a `library.list` response. Responses
+/// that can't be filtered aren't sent at all.
+async fn only_paired_libraries(
+	response: Response<BoxBody>,
+	libraries: &HashSet<Uuid>,
+) -> Response<BoxBody> {
+	let (mut parts, body) = response.into_parts();
+
+	let Ok(mut value) = hyper::body::to_bytes(body)
+		.await
+		.map_err(|e| error!(?e, "Failed to read the library list;"))
+		.and_then(|bytes| {
+			serde_json::from_slice::<Value>(&bytes)
+				.map_err(|e| error!(?e, "Failed to parse the library list;"))
+		})
+	else {
+		return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+	};
+
+	retain_libraries(&mut value, libraries);
+
+	parts.headers.remove(header::CONTENT_LENGTH);
+
+	Response::from_parts(parts, Body::from(value.to_string())).into_response()
+}
+
+/// Keeps only the `libraries` among the library entries, the objects with a `uuid`, wherever they
+/// are in rspc's response envelope
+fn retain_libraries(value: &mut Value, libraries: &HashSet<Uuid>) {
+	match value {
+		Value::Array(items) => {
+			items.retain(|item| match item.get("uuid").and_then(Value::as_str) {
+				Some(uuid) => Uuid::from_str(uuid).is_ok_and(|uuid| libraries.contains(&uuid)),
+				None => true,
+			});
+
+			items
+				.iter_mut()
+				.for_each(|item| retain_libraries(item, libraries));
+		}
+		Value::Object(fields) => fields
+			.values_mut()
+			.for_each(|field| retain_libraries(field, libraries)),
+		_ => {}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+
+	fn get(uri: &str) -> http::Request<Body> {
+		http::Request::get(uri)
+			.body(Body::empty())
+			.expect("valid request")
+	}
+
+	fn query(key: &str, library_id: Uuid) -> String {
+		let input = format!(r#"{{"library_id":"{library_id}","arg":null}}"#);
+
+		format!(
+			"/rspc/{key}?input={}",
+			utf8_percent_encode(&input, NON_ALPHANUMERIC)
+		)
+	}
+
+	#[test]
+	fn only_read_only_queries_on_paired_libraries_are_exposed() {
+		let paired = Uuid::new_v4();
+		let other = Uuid::new_v4();
+		let libraries = HashSet::from([paired]);
+
+		let file = format!("/uri/file/{paired}/1/2");
+		let thumbnail = format!("/uri/thumbnail/{paired}/abc/abc123.webp");
+
+		for uri in [query("search.paths", paired), file, thumbnail] {
+			assert_eq!(access(&get(&uri), &libraries), Access::Allowed, "{uri}");
+		}
+
+		assert_eq!(
+			access(&get("/rspc/library.list"), &libraries),
+			Access::PairedLibraries
+		);
+
+		for uri in [
+			"/".to_string(),
+			"/rspc/ws".to_string(),
+			"/rspc/search.paths".to_string(),
+			"/rspc/nodeState".to_string(),
+			query("search.paths", other),
+			query("files.getPath", paired),
+			query("locations.fullRescan", paired),
+			"/uri/local-file-by-path/%2Fetc%2Fpasswd".to_string(),
+			"/uri/thumbnail/ephemeral/abc/abc123.webp".to_string(),
+			format!("/uri/file/{other}/1/2"),
+			"/uri/file/not-a-library/1/2".to_string(),
+			"/remote/someone/rspc/ws".to_string(),
+		] {
+			assert_eq!(access(&get(&uri), &libraries), Access::Denied, "{uri}");
+		}
+
+		let mutation = http::Request::post(query("tags.list", paired))
+			.body(Body::empty())
+			.expect("valid request");
+
+		assert_eq!(access(&mutation, &libraries), Access::Denied);
+		assert_eq!(
+			access(&get(&query("tags.list", paired)), &HashSet::new()),
+			Access::Denied
+		);
+	}
+
+	#[test]
+	fn only_paired_libraries_are_listed() {
+		let paired = Uuid::new_v4();
+		let other = Uuid::new_v4();
+
+		let mut value = serde_json::json!({
+			"result": {
+				"type": "response",
+				"data": [{ "uuid": paired }, { "uuid": other }]
+			}
+		});
+
+		retain_libraries(&mut value, &HashSet::from([paired]));
+
+		assert_eq!(
+			value["result"]["data"],
+			serde_json::json!([{ "uuid": paired }])
+		);
+	}
+}
@@ -0,0 +1,260 @@
+use sd_p2p::RemoteIdentity;
+use sd_p2p_block::SpaceblockRequests;
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+use specta::Type;
+use tokio::{fs, sync::Mutex};
+use tracing::{error, warn};
+use uuid::Uuid;
+
+pub const SPACEDROP_HISTORY_NAME: &str = "spacedrop_history.json";
+
+/// Transfers kept in the history, the oldest ones going first
+const MAX_TRANSFERS: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum TransferDirection {
+	Sent,
+	Received,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum TransferStatus {
+	InProgress,
+	/// The connection dropped, the transfer picks up where it stopped when the sender retries
+	Interrupted,
+	Completed,
+	Cancelled,
+	Rejected,
+	Failed,
+}
+
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct TransferredFile {
+	pub name: String,
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub size: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct SpacedropTransfer {
+	pub id: Uuid,
+	pub direction: TransferDirection,
+	pub peer: RemoteIdentity,
+	pub files: Vec<TransferredFile>,
+	/// Where the files are saved, for the transfers we received
+	pub path: Option<PathBuf>,
+	pub status: TransferStatus,
+	pub date_started: DateTime<Utc>,
+	pub date_updated: DateTime<Utc>,
+}
+
+impl SpacedropTransfer {
+	pub fn new(
+		req: &SpaceblockRequests,
+		direction: TransferDirection,
+		peer: RemoteIdentity,
+		path: Option<PathBuf>,
+	) -> Self {
+		let now = Utc::now();
+
+		Self {
+			id: req.id,
+			direction,
+			peer,
+			files: files(req),
+			path,
+			status: TransferStatus::InProgress,
+			date_started: now,
+			date_updated: now,
+		}
+	}
+
+	/// Whether `req` from `peer` picks up this transfer after it was interrupted
+	pub fn is_resumed_by(&self, req: &SpaceblockRequests, peer: RemoteIdentity) -> bool {
+		self.direction == TransferDirection::Received
+			&& matches!(
+				self.status,
+				TransferStatus::InProgress | TransferStatus::Interrupted
+			) && self.peer == peer
+			&& self.files == files(req)
+	}
+}
+
+fn files(req: &SpaceblockRequests) -> Vec<TransferredFile> {
+	req.requests
+		.iter()
+		.map(|req| TransferredFile {
+			name: req.name.clone(),
+			size: req.size,
+		})
+		.collect()
+}
+
+/// The Spacedrops this node sent and received, persisted so interrupted ones can be resumed
+pub struct History {
+	path: PathBuf,
+	transfers: Mutex<Vec<SpacedropTransfer>>,
+}
+
+impl History {
+	pub async fn load(data_directory: impl AsRef<Path>) -> Self {
+		let path = data_directory.as_ref().join(SPACEDROP_HISTORY_NAME);
+
+		let mut transfers = match fs::read(&path).await {
+			Ok(bytes) => serde_json::from_slice::<Vec<SpacedropTransfer>>(&bytes)
+				.map_err(|e| warn!(?e, "Discarding malformed Spacedrop history;"))
+				.unwrap_or_default(),
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+			Err(e) => {
+				warn!(?e, "Failed to read Spacedrop history;");
+				vec![]
+			}
+		};
+
+		// The node stopped while these were running
+		for transfer in &mut transfers {
+			if transfer.status == TransferStatus::InProgress {
+				transfer.status = TransferStatus::Interrupted;
+			}
+		}
+
+		Self {
+			path,
+			transfers: Mutex::new(transfers),
+		}
+	}
+
+	pub async fn list(&self) -> Vec<SpacedropTransfer> {
+		self.transfers.lock().await.clone()
+	}
+
+	pub async fn get(&self, id: Uuid) -> Option<SpacedropTransfer> {
+		self.transfers
+			.lock()
+			.await
+			.iter()
+			.find(|transfer| transfer.id == id)
+			.cloned()
+	}
+
+	pub async fn insert(&self, transfer: SpacedropTransfer) {
+		let mut transfers = self.transfers.lock().await;
+
+		transfers.retain(|t| t.id != transfer.id);
+		transfers.push(transfer);
+
+		if transfers.len() > MAX_TRANSFERS {
+			let excess = transfers.len() - MAX_TRANSFERS;
+			transfers.drain(..excess);
+		}
+
+		self.save(&transfers).await;
+	}
+
+	pub async fn set_status(&self, id: Uuid, status: TransferStatus) {
+		let mut transfers = self.transfers.lock().await;
+
+		if let Some(transfer) = transfers.iter_mut().find(|transfer| transfer.id == id) {
+			transfer.status = status;
+			transfer.date_updated = Utc::now();
+
+			self.save(&transfers).await;
+		}
+	}
+
+	/// Removes the transfers that can't be resumed anymore
+	pub async fn clear(&self) {
+		let mut transfers = self.transfers.lock().await;
+
+		transfers.retain(|transfer| {
+			matches!(
+				transfer.status,
+				TransferStatus::InProgress | TransferStatus::Interrupted
+			)
+		});
+
+		self.save(&transfers).await;
+	}
+
+	async fn save(&self, transfers: &[SpacedropTransfer]) {
+		let bytes = serde_json::to_vec(transfers).expect("transfers are always serializable");
+
+		if let Err(e) = fs::write(&self.path, bytes).await {
+			error!(?e, path = %self.path.display(), "Failed to save Spacedrop history;");
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use sd_p2p::Identity;
+	use sd_p2p_block::{BlockSize, Range, SpaceblockRequest};
+
+	use super::*;
+
+	fn requests(size: u64) -> SpaceblockRequests {
+		SpaceblockRequests {
+			id: Uuid::new_v4(),
+			block_size: BlockSize::from_file_size(size),
+			requests: vec![SpaceblockRequest {
+				name: "Demo".to_string(),
+				size,
+				range: Range::Full,
+			}],
+		}
+	}
+
+	#[test]
+	fn only_the_same_files_from_the_same_peer_resume() {
+		let peer = Identity::new().to_remote_identity();
+		let req = requests(42069);
+
+		let mut transfer =
+			SpacedropTransfer::new(&req, TransferDirection::Received, peer, Some("/tmp".into()));
+
+		assert!(transfer.is_resumed_by(&req, peer));
+		assert!(!transfer.is_resumed_by(&req, Identity::new().to_remote_identity()));
+		assert!(!transfer.is_resumed_by(
+			&SpaceblockRequests {
+				id: req.id,
+				..requests(420)
+			},
+			peer
+		));
+
+		transfer.status = TransferStatus::Completed;
+		assert!(!transfer.is_resumed_by(&req, peer));
+	}
+
+	#[tokio::test]
+	async fn running_transfers_are_interrupted_on_load() {
+		let dir = tempfile::tempdir().expect("temp dir");
+		let req = requests(42069);
+
+		History::load(dir.path())
+			.await
+			.insert(SpacedropTransfer::new(
+				&req,
+				TransferDirection::Sent,
+				Identity::new().to_remote_identity(),
+				None,
+			))
+			.await;
+
+		assert_eq!(
+			History::load(dir.path())
+				.await
+				.get(req.id)
+				.await
+				.map(|transfer| transfer.status),
+			Some(TransferStatus::Interrupted)
+		);
+	}
+}
@@ -0,0 +1,200 @@
+use sd_p2p_block::{Range, SpaceblockRequest};
+
+use std::{
+	io,
+	path::{Component, Path, PathBuf},
+	time::SystemTime,
+};
+
+use tokio::fs;
+
+/// What a Spacedrop sends, the files and folders picked with everything under the folders
+#[derive(Debug, Default)]
+pub struct Manifest {
+	/// Where each request is read from
+	pub files: Vec<SourceFile>,
+	pub requests: Vec<SpaceblockRequest>,
+	pub directories: Vec<String>,
+}
+
+impl Manifest {
+	/// Folders are walked so the receiver can recreate their tree, entries are named by their path
+	/// relative to the folder picked, separated by `/`. Symlinks are skipped as they can point
+	/// outside of what was picked.
+	pub async fn walk(paths: Vec<PathBuf>) -> Result<Self, io::Error> {
+		let mut manifest = Self::default();
+
+		for path in paths {
+			let name = file_name(&path);
+
+			if fs::metadata(&path).await?.is_dir() {
+				manifest.walk_dir(path, name).await?;
+			} else {
+				manifest.push_file(path, name).await?;
+			}
+		}
+
+		Ok(manifest)
+	}
+
+	async fn walk_dir(&mut self, path: PathBuf, name: String) -> Result<(), io::Error> {
+		let mut to_walk = vec![(path, name)];
+
+		while let Some((path, name)) = to_walk.pop() {
+			let mut entries = vec![];
+			let mut read_dir = fs::read_dir(&path).await?;
+			while let Some(entry) = read_dir.next_entry().await? {
+				entries.push(entry);
+			}
+
+			// Keeps the manifest the same when an interrupted Spacedrop is retried
+			entries.sort_by_key(|entry| entry.file_name());
+
+			for entry in entries {
+				let file_type = entry.file_type().await?;
+				let entry_name = format!("{name}/{}", file_name(&entry.path()));
+
+				if file_type.is_dir() {
+					to_walk.push((entry.path(), entry_name));
+				} else if file_type.is_file() {
+					self.push_file(entry.path(), entry_name).await?;
+				}
+			}
+
+			self.directories.push(name);
+		}
+
+		Ok(())
+	}
+
+	async fn push_file(&mut self, path: PathBuf, name: String) -> Result<(), io::Error> {
+		let metadata = fs::metadata(&path).await?;
+
+		self.requests.push(SpaceblockRequest {
+			name,
+			size: metadata.len(),
+			range: Range::Full,
+		});
+		self.files.push(SourceFile {
+			path,
+			size: metadata.len(),
+			modified: metadata.modified().ok(),
+		});
+
+		Ok(())
+	}
+}
+
+/// A file sent by a Spacedrop, as it was when the Spacedrop started
+#[derive(Debug)]
+pub struct SourceFile {
+	pub path: PathBuf,
+	size: u64,
+	modified: Option<SystemTime>,
+}
+
+impl SourceFile {
+	/// Whether the file wasn't changed since, so a resumed transfer doesn't splice what the
+	/// receiver already has of it with another version
+	pub async fn is_unchanged(&self) -> Result<bool, io::Error> {
+		let metadata = fs::metadata(&self.path).await?;
+
+		Ok(metadata.len() == self.size && metadata.modified().ok() == self.modified)
+	}
+}
+
+fn file_name(path: &Path) -> String {
+	path.file_name()
+		.map(|name| name.to_string_lossy().to_string())
+		.unwrap_or_default()
+}
+
+/// Where the entry named `name` in a manifest is saved under `root`, or `None` for names that
+/// would land outside of it
+pub fn resolve(root: &Path, name: &str) -> Option<PathBuf> {
+	let mut path = root.to_path_buf();
+
+	for part in name.split('/') {
+		let mut components = Path::new(part).components();
+
+		match (components.next(), components.next()) {
+			(Some(Component::Normal(component)), None) => path.push(component),
+			_ => return None,
+		}
+	}
+
+	Some(path)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn names_stay_under_the_root() {
+		let root = Path::new("/downloads");
+
+		assert_eq!(
+			resolve(root, "Photos/2024/beach.jpg"),
+			Some(root.join("Photos").join("2024").join("beach.jpg"))
+		);
+
+		for name in [
+			"",
+			"..",
+			"Photos/../../etc/passwd",
+			"/etc/passwd",
+			"Photos//beach.jpg",
+			"./beach.jpg",
+		] {
+			assert_eq!(resolve(root, name), None, "{name}");
+		}
+	}
+
+	#[tokio::test]
+	async fn folders_keep_their_tree() {
+		let dir = tempfile::tempdir().expect("temp dir");
+		let photos = dir.path().join("Photos");
+
+		fs::create_dir_all(photos.join("2024").join("Empty"))
+			.await
+			.expect("create folders");
+		fs::write(photos.join("2024").join("beach.jpg"), b"beach")
+			.await
+			.expect("write file");
+		fs::write(photos.join("cover.jpg"), b"cover")
+			.await
+			.expect("write file");
+		fs::write(dir.path().join("notes.txt"), b"notes")
+			.await
+			.expect("write file");
+
+		let manifest = Manifest::walk(vec![photos, dir.path().join("notes.txt")])
+			.await
+			.expect("walk");
+
+		let mut names = manifest
+			.requests
+			.iter()
+			.map(|req| (req.name.as_str(), req.size))
+			.collect::<Vec<_>>();
+		names.sort_unstable();
+
+		assert_eq!(
+			names,
+			vec![
+				("Photos/2024/beach.jpg", 5),
+				("Photos/cover.jpg", 5),
+				("notes.txt", 5)
+			]
+		);
+
+		let mut directories = manifest.directories;
+		directories.sort_unstable();
+
+		assert_eq!(
+			directories,
+			vec!["Photos", "Photos/2024", "Photos/2024/Empty"]
+		);
+	}
+}
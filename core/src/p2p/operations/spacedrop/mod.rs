@@ -0,0 +1,584 @@
+use std::{
+	io::SeekFrom,
+	path::{Path, PathBuf},
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc, PoisonError,
+	},
+	time::Duration,
+};
+
+use crate::{
+	node::bandwidth::{BandwidthProtocol, Throttled},
+	p2p::{Header, P2PEvent, P2PManager},
+};
+use sd_p2p::{RemoteIdentity, UnicastStream};
+use sd_p2p_block::{BlockSize, SpaceblockRequests, Transfer};
+use thiserror::Error;
+use tokio::{
+	fs::{self, create_dir_all, File, OpenOptions},
+	io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader, BufWriter},
+	sync::oneshot,
+	time::{sleep, Instant},
+};
+use tracing::{debug, error, info, warn};
+use uuid::Uuid;
+
+mod history;
+mod manifest;
+
+pub use history::{History, SpacedropTransfer, TransferDirection, TransferStatus};
+
+use manifest::{Manifest, SourceFile};
+
+/// The amount of time to wait for a Spacedrop request to be accepted or rejected before it's automatically rejected
+pub(crate) const SPACEDROP_TIMEOUT: Duration = Duration::from_secs(60);
+/// How many times an interrupted Spacedrop is retried before it's given up on
+const RESUME_ATTEMPTS: u8 = 5;
+/// How long to wait before retrying an interrupted Spacedrop, giving the peer time to come back
+const RESUME_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Error)]
+pub enum SpacedropError {
+	#[error("paths argument is an empty vector")]
+	EmptyPath,
+	#[error("error connecting to peer")]
+	FailedPeerConnection,
+	#[error("error creating stream: {0}")]
+	FailedNewStream(#[from] sd_p2p::NewStreamError),
+	#[error("error opening file: {0}")]
+	FailedFileOpen(#[from] std::io::Error),
+	#[error("transfer was interrupted: {0}")]
+	Interrupted(std::io::Error),
+	#[error("unexpected response from peer: {0}")]
+	UnexpectedResponse(u8),
+	#[error("file changed since the Spacedrop started: {0:?}")]
+	SourceChanged(PathBuf),
+}
+
+pub async fn spacedrop(
+	p2p: Arc<P2PManager>,
+	identity: RemoteIdentity,
+	paths: Vec<PathBuf>,
+) -> Result<Uuid, SpacedropError> {
+	if paths.is_empty() {
+		return Err(SpacedropError::EmptyPath);
+	}
+
+	let Manifest {
+		files,
+		requests,
+		directories,
+	} = Manifest::walk(paths)
+		.await
+		.map_err(SpacedropError::FailedFileOpen)?;
+
+	let total_length: u64 = requests.iter().map(|req| req.size).sum();
+
+	let id = Uuid::new_v4();
+	debug!("({id}): starting Spacedrop with peer '{identity}");
+	let stream = connect(&p2p, id, identity).await?;
+
+	let requests = SpaceblockRequests {
+		id,
+		block_size: BlockSize::from_file_size(total_length),
+		directories,
+		requests,
+	};
+
+	tokio::spawn(async move {
+		p2p.spacedrop_history
+			.insert(SpacedropTransfer::new(
+				&requests,
+				TransferDirection::Sent,
+				identity,
+				None,
+			))
+			.await;
+
+		let cancelled = Arc::new(AtomicBool::new(false));
+		p2p.spacedrop_cancellations
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.insert(id, cancelled.clone());
+
+		let mut stream = Some(stream);
+		let mut attempts = 0;
+
+		let status = loop {
+			let result = async {
+				let mut stream = match stream.take() {
+					Some(stream) => stream,
+					None => connect(&p2p, id, identity).await?,
+				};
+
+				send(&p2p, &mut stream, &requests, &files, &cancelled).await
+			}
+			.await;
+
+			match result {
+				Ok(status) => break status,
+				// Retrying wouldn't help with these
+				Err(
+					err
+					@ (SpacedropError::UnexpectedResponse(_) | SpacedropError::SourceChanged(_)),
+				) => {
+					debug!("({id}): failed, not resuming: {err}");
+					break TransferStatus::Failed;
+				}
+				Err(err) => {
+					if cancelled.load(Ordering::Relaxed) {
+						break TransferStatus::Cancelled;
+					}
+
+					attempts += 1;
+					if attempts > RESUME_ATTEMPTS {
+						debug!("({id}): giving up on the transfer: {err}");
+						// TODO: Error to frontend
+						// p2p.events
+						// 	.send(P2PEvent::SpacedropFailed { id, file_id })
+						// 	.ok();
+						break TransferStatus::Failed;
+					}
+
+					debug!("({id}): interrupted, resuming in {RESUME_DELAY:?} ({attempts}/{RESUME_ATTEMPTS}): {err}");
+					p2p.spacedrop_history
+						.set_status(id, TransferStatus::Interrupted)
+						.await;
+
+					sleep(RESUME_DELAY).await;
+				}
+			}
+		};
+
+		p2p.spacedrop_history.set_status(id, status).await;
+	});
+
+	Ok(id)
+}
+
+async fn connect(
+	p2p: &P2PManager,
+	id: Uuid,
+	identity: RemoteIdentity,
+) -> Result<UnicastStream, SpacedropError> {
+	let peer = p2p
+		.p2p
+		.peers()
+		.get(&identity)
+		.ok_or_else(|| {
+			debug!("({id}): failed to find connection method with '{identity}'");
+			SpacedropError::FailedPeerConnection
+		})?
+		.clone();
+
+	peer.new_stream().await.map_err(|err| {
+		debug!("({id}): failed to connect to '{identity}': {err:?}");
+		SpacedropError::FailedNewStream(err)
+	})
+}
+
+/// Sends the files over `stream`, which the receiver picks up from where a previous attempt was
+/// interrupted
+async fn send(
+	p2p: &P2PManager,
+	stream: &mut UnicastStream,
+	requests: &SpaceblockRequests,
+	files: &[SourceFile],
+	cancelled: &AtomicBool,
+) -> Result<TransferStatus, SpacedropError> {
+	let id = requests.id;
+
+	// The receiver keeps what it got of the files in an interrupted attempt, and appends the rest
+	for file in files {
+		if !file.is_unchanged().await? {
+			debug!(
+				"({id}): '{:?}' changed since the Spacedrop started",
+				file.path
+			);
+			return Err(SpacedropError::SourceChanged(file.path.clone()));
+		}
+	}
+
+	debug!("({id}): connected, sending header");
+	stream
+		.write_all(&Header::Spacedrop(requests.clone()).to_bytes())
+		.await
+		.map_err(SpacedropError::Interrupted)?;
+
+	debug!("({id}): waiting for response");
+	let result = tokio::select! {
+	  result = stream.read_u8() => result,
+	  // Add 5 seconds incase the user responded on the deadline and slow network
+	   _ = sleep(SPACEDROP_TIMEOUT + Duration::from_secs(5)) => {
+			debug!("({id}): timed out, cancelling");
+			p2p.events.send(P2PEvent::SpacedropTimedOut { id }).ok();
+			return Ok(TransferStatus::Rejected);
+		},
+	};
+
+	match result.map_err(SpacedropError::Interrupted)? {
+		0 => {
+			debug!(
+				"({id}): Spacedrop was rejected from peer '{}'",
+				stream.remote_identity()
+			);
+			p2p.events.send(P2PEvent::SpacedropRejected { id }).ok();
+			return Ok(TransferStatus::Rejected);
+		}
+		1 => {} // Okay
+		response => {
+			debug!("({id}): unexpected response '{response}' from the peer");
+			return Err(SpacedropError::UnexpectedResponse(response));
+		}
+	}
+
+	debug!("({id}): starting transfer");
+	let i = Instant::now();
+
+	let mut transfer = Transfer::new(
+		requests,
+		|progress| {
+			p2p.events
+				.send(P2PEvent::spacedrop_progress(id, progress))
+				.ok();
+		},
+		cancelled,
+	);
+
+	for (file_id, SourceFile { path, .. }) in files.iter().enumerate() {
+		debug!("({id}): transmitting '{file_id}' from '{path:?}'");
+		let file = Throttled::new(
+			File::open(path).await?,
+			p2p.bandwidth.clone(),
+			BandwidthProtocol::Spacedrop,
+		);
+		transfer
+			.send(stream, BufReader::new(file))
+			.await
+			.map_err(|err| {
+				debug!("({id}): failed to send file '{file_id}': {err}");
+				SpacedropError::Interrupted(err)
+			})?;
+	}
+
+	debug!("({id}): finished; took '{:?}", i.elapsed());
+
+	Ok(if cancelled.load(Ordering::Relaxed) {
+		TransferStatus::Cancelled
+	} else {
+		TransferStatus::Completed
+	})
+}
+
+// TODO: Move these off the manager
+impl P2PManager {
+	pub async fn accept_spacedrop(&self, id: Uuid, path: String) {
+		if let Some(chan) = self
+			.spacedrop_pairing_reqs
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.remove(&id)
+		{
+			chan.send(Some(path))
+				.map_err(|err| {
+					warn!("error accepting Spacedrop '{id:?}': '{err:?}'");
+				})
+				.ok();
+		}
+	}
+
+	pub async fn reject_spacedrop(&self, id: Uuid) {
+		if let Some(chan) = self
+			.spacedrop_pairing_reqs
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.remove(&id)
+		{
+			chan.send(None)
+				.map_err(|err| {
+					warn!("error rejecting Spacedrop '{id:?}': '{err:?}'");
+				})
+				.ok();
+		}
+	}
+
+	pub async fn cancel_spacedrop(&self, id: Uuid) {
+		if let Some(cancelled) = self
+			.spacedrop_cancellations
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.remove(&id)
+		{
+			cancelled.store(true, Ordering::Relaxed);
+		}
+	}
+}
+
+pub(crate) async fn receiver(
+	this: &Arc<P2PManager>,
+	req: SpaceblockRequests,
+	mut stream: UnicastStream,
+) -> Result<(), ()> {
+	let id = req.id;
+	let peer = stream.remote_identity();
+
+	// The sender retries transfers that were interrupted, which we pick up without asking again
+	let resumed = this
+		.spacedrop_history
+		.get(id)
+		.await
+		.filter(|transfer| transfer.is_resumed_by(&req, peer))
+		.and_then(|transfer| transfer.path);
+
+	if let Some(file_path) = resumed {
+		info!("({id}): resuming transfer from peer '{peer}' saving to '{file_path:?}'");
+
+		return receive(this, req, stream, file_path, true).await;
+	}
+
+	if req
+		.directories
+		.iter()
+		.chain(req.requests.iter().map(|req| &req.name))
+		.any(|name| manifest::resolve(Path::new(""), name).is_none())
+	{
+		warn!("({id}): rejecting Spacedrop from peer '{peer}' with paths outside of its folder");
+
+		stream.write_all(&[0]).await.map_err(|err| {
+			error!("({id}): error sending rejection: '{err:?}'");
+		})?;
+
+		return Ok(());
+	}
+
+	let (tx, rx) = oneshot::channel();
+
+	info!(
+		"({id}): received '{}' files from peer '{peer}' with block size '{:?}'",
+		req.requests.len(),
+		req.block_size
+	);
+	this.spacedrop_pairing_reqs
+		.lock()
+		.unwrap_or_else(PoisonError::into_inner)
+		.insert(id, tx);
+
+	if this
+		.events
+		.send(P2PEvent::SpacedropRequest {
+			id,
+			identity: peer,
+			peer_name: "Unknown".into(),
+			// TODO: A better solution to this
+			// manager
+			// 	.get_discovered_peers()
+			// 	.await
+			// 	.into_iter()
+			// 	.find(|p| p.peer_id == event.peer_id)
+			// 	.map(|p| p.metadata.name)
+			// 	.unwrap_or_else(|| "Unknown".to_string()),
+			files: req
+				.requests
+				.iter()
+				.map(|req| req.name.clone())
+				.collect::<Vec<_>>(),
+		})
+		.is_err()
+	{
+		// No frontend's are active
+
+		// TODO: Implement this
+		error!("TODO: Outright reject Spacedrop");
+	}
+
+	tokio::select! {
+		_ = sleep(SPACEDROP_TIMEOUT) => {
+			info!("({id}): timeout, rejecting!");
+
+			stream.write_all(&[0]).await.map_err(|err| {
+				error!("({id}): error reject bit: '{err:?}'");
+			})?;
+			stream.flush().await.map_err(|err| {
+				error!("({id}): error flushing reject bit: '{err:?}'");
+			})?;
+		}
+		file_path = rx => {
+			match file_path {
+				Ok(Some(file_path)) => {
+					info!("({id}): accepted saving to '{:?}'", file_path);
+
+					let file_path = PathBuf::from(file_path);
+					this.spacedrop_history
+						.insert(SpacedropTransfer::new(
+							&req,
+							TransferDirection::Received,
+							peer,
+							Some(file_path.clone()),
+						))
+						.await;
+
+					receive(this, req, stream, file_path, false).await?;
+				}
+				Ok(None) => {
+					info!("({id}): rejected");
+
+					stream.write_all(&[0]).await.map_err(|err| {
+					   error!("({id}): error sending rejection: '{err:?}'");
+					})?;
+					stream.flush().await.map_err(|err| {
+					   error!("({id}): error flushing rejection: '{err:?}'");
+					})?;
+				}
+				Err(_) => {
+					warn!("({id}): error with Spacedrop pairing request receiver!");
+				}
+			}
+		}
+	};
+
+	Ok(())
+}
+
+/// Saves the files of an accepted Spacedrop to `file_path`, keeping what a previous attempt
+/// received of them if `resume`
+async fn receive(
+	this: &Arc<P2PManager>,
+	req: SpaceblockRequests,
+	mut stream: UnicastStream,
+	file_path: PathBuf,
+	resume: bool,
+) -> Result<(), ()> {
+	let id = req.id;
+
+	let cancelled = Arc::new(AtomicBool::new(false));
+	this.spacedrop_cancellations
+		.lock()
+		.unwrap_or_else(PoisonError::into_inner)
+		.insert(id, cancelled.clone());
+
+	stream.write_all(&[1]).await.map_err(|err| {
+		error!("({id}): error sending continuation bit: '{err:?}'");
+
+		// TODO: Send error to the frontend
+
+		// TODO: make sure the other peer times out or we retry???
+	})?;
+
+	let files = req
+		.requests
+		.iter()
+		.map(|req| (req.name.clone(), req.size))
+		.collect::<Vec<_>>();
+	let block_size = u64::from(req.block_size.size());
+	let mut transfer = Transfer::new(
+		&req,
+		|progress| {
+			this.events
+				.send(P2PEvent::spacedrop_progress(id, progress))
+				.ok();
+		},
+		&cancelled,
+	);
+
+	// When transferring more than a file we wanna recreate the tree in the directory provided by the user
+	let single_file = files.len() == 1 && req.directories.is_empty();
+
+	for directory in &req.directories {
+		let Some(path) = manifest::resolve(&file_path, directory) else {
+			continue;
+		};
+
+		create_dir_all(&path).await.map_err(|err| {
+			error!("({id}): error creating directory '{path:?}': '{err:?}'");
+
+			// TODO: Send error to the frontend
+		})?;
+	}
+
+	let mut status = TransferStatus::Completed;
+	for (file_name, size) in files {
+		// Names were checked before accepting
+		let Some(path) = (if single_file {
+			Some(file_path.clone())
+		} else {
+			manifest::resolve(&file_path, &file_name)
+		}) else {
+			continue;
+		};
+
+		debug!("({id}): accepting '{file_name}' and saving to '{:?}'", path);
+
+		if let Some(parent) = path.parent() {
+			create_dir_all(&parent).await.map_err(|err| {
+				error!("({id}): error creating parent directory '{parent:?}': '{err:?}'");
+
+				// TODO: Send error to the frontend
+
+				// TODO: Send error to remote peer
+			})?;
+		}
+
+		let offset = if resume {
+			received_len(&path, size, block_size).await
+		} else {
+			0
+		};
+
+		let f = open_at(&path, offset).await.map_err(|err| {
+			error!("({id}): error creating file at '{path:?}': '{err:?}'");
+
+			// TODO: Send error to the frontend
+
+			// TODO: Send error to remote peer
+		})?;
+		let f = BufWriter::new(Throttled::new(
+			f,
+			this.bandwidth.clone(),
+			BandwidthProtocol::Spacedrop,
+		));
+		if let Err(err) = transfer.receive(&mut stream, f, offset).await {
+			error!("({id}): error receiving file '{file_name}': '{err:?}'");
+
+			// TODO: Send error to frontend
+
+			status = TransferStatus::Interrupted;
+			break;
+		}
+	}
+
+	if cancelled.load(Ordering::Relaxed) {
+		status = TransferStatus::Cancelled;
+	}
+
+	this.spacedrop_history.set_status(id, status).await;
+
+	info!("({id}): finished with status '{status:?}'");
+
+	Ok(())
+}
+
+/// How much of the file at `path` an interrupted transfer received. It's rounded down to a block
+/// in case the last write was torn, as the blocks were only verified in memory.
+async fn received_len(path: &Path, size: u64, block_size: u64) -> u64 {
+	match fs::metadata(path).await {
+		Ok(metadata) if metadata.len() >= size => size,
+		Ok(metadata) => metadata.len() - metadata.len() % block_size,
+		Err(_) => 0,
+	}
+}
+
+/// Opens the file a transfer is saved to, keeping only the first `offset` bytes of it
+async fn open_at(path: &Path, offset: u64) -> Result<File, std::io::Error> {
+	let mut file = OpenOptions::new()
+		.write(true)
+		.create(true)
+		.truncate(false)
+		.open(path)
+		.await?;
+
+	file.set_len(offset).await?;
+	file.seek(SeekFrom::Start(offset)).await?;
+
+	Ok(file)
+}
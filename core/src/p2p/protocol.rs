@@ -21,6 +21,9 @@ pub enum Header {
 		file_path_id: Uuid,
 		range: Range,
 	},
+	// Run a job on a location attached to the remote node
+	// The request is sent within a `sd_p2p_tunnel::Tunnel` of the library it belongs to.
+	DelegateJob,
 }
 
 #[derive(Debug, Error)]
@@ -77,6 +80,7 @@ impl Header {
 					d => return Err(HeaderError::LibraryDiscriminatorInvalid(d)),
 				},
 			}),
+			7 => Ok(Self::DelegateJob),
 			d => Err(HeaderError::DiscriminatorInvalid(d)),
 		}
 	}
@@ -100,6 +104,7 @@ impl Header {
 				buf.extend_from_slice(&range.to_bytes());
 				buf
 			}
+			Self::DelegateJob => vec![7],
 		}
 	}
 }
@@ -174,6 +174,11 @@ impl InitConfig {
 					path: PathBuf::from(loc.path.clone()),
 					dry_run: false,
 					indexer_rules_ids: Vec::new(),
+					network_share: None,
+					s3: None,
+					webdav: None,
+					cloud_metadata: None,
+					mtp: None,
 				})
 				.create(node, &library)
 				.await?
@@ -0,0 +1,233 @@
+use std::{
+	collections::{BTreeMap, VecDeque},
+	fmt,
+	sync::Mutex,
+	time::{Duration, Instant},
+};
+
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use specta::Type;
+use tracing::{
+	field::{Field, Visit},
+	span::{Attributes, Id},
+	Level, Metadata, Subscriber,
+};
+use tracing_subscriber::{
+	filter::{filter_fn, FilterFn},
+	layer::Context,
+	registry::LookupSpan,
+	Layer,
+};
+use uuid::Uuid;
+
+/// How many jobs we keep traces for, the oldest ones are dropped first
+const MAX_TRACED_JOBS: usize = 16;
+/// Spans closed after this many in the same job aren't recorded, so a huge job can't eat the memory
+const MAX_EVENTS_PER_JOB: usize = 100_000;
+
+/// Crates whose spans are recorded, the job systems, the task system and the jobs themselves
+const TRACED_TARGETS: [&str; 3] = ["sd_core", "sd_core_heavy_lifting", "sd_task_system"];
+
+static JOB_TRACES: Lazy<JobTraces> = Lazy::new(|| JobTraces {
+	epoch: Instant::now(),
+	jobs: Mutex::new(VecDeque::with_capacity(MAX_TRACED_JOBS)),
+});
+
+/// A job trace in the Chrome trace event format, which can be opened in `chrome://tracing`,
+/// Perfetto or speedscope to get a flamegraph
+#[derive(Debug, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ChromeTrace {
+	trace_events: Vec<ChromeTraceEvent>,
+	display_time_unit: String,
+}
+
+/// A complete event (`"ph": "X"`), one for each span of the job. Timestamps are in microseconds and
+/// each task gets its own `tid`, so its spans are nested under it.
+#[derive(Debug, Clone, Serialize, Type)]
+pub struct ChromeTraceEvent {
+	name: String,
+	cat: String,
+	ph: String,
+	ts: f64,
+	dur: f64,
+	pid: u32,
+	tid: u32,
+	args: BTreeMap<String, String>,
+}
+
+struct JobTraces {
+	epoch: Instant,
+	jobs: Mutex<VecDeque<(Uuid, Vec<ChromeTraceEvent>)>>,
+}
+
+impl JobTraces {
+	fn record(&self, job_id: Uuid, event: ChromeTraceEvent) {
+		let mut jobs = self.jobs.lock().expect("job traces lock poisoned");
+
+		if let Some((_, events)) = jobs.iter_mut().find(|(id, _)| *id == job_id) {
+			if events.len() < MAX_EVENTS_PER_JOB {
+				events.push(event);
+			}
+		} else {
+			if jobs.len() == MAX_TRACED_JOBS {
+				jobs.pop_front();
+			}
+			jobs.push_back((job_id, vec![event]));
+		}
+	}
+}
+
+/// The trace of `job_id`, if it ran since the node started and wasn't dropped for newer ones.
+/// Spans are only recorded when they close, so a running job only has its finished tasks.
+pub fn export_job_trace(job_id: Uuid) -> Option<ChromeTrace> {
+	JOB_TRACES
+		.jobs
+		.lock()
+		.expect("job traces lock poisoned")
+		.iter()
+		.find(|(id, _)| *id == job_id)
+		.map(|(_, events)| ChromeTrace {
+			trace_events: events.clone(),
+			display_time_unit: "ms".to_string(),
+		})
+}
+
+/// Records the spans under a `job_id` field, as set on the root span of each job run
+pub struct JobTraceLayer;
+
+impl JobTraceLayer {
+	/// Only spans from our crates reach this layer, it doesn't need any event
+	pub fn filter() -> FilterFn<impl Fn(&Metadata<'_>) -> bool> {
+		filter_fn(|metadata| {
+			metadata.is_span()
+				&& *metadata.level() <= Level::DEBUG
+				&& TRACED_TARGETS.iter().any(|target| {
+					metadata
+						.target()
+						.strip_prefix(target)
+						.is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+				})
+		})
+	}
+}
+
+struct TracedSpan {
+	job_id: Uuid,
+	tid: u32,
+	started_at: Instant,
+	entered_at: Option<Instant>,
+	busy: Duration,
+	fields: BTreeMap<String, String>,
+}
+
+#[derive(Default)]
+struct FieldsVisitor {
+	job_id: Option<Uuid>,
+	fields: BTreeMap<String, String>,
+}
+
+impl Visit for FieldsVisitor {
+	fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+		let value = format!("{value:?}");
+
+		if field.name() == "job_id" {
+			self.job_id = Uuid::parse_str(&value).ok();
+		}
+
+		self.fields.insert(field.name().to_string(), value);
+	}
+}
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for JobTraceLayer {
+	fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+		let Some(span) = ctx.span(id) else {
+			return;
+		};
+
+		let mut visitor = FieldsVisitor::default();
+		attrs.record(&mut visitor);
+
+		let traced = if let Some(job_id) = visitor.job_id {
+			Some((job_id, 0))
+		} else {
+			span.parent().and_then(|parent| {
+				parent.extensions().get::<TracedSpan>().map(|parent| {
+					// Direct children of the job get their own lane, with their children nested in it
+					let tid = if parent.tid == 0 {
+						id.into_u64() as u32
+					} else {
+						parent.tid
+					};
+
+					(parent.job_id, tid)
+				})
+			})
+		};
+
+		if let Some((job_id, tid)) = traced {
+			span.extensions_mut().insert(TracedSpan {
+				job_id,
+				tid,
+				// Never before the epoch, which is set on the first traced span
+				started_at: Instant::now().max(JOB_TRACES.epoch),
+				entered_at: None,
+				busy: Duration::ZERO,
+				fields: visitor.fields,
+			});
+		}
+	}
+
+	fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+		if let Some(span) = ctx.span(id) {
+			if let Some(traced) = span.extensions_mut().get_mut::<TracedSpan>() {
+				traced.entered_at = Some(Instant::now());
+			}
+		}
+	}
+
+	fn on_exit(&self, id: &Id, ctx: Context<'_, S>) {
+		if let Some(span) = ctx.span(id) {
+			if let Some(traced) = span.extensions_mut().get_mut::<TracedSpan>() {
+				if let Some(entered_at) = traced.entered_at.take() {
+					traced.busy += entered_at.elapsed();
+				}
+			}
+		}
+	}
+
+	fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+		let Some(span) = ctx.span(&id) else {
+			return;
+		};
+
+		let Some(TracedSpan {
+			job_id,
+			tid,
+			started_at,
+			busy,
+			mut fields,
+			..
+		}) = span.extensions_mut().remove::<TracedSpan>()
+		else {
+			return;
+		};
+
+		fields.insert("busy_us".to_string(), busy.as_micros().to_string());
+
+		JOB_TRACES.record(
+			job_id,
+			ChromeTraceEvent {
+				name: span.name().to_string(),
+				cat: span.metadata().target().to_string(),
+				ph: "X".to_string(),
+				ts: started_at.duration_since(JOB_TRACES.epoch).as_secs_f64() * 1_000_000.0,
+				dur: started_at.elapsed().as_secs_f64() * 1_000_000.0,
+				pid: 1,
+				tid,
+				args: fields,
+			},
+		);
+	}
+}
@@ -3,6 +3,7 @@ mod batched_stream;
 #[cfg(debug_assertions)]
 pub mod debug_initializer;
 mod infallible_request;
+mod job_trace;
 mod maybe_undefined;
 pub mod mpscrr;
 mod observable;
@@ -12,6 +13,7 @@ pub mod version_manager;
 pub use abort_on_drop::*;
 pub use batched_stream::*;
 pub use infallible_request::*;
+pub use job_trace::*;
 pub use maybe_undefined::*;
 pub use observable::*;
 pub use unsafe_streamed_query::*;
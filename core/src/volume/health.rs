@@ -0,0 +1,573 @@
+//! Watching the SMART data of the drives backing locations, so the user hears about a failing
+//! drive while its files can still be copied off.
+//!
+//! The data comes from `smartctl` (smartmontools), which has to be installed and allowed to read
+//! the drives. Without it the drives are just never sampled.
+
+use crate::{
+	api::{
+		notifications::{NotificationData, NotificationKind},
+		CoreEvent,
+	},
+	invalidate_query, Node,
+};
+
+use sd_prisma::prisma::location;
+
+use std::{
+	collections::HashMap,
+	fmt,
+	mem::discriminant,
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use specta::Type;
+use tokio::{fs, process::Command, sync::Mutex, time::sleep};
+use tracing::{debug, error, warn};
+
+use super::{get_volumes, Volume};
+
+pub const DEVICE_HEALTH_NAME: &str = "device_health.json";
+
+const POLL_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Samples kept per drive, a month of hourly ones
+const MAX_SAMPLES: usize = 24 * 30;
+
+/// Most drives are rated for 60-70°C, so this is a little before they start failing from the heat
+const MAX_TEMPERATURE_CELSIUS: u32 = 60;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct HealthSample {
+	pub date: DateTime<Utc>,
+	/// The drive's own verdict on its health
+	pub passed: Option<bool>,
+	pub temperature_celsius: Option<u32>,
+	/// Sectors the drive remapped after they failed, ATA drives only
+	pub reallocated_sectors: Option<u32>,
+	/// Sectors waiting to be remapped, ATA drives only
+	pub pending_sectors: Option<u32>,
+	/// Unrecovered data integrity errors, NVMe drives only
+	pub media_errors: Option<u32>,
+	/// Estimated wear of the drive, which goes past 100, NVMe drives only
+	pub percentage_used: Option<u32>,
+}
+
+impl HealthSample {
+	/// Reads the output of `smartctl --json --all`, `None` if it holds no health data
+	pub fn parse(json: &Value, date: DateTime<Utc>) -> Option<Self> {
+		let number = |value: &Value| value.as_u64().map(|n| n.min(u32::MAX as u64) as u32);
+		let attribute = |id: u64| {
+			json["ata_smart_attributes"]["table"]
+				.as_array()?
+				.iter()
+				.find(|attribute| attribute["id"].as_u64() == Some(id))
+				.and_then(|attribute| number(&attribute["raw"]["value"]))
+		};
+		let nvme = &json["nvme_smart_health_information_log"];
+
+		let sample = Self {
+			date,
+			passed: json["smart_status"]["passed"].as_bool(),
+			temperature_celsius: number(&json["temperature"]["current"]),
+			reallocated_sectors: attribute(5),
+			// Current pending and offline uncorrectable sectors
+			pending_sectors: match (attribute(197), attribute(198)) {
+				(None, None) => None,
+				(pending, uncorrectable) => {
+					Some(pending.unwrap_or(0).max(uncorrectable.unwrap_or(0)))
+				}
+			},
+			media_errors: number(&nvme["media_errors"]),
+			percentage_used: number(&nvme["percentage_used"]),
+		};
+
+		(sample.passed.is_some()
+			|| sample.temperature_celsius.is_some()
+			|| sample.reallocated_sectors.is_some()
+			|| sample.media_errors.is_some())
+		.then_some(sample)
+	}
+
+	pub fn alerts(&self) -> Vec<HealthAlert> {
+		let mut alerts = vec![];
+
+		if self.passed == Some(false) {
+			alerts.push(HealthAlert::Failing);
+		}
+		if let Some(count) = self.reallocated_sectors.filter(|count| *count > 0) {
+			alerts.push(HealthAlert::ReallocatedSectors { count });
+		}
+		if let Some(count) = self.pending_sectors.filter(|count| *count > 0) {
+			alerts.push(HealthAlert::PendingSectors { count });
+		}
+		if let Some(count) = self.media_errors.filter(|count| *count > 0) {
+			alerts.push(HealthAlert::MediaErrors { count });
+		}
+		if let Some(celsius) = self
+			.temperature_celsius
+			.filter(|celsius| *celsius >= MAX_TEMPERATURE_CELSIUS)
+		{
+			alerts.push(HealthAlert::Overheating { celsius });
+		}
+		if let Some(percentage_used) = self.percentage_used.filter(|used| *used >= 100) {
+			alerts.push(HealthAlert::WornOut { percentage_used });
+		}
+
+		alerts
+	}
+}
+
+/// A sign the drive is about to fail
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(tag = "type")]
+pub enum HealthAlert {
+	/// The drive reports it's failing
+	Failing,
+	ReallocatedSectors {
+		count: u32,
+	},
+	PendingSectors {
+		count: u32,
+	},
+	MediaErrors {
+		count: u32,
+	},
+	Overheating {
+		celsius: u32,
+	},
+	WornOut {
+		percentage_used: u32,
+	},
+}
+
+impl HealthAlert {
+	fn count(&self) -> Option<u32> {
+		match self {
+			Self::ReallocatedSectors { count }
+			| Self::PendingSectors { count }
+			| Self::MediaErrors { count } => Some(*count),
+			_ => None,
+		}
+	}
+}
+
+impl fmt::Display for HealthAlert {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Failing => write!(f, "the drive reports it's failing"),
+			Self::ReallocatedSectors { count } => write!(f, "{count} reallocated sectors"),
+			Self::PendingSectors { count } => write!(f, "{count} unreadable sectors"),
+			Self::MediaErrors { count } => write!(f, "{count} media errors"),
+			Self::Overheating { celsius } => write!(f, "running at {celsius}°C"),
+			Self::WornOut { percentage_used } => write!(f, "{percentage_used}% of its rated wear"),
+		}
+	}
+}
+
+/// The alerts of `current` the user wasn't told about with `previous`, which are the new kinds and
+/// the counts that went up since, as a few reallocated sectors are fine until more keep coming
+pub fn new_alerts(previous: &[HealthAlert], current: &[HealthAlert]) -> Vec<HealthAlert> {
+	current
+		.iter()
+		.filter(|alert| {
+			previous
+				.iter()
+				.find(|previous| discriminant(*previous) == discriminant(*alert))
+				.map_or(true, |previous| previous.count() < alert.count())
+		})
+		.cloned()
+		.collect()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct DeviceHealth {
+	/// The drive's device, eg. `/dev/sda`
+	pub device: String,
+	pub model: Option<String>,
+	pub serial: Option<String>,
+	/// Where the drive's volumes holding locations are mounted
+	pub mount_points: Vec<PathBuf>,
+	/// The oldest samples first
+	pub history: Vec<HealthSample>,
+	/// The alerts of the latest sample
+	pub alerts: Vec<HealthAlert>,
+}
+
+/// Emitted when a drive shows new signs of failing
+#[derive(Debug, Clone, Serialize, Type)]
+pub struct DeviceHealthAlert {
+	pub device: String,
+	pub model: Option<String>,
+	pub mount_points: Vec<PathBuf>,
+	pub alerts: Vec<HealthAlert>,
+}
+
+/// The SMART history of the drives, persisted so a slow decline shows across restarts
+pub struct DeviceHealthHistory {
+	path: PathBuf,
+	devices: Mutex<Vec<DeviceHealth>>,
+}
+
+impl DeviceHealthHistory {
+	pub async fn load(data_directory: impl AsRef<Path>) -> Self {
+		let path = data_directory.as_ref().join(DEVICE_HEALTH_NAME);
+
+		let devices = match fs::read(&path).await {
+			Ok(bytes) => serde_json::from_slice::<Vec<DeviceHealth>>(&bytes)
+				.map_err(|e| warn!(?e, "Discarding malformed device health history;"))
+				.unwrap_or_default(),
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+			Err(e) => {
+				warn!(?e, "Failed to read device health history;");
+				vec![]
+			}
+		};
+
+		Self {
+			path,
+			devices: Mutex::new(devices),
+		}
+	}
+
+	pub async fn list(&self) -> Vec<DeviceHealth> {
+		self.devices.lock().await.clone()
+	}
+
+	/// Adds a sample of `device`, returning the alerts the user has to be told about
+	async fn record(
+		&self,
+		device: &str,
+		reading: SmartReading,
+		mount_points: Vec<PathBuf>,
+	) -> Vec<HealthAlert> {
+		let mut devices = self.devices.lock().await;
+
+		let index = match devices.iter().position(|d| d.device == device) {
+			Some(index) => index,
+			None => {
+				devices.push(DeviceHealth {
+					device: device.to_string(),
+					model: None,
+					serial: None,
+					mount_points: vec![],
+					history: vec![],
+					alerts: vec![],
+				});
+				devices.len() - 1
+			}
+		};
+		let health = &mut devices[index];
+
+		// Another drive took its place
+		if health.serial.is_some() && health.serial != reading.serial {
+			health.history.clear();
+			health.alerts.clear();
+		}
+
+		let alerts = reading.sample.alerts();
+		let new = new_alerts(&health.alerts, &alerts);
+
+		health.model = reading.model;
+		health.serial = reading.serial;
+		health.mount_points = mount_points;
+		health.alerts = alerts;
+		health.history.push(reading.sample);
+
+		if health.history.len() > MAX_SAMPLES {
+			let excess = health.history.len() - MAX_SAMPLES;
+			health.history.drain(..excess);
+		}
+
+		self.save(&devices).await;
+
+		new
+	}
+
+	async fn save(&self, devices: &[DeviceHealth]) {
+		let bytes = serde_json::to_vec(devices).expect("device health is always serializable");
+
+		if let Err(e) = fs::write(&self.path, bytes).await {
+			error!(?e, path = %self.path.display(), "Failed to save device health history;");
+		}
+	}
+}
+
+struct SmartReading {
+	model: Option<String>,
+	serial: Option<String>,
+	sample: HealthSample,
+}
+
+/// The drive a volume is on, as SMART data is per drive and not per partition. `None` for
+/// volumes that aren't a partition of a single drive, like RAID, LVM or network ones.
+pub fn drive_of(volume: &str) -> Option<String> {
+	let name = volume.strip_prefix("/dev/")?;
+
+	if name.is_empty()
+		|| name.contains('/')
+		|| ["dm-", "md", "loop", "zram", "ram"]
+			.iter()
+			.any(|prefix| name.starts_with(prefix))
+	{
+		return None;
+	}
+
+	let drive = if name.starts_with("nvme") || name.starts_with("mmcblk") {
+		// Their partitions are `p` and a number after the drive's own number, eg. `nvme0n1p2`
+		match name.rsplit_once('p') {
+			Some((drive, partition))
+				if !partition.is_empty()
+					&& partition.bytes().all(|b| b.is_ascii_digit())
+					&& drive.ends_with(|c: char| c.is_ascii_digit()) =>
+			{
+				drive
+			}
+			_ => name,
+		}
+	} else {
+		// `sda1` and `disk0s2`
+		let name = name.trim_end_matches(|c: char| c.is_ascii_digit());
+		name.strip_suffix('s')
+			.filter(|drive| drive.starts_with("disk"))
+			.unwrap_or(name)
+	};
+
+	Some(format!("/dev/{drive}"))
+}
+
+async fn read_smart(device: &str) -> Option<SmartReading> {
+	// `smartctl` exits with a bitmask that's non zero for failing drives too, so its output is read
+	// regardless
+	let output = Command::new("smartctl")
+		.args(["--json", "--all", device])
+		.output()
+		.await
+		.map_err(|e| {
+			debug!(
+				?e,
+				"Failed to run smartctl, device health is not monitored;"
+			)
+		})
+		.ok()?;
+
+	let json = serde_json::from_slice::<Value>(&output.stdout)
+		.map_err(|e| debug!(?e, %device, "Failed to parse smartctl output;"))
+		.ok()?;
+
+	let sample = HealthSample::parse(&json, Utc::now())?;
+
+	Some(SmartReading {
+		model: json["model_name"].as_str().map(ToString::to_string),
+		serial: json["serial_number"].as_str().map(ToString::to_string),
+		sample,
+	})
+}
+
+/// The drives backing the locations of every library, with where their volumes are mounted
+async fn location_drives(node: &Node) -> HashMap<String, Vec<PathBuf>> {
+	let volumes = get_volumes().await;
+	let mut drives = HashMap::<String, Vec<PathBuf>>::new();
+
+	for library in node.libraries.get_all().await {
+		let locations = match library
+			.db
+			.location()
+			.find_many(vec![])
+			.select(location::select!({ path }))
+			.exec()
+			.await
+		{
+			Ok(locations) => locations,
+			Err(e) => {
+				error!(?e, library_id = %library.id, "Failed to list locations for device health;");
+				continue;
+			}
+		};
+
+		for path in locations.into_iter().filter_map(|location| location.path) {
+			let Some(volume) = Volume::for_path(&volumes, &path) else {
+				continue;
+			};
+			let Some(drive) = drive_of(&volume.name) else {
+				continue;
+			};
+
+			let mount_points = drives.entry(drive).or_default();
+			for mount_point in &volume.mount_points {
+				if !mount_points.contains(mount_point) {
+					mount_points.push(mount_point.clone());
+				}
+			}
+		}
+	}
+
+	drives
+}
+
+async fn poll(node: &Node) {
+	for (device, mount_points) in location_drives(node).await {
+		let Some(reading) = read_smart(&device).await else {
+			continue;
+		};
+		let model = reading.model.clone();
+
+		let alerts = node
+			.device_health
+			.record(&device, reading, mount_points.clone())
+			.await;
+
+		if alerts.is_empty() {
+			continue;
+		}
+
+		warn!(%device, ?alerts, "Drive is showing signs of failing;");
+
+		node.emit_notification(
+			NotificationData {
+				title: format!("{} may be failing", model.as_deref().unwrap_or(&device)),
+				content: format!(
+					"Back up the locations on {}: {}",
+					mount_points
+						.iter()
+						.map(|mount_point| mount_point.display().to_string())
+						.collect::<Vec<_>>()
+						.join(", "),
+					alerts
+						.iter()
+						.map(ToString::to_string)
+						.collect::<Vec<_>>()
+						.join(", ")
+				),
+				kind: NotificationKind::Warning,
+			},
+			None,
+		)
+		.await;
+
+		node.emit(CoreEvent::DeviceHealthAlert(DeviceHealthAlert {
+			device,
+			model,
+			mount_points,
+			alerts,
+		}));
+	}
+
+	invalidate_query!(node; node, "volumes.health");
+}
+
+/// Samples the drives backing locations every hour
+pub(crate) fn spawn_monitor(node: Arc<Node>) {
+	tokio::spawn(async move {
+		loop {
+			poll(&node).await;
+			sleep(POLL_INTERVAL).await;
+		}
+	});
+}
+
+#[cfg(test)]
+mod tests {
+	use serde_json::json;
+
+	use super::*;
+
+	#[test]
+	fn partitions_map_to_their_drive() {
+		assert_eq!(drive_of("/dev/sda1"), Some("/dev/sda".to_string()));
+		assert_eq!(drive_of("/dev/sdb"), Some("/dev/sdb".to_string()));
+		assert_eq!(drive_of("/dev/nvme0n1p2"), Some("/dev/nvme0n1".to_string()));
+		assert_eq!(drive_of("/dev/nvme0n1"), Some("/dev/nvme0n1".to_string()));
+		assert_eq!(drive_of("/dev/mmcblk0p1"), Some("/dev/mmcblk0".to_string()));
+		assert_eq!(drive_of("/dev/disk3s1"), Some("/dev/disk3".to_string()));
+		assert_eq!(drive_of("/dev/mapper/root"), None);
+		assert_eq!(drive_of("/dev/dm-0"), None);
+		assert_eq!(drive_of("tank/data"), None);
+	}
+
+	#[test]
+	fn parses_ata_and_nvme_drives() {
+		let date = Utc::now();
+
+		let ata = json!({
+			"smart_status": { "passed": true },
+			"temperature": { "current": 41 },
+			"ata_smart_attributes": { "table": [
+				{ "id": 5, "raw": { "value": 8 } },
+				{ "id": 9, "raw": { "value": 21000 } },
+				{ "id": 197, "raw": { "value": 2 } },
+				{ "id": 198, "raw": { "value": 0 } },
+			] },
+		});
+
+		let sample = HealthSample::parse(&ata, date).expect("ATA health");
+		assert_eq!(sample.reallocated_sectors, Some(8));
+		assert_eq!(sample.pending_sectors, Some(2));
+		assert_eq!(sample.media_errors, None);
+		assert_eq!(
+			sample.alerts(),
+			vec![
+				HealthAlert::ReallocatedSectors { count: 8 },
+				HealthAlert::PendingSectors { count: 2 }
+			]
+		);
+
+		let nvme = json!({
+			"smart_status": { "passed": false },
+			"temperature": { "current": 72 },
+			"nvme_smart_health_information_log": { "media_errors": 0, "percentage_used": 14 },
+		});
+
+		let sample = HealthSample::parse(&nvme, date).expect("NVMe health");
+		assert_eq!(sample.percentage_used, Some(14));
+		assert_eq!(
+			sample.alerts(),
+			vec![
+				HealthAlert::Failing,
+				HealthAlert::Overheating { celsius: 72 }
+			]
+		);
+
+		assert_eq!(
+			HealthSample::parse(&json!({ "smartctl": { "exit_status": 2 } }), date),
+			None
+		);
+	}
+
+	#[test]
+	fn only_worsening_alerts_are_new() {
+		let previous = vec![
+			HealthAlert::ReallocatedSectors { count: 8 },
+			HealthAlert::Overheating { celsius: 61 },
+		];
+
+		assert_eq!(
+			new_alerts(
+				&previous,
+				&[
+					HealthAlert::ReallocatedSectors { count: 8 },
+					HealthAlert::Overheating { celsius: 63 }
+				]
+			),
+			vec![]
+		);
+		assert_eq!(
+			new_alerts(
+				&previous,
+				&[
+					HealthAlert::ReallocatedSectors { count: 12 },
+					HealthAlert::Failing
+				]
+			),
+			vec![
+				HealthAlert::ReallocatedSectors { count: 12 },
+				HealthAlert::Failing
+			]
+		);
+	}
+}
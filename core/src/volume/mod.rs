@@ -3,7 +3,7 @@
 use std::{
 	fmt::Display,
 	hash::{Hash, Hasher},
-	path::PathBuf,
+	path::{Path, PathBuf},
 	sync::OnceLock,
 };
 
@@ -15,6 +15,7 @@ use thiserror::Error;
 use tokio::sync::Mutex;
 use tracing::error;
 
+pub mod health;
 pub mod watcher;
 
 fn sys_guard() -> &'static Mutex<System> {
@@ -83,6 +84,28 @@ impl PartialEq for Volume {
 
 impl Eq for Volume {}
 
+impl Volume {
+	/// The volume holding `path`, being the one with the deepest mount point containing it, as
+	/// volumes can be mounted inside other volumes
+	pub fn for_path<'v>(volumes: &'v [Self], path: impl AsRef<Path>) -> Option<&'v Self> {
+		let path = path.as_ref();
+
+		volumes
+			.iter()
+			.filter_map(|volume| {
+				volume
+					.mount_points
+					.iter()
+					.filter(|mount_point| path.starts_with(mount_point))
+					.map(|mount_point| mount_point.components().count())
+					.max()
+					.map(|depth| (depth, volume))
+			})
+			.max_by_key(|(depth, _)| *depth)
+			.map(|(_, volume)| volume)
+	}
+}
+
 #[derive(Error, Debug)]
 pub enum VolumeError {
 	#[error("Database error: {0}")]
@@ -99,7 +122,7 @@ impl From<VolumeError> for rspc::Error {
 
 #[cfg(target_os = "linux")]
 pub async fn get_volumes() -> Vec<Volume> {
-	use std::{collections::HashMap, path::Path};
+	use std::collections::HashMap;
 
 	let mut sys = sys_guard().lock().await;
 	sys.refresh_disks_list();
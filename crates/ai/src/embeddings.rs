@@ -0,0 +1,229 @@
+use crate::{
+	image_labeler::MODELS_DIR_NAME,
+	old_image_labeler::{
+		model::{download_model, load_model, ModelSource},
+		DownloadModelError, ImageLabelerError,
+	},
+	utils::normalize,
+};
+
+use std::path::{Path, PathBuf};
+
+use image::{imageops::FilterType, DynamicImage, GenericImageView};
+use ndarray::Array;
+use once_cell::sync::Lazy;
+use ort::{inputs, Session};
+use thiserror::Error;
+use tokenizers::Tokenizer;
+use tracing::{info, trace};
+use url::Url;
+
+/// Identifies the model, embeddings of different models can't be compared with each other
+pub const CLIP_MODEL_ID: &str = "CLIP ViT-B/32";
+
+/// Length of the embeddings, which are L2 normalized so comparing them is just a dot product
+pub const EMBEDDING_SIZE: usize = 512;
+
+const IMAGE_INPUT_SIDE: u32 = 224;
+
+/// Mean and standard deviation of each channel in the images CLIP was trained on
+const IMAGE_MEAN: [f32; 3] = [0.481_454_66, 0.457_827_5, 0.408_210_73];
+const IMAGE_STD: [f32; 3] = [0.268_629_54, 0.261_302_58, 0.275_777_1];
+
+/// Context length of the text encoder, longer texts are cut
+const MAX_TEXT_TOKENS: usize = 77;
+
+const MODEL_URL: &str = "https://huggingface.co/Xenova/clip-vit-base-patch32/resolve/main";
+
+static VISION_MODEL: Lazy<ModelSource> = Lazy::new(|| {
+	ModelSource::Url(
+		Url::parse(&format!("{MODEL_URL}/onnx/vision_model_quantized.onnx"))
+			.expect("Must be a valid URL"),
+	)
+});
+static TEXT_MODEL: Lazy<ModelSource> = Lazy::new(|| {
+	ModelSource::Url(
+		Url::parse(&format!("{MODEL_URL}/onnx/text_model_quantized.onnx"))
+			.expect("Must be a valid URL"),
+	)
+});
+static TOKENIZER: Lazy<ModelSource> = Lazy::new(|| {
+	ModelSource::Url(
+		Url::parse(&format!("{MODEL_URL}/tokenizer.json")).expect("Must be a valid URL"),
+	)
+});
+
+#[derive(Debug, Error)]
+pub enum EmbeddingsError {
+	#[error("model executor failed: {0}")]
+	ModelExecutorFailed(#[from] ort::Error),
+	#[error("failed to get the embeddings model: {0}")]
+	DownloadModel(#[from] DownloadModelError),
+	#[error("failed to tokenize text: {0}")]
+	Tokenizer(String),
+	#[error("unexpected output from embeddings model: {0}")]
+	UnexpectedOutput(String),
+}
+
+impl From<ImageLabelerError> for EmbeddingsError {
+	fn from(e: ImageLabelerError) -> Self {
+		match e {
+			ImageLabelerError::ModelExecutorFailed(e) => Self::ModelExecutorFailed(e),
+			ImageLabelerError::DownloadModel(e) => Self::DownloadModel(e),
+			e => Self::UnexpectedOutput(e.to_string()),
+		}
+	}
+}
+
+/// Image half of CLIP, embedding images in the same space as [`TextEmbedder`] embeds text, so
+/// images can be found by describing them
+pub struct ImageEmbedder {
+	session: Session,
+}
+
+impl ImageEmbedder {
+	/// Loads the model from `data_dir`, downloading it first if it isn't there yet
+	pub async fn load(data_dir: impl AsRef<Path>) -> Result<Self, EmbeddingsError> {
+		let session = load_model(download_model(&VISION_MODEL, models_dir(data_dir)).await?)?;
+
+		info!("Loaded image embeddings model: {CLIP_MODEL_ID}");
+		trace!("{session:#?}");
+
+		Ok(Self { session })
+	}
+
+	/// L2 normalized, of [`EMBEDDING_SIZE`] length
+	pub fn embed(&self, image: &DynamicImage) -> Result<Vec<f32>, EmbeddingsError> {
+		// Shortest side resized to the input size and the center cropped, as CLIP was trained
+		let image =
+			image.resize_to_fill(IMAGE_INPUT_SIDE, IMAGE_INPUT_SIDE, FilterType::CatmullRom);
+
+		let side = IMAGE_INPUT_SIDE as usize;
+		let mut input = Array::<f32, _>::zeros((1, 3, side, side));
+		for (x, y, pixel) in image.pixels() {
+			for (channel, value) in pixel.0.into_iter().take(3).enumerate() {
+				input[[0, channel, y as usize, x as usize]] =
+					(f32::from(value) / 255.0 - IMAGE_MEAN[channel]) / IMAGE_STD[channel];
+			}
+		}
+
+		let outputs = self.session.run(inputs!["pixel_values" => input.view()]?)?;
+
+		embedding(
+			outputs["image_embeds"]
+				.extract_tensor::<f32>()?
+				.view()
+				.iter(),
+		)
+	}
+}
+
+impl std::fmt::Debug for ImageEmbedder {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("ImageEmbedder")
+			.field("model", &CLIP_MODEL_ID)
+			.finish_non_exhaustive()
+	}
+}
+
+/// Text half of CLIP, to find the images whose embeddings are closest to a description
+pub struct TextEmbedder {
+	session: Session,
+	tokenizer: Tokenizer,
+	// Some exports of the model take the attention mask too, even if it's all ones for a single text
+	takes_attention_mask: bool,
+}
+
+impl TextEmbedder {
+	/// Loads the model and its tokenizer from `data_dir`, downloading them first if they aren't
+	/// there yet
+	pub async fn load(data_dir: impl AsRef<Path>) -> Result<Self, EmbeddingsError> {
+		let models_dir = models_dir(data_dir);
+
+		let session = load_model(download_model(&TEXT_MODEL, &models_dir).await?)?;
+		let tokenizer = Tokenizer::from_file(download_model(&TOKENIZER, &models_dir).await?)
+			.map_err(|e| EmbeddingsError::Tokenizer(e.to_string()))?;
+
+		let takes_attention_mask = session
+			.inputs
+			.iter()
+			.any(|input| input.name == "attention_mask");
+
+		info!("Loaded text embeddings model: {CLIP_MODEL_ID}");
+		trace!("{session:#?}");
+
+		Ok(Self {
+			session,
+			tokenizer,
+			takes_attention_mask,
+		})
+	}
+
+	/// L2 normalized, of [`EMBEDDING_SIZE`] length
+	pub fn embed(&self, text: &str) -> Result<Vec<f32>, EmbeddingsError> {
+		let encoding = self
+			.tokenizer
+			.encode(text, true)
+			.map_err(|e| EmbeddingsError::Tokenizer(e.to_string()))?;
+
+		let mut ids = encoding
+			.get_ids()
+			.iter()
+			.map(|&id| i64::from(id))
+			.collect::<Vec<_>>();
+
+		// The text is pooled at its end token, so it's kept when cutting
+		if ids.len() > MAX_TEXT_TOKENS {
+			let end = ids[ids.len() - 1];
+			ids.truncate(MAX_TEXT_TOKENS - 1);
+			ids.push(end);
+		}
+
+		let len = ids.len();
+		let input_ids = Array::from_shape_vec((1, len), ids)
+			.map_err(|e| EmbeddingsError::UnexpectedOutput(e.to_string()))?;
+		let attention_mask = Array::<i64, _>::ones((1, len));
+
+		let outputs = if self.takes_attention_mask {
+			self.session.run(inputs![
+				"input_ids" => input_ids.view(),
+				"attention_mask" => attention_mask.view()
+			]?)?
+		} else {
+			self.session
+				.run(inputs!["input_ids" => input_ids.view()]?)?
+		};
+
+		embedding(
+			outputs["text_embeds"]
+				.extract_tensor::<f32>()?
+				.view()
+				.iter(),
+		)
+	}
+}
+
+impl std::fmt::Debug for TextEmbedder {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("TextEmbedder")
+			.field("model", &CLIP_MODEL_ID)
+			.finish_non_exhaustive()
+	}
+}
+
+fn models_dir(data_dir: impl AsRef<Path>) -> PathBuf {
+	data_dir.as_ref().join(MODELS_DIR_NAME).join("clip")
+}
+
+fn embedding<'a>(values: impl Iterator<Item = &'a f32>) -> Result<Vec<f32>, EmbeddingsError> {
+	let embedding = values.copied().collect::<Vec<_>>();
+
+	if embedding.len() != EMBEDDING_SIZE {
+		return Err(EmbeddingsError::UnexpectedOutput(format!(
+			"embedding with {} dimensions, expected {EMBEDDING_SIZE}",
+			embedding.len()
+		)));
+	}
+
+	Ok(normalize(embedding))
+}
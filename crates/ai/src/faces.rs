@@ -0,0 +1,301 @@
+use crate::{
+	old_image_labeler::{model::load_model, ImageLabelerError},
+	utils::{get_path_relative_to_exe, normalize, MODEL_LOCATION},
+};
+
+use std::{
+	cmp::Ordering,
+	path::{Path, PathBuf},
+};
+
+use image::{imageops::FilterType, DynamicImage, GenericImageView};
+use ndarray::{s, Array, Axis};
+use ort::{inputs, Session};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::fs;
+use tracing::{info, trace};
+
+/// Identifies the pair of models, embeddings of different models can't be compared with each other
+pub const FACE_MODELS_ID: &str = "UltraFace RFB-320, MobileFaceNet";
+
+const DETECTOR_FILE_NAME: &str = "version-RFB-320.onnx";
+const EMBEDDER_FILE_NAME: &str = "w600k_mbf.onnx";
+
+const DETECTOR_INPUT_WIDTH: u32 = 320;
+const DETECTOR_INPUT_HEIGHT: u32 = 240;
+const EMBEDDER_INPUT_SIDE: u32 = 112;
+
+/// Length of the embeddings, which are L2 normalized so comparing them is just a dot product
+pub const EMBEDDING_SIZE: usize = 512;
+
+/// Detections below this score are mostly faces on posters, statues and random textures
+const MIN_DETECTION_SCORE: f32 = 0.85;
+
+/// Overlapping detections of the same face are merged above this intersection over union
+const NMS_IOU_THRESHOLD: f32 = 0.3;
+
+/// Faces smaller than this, in pixels of the image given, have too little detail to be recognized
+const MIN_FACE_SIDE: f32 = 32.0;
+
+/// How much the detected box grows on each side before cropping, as the embedder was trained on
+/// crops with some forehead and chin
+const CROP_MARGIN: f32 = 0.2;
+
+#[derive(Debug, Error)]
+pub enum FacesError {
+	#[error("model executor failed: {0}")]
+	ModelExecutorFailed(#[from] ort::Error),
+	#[error("face model file not found: {}", .0.display())]
+	ModelFileNotFound(Box<Path>),
+	#[error("unexpected output from face model: {0}")]
+	UnexpectedOutput(String),
+}
+
+impl From<ImageLabelerError> for FacesError {
+	fn from(e: ImageLabelerError) -> Self {
+		match e {
+			ImageLabelerError::ModelExecutorFailed(e) => Self::ModelExecutorFailed(e),
+			e => Self::UnexpectedOutput(e.to_string()),
+		}
+	}
+}
+
+/// Box around a face, relative to the size of the image so it applies to the image and its
+/// thumbnail alike
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BoundingBox {
+	pub x: f32,
+	pub y: f32,
+	pub width: f32,
+	pub height: f32,
+}
+
+impl BoundingBox {
+	fn area(&self) -> f32 {
+		self.width * self.height
+	}
+
+	fn intersection_over_union(&self, other: &Self) -> f32 {
+		let width = (self.x + self.width).min(other.x + other.width) - self.x.max(other.x);
+		let height = (self.y + self.height).min(other.y + other.height) - self.y.max(other.y);
+
+		if width <= 0.0 || height <= 0.0 {
+			return 0.0;
+		}
+
+		let intersection = width * height;
+
+		intersection / (self.area() + other.area() - intersection)
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DetectedFace {
+	pub bounding_box: BoundingBox,
+	pub score: f32,
+	/// L2 normalized, of [`EMBEDDING_SIZE`] length
+	pub embedding: Vec<f32>,
+}
+
+/// Detector and embedder models, bundled with the app so images never leave the device to find
+/// faces in them
+pub struct FaceModels {
+	detector: Session,
+	embedder: Session,
+}
+
+impl FaceModels {
+	pub async fn load() -> Result<Self, FacesError> {
+		let (detector_path, embedder_path) = (
+			bundled_model_path(DETECTOR_FILE_NAME),
+			bundled_model_path(EMBEDDER_FILE_NAME),
+		);
+
+		for path in [&detector_path, &embedder_path] {
+			if fs::metadata(path).await.is_err() {
+				return Err(FacesError::ModelFileNotFound(path.as_path().into()));
+			}
+		}
+
+		let (detector, embedder) = (load_model(&detector_path)?, load_model(&embedder_path)?);
+
+		info!("Loaded face models: {FACE_MODELS_ID}");
+		trace!("{detector:#?}\n{embedder:#?}");
+
+		Ok(Self { detector, embedder })
+	}
+
+	/// Faces in an upright image, with their embeddings to tell who they are
+	pub fn detect(&self, image: &DynamicImage) -> Result<Vec<DetectedFace>, FacesError> {
+		let (image_width, image_height) = image.dimensions();
+		#[allow(clippy::cast_precision_loss)]
+		let (image_width, image_height) = (image_width as f32, image_height as f32);
+
+		self.find_faces(image)?
+			.into_iter()
+			.filter(|(bounding_box, _)| {
+				bounding_box.width * image_width >= MIN_FACE_SIDE
+					&& bounding_box.height * image_height >= MIN_FACE_SIDE
+			})
+			.map(|(bounding_box, score)| {
+				self.embed(&crop_face(image, &bounding_box))
+					.map(|embedding| DetectedFace {
+						bounding_box,
+						score,
+						embedding,
+					})
+			})
+			.collect()
+	}
+
+	fn find_faces(&self, image: &DynamicImage) -> Result<Vec<(BoundingBox, f32)>, FacesError> {
+		let img = image.resize_exact(
+			DETECTOR_INPUT_WIDTH,
+			DETECTOR_INPUT_HEIGHT,
+			FilterType::Triangle,
+		);
+
+		let mut input = Array::<f32, _>::zeros((
+			1,
+			3,
+			DETECTOR_INPUT_HEIGHT as usize,
+			DETECTOR_INPUT_WIDTH as usize,
+		));
+		for (x, y, pixel) in img.pixels() {
+			let [r, g, b, _] = pixel.0;
+			input[[0, 0, y as usize, x as usize]] = (f32::from(r) - 127.0) / 128.0;
+			input[[0, 1, y as usize, x as usize]] = (f32::from(g) - 127.0) / 128.0;
+			input[[0, 2, y as usize, x as usize]] = (f32::from(b) - 127.0) / 128.0;
+		}
+
+		let outputs = self.detector.run(inputs!["input" => input.view()]?)?;
+
+		let scores = outputs["scores"].extract_tensor::<f32>()?;
+		let boxes = outputs["boxes"].extract_tensor::<f32>()?;
+
+		let (scores, boxes) = (scores.view(), boxes.view());
+
+		let (scores, boxes) = (scores.slice(s![0, .., 1]), boxes.slice(s![0, .., ..]));
+
+		if scores.len() != boxes.len_of(Axis(0)) {
+			return Err(FacesError::UnexpectedOutput(format!(
+				"{} scores for {} boxes",
+				scores.len(),
+				boxes.len_of(Axis(0))
+			)));
+		}
+
+		let candidates = scores
+			.iter()
+			.zip(boxes.axis_iter(Axis(0)))
+			.filter(|(score, _)| **score >= MIN_DETECTION_SCORE)
+			.map(|(score, corners)| {
+				let (x1, y1) = (corners[0].clamp(0.0, 1.0), corners[1].clamp(0.0, 1.0));
+				let (x2, y2) = (corners[2].clamp(0.0, 1.0), corners[3].clamp(0.0, 1.0));
+
+				(
+					BoundingBox {
+						x: x1,
+						y: y1,
+						width: x2 - x1,
+						height: y2 - y1,
+					},
+					*score,
+				)
+			})
+			.filter(|(bounding_box, _)| bounding_box.width > 0.0 && bounding_box.height > 0.0)
+			.collect();
+
+		Ok(non_max_suppression(candidates))
+	}
+
+	fn embed(&self, face: &DynamicImage) -> Result<Vec<f32>, FacesError> {
+		let mut input = Array::<f32, _>::zeros((
+			1,
+			3,
+			EMBEDDER_INPUT_SIDE as usize,
+			EMBEDDER_INPUT_SIDE as usize,
+		));
+		for (x, y, pixel) in face.pixels() {
+			let [r, g, b, _] = pixel.0;
+			input[[0, 0, y as usize, x as usize]] = (f32::from(r) - 127.5) / 127.5;
+			input[[0, 1, y as usize, x as usize]] = (f32::from(g) - 127.5) / 127.5;
+			input[[0, 2, y as usize, x as usize]] = (f32::from(b) - 127.5) / 127.5;
+		}
+
+		let outputs = self.embedder.run(inputs![input.view()]?)?;
+
+		let embedding = outputs[0].extract_tensor::<f32>()?;
+		let embedding = embedding.view().iter().copied().collect::<Vec<_>>();
+
+		if embedding.len() != EMBEDDING_SIZE {
+			return Err(FacesError::UnexpectedOutput(format!(
+				"embedding with {} dimensions, expected {EMBEDDING_SIZE}",
+				embedding.len()
+			)));
+		}
+
+		Ok(normalize(embedding))
+	}
+}
+
+impl std::fmt::Debug for FaceModels {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("FaceModels")
+			.field("models", &FACE_MODELS_ID)
+			.finish_non_exhaustive()
+	}
+}
+
+fn bundled_model_path(file_name: &str) -> PathBuf {
+	get_path_relative_to_exe(Path::new(MODEL_LOCATION).join(file_name))
+}
+
+/// Keeps the best detection of each face, dropping the ones overlapping a better one
+fn non_max_suppression(mut candidates: Vec<(BoundingBox, f32)>) -> Vec<(BoundingBox, f32)> {
+	candidates.sort_unstable_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap_or(Ordering::Equal));
+
+	let mut kept = Vec::<(BoundingBox, f32)>::new();
+	for (bounding_box, score) in candidates {
+		if kept.iter().all(|(kept_box, _)| {
+			kept_box.intersection_over_union(&bounding_box) < NMS_IOU_THRESHOLD
+		}) {
+			kept.push((bounding_box, score));
+		}
+	}
+
+	kept
+}
+
+/// Square crop around the face with some margin, the shape the embedder expects
+#[allow(
+	clippy::cast_possible_truncation,
+	clippy::cast_sign_loss,
+	clippy::cast_precision_loss
+)]
+fn crop_face(image: &DynamicImage, bounding_box: &BoundingBox) -> DynamicImage {
+	let (width, height) = image.dimensions();
+	let (width, height) = (width as f32, height as f32);
+
+	let side = (bounding_box.width * width).max(bounding_box.height * height)
+		* 2.0f32.mul_add(CROP_MARGIN, 1.0);
+	let center_x = bounding_box.width.mul_add(0.5, bounding_box.x) * width;
+	let center_y = bounding_box.height.mul_add(0.5, bounding_box.y) * height;
+
+	let x = (center_x - side / 2.0).clamp(0.0, width - 1.0);
+	let y = (center_y - side / 2.0).clamp(0.0, height - 1.0);
+
+	image
+		.crop_imm(
+			x as u32,
+			y as u32,
+			(side.min(width - x) as u32).max(1),
+			(side.min(height - y) as u32).max(1),
+		)
+		.resize_exact(
+			EMBEDDER_INPUT_SIDE,
+			EMBEDDER_INPUT_SIDE,
+			FilterType::CatmullRom,
+		)
+}
@@ -0,0 +1,71 @@
+use std::{collections::HashMap, path::Path};
+
+use image::DynamicImage;
+use ort::Session;
+use tokio::fs;
+use tracing::{info, trace};
+
+pub use crate::old_image_labeler::{
+	assign_labels, DownloadModelError, ImageLabelerError, Model, YoloV8, DEFAULT_MODEL_VERSION,
+};
+
+use crate::old_image_labeler::model::{download_model, load_model};
+
+/// Directory inside the data directory where models are downloaded to, one directory per model
+pub const MODELS_DIR_NAME: &str = "models";
+
+/// A model loaded in an ONNX Runtime session, which can label images from many threads at once.
+///
+/// Unlike [`OldImageLabeler`](crate::old_image_labeler::OldImageLabeler), this doesn't run its own
+/// actor, whoever loads it decides how images are scheduled.
+pub struct LoadedModel {
+	model: Box<dyn Model>,
+	session: Session,
+}
+
+impl LoadedModel {
+	/// Loads the model from `data_dir`, downloading it first if its version isn't there yet
+	pub async fn load(
+		model: Box<dyn Model>,
+		data_dir: impl AsRef<Path>,
+	) -> Result<Self, ImageLabelerError> {
+		let model_path = download_model(
+			model.origin(),
+			data_dir.as_ref().join(MODELS_DIR_NAME).join(model.name()),
+		)
+		.await?;
+
+		if fs::metadata(&model_path).await.is_err() {
+			return Err(ImageLabelerError::ModelFileNotFound(model_path.into()));
+		}
+
+		let session = load_model(&model_path)?;
+
+		info!("Loaded model: {} {}", model.name(), model.version());
+		trace!("{session:#?}");
+
+		Ok(Self { model, session })
+	}
+
+	/// Identifies the model and its version, like `YoloV8 Yolo Small`, to know which objects were
+	/// labeled by older models
+	#[must_use]
+	pub fn id(&self) -> String {
+		format!("{} {}", self.model.name(), self.model.version())
+	}
+
+	/// Labels found in the image, with the highest confidence the model had for each of them
+	pub fn label(&self, image: &DynamicImage) -> Result<HashMap<String, f32>, ImageLabelerError> {
+		let inputs = self.model.prepare_image(image)?;
+		let outputs = self.session.run(inputs)?;
+		self.model.process_output(outputs)
+	}
+}
+
+impl std::fmt::Debug for LoadedModel {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("LoadedModel")
+			.field("model", &self.id())
+			.finish_non_exhaustive()
+	}
+}
@@ -3,6 +3,9 @@ use thiserror::Error;
 use ort::EnvironmentBuilder;
 use tracing::{debug, error};
 
+pub mod embeddings;
+pub mod faces;
+pub mod image_labeler;
 pub mod old_image_labeler;
 mod utils;
 
@@ -7,12 +7,13 @@ use thiserror::Error;
 use tracing::error;
 use uuid::Uuid;
 
-mod model;
+pub(crate) mod model;
 mod old_actor;
 mod process;
 
 pub use model::{DownloadModelError, Model, YoloV8, DEFAULT_MODEL_VERSION};
 pub use old_actor::OldImageLabeler;
+pub use process::assign_labels;
 
 pub type BatchToken = Uuid;
 
@@ -1,12 +1,12 @@
 use sd_utils::error::FileIOError;
 
 use std::{
-	collections::HashSet,
+	collections::HashMap,
 	path::{Path, PathBuf},
 };
 
 use futures::prelude::stream::StreamExt;
-use image::ImageFormat;
+use image::{DynamicImage, ImageFormat};
 use ort::{Session, SessionBuilder, SessionInputs, SessionOutputs};
 use thiserror::Error;
 use tokio::{
@@ -46,10 +46,17 @@ pub trait Model: Send + Sync + 'static {
 		format: ImageFormat,
 	) -> Result<SessionInputs<'image>, ImageLabelerError>;
 
+	/// Same as [`Model::prepare_input`], for images already decoded
+	fn prepare_image<'image>(
+		&self,
+		image: &DynamicImage,
+	) -> Result<SessionInputs<'image>, ImageLabelerError>;
+
+	/// Labels found in the image, with the highest confidence the model had for each of them
 	fn process_output(
 		&self,
 		output: SessionOutputs<'_>,
-	) -> Result<HashSet<String>, ImageLabelerError>;
+	) -> Result<HashMap<String, f32>, ImageLabelerError>;
 }
 
 pub(super) struct ModelAndSession {
@@ -141,7 +148,7 @@ impl ModelAndSession {
 		image_path: &Path,
 		image: Vec<u8>,
 		format: ImageFormat,
-	) -> Result<HashSet<String>, ImageLabelerError> {
+	) -> Result<HashMap<String, f32>, ImageLabelerError> {
 		if let (Some(session), Some(model)) = (&self.maybe_session, self.maybe_model.as_deref()) {
 			let inputs = model.prepare_input(image_path, &image, format)?;
 			let outputs = session.run(inputs)?;
@@ -168,7 +175,7 @@ pub enum DownloadModelError {
 	FileIO(#[from] FileIOError),
 }
 
-fn load_model(model_path: impl AsRef<Path>) -> Result<Session, ImageLabelerError> {
+pub(crate) fn load_model(model_path: impl AsRef<Path>) -> Result<Session, ImageLabelerError> {
 	SessionBuilder::new()?
 		.with_parallel_execution(true)?
 		.with_memory_pattern(true)?
@@ -176,7 +183,7 @@ fn load_model(model_path: impl AsRef<Path>) -> Result<Session, ImageLabelerError
 		.map_err(Into::into)
 }
 
-async fn download_model(
+pub(crate) async fn download_model(
 	model_origin: &ModelSource,
 	data_dir: impl AsRef<Path>,
 ) -> Result<PathBuf, DownloadModelError> {
@@ -1,13 +1,11 @@
-use crate::utils::get_path_relative_to_exe;
+use crate::utils::{get_path_relative_to_exe, MODEL_LOCATION};
 
-use std::{
-	collections::{HashMap, HashSet},
-	fmt::Display,
-	path::Path,
-};
+use std::{collections::HashMap, fmt::Display, path::Path};
 
 use half::f16;
-use image::{imageops::FilterType, load_from_memory_with_format, GenericImageView, ImageFormat};
+use image::{
+	imageops::FilterType, load_from_memory_with_format, DynamicImage, GenericImageView, ImageFormat,
+};
 use ndarray::{s, Array, Axis};
 use once_cell::sync::Lazy;
 use ort::{inputs, SessionInputs, SessionOutputs};
@@ -20,16 +18,6 @@ pub struct YoloV8 {
 	model_version: String,
 }
 
-// This path must be relative to the running binary
-#[cfg(windows)]
-const MODEL_LOCATION: &str = "./models";
-#[cfg(unix)]
-const MODEL_LOCATION: &str = if cfg!(target_os = "macos") {
-	"../Frameworks/Spacedrive.framework/Resources/Models"
-} else {
-	"../share/spacedrive/models"
-};
-
 pub static DEFAULT_MODEL_VERSION: &str = "Yolo Small";
 
 static MODEL_VERSIONS: Lazy<HashMap<&'static str, ModelSource>> = Lazy::new(|| {
@@ -98,6 +86,13 @@ impl Model for YoloV8 {
 		let original_img = load_from_memory_with_format(image, format)
 			.map_err(|e| ImageLabelerError::ImageLoadFailed(e, path.into()))?;
 
+		self.prepare_image(&original_img)
+	}
+
+	fn prepare_image<'image>(
+		&self,
+		original_img: &DynamicImage,
+	) -> Result<SessionInputs<'image>, ImageLabelerError> {
 		let img = original_img.resize_exact(640, 640, FilterType::CatmullRom);
 		let mut input = Array::<f16, _>::zeros((1, 3, 640, 640));
 		for pixel in img.pixels() {
@@ -117,7 +112,7 @@ impl Model for YoloV8 {
 	fn process_output(
 		&self,
 		output: SessionOutputs<'_>,
-	) -> Result<HashSet<String>, ImageLabelerError> {
+	) -> Result<HashMap<String, f32>, ImageLabelerError> {
 		#[rustfmt::skip]
 		const YOLOV8_CLASS_LABELS: [&str; 80] = [
 			"person", "bicycle", "car", "motorcycle", "airplane", "bus", "train", "truck",
@@ -155,14 +150,16 @@ impl Model for YoloV8 {
 					.reduce(|accum, row| if row.1 > accum.1 { row } else { accum })
 					.expect("not empty output")
 			})
-			.filter(|(_, probability)| probability.to_f32() > 0.6)
-			.map(|(class_id, _)| YOLOV8_CLASS_LABELS[class_id])
-			.fold(HashSet::default(), |mut set, label| {
-				if !set.contains(label) {
-					set.insert(label.to_string());
-				}
-
-				set
+			.map(|(class_id, probability)| (class_id, probability.to_f32()))
+			.filter(|(_, probability)| *probability > 0.6)
+			.fold(HashMap::new(), |mut labels, (class_id, probability)| {
+				// The same label can be detected many times in an image, we keep its best detection
+				let confidence = labels
+					.entry(YOLOV8_CLASS_LABELS[class_id].to_string())
+					.or_insert(probability);
+				*confidence = confidence.max(probability);
+
+				labels
 			}))
 	}
 }
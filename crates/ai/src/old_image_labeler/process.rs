@@ -394,9 +394,16 @@ async fn extract_file_data(
 		.map_err(|e| FileIOError::from((path, e, "Failed to read file to get labels")).into())
 }
 
+/// Links the labels found by a model to an object, with their confidence, creating the labels that
+/// don't exist yet.
+///
+/// Labels that a model assigned to the object before and weren't found again are unlinked, while the
+/// ones assigned by users, which have no confidence, are always kept.
+///
+/// Returns if any new label was created.
 pub async fn assign_labels(
 	object_id: object::id::Type,
-	mut labels: HashSet<String>,
+	labels: HashMap<String, f32>,
 	db: &PrismaClient,
 	sync: &sd_core_sync::Manager,
 ) -> Result<bool, ImageLabelerError> {
@@ -408,17 +415,23 @@ pub async fn assign_labels(
 		.await?
 		.unwrap();
 
+	let object_sync_id = || prisma_sync::object::SyncId {
+		pub_id: object.pub_id.clone(),
+	};
+
 	let mut has_new_labels = false;
 
+	let mut missing_labels = labels.keys().cloned().collect::<HashSet<_>>();
+
 	let mut labels_ids = db
 		.label()
-		.find_many(vec![label::name::in_vec(labels.iter().cloned().collect())])
+		.find_many(vec![label::name::in_vec(labels.keys().cloned().collect())])
 		.select(label::select!({ id name }))
 		.exec()
 		.await?
 		.into_iter()
 		.map(|label| {
-			labels.remove(&label.name);
+			missing_labels.remove(&label.name);
 
 			(label.id, label.name)
 		})
@@ -426,10 +439,10 @@ pub async fn assign_labels(
 
 	let date_created: DateTime<FixedOffset> = Utc::now().into();
 
-	if !labels.is_empty() {
-		let mut sync_params = Vec::with_capacity(labels.len() * 2);
+	if !missing_labels.is_empty() {
+		let mut sync_params = Vec::with_capacity(missing_labels.len() * 2);
 
-		let db_params = labels
+		let db_params = missing_labels
 			.into_iter()
 			.map(|name| {
 				sync_params.extend(sync.shared_create(
@@ -453,25 +466,99 @@ pub async fn assign_labels(
 		has_new_labels = true;
 	}
 
+	let linked_labels = db
+		.label_on_object()
+		.find_many(vec![label_on_object::object_id::equals(object_id)])
+		.select(label_on_object::select!({ label_id confidence label: select { name } }))
+		.exec()
+		.await?;
+
+	let mut stale_sync_params = vec![];
+	let mut stale_labels_ids = vec![];
+	let mut update_sync_params = vec![];
+	let mut update_db_params = vec![];
+
+	for linked in linked_labels {
+		// Labels assigned by users have no confidence, we never touch them
+		if linked.confidence.is_none() {
+			labels_ids.remove(&linked.label_id);
+			continue;
+		}
+
+		let sync_id = prisma_sync::label_on_object::SyncId {
+			label: prisma_sync::label::SyncId {
+				name: linked.label.name.clone(),
+			},
+			object: object_sync_id(),
+		};
+
+		if let Some(&confidence) = labels.get(&linked.label.name) {
+			labels_ids.remove(&linked.label_id);
+
+			let confidence = Some(f64::from(confidence));
+
+			update_sync_params.push(sync.relation_update(
+				sync_id,
+				label_on_object::confidence::NAME,
+				msgpack!(confidence),
+			));
+			update_db_params.push(db.label_on_object().update(
+				label_on_object::label_id_object_id(linked.label_id, object_id),
+				vec![label_on_object::confidence::set(confidence)],
+			));
+		} else {
+			stale_sync_params.push(sync.relation_delete(sync_id));
+			stale_labels_ids.push(linked.label_id);
+		}
+	}
+
+	if !stale_labels_ids.is_empty() {
+		sync.write_ops(
+			db,
+			(
+				stale_sync_params,
+				db.label_on_object().delete_many(vec![
+					label_on_object::object_id::equals(object_id),
+					label_on_object::label_id::in_vec(stale_labels_ids),
+				]),
+			),
+		)
+		.await?;
+	}
+
+	if !update_db_params.is_empty() {
+		sync.write_ops(db, (update_sync_params, update_db_params))
+			.await?;
+	}
+
 	let mut sync_params = Vec::with_capacity(labels_ids.len() * 2);
 
 	let db_params: Vec<_> = labels_ids
 		.into_iter()
 		.map(|(label_id, name)| {
-			sync_params.extend(sync.relation_create(
-				prisma_sync::label_on_object::SyncId {
-					label: prisma_sync::label::SyncId { name },
-					object: prisma_sync::object::SyncId {
-						pub_id: object.pub_id.clone(),
-					},
-				},
-				[],
+			let confidence = labels.get(&name).copied().map(f64::from);
+
+			let sync_id = prisma_sync::label_on_object::SyncId {
+				label: prisma_sync::label::SyncId { name },
+				object: object_sync_id(),
+			};
+
+			// Relations are created without their fields on other devices, so the confidence
+			// goes in its own operation
+			sync_params.extend(sync.relation_create(sync_id.clone(), []));
+			sync_params.push(sync.relation_update(
+				sync_id,
+				label_on_object::confidence::NAME,
+				msgpack!(confidence),
 			));
 
 			label_on_object::create_unchecked(
 				label_id,
 				object_id,
-				vec![label_on_object::date_created::set(date_created)],
+				vec![
+					label_on_object::date_created::set(date_created),
+					label_on_object::confidence::set(confidence),
+				],
 			)
 		})
 		.collect();
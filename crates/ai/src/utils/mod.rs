@@ -4,6 +4,16 @@ use std::{
 };
 use tracing::error;
 
+/// Where models bundled with the app are, this path must be relative to the running binary
+#[cfg(windows)]
+pub(crate) const MODEL_LOCATION: &str = "./models";
+#[cfg(unix)]
+pub(crate) const MODEL_LOCATION: &str = if cfg!(target_os = "macos") {
+	"../Frameworks/Spacedrive.framework/Resources/Models"
+} else {
+	"../share/spacedrive/models"
+};
+
 pub(crate) fn get_path_relative_to_exe(path: impl AsRef<Path>) -> PathBuf {
 	current_exe()
 		.unwrap_or_else(|e| {
@@ -25,3 +35,14 @@ pub(crate) fn get_path_relative_to_exe(path: impl AsRef<Path>) -> PathBuf {
 			},
 		)
 }
+
+/// Scales the embedding to a length of 1, so comparing it with others is just a dot product
+pub(crate) fn normalize(mut embedding: Vec<f32>) -> Vec<f32> {
+	let norm = embedding.iter().map(|v| v * v).sum::<f32>().sqrt();
+
+	if norm > 0.0 {
+		embedding.iter_mut().for_each(|v| *v /= norm);
+	}
+
+	embedding
+}
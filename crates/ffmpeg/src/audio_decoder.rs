@@ -0,0 +1,214 @@
+use crate::{
+	codec_ctx::FFmpegCodecContext,
+	error::{Error, FFmpegError},
+	format_ctx::FFmpegFormatContext,
+	utils::from_path,
+	video_frame::FFmpegFrame,
+};
+
+use std::{ffi::c_int, path::Path, ptr};
+
+use ffmpeg_sys_next::{
+	av_packet_alloc, av_packet_free, av_packet_unref, avcodec_find_decoder, AVFrame, AVPacket,
+	AVSampleFormat,
+};
+
+#[derive(Debug, Clone, Copy)]
+enum SampleFormat {
+	U8,
+	S16,
+	S32,
+	S64,
+	Flt,
+	Dbl,
+}
+
+impl SampleFormat {
+	/// Returns the format of each sample and if channels are on separate planes
+	fn from_raw(format: c_int) -> Result<(Self, bool), Error> {
+		use AVSampleFormat::{
+			AV_SAMPLE_FMT_DBL, AV_SAMPLE_FMT_DBLP, AV_SAMPLE_FMT_FLT, AV_SAMPLE_FMT_FLTP,
+			AV_SAMPLE_FMT_S16, AV_SAMPLE_FMT_S16P, AV_SAMPLE_FMT_S32, AV_SAMPLE_FMT_S32P,
+			AV_SAMPLE_FMT_S64, AV_SAMPLE_FMT_S64P, AV_SAMPLE_FMT_U8, AV_SAMPLE_FMT_U8P,
+		};
+
+		Ok(match format {
+			f if f == AV_SAMPLE_FMT_U8 as c_int => (Self::U8, false),
+			f if f == AV_SAMPLE_FMT_U8P as c_int => (Self::U8, true),
+			f if f == AV_SAMPLE_FMT_S16 as c_int => (Self::S16, false),
+			f if f == AV_SAMPLE_FMT_S16P as c_int => (Self::S16, true),
+			f if f == AV_SAMPLE_FMT_S32 as c_int => (Self::S32, false),
+			f if f == AV_SAMPLE_FMT_S32P as c_int => (Self::S32, true),
+			f if f == AV_SAMPLE_FMT_S64 as c_int => (Self::S64, false),
+			f if f == AV_SAMPLE_FMT_S64P as c_int => (Self::S64, true),
+			f if f == AV_SAMPLE_FMT_FLT as c_int => (Self::Flt, false),
+			f if f == AV_SAMPLE_FMT_FLTP as c_int => (Self::Flt, true),
+			f if f == AV_SAMPLE_FMT_DBL as c_int => (Self::Dbl, false),
+			f if f == AV_SAMPLE_FMT_DBLP as c_int => (Self::Dbl, true),
+			other => return Err(Error::UnsupportedSampleFormat(other)),
+		})
+	}
+
+	/// Normalized value, between -1.0 and 1.0, of the sample at `index` of a plane
+	///
+	/// # Safety
+	/// `plane` must point to at least `index + 1` samples of this format
+	#[allow(clippy::cast_ptr_alignment, clippy::cast_precision_loss)]
+	unsafe fn value(self, plane: *const u8, index: usize) -> f64 {
+		let value = match self {
+			Self::U8 => (f64::from(plane.add(index).read()) - 128.0) / 128.0,
+			Self::S16 => f64::from(plane.cast::<i16>().add(index).read_unaligned()) / 32_768.0,
+			Self::S32 => {
+				f64::from(plane.cast::<i32>().add(index).read_unaligned()) / 2_147_483_648.0
+			}
+			// Precision loss is fine here, we only want a rough value
+			Self::S64 => {
+				plane.cast::<i64>().add(index).read_unaligned() as f64 / 9_223_372_036_854_775_808.0
+			}
+			Self::Flt => f64::from(plane.cast::<f32>().add(index).read_unaligned()),
+			Self::Dbl => plane.cast::<f64>().add(index).read_unaligned(),
+		};
+
+		// Floating point samples can go over 1.0 when clipping
+		value.clamp(-1.0, 1.0)
+	}
+}
+
+/// Receives the decoded samples of an audio stream, one sample of all channels at a time
+pub(crate) trait AudioSink {
+	fn push_sample(&mut self, channels: &[f64]);
+
+	/// Sinks that only need the beginning of a file can stop the decoding early
+	fn is_full(&self) -> bool {
+		false
+	}
+}
+
+struct Packet(*mut AVPacket);
+
+impl Drop for Packet {
+	fn drop(&mut self) {
+		unsafe { av_packet_free(&mut self.0) };
+	}
+}
+
+/// Decodes the first audio stream of a file, feeding every sample to the sink built by `new_sink`
+/// from the stream's sample rate
+pub(crate) fn decode_audio<Sink: AudioSink>(
+	audio_file_path: impl AsRef<Path>,
+	new_sink: impl FnOnce(c_int) -> Sink,
+) -> Result<Sink, Error> {
+	let mut format_ctx =
+		FFmpegFormatContext::open_file(from_path(audio_file_path.as_ref())?.as_c_str())?;
+
+	format_ctx.find_stream_info()?;
+
+	let audio_stream = format_ctx.find_audio_stream()?;
+	let stream_index = audio_stream.index;
+	let codec_params = unsafe { audio_stream.codecpar.as_ref() }.ok_or(FFmpegError::NullError)?;
+
+	let audio_codec = unsafe { avcodec_find_decoder(codec_params.codec_id).as_ref() }
+		.ok_or(FFmpegError::DecoderNotFound)?;
+
+	let mut codec_ctx = FFmpegCodecContext::new()?;
+	codec_ctx.parameters_to_context(codec_params)?;
+	codec_ctx.open2(audio_codec)?;
+
+	let mut sink = new_sink(codec_ctx.as_ref().sample_rate);
+	let mut frame = FFmpegFrame::new()?;
+	let mut channels = Vec::new();
+
+	let packet = Packet(unsafe { av_packet_alloc() });
+	if packet.0.is_null() {
+		return Err(FFmpegError::NullError.into());
+	}
+
+	while !sink.is_full() && format_ctx.read_frame(packet.0).is_ok() {
+		if unsafe { (*packet.0).stream_index } == stream_index {
+			match codec_ctx.send_packet(packet.0) {
+				Ok(_) | Err(FFmpegError::Again) => {}
+				Err(e) => {
+					return Err(Error::FFmpegWithReason(
+						e,
+						"Failed to send packet to decoder".to_string(),
+					))
+				}
+			}
+
+			receive_frames(&mut codec_ctx, &mut frame, &mut channels, &mut sink)?;
+		}
+
+		unsafe { av_packet_unref(packet.0) };
+	}
+
+	if sink.is_full() {
+		return Ok(sink);
+	}
+
+	// Flushing the decoder, as it can still be holding some frames
+	match codec_ctx.send_packet(ptr::null_mut()) {
+		Ok(_) | Err(FFmpegError::Again) => {}
+		Err(e) => {
+			return Err(Error::FFmpegWithReason(
+				e,
+				"Failed to flush decoder".to_string(),
+			))
+		}
+	}
+	receive_frames(&mut codec_ctx, &mut frame, &mut channels, &mut sink)?;
+
+	Ok(sink)
+}
+
+fn receive_frames(
+	codec_ctx: &mut FFmpegCodecContext,
+	frame: &mut FFmpegFrame,
+	channels: &mut Vec<f64>,
+	sink: &mut impl AudioSink,
+) -> Result<(), Error> {
+	loop {
+		match codec_ctx.receive_frame(frame.as_mut()) {
+			Ok(true) => push_frame(frame.as_ref(), channels, sink)?,
+			Ok(false) | Err(FFmpegError::Again) => return Ok(()),
+			Err(e) => {
+				return Err(Error::FFmpegWithReason(
+					e,
+					"Failed to receive frame from decoder".to_string(),
+				))
+			}
+		}
+	}
+}
+
+fn push_frame(
+	frame: &AVFrame,
+	channels: &mut Vec<f64>,
+	sink: &mut impl AudioSink,
+) -> Result<(), Error> {
+	let (format, planar) = SampleFormat::from_raw(frame.format)?;
+	let samples = usize::try_from(frame.nb_samples)?;
+	let channels_count = usize::try_from(frame.ch_layout.nb_channels)?.max(1);
+
+	if frame.extended_data.is_null() {
+		return Err(FFmpegError::NullError.into());
+	}
+
+	for sample in 0..samples {
+		channels.clear();
+		channels.extend((0..channels_count).map(|channel| {
+			// SAFETY: the decoder gave us `samples` samples for each of the `channels_count`
+			// channels, on one plane per channel if planar or interleaved in the first one
+			unsafe {
+				if planar {
+					format.value(*frame.extended_data.add(channel), sample)
+				} else {
+					format.value(*frame.extended_data, sample * channels_count + channel)
+				}
+			}
+		}));
+
+		sink.push_sample(channels);
+	}
+
+	Ok(())
+}
@@ -0,0 +1,190 @@
+use crate::{
+	audio_decoder::{decode_audio, AudioSink},
+	error::Error,
+};
+
+use std::{f64::consts::PI, ffi::c_int, path::Path, time::Duration};
+
+/// Audio is decimated to around this rate before analysis, the notes we look at are way below its
+/// Nyquist frequency
+const TARGET_SAMPLE_RATE: usize = 11_025;
+
+/// Samples analyzed for each chroma frame, ~190ms at the target sample rate
+const WINDOW_SIZE: usize = 2048;
+
+/// Consecutive windows overlap by half of them
+const HOP_SIZE: usize = WINDOW_SIZE / 2;
+
+/// MIDI notes analyzed, from C3 to B6, where most melodies and harmonies live
+const NOTES: std::ops::Range<u8> = 48..96;
+
+/// Energy of each of the 12 pitch classes, from C to B, normalized so they add up to 1.0
+pub type Chroma = [f32; 12];
+
+/// Accumulates mono samples and computes the chroma of every window of them
+struct ChromaAccumulator {
+	decimation: usize,
+	decimation_acc: f64,
+	decimated_count: usize,
+	max_samples: usize,
+	total_samples: usize,
+	window: Vec<f64>,
+	hann: Vec<f64>,
+	/// Goertzel coefficient and pitch class of each analyzed note
+	notes: Vec<(f64, usize)>,
+	chromas: Vec<Chroma>,
+}
+
+impl ChromaAccumulator {
+	#[allow(clippy::cast_precision_loss)] // Sizes here are way smaller than f64's mantissa
+	fn new(sample_rate: c_int, max_duration: Duration) -> Self {
+		let sample_rate = usize::try_from(sample_rate)
+			.unwrap_or(TARGET_SAMPLE_RATE)
+			.max(1);
+		let decimation = (sample_rate / TARGET_SAMPLE_RATE).max(1);
+		let effective_rate = (sample_rate / decimation) as f64;
+
+		Self {
+			decimation,
+			decimation_acc: 0.0,
+			decimated_count: 0,
+			#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+			max_samples: (max_duration.as_secs_f64() * effective_rate) as usize,
+			total_samples: 0,
+			window: Vec::with_capacity(WINDOW_SIZE),
+			hann: (0..WINDOW_SIZE)
+				.map(|i| 0.5 - 0.5 * (2.0 * PI * i as f64 / (WINDOW_SIZE - 1) as f64).cos())
+				.collect(),
+			notes: NOTES
+				.map(|note| {
+					let frequency = 440.0 * 2.0f64.powf((f64::from(note) - 69.0) / 12.0);
+					(
+						2.0 * (2.0 * PI * frequency / effective_rate).cos(),
+						usize::from(note % 12),
+					)
+				})
+				.collect(),
+			chromas: Vec::new(),
+		}
+	}
+
+	fn push(&mut self, value: f64) {
+		self.decimation_acc += value;
+		self.decimated_count += 1;
+
+		if self.decimated_count < self.decimation {
+			return;
+		}
+
+		#[allow(clippy::cast_precision_loss)]
+		self.window
+			.push(self.decimation_acc / self.decimated_count as f64);
+		self.decimation_acc = 0.0;
+		self.decimated_count = 0;
+		self.total_samples += 1;
+
+		if self.window.len() == WINDOW_SIZE {
+			self.analyze_window();
+			self.window.drain(..HOP_SIZE);
+		}
+	}
+
+	/// Goertzel filters only compute the few frequencies we care about, which for 48 notes is
+	/// cheaper than a full FFT of the window
+	#[allow(clippy::cast_possible_truncation)] // Chromas don't need f64 precision
+	fn analyze_window(&mut self) {
+		let mut chroma = [0.0f64; 12];
+
+		for &(coefficient, pitch_class) in &self.notes {
+			let (s1, s2) = self.window.iter().zip(&self.hann).fold(
+				(0.0f64, 0.0f64),
+				|(s1, s2), (sample, hann)| {
+					(coefficient.mul_add(s1, sample.mul_add(*hann, -s2)), s1)
+				},
+			);
+
+			chroma[pitch_class] += (coefficient * s1).mul_add(-s2, s1.mul_add(s1, s2 * s2));
+		}
+
+		let total = chroma.iter().sum::<f64>();
+
+		// Silent windows have no pitch, so they are all zeros instead of noise amplified
+		self.chromas.push(if total > f64::EPSILON {
+			chroma.map(|energy| (energy / total) as f32)
+		} else {
+			[0.0; 12]
+		});
+	}
+}
+
+impl AudioSink for ChromaAccumulator {
+	fn push_sample(&mut self, channels: &[f64]) {
+		#[allow(clippy::cast_precision_loss)]
+		self.push(channels.iter().sum::<f64>() / channels.len().max(1) as f64);
+	}
+
+	fn is_full(&self) -> bool {
+		self.total_samples >= self.max_samples
+	}
+}
+
+/// Decodes up to `max_duration` of the first audio stream of a file, returning the chroma of each
+/// ~93ms of it
+pub(crate) fn extract_chromas(
+	audio_file_path: impl AsRef<Path>,
+	max_duration: Duration,
+) -> Result<Vec<Chroma>, Error> {
+	let chromas = decode_audio(audio_file_path, |sample_rate| {
+		ChromaAccumulator::new(sample_rate, max_duration)
+	})?
+	.chromas;
+
+	if chromas.is_empty() {
+		return Err(Error::NoAudioSamples);
+	}
+
+	Ok(chromas)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	#[allow(clippy::cast_precision_loss)]
+	fn sine_lands_on_its_pitch_class() {
+		let mut accumulator = ChromaAccumulator::new(44_100, Duration::from_secs(1));
+
+		// A4, so pitch class 9 counting from C
+		for i in 0..44_100 {
+			accumulator.push_sample(&[(2.0 * PI * 440.0 * f64::from(i) / 44_100.0).sin()]);
+		}
+
+		assert!(accumulator.is_full());
+		assert!(!accumulator.chromas.is_empty());
+
+		for chroma in &accumulator.chromas {
+			let loudest = chroma
+				.iter()
+				.enumerate()
+				.max_by(|(_, a), (_, b)| a.total_cmp(b))
+				.map(|(pitch_class, _)| pitch_class);
+
+			assert_eq!(loudest, Some(9));
+		}
+	}
+
+	#[test]
+	fn silence_has_no_pitch() {
+		let mut accumulator = ChromaAccumulator::new(11_025, Duration::from_secs(1));
+
+		for _ in 0..11_025 {
+			accumulator.push_sample(&[0.0, 0.0]);
+		}
+
+		assert!(accumulator
+			.chromas
+			.iter()
+			.all(|chroma| chroma.iter().all(|energy| *energy == 0.0)));
+	}
+}
@@ -42,6 +42,10 @@ pub enum Error {
 	FFmpegWithReason(FFmpegError, String),
 	#[error("Failed to decode video frame")]
 	FrameDecodeError,
+	#[error("No audio samples could be decoded")]
+	NoAudioSamples,
+	#[error("Unsupported audio sample format: {0}")]
+	UnsupportedSampleFormat(c_int),
 	#[error("Failed to seek video")]
 	SeekError,
 	#[error("Seek not allowed")]
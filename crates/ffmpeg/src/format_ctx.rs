@@ -191,6 +191,17 @@ impl FFmpegFormatContext {
 		Err(FFmpegError::StreamNotFound)?
 	}
 
+	pub(crate) fn find_audio_stream(&self) -> Result<&mut AVStream, Error> {
+		(0..self.as_ref().nb_streams)
+			.filter_map(|stream_idx| self.stream(stream_idx))
+			.find(|stream| {
+				unsafe { stream.codecpar.as_ref() }.is_some_and(|codec_params| {
+					codec_params.codec_type == AVMediaType::AVMEDIA_TYPE_AUDIO
+				})
+			})
+			.ok_or_else(|| FFmpegError::StreamNotFound.into())
+	}
+
 	fn formats(&self) -> Vec<String> {
 		unsafe { self.as_ref().iformat.as_ref() }
 			.and_then(|format| unsafe { format.name.as_ref() })
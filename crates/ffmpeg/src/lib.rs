@@ -29,10 +29,13 @@
 
 use crate::{format_ctx::FFmpegFormatContext, frame_decoder::FrameDecoder, utils::from_path};
 
-use std::path::Path;
+use std::{num::NonZeroU32, path::Path, time::Duration};
 
 use ffmpeg_sys_next::{av_log_set_level, AV_LOG_FATAL};
+use image::DynamicImage;
 
+mod audio_decoder;
+mod chroma;
 mod codec_ctx;
 mod dict;
 mod error;
@@ -40,13 +43,17 @@ mod filter_graph;
 mod format_ctx;
 mod frame_decoder;
 pub mod model;
+mod speech;
 mod thumbnailer;
 mod utils;
 mod video_frame;
+mod waveform;
 
-pub use error::Error;
+pub use chroma::Chroma;
+pub use error::{Error, FFmpegError};
 pub use frame_decoder::ThumbnailSize;
 pub use model::FFmpegMediaData;
+pub use speech::SPEECH_SAMPLE_RATE;
 pub use thumbnailer::ThumbnailerBuilder;
 use tokio::task::spawn_blocking;
 
@@ -97,6 +104,90 @@ pub async fn to_thumbnail(
 		.await
 }
 
+/// Helper function to generate a preview strip file from a video file, with `frames` frames taken
+/// across its duration side by side in a single image, so UIs can scrub through it on hover
+pub async fn to_preview_strip(
+	video_file_path: impl AsRef<Path> + Send,
+	output_strip_path: impl AsRef<Path> + Send,
+	frames: NonZeroU32,
+	size: ThumbnailSize,
+	quality: f32,
+) -> Result<(), Error> {
+	// Reduce the amount of logs generated by FFmpeg
+	unsafe { av_log_set_level(AV_LOG_FATAL) };
+
+	ThumbnailerBuilder::new()
+		.size(size)
+		.quality(quality)?
+		.build()
+		.process_strip(video_file_path, output_strip_path, frames)
+		.await
+}
+
+/// Helper function to extract the waveform of an audio file, as `peaks` peak amplitudes between
+/// 0.0 and 1.0 evenly spread across its duration
+pub async fn to_waveform(
+	audio_file_path: impl AsRef<Path> + Send,
+	peaks: NonZeroU32,
+) -> Result<Vec<f64>, Error> {
+	// Reduce the amount of logs generated by FFmpeg
+	unsafe { av_log_set_level(AV_LOG_FATAL) };
+
+	spawn_blocking({
+		let audio_file_path = audio_file_path.as_ref().to_path_buf();
+		move || waveform::extract_peaks(audio_file_path, peaks)
+	})
+	.await?
+}
+
+/// Helper function to extract the chroma of the beginning of an audio file, the energy of each of
+/// the 12 pitch classes over time, which survives re-encoding way better than the samples themselves
+pub async fn to_chroma(
+	audio_file_path: impl AsRef<Path> + Send,
+	max_duration: Duration,
+) -> Result<Vec<Chroma>, Error> {
+	// Reduce the amount of logs generated by FFmpeg
+	unsafe { av_log_set_level(AV_LOG_FATAL) };
+
+	spawn_blocking({
+		let audio_file_path = audio_file_path.as_ref().to_path_buf();
+		move || chroma::extract_chromas(audio_file_path, max_duration)
+	})
+	.await?
+}
+
+/// Helper function to decode the audio of a file for speech recognition, as up to `max_duration` of
+/// mono samples at [`SPEECH_SAMPLE_RATE`]
+pub async fn to_speech_samples(
+	audio_file_path: impl AsRef<Path> + Send,
+	max_duration: Duration,
+) -> Result<Vec<f32>, Error> {
+	// Reduce the amount of logs generated by FFmpeg
+	unsafe { av_log_set_level(AV_LOG_FATAL) };
+
+	spawn_blocking({
+		let audio_file_path = audio_file_path.as_ref().to_path_buf();
+		move || speech::extract_speech_samples(audio_file_path, max_duration)
+	})
+	.await?
+}
+
+/// Helper function to decode `frames` frames taken evenly across the duration of a video file
+pub async fn to_frame_samples(
+	video_file_path: impl AsRef<Path> + Send,
+	frames: NonZeroU32,
+	size: ThumbnailSize,
+) -> Result<Vec<DynamicImage>, Error> {
+	// Reduce the amount of logs generated by FFmpeg
+	unsafe { av_log_set_level(AV_LOG_FATAL) };
+
+	spawn_blocking({
+		let video_file_path = video_file_path.as_ref().to_path_buf();
+		move || thumbnailer::sample_frames(&video_file_path, frames, size, true)
+	})
+	.await?
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
@@ -0,0 +1,159 @@
+use crate::{
+	audio_decoder::{decode_audio, AudioSink},
+	error::Error,
+};
+
+use std::{ffi::c_int, path::Path, time::Duration};
+
+/// Sample rate of the audio decoded for speech recognition, the one its models are trained with
+pub const SPEECH_SAMPLE_RATE: u32 = 16_000;
+
+/// Downmixes to mono and resamples to [`SPEECH_SAMPLE_RATE`], averaging the input samples that
+/// fall on each output sample so higher frequencies don't fold back as noise
+struct SpeechAccumulator {
+	/// Input samples for each output sample
+	ratio: f64,
+	input_count: u64,
+	sum: f64,
+	summed: u32,
+	max_samples: usize,
+	samples: Vec<f32>,
+}
+
+impl SpeechAccumulator {
+	#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+	fn new(sample_rate: c_int, max_duration: Duration) -> Self {
+		let sample_rate = u32::try_from(sample_rate)
+			.ok()
+			.filter(|sample_rate| *sample_rate > 0)
+			.unwrap_or(SPEECH_SAMPLE_RATE);
+
+		Self {
+			ratio: f64::from(sample_rate) / f64::from(SPEECH_SAMPLE_RATE),
+			input_count: 0,
+			sum: 0.0,
+			summed: 0,
+			max_samples: (max_duration.as_secs_f64() * f64::from(SPEECH_SAMPLE_RATE)) as usize,
+			samples: Vec::new(),
+		}
+	}
+
+	#[allow(
+		clippy::cast_possible_truncation,
+		clippy::cast_sign_loss,
+		clippy::cast_precision_loss
+	)]
+	fn push(&mut self, value: f64) {
+		let output_index = (self.input_count as f64 / self.ratio) as usize;
+		self.input_count += 1;
+
+		if output_index > self.samples.len() {
+			self.flush(output_index);
+		}
+
+		self.sum += value;
+		self.summed += 1;
+	}
+
+	/// Outputs the average of the summed input samples up to `output_index`, repeating it when
+	/// upsampling leaves gaps between input samples
+	#[allow(clippy::cast_possible_truncation)]
+	fn flush(&mut self, output_index: usize) {
+		if self.summed == 0 {
+			return;
+		}
+
+		let value = (self.sum / f64::from(self.summed)) as f32;
+		let end = output_index.min(self.max_samples);
+		if end > self.samples.len() {
+			self.samples.resize(end, value);
+		}
+
+		self.sum = 0.0;
+		self.summed = 0;
+	}
+
+	fn finish(mut self) -> Vec<f32> {
+		self.flush(self.samples.len() + 1);
+		self.samples
+	}
+}
+
+impl AudioSink for SpeechAccumulator {
+	fn push_sample(&mut self, channels: &[f64]) {
+		#[allow(clippy::cast_precision_loss)]
+		self.push(channels.iter().sum::<f64>() / channels.len().max(1) as f64);
+	}
+
+	fn is_full(&self) -> bool {
+		self.samples.len() >= self.max_samples
+	}
+}
+
+/// Decodes up to `max_duration` of the first audio stream of a file, as mono samples between -1.0
+/// and 1.0 at [`SPEECH_SAMPLE_RATE`]
+pub(crate) fn extract_speech_samples(
+	audio_file_path: impl AsRef<Path>,
+	max_duration: Duration,
+) -> Result<Vec<f32>, Error> {
+	let samples = decode_audio(audio_file_path, |sample_rate| {
+		SpeechAccumulator::new(sample_rate, max_duration)
+	})?
+	.finish();
+
+	if samples.is_empty() {
+		return Err(Error::NoAudioSamples);
+	}
+
+	Ok(samples)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn downsampling_keeps_the_duration() {
+		let mut accumulator = SpeechAccumulator::new(48_000, Duration::from_secs(10));
+
+		for i in 0..48_000 {
+			accumulator.push_sample(&[f64::from(i % 2), 0.0]);
+		}
+
+		let samples = accumulator.finish();
+
+		assert_eq!(samples.len(), 16_000);
+		// Each output sample averages 3 input samples of both channels
+		assert!(samples.iter().all(|sample| (0.15..=0.35).contains(sample)));
+	}
+
+	#[test]
+	fn upsampling_fills_the_gaps() {
+		let mut accumulator = SpeechAccumulator::new(8_000, Duration::from_secs(10));
+
+		for _ in 0..8_000 {
+			accumulator.push_sample(&[0.5]);
+		}
+
+		let samples = accumulator.finish();
+
+		assert_eq!(samples.len(), 15_999);
+		assert!(samples
+			.iter()
+			.all(|sample| (*sample - 0.5).abs() < f32::EPSILON));
+	}
+
+	#[test]
+	fn stops_at_max_duration() {
+		let mut accumulator = SpeechAccumulator::new(16_000, Duration::from_secs(1));
+
+		for _ in 0..32_000 {
+			if accumulator.is_full() {
+				break;
+			}
+			accumulator.push_sample(&[0.0]);
+		}
+
+		assert_eq!(accumulator.finish().len(), 16_000);
+	}
+}
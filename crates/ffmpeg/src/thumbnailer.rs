@@ -1,6 +1,9 @@
-use crate::{frame_decoder::ThumbnailSize, Error, FrameDecoder};
+use crate::{
+	frame_decoder::{ThumbnailSize, VideoFrame},
+	Error, FrameDecoder,
+};
 
-use std::{io, ops::Deref, path::Path};
+use std::{io, num::NonZeroU32, ops::Deref, path::Path};
 
 use image::{imageops, DynamicImage, RgbImage};
 use sd_utils::error::FileIOError;
@@ -97,24 +100,10 @@ impl Thumbnailer {
 					}
 				}
 
-				let video_frame =
-					decoder.get_scaled_video_frame(Some(size), maintain_aspect_ratio)?;
-
-				let mut image = DynamicImage::ImageRgb8(
-					RgbImage::from_raw(video_frame.width, video_frame.height, video_frame.data)
-						.ok_or(Error::CorruptVideo(video_file_path.into_boxed_path()))?,
-				);
-
-				let image = if video_frame.rotation < -135.0 {
-					imageops::rotate180_in_place(&mut image);
-					image
-				} else if video_frame.rotation > 45.0 && video_frame.rotation < 135.0 {
-					image.rotate270()
-				} else if video_frame.rotation < -45.0 && video_frame.rotation > -135.0 {
-					image.rotate90()
-				} else {
-					image
-				};
+				let image = frame_to_image(
+					decoder.get_scaled_video_frame(Some(size), maintain_aspect_ratio)?,
+					&video_file_path,
+				)?;
 
 				// Type WebPMemory is !Send, which makes the Future in this function !Send,
 				// this make us `deref` to have a `&[u8]` and then `to_owned` to make a Vec<u8>
@@ -128,6 +117,137 @@ impl Thumbnailer {
 		})
 		.await?
 	}
+
+	/// Processes an video input file and write to file system a preview strip with webp format,
+	/// a single image with `frames` frames side by side, taken evenly across the video's duration
+	pub(crate) async fn process_strip(
+		&self,
+		video_file_path: impl AsRef<Path> + Send,
+		output_strip_path: impl AsRef<Path> + Send,
+		frames: NonZeroU32,
+	) -> Result<(), Error> {
+		let output_strip_path = output_strip_path.as_ref();
+		let path = output_strip_path.parent().ok_or_else(|| {
+			FileIOError::from((
+				output_strip_path,
+				io::Error::new(
+					io::ErrorKind::InvalidInput,
+					"Cannot determine parent directory",
+				),
+			))
+		})?;
+
+		fs::create_dir_all(path)
+			.await
+			.map_err(|e| FileIOError::from((path, e)))?;
+
+		fs::write(
+			output_strip_path,
+			&*self
+				.process_strip_to_webp_bytes(video_file_path, frames)
+				.await?,
+		)
+		.await
+		.map_err(|e| FileIOError::from((output_strip_path, e)).into())
+	}
+
+	/// Processes an video input file and returns a webp encoded preview strip as bytes
+	async fn process_strip_to_webp_bytes(
+		&self,
+		video_file_path: impl AsRef<Path> + Send,
+		frames: NonZeroU32,
+	) -> Result<Vec<u8>, Error> {
+		let size = self.builder.size;
+		let maintain_aspect_ratio = self.builder.maintain_aspect_ratio;
+		let quality = self.builder.quality;
+
+		spawn_blocking({
+			let video_file_path = video_file_path.as_ref().to_path_buf();
+			move || -> Result<Vec<u8>, Error> {
+				let images = sample_frames(&video_file_path, frames, size, maintain_aspect_ratio)?;
+
+				// Every frame is scaled the same way, only the first one's size is needed
+				let (frame_width, frame_height) = images
+					.first()
+					.map(|image| (image.width(), image.height()))
+					.ok_or(Error::FrameDecodeError)?;
+
+				let mut strip = RgbImage::new(frame_width * frames.get(), frame_height);
+				for (i, image) in (0..).zip(images) {
+					imageops::replace(
+						&mut strip,
+						&image.into_rgb8(),
+						i64::from(i * frame_width),
+						0,
+					);
+				}
+
+				// Type WebPMemory is !Send, which makes the Future in this function !Send,
+				// this make us `deref` to have a `&[u8]` and then `to_owned` to make a Vec<u8>
+				// which implies on a unwanted clone...
+				Ok(Encoder::from_image(&DynamicImage::ImageRgb8(strip))
+					.expect("Should not fail as the underlining DynamicImage is an RgbImage")
+					.encode(quality)
+					.deref()
+					.to_vec())
+			}
+		})
+		.await?
+	}
+}
+
+/// Decodes `frames` frames taken evenly across the video's duration, from the middle of each equal
+/// slice of it, so the first and last ones aren't black fades
+pub(crate) fn sample_frames(
+	video_file_path: &Path,
+	frames: NonZeroU32,
+	size: ThumbnailSize,
+	maintain_aspect_ratio: bool,
+) -> Result<Vec<DynamicImage>, Error> {
+	// Embedded covers are a single image, frames must come from the video stream
+	let mut decoder = FrameDecoder::new(video_file_path, true, false)?;
+
+	// We actually have to decode a frame to get some metadata before we can start decoding for real
+	decoder.decode_video_frame()?;
+
+	let duration = decoder.get_duration_secs().ok_or(Error::NoVideoDuration)?;
+
+	let mut images = Vec::with_capacity(frames.get() as usize);
+	for i in 0..frames.get() {
+		let seek_secs = duration * (f64::from(i) + 0.5) / f64::from(frames.get());
+
+		#[allow(clippy::cast_possible_truncation)]
+		{
+			// This conversion is ok because we don't worry much about precision here
+			decoder.seek(seek_secs.floor() as i64)?;
+		}
+
+		images.push(frame_to_image(
+			decoder.get_scaled_video_frame(Some(size), maintain_aspect_ratio)?,
+			video_file_path,
+		)?);
+	}
+
+	Ok(images)
+}
+
+/// Turns a decoded frame into an image, rotated as the video stream says it should be shown
+fn frame_to_image(video_frame: VideoFrame, video_file_path: &Path) -> Result<DynamicImage, Error> {
+	let mut image = DynamicImage::ImageRgb8(
+		RgbImage::from_raw(video_frame.width, video_frame.height, video_frame.data)
+			.ok_or_else(|| Error::CorruptVideo(video_file_path.into()))?,
+	);
+
+	Ok(if video_frame.rotation < -135.0 {
+		imageops::rotate180_in_place(&mut image);
+		image
+	} else if video_frame.rotation > 45.0 && video_frame.rotation < 135.0 {
+		image.rotate270()
+	} else if video_frame.rotation < -45.0 && video_frame.rotation > -135.0 {
+		image.rotate90()
+	} else {
+		image
+	})
 }
 
 /// `ThumbnailerBuilder` struct holds data to build a `Thumbnailer` struct, exposing many methods
@@ -0,0 +1,115 @@
+use crate::{
+	audio_decoder::{decode_audio, AudioSink},
+	error::Error,
+};
+
+use std::{ffi::c_int, num::NonZeroU32, path::Path};
+
+/// Amount of peaks kept per second of audio while decoding, before downsampling them to the
+/// requested amount, so long files don't need to have all their samples in memory
+const PEAKS_PER_SECOND: usize = 100;
+
+/// Accumulates the peak amplitude of every chunk of samples
+struct PeakAccumulator {
+	chunk_size: usize,
+	current_peak: f64,
+	samples_in_chunk: usize,
+	peaks: Vec<f64>,
+}
+
+impl PeakAccumulator {
+	fn new(sample_rate: c_int) -> Self {
+		Self {
+			chunk_size: usize::try_from(sample_rate)
+				.map_or(1, |sample_rate| sample_rate / PEAKS_PER_SECOND)
+				.max(1),
+			current_peak: 0.0,
+			samples_in_chunk: 0,
+			peaks: Vec::new(),
+		}
+	}
+
+	fn push(&mut self, amplitude: f64) {
+		self.current_peak = self.current_peak.max(amplitude);
+		self.samples_in_chunk += 1;
+
+		if self.samples_in_chunk == self.chunk_size {
+			self.peaks.push(self.current_peak);
+			self.current_peak = 0.0;
+			self.samples_in_chunk = 0;
+		}
+	}
+
+	fn finish(mut self) -> Vec<f64> {
+		if self.samples_in_chunk > 0 {
+			self.peaks.push(self.current_peak);
+		}
+
+		self.peaks
+	}
+}
+
+impl AudioSink for PeakAccumulator {
+	fn push_sample(&mut self, channels: &[f64]) {
+		self.push(channels.iter().map(|value| value.abs()).fold(0.0, f64::max));
+	}
+}
+
+/// Takes the max of evenly sized ranges of `peaks`, so we end up with exactly `amount` peaks
+fn downsample(peaks: &[f64], amount: NonZeroU32) -> Vec<f64> {
+	let amount = amount.get() as usize;
+
+	(0..amount)
+		.map(|i| {
+			let start = i * peaks.len() / amount;
+			// Short files have less peaks than requested, so the same peak is repeated
+			let end = ((i + 1) * peaks.len() / amount).max(start + 1);
+
+			peaks
+				.get(start..end.min(peaks.len()))
+				.unwrap_or_default()
+				.iter()
+				.copied()
+				.fold(0.0, f64::max)
+		})
+		.collect()
+}
+
+/// Decodes the whole first audio stream of a file, returning `amount` peak amplitudes evenly
+/// spread across its duration, between 0.0 and 1.0
+pub(crate) fn extract_peaks(
+	audio_file_path: impl AsRef<Path>,
+	amount: NonZeroU32,
+) -> Result<Vec<f64>, Error> {
+	let peaks = decode_audio(audio_file_path, PeakAccumulator::new)?.finish();
+	if peaks.is_empty() {
+		return Err(Error::NoAudioSamples);
+	}
+
+	Ok(downsample(&peaks, amount))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn downsample_takes_max_of_each_range() {
+		let peaks = [0.1, 0.5, 0.2, 0.9, 0.3, 0.4];
+
+		assert_eq!(
+			downsample(&peaks, NonZeroU32::new(3).unwrap()),
+			vec![0.5, 0.9, 0.4]
+		);
+	}
+
+	#[test]
+	fn downsample_repeats_peaks_of_short_files() {
+		let peaks = [0.1, 0.5];
+
+		assert_eq!(
+			downsample(&peaks, NonZeroU32::new(4).unwrap()),
+			vec![0.1, 0.1, 0.5, 0.5]
+		);
+	}
+}
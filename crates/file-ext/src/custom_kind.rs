@@ -0,0 +1,241 @@
+use std::{ffi::OsStr, path::Path};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::{fs::File, io::AsyncReadExt};
+
+/// How far into a file magic bytes are looked for, matchers reaching past it never match
+pub const MAX_HEADER_LENGTH: usize = 64 * 1024;
+
+/// A byte signature expected at `offset` from the start of a file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct MagicBytesMatcher {
+	pub offset: usize,
+	pub bytes: Vec<u8>,
+}
+
+impl MagicBytesMatcher {
+	#[must_use]
+	pub fn matches(&self, header: &[u8]) -> bool {
+		self.end()
+			.and_then(|end| header.get(self.offset..end))
+			.is_some_and(|window| window == self.bytes)
+	}
+
+	const fn end(&self) -> Option<usize> {
+		self.offset.checked_add(self.bytes.len())
+	}
+}
+
+/// A kind defined by a library on top of the builtin [`ObjectKind`](crate::kind::ObjectKind)s,
+/// like "CAD" or "Genomics"
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct CustomKind {
+	pub name: String,
+	/// Extensions without the leading dot, compared case insensitively
+	#[serde(default)]
+	pub extensions: Vec<String>,
+	#[serde(default)]
+	pub magic_bytes: Vec<MagicBytesMatcher>,
+}
+
+/// Custom kinds known to a library, resolved alongside the builtin kind of each file
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KindRegistry {
+	kinds: Vec<CustomKind>,
+}
+
+impl KindRegistry {
+	#[must_use]
+	pub fn new(kinds: impl IntoIterator<Item = CustomKind>) -> Self {
+		let mut registry = Self::default();
+		for kind in kinds {
+			registry.register(kind);
+		}
+		registry
+	}
+
+	/// Adds a kind to the registry, replacing a previous one with the same name
+	pub fn register(&mut self, mut kind: CustomKind) {
+		kind.extensions
+			.iter_mut()
+			.for_each(|ext| *ext = ext.trim_start_matches('.').to_lowercase());
+
+		if let Some(existing) = self.kinds.iter_mut().find(|k| k.name == kind.name) {
+			*existing = kind;
+		} else {
+			self.kinds.push(kind);
+		}
+	}
+
+	#[must_use]
+	pub fn kinds(&self) -> &[CustomKind] {
+		&self.kinds
+	}
+
+	#[must_use]
+	pub fn is_empty(&self) -> bool {
+		self.kinds.is_empty()
+	}
+
+	/// How many bytes from the start of a file are needed to check every magic bytes matcher,
+	/// up to [`MAX_HEADER_LENGTH`]
+	fn header_length(&self) -> usize {
+		self.kinds
+			.iter()
+			.flat_map(|kind| kind.magic_bytes.iter().filter_map(MagicBytesMatcher::end))
+			.filter(|end| *end <= MAX_HEADER_LENGTH)
+			.max()
+			.unwrap_or(0)
+	}
+
+	/// Finds the custom kind of a file from its extension, falling back to its first bytes.
+	/// Kinds are checked in registration order, so the first one registered wins conflicts.
+	#[must_use]
+	pub fn resolve(&self, extension: Option<&str>, header: &[u8]) -> Option<&CustomKind> {
+		extension
+			.map(str::to_lowercase)
+			.and_then(|extension| {
+				self.kinds
+					.iter()
+					.find(|kind| kind.extensions.contains(&extension))
+			})
+			.or_else(|| {
+				self.kinds.iter().find(|kind| {
+					kind.magic_bytes
+						.iter()
+						.any(|matcher| matcher.matches(header))
+				})
+			})
+	}
+
+	/// Same as [`KindRegistry::resolve`], reading the header from the file only when the extension
+	/// alone doesn't tell its kind
+	pub async fn resolve_path(&self, path: impl AsRef<Path>) -> Option<&CustomKind> {
+		let path = path.as_ref();
+		let extension = path.extension().and_then(OsStr::to_str);
+
+		if let Some(kind) = self.resolve(extension, &[]) {
+			return Some(kind);
+		}
+
+		let header_length = self.header_length();
+		if header_length == 0 {
+			return None;
+		}
+
+		let mut header = Vec::with_capacity(header_length);
+		File::open(path)
+			.await
+			.ok()?
+			.take(header_length as u64)
+			.read_to_end(&mut header)
+			.await
+			.ok()?;
+
+		self.resolve(None, &header)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn registry() -> KindRegistry {
+		KindRegistry::new([
+			CustomKind {
+				name: "CAD".to_string(),
+				extensions: vec!["dwg".to_string(), ".STEP".to_string()],
+				magic_bytes: vec![MagicBytesMatcher {
+					offset: 0,
+					bytes: b"AC10".to_vec(),
+				}],
+			},
+			CustomKind {
+				name: "Genomics".to_string(),
+				extensions: vec!["fastq".to_string(), "bam".to_string()],
+				magic_bytes: vec![MagicBytesMatcher {
+					offset: 0,
+					bytes: vec![0x42, 0x41, 0x4D, 0x01],
+				}],
+			},
+		])
+	}
+
+	#[test]
+	fn resolve_by_extension() {
+		let registry = registry();
+
+		assert_eq!(
+			registry.resolve(Some("step"), &[]).map(|k| k.name.as_str()),
+			Some("CAD")
+		);
+		assert_eq!(
+			registry
+				.resolve(Some("FASTQ"), &[])
+				.map(|k| k.name.as_str()),
+			Some("Genomics")
+		);
+		assert_eq!(registry.resolve(Some("jpg"), &[]), None);
+	}
+
+	#[test]
+	fn resolve_by_magic_bytes() {
+		let registry = registry();
+
+		assert_eq!(
+			registry
+				.resolve(None, b"BAM\x01\x00\x00")
+				.map(|k| k.name.as_str()),
+			Some("Genomics")
+		);
+		// extensions win over magic bytes
+		assert_eq!(
+			registry
+				.resolve(Some("dwg"), b"BAM\x01\x00\x00")
+				.map(|k| k.name.as_str()),
+			Some("CAD")
+		);
+		// header too short for the matcher
+		assert_eq!(registry.resolve(None, b"AC"), None);
+	}
+
+	#[test]
+	fn register_replaces_same_name() {
+		let mut registry = registry();
+
+		registry.register(CustomKind {
+			name: "CAD".to_string(),
+			extensions: vec!["dxf".to_string()],
+			magic_bytes: vec![],
+		});
+
+		assert_eq!(registry.kinds().len(), 2);
+		assert_eq!(registry.resolve(Some("dwg"), &[]), None);
+		assert_eq!(
+			registry.resolve(Some("dxf"), &[]).map(|k| k.name.as_str()),
+			Some("CAD")
+		);
+	}
+
+	#[test]
+	fn out_of_reach_matchers() {
+		let registry = KindRegistry::new([CustomKind {
+			name: "Broken".to_string(),
+			extensions: vec![],
+			magic_bytes: vec![
+				MagicBytesMatcher {
+					offset: usize::MAX,
+					bytes: b"AC".to_vec(),
+				},
+				MagicBytesMatcher {
+					offset: MAX_HEADER_LENGTH,
+					bytes: b"AC".to_vec(),
+				},
+			],
+		}]);
+
+		assert_eq!(registry.resolve(None, b"AC10"), None);
+		assert_eq!(registry.header_length(), 0);
+	}
+}
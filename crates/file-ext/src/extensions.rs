@@ -85,10 +85,12 @@ extension_category_enum! {
 		Avif = [],
 		Avci = [],
 		Avcs = [],
+		Jxl = [0xFF, 0x0A] | [0x00, 0x00, 0x00, 0x0C, 0x4A, 0x58, 0x4C, 0x20, 0x0D, 0x0A, 0x87, 0x0A],
 		Raw = [],
 		Akw = [0x41, 0x4B, 0x57, 0x42],
 		Dng = [0x49, 0x49, 0x2A, 0x00, 0x08, 0x00, 0x00, 0x00, 0x44, 0x4E, 0x47, 0x00],
 		Cr2 = [0x49, 0x49, 0x2A, 0x00, 0x10, 0x00, 0x00, 0x00, 0x43, 0x52, 0x02, 0x00],
+		Cr3 = [_, _, _, _, 0x66, 0x74, 0x79, 0x70, 0x63, 0x72, 0x78, 0x20],
 		Dcr = [0x49, 0x49, 0x2A, 0x00, 0x10, 0x00, 0x00, 0x00, 0x44, 0x43, 0x52, 0x00],
 		Nwr = [0x49, 0x49, 0x2A, 0x00, 0x10, 0x00, 0x00, 0x00, 0x4E, 0x57, 0x52, 0x00],
 		Nef = [0x49, 0x49, 0x2A, 0x00, 0x08, 0x00, 0x00, 0x00, 0x4E, 0x45, 0x46, 0x00],
@@ -179,7 +181,7 @@ extension_category_enum! {
 
 // text file extensions
 extension_category_enum! {
-	TextExtension _ALL_TEXT_EXTENSIONS {
+	TextExtension ALL_TEXT_EXTENSIONS {
 		Txt,
 		Rtf,
 		Md,
@@ -188,7 +190,7 @@ extension_category_enum! {
 }
 // config file extensions
 extension_category_enum! {
-	ConfigExtension _ALL_CONFIG_EXTENSIONS {
+	ConfigExtension ALL_CONFIG_EXTENSIONS {
 		Ini,
 		Json,
 		Yaml,
@@ -248,7 +250,7 @@ extension_category_enum! {
 
 // code extensions
 extension_category_enum! {
-	CodeExtension _ALL_CODE_EXTENSIONS {
+	CodeExtension ALL_CODE_EXTENSIONS {
 		// AppleScript
 		Scpt,
 		Scptd,
@@ -389,6 +391,36 @@ mod test {
 		assert_eq!(Extension::from_str("jeff"), None);
 	}
 
+	#[test]
+	fn sniff_bytes() {
+		assert_eq!(
+			Extension::sniff_bytes(
+				&[0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00],
+				true
+			),
+			Some(Extension::Image(ImageExtension::Png))
+		);
+		// zip based formats resolve to a plain zip archive
+		assert_eq!(
+			Extension::sniff_bytes(&[0x50, 0x4B, 0x03, 0x04, 0x14, 0x00], true),
+			Some(Extension::Archive(ArchiveExtension::Zip))
+		);
+		// longest signature wins over shorter ones from other categories
+		assert_eq!(
+			Extension::sniff_bytes(b"RIFF\x24\x08\x00\x00WAVEfmt ", true),
+			Some(Extension::Audio(AudioExtension::Wav))
+		);
+		assert_eq!(
+			Extension::sniff_bytes(b"just some plain text\n", false),
+			Some(Extension::Text(TextExtension::Txt))
+		);
+		assert_eq!(
+			Extension::sniff_bytes(&[0xDE, 0xAD, 0xBE, 0xEF, 0x00, 0x00], false),
+			None
+		);
+		assert_eq!(Extension::sniff_bytes(&[], false), None);
+	}
+
 	#[tokio::test]
 	async fn magic_bytes() {
 		async fn test_path(subpath: &str) -> Option<Extension> {
@@ -1,3 +1,4 @@
+pub mod custom_kind;
 pub mod extensions;
 pub mod kind;
 pub mod magic;
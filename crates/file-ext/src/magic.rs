@@ -1,6 +1,14 @@
 #![allow(dead_code)]
 
-use crate::extensions::{CodeExtension, Extension, VideoExtension};
+use crate::{
+	extensions::{
+		CodeExtension, Extension, TextExtension, VideoExtension, _ALL_ARCHIVE_EXTENSIONS,
+		_ALL_BOOK_EXTENSIONS, _ALL_DATABASE_EXTENSIONS, _ALL_ENCRYPTED_EXTENSIONS,
+		_ALL_EXECUTABLE_EXTENSIONS, _ALL_FONT_EXTENSIONS, _ALL_MESH_EXTENSIONS,
+		ALL_AUDIO_EXTENSIONS, ALL_DOCUMENT_EXTENSIONS, ALL_IMAGE_EXTENSIONS, ALL_VIDEO_EXTENSIONS,
+	},
+	text::is_text,
+};
 use std::{ffi::OsStr, io::SeekFrom, path::Path};
 
 use tokio::{
@@ -8,6 +16,9 @@ use tokio::{
 	io::{AsyncReadExt, AsyncSeekExt},
 };
 
+// Shorter signatures (like the MPEG-TS sync byte) are too ambiguous to be trusted without an extension
+const MIN_SNIFFED_MAGIC_BYTES_LENGTH: usize = 2;
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum ExtensionPossibility {
 	Known(Extension),
@@ -172,7 +183,85 @@ pub async fn verify_magic_bytes<T: MagicBytes>(ext: T, file: &mut File) -> Optio
 	None
 }
 
+/// Finds the candidate with the longest magic bytes signature matching `buf`, the first declared one wins ties
+fn longest_magic_bytes_match<T: MagicBytes + Copy>(
+	candidates: &[T],
+	buf: &[u8],
+) -> Option<(T, usize)> {
+	candidates
+		.iter()
+		.filter_map(|&ext| {
+			ext.magic_bytes_meta()
+				.into_iter()
+				.filter(|meta| {
+					meta.length >= MIN_SNIFFED_MAGIC_BYTES_LENGTH
+						&& buf.len() >= meta.offset + meta.length
+						&& ext.has_magic_bytes(&buf[meta.offset..meta.offset + meta.length])
+				})
+				.map(|meta| meta.length)
+				.max()
+				.map(|length| (ext, length))
+		})
+		.fold(None, keep_longest)
+}
+
+fn keep_longest<T>(best: Option<(T, usize)>, (ext, length): (T, usize)) -> Option<(T, usize)> {
+	match best {
+		Some((_, best_length)) if best_length >= length => best,
+		_ => Some((ext, length)),
+	}
+}
+
 impl Extension {
+	/// Guesses the extension from the file content alone, reading at most `read_budget` bytes from
+	/// its start. Meant as a fallback for files without an extension or with an unknown one.
+	pub async fn sniff(path: impl AsRef<Path>, read_budget: usize) -> Option<Self> {
+		let mut buf = Vec::with_capacity(read_budget);
+
+		File::open(path)
+			.await
+			.ok()?
+			.take(read_budget as u64)
+			.read_to_end(&mut buf)
+			.await
+			.ok()?;
+
+		Self::sniff_bytes(&buf, buf.len() == read_budget)
+	}
+
+	/// Guesses the extension from the first bytes of a file, `partial` tells if `buf` doesn't hold
+	/// the entire file content.
+	///
+	/// Categories are checked in order, so ambiguous signatures (like zip based formats) resolve
+	/// to the most generic extension. Anything without a known signature that looks like text is
+	/// considered a plain text file.
+	#[must_use]
+	pub fn sniff_bytes(buf: &[u8], partial: bool) -> Option<Self> {
+		[
+			longest_magic_bytes_match(ALL_IMAGE_EXTENSIONS, buf).map(|(e, l)| (Self::Image(e), l)),
+			longest_magic_bytes_match(ALL_VIDEO_EXTENSIONS, buf).map(|(e, l)| (Self::Video(e), l)),
+			longest_magic_bytes_match(ALL_AUDIO_EXTENSIONS, buf).map(|(e, l)| (Self::Audio(e), l)),
+			longest_magic_bytes_match(_ALL_ARCHIVE_EXTENSIONS, buf)
+				.map(|(e, l)| (Self::Archive(e), l)),
+			longest_magic_bytes_match(_ALL_DATABASE_EXTENSIONS, buf)
+				.map(|(e, l)| (Self::Database(e), l)),
+			longest_magic_bytes_match(_ALL_ENCRYPTED_EXTENSIONS, buf)
+				.map(|(e, l)| (Self::Encrypted(e), l)),
+			longest_magic_bytes_match(_ALL_FONT_EXTENSIONS, buf).map(|(e, l)| (Self::Font(e), l)),
+			longest_magic_bytes_match(ALL_DOCUMENT_EXTENSIONS, buf)
+				.map(|(e, l)| (Self::Document(e), l)),
+			longest_magic_bytes_match(_ALL_EXECUTABLE_EXTENSIONS, buf)
+				.map(|(e, l)| (Self::Executable(e), l)),
+			longest_magic_bytes_match(_ALL_MESH_EXTENSIONS, buf).map(|(e, l)| (Self::Mesh(e), l)),
+			longest_magic_bytes_match(_ALL_BOOK_EXTENSIONS, buf).map(|(e, l)| (Self::Book(e), l)),
+		]
+		.into_iter()
+		.flatten()
+		.fold(None, keep_longest)
+		.map(|(ext, _)| ext)
+		.or_else(|| is_text(buf, partial).map(|_| Self::Text(TextExtension::Txt)))
+	}
+
 	pub async fn resolve_conflicting(
 		path: impl AsRef<Path>,
 		always_check_magic_bytes: bool,
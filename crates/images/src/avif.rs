@@ -0,0 +1,16 @@
+pub use crate::error::Result;
+use crate::ImageHandler;
+use image::{DynamicImage, ImageFormat};
+use std::path::Path;
+
+pub struct AvifHandler {}
+
+impl ImageHandler for AvifHandler {
+	fn handle_image(&self, path: &Path) -> Result<DynamicImage> {
+		let data = self.get_data(path)?; // this also makes sure the file isn't above the maximum size
+		Ok(image::load_from_memory_with_format(
+			&data,
+			ImageFormat::Avif,
+		)?)
+	}
+}
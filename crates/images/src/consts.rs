@@ -17,11 +17,17 @@ pub const GENERIC_EXTENSIONS: [&str; 17] = [
 ];
 pub const SVG_EXTENSIONS: [&str; 2] = ["svg", "svgz"];
 pub const PDF_EXTENSIONS: [&str; 1] = ["pdf"];
+pub const RAW_EXTENSIONS: [&str; 8] = ["raw", "dng", "cr2", "cr3", "dcr", "nef", "arw", "rw2"];
 #[cfg(feature = "heif")]
 pub const HEIF_EXTENSIONS: [&str; 8] = [
 	"hif", "heif", "heifs", "heic", "heics", "avif", "avci", "avcs",
 ];
 
+#[cfg(feature = "avif")]
+pub const AVIF_EXTENSIONS: [&str; 1] = ["avif"];
+#[cfg(feature = "jxl")]
+pub const JXL_EXTENSIONS: [&str; 1] = ["jxl"];
+
 // Will be needed for validating HEIF images
 // #[cfg(feature = "heif")]
 // pub const HEIF_BPS: u8 = 8;
@@ -32,6 +38,10 @@ pub const HEIF_EXTENSIONS: [&str; 8] = [
 /// It is 512x512, but if the SVG has a non-1:1 aspect ratio we need to account for that.
 pub const SVG_TARGET_PX: f32 = 262_144_f32;
 
+/// Embedded previews smaller than this (on their longest side) are ignored, as some RAW
+/// containers also carry tiny EXIF thumbnails that would look awful once scaled up.
+pub const RAW_MINIMUM_PREVIEW_SIDE: u32 = 512;
+
 /// The size that PDF pages are rendered at.
 ///
 /// This is 96DPI at standard A4 printer paper size - the target aspect
@@ -39,6 +49,11 @@ pub const SVG_TARGET_PX: f32 = 262_144_f32;
 pub const PDF_PORTRAIT_RENDER_WIDTH: pdfium_render::prelude::Pixels = 794;
 pub const PDF_LANDSCAPE_RENDER_WIDTH: pdfium_render::prelude::Pixels = 1123;
 
+/// The width that scanned PDF pages are rendered at for text recognition.
+///
+/// This is 300DPI at standard A4 printer paper size, as OCR engines struggle with smaller text.
+pub const PDF_OCR_RENDER_WIDTH: pdfium_render::prelude::Pixels = 2480;
+
 #[cfg_attr(feature = "specta", derive(specta::Type))]
 #[cfg_attr(feature = "bincode", derive(bincode::Encode, bincode::Decode))]
 #[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
@@ -72,6 +87,15 @@ pub enum ConvertibleExtension {
 	Svgz,
 	Pdf,
 	Webp,
+	Raw,
+	Dng,
+	Cr2,
+	Cr3,
+	Dcr,
+	Nef,
+	Arw,
+	Rw2,
+	Jxl,
 }
 
 impl ConvertibleExtension {
@@ -83,7 +107,7 @@ impl ConvertibleExtension {
 				| Self::Heif | Self::Heifs
 				| Self::Heic | Self::Heics
 				| Self::Avif | Self::Avci
-				| Self::Avcs
+				| Self::Avcs | Self::Jxl
 		)
 	}
 }
@@ -128,6 +152,15 @@ impl TryFrom<String> for ConvertibleExtension {
 			"svgz" => Ok(Self::Svgz),
 			"pdf" => Ok(Self::Pdf),
 			"webp" => Ok(Self::Webp),
+			"raw" => Ok(Self::Raw),
+			"dng" => Ok(Self::Dng),
+			"cr2" => Ok(Self::Cr2),
+			"cr3" => Ok(Self::Cr3),
+			"dcr" => Ok(Self::Dcr),
+			"nef" => Ok(Self::Nef),
+			"arw" => Ok(Self::Arw),
+			"rw2" => Ok(Self::Rw2),
+			"jxl" => Ok(Self::Jxl),
 			_ => Err(crate::Error::Unsupported),
 		}
 	}
@@ -187,22 +220,24 @@ impl<'de> serde::Deserialize<'de> for ConvertibleExtension {
 #[inline]
 #[must_use]
 pub fn all_compatible_extensions() -> Vec<String> {
-	#[cfg(feature = "heif")]
-	let res = GENERIC_EXTENSIONS
+	#[allow(unused_mut)]
+	let mut res = GENERIC_EXTENSIONS
 		.into_iter()
-		.chain(HEIF_EXTENSIONS)
 		.chain(SVG_EXTENSIONS)
 		.chain(PDF_EXTENSIONS)
+		.chain(RAW_EXTENSIONS)
 		.map(String::from)
-		.collect();
+		.collect::<Vec<_>>();
 
-	#[cfg(not(feature = "heif"))]
-	let res = GENERIC_EXTENSIONS
-		.into_iter()
-		.chain(SVG_EXTENSIONS)
-		.chain(PDF_EXTENSIONS)
-		.map(String::from)
-		.collect();
+	#[cfg(feature = "heif")]
+	res.extend(HEIF_EXTENSIONS.into_iter().map(String::from));
+
+	// libheif already covers AVIF when enabled, so we don't want to list it twice
+	#[cfg(all(feature = "avif", not(feature = "heif")))]
+	res.extend(AVIF_EXTENSIONS.into_iter().map(String::from));
+
+	#[cfg(feature = "jxl")]
+	res.extend(JXL_EXTENSIONS.into_iter().map(String::from));
 
 	res
 }
@@ -23,6 +23,9 @@ pub enum Error {
 	#[cfg(feature = "heif")]
 	#[error("error with libheif: {0}")]
 	LibHeif(#[from] libheif_rs::HeifError),
+	#[cfg(feature = "jxl")]
+	#[error("error with jxl-oxide: {0}")]
+	JxlOxide(#[from] jxl_oxide::Error),
 	#[error("there was an error while converting the image to an `RgbImage`")]
 	RgbImageConversion,
 	#[error("error with pdfium: {0}")]
@@ -33,8 +36,8 @@ pub enum Error {
 	Pixbuf,
 	#[error("error while loading the image (via the `image` crate): {0}")]
 	Image(#[from] image::ImageError),
-	// #[error("error while converting from raw")] // not enough rust support for it to be feasible
-	// RawConversion,
+	#[error("error while decoding the sensor data of a raw image")]
+	RawDecoding,
 	#[error("error while parsing integers")]
 	TryFromInt(#[from] TryFromIntError),
 }
@@ -3,6 +3,7 @@ use crate::{
 	error::{Error, Result},
 	generic::GenericHandler,
 	pdf::PdfHandler,
+	raw::RawHandler,
 	svg::SvgHandler,
 	ImageHandler,
 };
@@ -12,8 +13,12 @@ use std::{
 	path::Path,
 };
 
+#[cfg(feature = "avif")]
+use crate::avif::AvifHandler;
 #[cfg(feature = "heif")]
 use crate::heif::HeifHandler;
+#[cfg(feature = "jxl")]
+use crate::jxl::JxlHandler;
 
 pub fn format_image(path: impl AsRef<Path>) -> Result<DynamicImage> {
 	let path = path.as_ref();
@@ -47,6 +52,25 @@ fn match_to_handler(ext: Option<&OsStr>) -> Result<Box<dyn ImageHandler>> {
 		handler = Some(Box::new(HeifHandler {}));
 	}
 
+	// This comes after HEIF on purpose, dav1d is faster and more reliable than libheif for AVIF
+	#[cfg(feature = "avif")]
+	if consts::AVIF_EXTENSIONS
+		.iter()
+		.map(OsString::from)
+		.any(|x| x == ext)
+	{
+		handler = Some(Box::new(AvifHandler {}));
+	}
+
+	#[cfg(feature = "jxl")]
+	if consts::JXL_EXTENSIONS
+		.iter()
+		.map(OsString::from)
+		.any(|x| x == ext)
+	{
+		handler = Some(Box::new(JxlHandler {}));
+	}
+
 	if consts::SVG_EXTENSIONS
 		.iter()
 		.map(OsString::from)
@@ -63,5 +87,13 @@ fn match_to_handler(ext: Option<&OsStr>) -> Result<Box<dyn ImageHandler>> {
 		handler = Some(Box::new(PdfHandler {}));
 	}
 
+	if consts::RAW_EXTENSIONS
+		.iter()
+		.map(OsString::from)
+		.any(|x| x == ext)
+	{
+		handler = Some(Box::new(RawHandler {}));
+	}
+
 	handler.ok_or(Error::Unsupported)
 }
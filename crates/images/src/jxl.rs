@@ -0,0 +1,18 @@
+pub use crate::error::Result;
+use crate::ImageHandler;
+use image::DynamicImage;
+use jxl_oxide::integration::JxlDecoder;
+use std::{io::Cursor, path::Path};
+
+pub struct JxlHandler {}
+
+impl ImageHandler for JxlHandler {
+	fn handle_image(&self, path: &Path) -> Result<DynamicImage> {
+		let data = self.get_data(path)?; // this also makes sure the file isn't above the maximum size
+
+		// The decoder already applies the orientation stored in the codestream header
+		Ok(DynamicImage::from_decoder(JxlDecoder::new(Cursor::new(
+			data,
+		))?)?)
+	}
+}
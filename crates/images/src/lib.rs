@@ -22,13 +22,18 @@
 
 use std::{fs, path::Path};
 
+#[cfg(feature = "avif")]
+mod avif;
 mod consts;
 mod error;
 mod generic;
 mod handler;
 #[cfg(feature = "heif")]
 mod heif;
+#[cfg(feature = "jxl")]
+mod jxl;
 mod pdf;
+mod raw;
 mod svg;
 
 use consts::MAXIMUM_FILE_SIZE;
@@ -38,6 +43,8 @@ pub use consts::{all_compatible_extensions, ConvertibleExtension};
 pub use error::{Error, Result};
 pub use handler::{convert_image, format_image};
 pub use image::DynamicImage;
+pub use pdf::{pdf_pages, PdfPageContent};
+pub use raw::largest_embedded_jpeg;
 
 pub trait ImageHandler {
 	#[inline]
@@ -4,7 +4,7 @@ use std::{
 };
 
 use crate::{
-	consts::{PDF_LANDSCAPE_RENDER_WIDTH, PDF_PORTRAIT_RENDER_WIDTH},
+	consts::{PDF_LANDSCAPE_RENDER_WIDTH, PDF_OCR_RENDER_WIDTH, PDF_PORTRAIT_RENDER_WIDTH},
 	ImageHandler, Result,
 };
 use image::DynamicImage;
@@ -76,14 +76,23 @@ static LANDSCAPE_CONFIG: Lazy<PdfRenderConfig> = Lazy::new(|| {
 	thumbnail_config(PdfRenderConfig::new().set_target_width(PDF_LANDSCAPE_RENDER_WIDTH))
 });
 
+static OCR_CONFIG: Lazy<PdfRenderConfig> =
+	Lazy::new(|| thumbnail_config(PdfRenderConfig::new().set_target_width(PDF_OCR_RENDER_WIDTH)));
+
+fn load_pdfium() -> Result<Pdfium> {
+	Ok(Pdfium::new(
+		Pdfium::bind_to_library(PDFIUM_LIB.as_str()).or_else(|err| {
+			error!("{err:#?}");
+			Pdfium::bind_to_system_library()
+		})?,
+	))
+}
+
 pub struct PdfHandler {}
 
 impl ImageHandler for PdfHandler {
 	fn handle_image(&self, path: &Path) -> Result<DynamicImage> {
-		let pdfium = Pdfium::new(Pdfium::bind_to_library(PDFIUM_LIB.as_str()).or_else(|err| {
-			error!("{err:#?}");
-			Pdfium::bind_to_system_library()
-		})?);
+		let pdfium = load_pdfium()?;
 
 		let pdf = pdfium.load_pdf_from_file(path, None)?;
 		let first_page = pdf.pages().first()?;
@@ -99,3 +108,33 @@ impl ImageHandler for PdfHandler {
 		Ok(image)
 	}
 }
+
+/// The content of a PDF page, for text extraction
+pub enum PdfPageContent {
+	/// Text embedded in the page, as born-digital documents have
+	Text(String),
+	/// The page has no embedded text, likely a scan, so it's rendered for text recognition
+	Scan(DynamicImage),
+}
+
+/// Reads the first `max_pages` pages of a PDF, rendering only the ones without any embedded text
+pub fn pdf_pages(path: impl AsRef<Path>, max_pages: usize) -> Result<Vec<PdfPageContent>> {
+	let pdfium = load_pdfium()?;
+	let pdf = pdfium.load_pdf_from_file(path.as_ref(), None)?;
+
+	pdf.pages()
+		.iter()
+		.take(max_pages)
+		.map(|page| {
+			let text = page.text()?.all();
+
+			if text.trim().is_empty() {
+				Ok(PdfPageContent::Scan(
+					page.render_with_config(&OCR_CONFIG)?.as_image(),
+				))
+			} else {
+				Ok(PdfPageContent::Text(text))
+			}
+		})
+		.collect()
+}
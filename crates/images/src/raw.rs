@@ -0,0 +1,139 @@
+pub use crate::error::{Error, Result};
+use crate::{consts::RAW_MINIMUM_PREVIEW_SIDE, ImageHandler};
+use image::{codecs::jpeg::JpegDecoder, DynamicImage, ImageDecoder, RgbImage};
+use std::{io::Cursor, path::Path};
+
+/// sRGB (D65) from CIE XYZ, applied after `rawloader`'s camera to XYZ matrix
+const XYZ_TO_SRGB: [[f32; 3]; 3] = [
+	[3.240_454_2, -1.537_138_5, -0.498_531_4],
+	[-0.969_266, 1.876_010_8, 0.041_556],
+	[0.055_643_4, -0.204_025_9, 1.057_225_2],
+];
+
+pub struct RawHandler {}
+
+impl ImageHandler for RawHandler {
+	fn handle_image(&self, path: &Path) -> Result<DynamicImage> {
+		let data = self.get_data(path)?; // this also makes sure the file isn't above the maximum size
+
+		// Almost every camera stores a full size (or close to it) JPEG preview inside its RAW files,
+		// decoding it is way cheaper than demosaicing the sensor data, so we always try it first
+		if let Some(preview) = largest_embedded_jpeg(&data) {
+			return Ok(image::load_from_memory_with_format(
+				preview,
+				image::ImageFormat::Jpeg,
+			)?);
+		}
+
+		demosaic(&data)
+	}
+}
+
+/// Scans the RAW container for embedded JPEG streams and returns the one with the most pixels,
+/// as long as it is big enough to be used for thumbnails.
+///
+/// We don't walk each vendor's IFDs or boxes here, every container we support stores its previews
+/// as plain JPEG streams, so looking for the SOI marker and letting the decoder read the headers
+/// is enough to find them all.
+#[must_use]
+pub fn largest_embedded_jpeg(data: &[u8]) -> Option<&[u8]> {
+	data.windows(3)
+		.enumerate()
+		.filter(|(_, window)| *window == [0xFF, 0xD8, 0xFF])
+		.filter_map(|(offset, _)| {
+			let candidate = &data[offset..];
+			JpegDecoder::new(Cursor::new(candidate))
+				.ok()
+				.map(|decoder| (candidate, decoder.dimensions()))
+		})
+		.filter(|(_, (w, h))| (*w).max(*h) >= RAW_MINIMUM_PREVIEW_SIDE)
+		.max_by_key(|(_, (w, h))| u64::from(*w) * u64::from(*h))
+		.map(|(candidate, _)| candidate)
+}
+
+/// Fallback for files without a usable preview, decoding the sensor data with `rawloader` and
+/// demosaicing it by collapsing each 2x2 CFA block into a single pixel.
+///
+/// This halves the resolution, which is fine as the output is going to be downscaled to thumbnail
+/// size anyway, and skips any interpolation artifacts.
+#[allow(
+	clippy::as_conversions,
+	clippy::cast_precision_loss,
+	clippy::cast_possible_truncation,
+	clippy::cast_sign_loss
+)]
+fn demosaic(data: &[u8]) -> Result<DynamicImage> {
+	let raw = rawloader::decode(&mut Cursor::new(data)).map_err(|_| Error::RawDecoding)?;
+
+	let rawloader::RawImageData::Integer(pixels) = &raw.data else {
+		return Err(Error::RawDecoding);
+	};
+
+	if raw.cpp != 1 || raw.width < 2 || raw.height < 2 {
+		// Linear DNGs are already demosaiced, and those always carry a preview anyway
+		return Err(Error::Unsupported);
+	}
+
+	let cam_to_xyz = raw.cam_to_xyz_normalized();
+	let mut cam_to_srgb = [[0f32; 3]; 3];
+	for (i, row) in cam_to_srgb.iter_mut().enumerate() {
+		for (j, cell) in row.iter_mut().enumerate() {
+			*cell = (0..3).map(|k| XYZ_TO_SRGB[i][k] * cam_to_xyz[k][j]).sum();
+		}
+	}
+
+	let wb = if raw.wb_coeffs[0].is_nan() || raw.wb_coeffs[1].abs() < f32::EPSILON {
+		[1.0, 1.0, 1.0]
+	} else {
+		[
+			raw.wb_coeffs[0] / raw.wb_coeffs[1],
+			1.0,
+			raw.wb_coeffs[2] / raw.wb_coeffs[1],
+		]
+	};
+
+	let (width, height) = (raw.width / 2, raw.height / 2);
+	let mut out = Vec::with_capacity(width * height * 3);
+
+	for y in 0..height {
+		for x in 0..width {
+			let mut sums = [0f32; 3];
+			let mut counts = [0f32; 3];
+
+			for (dy, dx) in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+				let (row, col) = (y * 2 + dy, x * 2 + dx);
+				let color = raw.cfa.color_at(row, col).min(2);
+				let black = f32::from(raw.blacklevels[color]);
+				let white = f32::from(raw.whitelevels[color]);
+				let value = f32::from(pixels[row * raw.width + col]);
+
+				sums[color] += ((value - black) / (white - black)).clamp(0.0, 1.0);
+				counts[color] += 1.0;
+			}
+
+			let cam = [0, 1, 2].map(|c| {
+				if counts[c] > 0.0 {
+					sums[c] / counts[c] * wb[c]
+				} else {
+					0.0
+				}
+			});
+
+			for row in cam_to_srgb {
+				let linear = row[0] * cam[0] + row[1] * cam[1] + row[2] * cam[2];
+				out.push((srgb_gamma(linear.clamp(0.0, 1.0)) * 255.0).round() as u8);
+			}
+		}
+	}
+
+	RgbImage::from_raw(u32::try_from(width)?, u32::try_from(height)?, out)
+		.map_or_else(|| Err(Error::RgbImageConversion), |img| Ok(img.into()))
+}
+
+fn srgb_gamma(linear: f32) -> f32 {
+	if linear <= 0.003_130_8 {
+		linear * 12.92
+	} else {
+		1.055f32.mul_add(linear.powf(1.0 / 2.4), -0.055)
+	}
+}
@@ -1,5 +1,7 @@
 mod location;
+mod places;
 mod pluscodes;
 
 pub use location::MediaLocation;
+pub use places::{reverse_geocode, Place};
 pub use pluscodes::PlusCode;
@@ -0,0 +1,171 @@
+use crate::exif::MediaLocation;
+
+use std::sync::OnceLock;
+
+/// Bundled `code,name` pairs of countries and territories
+const COUNTRIES: &str = include_str!("places/countries.csv");
+
+/// Bundled `name,country_code,latitude,longitude` of major cities around the world
+const CITIES: &str = include_str!("places/cities.csv");
+
+/// The mean radius of the Earth, in kilometers
+const EARTH_RADIUS_KM: f64 = 6_371.0;
+
+/// How close to a known city a coordinate must be to be labelled with it
+const CITY_MAX_DISTANCE_KM: f64 = 50.0;
+
+/// How close to a known city a coordinate must be to be labelled with its country.
+///
+/// The dataset is coarse, so past this we'd rather have no label than a wrong one (e.g. in the
+/// middle of the ocean).
+const COUNTRY_MAX_DISTANCE_KM: f64 = 500.0;
+
+/// A human readable place, reverse geocoded from a coordinate with the bundled dataset.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Place {
+	/// Only present when the coordinate is close enough to one of the known cities
+	pub city: Option<&'static str>,
+	pub country: &'static str,
+	/// ISO 3166-1 alpha-2 code of the country
+	pub country_code: &'static str,
+}
+
+struct City {
+	name: &'static str,
+	country_code: &'static str,
+	latitude: f64,
+	longitude: f64,
+}
+
+fn cities() -> &'static [City] {
+	static CITIES_CELL: OnceLock<Vec<City>> = OnceLock::new();
+
+	CITIES_CELL.get_or_init(|| {
+		CITIES
+			.lines()
+			.filter_map(|line| {
+				let mut fields = line.split(',');
+
+				Some(City {
+					name: fields.next()?,
+					country_code: fields.next()?,
+					latitude: fields.next()?.parse().ok()?,
+					longitude: fields.next()?.parse().ok()?,
+				})
+			})
+			.collect()
+	})
+}
+
+fn country_name(code: &str) -> Option<&'static str> {
+	COUNTRIES
+		.lines()
+		.filter_map(|line| line.split_once(','))
+		.find_map(|(country_code, name)| (country_code == code).then_some(name))
+}
+
+/// Great-circle distance between two coordinates, in kilometers
+fn haversine_distance((lat_a, long_a): (f64, f64), (lat_b, long_b): (f64, f64)) -> f64 {
+	let (lat_a, lat_b) = (lat_a.to_radians(), lat_b.to_radians());
+	let delta_lat = lat_b - lat_a;
+	let delta_long = (long_b - long_a).to_radians();
+
+	let a = (delta_long / 2.0)
+		.sin()
+		.powi(2)
+		.mul_add(lat_a.cos() * lat_b.cos(), (delta_lat / 2.0).sin().powi(2));
+
+	2.0 * EARTH_RADIUS_KM * a.sqrt().min(1.0).asin()
+}
+
+/// Finds the place of a coordinate, fully offline, using the nearest city of the bundled dataset.
+///
+/// Returns [`None`] if the coordinate is too far away from any known city.
+///
+/// # Examples
+///
+/// ```
+/// use sd_media_metadata::exif::reverse_geocode;
+///
+/// let place = reverse_geocode(38.7223, -9.1393).unwrap();
+/// assert_eq!(place.city, Some("Lisbon"));
+/// assert_eq!(place.country, "Portugal");
+/// ```
+#[must_use]
+pub fn reverse_geocode(latitude: f64, longitude: f64) -> Option<Place> {
+	let (city, distance) = cities()
+		.iter()
+		.map(|city| {
+			(
+				city,
+				haversine_distance((latitude, longitude), (city.latitude, city.longitude)),
+			)
+		})
+		.min_by(|(_, a), (_, b)| a.total_cmp(b))?;
+
+	if distance > COUNTRY_MAX_DISTANCE_KM {
+		return None;
+	}
+
+	Some(Place {
+		city: (distance <= CITY_MAX_DISTANCE_KM).then_some(city.name),
+		country: country_name(city.country_code)?,
+		country_code: city.country_code,
+	})
+}
+
+impl MediaLocation {
+	/// Reverse geocodes this location with [`reverse_geocode`]
+	#[must_use]
+	pub fn place(&self) -> Option<Place> {
+		let (latitude, longitude) = self.coordinates();
+		reverse_geocode(latitude, longitude)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn every_city_has_a_country() {
+		assert!(!cities().is_empty());
+
+		for city in cities() {
+			assert!(
+				country_name(city.country_code).is_some(),
+				"{} has an unknown country code {}",
+				city.name,
+				city.country_code
+			);
+		}
+	}
+
+	#[test]
+	fn nearby_coordinates_get_the_city() {
+		// Eiffel Tower
+		let place = reverse_geocode(48.8584, 2.2945).unwrap();
+		assert_eq!(place.city, Some("Paris"));
+		assert_eq!(place.country, "France");
+		assert_eq!(place.country_code, "FR");
+
+		// Shibuya crossing
+		assert_eq!(
+			reverse_geocode(35.6595, 139.7005).unwrap().city,
+			Some("Tokyo")
+		);
+	}
+
+	#[test]
+	fn remote_coordinates_only_get_the_country() {
+		// Monument Valley, far from any bundled city but still in the US
+		let place = reverse_geocode(36.9980, -110.0985).unwrap();
+		assert_eq!(place.city, None);
+		assert_eq!(place.country_code, "US");
+	}
+
+	#[test]
+	fn open_ocean_has_no_place() {
+		assert_eq!(reverse_geocode(-40.0, -130.0), None);
+	}
+}
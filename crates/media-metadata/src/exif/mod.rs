@@ -18,7 +18,7 @@ pub use composite::Composite;
 pub use consts::DMS_DIVISION;
 pub use datetime::MediaDate;
 pub use flash::{Flash, FlashMode, FlashValue};
-pub use geographic::{MediaLocation, PlusCode};
+pub use geographic::{reverse_geocode, MediaLocation, Place, PlusCode};
 pub use orientation::Orientation;
 pub use profile::ColorProfile;
 pub use reader::ExifReader;
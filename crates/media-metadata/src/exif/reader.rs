@@ -1,26 +1,69 @@
 use crate::Result;
 
 use std::{
-	fs::File,
+	ffi::OsStr,
+	fs::{self, File},
 	io::{BufReader, Cursor},
 	path::Path,
 	str::FromStr,
 };
 
 use exif::{Exif, In, Tag};
+use sd_images::largest_embedded_jpeg;
 use sd_utils::error::FileIOError;
 
+/// RAW containers that `kamadak-exif` can't read on its own, so we dig their exif out ourselves
+const RAW_EXTENSIONS: [&str; 7] = ["dng", "cr2", "cr3", "dcr", "nef", "arw", "rw2"];
+
+/// Canon's CR3 is an ISO BMFF container, and its IFD0 lives as a plain TIFF inside this box
+const CR3_IFD0_BOX: &[u8; 4] = b"CMT1";
+
 /// An [`ExifReader`]. This can get exif tags from images (either files or slices).
 pub struct ExifReader(Exif);
 
 impl ExifReader {
 	pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
-		exif::Reader::new()
-			.read_from_container(&mut BufReader::new(
-				File::open(&path).map_err(|e| FileIOError::from((path, e)))?,
-			))
-			.map(Self)
-			.map_err(Into::into)
+		let path = path.as_ref();
+
+		match exif::Reader::new().read_from_container(&mut BufReader::new(
+			File::open(path).map_err(|e| FileIOError::from((path, e)))?,
+		)) {
+			Ok(exif) => Ok(Self(exif)),
+			Err(exif::Error::InvalidFormat(_) | exif::Error::NotFound(_)) if is_raw(path) => {
+				Self::from_raw_slice(&fs::read(path).map_err(|e| FileIOError::from((path, e)))?)
+			}
+			Err(e) => Err(e.into()),
+		}
+	}
+
+	/// Reads exif from RAW containers which don't follow plain TIFF closely enough for
+	/// [`exif::Reader::read_from_container`], falling back to the embedded JPEG preview
+	/// when we can't find the camera's own IFDs.
+	pub fn from_raw_slice(slice: &[u8]) -> Result<Self> {
+		// Panasonic's RW2 is a TIFF with a custom magic number, patching it back is enough
+		if slice.starts_with(b"IIU\0") {
+			let mut tiff = slice.to_vec();
+			tiff[2..4].copy_from_slice(&[0x2A, 0x00]);
+			if let Ok(exif) = exif::Reader::new().read_raw(tiff) {
+				return Ok(Self(exif));
+			}
+		}
+
+		if let Some(offset) = slice
+			.windows(CR3_IFD0_BOX.len())
+			.position(|window| window == CR3_IFD0_BOX)
+		{
+			if let Ok(exif) =
+				exif::Reader::new().read_raw(slice[offset + CR3_IFD0_BOX.len()..].to_vec())
+			{
+				return Ok(Self(exif));
+			}
+		}
+
+		largest_embedded_jpeg(slice).map_or_else(
+			|| Err(exif::Error::NotFound("RAW container").into()),
+			Self::from_slice,
+		)
 	}
 
 	pub fn from_slice(slice: &[u8]) -> Result<Self> {
@@ -54,3 +97,9 @@ impl ExifReader {
 			.unwrap_or_default()
 	}
 }
+
+fn is_raw(path: &Path) -> bool {
+	path.extension()
+		.and_then(OsStr::to_str)
+		.is_some_and(|ext| RAW_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
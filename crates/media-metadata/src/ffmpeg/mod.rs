@@ -12,6 +12,7 @@ pub mod metadata;
 pub mod program;
 pub mod stream;
 pub mod subtitle_props;
+pub mod tracks;
 pub mod video_props;
 
 use chapter::Chapter;
@@ -0,0 +1,186 @@
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use super::{chapter::Chapter, stream::Stream, FFmpegMetadata};
+
+/// Audio and subtitle tracks, and chapters, embedded in a media container.
+///
+/// This is a flattened view over [`FFmpegMetadata`], which nests streams inside programs and
+/// keeps chapter times in their own time base.
+#[derive(Default, Debug, Serialize, Deserialize, Type)]
+pub struct MediaTracks {
+	pub audio: Vec<Track>,
+	pub subtitles: Vec<Track>,
+	pub chapters: Vec<ChapterMark>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Type)]
+pub struct Track {
+	pub stream_id: i32,
+	pub codec: Option<String>,
+	/// Usually an ISO 639-2 code, like `eng` or `por`, as containers store them
+	pub language: Option<String>,
+	pub title: Option<String>,
+	pub default: bool,
+	pub forced: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Type)]
+pub struct ChapterMark {
+	pub id: i32,
+	pub title: Option<String>,
+	pub start_secs: f64,
+	pub end_secs: f64,
+}
+
+impl Track {
+	fn from_stream(stream: &Stream) -> Self {
+		Self {
+			stream_id: stream.id,
+			codec: stream.codec.as_ref().and_then(|codec| codec.name.clone()),
+			language: stream
+				.metadata
+				.language
+				.as_ref()
+				.map(|language| language.to_lowercase()),
+			title: stream.metadata.title.clone(),
+			default: stream
+				.dispositions
+				.iter()
+				.any(|disposition| disposition == "default"),
+			forced: stream
+				.dispositions
+				.iter()
+				.any(|disposition| disposition == "forced"),
+		}
+	}
+}
+
+impl From<&Chapter> for ChapterMark {
+	fn from(chapter: &Chapter) -> Self {
+		Self {
+			id: chapter.id,
+			title: chapter.metadata.title.clone(),
+			start_secs: to_secs(chapter.start, chapter.time_base_num, chapter.time_base_den),
+			end_secs: to_secs(chapter.end, chapter.time_base_num, chapter.time_base_den),
+		}
+	}
+}
+
+/// Joins a (high, low) split timestamp and converts it from its time base to seconds
+#[allow(clippy::cast_precision_loss)] // Chapters would need to be longer than a lifetime to lose precision
+fn to_secs((high, low): (i32, u32), time_base_num: i32, time_base_den: i32) -> f64 {
+	if time_base_den == 0 {
+		return 0.0;
+	}
+
+	let timestamp = i64::from(high) << 32 | i64::from(low);
+
+	timestamp as f64 * f64::from(time_base_num) / f64::from(time_base_den)
+}
+
+impl FFmpegMetadata {
+	#[must_use]
+	pub fn tracks(&self) -> MediaTracks {
+		let mut tracks = MediaTracks {
+			chapters: self.chapters.iter().map(Into::into).collect(),
+			..Default::default()
+		};
+
+		for stream in self.programs.iter().flat_map(|program| &program.streams) {
+			match stream
+				.codec
+				.as_ref()
+				.and_then(|codec| codec.kind.as_deref())
+			{
+				Some("audio") => tracks.audio.push(Track::from_stream(stream)),
+				Some("subtitle") => tracks.subtitles.push(Track::from_stream(stream)),
+				_ => {}
+			}
+		}
+
+		tracks
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use crate::ffmpeg::{codec::Codec, metadata::Metadata, program::Program};
+
+	fn stream(id: i32, kind: &str, language: &str, dispositions: &[&str]) -> Stream {
+		Stream {
+			id,
+			name: None,
+			codec: Some(Codec {
+				kind: Some(kind.to_string()),
+				sub_kind: None,
+				tag: None,
+				name: Some(format!("{kind}_codec")),
+				profile: None,
+				bit_rate: 0,
+				props: None,
+			}),
+			aspect_ratio_num: 0,
+			aspect_ratio_den: 0,
+			frames_per_second_num: 0,
+			frames_per_second_den: 0,
+			time_base_real_den: 0,
+			time_base_real_num: 0,
+			dispositions: dispositions.iter().map(ToString::to_string).collect(),
+			metadata: Metadata {
+				language: Some(language.to_string()),
+				..Default::default()
+			},
+		}
+	}
+
+	#[test]
+	fn tracks_are_split_by_kind() {
+		let metadata = FFmpegMetadata {
+			formats: vec!["matroska".to_string()],
+			duration: None,
+			start_time: None,
+			bit_rate: (0, 0),
+			chapters: vec![Chapter {
+				id: 0,
+				start: (0, 0),
+				end: (0, 90_000),
+				time_base_den: 1_000,
+				time_base_num: 1,
+				metadata: Metadata {
+					title: Some("Opening".to_string()),
+					..Default::default()
+				},
+			}],
+			programs: vec![Program {
+				id: 0,
+				name: None,
+				streams: vec![
+					stream(0, "video", "und", &["default"]),
+					stream(1, "audio", "ENG", &["default"]),
+					stream(2, "audio", "por", &[]),
+					stream(3, "subtitle", "por", &["forced"]),
+				],
+				metadata: Metadata::default(),
+			}],
+			metadata: Metadata::default(),
+		};
+
+		let tracks = metadata.tracks();
+
+		assert_eq!(tracks.audio.len(), 2);
+		assert_eq!(tracks.audio[0].language.as_deref(), Some("eng"));
+		assert!(tracks.audio[0].default);
+		assert!(!tracks.audio[1].default);
+
+		assert_eq!(tracks.subtitles.len(), 1);
+		assert_eq!(tracks.subtitles[0].stream_id, 3);
+		assert!(tracks.subtitles[0].forced);
+
+		assert_eq!(tracks.chapters.len(), 1);
+		assert_eq!(tracks.chapters[0].title.as_deref(), Some("Opening"));
+		assert!((tracks.chapters[0].end_secs - 90.0).abs() < f64::EPSILON);
+	}
+}
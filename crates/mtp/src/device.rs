@@ -0,0 +1,443 @@
+use crate::{ffi, Error, Object, Storage};
+
+use std::{
+	collections::{HashMap, HashSet},
+	ffi::{c_char, CStr, CString},
+	os::unix::ffi::OsStrExt,
+	path::Path,
+	ptr::{self, addr_of_mut},
+	slice,
+	sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError, Weak},
+};
+
+use chrono::DateTime;
+use tracing::{debug, warn};
+
+/// Bus location and device number of a connected device, which change on every reconnection
+type UsbLocation = (u32, u8);
+
+/// libmtp isn't thread safe, so every call to it goes through this state's lock
+#[derive(Default)]
+struct Libmtp {
+	/// Learning a serial number requires opening the device, so we only do it once per connection
+	serial_numbers: HashMap<UsbLocation, String>,
+	open_devices: HashMap<String, Weak<Device>>,
+}
+
+fn libmtp() -> MutexGuard<'static, Libmtp> {
+	static LIBMTP: OnceLock<Mutex<Libmtp>> = OnceLock::new();
+
+	LIBMTP
+		.get_or_init(|| {
+			// SAFETY: Called only once, before any other libmtp function
+			unsafe { ffi::LIBMTP_Init() };
+			Mutex::default()
+		})
+		.lock()
+		.unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Array of raw devices allocated by libmtp
+struct RawDevices {
+	devices: *mut ffi::LIBMTP_raw_device_t,
+	count: usize,
+}
+
+impl RawDevices {
+	fn detect(_libmtp: &Libmtp) -> Result<Self, Error> {
+		let mut devices = ptr::null_mut();
+		let mut count = 0;
+
+		// SAFETY: Both out pointers are valid, and the libmtp lock is held
+		match unsafe { ffi::LIBMTP_Detect_Raw_Devices(addr_of_mut!(devices), addr_of_mut!(count)) }
+		{
+			ffi::LIBMTP_ERROR_NONE | ffi::LIBMTP_ERROR_NO_DEVICE_ATTACHED => Ok(Self {
+				devices,
+				count: if devices.is_null() {
+					0
+				} else {
+					usize::try_from(count).unwrap_or_default()
+				},
+			}),
+			error_number => Err(Error::Libmtp {
+				action: "detect devices",
+				message: format!("error number {error_number}"),
+			}),
+		}
+	}
+
+	fn iter_mut(&mut self) -> impl Iterator<Item = &mut ffi::LIBMTP_raw_device_t> {
+		if self.devices.is_null() {
+			[].iter_mut()
+		} else {
+			// SAFETY: libmtp allocated `count` contiguous raw devices
+			unsafe { slice::from_raw_parts_mut(self.devices, self.count) }.iter_mut()
+		}
+	}
+}
+
+impl Drop for RawDevices {
+	fn drop(&mut self) {
+		if !self.devices.is_null() {
+			// SAFETY: Allocated by libmtp with malloc
+			unsafe { libc::free(self.devices.cast()) };
+		}
+	}
+}
+
+/// Serial numbers of all connected devices, to tell which device locations are online
+#[allow(clippy::significant_drop_tightening)] // The lock is needed until the end
+pub fn connected_serial_numbers() -> Result<Vec<String>, Error> {
+	let mut libmtp = libmtp();
+	let mut raw_devices = RawDevices::detect(&libmtp)?;
+
+	let mut connected = HashSet::with_capacity(raw_devices.count);
+	let mut serial_numbers = Vec::with_capacity(raw_devices.count);
+
+	for raw_device in raw_devices.iter_mut() {
+		let location = (raw_device.bus_location, raw_device.devnum);
+		connected.insert(location);
+
+		if let Some(serial_number) = libmtp.serial_numbers.get(&location) {
+			serial_numbers.push(serial_number.clone());
+			continue;
+		}
+
+		// SAFETY: The raw device came from libmtp, and the libmtp lock is held
+		let device = unsafe { ffi::LIBMTP_Open_Raw_Device_Uncached(raw_device) };
+		if device.is_null() {
+			// Usually claimed by another program, like a desktop environment's file manager
+			warn!(
+				"Failed to open MTP device <bus={}, devnum={}>",
+				location.0, location.1
+			);
+			continue;
+		}
+
+		// SAFETY: The device was just opened
+		let serial_number = unsafe {
+			let serial_number = take_string(ffi::LIBMTP_Get_Serialnumber(device));
+			ffi::LIBMTP_Release_Device(device);
+			serial_number
+		};
+
+		if let Some(serial_number) = serial_number {
+			libmtp
+				.serial_numbers
+				.insert(location, serial_number.clone());
+			serial_numbers.push(serial_number);
+		}
+	}
+
+	libmtp
+		.serial_numbers
+		.retain(|location, _| connected.contains(location));
+
+	Ok(serial_numbers)
+}
+
+/// An open device, shared by everyone using it until the last reference drops, as a device can
+/// only be opened once
+pub struct Device {
+	raw: *mut ffi::LIBMTP_mtpdevice_t,
+	location: UsbLocation,
+	serial_number: String,
+	name: String,
+}
+
+// SAFETY: The device pointer is only used while holding the libmtp lock
+unsafe impl Send for Device {}
+// SAFETY: The device pointer is only used while holding the libmtp lock
+unsafe impl Sync for Device {}
+
+impl Device {
+	/// Opens the connected device with `serial_number`, or gets it if it's already open
+	#[allow(clippy::significant_drop_tightening)] // The lock is needed until the end
+	pub fn open(serial_number: &str) -> Result<Arc<Self>, Error> {
+		let mut libmtp = libmtp();
+		let mut raw_devices = RawDevices::detect(&libmtp)?;
+
+		if let Some(device) = libmtp
+			.open_devices
+			.get(serial_number)
+			.and_then(Weak::upgrade)
+		{
+			// Devices reconnected while still referenced must be opened again
+			if raw_devices
+				.iter_mut()
+				.any(|raw_device| (raw_device.bus_location, raw_device.devnum) == device.location)
+			{
+				return Ok(device);
+			}
+		}
+
+		for raw_device in raw_devices.iter_mut() {
+			let location = (raw_device.bus_location, raw_device.devnum);
+
+			if libmtp
+				.serial_numbers
+				.get(&location)
+				.is_some_and(|known| known != serial_number)
+			{
+				continue;
+			}
+
+			// SAFETY: The raw device came from libmtp, and the libmtp lock is held
+			let device = unsafe { ffi::LIBMTP_Open_Raw_Device_Uncached(raw_device) };
+			if device.is_null() {
+				continue;
+			}
+
+			// SAFETY: The device was just opened
+			let Some(found_serial_number) =
+				(unsafe { take_string(ffi::LIBMTP_Get_Serialnumber(device)) })
+			else {
+				// SAFETY: The device was just opened
+				unsafe { ffi::LIBMTP_Release_Device(device) };
+				continue;
+			};
+
+			libmtp
+				.serial_numbers
+				.insert(location, found_serial_number.clone());
+
+			if found_serial_number != serial_number {
+				// SAFETY: The device was just opened
+				unsafe { ffi::LIBMTP_Release_Device(device) };
+				continue;
+			}
+
+			// SAFETY: The device was just opened
+			let name = unsafe {
+				take_string(ffi::LIBMTP_Get_Friendlyname(device))
+					.filter(|name| !name.is_empty())
+					.or_else(|| take_string(ffi::LIBMTP_Get_Modelname(device)))
+			}
+			.unwrap_or_else(|| serial_number.to_string());
+
+			debug!("Opened MTP device <name='{name}', serial_number='{serial_number}'>");
+
+			let device = Arc::new(Self {
+				raw: device,
+				location,
+				serial_number: found_serial_number,
+				name,
+			});
+
+			libmtp
+				.open_devices
+				.insert(serial_number.to_string(), Arc::downgrade(&device));
+
+			return Ok(device);
+		}
+
+		Err(Error::NotConnected(serial_number.to_string()))
+	}
+
+	#[must_use]
+	pub fn serial_number(&self) -> &str {
+		&self.serial_number
+	}
+
+	/// The name the user gave to the device, or its model name
+	#[must_use]
+	pub fn name(&self) -> &str {
+		&self.name
+	}
+
+	pub fn storages(&self) -> Result<Vec<Storage>, Error> {
+		let _libmtp = libmtp();
+
+		// SAFETY: The device is open, and the libmtp lock is held
+		unsafe {
+			ffi::LIBMTP_Clear_Errorstack(self.raw);
+
+			if ffi::LIBMTP_Get_Storage(self.raw, ffi::LIBMTP_STORAGE_SORTBY_NOTSORTED) != 0 {
+				return Err(self.error("list storages"));
+			}
+
+			let mut storages = vec![];
+			let mut storage = (*self.raw).storage;
+
+			while let Some(current) = storage.as_ref() {
+				storages.push(Storage {
+					id: current.id,
+					description: copy_string(current.StorageDescription),
+					volume_identifier: copy_string(current.VolumeIdentifier),
+					max_capacity: current.MaxCapacity,
+					free_space: current.FreeSpaceInBytes,
+				});
+
+				storage = current.next;
+			}
+
+			Ok(storages)
+		}
+	}
+
+	/// Direct children of the folder with `parent_id`, or of the storage root if missing
+	pub fn list(&self, storage_id: u32, parent_id: Option<u32>) -> Result<Vec<Object>, Error> {
+		let _libmtp = libmtp();
+
+		// SAFETY: The device is open, and the libmtp lock is held
+		unsafe {
+			ffi::LIBMTP_Clear_Errorstack(self.raw);
+
+			let mut file = ffi::LIBMTP_Get_Files_And_Folders(
+				self.raw,
+				storage_id,
+				parent_id.unwrap_or(ffi::LIBMTP_FILES_AND_FOLDERS_ROOT),
+			);
+
+			// Empty folders and failures both give no files, only the error stack tells them apart
+			if file.is_null() && !ffi::LIBMTP_Get_Errorstack(self.raw).is_null() {
+				return Err(self.error("list folder"));
+			}
+
+			let mut objects = vec![];
+
+			while let Some(current) = file.as_ref() {
+				if let Some(name) = copy_string(current.filename) {
+					#[allow(clippy::useless_conversion)] // `time_t` is 32 bits on some platforms
+					objects.push(Object {
+						id: current.item_id,
+						name,
+						is_folder: current.filetype == ffi::LIBMTP_FILETYPE_FOLDER,
+						size: current.filesize,
+						modified_at: DateTime::from_timestamp(
+							i64::from(current.modificationdate),
+							0,
+						)
+						.unwrap_or_default(),
+					});
+				}
+
+				let next = current.next;
+				ffi::LIBMTP_destroy_file_t(file);
+				file = next;
+			}
+
+			Ok(objects)
+		}
+	}
+
+	/// Whether the device supports reading parts of files, otherwise they have to be downloaded
+	#[must_use]
+	pub fn supports_partial_reads(&self) -> bool {
+		let _libmtp = libmtp();
+
+		// SAFETY: The device is open, and the libmtp lock is held
+		unsafe {
+			ffi::LIBMTP_Check_Capability(self.raw, ffi::LIBMTP_DEVICECAP_GET_PARTIAL_OBJECT) != 0
+		}
+	}
+
+	/// Reads up to `len` bytes of the file with `id`, starting at `offset`
+	pub fn read_partial(&self, id: u32, offset: u64, len: u32) -> Result<Vec<u8>, Error> {
+		let _libmtp = libmtp();
+
+		let mut data = ptr::null_mut();
+		let mut size = 0;
+
+		// SAFETY: The device is open, the libmtp lock is held, and libmtp allocates `data` with
+		// `size` bytes, which we free after copying them
+		unsafe {
+			ffi::LIBMTP_Clear_Errorstack(self.raw);
+
+			if ffi::LIBMTP_GetPartialObject(
+				self.raw,
+				id,
+				offset,
+				len,
+				addr_of_mut!(data),
+				addr_of_mut!(size),
+			) != 0
+			{
+				return Err(self.error("read file"));
+			}
+
+			if data.is_null() {
+				return Ok(vec![]);
+			}
+
+			let bytes = slice::from_raw_parts(data, size as usize).to_vec();
+			libc::free(data.cast());
+
+			Ok(bytes)
+		}
+	}
+
+	/// Downloads the file with `id` to `path`
+	pub fn download(&self, id: u32, path: impl AsRef<Path>) -> Result<(), Error> {
+		let path =
+			CString::new(path.as_ref().as_os_str().as_bytes()).map_err(|_| Error::NulInPath)?;
+
+		let _libmtp = libmtp();
+
+		// SAFETY: The device is open, the libmtp lock is held and the path is nul terminated
+		unsafe {
+			ffi::LIBMTP_Clear_Errorstack(self.raw);
+
+			if ffi::LIBMTP_Get_File_To_File(self.raw, id, path.as_ptr(), None, ptr::null()) != 0 {
+				return Err(self.error("download file"));
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Collects the messages of the device's error stack, which must be called holding the libmtp lock
+	unsafe fn error(&self, action: &'static str) -> Error {
+		let mut messages = vec![];
+		let mut error = ffi::LIBMTP_Get_Errorstack(self.raw);
+
+		while let Some(current) = error.as_ref() {
+			if let Some(message) = copy_string(current.error_text) {
+				messages.push(message);
+			}
+
+			error = current.next;
+		}
+
+		ffi::LIBMTP_Clear_Errorstack(self.raw);
+
+		Error::Libmtp {
+			action,
+			message: if messages.is_empty() {
+				"unknown error".to_string()
+			} else {
+				messages.join("; ")
+			},
+		}
+	}
+}
+
+impl Drop for Device {
+	fn drop(&mut self) {
+		let _libmtp = libmtp();
+
+		// SAFETY: This is the last reference to the device, and the libmtp lock is held
+		unsafe { ffi::LIBMTP_Release_Device(self.raw) };
+
+		debug!(
+			"Released MTP device <name='{}', serial_number='{}'>",
+			self.name, self.serial_number
+		);
+	}
+}
+
+/// Copies a string owned by a libmtp struct
+unsafe fn copy_string(string: *const c_char) -> Option<String> {
+	(!string.is_null()).then(|| CStr::from_ptr(string).to_string_lossy().into_owned())
+}
+
+/// Takes a string libmtp allocated for us, freeing it
+unsafe fn take_string(string: *mut c_char) -> Option<String> {
+	let copy = copy_string(string);
+
+	if !string.is_null() {
+		libc::free(string.cast());
+	}
+
+	copy
+}
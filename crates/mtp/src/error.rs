@@ -0,0 +1,16 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+	#[error("MTP device isn't connected <serial_number='{0}'>")]
+	NotConnected(String),
+	#[error("libmtp failed to {action}: {message}")]
+	Libmtp {
+		action: &'static str,
+		message: String,
+	},
+	#[error("path can't be passed to libmtp as it has a nul byte")]
+	NulInPath,
+	#[error("MTP devices aren't supported on this platform")]
+	Unsupported,
+}
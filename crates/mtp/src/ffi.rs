@@ -0,0 +1,122 @@
+//! Bindings to the parts of libmtp we use, from `libmtp.h` of libmtp 1.1.
+
+#![allow(non_camel_case_types, non_snake_case, clippy::use_self)]
+
+use std::ffi::{c_char, c_int, c_uchar, c_uint, c_void};
+
+pub const LIBMTP_ERROR_NONE: c_int = 0;
+pub const LIBMTP_ERROR_NO_DEVICE_ATTACHED: c_int = 5;
+
+pub const LIBMTP_STORAGE_SORTBY_NOTSORTED: c_int = 0;
+pub const LIBMTP_FILES_AND_FOLDERS_ROOT: u32 = 0xffff_ffff;
+pub const LIBMTP_FILETYPE_FOLDER: c_int = 0;
+pub const LIBMTP_DEVICECAP_GET_PARTIAL_OBJECT: c_int = 0;
+
+#[repr(C)]
+pub struct LIBMTP_device_entry_t {
+	pub vendor: *mut c_char,
+	pub vendor_id: u16,
+	pub product: *mut c_char,
+	pub product_id: u16,
+	pub device_flags: u32,
+}
+
+#[repr(C)]
+pub struct LIBMTP_raw_device_t {
+	pub device_entry: LIBMTP_device_entry_t,
+	pub bus_location: u32,
+	pub devnum: u8,
+}
+
+#[repr(C)]
+pub struct LIBMTP_error_t {
+	pub errornumber: c_int,
+	pub error_text: *mut c_char,
+	pub next: *mut LIBMTP_error_t,
+}
+
+#[repr(C)]
+pub struct LIBMTP_devicestorage_t {
+	pub id: u32,
+	pub StorageType: u16,
+	pub FilesystemType: u16,
+	pub AccessCapability: u16,
+	pub MaxCapacity: u64,
+	pub FreeSpaceInBytes: u64,
+	pub FreeSpaceInObjects: u64,
+	pub StorageDescription: *mut c_char,
+	pub VolumeIdentifier: *mut c_char,
+	pub next: *mut LIBMTP_devicestorage_t,
+	pub prev: *mut LIBMTP_devicestorage_t,
+}
+
+/// Only the leading fields, the struct is always allocated by libmtp
+#[repr(C)]
+pub struct LIBMTP_mtpdevice_t {
+	pub object_bitsize: u8,
+	pub params: *mut c_void,
+	pub usbinfo: *mut c_void,
+	pub storage: *mut LIBMTP_devicestorage_t,
+	pub errorstack: *mut LIBMTP_error_t,
+}
+
+#[repr(C)]
+pub struct LIBMTP_file_t {
+	pub item_id: u32,
+	pub parent_id: u32,
+	pub storage_id: u32,
+	pub filename: *mut c_char,
+	pub filesize: u64,
+	pub modificationdate: libc::time_t,
+	pub filetype: c_int,
+	pub next: *mut LIBMTP_file_t,
+}
+
+pub type LIBMTP_progressfunc_t =
+	Option<unsafe extern "C" fn(sent: u64, total: u64, data: *const c_void) -> c_int>;
+
+#[link(name = "mtp")]
+extern "C" {
+	pub fn LIBMTP_Init();
+
+	pub fn LIBMTP_Detect_Raw_Devices(
+		devices: *mut *mut LIBMTP_raw_device_t,
+		numdevs: *mut c_int,
+	) -> c_int;
+	pub fn LIBMTP_Open_Raw_Device_Uncached(
+		rawdevice: *mut LIBMTP_raw_device_t,
+	) -> *mut LIBMTP_mtpdevice_t;
+	pub fn LIBMTP_Release_Device(device: *mut LIBMTP_mtpdevice_t);
+
+	pub fn LIBMTP_Get_Serialnumber(device: *mut LIBMTP_mtpdevice_t) -> *mut c_char;
+	pub fn LIBMTP_Get_Friendlyname(device: *mut LIBMTP_mtpdevice_t) -> *mut c_char;
+	pub fn LIBMTP_Get_Modelname(device: *mut LIBMTP_mtpdevice_t) -> *mut c_char;
+	pub fn LIBMTP_Check_Capability(device: *mut LIBMTP_mtpdevice_t, cap: c_int) -> c_int;
+
+	pub fn LIBMTP_Get_Errorstack(device: *mut LIBMTP_mtpdevice_t) -> *mut LIBMTP_error_t;
+	pub fn LIBMTP_Clear_Errorstack(device: *mut LIBMTP_mtpdevice_t);
+
+	pub fn LIBMTP_Get_Storage(device: *mut LIBMTP_mtpdevice_t, sortby: c_int) -> c_int;
+	pub fn LIBMTP_Get_Files_And_Folders(
+		device: *mut LIBMTP_mtpdevice_t,
+		storage: u32,
+		parent: u32,
+	) -> *mut LIBMTP_file_t;
+	pub fn LIBMTP_destroy_file_t(file: *mut LIBMTP_file_t);
+
+	pub fn LIBMTP_Get_File_To_File(
+		device: *mut LIBMTP_mtpdevice_t,
+		id: u32,
+		path: *const c_char,
+		callback: LIBMTP_progressfunc_t,
+		data: *const c_void,
+	) -> c_int;
+	pub fn LIBMTP_GetPartialObject(
+		device: *mut LIBMTP_mtpdevice_t,
+		id: u32,
+		offset: u64,
+		maxbytes: u32,
+		data: *mut *mut c_uchar,
+		size: *mut c_uint,
+	) -> c_int;
+}
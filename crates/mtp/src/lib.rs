@@ -0,0 +1,60 @@
+#![doc = include_str!("../README.md")]
+#![warn(
+	clippy::all,
+	clippy::pedantic,
+	clippy::correctness,
+	clippy::perf,
+	clippy::style,
+	clippy::suspicious,
+	clippy::complexity,
+	clippy::nursery,
+	clippy::unwrap_used,
+	unused_qualifications,
+	rust_2018_idioms,
+	trivial_casts,
+	trivial_numeric_casts,
+	unused_allocation,
+	clippy::dbg_macro,
+	clippy::deprecated_cfg_attr,
+	clippy::separated_literal_suffix,
+	deprecated
+)]
+#![forbid(deprecated_in_future)]
+#![allow(clippy::missing_errors_doc, clippy::module_name_repetitions)]
+
+use chrono::{DateTime, Utc};
+
+#[cfg(all(feature = "libmtp", any(target_os = "linux", target_os = "macos")))]
+mod device;
+#[cfg(all(feature = "libmtp", any(target_os = "linux", target_os = "macos")))]
+mod ffi;
+
+#[cfg(not(all(feature = "libmtp", any(target_os = "linux", target_os = "macos"))))]
+#[path = "unsupported.rs"]
+mod device;
+
+mod error;
+
+pub use device::{connected_serial_numbers, Device};
+pub use error::Error;
+
+/// A storage of a device, like the internal memory of a phone or each card slot of a camera
+#[derive(Debug, Clone)]
+pub struct Storage {
+	pub id: u32,
+	pub description: Option<String>,
+	pub volume_identifier: Option<String>,
+	pub max_capacity: u64,
+	pub free_space: u64,
+}
+
+/// A file or folder in a storage
+#[derive(Debug, Clone)]
+pub struct Object {
+	/// Handle of the object, which some devices, like Android phones, assign again on every connection
+	pub id: u32,
+	pub name: String,
+	pub is_folder: bool,
+	pub size: u64,
+	pub modified_at: DateTime<Utc>,
+}
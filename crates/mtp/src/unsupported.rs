@@ -0,0 +1,50 @@
+//! Stand-in for platforms without libmtp, or builds without the `libmtp` feature, where no device
+//! is ever connected.
+
+#![allow(clippy::missing_const_for_fn, clippy::must_use_candidate)]
+
+use crate::{Error, Object, Storage};
+
+use std::{convert::Infallible, path::Path, sync::Arc};
+
+#[allow(clippy::unnecessary_wraps)]
+pub fn connected_serial_numbers() -> Result<Vec<String>, Error> {
+	Ok(vec![])
+}
+
+/// Never constructed, as devices can't be opened
+pub struct Device(Infallible);
+
+impl Device {
+	pub fn open(_serial_number: &str) -> Result<Arc<Self>, Error> {
+		Err(Error::Unsupported)
+	}
+
+	pub fn serial_number(&self) -> &str {
+		match self.0 {}
+	}
+
+	pub fn name(&self) -> &str {
+		match self.0 {}
+	}
+
+	pub fn storages(&self) -> Result<Vec<Storage>, Error> {
+		match self.0 {}
+	}
+
+	pub fn list(&self, _storage_id: u32, _parent_id: Option<u32>) -> Result<Vec<Object>, Error> {
+		match self.0 {}
+	}
+
+	pub fn supports_partial_reads(&self) -> bool {
+		match self.0 {}
+	}
+
+	pub fn read_partial(&self, _id: u32, _offset: u64, _len: u32) -> Result<Vec<u8>, Error> {
+		match self.0 {}
+	}
+
+	pub fn download(&self, _id: u32, _path: impl AsRef<Path>) -> Result<(), Error> {
+		match self.0 {}
+	}
+}
@@ -5,20 +5,38 @@ use tokio::io::AsyncReadExt;
 /// TODO
 #[derive(Debug, PartialEq, Eq)]
 pub struct Block<'a> {
-	// TODO: File content, checksum, source location so it can be resent!
+	// TODO: File content, source location so it can be resent!
 	pub offset: u64,
 	pub size: u64,
+	/// BLAKE3 hash of `data` so the receiver can tell the block was corrupted in transit
+	pub checksum: [u8; 32],
 	pub data: &'a [u8],
-	// TODO: Checksum?
 }
 
 impl<'a> Block<'a> {
+	#[must_use]
+	pub fn new(offset: u64, data: &'a [u8]) -> Self {
+		Self {
+			offset,
+			size: data.len() as u64,
+			checksum: *blake3::hash(data).as_bytes(),
+			data,
+		}
+	}
+
+	/// Check the data received for this block matches what was sent
+	#[must_use]
+	pub fn verify(&self, data: &[u8]) -> bool {
+		blake3::hash(data) == self.checksum
+	}
+
 	#[must_use]
 	pub fn to_bytes(&self) -> Vec<u8> {
 		let mut buf = Vec::new();
 		buf.extend_from_slice(&self.offset.to_le_bytes());
 		debug_assert_eq!(self.data.len(), self.size as usize); // TODO: Should `self.size` be inferred instead?
 		buf.extend_from_slice(&self.size.to_le_bytes());
+		buf.extend_from_slice(&self.checksum);
 		buf.extend_from_slice(self.data);
 		buf
 	}
@@ -35,6 +53,9 @@ impl<'a> Block<'a> {
 		stream.read_exact(&mut size).await?;
 		let size = u64::from_le_bytes(size);
 
+		let mut checksum = [0; 32];
+		stream.read_exact(&mut checksum).await?;
+
 		// TODO: Ensure `size` is `block_size` or smaller else buffer overflow
 
 		if size as usize > data_buf.len() {
@@ -49,6 +70,7 @@ impl<'a> Block<'a> {
 		Ok(Self {
 			offset,
 			size,
+			checksum,
 			data: &[], // TODO: This is super cringe. Data should be decoded here but lifetimes and extra allocations become a major concern.
 		})
 	}
@@ -62,11 +84,7 @@ mod tests {
 
 	#[tokio::test]
 	async fn test_block() {
-		let mut req = Block {
-			offset: 420,
-			size: 10, // Matches length of string on next line
-			data: b"Spacedrive".as_ref(),
-		};
+		let mut req = Block::new(420, b"Spacedrive".as_ref());
 		let bytes = req.to_bytes();
 		let mut data2 = vec![0; req.data.len()];
 		let req2 = Block::from_stream(&mut Cursor::new(bytes), &mut data2)
@@ -80,11 +98,7 @@ mod tests {
 	#[tokio::test]
 	#[should_panic] // TODO: This currently panics but long term it should have proper error handling
 	async fn test_block_data_buf_overflow() {
-		let mut req = Block {
-			offset: 420,
-			size: 10, // Matches length of string on next line
-			data: b"Spacedrive".as_ref(),
-		};
+		let mut req = Block::new(420, b"Spacedrive".as_ref());
 		let bytes = req.to_bytes();
 		let mut data2 = vec![0; 5]; // Length smaller than `req.data.len()`
 		let req2 = Block::from_stream(&mut Cursor::new(bytes), &mut data2)
@@ -94,4 +108,18 @@ mod tests {
 		assert_eq!(req, req2);
 		assert_eq!(data, data2);
 	}
+
+	#[tokio::test]
+	async fn test_block_corrupted() {
+		let req = Block::new(420, b"Spacedrive".as_ref());
+		let bytes = req.to_bytes();
+		let mut data2 = vec![0; req.data.len()];
+		let req2 = Block::from_stream(&mut Cursor::new(bytes), &mut data2)
+			.await
+			.unwrap();
+		assert!(req2.verify(&data2));
+
+		data2[0] ^= 1;
+		assert!(!req2.verify(&data2));
+	}
 }
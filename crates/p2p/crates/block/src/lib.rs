@@ -10,11 +10,13 @@
 #![warn(clippy::unwrap_used, clippy::panic)]
 
 use std::{
-	io,
+	io::{self, SeekFrom},
 	sync::atomic::{AtomicBool, Ordering},
 };
 
-use tokio::io::{AsyncBufRead, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::io::{
+	AsyncBufRead, AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWrite, AsyncWriteExt,
+};
 use tracing::debug;
 
 mod block;
@@ -61,6 +63,18 @@ impl<'a> Msg<'a> {
 	}
 }
 
+/// How many times in a row a block can arrive corrupted before the transfer is given up on
+const MAX_BLOCK_RETRIES: u8 = 3;
+
+/// How far along a transfer is, in percent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+	/// Index of the file being transferred in the requests
+	pub file: usize,
+	pub file_percent: u8,
+	pub percent: u8,
+}
+
 /// TODO
 pub struct Transfer<'a, F> {
 	reqs: &'a SpaceblockRequests,
@@ -74,7 +88,7 @@ pub struct Transfer<'a, F> {
 
 impl<'a, F> Transfer<'a, F>
 where
-	F: Fn(u8) + 'a,
+	F: Fn(Progress) + 'a,
 {
 	// TODO: Handle `req.range` correctly in this code
 
@@ -89,15 +103,44 @@ where
 		}
 	}
 
+	fn progress(&self, offset: u64, size: u64) {
+		// SAFETY: Percent must be between 0 and 100
+		let percent = |done: u64, total: u64| ((done as f64 / total as f64) * 100.0) as u8;
+
+		(self.on_progress)(Progress {
+			file: self.i.saturating_sub(1),
+			file_percent: percent(offset, size),
+			percent: percent(self.total_offset, self.total_bytes),
+		});
+	}
+
 	// TODO: Should `new` take in the streams too cause this means we `Stream` `SpaceblockRequest` could get outta sync.
 	pub async fn send(
 		&mut self,
 		stream: &mut (impl AsyncRead + AsyncWrite + Unpin),
-		mut file: (impl AsyncBufRead + Unpin),
+		mut file: (impl AsyncBufRead + AsyncSeek + Unpin),
 	) -> Result<(), io::Error> {
+		let size = self.current_size()?;
+
+		// The receiver tells us how much of the file it already has from an interrupted transfer
+		let mut offset = stream.read_u64_le().await?;
+		if offset > size {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidData,
+				"Resume offset is past the end of the file!",
+			));
+		}
+
+		self.total_offset += offset;
+		if offset == size {
+			return Ok(());
+		}
+
+		file.seek(SeekFrom::Start(offset)).await?;
+
 		// We manually implement what is basically a `BufReader` so we have more control
 		let mut buf = vec![0u8; self.reqs.block_size.size() as usize];
-		let mut offset: u64 = 0;
+		let mut retries = 0;
 
 		loop {
 			if self.cancelled.load(Ordering::Relaxed) {
@@ -107,37 +150,50 @@ where
 			}
 
 			let read = file.read(&mut buf[..]).await?;
-			self.total_offset += read as u64;
-			(self.on_progress)(
-				((self.total_offset as f64 / self.total_bytes as f64) * 100.0) as u8,
-			); // SAFETY: Percent must be between 0 and 100
 
 			if read == 0 {
 				// The file may have been modified during sender on the sender and we don't account for that.
 				// TODO: Error handling + send error to remote
 				assert!(
-					(offset + read as u64) == self.reqs.requests[self.i].size,
+					offset == size,
 					"File sending has stopped but it doesn't match the expected length!"
 				);
 
 				return Ok(());
 			}
 
-			let block = Block {
-				offset,
-				size: read as u64,
-				data: &buf[..read],
-			};
+			let block = Block::new(offset, &buf[..read]);
 			debug!(
 				"Sending block at offset {} of size {}",
 				block.offset, block.size
 			);
-			offset += read as u64;
 
 			stream.write_all(&Msg::Block(block).to_bytes()).await?;
 			stream.flush().await?;
 
-			match stream.read_u8().await? {
+			let response = stream.read_u8().await?;
+
+			// Corrupted in transit, so we send the same block again
+			if response == 3 {
+				retries += 1;
+				if retries > MAX_BLOCK_RETRIES {
+					return Err(io::Error::new(
+						io::ErrorKind::InvalidData,
+						"Block was corrupted too many times in a row!",
+					));
+				}
+
+				debug!("Receiver got a corrupted block at offset {offset}, resending");
+				file.seek(SeekFrom::Start(offset)).await?;
+				continue;
+			}
+
+			retries = 0;
+			offset += read as u64;
+			self.total_offset += read as u64;
+			self.progress(offset, size);
+
+			match response {
 				// Continue sending
 				0 => {}
 				// Cancelled by user
@@ -147,27 +203,39 @@ where
 				}
 				// Transfer complete
 				2 => return Ok(()),
-				_ => todo!(),
+				_ => {
+					return Err(io::Error::new(
+						io::ErrorKind::InvalidData,
+						"Invalid block response!",
+					))
+				}
 			}
 		}
 	}
 
+	/// `offset` is how much of the file `file` already holds from an interrupted transfer, which the
+	/// sender picks up from. `file` must be positioned at its end.
 	// TODO: Timeout on receiving/sending
 	pub async fn receive(
 		&mut self,
 		stream: &mut (impl AsyncRead + AsyncWrite + Unpin),
 		mut file: (impl AsyncWrite + Unpin),
+		mut offset: u64,
 		// TODO: Proper error type
 	) -> Result<(), io::Error> {
-		// We manually implement what is basically a `BufReader` so we have more control
-		let mut data_buf = vec![0u8; self.reqs.block_size.size() as usize];
-		let mut offset: u64 = 0;
+		let size = self.current_size()?;
 
-		if self.reqs.requests[self.i].size == 0 {
-			self.i += 1;
+		stream.write_u64_le(offset).await?;
+		stream.flush().await?;
+
+		self.total_offset += offset;
+		if offset == size {
 			return Ok(());
 		}
 
+		// We manually implement what is basically a `BufReader` so we have more control
+		let mut data_buf = vec![0u8; self.reqs.block_size.size() as usize];
+
 		// TODO: Prevent loop being a DOS vector
 		loop {
 			if self.cancelled.load(Ordering::Relaxed) {
@@ -180,25 +248,34 @@ where
 			let msg = Msg::from_stream(stream, &mut data_buf).await?;
 			match msg {
 				Msg::Block(block) => {
-					self.total_offset += block.size;
-					(self.on_progress)(
-						((self.total_offset as f64 / self.total_bytes as f64) * 100.0) as u8,
-					); // SAFETY: Percent must be between 0 and 100
-
 					debug!(
 						"Received block at offset {} of size {}",
 						block.offset, block.size
 					);
-					offset += block.size;
 
-					file.write_all(&data_buf[..block.size as usize]).await?;
+					if block.offset != offset {
+						return Err(io::Error::new(
+							io::ErrorKind::InvalidData,
+							"Received block doesn't follow the previous one!",
+						));
+					}
+
+					let data = &data_buf[..block.size as usize];
+					if !block.verify(data) {
+						debug!("Block at offset {offset} is corrupted, requesting it again");
+						stream.write_u8(3).await?;
+						stream.flush().await?;
+						continue;
+					}
+
+					file.write_all(data).await?;
+
+					offset += block.size;
+					self.total_offset += block.size;
+					self.progress(offset, size);
 
-					let req = self.reqs.requests.get(self.i).ok_or_else(|| {
-						debug!("Vector read out of bounds!");
-						io::ErrorKind::Other
-					})?;
 					// TODO: Should this be `read == 0`
-					if offset == req.size {
+					if offset == size {
 						break;
 					}
 
@@ -217,10 +294,20 @@ where
 		stream.write_u8(2).await?;
 		stream.flush().await?;
 		file.flush().await?;
-		self.i += 1;
 
 		Ok(())
 	}
+
+	/// Size of the file being transferred, moving on to the next one for the following call
+	fn current_size(&mut self) -> Result<u64, io::Error> {
+		let req = self.reqs.requests.get(self.i).ok_or_else(|| {
+			debug!("Vector read out of bounds!");
+			io::ErrorKind::Other
+		})?;
+		self.i += 1;
+
+		Ok(req.size)
+	}
 }
 
 #[cfg(test)]
@@ -241,6 +328,7 @@ mod tests {
 		let req = SpaceblockRequests {
 			id: Uuid::new_v4(),
 			block_size: BlockSize::from_file_size(data.len() as u64),
+			directories: vec![],
 			requests: vec![SpaceblockRequest {
 				name: "Demo".to_string(),
 				size: data.len() as u64,
@@ -265,7 +353,7 @@ mod tests {
 
 		let mut result = Vec::new();
 		Transfer::new(&req, |_| {}, &Default::default())
-			.receive(&mut server, &mut result)
+			.receive(&mut server, &mut result, 0)
 			.await;
 		assert_eq!(result, data);
 	}
@@ -282,6 +370,7 @@ mod tests {
 		let req = SpaceblockRequests {
 			id: Uuid::new_v4(),
 			block_size,
+			directories: vec![],
 			requests: vec![SpaceblockRequest {
 				name: "Demo".to_string(),
 				size: data.len() as u64,
@@ -306,7 +395,118 @@ mod tests {
 
 		let mut result = Vec::new();
 		Transfer::new(&req, |_| {}, &Default::default())
-			.receive(&mut server, &mut result)
+			.receive(&mut server, &mut result, 0)
+			.await;
+		assert_eq!(result, data);
+	}
+
+	#[tokio::test]
+	async fn test_spaceblock_multiple_files() {
+		let (mut client, mut server) = tokio::io::duplex(64);
+
+		// This is sent out of band of Spaceblock
+		let files = vec![b"Spacedrive".to_vec(), b"Spacedrop".to_vec()];
+		let req = SpaceblockRequests {
+			id: Uuid::new_v4(),
+			block_size: BlockSize::_128KiB,
+			directories: vec!["Demo".to_string()],
+			requests: files
+				.iter()
+				.enumerate()
+				.map(|(i, data)| SpaceblockRequest {
+					name: format!("Demo/{i}"),
+					size: data.len() as u64,
+					range: Range::Full,
+				})
+				.collect(),
+		};
+
+		let (tx, rx) = oneshot::channel();
+		tokio::spawn({
+			let req = req.clone();
+			let files = files.clone();
+			async move {
+				tx.send(()).unwrap();
+				let cancelled = AtomicBool::new(false);
+				let mut transfer = Transfer::new(&req, |_| {}, &cancelled);
+				for data in files {
+					transfer
+						.send(&mut client, BufReader::new(Cursor::new(data)))
+						.await
+						.unwrap();
+				}
+			}
+		});
+
+		rx.await.unwrap();
+
+		let progress = std::sync::Mutex::new(Vec::new());
+		let cancelled = AtomicBool::new(false);
+		let mut transfer = Transfer::new(&req, |p| progress.lock().unwrap().push(p), &cancelled);
+		for data in &files {
+			let mut result = Vec::new();
+			transfer.receive(&mut server, &mut result, 0).await.unwrap();
+			assert_eq!(&result, data);
+		}
+
+		assert_eq!(
+			progress.into_inner().unwrap(),
+			vec![
+				Progress {
+					file: 0,
+					file_percent: 100,
+					percent: 52,
+				},
+				Progress {
+					file: 1,
+					file_percent: 100,
+					percent: 100,
+				},
+			]
+		);
+	}
+
+	#[tokio::test]
+	async fn test_spaceblock_resume() {
+		let (mut client, mut server) = tokio::io::duplex(64);
+
+		// This is sent out of band of Spaceblock
+		let block_size = BlockSize::_128KiB;
+		let data = (0..block_size.size() * 3)
+			.map(|i| (i % 251) as u8)
+			.collect::<Vec<_>>();
+
+		let req = SpaceblockRequests {
+			id: Uuid::new_v4(),
+			block_size: block_size.clone(),
+			directories: vec![],
+			requests: vec![SpaceblockRequest {
+				name: "Demo".to_string(),
+				size: data.len() as u64,
+				range: Range::Full,
+			}],
+		};
+
+		let (tx, rx) = oneshot::channel();
+		tokio::spawn({
+			let req = req.clone();
+			let data = data.clone();
+			async move {
+				let file = BufReader::new(Cursor::new(data));
+				tx.send(()).unwrap();
+				Transfer::new(&req, |_| {}, &Default::default())
+					.send(&mut client, file)
+					.await;
+			}
+		});
+
+		rx.await.unwrap();
+
+		// The first block made it before the transfer was interrupted
+		let received = block_size.size() as usize;
+		let mut result = data[..received].to_vec();
+		Transfer::new(&req, |_| {}, &Default::default())
+			.receive(&mut server, &mut result, received as u64)
 			.await;
 		assert_eq!(result, data);
 	}
@@ -322,6 +522,7 @@ mod tests {
 		let req = SpaceblockRequests {
 			id: Uuid::new_v4(),
 			block_size,
+			directories: vec![],
 			requests: vec![SpaceblockRequest {
 				name: "Demo".to_string(),
 				size: data.len() as u64,
@@ -347,7 +548,7 @@ mod tests {
 
 		let mut result = Vec::new();
 		Transfer::new(&req, |_| {}, &Default::default())
-			.receive(&mut server, &mut result)
+			.receive(&mut server, &mut result, 0)
 			.await;
 		assert_eq!(result, Vec::<u8>::new()); // Cancelled by sender so no data
 	}
@@ -363,6 +564,7 @@ mod tests {
 		let req = SpaceblockRequests {
 			id: Uuid::new_v4(),
 			block_size,
+			directories: vec![],
 			requests: vec![SpaceblockRequest {
 				name: "Demo".to_string(),
 				size: data.len() as u64,
@@ -388,7 +590,7 @@ mod tests {
 
 		let mut result = Vec::new();
 		Transfer::new(&req, |_| {}, &Arc::new(AtomicBool::new(true)))
-			.receive(&mut server, &mut result)
+			.receive(&mut server, &mut result, 0)
 			.await;
 		assert_eq!(result, Vec::<u8>::new()); // Cancelled by sender so no data
 	}
@@ -405,6 +607,7 @@ mod tests {
 		let req = SpaceblockRequests {
 			id: Uuid::new_v4(),
 			block_size,
+			directories: vec![],
 			requests: vec![SpaceblockRequest {
 				name: "Demo".to_string(),
 				size: data.len() as u64,
@@ -430,18 +633,14 @@ mod tests {
 
 		let mut result = Vec::new();
 		Transfer::new(&req, |_| {}, &Default::default())
-			.receive(&mut server, &mut result)
+			.receive(&mut server, &mut result, 0)
 			.await;
 		assert_eq!(result, Vec::<u8>::new()); // Cancelled by sender so no data
 	}
 
 	#[tokio::test]
 	async fn test_msg() {
-		let block = Block {
-			offset: 0,
-			size: 10,
-			data: b"Spacedrive".as_ref(),
-		};
+		let block = Block::new(0, b"Spacedrive".as_ref());
 		let data_len = block.data.len();
 		let mut msg = Msg::Block(block);
 		let bytes = msg.to_bytes();
@@ -54,6 +54,9 @@ impl Range {
 pub struct SpaceblockRequests {
 	pub id: Uuid,
 	pub block_size: BlockSize,
+	/// Directories of the tree being sent, as paths relative to its root separated by `/`, so the
+	/// empty ones are recreated too
+	pub directories: Vec<String>,
 	pub requests: Vec<SpaceblockRequest>,
 }
 
@@ -63,6 +66,8 @@ pub enum SpaceblockRequestsError {
 	Id(#[from] decode::Error),
 	#[error("SpaceblockRequestsError::InvalidLen({0})")]
 	InvalidLen(std::io::Error),
+	#[error("SpaceblockRequestsError::Directory({0:?})")]
+	Directory(decode::Error),
 	#[error("SpaceblockRequestsError::SpaceblockRequest({0:?})")]
 	SpaceblockRequest(#[from] SpaceblockRequestError),
 	#[error("SpaceblockRequestsError::BlockSize({0:?})")]
@@ -82,8 +87,21 @@ impl SpaceblockRequests {
 			.map_err(SpaceblockRequestsError::BlockSize)?;
 
 		let size = stream
-			// Max of 255 files in one request
-			.read_u8()
+			.read_u32_le()
+			.await
+			.map_err(SpaceblockRequestsError::InvalidLen)?;
+
+		let mut directories = Vec::new();
+		for _i in 0..size {
+			directories.push(
+				decode::string(stream)
+					.await
+					.map_err(SpaceblockRequestsError::Directory)?,
+			);
+		}
+
+		let size = stream
+			.read_u32_le()
 			.await
 			.map_err(SpaceblockRequestsError::InvalidLen)?;
 
@@ -95,6 +113,7 @@ impl SpaceblockRequests {
 		Ok(Self {
 			id,
 			block_size,
+			directories,
 			requests,
 		})
 	}
@@ -104,17 +123,23 @@ impl SpaceblockRequests {
 		let Self {
 			id,
 			block_size,
+			directories,
 			requests,
 		} = self;
 		assert!(
-			requests.len() <= 255,
-			"Can't Spacedrop more than 255 files at once!"
+			u32::try_from(requests.len().max(directories.len())).is_ok(),
+			"Can't Spacedrop more than {} files at once!",
+			u32::MAX
 		);
 
 		let mut buf = vec![];
 		encode::uuid(&mut buf, id);
 		buf.append(&mut block_size.to_bytes().to_vec());
-		buf.push(requests.len() as u8);
+		buf.extend_from_slice(&(directories.len() as u32).to_le_bytes());
+		for directory in directories {
+			encode::string(&mut buf, directory);
+		}
+		buf.extend_from_slice(&(requests.len() as u32).to_le_bytes());
 		for request in requests {
 			buf.extend_from_slice(&request.to_bytes());
 		}
@@ -199,6 +224,7 @@ mod tests {
 		let req = SpaceblockRequests {
 			id: Uuid::new_v4(),
 			block_size: BlockSize::from_file_size(42069),
+			directories: vec![],
 			requests: vec![],
 		};
 
@@ -214,6 +240,7 @@ mod tests {
 		let req = SpaceblockRequests {
 			id: Uuid::new_v4(),
 			block_size: BlockSize::from_file_size(42069),
+			directories: vec![],
 			requests: vec![SpaceblockRequest {
 				name: "Demo".to_string(),
 				size: 42069,
@@ -245,9 +272,10 @@ mod tests {
 		let req = SpaceblockRequests {
 			id: Uuid::new_v4(),
 			block_size: BlockSize::from_file_size(42069),
+			directories: vec!["Demo".to_string(), "Demo/Empty".to_string()],
 			requests: vec![
 				SpaceblockRequest {
-					name: "Demo".to_string(),
+					name: "Demo/Demo".to_string(),
 					size: 42069,
 					range: Range::Full,
 				},
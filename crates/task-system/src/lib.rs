@@ -11,6 +11,7 @@
 //! - Gracefully pause and cancel tasks;
 //! - Forced abortion of tasks;
 //! - Prioritizing tasks that will suspend running tasks without priority;
+//! - Resource profiles capping how much of the machine tasks can use, lowered automatically on battery;
 //! - When the system is shutdown, it will return all pending and running tasks to theirs dispatchers, so the user can store them on disk or any other storage to be re-dispatched later;
 //!
 //!
@@ -89,11 +90,13 @@
 
 mod error;
 mod message;
+mod profile;
 mod system;
 mod task;
 mod worker;
 
 pub use error::{RunError, SystemError as TaskSystemError};
+pub use profile::{PowerSource, ResourceProfile};
 pub use system::{
 	BaseDispatcher as BaseTaskDispatcher, Dispatcher as TaskDispatcher, System as TaskSystem,
 };
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use tokio::sync::oneshot;
 
 use super::{
@@ -57,7 +59,10 @@ pub enum WorkerMessage<E: RunError> {
 		task_id: TaskId,
 		ack: oneshot::Sender<Result<(), SystemError>>,
 	},
-	ShutdownRequest(oneshot::Sender<()>),
+	ShutdownRequest {
+		suspend_timeout: Option<Duration>,
+		ack: oneshot::Sender<()>,
+	},
 	StealRequest(oneshot::Sender<Option<TaskWorkState<E>>>),
 	WakeUp,
 }
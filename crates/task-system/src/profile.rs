@@ -0,0 +1,167 @@
+use std::{
+	num::{NonZeroU64, NonZeroUsize},
+	sync::{
+		atomic::{AtomicBool, AtomicU8, Ordering},
+		Arc,
+	},
+};
+
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::{
+	spawn,
+	sync::{OwnedSemaphorePermit, Semaphore},
+	task::JoinHandle,
+};
+use tracing::{debug, trace};
+
+const MIB: u64 = 1024 * 1024;
+
+/// How much of the machine tasks are allowed to use, from the lightest to the heaviest.
+///
+/// A profile caps how many tasks run at the same time, how many bytes per second they may read
+/// from disk, and whether priority tasks may suspend the running ones. Tasks following the same
+/// profile share its budget.
+#[derive(
+	Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub enum ResourceProfile {
+	/// A quarter of the workers and 16 MiB/s of disk reads, without priority tasks
+	LowPower,
+	/// Half of the workers and 128 MiB/s of disk reads
+	Balanced,
+	/// Every worker and no disk read cap
+	#[default]
+	Max,
+}
+
+/// Where the machine currently draws its power from, as reported by a platform hook, see
+/// [`BaseTaskDispatcher::follow_power_source`](crate::BaseTaskDispatcher::follow_power_source)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerSource {
+	Ac,
+	Battery,
+}
+
+impl ResourceProfile {
+	const ALL: [Self; 3] = [Self::LowPower, Self::Balanced, Self::Max];
+
+	/// How many tasks following this profile can run at the same time on a system with
+	/// `workers_count` workers
+	#[must_use]
+	pub fn max_running_tasks(self, workers_count: NonZeroUsize) -> NonZeroUsize {
+		let workers_count = workers_count.get();
+
+		NonZeroUsize::new(match self {
+			Self::LowPower => workers_count / 4,
+			Self::Balanced => workers_count / 2,
+			Self::Max => workers_count,
+		})
+		.unwrap_or(NonZeroUsize::MIN)
+	}
+
+	/// Cap for disk reads, `None` for unlimited
+	#[must_use]
+	pub const fn io_bytes_per_sec(self) -> Option<NonZeroU64> {
+		match self {
+			Self::LowPower => NonZeroU64::new(16 * MIB),
+			Self::Balanced => NonZeroU64::new(128 * MIB),
+			Self::Max => None,
+		}
+	}
+
+	/// If priority tasks may suspend the running ones, otherwise they're just enqueued
+	#[must_use]
+	pub const fn allows_priority(self) -> bool {
+		!matches!(self, Self::LowPower)
+	}
+
+	const fn from_u8(value: u8) -> Self {
+		match value {
+			0 => Self::LowPower,
+			1 => Self::Balanced,
+			_ => Self::Max,
+		}
+	}
+}
+
+/// Profile selected for the whole system and the budgets of each profile, shared by the
+/// dispatchers and workers
+#[derive(Debug)]
+pub(crate) struct ResourceProfiles {
+	selected: AtomicU8,
+	on_battery: AtomicBool,
+	running_tasks_limits: [Arc<Semaphore>; 3],
+}
+
+impl ResourceProfiles {
+	pub(crate) fn new(workers_count: NonZeroUsize) -> Self {
+		Self {
+			selected: AtomicU8::new(ResourceProfile::default() as u8),
+			on_battery: AtomicBool::new(false),
+			running_tasks_limits: ResourceProfile::ALL.map(|profile| {
+				Arc::new(Semaphore::new(
+					profile.max_running_tasks(workers_count).get(),
+				))
+			}),
+		}
+	}
+
+	/// The selected profile, lowered to [`ResourceProfile::LowPower`] while on battery
+	pub(crate) fn current(&self) -> ResourceProfile {
+		let selected = ResourceProfile::from_u8(self.selected.load(Ordering::Relaxed));
+
+		if self.on_battery.load(Ordering::Relaxed) {
+			selected.min(ResourceProfile::LowPower)
+		} else {
+			selected
+		}
+	}
+
+	pub(crate) fn select(&self, profile: ResourceProfile) {
+		self.selected.store(profile as u8, Ordering::Relaxed);
+	}
+
+	/// The profile a task asking for `requested` actually follows, never above the current one
+	pub(crate) fn effective(&self, requested: ResourceProfile) -> ResourceProfile {
+		requested.min(self.current())
+	}
+
+	/// Waits for a slot in the budget of the effective profile, `None` when it's unlimited
+	pub(crate) async fn acquire(&self, requested: ResourceProfile) -> Option<OwnedSemaphorePermit> {
+		let profile = self.effective(requested);
+
+		if profile == ResourceProfile::Max {
+			return None;
+		}
+
+		Some(
+			Arc::clone(&self.running_tasks_limits[profile as usize])
+				.acquire_owned()
+				.await
+				.expect("we never close resource profiles semaphores"),
+		)
+	}
+
+	pub(crate) fn follow_power_source(
+		self: Arc<Self>,
+		power_source: impl Stream<Item = PowerSource> + Send + 'static,
+	) -> JoinHandle<()> {
+		spawn(async move {
+			let mut power_source = Box::pin(power_source);
+
+			while let Some(source) = power_source.next().await {
+				let on_battery = source == PowerSource::Battery;
+
+				if self.on_battery.swap(on_battery, Ordering::Relaxed) != on_battery {
+					debug!(
+						"Power source changed, tasks now follow the {:?} profile: <source='{source:?}'>",
+						self.current()
+					);
+				}
+			}
+
+			trace!("Power source stream ended, keeping the last known power source");
+		})
+	}
+}
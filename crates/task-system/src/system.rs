@@ -1,25 +1,31 @@
 use std::{
 	cell::RefCell,
-	collections::HashSet,
+	collections::{HashMap, HashSet},
 	fmt,
 	future::Future,
 	num::NonZeroUsize,
 	pin::pin,
 	sync::{
 		atomic::{AtomicBool, Ordering},
-		Arc,
+		Arc, RwLock,
 	},
+	time::Duration,
 };
 
 use async_channel as chan;
-use futures::StreamExt;
+use futures::{Stream, StreamExt};
 use futures_concurrency::future::Join;
-use tokio::{spawn, sync::oneshot, task::JoinHandle};
+use tokio::{
+	spawn,
+	sync::{oneshot, OwnedSemaphorePermit, Semaphore},
+	task::JoinHandle,
+};
 use tracing::{error, info, trace, warn};
 
 use super::{
 	error::{RunError, SystemError},
 	message::SystemMessage,
+	profile::{PowerSource, ResourceProfile, ResourceProfiles},
 	task::{IntoTask, Task, TaskHandle, TaskId},
 	worker::{AtomicWorkerId, WorkStealer, Worker, WorkerBuilder, WorkerId},
 };
@@ -50,6 +56,10 @@ impl<E: RunError> System<E> {
 		let (msgs_tx, msgs_rx) = chan::bounded(8);
 		let system_comm = SystemComm(msgs_tx.clone());
 
+		let resource_profiles = Arc::new(ResourceProfiles::new(
+			NonZeroUsize::new(workers_count).unwrap_or(NonZeroUsize::MIN),
+		));
+
 		let (workers_builders, worker_comms) = (0..workers_count)
 			.map(WorkerBuilder::new)
 			.unzip::<_, _, Vec<_>, Vec<_>>();
@@ -61,7 +71,13 @@ impl<E: RunError> System<E> {
 		let workers = Arc::new(
 			workers_builders
 				.into_iter()
-				.map(|builder| builder.build(system_comm.clone(), task_stealer.clone()))
+				.map(|builder| {
+					builder.build(
+						system_comm.clone(),
+						task_stealer.clone(),
+						Arc::clone(&resource_profiles),
+					)
+				})
 				.collect::<Vec<_>>(),
 		);
 
@@ -100,6 +116,8 @@ impl<E: RunError> System<E> {
 				workers,
 				idle_workers,
 				last_worker_id: Arc::new(AtomicWorkerId::new(0)),
+				concurrency_limits: Arc::default(),
+				resource_profiles,
 			},
 
 			handle: RefCell::new(Some(handle)),
@@ -235,6 +253,24 @@ impl<E: RunError> System<E> {
 	/// If the system message channel is closed for some unknown reason or if we fail to respond to
 	/// oneshot channel with shutdown response.
 	pub async fn shutdown(&self) {
+		self.inner_shutdown(None).await;
+	}
+
+	/// Shuts down the system like [`System::shutdown`], but running tasks only have `suspend_timeout`
+	/// to be suspended and returned as [`TaskStatus::Shutdown`](crate::TaskStatus::Shutdown) to
+	/// their handles. The ones stuck past it are forcibly aborted, returning
+	/// [`TaskStatus::ForcedAbortion`](crate::TaskStatus::ForcedAbortion) instead, so the process can
+	/// exit in a bounded time when the app is being closed.
+	///
+	/// # Panics
+	///
+	/// If the system message channel is closed for some unknown reason or if we fail to respond to
+	/// oneshot channel with shutdown response.
+	pub async fn shutdown_with_timeout(&self, suspend_timeout: Duration) {
+		self.inner_shutdown(Some(suspend_timeout)).await;
+	}
+
+	async fn inner_shutdown(&self, suspend_timeout: Option<Duration>) {
 		if let Some(handle) = self
 			.handle
 			.try_borrow_mut()
@@ -243,7 +279,7 @@ impl<E: RunError> System<E> {
 		{
 			self.workers
 				.iter()
-				.map(|worker| async move { worker.shutdown().await })
+				.map(|worker| async move { worker.shutdown(suspend_timeout).await })
 				.collect::<Vec<_>>()
 				.join()
 				.await;
@@ -398,6 +434,8 @@ pub struct BaseDispatcher<E: RunError> {
 	workers: Arc<Vec<Worker<E>>>,
 	idle_workers: Arc<Vec<AtomicBool>>,
 	last_worker_id: Arc<AtomicWorkerId>,
+	concurrency_limits: Arc<RwLock<HashMap<String, Arc<Semaphore>>>>,
+	resource_profiles: Arc<ResourceProfiles>,
 }
 
 pub trait Dispatcher<E: RunError>: fmt::Debug + Clone + Send + Sync + 'static {
@@ -438,6 +476,8 @@ impl<E: RunError> Clone for BaseDispatcher<E> {
 			workers: Arc::clone(&self.workers),
 			idle_workers: Arc::clone(&self.idle_workers),
 			last_worker_id: Arc::clone(&self.last_worker_id),
+			concurrency_limits: Arc::clone(&self.concurrency_limits),
+			resource_profiles: Arc::clone(&self.resource_profiles),
 		}
 	}
 }
@@ -507,4 +547,105 @@ impl<E: RunError> BaseDispatcher<E> {
 	pub fn workers_count(&self) -> usize {
 		self.workers.len()
 	}
+
+	/// Limits how many tasks tagged with any of the given keys can be running at the same time.
+	///
+	/// All keys share the same limit, so the user can group many locations that live on the same slow
+	/// device under a single budget. Tasks already holding a permit from a previous limit are not affected.
+	///
+	/// # Panics
+	///
+	/// Will panic if the concurrency limits lock is poisoned
+	pub fn set_concurrency_limit(
+		&self,
+		keys: impl IntoIterator<Item = impl Into<String>>,
+		limit: NonZeroUsize,
+	) {
+		let semaphore = Arc::new(Semaphore::new(limit.get()));
+
+		let mut concurrency_limits = self
+			.concurrency_limits
+			.write()
+			.expect("concurrency limits lock poisoned");
+
+		for key in keys {
+			concurrency_limits.insert(key.into(), Arc::clone(&semaphore));
+		}
+	}
+
+	/// Removes the concurrency limit for the given key, new tasks tagged with it will be dispatched right away.
+	///
+	/// # Panics
+	///
+	/// Will panic if the concurrency limits lock is poisoned
+	pub fn remove_concurrency_limit(&self, key: &str) {
+		self.concurrency_limits
+			.write()
+			.expect("concurrency limits lock poisoned")
+			.remove(key);
+	}
+
+	/// Waits until a task tagged with the given key is allowed to run, returning a permit that must be
+	/// kept alive until the task finishes, see [`TaskHandle::hold_until_completion`].
+	///
+	/// Returns `None` if there is no concurrency limit for this key.
+	///
+	/// # Panics
+	///
+	/// Will panic if the concurrency limits lock is poisoned
+	pub async fn acquire_concurrency_permit(&self, key: &str) -> Option<OwnedSemaphorePermit> {
+		let semaphore = self
+			.concurrency_limits
+			.read()
+			.expect("concurrency limits lock poisoned")
+			.get(key)
+			.map(Arc::clone)?;
+
+		Some(
+			semaphore
+				.acquire_owned()
+				.await
+				.expect("we never close concurrency limits semaphores"),
+		)
+	}
+
+	/// The profile followed by the whole system, already lowered if the machine is on battery
+	#[must_use]
+	pub fn resource_profile(&self) -> ResourceProfile {
+		self.resource_profiles.current()
+	}
+
+	/// Switches the profile of the whole system, tasks asking for a heavier profile follow this one
+	/// instead. Running tasks keep their slots, the new budget applies to the next ones.
+	pub fn set_resource_profile(&self, profile: ResourceProfile) {
+		self.resource_profiles.select(profile);
+	}
+
+	/// The profile a task asking for `requested` actually follows, never above the system one
+	#[must_use]
+	pub fn effective_resource_profile(&self, requested: ResourceProfile) -> ResourceProfile {
+		self.resource_profiles.effective(requested)
+	}
+
+	/// Waits until a task asking for `requested` is allowed to run, returning a permit that must be
+	/// kept alive until the task finishes, see [`TaskHandle::hold_until_completion`].
+	///
+	/// Returns `None` if the effective profile is [`ResourceProfile::Max`], which has no limit.
+	pub async fn acquire_resource_profile_permit(
+		&self,
+		requested: ResourceProfile,
+	) -> Option<OwnedSemaphorePermit> {
+		self.resource_profiles.acquire(requested).await
+	}
+
+	/// Platform hook to drop every task to [`ResourceProfile::LowPower`] while the machine is on
+	/// battery, restoring the selected profile once it's back on AC.
+	///
+	/// The returned handle can be aborted to stop following the power source.
+	pub fn follow_power_source(
+		&self,
+		power_source: impl Stream<Item = PowerSource> + Send + 'static,
+	) -> JoinHandle<()> {
+		Arc::clone(&self.resource_profiles).follow_power_source(power_source)
+	}
 }
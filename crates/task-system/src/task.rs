@@ -14,8 +14,8 @@ use async_channel as chan;
 use async_trait::async_trait;
 use chan::{Recv, RecvError};
 use downcast_rs::{impl_downcast, Downcast};
-use tokio::{runtime::Handle, sync::oneshot};
-use tracing::{trace, warn};
+use tokio::{runtime::Handle, spawn, sync::oneshot};
+use tracing::{trace, warn, Span};
 use uuid::Uuid;
 
 use super::{
@@ -241,6 +241,13 @@ impl Interrupter {
 		}
 	}
 
+	/// An interrupter that will never be interrupted, to run code written for tasks outside of the task system.
+	#[must_use]
+	pub fn uninterruptible() -> Self {
+		// The sender is dropped right away, so no interruption request can ever arrive
+		Self::new(chan::bounded(1).1)
+	}
+
 	/// Check if the user requested a pause or a cancel, returning the kind of interruption that was requested
 	/// in a non-blocking manner.
 	pub fn try_check_interrupt(&self) -> Option<InterruptionKind> {
@@ -505,6 +512,38 @@ impl<E: RunError> TaskHandle<E> {
 	pub fn remote_controller(&self) -> TaskRemoteController {
 		self.controller.clone()
 	}
+
+	/// Keeps `guard` alive until the task reaches a final state, even if this handle is never polled.
+	///
+	/// Useful to hold resources like the permit returned by
+	/// [`BaseTaskDispatcher::acquire_concurrency_permit`](crate::BaseTaskDispatcher::acquire_concurrency_permit)
+	/// only while the task is on the system.
+	#[must_use]
+	pub fn hold_until_completion(self, guard: impl Send + 'static) -> Self {
+		let Self {
+			done_rx: inner_done_rx,
+			controller,
+		} = self;
+
+		let (done_tx, done_rx) = oneshot::channel();
+
+		spawn(async move {
+			let res = inner_done_rx
+				.await
+				.expect("TaskHandle done channel unexpectedly closed");
+
+			drop(guard);
+
+			if done_tx.send(res).is_err() {
+				trace!("TaskHandle dropped before the task was completed");
+			}
+		});
+
+		Self {
+			done_rx,
+			controller,
+		}
+	}
 }
 
 /// A helper struct when you just want to cancel a task if its `TaskHandle` gets dropped.
@@ -535,6 +574,7 @@ pub struct TaskWorktable {
 	is_aborted: AtomicBool,
 	interrupt_tx: chan::Sender<InterruptionRequest>,
 	current_worker_id: AtomicWorkerId,
+	span: Span,
 }
 
 impl TaskWorktable {
@@ -548,9 +588,16 @@ impl TaskWorktable {
 			is_aborted: AtomicBool::new(false),
 			interrupt_tx,
 			current_worker_id: AtomicWorkerId::new(worker_id),
+			// Created on dispatch, so task spans are children of the span that dispatched them
+			span: Span::current(),
 		}
 	}
 
+	/// Span that was current when the task was dispatched, parent of the spans of each of its runs
+	pub const fn span(&self) -> &Span {
+		&self.span
+	}
+
 	pub fn set_started(&self) {
 		self.started.store(true, Ordering::Relaxed);
 		self.is_running.store(true, Ordering::Relaxed);
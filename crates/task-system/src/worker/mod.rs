@@ -13,6 +13,7 @@ use crate::task::TaskRemoteController;
 use super::{
 	error::{RunError, SystemError},
 	message::WorkerMessage,
+	profile::ResourceProfiles,
 	system::SystemComm,
 	task::{
 		InternalTaskExecStatus, Interrupter, Task, TaskHandle, TaskId, TaskWorkState, TaskWorktable,
@@ -54,7 +55,12 @@ impl<E: RunError> WorkerBuilder<E> {
 		)
 	}
 
-	pub fn build(self, system_comm: SystemComm, task_stealer: WorkStealer<E>) -> Worker<E> {
+	pub fn build(
+		self,
+		system_comm: SystemComm,
+		task_stealer: WorkStealer<E>,
+		resource_profiles: Arc<ResourceProfiles>,
+	) -> Worker<E> {
 		let Self {
 			id,
 			msgs_tx,
@@ -70,6 +76,7 @@ impl<E: RunError> WorkerBuilder<E> {
 					id,
 					system_comm.clone(),
 					task_stealer.clone(),
+					Arc::clone(&resource_profiles),
 					msgs_rx.clone(),
 				))
 				.await
@@ -190,17 +197,20 @@ impl<E: RunError> Worker<E> {
 			.expect("Worker channel closed trying to force task abortion");
 	}
 
-	pub async fn shutdown(&self) {
+	pub async fn shutdown(&self, suspend_timeout: Option<Duration>) {
 		if let Some(handle) = self
 			.handle
 			.try_borrow_mut()
 			.ok()
 			.and_then(|mut maybe_handle| maybe_handle.take())
 		{
-			let (tx, rx) = oneshot::channel();
+			let (ack, rx) = oneshot::channel();
 
 			self.msgs_tx
-				.send(WorkerMessage::ShutdownRequest(tx))
+				.send(WorkerMessage::ShutdownRequest {
+					suspend_timeout,
+					ack,
+				})
 				.await
 				.expect("Worker channel closed trying to shutdown");
 
@@ -1,4 +1,4 @@
-use std::pin::pin;
+use std::{pin::pin, sync::Arc};
 
 use async_channel as chan;
 use futures::StreamExt;
@@ -8,7 +8,9 @@ use tokio_stream::wrappers::IntervalStream;
 use tracing::{error, warn};
 
 use super::{
-	super::{error::RunError, message::WorkerMessage, system::SystemComm},
+	super::{
+		error::RunError, message::WorkerMessage, profile::ResourceProfiles, system::SystemComm,
+	},
 	runner::Runner,
 	RunnerMessage, WorkStealer, WorkerId, ONE_SECOND,
 };
@@ -17,6 +19,7 @@ pub(super) async fn run<E: RunError>(
 	id: WorkerId,
 	system_comm: SystemComm,
 	work_stealer: WorkStealer<E>,
+	resource_profiles: Arc<ResourceProfiles>,
 	msgs_rx: chan::Receiver<WorkerMessage<E>>,
 ) {
 	enum StreamMessage<E: RunError> {
@@ -25,7 +28,7 @@ pub(super) async fn run<E: RunError>(
 		IdleCheck,
 	}
 
-	let (mut runner, runner_rx) = Runner::new(id, work_stealer, system_comm);
+	let (mut runner, runner_rx) = Runner::new(id, work_stealer, system_comm, resource_profiles);
 
 	let mut idle_checker_interval = interval_at(Instant::now(), ONE_SECOND);
 	idle_checker_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
@@ -76,8 +79,11 @@ pub(super) async fn run<E: RunError>(
 				}
 			}
 
-			StreamMessage::Commands(WorkerMessage::ShutdownRequest(tx)) => {
-				return runner.shutdown(tx).await;
+			StreamMessage::Commands(WorkerMessage::ShutdownRequest {
+				suspend_timeout,
+				ack,
+			}) => {
+				return runner.shutdown(suspend_timeout, ack).await;
 			}
 
 			StreamMessage::Commands(WorkerMessage::StealRequest(tx)) => runner.steal_request(tx),
@@ -18,11 +18,12 @@ use tokio::{
 	task::{JoinError, JoinHandle},
 	time::{sleep, timeout, Instant},
 };
-use tracing::{debug, error, trace, warn};
+use tracing::{debug, debug_span, error, trace, warn, Instrument};
 
 use super::{
 	super::{
 		error::{RunError, SystemError},
+		profile::ResourceProfiles,
 		system::SystemComm,
 		task::{
 			ExecStatus, InternalTaskExecStatus, Interrupter, Task, TaskId, TaskOutput, TaskStatus,
@@ -102,6 +103,7 @@ pub(super) struct Runner<E: RunError> {
 	worker_id: WorkerId,
 	system_comm: SystemComm,
 	work_stealer: WorkStealer<E>,
+	resource_profiles: Arc<ResourceProfiles>,
 	task_kinds: HashMap<TaskId, PendingTaskKind>,
 	tasks: VecDeque<TaskWorkState<E>>,
 	paused_tasks: HashMap<TaskId, TaskWorkState<E>>,
@@ -124,6 +126,7 @@ impl<E: RunError> Runner<E> {
 		worker_id: WorkerId,
 		work_stealer: WorkStealer<E>,
 		system_comm: SystemComm,
+		resource_profiles: Arc<ResourceProfiles>,
 	) -> (Self, chan::Receiver<RunnerMessage<E>>) {
 		let (runner_tx, runner_rx) = chan::bounded(8);
 
@@ -132,6 +135,7 @@ impl<E: RunError> Runner<E> {
 				worker_id,
 				system_comm,
 				work_stealer,
+				resource_profiles,
 				task_kinds: HashMap::with_capacity(TASK_QUEUE_INITIAL_SIZE),
 				tasks: VecDeque::with_capacity(TASK_QUEUE_INITIAL_SIZE),
 				paused_tasks: HashMap::new(),
@@ -205,7 +209,11 @@ impl<E: RunError> Runner<E> {
 
 	pub(super) async fn new_task(&mut self, task_work_state: TaskWorkState<E>) {
 		let task_id = task_work_state.task.id();
-		let new_kind = PendingTaskKind::with_priority(task_work_state.task.with_priority());
+		// Priority tasks don't suspend the running ones when the system follows a lighter profile
+		let new_kind = PendingTaskKind::with_priority(
+			task_work_state.task.with_priority()
+				&& self.resource_profiles.current().allows_priority(),
+		);
 
 		trace!(
 			"Received new task: <worker_id='{}', task_id='{task_id}', kind='{new_kind:#?}'>",
@@ -656,7 +664,11 @@ impl<E: RunError> Runner<E> {
 		}
 	}
 
-	pub(super) async fn shutdown(mut self, tx: oneshot::Sender<()>) {
+	pub(super) async fn shutdown(
+		mut self,
+		suspend_timeout: Option<Duration>,
+		tx: oneshot::Sender<()>,
+	) {
 		trace!(
 			"Worker beginning shutdown process: <worker_id='{}'>",
 			self.worker_id
@@ -688,10 +700,20 @@ impl<E: RunError> Runner<E> {
 			trace!("Worker is busy, will shutdown tasks: <worker_id='{worker_id}'>");
 
 			if let Some(RunningTask {
-				task_id, handle, ..
+				task_id,
+				mut handle,
+				..
 			}) = current_task_handle.take()
 			{
-				for (task_id, AbortAndSuspendSignalers { suspend_tx, .. }) in abort_and_suspend_map
+				let mut abort_txs = Vec::with_capacity(abort_and_suspend_map.len());
+
+				for (
+					task_id,
+					AbortAndSuspendSignalers {
+						abort_tx,
+						suspend_tx,
+					},
+				) in abort_and_suspend_map
 				{
 					if suspend_tx.send(()).is_err() {
 						warn!(
@@ -704,9 +726,37 @@ impl<E: RunError> Runner<E> {
 								<worker_id='{worker_id}', task_id='{task_id}'>"
 						);
 					}
+
+					abort_txs.push(abort_tx);
 				}
 
-				if let Err(e) = handle.await {
+				let join_result = match suspend_timeout {
+					Some(suspend_timeout) => {
+						if let Ok(join_result) = timeout(suspend_timeout, &mut handle).await {
+							join_result
+						} else {
+							warn!(
+								"Task didn't suspend in {suspend_timeout:?} on shutdown, forcing its abortion: \
+									<worker_id='{worker_id}', task_id='{task_id}'>"
+							);
+
+							// Keeping the receivers alive until the task is joined, as the task
+							// runner acks the abortion through them
+							let _abort_rxs = abort_txs
+								.into_iter()
+								.filter_map(|abort_tx| {
+									let (tx, rx) = oneshot::channel();
+									abort_tx.send(tx).ok().map(|()| rx)
+								})
+								.collect::<Vec<_>>();
+
+							handle.await
+						}
+					}
+					None => handle.await,
+				};
+
+				if let Err(e) = join_result {
 					error!("Task <worker_id='{worker_id}', task_id='{task_id}'> failed to join: {e:#?}");
 				}
 
@@ -1144,6 +1194,7 @@ fn handle_run_task_attempt<E: RunError>(
 		let already_paused = worktable.is_paused();
 		let already_canceled = worktable.is_canceled();
 		let already_aborted = worktable.is_aborted();
+		let span = debug_span!(parent: worktable.span(), "task", %task_id, %worker_id);
 
 		async move {
 			if already_paused {
@@ -1187,6 +1238,7 @@ fn handle_run_task_attempt<E: RunError>(
 				}
 			}
 		}
+		.instrument(span)
 	})
 }
 
@@ -1,6 +1,6 @@
-use sd_task_system::{TaskOutput, TaskStatus, TaskSystem};
+use sd_task_system::{PowerSource, ResourceProfile, TaskOutput, TaskStatus, TaskSystem};
 
-use std::{collections::VecDeque, time::Duration};
+use std::{collections::VecDeque, num::NonZeroUsize, time::Duration};
 
 use futures_concurrency::future::Join;
 use rand::Rng;
@@ -64,6 +64,25 @@ async fn shutdown_test() {
 	assert!(matches!(handle.await, Ok(TaskStatus::Shutdown(_))));
 }
 
+#[tokio::test]
+#[traced_test]
+async fn shutdown_with_timeout_test() {
+	let system = TaskSystem::new();
+
+	let (task, began_rx) = BrokenTask::new();
+
+	let handle = system.dispatch(task).await;
+
+	began_rx.await.unwrap();
+
+	// BrokenTask never checks its interrupter, so it can't be suspended
+	system
+		.shutdown_with_timeout(Duration::from_millis(100))
+		.await;
+
+	assert!(matches!(handle.await, Ok(TaskStatus::ForcedAbortion)));
+}
+
 #[tokio::test]
 #[traced_test]
 async fn cancel_test() {
@@ -222,3 +241,109 @@ async fn steal_test() {
 
 	system.shutdown().await;
 }
+
+#[tokio::test]
+#[traced_test]
+async fn concurrency_limit_test() {
+	let system = TaskSystem::<SampleError>::new();
+
+	let dispatcher = system.get_dispatcher();
+	dispatcher.set_concurrency_limit(["slow_device"], NonZeroUsize::new(1).unwrap());
+
+	let permit = dispatcher
+		.acquire_concurrency_permit("slow_device")
+		.await
+		.unwrap();
+
+	let handle = system
+		.dispatch(NeverTask::default())
+		.await
+		.hold_until_completion(permit);
+
+	// No permits left while the first task is running
+	assert!(tokio::time::timeout(
+		Duration::from_millis(100),
+		dispatcher.acquire_concurrency_permit("slow_device")
+	)
+	.await
+	.is_err());
+
+	// Keys without limits never wait
+	assert!(dispatcher
+		.acquire_concurrency_permit("fast_device")
+		.await
+		.is_none());
+
+	handle.cancel().await;
+	assert!(matches!(handle.await, Ok(TaskStatus::Canceled)));
+
+	assert!(dispatcher
+		.acquire_concurrency_permit("slow_device")
+		.await
+		.is_some());
+
+	system.shutdown().await;
+}
+
+#[tokio::test]
+#[traced_test]
+async fn resource_profile_test() {
+	let system = TaskSystem::<SampleError>::new();
+
+	let dispatcher = system.get_dispatcher();
+	let workers_count = NonZeroUsize::new(system.workers_count()).unwrap();
+
+	// The max profile has no limit
+	assert!(dispatcher
+		.acquire_resource_profile_permit(ResourceProfile::Max)
+		.await
+		.is_none());
+
+	let (power_source_tx, power_source_rx) = async_channel::unbounded();
+	let hook = dispatcher.follow_power_source(power_source_rx);
+
+	power_source_tx.send(PowerSource::Battery).await.unwrap();
+	tokio::time::timeout(Duration::from_secs(1), async {
+		while dispatcher.resource_profile() != ResourceProfile::LowPower {
+			tokio::time::sleep(Duration::from_millis(10)).await;
+		}
+	})
+	.await
+	.unwrap();
+
+	// On battery, even tasks asking for the max profile share the low power budget
+	let low_power_slots = ResourceProfile::LowPower.max_running_tasks(workers_count);
+	let mut permits = Vec::with_capacity(low_power_slots.get());
+	for _ in 0..low_power_slots.get() {
+		permits.push(
+			dispatcher
+				.acquire_resource_profile_permit(ResourceProfile::Max)
+				.await
+				.unwrap(),
+		);
+	}
+
+	assert!(tokio::time::timeout(
+		Duration::from_millis(100),
+		dispatcher.acquire_resource_profile_permit(ResourceProfile::Balanced)
+	)
+	.await
+	.is_err());
+
+	power_source_tx.send(PowerSource::Ac).await.unwrap();
+	tokio::time::timeout(Duration::from_secs(1), async {
+		while dispatcher.resource_profile() != ResourceProfile::Max {
+			tokio::time::sleep(Duration::from_millis(10)).await;
+		}
+	})
+	.await
+	.unwrap();
+
+	assert!(dispatcher
+		.acquire_resource_profile_permit(ResourceProfile::Balanced)
+		.await
+		.is_some());
+
+	hook.abort();
+	system.shutdown().await;
+}
@@ -1,6 +1,6 @@
 import { useEffect, useRef, useState } from 'react';
 import { useDebouncedCallback } from 'use-debounce';
-import { Object as SDObject, useLibraryMutation } from '@sd/client';
+import { Object as SDObject, useLibraryMutation, useLibraryQuery } from '@sd/client';
 import { Divider, TextArea } from '@sd/ui';
 import { useLocale } from '~/hooks';
 
@@ -11,13 +11,22 @@ interface Props {
 }
 
 export default function Note(props: Props) {
-	const setNote = useLibraryMutation('files.setNote');
+	const note = useLibraryQuery(['notes.get', props.data.id]);
+	const setNote = useLibraryMutation('notes.set');
+
+	// Edits are written over the versions they were made from, so the ones made on other devices
+	// meanwhile get merged instead of replaced
+	const heads = useRef<number[][]>([]);
+	useEffect(() => {
+		if (note.data) heads.current = note.data.heads;
+	}, [note.data]);
 
 	const flush = useRef<() => void>();
-	const debouncedSetNote = useDebouncedCallback((note: string) => {
+	const debouncedSetNote = useDebouncedCallback((text: string) => {
 		setNote.mutate({
-			id: props.data.id,
-			note
+			object_id: props.data.id,
+			note: text,
+			parents: heads.current
 		});
 	}, 500);
 
@@ -28,6 +37,11 @@ export default function Note(props: Props) {
 	useEffect(() => () => flush.current?.(), []);
 
 	const [cachedNote, setCachedNote] = useState(props.data.note);
+
+	// Picks up edits merged from other devices, unless there's one of our own waiting to be saved
+	useEffect(() => {
+		if (note.data && !debouncedSetNote.isPending()) setCachedNote(note.data.note);
+	}, [note.data, debouncedSetNote]);
 	const { t } = useLocale();
 
 	return (
@@ -93,7 +93,8 @@ export const RenamableItemText = ({
 							generate_preview_media: null,
 							sync_preview_media: null,
 							hidden: null,
-							indexer_rules_ids: []
+							indexer_rules_ids: [],
+							identifier_rules: null
 						});
 
 						break;
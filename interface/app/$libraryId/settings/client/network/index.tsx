@@ -80,7 +80,7 @@ export const Component = () => {
 			ipv6_disabled: node.data?.p2p.disable_ipv6 || false,
 			relay_disabled: node.data?.p2p.disable_relay || false,
 			discovery: node.data?.p2p.discovery || 'Everyone',
-			enable_remote_access: node.data?.p2p.disable_relay || false,
+			enable_remote_access: node.data?.p2p.enable_remote_access || false,
 			p2p_manual_peers: node.data?.p2p.manual_peers || []
 		}
 	});
@@ -96,6 +96,7 @@ export const Component = () => {
 				p2p_discovery: value.discovery ?? null,
 				p2p_remote_access: value.enable_remote_access ?? null,
 				p2p_manual_peers: value.p2p_manual_peers?.flatMap((v) => (v ? [v] : [])) ?? null,
+				p2p_network_policy: null,
 				image_labeler_version: null
 			});
 		}
@@ -303,8 +304,8 @@ export const Component = () => {
 											{t('remote_access_description')}
 										</p>
 										<p className="text-sm text-yellow-500">
-											WARNING: This protocol has no security at the moment and
-											effectively gives root access!
+											WARNING: Nodes you share a library with get the same
+											access to this node as you have!
 										</p>
 									</>
 								}
@@ -3,7 +3,7 @@ import { useQueryClient } from '@tanstack/react-query';
 import { Suspense } from 'react';
 import { Controller } from 'react-hook-form';
 import { useNavigate } from 'react-router';
-import { useLibraryMutation, useLibraryQuery, useZodForm } from '@sd/client';
+import { IdentifierRule, useLibraryMutation, useLibraryQuery, useZodForm } from '@sd/client';
 import {
 	Button,
 	dialogManager,
@@ -14,6 +14,7 @@ import {
 	Label,
 	RadioGroupField,
 	SwitchField,
+	TextAreaField,
 	toast,
 	Tooltip,
 	tw,
@@ -37,9 +38,51 @@ const schema = z.object({
 	indexerRulesIds: z.array(z.number()),
 	locationType: z.string(),
 	syncPreviewMedia: z.boolean().nullable(),
-	generatePreviewMedia: z.boolean().nullable()
+	generatePreviewMedia: z.boolean().nullable(),
+	identifierMaxSizeMiB: z.string().regex(/^\d*$/),
+	identifierExtensions: z.string(),
+	// One per line, as globs can have commas
+	identifierGlobs: z.string()
 });
 
+const identifierRulesToForm = (rules: IdentifierRule[]) => {
+	const maxSizes = rules.flatMap((rule) => ('MaxSizeMiB' in rule ? [rule.MaxSizeMiB] : []));
+
+	return {
+		identifierMaxSizeMiB: maxSizes.length > 0 ? Math.min(...maxSizes).toString() : '',
+		identifierExtensions: rules
+			.flatMap((rule) => ('Extensions' in rule ? rule.Extensions : []))
+			.join(', '),
+		identifierGlobs: rules
+			.flatMap((rule) => ('Glob' in rule ? [rule.Glob] : []))
+			.join('\n')
+	};
+};
+
+const identifierRulesFromForm = (
+	data: Pick<
+		z.infer<typeof schema>,
+		'identifierMaxSizeMiB' | 'identifierExtensions' | 'identifierGlobs'
+	>
+): IdentifierRule[] => {
+	const extensions = data.identifierExtensions
+		.split(',')
+		.map((extension) => extension.trim())
+		.filter(Boolean);
+	const globs = data.identifierGlobs
+		.split('\n')
+		.map((glob) => glob.trim())
+		.filter(Boolean);
+
+	return [
+		...(data.identifierMaxSizeMiB !== ''
+			? [{ MaxSizeMiB: Number(data.identifierMaxSizeMiB) }]
+			: []),
+		...(extensions.length > 0 ? [{ Extensions: extensions }] : []),
+		...globs.map((glob) => ({ Glob: glob }))
+	];
+};
+
 export const Component = () => {
 	return (
 		<Suspense fallback={<div></div>}>
@@ -68,7 +111,8 @@ const EditLocationForm = () => {
 			path: locationData?.path ?? '',
 			hidden: locationData?.hidden ?? false,
 			syncPreviewMedia: locationData?.sync_preview_media ?? false,
-			generatePreviewMedia: locationData?.generate_preview_media ?? false
+			generatePreviewMedia: locationData?.generate_preview_media ?? false,
+			...identifierRulesToForm(locationData?.identifier_rules ?? [])
 		}
 	});
 
@@ -90,7 +134,8 @@ const EditLocationForm = () => {
 			hidden: data.hidden,
 			indexer_rules_ids: data.indexerRulesIds,
 			sync_preview_media: data.syncPreviewMedia,
-			generate_preview_media: data.generatePreviewMedia
+			generate_preview_media: data.generatePreviewMedia,
+			identifier_rules: identifierRulesFromForm(data)
 		})
 	);
 
@@ -186,6 +231,33 @@ const EditLocationForm = () => {
 					control={form.control}
 				/>
 				<Divider />
+				<div className="space-y-2">
+					<Label>{t('identifier_rules')}</Label>
+					<InfoText>{t('identifier_rules_info')}</InfoText>
+					<div className="flex space-x-4">
+						<FlexCol>
+							<InputField
+								label={t('identifier_rules_max_size')}
+								placeholder="51200"
+								{...form.register('identifierMaxSizeMiB')}
+							/>
+						</FlexCol>
+						<FlexCol>
+							<InputField
+								label={t('identifier_rules_extensions')}
+								placeholder="iso, vmdk"
+								{...form.register('identifierExtensions')}
+							/>
+						</FlexCol>
+					</div>
+					<TextAreaField
+						label={t('identifier_rules_globs')}
+						placeholder="backups/**"
+						className="!h-20 w-full"
+						{...form.register('identifierGlobs')}
+					/>
+				</div>
+				<Divider />
 				<div className="flex space-x-5">
 					<FlexCol>
 						<div>
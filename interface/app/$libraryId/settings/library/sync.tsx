@@ -102,7 +102,7 @@ function SyncBackfillDialog(props: UseDialogProps & { onEnabled: () => void }) {
 	useEffect(() => {
 		form.handleSubmit(
 			async () => {
-				await enableSync.mutateAsync(null).then(() => (dialog.state.open = false));
+				await enableSync.mutateAsync({ bytes_per_second: null }).then(() => (dialog.state.open = false));
 				await props.onEnabled();
 			},
 			() => {}
@@ -1,4 +1,4 @@
-import { initRspc, wsBatchLink, type AlphaClient } from '@oscartbeaumont-sd/rspc-client/v2';
+import { httpLink, initRspc, type AlphaClient } from '@oscartbeaumont-sd/rspc-client/v2';
 import { QueryClient, QueryClientProvider } from '@tanstack/react-query';
 import { useEffect, useMemo, useState } from 'react';
 import {
@@ -203,9 +203,11 @@ function RemoteLayout() {
 	useEffect(() => {
 		const endpoint = platform.getRemoteRspcEndpoint(params.node);
 
+		// Remote nodes only answer plain queries, so no websocket
 		const links = [
-			wsBatchLink({
-				url: endpoint.url
+			httpLink({
+				url: endpoint.url,
+				headers: endpoint.headers
 			})
 		];
 
@@ -296,8 +298,8 @@ function BrowsePage() {
 				<Button
 					key={l.uuid}
 					variant="accent"
-					// TODO: Take into account Windows vs Mac vs Linux with the default `path`
-					onClick={() => navigate(`../${l.uuid}/ephemeral/0-0?path=/System/Volumes/Data`)}
+					// Its locations are listed in the sidebar, files and search run on the remote node
+					onClick={() => navigate(`../${l.uuid}/overview`)}
 				>
 					{l.config.name}
 				</Button>
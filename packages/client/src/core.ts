@@ -10,51 +10,101 @@ export type Procedures = {
         { key: "cloud.library.get", input: LibraryArgs<null>, result: CloudLibrary | null } | 
         { key: "cloud.library.list", input: never, result: CloudLibrary[] } | 
         { key: "cloud.locations.list", input: never, result: CloudLocation[] } | 
+        { key: "collections.get", input: LibraryArgs<number>, result: Collection | null } | 
+        { key: "collections.list", input: LibraryArgs<null>, result: Collection[] } | 
+        { key: "collections.objects", input: LibraryArgs<number>, result: ExplorerItem[] } | 
+        { key: "customFields.getForObject", input: LibraryArgs<number>, result: ObjectFieldValue[] } | 
+        { key: "customFields.list", input: LibraryArgs<null>, result: CustomField[] } | 
         { key: "ephemeralFiles.getMediaData", input: string, result: MediaData | null } | 
+        { key: "ephemeralFiles.identify", input: LibraryArgs<string[]>, result: EphemeralIdentification } | 
+        { key: "files.bulkRenamePreview", input: LibraryArgs<BulkRenameArgs>, result: RenameMapping[] } | 
+        { key: "files.eraseCaveats", input: LibraryArgs<number>, result: EraseCaveat[] } | 
         { key: "files.get", input: LibraryArgs<number>, result: ObjectWithFilePaths2 | null } | 
         { key: "files.getConvertibleImageExtensions", input: never, result: string[] } | 
         { key: "files.getMediaData", input: LibraryArgs<number>, result: MediaData } | 
+        { key: "files.getMediaTracks", input: LibraryArgs<number>, result: MediaTracks } | 
         { key: "files.getPath", input: LibraryArgs<number>, result: string | null } | 
+        { key: "files.getTranscript", input: LibraryArgs<number>, result: Transcript | null } | 
         { key: "invalidation.test-invalidate", input: never, result: number } | 
+        { key: "jobs.chainProgress", input: LibraryArgs<string>, result: ChainProgress | null } | 
+        { key: "jobs.history", input: LibraryArgs<JobHistoryArgs>, result: JobHistoryPage } | 
         { key: "jobs.isActive", input: LibraryArgs<null>, result: boolean } | 
         { key: "jobs.reports", input: LibraryArgs<null>, result: JobGroup[] } | 
+        { key: "jobs.trace", input: LibraryArgs<string>, result: ChromeTrace } | 
+        { key: "keys.list", input: LibraryArgs<null>, result: KeyItem[] } | 
         { key: "labels.count", input: LibraryArgs<null>, result: number } | 
         { key: "labels.get", input: LibraryArgs<number>, result: Label | null } | 
         { key: "labels.getForObject", input: LibraryArgs<number>, result: Label[] } | 
         { key: "labels.getWithObjects", input: LibraryArgs<number[]>, result: { [key in number]: { date_created: string; object: { id: number } }[] } } | 
         { key: "labels.list", input: LibraryArgs<null>, result: Label[] } | 
         { key: "labels.listWithThumbnails", input: LibraryArgs<string>, result: ExplorerItem[] } | 
+        { key: "library.encryptionStatus", input: LibraryArgs<null>, result: EncryptionStatus } | 
         { key: "library.kindStatistics", input: LibraryArgs<null>, result: KindStatistics } | 
         { key: "library.list", input: never, result: LibraryConfigWrapped[] } | 
+        { key: "library.locked", input: never, result: string[] } | 
         { key: "library.statistics", input: LibraryArgs<null>, result: StatisticsResponse } | 
+        { key: "library.thumbnailCacheUsage", input: LibraryArgs<null>, result: ThumbnailCacheUsage } | 
+        { key: "locations.backupPreview", input: LibraryArgs<BackupPreviewArgs>, result: BackupDiff } | 
+        { key: "locations.capacityHistory", input: LibraryArgs<CapacityHistoryArgs>, result: LocationCapacity[] } | 
+        { key: "locations.coldArchive.policy", input: LibraryArgs<number>, result: ArchivePolicy | null } | 
+        { key: "locations.coldArchive.stubs", input: LibraryArgs<number>, result: ArchiveStub[] } | 
+        { key: "locations.diskUsage", input: LibraryArgs<DiskUsageArgs>, result: UsageNode | null } | 
+        { key: "locations.folderSync.conflicts", input: LibraryArgs<number>, result: FolderSyncConflict[] } | 
+        { key: "locations.folderSync.list", input: LibraryArgs<null>, result: FolderSyncPair[] } | 
+        { key: "locations.folderSync.preview", input: LibraryArgs<number>, result: FolderSyncChange[] } | 
         { key: "locations.get", input: LibraryArgs<number>, result: Location | null } | 
         { key: "locations.getWithRules", input: LibraryArgs<number>, result: LocationWithIndexerRule | null } | 
+        { key: "locations.identificationStatistics", input: LibraryArgs<number>, result: IdentificationStatistics } | 
         { key: "locations.indexer_rules.get", input: LibraryArgs<number>, result: IndexerRule } | 
         { key: "locations.indexer_rules.list", input: LibraryArgs<null>, result: IndexerRule[] } | 
         { key: "locations.indexer_rules.listForLocation", input: LibraryArgs<number>, result: IndexerRule[] } | 
         { key: "locations.list", input: LibraryArgs<null>, result: Location[] } | 
+        { key: "locations.listFailures", input: LibraryArgs<ListFailuresArgs>, result: JobErrorPage } | 
+        { key: "locations.mtpDevices", input: never, result: MtpDevice[] } | 
+        { key: "locations.schedules.list", input: LibraryArgs<number>, result: JobSchedule[] } | 
         { key: "locations.systemLocations", input: never, result: SystemLocations } | 
+        { key: "locations.versions.list", input: LibraryArgs<ListVersionsArgs>, result: FileVersion[] } | 
+        { key: "locations.versions.policy", input: LibraryArgs<number>, result: VersioningPolicy | null } | 
         { key: "models.image_detection.list", input: never, result: string[] } | 
         { key: "nodeState", input: never, result: NodeState } | 
         { key: "nodes.listLocations", input: LibraryArgs<string | null>, result: ExplorerItem[] } | 
+        { key: "notes.get", input: LibraryArgs<number>, result: ObjectNote } | 
+        { key: "notes.history", input: LibraryArgs<number>, result: NoteHistoryEntry[] } | 
         { key: "notifications.dismiss", input: NotificationId, result: null } | 
         { key: "notifications.dismissAll", input: never, result: null } | 
         { key: "notifications.get", input: never, result: Notification[] } | 
         { key: "p2p.listeners", input: never, result: Listeners } | 
+        { key: "p2p.reachability", input: RemoteIdentity, result: Reachability } | 
+        { key: "p2p.spacedropHistory", input: never, result: SpacedropTransfer[] } | 
         { key: "p2p.state", input: never, result: JsonValue } | 
+        { key: "people.getFaces", input: LibraryArgs<number>, result: FaceForFrontend[] } | 
+        { key: "people.getForObject", input: LibraryArgs<number>, result: FaceForFrontend[] } | 
+        { key: "people.list", input: LibraryArgs<null>, result: PersonItem[] } | 
         { key: "preferences.get", input: LibraryArgs<null>, result: LibraryPreferences } | 
+        { key: "search.allLibraries", input: AllLibrariesSearchArgs, result: LibrarySearchItem[] } | 
+        { key: "search.duplicates", input: LibraryArgs<DuplicatesArgs>, result: DuplicatesData } | 
         { key: "search.objects", input: LibraryArgs<ObjectSearchArgs>, result: SearchData<ExplorerItem> } | 
         { key: "search.objectsCount", input: LibraryArgs<{ filters?: SearchFilterArgs[] }>, result: number } | 
         { key: "search.paths", input: LibraryArgs<FilePathSearchArgs>, result: SearchData<ExplorerItem> } | 
         { key: "search.pathsCount", input: LibraryArgs<{ filters?: SearchFilterArgs[] }>, result: number } | 
+        { key: "search.places", input: LibraryArgs<null>, result: PlaceCount[] } | 
+        { key: "search.query", input: LibraryArgs<QueryArgs>, result: QueryData } | 
         { key: "search.saved.get", input: LibraryArgs<number>, result: SavedSearch | null } | 
         { key: "search.saved.list", input: LibraryArgs<null>, result: SavedSearch[] } | 
+        { key: "search.semantic", input: LibraryArgs<SemanticSearchArgs>, result: SemanticSearchItem[] } | 
+        { key: "search.similar", input: LibraryArgs<SimilarArgs>, result: SimilarGroup[] } | 
+        { key: "search.similarContent", input: LibraryArgs<SimilarContentArgs>, result: SimilarGroup[] } | 
+        { key: "search.text", input: LibraryArgs<TextSearchArgs>, result: TextSearchItem[] } | 
+        { key: "sync.conflicts", input: LibraryArgs<null>, result: SyncConflict[] } | 
         { key: "sync.enabled", input: LibraryArgs<null>, result: boolean } | 
         { key: "sync.messages", input: LibraryArgs<null>, result: CRDTOperation[] } | 
+        { key: "sync.relay", input: LibraryArgs<null>, result: RelayStorage | null } | 
         { key: "tags.get", input: LibraryArgs<number>, result: Tag | null } | 
         { key: "tags.getForObject", input: LibraryArgs<number>, result: Tag[] } | 
         { key: "tags.getWithObjects", input: LibraryArgs<number[]>, result: { [key in number]: ({ object: { id: number }; date_created: string | null })[] } } | 
         { key: "tags.list", input: LibraryArgs<null>, result: Tag[] } | 
+        { key: "tags.rules.list", input: LibraryArgs<null>, result: TagRule[] } | 
+        { key: "volumes.health", input: never, result: DeviceHealth[] } | 
         { key: "volumes.list", input: never, result: Volume[] },
     mutations: 
         { key: "api.sendFeedback", input: Feedback, result: null } | 
@@ -69,6 +119,15 @@ export type Procedures = {
         { key: "cloud.locations.remove", input: string, result: CloudLocation } | 
         { key: "cloud.locations.testing", input: TestingParams, result: null } | 
         { key: "cloud.setApiOrigin", input: string, result: null } | 
+        { key: "collections.addObjects", input: LibraryArgs<CollectionObjectsArgs>, result: null } | 
+        { key: "collections.create", input: LibraryArgs<CollectionCreateArgs>, result: Collection } | 
+        { key: "collections.delete", input: LibraryArgs<number>, result: null } | 
+        { key: "collections.removeObjects", input: LibraryArgs<CollectionObjectsArgs>, result: null } | 
+        { key: "collections.update", input: LibraryArgs<[number, CollectionUpdateArgs]>, result: null } | 
+        { key: "customFields.create", input: LibraryArgs<CustomFieldCreateArgs>, result: CustomField } | 
+        { key: "customFields.delete", input: LibraryArgs<number>, result: null } | 
+        { key: "customFields.set", input: LibraryArgs<CustomFieldSetArgs>, result: null } | 
+        { key: "customFields.update", input: LibraryArgs<CustomFieldUpdateArgs>, result: null } | 
         { key: "ephemeralFiles.copyFiles", input: LibraryArgs<EphemeralFileSystemOps>, result: null } | 
         { key: "ephemeralFiles.createFile", input: LibraryArgs<CreateEphemeralFileArgs>, result: string } | 
         { key: "ephemeralFiles.createFolder", input: LibraryArgs<CreateEphemeralFolderArgs>, result: string } | 
@@ -76,63 +135,128 @@ export type Procedures = {
         { key: "ephemeralFiles.deleteFiles", input: LibraryArgs<string[]>, result: null } | 
         { key: "ephemeralFiles.moveToTrash", input: LibraryArgs<string[]>, result: null } | 
         { key: "ephemeralFiles.renameFile", input: LibraryArgs<EphemeralRenameFileArgs>, result: null } | 
+        { key: "files.bulkRename", input: LibraryArgs<BulkRenameArgs>, result: null } | 
+        { key: "files.compress", input: LibraryArgs<CompressArgs>, result: null } | 
         { key: "files.convertImage", input: LibraryArgs<ConvertImageArgs>, result: null } | 
-        { key: "files.copyFiles", input: LibraryArgs<OldFileCopierJobInit>, result: null } | 
+        { key: "files.copyFiles", input: LibraryArgs<TransferFilesArgs>, result: null } | 
         { key: "files.createFile", input: LibraryArgs<CreateFileArgs>, result: string } | 
         { key: "files.createFolder", input: LibraryArgs<CreateFolderArgs>, result: string } | 
-        { key: "files.cutFiles", input: LibraryArgs<OldFileCutterJobInit>, result: null } | 
+        { key: "files.cutFiles", input: LibraryArgs<TransferFilesArgs>, result: null } | 
+        { key: "files.decryptFiles", input: LibraryArgs<DecryptFilesArgs>, result: null } | 
         { key: "files.deleteFiles", input: LibraryArgs<OldFileDeleterJobInit>, result: null } | 
+        { key: "files.encryptFiles", input: LibraryArgs<EncryptFilesArgs>, result: null } | 
         { key: "files.eraseFiles", input: LibraryArgs<OldFileEraserJobInit>, result: null } | 
+        { key: "files.exportChecksums", input: LibraryArgs<ExportChecksumsArgs>, result: null } | 
+        { key: "files.extract", input: LibraryArgs<ExtractArgs>, result: null } | 
+        { key: "files.hydrateFiles", input: LibraryArgs<OldFileHydratorJobInit>, result: null } | 
         { key: "files.moveToTrash", input: LibraryArgs<OldFileDeleterJobInit>, result: null } | 
+        { key: "files.provideArchivePassword", input: LibraryArgs<ProvideArchivePasswordArgs>, result: null } | 
         { key: "files.removeAccessTime", input: LibraryArgs<number[]>, result: null } | 
         { key: "files.renameFile", input: LibraryArgs<RenameFileArgs>, result: null } | 
+        { key: "files.requestThumbnails", input: LibraryArgs<string[]>, result: null } | 
+        { key: "files.restoreFromTrash", input: LibraryArgs<number>, result: null } | 
         { key: "files.setFavorite", input: LibraryArgs<SetFavoriteArgs>, result: null } | 
         { key: "files.setNote", input: LibraryArgs<SetNoteArgs>, result: null } | 
         { key: "files.updateAccessTime", input: LibraryArgs<number[]>, result: null } | 
+        { key: "files.verifyChecksums", input: LibraryArgs<VerifyChecksumsArgs>, result: null } | 
         { key: "invalidation.test-invalidate-mutation", input: LibraryArgs<null>, result: null } | 
         { key: "jobs.cancel", input: LibraryArgs<string>, result: null } | 
+        { key: "jobs.cleanupLocation", input: LibraryArgs<OldCleanupJobInit>, result: null } | 
         { key: "jobs.clear", input: LibraryArgs<string>, result: null } | 
         { key: "jobs.clearAll", input: LibraryArgs<null>, result: null } | 
+        { key: "jobs.embedForLocation", input: LibraryArgs<EmbedForLocationArgs>, result: null } | 
+        { key: "jobs.extractTextForLocation", input: LibraryArgs<ExtractTextForLocationArgs>, result: null } | 
         { key: "jobs.generateLabelsForLocation", input: LibraryArgs<GenerateLabelsForLocationArgs>, result: null } | 
         { key: "jobs.generateThumbsForLocation", input: LibraryArgs<GenerateThumbsForLocationArgs>, result: null } | 
-        { key: "jobs.identifyUniqueFiles", input: LibraryArgs<IdentifyUniqueFilesArgs>, result: null } | 
+        { key: "jobs.identifyUniqueFiles", input: LibraryArgs<IdentifyUniqueFilesArgs>, result: JobReport | null } | 
+        { key: "jobs.importOsTags", input: ImportOsTagsArgs, result: null } | 
         { key: "jobs.objectValidator", input: LibraryArgs<ObjectValidatorArgs>, result: null } | 
         { key: "jobs.pause", input: LibraryArgs<string>, result: null } | 
+        { key: "jobs.reidentifyKinds", input: LibraryArgs<ReidentifyKindsArgs>, result: null } | 
+        { key: "jobs.removeOrphanObjects", input: LibraryArgs<OldOrphanRemoverJobInit>, result: null } | 
         { key: "jobs.resume", input: LibraryArgs<string>, result: null } | 
+        { key: "jobs.runChain", input: LibraryArgs<RunChainArgs>, result: string } | 
+        { key: "jobs.transcribeForLocation", input: LibraryArgs<TranscribeForLocationArgs>, result: null } | 
+        { key: "keys.create", input: LibraryArgs<CreateKeyArgs>, result: string } | 
+        { key: "keys.delete", input: LibraryArgs<string>, result: null } | 
+        { key: "keys.mount", input: LibraryArgs<MountKeyArgs>, result: null } | 
+        { key: "keys.unmount", input: LibraryArgs<string>, result: null } | 
         { key: "labels.delete", input: LibraryArgs<number>, result: null } | 
+        { key: "library.cleanUpThumbnailCache", input: LibraryArgs<null>, result: ThumbnailCacheEviction } | 
         { key: "library.create", input: CreateLibraryArgs, result: LibraryConfigWrapped } | 
         { key: "library.delete", input: string, result: null } | 
         { key: "library.edit", input: EditLibraryArgs, result: null } | 
+        { key: "library.encrypt", input: LibraryArgs<string>, result: null } | 
+        { key: "library.export", input: LibraryArgs<string>, result: null } | 
+        { key: "library.import", input: string, result: ArchiveManifest } | 
         { key: "library.startActor", input: LibraryArgs<string>, result: null } | 
         { key: "library.stopActor", input: LibraryArgs<string>, result: null } | 
+        { key: "library.unlock", input: UnlockLibraryArgs, result: LibraryConfigWrapped } | 
         { key: "library.vaccumDb", input: LibraryArgs<null>, result: null } | 
         { key: "locations.addLibrary", input: LibraryArgs<LocationCreateArgs>, result: number | null } | 
+        { key: "locations.analyzeDiskUsage", input: LibraryArgs<AnalyzeDiskUsageArgs>, result: null } | 
+        { key: "locations.backup", input: LibraryArgs<BackupArgs>, result: null } | 
+        { key: "locations.coldArchive.deletePolicy", input: LibraryArgs<number>, result: null } | 
+        { key: "locations.coldArchive.run", input: LibraryArgs<number>, result: null } | 
+        { key: "locations.coldArchive.setPolicy", input: LibraryArgs<SetArchivePolicyArgs>, result: ArchivePolicy } | 
         { key: "locations.create", input: LibraryArgs<LocationCreateArgs>, result: number | null } | 
         { key: "locations.delete", input: LibraryArgs<number>, result: null } | 
+        { key: "locations.folderSync.create", input: LibraryArgs<CreateFolderSyncArgs>, result: FolderSyncPair } | 
+        { key: "locations.folderSync.delete", input: LibraryArgs<number>, result: null } | 
+        { key: "locations.folderSync.resolve", input: LibraryArgs<ResolveFolderSyncConflictArgs>, result: null } | 
+        { key: "locations.folderSync.run", input: LibraryArgs<number>, result: null } | 
         { key: "locations.fullRescan", input: LibraryArgs<FullRescanArgs>, result: null } | 
         { key: "locations.indexer_rules.create", input: LibraryArgs<IndexerRuleCreateArgs>, result: null } | 
         { key: "locations.indexer_rules.delete", input: LibraryArgs<number>, result: null } | 
+        { key: "locations.recomputeIdentificationStatistics", input: LibraryArgs<number>, result: null } | 
+        { key: "locations.reconcileSnapshot", input: LibraryArgs<number>, result: null } | 
         { key: "locations.relink", input: LibraryArgs<string>, result: number } | 
+        { key: "locations.retryFailures", input: LibraryArgs<number>, result: null } | 
+        { key: "locations.schedules.create", input: LibraryArgs<CreateScheduleArgs>, result: JobSchedule } | 
+        { key: "locations.schedules.delete", input: LibraryArgs<number>, result: null } | 
+        { key: "locations.schedules.setEnabled", input: LibraryArgs<SetScheduleEnabledArgs>, result: null } | 
+        { key: "locations.setSyncPolicy", input: LibraryArgs<SetSyncPolicyArgs>, result: null } | 
         { key: "locations.subPathRescan", input: LibraryArgs<RescanArgs>, result: null } | 
         { key: "locations.update", input: LibraryArgs<LocationUpdateArgs>, result: null } | 
+        { key: "locations.verifyIntegrity", input: LibraryArgs<VerifyIntegrityArgs>, result: null } | 
+        { key: "locations.versions.restore", input: LibraryArgs<number>, result: string } | 
+        { key: "locations.versions.setPolicy", input: LibraryArgs<SetVersioningPolicyArgs>, result: null } | 
         { key: "nodes.edit", input: ChangeNodeNameArgs, result: null } | 
+        { key: "nodes.updateBandwidthLimits", input: BandwidthLimit[], result: null } | 
+        { key: "nodes.updateIoThrottleLimits", input: IoThrottleLimit[], result: null } | 
+        { key: "nodes.updateResourceProfile", input: UpdateResourceProfileArgs, result: null } | 
+        { key: "nodes.updateThumbnailCacheBudget", input: number | null, result: null } | 
         { key: "nodes.updateThumbnailerPreferences", input: UpdateThumbnailerPreferences, result: null } | 
+        { key: "notes.set", input: LibraryArgs<NoteSetArgs>, result: string | null } | 
         { key: "p2p.acceptSpacedrop", input: [string, string | null], result: null } | 
         { key: "p2p.cancelSpacedrop", input: string, result: null } | 
+        { key: "p2p.clearSpacedropHistory", input: never, result: null } | 
         { key: "p2p.debugConnect", input: RemoteIdentity, result: string } | 
         { key: "p2p.spacedrop", input: SpacedropArgs, result: string } | 
+        { key: "people.merge", input: LibraryArgs<MergePeopleArgs>, result: null } | 
+        { key: "people.rename", input: LibraryArgs<RenamePersonArgs>, result: null } | 
+        { key: "people.split", input: LibraryArgs<SplitPersonArgs>, result: number } | 
         { key: "preferences.update", input: LibraryArgs<LibraryPreferences>, result: null } | 
+        { key: "search.findDuplicates", input: LibraryArgs<null>, result: JobReport | null } | 
         { key: "search.saved.create", input: LibraryArgs<{ name: string; target?: SearchTarget; search?: string | null; filters?: string | null; description?: string | null; icon?: string | null }>, result: null } | 
         { key: "search.saved.delete", input: LibraryArgs<number>, result: null } | 
         { key: "search.saved.update", input: LibraryArgs<[number, Args]>, result: null } | 
-        { key: "sync.backfill", input: LibraryArgs<null>, result: null } | 
+        { key: "sync.backfill", input: LibraryArgs<OldSyncBackfillJobInit>, result: null } | 
+        { key: "sync.removeRelay", input: LibraryArgs<null>, result: null } | 
+        { key: "sync.resolveConflict", input: LibraryArgs<ResolveConflictArgs>, result: null } | 
+        { key: "sync.setRelay", input: LibraryArgs<SetRelayArgs>, result: null } | 
         { key: "tags.assign", input: LibraryArgs<{ targets: Target[]; tag_id: number; unassign: boolean }>, result: null } | 
         { key: "tags.create", input: LibraryArgs<TagCreateArgs>, result: Tag } | 
         { key: "tags.delete", input: LibraryArgs<number>, result: null } | 
+        { key: "tags.rules.create", input: LibraryArgs<TagRuleCreateArgs>, result: TagRule } | 
+        { key: "tags.rules.delete", input: LibraryArgs<number>, result: null } | 
+        { key: "tags.rules.update", input: LibraryArgs<TagRuleUpdateArgs>, result: null } | 
+        { key: "tags.setParent", input: LibraryArgs<TagSetParentArgs>, result: null } | 
         { key: "tags.update", input: LibraryArgs<TagUpdateArgs>, result: null } | 
         { key: "toggleFeatureFlag", input: BackendFeature, result: null },
     subscriptions: 
         { key: "auth.loginSession", input: never, result: Response } | 
+        { key: "collections.membership", input: LibraryArgs<number>, result: MembershipUpdate } | 
         { key: "invalidation.listen", input: never, result: InvalidateOperationEvent[] } | 
         { key: "jobs.newFilePathIdentified", input: LibraryArgs<null>, result: number[] } | 
         { key: "jobs.newThumbnail", input: LibraryArgs<null>, result: string[] } | 
@@ -143,10 +267,67 @@ export type Procedures = {
         { key: "notifications.listen", input: never, result: Notification } | 
         { key: "p2p.events", input: never, result: P2PEvent } | 
         { key: "search.ephemeralPaths", input: LibraryArgs<EphemeralPathSearchArgs>, result: EphemeralPathsResultItem } | 
+        { key: "search.saved.live", input: LibraryArgs<number>, result: LiveSearchUpdate } | 
         { key: "sync.active", input: LibraryArgs<null>, result: SyncStatus } | 
-        { key: "sync.newMessage", input: LibraryArgs<null>, result: null }
+        { key: "sync.newMessage", input: LibraryArgs<null>, result: null } | 
+        { key: "volumes.healthAlerts", input: never, result: DeviceHealthAlert }
 };
 
+export type AllLibrariesSearchArgs = { 
+/**
+ * Written in the search query language, like `search.query`
+ */
+query: string; take?: number | null }
+
+export type AnalyzeDiskUsageArgs = { location_id: number; 
+/**
+ * Relative to the root of the location, the whole location if `None`
+ */
+sub_path?: string | null }
+
+export type ArchiveManifest = { library_id: string; library_name: string; created_at: string; 
+/**
+ * Version of Spacedrive the archive was exported with
+ */
+core_version: string; 
+/**
+ * Latest migration applied to the database in the archive. Databases migrated further than
+ * [`LATEST_MIGRATION`] were exported by a newer version and can't be opened by this one
+ */
+migration: string | null }
+
+/**
+ * Moves the cold files of a location into its cold location each time the cold archiver runs
+ */
+export type ArchivePolicy = { location_id: number; cold_location_id: number; rules: ArchiveRules; date_last_run: string | null }
+
+/**
+ * Which files of a location went cold, every rule set having to match
+ */
+export type ArchiveRules = { 
+/**
+ * Files whose object wasn't opened in this many months, nor the file modified, every file if
+ * `None`
+ */
+not_accessed_months?: number | null; 
+/**
+ * Files of these kinds only, every kind if `None`
+ */
+kinds?: ObjectKind[] | null }
+
+/**
+ * Where an archived file was, found in its original location while it's stored on another one
+ */
+export type ArchiveStub = { id: number; file_path_id: number; object_id: number | null; 
+/**
+ * Relative to the root of the original location
+ */
+path: string; 
+/**
+ * The location the file is stored on now
+ */
+stored_on_location_id: number | null; stored_on: string | null; date_archived: string }
+
 export type Args = { search?: string | null; filters?: string | null; name?: string | null; icon?: string | null; description?: string | null }
 
 export type AudioProps = { delay: number; padding: number; sample_rate: number | null; sample_format: string | null; bit_per_sample: number | null; channel_layout: string | null }
@@ -160,26 +341,205 @@ export type BackendFeature = "cloudSync"
 
 export type Backup = ({ id: string; timestamp: string; library_id: string; library_name: string }) & { path: string }
 
+export type BackupArgs = { source_location_id: number; target_location_id: number; 
+/**
+ * The whole source location if `None`
+ */
+file_path_ids?: number[] | null; deletion_policy?: DeletionPolicy }
+
+export type BackupChange = 
+/**
+ * Not in the target yet
+ */
+"Added" | 
+/**
+ * In the target with different contents
+ */
+"Modified" | 
+/**
+ * Only in the target, deleted with [`DeletionPolicy::Mirror`]
+ */
+"Deleted"
+
+/**
+ * What a backup would do, without doing it
+ */
+export type BackupDiff = { entries: BackupDiffEntry[]; 
+/**
+ * Files whose `cas_id` matches the target's, so they're skipped
+ */
+unchanged_files: number; bytes_to_copy: string }
+
+export type BackupDiffEntry = { 
+/**
+ * Relative to the root of the locations
+ */
+path: string; is_dir: boolean; change: BackupChange; 
+/**
+ * As a string as it may not fit in a JS number, zero for directories
+ */
+size_in_bytes: string }
+
+export type BackupPreviewArgs = { source_location_id: number; target_location_id: number; 
+/**
+ * The whole source location if `None`
+ */
+file_path_ids?: number[] | null; deletion_policy?: DeletionPolicy }
+
+export type BandwidthLimit = { 
+/**
+ * Every protocol if empty
+ */
+protocols: BandwidthProtocol[]; bytes_per_second: number; 
+/**
+ * Always applies if `None`
+ */
+schedule: BandwidthSchedule | null }
+
+export type BandwidthPreferences = { limits: BandwidthLimit[] }
+
+export type BandwidthProtocol = "Spacedrop" | "SyncBackfill" | "CloudHydration"
+
+/**
+ * The hours of the day a limit applies at, in the node's local time. An `end_hour` before the
+ * `start_hour` wraps past midnight and equal hours cover the whole day.
+ */
+export type BandwidthSchedule = { start_hour: number; end_hour: number; 
+/**
+ * Leaves Saturdays and Sundays unlimited
+ */
+weekdays_only?: boolean }
+
+/**
+ * Bounds for the amount of orphans identified by each task, see [`AdaptiveBatchSize`]
+ */
+export type BatchSizeBounds = { min: number; max: number }
+
 export type BuildInfo = { version: string; commit: string }
 
+/**
+ * File paths of a location renamed following a pattern, previewed before renaming them
+ */
+export type BulkRenameArgs = { locationId: number; filePathIds: number[]; pattern: RenamePattern }
+
 export type CRDTOperation = { instance: string; timestamp: number; model: number; record_id: JsonValue; data: CRDTOperationData }
 
 export type CRDTOperationData = { c: { [key in string]: JsonValue } } | { u: { field: string; value: JsonValue } } | "d"
 
 export type CameraData = { device_make: string | null; device_model: string | null; color_space: string | null; color_profile: ColorProfile | null; focal_length: number | null; shutter_speed: number | null; flash: Flash | null; orientation: Orientation; lens_make: string | null; lens_model: string | null; bit_depth: number | null; zoom: number | null; iso: number | null; software: string | null; serial_number: string | null; lens_serial_number: string | null; contrast: number | null; saturation: number | null; sharpness: number | null; composite: Composite | null }
 
-export type ChangeNodeNameArgs = { name: string | null; p2p_port: Port | null; p2p_disabled: boolean | null; p2p_ipv6_disabled: boolean | null; p2p_relay_disabled: boolean | null; p2p_discovery: P2PDiscoveryState | null; p2p_remote_access: boolean | null; p2p_manual_peers: string[] | null; image_labeler_version: string | null }
+export type CapacityHistoryArgs = { locationId: number; since?: string | null }
+
+/**
+ * Hashing scheme used to generate `cas_id`s, stored per library.
+ * 
+ * `Blake3Sampled` is Spacedrive's historical scheme, it only hashes some samples of large files and
+ * truncates the result. The other variants hash the entire file content and keep the whole digest,
+ * so they match the output of external tools like `b3sum` and `sha256sum`.
+ */
+export type CasIdAlgorithm = "Blake3Sampled" | "Blake3Full" | "Sha256"
+
+/**
+ * What to do with the occurrences missed while the node was offline
+ */
+export type CatchUp = 
+/**
+ * Run once as soon as possible, no matter how many occurrences were missed
+ */
+"RunOnce" | 
+/**
+ * Wait for the next occurrence
+ */
+"Skip"
+
+/**
+ * How far a running chain is, by stages as jobs don't share a common progress unit
+ */
+export type ChainProgress = { chain_id: string; current_stage: JobName; current_stage_task_count: number; current_stage_completed_task_count: number; completed_stages: number; total_stages: number; remaining_stages: JobName[] }
+
+/**
+ * A job run as a stage of a chain, on the location and sub path of the chain
+ */
+export type ChainStage = "Index" | "Identify" | "ProcessMedia" | { Script: ScriptCommand }
+
+export type ChangeNodeNameArgs = { name: string | null; p2p_port: Port | null; p2p_disabled: boolean | null; p2p_ipv6_disabled: boolean | null; p2p_relay_disabled: boolean | null; p2p_discovery: P2PDiscoveryState | null; p2p_remote_access: boolean | null; p2p_manual_peers: string[] | null; p2p_network_policy: NetworkPolicy | null; image_labeler_version: string | null }
 
 export type Chapter = { id: number; start: [number, number]; end: [number, number]; time_base_den: number; time_base_num: number; metadata: Metadata }
 
+export type ChapterMark = { id: number; title: string | null; start_secs: number; end_secs: number }
+
+/**
+ * Standard formats of checksum manifests, as read and written by common tools
+ */
+export type ChecksumFormat = 
+/**
+ * SHA-256 digests as written by `sha256sum`, in a file named `SHA256SUMS`
+ */
+"Sha256Sums" | 
+/**
+ * CRC32 checksums in a Simple File Verification `.sfv` file, weaker but still common for
+ * archived collections
+ */
+"Sfv"
+
+/**
+ * A job trace in the Chrome trace event format, which can be opened in `chrome://tracing`,
+ * Perfetto or speedscope to get a flamegraph
+ */
+export type ChromeTrace = { traceEvents: ChromeTraceEvent[]; displayTimeUnit: string }
+
+/**
+ * A complete event (`"ph": "X"`), one for each span of the job. Timestamps are in microseconds and
+ * each task gets its own `tid`, so its spans are nested under it.
+ */
+export type ChromeTraceEvent = { name: string; cat: string; ph: string; ts: number; dur: number; pid: number; tid: number; args: { [key in string]: string } }
+
+export type CleanupMode = 
+/**
+ * Only lists leftovers in the job report
+ */
+"Report" | 
+/**
+ * Moves leftovers to the platform trash, from where `files.restoreFromTrash` brings them back
+ */
+"Trash" | 
+/**
+ * Unlinks leftovers, for good, and removes them from the library
+ */
+"Delete"
+
 export type CloudInstance = { id: string; uuid: string; identity: RemoteIdentity; nodeId: string; nodeRemoteIdentity: string; metadata: { [key in string]: string } }
 
 export type CloudLibrary = { id: string; uuid: string; name: string; instances: CloudInstance[]; ownerId: string }
 
 export type CloudLocation = { id: string; name: string }
 
+/**
+ * Drive or folder of a cloud metadata location, stored msgpack encoded on
+ * `location.cloud_config`, which is local only as it holds the credentials.
+ */
+export type CloudMetadataLocationConfig = { provider: CloudProvider; 
+/**
+ * Id of the folder to add as a location, the whole drive if missing
+ */
+folder_id: string | null; credentials: OAuthCredentials }
+
+export type CloudProvider = "GoogleDrive" | "OneDrive"
+
 export type Codec = { kind: string | null; sub_kind: string | null; tag: string | null; name: string | null; profile: string | null; bit_rate: number; props: Props | null }
 
+export type Collection = { id: number; pub_id: number[]; name: string | null; description: string | null; icon: string | null; query: string | null; date_created: string | null; date_modified: string | null }
+
+export type CollectionCreateArgs = { name: string; description?: string | null; icon?: string | null; 
+/**
+ * Makes the collection rule-based, with the objects matched by this query
+ */
+query?: string | null }
+
+export type CollectionObjectsArgs = { id: number; object_ids: number[] }
+
+export type CollectionUpdateArgs = { name?: string | null; description?: string | null; icon?: string | null; query?: string | null }
+
 export type ColorProfile = "Normal" | "Custom" | "HDRNoOriginal" | "HDRWithOriginal" | "OriginalForHDR" | "Panorama" | "PortraitHDR" | "Portrait"
 
 export type Composite = 
@@ -200,6 +560,86 @@ export type Composite =
  */
 "Live"
 
+export type CompressArgs = { location_id: number; file_path_ids: number[]; target_location_relative_directory_path: string; name: string; format?: CompressionFormat }
+
+/**
+ * Formats archives are created in
+ */
+export type CompressionFormat = "Zip" | "TarZstd"
+
+/**
+ * At most `limit` tasks of jobs on any of these locations run at the same time, so reads on a
+ * spinning or network drive don't interleave with the ones on faster storage
+ */
+export type ConcurrencyLimit = { location_ids: number[]; limit: number }
+
+/**
+ * What a file must be for a tag rule to assign its tag to the file's object. A rule only applies
+ * when all of its conditions match.
+ * 
+ * Stored msgpack encoded in `tag_rule.conditions`.
+ */
+export type Condition = 
+/**
+ * Any of these kinds
+ */
+{ Kind: ObjectKind[] } | 
+/**
+ * Any of these extensions, without the leading dot, case insensitive
+ */
+{ Extensions: string[] } | 
+/**
+ * Case insensitive, matched against the path of the file relative to its location root, e.g.
+ * `/Screenshots/`
+ */
+{ PathContains: string } | 
+/**
+ * Case insensitive, matched against the name of the file with its extension
+ */
+{ NameContains: string } | 
+/**
+ * In any of these locations
+ */
+{ Locations: number[] } | 
+/**
+ * Files of at least this many MiB
+ */
+{ MinSizeMiB: number } | 
+/**
+ * Files of at most this many MiB
+ */
+{ MaxSizeMiB: number }
+
+/**
+ * What to do when a copy would land on a file or directory that already exists
+ */
+export type ConflictPolicy = "Skip" | "Rename" | "Overwrite"
+
+/**
+ * How the user settled a path both sides changed, applied by the next sync
+ */
+export type ConflictResolution = 
+/**
+ * Location A's version replaces B's, deletions included
+ */
+"KeepA" | 
+/**
+ * Location B's version replaces A's, deletions included
+ */
+"KeepB" | 
+/**
+ * B's version is renamed with a ` (n)` suffix and synced as a new file, then A's version is
+ * synced. Like [`ConflictResolution::KeepA`] unless both sides have a file.
+ */
+"KeepBoth"
+
+/**
+ * Which of the writes of a conflict to keep
+ */
+export type ConflictSide = "Winner" | "Loser"
+
+export type ConflictingWrite = { instance: string; timestamp: number; value: JsonValue }
+
 /**
  * The method used for the connection with this peer.
  * *Technically* you can have multiple under the hood but this simplifies things for the UX.
@@ -208,7 +648,7 @@ export type ConnectionMethod = "Relay" | "Local" | "Disconnected"
 
 export type ConvertImageArgs = { location_id: number; file_path_id: number; delete_src: boolean; desired_extension: ConvertibleExtension; quality_percentage: number | null }
 
-export type ConvertibleExtension = "bmp" | "dib" | "ff" | "gif" | "ico" | "jpg" | "jpeg" | "png" | "pnm" | "qoi" | "tga" | "icb" | "vda" | "vst" | "tiff" | "tif" | "hif" | "heif" | "heifs" | "heic" | "heics" | "avif" | "avci" | "avcs" | "svg" | "svgz" | "pdf" | "webp"
+export type ConvertibleExtension = "bmp" | "dib" | "ff" | "gif" | "ico" | "jpg" | "jpeg" | "png" | "pnm" | "qoi" | "tga" | "icb" | "vda" | "vst" | "tiff" | "tif" | "hif" | "heif" | "heifs" | "heic" | "heics" | "avif" | "avci" | "avcs" | "svg" | "svgz" | "pdf" | "webp" | "raw" | "dng" | "cr2" | "cr3" | "dcr" | "nef" | "arw" | "rw2" | "jxl"
 
 export type CreateEphemeralFileArgs = { path: string; context: EphemeralFileCreateContextTypes; name: string | null }
 
@@ -218,12 +658,128 @@ export type CreateFileArgs = { location_id: number; sub_path: string | null; nam
 
 export type CreateFolderArgs = { location_id: number; sub_path: string | null; name: string | null }
 
+export type CreateFolderSyncArgs = { location_a_id: number; location_b_id: number }
+
+export type CreateKeyArgs = { name: string | null; password: string; strength?: KeyStrength }
+
 export type CreateLibraryArgs = { name: LibraryName; default_locations: DefaultLocations | null }
 
+export type CreateScheduleArgs = { location_id: number; job: ScheduledJob; 
+/**
+ * Cron expression, e.g. `0 3 * * SUN` for Sundays at 3am
+ */
+recurrence: string; catch_up?: CatchUp }
+
 export type CursorOrderItem<T> = { order: SortOrder; data: T }
 
+export type CustomField = { id: number; pub_id: number[]; name: string | null; 
+/**
+ * `null` for fields of kinds this version doesn't know about
+ */
+kind: FieldKind | null; options: string[]; date_created: string | null; date_modified: string | null }
+
+export type CustomFieldCondition = 
+/**
+ * Whether the object has a value for the field at all
+ */
+{ isSet: boolean } | { text: TextMatch } | 
+/**
+ * Any of these options of a select field
+ */
+{ oneOf: string[] } | 
+/**
+ * Numbers and ratings
+ */
+{ number: Range<number> } | { boolean: boolean } | { date: Range<string> }
+
+export type CustomFieldCreateArgs = { name: string; kind: FieldKind; 
+/**
+ * Values a select field can take, ignored for other kinds
+ */
+options?: string[] }
+
+/**
+ * Objects by the value they have for a custom field, see [`crate::object::custom_field`]
+ */
+export type CustomFieldFilter = { fieldId: number; condition: CustomFieldCondition }
+
+export type CustomFieldSetArgs = { object_ids: number[]; field_id: number; 
+/**
+ * `null` clears the field
+ */
+value: FieldValue | null }
+
+export type CustomFieldUpdateArgs = { id: number; name: string | null; 
+/**
+ * Objects keep values of options that are removed until they're set again
+ */
+options: string[] | null }
+
+/**
+ * A kind defined by a library on top of the builtin [`ObjectKind`](crate::kind::ObjectKind)s,
+ * like "CAD" or "Genomics"
+ */
+export type CustomKind = { name: string; 
+/**
+ * Extensions without the leading dot, compared case insensitively
+ */
+extensions?: string[]; magic_bytes?: MagicBytesMatcher[] }
+
+export type DecryptFilesArgs = { location_id: number; file_path_ids: number[] }
+
 export type DefaultLocations = { desktop: boolean; documents: boolean; downloads: boolean; pictures: boolean; music: boolean; videos: boolean }
 
+export type DeleteMode = 
+/**
+ * Unlinks files, for good
+ */
+"Delete" | 
+/**
+ * Moves files to the platform trash, keeping their file paths in the library to restore them
+ */
+"Trash" | 
+/**
+ * Overwrites file contents before unlinking them, which is best effort only on some volumes,
+ * see [`erase_caveats`]
+ */
+{ Erase: { passes: string } }
+
+/**
+ * What happens to the files of the target that aren't in the source
+ */
+export type DeletionPolicy = 
+/**
+ * Keep them, so files deleted from the source stay in the backup
+ */
+"Additive" | 
+/**
+ * Delete them, the target ending up with exactly the files of the source
+ */
+"Mirror"
+
+export type DeviceHealth = { 
+/**
+ * The drive's device, eg. `/dev/sda`
+ */
+device: string; model: string | null; serial: string | null; 
+/**
+ * Where the drive's volumes holding locations are mounted
+ */
+mount_points: string[]; 
+/**
+ * The oldest samples first
+ */
+history: HealthSample[]; 
+/**
+ * The alerts of the latest sample
+ */
+alerts: HealthAlert[] }
+
+/**
+ * Emitted when a drive shows new signs of failing
+ */
+export type DeviceHealthAlert = { device: string; model: string | null; mount_points: string[]; alerts: HealthAlert[] }
+
 /**
  * The method used for the discovery of this peer.
  * *Technically* you can have multiple under the hood but this simplifies things for the UX.
@@ -232,14 +788,76 @@ export type DiscoveryMethod = "Relay" | "Local" | "Manual"
 
 export type DiskType = "SSD" | "HDD" | "Removable"
 
+export type DiskUsageArgs = { location_id: number; 
+/**
+ * Materialized path of the children of the directory, the root of the location if
+ * `None`
+ */
+path?: string | null; 
+/**
+ * Levels of subdirectories to send, [`disk_usage::DEFAULT_TREE_DEPTH`] if `None`
+ */
+depth?: number | null }
+
 export type DoubleClickAction = "openFile" | "quickPreview"
 
-export type EditLibraryArgs = { id: string; name: LibraryName | null; description: MaybeUndefined<string> }
+/**
+ * File paths linked to the same object, so with the same content, across all locations
+ */
+export type DuplicateGroup = { object_id: number; cas_id: string | null; 
+/**
+ * Size of a single copy, as a string as it may not fit in a JS number
+ */
+size_in_bytes: string; 
+/**
+ * Sum of the bytes each copy takes on disk on its own, without what it shares with
+ * copy-on-write clones
+ */
+physical_size: string; 
+/**
+ * Bytes freed by keeping a single copy
+ */
+reclaimable_bytes: string; file_paths: ({ id: number; pub_id: number[]; location_id: number | null; materialized_path: string | null; is_dir: boolean | null; name: string | null; extension: string | null; cas_id: string | null; size_in_bytes_bytes: number[] | null; physical_size_bytes: number[] | null; object_id: number | null })[] }
+
+export type DuplicatesArgs = { take?: number | null; cursor?: number | null }
+
+export type DuplicatesData = { groups: DuplicateGroup[]; reclaimableBytes: string; cursor: number | null }
+
+export type EditLibraryArgs = { id: string; name: LibraryName | null; description: MaybeUndefined<string>; cas_id_algorithm?: CasIdAlgorithm | null; 
+/**
+ * As a string as it may not fit in a JS number, `null` hashes every file sampled
+ */
+deep_hash_threshold?: MaybeUndefined<string>; custom_kinds?: CustomKind[] | null; access_mode?: LibraryAccessMode | null; concurrency_limits?: ConcurrencyLimit[] | null; identifier_batch_size_bounds?: BatchSizeBounds | null }
+
+export type EmbedForLocationArgs = { id: number; path: string; regenerate?: boolean }
+
+export type EncryptFilesArgs = { location_id: number; file_path_ids: number[]; key_pub_id: string }
+
+export type EncryptionStatus = "Plaintext" | "Pending" | "Encrypted"
 
 export type EphemeralFileCreateContextTypes = "empty" | "text"
 
+/**
+ * What we could tell about a file outside of any location, see [`ephemeral_identify`]
+ */
+export type EphemeralFileMetadata = { path: string; cas_id: string | null; kind: ObjectKind; 
+/**
+ * Name of the library defined kind, see [`KindRegistry`](sd_file_ext::custom_kind::KindRegistry)
+ */
+custom_kind: string | null; 
+/**
+ * Only set for symbolic links identified with [`SymlinkPolicy::RecordAsLink`](super::SymlinkPolicy::RecordAsLink)
+ */
+link_target: string | null; 
+/**
+ * Cloud placeholder identified without reading its content, see [`OnDemandFilePolicy`](super::OnDemandFilePolicy)
+ */
+remote_only: boolean }
+
 export type EphemeralFileSystemOps = { sources: string[]; target_dir: string }
 
+export type EphemeralIdentification = { files: EphemeralFileMetadata[]; errors: string[] }
+
 export type EphemeralPathOrder = { field: "name"; value: SortOrder } | { field: "sizeInBytes"; value: SortOrder } | { field: "dateCreated"; value: SortOrder } | { field: "dateModified"; value: SortOrder }
 
 export type EphemeralPathSearchArgs = { path: string; withHiddenFiles: boolean; order?: EphemeralPathOrder | null }
@@ -254,13 +872,28 @@ export type EphemeralRenameMany = { from_pattern: FromPattern; to_pattern: strin
 
 export type EphemeralRenameOne = { from_path: string; to: string }
 
-export type Error = { code: ErrorCode; message: string }
-
+export type EraseCaveat = 
 /**
- * TODO
+ * The file system writes new contents to other blocks, old ones staying on disk until reused
  */
-export type ErrorCode = "BadRequest" | "Unauthorized" | "Forbidden" | "NotFound" | "Timeout" | "Conflict" | "PreconditionFailed" | "PayloadTooLarge" | "MethodNotSupported" | "ClientClosedRequest" | "InternalServerError"
-
+{ CopyOnWrite: string } | 
+/**
+ * SSDs and flash drives spread writes over other cells for wear leveling, and only their
+ * firmware can wipe the old ones
+ */
+"FlashStorage" | 
+/**
+ * No mounted volume holds the path, like with network shares, so nothing is known about it
+ */
+"UnknownVolume"
+
+export type Error = { code: ErrorCode; message: string }
+
+/**
+ * TODO
+ */
+export type ErrorCode = "BadRequest" | "Unauthorized" | "Forbidden" | "NotFound" | "Timeout" | "Conflict" | "PreconditionFailed" | "PayloadTooLarge" | "MethodNotSupported" | "ClientClosedRequest" | "InternalServerError"
+
 export type ExifDataOrder = { field: "epochTime"; value: SortOrder }
 
 export type ExifMetadata = { resolution: Resolution; date_taken: MediaDate | null; location: MediaLocation | null; camera_data: CameraData; artist: string | null; description: string | null; copyright: string | null; exif_version: string | null }
@@ -271,8 +904,24 @@ export type ExplorerLayout = "grid" | "list" | "media"
 
 export type ExplorerSettings<TOrder> = { layoutMode: ExplorerLayout | null; gridItemSize: number | null; gridGap: number | null; mediaColumns: number | null; mediaAspectSquare: boolean | null; mediaViewWithDescendants: boolean | null; openOnDoubleClick: DoubleClickAction | null; showBytesInGridView: boolean | null; colVisibility: { [key in string]: boolean } | null; colSizes: { [key in string]: number } | null; listViewIconSize: string | null; listViewTextSize: string | null; order?: TOrder | null; showHiddenFiles?: boolean }
 
+export type ExportChecksumsArgs = { location_id: number; sub_path: string | null; 
+/**
+ * Exports only these file paths instead of every file in `sub_path`
+ */
+file_path_ids: number[] | null; format?: ChecksumFormat }
+
+export type ExtractArgs = { location_id: number; file_path_id: number; target_location_relative_directory_path: string; conflict_policy?: ConflictPolicy; password: string | null }
+
+export type ExtractTextForLocationArgs = { id: number; path: string; regenerate?: boolean; 
+/**
+ * Tesseract's languages to recognize, like `eng+por`, English when missing
+ */
+languages: string | null }
+
 export type FFmpegMetadata = { formats: string[]; duration: [number, number] | null; start_time: [number, number] | null; bit_rate: [number, number]; chapters: Chapter[]; programs: Program[]; metadata: Metadata }
 
+export type FaceForFrontend = { id: number; x: number; y: number; width: number; height: number; score: number; object_id: number; person: { id: number; name: string | null } | null }
+
 export type Feedback = { message: string; emoji: number }
 
 export type FfmpegMediaAudioProps = { id: number; delay: number; padding: number; sample_rate: number | null; sample_format: string | null; bit_per_sample: number | null; channel_layout: string | null; codec_id: number }
@@ -281,17 +930,29 @@ export type FfmpegMediaChapter = { chapter_id: number; start: number[]; end: num
 
 export type FfmpegMediaVideoProps = { id: number; pixel_format: string | null; color_range: string | null; bits_per_channel: number | null; color_space: string | null; color_primaries: string | null; color_transfer: string | null; field_order: string | null; chroma_location: string | null; width: number; height: number; aspect_ratio_num: number | null; aspect_ratio_Den: number | null; properties: string | null; codec_id: number }
 
+export type FieldKind = "Text" | "Number" | "Boolean" | "Date" | 
+/**
+ * From 0 to [`MAX_RATING`] stars
+ */
+"Rating" | 
+/**
+ * One of the options of the field, like the status of a task
+ */
+"Select"
+
+export type FieldValue = { kind: "Text"; value: string } | { kind: "Number"; value: number } | { kind: "Boolean"; value: boolean } | { kind: "Date"; value: string } | { kind: "Rating"; value: number } | { kind: "Select"; value: string }
+
 export type FileCreateContextTypes = "empty" | "text"
 
-export type FilePath = { id: number; pub_id: number[]; is_dir: boolean | null; cas_id: string | null; integrity_checksum: string | null; location_id: number | null; materialized_path: string | null; name: string | null; extension: string | null; hidden: boolean | null; size_in_bytes: string | null; size_in_bytes_bytes: number[] | null; inode: number[] | null; object_id: number | null; key_id: number | null; date_created: string | null; date_modified: string | null; date_indexed: string | null }
+export type FilePath = { id: number; pub_id: number[]; is_dir: boolean | null; cas_id: string | null; integrity_checksum: string | null; integrity_status: number | null; location_id: number | null; materialized_path: string | null; name: string | null; extension: string | null; hidden: boolean | null; size_in_bytes: string | null; size_in_bytes_bytes: number[] | null; physical_size_bytes: number[] | null; allocated_size_bytes: number[] | null; inode: number[] | null; link_target: string | null; remote_only: boolean | null; in_archive: boolean | null; identification_skipped: boolean | null; xattrs: number[] | null; object_id: number | null; key_id: number | null; date_created: string | null; date_modified: string | null; date_indexed: string | null; date_trashed: string | null }
 
 export type FilePathCursor = { isDir: boolean; variant: FilePathCursorVariant }
 
 export type FilePathCursorVariant = "none" | { name: CursorOrderItem<string> } | { sizeInBytes: SortOrder } | { dateCreated: CursorOrderItem<string> } | { dateModified: CursorOrderItem<string> } | { dateIndexed: CursorOrderItem<string> } | { object: FilePathObjectCursor }
 
-export type FilePathFilterArgs = { locations: InOrNotIn<number> } | { path: { location_id: number; path: string; include_descendants: boolean } } | { name: TextMatch } | { extension: InOrNotIn<string> } | { createdAt: Range<string> } | { modifiedAt: Range<string> } | { indexedAt: Range<string> } | { hidden: boolean }
+export type FilePathFilterArgs = { locations: InOrNotIn<number> } | { path: { location_id: number; path: string; include_descendants: boolean } } | { name: TextMatch } | { extension: InOrNotIn<string> } | { createdAt: Range<string> } | { modifiedAt: Range<string> } | { indexedAt: Range<string> } | { hidden: boolean } | { trashed: boolean } | { linkTarget: TextMatch }
 
-export type FilePathForFrontend = { id: number; pub_id: number[]; is_dir: boolean | null; cas_id: string | null; integrity_checksum: string | null; location_id: number | null; materialized_path: string | null; name: string | null; extension: string | null; hidden: boolean | null; size_in_bytes: string | null; size_in_bytes_bytes: number[] | null; inode: number[] | null; object_id: number | null; object: { id: number; pub_id: number[]; kind: number | null; key_id: number | null; hidden: boolean | null; favorite: boolean | null; important: boolean | null; note: string | null; date_created: string | null; date_accessed: string | null; tags: ({ object_id: number; tag_id: number; tag: Tag; date_created: string | null })[]; exif_data: { resolution: number[] | null; media_date: number[] | null; media_location: number[] | null; camera_data: number[] | null; artist: string | null; description: string | null; copyright: string | null; exif_version: string | null } | null } | null; key_id: number | null; date_created: string | null; date_modified: string | null; date_indexed: string | null }
+export type FilePathForFrontend = { id: number; pub_id: number[]; is_dir: boolean | null; cas_id: string | null; integrity_checksum: string | null; integrity_status: number | null; location_id: number | null; materialized_path: string | null; name: string | null; extension: string | null; hidden: boolean | null; size_in_bytes: string | null; size_in_bytes_bytes: number[] | null; physical_size_bytes: number[] | null; allocated_size_bytes: number[] | null; inode: number[] | null; link_target: string | null; remote_only: boolean | null; in_archive: boolean | null; identification_skipped: boolean | null; xattrs: number[] | null; object_id: number | null; object: { id: number; pub_id: number[]; kind: number | null; custom_kind: string | null; quick_metadata: number[] | null; key_id: number | null; hidden: boolean | null; favorite: boolean | null; important: boolean | null; note: string | null; date_created: string | null; date_accessed: string | null; tags: ({ object_id: number; tag_id: number; tag: Tag; date_created: string | null })[]; exif_data: { resolution: number[] | null; media_date: number[] | null; media_location: number[] | null; camera_data: number[] | null; artist: string | null; description: string | null; copyright: string | null; exif_version: string | null } | null } | null; key_id: number | null; date_created: string | null; date_modified: string | null; date_indexed: string | null; date_trashed: string | null }
 
 export type FilePathObjectCursor = { dateAccessed: CursorOrderItem<string> } | { kind: CursorOrderItem<number> }
 
@@ -299,6 +960,12 @@ export type FilePathOrder = { field: "name"; value: SortOrder } | { field: "size
 
 export type FilePathSearchArgs = { take?: number | null; orderAndPagination?: OrderAndPagination<number, FilePathOrder, FilePathCursor> | null; filters?: SearchFilterArgs[]; groupDirectories?: boolean }
 
+export type FileVersion = { id: number; 
+/**
+ * Relative to the root of the location
+ */
+path: string; cas_id: string; size_in_bytes: string; date_modified: string; date_replaced: string }
+
 export type Flash = { 
 /**
  * Specifies how flash was used (on, auto, off, forced, onvalid)
@@ -344,6 +1011,28 @@ export type FlashMode =
  */
 "Forced"
 
+export type FolderSyncChange = { 
+/**
+ * Relative to the roots of the locations
+ */
+path: string; is_dir: boolean; action: SyncAction }
+
+export type FolderSyncConflict = { 
+/**
+ * Relative to the roots of the locations
+ */
+path: string; 
+/**
+ * Set once the user resolved it, until the next sync applies it
+ */
+resolution: ConflictResolution | null }
+
+export type FolderSyncPair = { id: number; location_a_id: number; location_b_id: number; date_last_synced: string | null; 
+/**
+ * Paths waiting for the user to resolve them
+ */
+conflicts: number }
+
 export type FromPattern = { pattern: string; replace_all: boolean }
 
 export type FullRescanArgs = { location_id: number; reidentify_objects: boolean }
@@ -356,7 +1045,104 @@ export type GetAll = { backups: Backup[]; directory: string }
 
 export type HardwareModel = "Other" | "MacStudio" | "MacBookAir" | "MacBookPro" | "MacBook" | "MacMini" | "MacPro" | "IMac" | "IMacPro" | "IPad" | "IPhone" | "Simulator" | "Android"
 
-export type IdentifyUniqueFilesArgs = { id: number; path: string }
+/**
+ * A sign the drive is about to fail
+ */
+export type HealthAlert = 
+/**
+ * The drive reports it's failing
+ */
+{ type: "Failing" } | { type: "ReallocatedSectors"; count: number } | { type: "PendingSectors"; count: number } | { type: "MediaErrors"; count: number } | { type: "Overheating"; celsius: number } | { type: "WornOut"; percentage_used: number }
+
+export type HealthSample = { date: string; 
+/**
+ * The drive's own verdict on its health
+ */
+passed: boolean | null; temperature_celsius: number | null; 
+/**
+ * Sectors the drive remapped after they failed, ATA drives only
+ */
+reallocated_sectors: number | null; 
+/**
+ * Sectors waiting to be remapped, ATA drives only
+ */
+pending_sectors: number | null; 
+/**
+ * Unrecovered data integrity errors, NVMe drives only
+ */
+media_errors: number | null; 
+/**
+ * Estimated wear of the drive, which goes past 100, NVMe drives only
+ */
+percentage_used: number | null }
+
+
+/**
+ * How much of a location the file identifier has gone through
+ */
+export type IdentificationStatistics = { identified_count: number; orphan_count: number; empty_file_count: number; 
+/**
+ * As a string as it may not fit in a JS number
+ */
+total_bytes: string; by_kind: KindIdentificationStatistics[] }
+
+/**
+ * Tells which files of a location the file identifier must leave alone. Matching files stay
+ * indexed, but they aren't hashed nor get an object, and are flagged with `identification_skipped`.
+ * 
+ * Stored msgpack encoded in `location.identifier_rules`.
+ */
+export type IdentifierRule = 
+/**
+ * Files bigger than this many MiB
+ */
+{ MaxSizeMiB: number } | 
+/**
+ * Extensions without the leading dot, case insensitive
+ */
+{ Extensions: string[] } | 
+/**
+ * Glob matched against the path of the file relative to the location root, e.g. `backups/**`
+ */
+{ Glob: string }
+
+/**
+ * How the file identifier treats the files of a location, applied to every scan of it.
+ * 
+ * Stored msgpack encoded in `location.identifier_settings`.
+ */
+export type IdentifierSettings = { 
+/**
+ * What to do with the symbolic links of the location
+ */
+symlink_policy?: SymlinkPolicy; 
+/**
+ * Identify the entries of zip, tar and 7z archives, see [`ArchiveFormat`](super::ArchiveFormat)
+ */
+walk_archives?: boolean; 
+/**
+ * Read dimensions, durations and codecs from the headers of media files while identifying
+ * them, see [`QuickMetadata`](super::QuickMetadata)
+ */
+extract_quick_metadata?: boolean; 
+/**
+ * How many bytes we read to sniff the kind of files with a missing or unknown extension, 0
+ * disables sniffing, [`DEFAULT_SNIFF_READ_BUDGET`](super::DEFAULT_SNIFF_READ_BUDGET) if unset
+ */
+sniff_read_budget?: number | null; 
+/**
+ * What to do with cloud placeholder files, whose content is downloaded by reading it
+ */
+on_demand_policy?: OnDemandFilePolicy }
+
+export type IdentifyUniqueFilesArgs = { id: number; path: string; 
+/**
+ * Only reports the objects that would be created and linked, without writing
+ * anything, once the identification is done
+ */
+dry_run?: boolean }
+
+export type ImportOsTagsArgs = { id: number; path: string }
 
 export type InOrNotIn<T> = { in: T[] } | { notIn: T[] }
 
@@ -376,16 +1162,111 @@ export type IndexerRuleCreateArgs = { name: string; dry_run: boolean; rules: ([R
 
 export type InvalidateOperationEvent = { type: "single"; data: SingleInvalidateOperationEvent } | { type: "all" }
 
+export type IoThrottleLimit = { 
+/**
+ * Every job if empty
+ */
+jobs: JobName[]; bytes_per_second: number }
+
+export type IoThrottlePreferences = { limits: IoThrottleLimit[] }
+
+export type JobError = { id: number; job_name: string | null; error: number[] | null; message: string | null; location_id: number | null; file_path_id: number | null; date_created: string | null }
+
+export type JobErrorPage = { items: JobError[]; cursor: number | null }
+
 export type JobGroup = { id: string; action: string | null; status: JobStatus; created_at: string; jobs: JobReport[] }
 
-export type JobProgressEvent = { id: string; library_id: string; task_count: number; completed_task_count: number; phase: string; message: string; estimated_completion: string }
+export type JobHistoryArgs = { locationId?: number | null; name?: string | null; status?: JobStatus | null; take?: number | null; cursor?: number | null }
+
+/**
+ * A finished job run, as stored in the `job_history` table
+ */
+export type JobHistoryEntry = { id: number; jobId: string; name: string; action: string | null; locationId: number | null; status: JobStatus; trigger: JobTrigger | null; 
+/**
+ * The init of the job, e.g. the location and sub path it ran on
+ */
+parameters: JsonValue | null; 
+/**
+ * The report metadata, with the output counts of the job under the `output` key
+ */
+metadata: JsonValue | null; errors: string[]; taskCount: number; completedTaskCount: number; startedAt: string | null; completedAt: string; durationMs: number | null }
+
+export type JobHistoryPage = { items: JobHistoryEntry[]; cursor: number | null }
+
+export type JobName = "Indexer" | "FileIdentifier" | "MediaProcessor" | "DuplicateFinder" | "VerifyIntegrity" | "FileCopier" | "FileMover" | "Backup" | "FolderSync" | "ColdArchiver" | "DiskUsageAnalyzer" | "BulkRename" | "Compressor" | "Extractor" | "FileEncryptor" | "FileDecryptor" | "ChecksumExporter" | "ChecksumImporter" | "TextExtractor" | "TagRuleApplier" | "ScriptRunner" | "ImageLabeler" | "Embedder" | "Transcriber"
+
+export type JobProgressEvent = { id: string; library_id: string; task_count: number; completed_task_count: number; phase: string; message: string; estimated_completion: string; 
+/**
+ * Throughput of the job, the old job system counts tasks as files, which most of its jobs
+ * go through one per task
+ */
+metrics: JobProgressMetrics | null }
+
+/**
+ * Throughput of a running job, so the UI can show more than task counts
+ */
+export type JobProgressMetrics = { files_per_second: number; 
+/**
+ * Content size of hashed files per second, sampled hashing reads only a fraction of it
+ */
+bytes_hashed_per_second: number; 
+/**
+ * `None` until at least one file was processed
+ */
+estimated_completion: string | null; phases: PhaseMetrics[] }
 
 export type JobReport = { id: string; name: string; action: string | null; data: number[] | null; metadata: { [key in string]: JsonValue } | null; errors_text: string[]; created_at: string | null; started_at: string | null; completed_at: string | null; parent_id: string | null; status: JobStatus; task_count: number; completed_task_count: number; phase: string; message: string; estimated_completion: string }
 
+export type JobSchedule = { id: number; location_id: number; job: ScheduledJob; recurrence: string; catch_up: CatchUp; enabled: boolean; 
+/**
+ * Only set while enabled
+ */
+next_run_at: string | null; last_run_at: string | null }
+
 export type JobStatus = "Queued" | "Running" | "Completed" | "Canceled" | "Failed" | "Paused" | "CompletedWithErrors"
 
+/**
+ * Who or what started a job, kept in the report metadata under the `trigger` key so it survives
+ * pauses and is inherited by the queued children
+ */
+export type JobTrigger = 
+/**
+ * Started from the interface, the default as almost every job is
+ */
+"User" | 
+/**
+ * Started by the location watcher reacting to changes on disk
+ */
+"Watcher" | 
+/**
+ * Delegated by a paired node, as the location is attached to this one
+ */
+"Peer"
+
 export type JsonValue = null | boolean | number | string | JsonValue[] | { [key in string]: JsonValue }
 
+/**
+ * A key files of the library are encrypted with, only usable while mounted with its password
+ */
+export type KeyItem = { pub_id: string; name: string | null; date_created: string | null; mounted: boolean }
+
+/**
+ * How costly passwords are to hash, and so to guess, for new keys
+ */
+export type KeyStrength = "Standard" | "Hardened" | "Paranoid"
+
+export type KindIdentificationStatistics = { 
+/**
+ * Enum: `sd_file_ext::kind::ObjectKind`
+ */
+kind: number; identified_count: number; empty_file_count: number; total_bytes: string }
+
+export type KindRollup = { 
+/**
+ * Enum: `sd_file_ext::kind::ObjectKind`
+ */
+kind: number; object_count: number; total_bytes: string }
+
 export type KindStatistic = { kind: number; name: string; count: number; total_bytes: string }
 
 export type KindStatistics = { statistics: KindStatistic[] }
@@ -394,248 +1275,1038 @@ export type Label = { id: number; name: string; date_created: string | null; dat
 
 export type LabelWithObjects = { id: number; name: string; date_created: string | null; date_modified: string | null; label_objects: { object: { id: number; file_paths: FilePath[] } }[] }
 
+export type LeftoverKind = 
+/**
+ * Directories with no files under them, only the outermost one being listed
+ */
+"EmptyDirectory" | 
+/**
+ * Symbolic links whose target doesn't exist anymore
+ */
+"BrokenLink" | 
+/**
+ * Files of zero bytes
+ */
+"EmptyFile"
+
+/**
+ * Read-only libraries can be shared with another node, like a family member's, without it
+ * changing them. Changes synced from other nodes still apply.
+ */
+export type LibraryAccessMode = "ReadWrite" | "ReadOnly"
+
 /**
  * Can wrap a query argument to require it to contain a `library_id` and provide helpers for working with libraries.
  */
 export type LibraryArgs<T> = { library_id: string; arg: T }
 
 /**
- * LibraryConfig holds the configuration for a specific library. This is stored as a '{uuid}.sdlibrary' file.
+ * LibraryConfig holds the configuration for a specific library. This is stored as a '{uuid}.sdlibrary' file.
+ */
+export type LibraryConfig = { 
+/**
+ * name is the display name of the library. This is used in the UI and is set by the user.
+ */
+name: LibraryName; 
+/**
+ * description is a user set description of the library. This is used in the UI and is set by the user.
+ */
+description: string | null; 
+/**
+ * id of the current instance so we know who this `.db` is. This can be looked up within the `Instance` table.
+ */
+instance_id: number; 
+/**
+ * cloud_id is the ID of the cloud library this library is linked to.
+ * If this is set we can assume the library is synced with the Cloud.
+ */
+cloud_id?: string | null; generate_sync_operations?: boolean; 
+/**
+ * cas_id_algorithm is the hashing scheme used to generate content addressable ids for this library.
+ * Changing it requires re-identifying every location, so existing cas_ids are comparable.
+ */
+cas_id_algorithm?: CasIdAlgorithm; 
+/**
+ * deep_hash_threshold is the size up to which files get a cas_id of their entire content instead of a sampled one,
+ * trading identification speed for exact deduplication. Every job hashing files reads it from here.
+ * As a string as it may not fit in a JS number.
+ */
+deep_hash_threshold?: string | null; 
+/**
+ * custom_kinds are object kinds defined by the user on top of the builtin ones, resolved by the file identifier.
+ */
+custom_kinds?: CustomKind[]; 
+/**
+ * access_mode is whether the library can be changed from this node, read-only libraries refuse every mutation and job.
+ */
+access_mode?: LibraryAccessMode; 
+/**
+ * concurrency_limits are groups of locations on the same slow device, like a network drive, whose job tasks share a budget.
+ */
+concurrency_limits?: ConcurrencyLimit[]; 
+/**
+ * identifier_batch_size_bounds are how few and how many files the file identifier commits at once, adapting to the database speed in between.
+ */
+identifier_batch_size_bounds?: BatchSizeBounds; version: LibraryConfigVersion }
+
+export type LibraryConfigVersion = "V0" | "V1" | "V2" | "V3" | "V4" | "V5" | "V6" | "V7" | "V8" | "V9" | "V10" | "V11"
+
+export type LibraryConfigWrapped = { uuid: string; instance_id: string; instance_public_key: RemoteIdentity; config: LibraryConfig }
+
+export type LibraryName = string
+
+export type LibraryPreferences = { location?: { [key in string]: LocationSettings }; tag?: { [key in string]: TagSettings } }
+
+/**
+ * Objects in more than one location are counted once for each of them
+ */
+export type LibraryRollups = { object_count: number; 
+/**
+ * As a string as it may not fit in a JS number
+ */
+total_bytes: string; by_kind: KindRollup[]; by_location: LocationRollup[]; 
+/**
+ * Objects tagged directly, not through the tags nested under each tag
+ */
+by_tag: TagRollup[]; 
+/**
+ * Oldest first, one entry for each day the library changed
+ */
+history: RollupHistoryEntry[] }
+
+export type LibrarySearchItem = { libraryId: string; libraryName: string; item: ExplorerItem }
+
+export type LightScanArgs = { location_id: number; sub_path: string }
+
+export type ListFailuresArgs = { locationId: number; take?: number | null; cursor?: number | null }
+
+export type ListVersionsArgs = { location_id: number; 
+/**
+ * Relative to the root of the location, every file of it if `None`
+ */
+path?: string | null }
+
+export type ListenerState = { type: "Listening" } | { type: "Error"; error: string } | { type: "NotListening" }
+
+export type Listeners = { ipv4: ListenerState; ipv6: ListenerState; relay: ListenerState }
+
+export type LiveSearchUpdate = 
+/**
+ * Every match, sent first
+ */
+{ type: "initial"; data: ExplorerItem[] } | 
+/**
+ * What changed since the previous update
+ */
+{ type: "changed"; data: { added: ExplorerItem[]; removed: number[] } }
+
+export type Location = { id: number; pub_id: number[]; name: string | null; path: string | null; total_capacity: number | null; available_capacity: number | null; size_in_bytes: number[] | null; is_archived: boolean | null; generate_preview_media: boolean | null; sync_preview_media: boolean | null; hidden: boolean | null; is_snapshot: boolean | null; date_created: string | null; identifier_rules: number[] | null; identifier_settings: number[] | null; scan_state: number; usn_journal_cursor: number[] | null; network_share: number[] | null; s3_config: number[] | null; webdav_config: number[] | null; cloud_config: number[] | null; mtp_config: number[] | null; sync_policy: number | null; versioning: number[] | null; instance_id: number | null }
+
+/**
+ * Size and free space of the volume holding a location at some point in time
+ */
+export type LocationCapacity = { date_captured: string; total_bytes: string; free_bytes: string; file_system: string | null }
+
+/**
+ * `LocationCreateArgs` is the argument received from the client using `rspc` to create a new location.
+ * It has the actual path and a vector of indexer rules ids, to create many-to-many relationships
+ * between the location and indexer rules.
+ */
+export type LocationCreateArgs = { path: string; dry_run: boolean; indexer_rules_ids: number[]; 
+/**
+ * Connection info for locations in a SMB or NFS share, which gets mounted on `path`
+ */
+network_share?: NetworkShare | null; 
+/**
+ * Bucket of locations in S3 compatible object storage, which use a local mirror directory
+ * as their path instead of the received one
+ */
+s3?: S3LocationConfig | null; 
+/**
+ * Folder of locations in a WebDAV server, which use a local mirror directory as their path
+ * instead of the received one
+ */
+webdav?: WebDavLocationConfig | null; 
+/**
+ * Google Drive or OneDrive folder of locations made only of file metadata, which use a
+ * local mirror directory as their path instead of the received one
+ */
+cloud_metadata?: CloudMetadataLocationConfig | null; 
+/**
+ * Storage of a phone or camera connected over MTP/PTP, which use a local mirror directory as
+ * their path instead of the received one
+ */
+mtp?: MtpLocationConfig | null }
+
+export type LocationRollup = { location_id: number; object_count: number; total_bytes: string }
+
+export type LocationSettings = { explorer: ExplorerSettings<FilePathOrder> }
+
+/**
+ * `LocationUpdateArgs` is the argument received from the client using `rspc` to update a location.
+ * It contains the id of the location to be updated, possible a name to change the current location's name
+ * and a vector of indexer rules ids to add or remove from the location.
+ * 
+ * It is important to note that only the indexer rule ids in this vector will be used from now on.
+ * Old rules that aren't in this vector will be purged.
+ */
+export type LocationUpdateArgs = { id: number; name: string | null; generate_preview_media: boolean | null; sync_preview_media: boolean | null; hidden: boolean | null; indexer_rules_ids: number[]; path: string | null; 
+/**
+ * Replaces the location's identifier rules, files skipped by the old ones are identified again
+ */
+identifier_rules: IdentifierRule[] | null; 
+/**
+ * Replaces how the file identifier treats the location's files, from its next scan on
+ */
+identifier_settings?: IdentifierSettings | null; 
+/**
+ * Replaces the location's network share connection info
+ */
+network_share?: NetworkShare | null; 
+/**
+ * Snapshot locations keep their catalog while their drive is unplugged, suspending jobs
+ */
+snapshot?: boolean | null }
+
+export type LocationWithIndexerRule = { id: number; pub_id: number[]; name: string | null; path: string | null; total_capacity: number | null; available_capacity: number | null; size_in_bytes: number[] | null; is_archived: boolean | null; generate_preview_media: boolean | null; sync_preview_media: boolean | null; hidden: boolean | null; is_snapshot: boolean | null; date_created: string | null; instance_id: number | null; indexer_rules: IndexerRule[]; identifier_rules: IdentifierRule[]; identifier_settings: IdentifierSettings }
+
+/**
+ * A byte signature expected at `offset` from the start of a file
+ */
+export type MagicBytesMatcher = { offset: number; bytes: number[] }
+
+export type MaybeUndefined<T> = null | T
+
+export type MediaData = { Exif: ExifMetadata } | { FFmpeg: FFmpegMetadata }
+
+/**
+ * This can be either naive with no TZ (`YYYY-MM-DD HH-MM-SS`) or UTC (`YYYY-MM-DD HH-MM-SS ±HHMM`),
+ * where `±HHMM` is the timezone data. It may be negative if West of the Prime Meridian, or positive if East.
+ */
+export type MediaDate = string
+
+export type MediaLocation = { latitude: number; longitude: number; pluscode: PlusCode; altitude: number | null; direction: number | null }
+
+/**
+ * Audio and subtitle tracks, and chapters, embedded in a media container.
+ * 
+ * This is a flattened view over [`FFmpegMetadata`], which nests streams inside programs and
+ * keeps chapter times in their own time base.
+ */
+export type MediaTracks = { audio: Track[]; subtitles: Track[]; chapters: ChapterMark[] }
+
+export type MembershipUpdate = 
+/**
+ * Every object of the collection, sent first
+ */
+{ type: "initial"; data: ExplorerItem[] } | 
+/**
+ * What changed since the previous update
+ */
+{ type: "changed"; data: { added: ExplorerItem[]; removed: number[] } }
+
+export type MergePeopleArgs = { 
+/**
+ * Person that keeps the faces of the others
+ */
+into: number; people: number[] }
+
+export type Metadata = { album: string | null; album_artist: string | null; artist: string | null; comment: string | null; composer: string | null; copyright: string | null; creation_time: string | null; date: string | null; disc: number | null; encoder: string | null; encoded_by: string | null; filename: string | null; genre: string | null; language: string | null; performer: string | null; publisher: string | null; service_name: string | null; service_provider: string | null; title: string | null; track: number | null; variant_bit_rate: number | null; custom: { [key in string]: string } }
+
+export type MountKeyArgs = { pub_id: string; password: string }
+
+/**
+ * A connected device with its storages, for users to pick the one to add as a location
+ */
+export type MtpDevice = { serial_number: string; name: string; storages: MtpStorage[] }
+
+export type MtpImportConfig = { location_id: number; 
+/**
+ * Directory of the destination location, relative to its path, defaults to its root
+ */
+sub_path: string | null }
+
+/**
+ * Storage of an MTP device where a location lives, stored msgpack encoded on
+ * `location.mtp_config`, which is local only as devices are plugged into a single node.
+ */
+export type MtpLocationConfig = { serial_number: string; storage_id: number; 
+/**
+ * Where new photos and videos are copied to after every scan of the device
+ */
+import?: MtpImportConfig | null }
+
+export type MtpStorage = { id: number; description: string | null; max_capacity: string; free_space: string }
+
+export type NetworkPolicy = { 
+/**
+ * Names of the interfaces (eg. `en0`) whose networks are used, every interface if empty
+ */
+interfaces?: string[]; 
+/**
+ * Networks we trust, in CIDR notation (eg. `192.168.1.0/24`). Discovery only runs while the
+ * node is on one and peers must be in one. Every network if empty.
+ */
+trusted_subnets?: string[]; 
+/**
+ * Only reach peers through private addresses
+ */
+lan_only?: boolean }
+
+/**
+ * Connection info of a location living in a SMB or NFS share, stored msgpack encoded on
+ * `location.network_share`, so jobs can mount the share on the location path before touching it.
+ * 
+ * Passwords are never stored, SMB shares authenticate with the credentials saved by the system
+ * (keychain, credential manager or a `credentials=` file on `mount_options`).
+ */
+export type NetworkShare = { protocol: NetworkShareProtocol; host: string; 
+/**
+ * Share name for SMB, exported path for NFS
+ */
+share: string; username: string | null; 
+/**
+ * Passed as they are to the platform's mount command
+ */
+mount_options: string | null }
+
+export type NetworkShareProtocol = "Smb" | "Nfs"
+
+export type NodeConfigP2P = { discovery?: P2PDiscoveryState; port: Port; disabled: boolean; disable_ipv6: boolean; disable_relay: boolean; enable_remote_access: boolean; 
+/**
+ * A list of peer addresses to try and manually connect to, instead of relying on discovery.
+ * 
+ * All of these are valid values:
+ * - `localhost`
+ * - `otbeaumont.me` or `otbeaumont.me:3000`
+ * - `127.0.0.1` or `127.0.0.1:300`
+ * - `[::1]` or `[::1]:3000`
+ * which is why we use `String` not `SocketAddr`
+ */
+manual_peers?: string[]; network_policy?: NetworkPolicy }
+
+export type NodePreferences = { thumbnailer: ThumbnailerPreferences; bandwidth?: BandwidthPreferences; io_throttle?: IoThrottlePreferences; 
+/**
+ * How much of the machine the tasks of every job use, lowered to `LowPower` while on battery
+ */
+resource_profile?: string }
+
+export type NodeState = ({ 
+/**
+ * id is a unique identifier for the current node. Each node has a public identifier (this one) and is given a local id for each library (done within the library code).
+ */
+id: string; 
+/**
+ * name is the display name of the current node. This is set by the user and is shown in the UI. // TODO: Length validation so it can fit in DNS record
+ */
+name: string; identity: RemoteIdentity; p2p: NodeConfigP2P; features: BackendFeature[]; preferences: NodePreferences; image_labeler_version: string | null }) & { data_path: string; device_model: string | null; is_in_docker: boolean }
+
+export type NonIndexedPathItem = { path: string; name: string; extension: string; kind: number; is_dir: boolean; date_created: string; date_modified: string; size_in_bytes_bytes: number[]; hidden: boolean }
+
+export type NoteHistoryEntry = { pub_id: number[]; content: string | null; 
+/**
+ * Versions this one was written over, more than one where concurrent edits were merged
+ */
+parents: number[][]; date_created: string | null; 
+/**
+ * Name of the device that wrote the version, if it's known here
+ */
+device: string | null }
+
+export type NoteSetArgs = { object_id: number; note: string | null; 
+/**
+ * Heads of the note the edit was made over, from `notes.get`. Edits made over
+ * older heads are merged with the ones written since
+ */
+parents: number[][] }
+
+/**
+ * Represents a single notification.
+ */
+export type Notification = ({ type: "library"; id: [string, number] } | { type: "node"; id: number }) & { data: NotificationData; read: boolean; expires: string | null }
+
+/**
+ * Represents the data of a single notification.
+ * This data is used by the frontend to properly display the notification.
+ */
+export type NotificationData = { title: string; content: string; kind: NotificationKind }
+
+export type NotificationId = { type: "library"; id: [string, number] } | { type: "node"; id: number }
+
+export type NotificationKind = "info" | "success" | "error" | "warning"
+
+/**
+ * OAuth refresh token the location lists files with, from an OAuth client of the user's own
+ * Google Cloud or Azure project
+ */
+export type OAuthCredentials = { client_id: string; 
+/**
+ * Only Google requires it, even for desktop clients
+ */
+client_secret: string | null; refresh_token: string }
+
+export type Object = { id: number; pub_id: number[]; kind: number | null; custom_kind: string | null; quick_metadata: number[] | null; key_id: number | null; hidden: boolean | null; favorite: boolean | null; important: boolean | null; note: string | null; date_created: string | null; date_accessed: string | null }
+
+export type ObjectCursor = "none" | { dateAccessed: CursorOrderItem<string> } | { kind: CursorOrderItem<number> }
+
+export type ObjectFieldValue = { field_id: number; value: FieldValue }
+
+export type ObjectFilterArgs = { favorite: boolean } | { hidden: ObjectHiddenFilter } | { kind: InOrNotIn<number> } | { tags: InOrNotIn<number> } | { labels: InOrNotIn<number> } | { dateAccessed: Range<string> } | { city: InOrNotIn<string> } | { country: InOrNotIn<string> } | { audioLanguage: InOrNotIn<string> } | { subtitleLanguage: InOrNotIn<string> } | { text: string } | { customField: CustomFieldFilter }
+
+export type ObjectHiddenFilter = "exclude" | "include"
+
+export type ObjectKind = "Unknown" | "Document" | "Folder" | "Text" | "Package" | "Image" | "Audio" | "Video" | "Archive" | "Executable" | "Alias" | "Encrypted" | "Key" | "Link" | "WebPageArchive" | "Widget" | "Album" | "Collection" | "Font" | "Mesh" | "Code" | "Database" | "Book" | "Config" | "Dotfile" | "Screenshot" | "Label"
+
+/**
+ * The note of an object as it reads after merging the edits of every device
+ */
+export type ObjectNote = { note: string | null; 
+/**
+ * Versions the note was last written as, to pass as the parents of the next edit
+ */
+heads: number[][]; versions: number }
+
+export type ObjectOrder = { field: "dateAccessed"; value: SortOrder } | { field: "kind"; value: SortOrder } | { field: "mediaData"; value: ExifDataOrder }
+
+export type ObjectSearchArgs = { take: number; orderAndPagination?: OrderAndPagination<number, ObjectOrder, ObjectCursor> | null; filters?: SearchFilterArgs[] }
+
+export type ObjectValidatorArgs = { id: number; path: string }
+
+export type ObjectWithFilePaths = { id: number; pub_id: number[]; kind: number | null; custom_kind: string | null; quick_metadata: number[] | null; key_id: number | null; hidden: boolean | null; favorite: boolean | null; important: boolean | null; note: string | null; date_created: string | null; date_accessed: string | null; file_paths: ({ id: number; pub_id: number[]; is_dir: boolean | null; cas_id: string | null; integrity_checksum: string | null; integrity_status: number | null; location_id: number | null; materialized_path: string | null; name: string | null; extension: string | null; hidden: boolean | null; size_in_bytes: string | null; size_in_bytes_bytes: number[] | null; physical_size_bytes: number[] | null; allocated_size_bytes: number[] | null; inode: number[] | null; link_target: string | null; remote_only: boolean | null; in_archive: boolean | null; identification_skipped: boolean | null; xattrs: number[] | null; object_id: number | null; object: { id: number; pub_id: number[]; kind: number | null; custom_kind: string | null; quick_metadata: number[] | null; key_id: number | null; hidden: boolean | null; favorite: boolean | null; important: boolean | null; note: string | null; date_created: string | null; date_accessed: string | null; exif_data: { resolution: number[] | null; media_date: number[] | null; media_location: number[] | null; camera_data: number[] | null; artist: string | null; description: string | null; copyright: string | null; exif_version: string | null } | null; ffmpeg_data: { id: number; formats: string; bit_rate: number[]; duration: number[] | null; start_time: number[] | null; chapters: FfmpegMediaChapter[]; programs: ({ program_id: number; streams: ({ stream_id: number; name: string | null; codec: { id: number; kind: string | null; sub_kind: string | null; tag: string | null; name: string | null; profile: string | null; bit_rate: number; video_props: FfmpegMediaVideoProps | null; audio_props: FfmpegMediaAudioProps | null; stream_id: number; program_id: number; ffmpeg_data_id: number } | null; aspect_ratio_num: number; aspect_ratio_den: number; frames_per_second_num: number; frames_per_second_den: number; time_base_real_den: number; time_base_real_num: number; dispositions: string | null; title: string | null; encoder: string | null; language: string | null; duration: number[] | null; metadata: number[] | null; program_id: number; ffmpeg_data_id: number })[]; name: string | null; metadata: number[] | null; ffmpeg_data_id: number })[]; title: string | null; creation_time: string | null; date: string | null; album_artist: string | null; disc: string | null; track: string | null; album: string | null; artist: string | null; metadata: number[] | null; object_id: number } | null } | null; key_id: number | null; date_created: string | null; date_modified: string | null; date_indexed: string | null; date_trashed: string | null })[] }
+
+export type ObjectWithFilePaths2 = { id: number; pub_id: number[]; kind: number | null; custom_kind: string | null; quick_metadata: number[] | null; key_id: number | null; hidden: boolean | null; favorite: boolean | null; important: boolean | null; note: string | null; date_created: string | null; date_accessed: string | null; file_paths: ({ id: number; pub_id: number[]; is_dir: boolean | null; cas_id: string | null; integrity_checksum: string | null; integrity_status: number | null; location_id: number | null; materialized_path: string | null; name: string | null; extension: string | null; hidden: boolean | null; size_in_bytes: string | null; size_in_bytes_bytes: number[] | null; physical_size_bytes: number[] | null; allocated_size_bytes: number[] | null; inode: number[] | null; link_target: string | null; remote_only: boolean | null; in_archive: boolean | null; identification_skipped: boolean | null; xattrs: number[] | null; object_id: number | null; object: { id: number; pub_id: number[]; kind: number | null; custom_kind: string | null; quick_metadata: number[] | null; key_id: number | null; hidden: boolean | null; favorite: boolean | null; important: boolean | null; note: string | null; date_created: string | null; date_accessed: string | null; exif_data: { resolution: number[] | null; media_date: number[] | null; media_location: number[] | null; camera_data: number[] | null; artist: string | null; description: string | null; copyright: string | null; exif_version: string | null } | null; ffmpeg_data: { id: number; formats: string; bit_rate: number[]; duration: number[] | null; start_time: number[] | null; chapters: FfmpegMediaChapter[]; programs: ({ program_id: number; streams: ({ stream_id: number; name: string | null; codec: { id: number; kind: string | null; sub_kind: string | null; tag: string | null; name: string | null; profile: string | null; bit_rate: number; video_props: FfmpegMediaVideoProps | null; audio_props: FfmpegMediaAudioProps | null; stream_id: number; program_id: number; ffmpeg_data_id: number } | null; aspect_ratio_num: number; aspect_ratio_den: number; frames_per_second_num: number; frames_per_second_den: number; time_base_real_den: number; time_base_real_num: number; dispositions: string | null; title: string | null; encoder: string | null; language: string | null; duration: number[] | null; metadata: number[] | null; program_id: number; ffmpeg_data_id: number })[]; name: string | null; metadata: number[] | null; ffmpeg_data_id: number })[]; title: string | null; creation_time: string | null; date: string | null; album_artist: string | null; disc: string | null; track: string | null; album: string | null; artist: string | null; metadata: number[] | null; object_id: number } | null } | null; key_id: number | null; date_created: string | null; date_modified: string | null; date_indexed: string | null; date_trashed: string | null })[] }
+
+/**
+ * `OldCleanupJobInit` looks for leftovers in a location, or starting from a `sub_path`: empty
+ * directories, symbolic links to nothing and empty files. Each one is checked again on disk
+ * before being listed in the job report, and removed according to `mode`.
+ */
+export type OldCleanupJobInit = { location_id: number; 
+/**
+ * The whole location if `None`
+ */
+sub_path?: string | null; 
+/**
+ * Every kind of leftover if `None`
+ */
+kinds?: LeftoverKind[] | null; mode?: CleanupMode }
+
+export type OldFileDeleterJobInit = { location_id: number; file_path_ids: number[]; mode?: DeleteMode }
+
+export type OldFileEraserJobInit = { location_id: number; file_path_ids: number[]; passes: string }
+
+/**
+ * `OldFileHydratorJobInit` downloads the content of remote only file paths, either by reading
+ * on-demand placeholders so their cloud provider fetches them, or from the provider of cloud
+ * metadata locations. Hydrated file paths are left without a `cas_id`, so the next file
+ * identifier run hashes them and links them to their objects.
+ */
+export type OldFileHydratorJobInit = { location_id: number; file_path_ids: number[] }
+
+/**
+ * `OldOrphanRemoverJobInit` removes the objects of the library left without any file path, along
+ * with their metadata and the thumbnails of their content. Objects other instances never heard
+ * of, like the ones of excluded locations, are removed from this instance only. The others are
+ * removed with synced deletions, so other instances drop them too, but only while no other
+ * instance exists: their file paths in locations they don't sync could still use them, so those
+ * objects are kept. With `dry_run`, orphans are only counted in the job report.
+ */
+export type OldOrphanRemoverJobInit = { dry_run?: boolean }
+
+/**
+ * `OldSyncBackfillJobInit` generates the sync operations of the records that existed before
+ * sync was enabled on the library, a page at a time so pairing a device with a large library can
+ * be followed, paused and resumed. Sync is only enabled once it completes, so canceling it never
+ * leaves other devices with part of the library.
+ * 
+ * It also backfills the records of a location whose sync policy changed to sync more of them.
+ */
+export type OldSyncBackfillJobInit = { 
+/**
+ * Limits the size of the operations generated per second, unlimited if `None`
+ */
+bytes_per_second: number | null }
+
+/**
+ * Represents the operating system which the remote peer is running.
+ * This is not used internally and predominantly is designed to be used for display purposes by the embedding application.
+ */
+/**
+ * What the identifier does with cloud backed placeholder files (e.g. OneDrive files available
+ * on-demand), whose content is only downloaded when read. Only Windows exposes them for now.
+ */
+export type OnDemandFilePolicy = 
+/**
+ * Read them like any other file, failing if the cloud provider can't hydrate them
+ */
+"Fail" | 
+/**
+ * Identify them without reading their content, taking the kind from their extension and
+ * leaving them without a `cas_id`, flagged in `file_path.remote_only` until hydrated
+ */
+"RecordAsRemote"
+
+export type OperatingSystem = "Windows" | "Linux" | "MacOS" | "Ios" | "Android" | { Other: string }
+
+export type OrderAndPagination<TId, TOrder, TCursor> = { orderOnly: TOrder } | { offset: { offset: number; order: TOrder | null } } | { cursor: { id: TId; cursor: TCursor } }
+
+export type Orientation = "Normal" | "CW90" | "CW180" | "CW270" | "MirroredVertical" | "MirroredHorizontal" | "MirroredHorizontalAnd90CW" | "MirroredHorizontalAnd270CW"
+
+export type P2PDiscoveryState = "Everyone" | "ContactsOnly" | "Disabled"
+
+export type P2PEvent = { type: "PeerChange"; identity: RemoteIdentity; connection: ConnectionMethod; discovery: DiscoveryMethod; metadata: PeerMetadata; addrs: string[] } | { type: "PeerDelete"; identity: RemoteIdentity } | { type: "SpacedropRequest"; id: string; identity: RemoteIdentity; peer_name: string; files: string[] } | { type: "SpacedropProgress"; id: string; percent: number; 
+/**
+ * Index of the file being transferred
+ */
+file: number; file_percent: number } | { type: "SpacedropTimedOut"; id: string } | { type: "SpacedropRejected"; id: string }
+
+export type PeerMetadata = { name: string; operating_system: OperatingSystem | null; device_model: HardwareModel | null; version: string | null }
+
+/**
+ * Time spent in one of the phases of a job, summed across all its tasks
+ */
+export type PersonItem = { id: number; name: string | null; facesCount: number; 
+/**
+ * Thumbnail of the image with the clearest face of the person
+ */
+thumbnail: string[] | null }
+
+export type PhaseMetrics = { name: string; elapsed_secs: number }
+
+export type PlaceCount = { country: string; city: string | null; count: number }
+
+export type PlusCode = string
+
+export type Port = { type: "random" } | { type: "discrete"; value: number }
+
+export type Program = { id: number; name: string | null; streams: Stream[]; metadata: Metadata }
+
+export type Props = { Video: VideoProps } | { Audio: AudioProps } | { Subtitle: SubtitleProps }
+
+/**
+ * Passwords of encrypted archives aren't stored, so an extraction resumed after a
+ * shutdown waits for its password to be given again here
+ */
+export type ProvideArchivePasswordArgs = { job_id: string; location_id: number; file_path_id: number; password: string }
+
+export type QueryArgs = { 
+/**
+ * Written in the search query language, like `kind:video size:>1GB "project x"`
+ */
+query: string; take?: number | null; 
+/**
+ * How many results to skip, the cursor returned with the previous page
+ */
+skip?: number | null; order?: QueryOrder | null; groupDirectories?: boolean }
+
+export type QueryData = { items: ExplorerItem[]; 
+/**
+ * Where the next page starts, none on the last page
+ */
+cursor: number | null }
+
+export type QueryOrder = { field: "name"; value: SortOrder } | { field: "sizeInBytes"; value: SortOrder } | { field: "dateCreated"; value: SortOrder } | { field: "dateModified"; value: SortOrder } | { field: "dateIndexed"; value: SortOrder }
+
+export type Range<T> = { from: T } | { to: T }
+
+/**
+ * Why a peer is or isn't reachable
+ */
+export type Reachability = { type: "Reachable" } | 
+/**
+ * P2P is disabled on this node
+ */
+{ type: "Disabled" } | 
+/**
+ * The peer wasn't discovered, so we don't know how to reach it
+ */
+{ type: "NotDiscovered" } | 
+/**
+ * The node isn't on a trusted network, so it doesn't look for peers
+ */
+{ type: "UntrustedNetwork" } | 
+/**
+ * We only know of the peer through the relay, which the policy doesn't allow
+ */
+{ type: "NoDirectAddress" } | 
+/**
+ * None of the addresses of the peer are on a network the policy allows
+ */
+{ type: "OutsidePolicy"; addresses: string[] }
+
+export type ReidentifyKindsArgs = { id: number; path: string }
+
+/**
+ * Where the relay queue lives, a folder that holds nothing else
+ */
+export type RelayStorage = { S3: S3LocationConfig } | { WebDav: WebDavLocationConfig }
+
+export type RemoteIdentity = string
+
+export type RenameFileArgs = { location_id: number; kind: RenameKind }
+
+export type RenameKind = { One: RenameOne } | { Many: RenameMany }
+
+export type RenameMany = { from_pattern: FromPattern; to_pattern: string; from_file_path_ids: number[] }
+
+/**
+ * The new name of a file path, as seen on a preview
+ */
+export type RenameMapping = { file_path_id: number; from: string; to: string; 
+/**
+ * Why the file path can't be renamed, renaming nothing while any has one
+ */
+problem: RenameProblem | null }
+
+export type RenameOne = { from_file_path_id: number; to: string }
+
+/**
+ * How to rename a batch of file paths
  */
-export type LibraryConfig = { 
+export type RenamePattern = { 
 /**
- * name is the display name of the library. This is used in the UI and is set by the user.
+ * New name, see [`Template`] for its tokens
  */
-name: LibraryName; 
+template: string; 
 /**
- * description is a user set description of the library. This is used in the UI and is set by the user.
+ * Regex matched on names without extension, for the `{0}`, `{1}`, ... tokens. File paths it
+ * doesn't match are left alone.
  */
-description: string | null; 
+find?: string | null; 
 /**
- * id of the current instance so we know who this `.db` is. This can be looked up within the `Instance` table.
+ * The template renders whole names, otherwise it renders names without extension and
+ * extensions are kept
  */
-instance_id: number; 
+replace_extension?: boolean; 
 /**
- * cloud_id is the ID of the cloud library this library is linked to.
- * If this is set we can assume the library is synced with the Cloud.
+ * Value of `{counter}` for the first file path, following ones counting up in the order they
+ * were selected
  */
-cloud_id?: string | null; generate_sync_operations?: boolean; version: LibraryConfigVersion }
-
-export type LibraryConfigVersion = "V0" | "V1" | "V2" | "V3" | "V4" | "V5" | "V6" | "V7" | "V8" | "V9" | "V10" | "V11"
-
-export type LibraryConfigWrapped = { uuid: string; instance_id: string; instance_public_key: RemoteIdentity; config: LibraryConfig }
-
-export type LibraryName = string
+counter_start?: number }
 
-export type LibraryPreferences = { location?: { [key in string]: LocationSettings }; tag?: { [key in string]: TagSettings } }
+export type RenamePersonArgs = { id: number; name: string | null }
 
-export type LightScanArgs = { location_id: number; sub_path: string }
+export type RenameProblem = { MissingValue: string } | "InvalidName" | "AlreadyExists" | "Duplicate"
 
-export type ListenerState = { type: "Listening" } | { type: "Error"; error: string } | { type: "NotListening" }
+export type RescanArgs = { location_id: number; sub_path: string }
 
-export type Listeners = { ipv4: ListenerState; ipv6: ListenerState; relay: ListenerState }
+export type Resolution = { width: number; height: number }
 
-export type Location = { id: number; pub_id: number[]; name: string | null; path: string | null; total_capacity: number | null; available_capacity: number | null; size_in_bytes: number[] | null; is_archived: boolean | null; generate_preview_media: boolean | null; sync_preview_media: boolean | null; hidden: boolean | null; date_created: string | null; scan_state: number; instance_id: number | null }
+export type ResolveConflictArgs = { id: number; keep: ConflictSide }
 
+export type ResolveFolderSyncConflictArgs = { id: number; 
 /**
- * `LocationCreateArgs` is the argument received from the client using `rspc` to create a new location.
- * It has the actual path and a vector of indexer rules ids, to create many-to-many relationships
- * between the location and indexer rules.
+ * Relative to the roots of the locations
  */
-export type LocationCreateArgs = { path: string; dry_run: boolean; indexer_rules_ids: number[] }
+path: string; resolution: ConflictResolution }
 
-export type LocationSettings = { explorer: ExplorerSettings<FilePathOrder> }
+export type Response = { Start: { user_code: string; verification_url: string; verification_url_complete: string } } | "Complete" | { Error: string }
 
+export type RollupHistoryEntry = { 
 /**
- * `LocationUpdateArgs` is the argument received from the client using `rspc` to update a location.
- * It contains the id of the location to be updated, possible a name to change the current location's name
- * and a vector of indexer rules ids to add or remove from the location.
- * 
- * It is important to note that only the indexer rule ids in this vector will be used from now on.
- * Old rules that aren't in this vector will be purged.
+ * Midnight UTC of the day
  */
-export type LocationUpdateArgs = { id: number; name: string | null; generate_preview_media: boolean | null; sync_preview_media: boolean | null; hidden: boolean | null; indexer_rules_ids: number[]; path: string | null }
-
-export type LocationWithIndexerRule = { id: number; pub_id: number[]; name: string | null; path: string | null; total_capacity: number | null; available_capacity: number | null; size_in_bytes: number[] | null; is_archived: boolean | null; generate_preview_media: boolean | null; sync_preview_media: boolean | null; hidden: boolean | null; date_created: string | null; instance_id: number | null; indexer_rules: IndexerRule[] }
-
-export type MaybeUndefined<T> = null | T
+date: string; object_count: number; total_bytes: string }
 
-export type MediaData = { Exif: ExifMetadata } | { FFmpeg: FFmpegMetadata }
+export type RuleKind = "AcceptFilesByGlob" | "RejectFilesByGlob" | "AcceptIfChildrenDirectoriesArePresent" | "RejectIfChildrenDirectoriesArePresent" | "IgnoredByGit"
 
+export type RunChainArgs = { locationId: number; subPath?: string | null; stages: ChainStage[]; 
 /**
- * This can be either naive with no TZ (`YYYY-MM-DD HH-MM-SS`) or UTC (`YYYY-MM-DD HH-MM-SS ±HHMM`),
- * where `±HHMM` is the timezone data. It may be negative if West of the Prime Meridian, or positive if East.
+ * Caps how much of the machine the stages use, below the profile of the node
  */
-export type MediaDate = string
-
-export type MediaLocation = { latitude: number; longitude: number; pluscode: PlusCode; altitude: number | null; direction: number | null }
+resourceProfile?: string | null }
 
-export type Metadata = { album: string | null; album_artist: string | null; artist: string | null; comment: string | null; composer: string | null; copyright: string | null; creation_time: string | null; date: string | null; disc: number | null; encoder: string | null; encoded_by: string | null; filename: string | null; genre: string | null; language: string | null; performer: string | null; publisher: string | null; service_name: string | null; service_provider: string | null; title: string | null; track: number | null; variant_bit_rate: number | null; custom: { [key in string]: string } }
-
-export type NodeConfigP2P = { discovery?: P2PDiscoveryState; port: Port; disabled: boolean; disable_ipv6: boolean; disable_relay: boolean; enable_remote_access: boolean; 
 /**
- * A list of peer addresses to try and manually connect to, instead of relying on discovery.
+ * Where the objects of a S3 location live, stored msgpack encoded on `location.s3_config`.
  * 
- * All of these are valid values:
- * - `localhost`
- * - `otbeaumont.me` or `otbeaumont.me:3000`
- * - `127.0.0.1` or `127.0.0.1:300`
- * - `[::1]` or `[::1]:3000`
- * which is why we use `String` not `SocketAddr`
+ * Credentials are never stored, they come from the AWS default credentials chain (environment
+ * variables, shared credentials file, SSO or instance metadata), optionally from a named profile.
+ */
+export type S3LocationConfig = { bucket: string; 
+/**
+ * Only objects under this key prefix belong to the location
  */
-manual_peers?: string[] }
+prefix?: string; region: string; 
+/**
+ * Endpoint of S3 compatible services, like MinIO, Cloudflare R2 or Backblaze B2
+ */
+endpoint: string | null; 
+/**
+ * AWS profile to take credentials from
+ */
+profile: string | null; 
+/**
+ * Addresses buckets as `endpoint/bucket` instead of `bucket.endpoint`, as most self hosted
+ * services require
+ */
+force_path_style?: boolean }
 
-export type NodePreferences = { thumbnailer: ThumbnailerPreferences }
+export type SavedSearch = { id: number; pub_id: number[]; target: string | null; search: string | null; filters: string | null; name: string | null; icon: string | null; description: string | null; date_created: string | null; date_modified: string | null }
 
-export type NodeState = ({ 
 /**
- * id is a unique identifier for the current node. Each node has a public identifier (this one) and is given a local id for each library (done within the library code).
+ * Job run on each occurrence of a schedule, over the whole location
  */
-id: string; 
+export type ScheduledJob = 
 /**
- * name is the display name of the current node. This is set by the user and is shown in the UI. // TODO: Length validation so it can fit in DNS record
+ * Indexes the location again, shallow only goes through its root directory
  */
-name: string; identity: RemoteIdentity; p2p: NodeConfigP2P; features: BackendFeature[]; preferences: NodePreferences; image_labeler_version: string | null }) & { data_path: string; device_model: string | null; is_in_docker: boolean }
-
-export type NonIndexedPathItem = { path: string; name: string; extension: string; kind: number; is_dir: boolean; date_created: string; date_modified: string; size_in_bytes_bytes: number[]; hidden: boolean }
+{ Indexer: { shallow: boolean } } | 
+/**
+ * Identifies the orphans of the location, shallow only those in its root directory
+ */
+{ FileIdentifier: { shallow: boolean } } | "VerifyIntegrity" | 
+/**
+ * Extracts the text of files that don't have it yet or whose content changed, in the default
+ * OCR languages
+ */
+"TextExtractor" | 
+/**
+ * Tags the objects of the location matched by the tag rules of the library
+ */
+"TagRuleApplier" | 
+/**
+ * Backs the location up into another one, skipping the files already backed up
+ */
+{ Backup: { target_location_id: number; deletion_policy: DeletionPolicy } } | 
+/**
+ * Syncs the location with the other one of a folder sync, both ways
+ */
+{ FolderSync: { folder_sync_id: number } } | 
+/**
+ * Moves the files of the location that went cold into the cold location of its archive policy
+ */
+"ColdArchiver" | 
+/**
+ * Computes the usage of each directory of the location, kept up to date afterwards as it's
+ * indexed and identified
+ */
+"DiskUsageAnalyzer" | 
+/**
+ * Labels the images that the current labeling model hasn't labeled yet
+ */
+"ImageLabeler" | 
+/**
+ * Embeds the images that have no embedding of their current content yet, then updates the
+ * index semantic search goes through
+ */
+"Embedder" | 
+/**
+ * Transcribes the audio and video files that the default Whisper model hasn't transcribed yet
+ */
+"Transcriber"
 
 /**
- * Represents a single notification.
+ * A program run on a location, e.g. as the last stage of a [`JobChain`](crate::JobChain) to
+ * upload or post-process what the previous stages indexed. It runs from the location root, or
+ * from the sub path the chain was run on, without a shell, so `args` aren't expanded.
  */
-export type Notification = ({ type: "library"; id: [string, number] } | { type: "node"; id: number }) & { data: NotificationData; read: boolean; expires: string | null }
+export type ScriptCommand = { program: string; args?: string[] }
+
+export type SearchData<T> = { cursor: number[] | null; items: T[] }
+
+export type SearchFilterArgs = { filePath: FilePathFilterArgs } | { object: ObjectFilterArgs }
 
+export type SearchTarget = "paths" | "objects"
+
+export type SemanticSearchArgs = { 
 /**
- * Represents the data of a single notification.
- * This data is used by the frontend to properly display the notification.
+ * What the images look like, like `beach sunset with dog`
  */
-export type NotificationData = { title: string; content: string; kind: NotificationKind }
+query: string; take?: number | null }
 
-export type NotificationId = { type: "library"; id: [string, number] } | { type: "node"; id: number }
+export type SemanticSearchItem = { item: ExplorerItem; 
+/**
+ * Cosine similarity between the image and the query
+ */
+similarity: number }
 
-export type NotificationKind = "info" | "success" | "error" | "warning"
+export type SetArchivePolicyArgs = { location_id: number; 
+/**
+ * Where the cold files of the location are moved
+ */
+cold_location_id: number; rules: ArchiveRules }
 
-export type Object = { id: number; pub_id: number[]; kind: number | null; key_id: number | null; hidden: boolean | null; favorite: boolean | null; important: boolean | null; note: string | null; date_created: string | null; date_accessed: string | null }
+export type SetFavoriteArgs = { id: number; favorite: boolean }
 
-export type ObjectCursor = "none" | { dateAccessed: CursorOrderItem<string> } | { kind: CursorOrderItem<number> }
+export type SetNoteArgs = { id: number; note: string | null }
 
-export type ObjectFilterArgs = { favorite: boolean } | { hidden: ObjectHiddenFilter } | { kind: InOrNotIn<number> } | { tags: InOrNotIn<number> } | { labels: InOrNotIn<number> } | { dateAccessed: Range<string> }
+export type SetRelayArgs = { storage: RelayStorage; passphrase: string }
 
-export type ObjectHiddenFilter = "exclude" | "include"
+export type SetScheduleEnabledArgs = { id: number; enabled: boolean }
 
-export type ObjectOrder = { field: "dateAccessed"; value: SortOrder } | { field: "kind"; value: SortOrder } | { field: "mediaData"; value: ExifDataOrder }
+export type SetSyncPolicyArgs = { id: number; policy: SyncPolicy }
 
-export type ObjectSearchArgs = { take: number; orderAndPagination?: OrderAndPagination<number, ObjectOrder, ObjectCursor> | null; filters?: SearchFilterArgs[] }
+export type SetVersioningPolicyArgs = { location_id: number; 
+/**
+ * Stops versioning the location if `None`, dropping its versions
+ */
+policy: VersioningPolicy | null }
 
-export type ObjectValidatorArgs = { id: number; path: string }
+export type SimilarArgs = { 
+/**
+ * How many bits of the perceptual hashes may differ, higher finds looser matches
+ */
+threshold?: number | null; take?: number | null }
 
-export type ObjectWithFilePaths = { id: number; pub_id: number[]; kind: number | null; key_id: number | null; hidden: boolean | null; favorite: boolean | null; important: boolean | null; note: string | null; date_created: string | null; date_accessed: string | null; file_paths: ({ id: number; pub_id: number[]; is_dir: boolean | null; cas_id: string | null; integrity_checksum: string | null; location_id: number | null; materialized_path: string | null; name: string | null; extension: string | null; hidden: boolean | null; size_in_bytes: string | null; size_in_bytes_bytes: number[] | null; inode: number[] | null; object_id: number | null; object: { id: number; pub_id: number[]; kind: number | null; key_id: number | null; hidden: boolean | null; favorite: boolean | null; important: boolean | null; note: string | null; date_created: string | null; date_accessed: string | null; exif_data: { resolution: number[] | null; media_date: number[] | null; media_location: number[] | null; camera_data: number[] | null; artist: string | null; description: string | null; copyright: string | null; exif_version: string | null } | null; ffmpeg_data: { id: number; formats: string; bit_rate: number[]; duration: number[] | null; start_time: number[] | null; chapters: FfmpegMediaChapter[]; programs: ({ program_id: number; streams: ({ stream_id: number; name: string | null; codec: { id: number; kind: string | null; sub_kind: string | null; tag: string | null; name: string | null; profile: string | null; bit_rate: number; video_props: FfmpegMediaVideoProps | null; audio_props: FfmpegMediaAudioProps | null; stream_id: number; program_id: number; ffmpeg_data_id: number } | null; aspect_ratio_num: number; aspect_ratio_den: number; frames_per_second_num: number; frames_per_second_den: number; time_base_real_den: number; time_base_real_num: number; dispositions: string | null; title: string | null; encoder: string | null; language: string | null; duration: number[] | null; metadata: number[] | null; program_id: number; ffmpeg_data_id: number })[]; name: string | null; metadata: number[] | null; ffmpeg_data_id: number })[]; title: string | null; creation_time: string | null; date: string | null; album_artist: string | null; disc: string | null; track: string | null; album: string | null; artist: string | null; metadata: number[] | null; object_id: number } | null } | null; key_id: number | null; date_created: string | null; date_modified: string | null; date_indexed: string | null })[] }
+export type SimilarContentArgs = { take?: number | null }
 
-export type ObjectWithFilePaths2 = { id: number; pub_id: number[]; kind: number | null; key_id: number | null; hidden: boolean | null; favorite: boolean | null; important: boolean | null; note: string | null; date_created: string | null; date_accessed: string | null; file_paths: ({ id: number; pub_id: number[]; is_dir: boolean | null; cas_id: string | null; integrity_checksum: string | null; location_id: number | null; materialized_path: string | null; name: string | null; extension: string | null; hidden: boolean | null; size_in_bytes: string | null; size_in_bytes_bytes: number[] | null; inode: number[] | null; object_id: number | null; object: { id: number; pub_id: number[]; kind: number | null; key_id: number | null; hidden: boolean | null; favorite: boolean | null; important: boolean | null; note: string | null; date_created: string | null; date_accessed: string | null; exif_data: { resolution: number[] | null; media_date: number[] | null; media_location: number[] | null; camera_data: number[] | null; artist: string | null; description: string | null; copyright: string | null; exif_version: string | null } | null; ffmpeg_data: { id: number; formats: string; bit_rate: number[]; duration: number[] | null; start_time: number[] | null; chapters: FfmpegMediaChapter[]; programs: ({ program_id: number; streams: ({ stream_id: number; name: string | null; codec: { id: number; kind: string | null; sub_kind: string | null; tag: string | null; name: string | null; profile: string | null; bit_rate: number; video_props: FfmpegMediaVideoProps | null; audio_props: FfmpegMediaAudioProps | null; stream_id: number; program_id: number; ffmpeg_data_id: number } | null; aspect_ratio_num: number; aspect_ratio_den: number; frames_per_second_num: number; frames_per_second_den: number; time_base_real_den: number; time_base_real_num: number; dispositions: string | null; title: string | null; encoder: string | null; language: string | null; duration: number[] | null; metadata: number[] | null; program_id: number; ffmpeg_data_id: number })[]; name: string | null; metadata: number[] | null; ffmpeg_data_id: number })[]; title: string | null; creation_time: string | null; date: string | null; album_artist: string | null; disc: string | null; track: string | null; album: string | null; artist: string | null; metadata: number[] | null; object_id: number } | null } | null; key_id: number | null; date_created: string | null; date_modified: string | null; date_indexed: string | null })[] }
+/**
+ * Objects that look or sound alike, even if their contents differ, like resized images or
+ * re-encoded videos
+ */
+export type SimilarGroup = { object_ids: number[]; file_paths: ({ id: number; pub_id: number[]; location_id: number | null; materialized_path: string | null; is_dir: boolean | null; name: string | null; extension: string | null; cas_id: string | null; size_in_bytes_bytes: number[] | null; physical_size_bytes: number[] | null; object_id: number | null })[] }
 
-export type OldFileCopierJobInit = { source_location_id: number; target_location_id: number; sources_file_path_ids: number[]; target_location_relative_directory_path: string }
+export type SingleInvalidateOperationEvent = { 
+/**
+ * This fields are intentionally private.
+ */
+key: string; arg: JsonValue; result: JsonValue | null }
 
-export type OldFileCutterJobInit = { source_location_id: number; target_location_id: number; sources_file_path_ids: number[]; target_location_relative_directory_path: string }
+/**
+ * Piece of a snippet, highlighted when it's one of the words searched for
+ */
+export type SnippetPart = { text: string; highlighted: boolean }
 
-export type OldFileDeleterJobInit = { location_id: number; file_path_ids: number[] }
+export type SortOrder = "Asc" | "Desc"
 
-export type OldFileEraserJobInit = { location_id: number; file_path_ids: number[]; passes: string }
+export type SpacedropArgs = { identity: RemoteIdentity; file_path: string[] }
 
+export type SpacedropTransfer = { id: string; direction: TransferDirection; peer: RemoteIdentity; files: TransferredFile[]; 
 /**
- * Represents the operating system which the remote peer is running.
- * This is not used internally and predominantly is designed to be used for display purposes by the embedding application.
+ * Where the files are saved, for the transfers we received
  */
-export type OperatingSystem = "Windows" | "Linux" | "MacOS" | "Ios" | "Android" | { Other: string }
+path: string | null; status: TransferStatus; date_started: string; date_updated: string }
 
-export type OrderAndPagination<TId, TOrder, TCursor> = { orderOnly: TOrder } | { offset: { offset: number; order: TOrder | null } } | { cursor: { id: TId; cursor: TCursor } }
+export type SplitPersonArgs = { 
+/**
+ * Faces that belong to someone else, they become a new person
+ */
+faces: number[]; name: string | null }
 
-export type Orientation = "Normal" | "CW90" | "CW180" | "CW270" | "MirroredVertical" | "MirroredHorizontal" | "MirroredHorizontalAnd90CW" | "MirroredHorizontalAnd270CW"
+export type Statistics = { id: number; date_captured: string; total_object_count: number; library_db_size: string; total_local_bytes_used: string; total_local_bytes_capacity: string; total_local_bytes_free: string; total_library_bytes: string; total_library_unique_bytes: string; total_library_preview_media_bytes: string }
 
-export type P2PDiscoveryState = "Everyone" | "ContactsOnly" | "Disabled"
+export type StatisticsResponse = { statistics: Statistics | null; 
+/**
+ * Objects and bytes by kind, location and tag, and how they grew
+ */
+rollups: LibraryRollups }
 
-export type P2PEvent = { type: "PeerChange"; identity: RemoteIdentity; connection: ConnectionMethod; discovery: DiscoveryMethod; metadata: PeerMetadata; addrs: string[] } | { type: "PeerDelete"; identity: RemoteIdentity } | { type: "SpacedropRequest"; id: string; identity: RemoteIdentity; peer_name: string; files: string[] } | { type: "SpacedropProgress"; id: string; percent: number } | { type: "SpacedropTimedOut"; id: string } | { type: "SpacedropRejected"; id: string }
+export type Stream = { id: number; name: string | null; codec: Codec | null; aspect_ratio_num: number; aspect_ratio_den: number; frames_per_second_num: number; frames_per_second_den: number; time_base_real_den: number; time_base_real_num: number; dispositions: string[]; metadata: Metadata }
 
-export type PeerMetadata = { name: string; operating_system: OperatingSystem | null; device_model: HardwareModel | null; version: string | null }
+export type SubtitleProps = { width: number; height: number }
 
-export type PlusCode = string
+/**
+ * What the identifier does with file paths that are symbolic links
+ */
+export type SymlinkPolicy = 
+/**
+ * Leave them without an object
+ */
+"Skip" | 
+/**
+ * Identify the file they point to, broken links are reported as errors
+ */
+"Follow" | 
+/**
+ * Identify them as [`ObjectKind::Link`], keeping where they point to in `file_path.link_target`
+ */
+"RecordAsLink"
 
-export type Port = { type: "random" } | { type: "discrete"; value: number }
+export type SyncAction = "CopyToA" | "CopyToB" | "DeleteFromA" | "DeleteFromB" | 
+/**
+ * Both sides made the same change, only the record of the last sync is updated
+ */
+"Converged" | 
+/**
+ * Both sides changed the path differently, it's left alone until the user resolves it
+ */
+"Conflict" | 
+/**
+ * See [`ConflictResolution::KeepBoth`]
+ */
+"KeepBoth"
 
-export type Program = { id: number; name: string | null; streams: Stream[]; metadata: Metadata }
+export type SyncConflict = { id: number; model: number; record_id: JsonValue; field: string; 
+/**
+ * The write sync kept
+ */
+winner: ConflictingWrite; loser: ConflictingWrite; date_detected: string; date_resolved: string | null }
 
-export type Props = { Video: VideoProps } | { Audio: AudioProps } | { Subtitle: SubtitleProps }
+export type SyncPolicy = "Full" | "MetadataOnly" | "Excluded"
 
-export type Range<T> = { from: T } | { to: T }
+export type SyncStatus = { ingest: boolean; cloud_send: boolean; cloud_receive: boolean; cloud_ingest: boolean; relay: boolean }
 
-export type RemoteIdentity = string
+export type SystemLocations = { desktop: string | null; documents: string | null; downloads: string | null; pictures: string | null; music: string | null; videos: string | null }
 
-export type RenameFileArgs = { location_id: number; kind: RenameKind }
+export type Tag = { id: number; pub_id: number[]; name: string | null; color: string | null; is_hidden: boolean | null; date_created: string | null; date_modified: string | null; parent_id: number | null }
 
-export type RenameKind = { One: RenameOne } | { Many: RenameMany }
+export type TagCreateArgs = { name: string; color: string }
 
-export type RenameMany = { from_pattern: FromPattern; to_pattern: string; from_file_path_ids: number[] }
+export type TagRollup = { tag_id: number; object_count: number; total_bytes: string }
 
-export type RenameOne = { from_file_path_id: number; to: string }
+/**
+ * A tag rule with its conditions decoded
+ */
+export type TagRule = { id: number; name: string; tag_id: number; conditions: Condition[]; enabled: boolean; date_created: string; date_modified: string }
 
-export type RescanArgs = { location_id: number; sub_path: string }
+export type TagRuleCreateArgs = { tag_id: number; name: string; conditions: Condition[] }
 
-export type Resolution = { width: number; height: number }
+export type TagRuleUpdateArgs = { id: number; name: string | null; conditions: Condition[] | null; enabled: boolean | null }
 
-export type Response = { Start: { user_code: string; verification_url: string; verification_url_complete: string } } | "Complete" | { Error: string }
+export type TagSetParentArgs = { id: number; 
+/**
+ * `None` moves the tag back to the top level
+ */
+parent_id: number | null }
 
-export type RuleKind = "AcceptFilesByGlob" | "RejectFilesByGlob" | "AcceptIfChildrenDirectoriesArePresent" | "RejectIfChildrenDirectoriesArePresent" | "IgnoredByGit"
+export type TagSettings = { explorer: ExplorerSettings<ObjectOrder> }
 
-export type SavedSearch = { id: number; pub_id: number[]; target: string | null; search: string | null; filters: string | null; name: string | null; icon: string | null; description: string | null; date_created: string | null; date_modified: string | null }
+export type TagUpdateArgs = { id: number; name: string | null; color: string | null }
 
-export type SearchData<T> = { cursor: number[] | null; items: T[] }
+export type Target = { Object: number } | { FilePath: number }
 
-export type SearchFilterArgs = { filePath: FilePathFilterArgs } | { object: ObjectFilterArgs }
+export type TestingParams = { id: string; path: string }
 
-export type SearchTarget = "paths" | "objects"
+export type TextMatch = { contains: string } | { startsWith: string } | { endsWith: string } | { equals: string }
 
-export type SetFavoriteArgs = { id: number; favorite: boolean }
+export type TextSearchArgs = { query: string; take?: number | null }
 
-export type SetNoteArgs = { id: number; note: string | null }
+export type TextSearchItem = { item: ExplorerItem; source: TextSource; 
+/**
+ * Passage of the text around the matches, which are highlighted
+ */
+snippet: SnippetPart[] }
 
-export type SingleInvalidateOperationEvent = { 
 /**
- * This fields are intentionally private.
+ * Where the text of an object came from, stored in `object_text.source`
  */
-key: string; arg: JsonValue; result: JsonValue | null }
+export type TextSource = "Embedded" | "Ocr" | "Transcript"
 
-export type SortOrder = "Asc" | "Desc"
+export type ThumbnailCacheEviction = { evicted_count: number; freed_bytes: string }
 
-export type SpacedropArgs = { identity: RemoteIdentity; file_path: string[] }
+export type ThumbnailCacheUsage = { files_count: number; total_bytes: string; budget_bytes: string | null }
 
-export type Statistics = { id: number; date_captured: string; total_object_count: number; library_db_size: string; total_local_bytes_used: string; total_local_bytes_capacity: string; total_local_bytes_free: string; total_library_bytes: string; total_library_unique_bytes: string; total_library_preview_media_bytes: string }
+export type ThumbnailerPreferences = { background_processing_percentage: number; 
+/**
+ * Maximum size of each library's thumbnails directory, `None` means unbounded
+ */
+library_cache_budget_mib?: number | null }
 
-export type StatisticsResponse = { statistics: Statistics | null }
+export type Track = { stream_id: number; codec: string | null; 
+/**
+ * Usually an ISO 639-2 code, like `eng` or `por`, as containers store them
+ */
+language: string | null; title: string | null; default: boolean; forced: boolean }
 
-export type Stream = { id: number; name: string | null; codec: Codec | null; aspect_ratio_num: number; aspect_ratio_den: number; frames_per_second_num: number; frames_per_second_den: number; time_base_real_den: number; time_base_real_num: number; dispositions: string[]; metadata: Metadata }
+export type TranscribeForLocationArgs = { id: number; path: string; regenerate?: boolean }
 
-export type SubtitleProps = { width: number; height: number }
+export type Transcript = { 
+/**
+ * ISO 639-1 code of the spoken language, like `en`
+ */
+language: string | null; 
+/**
+ * Whisper model that transcribed it
+ */
+model: string; segments: TranscriptSegment[] }
 
-export type SyncStatus = { ingest: boolean; cloud_send: boolean; cloud_receive: boolean; cloud_ingest: boolean }
+export type TranscriptSegment = { 
+/**
+ * Milliseconds from the start of the media
+ */
+startMs: number; endMs: number; text: string }
 
-export type SystemLocations = { desktop: string | null; documents: string | null; downloads: string | null; pictures: string | null; music: string | null; videos: string | null }
+export type TransferDirection = "Sent" | "Received"
 
-export type Tag = { id: number; pub_id: number[]; name: string | null; color: string | null; is_hidden: boolean | null; date_created: string | null; date_modified: string | null }
+/**
+ * File paths of a location copied or moved into a directory of another location (or the same one)
+ */
+export type TransferFilesArgs = { source_location_id: number; target_location_id: number; sources_file_path_ids: number[]; target_location_relative_directory_path: string; conflict_policy?: ConflictPolicy }
 
-export type TagCreateArgs = { name: string; color: string }
+export type TransferStatus = "InProgress" | 
+/**
+ * The connection dropped, the transfer picks up where it stopped when the sender retries
+ */
+"Interrupted" | "Completed" | "Cancelled" | "Rejected" | "Failed"
 
-export type TagSettings = { explorer: ExplorerSettings<ObjectOrder> }
+export type TransferredFile = { name: string; size: string }
 
-export type TagUpdateArgs = { id: number; name: string | null; color: string | null }
+export type UnlockLibraryArgs = { id: string; passphrase: string }
 
-export type Target = { Object: number } | { FilePath: number }
+export type UpdateResourceProfileArgs = { profile: string }
 
-export type TestingParams = { id: string; path: string }
+export type UpdateThumbnailerPreferences = { background_processing_percentage: number }
 
-export type TextMatch = { contains: string } | { startsWith: string } | { endsWith: string } | { equals: string }
+/**
+ * A directory of a treemap, sizes being strings as they may not fit in a JS number
+ */
+export type UsageNode = { 
+/**
+ * Empty for the root of the location
+ */
+name: string; 
+/**
+ * Materialized path of the children of the directory, to ask for the tree under it
+ */
+path: string; file_count: number; total_bytes: string; 
+/**
+ * Of files with an object, the others not being identified yet
+ */
+identified_file_count: number; identified_bytes: string; 
+/**
+ * Biggest first, empty past the requested depth
+ */
+children: UsageNode[] }
 
-export type ThumbnailerPreferences = { background_processing_percentage: number }
+export type VerifyChecksumsArgs = { location_id: number; 
+/**
+ * The `SHA256SUMS` or `.sfv` manifest to verify the files beside it against
+ */
+manifest_file_path_id: number }
 
-export type UpdateThumbnailerPreferences = { background_processing_percentage: number }
+export type VerifyIntegrityArgs = { location_id: number; 
+/**
+ * Relative to the root of the location, the whole location if `None`
+ */
+sub_path?: string | null; 
+/**
+ * Only verifies these file paths, instead of every file under `sub_path`
+ */
+file_path_ids?: number[] | null }
+
+/**
+ * How long the previous contents of the files of a location are kept, stored msgpack encoded on
+ * `location.versioning`, which is local only as the version store lives on this node. A location
+ * without a policy isn't versioned.
+ */
+export type VersioningPolicy = { 
+/**
+ * Versions kept for each file, the oldest ones going first, every version if `None`
+ */
+max_versions?: number | null; 
+/**
+ * Versions replaced more than this many days ago go, whatever their number
+ */
+max_age_days?: number | null; 
+/**
+ * Files bigger than this many megabytes aren't versioned
+ */
+max_file_size_mb?: number | null }
 
 export type VideoProps = { pixel_format: string | null; color_range: string | null; bits_per_channel: number | null; color_space: string | null; color_primaries: string | null; color_transfer: string | null; field_order: string | null; chroma_location: string | null; width: number; height: number; aspect_ratio_num: number | null; aspect_ratio_den: number | null; properties: string[] }
 
 export type Volume = { name: string; mount_points: string[]; total_capacity: string; available_capacity: string; disk_type: DiskType; file_system: string | null; is_root_filesystem: boolean }
+
+/**
+ * Folder of a WebDAV server where a location lives, stored msgpack encoded on
+ * `location.webdav_config`, which is local only as it holds the password.
+ */
+export type WebDavLocationConfig = { 
+/**
+ * Url of the folder, like `https://cloud.example.com/remote.php/dav/files/<user>/Photos`
+ */
+url: string; username: string | null; 
+/**
+ * Better an app password, which Nextcloud and ownCloud let users revoke for each device
+ */
+password: string | null }
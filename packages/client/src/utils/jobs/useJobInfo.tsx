@@ -205,6 +205,24 @@ export function useJobInfo(job: JobReport, realtimeUpdate: JobProgressEvent | nu
 				} ${plural(completedTaskCount, 'object')}`,
 				textItems: [[{ text: job.status }]]
 			};
+		case 'sync_backfill':
+			return {
+				...data,
+				name: `${isQueued ? 'Backfill' : isRunning ? 'Backfilling' : 'Backfilled'} sync`,
+				textItems: [
+					[
+						{
+							text:
+								isRunning && realtimeUpdate?.message
+									? addCommasToNumbersInMessage(realtimeUpdate.message)
+									: `${formatNumber(output?.records_synced)} ${plural(
+											output?.records_synced,
+											'record'
+										)} synced`
+						}
+					]
+				]
+			};
 		default:
 			return {
 				...data,
@@ -110,6 +110,9 @@ case "$(uname)" in
       brew install nasm
     fi
 
+    # Phones and cameras connected over MTP/PTP
+    brew install libmtp
+
     # Install rust deps for iOS
     if [ $MOBILE -eq 1 ]; then
       echo "Checking for Xcode..."
@@ -154,6 +157,9 @@ case "$(uname)" in
       # React dependencies
       set -- "$@" libvips42
 
+      # Phones and cameras connected over MTP/PTP
+      set -- "$@" libmtp-dev
+
       sudo apt-get -y update
       sudo apt-get -y install "$@"
     elif has pacman; then
@@ -172,6 +178,9 @@ case "$(uname)" in
       # React dependencies
       set -- "$@" libvips
 
+      # Phones and cameras connected over MTP/PTP
+      set -- "$@" libmtp
+
       sudo pacman -Sy --needed "$@"
     elif has dnf; then
       echo "Detected dnf!"
@@ -197,6 +206,9 @@ case "$(uname)" in
       # React dependencies
       set -- "$@" vips
 
+      # Phones and cameras connected over MTP/PTP
+      set -- "$@" libmtp-devel
+
       sudo dnf install "$@"
     elif has apk; then
       echo "Detected apk!"
@@ -216,6 +228,9 @@ case "$(uname)" in
       # React dependencies
       set -- "$@" vips
 
+      # Phones and cameras connected over MTP/PTP
+      set -- "$@" libmtp-dev
+
       sudo apk add "$@"
     else
       if has lsb_release; then